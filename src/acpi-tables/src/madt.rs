@@ -6,7 +6,7 @@
 use std::mem::size_of;
 
 use vm_memory::{Address, Bytes, GuestAddress, GuestMemory};
-use zerocopy::little_endian::U32;
+use zerocopy::little_endian::{U32, U64};
 use zerocopy::AsBytes;
 
 use crate::{checksum, AcpiError, Result, Sdt, SdtHeader};
@@ -67,6 +67,34 @@ impl IoAPIC {
     }
 }
 
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, AsBytes)]
+pub struct GicIts {
+    r#type: u8,
+    length: u8,
+    reserved0: u16,
+    its_id: U32,
+    base_address: U64,
+    reserved1: U32,
+}
+
+impl GicIts {
+    pub fn new(its_id: u32, base_address: u64) -> Self {
+        GicIts {
+            r#type: 0xf,
+            length: 20,
+            reserved0: 0,
+            its_id: U32::new(its_id),
+            base_address: U64::new(base_address),
+            reserved1: U32::ZERO,
+        }
+    }
+}
+
 // clippy doesn't understand that we actually "use" the fields of this struct when we serialize
 // them as bytes in guest memory, so here we just ignore dead code to avoid having to name
 // everything with an underscore prefix
@@ -140,3 +168,24 @@ impl Sdt for Madt {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use zerocopy::AsBytes;
+
+    use super::GicIts;
+
+    #[test]
+    fn test_gic_its() {
+        let its = GicIts::new(1, 0x3ffd_0000);
+        assert_eq!(
+            its.as_bytes(),
+            [
+                0xf, 20, 0, 0, // type, length, reserved
+                1, 0, 0, 0, // GIC ITS ID
+                0, 0, 0xfd, 0x3f, 0, 0, 0, 0, // physical base address
+                0, 0, 0, 0, // reserved
+            ]
+        );
+    }
+}
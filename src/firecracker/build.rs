@@ -13,6 +13,11 @@ const ADVANCED_BINARY_FILTER_FILE_NAME: &str = "seccomp_filter.bpf";
 const JSON_DIR: &str = "../../resources/seccomp";
 const SECCOMPILER_SRC_DIR: &str = "../seccompiler/src";
 
+// In single-threaded API mode, the VMM thread also serves the API, so it gets a filter allowing
+// the syscalls of both threads.
+const COMBINED_FILTER: &str = "vmm_api";
+const COMBINED_FILTER_THREADS: [&str; 2] = ["vmm", "api"];
+
 // This script is run on every modification in the target-specific JSON file in `resources/seccomp`.
 // It compiles the JSON seccomp policies into a serializable BPF format, using seccompiler-bin.
 // The generated binary code will get included in Firecracker's code, at compile-time.
@@ -45,7 +50,9 @@ fn main() {
     println!("cargo:rerun-if-changed={}", SECCOMPILER_SRC_DIR);
 
     let input = std::fs::read_to_string(seccomp_json_path).expect("Correct input file");
-    let filters: JsonFile = serde_json::from_str(&input).expect("Input read");
+    let mut input: serde_json::Value = serde_json::from_str(&input).expect("Input read");
+    add_combined_filter(&mut input, COMBINED_FILTER, &COMBINED_FILTER_THREADS);
+    let filters: JsonFile = serde_json::from_value(input).expect("Input read");
 
     let arch = target_arch.as_str().try_into().expect("Target");
     let compiler = Compiler::new(arch);
@@ -60,3 +67,28 @@ fn main() {
     let output_file = File::create(out_path).expect("Create seccompiler output path");
     bincode::serialize_into(output_file, &bpf_data).expect("Seccompiler serialization");
 }
+
+// Adds the filter `name`, with the rules of the filters of all the `threads`, to the JSON policy.
+// The actions are the ones of the first thread.
+fn add_combined_filter(policy: &mut serde_json::Value, name: &str, threads: &[&str]) {
+    let mut combined = policy[threads[0]].clone();
+    let rules = combined["filter"]
+        .as_array_mut()
+        .expect("Missing filter rules");
+    for thread in &threads[1..] {
+        let thread_rules = policy[thread]["filter"]
+            .as_array()
+            .expect("Missing filter rules");
+        for rule in thread_rules {
+            if !rules.iter().any(|r| same_rule(r, rule)) {
+                rules.push(rule.clone());
+            }
+        }
+    }
+    policy[name] = combined;
+}
+
+// Whether two rules match the same syscalls, regardless of their comments.
+fn same_rule(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    a["syscall"] == b["syscall"] && a["args"] == b["args"]
+}
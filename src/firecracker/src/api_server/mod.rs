@@ -8,21 +8,29 @@
 
 pub mod parsed_request;
 pub mod request;
+mod request_timer;
 
 use std::fmt::Debug;
 use std::sync::mpsc;
 
 pub use micro_http::{Body, HttpServer, Request, Response, ServerError, StatusCode, Version};
-use parsed_request::{ParsedRequest, RequestAction};
+use parsed_request::{endpoint_template, ParsedRequest, RequestAction};
+pub use request_timer::RequestTimer;
 use seccompiler::BpfProgramRef;
 use serde_json::json;
 use utils::eventfd::EventFd;
 use vmm::logger::{
     debug, error, info, update_metric_with_elapsed_time, warn, ProcessTimeReporter, METRICS,
 };
-use vmm::rpc_interface::{ApiRequest, ApiResponse, VmmAction};
+use vmm::rpc_interface::{ApiRequest, ApiResponse, VmmAction, VmmActionError, VmmData};
 use vmm::vmm_config::snapshot::SnapshotType;
 
+/// Outcome of a VMM action.
+pub type VmmActionResult = Result<VmmData, VmmActionError>;
+
+/// Default duration above which API requests are logged as slow, in milliseconds.
+pub const DEFAULT_SLOW_REQUEST_THRESHOLD_MS: u64 = 1000;
+
 /// Structure associated with the API server implementation.
 #[derive(Debug)]
 pub struct ApiServer {
@@ -59,12 +67,15 @@ impl ApiServer {
     /// * `start_time_us` - the timestamp for when the process was started in us.
     /// * `start_time_cpu_us` - the timestamp for when the process was started in CPU us.
     /// * `seccomp_filter` - the seccomp filter to apply.
+    /// * `api_payload_limit` - the maximum size of the body of the requests, in bytes.
+    /// * `slow_request_threshold_ms` - the duration above which requests are logged as slow.
     pub fn run(
         &mut self,
         mut server: HttpServer,
         process_time_reporter: ProcessTimeReporter,
         seccomp_filter: BpfProgramRef,
         api_payload_limit: usize,
+        slow_request_threshold_ms: u64,
     ) {
         // Set the api payload size limit.
         server.set_payload_max_size(api_payload_limit);
@@ -101,87 +112,49 @@ impl ApiServer {
                 }
             };
             for server_request in request_vec {
-                let request_processing_start_us =
-                    utils::time::get_time_us(utils::time::ClockType::Monotonic);
+                let mut request_timer =
+                    RequestTimer::new(utils::time::get_time_us(utils::time::ClockType::Monotonic));
                 // Use `self.handle_request()` as the processing callback.
                 let response = server_request
-                    .process(|request| self.handle_request(request, request_processing_start_us));
+                    .process(|request| self.handle_request(request, &mut request_timer));
                 if let Err(err) = server.respond(response) {
                     error!("API Server encountered an error on response: {}", err);
                 };
 
-                let delta_us = utils::time::get_time_us(utils::time::ClockType::Monotonic)
-                    - request_processing_start_us;
-                debug!("Total previous API call duration: {} us.", delta_us);
+                request_timer.finish(slow_request_threshold_ms.saturating_mul(1000));
             }
         }
     }
 
-    /// Handles an API request received through the associated socket.
+    /// Handles an API request received through the associated socket, timing its processing
+    /// with `request_timer`.
     pub fn handle_request(
         &mut self,
         request: &Request,
-        request_processing_start_us: u64,
+        request_timer: &mut RequestTimer,
     ) -> Response {
-        match ParsedRequest::try_from(request).map(|r| r.into_parts()) {
-            Ok((req_action, mut parsing_info)) => {
-                let mut response = match req_action {
-                    RequestAction::Sync(vmm_action) => {
-                        self.serve_vmm_action_request(vmm_action, request_processing_start_us)
-                    }
-                };
-                if let Some(message) = parsing_info.take_deprecation_message() {
-                    warn!("{}", message);
-                    response.set_deprecation();
-                }
-                response
-            }
-            Err(err) => {
-                error!("{:?}", err);
-                err.into()
-            }
-        }
+        handle_request(request, request_timer, |vmm_action| {
+            self.send_vmm_action(vmm_action)
+        })
     }
 
     fn serve_vmm_action_request(
         &mut self,
         vmm_action: Box<VmmAction>,
-        request_processing_start_us: u64,
+        request_timer: &mut RequestTimer,
     ) -> Response {
-        let metric_with_action = match *vmm_action {
-            VmmAction::CreateSnapshot(ref params) => match params.snapshot_type {
-                SnapshotType::Full => Some((
-                    &METRICS.latencies_us.full_create_snapshot,
-                    "create full snapshot",
-                )),
-                SnapshotType::Diff => Some((
-                    &METRICS.latencies_us.diff_create_snapshot,
-                    "create diff snapshot",
-                )),
-            },
-            VmmAction::LoadSnapshot(_) => {
-                Some((&METRICS.latencies_us.load_snapshot, "load snapshot"))
-            }
-            VmmAction::Pause => Some((&METRICS.latencies_us.pause_vm, "pause vm")),
-            VmmAction::Resume => Some((&METRICS.latencies_us.resume_vm, "resume vm")),
-            _ => None,
-        };
+        serve_vmm_action_request(vmm_action, request_timer, |vmm_action| {
+            self.send_vmm_action(vmm_action)
+        })
+    }
 
+    /// Sends `vmm_action` to the VMM thread and waits for its outcome.
+    fn send_vmm_action(&mut self, vmm_action: Box<VmmAction>) -> VmmActionResult {
         self.api_request_sender
             .send(vmm_action)
             .expect("Failed to send VMM message");
         self.to_vmm_fd.write(1).expect("Cannot update send VMM fd");
-        let vmm_outcome = *(self.vmm_response_receiver.recv().expect("VMM disconnected"));
-        let response = ParsedRequest::convert_to_response(&vmm_outcome);
-
-        if vmm_outcome.is_ok() {
-            if let Some((metric, action)) = metric_with_action {
-                let elapsed_time_us =
-                    update_metric_with_elapsed_time(metric, request_processing_start_us);
-                info!("'{}' API request took {} us.", action, elapsed_time_us);
-            }
-        }
-        response
+        *(self.vmm_response_receiver.recv().expect("VMM disconnected"))
     }
 
     /// An HTTP response which also includes a body.
@@ -196,6 +169,78 @@ impl ApiServer {
     }
 }
 
+/// Handles an API request, timing its processing with `request_timer`. The VMM action of the
+/// request is executed by `run_vmm_action`.
+pub fn handle_request<F>(
+    request: &Request,
+    request_timer: &mut RequestTimer,
+    run_vmm_action: F,
+) -> Response
+where
+    F: FnOnce(Box<VmmAction>) -> VmmActionResult,
+{
+    let parsed_request = ParsedRequest::try_from(request);
+    request_timer.request_parsed(request.method(), endpoint_template(request));
+    match parsed_request.map(|r| r.into_parts()) {
+        Ok((req_action, mut parsing_info)) => {
+            let mut response = match req_action {
+                RequestAction::Sync(vmm_action) => {
+                    serve_vmm_action_request(vmm_action, request_timer, run_vmm_action)
+                }
+            };
+            if let Some(message) = parsing_info.take_deprecation_message() {
+                warn!("{}", message);
+                response.set_deprecation();
+            }
+            response
+        }
+        Err(err) => {
+            error!("{:?}", err);
+            err.into()
+        }
+    }
+}
+
+fn serve_vmm_action_request<F>(
+    vmm_action: Box<VmmAction>,
+    request_timer: &mut RequestTimer,
+    run_vmm_action: F,
+) -> Response
+where
+    F: FnOnce(Box<VmmAction>) -> VmmActionResult,
+{
+    let metric_with_action = match *vmm_action {
+        VmmAction::CreateSnapshot(ref params) => match params.snapshot_type {
+            SnapshotType::Full => Some((
+                &METRICS.latencies_us.full_create_snapshot,
+                "create full snapshot",
+            )),
+            SnapshotType::Diff => Some((
+                &METRICS.latencies_us.diff_create_snapshot,
+                "create diff snapshot",
+            )),
+        },
+        VmmAction::LoadSnapshot(_) => Some((&METRICS.latencies_us.load_snapshot, "load snapshot")),
+        VmmAction::Pause => Some((&METRICS.latencies_us.pause_vm, "pause vm")),
+        VmmAction::Resume => Some((&METRICS.latencies_us.resume_vm, "resume vm")),
+        VmmAction::CloneMicrovm(_) => Some((&METRICS.latencies_us.clone_microvm, "clone microvm")),
+        _ => None,
+    };
+
+    request_timer.vmm_action_sent();
+    let vmm_outcome = run_vmm_action(vmm_action);
+    request_timer.vmm_action_done();
+    let response = ParsedRequest::convert_to_response(&vmm_outcome);
+
+    if vmm_outcome.is_ok() {
+        if let Some((metric, action)) = metric_with_action {
+            let elapsed_time_us = update_metric_with_elapsed_time(metric, request_timer.start_us());
+            info!("'{}' API request took {} us.", action, elapsed_time_us);
+        }
+    }
+    response
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::{Read, Write};
@@ -203,15 +248,16 @@ mod tests {
     use std::path::PathBuf;
     use std::sync::mpsc::channel;
     use std::thread;
+    use std::time::Duration;
 
     use micro_http::HttpConnection;
     use utils::tempfile::TempFile;
     use utils::time::ClockType;
     use vmm::builder::StartMicrovmError;
-    use vmm::logger::StoreMetric;
-    use vmm::rpc_interface::{VmmActionError, VmmData};
+    use vmm::logger::{IncMetric, StoreMetric};
     use vmm::seccomp_filters::get_empty_filters;
     use vmm::vmm_config::instance_info::InstanceInfo;
+    use vmm::vmm_config::machine_config::MachineConfig;
     use vmm::vmm_config::snapshot::CreateSnapshotParams;
 
     use super::request::cpu_configuration::parse_put_cpu_config;
@@ -251,7 +297,8 @@ mod tests {
                 StartMicrovmError::MissingKernelConfig,
             ))))
             .unwrap();
-        let response = api_server.serve_vmm_action_request(Box::new(VmmAction::StartMicroVm), 0);
+        let response = api_server
+            .serve_vmm_action_request(Box::new(VmmAction::StartMicroVm), &mut RequestTimer::new(0));
         assert_eq!(response.status(), StatusCode::BadRequest);
 
         // Since the vmm side is mocked out in this test, the call to serve_vmm_action_request can
@@ -262,8 +309,10 @@ mod tests {
         let start_time_us = utils::time::get_time_us(ClockType::Monotonic) - 1;
         assert_eq!(METRICS.latencies_us.pause_vm.fetch(), 0);
         to_api.send(Box::new(Ok(VmmData::Empty))).unwrap();
-        let response =
-            api_server.serve_vmm_action_request(Box::new(VmmAction::Pause), start_time_us);
+        let response = api_server.serve_vmm_action_request(
+            Box::new(VmmAction::Pause),
+            &mut RequestTimer::new(start_time_us),
+        );
         assert_eq!(response.status(), StatusCode::NoContent);
         assert_ne!(METRICS.latencies_us.pause_vm.fetch(), 0);
 
@@ -277,7 +326,7 @@ mod tests {
                 snapshot_path: PathBuf::new(),
                 mem_file_path: PathBuf::new(),
             })),
-            start_time_us,
+            &mut RequestTimer::new(start_time_us),
         );
         assert_eq!(response.status(), StatusCode::BadRequest);
         // The metric should not be updated if the request wasn't successful.
@@ -290,7 +339,7 @@ mod tests {
                 snapshot_path: PathBuf::new(),
                 mem_file_path: PathBuf::new(),
             })),
-            start_time_us,
+            &mut RequestTimer::new(start_time_us),
         );
         assert_eq!(response.status(), StatusCode::NoContent);
         assert_ne!(METRICS.latencies_us.diff_create_snapshot.fetch(), 0);
@@ -320,7 +369,7 @@ mod tests {
             .unwrap();
         connection.try_read().unwrap();
         let req = connection.pop_parsed_request().unwrap();
-        let response = api_server.handle_request(&req, 0);
+        let response = api_server.handle_request(&req, &mut RequestTimer::new(0));
         assert_eq!(response.status(), StatusCode::BadRequest);
 
         // Test a Get Info request.
@@ -332,7 +381,7 @@ mod tests {
         sender.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
         connection.try_read().unwrap();
         let req = connection.pop_parsed_request().unwrap();
-        let response = api_server.handle_request(&req, 0);
+        let response = api_server.handle_request(&req, &mut RequestTimer::new(0));
         assert_eq!(response.status(), StatusCode::OK);
 
         // Test erroneous request.
@@ -345,10 +394,70 @@ mod tests {
             .unwrap();
         connection.try_read().unwrap();
         let req = connection.pop_parsed_request().unwrap();
-        let response = api_server.handle_request(&req, 0);
+        let response = api_server.handle_request(&req, &mut RequestTimer::new(0));
         assert_eq!(response.status(), StatusCode::BadRequest);
     }
 
+    #[test]
+    fn test_handle_slow_request() {
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let (api_request_sender, from_api) = channel();
+        let (to_api, vmm_response_receiver) = channel();
+
+        let mut api_server = ApiServer::new(api_request_sender, vmm_response_receiver, to_vmm_fd);
+
+        // Mock a VMM thread taking 20 ms to execute the VMM action.
+        let vmm_thread = thread::spawn(move || {
+            let vmm_action: ApiRequest = from_api.recv().unwrap();
+            assert_eq!(*vmm_action, VmmAction::GetVmMachineConfig);
+            thread::sleep(Duration::from_millis(20));
+            to_api
+                .send(Box::new(Ok(VmmData::MachineConfiguration(
+                    MachineConfig::default(),
+                ))))
+                .unwrap();
+        });
+
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(b"GET /machine-config HTTP/1.1\r\n\r\n")
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        let mut request_timer = RequestTimer::new(utils::time::get_time_us(ClockType::Monotonic));
+        let response = api_server.handle_request(&req, &mut request_timer);
+        assert_eq!(response.status(), StatusCode::OK);
+        vmm_thread.join().unwrap();
+
+        let latencies = request_timer.record(utils::time::get_time_us(ClockType::Monotonic));
+        assert!(latencies.vmm_action_us.unwrap() >= 20_000);
+        assert!(latencies.total_us >= 20_000);
+        let metrics = METRICS.api_server.endpoints.get("GET", "/machine-config");
+        assert_eq!(metrics.vmm_action.le_100ms.count(), 1);
+        assert_eq!(metrics.total.le_100ms.count(), 1);
+        assert_eq!(metrics.parse.gt_1s.count(), 0);
+
+        let warning = request_timer
+            .slow_request_warning(&latencies, 10_000)
+            .unwrap();
+        assert!(
+            warning.starts_with("Slow API request: 'GET /machine-config' took "),
+            "{}",
+            warning
+        );
+        assert!(
+            warning.contains("above the threshold of 10000 us"),
+            "{}",
+            warning
+        );
+        assert_eq!(
+            request_timer
+                .slow_request_warning(&latencies, DEFAULT_SLOW_REQUEST_THRESHOLD_MS * 1000),
+            None
+        );
+    }
+
     #[test]
     fn test_handle_request_logging() {
         let cpu_template_json = TEST_UNESCAPED_JSON_TEMPLATE;
@@ -392,6 +501,7 @@ mod tests {
                     ProcessTimeReporter::new(Some(1), Some(1), Some(1)),
                     seccomp_filters.get("api").unwrap(),
                     vmm::HTTP_MAX_PAYLOAD_SIZE,
+                    DEFAULT_SLOW_REQUEST_THRESHOLD_MS,
                 );
             })
             .unwrap();
@@ -435,6 +545,7 @@ mod tests {
                     ProcessTimeReporter::new(Some(1), Some(1), Some(1)),
                     seccomp_filters.get("api").unwrap(),
                     50,
+                    DEFAULT_SLOW_REQUEST_THRESHOLD_MS,
                 )
             })
             .unwrap();
@@ -485,6 +596,7 @@ mod tests {
                     ProcessTimeReporter::new(Some(1), Some(1), Some(1)),
                     seccomp_filters.get("api").unwrap(),
                     vmm::HTTP_MAX_PAYLOAD_SIZE,
+                    DEFAULT_SLOW_REQUEST_THRESHOLD_MS,
                 )
             })
             .unwrap();
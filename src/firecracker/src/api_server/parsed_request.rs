@@ -12,6 +12,7 @@ use vmm::rpc_interface::{VmmAction, VmmActionError, VmmData};
 use super::request::actions::parse_put_actions;
 use super::request::balloon::{parse_get_balloon, parse_patch_balloon, parse_put_balloon};
 use super::request::boot_source::parse_put_boot_source;
+use super::request::console_scanner::parse_put_console_scanner;
 use super::request::cpu_configuration::parse_put_cpu_config;
 use super::request::drive::{parse_patch_drive, parse_put_drive};
 use super::request::entropy::parse_put_entropy;
@@ -23,9 +24,11 @@ use super::request::machine_configuration::{
 use super::request::metrics::parse_put_metrics;
 use super::request::mmds::{parse_get_mmds, parse_patch_mmds, parse_put_mmds};
 use super::request::net::{parse_patch_net, parse_put_net};
-use super::request::snapshot::{parse_patch_vm_state, parse_put_snapshot};
+use super::request::snapshot::{parse_get_snapshot, parse_patch_vm_state, parse_put_snapshot};
 use super::request::version::parse_get_version;
-use super::request::vsock::parse_put_vsock;
+use super::request::vhost_user::{parse_get_vhost_user, parse_put_vhost_user};
+use super::request::vsock::{parse_get_vsock, parse_put_vsock};
+use super::request_timer::UNKNOWN_ENDPOINT;
 use super::ApiServer;
 
 #[derive(Debug)]
@@ -82,11 +85,17 @@ impl TryFrom<&Request> for ParsedRequest {
                 Ok(ParsedRequest::new_sync(VmmAction::GetFullVmConfig))
             }
             (Method::Get, "machine-config", None) => parse_get_machine_config(),
-            (Method::Get, "mmds", None) => parse_get_mmds(),
+            (Method::Get, "mmds", None) => parse_get_mmds(path_tokens.next()),
+            (Method::Get, "snapshot", None) => parse_get_snapshot(path_tokens.next()),
+            (Method::Get, "vhost-user", None) => {
+                parse_get_vhost_user(path_tokens.next(), path_tokens.next())
+            }
+            (Method::Get, "vsock", None) => parse_get_vsock(path_tokens.next()),
             (Method::Get, _, Some(_)) => method_to_error(Method::Get),
             (Method::Put, "actions", Some(body)) => parse_put_actions(body),
             (Method::Put, "balloon", Some(body)) => parse_put_balloon(body),
             (Method::Put, "boot-source", Some(body)) => parse_put_boot_source(body),
+            (Method::Put, "console-scanner", Some(body)) => parse_put_console_scanner(body),
             (Method::Put, "cpu-config", Some(body)) => parse_put_cpu_config(body),
             (Method::Put, "drives", Some(body)) => parse_put_drive(body, path_tokens.next()),
             (Method::Put, "logger", Some(body)) => parse_put_logger(body),
@@ -99,6 +108,9 @@ impl TryFrom<&Request> for ParsedRequest {
             (Method::Put, "snapshot", Some(body)) => parse_put_snapshot(body, path_tokens.next()),
             (Method::Put, "vsock", Some(body)) => parse_put_vsock(body),
             (Method::Put, "entropy", Some(body)) => parse_put_entropy(body),
+            (Method::Put, "vhost-user", body) => {
+                parse_put_vhost_user(body, path_tokens.next(), path_tokens.next())
+            }
             (Method::Put, _, None) => method_to_error(Method::Put),
             (Method::Patch, "balloon", Some(body)) => parse_patch_balloon(body, path_tokens.next()),
             (Method::Patch, "drives", Some(body)) => parse_patch_drive(body, path_tokens.next()),
@@ -117,6 +129,49 @@ impl TryFrom<&Request> for ParsedRequest {
     }
 }
 
+/// Returns the template of the path of `request`, in which the resource IDs are replaced by the
+/// name of their parameter (e.g. "/drives/{drive_id}"), so that the requests can be grouped by
+/// endpoint.
+pub(crate) fn endpoint_template(request: &Request) -> &'static str {
+    let request_uri = request.uri().get_abs_path();
+    let mut path_tokens = request_uri.trim_start_matches('/').split_terminator('/');
+    let path = path_tokens.next().unwrap_or("");
+
+    match (path, path_tokens.next()) {
+        ("", None) => "/",
+        ("actions", None) => "/actions",
+        ("balloon", None) => "/balloon",
+        ("balloon", Some("statistics")) => "/balloon/statistics",
+        ("boot-source", None) => "/boot-source",
+        ("console-scanner", None) => "/console-scanner",
+        ("cpu-config", None) => "/cpu-config",
+        ("drives", Some(_)) => "/drives/{drive_id}",
+        ("entropy", None) => "/entropy",
+        ("logger", None) => "/logger",
+        ("machine-config", None) => "/machine-config",
+        ("metrics", None) => "/metrics",
+        ("mmds", None) => "/mmds",
+        ("mmds", Some("config")) => "/mmds/config",
+        ("mmds", Some("info")) => "/mmds/info",
+        ("network-interfaces", Some(_)) => "/network-interfaces/{iface_id}",
+        ("snapshot", Some("create")) => "/snapshot/create",
+        ("snapshot", Some("load")) => "/snapshot/load",
+        ("snapshot", Some("clone")) => "/snapshot/clone",
+        ("snapshot", Some("access-profile")) => "/snapshot/access-profile",
+        ("version", None) => "/version",
+        ("vhost-user", Some(_)) => match path_tokens.next() {
+            Some("info") => "/vhost-user/{id}/info",
+            Some("ping") => "/vhost-user/{id}/ping",
+            _ => UNKNOWN_ENDPOINT,
+        },
+        ("vm", None) => "/vm",
+        ("vm", Some("config")) => "/vm/config",
+        ("vsock", None) => "/vsock",
+        ("vsock", Some("connections")) => "/vsock/connections",
+        _ => UNKNOWN_ENDPOINT,
+    }
+}
+
 impl ParsedRequest {
     pub(crate) fn new(action: RequestAction) -> Self {
         Self {
@@ -167,6 +222,8 @@ impl ParsedRequest {
                     Self::success_response_with_data(vm_config)
                 }
                 VmmData::MmdsValue(value) => Self::success_response_with_mmds_value(value),
+                VmmData::MmdsInfo(info) => Self::success_response_with_data(info),
+                VmmData::AccessProfile(profile) => Self::success_response_with_data(profile),
                 VmmData::BalloonConfig(balloon_config) => {
                     Self::success_response_with_data(balloon_config)
                 }
@@ -176,6 +233,9 @@ impl ParsedRequest {
                     &serde_json::json!({ "firecracker_version": version.as_str() }),
                 ),
                 VmmData::FullVmConfig(config) => Self::success_response_with_data(config),
+                VmmData::VhostUserInfo(info) => Self::success_response_with_data(info),
+                VmmData::VhostUserPing(ping) => Self::success_response_with_data(ping),
+                VmmData::VsockConnections(conns) => Self::success_response_with_data(conns),
             },
             Err(vmm_action_error) => {
                 let mut response = match vmm_action_error {
@@ -319,13 +379,16 @@ pub mod tests {
     use std::str::FromStr;
 
     use micro_http::HttpConnection;
+    use vmm::access_profile::{AccessProfile, AccessRun, AccessTrackingState};
     use vmm::builder::StartMicrovmError;
     use vmm::cpu_config::templates::test_utils::build_test_template;
+    use vmm::mmds::data_store::{MmdsDataStoreInfo, MmdsDatastoreError};
     use vmm::resources::VmmConfig;
     use vmm::rpc_interface::VmmActionError;
     use vmm::vmm_config::balloon::{BalloonDeviceConfig, BalloonStats};
     use vmm::vmm_config::instance_info::InstanceInfo;
     use vmm::vmm_config::machine_config::MachineConfig;
+    use vmm::vmm_config::vhost_user::{VhostUserPingConfig, VhostUserPingResult};
 
     use super::*;
 
@@ -409,6 +472,36 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap_err();
     }
 
+    #[test]
+    fn test_endpoint_template() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        for (path, template) in [
+            ("/", "/"),
+            ("/balloon/statistics", "/balloon/statistics"),
+            ("/drives/rootfs", "/drives/{drive_id}"),
+            ("/network-interfaces/eth0", "/network-interfaces/{iface_id}"),
+            ("/mmds/config", "/mmds/config"),
+            ("/snapshot/create", "/snapshot/create"),
+            ("/snapshot/access-profile", "/snapshot/access-profile"),
+            ("/vm", "/vm"),
+            ("/vhost-user/rootfs/info", "/vhost-user/{id}/info"),
+            ("/vhost-user/rootfs/ping", "/vhost-user/{id}/ping"),
+            ("/vsock/connections", "/vsock/connections"),
+            ("/drives", UNKNOWN_ENDPOINT),
+            ("/vhost-user/rootfs", UNKNOWN_ENDPOINT),
+            ("/snapshot/invalid", UNKNOWN_ENDPOINT),
+            ("/invalid", UNKNOWN_ENDPOINT),
+        ] {
+            sender
+                .write_all(http_request("GET", path, None).as_bytes())
+                .unwrap();
+            connection.try_read().unwrap();
+            let req = connection.pop_parsed_request().unwrap();
+            assert_eq!(endpoint_template(&req), template, "{}", path);
+        }
+    }
+
     #[test]
     fn test_checked_id() {
         checked_id("dummy").unwrap();
@@ -549,6 +642,9 @@ pub mod tests {
             let data = Ok(vmm_data);
             let mut buf = Cursor::new(vec![0]);
             let expected_response = match data.as_ref().unwrap() {
+                VmmData::AccessProfile(profile) => {
+                    http_response(&serde_json::to_string(profile).unwrap(), 200)
+                }
                 VmmData::BalloonConfig(cfg) => {
                     http_response(&serde_json::to_string(cfg).unwrap(), 200)
                 }
@@ -565,6 +661,9 @@ pub mod tests {
                 VmmData::MmdsValue(value) => {
                     http_response(&serde_json::to_string(value).unwrap(), 200)
                 }
+                VmmData::MmdsInfo(info) => {
+                    http_response(&serde_json::to_string(info).unwrap(), 200)
+                }
                 VmmData::InstanceInformation(info) => {
                     http_response(&serde_json::to_string(info).unwrap(), 200)
                 }
@@ -572,12 +671,32 @@ pub mod tests {
                     &serde_json::json!({ "firecracker_version": version.as_str() }).to_string(),
                     200,
                 ),
+                VmmData::VhostUserInfo(info) => {
+                    http_response(&serde_json::to_string(info).unwrap(), 200)
+                }
+                VmmData::VhostUserPing(ping) => {
+                    http_response(&serde_json::to_string(ping).unwrap(), 200)
+                }
+                VmmData::VsockConnections(conns) => {
+                    http_response(&serde_json::to_string(conns).unwrap(), 200)
+                }
             };
             let response = ParsedRequest::convert_to_response(&data);
             response.write_all(&mut buf).unwrap();
             assert_eq!(buf.into_inner(), expected_response.as_bytes());
         };
 
+        verify_ok_response_with(VmmData::AccessProfile(AccessProfile {
+            state: AccessTrackingState::Complete,
+            duration_s: 10,
+            page_size: 4096,
+            touched_pages: 3,
+            runs: vec![AccessRun {
+                guest_addr: 0x1000,
+                file_offset: 0x1000,
+                num_pages: 3,
+            }],
+        }));
         verify_ok_response_with(VmmData::BalloonConfig(BalloonDeviceConfig::default()));
         verify_ok_response_with(VmmData::BalloonStats(BalloonStats {
             swap_in: Some(1),
@@ -588,8 +707,17 @@ pub mod tests {
         verify_ok_response_with(VmmData::FullVmConfig(VmmConfig::default()));
         verify_ok_response_with(VmmData::MachineConfiguration(MachineConfig::default()));
         verify_ok_response_with(VmmData::MmdsValue(serde_json::from_str("{}").unwrap()));
+        verify_ok_response_with(VmmData::MmdsInfo(MmdsDataStoreInfo {
+            used_bytes: 2,
+            limit_bytes: 51200,
+        }));
         verify_ok_response_with(VmmData::InstanceInformation(InstanceInfo::default()));
         verify_ok_response_with(VmmData::VmmVersion(String::default()));
+        verify_ok_response_with(VmmData::VhostUserInfo(Default::default()));
+        verify_ok_response_with(VmmData::VhostUserPing(VhostUserPingResult {
+            latency_us: 42,
+        }));
+        verify_ok_response_with(VmmData::VsockConnections(Default::default()));
 
         // Error.
         let error = VmmActionError::StartMicrovm(StartMicrovmError::MissingKernelConfig);
@@ -600,6 +728,22 @@ pub mod tests {
 
         let expected_response = http_response(&json, 400);
         assert_eq!(buf.into_inner(), expected_response.as_bytes());
+
+        // MMDS data store limit error.
+        let error = VmmActionError::MmdsLimitExceeded(MmdsDatastoreError::DataStoreLimitExceeded(
+            51300, 51200,
+        ));
+        let mut buf = Cursor::new(vec![0]);
+        let json = ApiServer::json_fault_message(error.to_string());
+        assert!(json.contains(
+            "MMDS limit exceeded error: The MMDS data store would grow to 51300 bytes, over its \
+             51200 bytes limit."
+        ));
+        let response = ParsedRequest::convert_to_response(&Err(error));
+        response.write_all(&mut buf).unwrap();
+
+        let expected_response = http_response(&json, 413);
+        assert_eq!(buf.into_inner(), expected_response.as_bytes());
     }
 
     #[test]
@@ -660,6 +804,16 @@ pub mod tests {
         connection.try_read().unwrap();
         let req = connection.pop_parsed_request().unwrap();
         ParsedRequest::try_from(&req).unwrap();
+
+        sender
+            .write_all(http_request("GET", "/mmds/info", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetMmdsInfo
+        );
     }
 
     #[test]
@@ -674,6 +828,51 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_get_snapshot() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/snapshot/access-profile", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetAccessProfile
+        );
+    }
+
+    #[test]
+    fn test_try_from_get_vhost_user() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/vhost-user/rootfs/info", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetVhostUserInfo("rootfs".to_string())
+        );
+    }
+
+    #[test]
+    fn test_try_from_get_vsock() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/vsock/connections", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetVsockConnections
+        );
+    }
+
     #[test]
     fn test_try_from_put_actions() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -701,6 +900,52 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_put_console_scanner() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"patterns\": [\"Kernel panic\"], \"on_match\": \"log\" }";
+        sender
+            .write_all(http_request("PUT", "/console-scanner", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_vhost_user() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        // The body of a ping is optional.
+        sender
+            .write_all(http_request("PUT", "/vhost-user/rootfs/ping", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::PingVhostUser(VhostUserPingConfig {
+                id: "rootfs".to_string(),
+                timeout_ms: None,
+            })
+        );
+
+        let body = "{ \"timeout_ms\": 100 }";
+        sender
+            .write_all(http_request("PUT", "/vhost-user/rootfs/ping", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::PingVhostUser(VhostUserPingConfig {
+                id: "rootfs".to_string(),
+                timeout_ms: Some(100),
+            })
+        );
+    }
+
     #[test]
     fn test_try_from_put_entropy() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -6,15 +6,29 @@ use vmm::rpc_interface::VmmAction;
 use vmm::vmm_config::boot_source::BootSourceConfig;
 
 use super::super::parsed_request::{ParsedRequest, RequestError};
-use super::Body;
+use super::{Body, StatusCode};
 
 pub(crate) fn parse_put_boot_source(body: &Body) -> Result<ParsedRequest, RequestError> {
     METRICS.put_api_requests.boot_source_count.inc();
-    Ok(ParsedRequest::new_sync(VmmAction::ConfigureBootSource(
+    let boot_source_cfg =
         serde_json::from_slice::<BootSourceConfig>(body.raw()).map_err(|err| {
             METRICS.put_api_requests.boot_source_fails.inc();
             err
-        })?,
+        })?;
+
+    // File descriptors are inherited by the Firecracker process when it is started, so they can
+    // only be passed in the configuration file.
+    if boot_source_cfg.kernel_fd.is_some() || boot_source_cfg.initrd_fd.is_some() {
+        METRICS.put_api_requests.boot_source_fails.inc();
+        return Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "The kernel and initrd file descriptors can only be set in the configuration file."
+                .to_string(),
+        ));
+    }
+
+    Ok(ParsedRequest::new_sync(VmmAction::ConfigureBootSource(
+        boot_source_cfg,
     )))
 }
 
@@ -35,6 +49,7 @@ mod tests {
             kernel_image_path: String::from("/foo/bar"),
             initrd_path: Some(String::from("/bar/foo")),
             boot_args: Some(String::from("foobar")),
+            ..Default::default()
         };
         let parsed_req = parse_put_boot_source(&Body::new(body)).unwrap();
 
@@ -42,5 +57,15 @@ mod tests {
             parsed_req,
             ParsedRequest::new_sync(VmmAction::ConfigureBootSource(same_body))
         );
+
+        let body = r#"{
+            "kernel_fd": 3
+        }"#;
+        parse_put_boot_source(&Body::new(body)).unwrap_err();
+        let body = r#"{
+            "kernel_image_path": "/foo/bar",
+            "initrd_fd": 4
+        }"#;
+        parse_put_boot_source(&Body::new(body)).unwrap_err();
     }
 }
@@ -0,0 +1,46 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::console_scanner::ConsoleScannerConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::Body;
+
+pub(crate) fn parse_put_console_scanner(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<ConsoleScannerConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::SetConsoleScanner(cfg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::vmm_config::console_scanner::ConsoleMatchAction;
+
+    use super::*;
+    use crate::api_server::parsed_request::tests::vmm_action_from_request;
+
+    #[test]
+    fn test_parse_put_console_scanner_request() {
+        parse_put_console_scanner(&Body::new("invalid_payload")).unwrap_err();
+
+        // PUT with invalid fields.
+        let body = r#"{
+            "on_match": "reboot"
+        }"#;
+        parse_put_console_scanner(&Body::new(body)).unwrap_err();
+
+        // PUT with valid fields.
+        let body = r#"{
+            "patterns": ["Kernel panic - not syncing"],
+            "on_match": "pause"
+        }"#;
+        let expected_config = ConsoleScannerConfig {
+            patterns: Some(vec!["Kernel panic - not syncing".to_string()]),
+            on_match: ConsoleMatchAction::Pause,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_console_scanner(&Body::new(body)).unwrap()),
+            VmmAction::SetConsoleScanner(expected_config)
+        );
+    }
+}
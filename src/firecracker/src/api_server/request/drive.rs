@@ -139,6 +139,8 @@ mod tests {
             drive_id: "foo".to_string(),
             path_on_host: Some("dummy".to_string()),
             rate_limiter: None,
+            is_read_only: None,
+            retry_pending: None,
         };
         assert_eq!(
             vmm_action_from_request(parse_patch_drive(&Body::new(body), Some("foo")).unwrap()),
@@ -152,6 +154,34 @@ mod tests {
         // Must fail since the drive id differs from id_from_path (foo vs bar).
         parse_patch_drive(&Body::new(body), Some("bar")).unwrap_err();
 
+        let body = r#"{
+            "drive_id": "foo",
+            "is_read_only": true
+        }"#;
+        let expected_config = BlockDeviceUpdateConfig {
+            drive_id: "foo".to_string(),
+            is_read_only: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_patch_drive(&Body::new(body), Some("foo")).unwrap()),
+            VmmAction::UpdateBlockDevice(expected_config)
+        );
+
+        let body = r#"{
+            "drive_id": "foo",
+            "retry_pending": true
+        }"#;
+        let expected_config = BlockDeviceUpdateConfig {
+            drive_id: "foo".to_string(),
+            retry_pending: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_patch_drive(&Body::new(body), Some("foo")).unwrap()),
+            VmmAction::UpdateBlockDevice(expected_config)
+        );
+
         let body = r#"{
             "drive_id": "foo",
             "rate_limiter": {
@@ -231,6 +261,7 @@ mod tests {
             "is_read_only": true,
             "cache_type": "Unsafe",
             "io_engine": "Sync",
+            "detect_zeroes": "unmap",
             "rate_limiter": {
                 "bandwidth": {
                     "size": 0,
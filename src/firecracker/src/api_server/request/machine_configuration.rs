@@ -74,7 +74,12 @@ pub(crate) fn parse_patch_machine_config(body: &Body) -> Result<ParsedRequest, R
 #[cfg(test)]
 mod tests {
     use vmm::cpu_config::templates::StaticCpuTemplate;
-    use vmm::vmm_config::machine_config::HugePageConfig;
+    use vmm::vmm_config::auto_pause::{ActivitySignal, AutoPauseConfig};
+    use vmm::vmm_config::machine_config::{HugePageConfig, SmbiosConfig};
+    use vmm::vmm_config::readiness_probe::{
+        ReadinessCheck, ReadinessProbeConfig, DEFAULT_PROBE_MAX_ATTEMPTS,
+    };
+    use vmm::vmm_config::serial::SerialPortConfig;
 
     use super::*;
     use crate::api_server::parsed_request::tests::{depr_action_from_req, vmm_action_from_request};
@@ -123,6 +128,12 @@ mod tests {
                 cpu_template: None,
                 track_dirty_pages: Some(false),
                 huge_pages: Some(expected),
+                smbios: None,
+                gic: None,
+                readiness_probe: None,
+                serial_ports: None,
+                auto_pause: None,
+                hotplug_slots: None,
             };
             assert_eq!(
                 vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -142,6 +153,12 @@ mod tests {
             cpu_template: Some(StaticCpuTemplate::None),
             track_dirty_pages: Some(false),
             huge_pages: Some(HugePageConfig::None),
+            smbios: None,
+            gic: None,
+            readiness_probe: None,
+            serial_ports: None,
+            auto_pause: None,
+            hotplug_slots: None,
         };
         assert_eq!(
             vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -161,6 +178,12 @@ mod tests {
             cpu_template: None,
             track_dirty_pages: Some(true),
             huge_pages: Some(HugePageConfig::None),
+            smbios: None,
+            gic: None,
+            readiness_probe: None,
+            serial_ports: None,
+            auto_pause: None,
+            hotplug_slots: None,
         };
         assert_eq!(
             vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -184,6 +207,12 @@ mod tests {
                 cpu_template: Some(StaticCpuTemplate::T2),
                 track_dirty_pages: Some(true),
                 huge_pages: Some(HugePageConfig::None),
+                smbios: None,
+                gic: None,
+                readiness_probe: None,
+                serial_ports: None,
+                auto_pause: None,
+                hotplug_slots: None,
             };
             assert_eq!(
                 vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -209,6 +238,12 @@ mod tests {
             cpu_template: None,
             track_dirty_pages: Some(true),
             huge_pages: Some(HugePageConfig::None),
+            smbios: None,
+            gic: None,
+            readiness_probe: None,
+            serial_ports: None,
+            auto_pause: None,
+            hotplug_slots: None,
         };
         assert_eq!(
             vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -222,6 +257,162 @@ mod tests {
             "huge_pages": "7M"
         }"#;
         parse_put_machine_config(&Body::new(body)).unwrap_err();
+
+        // 7. Test the SMBIOS configuration.
+        let body = r#"{
+            "vcpu_count": 8,
+            "mem_size_mib": 1024,
+            "smbios": {
+                "manufacturer": "ACME",
+                "product_name": "Widget",
+                "oem_strings": ["first", "second"]
+            }
+        }"#;
+        let expected_config = MachineConfigUpdate {
+            vcpu_count: Some(8),
+            mem_size_mib: Some(1024),
+            smt: Some(false),
+            cpu_template: None,
+            track_dirty_pages: Some(false),
+            huge_pages: Some(HugePageConfig::None),
+            smbios: Some(SmbiosConfig {
+                manufacturer: Some("ACME".to_string()),
+                product_name: Some("Widget".to_string()),
+                serial_number: None,
+                uuid: None,
+                oem_strings: vec!["first".to_string(), "second".to_string()],
+            }),
+            gic: None,
+            readiness_probe: None,
+            serial_ports: None,
+            auto_pause: None,
+            hotplug_slots: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
+            VmmAction::UpdateVmConfiguration(expected_config)
+        );
+
+        // 8. Test unknown SMBIOS fields.
+        let body = r#"{
+            "vcpu_count": 8,
+            "mem_size_mib": 1024,
+            "smbios": {
+                "bios_vendor": "ACME"
+            }
+        }"#;
+        parse_put_machine_config(&Body::new(body)).unwrap_err();
+
+        // 9. Test the readiness probe.
+        let body = r#"{
+            "vcpu_count": 8,
+            "mem_size_mib": 1024,
+            "readiness_probe": {
+                "check": {"vsock": {"port": 52}},
+                "timeout_ms": 200
+            }
+        }"#;
+        let expected_config = MachineConfigUpdate {
+            vcpu_count: Some(8),
+            mem_size_mib: Some(1024),
+            smt: Some(false),
+            cpu_template: None,
+            track_dirty_pages: Some(false),
+            huge_pages: Some(HugePageConfig::None),
+            smbios: None,
+            gic: None,
+            readiness_probe: Some(ReadinessProbeConfig {
+                check: ReadinessCheck::Vsock { port: 52 },
+                timeout_ms: 200,
+                max_attempts: DEFAULT_PROBE_MAX_ATTEMPTS,
+            }),
+            serial_ports: None,
+            auto_pause: None,
+            hotplug_slots: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
+            VmmAction::UpdateVmConfiguration(expected_config)
+        );
+
+        // 10. Test the serial ports.
+        let body = r#"{
+            "vcpu_count": 8,
+            "mem_size_mib": 1024,
+            "serial_ports": [
+                {"id": "console", "output": "stdout", "input": true},
+                {"id": "mgmt", "output": "/tmp/mgmt.log"}
+            ]
+        }"#;
+        let expected_config = MachineConfigUpdate {
+            vcpu_count: Some(8),
+            mem_size_mib: Some(1024),
+            smt: Some(false),
+            cpu_template: None,
+            track_dirty_pages: Some(false),
+            huge_pages: Some(HugePageConfig::None),
+            smbios: None,
+            gic: None,
+            readiness_probe: None,
+            serial_ports: Some(vec![
+                SerialPortConfig {
+                    id: "console".to_string(),
+                    output: Some("stdout".to_string()),
+                    input: true,
+                },
+                SerialPortConfig {
+                    id: "mgmt".to_string(),
+                    output: Some("/tmp/mgmt.log".to_string()),
+                    input: false,
+                },
+            ]),
+            auto_pause: None,
+            hotplug_slots: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
+            VmmAction::UpdateVmConfiguration(expected_config)
+        );
+        let body = r#"{
+            "vcpu_count": 8,
+            "mem_size_mib": 1024,
+            "serial_ports": [{"id": "console", "baud_rate": 9600}]
+        }"#;
+        parse_put_machine_config(&Body::new(body)).unwrap_err();
+
+        // 11. Test the auto-pause.
+        let body = r#"{
+            "vcpu_count": 8,
+            "mem_size_mib": 1024,
+            "auto_pause": {"idle_seconds": 300, "signals": ["block", "net", "vsock", "vcpu_halt_pct"]}
+        }"#;
+        let expected_config = MachineConfigUpdate {
+            vcpu_count: Some(8),
+            mem_size_mib: Some(1024),
+            smt: Some(false),
+            cpu_template: None,
+            track_dirty_pages: Some(false),
+            huge_pages: Some(HugePageConfig::None),
+            smbios: None,
+            gic: None,
+            readiness_probe: None,
+            serial_ports: None,
+            auto_pause: Some(AutoPauseConfig {
+                idle_seconds: 300,
+                signals: ActivitySignal::ALL.to_vec(),
+            }),
+            hotplug_slots: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
+            VmmAction::UpdateVmConfiguration(expected_config)
+        );
+        let body = r#"{
+            "vcpu_count": 8,
+            "mem_size_mib": 1024,
+            "auto_pause": {"idle_seconds": 300, "signals": ["disk"]}
+        }"#;
+        parse_put_machine_config(&Body::new(body)).unwrap_err();
     }
 
     #[test]
@@ -3,7 +3,7 @@
 
 use vmm::logger::{IncMetric, METRICS};
 use vmm::rpc_interface::VmmAction;
-use vmm::vmm_config::metrics::MetricsConfig;
+use vmm::vmm_config::metrics::{MetricsConfig, MetricsVerbosity};
 
 use super::super::parsed_request::{ParsedRequest, RequestError};
 use super::Body;
@@ -30,9 +30,20 @@ mod tests {
         let body = r#"{
             "metrics_path": "metrics"
         }"#;
-        let expected_config = MetricsConfig {
+        let mut expected_config = MetricsConfig {
             metrics_path: PathBuf::from("metrics"),
+            verbosity: MetricsVerbosity::Default,
         };
+        assert_eq!(
+            vmm_action_from_request(parse_put_metrics(&Body::new(body)).unwrap()),
+            VmmAction::ConfigureMetrics(expected_config.clone())
+        );
+
+        let body = r#"{
+            "metrics_path": "metrics",
+            "verbosity": "Detailed"
+        }"#;
+        expected_config.verbosity = MetricsVerbosity::Detailed;
         assert_eq!(
             vmm_action_from_request(parse_put_metrics(&Body::new(body)).unwrap()),
             VmmAction::ConfigureMetrics(expected_config)
@@ -10,9 +10,18 @@ use vmm::vmm_config::mmds::MmdsConfig;
 use super::super::parsed_request::{ParsedRequest, RequestError};
 use super::Body;
 
-pub(crate) fn parse_get_mmds() -> Result<ParsedRequest, RequestError> {
+pub(crate) fn parse_get_mmds(
+    path_second_token: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
     METRICS.get_api_requests.mmds_count.inc();
-    Ok(ParsedRequest::new_sync(VmmAction::GetMMDS))
+    match path_second_token {
+        None => Ok(ParsedRequest::new_sync(VmmAction::GetMMDS)),
+        Some("info") => Ok(ParsedRequest::new_sync(VmmAction::GetMmdsInfo)),
+        Some(unrecognized) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized GET request path `{}`.", unrecognized),
+        )),
+    }
 }
 
 fn parse_put_mmds_config(body: &Body) -> Result<ParsedRequest, RequestError> {
@@ -71,12 +80,21 @@ pub(crate) fn parse_patch_mmds(body: &Body) -> Result<ParsedRequest, RequestErro
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::api_server::parsed_request::tests::depr_action_from_req;
+    use crate::api_server::parsed_request::tests::{depr_action_from_req, vmm_action_from_request};
 
     #[test]
     fn test_parse_get_mmds_request() {
-        parse_get_mmds().unwrap();
+        assert_eq!(
+            vmm_action_from_request(parse_get_mmds(None).unwrap()),
+            VmmAction::GetMMDS
+        );
         assert!(METRICS.get_api_requests.mmds_count.count() > 0);
+
+        assert_eq!(
+            vmm_action_from_request(parse_get_mmds(Some("info")).unwrap()),
+            VmmAction::GetMmdsInfo
+        );
+        parse_get_mmds(Some("invalid_path")).unwrap_err();
     }
 
     #[test]
@@ -104,6 +122,28 @@ mod tests {
         }"#;
         parse_put_mmds(&Body::new(body), Some(config_path)).unwrap();
 
+        let body = r#"{
+            "version": "V2",
+            "network_interfaces": ["eth0"],
+            "data_store_limit_mib": 2
+        }"#;
+        let expected_config = MmdsConfig {
+            version: MmdsVersion::V2,
+            network_interfaces: vec!["eth0".to_string()],
+            ipv4_address: None,
+            data_store_limit_mib: Some(2),
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_mmds(&Body::new(body), Some(config_path)).unwrap()),
+            VmmAction::SetMmdsConfiguration(expected_config)
+        );
+
+        let body = r#"{
+            "network_interfaces": [],
+            "data_store_limit_mib": -1
+        }"#;
+        parse_put_mmds(&Body::new(body), Some(config_path)).unwrap_err();
+
         let body = r#"{
             "version": "foo",
             "ipv4_address": "169.254.170.2",
@@ -4,6 +4,7 @@
 pub mod actions;
 pub mod balloon;
 pub mod boot_source;
+pub mod console_scanner;
 pub mod cpu_configuration;
 pub mod drive;
 pub mod entropy;
@@ -15,5 +16,6 @@ pub mod mmds;
 pub mod net;
 pub mod snapshot;
 pub mod version;
+pub mod vhost_user;
 pub mod vsock;
 pub use micro_http::{Body, Method, StatusCode};
@@ -75,6 +75,8 @@ pub(crate) fn parse_patch_net(
 
 #[cfg(test)]
 mod tests {
+    use vmm::devices::virtio::net::NetOffloads;
+
     use super::*;
     use crate::api_server::parsed_request::tests::vmm_action_from_request;
 
@@ -114,6 +116,66 @@ mod tests {
             }
         }"#;
         parse_put_net(&Body::new(body), Some("foo")).unwrap_err();
+
+        // 5. Offloads, with the unset ones offered.
+        let body = r#"{
+            "iface_id": "foo",
+            "host_dev_name": "bar",
+            "offloads": {
+                "guest_tso4": false,
+                "guest_ufo": false
+            }
+        }"#;
+        let config = serde_json::from_str::<NetworkInterfaceConfig>(body).unwrap();
+        assert_eq!(
+            config.offloads,
+            Some(NetOffloads {
+                guest_tso4: false,
+                guest_ufo: false,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vmm_action_from_request(parse_put_net(&Body::new(body), Some("foo")).unwrap()),
+            VmmAction::InsertNetworkDevice(config)
+        );
+
+        // 6. Serde error for an unknown offload.
+        let body = r#"{
+            "iface_id": "foo",
+            "host_dev_name": "bar",
+            "offloads": {
+                "guest_tso6": false
+            }
+        }"#;
+        parse_put_net(&Body::new(body), Some("foo")).unwrap_err();
+
+        // 7. MTU, absent by default.
+        let body = r#"{
+            "iface_id": "foo",
+            "host_dev_name": "bar",
+            "mtu": 9000
+        }"#;
+        let config = serde_json::from_str::<NetworkInterfaceConfig>(body).unwrap();
+        assert_eq!(config.mtu, Some(9000));
+        assert_eq!(
+            vmm_action_from_request(parse_put_net(&Body::new(body), Some("foo")).unwrap()),
+            VmmAction::InsertNetworkDevice(config)
+        );
+        let body = r#"{
+            "iface_id": "foo",
+            "host_dev_name": "bar"
+        }"#;
+        let config = serde_json::from_str::<NetworkInterfaceConfig>(body).unwrap();
+        assert_eq!(config.mtu, None);
+
+        // 8. Serde error for an MTU which doesn't fit in 16 bits.
+        let body = r#"{
+            "iface_id": "foo",
+            "host_dev_name": "bar",
+            "mtu": 65536
+        }"#;
+        parse_put_net(&Body::new(body), Some("foo")).unwrap_err();
     }
 
     #[test]
@@ -152,5 +214,40 @@ mod tests {
             }
         }"#;
         parse_patch_net(&Body::new(body), Some("foo")).unwrap_err();
+
+        // 5. The offloads can not be changed after the interface is created.
+        let body = r#"{
+            "iface_id": "foo",
+            "offloads": {
+                "guest_tso4": false
+            }
+        }"#;
+        parse_patch_net(&Body::new(body), Some("foo")).unwrap_err();
+
+        // 6. The guest MAC address can.
+        let body = r#"{
+            "iface_id": "foo",
+            "guest_mac": "06:00:c0:a8:34:03"
+        }"#;
+        let expected_config = serde_json::from_str::<NetworkInterfaceUpdateConfig>(body).unwrap();
+        assert_eq!(
+            expected_config.guest_mac.unwrap().to_string(),
+            "06:00:c0:a8:34:03"
+        );
+        assert_eq!(
+            vmm_action_from_request(parse_patch_net(&Body::new(body), Some("foo")).unwrap()),
+            VmmAction::UpdateNetworkInterface(expected_config)
+        );
+
+        // 7. Serde error for malformed MAC addresses.
+        for guest_mac in [
+            "06:00:c0:a8:34",
+            "06:00:c0:a8:34:0g",
+            "06-00-c0-a8-34-03",
+            "",
+        ] {
+            let body = format!(r#"{{"iface_id": "foo", "guest_mac": "{guest_mac}"}}"#);
+            parse_patch_net(&Body::new(body.as_bytes()), Some("foo")).unwrap_err();
+        }
     }
 }
@@ -5,8 +5,8 @@ use serde::de::Error as DeserializeError;
 use vmm::logger::{IncMetric, METRICS};
 use vmm::rpc_interface::VmmAction;
 use vmm::vmm_config::snapshot::{
-    CreateSnapshotParams, LoadSnapshotConfig, LoadSnapshotParams, MemBackendConfig, MemBackendType,
-    Vm, VmState,
+    CloneMicrovmParams, CreateSnapshotParams, LoadSnapshotConfig, LoadSnapshotParams,
+    MemBackendConfig, MemBackendType, Vm, VmState,
 };
 
 use super::super::parsed_request::{ParsedRequest, RequestError};
@@ -28,6 +28,7 @@ pub(crate) fn parse_put_snapshot(
 ) -> Result<ParsedRequest, RequestError> {
     match request_type_from_path {
         Some(request_type) => match request_type {
+            "clone" => parse_put_snapshot_clone(body),
             "create" => parse_put_snapshot_create(body),
             "load" => parse_put_snapshot_load(body),
             _ => Err(RequestError::InvalidPathMethod(
@@ -42,6 +43,22 @@ pub(crate) fn parse_put_snapshot(
     }
 }
 
+pub(crate) fn parse_get_snapshot(
+    request_type_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    match request_type_from_path {
+        Some("access-profile") => Ok(ParsedRequest::new_sync(VmmAction::GetAccessProfile)),
+        Some(request_type) => Err(RequestError::InvalidPathMethod(
+            format!("/snapshot/{}", request_type),
+            Method::Get,
+        )),
+        None => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "Missing snapshot operation type.".to_string(),
+        )),
+    }
+}
+
 pub(crate) fn parse_patch_vm_state(body: &Body) -> Result<ParsedRequest, RequestError> {
     let vm = serde_json::from_slice::<Vm>(body.raw())?;
 
@@ -51,6 +68,13 @@ pub(crate) fn parse_patch_vm_state(body: &Body) -> Result<ParsedRequest, Request
     }
 }
 
+fn parse_put_snapshot_clone(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let clone_config = serde_json::from_slice::<CloneMicrovmParams>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::CloneMicrovm(
+        clone_config,
+    )))
+}
+
 fn parse_put_snapshot_create(body: &Body) -> Result<ParsedRequest, RequestError> {
     let snapshot_config = serde_json::from_slice::<CreateSnapshotParams>(body.raw())?;
     Ok(ParsedRequest::new_sync(VmmAction::CreateSnapshot(
@@ -105,6 +129,8 @@ fn parse_put_snapshot_load(body: &Body) -> Result<ParsedRequest, RequestError> {
         mem_backend,
         enable_diff_snapshots: snapshot_config.enable_diff_snapshots,
         resume_vm: snapshot_config.resume_vm,
+        track_access: snapshot_config.track_access,
+        network_overrides: snapshot_config.network_overrides,
     };
 
     // Construct the `ParsedRequest` object.
@@ -181,6 +207,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: false,
+            track_access: None,
+            network_overrides: vec![],
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(parsed_request
@@ -208,6 +236,8 @@ mod tests {
             },
             enable_diff_snapshots: true,
             resume_vm: false,
+            track_access: None,
+            network_overrides: vec![],
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(parsed_request
@@ -235,6 +265,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: true,
+            track_access: None,
+            network_overrides: vec![],
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(parsed_request
@@ -259,6 +291,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: true,
+            track_access: None,
+            network_overrides: vec![],
         };
         let parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert_eq!(
@@ -343,6 +377,176 @@ mod tests {
         parse_put_snapshot(&Body::new(body), None).unwrap_err();
     }
 
+    #[test]
+    fn test_parse_put_snapshot_load_network_overrides() {
+        use std::path::PathBuf;
+
+        use vmm::vmm_config::snapshot::LoadNetworkOverride;
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_backend": {
+                "backend_path": "bar",
+                "backend_type": "File"
+            },
+            "network_overrides": [
+                {
+                    "iface_id": "eth0",
+                    "tap_fd": 3
+                },
+                {
+                    "iface_id": "eth1",
+                    "guest_announce": false
+                }
+            ]
+        }"#;
+        let expected_config = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            mem_backend: MemBackendConfig {
+                backend_path: PathBuf::from("bar"),
+                backend_type: MemBackendType::File,
+            },
+            enable_diff_snapshots: false,
+            resume_vm: false,
+            track_access: None,
+            network_overrides: vec![
+                LoadNetworkOverride {
+                    iface_id: "eth0".to_string(),
+                    tap_fd: Some(3),
+                    guest_announce: true,
+                },
+                LoadNetworkOverride {
+                    iface_id: "eth1".to_string(),
+                    tap_fd: None,
+                    guest_announce: false,
+                },
+            ],
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("load")).unwrap()),
+            VmmAction::LoadSnapshot(expected_config)
+        );
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_backend": {
+                "backend_path": "bar",
+                "backend_type": "File"
+            },
+            "network_overrides": [
+                {
+                    "tap_fd": 3
+                }
+            ]
+        }"#;
+        parse_put_snapshot(&Body::new(body), Some("load")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_snapshot_load_track_access() {
+        use std::path::PathBuf;
+
+        use vmm::vmm_config::snapshot::TrackAccessConfig;
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_backend": {
+                "backend_path": "bar",
+                "backend_type": "Uffd"
+            },
+            "resume_vm": true,
+            "track_access": {
+                "duration_s": 10
+            }
+        }"#;
+        let expected_config = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            mem_backend: MemBackendConfig {
+                backend_path: PathBuf::from("bar"),
+                backend_type: MemBackendType::Uffd,
+            },
+            enable_diff_snapshots: false,
+            resume_vm: true,
+            track_access: Some(TrackAccessConfig { duration_s: 10 }),
+            network_overrides: vec![],
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("load")).unwrap()),
+            VmmAction::LoadSnapshot(expected_config)
+        );
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_backend": {
+                "backend_path": "bar",
+                "backend_type": "Uffd"
+            },
+            "track_access": {
+                "duration_s": 10,
+                "invalid_field": true
+            }
+        }"#;
+        parse_put_snapshot(&Body::new(body), Some("load")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_get_snapshot() {
+        assert_eq!(
+            vmm_action_from_request(parse_get_snapshot(Some("access-profile")).unwrap()),
+            VmmAction::GetAccessProfile
+        );
+        parse_get_snapshot(Some("load")).unwrap_err();
+        parse_get_snapshot(None).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_snapshot_clone() {
+        use std::path::PathBuf;
+
+        use vmm::vmm_config::snapshot::CloneNetworkOverride;
+
+        let body = r#"{
+            "socket": "/tmp/clone.sock"
+        }"#;
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("clone")).unwrap()),
+            VmmAction::CloneMicrovm(CloneMicrovmParams {
+                socket: PathBuf::from("/tmp/clone.sock"),
+                network_overrides: vec![],
+            })
+        );
+
+        let body = r#"{
+            "socket": "/tmp/clone.sock",
+            "network_overrides": [
+                {
+                    "iface_id": "eth0",
+                    "host_dev_name": "tap1"
+                }
+            ]
+        }"#;
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("clone")).unwrap()),
+            VmmAction::CloneMicrovm(CloneMicrovmParams {
+                socket: PathBuf::from("/tmp/clone.sock"),
+                network_overrides: vec![CloneNetworkOverride {
+                    iface_id: "eth0".to_string(),
+                    host_dev_name: "tap1".to_string(),
+                }],
+            })
+        );
+
+        let body = r#"{
+            "network_overrides": []
+        }"#;
+        parse_put_snapshot(&Body::new(body), Some("clone")).unwrap_err();
+        let body = r#"{
+            "socket": "/tmp/clone.sock",
+            "resume_vm": true
+        }"#;
+        parse_put_snapshot(&Body::new(body), Some("clone")).unwrap_err();
+    }
+
     #[test]
     fn test_parse_patch_vm_state() {
         let body = r#"{
@@ -0,0 +1,113 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::vhost_user::VhostUserPingConfig;
+
+use super::super::parsed_request::{checked_id, ParsedRequest, RequestError};
+use super::{Body, StatusCode};
+
+pub(crate) fn parse_get_vhost_user(
+    id_from_path: Option<&str>,
+    action_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    let id = checked_id(id_from_path.ok_or(RequestError::EmptyID)?)?;
+    match action_from_path {
+        Some("info") => Ok(ParsedRequest::new_sync(VmmAction::GetVhostUserInfo(
+            id.to_string(),
+        ))),
+        _ => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!(
+                "Unrecognized GET request path `{}`.",
+                action_from_path.unwrap_or_default()
+            ),
+        )),
+    }
+}
+
+pub(crate) fn parse_put_vhost_user(
+    body: Option<&Body>,
+    id_from_path: Option<&str>,
+    action_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    let id = checked_id(id_from_path.ok_or(RequestError::EmptyID)?)?;
+    match action_from_path {
+        Some("ping") => {
+            // The body is optional, all the fields of the ping having defaults.
+            let mut ping_cfg = match body {
+                Some(body) => serde_json::from_slice::<VhostUserPingConfig>(body.raw())?,
+                None => VhostUserPingConfig::default(),
+            };
+            ping_cfg.id = id.to_string();
+            Ok(ParsedRequest::new_sync(VmmAction::PingVhostUser(ping_cfg)))
+        }
+        _ => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!(
+                "Unrecognized PUT request path `{}`.",
+                action_from_path.unwrap_or_default()
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_server::parsed_request::tests::vmm_action_from_request;
+
+    #[test]
+    fn test_parse_get_vhost_user_request() {
+        assert_eq!(
+            vmm_action_from_request(parse_get_vhost_user(Some("drive"), Some("info")).unwrap()),
+            VmmAction::GetVhostUserInfo("drive".to_string())
+        );
+
+        parse_get_vhost_user(None, None).unwrap_err();
+        parse_get_vhost_user(Some("drive"), None).unwrap_err();
+        parse_get_vhost_user(Some("drive"), Some("ping")).unwrap_err();
+        parse_get_vhost_user(Some("drive!"), Some("info")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_vhost_user_request() {
+        // Without a body, the default timeout is used.
+        assert_eq!(
+            vmm_action_from_request(
+                parse_put_vhost_user(None, Some("drive"), Some("ping")).unwrap()
+            ),
+            VmmAction::PingVhostUser(VhostUserPingConfig {
+                id: "drive".to_string(),
+                timeout_ms: None,
+            })
+        );
+
+        let body = r#"{
+            "timeout_ms": 200
+        }"#;
+        assert_eq!(
+            vmm_action_from_request(
+                parse_put_vhost_user(Some(&Body::new(body)), Some("drive"), Some("ping")).unwrap()
+            ),
+            VmmAction::PingVhostUser(VhostUserPingConfig {
+                id: "drive".to_string(),
+                timeout_ms: Some(200),
+            })
+        );
+
+        // The id only comes from the path.
+        let body = r#"{
+            "id": "other_drive"
+        }"#;
+        parse_put_vhost_user(Some(&Body::new(body)), Some("drive"), Some("ping")).unwrap_err();
+        parse_put_vhost_user(
+            Some(&Body::new("invalid_payload")),
+            Some("drive"),
+            Some("ping"),
+        )
+        .unwrap_err();
+        parse_put_vhost_user(None, None, Some("ping")).unwrap_err();
+        parse_put_vhost_user(None, Some("drive"), Some("info")).unwrap_err();
+    }
+}
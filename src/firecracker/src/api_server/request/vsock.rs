@@ -6,7 +6,23 @@ use vmm::rpc_interface::VmmAction;
 use vmm::vmm_config::vsock::VsockDeviceConfig;
 
 use super::super::parsed_request::{ParsedRequest, RequestError};
-use super::Body;
+use super::{Body, Method};
+
+pub(crate) fn parse_get_vsock(
+    request_type_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    match request_type_from_path {
+        Some("connections") => Ok(ParsedRequest::new_sync(VmmAction::GetVsockConnections)),
+        Some(request_type) => Err(RequestError::InvalidPathMethod(
+            format!("/vsock/{}", request_type),
+            Method::Get,
+        )),
+        None => Err(RequestError::InvalidPathMethod(
+            "/vsock".to_string(),
+            Method::Get,
+        )),
+    }
+}
 
 pub(crate) fn parse_put_vsock(body: &Body) -> Result<ParsedRequest, RequestError> {
     METRICS.put_api_requests.vsock_count.inc();
@@ -36,7 +52,17 @@ pub(crate) fn parse_put_vsock(body: &Body) -> Result<ParsedRequest, RequestError
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::api_server::parsed_request::tests::depr_action_from_req;
+    use crate::api_server::parsed_request::tests::{depr_action_from_req, vmm_action_from_request};
+
+    #[test]
+    fn test_parse_get_vsock_request() {
+        assert_eq!(
+            vmm_action_from_request(parse_get_vsock(Some("connections")).unwrap()),
+            VmmAction::GetVsockConnections
+        );
+        parse_get_vsock(Some("listeners")).unwrap_err();
+        parse_get_vsock(None).unwrap_err();
+    }
 
     #[test]
     fn test_parse_put_vsock_request() {
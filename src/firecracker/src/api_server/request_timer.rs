@@ -0,0 +1,231 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use micro_http::Method;
+use utils::time::{get_time_us, ClockType};
+use vmm::logger::{debug, warn, METRICS};
+
+/// Path template of the requests which do not target a known endpoint.
+pub(crate) const UNKNOWN_ENDPOINT: &str = "unknown";
+
+/// Times the phases of the processing of an API request, from the moment the HTTP request is
+/// parsed by the HTTP server to the moment its response is handed back to it:
+/// * parse: the request is parsed into a VMM action, by the dispatcher;
+/// * VMM action: the VMM action is sent to the VMM thread, which executes it and sends back its
+///   outcome;
+/// * serialization: the outcome is turned into an HTTP response, and handed to the HTTP server.
+#[derive(Debug)]
+pub struct RequestTimer {
+    start_us: u64,
+    // Method and path template of the request, known once it is parsed.
+    endpoint: Option<(Method, &'static str)>,
+    parsed_us: Option<u64>,
+    vmm_action_sent_us: Option<u64>,
+    vmm_action_done_us: Option<u64>,
+}
+
+/// Duration of the phases of the processing of an API request, in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RequestLatencies {
+    /// Total duration.
+    pub(crate) total_us: u64,
+    /// Time spent parsing the request into a VMM action.
+    pub(crate) parse_us: u64,
+    /// Time spent waiting for the VMM thread to execute the VMM action, if any.
+    pub(crate) vmm_action_us: Option<u64>,
+    /// Time spent turning the outcome of the request into an HTTP response.
+    pub(crate) serialize_us: u64,
+}
+
+impl RequestTimer {
+    /// Starts timing a request whose HTTP request was parsed at `start_us`, in microseconds
+    /// on the monotonic clock.
+    pub fn new(start_us: u64) -> Self {
+        RequestTimer {
+            start_us,
+            endpoint: None,
+            parsed_us: None,
+            vmm_action_sent_us: None,
+            vmm_action_done_us: None,
+        }
+    }
+
+    /// Time at which the HTTP request was parsed, in microseconds on the monotonic clock.
+    pub fn start_us(&self) -> u64 {
+        self.start_us
+    }
+
+    /// Marks the end of the parsing of the request with `method` on `path_template`.
+    pub(crate) fn request_parsed(&mut self, method: Method, path_template: &'static str) {
+        self.endpoint = Some((method, path_template));
+        self.parsed_us = Some(get_time_us(ClockType::Monotonic));
+    }
+
+    /// Marks the VMM action of the request as sent to the VMM thread.
+    pub(crate) fn vmm_action_sent(&mut self) {
+        self.vmm_action_sent_us = Some(get_time_us(ClockType::Monotonic));
+    }
+
+    /// Marks the outcome of the VMM action as received from the VMM thread.
+    pub(crate) fn vmm_action_done(&mut self) {
+        self.vmm_action_done_us = Some(get_time_us(ClockType::Monotonic));
+    }
+
+    /// Method and path template of the request, as "$method $path_template".
+    fn endpoint(&self) -> String {
+        match self.endpoint {
+            Some((method, path_template)) => format!("{} {}", method.to_str(), path_template),
+            None => UNKNOWN_ENDPOINT.to_string(),
+        }
+    }
+
+    /// Computes the duration of the phases of the request, whose response was handed to the
+    /// HTTP server at `end_us`.
+    fn latencies(&self, end_us: u64) -> RequestLatencies {
+        let total_us = end_us.saturating_sub(self.start_us);
+        let parse_us = self
+            .parsed_us
+            .map_or(0, |parsed_us| parsed_us.saturating_sub(self.start_us));
+        let vmm_action_us = self
+            .vmm_action_sent_us
+            .zip(self.vmm_action_done_us)
+            .map(|(sent_us, done_us)| done_us.saturating_sub(sent_us));
+        RequestLatencies {
+            total_us,
+            parse_us,
+            vmm_action_us,
+            serialize_us: total_us
+                .saturating_sub(parse_us)
+                .saturating_sub(vmm_action_us.unwrap_or(0)),
+        }
+    }
+
+    /// Records the latencies of the request, whose response was handed to the HTTP server at
+    /// `end_us`, in the metrics of its endpoint, if it was parsed.
+    pub(crate) fn record(&self, end_us: u64) -> RequestLatencies {
+        let latencies = self.latencies(end_us);
+        if let Some((method, path_template)) = self.endpoint {
+            let metrics = METRICS
+                .api_server
+                .endpoints
+                .get(method.to_str(), path_template);
+            metrics.total.record(latencies.total_us);
+            metrics.parse.record(latencies.parse_us);
+            if let Some(vmm_action_us) = latencies.vmm_action_us {
+                metrics.vmm_action.record(vmm_action_us);
+            }
+            metrics.serialize.record(latencies.serialize_us);
+        }
+        latencies
+    }
+
+    /// Returns the warning logged for the request if it took longer than `threshold_us`.
+    pub(crate) fn slow_request_warning(
+        &self,
+        latencies: &RequestLatencies,
+        threshold_us: u64,
+    ) -> Option<String> {
+        if latencies.total_us <= threshold_us {
+            return None;
+        }
+        let vmm_action = match latencies.vmm_action_us {
+            Some(vmm_action_us) => format!("{} us", vmm_action_us),
+            None => "none".to_string(),
+        };
+        Some(format!(
+            "Slow API request: '{}' took {} us, above the threshold of {} us (parse: {} us, VMM \
+             action: {}, serialization: {} us).",
+            self.endpoint(),
+            latencies.total_us,
+            threshold_us,
+            latencies.parse_us,
+            vmm_action,
+            latencies.serialize_us
+        ))
+    }
+
+    /// Ends the timing of the request, whose response was just handed to the HTTP server:
+    /// records its latencies in the metrics, and logs a warning if it took longer than
+    /// `slow_threshold_us`.
+    pub fn finish(self, slow_threshold_us: u64) {
+        let latencies = self.record(get_time_us(ClockType::Monotonic));
+        debug!(
+            "Total previous API call duration: {} us.",
+            latencies.total_us
+        );
+        if let Some(message) = self.slow_request_warning(&latencies, slow_threshold_us) {
+            warn!("{}", message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::logger::IncMetric;
+
+    use super::*;
+
+    #[test]
+    fn test_latencies() {
+        let mut timer = RequestTimer::new(1_000);
+        timer.endpoint = Some((Method::Put, "/drives/{drive_id}"));
+        timer.parsed_us = Some(1_100);
+        timer.vmm_action_sent_us = Some(1_150);
+        timer.vmm_action_done_us = Some(3_150);
+        let latencies = timer.latencies(3_400);
+        assert_eq!(
+            latencies,
+            RequestLatencies {
+                total_us: 2_400,
+                parse_us: 100,
+                vmm_action_us: Some(2_000),
+                serialize_us: 300,
+            }
+        );
+
+        assert_eq!(timer.slow_request_warning(&latencies, 2_400), None);
+        assert_eq!(
+            timer.slow_request_warning(&latencies, 2_000).unwrap(),
+            "Slow API request: 'PUT /drives/{drive_id}' took 2400 us, above the threshold of 2000 \
+             us (parse: 100 us, VMM action: 2000 us, serialization: 300 us)."
+        );
+
+        // Requests rejected while being parsed have no VMM action.
+        let mut timer = RequestTimer::new(1_000);
+        timer.parsed_us = Some(1_100);
+        let latencies = timer.latencies(1_200);
+        assert_eq!(
+            latencies,
+            RequestLatencies {
+                total_us: 200,
+                parse_us: 100,
+                vmm_action_us: None,
+                serialize_us: 100,
+            }
+        );
+        assert_eq!(
+            timer.slow_request_warning(&latencies, 0).unwrap(),
+            "Slow API request: 'unknown' took 200 us, above the threshold of 0 us (parse: 100 us, \
+             VMM action: none, serialization: 100 us)."
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let mut timer = RequestTimer::new(0);
+        timer.endpoint = Some((Method::Patch, "/request-timer-test"));
+        timer.parsed_us = Some(50);
+        timer.vmm_action_sent_us = Some(50);
+        timer.vmm_action_done_us = Some(250_050);
+        timer.record(250_100);
+
+        let metrics = METRICS
+            .api_server
+            .endpoints
+            .get("PATCH", "/request-timer-test");
+        assert_eq!(metrics.total.le_1s.count(), 1);
+        assert_eq!(metrics.parse.le_100us.count(), 1);
+        assert_eq!(metrics.vmm_action.le_1s.count(), 1);
+        assert_eq!(metrics.serialize.le_100us.count(), 1);
+    }
+}
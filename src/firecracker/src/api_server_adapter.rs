@@ -1,16 +1,20 @@
 // Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::VecDeque;
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::{fmt, thread};
 
 use event_manager::{EventOps, Events, MutEventSubscriber, SubscriberOps};
-use seccompiler::BpfThreadMap;
+use micro_http::ServerRequest;
+use seccompiler::{BpfProgram, BpfThreadMap};
 use utils::epoll::EventSet;
 use utils::eventfd::EventFd;
+use utils::time::{get_time_us, ClockType};
+use vmm::events::{VmmEvent, EVENTS};
 use vmm::logger::{error, warn, ProcessTimeReporter};
 use vmm::resources::VmResources;
 use vmm::rpc_interface::{
@@ -20,7 +24,9 @@ use vmm::rpc_interface::{
 use vmm::vmm_config::instance_info::InstanceInfo;
 use vmm::{EventManager, FcExitCode, Vmm};
 
-use super::api_server::{ApiServer, HttpServer, ServerError};
+use super::api_server::{
+    handle_request, ApiServer, HttpServer, RequestTimer, ServerError, VmmActionResult,
+};
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum ApiServerError {
@@ -34,6 +40,8 @@ pub enum ApiServerError {
     FailedToBindAndRunHttpServer(ServerError),
     /// Failed to build MicroVM from Json: {0}
     BuildFromJson(crate::BuildFromJsonError),
+    /// Failed to restore the cloned MicroVM: {0}
+    BuildFromClone(vmm::persist::RestoreFromCloneError),
 }
 
 #[derive(Debug)]
@@ -62,18 +70,7 @@ impl ApiServerAdapter {
             controller: RuntimeApiController::new(vm_resources, vmm.clone()),
         }));
         event_manager.add_subscriber(api_adapter);
-        loop {
-            event_manager
-                .run()
-                .expect("EventManager events driver fatal error");
-
-            match vmm.lock().unwrap().shutdown_exit_code() {
-                Some(FcExitCode::Ok) => break,
-                Some(exit_code) => return Err(ApiServerError::MicroVMStoppedWithError(exit_code)),
-                None => continue,
-            }
-        }
-        Ok(())
+        run_event_loop(&vmm, event_manager)
     }
 
     fn handle_request(&mut self, req_action: VmmAction) {
@@ -135,17 +132,301 @@ impl MutEventSubscriber for ApiServerAdapter {
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-pub(crate) fn run_with_api(
-    seccomp_filters: &mut BpfThreadMap,
+/// Runs the event loop until the microVM stops.
+fn run_event_loop(
+    vmm: &Arc<Mutex<Vmm>>,
+    event_manager: &mut EventManager,
+) -> Result<(), ApiServerError> {
+    loop {
+        event_manager
+            .run()
+            .expect("EventManager events driver fatal error");
+
+        let mut locked_vmm = vmm.lock().unwrap();
+        // Devices hot-plugged or unplugged by the API requests handled above are not known to the
+        // event manager yet.
+        locked_vmm.update_event_subscribers(event_manager);
+
+        match locked_vmm.shutdown_exit_code() {
+            Some(FcExitCode::Ok) => break,
+            Some(exit_code) => return Err(ApiServerError::MicroVMStoppedWithError(exit_code)),
+            None => continue,
+        }
+    }
+    Ok(())
+}
+
+/// Name of the API requests which block the VMM thread until they are done, as reported by the
+/// `api_blocked` and `api_unblocked` events.
+fn blocking_action_name(vmm_action: &VmmAction) -> Option<&'static str> {
+    match vmm_action {
+        VmmAction::CreateSnapshot(_) => Some("create_snapshot"),
+        VmmAction::LoadSnapshot(_) => Some("load_snapshot"),
+        VmmAction::CloneMicrovm(_) => Some("clone_microvm"),
+        _ => None,
+    }
+}
+
+/// Runs `vmm_action` with `run`, around `api_blocked` and `api_unblocked` events if the VMM
+/// thread is blocked until it is done.
+fn run_vmm_action<F>(vmm_action: Box<VmmAction>, run: F) -> VmmActionResult
+where
+    F: FnOnce(VmmAction) -> VmmActionResult,
+{
+    let blocking_action = blocking_action_name(&vmm_action);
+    if let Some(action) = blocking_action {
+        EVENTS.publish(VmmEvent::ApiBlocked {
+            action: action.to_owned(),
+        });
+    }
+    let result = run(*vmm_action);
+    if let Some(action) = blocking_action {
+        EVENTS.publish(VmmEvent::ApiUnblocked {
+            action: action.to_owned(),
+        });
+    }
+    result
+}
+
+/// HTTP server serving the API on the VMM thread, in single-threaded API mode.
+struct SingleThreadApi {
+    server: HttpServer,
+    /// Requests received but not served yet.
+    pending: VecDeque<ServerRequest>,
+    /// Written while requests are pending, to serve them in the next iterations of the event loop.
+    pending_evt: EventFd,
+    slow_request_threshold_ms: u64,
+}
+
+// TODO Remove when `HttpServer` and `ServerRequest` implement `std::fmt::Debug`.
+impl fmt::Debug for SingleThreadApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SingleThreadApi")
+            .field("server", &"?")
+            .field("pending", &self.pending.len())
+            .field("pending_evt", &self.pending_evt)
+            .field("slow_request_threshold_ms", &self.slow_request_threshold_ms)
+            .finish()
+    }
+}
+
+impl SingleThreadApi {
+    fn new(
+        mut server: HttpServer,
+        api_payload_limit: usize,
+        slow_request_threshold_ms: u64,
+    ) -> Result<Self, ServerError> {
+        server.set_payload_max_size(api_payload_limit);
+        server.start_server()?;
+        Ok(Self {
+            server,
+            pending: VecDeque::new(),
+            pending_evt: EventFd::new(libc::EFD_NONBLOCK)
+                .expect("Cannot create API pending requests eventfd."),
+            slow_request_threshold_ms,
+        })
+    }
+
+    /// Reads the requests received by the server and writes the queued responses, blocking until
+    /// there is some activity on the API socket.
+    fn read_requests(&mut self) {
+        match self.server.requests() {
+            Ok(requests) => self.pending.extend(requests),
+            Err(err) => error!("API Server error on retrieving incoming request: {}", err),
+        }
+    }
+
+    /// Serves the oldest pending request, running its VMM action with `run_vmm_action`.
+    ///
+    /// Returns whether a request was pending.
+    fn serve_request<F>(&mut self, mut run_vmm_action: F) -> bool
+    where
+        F: FnMut(Box<VmmAction>) -> VmmActionResult,
+    {
+        let server_request = match self.pending.pop_front() {
+            Some(server_request) => server_request,
+            None => return false,
+        };
+
+        let mut request_timer = RequestTimer::new(get_time_us(ClockType::Monotonic));
+        let response = server_request
+            .process(|request| handle_request(request, &mut request_timer, &mut run_vmm_action));
+        if let Err(err) = self.server.respond(response) {
+            error!("API Server encountered an error on response: {}", err);
+        }
+        request_timer.finish(self.slow_request_threshold_ms.saturating_mul(1000));
+        true
+    }
+}
+
+/// Maximum number of API requests served per iteration of the event loop in single-threaded API
+/// mode.
+const MAX_API_REQUESTS_PER_ITERATION: usize = 4;
+
+/// Serves the API on the VMM thread once the microVM is running, in single-threaded API mode.
+/// The VMM actions are run by `handler`.
+struct SingleThreadApiAdapter<H> {
+    api: SingleThreadApi,
+    handler: H,
+    // Set by pause requests. While paused, only the API is served, until a resume request.
+    paused: bool,
+}
+
+impl<H> fmt::Debug for SingleThreadApiAdapter<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SingleThreadApiAdapter")
+            .field("api", &self.api)
+            .field("handler", &"?")
+            .field("paused", &self.paused)
+            .finish()
+    }
+}
+
+impl<H> SingleThreadApiAdapter<H>
+where
+    H: FnMut(VmmAction) -> VmmActionResult,
+{
+    fn new(api: SingleThreadApi, handler: H) -> Self {
+        Self {
+            api,
+            handler,
+            paused: false,
+        }
+    }
+
+    /// Serves up to `MAX_API_REQUESTS_PER_ITERATION` pending requests, so that a burst of
+    /// requests does not starve the devices.
+    fn serve_requests(&mut self) {
+        for _ in 0..MAX_API_REQUESTS_PER_ITERATION {
+            if !self.serve_request() {
+                break;
+            }
+
+            // The device emulation is paused along with the microVM, by not returning to the
+            // event manager until a resume request, like `ApiServerAdapter` does.
+            while self.paused {
+                if !self.serve_request() {
+                    self.api.read_requests();
+                }
+            }
+        }
+    }
+
+    /// Serves the oldest pending request.
+    ///
+    /// Returns whether a request was pending.
+    fn serve_request(&mut self) -> bool {
+        let handler = &mut self.handler;
+        let paused = &mut self.paused;
+        self.api.serve_request(|vmm_action| {
+            match *vmm_action {
+                VmmAction::Pause => *paused = true,
+                VmmAction::Resume => *paused = false,
+                _ => (),
+            }
+            run_vmm_action(vmm_action, &mut *handler)
+        })
+    }
+}
+
+impl<H> MutEventSubscriber for SingleThreadApiAdapter<H>
+where
+    H: FnMut(VmmAction) -> VmmActionResult,
+{
+    /// Handle a read event (EPOLLIN).
+    fn process(&mut self, event: Events, _: &mut EventOps) {
+        let source = event.fd();
+        let event_set = event.event_set();
+
+        // The requests are served on the next iteration of the event loop after they are read,
+        // so that every iteration serves a bounded number of requests.
+        if source == self.api.server.epoll().as_raw_fd() && event_set == EventSet::IN {
+            self.api.read_requests();
+        } else if source == self.api.pending_evt.as_raw_fd() && event_set == EventSet::IN {
+            let _ = self.api.pending_evt.read();
+            self.serve_requests();
+        } else {
+            error!("Spurious EventManager event for handler: SingleThreadApiAdapter");
+            return;
+        }
+
+        if !self.api.pending.is_empty() {
+            if let Err(err) = self.api.pending_evt.write(1) {
+                error!("Failed to signal pending API requests: {}", err);
+            }
+        }
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.add(Events::new(self.api.server.epoll(), EventSet::IN)) {
+            error!("Failed to register API server event: {}", err);
+        }
+        if let Err(err) = ops.add(Events::new(&self.api.pending_evt, EventSet::IN)) {
+            error!("Failed to register API pending requests event: {}", err);
+        }
+    }
+}
+
+/// Parameters of the microVM build.
+#[derive(Debug)]
+struct BuildArgs<'a> {
     config_json: Option<String>,
-    bind_path: PathBuf,
+    await_clone_path: Option<PathBuf>,
     instance_info: InstanceInfo,
-    process_time_reporter: ProcessTimeReporter,
     boot_timer_enabled: bool,
-    api_payload_limit: usize,
     mmds_size_limit: usize,
-    metadata_json: Option<&str>,
+    metadata_json: Option<&'a str>,
+}
+
+/// Builds the microVM from the configuration file, from the clone stream or from the API
+/// requests served by `build_from_requests`.
+fn build_microvm<'a, F>(
+    seccomp_filters: &BpfThreadMap,
+    event_manager: &mut EventManager,
+    mut args: BuildArgs<'a>,
+    build_from_requests: F,
+) -> Result<(VmResources, Arc<Mutex<Vmm>>), ApiServerError>
+where
+    F: FnOnce(
+        &BpfThreadMap,
+        &mut EventManager,
+        BuildArgs<'a>,
+    ) -> Result<(VmResources, Arc<Mutex<Vmm>>), BuildMicrovmFromRequestsError>,
+{
+    match (args.config_json.take(), args.await_clone_path.take()) {
+        (Some(json), _) => super::build_microvm_from_json(
+            seccomp_filters,
+            event_manager,
+            json,
+            args.instance_info,
+            args.boot_timer_enabled,
+            args.mmds_size_limit,
+            args.metadata_json,
+        )
+        .map_err(ApiServerError::BuildFromJson),
+        (None, Some(await_clone_path)) => super::build_microvm_from_clone(
+            seccomp_filters,
+            event_manager,
+            &await_clone_path,
+            args.instance_info,
+            args.boot_timer_enabled,
+            args.mmds_size_limit,
+        )
+        .map_err(ApiServerError::BuildFromClone),
+        (None, None) => build_from_requests(seccomp_filters, event_manager, args)
+            .map_err(ApiServerError::BuildMicroVmError),
+    }
+}
+
+/// Serves the API on a separate thread, which forwards the VMM actions to the VMM thread.
+fn run_with_api_thread(
+    seccomp_filters: &BpfThreadMap,
+    mut server: HttpServer,
+    build_args: BuildArgs<'_>,
+    process_time_reporter: ProcessTimeReporter,
+    api_seccomp_filter: Arc<BpfProgram>,
+    api_payload_limit: usize,
+    slow_request_threshold_ms: u64,
 ) -> Result<(), ApiServerError> {
     // FD to notify of API events. This is a blocking eventfd by design.
     // It is used in the config/pre-boot loop which is a simple blocking loop
@@ -161,20 +442,6 @@ pub(crate) fn run_with_api(
     let to_vmm_event_fd = api_event_fd
         .try_clone()
         .expect("Failed to clone API event FD");
-    let api_seccomp_filter = seccomp_filters
-        .remove("api")
-        .expect("Missing seccomp filter for API thread.");
-
-    let mut server = match HttpServer::new(&bind_path) {
-        Ok(s) => s,
-        Err(ServerError::IOError(inner)) if inner.kind() == std::io::ErrorKind::AddrInUse => {
-            let sock_path = bind_path.display().to_string();
-            return Err(ApiServerError::FailedToBindSocket(sock_path));
-        }
-        Err(err) => {
-            return Err(ApiServerError::FailedToBindAndRunHttpServer(err));
-        }
-    };
 
     let api_kill_switch_clone = api_kill_switch
         .try_clone()
@@ -193,6 +460,7 @@ pub(crate) fn run_with_api(
                 process_time_reporter,
                 &api_seccomp_filter,
                 api_payload_limit,
+                slow_request_threshold_ms,
             );
         })
         .expect("API thread spawn failed.");
@@ -204,30 +472,24 @@ pub(crate) fn run_with_api(
     event_manager.add_subscriber(firecracker_metrics.clone());
 
     // Configure, build and start the microVM.
-    let build_result = match config_json {
-        Some(json) => super::build_microvm_from_json(
-            seccomp_filters,
-            &mut event_manager,
-            json,
-            instance_info,
-            boot_timer_enabled,
-            mmds_size_limit,
-            metadata_json,
-        )
-        .map_err(ApiServerError::BuildFromJson),
-        None => PrebootApiController::build_microvm_from_requests(
-            seccomp_filters,
-            &mut event_manager,
-            instance_info,
-            &from_api,
-            &to_api,
-            &api_event_fd,
-            boot_timer_enabled,
-            mmds_size_limit,
-            metadata_json,
-        )
-        .map_err(ApiServerError::BuildMicroVmError),
-    };
+    let build_result = build_microvm(
+        seccomp_filters,
+        &mut event_manager,
+        build_args,
+        |seccomp_filters, event_manager, args| {
+            PrebootApiController::build_microvm_from_requests(
+                seccomp_filters,
+                event_manager,
+                args.instance_info,
+                &from_api,
+                &to_api,
+                &api_event_fd,
+                args.boot_timer_enabled,
+                args.mmds_size_limit,
+                args.metadata_json,
+            )
+        },
+    );
 
     let result = build_result.and_then(|(vm_resources, vmm)| {
         firecracker_metrics
@@ -252,3 +514,281 @@ pub(crate) fn run_with_api(
 
     result
 }
+
+/// Serves the API on the VMM thread, with the HTTP server registered to the event manager of the
+/// VMM alongside the devices.
+fn run_with_single_thread_api(
+    seccomp_filters: &BpfThreadMap,
+    server: HttpServer,
+    build_args: BuildArgs<'_>,
+    process_time_reporter: ProcessTimeReporter,
+    api_payload_limit: usize,
+    slow_request_threshold_ms: u64,
+) -> Result<(), ApiServerError> {
+    // Store process start time metric.
+    process_time_reporter.report_start_time();
+    // Store process CPU start time metric.
+    process_time_reporter.report_cpu_start_time();
+
+    let mut api = SingleThreadApi::new(server, api_payload_limit, slow_request_threshold_ms)
+        .map_err(ApiServerError::FailedToBindAndRunHttpServer)?;
+
+    let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+
+    // Create the firecracker metrics object responsible for periodically printing metrics.
+    let firecracker_metrics = Arc::new(Mutex::new(super::metrics::PeriodicMetrics::new()));
+    event_manager.add_subscriber(firecracker_metrics.clone());
+
+    // Configure, build and start the microVM. The devices do not exist yet, so the requests are
+    // served in a simple blocking loop.
+    let (vm_resources, vmm) = build_microvm(
+        seccomp_filters,
+        &mut event_manager,
+        build_args,
+        |seccomp_filters, event_manager, args| {
+            PrebootApiController::build_microvm_from_served_requests(
+                seccomp_filters,
+                event_manager,
+                args.instance_info,
+                args.boot_timer_enabled,
+                args.mmds_size_limit,
+                args.metadata_json,
+                |preboot_controller| {
+                    let served = api.serve_request(|vmm_action| {
+                        run_vmm_action(vmm_action, |vmm_action| {
+                            preboot_controller.handle_preboot_request(vmm_action)
+                        })
+                    });
+                    if !served {
+                        api.read_requests();
+                    }
+                },
+            )
+        },
+    )?;
+
+    firecracker_metrics
+        .lock()
+        .expect("Poisoned lock")
+        .start(super::metrics::WRITE_METRICS_PERIOD_MS);
+
+    let mut controller = RuntimeApiController::new(vm_resources, vmm.clone());
+    let api_adapter = Arc::new(Mutex::new(SingleThreadApiAdapter::new(
+        api,
+        move |vmm_action| controller.handle_request(vmm_action),
+    )));
+    event_manager.add_subscriber(api_adapter.clone());
+
+    let result = run_event_loop(&vmm, &mut event_manager);
+
+    // Send the responses still queued before exiting.
+    api_adapter
+        .lock()
+        .expect("Poisoned lock")
+        .api
+        .server
+        .flush_outgoing_writes();
+
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_with_api(
+    seccomp_filters: &mut BpfThreadMap,
+    config_json: Option<String>,
+    await_clone_path: Option<PathBuf>,
+    bind_path: PathBuf,
+    instance_info: InstanceInfo,
+    process_time_reporter: ProcessTimeReporter,
+    boot_timer_enabled: bool,
+    single_thread_api: bool,
+    api_payload_limit: usize,
+    slow_request_threshold_ms: u64,
+    mmds_size_limit: usize,
+    metadata_json: Option<&str>,
+) -> Result<(), ApiServerError> {
+    let api_seccomp_filter = seccomp_filters
+        .remove("api")
+        .expect("Missing seccomp filter for API thread.");
+
+    let server = match HttpServer::new(&bind_path) {
+        Ok(s) => s,
+        Err(ServerError::IOError(inner)) if inner.kind() == std::io::ErrorKind::AddrInUse => {
+            let sock_path = bind_path.display().to_string();
+            return Err(ApiServerError::FailedToBindSocket(sock_path));
+        }
+        Err(err) => {
+            return Err(ApiServerError::FailedToBindAndRunHttpServer(err));
+        }
+    };
+
+    let build_args = BuildArgs {
+        config_json,
+        await_clone_path,
+        instance_info,
+        boot_timer_enabled,
+        mmds_size_limit,
+        metadata_json,
+    };
+    if single_thread_api {
+        run_with_single_thread_api(
+            seccomp_filters,
+            server,
+            build_args,
+            process_time_reporter,
+            api_payload_limit,
+            slow_request_threshold_ms,
+        )
+    } else {
+        run_with_api_thread(
+            seccomp_filters,
+            server,
+            build_args,
+            process_time_reporter,
+            api_seccomp_filter,
+            api_payload_limit,
+            slow_request_threshold_ms,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    use utils::tempfile::TempFile;
+    use vmm::events::{StreamItem, DEFAULT_SUBSCRIBER_CAPACITY};
+    use vmm::rpc_interface::VmmData;
+    use vmm::vmm_config::snapshot::{CreateSnapshotParams, SnapshotType};
+
+    use super::*;
+    use crate::api_server::DEFAULT_SLOW_REQUEST_THRESHOLD_MS;
+
+    // Device which gets an event in every iteration of the event loop, as it never reads its
+    // event fd.
+    #[derive(Debug)]
+    struct MockDevice {
+        evt: EventFd,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl MutEventSubscriber for MockDevice {
+        fn process(&mut self, _: Events, _: &mut EventOps) {
+            self.log.lock().unwrap().push("device");
+        }
+
+        fn init(&mut self, ops: &mut EventOps) {
+            ops.add(Events::new(&self.evt, EventSet::IN)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_single_thread_api() {
+        let mut tmp_socket = TempFile::new().unwrap();
+        tmp_socket.remove().unwrap();
+        let api = SingleThreadApi::new(
+            HttpServer::new(tmp_socket.as_path()).unwrap(),
+            vmm::HTTP_MAX_PAYLOAD_SIZE,
+            DEFAULT_SLOW_REQUEST_THRESHOLD_MS,
+        )
+        .unwrap();
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut event_manager = EventManager::new().unwrap();
+        let api_log = log.clone();
+        event_manager.add_subscriber(Arc::new(Mutex::new(SingleThreadApiAdapter::new(
+            api,
+            move |vmm_action| {
+                assert_eq!(vmm_action, VmmAction::GetVmmVersion);
+                api_log.lock().unwrap().push("api");
+                Ok(VmmData::VmmVersion("1.0.0".to_string()))
+            },
+        ))));
+        let evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        evt.write(1).unwrap();
+        event_manager.add_subscriber(Arc::new(Mutex::new(MockDevice {
+            evt,
+            log: log.clone(),
+        })));
+
+        // Send a burst of requests.
+        let requests = 3 * MAX_API_REQUESTS_PER_ITERATION;
+        let mut sock = UnixStream::connect(tmp_socket.as_path()).unwrap();
+        sock.write_all(&b"GET /version HTTP/1.1\r\n\r\n".repeat(requests))
+            .unwrap();
+        let client = thread::spawn(move || {
+            let mut responses = String::new();
+            while responses.matches("HTTP/1.1 200").count() < requests {
+                let mut buf = [0; 1024];
+                let len = sock.read(&mut buf).unwrap();
+                responses.push_str(std::str::from_utf8(&buf[..len]).unwrap());
+            }
+            assert_eq!(
+                responses.matches("\"firecracker_version\"").count(),
+                requests
+            );
+        });
+
+        // Parse, dispatch and respond to the requests on the event loop of the devices.
+        while !client.is_finished() {
+            event_manager.run_with_timeout(10).unwrap();
+        }
+        client.join().unwrap();
+
+        // The device processed its events between the batches of requests.
+        let log = log.lock().unwrap();
+        assert_eq!(
+            log.iter().filter(|entry| **entry == "api").count(),
+            requests
+        );
+        for batch in log.split(|entry| *entry == "device") {
+            assert!(batch.len() <= MAX_API_REQUESTS_PER_ITERATION, "{:?}", log);
+        }
+        let first = log.iter().position(|entry| *entry == "api").unwrap();
+        let last = log.iter().rposition(|entry| *entry == "api").unwrap();
+        assert!(log[first..last].contains(&"device"), "{:?}", log);
+    }
+
+    #[test]
+    fn test_run_vmm_action() {
+        let subscription = EVENTS.subscribe(DEFAULT_SUBSCRIBER_CAPACITY).unwrap();
+
+        let create_snapshot = VmmAction::CreateSnapshot(CreateSnapshotParams {
+            snapshot_type: SnapshotType::Full,
+            snapshot_path: PathBuf::new(),
+            mem_file_path: PathBuf::new(),
+        });
+        run_vmm_action(Box::new(create_snapshot), |vmm_action| {
+            assert!(matches!(vmm_action, VmmAction::CreateSnapshot(_)));
+            Ok(VmmData::Empty)
+        })
+        .unwrap();
+        run_vmm_action(Box::new(VmmAction::GetVmmVersion), |_| {
+            Ok(VmmData::VmmVersion("1.0.0".to_string()))
+        })
+        .unwrap();
+
+        // Only the blocking request is reported.
+        let events: Vec<_> = subscription
+            .take()
+            .into_iter()
+            .filter_map(|item| match item {
+                StreamItem::Event(record) => Some(record.event),
+                StreamItem::Gap { .. } => None,
+            })
+            .filter(|event| event.name().starts_with("api_"))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                VmmEvent::ApiBlocked {
+                    action: "create_snapshot".to_string()
+                },
+                VmmEvent::ApiUnblocked {
+                    action: "create_snapshot".to_string()
+                },
+            ]
+        );
+    }
+}
@@ -7,12 +7,13 @@ mod metrics;
 mod seccomp;
 
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::{io, panic};
 
+use api_server::DEFAULT_SLOW_REQUEST_THRESHOLD_MS;
 use api_server_adapter::ApiServerError;
 use event_manager::SubscriberOps;
 use seccomp::FilterError;
@@ -24,12 +25,13 @@ use vmm::builder::StartMicrovmError;
 use vmm::logger::{
     debug, error, info, LoggerConfig, ProcessTimeReporter, StoreMetric, LOGGER, METRICS,
 };
-use vmm::persist::SNAPSHOT_VERSION;
-use vmm::resources::VmResources;
+use vmm::persist::{RestoreFromCloneError, SNAPSHOT_VERSION};
+use vmm::resources::{VmResources, VmmConfig};
 use vmm::signal_handler::register_signal_handlers;
 use vmm::snapshot::{Snapshot, SnapshotError};
+use vmm::vmm_config::config_schema::{migrate_config, ConfigSchemaError};
 use vmm::vmm_config::instance_info::{InstanceInfo, VmState};
-use vmm::vmm_config::metrics::{init_metrics, MetricsConfig, MetricsConfigError};
+use vmm::vmm_config::metrics::{init_metrics, MetricsConfig, MetricsConfigError, MetricsVerbosity};
 use vmm::{EventManager, FcExitCode, HTTP_MAX_PAYLOAD_SIZE};
 
 use crate::seccomp::SeccompConfig;
@@ -51,6 +53,8 @@ enum MainError {
     ParseArguments(#[from] utils::arg_parser::UtilsArgParserError),
     /// When printing Snapshot Data format: {0}
     PrintSnapshotDataFormat(#[from] SnapshotVersionError),
+    /// When migrating the configuration file: {0}
+    MigrateConfigFile(#[from] MigrateConfigError),
     /// Invalid value for logger level: {0}.Possible values: [Error, Warning, Info, Debug]
     InvalidLogLevel(vmm::logger::LevelFilterFromStrError),
     /// Could not initialize logger: {0}
@@ -82,6 +86,7 @@ impl From<MainError> for FcExitCode {
         match value {
             MainError::ParseArguments(_) => FcExitCode::ArgParsing,
             MainError::InvalidLogLevel(_) => FcExitCode::BadConfiguration,
+            MainError::MigrateConfigFile(_) => FcExitCode::BadConfiguration,
             MainError::RunWithApi(ApiServerError::MicroVMStoppedWithError(code)) => code,
             MainError::RunWithoutApiError(RunWithoutApiError::Shutdown(code)) => code,
             _ => FcExitCode::GenericError,
@@ -134,6 +139,7 @@ fn main_exec() -> Result<(), MainError> {
     }));
 
     let http_max_payload_size_str = HTTP_MAX_PAYLOAD_SIZE.to_string();
+    let slow_request_threshold_ms_str = DEFAULT_SLOW_REQUEST_THRESHOLD_MS.to_string();
 
     let mut arg_parser =
         ArgParser::new()
@@ -183,6 +189,28 @@ fn main_exec() -> Result<(), MainError> {
                     .takes_value(true)
                     .help("Path to a file that contains the microVM configuration in JSON format."),
             )
+            .arg(Argument::new("migrate-config").takes_value(true).help(
+                "Path to a configuration file to migrate to the current schema version, without \
+                 starting a microVM.",
+            ))
+            .arg(
+                Argument::new("migrate-config-output")
+                    .takes_value(true)
+                    .requires("migrate-config")
+                    .help(
+                        "Path to the file the migrated configuration is written to. Defaults to \
+                         the standard output.",
+                    ),
+            )
+            .arg(
+                Argument::new("await-clone")
+                    .takes_value(true)
+                    .forbids(vec!["config-file", "no-api", MMDS_CONTENT_ARG])
+                    .help(
+                        "Path to a unix socket on which to wait for a paused microVM to be cloned \
+                         into this process, instead of configuring a new microVM.",
+                    ),
+            )
             .arg(
                 Argument::new(MMDS_CONTENT_ARG).takes_value(true).help(
                     "Path to a file that contains metadata in JSON format to add to the mmds.",
@@ -197,6 +225,16 @@ fn main_exec() -> Result<(), MainError> {
                          active API socket.",
                     ),
             )
+            .arg(
+                Argument::new("single-thread-api")
+                    .takes_value(false)
+                    .forbids(vec!["no-api"])
+                    .help(
+                        "Serve the API on the VMM thread instead of a separate thread, to reduce \
+                         the footprint of the process. The API is unresponsive during blocking \
+                         requests, such as creating a snapshot.",
+                    ),
+            )
             .arg(
                 Argument::new("log-path")
                     .takes_value(true)
@@ -250,6 +288,15 @@ fn main_exec() -> Result<(), MainError> {
                     .default_value(&http_max_payload_size_str)
                     .help("Http API request payload max size, in bytes."),
             )
+            .arg(
+                Argument::new("http-api-slow-request-threshold-ms")
+                    .takes_value(true)
+                    .default_value(&slow_request_threshold_ms_str)
+                    .help(
+                        "Duration above which Http API requests are logged as slow, in \
+                         milliseconds.",
+                    ),
+            )
             .arg(
                 Argument::new("mmds-size-limit")
                     .takes_value(true)
@@ -280,6 +327,11 @@ fn main_exec() -> Result<(), MainError> {
         return Ok(());
     }
 
+    if let Some(config_path) = arguments.single_value("migrate-config") {
+        migrate_config_file(config_path, arguments.single_value("migrate-config-output"))?;
+        return Ok(());
+    }
+
     // It's safe to unwrap here because the field's been provided with a default value.
     let instance_id = arguments.single_value("id").unwrap();
     validate_instance_id(instance_id.as_str()).expect("Invalid instance ID");
@@ -336,11 +388,16 @@ fn main_exec() -> Result<(), MainError> {
         state: VmState::NotStarted,
         vmm_version: FIRECRACKER_VERSION.to_string(),
         app_name: "Firecracker".to_string(),
+        uuid: None,
+        ready: false,
+        readiness_probe: None,
+        out_of_space_drives: Vec::new(),
     };
 
     if let Some(metrics_path) = arguments.single_value("metrics-path") {
         let metrics_config = MetricsConfig {
             metrics_path: PathBuf::from(metrics_path),
+            verbosity: MetricsVerbosity::Default,
         };
         init_metrics(metrics_config).map_err(MainError::MetricsInitialization)?;
     }
@@ -364,6 +421,7 @@ fn main_exec() -> Result<(), MainError> {
 
     let boot_timer_enabled = arguments.flag_present("boot-timer");
     let api_enabled = !arguments.flag_present("no-api");
+    let single_thread_api = arguments.flag_present("single-thread-api");
     let api_payload_limit = arg_parser
         .arguments()
         .single_value("http-api-max-payload-size")
@@ -373,6 +431,16 @@ fn main_exec() -> Result<(), MainError> {
         })
         // Safe to unwrap as we provide a default value.
         .unwrap();
+    let slow_request_threshold_ms = arg_parser
+        .arguments()
+        .single_value("http-api-slow-request-threshold-ms")
+        .map(|threshold| {
+            threshold.parse::<u64>().expect(
+                "'http-api-slow-request-threshold-ms' parameter expected to be of 'u64' type.",
+            )
+        })
+        // Safe to unwrap as we provide a default value.
+        .unwrap();
 
     // If the mmds size limit is not explicitly configured, default to using the
     // `http-api-max-payload-size` value.
@@ -390,6 +458,7 @@ fn main_exec() -> Result<(), MainError> {
             .single_value("api-sock")
             .map(PathBuf::from)
             .expect("Missing argument: api-sock");
+        let await_clone_path = arguments.single_value("await-clone").map(PathBuf::from);
 
         let start_time_us = arguments.single_value("start-time-us").map(|s| {
             s.parse::<u64>()
@@ -409,14 +478,22 @@ fn main_exec() -> Result<(), MainError> {
         let process_time_reporter =
             ProcessTimeReporter::new(start_time_us, start_time_cpu_us, parent_cpu_time_us);
 
+        if single_thread_api {
+            seccomp::use_single_thread_api_filter(&mut seccomp_filters)
+                .map_err(MainError::SeccompFilter)?;
+        }
+
         api_server_adapter::run_with_api(
             &mut seccomp_filters,
             vmm_config_json,
+            await_clone_path,
             bind_path,
             instance_info,
             process_time_reporter,
             boot_timer_enabled,
+            single_thread_api,
             api_payload_limit,
+            slow_request_threshold_ms,
             mmds_size_limit,
             metadata_json.as_deref(),
         )
@@ -546,6 +623,52 @@ fn print_snapshot_data_format(snapshot_path: &str) -> Result<(), SnapshotVersion
     Ok(())
 }
 
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+enum MigrateConfigError {
+    /// Unable to read the configuration file: {0}
+    ReadConfig(io::Error),
+    /// Invalid JSON: {0}
+    InvalidJson(serde_json::Error),
+    /// {0}
+    Schema(ConfigSchemaError),
+    /// The migrated configuration is invalid: {0}
+    InvalidConfig(serde_json::Error),
+    /// Unable to write the migrated configuration: {0}
+    WriteConfig(io::Error),
+}
+
+// Migrate the configuration file at `config_path` to the current schema version, writing the
+// result to `output_path`, or to the standard output.
+fn migrate_config_file(
+    config_path: &str,
+    output_path: Option<&String>,
+) -> Result<(), MigrateConfigError> {
+    let config_json = fs::read_to_string(config_path).map_err(MigrateConfigError::ReadConfig)?;
+    let migrated = migrate_config(
+        serde_json::from_str(&config_json).map_err(MigrateConfigError::InvalidJson)?,
+    )
+    .map_err(MigrateConfigError::Schema)?;
+    for warning in migrated.warnings() {
+        eprintln!("Warning: {warning}");
+    }
+
+    // Make sure that the migrated configuration can be loaded.
+    serde_json::from_value::<VmmConfig>(migrated.config.clone())
+        .map_err(MigrateConfigError::InvalidConfig)?;
+    let migrated_json = format!(
+        "{}\n",
+        serde_json::to_string_pretty(&migrated.config).map_err(MigrateConfigError::InvalidJson)?
+    );
+
+    match output_path {
+        Some(output_path) => {
+            fs::write(output_path, migrated_json).map_err(MigrateConfigError::WriteConfig)?
+        }
+        None => print!("{migrated_json}"),
+    }
+    Ok(())
+}
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum BuildFromJsonError {
     /// Configuration for VMM from one single json failed: {0}
@@ -581,6 +704,39 @@ fn build_microvm_from_json(
     Ok((vm_resources, vmm))
 }
 
+// Wait for a paused microVM to be cloned into this process, and restore it.
+fn build_microvm_from_clone(
+    seccomp_filters: &BpfThreadMap,
+    event_manager: &mut EventManager,
+    socket_path: &Path,
+    instance_info: InstanceInfo,
+    boot_timer_enabled: bool,
+    mmds_size_limit: usize,
+) -> Result<(VmResources, Arc<Mutex<vmm::Vmm>>), RestoreFromCloneError> {
+    let mut vm_resources = VmResources::default();
+    // VmResources contains private fields, so it cannot be built with a struct expression.
+    #[allow(clippy::field_reassign_with_default)]
+    {
+        vm_resources.mmds_size_limit = mmds_size_limit;
+        vm_resources.boot_timer = boot_timer_enabled;
+    }
+    info!(
+        "Waiting for a microVM to be cloned on {}",
+        socket_path.display()
+    );
+    let vmm = vmm::persist::restore_from_clone(
+        &instance_info,
+        event_manager,
+        seccomp_filters,
+        socket_path,
+        &mut vm_resources,
+    )?;
+
+    info!("Successfully restored the microVM cloned into this process");
+
+    Ok((vm_resources, vmm))
+}
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 enum RunWithoutApiError {
     /// MicroVMStopped without an error: {0:?}
@@ -9,6 +9,9 @@ use seccompiler::{deserialize_binary, BpfThreadMap, DeserializationError};
 use vmm::seccomp_filters::get_empty_filters;
 
 const THREAD_CATEGORIES: [&str; 3] = ["vmm", "api", "vcpu"];
+// Filter of the VMM thread in single-threaded API mode, allowing the syscalls of both the VMM and
+// the API threads. It is only required in this mode.
+const SINGLE_THREAD_API_CATEGORY: &str = "vmm_api";
 
 // This byte limit is passed to `bincode` to guard against a potential memory
 // allocation DOS caused by binary filters that are too large.
@@ -85,11 +88,22 @@ fn get_custom_filters<R: Read + Debug>(reader: R) -> Result<BpfThreadMap, Filter
     filter_thread_categories(map)
 }
 
+/// Makes the VMM thread use the filter of the single-threaded API mode, which also allows the
+/// syscalls of the API thread.
+pub fn use_single_thread_api_filter(filters: &mut BpfThreadMap) -> Result<(), FilterError> {
+    let filter = filters.remove(SINGLE_THREAD_API_CATEGORY).ok_or_else(|| {
+        FilterError::MissingThreadCategory(SINGLE_THREAD_API_CATEGORY.to_string())
+    })?;
+    filters.insert("vmm".to_string(), filter);
+    Ok(())
+}
+
 /// Return an error if the BpfThreadMap contains invalid thread categories.
 fn filter_thread_categories(map: BpfThreadMap) -> Result<BpfThreadMap, FilterError> {
-    let (filters, invalid_filters): (BpfThreadMap, BpfThreadMap) = map
-        .into_iter()
-        .partition(|(k, _)| THREAD_CATEGORIES.contains(&k.as_str()));
+    let (filters, invalid_filters): (BpfThreadMap, BpfThreadMap) =
+        map.into_iter().partition(|(k, _)| {
+            THREAD_CATEGORIES.contains(&k.as_str()) || k == SINGLE_THREAD_API_CATEGORY
+        });
     if !invalid_filters.is_empty() {
         // build the error message
         let mut thread_categories_string =
@@ -118,7 +132,7 @@ fn filter_thread_categories(map: BpfThreadMap) -> Result<BpfThreadMap, FilterErr
 mod tests {
     use std::sync::Arc;
 
-    use seccompiler::BpfThreadMap;
+    use seccompiler::{sock_filter, BpfThreadMap};
     use utils::tempfile::TempFile;
 
     use super::*;
@@ -126,16 +140,18 @@ mod tests {
     #[test]
     fn test_get_filters() {
         let mut filters = get_empty_filters();
-        assert_eq!(filters.len(), 3);
+        assert_eq!(filters.len(), 4);
         assert!(filters.remove("vmm").is_some());
         assert!(filters.remove("api").is_some());
         assert!(filters.remove("vcpu").is_some());
+        assert!(filters.remove("vmm_api").is_some());
 
         let mut filters = get_empty_filters();
-        assert_eq!(filters.len(), 3);
+        assert_eq!(filters.len(), 4);
         assert_eq!(filters.remove("vmm").unwrap().len(), 0);
         assert_eq!(filters.remove("api").unwrap().len(), 0);
         assert_eq!(filters.remove("vcpu").unwrap().len(), 0);
+        assert_eq!(filters.remove("vmm_api").unwrap().len(), 0);
 
         let file = TempFile::new().unwrap().into_file();
 
@@ -152,6 +168,15 @@ mod tests {
 
         assert_eq!(filter_thread_categories(map).unwrap().len(), 3);
 
+        // optional category
+        let mut map = BpfThreadMap::new();
+        map.insert("vcpu".to_string(), Arc::new(vec![]));
+        map.insert("vmm".to_string(), Arc::new(vec![]));
+        map.insert("api".to_string(), Arc::new(vec![]));
+        map.insert("vmm_api".to_string(), Arc::new(vec![]));
+
+        assert_eq!(filter_thread_categories(map).unwrap().len(), 4);
+
         // invalid categories
         let mut map = BpfThreadMap::new();
         map.insert("vcpu".to_string(), Arc::new(vec![]));
@@ -177,6 +202,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_use_single_thread_api_filter() {
+        let mut map = BpfThreadMap::new();
+        map.insert("vmm".to_string(), Arc::new(vec![]));
+        map.insert(
+            "vmm_api".to_string(),
+            Arc::new(vec![sock_filter {
+                code: 32,
+                jt: 0,
+                jf: 0,
+                k: 0,
+            }]),
+        );
+
+        use_single_thread_api_filter(&mut map).unwrap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["vmm"].len(), 1);
+
+        match use_single_thread_api_filter(&mut map).unwrap_err() {
+            FilterError::MissingThreadCategory(name) => assert_eq!(name, "vmm_api"),
+            _ => panic!("Expected MissingThreadCategory error."),
+        }
+    }
+
     #[test]
     fn test_seccomp_config() {
         assert!(matches!(
@@ -4,9 +4,11 @@
 use std::path::PathBuf;
 
 use clap::Subcommand;
+#[cfg(target_arch = "aarch64")]
 use clap_num::maybe_hex;
+#[cfg(target_arch = "aarch64")]
 use vmm::arch::aarch64::regs::Aarch64RegisterVec;
-use vmm::persist::MicrovmState;
+use vmm::persist::{EditMicrovmStateError, MicrovmState, SavedDevice};
 
 use crate::utils::{open_vmstate, save_vmstate, UtilsError};
 
@@ -14,11 +16,14 @@ use crate::utils::{open_vmstate, save_vmstate, UtilsError};
 pub enum EditVmStateError {
     /// {0}
     Utils(#[from] UtilsError),
+    /// {0}
+    EditState(#[from] EditMicrovmStateError),
 }
 
 #[derive(Debug, Subcommand)]
 pub enum EditVmStateSubCommand {
     /// Remove registers from vcpu states.
+    #[cfg(target_arch = "aarch64")]
     RemoveRegs {
         /// Set of registers to remove.
         /// Values should be registers ids as the are defined in KVM.
@@ -31,10 +36,38 @@ pub enum EditVmStateSubCommand {
         #[arg(short, long)]
         output_path: PathBuf,
     },
+    /// Remove a virtio device, releasing its MMIO slot.
+    RemoveDevice {
+        /// Id of the device to remove.
+        #[arg(long)]
+        device_id: String,
+        /// Path to the vmstate file.
+        #[arg(short, long)]
+        vmstate_path: PathBuf,
+        /// Path of output file.
+        #[arg(short, long)]
+        output_path: PathBuf,
+    },
+    /// Replace the path of the backing file of a drive.
+    SetDrivePath {
+        /// Id of the drive.
+        #[arg(long)]
+        drive_id: String,
+        /// New path of the backing file on the host.
+        #[arg(long)]
+        path_on_host: String,
+        /// Path to the vmstate file.
+        #[arg(short, long)]
+        vmstate_path: PathBuf,
+        /// Path of output file.
+        #[arg(short, long)]
+        output_path: PathBuf,
+    },
 }
 
 pub fn edit_vmstate_command(command: EditVmStateSubCommand) -> Result<(), EditVmStateError> {
     match command {
+        #[cfg(target_arch = "aarch64")]
         EditVmStateSubCommand::RemoveRegs {
             regs,
             vmstate_path,
@@ -42,6 +75,21 @@ pub fn edit_vmstate_command(command: EditVmStateSubCommand) -> Result<(), EditVm
         } => edit(&vmstate_path, &output_path, |state| {
             remove_regs(state, &regs)
         })?,
+        EditVmStateSubCommand::RemoveDevice {
+            device_id,
+            vmstate_path,
+            output_path,
+        } => edit(&vmstate_path, &output_path, |state| {
+            remove_device(state, &device_id)
+        })?,
+        EditVmStateSubCommand::SetDrivePath {
+            drive_id,
+            path_on_host,
+            vmstate_path,
+            output_path,
+        } => edit(&vmstate_path, &output_path, |state| {
+            set_drive_path(state, &drive_id, &path_on_host)
+        })?,
     }
     Ok(())
 }
@@ -53,10 +101,59 @@ fn edit(
 ) -> Result<(), EditVmStateError> {
     let (microvm_state, version) = open_vmstate(vmstate_path)?;
     let microvm_state = f(microvm_state)?;
+    // Refuse to write a state which could not be restored.
+    microvm_state.check_devices()?;
     save_vmstate(microvm_state, output_path, version)?;
     Ok(())
 }
 
+fn remove_device(
+    mut state: MicrovmState,
+    device_id: &str,
+) -> Result<MicrovmState, EditVmStateError> {
+    let removed = state.remove_device(device_id)?;
+    println!(
+        "Removed {} device {} at MMIO address {:#x}",
+        removed.device_type, removed.device_id, removed.mmio_addr
+    );
+    let boot_args = state.vm_info.boot_source.boot_args.as_deref();
+    for warning in removal_warnings(&removed, boot_args) {
+        println!("Warning: {warning}");
+    }
+    Ok(state)
+}
+
+// The guest still knows about the removed device, so point out the places where it is
+// referenced.
+fn removal_warnings(removed: &SavedDevice, boot_args: Option<&str>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if removed.root_device {
+        warnings.push(format!(
+            "device {} is the root device of the guest",
+            removed.device_id
+        ));
+    }
+    let addr = format!("{:#x}", removed.mmio_addr);
+    if boot_args.is_some_and(|args| args.to_lowercase().contains(&addr)) {
+        warnings.push(format!(
+            "the kernel command line refers to the MMIO slot {addr} of device {}",
+            removed.device_id
+        ));
+    }
+    warnings
+}
+
+fn set_drive_path(
+    mut state: MicrovmState,
+    drive_id: &str,
+    path_on_host: &str,
+) -> Result<MicrovmState, EditVmStateError> {
+    let old_path = state.set_drive_path(drive_id, path_on_host.to_string())?;
+    println!("Drive {drive_id}: replaced {old_path} with {path_on_host}");
+    Ok(state)
+}
+
+#[cfg(target_arch = "aarch64")]
 fn remove_regs(
     mut state: MicrovmState,
     remove_regs: &[u64],
@@ -92,6 +189,48 @@ fn remove_regs(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_removal_warnings() {
+        let removed = SavedDevice {
+            device_type: "block",
+            device_id: "rootfs".to_string(),
+            mmio_addr: 0xd000_1000,
+            irqs: vec![6],
+            disk_path: Some("/rootfs.ext4".to_string()),
+            root_device: false,
+        };
+        assert!(removal_warnings(&removed, None).is_empty());
+        assert!(removal_warnings(&removed, Some("console=ttyS0 reboot=k")).is_empty());
+        assert_eq!(
+            removal_warnings(&removed, Some("virtio_mmio.device=4K@0xD0001000:6")),
+            ["the kernel command line refers to the MMIO slot 0xd0001000 of device rootfs"]
+        );
+
+        let removed = SavedDevice {
+            root_device: true,
+            ..removed
+        };
+        assert_eq!(
+            removal_warnings(&removed, None),
+            ["device rootfs is the root device of the guest"]
+        );
+    }
+
+    #[test]
+    fn test_edit_errors() {
+        let state = MicrovmState::default();
+        assert!(matches!(
+            remove_device(state, "net0").unwrap_err(),
+            EditVmStateError::EditState(EditMicrovmStateError::DeviceNotFound(_))
+        ));
+        let state = MicrovmState::default();
+        assert!(matches!(
+            set_drive_path(state, "rootfs", "/rootfs.ext4").unwrap_err(),
+            EditVmStateError::EditState(EditMicrovmStateError::DriveNotFound(_))
+        ));
+    }
+
+    #[cfg(target_arch = "aarch64")]
     #[test]
     fn test_remove_regs() {
         const KVM_REG_SIZE_U8: u64 = 0;
@@ -151,6 +290,7 @@ mod tests {
         assert_eq!(new_state.vcpu_states[0].regs, expected_vcpu_state.regs);
     }
 
+    #[cfg(target_arch = "aarch64")]
     #[test]
     fn test_remove_non_existed_regs() {
         const KVM_REG_SIZE_U8: u64 = 0;
@@ -29,6 +29,12 @@ pub enum InfoVmStateSubCommand {
         #[arg(short, long)]
         vmstate_path: PathBuf,
     },
+    /// Print the virtio devices, with their MMIO slots.
+    Devices {
+        /// Path to the vmstate file.
+        #[arg(short, long)]
+        vmstate_path: PathBuf,
+    },
     /// Print readable MicroVM state.
     VmState {
         /// Path to the vmstate file.
@@ -43,6 +49,7 @@ pub fn info_vmstate_command(command: InfoVmStateSubCommand) -> Result<(), InfoVm
         InfoVmStateSubCommand::VcpuStates { vmstate_path } => {
             info(&vmstate_path, info_vcpu_states)?
         }
+        InfoVmStateSubCommand::Devices { vmstate_path } => info(&vmstate_path, info_devices)?,
         InfoVmStateSubCommand::VmState { vmstate_path } => info(&vmstate_path, info_vmstate)?,
     }
     Ok(())
@@ -70,6 +77,23 @@ fn info_vcpu_states(state: &MicrovmState, _: Version) -> Result<(), InfoVmStateE
     Ok(())
 }
 
+fn info_devices(state: &MicrovmState, _: Version) -> Result<(), InfoVmStateError> {
+    for device in state.devices() {
+        print!(
+            "{} {}: MMIO address {:#x}, irqs {:?}",
+            device.device_type, device.device_id, device.mmio_addr, device.irqs
+        );
+        if let Some(disk_path) = &device.disk_path {
+            print!(", backing file {disk_path}");
+        }
+        if device.root_device {
+            print!(", root device");
+        }
+        println!();
+    }
+    Ok(())
+}
+
 fn info_vmstate(vmstate: &MicrovmState, _version: Version) -> Result<(), InfoVmStateError> {
     println!("{vmstate:#?}");
     Ok(())
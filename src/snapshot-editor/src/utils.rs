@@ -9,8 +9,6 @@ use semver::Version;
 use vmm::persist::MicrovmState;
 use vmm::snapshot::Snapshot;
 
-// Some errors are only used in aarch64 code
-#[allow(unused)]
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum UtilsError {
     /// Can not open snapshot file: {0}
@@ -25,7 +23,6 @@ pub enum UtilsError {
     VmStateSave(vmm::snapshot::SnapshotError),
 }
 
-#[allow(unused)]
 pub fn open_vmstate(snapshot_path: &PathBuf) -> Result<(MicrovmState, Version), UtilsError> {
     let mut snapshot_reader = File::open(snapshot_path).map_err(UtilsError::VmStateFileOpen)?;
     let metadata = std::fs::metadata(snapshot_path).map_err(UtilsError::VmStateFileMeta)?;
@@ -33,8 +30,6 @@ pub fn open_vmstate(snapshot_path: &PathBuf) -> Result<(MicrovmState, Version),
     Snapshot::load(&mut snapshot_reader, snapshot_len).map_err(UtilsError::VmStateLoad)
 }
 
-// This method is used only in aarch64 code so far
-#[allow(unused)]
 pub fn save_vmstate(
     microvm_state: MicrovmState,
     output_path: &PathBuf,
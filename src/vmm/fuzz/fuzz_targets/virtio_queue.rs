@@ -0,0 +1,99 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fuzzes the processing of a virtio queue whose descriptor table and avail ring are written by
+//! a malicious guest: popping the available descriptor chains, walking them and returning them
+//! through the used ring.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use vmm::devices::virtio::test_utils::VirtQueue;
+use vmm::utilities::test_utils::single_region_mem;
+use vmm::vstate::memory::{Bytes, GuestAddress, GuestMemoryMmap};
+
+/// Size of the guest memory, which holds a queue of the maximum size.
+const MEM_SIZE: usize = 0x10000;
+/// Max size of the queues of the Firecracker devices.
+const MAX_QUEUE_SIZE_SHIFT: u8 = 8;
+
+#[derive(Debug, Arbitrary)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// The queue size is `1 << queue_size_shift`, up to the max size.
+    queue_size_shift: u8,
+    descriptors: Vec<Descriptor>,
+    avail_ring: Vec<u16>,
+    avail_idx: u16,
+    used_lens: Vec<u32>,
+}
+
+fn read_mem(mem: &GuestMemoryMmap) -> Vec<u8> {
+    let mut buf = vec![0; MEM_SIZE];
+    mem.read_slice(&mut buf, GuestAddress(0)).unwrap();
+    buf
+}
+
+fuzz_target!(|input: Input| {
+    let mem = single_region_mem(MEM_SIZE);
+    let queue_size = 1u16 << (input.queue_size_shift % (MAX_QUEUE_SIZE_SHIFT + 1));
+    let vq = VirtQueue::new(GuestAddress(0), &mem, queue_size);
+
+    for (desc, fuzzed) in vq.dtable.iter().zip(&input.descriptors) {
+        desc.set(fuzzed.addr, fuzzed.len, fuzzed.flags, fuzzed.next);
+    }
+    for (entry, index) in vq.avail.ring.iter().zip(&input.avail_ring) {
+        entry.set(*index);
+    }
+    vq.avail.idx.set(input.avail_idx);
+
+    let mut queue = vq.create_queue();
+    assert!(queue.is_valid(&mem));
+    // Firecracker deliberately panics when the guest makes more descriptor chains available than
+    // the queue size.
+    if queue.len(&mem) > queue.actual_size() {
+        return;
+    }
+
+    let before = read_mem(&mem);
+    let mut used: u16 = 0;
+
+    while let Some(head) = queue.pop(&mem) {
+        let head_index = head.index;
+        assert!(head_index < queue_size);
+
+        // The walk stays in the descriptor table and ends, even if the descriptors form a cycle.
+        let chain_len = head
+            .into_iter()
+            .inspect(|desc| assert!(desc.index < queue_size))
+            .count();
+        assert!(chain_len <= usize::from(queue_size));
+
+        let len = input
+            .used_lens
+            .get(usize::from(used))
+            .copied()
+            .unwrap_or_default();
+        queue.add_used(&mem, head_index, len).unwrap();
+        vq.check_used_elem(used, head_index, len);
+        used += 1;
+    }
+
+    assert!(used <= queue_size);
+    assert_eq!(vq.used.idx.get(), used);
+
+    // Only the used ring was written.
+    let after = read_mem(&mem);
+    let used_ring =
+        usize::try_from(vq.used_start().0).unwrap()..usize::try_from(vq.end().0).unwrap();
+    assert_eq!(before[..used_ring.start], after[..used_ring.start]);
+    assert_eq!(before[used_ring.end..], after[used_ring.end..]);
+});
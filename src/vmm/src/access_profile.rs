@@ -0,0 +1,557 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Records the guest pages touched by a restored microVM in the first seconds after it resumed,
+//! to estimate the working set to prefetch when restoring it again.
+//!
+//! When the microVM first resumes, the tracker records which pages of the guest memory are
+//! already mapped, one bit per page, and clears the soft-dirty bits of the process by writing `4`
+//! to `/proc/self/clear_refs`. Once the configured duration elapsed, it walks
+//! `/proc/self/pagemap` over the guest memory: a mapped page was touched when it is soft-dirty
+//! (written by the guest) or when it was not mapped at the resume (faulted in by the guest, or
+//! served by the UFFD handler). The pagemap is read in chunks of a few pages, and the walk only
+//! covers a bounded number of chunks per expiration of the timer, so it does not hold the VMM
+//! thread for long whatever the size of the guest memory.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
+use std::time::Duration;
+
+use serde::Serialize;
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+use utils::u64_to_usize;
+
+use crate::logger::{info, warn};
+use crate::vmm_config::snapshot::TrackAccessConfig;
+
+/// Size of the pages reported by the access profile.
+pub const PAGE_SIZE: u64 = 4096;
+
+// Number of pagemap entries read at once, one page worth of entries.
+const PAGEMAP_CHUNK_PAGES: usize = 512;
+// Size of a pagemap entry.
+const PAGEMAP_ENTRY_LEN: usize = 8;
+// Number of chunks walked on every expiration of the timer.
+const CHUNKS_PER_EVENT: usize = 64;
+// Delay between two steps of the walk.
+const WALK_STEP_DELAY: Duration = Duration::from_millis(1);
+
+// Bits of a pagemap entry, see Documentation/admin-guide/mm/pagemap.rst.
+const PM_PRESENT: u64 = 1 << 63;
+const PM_SWAPPED: u64 = 1 << 62;
+const PM_SOFT_DIRTY: u64 = 1 << 55;
+
+/// State of the tracking of the guest memory accesses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessTrackingState {
+    /// The microVM has not been resumed yet.
+    #[default]
+    Waiting,
+    /// The accesses of the guest are being recorded.
+    Tracking,
+    /// The duration elapsed, the touched pages are being collected.
+    Collecting,
+    /// The access profile is available.
+    Complete,
+    /// The accesses could not be recorded.
+    Failed,
+}
+
+/// Run of consecutive guest pages touched by the guest, which are also consecutive in the guest
+/// memory file of the snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct AccessRun {
+    /// Guest physical address of the first page.
+    pub guest_addr: u64,
+    /// Offset of the first page in the guest memory file of the snapshot.
+    pub file_offset: u64,
+    /// Number of pages of the run.
+    pub num_pages: u64,
+}
+
+/// Guest pages touched by the guest after the microVM resumed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AccessProfile {
+    /// State of the tracking.
+    pub state: AccessTrackingState,
+    /// Time during which the accesses are recorded, in seconds.
+    pub duration_s: u64,
+    /// Size of the pages, in bytes.
+    pub page_size: u64,
+    /// Number of touched pages, once the profile is complete.
+    pub touched_pages: u64,
+    /// Touched pages, ordered by guest address, once the profile is complete.
+    pub runs: Vec<AccessRun>,
+}
+
+/// Errors associated with the tracking of the guest memory accesses.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum AccessTrackerError {
+    /// The memory accesses cannot be tracked when the guest memory is backed by huge pages.
+    HugePages,
+    /// Failed to open the pagemap of the process: {0}
+    Pagemap(io::Error),
+    /// Failed to open the soft-dirty bits of the process: {0}
+    ClearRefs(io::Error),
+    /// Failed to create the memory access tracking timer: {0}
+    Timer(io::Error),
+}
+
+/// Guest memory region whose accesses are tracked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackedRegion {
+    /// Guest physical address of the region.
+    pub guest_addr: u64,
+    /// Address of the region in the process.
+    pub host_addr: u64,
+    /// Offset of the region in the guest memory file of the snapshot.
+    pub file_offset: u64,
+    /// Number of pages of the region.
+    pub num_pages: u64,
+}
+
+#[derive(Debug)]
+struct Region {
+    desc: TrackedRegion,
+    // Pages mapped when the tracking started, one bit per page.
+    baseline: Vec<u64>,
+}
+
+impl Region {
+    fn was_mapped(&self, page: u64) -> bool {
+        let page = u64_to_usize(page);
+        self.baseline[page / 64] & (1 << (page % 64)) != 0
+    }
+
+    fn set_mapped(&mut self, page: u64) {
+        let page = u64_to_usize(page);
+        self.baseline[page / 64] |= 1 << (page % 64);
+    }
+}
+
+// Position of the walk of the pagemap.
+#[derive(Debug, Default, Clone, Copy)]
+struct Cursor {
+    region: usize,
+    page: u64,
+}
+
+/// Tracks the guest pages touched by the microVM after it first resumed.
+#[derive(Debug)]
+pub struct AccessTracker {
+    duration_s: u64,
+    regions: Vec<Region>,
+    pagemap: File,
+    clear_refs: File,
+    timer_fd: TimerFd,
+    state: AccessTrackingState,
+    cursor: Cursor,
+    touched_pages: u64,
+    runs: Vec<AccessRun>,
+}
+
+impl AccessTracker {
+    /// Creates the tracker of the accesses to `regions` described by the (validated) `config`.
+    /// The files and the timer are opened right away, since the seccomp filters of the VMM
+    /// thread do not allow it later on.
+    pub fn new(
+        config: &TrackAccessConfig,
+        regions: impl Iterator<Item = TrackedRegion>,
+    ) -> Result<Self, AccessTrackerError> {
+        let pagemap = File::open("/proc/self/pagemap").map_err(AccessTrackerError::Pagemap)?;
+        let clear_refs = OpenOptions::new()
+            .write(true)
+            .open("/proc/self/clear_refs")
+            .map_err(AccessTrackerError::ClearRefs)?;
+        let timer_fd = TimerFd::new_custom(ClockId::Monotonic, true, true)
+            .map_err(AccessTrackerError::Timer)?;
+        let regions = regions
+            .map(|desc| Region {
+                baseline: vec![0; u64_to_usize(desc.num_pages.div_ceil(64))],
+                desc,
+            })
+            .collect();
+
+        Ok(AccessTracker {
+            duration_s: config.duration_s,
+            regions,
+            pagemap,
+            clear_refs,
+            timer_fd,
+            state: AccessTrackingState::Waiting,
+            cursor: Cursor::default(),
+            touched_pages: 0,
+            runs: Vec::new(),
+        })
+    }
+
+    /// Timer driving the tracking.
+    pub fn timer_fd(&self) -> &TimerFd {
+        &self.timer_fd
+    }
+
+    /// Starts recording the accesses when the microVM resumes for the first time. Called before
+    /// the vCPUs are resumed.
+    pub fn on_resume(&mut self) {
+        if self.state != AccessTrackingState::Waiting {
+            return;
+        }
+        if let Err(err) = self.start() {
+            warn!(
+                "Failed to start tracking the guest memory accesses: {}",
+                err
+            );
+            self.state = AccessTrackingState::Failed;
+            return;
+        }
+        self.timer_fd.set_state(
+            TimerState::Oneshot(Duration::from_secs(self.duration_s)),
+            SetTimeFlags::Default,
+        );
+        self.state = AccessTrackingState::Tracking;
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        let mut entries = [0u8; PAGEMAP_CHUNK_PAGES * PAGEMAP_ENTRY_LEN];
+        for region in self.regions.iter_mut() {
+            let mut page = 0;
+            while page < region.desc.num_pages {
+                let chunk = read_chunk(&self.pagemap, &region.desc, page, &mut entries)?;
+                for (idx, entry) in chunk.enumerate() {
+                    if entry & (PM_PRESENT | PM_SWAPPED) != 0 {
+                        region.set_mapped(page + idx as u64);
+                    }
+                }
+                page += PAGEMAP_CHUNK_PAGES as u64;
+            }
+        }
+        // Also write-protects the pages, so the next write of the guest sets their soft-dirty
+        // bit.
+        (&self.clear_refs).write_all(b"4")
+    }
+
+    /// Consumes an expiration of the timer, and walks the next chunks of the pagemap once the
+    /// duration elapsed.
+    pub fn process_timer(&mut self) {
+        // Reading the timer resets it, the number of expirations does not matter.
+        self.timer_fd.read();
+        match self.state {
+            AccessTrackingState::Tracking => {
+                self.state = AccessTrackingState::Collecting;
+                self.cursor = Cursor::default();
+            }
+            AccessTrackingState::Collecting => {}
+            _ => return,
+        }
+        match self.collect(CHUNKS_PER_EVENT) {
+            Ok(true) => {
+                self.state = AccessTrackingState::Complete;
+                info!(
+                    "The guest touched {} pages in {} runs during the {} s following the resume.",
+                    self.touched_pages,
+                    self.runs.len(),
+                    self.duration_s
+                );
+            }
+            Ok(false) => self
+                .timer_fd
+                .set_state(TimerState::Oneshot(WALK_STEP_DELAY), SetTimeFlags::Default),
+            Err(err) => {
+                warn!("Failed to read the pagemap of the guest memory: {}", err);
+                self.state = AccessTrackingState::Failed;
+                self.runs = Vec::new();
+                self.touched_pages = 0;
+            }
+        }
+    }
+
+    // Walks up to `max_chunks` chunks of the pagemap from the cursor, and tells whether the walk
+    // is done.
+    fn collect(&mut self, max_chunks: usize) -> io::Result<bool> {
+        let mut entries = [0u8; PAGEMAP_CHUNK_PAGES * PAGEMAP_ENTRY_LEN];
+        for _ in 0..max_chunks {
+            let Some(region) = self.regions.get(self.cursor.region) else {
+                return Ok(true);
+            };
+            let first_page = self.cursor.page;
+            let chunk = read_chunk(&self.pagemap, &region.desc, first_page, &mut entries)?;
+            for (idx, entry) in chunk.enumerate() {
+                let page = first_page + idx as u64;
+                let touched = entry & (PM_PRESENT | PM_SWAPPED) != 0
+                    && (entry & PM_SOFT_DIRTY != 0 || !region.was_mapped(page));
+                if touched {
+                    self.touched_pages += 1;
+                    push_page(
+                        &mut self.runs,
+                        region.desc.guest_addr + page * PAGE_SIZE,
+                        region.desc.file_offset + page * PAGE_SIZE,
+                    );
+                }
+            }
+            self.cursor.page += PAGEMAP_CHUNK_PAGES as u64;
+            if self.cursor.page >= region.desc.num_pages {
+                self.cursor = Cursor {
+                    region: self.cursor.region + 1,
+                    page: 0,
+                };
+            }
+        }
+        Ok(self.cursor.region >= self.regions.len())
+    }
+
+    /// Returns the access profile recorded so far.
+    pub fn profile(&self) -> AccessProfile {
+        let complete = self.state == AccessTrackingState::Complete;
+        AccessProfile {
+            state: self.state,
+            duration_s: self.duration_s,
+            page_size: PAGE_SIZE,
+            touched_pages: if complete { self.touched_pages } else { 0 },
+            runs: if complete {
+                self.runs.clone()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+// Reads the pagemap entries of the chunk of `region` starting at `first_page`.
+fn read_chunk<'a>(
+    pagemap: &File,
+    region: &TrackedRegion,
+    first_page: u64,
+    buf: &'a mut [u8; PAGEMAP_CHUNK_PAGES * PAGEMAP_ENTRY_LEN],
+) -> io::Result<impl Iterator<Item = u64> + 'a> {
+    let num_pages = region
+        .num_pages
+        .saturating_sub(first_page)
+        .min(PAGEMAP_CHUNK_PAGES as u64);
+    let len = u64_to_usize(num_pages) * PAGEMAP_ENTRY_LEN;
+    let offset = (region.host_addr / PAGE_SIZE + first_page) * PAGEMAP_ENTRY_LEN as u64;
+    pagemap.read_exact_at(&mut buf[..len], offset)?;
+    Ok(buf[..len]
+        .chunks_exact(PAGEMAP_ENTRY_LEN)
+        .map(|entry| u64::from_ne_bytes(entry.try_into().unwrap())))
+}
+
+// Adds a touched page to the runs, extending the last run when the page follows it both in the
+// guest memory and in the memory file.
+fn push_page(runs: &mut Vec<AccessRun>, guest_addr: u64, file_offset: u64) {
+    if let Some(last) = runs.last_mut() {
+        let len = last.num_pages * PAGE_SIZE;
+        if last.guest_addr + len == guest_addr && last.file_offset + len == file_offset {
+            last.num_pages += 1;
+            return;
+        }
+    }
+    runs.push(AccessRun {
+        guest_addr,
+        file_offset,
+        num_pages: 1,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr::{read_volatile, write_volatile};
+    use std::sync::Mutex;
+
+    use super::*;
+
+    // Clearing the soft-dirty bits affects the whole process, the tests doing it cannot run
+    // concurrently.
+    static CLEAR_REFS_LOCK: Mutex<()> = Mutex::new(());
+
+    // Anonymous mapping standing in for the guest memory.
+    struct TestMapping {
+        addr: *mut u8,
+        len: usize,
+    }
+
+    impl TestMapping {
+        fn new(num_pages: usize) -> Self {
+            let len = num_pages * PAGE_SIZE as usize;
+            // SAFETY: Creates a new private anonymous mapping, no existing memory is affected.
+            let addr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                    -1,
+                    0,
+                )
+            };
+            assert_ne!(addr, libc::MAP_FAILED);
+            // Transparent huge pages would map (and dirty) 512 pages at once.
+            // SAFETY: The range is the mapping created above.
+            unsafe { libc::madvise(addr, len, libc::MADV_NOHUGEPAGE) };
+            TestMapping {
+                addr: addr.cast(),
+                len,
+            }
+        }
+
+        fn write(&self, page: usize) {
+            assert!(page * (PAGE_SIZE as usize) < self.len);
+            // SAFETY: The address is within the mapping.
+            unsafe { write_volatile(self.addr.add(page * PAGE_SIZE as usize), 1) };
+        }
+
+        fn read(&self, page: usize) {
+            assert!(page * (PAGE_SIZE as usize) < self.len);
+            // SAFETY: The address is within the mapping.
+            unsafe { read_volatile(self.addr.add(page * PAGE_SIZE as usize)) };
+        }
+
+        fn region(&self, first_page: u64, num_pages: u64, guest_addr: u64) -> TrackedRegion {
+            TrackedRegion {
+                guest_addr,
+                host_addr: self.addr as u64 + first_page * PAGE_SIZE,
+                file_offset: first_page * PAGE_SIZE,
+                num_pages,
+            }
+        }
+    }
+
+    impl Drop for TestMapping {
+        fn drop(&mut self) {
+            // SAFETY: The mapping was created by `TestMapping::new`.
+            unsafe { libc::munmap(self.addr.cast(), self.len) };
+        }
+    }
+
+    fn run(guest_addr: u64, file_offset: u64, num_pages: u64) -> AccessRun {
+        AccessRun {
+            guest_addr,
+            file_offset,
+            num_pages,
+        }
+    }
+
+    #[test]
+    fn test_push_page() {
+        let mut runs = Vec::new();
+        push_page(&mut runs, 0, 0);
+        push_page(&mut runs, PAGE_SIZE, PAGE_SIZE);
+        // Not consecutive in the guest memory.
+        push_page(&mut runs, 3 * PAGE_SIZE, 2 * PAGE_SIZE);
+        // Not consecutive in the memory file.
+        push_page(&mut runs, 4 * PAGE_SIZE, 0x10_0000);
+        push_page(&mut runs, 5 * PAGE_SIZE, 0x10_0000 + PAGE_SIZE);
+        assert_eq!(
+            runs,
+            vec![
+                run(0, 0, 2),
+                run(3 * PAGE_SIZE, 2 * PAGE_SIZE, 1),
+                run(4 * PAGE_SIZE, 0x10_0000, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_access_profile() {
+        const NUM_PAGES: usize = 1100;
+        // The second region starts above a hole of the guest memory.
+        const HIGH_GUEST_ADDR: u64 = 0x1_0000_0000;
+
+        let _guard = CLEAR_REFS_LOCK.lock().unwrap();
+        let mapping = TestMapping::new(NUM_PAGES);
+        let regions = [
+            mapping.region(0, 1024, 0),
+            mapping.region(1024, NUM_PAGES as u64 - 1024, HIGH_GUEST_ADDR),
+        ];
+        let config = TrackAccessConfig { duration_s: 10 };
+        let mut tracker = AccessTracker::new(&config, regions.into_iter()).unwrap();
+        assert_eq!(tracker.profile().state, AccessTrackingState::Waiting);
+
+        // Pages mapped before the resume are not reported unless written again.
+        mapping.write(0);
+        mapping.write(2);
+        tracker.on_resume();
+        assert_eq!(tracker.state, AccessTrackingState::Tracking);
+
+        mapping.write(2);
+        mapping.read(5);
+        mapping.write(6);
+        mapping.write(7);
+        // Across two chunks of the walk.
+        mapping.write(511);
+        mapping.write(512);
+        // Across the two regions, which are not consecutive in the guest memory.
+        mapping.write(1023);
+        mapping.write(1024);
+        mapping.read(NUM_PAGES - 1);
+
+        // The profile is only reported once complete.
+        tracker.state = AccessTrackingState::Collecting;
+        assert!(!tracker.collect(1).unwrap());
+        assert!(!tracker.collect(1).unwrap());
+        let profile = tracker.profile();
+        assert_eq!(profile.state, AccessTrackingState::Collecting);
+        assert!(profile.runs.is_empty());
+        // The first region takes two chunks, and the second one a single chunk.
+        assert!(tracker.collect(1).unwrap());
+        tracker.state = AccessTrackingState::Complete;
+
+        let mut expected = vec![
+            run(5 * PAGE_SIZE, 5 * PAGE_SIZE, 3),
+            run(511 * PAGE_SIZE, 511 * PAGE_SIZE, 2),
+            run(1023 * PAGE_SIZE, 1023 * PAGE_SIZE, 1),
+            run(HIGH_GUEST_ADDR, 1024 * PAGE_SIZE, 1),
+            run(
+                HIGH_GUEST_ADDR + (NUM_PAGES as u64 - 1025) * PAGE_SIZE,
+                (NUM_PAGES as u64 - 1) * PAGE_SIZE,
+                1,
+            ),
+        ];
+        // Only the pages written by the guest are soft-dirty, which the kernel supports on
+        // x86_64.
+        if cfg!(target_arch = "x86_64") {
+            expected.insert(0, run(2 * PAGE_SIZE, 2 * PAGE_SIZE, 1));
+        }
+        let profile = tracker.profile();
+        assert_eq!(profile.state, AccessTrackingState::Complete);
+        assert_eq!(profile.page_size, PAGE_SIZE);
+        assert_eq!(
+            profile.touched_pages,
+            expected.iter().map(|run| run.num_pages).sum::<u64>()
+        );
+        assert_eq!(profile.runs, expected);
+    }
+
+    #[test]
+    fn test_access_tracker_timer() {
+        let _guard = CLEAR_REFS_LOCK.lock().unwrap();
+        let mapping = TestMapping::new(16);
+        let config = TrackAccessConfig { duration_s: 1 };
+        let mut tracker =
+            AccessTracker::new(&config, std::iter::once(mapping.region(0, 16, 0))).unwrap();
+
+        // Nothing happens before the microVM resumed.
+        tracker.process_timer();
+        assert_eq!(tracker.state, AccessTrackingState::Waiting);
+
+        tracker.on_resume();
+        mapping.write(3);
+        assert!(matches!(
+            tracker.timer_fd.get_state(),
+            TimerState::Oneshot(_)
+        ));
+        // The tracking only starts on the first resume.
+        tracker.on_resume();
+        assert_eq!(tracker.state, AccessTrackingState::Tracking);
+
+        // A single step of the walk covers the region.
+        tracker.process_timer();
+        let profile = tracker.profile();
+        assert_eq!(profile.state, AccessTrackingState::Complete);
+        assert_eq!(profile.duration_s, 1);
+        assert_eq!(profile.touched_pages, 1);
+        assert_eq!(profile.runs, vec![run(3 * PAGE_SIZE, 3 * PAGE_SIZE, 1)]);
+    }
+}
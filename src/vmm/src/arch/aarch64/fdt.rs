@@ -16,12 +16,16 @@ use super::super::{DeviceType, InitrdConfig};
 use super::cache_info::{read_cache_config, CacheEntry};
 use super::get_fdt_addr;
 use super::gic::GICDevice;
-use crate::vstate::memory::{Address, Bytes, GuestAddress, GuestMemory, GuestMemoryMmap};
+use crate::vstate::memory::{
+    Address, Bytes, GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion,
+};
 
 // This is a value for uniquely identifying the FDT node declaring the interrupt controller.
 const GIC_PHANDLE: u32 = 1;
 // This is a value for uniquely identifying the FDT node containing the clock definition.
 const CLOCK_PHANDLE: u32 = 2;
+// This is a value for uniquely identifying the FDT node declaring the GICv3 ITS.
+const GIC_ITS_PHANDLE: u32 = 3;
 // You may be wondering why this big value?
 // This phandle is used to uniquely identify the FDT nodes containing cache information. Each cpu
 // can have a variable number of caches, some of these caches may be shared with other cpus.
@@ -219,14 +223,16 @@ fn create_cpu_nodes(fdt: &mut FdtWriter, vcpu_mpidr: &[u64]) -> Result<(), FdtEr
 }
 
 fn create_memory_node(fdt: &mut FdtWriter, guest_mem: &GuestMemoryMmap) -> Result<(), FdtError> {
-    let mem_size = guest_mem.last_addr().raw_value() - super::layout::DRAM_MEM_START + 1;
     // See https://github.com/torvalds/linux/blob/master/Documentation/devicetree/booting-without-of.txt#L960
-    // for an explanation of this.
-    let mem_reg_prop = &[super::layout::DRAM_MEM_START, mem_size];
+    // for an explanation of this. Each region of guest memory is one (address, size) pair.
+    let mem_reg_prop = guest_mem
+        .iter()
+        .flat_map(|region| [region.start_addr().raw_value(), region.len()])
+        .collect::<Vec<_>>();
 
     let mem = fdt.begin_node("memory")?;
     fdt.property_string("device_type", "memory")?;
-    fdt.property_array_u64("reg", mem_reg_prop)?;
+    fdt.property_array_u64("reg", &mem_reg_prop)?;
     fdt.end_node(mem)?;
 
     Ok(())
@@ -279,11 +285,30 @@ fn create_gic_node(fdt: &mut FdtWriter, gic_device: &GICDevice) -> Result<(), Fd
     ];
 
     fdt.property_array_u32("interrupts", &gic_intr)?;
+
+    if let Some(its_properties) = gic_device.its_properties() {
+        create_its_node(fdt, &its_properties)?;
+    }
+
     fdt.end_node(interrupt)?;
 
     Ok(())
 }
 
+fn create_its_node(fdt: &mut FdtWriter, its_properties: &[u64; 2]) -> Result<(), FdtError> {
+    // The ITS translates the MSIs written by devices into LPIs, it is therefore described as a
+    // child of the GIC node. Each device is identified to the ITS by a single cell (its DeviceID).
+    let its = fdt.begin_node(&format!("its@{:x}", its_properties[0]))?;
+    fdt.property_string("compatible", "arm,gic-v3-its")?;
+    fdt.property_null("msi-controller")?;
+    fdt.property_u32("#msi-cells", 1)?;
+    fdt.property_array_u64("reg", its_properties)?;
+    fdt.property_u32("phandle", GIC_ITS_PHANDLE)?;
+    fdt.end_node(its)?;
+
+    Ok(())
+}
+
 fn create_clock_node(fdt: &mut FdtWriter) -> Result<(), FdtError> {
     // The Advanced Peripheral Bus (APB) is part of the Advanced Microcontroller Bus Architecture
     // (AMBA) protocol family. It defines a low-cost interface that is optimized for minimal power
@@ -398,18 +423,36 @@ fn create_devices_node<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildH
 ) -> Result<(), FdtError> {
     // Create one temp Vec to store all virtio devices
     let mut ordered_virtio_device: Vec<&T> = Vec::new();
+    let mut ordered_serial_device: Vec<&T> = Vec::new();
 
     for ((device_type, _device_id), info) in dev_info {
         match device_type {
             DeviceType::BootTimer => (), // since it's not a real device
             DeviceType::Rtc => create_rtc_node(fdt, info)?,
-            DeviceType::Serial => create_serial_node(fdt, info)?,
+            DeviceType::Serial => ordered_serial_device.push(info),
             DeviceType::Virtio(_) => {
                 ordered_virtio_device.push(info);
             }
         }
     }
 
+    // Serial ports are allocated in the order they are configured, so sorting them by address
+    // gives the guest the same order through the `serialN` aliases.
+    ordered_serial_device.sort_by_key(|&a| a.addr());
+    for serial_device_info in ordered_serial_device.iter() {
+        create_serial_node(fdt, *serial_device_info)?;
+    }
+    if !ordered_serial_device.is_empty() {
+        let aliases = fdt.begin_node("aliases")?;
+        for (index, serial_device_info) in ordered_serial_device.iter().enumerate() {
+            fdt.property_string(
+                &format!("serial{}", index),
+                &format!("/uart@{:x}", serial_device_info.addr()),
+            )?;
+        }
+        fdt.end_node(aliases)?;
+    }
+
     // Sort out virtio devices by address from low to high and insert them into fdt table.
     ordered_virtio_device.sort_by_key(|&a| a.addr());
     for ordered_device_info in ordered_virtio_device.drain(..) {
@@ -478,13 +521,20 @@ mod tests {
                     irq: 3,
                 },
             ),
+            (
+                (DeviceType::Serial, "mgmt".to_string()),
+                MMIODeviceInfo {
+                    addr: 3 * LEN,
+                    irq: 4,
+                },
+            ),
         ]
         .iter()
         .cloned()
         .collect();
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
-        let gic = create_gic(&vm, 1, None).unwrap();
+        let gic = create_gic(&vm, 1, None, false).unwrap();
         create_fdt(
             &mem,
             vec![0],
@@ -501,7 +551,7 @@ mod tests {
         let mem = arch_mem(layout::FDT_MAX_SIZE + 0x1000);
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
-        let gic = create_gic(&vm, 1, None).unwrap();
+        let gic = create_gic(&vm, 1, None, false).unwrap();
 
         let saved_dtb_bytes = match gic.fdt_compatibility() {
             "arm,gic-v3" => include_bytes!("output_GICv3.dtb"),
@@ -557,7 +607,7 @@ mod tests {
         let mem = arch_mem(layout::FDT_MAX_SIZE + 0x1000);
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
-        let gic = create_gic(&vm, 1, None).unwrap();
+        let gic = create_gic(&vm, 1, None, false).unwrap();
 
         let saved_dtb_bytes = match gic.fdt_compatibility() {
             "arm,gic-v3" => include_bytes!("output_initrd_GICv3.dtb"),
@@ -612,4 +662,25 @@ mod tests {
             format!("{:?}", generated_fdt)
         );
     }
+
+    #[test]
+    fn test_create_its_node() {
+        let mut fdt = FdtWriter::new().unwrap();
+        let root = fdt.begin_node("").unwrap();
+        fdt.property_u32("#address-cells", ADDRESS_CELLS).unwrap();
+        fdt.property_u32("#size-cells", SIZE_CELLS).unwrap();
+        create_its_node(&mut fdt, &[0x3ffd_0000, 0x2_0000]).unwrap();
+        fdt.end_node(root).unwrap();
+        let fdt = device_tree::DeviceTree::load(&fdt.finish().unwrap()).unwrap();
+
+        let its = fdt.find("/its@3ffd0000").unwrap();
+        assert_eq!(its.prop_str("compatible").unwrap(), "arm,gic-v3-its");
+        assert!(its.has_prop("msi-controller"));
+        assert_eq!(its.prop_u32("#msi-cells").unwrap(), 1);
+        assert_eq!(its.prop_u32("phandle").unwrap(), GIC_ITS_PHANDLE);
+        assert_eq!(
+            *its.prop_raw("reg").unwrap(),
+            [0x3ffd_0000u64.to_be_bytes(), 0x2_0000u64.to_be_bytes()].concat()
+        );
+    }
 }
@@ -140,7 +140,7 @@ mod tests {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
         let _ = vm.create_vcpu(0).unwrap();
-        let gic_fd = match create_gic(&vm, 1, Some(GICVersion::GICV2)) {
+        let gic_fd = match create_gic(&vm, 1, Some(GICVersion::GICV2), false) {
             Ok(gic_fd) => gic_fd,
             Err(GicError::CreateGIC(_)) => return,
             _ => panic!("Failed to open setup GICv2"),
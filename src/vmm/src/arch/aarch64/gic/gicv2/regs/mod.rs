@@ -22,6 +22,7 @@ pub fn save_state(fd: &DeviceFd, mpidrs: &[u64]) -> Result<GicState, GicError> {
     Ok(GicState {
         dist: dist_regs::get_dist_regs(fd)?,
         gic_vcpu_states: vcpu_states,
+        its: None,
     })
 }
 
@@ -50,7 +51,7 @@ mod tests {
     fn test_vm_save_restore_state() {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
-        let gic_fd = match create_gic(&vm, 1, Some(GICVersion::GICV2)) {
+        let gic_fd = match create_gic(&vm, 1, Some(GICVersion::GICV2), false) {
             Ok(gic_fd) => gic_fd,
             Err(GicError::CreateGIC(_)) => return,
             _ => panic!("Failed to open setup GICv2"),
@@ -67,7 +68,7 @@ mod tests {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
         let _vcpu = vm.create_vcpu(0).unwrap();
-        let gic = create_gic(&vm, 1, Some(GICVersion::GICV2)).expect("Cannot create gic");
+        let gic = create_gic(&vm, 1, Some(GICVersion::GICV2), false).expect("Cannot create gic");
         let gic_fd = gic.device_fd();
 
         let vm_state = save_state(gic_fd, &mpidr).unwrap();
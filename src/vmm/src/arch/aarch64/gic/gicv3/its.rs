@@ -0,0 +1,361 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The GICv3 Interrupt Translation Service (ITS), which translates the message signaled
+//! interrupts (MSIs) written by devices into LPIs.
+//!
+//! The ITS keeps most of its state in tables allocated by the guest in its own memory. KVM flushes
+//! them to guest memory when saving and reads them back when restoring, so only the ITS
+//! registers are part of the snapshot. See `Documentation/virt/kvm/devices/arm-vgic-its.rst` in
+//! the Linux kernel for the save and restore sequences.
+
+use kvm_bindings::kvm_device_attr;
+use kvm_ioctls::{DeviceFd, VmFd};
+use serde::{Deserialize, Serialize};
+
+use crate::arch::aarch64::gic::GicError;
+
+// Offsets of the ITS registers in the ITS control register frame.
+const GITS_CTLR: u64 = 0x0000;
+const GITS_IIDR: u64 = 0x0004;
+const GITS_CBASER: u64 = 0x0080;
+const GITS_CWRITER: u64 = 0x0088;
+const GITS_CREADR: u64 = 0x0090;
+const GITS_BASER: u64 = 0x0100;
+/// Number of GITS_BASER<n> registers.
+const GITS_BASER_COUNT: usize = 8;
+
+/// Access to the attributes of a KVM device.
+///
+/// Abstracted so that the sequences of attribute accesses can be checked in unit tests.
+pub(crate) trait DeviceAttributes {
+    /// Sets an attribute of the device.
+    fn set_attr(&self, attr: &kvm_device_attr) -> Result<(), kvm_ioctls::Error>;
+    /// Gets an attribute of the device.
+    fn get_attr(&self, attr: &mut kvm_device_attr) -> Result<(), kvm_ioctls::Error>;
+}
+
+impl DeviceAttributes for DeviceFd {
+    fn set_attr(&self, attr: &kvm_device_attr) -> Result<(), kvm_ioctls::Error> {
+        self.set_device_attr(attr)
+    }
+
+    fn get_attr(&self, attr: &mut kvm_device_attr) -> Result<(), kvm_ioctls::Error> {
+        self.get_device_attr(attr)
+    }
+}
+
+/// Structure used for serializing the state of the ITS registers.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItsState {
+    /// GITS_CTLR register.
+    pub ctlr: u64,
+    /// GITS_IIDR register.
+    pub iidr: u64,
+    /// GITS_CBASER register.
+    pub cbaser: u64,
+    /// GITS_CWRITER register.
+    pub cwriter: u64,
+    /// GITS_CREADR register.
+    pub creadr: u64,
+    /// GITS_BASER<n> registers.
+    pub baser: [u64; GITS_BASER_COUNT],
+}
+
+/// A GICv3 ITS device.
+#[derive(Debug)]
+pub struct GicIts {
+    fd: DeviceFd,
+    addr: u64,
+}
+
+impl GicIts {
+    /// Size of the ITS register frames.
+    pub const SIZE: u64 = 2 * 0x0001_0000;
+
+    const DEVICE_TYPE: u32 = kvm_bindings::kvm_device_type_KVM_DEV_TYPE_ARM_VGIC_ITS;
+
+    /// Creates an ITS with its register frames at `addr`.
+    ///
+    /// The GICv3 distributor and redistributors must be created first.
+    pub fn create(vm: &VmFd, addr: u64) -> Result<Self, GicError> {
+        // Probe for ITS support first, so that hosts without it get a precise error.
+        let mut its_device = kvm_bindings::kvm_create_device {
+            type_: Self::DEVICE_TYPE,
+            fd: 0,
+            flags: kvm_bindings::KVM_CREATE_DEVICE_TEST,
+        };
+        vm.create_device(&mut its_device)
+            .map_err(GicError::ItsNotSupported)?;
+
+        its_device.flags = 0;
+        let fd = vm
+            .create_device(&mut its_device)
+            .map_err(GicError::CreateITS)?;
+
+        set_attr(
+            &fd,
+            kvm_bindings::KVM_DEV_ARM_VGIC_GRP_ADDR,
+            u64::from(kvm_bindings::KVM_VGIC_ITS_ADDR_TYPE),
+            &addr as *const u64 as u64,
+        )?;
+        set_attr(
+            &fd,
+            kvm_bindings::KVM_DEV_ARM_VGIC_GRP_CTRL,
+            u64::from(kvm_bindings::KVM_DEV_ARM_VGIC_CTRL_INIT),
+            0,
+        )?;
+
+        Ok(GicIts { fd, addr })
+    }
+
+    /// Returns the address and the size of the ITS register frames.
+    pub fn device_properties(&self) -> [u64; 2] {
+        [self.addr, Self::SIZE]
+    }
+
+    /// Flushes the ITS tables to guest memory and saves the ITS registers.
+    pub fn save_state(&self) -> Result<ItsState, GicError> {
+        save_state(&self.fd)
+    }
+
+    /// Restores the ITS registers and reloads the ITS tables from guest memory.
+    ///
+    /// Guest memory, the vCPUs and the GIC redistributors must be restored first.
+    pub fn restore_state(&self, state: &ItsState) -> Result<(), GicError> {
+        restore_state(&self.fd, state)
+    }
+}
+
+fn set_attr<F: DeviceAttributes>(fd: &F, group: u32, attr: u64, addr: u64) -> Result<(), GicError> {
+    let attr = kvm_device_attr {
+        group,
+        attr,
+        addr,
+        flags: 0,
+    };
+    fd.set_attr(&attr)
+        .map_err(|err| GicError::DeviceAttribute(err, true, group))
+}
+
+fn get_reg<F: DeviceAttributes>(fd: &F, offset: u64) -> Result<u64, GicError> {
+    let mut val = 0u64;
+    let mut attr = kvm_device_attr {
+        group: kvm_bindings::KVM_DEV_ARM_VGIC_GRP_ITS_REGS,
+        attr: offset,
+        addr: &mut val as *mut u64 as u64,
+        flags: 0,
+    };
+    fd.get_attr(&mut attr).map_err(|err| {
+        GicError::DeviceAttribute(err, false, kvm_bindings::KVM_DEV_ARM_VGIC_GRP_ITS_REGS)
+    })?;
+    Ok(val)
+}
+
+fn set_reg<F: DeviceAttributes>(fd: &F, offset: u64, val: u64) -> Result<(), GicError> {
+    set_attr(
+        fd,
+        kvm_bindings::KVM_DEV_ARM_VGIC_GRP_ITS_REGS,
+        offset,
+        &val as *const u64 as u64,
+    )
+}
+
+fn baser_offset(index: usize) -> u64 {
+    GITS_BASER + 8 * index as u64
+}
+
+pub(crate) fn save_state<F: DeviceAttributes>(fd: &F) -> Result<ItsState, GicError> {
+    // The tables must be flushed while the registers still point to them.
+    set_attr(
+        fd,
+        kvm_bindings::KVM_DEV_ARM_VGIC_GRP_CTRL,
+        u64::from(kvm_bindings::KVM_DEV_ARM_ITS_SAVE_TABLES),
+        0,
+    )?;
+
+    let mut state = ItsState {
+        ctlr: get_reg(fd, GITS_CTLR)?,
+        iidr: get_reg(fd, GITS_IIDR)?,
+        cbaser: get_reg(fd, GITS_CBASER)?,
+        cwriter: get_reg(fd, GITS_CWRITER)?,
+        creadr: get_reg(fd, GITS_CREADR)?,
+        baser: [0; GITS_BASER_COUNT],
+    };
+    for (index, baser) in state.baser.iter_mut().enumerate() {
+        *baser = get_reg(fd, baser_offset(index))?;
+    }
+
+    Ok(state)
+}
+
+pub(crate) fn restore_state<F: DeviceAttributes>(fd: &F, state: &ItsState) -> Result<(), GicError> {
+    // The order is mandated by the KVM ITS device: the registers describing the tables come
+    // first, then the tables are restored, and the ITS is enabled last.
+    set_reg(fd, GITS_IIDR, state.iidr)?;
+    for (index, baser) in state.baser.iter().enumerate() {
+        set_reg(fd, baser_offset(index), *baser)?;
+    }
+    set_reg(fd, GITS_CBASER, state.cbaser)?;
+    set_reg(fd, GITS_CREADR, state.creadr)?;
+    set_reg(fd, GITS_CWRITER, state.cwriter)?;
+    set_attr(
+        fd,
+        kvm_bindings::KVM_DEV_ARM_VGIC_GRP_CTRL,
+        u64::from(kvm_bindings::KVM_DEV_ARM_ITS_RESTORE_TABLES),
+        0,
+    )?;
+    set_reg(fd, GITS_CTLR, state.ctlr)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::undocumented_unsafe_blocks)]
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const GRP_CTRL: u32 = kvm_bindings::KVM_DEV_ARM_VGIC_GRP_CTRL;
+    const GRP_ITS_REGS: u32 = kvm_bindings::KVM_DEV_ARM_VGIC_GRP_ITS_REGS;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Access {
+        Set(u32, u64),
+        Get(u32, u64),
+    }
+
+    /// Records the attribute accesses and emulates the ITS registers.
+    #[derive(Debug, Default)]
+    struct MockItsFd {
+        accesses: RefCell<Vec<Access>>,
+        regs: RefCell<HashMap<u64, u64>>,
+        fail_on: Option<Access>,
+    }
+
+    impl MockItsFd {
+        fn access(&self, access: Access) -> Result<(), kvm_ioctls::Error> {
+            self.accesses.borrow_mut().push(access);
+            if self.fail_on == Some(access) {
+                return Err(kvm_ioctls::Error::new(libc::EINVAL));
+            }
+            Ok(())
+        }
+    }
+
+    impl DeviceAttributes for MockItsFd {
+        fn set_attr(&self, attr: &kvm_device_attr) -> Result<(), kvm_ioctls::Error> {
+            self.access(Access::Set(attr.group, attr.attr))?;
+            if attr.group == GRP_ITS_REGS {
+                let val = unsafe { *(attr.addr as *const u64) };
+                self.regs.borrow_mut().insert(attr.attr, val);
+            }
+            Ok(())
+        }
+
+        fn get_attr(&self, attr: &mut kvm_device_attr) -> Result<(), kvm_ioctls::Error> {
+            self.access(Access::Get(attr.group, attr.attr))?;
+            let val = self.regs.borrow().get(&attr.attr).copied().unwrap_or(0);
+            unsafe { *(attr.addr as *mut u64) = val };
+            Ok(())
+        }
+    }
+
+    fn test_state() -> ItsState {
+        ItsState {
+            ctlr: 1,
+            iidr: 0x4300_143b,
+            cbaser: 0xb800_0000_8001_0000,
+            cwriter: 0x40,
+            creadr: 0x20,
+            baser: [
+                0x8000_0000_8002_0000,
+                0x8000_0000_8003_0000,
+                0,
+                0,
+                0,
+                0,
+                0,
+                7,
+            ],
+        }
+    }
+
+    #[test]
+    fn test_save_restore_sequence() {
+        let fd = MockItsFd::default();
+        let state = test_state();
+
+        restore_state(&fd, &state).unwrap();
+        let mut expected = vec![Access::Set(GRP_ITS_REGS, GITS_IIDR)];
+        expected.extend((0..GITS_BASER_COUNT).map(|i| Access::Set(GRP_ITS_REGS, baser_offset(i))));
+        expected.extend([
+            Access::Set(GRP_ITS_REGS, GITS_CBASER),
+            Access::Set(GRP_ITS_REGS, GITS_CREADR),
+            Access::Set(GRP_ITS_REGS, GITS_CWRITER),
+            Access::Set(
+                GRP_CTRL,
+                u64::from(kvm_bindings::KVM_DEV_ARM_ITS_RESTORE_TABLES),
+            ),
+            Access::Set(GRP_ITS_REGS, GITS_CTLR),
+        ]);
+        assert_eq!(*fd.accesses.borrow(), expected);
+
+        fd.accesses.borrow_mut().clear();
+        assert_eq!(save_state(&fd).unwrap(), state);
+        let accesses = fd.accesses.borrow();
+        // The tables are flushed to guest memory before anything else.
+        assert_eq!(
+            accesses[0],
+            Access::Set(
+                GRP_CTRL,
+                u64::from(kvm_bindings::KVM_DEV_ARM_ITS_SAVE_TABLES)
+            )
+        );
+        assert_eq!(accesses.len(), 1 + 5 + GITS_BASER_COUNT);
+        assert!(accesses[1..]
+            .iter()
+            .all(|access| matches!(access, Access::Get(GRP_ITS_REGS, _))));
+    }
+
+    #[test]
+    fn test_save_restore_errors() {
+        let state = test_state();
+
+        // A failure to restore the tables leaves the ITS disabled.
+        let restore_tables = Access::Set(
+            GRP_CTRL,
+            u64::from(kvm_bindings::KVM_DEV_ARM_ITS_RESTORE_TABLES),
+        );
+        let fd = MockItsFd {
+            fail_on: Some(restore_tables),
+            ..Default::default()
+        };
+        assert_eq!(
+            restore_state(&fd, &state).unwrap_err(),
+            GicError::DeviceAttribute(kvm_ioctls::Error::new(libc::EINVAL), true, GRP_CTRL)
+        );
+        assert_eq!(fd.accesses.borrow().last(), Some(&restore_tables));
+        assert!(!fd.regs.borrow().contains_key(&GITS_CTLR));
+
+        // Registers are not read if the tables could not be saved.
+        let fd = MockItsFd {
+            fail_on: Some(Access::Set(
+                GRP_CTRL,
+                u64::from(kvm_bindings::KVM_DEV_ARM_ITS_SAVE_TABLES),
+            )),
+            ..Default::default()
+        };
+        save_state(&fd).unwrap_err();
+        assert_eq!(fd.accesses.borrow().len(), 1);
+
+        let fd = MockItsFd {
+            fail_on: Some(Access::Get(GRP_ITS_REGS, GITS_CBASER)),
+            ..Default::default()
+        };
+        assert_eq!(
+            save_state(&fd).unwrap_err(),
+            GicError::DeviceAttribute(kvm_ioctls::Error::new(libc::EINVAL), false, GRP_ITS_REGS)
+        );
+    }
+}
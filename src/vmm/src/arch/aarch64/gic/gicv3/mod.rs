@@ -1,14 +1,16 @@
 // Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod its;
 mod regs;
 
+use its::GicIts;
 use kvm_ioctls::{DeviceFd, VmFd};
 
 use crate::arch::aarch64::gic::{GicError, GicState};
 
 #[derive(Debug)]
-pub struct GICv3(super::GIC);
+pub struct GICv3(super::GIC, Option<GicIts>);
 
 impl std::ops::Deref for GICv3 {
     type Target = super::GIC;
@@ -48,6 +50,11 @@ impl GICv3 {
         vcpu_count * GICv3::KVM_VGIC_V3_REDIST_SIZE
     }
 
+    /// Get the address of the ITS, right below the redistributors.
+    fn get_its_addr(vcpu_count: u64) -> u64 {
+        GICv3::get_redists_addr(vcpu_count) - GicIts::SIZE
+    }
+
     pub const VERSION: u32 = kvm_bindings::kvm_device_type_KVM_DEV_TYPE_ARM_VGIC_V3;
 
     pub fn fdt_compatibility(&self) -> &str {
@@ -58,26 +65,42 @@ impl GICv3 {
         GICv3::ARCH_GIC_V3_MAINT_IRQ
     }
 
+    /// Returns the address and the size of the ITS, if the device has one.
+    pub fn its_properties(&self) -> Option<[u64; 2]> {
+        self.1.as_ref().map(GicIts::device_properties)
+    }
+
     /// Create the GIC device object
     pub fn create_device(fd: DeviceFd, vcpu_count: u64) -> Self {
-        GICv3(super::GIC {
-            fd,
-            properties: [
-                GICv3::get_dist_addr(),
-                GICv3::get_dist_size(),
-                GICv3::get_redists_addr(vcpu_count),
-                GICv3::get_redists_size(vcpu_count),
-            ],
-            vcpu_count,
-        })
+        GICv3(
+            super::GIC {
+                fd,
+                properties: [
+                    GICv3::get_dist_addr(),
+                    GICv3::get_dist_size(),
+                    GICv3::get_redists_addr(vcpu_count),
+                    GICv3::get_redists_size(vcpu_count),
+                ],
+                vcpu_count,
+            },
+            None,
+        )
     }
 
     pub fn save_device(&self, mpidrs: &[u64]) -> Result<GicState, GicError> {
-        regs::save_state(&self.fd, mpidrs)
+        let mut state = regs::save_state(&self.fd, mpidrs)?;
+        state.its = self.1.as_ref().map(GicIts::save_state).transpose()?;
+        Ok(state)
     }
 
     pub fn restore_device(&self, mpidrs: &[u64], state: &GicState) -> Result<(), GicError> {
-        regs::restore_state(&self.fd, mpidrs, state)
+        // The ITS reads its tables through the redistributors, which must be restored first.
+        regs::restore_state(&self.fd, mpidrs, state)?;
+        match (&self.1, &state.its) {
+            (Some(its), Some(its_state)) => its.restore_state(its_state),
+            (None, None) => Ok(()),
+            _ => Err(GicError::InconsistentItsState),
+        }
     }
 
     pub fn init_device_attributes(gic_device: &Self) -> Result<(), GicError> {
@@ -116,16 +139,20 @@ impl GICv3 {
             .map_err(GicError::CreateGIC)
     }
 
-    /// Method to initialize the GIC device
-    pub fn create(vm: &VmFd, vcpu_count: u64) -> Result<Self, GicError> {
+    /// Method to initialize the GIC device, with an ITS if `its` is set.
+    pub fn create(vm: &VmFd, vcpu_count: u64, its: bool) -> Result<Self, GicError> {
         let vgic_fd = Self::init_device(vm)?;
 
-        let device = Self::create_device(vgic_fd, vcpu_count);
+        let mut device = Self::create_device(vgic_fd, vcpu_count);
 
         Self::init_device_attributes(&device)?;
 
         Self::finalize_device(&device)?;
 
+        if its {
+            device.1 = Some(GicIts::create(vm, Self::get_its_addr(vcpu_count))?);
+        }
+
         Ok(device)
     }
 
@@ -210,7 +237,7 @@ mod tests {
 
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
-        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3)).expect("Cannot create gic");
+        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3), false).expect("Cannot create gic");
         save_pending_tables(gic.device_fd()).unwrap();
 
         unsafe { libc::close(gic.device_fd().as_raw_fd()) };
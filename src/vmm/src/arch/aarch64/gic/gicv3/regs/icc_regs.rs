@@ -177,7 +177,7 @@ mod tests {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
         let _ = vm.create_vcpu(0).unwrap();
-        let gic_fd = create_gic(&vm, 1, Some(GICVersion::GICV3)).expect("Cannot create gic");
+        let gic_fd = create_gic(&vm, 1, Some(GICVersion::GICV3), false).expect("Cannot create gic");
 
         let gicr_typer = 123;
         let res = get_icc_regs(gic_fd.device_fd(), gicr_typer);
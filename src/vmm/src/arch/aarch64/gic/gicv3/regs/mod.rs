@@ -26,6 +26,7 @@ pub fn save_state(fd: &DeviceFd, mpidrs: &[u64]) -> Result<GicState, GicError> {
     Ok(GicState {
         dist: dist_regs::get_dist_regs(fd)?,
         gic_vcpu_states: vcpu_states,
+        its: None,
     })
 }
 
@@ -55,7 +56,7 @@ mod tests {
     fn test_vm_save_restore_state() {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
-        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3)).expect("Cannot create gic");
+        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3), false).expect("Cannot create gic");
         let gic_fd = gic.device_fd();
 
         let mpidr = vec![1];
@@ -69,7 +70,7 @@ mod tests {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
         let _vcpu = vm.create_vcpu(0).unwrap();
-        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3)).expect("Cannot create gic");
+        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3), false).expect("Cannot create gic");
         let gic_fd = gic.device_fd();
 
         let vm_state = save_state(gic_fd, &mpidr).unwrap();
@@ -6,6 +6,7 @@ mod gicv3;
 mod regs;
 
 use gicv2::GICv2;
+pub use gicv3::its::ItsState;
 use gicv3::GICv3;
 use kvm_ioctls::{DeviceFd, VmFd};
 pub use regs::GicState;
@@ -52,6 +53,14 @@ pub enum GicError {
     InconsistentVcpuCount,
     /// The VgicSysRegsState is invalid.
     InvalidVgicSysRegState,
+    /// The host does not support the GICv3 ITS: {0}
+    ItsNotSupported(kvm_ioctls::Error),
+    /// Error while calling KVM ioctl for setting up the GICv3 ITS: {0}
+    CreateITS(kvm_ioctls::Error),
+    /// The GICv3 ITS requires a GICv3 interrupt controller.
+    ItsRequiresGicV3,
+    /// The presence of an ITS in the GicState doesn't match the GIC device.
+    InconsistentItsState,
 }
 
 /// List of implemented GICs.
@@ -59,7 +68,7 @@ pub enum GicError {
 pub enum GICVersion {
     /// Legacy version.
     GICV2,
-    /// GICV3 without ITS.
+    /// GICV3, with or without ITS.
     GICV3,
 }
 
@@ -68,7 +77,7 @@ pub enum GICVersion {
 pub enum GICDevice {
     /// Legacy version.
     V2(GICv2),
-    /// GICV3 without ITS.
+    /// GICV3, with or without ITS.
     V3(GICv3),
 }
 impl GICDevice {
@@ -96,6 +105,14 @@ impl GICDevice {
         }
     }
 
+    /// Returns the address and the size of the ITS, if the device has one
+    pub fn its_properties(&self) -> Option<[u64; 2]> {
+        match self {
+            Self::V2(_) => None,
+            Self::V3(x) => x.its_properties(),
+        }
+    }
+
     /// Returns the fdt compatibility property of the device
     pub fn fdt_compatibility(&self) -> &str {
         match self {
@@ -150,15 +167,19 @@ impl GICDevice {
 /// If "version" parameter is "None" the function will try to create by default a GICv3 device.
 /// If that fails it will try to fall-back to a GICv2 device.
 /// If version is Some the function will try to create a device of exactly the specified version.
+/// If "its" is set, the device is a GICv3 with an ITS, or the creation fails.
 pub fn create_gic(
     vm: &VmFd,
     vcpu_count: u64,
     version: Option<GICVersion>,
+    its: bool,
 ) -> Result<GICDevice, GicError> {
     match version {
+        Some(GICVersion::GICV2) if its => Err(GicError::ItsRequiresGicV3),
         Some(GICVersion::GICV2) => GICv2::create(vm, vcpu_count).map(GICDevice::V2),
-        Some(GICVersion::GICV3) => GICv3::create(vm, vcpu_count).map(GICDevice::V3),
-        None => GICv3::create(vm, vcpu_count)
+        Some(GICVersion::GICV3) => GICv3::create(vm, vcpu_count, its).map(GICDevice::V3),
+        None if its => GICv3::create(vm, vcpu_count, true).map(GICDevice::V3),
+        None => GICv3::create(vm, vcpu_count, false)
             .map(GICDevice::V3)
             .or_else(|_| GICv2::create(vm, vcpu_count).map(GICDevice::V2)),
     }
@@ -175,6 +196,27 @@ mod tests {
     fn test_create_gic() {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
-        create_gic(&vm, 1, None).unwrap();
+        create_gic(&vm, 1, None, false).unwrap();
+    }
+
+    #[test]
+    fn test_create_gic_its() {
+        let kvm = Kvm::new().unwrap();
+        let vm = kvm.create_vm().unwrap();
+        assert_eq!(
+            create_gic(&vm, 1, Some(GICVersion::GICV2), true).unwrap_err(),
+            GicError::ItsRequiresGicV3
+        );
+
+        let vm = kvm.create_vm().unwrap();
+        match create_gic(&vm, 1, None, true) {
+            Ok(gic) => {
+                let [addr, size] = gic.its_properties().unwrap();
+                // The ITS lies right below the redistributors.
+                assert_eq!(addr + size, gic.device_properties()[2]);
+            }
+            // Not all hosts support the ITS, but those which don't must say so.
+            Err(err) => assert!(matches!(err, GicError::ItsNotSupported(_)), "{err}"),
+        }
     }
 }
@@ -9,6 +9,7 @@ use kvm_bindings::kvm_device_attr;
 use kvm_ioctls::DeviceFd;
 use serde::{Deserialize, Serialize};
 
+use crate::arch::aarch64::gic::gicv3::its::ItsState;
 use crate::arch::aarch64::gic::GicError;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,6 +31,8 @@ pub struct GicState {
     pub dist: Vec<GicRegState<u32>>,
     /// The state of the vcpu interfaces.
     pub gic_vcpu_states: Vec<GicVcpuState>,
+    /// The state of the ITS, if the GIC has one.
+    pub its: Option<ItsState>,
 }
 
 /// Structure used for serializing the state of the GIC registers for a specific vCPU.
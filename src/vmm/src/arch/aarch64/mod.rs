@@ -12,13 +12,13 @@ pub mod regs;
 /// Helper methods for VcpuFd.
 pub mod vcpu;
 
-use std::cmp::min;
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fmt::Debug;
 
 pub use self::fdt::DeviceInfoForFDT;
 use self::gic::GICDevice;
+use crate::arch::memory_layout::{ram_regions, MemoryLayoutError, MemoryRange};
 use crate::arch::DeviceType;
 use crate::vstate::memory::{Address, GuestAddress, GuestMemory, GuestMemoryMmap};
 
@@ -36,11 +36,25 @@ pub const MMIO_MEM_START: u64 = layout::MAPPED_IO_START;
 /// The size of the memory area reserved for MMIO devices.
 pub const MMIO_MEM_SIZE: u64 = layout::DRAM_MEM_START - layout::MAPPED_IO_START; //>> 1GB
 
+/// Returns the regions of guest RAM for `size` bytes of guest memory.
+/// For aarch64 RAM starts at DRAM_MEM_START and must not exceed DRAM_MEM_MAX_SIZE.
+pub fn arch_memory_layout(size: usize) -> Result<Vec<MemoryRange>, MemoryLayoutError> {
+    ram_regions(
+        layout::DRAM_MEM_START,
+        layout::DRAM_MEM_START + layout::DRAM_MEM_MAX_SIZE as u64,
+        size as u64,
+        &[],
+        &[],
+    )
+}
+
 /// Returns a Vec of the valid memory addresses for aarch64.
 /// See [`layout`](layout) module for a drawing of the specific memory model for this platform.
-pub fn arch_memory_regions(size: usize) -> Vec<(GuestAddress, usize)> {
-    let dram_size = min(size, layout::DRAM_MEM_MAX_SIZE);
-    vec![(GuestAddress(layout::DRAM_MEM_START), dram_size)]
+pub fn arch_memory_regions(size: usize) -> Result<Vec<(GuestAddress, usize)>, MemoryLayoutError> {
+    Ok(arch_memory_layout(size)?
+        .into_iter()
+        .map(Into::into)
+        .collect())
 }
 
 /// Configures the system and should be called once per vm before starting vcpu threads.
@@ -118,7 +132,7 @@ mod tests {
 
     #[test]
     fn test_regions_lt_1024gb() {
-        let regions = arch_memory_regions(1usize << 29);
+        let regions = arch_memory_regions(1usize << 29).unwrap();
         assert_eq!(1, regions.len());
         assert_eq!(GuestAddress(super::layout::DRAM_MEM_START), regions[0].0);
         assert_eq!(1usize << 29, regions[0].1);
@@ -126,7 +140,16 @@ mod tests {
 
     #[test]
     fn test_regions_gt_1024gb() {
-        let regions = arch_memory_regions(1usize << 41);
+        // Memory which does not fit in the DRAM is rejected instead of being truncated.
+        assert_eq!(
+            arch_memory_regions(1usize << 41).unwrap_err(),
+            MemoryLayoutError::TooLarge(
+                1 << 41,
+                super::layout::DRAM_MEM_START + super::layout::DRAM_MEM_MAX_SIZE as u64
+            )
+        );
+
+        let regions = arch_memory_regions(super::layout::DRAM_MEM_MAX_SIZE).unwrap();
         assert_eq!(1, regions.len());
         assert_eq!(GuestAddress(super::layout::DRAM_MEM_START), regions[0].0);
         assert_eq!(super::layout::DRAM_MEM_MAX_SIZE, regions[0].1);
@@ -0,0 +1,280 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Architecture independent computation of the guest memory layout.
+//!
+//! Guest RAM is laid out from a base address upwards, skipping the ranges of the guest physical
+//! address space which cannot hold RAM: the gaps reserved by the architecture (e.g. for MMIO
+//! devices) and the ranges reserved for hotplugging memory or devices later on. Each
+//! architecture then describes the resulting RAM regions to the guest in its own format (e820
+//! entries on x86_64, DT memory nodes on aarch64).
+
+use serde::Serialize;
+use utils::u64_to_usize;
+
+use crate::arch::PAGE_SIZE;
+use crate::vstate::memory::GuestAddress;
+
+/// A range of the guest physical address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemoryRange {
+    /// First address of the range.
+    pub start: u64,
+    /// Size of the range in bytes.
+    pub size: u64,
+}
+
+impl MemoryRange {
+    /// Creates a range of `size` bytes starting at `start`.
+    pub const fn new(start: u64, size: u64) -> Self {
+        Self { start, size }
+    }
+
+    /// Returns the address right after the end of the range, if it is representable.
+    pub fn end(&self) -> Option<u64> {
+        self.start.checked_add(self.size)
+    }
+}
+
+impl From<MemoryRange> for (GuestAddress, usize) {
+    fn from(range: MemoryRange) -> Self {
+        (GuestAddress(range.start), u64_to_usize(range.size))
+    }
+}
+
+/// Errors associated with the computation of the guest memory layout.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum MemoryLayoutError {
+    /// The guest memory size ({0:#x} bytes) is not a multiple of the page size.
+    UnalignedSize(u64),
+    /// The reserved range [{0:#x}, +{1:#x}) is empty, not page aligned or out of the address space.
+    InvalidReservation(u64, u64),
+    /// The reserved ranges starting at {0:#x} and {1:#x} overlap.
+    OverlappingReservations(u64, u64),
+    /// The guest memory ({0:#x} bytes) does not fit below the end of the guest address space ({1:#x}).
+    TooLarge(u64, u64),
+}
+
+/// Computes the regions of guest RAM.
+///
+/// `mem_size` bytes of RAM are laid out from `base` upwards and must end at or below `limit`.
+/// RAM is not placed in the architectural `gaps` nor in the `reserved` ranges (e.g. set aside
+/// for hotplug), which may be given in any order but must not overlap. Gaps and reservations
+/// lying below `base` are ignored. The returned regions are sorted, page aligned, do not overlap
+/// and their sizes add up to `mem_size`.
+pub fn ram_regions(
+    base: u64,
+    limit: u64,
+    mem_size: u64,
+    gaps: &[MemoryRange],
+    reserved: &[MemoryRange],
+) -> Result<Vec<MemoryRange>, MemoryLayoutError> {
+    let page_size = PAGE_SIZE as u64;
+    if mem_size % page_size != 0 {
+        return Err(MemoryLayoutError::UnalignedSize(mem_size));
+    }
+
+    let mut holes = gaps.iter().chain(reserved).copied().collect::<Vec<_>>();
+    if let Some(hole) = holes.iter().find(|hole| {
+        hole.size == 0
+            || hole.start % page_size != 0
+            || hole.size % page_size != 0
+            || hole.end().is_none()
+    }) {
+        return Err(MemoryLayoutError::InvalidReservation(hole.start, hole.size));
+    }
+    holes.sort_unstable_by_key(|hole| hole.start);
+    if let Some(pair) = holes
+        .windows(2)
+        .find(|pair| pair[0].start + pair[0].size > pair[1].start)
+    {
+        return Err(MemoryLayoutError::OverlappingReservations(
+            pair[0].start,
+            pair[1].start,
+        ));
+    }
+
+    let too_large = MemoryLayoutError::TooLarge(mem_size, limit);
+    let mut regions = Vec::new();
+    let mut next = base;
+    let mut remaining = mem_size;
+    for hole in holes {
+        if remaining == 0 {
+            break;
+        }
+        // The end of the holes is representable, as checked above.
+        let hole_end = hole.start + hole.size;
+        if hole_end <= next {
+            continue;
+        }
+        if hole.start > next {
+            let size = remaining.min(hole.start - next);
+            regions.push(MemoryRange::new(next, size));
+            remaining -= size;
+        }
+        next = hole_end;
+    }
+    if remaining > 0 {
+        regions.push(MemoryRange::new(next, remaining));
+    }
+
+    match regions.last().map(MemoryRange::end) {
+        Some(None) => Err(too_large),
+        Some(Some(end)) if end > limit => Err(too_large),
+        _ => Ok(regions),
+    }
+}
+
+/// Returns the total size in bytes of `regions`.
+pub fn total_size(regions: &[MemoryRange]) -> u64 {
+    regions.iter().map(|region| region.size).sum()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const MIB: u64 = 1 << 20;
+    const GIB: u64 = 1 << 30;
+
+    /// Checks the invariants of `regions` laid out for `mem_size` bytes from `base` around `holes`.
+    pub(crate) fn check_invariants(
+        regions: &[MemoryRange],
+        base: u64,
+        mem_size: u64,
+        holes: &[MemoryRange],
+    ) {
+        assert_eq!(total_size(regions), mem_size);
+        for region in regions {
+            assert_ne!(region.size, 0);
+            assert!(region.start >= base);
+            assert_eq!(region.start % PAGE_SIZE as u64, 0);
+            assert_eq!(region.size % PAGE_SIZE as u64, 0);
+            for hole in holes {
+                let overlaps =
+                    region.start < hole.start + hole.size && hole.start < region.end().unwrap();
+                assert!(!overlaps, "{region:x?} overlaps {hole:x?}");
+            }
+        }
+        for pair in regions.windows(2) {
+            assert!(pair[0].end().unwrap() <= pair[1].start);
+        }
+    }
+
+    #[test]
+    fn test_no_holes() {
+        assert_eq!(ram_regions(0, u64::MAX, 0, &[], &[]).unwrap(), vec![]);
+        assert_eq!(
+            ram_regions(GIB, 2 * GIB, GIB, &[], &[]).unwrap(),
+            vec![MemoryRange::new(GIB, GIB)]
+        );
+        assert_eq!(
+            ram_regions(GIB, 2 * GIB, GIB + 0x1000, &[], &[]).unwrap_err(),
+            MemoryLayoutError::TooLarge(GIB + 0x1000, 2 * GIB)
+        );
+        assert_eq!(
+            ram_regions(u64::MAX - 0xfff, u64::MAX, 0x2000, &[], &[]).unwrap_err(),
+            MemoryLayoutError::TooLarge(0x2000, u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_holes() {
+        let gaps = [MemoryRange::new(3 * GIB, GIB)];
+        let reserved = [MemoryRange::new(GIB, 256 * MIB)];
+
+        // RAM ending right before a hole does not continue after it.
+        assert_eq!(
+            ram_regions(0, u64::MAX, GIB, &gaps, &reserved).unwrap(),
+            vec![MemoryRange::new(0, GIB)]
+        );
+        assert_eq!(
+            ram_regions(0, u64::MAX, 3 * GIB, &gaps, &reserved).unwrap(),
+            vec![
+                MemoryRange::new(0, GIB),
+                MemoryRange::new(GIB + 256 * MIB, 2 * GIB - 256 * MIB),
+                MemoryRange::new(4 * GIB, 256 * MIB),
+            ]
+        );
+        // Holes below the base are ignored, and RAM starts after a hole starting at the base.
+        assert_eq!(
+            ram_regions(3 * GIB, u64::MAX, GIB, &gaps, &reserved).unwrap(),
+            vec![MemoryRange::new(4 * GIB, GIB)]
+        );
+        // The limit applies to the end of the RAM, after skipping the holes.
+        assert_eq!(
+            ram_regions(2 * GIB, 4 * GIB, GIB, &gaps, &[]).unwrap(),
+            vec![MemoryRange::new(2 * GIB, GIB)]
+        );
+        assert_eq!(
+            ram_regions(2 * GIB, 4 * GIB, GIB + 0x1000, &gaps, &[]).unwrap_err(),
+            MemoryLayoutError::TooLarge(GIB + 0x1000, 4 * GIB)
+        );
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(
+            ram_regions(0, u64::MAX, GIB + 1, &[], &[]).unwrap_err(),
+            MemoryLayoutError::UnalignedSize(GIB + 1)
+        );
+        for hole in [
+            MemoryRange::new(GIB, 0),
+            MemoryRange::new(GIB + 1, GIB),
+            MemoryRange::new(GIB, 0x800),
+            MemoryRange::new(u64::MAX & !0xfff, 0x2000),
+        ] {
+            assert_eq!(
+                ram_regions(0, u64::MAX, GIB, &[hole], &[]).unwrap_err(),
+                MemoryLayoutError::InvalidReservation(hole.start, hole.size)
+            );
+        }
+        assert_eq!(
+            ram_regions(
+                0,
+                u64::MAX,
+                GIB,
+                &[MemoryRange::new(3 * GIB, GIB)],
+                &[MemoryRange::new(2 * GIB, GIB + 0x1000)]
+            )
+            .unwrap_err(),
+            MemoryLayoutError::OverlappingReservations(2 * GIB, 3 * GIB)
+        );
+    }
+
+    #[test]
+    fn test_invariants() {
+        // Deterministic pseudo-random sizes, so that failures can be reproduced.
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next_page_count = |max: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % max
+        };
+        let page_size = PAGE_SIZE as u64;
+
+        for _ in 0..1000 {
+            let base = next_page_count(GIB / page_size) * page_size;
+            let mem_size = next_page_count(8 * GIB / page_size) * page_size;
+            let gap_start = next_page_count(8 * GIB / page_size) * page_size;
+            let gap_size = (next_page_count(GIB / page_size) + 1) * page_size;
+            let reserved_start = gap_start + gap_size + next_page_count(4) * page_size;
+            let reserved_size = (next_page_count(16) + 1) * page_size;
+            let holes = [
+                MemoryRange::new(gap_start, gap_size),
+                MemoryRange::new(reserved_start, reserved_size),
+            ];
+
+            let regions = ram_regions(base, u64::MAX, mem_size, &holes[..1], &holes[1..]).unwrap();
+            check_invariants(&regions, base, mem_size, &holes);
+            // A region boundary only ever happens at a hole.
+            for pair in regions.windows(2) {
+                assert!(holes
+                    .iter()
+                    .any(|hole| hole.start == pair[0].end().unwrap()));
+            }
+        }
+    }
+}
@@ -39,6 +39,12 @@ pub const IOAPIC_ADDR: u32 = 0xfec0_0000;
 /// Location of RSDP pointer in x86 machines
 pub const RSDP_ADDR: u64 = 0x000e_0000;
 
+/// Location of the SMBIOS entry point, in the [0xf0000, 0x100000) range scanned by the guest.
+pub const SMBIOS_START: u64 = 0x000f_0000;
+
+/// Size of the memory region holding the SMBIOS entry point and structure table.
+pub const SMBIOS_MAX_SIZE: u64 = 0x1_0000;
+
 /// Start of memory region we will use for system data (MPTable, ACPI, etc). We are putting its
 /// start address where EBDA normally starts, i.e. in the last 1 KiB of the first 640KiB of memory
 pub const SYSTEM_MEM_START: u64 = 0x9fc00;
@@ -17,12 +17,15 @@ mod mptable;
 pub mod msr;
 /// Logic for configuring x86_64 registers.
 pub mod regs;
+/// Logic for generating the SMBIOS tables.
+pub mod smbios;
 
 use linux_loader::configurator::linux::LinuxBootConfigurator;
 use linux_loader::configurator::{BootConfigurator, BootParams};
 use linux_loader::loader::bootparam::boot_params;
 use utils::u64_to_usize;
 
+use crate::arch::memory_layout::{ram_regions, MemoryLayoutError, MemoryRange};
 use crate::arch::InitrdConfig;
 use crate::device_manager::resources::ResourceAllocator;
 use crate::vstate::memory::{
@@ -46,6 +49,8 @@ pub enum ConfigurationError {
     ZeroPageSetup,
     /// Failed to compute initrd address.
     InitrdAddress,
+    /// Error writing SMBIOS tables to memory: {0}
+    SmbiosSetup(#[from] smbios::SmbiosError),
 }
 
 const FIRST_ADDR_PAST_32BITS: u64 = 1 << 32;
@@ -57,22 +62,28 @@ pub const MMIO_MEM_START: u64 = FIRST_ADDR_PAST_32BITS - MEM_32BIT_GAP_SIZE;
 /// The size of the memory area reserved for MMIO devices.
 pub const MMIO_MEM_SIZE: u64 = MEM_32BIT_GAP_SIZE;
 
+/// Returns the regions of guest RAM for `size` bytes of guest memory.
+/// For x86_64 RAM starts at address 0 and skips the MMIO gap at the end of the 32bit address
+/// space, so the memory which does not fit below the gap continues from 4GiB onwards.
+pub fn arch_memory_layout(size: usize) -> Result<Vec<MemoryRange>, MemoryLayoutError> {
+    ram_regions(
+        0,
+        u64::MAX,
+        size as u64,
+        &[MemoryRange::new(MMIO_MEM_START, MMIO_MEM_SIZE)],
+        &[],
+    )
+}
+
 /// Returns a Vec of the valid memory addresses.
 /// These should be used to configure the GuestMemoryMmap structure for the platform.
 /// For x86_64 all addresses are valid from the start of the kernel except a
 /// carve out at the end of 32bit address space.
-pub fn arch_memory_regions(size: usize) -> Vec<(GuestAddress, usize)> {
-    // It's safe to cast MMIO_MEM_START to usize because it fits in a u32 variable
-    // (It points to an address in the 32 bit space).
-    match size.checked_sub(usize::try_from(MMIO_MEM_START).unwrap()) {
-        // case1: guest memory fits before the gap
-        None | Some(0) => vec![(GuestAddress(0), size)],
-        // case2: guest memory extends beyond the gap
-        Some(remaining) => vec![
-            (GuestAddress(0), usize::try_from(MMIO_MEM_START).unwrap()),
-            (GuestAddress(FIRST_ADDR_PAST_32BITS), remaining),
-        ],
-    }
+pub fn arch_memory_regions(size: usize) -> Result<Vec<(GuestAddress, usize)>, MemoryLayoutError> {
+    Ok(arch_memory_layout(size)?
+        .into_iter()
+        .map(Into::into)
+        .collect())
 }
 
 /// Returns the memory address where the kernel could be loaded.
@@ -119,10 +130,6 @@ pub fn configure_system(
     const KERNEL_HDR_MAGIC: u32 = 0x5372_6448;
     const KERNEL_LOADER_OTHER: u8 = 0xff;
     const KERNEL_MIN_ALIGNMENT_BYTES: u32 = 0x0100_0000; // Must be non-zero.
-    let first_addr_past_32bits = GuestAddress(FIRST_ADDR_PAST_32BITS);
-    let end_32bit_gap_start = GuestAddress(MMIO_MEM_START);
-
-    let himem_start = GuestAddress(layout::HIMEM_START);
 
     // Note that this puts the mptable at the last 1k of Linux's 640k base RAM
     mptable::setup_mptable(guest_mem, resource_allocator, num_cpus)?;
@@ -144,47 +151,12 @@ pub fn configure_system(
         params.hdr.ramdisk_size = u32::try_from(initrd_config.size).unwrap();
     }
 
-    // We mark first [0x0, SYSTEM_MEM_START) region as usable RAM and the subsequent
-    // [SYSTEM_MEM_START, (SYSTEM_MEM_START + SYSTEM_MEM_SIZE)) as reserved (note
-    // SYSTEM_MEM_SIZE + SYSTEM_MEM_SIZE == HIMEM_START).
-    add_e820_entry(&mut params, 0, layout::SYSTEM_MEM_START, E820_RAM)?;
-    add_e820_entry(
-        &mut params,
-        layout::SYSTEM_MEM_START,
-        layout::SYSTEM_MEM_SIZE,
-        E820_RESERVED,
-    )?;
-
-    let last_addr = guest_mem.last_addr();
-    if last_addr < end_32bit_gap_start {
-        add_e820_entry(
-            &mut params,
-            himem_start.raw_value(),
-            // it's safe to use unchecked_offset_from because
-            // mem_end > himem_start
-            last_addr.unchecked_offset_from(himem_start) + 1,
-            E820_RAM,
-        )?;
-    } else {
-        add_e820_entry(
-            &mut params,
-            himem_start.raw_value(),
-            // it's safe to use unchecked_offset_from because
-            // end_32bit_gap_start > himem_start
-            end_32bit_gap_start.unchecked_offset_from(himem_start),
-            E820_RAM,
-        )?;
-
-        if last_addr > first_addr_past_32bits {
-            add_e820_entry(
-                &mut params,
-                first_addr_past_32bits.raw_value(),
-                // it's safe to use unchecked_offset_from because
-                // mem_end > first_addr_past_32bits
-                last_addr.unchecked_offset_from(first_addr_past_32bits) + 1,
-                E820_RAM,
-            )?;
-        }
+    let regions = guest_mem
+        .iter()
+        .map(|region| MemoryRange::new(region.start_addr().raw_value(), region.len()))
+        .collect::<Vec<_>>();
+    for (addr, size, mem_type) in e820_entries(&regions) {
+        add_e820_entry(&mut params, addr, size, mem_type)?;
     }
 
     LinuxBootConfigurator::write_bootparams(
@@ -194,6 +166,33 @@ pub fn configure_system(
     .map_err(|_| ConfigurationError::ZeroPageSetup)
 }
 
+/// Returns the e820 entries, as (address, size, type), describing the guest RAM `regions`.
+///
+/// The RAM below SYSTEM_MEM_START and from HIMEM_START onwards is marked as usable, while
+/// [SYSTEM_MEM_START, HIMEM_START), which holds the MP table, the ACPI and the SMBIOS tables, is
+/// marked as reserved (note SYSTEM_MEM_START + SYSTEM_MEM_SIZE == HIMEM_START).
+fn e820_entries(regions: &[MemoryRange]) -> Vec<(u64, u64, u32)> {
+    let mut entries = vec![(
+        layout::SYSTEM_MEM_START,
+        layout::SYSTEM_MEM_SIZE,
+        E820_RESERVED,
+    )];
+    for region in regions {
+        // The end of guest memory regions is representable.
+        let end = region.start + region.size;
+        if region.start < layout::SYSTEM_MEM_START {
+            let low_end = end.min(layout::SYSTEM_MEM_START);
+            entries.push((region.start, low_end - region.start, E820_RAM));
+        }
+        if end > layout::HIMEM_START {
+            let high_start = region.start.max(layout::HIMEM_START);
+            entries.push((high_start, end - high_start, E820_RAM));
+        }
+    }
+    entries.sort_unstable_by_key(|(addr, _, _)| *addr);
+    entries
+}
+
 /// Add an e820 region to the e820 map.
 /// Returns Ok(()) if successful, or an error if there is no space left in the map.
 fn add_e820_entry(
@@ -219,11 +218,12 @@ mod tests {
     use linux_loader::loader::bootparam::boot_e820_entry;
 
     use super::*;
+    use crate::arch::memory_layout::tests::check_invariants;
     use crate::utilities::test_utils::{arch_mem, single_region_mem};
 
     #[test]
     fn regions_lt_4gb() {
-        let regions = arch_memory_regions(1usize << 29);
+        let regions = arch_memory_regions(1usize << 29).unwrap();
         assert_eq!(1, regions.len());
         assert_eq!(GuestAddress(0), regions[0].0);
         assert_eq!(1usize << 29, regions[0].1);
@@ -231,12 +231,118 @@ mod tests {
 
     #[test]
     fn regions_gt_4gb() {
-        let regions = arch_memory_regions((1usize << 32) + 0x8000);
+        let regions = arch_memory_regions((1usize << 32) + 0x8000).unwrap();
         assert_eq!(2, regions.len());
         assert_eq!(GuestAddress(0), regions[0].0);
         assert_eq!(GuestAddress(1u64 << 32), regions[1].0);
     }
 
+    #[test]
+    fn test_memory_layout_invariants() {
+        let gap = MemoryRange::new(MMIO_MEM_START, MMIO_MEM_SIZE);
+        // Every size in MiB up to 8GiB, plus sizes which are only page aligned around the gap.
+        let sizes = (1..=8192u64)
+            .map(|mib| mib << 20)
+            .chain((1..=16).map(|pages| MMIO_MEM_START - 0x8000 + pages * 0x1000));
+
+        for size in sizes {
+            let regions = arch_memory_layout(u64_to_usize(size)).unwrap();
+            check_invariants(&regions, 0, size, &[gap]);
+            assert!(regions.len() <= 2);
+
+            // The e820 map is sorted, has no overlaps and marks all the RAM as usable, except
+            // for the system memory.
+            let entries = e820_entries(&regions);
+            for pair in entries.windows(2) {
+                assert!(pair[0].0 + pair[0].1 <= pair[1].0);
+            }
+            let usable: u64 = entries
+                .iter()
+                .filter(|(_, _, mem_type)| *mem_type == E820_RAM)
+                .map(|(_, size, _)| size)
+                .sum();
+            let system_mem = size.clamp(layout::SYSTEM_MEM_START, layout::HIMEM_START)
+                - layout::SYSTEM_MEM_START;
+            assert_eq!(usable, size - system_mem);
+            for (addr, entry_size, mem_type) in entries {
+                if mem_type == E820_RAM {
+                    assert!(regions
+                        .iter()
+                        .any(|region| region.start <= addr
+                            && addr + entry_size <= region.end().unwrap()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_memory_layout_regressions() {
+        const MIB: u64 = 1 << 20;
+        let ram = |size: u64| arch_memory_layout(u64_to_usize(size)).unwrap();
+        let high_ram = |size: u64| (layout::HIMEM_START, size - layout::HIMEM_START, E820_RAM);
+        let low_entries = [
+            (0, layout::SYSTEM_MEM_START, E820_RAM),
+            (
+                layout::SYSTEM_MEM_START,
+                layout::SYSTEM_MEM_SIZE,
+                E820_RESERVED,
+            ),
+        ];
+
+        // 1MiB of memory ends at HIMEM_START. Computing the size of the usable memory from
+        // HIMEM_START used to underflow.
+        assert_eq!(e820_entries(&ram(MIB)), low_entries);
+
+        // A size which is not a multiple of 128MiB.
+        assert_eq!(
+            e820_entries(&ram(1000 * MIB)),
+            [low_entries[0], low_entries[1], high_ram(1000 * MIB)]
+        );
+
+        // Memory ending right at the start of the MMIO gap must not get a region above 4GiB.
+        assert_eq!(ram(MMIO_MEM_START), [MemoryRange::new(0, MMIO_MEM_START)]);
+        assert_eq!(
+            e820_entries(&ram(MMIO_MEM_START)),
+            [low_entries[0], low_entries[1], high_ram(MMIO_MEM_START)]
+        );
+
+        // One MiB, and one page, past the start of the MMIO gap end up right after 4GiB.
+        for extra in [MIB, 0x1000] {
+            let regions = ram(MMIO_MEM_START + extra);
+            assert_eq!(
+                regions,
+                [
+                    MemoryRange::new(0, MMIO_MEM_START),
+                    MemoryRange::new(FIRST_ADDR_PAST_32BITS, extra)
+                ]
+            );
+            assert_eq!(
+                e820_entries(&regions),
+                [
+                    low_entries[0],
+                    low_entries[1],
+                    high_ram(MMIO_MEM_START),
+                    (FIRST_ADDR_PAST_32BITS, extra, E820_RAM)
+                ]
+            );
+        }
+
+        // Exactly 4GiB of memory: the size of the gap is moved above 4GiB.
+        assert_eq!(
+            ram(4096 * MIB),
+            [
+                MemoryRange::new(0, MMIO_MEM_START),
+                MemoryRange::new(FIRST_ADDR_PAST_32BITS, MMIO_MEM_SIZE)
+            ]
+        );
+
+        // Memory smaller than the system memory only gets a single usable entry.
+        assert_eq!(
+            e820_entries(&ram(0x1000)),
+            [(0, 0x1000, E820_RAM), low_entries[1]]
+        );
+    }
+
     #[test]
     fn test_system_configuration() {
         let no_vcpus = 4;
@@ -0,0 +1,430 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generation of the SMBIOS tables, as described by the DMTF System Management BIOS (SMBIOS)
+//! Reference Specification 3.0.
+
+use log::debug;
+use utils::u64_to_usize;
+
+use crate::arch::x86_64::layout::{SMBIOS_MAX_SIZE, SMBIOS_START};
+use crate::vmm_config::machine_config::SmbiosConfig;
+use crate::vstate::memory::{Bytes, GuestAddress, GuestMemoryMmap};
+
+/// Errors thrown while writing the SMBIOS tables.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum SmbiosError {
+    /// The SMBIOS tables do not fit in the memory reserved for them.
+    TooLarge,
+    /// The SMBIOS UUID is missing or invalid.
+    InvalidUuid,
+    /// Failure to write the SMBIOS tables to guest memory.
+    Write,
+}
+
+const SM3_MAGIC_IDENT: [u8; 5] = *b"_SM3_";
+const SM3_ENTRY_POINT_LENGTH: u8 = 0x18;
+const SM3_MAJOR_VERSION: u8 = 3;
+const SM3_MINOR_VERSION: u8 = 0;
+const SM3_DOCREV: u8 = 0;
+const SM3_ENTRY_POINT_REVISION: u8 = 1;
+
+const BIOS_INFORMATION: u8 = 0;
+const SYSTEM_INFORMATION: u8 = 1;
+const SYSTEM_ENCLOSURE: u8 = 3;
+const OEM_STRINGS: u8 = 11;
+const END_OF_TABLE: u8 = 127;
+
+const BIOS_VENDOR: &str = "Firecracker";
+const BIOS_VERSION: &str = "0";
+// BIOS characteristics are not supported.
+const BIOS_CHARACTERISTICS_NOT_SUPPORTED: u64 = 1 << 3;
+// The SMBIOS table describes a virtual machine.
+const BIOS_CHARACTERISTICS_EXT2_VIRTUAL_MACHINE: u8 = 1 << 4;
+const EMBEDDED_CONTROLLER_NOT_SUPPORTED: u8 = 0xff;
+const WAKE_UP_TYPE_POWER_SWITCH: u8 = 0x06;
+const ENCLOSURE_TYPE_OTHER: u8 = 0x01;
+const ENCLOSURE_STATE_SAFE: u8 = 0x03;
+const ENCLOSURE_SECURITY_STATUS_UNKNOWN: u8 = 0x02;
+
+/// A single SMBIOS structure: its formatted section followed by its string-set.
+#[derive(Debug)]
+struct Structure {
+    formatted: Vec<u8>,
+    strings: Vec<u8>,
+    num_strings: u8,
+}
+
+impl Structure {
+    fn new(structure_type: u8, handle: u16) -> Self {
+        let mut structure = Structure {
+            formatted: Vec::new(),
+            strings: Vec::new(),
+            num_strings: 0,
+        };
+        structure.push_u8(structure_type);
+        // The length is only known once the whole formatted section is written.
+        structure.push_u8(0);
+        structure.push_u16(handle);
+        structure
+    }
+
+    fn push_u8(&mut self, value: u8) {
+        self.formatted.push(value);
+    }
+
+    fn push_u16(&mut self, value: u16) {
+        self.formatted.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.formatted.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_u64(&mut self, value: u64) {
+        self.formatted.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        self.formatted.extend_from_slice(bytes);
+    }
+
+    /// Appends `value` to the string-set and returns its number.
+    fn add_string(&mut self, value: &str) -> Result<u8, SmbiosError> {
+        self.num_strings = self
+            .num_strings
+            .checked_add(1)
+            .ok_or(SmbiosError::TooLarge)?;
+        self.strings.extend_from_slice(value.as_bytes());
+        self.strings.push(0);
+        Ok(self.num_strings)
+    }
+
+    /// Appends `value` to the string-set and writes its number in the formatted section. Absent
+    /// strings are referenced with the number 0.
+    fn push_string(&mut self, value: Option<&str>) -> Result<(), SmbiosError> {
+        let number = match value {
+            Some(value) => self.add_string(value)?,
+            None => 0,
+        };
+        self.push_u8(number);
+        Ok(())
+    }
+
+    fn write_to(mut self, table: &mut Vec<u8>) -> Result<(), SmbiosError> {
+        self.formatted[1] =
+            u8::try_from(self.formatted.len()).map_err(|_| SmbiosError::TooLarge)?;
+        table.extend_from_slice(&self.formatted);
+        // The string-set is terminated by an additional NUL, which makes for a double NUL when
+        // the structure has no strings.
+        if self.strings.is_empty() {
+            table.push(0);
+        } else {
+            table.extend_from_slice(&self.strings);
+        }
+        table.push(0);
+        Ok(())
+    }
+}
+
+/// Encodes the UUID the way SMBIOS expects it, i.e. with its first three fields in little-endian.
+fn smbios_uuid(uuid: [u8; 16]) -> [u8; 16] {
+    let mut encoded = uuid;
+    encoded[0..4].reverse();
+    encoded[4..6].reverse();
+    encoded[6..8].reverse();
+    encoded
+}
+
+fn bios_information(handle: u16) -> Result<Structure, SmbiosError> {
+    let mut structure = Structure::new(BIOS_INFORMATION, handle);
+    structure.push_string(Some(BIOS_VENDOR))?;
+    structure.push_string(Some(BIOS_VERSION))?;
+    // BIOS starting address segment.
+    structure.push_u16(0);
+    // BIOS release date.
+    structure.push_string(None)?;
+    // BIOS ROM size.
+    structure.push_u8(0);
+    structure.push_u64(BIOS_CHARACTERISTICS_NOT_SUPPORTED);
+    structure.push_bytes(&[0, BIOS_CHARACTERISTICS_EXT2_VIRTUAL_MACHINE]);
+    // System BIOS major and minor release.
+    structure.push_bytes(&[0, 0]);
+    structure.push_bytes(&[
+        EMBEDDED_CONTROLLER_NOT_SUPPORTED,
+        EMBEDDED_CONTROLLER_NOT_SUPPORTED,
+    ]);
+    Ok(structure)
+}
+
+fn system_information(handle: u16, config: &SmbiosConfig) -> Result<Structure, SmbiosError> {
+    let uuid = config.uuid_bytes().ok_or(SmbiosError::InvalidUuid)?;
+
+    let mut structure = Structure::new(SYSTEM_INFORMATION, handle);
+    structure.push_string(config.manufacturer.as_deref())?;
+    structure.push_string(config.product_name.as_deref())?;
+    // Version.
+    structure.push_string(None)?;
+    structure.push_string(config.serial_number.as_deref())?;
+    structure.push_bytes(&smbios_uuid(uuid));
+    structure.push_u8(WAKE_UP_TYPE_POWER_SWITCH);
+    // SKU number and family.
+    structure.push_string(None)?;
+    structure.push_string(None)?;
+    Ok(structure)
+}
+
+fn system_enclosure(handle: u16, config: &SmbiosConfig) -> Result<Structure, SmbiosError> {
+    let mut structure = Structure::new(SYSTEM_ENCLOSURE, handle);
+    structure.push_string(config.manufacturer.as_deref())?;
+    structure.push_u8(ENCLOSURE_TYPE_OTHER);
+    // Version.
+    structure.push_string(None)?;
+    structure.push_string(config.serial_number.as_deref())?;
+    // Asset tag number.
+    structure.push_string(None)?;
+    // Boot-up, power supply and thermal states.
+    structure.push_bytes(&[
+        ENCLOSURE_STATE_SAFE,
+        ENCLOSURE_STATE_SAFE,
+        ENCLOSURE_STATE_SAFE,
+    ]);
+    structure.push_u8(ENCLOSURE_SECURITY_STATUS_UNKNOWN);
+    // OEM-defined.
+    structure.push_u32(0);
+    // Height, number of power cords, contained element count and record length.
+    structure.push_bytes(&[0, 0, 0, 0]);
+    // SKU number.
+    structure.push_string(None)?;
+    Ok(structure)
+}
+
+fn oem_strings(handle: u16, config: &SmbiosConfig) -> Result<Structure, SmbiosError> {
+    let mut structure = Structure::new(OEM_STRINGS, handle);
+    structure.push_u8(u8::try_from(config.oem_strings.len()).map_err(|_| SmbiosError::TooLarge)?);
+    for oem_string in &config.oem_strings {
+        structure.add_string(oem_string)?;
+    }
+    Ok(structure)
+}
+
+/// Builds the SMBIOS structure table.
+fn build_structure_table(config: &SmbiosConfig) -> Result<Vec<u8>, SmbiosError> {
+    let mut structures = vec![
+        bios_information(0)?,
+        system_information(1, config)?,
+        system_enclosure(2, config)?,
+    ];
+    if !config.oem_strings.is_empty() {
+        structures.push(oem_strings(3, config)?);
+    }
+    let end_handle = u16::try_from(structures.len()).map_err(|_| SmbiosError::TooLarge)?;
+    structures.push(Structure::new(END_OF_TABLE, end_handle));
+
+    let mut table = Vec::new();
+    for structure in structures {
+        structure.write_to(&mut table)?;
+    }
+    Ok(table)
+}
+
+/// Builds the SMBIOS 3.0 (64-bit) entry point for a structure table of `table_len` bytes
+/// located at `table_addr`.
+fn build_entry_point(table_len: u32, table_addr: u64) -> Vec<u8> {
+    let mut entry_point = Vec::with_capacity(usize::from(SM3_ENTRY_POINT_LENGTH));
+    entry_point.extend_from_slice(&SM3_MAGIC_IDENT);
+    // Checksum, computed below.
+    entry_point.push(0);
+    entry_point.extend_from_slice(&[
+        SM3_ENTRY_POINT_LENGTH,
+        SM3_MAJOR_VERSION,
+        SM3_MINOR_VERSION,
+        SM3_DOCREV,
+        SM3_ENTRY_POINT_REVISION,
+        // Reserved.
+        0,
+    ]);
+    entry_point.extend_from_slice(&table_len.to_le_bytes());
+    entry_point.extend_from_slice(&table_addr.to_le_bytes());
+    entry_point[5] = compute_checksum(&entry_point);
+    entry_point
+}
+
+/// Returns the byte which makes all the bytes of `data`, including itself, sum up to 0.
+fn compute_checksum(data: &[u8]) -> u8 {
+    data.iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg()
+}
+
+/// Builds the SMBIOS entry point and structure table, as they are laid out in guest memory
+/// starting at `SMBIOS_START`.
+fn build_smbios(config: &SmbiosConfig) -> Result<Vec<u8>, SmbiosError> {
+    let table = build_structure_table(config)?;
+    let table_len = u32::try_from(table.len()).map_err(|_| SmbiosError::TooLarge)?;
+    let table_addr = SMBIOS_START + u64::from(SM3_ENTRY_POINT_LENGTH);
+
+    let mut smbios = build_entry_point(table_len, table_addr);
+    smbios.extend_from_slice(&table);
+    if smbios.len() > u64_to_usize(SMBIOS_MAX_SIZE) {
+        return Err(SmbiosError::TooLarge);
+    }
+    Ok(smbios)
+}
+
+/// Writes the SMBIOS tables describing the microVM to guest memory.
+pub fn setup_smbios(mem: &GuestMemoryMmap, config: &SmbiosConfig) -> Result<(), SmbiosError> {
+    let smbios = build_smbios(config)?;
+    mem.write_slice(&smbios, GuestAddress(SMBIOS_START))
+        .map_err(|_| SmbiosError::Write)?;
+    debug!(
+        "smbios: Wrote {} bytes of SMBIOS tables at address {:#010x}",
+        smbios.len(),
+        SMBIOS_START
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::test_utils::single_region_mem;
+
+    fn golden_config() -> SmbiosConfig {
+        SmbiosConfig {
+            manufacturer: Some("ACME Corporation".to_string()),
+            product_name: Some("Firecracker microVM".to_string()),
+            serial_number: Some("SN-0123456789".to_string()),
+            uuid: Some("00112233-4455-6677-8899-aabbccddeeff".to_string()),
+            oem_strings: vec!["oem string 1".to_string(), "oem string 2".to_string()],
+        }
+    }
+
+    // Iterates over the structures of `table`, returning their formatted sections and strings.
+    fn parse_structures(mut table: &[u8]) -> Vec<(&[u8], Vec<&[u8]>)> {
+        let mut structures = Vec::new();
+        while !table.is_empty() {
+            let len = usize::from(table[1]);
+            let (formatted, rest) = table.split_at(len);
+            let end = rest.windows(2).position(|w| w == [0, 0]).unwrap();
+            let strings = rest[..end]
+                .split(|b| *b == 0)
+                .filter(|s| !s.is_empty())
+                .collect();
+            structures.push((formatted, strings));
+            table = &rest[end + 2..];
+        }
+        structures
+    }
+
+    #[test]
+    fn test_golden_tables() {
+        let smbios = build_smbios(&golden_config()).unwrap();
+        assert_eq!(smbios, include_bytes!("smbios_golden.bin"));
+    }
+
+    #[test]
+    fn test_entry_point() {
+        let smbios = build_smbios(&golden_config()).unwrap();
+        let entry_point = &smbios[..usize::from(SM3_ENTRY_POINT_LENGTH)];
+
+        assert_eq!(&entry_point[..5], b"_SM3_");
+        assert_eq!(compute_checksum(entry_point), 0);
+        let table_len = u32::from_le_bytes(entry_point[0xc..0x10].try_into().unwrap());
+        assert_eq!(
+            usize::try_from(table_len).unwrap(),
+            smbios.len() - entry_point.len()
+        );
+        let table_addr = u64::from_le_bytes(entry_point[0x10..0x18].try_into().unwrap());
+        assert_eq!(table_addr, SMBIOS_START + u64::from(SM3_ENTRY_POINT_LENGTH));
+    }
+
+    #[test]
+    fn test_structures() {
+        let smbios = build_smbios(&golden_config()).unwrap();
+        let structures = parse_structures(&smbios[usize::from(SM3_ENTRY_POINT_LENGTH)..]);
+
+        let types: Vec<u8> = structures.iter().map(|(f, _)| f[0]).collect();
+        assert_eq!(
+            types,
+            [
+                BIOS_INFORMATION,
+                SYSTEM_INFORMATION,
+                SYSTEM_ENCLOSURE,
+                OEM_STRINGS,
+                END_OF_TABLE
+            ]
+        );
+        for (handle, (formatted, _)) in structures.iter().enumerate() {
+            assert_eq!(
+                usize::from(u16::from_le_bytes([formatted[2], formatted[3]])),
+                handle
+            );
+        }
+
+        let (system, strings) = &structures[1];
+        assert_eq!(
+            strings,
+            &[
+                b"ACME Corporation".as_slice(),
+                b"Firecracker microVM",
+                b"SN-0123456789"
+            ]
+        );
+        assert_eq!(&system[4..8], &[1, 2, 0, 3]);
+        assert_eq!(
+            &system[8..0x18],
+            &[
+                0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+                0xee, 0xff
+            ]
+        );
+
+        let (oem, strings) = &structures[3];
+        assert_eq!(oem[4], 2);
+        assert_eq!(strings, &[b"oem string 1".as_slice(), b"oem string 2"]);
+    }
+
+    #[test]
+    fn test_minimal_config() {
+        let config = SmbiosConfig {
+            uuid: Some("00112233-4455-6677-8899-aabbccddeeff".to_string()),
+            ..Default::default()
+        };
+        let smbios = build_smbios(&config).unwrap();
+        let structures = parse_structures(&smbios[usize::from(SM3_ENTRY_POINT_LENGTH)..]);
+
+        // No OEM strings structure is generated when there are no OEM strings.
+        let types: Vec<u8> = structures.iter().map(|(f, _)| f[0]).collect();
+        assert_eq!(
+            types,
+            [
+                BIOS_INFORMATION,
+                SYSTEM_INFORMATION,
+                SYSTEM_ENCLOSURE,
+                END_OF_TABLE
+            ]
+        );
+        // Structures without strings are terminated by a double NUL.
+        assert!(structures[1].1.is_empty());
+        assert!(smbios.ends_with(&[END_OF_TABLE, 4, 3, 0, 0, 0]));
+
+        let config = SmbiosConfig::default();
+        assert_eq!(build_smbios(&config).unwrap_err(), SmbiosError::InvalidUuid);
+    }
+
+    #[test]
+    fn test_setup_smbios() {
+        let config = golden_config();
+        let mem = single_region_mem(0x10_0000);
+        setup_smbios(&mem, &config).unwrap();
+
+        let mut smbios = vec![0u8; include_bytes!("smbios_golden.bin").len()];
+        mem.read_slice(&mut smbios, GuestAddress(SMBIOS_START))
+            .unwrap();
+        assert_eq!(smbios, include_bytes!("smbios_golden.bin"));
+
+        let mem = single_region_mem(0xf_0010);
+        assert_eq!(setup_smbios(&mem, &config).unwrap_err(), SmbiosError::Write);
+    }
+}
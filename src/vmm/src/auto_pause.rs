@@ -0,0 +1,450 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pauses the microVM once it is idle, and resumes it on activity detected without its vCPUs.
+//!
+//! The devices record the time of their last activity with [`record_activity`], which only
+//! stores a timestamp. Every second, the VMM samples the time the vCPUs spent halted and checks
+//! whether all the selected signals have been idle for the configured time. Once the microVM is
+//! auto-paused, the next activity which does not need the vCPUs to be detected (frames received
+//! on a tap, connections and data from the host on the vsock) writes an event which resumes it.
+//! The taps and the vsock sockets stay registered with the event manager while paused.
+
+use std::fs::File;
+use std::io;
+use std::os::raw::c_uint;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use kvm_ioctls::VcpuFd;
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+use utils::eventfd::EventFd;
+use utils::ioctl::{ioctl, _IOC_NONE};
+use utils::ioctl_ioc_nr;
+use utils::time::{get_time_us, ClockType};
+
+use crate::logger::warn;
+use crate::vmm_config::auto_pause::{ActivitySignal, AutoPauseConfig};
+
+/// Interval between two checks of the activity of the microVM.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// The vCPUs are active when they spend less than this share of their time halted, in percent.
+pub const ACTIVE_HALT_PCT: u64 = 90;
+
+const KVMIO: c_uint = 0xAE;
+ioctl_ioc_nr!(KVM_GET_STATS_FD, _IOC_NONE, KVMIO, 0xce, 0);
+
+// Name of the KVM statistic accumulating the time a vCPU spent halted, in nanoseconds.
+const HALT_WAIT_STAT: &[u8] = b"halt_wait_ns";
+// Size of `struct kvm_stats_header`.
+const STATS_HEADER_LEN: usize = 24;
+// Size of `struct kvm_stats_desc`, without the name.
+const STATS_DESC_LEN: usize = 16;
+
+/// Errors associated with the auto-pause.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum AutoPauseError {
+    /// Failed to open the KVM statistics of a vCPU: {0}
+    StatsFd(io::Error),
+    /// Failed to read the KVM statistics of a vCPU: {0}
+    ReadStats(io::Error),
+    /// The KVM statistics of the vCPUs have no halt time, which requires Linux 5.14 or later.
+    MissingHaltStat,
+    /// Failed to create the auto-pause timer: {0}
+    Timer(io::Error),
+    /// Failed to create the auto-pause wake event: {0}
+    EventFd(io::Error),
+}
+
+// Last activity of each signal, and the event resuming the auto-paused microVM.
+#[derive(Debug)]
+struct ActivityTracker {
+    last_activity_us: [AtomicU64; ActivitySignal::ALL.len()],
+    // Set while the microVM is auto-paused.
+    armed: AtomicBool,
+    // The wake event, and the signal which wrote it.
+    wake: Mutex<Option<(EventFd, Option<ActivitySignal>)>>,
+}
+
+impl ActivityTracker {
+    const fn new() -> Self {
+        ActivityTracker {
+            last_activity_us: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+            armed: AtomicBool::new(false),
+            wake: Mutex::new(None),
+        }
+    }
+
+    fn record(&self, signal: ActivitySignal, now_us: u64) {
+        self.last_activity_us[signal as usize].store(now_us, Ordering::Relaxed);
+        if signal.can_resume() && self.armed.swap(false, Ordering::AcqRel) {
+            if let Some((evt, trigger)) = self.wake.lock().expect("Poisoned lock").as_mut() {
+                *trigger = Some(signal);
+                let _ = evt.write(1);
+            }
+        }
+    }
+
+    fn last_activity_us(&self, signal: ActivitySignal) -> u64 {
+        self.last_activity_us[signal as usize].load(Ordering::Relaxed)
+    }
+
+    fn set_armed(&self, armed: bool) {
+        let mut wake = self.wake.lock().expect("Poisoned lock");
+        self.armed.store(armed, Ordering::Release);
+        if let Some((_, trigger)) = wake.as_mut() {
+            *trigger = None;
+        }
+    }
+}
+
+#[cfg(not(test))]
+static ACTIVITY: ActivityTracker = ActivityTracker::new();
+
+#[cfg(not(test))]
+fn with_tracker<T>(f: impl FnOnce(&ActivityTracker) -> T) -> T {
+    f(&ACTIVITY)
+}
+
+// Every test thread has its own tracker, so tests of concurrently running devices do not see
+// the activity of each other.
+#[cfg(test)]
+thread_local! {
+    static ACTIVITY: ActivityTracker = const { ActivityTracker::new() };
+}
+
+#[cfg(test)]
+fn with_tracker<T>(f: impl FnOnce(&ActivityTracker) -> T) -> T {
+    ACTIVITY.with(f)
+}
+
+/// Records activity of `signal`. Called by the devices as they process their events.
+pub fn record_activity(signal: ActivitySignal) {
+    with_tracker(|tracker| tracker.record(signal, get_time_us(ClockType::Monotonic)));
+}
+
+// Time a vCPU spent halted, read from its KVM binary statistics.
+#[derive(Debug)]
+struct HaltWaitStat {
+    stats: File,
+    offset: u64,
+}
+
+impl HaltWaitStat {
+    fn new(vcpu_fd: &VcpuFd) -> Result<Self, AutoPauseError> {
+        // SAFETY: KVM_GET_STATS_FD takes no argument, and returns a new file descriptor.
+        let fd = unsafe { ioctl(vcpu_fd, KVM_GET_STATS_FD()) };
+        if fd < 0 {
+            return Err(AutoPauseError::StatsFd(io::Error::last_os_error()));
+        }
+        // SAFETY: The file descriptor was just created by KVM and is not owned by anything else.
+        let stats = unsafe { File::from_raw_fd(fd) };
+
+        let mut header = [0u8; STATS_HEADER_LEN];
+        stats
+            .read_exact_at(&mut header, 0)
+            .map_err(AutoPauseError::ReadStats)?;
+        let name_size = read_u32(&header, 4) as usize;
+        let num_desc = read_u32(&header, 8) as usize;
+        let desc_offset = u64::from(read_u32(&header, 16));
+        let data_offset = u64::from(read_u32(&header, 20));
+
+        let desc_len = STATS_DESC_LEN + name_size;
+        let mut descs = vec![0u8; desc_len * num_desc];
+        stats
+            .read_exact_at(&mut descs, desc_offset)
+            .map_err(AutoPauseError::ReadStats)?;
+        descs
+            .chunks_exact(desc_len)
+            .find(|desc| {
+                let name = &desc[STATS_DESC_LEN..];
+                name.split(|byte| *byte == 0).next() == Some(HALT_WAIT_STAT)
+            })
+            .map(|desc| HaltWaitStat {
+                offset: data_offset + u64::from(read_u32(desc, 8)),
+                stats,
+            })
+            .ok_or(AutoPauseError::MissingHaltStat)
+    }
+
+    fn read_ns(&self) -> io::Result<u64> {
+        let mut value = [0u8; 8];
+        self.stats.read_exact_at(&mut value, self.offset)?;
+        Ok(u64::from_ne_bytes(value))
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Controller of the automatic pause of the microVM, driven by the VMM.
+#[derive(Debug)]
+pub struct AutoPause {
+    config: AutoPauseConfig,
+    timer_fd: TimerFd,
+    wake_evt: EventFd,
+    halt_stats: Vec<HaltWaitStat>,
+    // Time of the previous sample of the halt time of the vCPUs, and the sampled halt time.
+    last_halt_sample: Option<(u64, u64)>,
+    // Activity before the microVM last started running does not count.
+    running_since_us: u64,
+}
+
+impl AutoPause {
+    /// Creates the auto-pause described by the (validated) `config`. The halt time of the vCPUs
+    /// is read from the KVM statistics of `vcpu_fds` when the `vcpu_halt_pct` signal is selected.
+    pub fn new<'a>(
+        config: &AutoPauseConfig,
+        vcpu_fds: impl Iterator<Item = &'a VcpuFd>,
+    ) -> Result<Self, AutoPauseError> {
+        let halt_stats = if config.signals.contains(&ActivitySignal::VcpuHaltPct) {
+            vcpu_fds.map(HaltWaitStat::new).collect::<Result<_, _>>()?
+        } else {
+            Vec::new()
+        };
+        let timer_fd =
+            TimerFd::new_custom(ClockId::Monotonic, true, true).map_err(AutoPauseError::Timer)?;
+        timer_fd.set_state(
+            TimerState::Periodic {
+                current: CHECK_INTERVAL,
+                interval: CHECK_INTERVAL,
+            },
+            SetTimeFlags::Default,
+        );
+        let wake_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(AutoPauseError::EventFd)?;
+        let tracker_evt = wake_evt.try_clone().map_err(AutoPauseError::EventFd)?;
+        with_tracker(|tracker| {
+            *tracker.wake.lock().expect("Poisoned lock") = Some((tracker_evt, None));
+        });
+
+        Ok(AutoPause {
+            config: config.clone(),
+            timer_fd,
+            wake_evt,
+            halt_stats,
+            last_halt_sample: None,
+            running_since_us: get_time_us(ClockType::Monotonic),
+        })
+    }
+
+    /// Time the microVM must be idle for before being paused, in seconds.
+    pub fn idle_seconds(&self) -> u64 {
+        self.config.idle_seconds
+    }
+
+    /// Timer triggering the checks of the activity of the microVM.
+    pub fn timer_fd(&self) -> &TimerFd {
+        &self.timer_fd
+    }
+
+    /// Event written by the activity resuming the auto-paused microVM.
+    pub fn wake_evt(&self) -> &EventFd {
+        &self.wake_evt
+    }
+
+    /// Consumes an expiration of the timer, and tells whether the microVM is `running` and idle.
+    pub fn check_idle(&mut self, running: bool) -> bool {
+        // Reading the timer resets it, the number of expirations does not matter.
+        self.timer_fd.read();
+        running && self.is_idle(get_time_us(ClockType::Monotonic))
+    }
+
+    fn is_idle(&mut self, now_us: u64) -> bool {
+        if !self.halt_stats.is_empty() {
+            self.sample_halt_time(now_us);
+        }
+        let idle_us = self.config.idle_seconds * 1_000_000;
+        with_tracker(|tracker| {
+            self.config.signals.iter().all(|signal| {
+                let last_us = tracker.last_activity_us(*signal).max(self.running_since_us);
+                now_us.saturating_sub(last_us) >= idle_us
+            })
+        })
+    }
+
+    // Records activity of the vCPUs when they were halted less than `ACTIVE_HALT_PCT` of the
+    // time since the previous sample.
+    fn sample_halt_time(&mut self, now_us: u64) {
+        let mut halt_ns = 0u64;
+        for stat in &self.halt_stats {
+            match stat.read_ns() {
+                Ok(value) => halt_ns = halt_ns.wrapping_add(value),
+                Err(err) => {
+                    warn!("Failed to read the halt time of a vCPU: {}", err);
+                    return;
+                }
+            }
+        }
+        let previous = self.last_halt_sample.replace((now_us, halt_ns));
+        let Some((last_us, last_halt_ns)) = previous else {
+            return;
+        };
+        let elapsed_ns = now_us.saturating_sub(last_us) * 1000 * self.halt_stats.len() as u64;
+        if elapsed_ns == 0 {
+            return;
+        }
+        let halt_pct = halt_ns.wrapping_sub(last_halt_ns) * 100 / elapsed_ns;
+        if halt_pct < ACTIVE_HALT_PCT {
+            with_tracker(|tracker| tracker.record(ActivitySignal::VcpuHaltPct, now_us));
+        }
+    }
+
+    /// Lets the next activity which can be detected while paused resume the microVM.
+    pub fn arm(&self) {
+        with_tracker(|tracker| tracker.set_armed(true));
+    }
+
+    /// Stops the activity from resuming the microVM, once it was paused or resumed otherwise.
+    pub fn disarm(&self) {
+        with_tracker(|tracker| tracker.set_armed(false));
+    }
+
+    /// Restarts the idle time of the microVM once it resumed.
+    pub fn on_resumed(&mut self) {
+        self.disarm();
+        self.running_since_us = get_time_us(ClockType::Monotonic);
+        // The vCPUs did not run while paused.
+        self.last_halt_sample = None;
+    }
+
+    /// Consumes the wake event, and returns the signal which should resume the microVM, if any.
+    pub fn take_wake(&mut self) -> Option<ActivitySignal> {
+        let _ = self.wake_evt.read();
+        with_tracker(|tracker| {
+            tracker
+                .wake
+                .lock()
+                .expect("Poisoned lock")
+                .as_mut()
+                .and_then(|(_, trigger)| trigger.take())
+        })
+    }
+}
+
+impl Drop for AutoPause {
+    fn drop(&mut self) {
+        with_tracker(|tracker| {
+            tracker.armed.store(false, Ordering::Release);
+            *tracker.wake.lock().expect("Poisoned lock") = None;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::virtio::net::test_utils::test::TestHelper;
+    use crate::devices::virtio::net::test_utils::{inject_tap_tx_frame, ReadTapMock};
+
+    fn auto_pause(signals: Vec<ActivitySignal>) -> AutoPause {
+        let config = AutoPauseConfig {
+            idle_seconds: 300,
+            signals,
+        };
+        AutoPause::new(&config, std::iter::empty()).unwrap()
+    }
+
+    fn record_at(signal: ActivitySignal, now_us: u64) {
+        with_tracker(|tracker| tracker.record(signal, now_us));
+    }
+
+    #[test]
+    fn test_idle() {
+        let mut auto_pause = auto_pause(vec![ActivitySignal::Block, ActivitySignal::Net]);
+        auto_pause.running_since_us = 1_000_000;
+        let idle_us = 300 * 1_000_000;
+
+        // The idle time starts when the microVM starts running.
+        assert!(!auto_pause.is_idle(idle_us));
+        assert!(auto_pause.is_idle(1_000_000 + idle_us));
+
+        // Every selected signal must be idle.
+        record_at(ActivitySignal::Net, 2_000_000);
+        assert!(!auto_pause.is_idle(1_000_000 + idle_us));
+        assert!(auto_pause.is_idle(2_000_000 + idle_us));
+
+        // Signals which are not selected are ignored.
+        record_at(ActivitySignal::Vsock, 3_000_000);
+        assert!(auto_pause.is_idle(2_000_000 + idle_us));
+
+        // Activity before the microVM resumed does not count.
+        record_at(ActivitySignal::Block, 4_000_000);
+        auto_pause.running_since_us = 5_000_000;
+        assert!(!auto_pause.is_idle(4_000_000 + idle_us));
+        assert!(auto_pause.is_idle(5_000_000 + idle_us));
+    }
+
+    #[test]
+    fn test_wake() {
+        let mut auto_pause = auto_pause(vec![ActivitySignal::Block, ActivitySignal::Net]);
+
+        // Activity does not resume the microVM unless it was auto-paused.
+        record_activity(ActivitySignal::Net);
+        assert_eq!(auto_pause.take_wake(), None);
+
+        // Block requests need the vCPUs to run, they cannot resume the microVM.
+        auto_pause.arm();
+        record_activity(ActivitySignal::Block);
+        record_activity(ActivitySignal::VcpuHaltPct);
+        assert_eq!(auto_pause.take_wake(), None);
+
+        // The first activity which can be detected while paused resumes the microVM, once.
+        record_activity(ActivitySignal::Vsock);
+        record_activity(ActivitySignal::Net);
+        assert_eq!(auto_pause.wake_evt().read().unwrap(), 1);
+        assert_eq!(auto_pause.take_wake(), Some(ActivitySignal::Vsock));
+        assert_eq!(auto_pause.take_wake(), None);
+
+        // A pending wake is dropped once the microVM is resumed, or paused again, otherwise.
+        auto_pause.arm();
+        record_activity(ActivitySignal::Net);
+        auto_pause.on_resumed();
+        assert_eq!(auto_pause.take_wake(), None);
+        auto_pause.arm();
+        record_activity(ActivitySignal::Net);
+        auto_pause.disarm();
+        assert_eq!(auto_pause.take_wake(), None);
+    }
+
+    #[test]
+    fn test_resume_on_tap_readable() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        let mut auto_pause = auto_pause(vec![ActivitySignal::Net]);
+        auto_pause.arm();
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
+
+        // The vCPUs are paused: the guest does not add RX buffers, nor kicks the queues. A frame
+        // received on the tap is still seen by the device, and resumes the microVM.
+        inject_tap_tx_frame(&th.net(), 1000);
+        th.event_manager.run_with_timeout(100).unwrap();
+        assert_eq!(auto_pause.wake_evt().read().unwrap(), 1);
+        assert_eq!(auto_pause.take_wake(), Some(ActivitySignal::Net));
+        assert!(th.net().rx_deferred_frame);
+
+        // Once resumed, frames on the tap are only recorded as activity.
+        auto_pause.on_resumed();
+        let last_activity_us =
+            with_tracker(|tracker| tracker.last_activity_us(ActivitySignal::Net));
+        inject_tap_tx_frame(&th.net(), 1000);
+        th.event_manager.run_with_timeout(100).unwrap();
+        assert_eq!(auto_pause.take_wake(), None);
+        assert!(
+            with_tracker(|tracker| tracker.last_activity_us(ActivitySignal::Net))
+                > last_activity_us
+        );
+    }
+}
@@ -6,7 +6,9 @@
 #[cfg(target_arch = "x86_64")]
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::fs::OpenOptions;
 use std::io::{self, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
 use std::sync::{Arc, Mutex};
 
 use event_manager::{MutEventSubscriber, SubscriberOps};
@@ -27,9 +29,13 @@ use vm_memory::ReadVolatile;
 use vm_superio::Rtc;
 use vm_superio::Serial;
 
+use crate::access_profile::{AccessTracker, AccessTrackerError, TrackedRegion, PAGE_SIZE};
 #[cfg(target_arch = "x86_64")]
 use crate::acpi;
+#[cfg(target_arch = "aarch64")]
+use crate::arch::DeviceType;
 use crate::arch::InitrdConfig;
+use crate::auto_pause::{AutoPause, AutoPauseError};
 #[cfg(target_arch = "aarch64")]
 use crate::construct_kvm_mpidrs;
 use crate::cpu_config::templates::{
@@ -52,7 +58,9 @@ use crate::devices::acpi::vmgenid::{VmGenId, VmGenIdError};
 use crate::devices::legacy::serial::SerialOut;
 #[cfg(target_arch = "aarch64")]
 use crate::devices::legacy::RTCDevice;
-use crate::devices::legacy::{EventFdTrigger, SerialEventsWrapper, SerialWrapper};
+use crate::devices::legacy::{
+    EventFdTrigger, SerialEventsWrapper, SerialPortMetrics, SerialWrapper,
+};
 use crate::devices::virtio::balloon::Balloon;
 use crate::devices::virtio::block::device::Block;
 use crate::devices::virtio::device::VirtioDevice;
@@ -61,14 +69,23 @@ use crate::devices::virtio::net::Net;
 use crate::devices::virtio::rng::Entropy;
 use crate::devices::virtio::vsock::{Vsock, VsockUnixBackend};
 use crate::devices::BusDevice;
+use crate::events::{VmmEvent, EVENTS};
 use crate::logger::{debug, error};
 use crate::persist::{MicrovmState, MicrovmStateError};
+use crate::readiness_probe::{ReadinessProbe, ReadinessProbeError};
 use crate::resources::VmResources;
 use crate::snapshot::Persist;
+use crate::vmm_config::auto_pause::AutoPauseConfig;
 use crate::vmm_config::boot_source::BootConfig;
 use crate::vmm_config::instance_info::InstanceInfo;
 use crate::vmm_config::machine_config::{VmConfig, VmConfigError};
-use crate::vstate::memory::{GuestAddress, GuestMemory, GuestMemoryExtension, GuestMemoryMmap};
+use crate::vmm_config::readiness_probe::ReadinessProbeConfig;
+use crate::vmm_config::serial::{SerialPortConfig, SERIAL_OUTPUT_STDOUT};
+use crate::vmm_config::snapshot::TrackAccessConfig;
+use crate::vstate::memory::{
+    GuestAddress, GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryRegion,
+    GuestMemoryState,
+};
 use crate::vstate::vcpu::{Vcpu, VcpuConfig, VcpuError};
 use crate::vstate::vm::Vm;
 use crate::{device_manager, EventManager, Vmm, VmmError};
@@ -81,6 +98,8 @@ pub enum StartMicrovmError {
     /// Unable to attach the VMGenID device: {0}
     #[cfg(target_arch = "x86_64")]
     AttachVmgenidDevice(kvm_ioctls::Error),
+    /// Cannot set up the auto-pause: {0}
+    AutoPause(#[from] AutoPauseError),
     /// System configuration error: {0}
     ConfigureSystem(crate::arch::ConfigurationError),
     /// Failed to create guest config: {0}
@@ -121,6 +140,8 @@ pub enum StartMicrovmError {
     NetDeviceNotConfigured,
     /// Cannot open the block device backing file: {0}
     OpenBlockDevice(io::Error),
+    /// Cannot create the readiness probe: {0}
+    ReadinessProbe(#[from] ReadinessProbeError),
     /// Cannot initialize a MMIO Device or add a device to the MMIO Bus or cmdline: {0}
     RegisterMmioDevice(#[from] device_manager::mmio::MmioError),
     /// Cannot restore microvm state: {0}
@@ -145,6 +166,8 @@ impl std::convert::From<linux_loader::cmdline::Error> for StartMicrovmError {
 }
 
 #[cfg_attr(target_arch = "aarch64", allow(unused))]
+#[cfg_attr(target_arch = "x86_64", allow(unused_variables))]
+#[allow(clippy::too_many_arguments)]
 fn create_vmm_and_vcpus(
     instance_info: &InstanceInfo,
     event_manager: &mut EventManager,
@@ -152,7 +175,9 @@ fn create_vmm_and_vcpus(
     uffd: Option<Uffd>,
     track_dirty_pages: bool,
     vcpu_count: u8,
+    gic_its: bool,
     kvm_capabilities: Vec<KvmCapability>,
+    serial_ports: Option<&[SerialPortConfig]>,
 ) -> Result<(Vmm, Vec<Vcpu>), StartMicrovmError> {
     use self::StartMicrovmError::*;
 
@@ -168,6 +193,9 @@ fn create_vmm_and_vcpus(
     let vcpus_exit_evt = EventFd::new(libc::EFD_NONBLOCK)
         .map_err(VmmError::EventFd)
         .map_err(Internal)?;
+    let console_pause_evt = EventFd::new(libc::EFD_NONBLOCK)
+        .map_err(VmmError::EventFd)
+        .map_err(Internal)?;
 
     let resource_allocator = ResourceAllocator::new()?;
 
@@ -185,12 +213,8 @@ fn create_vmm_and_vcpus(
         setup_interrupt_controller(&mut vm)?;
         let vcpus = create_vcpus(&vm, vcpu_count, &vcpus_exit_evt).map_err(Internal)?;
 
-        // Make stdout non blocking.
-        set_stdout_nonblocking();
-
-        // Serial device setup.
-        let serial_device =
-            setup_serial_device(event_manager, std::io::stdin(), io::stdout()).map_err(Internal)?;
+        // Serial devices setup.
+        let serial_devices = setup_serial_ports(event_manager, serial_ports).map_err(Internal)?;
 
         // x86_64 uses the i8042 reset event as the Vmm exit event.
         let reset_evt = vcpus_exit_evt
@@ -201,7 +225,7 @@ fn create_vmm_and_vcpus(
         // create pio dev manager with legacy devices
         let pio_device_manager = {
             // TODO Remove these unwraps.
-            let mut pio_dev_mgr = PortIODeviceManager::new(serial_device, reset_evt).unwrap();
+            let mut pio_dev_mgr = PortIODeviceManager::new(serial_devices, reset_evt).unwrap();
             pio_dev_mgr.register_devices(vm.fd()).unwrap();
             pio_dev_mgr
         };
@@ -216,7 +240,7 @@ fn create_vmm_and_vcpus(
     #[cfg(target_arch = "aarch64")]
     let vcpus = {
         let vcpus = create_vcpus(&vm, vcpu_count, &vcpus_exit_evt).map_err(Internal)?;
-        setup_interrupt_controller(&mut vm, vcpu_count)?;
+        setup_interrupt_controller(&mut vm, vcpu_count, gic_its)?;
         vcpus
     };
 
@@ -229,6 +253,12 @@ fn create_vmm_and_vcpus(
         uffd,
         vcpus_handles: Vec::new(),
         vcpus_exit_evt,
+        console_pause_evt,
+        readiness_probe: None,
+        auto_pause: None,
+        access_tracker: None,
+        hotplugged_devices: Vec::new(),
+        unplugged_subscribers: Vec::new(),
         resource_allocator,
         mmio_device_manager,
         #[cfg(target_arch = "x86_64")]
@@ -268,16 +298,21 @@ pub fn build_microvm_for_boot(
         .iter()
         .any(|b| b.lock().expect("Poisoned lock").is_vhost_user());
 
+    // Huge pages inflated into the balloon can only be given back to the host by punching
+    // holes in the hugetlbfs file backing them.
+    let hugetlbfs_balloon_used =
+        vm_resources.vm_config.huge_pages.is_hugetlbfs() && vm_resources.balloon.get().is_some();
+
     // Page faults are more expensive for shared memory mapping, including  memfd.
     // For this reason, we only back guest memory with a memfd
-    // if a vhost-user-blk device is configured in the VM, otherwise we fall back to
-    // an anonymous private memory.
+    // if a vhost-user-blk device, or a balloon device on top of huge pages, is configured
+    // in the VM, otherwise we fall back to an anonymous private memory.
     //
     // The vhost-user-blk branch is not currently covered by integration tests in Rust,
     // because that would require running a backend process. If in the future we converge to
     // a single way of backing guest memory for vhost-user and non-vhost-user cases,
     // that would not be worth the effort.
-    let guest_memory = if vhost_user_device_used {
+    let guest_memory = if vhost_user_device_used || hugetlbfs_balloon_used {
         GuestMemoryMmap::memfd_backed(
             vm_resources.vm_config.mem_size_mib,
             track_dirty_pages,
@@ -285,7 +320,8 @@ pub fn build_microvm_for_boot(
         )
         .map_err(StartMicrovmError::GuestMemory)?
     } else {
-        let regions = crate::arch::arch_memory_regions(vm_resources.vm_config.mem_size_mib << 20);
+        let regions = crate::arch::arch_memory_regions(vm_resources.vm_config.mem_size_mib << 20)
+            .map_err(|err| StartMicrovmError::GuestMemory(err.into()))?;
         GuestMemoryMmap::from_raw_regions(
             &regions,
             track_dirty_pages,
@@ -309,7 +345,9 @@ pub fn build_microvm_for_boot(
         None,
         track_dirty_pages,
         vm_resources.vm_config.vcpu_count,
+        vm_resources.vm_config.gic_its(),
         cpu_template.kvm_capabilities.clone(),
+        vm_resources.vm_config.serial_ports.as_deref(),
     )?;
 
     // The boot timer device needs to be the first device attached in order
@@ -345,11 +383,35 @@ pub fn build_microvm_for_boot(
     }
 
     #[cfg(target_arch = "aarch64")]
-    attach_legacy_devices_aarch64(event_manager, &mut vmm, &mut boot_cmdline).map_err(Internal)?;
+    attach_legacy_devices_aarch64(
+        event_manager,
+        &mut vmm,
+        &mut boot_cmdline,
+        vm_resources.vm_config.serial_ports.as_deref(),
+    )
+    .map_err(Internal)?;
 
     #[cfg(target_arch = "x86_64")]
     attach_vmgenid_device(&mut vmm)?;
 
+    // The hot-plug slots come after all the other devices, so that those keep their MMIO address
+    // whether slots are reserved or not.
+    if let Some(count) = vm_resources.vm_config.hotplug_slots {
+        attach_hotplug_slots(&mut vmm, &mut boot_cmdline, count)?;
+    }
+
+    if let Some(config) = vm_resources.console_scanner.as_ref() {
+        vmm.attach_console_scanner(config).map_err(Internal)?;
+    }
+
+    if let Some(config) = vm_resources.vm_config.readiness_probe.as_ref() {
+        attach_readiness_probe(&mut vmm, vm_resources, config, event_manager)?;
+    }
+
+    if let Some(config) = vm_resources.vm_config.auto_pause.as_ref() {
+        attach_auto_pause(&mut vmm, config, &vcpus)?;
+    }
+
     configure_system_for_boot(
         &mut vmm,
         vcpus.as_mut(),
@@ -411,6 +473,7 @@ pub fn build_and_boot_microvm(
         .unwrap()
         .resume_vm()
         .map_err(StartMicrovmError::Internal)?;
+    EVENTS.publish(VmmEvent::Running);
     debug!("event_end: boot microvm");
     Ok(vmm)
 }
@@ -453,6 +516,8 @@ pub enum BuildMicrovmFromSnapshotError {
     ACPIDeviManager(#[from] ACPIDeviceManagerRestoreError),
     /// VMGenID update failed: {0}
     VMGenIDUpdate(std::io::Error),
+    /// Failed to set up the memory access tracking: {0}
+    AccessTracker(#[from] AccessTrackerError),
 }
 
 /// Builds and starts a microVM based on the provided MicrovmState.
@@ -468,6 +533,7 @@ pub fn build_microvm_from_snapshot(
     uffd: Option<Uffd>,
     seccomp_filters: &BpfThreadMap,
     vm_resources: &mut VmResources,
+    track_access: Option<&TrackAccessConfig>,
 ) -> Result<Arc<Mutex<Vmm>>, BuildMicrovmFromSnapshotError> {
     // Build Vmm.
     debug!("event_start: build microvm from snapshot");
@@ -478,7 +544,9 @@ pub fn build_microvm_from_snapshot(
         uffd,
         vm_resources.vm_config.track_dirty_pages,
         vm_resources.vm_config.vcpu_count,
+        vm_resources.vm_config.gic_its(),
         microvm_state.vm_state.kvm_cap_modifiers.clone(),
+        vm_resources.vm_config.serial_ports.as_deref(),
     )?;
 
     #[cfg(target_arch = "x86_64")]
@@ -531,6 +599,10 @@ pub fn build_microvm_from_snapshot(
         MMIODeviceManager::restore(mmio_ctor_args, &microvm_state.device_states)
             .map_err(MicrovmStateError::RestoreDevices)?;
     vmm.emulate_serial_init()?;
+    if let Some(config) = vm_resources.console_scanner.as_ref() {
+        vmm.attach_console_scanner(config)
+            .map_err(StartMicrovmError::Internal)?;
+    }
 
     #[cfg(target_arch = "x86_64")]
     {
@@ -551,6 +623,17 @@ pub fn build_microvm_from_snapshot(
             .map_err(BuildMicrovmFromSnapshotError::VMGenIDUpdate)?;
     }
 
+    if let Some(config) = vm_resources.vm_config.auto_pause.as_ref() {
+        attach_auto_pause(&mut vmm, config, &vcpus)?;
+    }
+
+    if let Some(config) = track_access {
+        if vm_resources.vm_config.huge_pages.is_hugetlbfs() {
+            return Err(AccessTrackerError::HugePages.into());
+        }
+        attach_access_tracker(&mut vmm, config, &microvm_state.memory_state)?;
+    }
+
     // Move vcpus to their own threads and start their state machine in the 'Paused' state.
     vmm.start_vcpus(
         vcpus,
@@ -676,61 +759,156 @@ pub fn setup_interrupt_controller(vm: &mut Vm) -> Result<(), StartMicrovmError>
         .map_err(StartMicrovmError::Internal)
 }
 
-/// Sets up the irqchip for a aarch64 microVM.
+/// Sets up the irqchip for a aarch64 microVM, with a GICv3 ITS if `gic_its` is set.
 #[cfg(target_arch = "aarch64")]
-pub fn setup_interrupt_controller(vm: &mut Vm, vcpu_count: u8) -> Result<(), StartMicrovmError> {
-    vm.setup_irqchip(vcpu_count)
+pub fn setup_interrupt_controller(
+    vm: &mut Vm,
+    vcpu_count: u8,
+    gic_its: bool,
+) -> Result<(), StartMicrovmError> {
+    vm.setup_irqchip(vcpu_count, gic_its)
         .map_err(VmmError::Vm)
         .map_err(StartMicrovmError::Internal)
 }
 
-/// Sets up the serial device.
+/// Sets up a serial device, reading `input` if set and writing to `out`.
 pub fn setup_serial_device(
     event_manager: &mut EventManager,
-    input: std::io::Stdin,
-    out: std::io::Stdout,
+    input: Option<std::io::Stdin>,
+    out: SerialOut,
+    metrics: SerialPortMetrics,
 ) -> Result<Arc<Mutex<BusDevice>>, VmmError> {
     let interrupt_evt = EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).map_err(VmmError::EventFd)?);
-    let kick_stdin_read_evt =
-        EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).map_err(VmmError::EventFd)?);
+    let kick_stdin_read_evt = match input {
+        Some(_) => Some(EventFdTrigger::new(
+            EventFd::new(EFD_NONBLOCK).map_err(VmmError::EventFd)?,
+        )),
+        None => None,
+    };
     let serial = Arc::new(Mutex::new(BusDevice::Serial(SerialWrapper {
         serial: Serial::with_events(
             interrupt_evt,
             SerialEventsWrapper {
-                buffer_ready_event_fd: Some(kick_stdin_read_evt),
+                buffer_ready_event_fd: kick_stdin_read_evt,
+                metrics,
             },
-            SerialOut::Stdout(out),
+            out.into(),
         ),
-        input: Some(input),
+        input,
     })));
     event_manager.add_subscriber(serial.clone());
     Ok(serial)
 }
 
+/// Sets up the default serial console, reading the standard input and writing to the standard
+/// output of Firecracker.
+pub fn setup_serial_console(
+    event_manager: &mut EventManager,
+) -> Result<Arc<Mutex<BusDevice>>, VmmError> {
+    // Make stdout non blocking.
+    set_stdout_nonblocking();
+    setup_serial_device(
+        event_manager,
+        Some(std::io::stdin()),
+        SerialOut::Stdout(std::io::stdout()),
+        SerialPortMetrics::default(),
+    )
+}
+
+/// Sets up the serial device of the serial port configured by `config`.
+pub fn setup_serial_port(
+    event_manager: &mut EventManager,
+    config: &SerialPortConfig,
+) -> Result<Arc<Mutex<BusDevice>>, VmmError> {
+    let out = match config.output.as_deref() {
+        None => SerialOut::Sink(std::io::sink()),
+        Some(SERIAL_OUTPUT_STDOUT) => {
+            // Make stdout non blocking.
+            set_stdout_nonblocking();
+            SerialOut::Stdout(std::io::stdout())
+        }
+        // The output is opened non blocking, so that a named pipe without reader does not
+        // block the guest.
+        Some(path) => SerialOut::File(
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path)
+                .map_err(|err| VmmError::SerialPortOutput(config.id.clone(), err))?,
+        ),
+    };
+    setup_serial_device(
+        event_manager,
+        config.input.then(std::io::stdin),
+        out,
+        SerialPortMetrics::alloc(config.id.clone()),
+    )
+}
+
+/// Sets up the serial devices of the configured serial ports, in order, or the default serial
+/// console if no serial port is configured.
+pub fn setup_serial_ports(
+    event_manager: &mut EventManager,
+    serial_ports: Option<&[SerialPortConfig]>,
+) -> Result<Vec<Arc<Mutex<BusDevice>>>, VmmError> {
+    match serial_ports {
+        Some(ports) => ports
+            .iter()
+            .map(|port| setup_serial_port(event_manager, port))
+            .collect(),
+        None => Ok(vec![setup_serial_console(event_manager)?]),
+    }
+}
+
 #[cfg(target_arch = "aarch64")]
 fn attach_legacy_devices_aarch64(
     event_manager: &mut EventManager,
     vmm: &mut Vmm,
     cmdline: &mut LoaderKernelCmdline,
+    serial_ports: Option<&[SerialPortConfig]>,
 ) -> Result<(), VmmError> {
-    // Serial device setup.
-    let cmdline_contains_console = cmdline
-        .as_cstring()
-        .map_err(|_| VmmError::Cmdline)?
-        .into_string()
-        .map_err(|_| VmmError::Cmdline)?
-        .contains("console=");
-
-    if cmdline_contains_console {
-        // Make stdout non-blocking.
-        set_stdout_nonblocking();
-        let serial = setup_serial_device(event_manager, std::io::stdin(), std::io::stdout())?;
-        vmm.mmio_device_manager
-            .register_mmio_serial(vmm.vm.fd(), &mut vmm.resource_allocator, serial, None)
-            .map_err(VmmError::RegisterMMIODevice)?;
+    // Serial devices setup. Without configured serial ports, the serial console is only set up
+    // if the guest uses it.
+    let serial_devices = match serial_ports {
+        Some(ports) => ports
+            .iter()
+            .map(|port| Ok((port.id.clone(), setup_serial_port(event_manager, port)?)))
+            .collect::<Result<Vec<_>, VmmError>>()?,
+        None => {
+            let cmdline_contains_console = cmdline
+                .as_cstring()
+                .map_err(|_| VmmError::Cmdline)?
+                .into_string()
+                .map_err(|_| VmmError::Cmdline)?
+                .contains("console=");
+            if cmdline_contains_console {
+                vec![(
+                    DeviceType::Serial.to_string(),
+                    setup_serial_console(event_manager)?,
+                )]
+            } else {
+                vec![]
+            }
+        }
+    };
+
+    for (index, (device_id, serial)) in serial_devices.into_iter().enumerate() {
         vmm.mmio_device_manager
-            .add_mmio_serial_to_cmdline(cmdline)
+            .register_mmio_serial(
+                vmm.vm.fd(),
+                &mut vmm.resource_allocator,
+                serial,
+                device_id.clone(),
+                None,
+            )
             .map_err(VmmError::RegisterMMIODevice)?;
+        // The first serial port is the early console of the guest.
+        if index == 0 {
+            vmm.mmio_device_manager
+                .add_mmio_serial_to_cmdline(cmdline, &device_id)
+                .map_err(VmmError::RegisterMMIODevice)?;
+        }
     }
 
     let rtc = RTCDevice(Rtc::with_events(
@@ -845,6 +1023,13 @@ pub fn configure_system_for_boot(
             &vmm.acpi_device_manager,
             vcpus,
         )?;
+
+        // Write the SMBIOS tables, if the guest should be given an identity.
+        if let Some(smbios) = &vm_config.smbios {
+            crate::arch::x86_64::smbios::setup_smbios(&vmm.guest_memory, smbios)
+                .map_err(crate::arch::ConfigurationError::from)
+                .map_err(ConfigureSystem)?;
+        }
     }
     #[cfg(target_arch = "aarch64")]
     {
@@ -857,7 +1042,7 @@ pub fn configure_system_for_boot(
             &vmm.guest_memory,
             cmdline,
             vcpu_mpidr,
-            vmm.mmio_device_manager.get_device_info(),
+            &vmm.mmio_device_manager.get_fdt_device_info(),
             vmm.vm.get_irqchip(),
             initrd,
         )
@@ -877,7 +1062,11 @@ fn attach_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber + Debug>(
 ) -> Result<(), StartMicrovmError> {
     use self::StartMicrovmError::*;
 
-    event_manager.add_subscriber(device.clone());
+    let subscriber_id = event_manager.add_subscriber(device.clone());
+    let identifier = (
+        DeviceType::Virtio(device.lock().expect("Poisoned lock").device_type()),
+        id.clone(),
+    );
 
     // The device mutex mustn't be locked here otherwise it will deadlock.
     let device = MmioTransport::new(vmm.guest_memory().clone(), device, is_vhost_user);
@@ -889,8 +1078,11 @@ fn attach_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber + Debug>(
             device,
             cmdline,
         )
-        .map_err(RegisterMmioDevice)
-        .map(|_| ())
+        .map_err(RegisterMmioDevice)?;
+    vmm.mmio_device_manager
+        .subscriber_ids
+        .insert(identifier, subscriber_id);
+    Ok(())
 }
 
 pub(crate) fn attach_boot_timer_device(
@@ -942,6 +1134,71 @@ fn attach_entropy_device(
     )
 }
 
+fn attach_hotplug_slots(
+    vmm: &mut Vmm,
+    cmdline: &mut LoaderKernelCmdline,
+    count: u8,
+) -> Result<(), StartMicrovmError> {
+    for _ in 0..count {
+        vmm.mmio_device_manager
+            .register_mmio_placeholder_for_boot(&mut vmm.resource_allocator, cmdline)
+            .map_err(StartMicrovmError::RegisterMmioDevice)?;
+    }
+    Ok(())
+}
+
+fn attach_readiness_probe(
+    vmm: &mut Vmm,
+    vm_resources: &VmResources,
+    config: &ReadinessProbeConfig,
+    event_manager: &mut EventManager,
+) -> Result<(), StartMicrovmError> {
+    let vsock_uds_path = vm_resources.vsock.config().map(|vsock| vsock.uds_path);
+    let probe = Arc::new(Mutex::new(ReadinessProbe::new(
+        config,
+        vsock_uds_path.as_deref(),
+        vm_resources.mmds.clone(),
+    )?));
+    // The probe starts running once registered.
+    event_manager.add_subscriber(probe.clone());
+    vmm.readiness_probe = Some(probe);
+    Ok(())
+}
+
+fn attach_auto_pause(
+    vmm: &mut Vmm,
+    config: &AutoPauseConfig,
+    vcpus: &[Vcpu],
+) -> Result<(), StartMicrovmError> {
+    // The VMM registers the events of the auto-pause once it is added to the event manager.
+    vmm.auto_pause = Some(AutoPause::new(
+        config,
+        vcpus.iter().map(|vcpu| &vcpu.kvm_vcpu.fd),
+    )?);
+    Ok(())
+}
+
+fn attach_access_tracker(
+    vmm: &mut Vmm,
+    config: &TrackAccessConfig,
+    memory_state: &GuestMemoryState,
+) -> Result<(), AccessTrackerError> {
+    // The regions of the guest memory are in the same order as in the snapshot.
+    let regions = vmm
+        .guest_memory
+        .iter()
+        .zip(memory_state.regions.iter())
+        .map(|(mem_region, state_region)| TrackedRegion {
+            guest_addr: state_region.base_address,
+            host_addr: mem_region.as_ptr() as u64,
+            file_offset: state_region.offset,
+            num_pages: mem_region.len() / PAGE_SIZE,
+        });
+    // The VMM registers the timer of the tracking once it is added to the event manager.
+    vmm.access_tracker = Some(AccessTracker::new(config, regions)?);
+    Ok(())
+}
+
 fn attach_block_devices<'a, I: Iterator<Item = &'a Arc<Mutex<Block>>> + Debug>(
     vmm: &mut Vmm,
     cmdline: &mut LoaderKernelCmdline,
@@ -1031,25 +1288,35 @@ pub(crate) fn set_stdout_nonblocking() {
 #[cfg(test)]
 pub mod tests {
     use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+    use std::str::FromStr;
+    use std::time::Duration;
 
     use linux_loader::cmdline::Cmdline;
+    use utils::net::mac::MacAddr;
     use utils::tempfile::TempFile;
 
     use super::*;
     use crate::arch::DeviceType;
+    use crate::auto_pause::record_activity;
+    use crate::device_manager::mmio::MmioError;
     use crate::device_manager::resources::ResourceAllocator;
     use crate::devices::virtio::block::CacheType;
     use crate::devices::virtio::rng::device::ENTROPY_DEV_ID;
     use crate::devices::virtio::vsock::{TYPE_VSOCK, VSOCK_DEV_ID};
-    use crate::devices::virtio::{TYPE_BALLOON, TYPE_BLOCK, TYPE_RNG};
+    use crate::devices::virtio::{TYPE_BALLOON, TYPE_BLOCK, TYPE_NET, TYPE_RNG};
     use crate::mmds::data_store::{Mmds, MmdsVersion};
     use crate::mmds::ns::MmdsNetworkStack;
+    use crate::readiness_probe::{ReadinessProbeState, ReadinessProbeStatus};
     use crate::utilities::test_utils::{arch_mem, single_region_mem, single_region_mem_at};
+    use crate::vmm_config::auto_pause::ActivitySignal;
     use crate::vmm_config::balloon::{BalloonBuilder, BalloonDeviceConfig, BALLOON_DEV_ID};
     use crate::vmm_config::boot_source::DEFAULT_KERNEL_CMDLINE;
-    use crate::vmm_config::drive::{BlockBuilder, BlockDeviceConfig};
+    use crate::vmm_config::drive::{BlockBuilder, BlockDeviceConfig, DriveError};
     use crate::vmm_config::entropy::{EntropyDeviceBuilder, EntropyDeviceConfig};
-    use crate::vmm_config::net::{NetBuilder, NetworkInterfaceConfig};
+    use crate::vmm_config::instance_info::VmState;
+    use crate::vmm_config::net::{NetBuilder, NetworkInterfaceConfig, NetworkInterfaceError};
+    use crate::vmm_config::readiness_probe::ReadinessCheck;
     use crate::vmm_config::vsock::tests::default_config;
     use crate::vmm_config::vsock::{VsockBuilder, VsockDeviceConfig};
 
@@ -1121,16 +1388,17 @@ pub mod tests {
         let acpi_device_manager = ACPIDeviceManager::new();
         #[cfg(target_arch = "x86_64")]
         let pio_device_manager = PortIODeviceManager::new(
-            Arc::new(Mutex::new(BusDevice::Serial(SerialWrapper {
+            vec![Arc::new(Mutex::new(BusDevice::Serial(SerialWrapper {
                 serial: Serial::with_events(
                     EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).unwrap()),
                     SerialEventsWrapper {
                         buffer_ready_event_fd: None,
+                        metrics: SerialPortMetrics::default(),
                     },
-                    SerialOut::Sink(std::io::sink()),
+                    SerialOut::Sink(std::io::sink()).into(),
                 ),
                 input: None,
-            }))),
+            })))],
             EventFd::new(libc::EFD_NONBLOCK).unwrap(),
         )
         .unwrap();
@@ -1142,7 +1410,7 @@ pub mod tests {
         {
             let exit_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
             let _vcpu = Vcpu::new(1, &vm, exit_evt).unwrap();
-            setup_interrupt_controller(&mut vm, 1).unwrap();
+            setup_interrupt_controller(&mut vm, 1, false).unwrap();
         }
 
         Vmm {
@@ -1154,6 +1422,12 @@ pub mod tests {
             uffd: None,
             vcpus_handles: Vec::new(),
             vcpus_exit_evt,
+            console_pause_evt: EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+            readiness_probe: None,
+            auto_pause: None,
+            access_tracker: None,
+            hotplugged_devices: Vec::new(),
+            unplugged_subscribers: Vec::new(),
             resource_allocator: ResourceAllocator::new().unwrap(),
             mmio_device_manager,
             #[cfg(target_arch = "x86_64")]
@@ -1191,9 +1465,22 @@ pub mod tests {
                         .to_string(),
                 ),
                 rate_limiter: None,
+                rate_limiter_scope: None,
                 file_engine_type: None,
+                detect_zeroes: None,
+                open_direct: None,
+                io_engine_opts: None,
+                queue_size: None,
+                num_queues: None,
+                serial: None,
+                logical_block_size: None,
+                physical_block_size: None,
+                on_host_enospc: None,
+                io_uring: None,
 
                 socket: None,
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
             };
 
             block_dev_configs.insert(block_device_config).unwrap();
@@ -1362,6 +1649,47 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_load_kernel_from_fd() {
+        use std::os::unix::io::AsRawFd;
+
+        use crate::utilities::mock_resources::kernel_image_path;
+        use crate::vmm_config::boot_source::BootSourceConfig;
+
+        let sealed_memfd = |image: &[u8]| {
+            let memfd = memfd::MemfdOptions::default()
+                .allow_sealing(true)
+                .create("kernel")
+                .unwrap();
+            memfd.as_file().write_all(image).unwrap();
+            memfd.add_seal(memfd::FileSeal::SealWrite).unwrap();
+            memfd
+        };
+        let gm = arch_mem(128 << 20);
+
+        let kernel = sealed_memfd(&std::fs::read(kernel_image_path(None)).unwrap());
+        let boot_config = BootConfig::new(&BootSourceConfig {
+            kernel_fd: Some(kernel.as_raw_fd()),
+            ..Default::default()
+        })
+        .unwrap();
+        load_kernel(&boot_config, &gm).unwrap();
+
+        // The image is read from the memfd, but it is not a kernel.
+        let kernel = sealed_memfd(&make_test_bin());
+        let boot_config = BootConfig::new(&BootSourceConfig {
+            kernel_fd: Some(kernel.as_raw_fd()),
+            ..Default::default()
+        })
+        .unwrap();
+        let res = load_kernel(&boot_config, &gm);
+        assert!(
+            matches!(res, Err(StartMicrovmError::KernelLoader(_))),
+            "{:?}",
+            res
+        );
+    }
+
     #[test]
     fn test_create_vcpus() {
         let vcpu_count = 2;
@@ -1387,9 +1715,17 @@ pub mod tests {
         let network_interface = NetworkInterfaceConfig {
             iface_id: String::from("netif"),
             host_dev_name: String::from("hostname"),
+            tap_fd: None,
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -1405,6 +1741,378 @@ pub mod tests {
         net_builder.build(network_interface).unwrap_err();
     }
 
+    #[test]
+    fn test_update_net_guest_mac() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        let mut cmdline = default_kernel_cmdline();
+        for (iface_id, mac) in [
+            ("netif1", "06:00:00:00:00:01"),
+            ("netif2", "06:00:00:00:00:02"),
+        ] {
+            let network_interface = NetworkInterfaceConfig {
+                iface_id: String::from(iface_id),
+                host_dev_name: format!("host{iface_id}"),
+                tap_fd: None,
+                guest_mac: Some(MacAddr::from_str(mac).unwrap()),
+                rx_rate_limiter: None,
+                tx_rate_limiter: None,
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
+                queue_size: None,
+                num_queues: None,
+                rate_limiter_scope: None,
+                offloads: None,
+                mtu: None,
+            };
+            insert_net_device(
+                &mut vmm,
+                &mut cmdline,
+                &mut event_manager,
+                network_interface,
+            );
+        }
+        let mac = MacAddr::from_str("06:00:00:00:00:03").unwrap();
+
+        // The MAC address can not be updated while the microVM runs.
+        vmm.resume_vm().unwrap();
+        assert!(matches!(
+            vmm.update_net_guest_mac("netif1", mac),
+            Err(NetworkInterfaceError::GuestMacUpdateRunning)
+        ));
+
+        // It can once paused, e.g. after a snapshot is restored.
+        vmm.pause_vm().unwrap();
+        vmm.update_net_guest_mac("netif1", mac).unwrap();
+        vmm.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_NET, "netif1", |net: &mut Net| {
+                assert_eq!(net.guest_mac(), Some(&mac));
+                Ok(())
+            })
+            .unwrap();
+
+        // Unless another interface has it.
+        assert!(matches!(
+            vmm.update_net_guest_mac("netif2", mac),
+            Err(NetworkInterfaceError::GuestMacAddressInUse(_))
+        ));
+        assert!(matches!(
+            vmm.update_net_guest_mac("netif3", MacAddr::from_str("06:00:00:00:00:04").unwrap()),
+            Err(NetworkInterfaceError::DeviceUpdate(
+                VmmError::DeviceManager(MmioError::DeviceNotFound)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_hotplug_net_device() {
+        let mut vmm = default_vmm();
+        let mut cmdline = default_kernel_cmdline();
+        let mut vm_resources = VmResources::default();
+        let netif = |iface_id: &str| NetworkInterfaceConfig {
+            iface_id: String::from(iface_id),
+            host_dev_name: format!("hp{iface_id}"),
+            tap_fd: None,
+            guest_mac: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
+        };
+
+        // No slot was reserved at boot.
+        assert!(matches!(
+            vm_resources.hotplug_net_device(netif("netif1"), &mut vmm),
+            Err(NetworkInterfaceError::NoHotplugSlot)
+        ));
+        assert_eq!(vm_resources.net_builder.iter().count(), 0);
+
+        attach_hotplug_slots(&mut vmm, &mut cmdline, 2).unwrap();
+        vm_resources
+            .hotplug_net_device(netif("netif1"), &mut vmm)
+            .unwrap();
+        assert_eq!(vm_resources.net_builder.iter().count(), 1);
+        assert_eq!(vmm.mmio_device_manager.hotplug_slots.len(), 1);
+        vmm.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_NET, "netif1", |net: &mut Net| {
+                assert!(!net.is_activated());
+                Ok(())
+            })
+            .unwrap();
+        // The device is left for the event manager to subscribe.
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        assert!(vmm.mmio_device_manager.subscriber_ids.is_empty());
+        vmm.update_event_subscribers(&mut event_manager);
+        assert!(vmm.hotplugged_devices.is_empty());
+        assert_eq!(vmm.mmio_device_manager.subscriber_ids.len(), 1);
+
+        // A failed hot-plug leaves the slot free.
+        assert!(matches!(
+            vm_resources.hotplug_net_device(netif("netif1"), &mut vmm),
+            Err(NetworkInterfaceError::IfaceIdInUse(_))
+        ));
+        assert!(vmm.has_free_hotplug_slot());
+
+        vm_resources
+            .hotplug_net_device(netif("netif2"), &mut vmm)
+            .unwrap();
+        assert!(!vmm.has_free_hotplug_slot());
+        assert!(matches!(
+            vm_resources.hotplug_net_device(netif("netif3"), &mut vmm),
+            Err(NetworkInterfaceError::NoHotplugSlot)
+        ));
+        assert_eq!(vm_resources.net_builder.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_hotplug_block_device() {
+        let mut vmm = default_vmm();
+        let mut cmdline = default_kernel_cmdline();
+        let mut vm_resources = VmResources::default();
+        let backing_file = TempFile::new().unwrap();
+        backing_file.as_file().set_len(0x1000).unwrap();
+        let drive = |drive_id: &str| BlockDeviceConfig {
+            drive_id: String::from(drive_id),
+            partuuid: None,
+            is_root_device: false,
+            cache_type: CacheType::Unsafe,
+
+            is_read_only: Some(false),
+            path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
+            rate_limiter: None,
+            rate_limiter_scope: None,
+            file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
+
+            socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+        };
+
+        // No slot was reserved at boot.
+        assert!(matches!(
+            vm_resources.hotplug_block_device(drive("drive1"), &mut vmm),
+            Err(DriveError::NoHotplugSlot)
+        ));
+        assert!(vm_resources.block.devices.is_empty());
+
+        attach_hotplug_slots(&mut vmm, &mut cmdline, 2).unwrap();
+        vm_resources
+            .hotplug_block_device(drive("drive1"), &mut vmm)
+            .unwrap();
+        assert_eq!(vm_resources.block.devices.len(), 1);
+        assert_eq!(vmm.mmio_device_manager.hotplug_slots.len(), 1);
+        vmm.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_BLOCK, "drive1", |block: &mut Block| {
+                assert!(!block.is_activated());
+                Ok(())
+            })
+            .unwrap();
+        // The device is left for the event manager to subscribe.
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        vmm.update_event_subscribers(&mut event_manager);
+        assert!(vmm.hotplugged_devices.is_empty());
+        assert!(vmm
+            .mmio_device_manager
+            .subscriber_ids
+            .contains_key(&(DeviceType::Virtio(TYPE_BLOCK), "drive1".to_string())));
+
+        // A failed hot-plug leaves the slot free.
+        assert!(matches!(
+            vm_resources.hotplug_block_device(drive("drive1"), &mut vmm),
+            Err(DriveError::DriveIdInUse(_))
+        ));
+        let mut missing_file = drive("drive2");
+        missing_file.path_on_host = Some("/invalid/path".to_string());
+        assert!(matches!(
+            vm_resources.hotplug_block_device(missing_file, &mut vmm),
+            Err(DriveError::CreateBlockDevice(_))
+        ));
+        assert!(vmm.has_free_hotplug_slot());
+
+        vm_resources
+            .hotplug_block_device(drive("drive2"), &mut vmm)
+            .unwrap();
+        assert!(!vmm.has_free_hotplug_slot());
+        assert!(matches!(
+            vm_resources.hotplug_block_device(drive("drive3"), &mut vmm),
+            Err(DriveError::NoHotplugSlot)
+        ));
+        assert_eq!(vm_resources.block.devices.len(), 2);
+    }
+
+    #[test]
+    fn test_unplug_block_device() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        let mut cmdline = default_kernel_cmdline();
+        let mut vm_resources = VmResources::default();
+        let backing_file = TempFile::new().unwrap();
+        backing_file.as_file().set_len(0x1000).unwrap();
+        let drive = |drive_id: &str, is_root_device: bool| BlockDeviceConfig {
+            drive_id: String::from(drive_id),
+            partuuid: None,
+            is_root_device,
+            cache_type: CacheType::Unsafe,
+
+            is_read_only: Some(false),
+            path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
+            rate_limiter: None,
+            rate_limiter_scope: None,
+            file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
+
+            socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+        };
+        vm_resources.set_block_device(drive("root", true)).unwrap();
+        attach_block_devices(
+            &mut vmm,
+            &mut cmdline,
+            vm_resources.block.devices.iter(),
+            &mut event_manager,
+        )
+        .unwrap();
+        attach_hotplug_slots(&mut vmm, &mut cmdline, 1).unwrap();
+        vm_resources
+            .hotplug_block_device(drive("drive1", false), &mut vmm)
+            .unwrap();
+        vmm.update_event_subscribers(&mut event_manager);
+        let block = Arc::downgrade(vm_resources.block.devices.back().unwrap());
+        let fd = match &*block.upgrade().unwrap().lock().unwrap() {
+            Block::Virtio(virtio) => virtio.disk.file_engine.file().as_raw_fd(),
+            Block::VhostUser(_) => unreachable!(),
+        };
+
+        // Error Case: the root device can't be removed.
+        assert!(matches!(
+            vm_resources.unplug_block_device("root", &mut vmm),
+            Err(DriveError::RemoveRootDevice)
+        ));
+        // Error Case: the drive doesn't exist.
+        assert!(matches!(
+            vm_resources.unplug_block_device("drive2", &mut vmm),
+            Err(DriveError::InvalidDriveId(_))
+        ));
+        assert_eq!(vm_resources.block.devices.len(), 2);
+
+        vm_resources
+            .unplug_block_device("drive1", &mut vmm)
+            .unwrap();
+        assert_eq!(vm_resources.block.devices.len(), 1);
+        assert!(vmm
+            .mmio_device_manager
+            .get_device(DeviceType::Virtio(TYPE_BLOCK), "drive1")
+            .is_none());
+        assert!(vmm.has_free_hotplug_slot());
+        // The device, and its backing file, are dropped once the event manager forgets it.
+        assert!(block.upgrade().is_some());
+        vmm.update_event_subscribers(&mut event_manager);
+        assert!(block.upgrade().is_none());
+        // SAFETY: `F_GETFD` only queries the descriptor flags.
+        assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETFD) }, -1);
+        assert_eq!(
+            std::io::Error::last_os_error().raw_os_error(),
+            Some(libc::EBADF)
+        );
+
+        // Its slot is free for hot-plugging another device, even with the same ID.
+        vm_resources
+            .hotplug_block_device(drive("drive1", false), &mut vmm)
+            .unwrap();
+        assert!(!vmm.has_free_hotplug_slot());
+    }
+
+    #[test]
+    fn test_unplug_net_device() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        let mut cmdline = default_kernel_cmdline();
+        let mut vm_resources = VmResources::default();
+        let netif = |iface_id: &str| NetworkInterfaceConfig {
+            iface_id: String::from(iface_id),
+            host_dev_name: format!("up{iface_id}"),
+            tap_fd: None,
+            guest_mac: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
+        };
+        vm_resources.build_net_device(netif("netif1")).unwrap();
+        attach_net_devices(
+            &mut vmm,
+            &mut cmdline,
+            vm_resources.net_builder.iter(),
+            &mut event_manager,
+        )
+        .unwrap();
+        let net = Arc::downgrade(vm_resources.net_builder.iter().next().unwrap());
+        assert_eq!(vmm.mmio_device_manager.subscriber_ids.len(), 1);
+
+        // The device can be unplugged while the microVM is paused.
+        vmm.pause_vm().unwrap();
+        vm_resources.unplug_net_device("netif1", &mut vmm).unwrap();
+        assert_eq!(vm_resources.net_builder.iter().count(), 0);
+        assert!(vmm
+            .mmio_device_manager
+            .get_device(DeviceType::Virtio(TYPE_NET), "netif1")
+            .is_none());
+        assert_eq!(vmm.mmio_device_manager.hotplug_slots.len(), 1);
+        // The device, and its tap, are dropped once the event manager forgets it.
+        assert!(net.upgrade().is_some());
+        vmm.update_event_subscribers(&mut event_manager);
+        assert!(net.upgrade().is_none());
+        assert!(vmm.mmio_device_manager.subscriber_ids.is_empty());
+
+        // Unplugging it again fails cleanly.
+        assert!(matches!(
+            vm_resources.unplug_net_device("netif1", &mut vmm),
+            Err(NetworkInterfaceError::InvalidIfaceId(_))
+        ));
+
+        // Its slot is free for hot-plugging another device, even with the same ID.
+        vm_resources
+            .hotplug_net_device(netif("netif1"), &mut vmm)
+            .unwrap();
+        assert!(!vmm.has_free_hotplug_slot());
+
+        // A hot-plugged device can be unplugged before the event manager subscribes it.
+        vm_resources.unplug_net_device("netif1", &mut vmm).unwrap();
+        vmm.update_event_subscribers(&mut event_manager);
+        assert!(vmm.mmio_device_manager.subscriber_ids.is_empty());
+        assert!(vmm.has_free_hotplug_slot());
+    }
+
     #[test]
     fn test_attach_block_devices() {
         let mut event_manager = EventManager::new().expect("Unable to create EventManager");
@@ -1596,6 +2304,26 @@ pub mod tests {
             .is_some());
     }
 
+    #[test]
+    fn test_attach_hotplug_slots() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        let mut cmdline = default_kernel_cmdline();
+
+        let entropy_config = EntropyDeviceConfig::default();
+        insert_entropy_device(&mut vmm, &mut cmdline, &mut event_manager, entropy_config);
+        attach_hotplug_slots(&mut vmm, &mut cmdline, 2).unwrap();
+        assert_eq!(vmm.mmio_device_manager.hotplug_slots.len(), 2);
+        assert_eq!(vmm.mmio_device_manager.get_device_info().len(), 1);
+        // The slots are described in the kernel cmdline after the devices.
+        #[cfg(target_arch = "x86_64")]
+        assert!(cmdline_contains(
+            &cmdline,
+            "virtio_mmio.device=4K@0xd0000000:5 virtio_mmio.device=4K@0xd0001000:6 \
+             virtio_mmio.device=4K@0xd0002000:7"
+        ));
+    }
+
     #[test]
     fn test_attach_balloon_device() {
         let mut event_manager = EventManager::new().expect("Unable to create EventManager");
@@ -1605,6 +2333,8 @@ pub mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -1652,4 +2382,89 @@ pub mod tests {
             "virtio_mmio.device=4K@0xd0000000:5"
         ));
     }
+
+    #[test]
+    fn test_attach_readiness_probe() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        // Without a readiness probe, the microVM is ready once started.
+        let info = vmm.instance_info();
+        assert!(info.ready);
+        assert!(info.readiness_probe.is_none());
+
+        let mut vm_resources = VmResources::default();
+        let config = ReadinessProbeConfig {
+            check: ReadinessCheck::Vsock { port: 52 },
+            timeout_ms: 100,
+            max_attempts: 3,
+        };
+        assert!(matches!(
+            attach_readiness_probe(&mut vmm, &vm_resources, &config, &mut event_manager),
+            Err(StartMicrovmError::ReadinessProbe(
+                ReadinessProbeError::MissingVsock
+            ))
+        ));
+
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        vm_resources.mmds = Some(mmds.clone());
+        let config = ReadinessProbeConfig {
+            check: ReadinessCheck::Mmds {
+                key: "ready".to_string(),
+            },
+            ..config
+        };
+        attach_readiness_probe(&mut vmm, &vm_resources, &config, &mut event_manager).unwrap();
+        let info = vmm.instance_info();
+        assert!(!info.ready);
+        assert_eq!(
+            info.readiness_probe,
+            Some(ReadinessProbeStatus {
+                state: ReadinessProbeState::Pending,
+                attempts: 1,
+            })
+        );
+
+        // The guest writes its key, which the probe notices on its next timer event.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .write_guest_value(serde_json::Value::from("up"))
+            .unwrap();
+        event_manager.run_with_timeout(1000).unwrap();
+        let info = serde_json::to_value(vmm.instance_info()).unwrap();
+        assert_eq!(info["ready"], true);
+        assert_eq!(
+            info["readiness_probe"],
+            serde_json::json!({"state": "ready", "attempts": 1})
+        );
+    }
+
+    #[test]
+    fn test_auto_pause() {
+        let mut vmm = default_vmm();
+        let config = AutoPauseConfig {
+            idle_seconds: 1,
+            signals: vec![ActivitySignal::Net],
+        };
+        attach_auto_pause(&mut vmm, &config, &[]).unwrap();
+        vmm.resume_vm().unwrap();
+        assert_eq!(vmm.instance_info().state, VmState::Running);
+
+        // The microVM is paused once idle for long enough.
+        vmm.process_auto_pause_timer();
+        assert_eq!(vmm.instance_info().state, VmState::Running);
+        std::thread::sleep(Duration::from_millis(1100));
+        vmm.process_auto_pause_timer();
+        assert_eq!(vmm.instance_info().state, VmState::Paused);
+
+        // And resumed by the next network activity.
+        record_activity(ActivitySignal::Net);
+        vmm.process_auto_pause_wake();
+        assert_eq!(vmm.instance_info().state, VmState::Running);
+
+        // A microVM paused through the API is not resumed by activity.
+        vmm.pause_vm().unwrap();
+        record_activity(ActivitySignal::Net);
+        vmm.process_auto_pause_wake();
+        assert_eq!(vmm.instance_info().state, VmState::Paused);
+    }
 }
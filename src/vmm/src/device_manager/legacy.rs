@@ -17,7 +17,9 @@ use vm_superio::Serial;
 
 use crate::devices::bus::BusDevice;
 use crate::devices::legacy::serial::SerialOut;
-use crate::devices::legacy::{EventFdTrigger, SerialDevice, SerialEventsWrapper};
+use crate::devices::legacy::{
+    EventFdTrigger, SerialDevice, SerialEventsWrapper, SerialPortMetrics,
+};
 
 /// Errors corresponding to the `PortIODeviceManager`.
 #[derive(Debug, derive_more::From, thiserror::Error, displaydoc::Display)]
@@ -34,15 +36,12 @@ pub enum LegacyDeviceError {
 #[derive(Debug)]
 pub struct PortIODeviceManager {
     pub io_bus: crate::devices::Bus,
-    // BusDevice::Serial
-    pub stdio_serial: Arc<Mutex<BusDevice>>,
+    // BusDevice::Serial, one per serial port of the microVM, placed at the standard COM port
+    // addresses in order. The first one is the serial console.
+    pub serial_ports: Vec<Arc<Mutex<BusDevice>>>,
     // BusDevice::I8042Device
     pub i8042: Arc<Mutex<BusDevice>>,
 
-    // Communication event on ports 1 & 3.
-    pub com_evt_1_3: EventFdTrigger,
-    // Communication event on ports 2 & 4.
-    pub com_evt_2_4: EventFdTrigger,
     // Keyboard event.
     pub kbd_evt: EventFd,
 }
@@ -56,6 +55,13 @@ impl PortIODeviceManager {
     /// & 4. See
     /// <https://en.wikipedia.org/wiki/Interrupt_request_(PC_architecture)>.
     const COM_EVT_2_4_GSI: u32 = 3;
+    /// Global system interrupts of the legacy serial ports.
+    const SERIAL_PORT_GSIS: [u32; 4] = [
+        Self::COM_EVT_1_3_GSI,
+        Self::COM_EVT_2_4_GSI,
+        Self::COM_EVT_1_3_GSI,
+        Self::COM_EVT_2_4_GSI,
+    ];
     /// x86 global system interrupt for keyboard port.
     /// See <https://en.wikipedia.org/wiki/Interrupt_request_(PC_architecture)>.
     const KBD_EVT_GSI: u32 = 1;
@@ -70,22 +76,17 @@ impl PortIODeviceManager {
     /// i8042 keyboard data register size.
     const I8042_KDB_DATA_REGISTER_SIZE: u64 = 0x5;
 
-    /// Create a new DeviceManager handling legacy devices (uart, i8042).
+    /// Create a new DeviceManager handling legacy devices (uart, i8042), with the serial
+    /// devices of the (1 to 4) serial ports of the microVM.
     pub fn new(
-        serial: Arc<Mutex<BusDevice>>,
+        serial_ports: Vec<Arc<Mutex<BusDevice>>>,
         i8042_reset_evfd: EventFd,
     ) -> Result<Self, LegacyDeviceError> {
-        debug_assert!(matches!(*serial.lock().unwrap(), BusDevice::Serial(_)));
+        debug_assert!((1..=Self::SERIAL_PORT_ADDRESSES.len()).contains(&serial_ports.len()));
+        debug_assert!(serial_ports
+            .iter()
+            .all(|serial| matches!(*serial.lock().unwrap(), BusDevice::Serial(_))));
         let io_bus = crate::devices::Bus::new();
-        let com_evt_1_3 = serial
-            .lock()
-            .expect("Poisoned lock")
-            .serial_mut()
-            .unwrap()
-            .serial
-            .interrupt_evt()
-            .try_clone()?;
-        let com_evt_2_4 = EventFdTrigger::new(EventFd::new(EFD_NONBLOCK)?);
         let kbd_evt = EventFd::new(libc::EFD_NONBLOCK)?;
 
         let i8042 = Arc::new(Mutex::new(BusDevice::I8042Device(
@@ -94,72 +95,59 @@ impl PortIODeviceManager {
 
         Ok(PortIODeviceManager {
             io_bus,
-            stdio_serial: serial,
+            serial_ports,
             i8042,
-            com_evt_1_3,
-            com_evt_2_4,
             kbd_evt,
         })
     }
 
+    /// Serial device of the serial console.
+    pub fn console_serial(&self) -> &Arc<Mutex<BusDevice>> {
+        &self.serial_ports[0]
+    }
+
     /// Register supported legacy devices.
     pub fn register_devices(&mut self, vm_fd: &VmFd) -> Result<(), LegacyDeviceError> {
-        let serial_2_4 = Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
-            serial: Serial::with_events(
-                self.com_evt_2_4.try_clone()?.try_clone()?,
-                SerialEventsWrapper {
-                    buffer_ready_event_fd: None,
-                },
-                SerialOut::Sink(std::io::sink()),
-            ),
-            input: None,
-        })));
-        let serial_1_3 = Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
-            serial: Serial::with_events(
-                self.com_evt_1_3.try_clone()?.try_clone()?,
-                SerialEventsWrapper {
-                    buffer_ready_event_fd: None,
-                },
-                SerialOut::Sink(std::io::sink()),
-            ),
-            input: None,
-        })));
-        self.io_bus.insert(
-            self.stdio_serial.clone(),
-            Self::SERIAL_PORT_ADDRESSES[0],
-            Self::SERIAL_PORT_SIZE,
-        )?;
-        self.io_bus.insert(
-            serial_2_4.clone(),
-            Self::SERIAL_PORT_ADDRESSES[1],
-            Self::SERIAL_PORT_SIZE,
-        )?;
-        self.io_bus.insert(
-            serial_1_3,
-            Self::SERIAL_PORT_ADDRESSES[2],
-            Self::SERIAL_PORT_SIZE,
-        )?;
-        self.io_bus.insert(
-            serial_2_4,
-            Self::SERIAL_PORT_ADDRESSES[3],
-            Self::SERIAL_PORT_SIZE,
-        )?;
+        // The COM ports without a configured serial port get a serial device discarding its
+        // output, as the guest probes all of them.
+        for (index, address) in Self::SERIAL_PORT_ADDRESSES.into_iter().enumerate() {
+            let serial = match self.serial_ports.get(index) {
+                Some(serial) => serial.clone(),
+                None => Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
+                    serial: Serial::with_events(
+                        EventFdTrigger::new(EventFd::new(EFD_NONBLOCK)?),
+                        SerialEventsWrapper {
+                            buffer_ready_event_fd: None,
+                            metrics: SerialPortMetrics::default(),
+                        },
+                        SerialOut::Sink(std::io::sink()).into(),
+                    ),
+                    input: None,
+                }))),
+            };
+            vm_fd
+                .register_irqfd(
+                    serial
+                        .lock()
+                        .expect("Poisoned lock")
+                        .serial_ref()
+                        .unwrap()
+                        .serial
+                        .interrupt_evt(),
+                    Self::SERIAL_PORT_GSIS[index],
+                )
+                .map_err(|e| {
+                    LegacyDeviceError::EventFd(std::io::Error::from_raw_os_error(e.errno()))
+                })?;
+            self.io_bus
+                .insert(serial, address, Self::SERIAL_PORT_SIZE)?;
+        }
         self.io_bus.insert(
             self.i8042.clone(),
             Self::I8042_KDB_DATA_REGISTER_ADDRESS,
             Self::I8042_KDB_DATA_REGISTER_SIZE,
         )?;
 
-        vm_fd
-            .register_irqfd(&self.com_evt_1_3, Self::COM_EVT_1_3_GSI)
-            .map_err(|e| {
-                LegacyDeviceError::EventFd(std::io::Error::from_raw_os_error(e.errno()))
-            })?;
-        vm_fd
-            .register_irqfd(&self.com_evt_2_4, Self::COM_EVT_2_4_GSI)
-            .map_err(|e| {
-                LegacyDeviceError::EventFd(std::io::Error::from_raw_os_error(e.errno()))
-            })?;
         vm_fd
             .register_irqfd(&self.kbd_evt, Self::KBD_EVT_GSI)
             .map_err(|e| {
@@ -171,12 +159,6 @@ impl PortIODeviceManager {
 
     pub(crate) fn append_aml_bytes(bytes: &mut Vec<u8>) {
         // Set up COM devices
-        let gsi = [
-            Self::COM_EVT_1_3_GSI,
-            Self::COM_EVT_2_4_GSI,
-            Self::COM_EVT_1_3_GSI,
-            Self::COM_EVT_2_4_GSI,
-        ];
         for com in 0u8..4 {
             // COM1
             aml::Device::new(
@@ -188,7 +170,13 @@ impl PortIODeviceManager {
                     &aml::Name::new(
                         "_CRS".into(),
                         &aml::ResourceTemplate::new(vec![
-                            &aml::Interrupt::new(true, true, false, false, gsi[com as usize]),
+                            &aml::Interrupt::new(
+                                true,
+                                true,
+                                false,
+                                false,
+                                Self::SERIAL_PORT_GSIS[com as usize],
+                            ),
                             &aml::Io::new(
                                 PortIODeviceManager::SERIAL_PORT_ADDRESSES[com as usize]
                                     .try_into()
@@ -237,10 +225,36 @@ impl PortIODeviceManager {
 
 #[cfg(test)]
 mod tests {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use utils::tempfile::TempFile;
+
     use super::*;
     use crate::utilities::test_utils::single_region_mem;
     use crate::Vm;
 
+    fn serial_device(out: SerialOut) -> Arc<Mutex<BusDevice>> {
+        Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
+            serial: Serial::with_events(
+                EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).unwrap()),
+                SerialEventsWrapper {
+                    buffer_ready_event_fd: None,
+                    metrics: SerialPortMetrics::default(),
+                },
+                out.into(),
+            ),
+            input: None,
+        })))
+    }
+
+    fn read_output(file: &TempFile) -> Vec<u8> {
+        let mut file = file.as_file();
+        let mut output = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut output).unwrap();
+        output
+    }
+
     #[test]
     fn test_register_legacy_devices() {
         let guest_mem = single_region_mem(0x1000);
@@ -248,19 +262,52 @@ mod tests {
         vm.memory_init(&guest_mem, false).unwrap();
         crate::builder::setup_interrupt_controller(&mut vm).unwrap();
         let mut ldm = PortIODeviceManager::new(
-            Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
-                serial: Serial::with_events(
-                    EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).unwrap()),
-                    SerialEventsWrapper {
-                        buffer_ready_event_fd: None,
-                    },
-                    SerialOut::Sink(std::io::sink()),
-                ),
-                input: None,
-            }))),
+            vec![serial_device(SerialOut::Sink(std::io::sink()))],
+            EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+        )
+        .unwrap();
+        ldm.register_devices(vm.fd()).unwrap();
+    }
+
+    #[test]
+    fn test_register_serial_ports() {
+        let guest_mem = single_region_mem(0x1000);
+        let mut vm = Vm::new(vec![]).unwrap();
+        vm.memory_init(&guest_mem, false).unwrap();
+        crate::builder::setup_interrupt_controller(&mut vm).unwrap();
+        let console_out = TempFile::new().unwrap();
+        let mgmt_out = TempFile::new().unwrap();
+        let mut ldm = PortIODeviceManager::new(
+            vec![
+                serial_device(SerialOut::File(console_out.as_file().try_clone().unwrap())),
+                serial_device(SerialOut::File(mgmt_out.as_file().try_clone().unwrap())),
+            ],
             EventFd::new(libc::EFD_NONBLOCK).unwrap(),
         )
         .unwrap();
         ldm.register_devices(vm.fd()).unwrap();
+
+        // Bytes written to the transmitter holding register of COM2 only reach the output of the
+        // second serial port.
+        for byte in b"mgmt" {
+            assert!(ldm
+                .io_bus
+                .write(PortIODeviceManager::SERIAL_PORT_ADDRESSES[1], &[*byte]));
+        }
+        assert_eq!(read_output(&mgmt_out), b"mgmt");
+        assert!(read_output(&console_out).is_empty());
+
+        assert!(ldm
+            .io_bus
+            .write(PortIODeviceManager::SERIAL_PORT_ADDRESSES[0], b"c"));
+        assert_eq!(read_output(&console_out), b"c");
+        assert_eq!(read_output(&mgmt_out), b"mgmt");
+
+        // The COM ports without a configured serial port discard their output.
+        assert!(ldm
+            .io_bus
+            .write(PortIODeviceManager::SERIAL_PORT_ADDRESSES[2], b"x"));
+        assert_eq!(read_output(&console_out), b"c");
+        assert_eq!(read_output(&mgmt_out), b"mgmt");
     }
 }
@@ -11,6 +11,7 @@ use std::sync::{Arc, Mutex};
 
 #[cfg(target_arch = "x86_64")]
 use acpi_tables::{aml, Aml};
+use event_manager::SubscriberId;
 use kvm_ioctls::{IoEventAddress, VmFd};
 use linux_loader::cmdline as kernel_cmdline;
 #[cfg(target_arch = "x86_64")]
@@ -30,10 +31,11 @@ use crate::devices::pseudo::BootTimer;
 use crate::devices::virtio::balloon::Balloon;
 use crate::devices::virtio::block::device::Block;
 use crate::devices::virtio::device::VirtioDevice;
-use crate::devices::virtio::mmio::MmioTransport;
+use crate::devices::virtio::mmio::{MmioPlaceholder, MmioTransport};
 use crate::devices::virtio::net::Net;
+use crate::devices::virtio::queue_metrics::QueueMetricsPerDevice;
 use crate::devices::virtio::rng::Entropy;
-use crate::devices::virtio::vsock::TYPE_VSOCK;
+use crate::devices::virtio::vsock::{Vsock, VsockUnixBackend, TYPE_VSOCK};
 use crate::devices::virtio::{TYPE_BALLOON, TYPE_BLOCK, TYPE_NET, TYPE_RNG};
 use crate::devices::BusDevice;
 #[cfg(target_arch = "x86_64")]
@@ -56,6 +58,8 @@ pub enum MmioError {
     InternalDeviceError(String),
     /// Invalid MMIO IRQ configuration.
     InvalidIrqConfig,
+    /// No free slot to hot-plug the device in.
+    NoHotplugSlot,
     /// Failed to register IO event: {0}
     RegisterIoEvent(kvm_ioctls::Error),
     /// Failed to register irqfd: {0}
@@ -123,6 +127,12 @@ pub struct MMIODeviceManager {
     // devices in the order they were added.
     #[cfg(target_arch = "x86_64")]
     pub(crate) dsdt_data: Vec<u8>,
+    // Slots reserved at boot for hot-plugging virtio devices, which are not used yet. They hold
+    // placeholders, which the guest driver gives up on until it probes them again.
+    pub(crate) hotplug_slots: Vec<MMIODeviceInfo>,
+    // The ids of the virtio devices as subscribers of the event manager, which has to forget them
+    // once they are unplugged.
+    pub(crate) subscriber_ids: HashMap<(DeviceType, String), SubscriberId>,
 }
 
 impl MMIODeviceManager {
@@ -133,6 +143,8 @@ impl MMIODeviceManager {
             id_to_dev_info: HashMap::new(),
             #[cfg(target_arch = "x86_64")]
             dsdt_data: vec![],
+            hotplug_slots: Vec::new(),
+            subscriber_ids: HashMap::new(),
         }
     }
 
@@ -182,24 +194,20 @@ impl MMIODeviceManager {
         if device_info.irqs.len() != 1 {
             return Err(MmioError::InvalidIrqConfig);
         }
-        let identifier;
-        {
-            let locked_device = mmio_device.locked_device();
-            identifier = (DeviceType::Virtio(locked_device.device_type()), device_id);
-            for (i, queue_evt) in locked_device.queue_events().iter().enumerate() {
-                let io_addr = IoEventAddress::Mmio(
-                    device_info.addr + u64::from(crate::devices::virtio::NOTIFY_REG_OFFSET),
-                );
-                vm.register_ioevent(queue_evt, &io_addr, u32::try_from(i).unwrap())
-                    .map_err(MmioError::RegisterIoEvent)?;
-            }
-            vm.register_irqfd(
-                &locked_device.interrupt_trigger().irq_evt,
-                device_info.irqs[0],
-            )
-            .map_err(MmioError::RegisterIrqFd)?;
+        if !mmio_device.is_vhost_user {
+            QueueMetricsPerDevice::register(
+                &device_id,
+                &mmio_device.device(),
+                mmio_device.mem().clone(),
+            );
         }
 
+        let identifier = (
+            DeviceType::Virtio(mmio_device.locked_device().device_type()),
+            device_id,
+        );
+        Self::register_mmio_virtio_events(vm, &mmio_device, device_info)?;
+
         self.register_mmio_device(
             identifier,
             device_info.clone(),
@@ -207,6 +215,183 @@ impl MMIODeviceManager {
         )
     }
 
+    // Registers the queue notifications and the interrupt of a virtio-over-MMIO device with KVM.
+    fn register_mmio_virtio_events(
+        vm: &VmFd,
+        mmio_device: &MmioTransport,
+        device_info: &MMIODeviceInfo,
+    ) -> Result<(), MmioError> {
+        let locked_device = mmio_device.locked_device();
+        let io_addr = IoEventAddress::Mmio(
+            device_info.addr + u64::from(crate::devices::virtio::NOTIFY_REG_OFFSET),
+        );
+        for (i, queue_evt) in locked_device.queue_events().iter().enumerate() {
+            vm.register_ioevent(queue_evt, &io_addr, u32::try_from(i).unwrap())
+                .map_err(MmioError::RegisterIoEvent)?;
+        }
+        vm.register_irqfd(
+            &locked_device.interrupt_trigger().irq_evt,
+            device_info.irqs[0],
+        )
+        .map_err(MmioError::RegisterIrqFd)
+    }
+
+    // Unregisters the queue notifications of a virtio-over-MMIO device, ignoring the ones which
+    // were not registered.
+    fn unregister_mmio_virtio_ioevents(
+        vm: &VmFd,
+        mmio_device: &MmioTransport,
+        device_info: &MMIODeviceInfo,
+    ) {
+        let locked_device = mmio_device.locked_device();
+        let io_addr = IoEventAddress::Mmio(
+            device_info.addr + u64::from(crate::devices::virtio::NOTIFY_REG_OFFSET),
+        );
+        for (i, queue_evt) in locked_device.queue_events().iter().enumerate() {
+            let _ = vm.unregister_ioevent(queue_evt, &io_addr, u32::try_from(i).unwrap());
+        }
+    }
+
+    /// Register a placeholder in a slot reserved for hot-plugging a virtio device.
+    pub fn register_mmio_placeholder(
+        &mut self,
+        device_info: MMIODeviceInfo,
+    ) -> Result<(), MmioError> {
+        if device_info.irqs.len() != 1 {
+            return Err(MmioError::InvalidIrqConfig);
+        }
+        self.bus
+            .insert(
+                Arc::new(Mutex::new(BusDevice::MmioPlaceholder(MmioPlaceholder))),
+                device_info.addr,
+                device_info.len,
+            )
+            .map_err(MmioError::BusInsert)?;
+        self.hotplug_slots.push(device_info);
+        Ok(())
+    }
+
+    /// Allocate a slot for hot-plugging a virtio device after boot, and register a placeholder
+    /// in it. Also adds the slot to the boot cmdline, so that the guest knows about it.
+    pub fn register_mmio_placeholder_for_boot(
+        &mut self,
+        resource_allocator: &mut ResourceAllocator,
+        _cmdline: &mut kernel_cmdline::Cmdline,
+    ) -> Result<MMIODeviceInfo, MmioError> {
+        let device_info = self.allocate_mmio_resources(resource_allocator, 1)?;
+        self.register_mmio_placeholder(device_info.clone())?;
+        #[cfg(target_arch = "x86_64")]
+        {
+            Self::add_virtio_device_to_cmdline(_cmdline, &device_info)?;
+            add_virtio_aml(
+                &mut self.dsdt_data,
+                device_info.addr,
+                device_info.len,
+                device_info.irqs[0],
+            );
+        }
+        Ok(device_info)
+    }
+
+    /// Plug a virtio-over-MMIO device in the first free hot-plug slot, in place of its
+    /// placeholder. The slot is left as it was if the device can't be plugged.
+    pub fn hotplug_mmio_virtio(
+        &mut self,
+        vm: &VmFd,
+        device_id: String,
+        mmio_device: MmioTransport,
+    ) -> Result<MMIODeviceInfo, MmioError> {
+        let device_info = self
+            .hotplug_slots
+            .first()
+            .cloned()
+            .ok_or(MmioError::NoHotplugSlot)?;
+        let (_, bus_device) = self
+            .bus
+            .get_device(device_info.addr)
+            .ok_or(MmioError::DeviceNotFound)?;
+
+        let identifier = (
+            DeviceType::Virtio(mmio_device.locked_device().device_type()),
+            device_id,
+        );
+        if let Err(err) = Self::register_mmio_virtio_events(vm, &mmio_device, &device_info) {
+            Self::unregister_mmio_virtio_ioevents(vm, &mmio_device, &device_info);
+            return Err(err);
+        }
+        if !mmio_device.is_vhost_user {
+            QueueMetricsPerDevice::register(
+                &identifier.1,
+                &mmio_device.device(),
+                mmio_device.mem().clone(),
+            );
+        }
+
+        // The vCPUs share the devices of the bus, so they see the device as soon as it replaces
+        // the placeholder.
+        *bus_device.lock().expect("Poisoned lock") = BusDevice::MmioTransport(mmio_device);
+        self.hotplug_slots.remove(0);
+        self.id_to_dev_info.insert(identifier, device_info.clone());
+        Ok(device_info)
+    }
+
+    /// Unplug the virtio-over-MMIO device matching `virtio_type` and `id`, in favour of a
+    /// placeholder, and free its slot for hot-plugging another device. `quiesce` is called with
+    /// the device once the guest can't reach it anymore, but can still be interrupted by it.
+    pub fn unplug_mmio_virtio<T, F>(
+        &mut self,
+        vm: &VmFd,
+        virtio_type: u32,
+        id: &str,
+        quiesce: F,
+    ) -> Result<(), MmioError>
+    where
+        T: VirtioDevice + 'static + Debug,
+        F: FnOnce(&mut T),
+    {
+        let identifier = (DeviceType::Virtio(virtio_type), id.to_string());
+        let device_info = self
+            .id_to_dev_info
+            .get(&identifier)
+            .cloned()
+            .ok_or(MmioError::DeviceNotFound)?;
+        let (_, bus_device) = self
+            .bus
+            .get_device(device_info.addr)
+            .ok_or(MmioError::DeviceNotFound)?;
+
+        let mut locked_bus_device = bus_device.lock().expect("Poisoned lock");
+        let mmio_device = match std::mem::replace(
+            &mut *locked_bus_device,
+            BusDevice::MmioPlaceholder(MmioPlaceholder),
+        ) {
+            BusDevice::MmioTransport(mmio_device) => mmio_device,
+            other => {
+                *locked_bus_device = other;
+                return Err(MmioError::InvalidDeviceType);
+            }
+        };
+        drop(locked_bus_device);
+
+        Self::unregister_mmio_virtio_ioevents(vm, &mmio_device, &device_info);
+        let mut locked_device = mmio_device.locked_device();
+        quiesce(
+            locked_device
+                .as_mut_any()
+                .downcast_mut::<T>()
+                .expect("Unexpected device type"),
+        );
+        let _ = vm.unregister_irqfd(
+            &locked_device.interrupt_trigger().irq_evt,
+            device_info.irqs[0],
+        );
+        drop(locked_device);
+
+        self.id_to_dev_info.remove(&identifier);
+        self.hotplug_slots.push(device_info);
+        Ok(())
+    }
+
     /// Append a registered virtio-over-MMIO device to the kernel cmdline.
     #[cfg(target_arch = "x86_64")]
     pub fn add_virtio_device_to_cmdline(
@@ -256,13 +441,14 @@ impl MMIODeviceManager {
     }
 
     #[cfg(target_arch = "aarch64")]
-    /// Register an early console at the specified MMIO configuration if given as parameter,
-    /// otherwise allocate a new MMIO resources for it.
+    /// Register the serial device with id `device_id` at the specified MMIO configuration if
+    /// given as parameter, otherwise allocate a new MMIO resources for it.
     pub fn register_mmio_serial(
         &mut self,
         vm: &VmFd,
         resource_allocator: &mut ResourceAllocator,
         serial: Arc<Mutex<BusDevice>>,
+        device_id: String,
         device_info_opt: Option<MMIODeviceInfo>,
     ) -> Result<(), MmioError> {
         // Create a new MMIODeviceInfo object on boot path or unwrap the
@@ -285,20 +471,21 @@ impl MMIODeviceManager {
         )
         .map_err(MmioError::RegisterIrqFd)?;
 
-        let identifier = (DeviceType::Serial, DeviceType::Serial.to_string());
+        let identifier = (DeviceType::Serial, device_id);
         // Register the newly created Serial object.
         self.register_mmio_device(identifier, device_info, serial)
     }
 
     #[cfg(target_arch = "aarch64")]
-    /// Append the registered early console to the kernel cmdline.
+    /// Append the serial device with id `device_id`, as early console, to the kernel cmdline.
     pub fn add_mmio_serial_to_cmdline(
         &self,
         cmdline: &mut kernel_cmdline::Cmdline,
+        device_id: &str,
     ) -> Result<(), MmioError> {
         let device_info = self
             .id_to_dev_info
-            .get(&(DeviceType::Serial, DeviceType::Serial.to_string()))
+            .get(&(DeviceType::Serial, device_id.to_string()))
             .ok_or(MmioError::DeviceNotFound)?;
         cmdline
             .insert("earlycon", &format!("uart,mmio,0x{:08x}", device_info.addr))
@@ -354,6 +541,20 @@ impl MMIODeviceManager {
         &self.id_to_dev_info
     }
 
+    #[cfg(target_arch = "aarch64")]
+    /// Gets the information of the devices registered up to some point in time, and of the free
+    /// hot-plug slots, which the guest has to find in the FDT as virtio devices as well.
+    pub fn get_fdt_device_info(&self) -> HashMap<(DeviceType, String), MMIODeviceInfo> {
+        let mut device_info = self.id_to_dev_info.clone();
+        for (i, slot_info) in self.hotplug_slots.iter().enumerate() {
+            device_info.insert(
+                (DeviceType::Virtio(0), format!("hotplug_slot{i}")),
+                slot_info.clone(),
+            );
+        }
+        device_info
+    }
+
     /// Gets the specified device.
     pub fn get_device(
         &self,
@@ -371,6 +572,22 @@ impl MMIODeviceManager {
         None
     }
 
+    #[cfg(target_arch = "aarch64")]
+    /// Gets the serial devices, ordered by MMIO address. The first one is the serial console.
+    pub fn get_serial_devices(&self) -> Vec<&Mutex<BusDevice>> {
+        let mut addresses: Vec<u64> = self
+            .id_to_dev_info
+            .iter()
+            .filter(|((device_type, _), _)| *device_type == DeviceType::Serial)
+            .map(|(_, device_info)| device_info.addr)
+            .collect();
+        addresses.sort_unstable();
+        addresses
+            .into_iter()
+            .filter_map(|addr| self.bus.get_device(addr).map(|(_, device)| device))
+            .collect()
+    }
+
     /// Run fn for each registered device.
     pub fn for_each_device<F, E: Debug>(&self, mut f: F) -> Result<(), E>
     where
@@ -461,9 +678,9 @@ impl MMIODeviceManager {
                         if let Some(block) = virtio.as_mut_any().downcast_mut::<Block>() {
                             // If device is activated, kick the block queue(s) to make up for any
                             // pending or in-flight epoll events we may have not captured in
-                            // snapshot. No need to kick Ratelimiters
-                            // because they are restored 'unblocked' so
-                            // any inflight `timer_fd` events can be safely discarded.
+                            // snapshot. No need to kick Ratelimiters: blocked ones are
+                            // restored with their timer armed, and process the queue once
+                            // it fires.
                             if block.is_activated() {
                                 info!("kick block {}.", id);
                                 block.process_virtio_queues();
@@ -474,8 +691,8 @@ impl MMIODeviceManager {
                         let net = virtio.as_mut_any().downcast_mut::<Net>().unwrap();
                         // If device is activated, kick the net queue(s) to make up for any
                         // pending or in-flight epoll events we may have not captured in snapshot.
-                        // No need to kick Ratelimiters because they are restored 'unblocked' so
-                        // any inflight `timer_fd` events can be safely discarded.
+                        // No need to kick Ratelimiters: blocked ones are restored with their
+                        // timer armed, and process the queues once it fires.
                         if net.is_activated() {
                             info!("kick net {}.", id);
                             net.process_virtio_queues();
@@ -483,9 +700,19 @@ impl MMIODeviceManager {
                     }
                     TYPE_VSOCK => {
                         // Vsock has complicated protocol that isn't resilient to any packet loss,
-                        // so for Vsock we don't support connection persistence through snapshot.
-                        // Any in-flight packets or events are simply lost.
-                        // Vsock is restored 'empty'.
+                        // so connections are only kept through snapshot when asked to, and
+                        // restored 'empty' otherwise. Either way, kick the vsock queues, so that
+                        // the guest gets the packets of restored connections, and the resets of
+                        // those which couldn't be restored.
+                        if let Some(vsock) = virtio
+                            .as_mut_any()
+                            .downcast_mut::<Vsock<VsockUnixBackend>>()
+                        {
+                            if vsock.is_activated() {
+                                info!("kick vsock {id}.");
+                                vsock.process_virtio_queues();
+                            }
+                        }
                     }
                     TYPE_RNG => {
                         let entropy = virtio.as_mut_any().downcast_mut::<Entropy>().unwrap();
@@ -653,7 +880,7 @@ mod tests {
         #[cfg(target_arch = "x86_64")]
         builder::setup_interrupt_controller(&mut vm).unwrap();
         #[cfg(target_arch = "aarch64")]
-        builder::setup_interrupt_controller(&mut vm, 1).unwrap();
+        builder::setup_interrupt_controller(&mut vm, 1, false).unwrap();
 
         device_manager
             .register_virtio_test_device(
@@ -681,7 +908,7 @@ mod tests {
         #[cfg(target_arch = "x86_64")]
         builder::setup_interrupt_controller(&mut vm).unwrap();
         #[cfg(target_arch = "aarch64")]
-        builder::setup_interrupt_controller(&mut vm, 1).unwrap();
+        builder::setup_interrupt_controller(&mut vm, 1, false).unwrap();
 
         for _i in crate::arch::IRQ_BASE..=crate::arch::IRQ_MAX {
             device_manager
@@ -734,7 +961,7 @@ mod tests {
         #[cfg(target_arch = "x86_64")]
         builder::setup_interrupt_controller(&mut vm).unwrap();
         #[cfg(target_arch = "aarch64")]
-        builder::setup_interrupt_controller(&mut vm, 1).unwrap();
+        builder::setup_interrupt_controller(&mut vm, 1, false).unwrap();
 
         let mut device_manager = MMIODeviceManager::new();
         let mut resource_allocator = ResourceAllocator::new().unwrap();
@@ -798,6 +1025,149 @@ mod tests {
         assert_eq!(device_manager.used_irqs_count(), 2);
     }
 
+    #[test]
+    fn test_hotplug_virtio_device() {
+        let start_addr1 = GuestAddress(0x0);
+        let start_addr2 = GuestAddress(0x1000);
+        let guest_mem = multi_region_mem(&[(start_addr1, 0x1000), (start_addr2, 0x1000)]);
+        let mut vm = Vm::new(vec![]).unwrap();
+        vm.memory_init(&guest_mem, false).unwrap();
+        #[cfg(target_arch = "x86_64")]
+        builder::setup_interrupt_controller(&mut vm).unwrap();
+        #[cfg(target_arch = "aarch64")]
+        builder::setup_interrupt_controller(&mut vm, 1, false).unwrap();
+
+        let mut device_manager = MMIODeviceManager::new();
+        let mut resource_allocator = ResourceAllocator::new().unwrap();
+        let mut cmdline = kernel_cmdline::Cmdline::new(4096).unwrap();
+
+        // Without a reserved slot, the device can't be plugged.
+        let mmio_device = MmioTransport::new(
+            guest_mem.clone(),
+            Arc::new(Mutex::new(DummyDevice::new())),
+            false,
+        );
+        assert!(matches!(
+            device_manager
+                .hotplug_mmio_virtio(vm.fd(), "dummy".to_string(), mmio_device)
+                .unwrap_err(),
+            MmioError::NoHotplugSlot
+        ));
+
+        let slot_info = device_manager
+            .register_mmio_placeholder_for_boot(&mut resource_allocator, &mut cmdline)
+            .unwrap();
+        assert_eq!(device_manager.hotplug_slots, vec![slot_info.clone()]);
+        assert!(device_manager.get_device_info().is_empty());
+        #[cfg(target_arch = "x86_64")]
+        assert!(cmdline
+            .as_cstring()
+            .unwrap()
+            .into_string()
+            .unwrap()
+            .contains(&format!(
+                "virtio_mmio.device=4K@0x{:08x}:{}",
+                slot_info.addr, slot_info.irqs[0]
+            )));
+        // The placeholder looks like a virtio device without a device ID.
+        let mut data = [0u8; 4];
+        assert!(device_manager.bus.read(slot_info.addr, &mut data));
+        assert_eq!(&data, b"virt");
+        assert!(device_manager.bus.read(slot_info.addr + 0x08, &mut data));
+        assert_eq!(u32::from_le_bytes(data), 0);
+
+        let mmio_device = MmioTransport::new(
+            guest_mem.clone(),
+            Arc::new(Mutex::new(DummyDevice::new())),
+            false,
+        );
+        let device_info = device_manager
+            .hotplug_mmio_virtio(vm.fd(), "dummy".to_string(), mmio_device)
+            .unwrap();
+        assert_eq!(device_info, slot_info);
+        assert!(device_manager.hotplug_slots.is_empty());
+        assert_eq!(
+            device_manager.id_to_dev_info[&(DeviceType::Virtio(0), "dummy".to_string())],
+            slot_info
+        );
+        assert!(device_manager
+            .get_device(DeviceType::Virtio(0), "dummy")
+            .unwrap()
+            .lock()
+            .unwrap()
+            .mmio_transport_ref()
+            .is_some());
+
+        // All the slots are used.
+        let mmio_device =
+            MmioTransport::new(guest_mem, Arc::new(Mutex::new(DummyDevice::new())), false);
+        assert!(matches!(
+            device_manager
+                .hotplug_mmio_virtio(vm.fd(), "dummy2".to_string(), mmio_device)
+                .unwrap_err(),
+            MmioError::NoHotplugSlot
+        ));
+    }
+
+    #[test]
+    fn test_unplug_virtio_device() {
+        let start_addr1 = GuestAddress(0x0);
+        let start_addr2 = GuestAddress(0x1000);
+        let guest_mem = multi_region_mem(&[(start_addr1, 0x1000), (start_addr2, 0x1000)]);
+        let mut vm = Vm::new(vec![]).unwrap();
+        vm.memory_init(&guest_mem, false).unwrap();
+        #[cfg(target_arch = "x86_64")]
+        builder::setup_interrupt_controller(&mut vm).unwrap();
+        #[cfg(target_arch = "aarch64")]
+        builder::setup_interrupt_controller(&mut vm, 1, false).unwrap();
+
+        let mut device_manager = MMIODeviceManager::new();
+        let mut resource_allocator = ResourceAllocator::new().unwrap();
+        let mut cmdline = kernel_cmdline::Cmdline::new(4096).unwrap();
+        let addr = device_manager
+            .register_virtio_test_device(
+                vm.fd(),
+                guest_mem.clone(),
+                &mut resource_allocator,
+                Arc::new(Mutex::new(DummyDevice::new())),
+                &mut cmdline,
+                "dummy",
+            )
+            .unwrap();
+        let device_info =
+            device_manager.id_to_dev_info[&(DeviceType::Virtio(0), "dummy".to_string())].clone();
+
+        let mut quiesced = false;
+        device_manager
+            .unplug_mmio_virtio(vm.fd(), 0, "dummy", |_: &mut DummyDevice| quiesced = true)
+            .unwrap();
+        assert!(quiesced);
+        assert!(device_manager.get_device_info().is_empty());
+        assert_eq!(device_manager.hotplug_slots, vec![device_info]);
+        // A placeholder took the place of the device.
+        let mut data = [0u8; 4];
+        assert!(device_manager.bus.read(addr, &mut data));
+        assert_eq!(&data, b"virt");
+        assert!(device_manager.bus.read(addr + 0x08, &mut data));
+        assert_eq!(u32::from_le_bytes(data), 0);
+
+        // The device is gone.
+        assert!(matches!(
+            device_manager
+                .unplug_mmio_virtio(vm.fd(), 0, "dummy", |_: &mut DummyDevice| {})
+                .unwrap_err(),
+            MmioError::DeviceNotFound
+        ));
+
+        // Another device can be plugged in its slot.
+        let mmio_device =
+            MmioTransport::new(guest_mem, Arc::new(Mutex::new(DummyDevice::new())), false);
+        let plugged_info = device_manager
+            .hotplug_mmio_virtio(vm.fd(), "dummy2".to_string(), mmio_device)
+            .unwrap();
+        assert_eq!(plugged_info.addr, addr);
+    }
+
     #[test]
     fn test_slot_irq_allocation() {
         let mut device_manager = MMIODeviceManager::new();
@@ -149,6 +149,8 @@ pub struct ConnectedEntropyState {
 pub struct ConnectedLegacyState {
     /// Device identifier.
     pub type_: DeviceType,
+    /// Device id, the id of the serial port for serial devices.
+    pub device_id: String,
     /// VmmResources.
     pub device_info: MMIODeviceInfo,
 }
@@ -196,6 +198,8 @@ pub struct DeviceStates {
     pub mmds_version: Option<MmdsVersionState>,
     /// Entropy device state.
     pub entropy_device: Option<ConnectedEntropyState>,
+    /// Slots reserved for hot-plugging devices, which are not used yet.
+    pub hotplug_slots: Vec<MMIODeviceInfo>,
 }
 
 /// A type used to extract the concrete `Arc<Mutex<T>>` for each of the device
@@ -301,6 +305,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                 if *devtype == DeviceType::Serial || *devtype == DeviceType::Rtc {
                     states.legacy_devices.push(ConnectedLegacyState {
                         type_: *devtype,
+                        device_id: devid.clone(),
                         device_info: device_info.clone(),
                     });
                     return Ok(());
@@ -371,14 +376,21 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                         .downcast_mut::<Vsock<VsockUnixBackend>>()
                         .unwrap();
 
+                    // Connections kept across snapshots are saved along with the host data
+                    // pending on them.
+                    let keep_connections = vsock.backend().keep_connections();
+                    if keep_connections && vsock.is_activated() {
+                        vsock.backend_mut().drain_connections();
+                    }
+
                     let vsock_state = VsockState {
                         backend: vsock.backend().save(),
                         frontend: vsock.save(),
                     };
 
                     // Send Transport event to reset connections if device
-                    // is activated.
-                    if vsock.is_activated() {
+                    // is activated, unless they are kept.
+                    if vsock.is_activated() && !keep_connections {
                         vsock.send_transport_reset_event().unwrap_or_else(|err| {
                             error!("Failed to send reset transport event: {:?}", err);
                         });
@@ -409,6 +421,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
 
             Ok(())
         });
+        states.hotplug_slots = self.hotplug_slots.clone();
         states
     }
 
@@ -424,11 +437,23 @@ impl<'a> Persist<'a> for MMIODeviceManager {
         {
             for state in &state.legacy_devices {
                 if state.type_ == DeviceType::Serial {
-                    let serial = crate::builder::setup_serial_device(
-                        constructor_args.event_manager,
-                        std::io::stdin(),
-                        std::io::stdout(),
-                    )?;
+                    // The configured serial ports are restored with the configuration of the
+                    // snapshotted microVM.
+                    let port = constructor_args
+                        .vm_resources
+                        .vm_config
+                        .serial_ports
+                        .iter()
+                        .flatten()
+                        .find(|port| port.id == state.device_id);
+                    let serial = match port {
+                        Some(port) => {
+                            crate::builder::setup_serial_port(constructor_args.event_manager, port)?
+                        }
+                        None => {
+                            crate::builder::setup_serial_console(constructor_args.event_manager)?
+                        }
+                    };
 
                     constructor_args
                         .resource_allocator
@@ -445,6 +470,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                         vm,
                         constructor_args.resource_allocator,
                         serial,
+                        state.device_id.clone(),
                         Some(state.device_info.clone()),
                     )?;
                 }
@@ -479,6 +505,10 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                                   device_info: &MMIODeviceInfo,
                                   event_manager: &mut EventManager|
          -> Result<(), Self::Error> {
+            let identifier = (
+                DeviceType::Virtio(device.lock().expect("Poisoned lock").device_type()),
+                id.clone(),
+            );
             let restore_args = MmioTransportConstructorArgs {
                 mem: mem.clone(),
                 device,
@@ -510,7 +540,8 @@ impl<'a> Persist<'a> for MMIODeviceManager {
 
             dev_manager.register_mmio_virtio(vm, id.clone(), mmio_transport, device_info)?;
 
-            event_manager.add_subscriber(as_subscriber);
+            let subscriber_id = event_manager.add_subscriber(as_subscriber);
+            dev_manager.subscriber_ids.insert(identifier, subscriber_id);
             Ok(())
         };
 
@@ -652,6 +683,20 @@ impl<'a> Persist<'a> for MMIODeviceManager {
             )?;
         }
 
+        for device_info in &state.hotplug_slots {
+            constructor_args
+                .resource_allocator
+                .allocate_mmio_memory(
+                    MMIO_LEN,
+                    MMIO_LEN,
+                    AllocPolicy::ExactMatch(device_info.addr),
+                )
+                .map_err(|e| {
+                    DevicePersistError::DeviceManager(super::mmio::MmioError::Allocator(e))
+                })?;
+            dev_manager.register_mmio_placeholder(device_info.clone())?;
+        }
+
         Ok(dev_manager)
     }
 }
@@ -704,6 +749,7 @@ mod tests {
                 && self.block_devices == other.block_devices
                 && self.net_devices == other.net_devices
                 && self.vsock_device == other.vsock_device
+                && self.hotplug_slots == other.hotplug_slots
         }
     }
 
@@ -714,13 +760,17 @@ mod tests {
             let mut clone = MMIODeviceManager::new();
             // We only care about the device hashmap.
             clone.id_to_dev_info = self.id_to_dev_info.clone();
+            clone.hotplug_slots = self.hotplug_slots.clone();
             clone
         }
     }
 
     impl PartialEq for MMIODeviceManager {
         fn eq(&self, other: &MMIODeviceManager) -> bool {
-            // We only care about the device hashmap.
+            // We only care about the device hashmap and the hot-plug slots.
+            if self.hotplug_slots != other.hotplug_slots {
+                return false;
+            }
             if self.id_to_dev_info.len() != other.id_to_dev_info.len() {
                 return false;
             }
@@ -753,6 +803,8 @@ mod tests {
                 amount_mib: 123,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 1,
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
             };
             insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_cfg);
             // Add a block device.
@@ -770,9 +822,17 @@ mod tests {
             let network_interface = NetworkInterfaceConfig {
                 iface_id: String::from("netif"),
                 host_dev_name: String::from("hostname"),
+                tap_fd: None,
                 guest_mac: None,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
+                queue_size: None,
+                num_queues: None,
+                rate_limiter_scope: None,
+                offloads: None,
+                mtu: None,
             };
             insert_net_device_with_mmds(
                 &mut vmm,
@@ -787,11 +847,19 @@ mod tests {
                 vsock_id: Some(vsock_dev_id.to_string()),
                 guest_cid: 3,
                 uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
+                queue_size: None,
+                keep_connections: None,
             };
             insert_vsock_device(&mut vmm, &mut cmdline, &mut event_manager, vsock_config);
             // Add an entropy device.
             let entropy_config = EntropyDeviceConfig::default();
             insert_entropy_device(&mut vmm, &mut cmdline, &mut event_manager, entropy_config);
+            // Reserve a hot-plug slot.
+            vmm.mmio_device_manager
+                .register_mmio_placeholder_for_boot(&mut vmm.resource_allocator, &mut cmdline)
+                .unwrap();
 
             Snapshot::serialize(&mut buf.as_mut_slice(), &vmm.mmio_device_manager.save()).unwrap();
 
@@ -832,12 +900,18 @@ mod tests {
       "path_on_host": "{}",
       "rate_limiter": null,
       "io_engine": "Sync",
+      "detect_zeroes": "off",
+      "io_engine_opts": {{
+        "max_requests_per_pass": 256
+      }},
       "socket": null
     }}
   ],
   "boot-source": {{
     "kernel_image_path": "",
+    "kernel_fd": null,
     "initrd_path": null,
+    "initrd_fd": null,
     "boot_args": null
   }},
   "cpu-config": null,
@@ -56,7 +56,7 @@ use event_manager::{EventOps, Events, MutEventSubscriber};
 use super::legacy::RTCDevice;
 use super::legacy::{I8042Device, SerialDevice};
 use super::pseudo::BootTimer;
-use super::virtio::mmio::MmioTransport;
+use super::virtio::mmio::{MmioPlaceholder, MmioTransport};
 
 #[derive(Debug)]
 pub enum BusDevice {
@@ -65,6 +65,7 @@ pub enum BusDevice {
     RTCDevice(RTCDevice),
     BootTimer(BootTimer),
     MmioTransport(MmioTransport),
+    MmioPlaceholder(MmioPlaceholder),
     Serial(SerialDevice<std::io::Stdin>),
     #[cfg(test)]
     Dummy(DummyDevice),
@@ -173,6 +174,7 @@ impl BusDevice {
             Self::RTCDevice(x) => x.bus_read(offset, data),
             Self::BootTimer(x) => x.bus_read(offset, data),
             Self::MmioTransport(x) => x.bus_read(offset, data),
+            Self::MmioPlaceholder(x) => x.bus_read(offset, data),
             Self::Serial(x) => x.bus_read(offset, data),
             #[cfg(test)]
             Self::Dummy(x) => x.bus_read(offset, data),
@@ -188,6 +190,7 @@ impl BusDevice {
             Self::RTCDevice(x) => x.bus_write(offset, data),
             Self::BootTimer(x) => x.bus_write(offset, data),
             Self::MmioTransport(x) => x.bus_write(offset, data),
+            Self::MmioPlaceholder(x) => x.bus_write(offset, data),
             Self::Serial(x) => x.bus_write(offset, data),
             #[cfg(test)]
             Self::Dummy(x) => x.bus_write(offset, data),
@@ -0,0 +1,247 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Looks for patterns, such as kernel panic messages, in the output of the serial console.
+//!
+//! The output is matched as it is written, one chunk at a time, so it never needs to be buffered:
+//! the patterns are compiled into an Aho-Corasick automaton whose transitions are all computed
+//! upfront, so scanning a byte costs two table lookups.
+
+use log::error;
+use utils::eventfd::EventFd;
+
+use crate::events::{VmmEvent, EVENTS};
+use crate::logger::IncMetric;
+use crate::vmm_config::console_scanner::{ConsoleMatchAction, ConsoleScannerConfig};
+
+/// Streaming matcher looking for several patterns at once.
+#[derive(Debug)]
+pub struct PatternMatcher {
+    // Class of each byte value. All the bytes which do not appear in any pattern share class 0.
+    classes: [u8; 256],
+    class_count: usize,
+    // Next state for each (state, class) pair, indexed by `state * class_count + class`.
+    transitions: Vec<u16>,
+    // Index of the pattern ending at each state, if any.
+    outputs: Vec<Option<usize>>,
+    state: u16,
+}
+
+impl PatternMatcher {
+    /// Builds a matcher for `patterns`, which must not be empty, and must add up to less than
+    /// 65535 bytes.
+    pub fn new<P: AsRef<[u8]>>(patterns: &[P]) -> Self {
+        let mut classes = [0u8; 256];
+        let mut class_count = 1;
+        for byte in patterns.iter().flat_map(|pattern| pattern.as_ref()) {
+            if classes[*byte as usize] == 0 {
+                // There are at most 256 distinct bytes, and class 0 is shared by those which do
+                // not appear in the patterns, so the class always fits in a byte.
+                classes[*byte as usize] = u8::try_from(class_count).unwrap();
+                class_count += 1;
+            }
+        }
+
+        // Build the trie of the patterns, with `u16::MAX` marking the missing transitions.
+        let mut transitions = vec![u16::MAX; class_count];
+        let mut outputs = vec![None];
+        for (index, pattern) in patterns.iter().enumerate() {
+            let mut state = 0;
+            for byte in pattern.as_ref() {
+                let slot = state * class_count + classes[*byte as usize] as usize;
+                if transitions[slot] == u16::MAX {
+                    transitions[slot] = u16::try_from(outputs.len()).unwrap();
+                    transitions.resize(transitions.len() + class_count, u16::MAX);
+                    outputs.push(None);
+                }
+                state = transitions[slot] as usize;
+            }
+            outputs[state].get_or_insert(index);
+        }
+
+        // Turn the trie into an automaton, walking it breadth-first so that the failure state of
+        // a state (the state of its longest proper suffix) is always complete when it is needed.
+        let mut failures = vec![0u16; outputs.len()];
+        let mut queue = std::collections::VecDeque::from([0u16]);
+        while let Some(state) = queue.pop_front() {
+            let state = state as usize;
+            let failure = failures[state] as usize;
+            for class in 0..class_count {
+                let slot = state * class_count + class;
+                let fallback = match state {
+                    0 => 0,
+                    _ => transitions[failure * class_count + class],
+                };
+                if transitions[slot] == u16::MAX {
+                    transitions[slot] = fallback;
+                } else {
+                    let next = transitions[slot] as usize;
+                    failures[next] = fallback;
+                    if outputs[next].is_none() {
+                        outputs[next] = outputs[fallback as usize];
+                    }
+                    queue.push_back(transitions[slot]);
+                }
+            }
+        }
+
+        PatternMatcher {
+            classes,
+            class_count,
+            transitions,
+            outputs,
+            state: 0,
+        }
+    }
+
+    /// Scans `data`, which follows the data previously scanned, and returns the index of a
+    /// pattern found in it, if any.
+    pub fn scan(&mut self, data: &[u8]) -> Option<usize> {
+        let mut found = None;
+        for byte in data {
+            let class = self.classes[*byte as usize] as usize;
+            self.state = self.transitions[self.state as usize * self.class_count + class];
+            found = found.or(self.outputs[self.state as usize]);
+        }
+        found
+    }
+}
+
+/// Scans the console output and reports the patterns found in it.
+#[derive(Debug)]
+pub struct ConsoleScanner {
+    matcher: PatternMatcher,
+    patterns: Vec<String>,
+    on_match: ConsoleMatchAction,
+    // Written to request the VMM to pause the microVM.
+    pause_evt: EventFd,
+}
+
+impl ConsoleScanner {
+    /// Creates a scanner for the (validated) `config`, which requests the microVM to be paused by
+    /// writing to `pause_evt`.
+    pub fn new(config: &ConsoleScannerConfig, pause_evt: EventFd) -> Self {
+        let patterns = config.patterns();
+        ConsoleScanner {
+            matcher: PatternMatcher::new(&patterns),
+            patterns,
+            on_match: config.on_match,
+            pause_evt,
+        }
+    }
+
+    /// Scans `data` written by the guest on the console, and reports the pattern found in it.
+    pub fn scan(&mut self, data: &[u8]) {
+        let Some(index) = self.matcher.scan(data) else {
+            return;
+        };
+        let pattern = &self.patterns[index];
+        super::serial::METRICS.console_pattern_matches.inc();
+        error!(
+            "Console scanner matched pattern {:?} in the guest output, action: {:?}",
+            pattern, self.on_match
+        );
+        EVENTS.publish(VmmEvent::ConsolePatternMatched {
+            pattern: pattern.clone(),
+            action: self.on_match,
+        });
+        if self.on_match == ConsoleMatchAction::Pause {
+            if let Err(err) = self.pause_evt.write(1) {
+                error!("Failed to request the microVM to be paused: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan_all(matcher: &mut PatternMatcher, chunks: &[&[u8]]) -> Vec<usize> {
+        chunks
+            .iter()
+            .filter_map(|chunk| matcher.scan(chunk))
+            .collect()
+    }
+
+    #[test]
+    fn test_single_pattern() {
+        let mut matcher = PatternMatcher::new(&["panic"]);
+        assert_eq!(matcher.scan(b"Kernel panic - not syncing"), Some(0));
+        assert_eq!(matcher.scan(b"all good"), None);
+        // Split across writes, one byte at a time.
+        assert_eq!(scan_all(&mut matcher, &[b"pa", b"n", b"i", b"c!"]), vec![0]);
+        let bytes = b"...panic...".map(|byte| [byte]);
+        let chunks = bytes.iter().map(|byte| &byte[..]).collect::<Vec<_>>();
+        assert_eq!(scan_all(&mut matcher, &chunks), vec![0]);
+        // A pattern which starts inside a partial match.
+        assert_eq!(matcher.scan(b"papanic"), Some(0));
+        assert_eq!(matcher.scan(b"panipanic"), Some(0));
+        assert_eq!(matcher.scan(b"pani"), None);
+        assert_eq!(matcher.scan(b"x"), None);
+        assert_eq!(matcher.scan(b"c"), None);
+    }
+
+    #[test]
+    fn test_multiple_patterns() {
+        let mut matcher = PatternMatcher::new(&["he", "she", "his", "hers"]);
+        assert_eq!(matcher.scan(b"ushers"), Some(1));
+        assert_eq!(matcher.scan(b"hi"), None);
+        assert_eq!(matcher.scan(b"s"), Some(2));
+        // "he" is found through the failure link of "she".
+        let mut matcher = PatternMatcher::new(&["she", "he"]);
+        assert_eq!(matcher.scan(b"sh"), None);
+        assert_eq!(matcher.scan(b"e"), Some(0));
+        let mut matcher = PatternMatcher::new(&["ab", "xaby"]);
+        assert_eq!(matcher.scan(b"xab"), Some(0));
+        assert_eq!(matcher.scan(b"y"), Some(1));
+        // Duplicated patterns report the first one.
+        let mut matcher = PatternMatcher::new(&["oops", "oops"]);
+        assert_eq!(matcher.scan(b"oops"), Some(0));
+    }
+
+    #[test]
+    fn test_against_naive_search() {
+        let patterns = ["aab", "abab", "b", "baa", "aaaa"];
+        let mut matcher = PatternMatcher::new(&patterns);
+        // Deterministic pseudo-random input over a small alphabet, so that partial matches are
+        // frequent.
+        let mut state: u32 = 0x1234_5678;
+        let mut output = Vec::new();
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let byte = [b'a', b'b', b'c'][(state % 3) as usize];
+            output.push(byte);
+            let expected = patterns
+                .iter()
+                .position(|pattern| output.ends_with(pattern.as_bytes()));
+            assert_eq!(matcher.scan(&[byte]), expected, "{:?}", &output);
+        }
+    }
+
+    #[test]
+    fn test_console_scanner() {
+        let pause_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let mut scanner = ConsoleScanner::new(
+            &ConsoleScannerConfig::default(),
+            pause_evt.try_clone().unwrap(),
+        );
+        scanner.scan(b"[    1.234] Kernel panic - not syncing: Attempted to kill init!\n");
+        // Only pause the microVM when requested.
+        pause_evt.read().unwrap_err();
+
+        let config = ConsoleScannerConfig {
+            patterns: Some(vec!["reboot: Power down".to_string()]),
+            on_match: ConsoleMatchAction::Pause,
+        };
+        let mut scanner = ConsoleScanner::new(&config, pause_evt.try_clone().unwrap());
+        scanner.scan(b"Kernel panic - not syncing\n");
+        pause_evt.read().unwrap_err();
+        scanner.scan(b"reboot: Power");
+        pause_evt.read().unwrap_err();
+        scanner.scan(b" down\n");
+        assert_eq!(pause_evt.read().unwrap(), 1);
+    }
+}
@@ -6,6 +6,7 @@
 // found in the THIRD-PARTY file.
 
 //! Implements legacy devices (UART, RTC etc).
+pub mod console_scanner;
 mod i8042;
 #[cfg(target_arch = "aarch64")]
 pub mod rtc_pl031;
@@ -23,7 +24,8 @@ pub use self::i8042::{I8042Device, I8042Error as I8042DeviceError};
 #[cfg(target_arch = "aarch64")]
 pub use self::rtc_pl031::RTCDevice;
 pub use self::serial::{
-    SerialDevice, SerialEventsWrapper, SerialWrapper, IER_RDA_BIT, IER_RDA_OFFSET,
+    SerialDevice, SerialEventsWrapper, SerialPortMetrics, SerialWrapper, IER_RDA_BIT,
+    IER_RDA_OFFSET,
 };
 
 /// Wrapper for implementing the trigger functionality for `EventFd`.
@@ -71,5 +73,8 @@ pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
     #[cfg(target_arch = "aarch64")]
     seq.serialize_entry("rtc", &rtc_pl031::METRICS)?;
     seq.serialize_entry("uart", &serial::METRICS)?;
+    for (port_id, metrics) in serial::PORT_METRICS.read().unwrap().iter() {
+        seq.serialize_entry(&format!("uart_{}", port_id), metrics.as_ref())?;
+    }
     seq.end()
 }
@@ -6,10 +6,13 @@
 // found in the THIRD-PARTY file.
 
 //! Implements a wrapper over an UART serial device.
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::fs::File;
 use std::io;
 use std::io::{Read, Write};
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, RwLock};
 
 use event_manager::{EventOps, Events, MutEventSubscriber};
 use log::{error, warn};
@@ -18,6 +21,7 @@ use utils::epoll::EventSet;
 use vm_superio::serial::{Error as SerialError, SerialEvents};
 use vm_superio::{Serial, Trigger};
 
+use crate::devices::legacy::console_scanner::ConsoleScanner;
 use crate::devices::legacy::EventFdTrigger;
 use crate::logger::{IncMetric, SharedIncMetric};
 
@@ -42,6 +46,8 @@ pub struct SerialDeviceMetrics {
     pub read_count: SharedIncMetric,
     /// Number of succeeded write calls.
     pub write_count: SharedIncMetric,
+    /// Number of patterns found in the output by the console scanner.
+    pub console_pattern_matches: SharedIncMetric,
 }
 impl SerialDeviceMetrics {
     /// Const default construction.
@@ -53,6 +59,7 @@ impl SerialDeviceMetrics {
             missed_write_count: SharedIncMetric::new(),
             read_count: SharedIncMetric::new(),
             write_count: SharedIncMetric::new(),
+            console_pattern_matches: SharedIncMetric::new(),
         }
     }
 }
@@ -60,6 +67,37 @@ impl SerialDeviceMetrics {
 /// Stores aggregated metrics
 pub(super) static METRICS: SerialDeviceMetrics = SerialDeviceMetrics::new();
 
+/// Metrics of the configured serial ports, by id of the port. This should be protected by a lock
+/// before accessing, and since the lock is initialized here it is safe to unwrap it.
+pub(super) static PORT_METRICS: RwLock<BTreeMap<String, Arc<SerialDeviceMetrics>>> =
+    RwLock::new(BTreeMap::new());
+
+/// Metrics of a serial device, counted in the aggregated metrics and, for the configured serial
+/// ports, in the metrics of the port.
+#[derive(Debug, Default, Clone)]
+pub struct SerialPortMetrics(Option<Arc<SerialDeviceMetrics>>);
+
+impl SerialPortMetrics {
+    /// Allocates the metrics of the serial port with id `port_id`, or reuses them if they
+    /// already exist.
+    pub fn alloc(port_id: String) -> Self {
+        Self(Some(Arc::clone(
+            PORT_METRICS
+                .write()
+                .unwrap()
+                .entry(port_id)
+                .or_insert_with(|| Arc::new(SerialDeviceMetrics::new())),
+        )))
+    }
+
+    fn inc(&self, metric: fn(&SerialDeviceMetrics) -> &SharedIncMetric) {
+        metric(&METRICS).inc();
+        if let Some(port_metrics) = self.0.as_deref() {
+            metric(port_metrics).inc();
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum RawIOError {
     /// Serial error: {0:?}
@@ -93,19 +131,21 @@ impl<EV: SerialEvents + Debug, W: Write + Debug> RawIOHandler for Serial<EventFd
 pub struct SerialEventsWrapper {
     /// Buffer ready event.
     pub buffer_ready_event_fd: Option<EventFdTrigger>,
+    /// Metrics of the serial device.
+    pub metrics: SerialPortMetrics,
 }
 
 impl SerialEvents for SerialEventsWrapper {
     fn buffer_read(&self) {
-        METRICS.read_count.inc();
+        self.metrics.inc(|m| &m.read_count);
     }
 
     fn out_byte(&self) {
-        METRICS.write_count.inc();
+        self.metrics.inc(|m| &m.write_count);
     }
 
     fn tx_lost_byte(&self) {
-        METRICS.missed_write_count.inc();
+        self.metrics.inc(|m| &m.missed_write_count);
     }
 
     fn in_buffer_empty(&self) {
@@ -127,27 +167,62 @@ impl SerialEvents for SerialEventsWrapper {
 pub enum SerialOut {
     Sink(std::io::Sink),
     Stdout(std::io::Stdout),
+    File(File),
 }
 impl std::io::Write for SerialOut {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         match self {
             Self::Sink(sink) => sink.write(buf),
             Self::Stdout(stdout) => stdout.write(buf),
+            Self::File(file) => file.write(buf),
         }
     }
     fn flush(&mut self) -> std::io::Result<()> {
         match self {
             Self::Sink(sink) => sink.flush(),
             Self::Stdout(stdout) => stdout.flush(),
+            Self::File(file) => file.flush(),
         }
     }
 }
 
+/// Output of the serial device, scanned by the console scanner when one is attached.
+#[derive(Debug)]
+pub struct ScannedSerialOut {
+    out: SerialOut,
+    /// Scanner looking for patterns in the output.
+    pub scanner: Option<ConsoleScanner>,
+}
+
+impl From<SerialOut> for ScannedSerialOut {
+    fn from(out: SerialOut) -> Self {
+        ScannedSerialOut { out, scanner: None }
+    }
+}
+
+impl std::io::Write for ScannedSerialOut {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let result = self.out.write(buf);
+        if let Some(scanner) = self.scanner.as_mut() {
+            // Bytes which could not be written are lost, but were still output by the guest.
+            match &result {
+                Ok(count) => scanner.scan(&buf[..*count]),
+                Err(err) if err.kind() != io::ErrorKind::Interrupted => scanner.scan(buf),
+                Err(_) => (),
+            }
+        }
+        result
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
 /// Wrapper over the imported serial device.
 #[derive(Debug)]
 pub struct SerialWrapper<T: Trigger, EV: SerialEvents, I: Read + AsRawFd + Send> {
     /// Serial device object.
-    pub serial: Serial<T, EV, SerialOut>,
+    pub serial: Serial<T, EV, ScannedSerialOut>,
     /// Input to the serial device (needs to be readable).
     pub input: Option<I>,
 }
@@ -344,7 +419,7 @@ impl<I: Read + AsRawFd + Send + Debug + 'static>
         if let (Ok(offset), 1) = (u8::try_from(offset), data.len()) {
             data[0] = self.serial.read(offset);
         } else {
-            METRICS.missed_read_count.inc();
+            self.serial.events().metrics.inc(|m| &m.missed_read_count);
         }
     }
 
@@ -353,10 +428,10 @@ impl<I: Read + AsRawFd + Send + Debug + 'static>
             if let Err(err) = self.serial.write(offset, data[0]) {
                 // Counter incremented for any handle_write() error.
                 error!("Failed the write to serial: {:?}", err);
-                METRICS.error_count.inc();
+                self.serial.events().metrics.inc(|m| &m.error_count);
             }
         } else {
-            METRICS.missed_write_count.inc();
+            self.serial.events().metrics.inc(|m| &m.missed_write_count);
         }
     }
 }
@@ -369,6 +444,7 @@ mod tests {
 
     use super::*;
     use crate::logger::IncMetric;
+    use crate::vmm_config::console_scanner::{ConsoleMatchAction, ConsoleScannerConfig};
 
     #[test]
     fn test_serial_bus_read() {
@@ -381,8 +457,9 @@ mod tests {
                 intr_evt,
                 SerialEventsWrapper {
                     buffer_ready_event_fd: None,
+                    metrics: SerialPortMetrics::default(),
                 },
-                SerialOut::Sink(std::io::sink()),
+                SerialOut::Sink(std::io::sink()).into(),
             ),
             input: None::<std::io::Stdin>,
         };
@@ -404,6 +481,41 @@ mod tests {
         assert_eq!(invalid_reads_after_2, invalid_reads_after);
     }
 
+    #[test]
+    fn test_serial_console_scanner() {
+        let intr_evt = EventFdTrigger::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        let pause_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let mut serial = SerialDevice {
+            serial: Serial::with_events(
+                intr_evt,
+                SerialEventsWrapper {
+                    buffer_ready_event_fd: None,
+                    metrics: SerialPortMetrics::default(),
+                },
+                SerialOut::Sink(std::io::sink()).into(),
+            ),
+            input: None::<std::io::Stdin>,
+        };
+        let config = ConsoleScannerConfig {
+            patterns: None,
+            on_match: ConsoleMatchAction::Pause,
+        };
+        serial.serial.writer_mut().scanner =
+            Some(ConsoleScanner::new(&config, pause_evt.try_clone().unwrap()));
+        let matches_before = METRICS.console_pattern_matches.count();
+
+        // The guest driver writes the output one byte at a time.
+        for byte in b"[    0.000000] Linux version 6.1.0\n[    0.512345] Kernel pan" {
+            serial.bus_write(0, &[*byte]);
+        }
+        pause_evt.read().unwrap_err();
+        for byte in b"ic - not syncing: VFS: Unable to mount root fs\n" {
+            serial.bus_write(0, &[*byte]);
+        }
+        assert_eq!(pause_evt.read().unwrap(), 1);
+        assert!(METRICS.console_pattern_matches.count() > matches_before);
+    }
+
     #[test]
     fn test_is_fifo() {
         // invalid file descriptors arent fifos
@@ -435,4 +547,37 @@ mod tests {
         serial_metrics.read_count.inc();
         assert_eq!(serial_metrics.read_count.count(), 1);
     }
+
+    #[test]
+    fn test_serial_port_metrics() {
+        let intr_evt = EventFdTrigger::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        let mut serial = SerialDevice {
+            serial: Serial::with_events(
+                intr_evt,
+                SerialEventsWrapper {
+                    buffer_ready_event_fd: None,
+                    metrics: SerialPortMetrics::alloc("serial-metrics-test".to_string()),
+                },
+                SerialOut::Sink(std::io::sink()).into(),
+            ),
+            input: None::<std::io::Stdin>,
+        };
+        let port_metrics = Arc::clone(
+            PORT_METRICS
+                .read()
+                .unwrap()
+                .get("serial-metrics-test")
+                .unwrap(),
+        );
+        let writes_before = METRICS.write_count.count();
+
+        serial.bus_write(0, &[b'a']);
+        serial.bus_write(0, &[b'b', b'c']);
+        assert_eq!(port_metrics.write_count.count(), 1);
+        assert_eq!(port_metrics.missed_write_count.count(), 1);
+        assert!(METRICS.write_count.count() > writes_before);
+        // Allocating the metrics of the same port again reuses them.
+        let metrics = SerialPortMetrics::alloc("serial-metrics-test".to_string());
+        assert!(Arc::ptr_eq(metrics.0.as_ref().unwrap(), &port_metrics));
+    }
 }
@@ -17,26 +17,47 @@ pub mod pseudo;
 pub mod virtio;
 
 pub use bus::{Bus, BusDevice, BusError};
-use log::error;
+use serde::Serialize;
 
+use crate::devices::virtio::balloon::BalloonError;
 use crate::devices::virtio::net::metrics::NetDeviceMetrics;
 use crate::devices::virtio::queue::QueueError;
 use crate::devices::virtio::vsock::VsockError;
 use crate::logger::IncMetric;
+use crate::rate_limiter::RateLimiterError;
 
-// Function used for reporting error in terms of logging
-// but also in terms of metrics of net event fails.
+// Function used for reporting error in terms of metrics of net event fails, before
+// the error is handed to the event handler, which logs it according to its severity.
 // network metrics is reported per device so we need a handle to each net device's
 // metrics `net_iface_metrics` to report metrics for that device.
-pub(crate) fn report_net_event_fail(net_iface_metrics: &NetDeviceMetrics, err: DeviceError) {
-    error!("{:?}", err);
+pub(crate) fn report_net_event_fail(
+    net_iface_metrics: &NetDeviceMetrics,
+    err: DeviceError,
+) -> DeviceError {
     net_iface_metrics.event_fails.inc();
+    err
+}
+
+/// How badly an error affects the device which encountered it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorSeverity {
+    /// The error only affects the request or event being processed, and the guest recovers
+    /// from it on its own.
+    Transient,
+    /// The device keeps working, but the guest may observe failed requests or lost
+    /// notifications.
+    Degraded,
+    /// The device cannot keep working, and has to be reset by the guest driver.
+    Fatal,
 }
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum DeviceError {
-    /// Failed to read from the TAP device.
-    FailedReadTap,
+    /// Balloon device error: {0}
+    Balloon(BalloonError),
+    /// Failed to read event: {0}
+    EventFd(io::Error),
     /// Failed to signal irq: {0}
     FailedSignalingIrq(io::Error),
     /// IO error: {0}
@@ -47,6 +68,31 @@ pub enum DeviceError {
     MalformedDescriptor,
     /// Error during queue processing: {0}
     QueueError(QueueError),
+    /// Rate limiter error: {0}
+    RateLimiter(RateLimiterError),
     /// Vsock device error: {0}
     VsockError(VsockError),
 }
+
+impl DeviceError {
+    /// Classifies the error by how badly it affects the device.
+    pub fn severity(&self) -> ErrorSeverity {
+        match self {
+            Self::Balloon(err) => err.severity(),
+            // A spurious or lost wakeup: the event is delivered again, or the next one
+            // catches up with the work left behind.
+            Self::EventFd(_) | Self::RateLimiter(_) => ErrorSeverity::Transient,
+            // The guest is not notified of the buffers used by the device, which stalls
+            // the driver until another notification goes through.
+            Self::FailedSignalingIrq(_) => ErrorSeverity::Degraded,
+            // The backend failed the request, which is reported to the guest.
+            Self::IoError(_) => ErrorSeverity::Degraded,
+            // The request is rejected, and the guest moves on to the next one.
+            Self::MalformedPayload | Self::MalformedDescriptor => ErrorSeverity::Transient,
+            // The device and the driver disagree on the state of the queue, so no further
+            // request can be processed safely.
+            Self::QueueError(_) => ErrorSeverity::Fatal,
+            Self::VsockError(err) => err.severity(),
+        }
+    }
+}
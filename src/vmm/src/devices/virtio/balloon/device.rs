@@ -13,6 +13,9 @@ use utils::u64_to_usize;
 use super::super::device::{DeviceState, VirtioDevice};
 use super::super::queue::Queue;
 use super::super::{ActivateError, TYPE_BALLOON};
+use super::hugepages::{
+    release_huge_pages, Fallocate, HostFallocate, HugePageTracker, HUGE_PAGE_SIZE,
+};
 use super::metrics::METRICS;
 use super::util::{compact_page_frame_numbers, remove_range};
 use super::{
@@ -25,10 +28,13 @@ use super::{
     VIRTIO_BALLOON_S_SWAP_OUT,
 };
 use crate::devices::virtio::balloon::BalloonError;
-use crate::devices::virtio::device::{IrqTrigger, IrqType};
+use crate::devices::virtio::device::{pin_virtio_features, IrqTrigger, IrqType};
 use crate::devices::virtio::gen::virtio_blk::VIRTIO_F_VERSION_1;
+use crate::events::{VmmEvent, EVENTS};
 use crate::logger::IncMetric;
-use crate::vstate::memory::{Address, ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
+use crate::vstate::memory::{
+    Address, ByteValued, Bytes, GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion,
+};
 
 const SIZE_OF_U32: usize = std::mem::size_of::<u32>();
 const SIZE_OF_STAT: usize = std::mem::size_of::<BalloonStat>();
@@ -154,6 +160,7 @@ pub struct Balloon {
     // Virtio fields.
     pub(crate) avail_features: u64,
     pub(crate) acked_features: u64,
+    pub(crate) virtio_features_pin: Option<u64>,
     pub(crate) config_space: ConfigSpace,
     pub(crate) activate_evt: EventFd,
 
@@ -162,6 +169,7 @@ pub struct Balloon {
     pub(crate) queue_evts: [EventFd; BALLOON_NUM_QUEUES],
     pub(crate) device_state: DeviceState,
     pub(crate) irq_trigger: IrqTrigger,
+    pub(crate) needs_reset: bool,
 
     // Implementation specific fields.
     pub(crate) restored: bool,
@@ -173,6 +181,11 @@ pub struct Balloon {
     pub(crate) latest_stats: BalloonStats,
     // A buffer used as pfn accumulator during descriptor processing.
     pub(crate) pfn_buffer: [u32; MAX_PAGE_COMPACT_BUFFER],
+    // The pages inflated in the huge pages of hugetlbfs-backed guest memory, until the huge
+    // pages are fully inflated and can be released.
+    pub(crate) hugepage_tracker: HugePageTracker,
+    // Used to release the huge pages of memfd-backed guest memory.
+    pub(crate) fallocate: Box<dyn Fallocate>,
 }
 
 // TODO Use `#[derive(Debug)]` when a new release of
@@ -183,6 +196,7 @@ impl fmt::Debug for Balloon {
         f.debug_struct("Balloon")
             .field("avail_features", &self.avail_features)
             .field("acked_features", &self.acked_features)
+            .field("virtio_features_pin", &self.virtio_features_pin)
             .field("config_space", &self.config_space)
             .field("activate_evt", &self.activate_evt)
             .field("queues", &self.queues)
@@ -194,6 +208,8 @@ impl fmt::Debug for Balloon {
             .field("stats_desc_index", &self.stats_desc_index)
             .field("latest_stats", &self.latest_stats)
             .field("pfn_buffer", &self.pfn_buffer)
+            .field("hugepage_tracker", &self.hugepage_tracker)
+            .field("fallocate", &self.fallocate)
             .finish()
     }
 }
@@ -236,6 +252,7 @@ impl Balloon {
         Ok(Balloon {
             avail_features,
             acked_features: 0u64,
+            virtio_features_pin: None,
             config_space: ConfigSpace {
                 num_pages: mib_to_pages(amount_mib)?,
                 actual_pages: 0,
@@ -244,6 +261,7 @@ impl Balloon {
             queues,
             irq_trigger: IrqTrigger::new().map_err(BalloonError::EventFd)?,
             device_state: DeviceState::Inactive,
+            needs_reset: false,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
             restored,
             stats_polling_interval_s,
@@ -251,9 +269,24 @@ impl Balloon {
             stats_desc_index: None,
             latest_stats: BalloonStats::default(),
             pfn_buffer: [0u32; MAX_PAGE_COMPACT_BUFFER],
+            hugepage_tracker: HugePageTracker::default(),
+            fallocate: Box::new(HostFallocate),
         })
     }
 
+    /// Restricts the virtio features offered to the guest to the ones of `pin`.
+    pub fn pin_virtio_features(&mut self, pin: u64) -> Result<(), BalloonError> {
+        let avail_features = pin_virtio_features(BALLOON_DEV_ID, self.avail_features, pin)
+            .map_err(BalloonError::PinVirtioFeatures)?;
+        // The statistics queue is there for the driver to set up when the statistics are enabled.
+        if self.stats_enabled() && avail_features & (1u64 << VIRTIO_BALLOON_F_STATS_VQ) == 0 {
+            return Err(BalloonError::StatisticsNotPinned);
+        }
+        self.avail_features = avail_features;
+        self.virtio_features_pin = Some(pin);
+        Ok(())
+    }
+
     pub(crate) fn process_inflate_queue_event(&mut self) -> Result<(), BalloonError> {
         self.queue_evts[INFLATE_INDEX]
             .read()
@@ -353,12 +386,23 @@ impl Balloon {
             for (page_frame_number, range_len) in page_ranges {
                 let guest_addr =
                     GuestAddress(u64::from(page_frame_number) << VIRTIO_BALLOON_PFN_SHIFT);
-
-                if let Err(err) = remove_range(
-                    mem,
-                    (guest_addr, u64::from(range_len) << VIRTIO_BALLOON_PFN_SHIFT),
-                    self.restored,
-                ) {
+                let range = (guest_addr, u64::from(range_len) << VIRTIO_BALLOON_PFN_SHIFT);
+
+                // Huge pages can only be given back to the host once fully inflated.
+                let hugetlbfs = mem
+                    .find_region(guest_addr)
+                    .and_then(|region| region.is_hugetlbfs())
+                    .unwrap_or(false);
+                if hugetlbfs {
+                    for huge_range in self.hugepage_tracker.inflate(range) {
+                        match release_huge_pages(mem, huge_range, self.fallocate.as_mut()) {
+                            Ok(()) => METRICS
+                                .hugepages_released
+                                .add(huge_range.1 / HUGE_PAGE_SIZE),
+                            Err(err) => error!("Error releasing huge pages: {:?}", err),
+                        }
+                    }
+                } else if let Err(err) = remove_range(mem, range, self.restored) {
                     error!("Error removing memory range: {:?}", err);
                 }
             }
@@ -380,6 +424,28 @@ impl Balloon {
         let mut needs_interrupt = false;
 
         while let Some(head) = queue.pop(mem) {
+            // The deflated pages are only relevant to the huge pages which are partially
+            // inflated: they must not be released anymore once the rest of their pages are
+            // inflated, as the guest is using these pages again.
+            if !self.hugepage_tracker.is_empty()
+                && !head.is_write_only()
+                && head.len as usize % SIZE_OF_U32 == 0
+            {
+                for index in (0..head.len).step_by(SIZE_OF_U32) {
+                    let addr = head
+                        .addr
+                        .checked_add(u64::from(index))
+                        .ok_or(BalloonError::MalformedDescriptor)?;
+                    let page_frame_number = mem
+                        .read_obj::<u32>(addr)
+                        .map_err(|_| BalloonError::MalformedDescriptor)?;
+                    self.hugepage_tracker.deflate((
+                        GuestAddress(u64::from(page_frame_number) << VIRTIO_BALLOON_PFN_SHIFT),
+                        1 << VIRTIO_BALLOON_PFN_SHIFT,
+                    ));
+                }
+            }
+
             queue
                 .add_used(mem, head.index, 0)
                 .map_err(BalloonError::Queue)?;
@@ -566,6 +632,10 @@ impl VirtioDevice for Balloon {
         self.acked_features = acked_features;
     }
 
+    fn virtio_features_pin(&self) -> Option<u64> {
+        self.virtio_features_pin
+    }
+
     fn device_type(&self) -> u32 {
         TYPE_BALLOON
     }
@@ -596,6 +666,7 @@ impl VirtioDevice for Balloon {
     }
 
     fn write_config(&mut self, offset: u64, data: &[u8]) {
+        let actual_pages = self.config_space.actual_pages;
         let config_space_bytes = self.config_space.as_mut_slice();
         let start = usize::try_from(offset).ok();
         let end = start.and_then(|s| s.checked_add(data.len()));
@@ -608,6 +679,15 @@ impl VirtioDevice for Balloon {
         };
 
         dst.copy_from_slice(data);
+
+        // The guest driver reports the balloon size it reached through `actual_pages`.
+        if self.config_space.actual_pages != actual_pages
+            && self.config_space.actual_pages == self.config_space.num_pages
+        {
+            EVENTS.publish(VmmEvent::BalloonConverged {
+                amount_mib: pages_to_mib(self.config_space.actual_pages),
+            });
+        }
     }
 
     fn activate(&mut self, mem: GuestMemoryMmap) -> Result<(), ActivateError> {
@@ -628,6 +708,10 @@ impl VirtioDevice for Balloon {
     fn is_activated(&self) -> bool {
         self.device_state.is_activated()
     }
+
+    fn needs_reset(&self) -> bool {
+        self.needs_reset
+    }
 }
 
 #[cfg(test)]
@@ -637,6 +721,7 @@ pub(crate) mod tests {
     use super::super::BALLOON_CONFIG_SPACE_SIZE;
     use super::*;
     use crate::check_metric_after_block;
+    use crate::devices::virtio::balloon::hugepages::tests::{hugetlbfs_file_mem, FakeFallocate};
     use crate::devices::virtio::balloon::report_balloon_event_fail;
     use crate::devices::virtio::balloon::test_utils::{
         check_request_completion, invoke_handler_for_queue_event, set_request,
@@ -644,7 +729,7 @@ pub(crate) mod tests {
     use crate::devices::virtio::queue::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
     use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
     use crate::utilities::test_utils::single_region_mem;
-    use crate::vstate::memory::GuestAddress;
+    use crate::vstate::memory::{Bitmap, GuestAddress};
 
     impl Balloon {
         pub(crate) fn set_queue(&mut self, idx: usize, q: Queue) {
@@ -908,7 +993,7 @@ pub(crate) mod tests {
                 1,
                 balloon
                     .process_inflate_queue_event()
-                    .unwrap_or_else(report_balloon_event_fail)
+                    .map_err(report_balloon_event_fail)
             );
             // Verify that nothing got processed.
             assert_eq!(infq.used.idx.get(), 0);
@@ -968,7 +1053,7 @@ pub(crate) mod tests {
                 1,
                 balloon
                     .process_deflate_queue_event()
-                    .unwrap_or_else(report_balloon_event_fail)
+                    .map_err(report_balloon_event_fail)
             );
             // Verify that nothing got processed.
             assert_eq!(defq.used.idx.get(), 0);
@@ -992,6 +1077,86 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_inflate_hugetlbfs() {
+        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let fallocate = FakeFallocate::default();
+        balloon.fallocate = Box::new(fallocate.clone());
+        let mem = hugetlbfs_file_mem(4 * u64_to_usize(HUGE_PAGE_SIZE), true);
+        let infq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let defq = VirtQueue::new(GuestAddress(0x4000), &mem, 16);
+        balloon.set_queue(INFLATE_INDEX, infq.create_queue());
+        balloon.set_queue(DEFLATE_INDEX, defq.create_queue());
+        balloon.activate(mem.clone()).unwrap();
+
+        let pfns_per_huge_page = u32::try_from(HUGE_PAGE_SIZE >> VIRTIO_BALLOON_PFN_SHIFT).unwrap();
+        let pfns_addr = 0x10000;
+        // Writes `pfns` in guest memory, and sends them in the next request of `queue`.
+        let send_pfns = |queue: &VirtQueue, idx: u16, pfns: &[u32]| {
+            for (i, pfn) in pfns.iter().enumerate() {
+                mem.write_obj(*pfn, GuestAddress(pfns_addr + (i * SIZE_OF_U32) as u64))
+                    .unwrap();
+            }
+            let len = u32::try_from(pfns.len() * SIZE_OF_U32).unwrap();
+            set_request(queue, idx, pfns_addr, len, 0);
+        };
+
+        // The first half of the second huge page is not released.
+        let huge_page = pfns_per_huge_page;
+        let half = pfns_per_huge_page / 2;
+        send_pfns(&infq, 0, &(huge_page..huge_page + half).collect::<Vec<_>>());
+        check_metric_after_block!(
+            METRICS.hugepages_released,
+            0,
+            invoke_handler_for_queue_event(&mut balloon, INFLATE_INDEX)
+        );
+        assert!(fallocate.holes.lock().unwrap().is_empty());
+
+        // The second half completes it.
+        send_pfns(
+            &infq,
+            1,
+            &(huge_page + half..huge_page + pfns_per_huge_page).collect::<Vec<_>>(),
+        );
+        check_metric_after_block!(
+            METRICS.hugepages_released,
+            1,
+            invoke_handler_for_queue_event(&mut balloon, INFLATE_INDEX)
+        );
+        assert_eq!(
+            *fallocate.holes.lock().unwrap(),
+            vec![(HUGE_PAGE_SIZE, HUGE_PAGE_SIZE)]
+        );
+
+        // Releasing huge pages does not dirty them: diff snapshots skip them, like the 4K pages
+        // removed with madvise.
+        let region = mem.find_region(GuestAddress(0)).unwrap();
+        assert!(region.bitmap().dirty_at(u64_to_usize(pfns_addr)));
+        for offset in (HUGE_PAGE_SIZE..2 * HUGE_PAGE_SIZE).step_by(1 << VIRTIO_BALLOON_PFN_SHIFT) {
+            assert!(!region.bitmap().dirty_at(u64_to_usize(offset)));
+        }
+
+        // A page of the third huge page is deflated before the huge page is fully inflated:
+        // the huge page is not released.
+        let huge_page = 2 * pfns_per_huge_page;
+        send_pfns(&infq, 2, &(huge_page..huge_page + half).collect::<Vec<_>>());
+        invoke_handler_for_queue_event(&mut balloon, INFLATE_INDEX);
+        send_pfns(&defq, 0, &[huge_page]);
+        invoke_handler_for_queue_event(&mut balloon, DEFLATE_INDEX);
+        send_pfns(
+            &infq,
+            3,
+            &(huge_page + half..huge_page + pfns_per_huge_page).collect::<Vec<_>>(),
+        );
+        check_metric_after_block!(
+            METRICS.hugepages_released,
+            0,
+            invoke_handler_for_queue_event(&mut balloon, INFLATE_INDEX)
+        );
+        assert_eq!(fallocate.holes.lock().unwrap().len(), 1);
+        assert!(!balloon.hugepage_tracker.is_empty());
+    }
+
     #[test]
     fn test_stats() {
         let mut balloon = Balloon::new(0, true, 1, false).unwrap();
@@ -1016,7 +1181,7 @@ pub(crate) mod tests {
                 1,
                 balloon
                     .process_stats_queue_event()
-                    .unwrap_or_else(report_balloon_event_fail)
+                    .map_err(report_balloon_event_fail)
             );
             // Verify that nothing got processed.
             assert_eq!(statsq.used.idx.get(), 0);
@@ -6,7 +6,7 @@ use utils::epoll::EventSet;
 
 use super::{report_balloon_event_fail, DEFLATE_INDEX, INFLATE_INDEX, STATS_INDEX};
 use crate::devices::virtio::balloon::device::Balloon;
-use crate::devices::virtio::device::VirtioDevice;
+use crate::devices::virtio::device::{DeviceErrorHandler, VirtioDevice};
 use crate::logger::{error, warn};
 
 impl Balloon {
@@ -74,6 +74,45 @@ impl Balloon {
     }
 }
 
+impl DeviceErrorHandler for Balloon {
+    fn set_needs_reset(&mut self) {
+        self.needs_reset = true;
+    }
+
+    fn unregister_runtime_events(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.remove(Events::with_data(
+            &self.queue_evts[INFLATE_INDEX],
+            Self::PROCESS_VIRTQ_INFLATE,
+            EventSet::IN,
+        )) {
+            error!("Failed to un-register inflate queue event: {}", err);
+        }
+        if let Err(err) = ops.remove(Events::with_data(
+            &self.queue_evts[DEFLATE_INDEX],
+            Self::PROCESS_VIRTQ_DEFLATE,
+            EventSet::IN,
+        )) {
+            error!("Failed to un-register deflate queue event: {}", err);
+        }
+        if self.stats_enabled() {
+            if let Err(err) = ops.remove(Events::with_data(
+                &self.queue_evts[STATS_INDEX],
+                Self::PROCESS_VIRTQ_STATS,
+                EventSet::IN,
+            )) {
+                error!("Failed to un-register stats queue event: {}", err);
+            }
+            if let Err(err) = ops.remove(Events::with_data(
+                &self.stats_timer,
+                Self::PROCESS_STATS_TIMER,
+                EventSet::IN,
+            )) {
+                error!("Failed to un-register stats timerfd event: {}", err);
+            }
+        }
+    }
+}
+
 impl MutEventSubscriber for Balloon {
     fn process(&mut self, event: Events, ops: &mut EventOps) {
         let source = event.data();
@@ -89,24 +128,23 @@ impl MutEventSubscriber for Balloon {
         }
 
         if self.is_activated() {
-            match source {
-                Self::PROCESS_ACTIVATE => self.process_activate_event(ops),
-                Self::PROCESS_VIRTQ_INFLATE => self
-                    .process_inflate_queue_event()
-                    .unwrap_or_else(report_balloon_event_fail),
-                Self::PROCESS_VIRTQ_DEFLATE => self
-                    .process_deflate_queue_event()
-                    .unwrap_or_else(report_balloon_event_fail),
-                Self::PROCESS_VIRTQ_STATS => self
-                    .process_stats_queue_event()
-                    .unwrap_or_else(report_balloon_event_fail),
-                Self::PROCESS_STATS_TIMER => self
-                    .process_stats_timer_event()
-                    .unwrap_or_else(report_balloon_event_fail),
+            let result = match source {
+                Self::PROCESS_ACTIVATE => {
+                    self.process_activate_event(ops);
+                    Ok(())
+                }
+                Self::PROCESS_VIRTQ_INFLATE => self.process_inflate_queue_event(),
+                Self::PROCESS_VIRTQ_DEFLATE => self.process_deflate_queue_event(),
+                Self::PROCESS_VIRTQ_STATS => self.process_stats_queue_event(),
+                Self::PROCESS_STATS_TIMER => self.process_stats_timer_event(),
                 _ => {
                     warn!("Balloon: Spurious event received: {:?}", source);
+                    Ok(())
                 }
             };
+            if let Err(err) = result {
+                self.handle_error(ops, report_balloon_event_fail(err));
+            }
         } else {
             warn!(
                 "Balloon: The device is not yet activated. Spurious event received: {:?}",
@@ -136,7 +174,11 @@ pub mod tests {
 
     use super::*;
     use crate::devices::virtio::balloon::test_utils::set_request;
+    use crate::devices::virtio::balloon::{BalloonError, RemoveRegionError};
+    use crate::devices::virtio::queue::QueueError;
+    use crate::devices::virtio::test_utils::test::check_error_handling;
     use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
+    use crate::devices::DeviceError;
     use crate::vstate::memory::GuestAddress;
 
     #[test]
@@ -189,4 +231,24 @@ pub mod tests {
         // Make sure the data queue advanced.
         assert_eq!(infq.used.idx.get(), 1);
     }
+
+    #[test]
+    fn test_error_handling() {
+        let mut balloon = Balloon::new(0, true, 10, false).unwrap();
+        let mem = default_mem();
+        let infq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        balloon.set_queue(INFLATE_INDEX, infq.create_queue());
+        balloon.activate(mem.clone()).unwrap();
+
+        check_error_handling(
+            Arc::new(Mutex::new(balloon)),
+            [
+                DeviceError::Balloon(BalloonError::MalformedDescriptor),
+                DeviceError::Balloon(BalloonError::RemoveMemoryRegion(
+                    RemoveRegionError::RegionNotFound,
+                )),
+                DeviceError::Balloon(BalloonError::Queue(QueueError::DescIndexOutOfBounds(16))),
+            ],
+        );
+    }
 }
@@ -0,0 +1,391 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Gives back to the host the huge pages of hugetlbfs-backed guest memory which are inflated
+//! into the balloon.
+//!
+//! The balloon driver reports the inflated memory in 4K pages, while the host can only free
+//! hugetlbfs memory a whole huge page at a time: `madvise(MADV_DONTNEED)` cannot discard part
+//! of a huge page. The 4K pages inflated in a huge page are thus tracked until the whole
+//! huge page is inflated, at which point its backing is released, by punching a hole in the
+//! memfd backing guest memory. Deflating the balloon does not need to reverse anything, as
+//! the released huge pages are faulted back in when the guest touches them again.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use utils::u64_to_usize;
+
+use super::util::remove_range;
+use super::{RemoveRegionError, VIRTIO_BALLOON_PFN_SHIFT};
+use crate::vstate::memory::{GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion};
+
+/// Size of the huge pages backing hugetlbfs guest memory. Firecracker only supports 2M huge
+/// pages.
+pub(crate) const HUGE_PAGE_SIZE: u64 = 2 << 20;
+
+// Number of balloon pages in a huge page.
+const BALLOON_PAGES_PER_HUGE_PAGE: u64 = HUGE_PAGE_SIZE >> VIRTIO_BALLOON_PFN_SHIFT;
+// Number of words of the bitmap of the inflated balloon pages of a huge page.
+const HUGE_PAGE_BITMAP_LEN: usize = u64_to_usize(BALLOON_PAGES_PER_HUGE_PAGE / 64);
+
+/// Deallocates ranges of the files backing guest memory.
+pub(crate) trait Fallocate: Debug + Send {
+    /// Deallocates the `len` bytes at `offset` in the file `fd`, without changing its size.
+    fn punch_hole(&mut self, fd: RawFd, offset: u64, len: u64) -> io::Result<()>;
+}
+
+/// [`Fallocate`] implementation calling `fallocate(2)`.
+#[derive(Debug, Default)]
+pub(crate) struct HostFallocate;
+
+impl Fallocate for HostFallocate {
+    fn punch_hole(&mut self, fd: RawFd, offset: u64, len: u64) -> io::Result<()> {
+        let invalid_input = |_| io::Error::from_raw_os_error(libc::EINVAL);
+        let offset = libc::off_t::try_from(offset).map_err(invalid_input)?;
+        let len = libc::off_t::try_from(len).map_err(invalid_input)?;
+        // SAFETY: fallocate only operates on the file `fd`, and does not touch our memory.
+        let ret = unsafe {
+            libc::fallocate(
+                fd,
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                offset,
+                len,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the balloon pages inflated in the huge pages of guest memory which are not fully
+/// inflated yet.
+///
+/// The tracked pages are not saved in snapshots: after a restore, the huge pages which were
+/// partially inflated when the snapshot was taken are only released once the guest inflates
+/// their remaining pages again.
+#[derive(Debug, Default)]
+pub(crate) struct HugePageTracker {
+    // Bitmaps of the inflated balloon pages of the partially inflated huge pages, keyed by the
+    // guest address of the huge page. Bit `i` is set if the `i`th balloon page of the huge page
+    // is inflated.
+    partial: BTreeMap<u64, [u64; HUGE_PAGE_BITMAP_LEN]>,
+}
+
+impl HugePageTracker {
+    /// Returns `true` if no huge page is partially inflated.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.partial.is_empty()
+    }
+
+    /// Records the inflation of the guest memory `range`, whose bounds are aligned to the
+    /// balloon page size. Returns the ranges of the huge pages which are now fully inflated,
+    /// with adjacent huge pages coalesced into a single range.
+    pub(crate) fn inflate(&mut self, range: (GuestAddress, u64)) -> Vec<(GuestAddress, u64)> {
+        let mut inflated: Vec<(GuestAddress, u64)> = Vec::new();
+
+        for (huge_page, start, end) in Self::split(range) {
+            // A range covering a whole huge page does not need to go through its bitmap.
+            let complete = if end - start == HUGE_PAGE_SIZE {
+                self.partial.remove(&huge_page);
+                true
+            } else {
+                let bitmap = self.partial.entry(huge_page).or_default();
+                for page in Self::balloon_pages(huge_page, start, end) {
+                    bitmap[page / 64] |= 1 << (page % 64);
+                }
+                let complete = bitmap.iter().all(|word| *word == u64::MAX);
+                if complete {
+                    self.partial.remove(&huge_page);
+                }
+                complete
+            };
+
+            if complete {
+                match inflated.last_mut() {
+                    Some((addr, len)) if addr.0 + *len == huge_page => {
+                        *len += HUGE_PAGE_SIZE;
+                    }
+                    _ => inflated.push((GuestAddress(huge_page), HUGE_PAGE_SIZE)),
+                }
+            }
+        }
+
+        inflated
+    }
+
+    /// Records the deflation of the guest memory `range`, whose bounds are aligned to the
+    /// balloon page size, so that the huge pages it covers are not released when the rest of
+    /// their balloon pages get inflated.
+    pub(crate) fn deflate(&mut self, range: (GuestAddress, u64)) {
+        for (huge_page, start, end) in Self::split(range) {
+            if let Some(bitmap) = self.partial.get_mut(&huge_page) {
+                for page in Self::balloon_pages(huge_page, start, end) {
+                    bitmap[page / 64] &= !(1 << (page % 64));
+                }
+                if bitmap.iter().all(|word| *word == 0) {
+                    self.partial.remove(&huge_page);
+                }
+            }
+        }
+    }
+
+    // Splits `range` at huge page boundaries, into (huge page, start, end) guest addresses.
+    fn split(range: (GuestAddress, u64)) -> impl Iterator<Item = (u64, u64, u64)> {
+        let (GuestAddress(start), len) = range;
+        let end = start.saturating_add(len);
+        let first_huge_page = start & !(HUGE_PAGE_SIZE - 1);
+
+        (first_huge_page..end)
+            .step_by(u64_to_usize(HUGE_PAGE_SIZE))
+            .map(move |huge_page| {
+                (
+                    huge_page,
+                    start.max(huge_page),
+                    end.min(huge_page + HUGE_PAGE_SIZE),
+                )
+            })
+    }
+
+    // Indexes, in their huge page, of the balloon pages between the `start` and `end` guest
+    // addresses of `huge_page`.
+    fn balloon_pages(huge_page: u64, start: u64, end: u64) -> std::ops::Range<usize> {
+        u64_to_usize((start - huge_page) >> VIRTIO_BALLOON_PFN_SHIFT)
+            ..u64_to_usize((end - huge_page) >> VIRTIO_BALLOON_PFN_SHIFT)
+    }
+}
+
+/// Releases the host memory backing the huge pages of guest memory `range`, which is aligned
+/// to the huge page size.
+///
+/// Punches a hole in the file backing the range if there is one. Otherwise, the range is
+/// anonymous hugetlbfs memory (e.g. guest memory restored from a snapshot through UFFD),
+/// which `madvise(MADV_DONTNEED)` can free since the whole huge pages are covered.
+pub(crate) fn release_huge_pages(
+    guest_memory: &GuestMemoryMmap,
+    range: (GuestAddress, u64),
+    fallocate: &mut dyn Fallocate,
+) -> Result<(), RemoveRegionError> {
+    let (guest_address, range_len) = range;
+    let region = guest_memory
+        .find_region(guest_address)
+        .ok_or(RemoveRegionError::RegionNotFound)?;
+    if guest_address.0 + range_len > region.start_addr().0 + region.len() {
+        return Err(RemoveRegionError::MalformedRange);
+    }
+
+    match region.file_offset() {
+        Some(file_offset) => {
+            let offset = file_offset.start() + (guest_address.0 - region.start_addr().0);
+            fallocate
+                .punch_hole(file_offset.file().as_raw_fd(), offset, range_len)
+                .map_err(RemoveRegionError::FallocateFail)
+        }
+        None => remove_range(guest_memory, range, false),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use utils::tempfile::TempFile;
+
+    use super::*;
+    use crate::utilities::test_utils::single_region_mem;
+    use crate::vmm_config::machine_config::HugePageConfig;
+    use crate::vstate::memory::{Bytes, FileOffset, GuestMemoryExtension};
+
+    const PAGE_SIZE: u64 = 1 << VIRTIO_BALLOON_PFN_SHIFT;
+
+    /// [`Fallocate`] implementation recording the holes it is asked to punch.
+    #[derive(Debug, Default, Clone)]
+    pub(crate) struct FakeFallocate {
+        pub(crate) holes: Arc<Mutex<Vec<(u64, u64)>>>,
+    }
+
+    impl Fallocate for FakeFallocate {
+        fn punch_hole(&mut self, _fd: RawFd, offset: u64, len: u64) -> io::Result<()> {
+            self.holes.lock().unwrap().push((offset, len));
+            Ok(())
+        }
+    }
+
+    /// Creates guest memory of `size` bytes backed by a (regular) file, and marked as backed
+    /// by hugetlbfs.
+    pub(crate) fn hugetlbfs_file_mem(size: usize, track_dirty_pages: bool) -> GuestMemoryMmap {
+        let file = TempFile::new().unwrap().into_file();
+        file.set_len(size as u64).unwrap();
+        GuestMemoryMmap::from_raw_regions_file(
+            vec![(FileOffset::new(file, 0), GuestAddress(0), size)],
+            track_dirty_pages,
+            true,
+            HugePageConfig::Hugetlbfs2M,
+        )
+        .unwrap()
+    }
+
+    fn range(start: u64, len: u64) -> (GuestAddress, u64) {
+        (GuestAddress(start), len)
+    }
+
+    #[test]
+    fn test_inflate_whole_huge_pages() {
+        let mut tracker = HugePageTracker::default();
+
+        // Aligned huge pages are released right away, and coalesced.
+        assert_eq!(
+            tracker.inflate(range(HUGE_PAGE_SIZE, 3 * HUGE_PAGE_SIZE)),
+            vec![range(HUGE_PAGE_SIZE, 3 * HUGE_PAGE_SIZE)]
+        );
+        assert!(tracker.is_empty());
+
+        // The sub-huge page head and tail of an unaligned range are carried.
+        assert_eq!(
+            tracker.inflate(range(
+                HUGE_PAGE_SIZE - PAGE_SIZE,
+                2 * HUGE_PAGE_SIZE + 2 * PAGE_SIZE
+            )),
+            vec![range(HUGE_PAGE_SIZE, 2 * HUGE_PAGE_SIZE)]
+        );
+        assert_eq!(tracker.partial.len(), 2);
+        assert!(tracker.partial.contains_key(&0));
+        assert!(tracker.partial.contains_key(&(3 * HUGE_PAGE_SIZE)));
+    }
+
+    #[test]
+    fn test_inflate_coalesces_remainders() {
+        let mut tracker = HugePageTracker::default();
+
+        // Inflate the first huge page one balloon page at a time, in reverse order.
+        for page in (1..BALLOON_PAGES_PER_HUGE_PAGE).rev() {
+            assert!(tracker
+                .inflate(range(page * PAGE_SIZE, PAGE_SIZE))
+                .is_empty());
+        }
+        // Inflating the same page twice does not complete the huge page.
+        assert!(tracker.inflate(range(PAGE_SIZE, PAGE_SIZE)).is_empty());
+        assert_eq!(tracker.partial.len(), 1);
+
+        // Inflating the last page releases the huge page.
+        assert_eq!(
+            tracker.inflate(range(0, PAGE_SIZE)),
+            vec![range(0, HUGE_PAGE_SIZE)]
+        );
+        assert!(tracker.is_empty());
+
+        // Two halves of a huge page, inflated separately.
+        assert!(tracker
+            .inflate(range(HUGE_PAGE_SIZE, HUGE_PAGE_SIZE / 2))
+            .is_empty());
+        // The second half completes the second huge page, which is coalesced with the whole
+        // third huge page inflated along with it.
+        assert_eq!(
+            tracker.inflate(range(
+                HUGE_PAGE_SIZE + HUGE_PAGE_SIZE / 2,
+                HUGE_PAGE_SIZE + HUGE_PAGE_SIZE / 2
+            )),
+            vec![range(HUGE_PAGE_SIZE, 2 * HUGE_PAGE_SIZE)]
+        );
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_deflate() {
+        let mut tracker = HugePageTracker::default();
+
+        // Deflating pages which are not tracked is a no-op.
+        tracker.deflate(range(0, HUGE_PAGE_SIZE));
+        assert!(tracker.is_empty());
+
+        assert!(tracker
+            .inflate(range(0, HUGE_PAGE_SIZE - PAGE_SIZE))
+            .is_empty());
+        // The guest takes a page back: the huge page must not be released when its last
+        // page gets inflated.
+        tracker.deflate(range(PAGE_SIZE, PAGE_SIZE));
+        assert!(tracker
+            .inflate(range(HUGE_PAGE_SIZE - PAGE_SIZE, PAGE_SIZE))
+            .is_empty());
+        // Until the deflated page is inflated again.
+        assert_eq!(
+            tracker.inflate(range(PAGE_SIZE, PAGE_SIZE)),
+            vec![range(0, HUGE_PAGE_SIZE)]
+        );
+
+        // Huge pages whose pages are all deflated are not tracked anymore.
+        assert!(tracker.inflate(range(0, 2 * PAGE_SIZE)).is_empty());
+        tracker.deflate(range(0, HUGE_PAGE_SIZE));
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_release_huge_pages() {
+        let mut fallocate = FakeFallocate::default();
+        let mem = hugetlbfs_file_mem(4 * HUGE_PAGE_SIZE as usize, false);
+
+        release_huge_pages(
+            &mem,
+            range(HUGE_PAGE_SIZE, 2 * HUGE_PAGE_SIZE),
+            &mut fallocate,
+        )
+        .unwrap();
+        assert_eq!(
+            *fallocate.holes.lock().unwrap(),
+            vec![(HUGE_PAGE_SIZE, 2 * HUGE_PAGE_SIZE)]
+        );
+
+        assert!(matches!(
+            release_huge_pages(
+                &mem,
+                range(3 * HUGE_PAGE_SIZE, 2 * HUGE_PAGE_SIZE),
+                &mut fallocate
+            ),
+            Err(RemoveRegionError::MalformedRange)
+        ));
+        assert!(matches!(
+            release_huge_pages(
+                &mem,
+                range(4 * HUGE_PAGE_SIZE, HUGE_PAGE_SIZE),
+                &mut fallocate
+            ),
+            Err(RemoveRegionError::RegionNotFound)
+        ));
+        assert_eq!(fallocate.holes.lock().unwrap().len(), 1);
+
+        // Anonymous memory is released through madvise.
+        let mem = single_region_mem(2 * HUGE_PAGE_SIZE as usize);
+        mem.write_obj(1u8, GuestAddress(HUGE_PAGE_SIZE)).unwrap();
+        release_huge_pages(&mem, range(HUGE_PAGE_SIZE, HUGE_PAGE_SIZE), &mut fallocate).unwrap();
+        assert_eq!(mem.read_obj::<u8>(GuestAddress(HUGE_PAGE_SIZE)).unwrap(), 0);
+        assert_eq!(fallocate.holes.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_host_fallocate() {
+        let file = TempFile::new().unwrap().into_file();
+        file.set_len(2 * PAGE_SIZE).unwrap();
+        let mem = GuestMemoryMmap::from_raw_regions_file(
+            vec![(
+                FileOffset::new(file, 0),
+                GuestAddress(0),
+                2 * PAGE_SIZE as usize,
+            )],
+            false,
+            true,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        mem.write_obj(1u8, GuestAddress(0)).unwrap();
+        mem.write_obj(1u8, GuestAddress(PAGE_SIZE)).unwrap();
+
+        // The hole is visible through the shared mapping of the file.
+        release_huge_pages(&mem, range(0, PAGE_SIZE), &mut HostFallocate).unwrap();
+        assert_eq!(mem.read_obj::<u8>(GuestAddress(0)).unwrap(), 0);
+        assert_eq!(mem.read_obj::<u8>(GuestAddress(PAGE_SIZE)).unwrap(), 1);
+    }
+}
@@ -61,6 +61,8 @@ pub(super) struct BalloonDeviceMetrics {
     pub stats_update_fails: SharedIncMetric,
     /// Number of balloon device deflations.
     pub deflate_count: SharedIncMetric,
+    /// Number of huge pages of hugetlbfs-backed guest memory released to the host by inflations.
+    pub hugepages_released: SharedIncMetric,
     /// Number of times when handling events on a balloon device failed.
     pub event_fails: SharedIncMetric,
 }
@@ -73,6 +75,7 @@ impl BalloonDeviceMetrics {
             stats_updates_count: SharedIncMetric::new(),
             stats_update_fails: SharedIncMetric::new(),
             deflate_count: SharedIncMetric::new(),
+            hugepages_released: SharedIncMetric::new(),
             event_fails: SharedIncMetric::new(),
         }
     }
@@ -5,18 +5,19 @@
 
 pub mod device;
 mod event_handler;
+mod hugepages;
 pub mod metrics;
 pub mod persist;
 pub mod test_utils;
 mod util;
 
-use log::error;
 use vm_memory::GuestMemoryError;
 
 pub use self::device::{Balloon, BalloonConfig, BalloonStats};
 use super::queue::QueueError;
 use crate::devices::virtio::balloon::metrics::METRICS;
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
+use crate::devices::{DeviceError, ErrorSeverity};
 use crate::logger::IncMetric;
 
 /// Device ID used in MMIO device identification.
@@ -80,6 +81,8 @@ pub enum BalloonError {
     GuestMemory(GuestMemoryError),
     /// Received error while sending an interrupt: {0}
     InterruptError(std::io::Error),
+    /// Cannot pin the virtio features: {0}
+    PinVirtioFeatures(super::device::VirtioFeaturesPinError),
     /// Guest gave us a malformed descriptor.
     MalformedDescriptor,
     /// Guest gave us a malformed payload.
@@ -90,6 +93,8 @@ pub enum BalloonError {
     StatisticsDisabled,
     /// Statistics cannot be enabled/disabled after activation.
     StatisticsStateChange,
+    /// Statistics are enabled, but the statistics queue feature is not pinned.
+    StatisticsNotPinned,
     /// Amount of pages requested cannot fit in `u32`.
     TooManyPagesRequested,
     /// Error while processing the virt queues: {0}
@@ -106,6 +111,8 @@ pub enum RemoveRegionError {
     AddressTranslation,
     /// Malformed guest address range.
     MalformedRange,
+    /// Error calling fallocate: {0}
+    FallocateFail(std::io::Error),
     /// Error calling madvise: {0}
     MadviseFail(std::io::Error),
     /// Error calling mmap: {0}
@@ -114,7 +121,39 @@ pub enum RemoveRegionError {
     RegionNotFound,
 }
 
-pub(super) fn report_balloon_event_fail(err: BalloonError) {
-    error!("{:?}", err);
+impl BalloonError {
+    /// Classifies the error by how badly it affects the balloon device.
+    pub fn severity(&self) -> ErrorSeverity {
+        match self {
+            // Lost queue or timer wakeups: the next event catches up with the work left
+            // behind.
+            Self::EventFd(_) | Self::Timer(_) => ErrorSeverity::Transient,
+            // The buffer is given back to the guest and the next one is processed.
+            Self::MalformedDescriptor | Self::MalformedPayload | Self::GuestMemory(_) => {
+                ErrorSeverity::Transient
+            }
+            // The pages stay resident in the host, or the guest is not notified of the
+            // buffers used, but the balloon keeps working.
+            Self::RemoveMemoryRegion(_) | Self::InterruptError(_) => ErrorSeverity::Degraded,
+            // The device and the driver disagree on the state of the queue, so no further
+            // request can be processed safely.
+            Self::Queue(_) => ErrorSeverity::Fatal,
+            // Errors of the configuration of the device, which are never returned when
+            // processing its events.
+            Self::Activate(_)
+            | Self::DeviceNotFound
+            | Self::DeviceNotActive
+            | Self::PinVirtioFeatures(_)
+            | Self::QueueRestoreError
+            | Self::StatisticsDisabled
+            | Self::StatisticsStateChange
+            | Self::StatisticsNotPinned
+            | Self::TooManyPagesRequested => ErrorSeverity::Degraded,
+        }
+    }
+}
+
+pub(super) fn report_balloon_event_fail(err: BalloonError) -> DeviceError {
     METRICS.event_fails.inc();
+    DeviceError::Balloon(err)
 }
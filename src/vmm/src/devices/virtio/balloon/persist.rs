@@ -142,6 +142,7 @@ impl Persist<'_> for Balloon {
             Arc::new(AtomicU32::new(state.virtio_state.interrupt_status));
         balloon.avail_features = state.virtio_state.avail_features;
         balloon.acked_features = state.virtio_state.acked_features;
+        balloon.virtio_features_pin = state.virtio_state.virtio_features_pin;
         balloon.latest_stats = state.latest_stats.create_stats();
         balloon.config_space = ConfigSpace {
             num_pages: state.config_space.num_pages,
@@ -1,15 +1,19 @@
 // Copyright 2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::time::Duration;
+
 use event_manager::{EventOps, Events, MutEventSubscriber};
 use utils::eventfd::EventFd;
 
 use super::persist::{BlockConstructorArgs, BlockState};
 use super::vhost_user::device::{VhostUserBlock, VhostUserBlockConfig};
+use super::vhost_user::VhostUserBlockError;
 use super::virtio::device::{VirtioBlock, VirtioBlockConfig};
 use super::BlockError;
 use crate::devices::virtio::device::{IrqTrigger, VirtioDevice};
 use crate::devices::virtio::queue::Queue;
+use crate::devices::virtio::vhost_user::VhostUserNegotiation;
 use crate::devices::virtio::{ActivateError, TYPE_BLOCK};
 use crate::rate_limiter::BucketUpdate;
 use crate::snapshot::Persist;
@@ -40,10 +44,12 @@ impl Block {
     }
 
     pub fn config(&self) -> BlockDeviceConfig {
-        match self {
+        let mut config: BlockDeviceConfig = match self {
             Self::Virtio(b) => b.config().into(),
             Self::VhostUser(b) => b.config().into(),
-        }
+        };
+        config.negotiated_virtio_features = self.negotiated_features();
+        config
     }
 
     pub fn update_disk_image(&mut self, disk_image_path: String) -> Result<(), BlockError> {
@@ -55,6 +61,24 @@ impl Block {
         }
     }
 
+    pub fn update_read_only(&mut self, read_only: bool) -> Result<(), BlockError> {
+        match self {
+            Self::Virtio(b) => b
+                .update_read_only(read_only)
+                .map_err(BlockError::VirtioBackend),
+            Self::VhostUser(_) => Err(BlockError::InvalidBlockBackend),
+        }
+    }
+
+    pub fn retry_pending_requests(&mut self) -> Result<(), BlockError> {
+        match self {
+            Self::Virtio(b) => b
+                .retry_pending_requests()
+                .map_err(BlockError::VirtioBackend),
+            Self::VhostUser(_) => Err(BlockError::InvalidBlockBackend),
+        }
+    }
+
     pub fn update_rate_limiter(
         &mut self,
         bytes: BucketUpdate,
@@ -76,6 +100,23 @@ impl Block {
         }
     }
 
+    pub fn vhost_user_negotiation(&self) -> Result<VhostUserNegotiation, BlockError> {
+        match self {
+            Self::Virtio(_) => Err(BlockError::InvalidBlockBackend),
+            Self::VhostUser(b) => Ok(b.vu_handle.negotiation.clone()),
+        }
+    }
+
+    pub fn ping_vhost_user_backend(&self, timeout: Duration) -> Result<Duration, BlockError> {
+        match self {
+            Self::Virtio(_) => Err(BlockError::InvalidBlockBackend),
+            Self::VhostUser(b) => b
+                .vu_handle
+                .ping(timeout)
+                .map_err(|err| BlockError::VhostUserBackend(VhostUserBlockError::VhostUser(err))),
+        }
+    }
+
     pub fn prepare_save(&mut self) {
         match self {
             Self::Virtio(b) => b.prepare_save(),
@@ -83,6 +124,16 @@ impl Block {
         }
     }
 
+    /// Stops the device before it is unplugged from the microVM.
+    pub fn quiesce(&mut self) {
+        match self {
+            Self::Virtio(b) => b.quiesce(),
+            // The backend stops processing the queues once the device is dropped, which closes
+            // its socket.
+            Self::VhostUser(_) => {}
+        }
+    }
+
     pub fn process_virtio_queues(&mut self) {
         match self {
             Self::Virtio(b) => b.process_virtio_queues(),
@@ -118,6 +169,14 @@ impl Block {
         }
     }
 
+    /// Whether requests are kept pending since the host file system ran out of space.
+    pub fn is_out_of_space(&self) -> bool {
+        match self {
+            Self::Virtio(b) => b.is_out_of_space(),
+            Self::VhostUser(_) => false,
+        }
+    }
+
     pub fn is_vhost_user(&self) -> bool {
         match self {
             Self::Virtio(_) => false,
@@ -148,6 +207,13 @@ impl VirtioDevice for Block {
         }
     }
 
+    fn virtio_features_pin(&self) -> Option<u64> {
+        match self {
+            Self::Virtio(b) => b.virtio_features_pin,
+            Self::VhostUser(b) => b.virtio_features_pin,
+        }
+    }
+
     fn device_type(&self) -> u32 {
         TYPE_BLOCK
     }
@@ -207,6 +273,13 @@ impl VirtioDevice for Block {
             Self::VhostUser(b) => b.device_state.is_activated(),
         }
     }
+
+    fn needs_reset(&self) -> bool {
+        match self {
+            Self::Virtio(b) => b.needs_reset,
+            Self::VhostUser(b) => b.needs_reset(),
+        }
+    }
 }
 
 impl MutEventSubscriber for Block {
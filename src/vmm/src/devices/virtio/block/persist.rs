@@ -14,6 +14,24 @@ pub enum BlockState {
     VhostUser(VhostUserBlockState),
 }
 
+impl BlockState {
+    /// Returns whether the device is the root device of the guest.
+    pub fn is_root_device(&self) -> bool {
+        match self {
+            BlockState::Virtio(state) => state.root_device,
+            BlockState::VhostUser(state) => state.root_device,
+        }
+    }
+
+    /// Returns the path of the backing file, or `None` for vhost-user devices.
+    pub fn disk_path(&self) -> Option<&str> {
+        match self {
+            BlockState::Virtio(state) => Some(&state.disk_path),
+            BlockState::VhostUser(_) => None,
+        }
+    }
+}
+
 /// Auxiliary structure for creating a device when resuming from a snapshot.
 #[derive(Debug)]
 pub struct BlockConstructorArgs {
@@ -14,7 +14,9 @@ use vhost::vhost_user::Frontend;
 
 use super::{VhostUserBlockError, NUM_QUEUES, QUEUE_SIZE};
 use crate::devices::virtio::block::CacheType;
-use crate::devices::virtio::device::{DeviceState, IrqTrigger, IrqType, VirtioDevice};
+use crate::devices::virtio::device::{
+    pin_virtio_features, DeviceState, IrqTrigger, IrqType, VirtioDevice,
+};
 use crate::devices::virtio::gen::virtio_blk::{
     VIRTIO_BLK_F_FLUSH, VIRTIO_BLK_F_RO, VIRTIO_F_VERSION_1,
 };
@@ -56,6 +58,8 @@ pub struct VhostUserBlockConfig {
     /// If set to true, the drive will ignore flush requests coming from
     /// the guest driver.
     pub cache_type: CacheType,
+    /// Virtio features the features offered by the device are restricted to.
+    pub virtio_features_pin: Option<u64>,
 
     /// Socket path of the vhost-user process
     pub socket: String,
@@ -69,13 +73,25 @@ impl TryFrom<&BlockDeviceConfig> for VhostUserBlockConfig {
             && value.is_read_only.is_none()
             && value.path_on_host.is_none()
             && value.rate_limiter.is_none()
+            && value.rate_limiter_scope.is_none()
             && value.file_engine_type.is_none()
+            && value.detect_zeroes.is_none()
+            && value.open_direct.is_none()
+            && value.io_engine_opts.is_none()
+            && value.queue_size.is_none()
+            && value.num_queues.is_none()
+            && value.serial.is_none()
+            && value.logical_block_size.is_none()
+            && value.physical_block_size.is_none()
+            && value.on_host_enospc.is_none()
+            && value.io_uring.is_none()
         {
             Ok(Self {
                 drive_id: value.drive_id.clone(),
                 partuuid: value.partuuid.clone(),
                 is_root_device: value.is_root_device,
                 cache_type: value.cache_type,
+                virtio_features_pin: value.virtio_features_pin,
 
                 socket: value.socket.as_ref().unwrap().clone(),
             })
@@ -92,11 +108,24 @@ impl From<VhostUserBlockConfig> for BlockDeviceConfig {
             partuuid: value.partuuid,
             is_root_device: value.is_root_device,
             cache_type: value.cache_type,
+            virtio_features_pin: value.virtio_features_pin,
+            negotiated_virtio_features: None,
 
             is_read_only: None,
             path_on_host: None,
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: Some(value.socket),
         }
@@ -110,6 +139,7 @@ pub struct VhostUserBlockImpl<T: VhostUserHandleBackend> {
     // Virtio fields.
     pub avail_features: u64,
     pub acked_features: u64,
+    pub virtio_features_pin: Option<u64>,
     pub config_space: Vec<u8>,
     pub activate_evt: EventFd,
 
@@ -138,6 +168,7 @@ impl<T: VhostUserHandleBackend> std::fmt::Debug for VhostUserBlockImpl<T> {
         f.debug_struct("VhostUserBlockImpl")
             .field("avail_features", &self.avail_features)
             .field("acked_features", &self.acked_features)
+            .field("virtio_features_pin", &self.virtio_features_pin)
             .field("config_space", &self.config_space)
             .field("activate_evt", &self.activate_evt)
             .field("queues", &self.queues)
@@ -206,9 +237,13 @@ impl<T: VhostUserHandleBackend> VhostUserBlockImpl<T> {
         let irq_trigger = IrqTrigger::new().map_err(VhostUserBlockError::IrqTrigger)?;
 
         // We negotiated features with backend. Now these acked_features
-        // are available for guest driver to choose from.
-        let avail_features = acked_features;
-        let acked_features = acked_features & VhostUserVirtioFeatures::PROTOCOL_FEATURES.bits();
+        // are available for guest driver to choose from, within the pinned ones.
+        let avail_features = match config.virtio_features_pin {
+            Some(pin) => pin_virtio_features(&config.drive_id, acked_features, pin)
+                .map_err(VhostUserBlockError::PinVirtioFeatures)?,
+            None => acked_features,
+        };
+        let acked_features = avail_features & VhostUserVirtioFeatures::PROTOCOL_FEATURES.bits();
         let read_only = acked_features & (1 << VIRTIO_BLK_F_RO) != 0;
         let vhost_user_block_metrics_name = format!("block_{}", config.drive_id);
 
@@ -219,6 +254,7 @@ impl<T: VhostUserHandleBackend> VhostUserBlockImpl<T> {
         Ok(Self {
             avail_features,
             acked_features,
+            virtio_features_pin: config.virtio_features_pin,
             config_space,
             activate_evt,
 
@@ -250,6 +286,7 @@ impl<T: VhostUserHandleBackend> VhostUserBlockImpl<T> {
             partuuid: self.partuuid.clone(),
             is_root_device: self.root_device,
             cache_type: self.cache_type,
+            virtio_features_pin: self.virtio_features_pin,
             socket: self.vu_handle.socket_path.clone(),
         }
     }
@@ -294,6 +331,10 @@ impl<T: VhostUserHandleBackend + Send + 'static> VirtioDevice for VhostUserBlock
         self.acked_features = acked_features;
     }
 
+    fn virtio_features_pin(&self) -> Option<u64> {
+        self.virtio_features_pin
+    }
+
     fn device_type(&self) -> u32 {
         TYPE_BLOCK
     }
@@ -372,6 +413,7 @@ mod tests {
     use crate::devices::virtio::block::virtio::device::FileEngineType;
     use crate::devices::virtio::mmio::VIRTIO_MMIO_INT_CONFIG;
     use crate::utilities::test_utils::create_tmp_socket;
+    use crate::vmm_config::machine_config::HugePageConfig;
     use crate::vstate::memory::{FileOffset, GuestAddress, GuestMemoryExtension};
 
     #[test]
@@ -385,9 +427,22 @@ mod tests {
             is_read_only: None,
             path_on_host: None,
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: Some("sock".to_string()),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         VhostUserBlockConfig::try_from(&block_config).unwrap();
 
@@ -400,9 +455,22 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: Some(FileEngineType::Sync),
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         VhostUserBlockConfig::try_from(&block_config).unwrap_err();
 
@@ -415,9 +483,22 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: Some(FileEngineType::Sync),
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: Some("sock".to_string()),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         VhostUserBlockConfig::try_from(&block_config).unwrap_err();
     }
@@ -479,6 +560,7 @@ mod tests {
             is_root_device: false,
             cache_type: CacheType::Unsafe,
             socket: tmp_socket_path.clone(),
+            virtio_features_pin: None,
         };
         let vhost_block = VhostUserBlockImpl::<MockMaster>::new(vhost_block_config).unwrap();
 
@@ -579,6 +661,7 @@ mod tests {
             is_root_device: false,
             cache_type: CacheType::Writeback,
             socket: tmp_socket_path.clone(),
+            virtio_features_pin: None,
         };
         let mut vhost_block = VhostUserBlockImpl::<MockMaster>::new(vhost_block_config).unwrap();
 
@@ -765,6 +848,7 @@ mod tests {
             is_root_device: false,
             cache_type: CacheType::Writeback,
             socket: tmp_socket_path,
+            virtio_features_pin: None,
         };
         let mut vhost_block = VhostUserBlockImpl::<MockMaster>::new(vhost_block_config).unwrap();
 
@@ -777,7 +861,9 @@ mod tests {
             GuestAddress(0x0),
             region_size,
         )];
-        let guest_memory = GuestMemoryMmap::from_raw_regions_file(regions, false, false).unwrap();
+        let guest_memory =
+            GuestMemoryMmap::from_raw_regions_file(regions, false, false, HugePageConfig::None)
+                .unwrap();
 
         // During actiavion of the device features, memory and queues should be set and activated.
         vhost_block.activate(guest_memory).unwrap();
@@ -785,5 +871,18 @@ mod tests {
         assert!(unsafe { *vhost_block.vu_handle.vu.memory_is_set.get() });
         assert!(unsafe { *vhost_block.vu_handle.vu.vring_enabled.get() });
         assert!(vhost_block.is_activated());
+
+        // The activation is recorded in the summary of the negotiation.
+        let negotiation = &vhost_block.vu_handle.negotiation;
+        assert_eq!(
+            negotiation.driver_features,
+            Some(vhost_block.acked_features)
+        );
+        assert_eq!(
+            negotiation.queue_sizes,
+            vec![vhost_block.queues[0].actual_size()]
+        );
+        assert_eq!(negotiation.memory_regions, 1);
+        assert!(negotiation.set_up_at_us.is_some());
     }
 }
@@ -29,4 +29,8 @@ pub enum VhostUserBlockError {
     EventFd(std::io::Error),
     /// Error creating irqfd: {0}
     IrqTrigger(std::io::Error),
+    /// Cannot pin the virtio features: {0}
+    PinVirtioFeatures(crate::devices::virtio::device::VirtioFeaturesPinError),
+    /// Snapshotting vhost-user block devices is not supported.
+    SnapshotNotSupported,
 }
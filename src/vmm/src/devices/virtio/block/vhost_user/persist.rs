@@ -18,7 +18,7 @@ pub struct VhostUserBlockState {
     id: String,
     partuuid: Option<String>,
     cache_type: CacheType,
-    root_device: bool,
+    pub(crate) root_device: bool,
     socket_path: String,
     vu_acked_protocol_features: u64,
     config_space: Vec<u8>,
@@ -38,6 +38,33 @@ impl Persist<'_> for VhostUserBlock {
         _constructor_args: Self::ConstructorArgs,
         _state: &Self::State,
     ) -> Result<Self, Self::Error> {
-        unimplemented!("VhostUserBlock does not support snapshotting yet");
+        // Reconnecting to the backend and negotiating again with it is not supported yet.
+        Err(VhostUserBlockError::SnapshotNotSupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::test_utils::single_region_mem;
+
+    #[test]
+    fn test_restore() {
+        let mem = single_region_mem(0x1000);
+        let state = VhostUserBlockState {
+            id: "drive".to_string(),
+            partuuid: None,
+            cache_type: CacheType::Unsafe,
+            root_device: false,
+            socket_path: "/path/to/socket".to_string(),
+            vu_acked_protocol_features: 0,
+            config_space: Vec::new(),
+            virtio_state: VirtioDeviceState::default(),
+        };
+
+        assert!(matches!(
+            VhostUserBlock::restore(BlockConstructorArgs { mem }, &state),
+            Err(VhostUserBlockError::SnapshotNotSupported)
+        ));
     }
 }
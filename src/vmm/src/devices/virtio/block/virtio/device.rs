@@ -10,32 +10,51 @@ use std::convert::From;
 use std::fs::{File, OpenOptions};
 use std::io::{Seek, SeekFrom, Write};
 use std::os::linux::fs::MetadataExt;
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use block_io::FileEngine;
 use serde::{Deserialize, Serialize};
+use utils::byte_order::{write_le_u16, write_le_u32, write_le_u64};
 use utils::eventfd::EventFd;
 use utils::kernel_version::{min_kernel_version_for_io_uring, KernelVersion};
+use utils::time::{get_time_us, ClockType};
 use utils::u64_to_usize;
 
 use super::io::async_io;
 use super::request::*;
 use super::{
-    io as block_io, VirtioBlockError, BLOCK_CONFIG_SPACE_SIZE, BLOCK_QUEUE_SIZES, SECTOR_SHIFT,
+    io as block_io, VirtioBlockError, BLOCK_CONFIG_SPACE_SIZE, BLOCK_DISCARD_SECTOR_ALIGNMENT,
+    BLOCK_MAX_DISCARD_SECTORS, BLOCK_MAX_DISCARD_SEGMENTS, BLOCK_MAX_LOGICAL_BLOCK_SIZE,
+    BLOCK_MAX_NUM_QUEUES, BLOCK_MAX_PHYSICAL_BLOCK_SIZE, BLOCK_MAX_QUEUE_SIZE,
+    BLOCK_MIN_QUEUE_SIZE, BLOCK_NUM_QUEUES, IO_URING_DEFAULT_SQPOLL_IDLE_MS,
+    IO_URING_MAX_RING_SIZE, IO_URING_MIN_RING_SIZE, IO_URING_NUM_ENTRIES, SECTOR_SHIFT,
     SECTOR_SIZE,
 };
+use crate::auto_pause::record_activity;
 use crate::devices::virtio::block::virtio::metrics::{BlockDeviceMetrics, BlockMetricsPerDevice};
 use crate::devices::virtio::block::CacheType;
-use crate::devices::virtio::device::{DeviceState, IrqTrigger, IrqType, VirtioDevice};
+use crate::devices::virtio::device::{
+    check_queue_size, pin_virtio_features, DeviceState, IrqTrigger, IrqType, VirtioDevice,
+};
 use crate::devices::virtio::gen::virtio_blk::{
-    VIRTIO_BLK_F_FLUSH, VIRTIO_BLK_F_RO, VIRTIO_BLK_ID_BYTES, VIRTIO_F_VERSION_1,
+    VIRTIO_BLK_F_BLK_SIZE, VIRTIO_BLK_F_DISCARD, VIRTIO_BLK_F_FLUSH, VIRTIO_BLK_F_MQ,
+    VIRTIO_BLK_F_RO, VIRTIO_BLK_F_TOPOLOGY, VIRTIO_BLK_F_WRITE_ZEROES, VIRTIO_BLK_ID_BYTES,
+    VIRTIO_F_VERSION_1,
+};
+use crate::devices::virtio::gen::virtio_ring::{
+    VIRTIO_RING_F_EVENT_IDX, VIRTIO_RING_F_INDIRECT_DESC,
+};
+use crate::devices::virtio::queue::{
+    recycle_chains, DescriptorChain, Queue, FIRECRACKER_MAX_QUEUE_SIZE,
 };
-use crate::devices::virtio::gen::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
-use crate::devices::virtio::queue::Queue;
 use crate::devices::virtio::{ActivateError, TYPE_BLOCK};
-use crate::logger::{error, warn, IncMetric};
+use crate::devices::{DeviceError, ErrorSeverity};
+use crate::events::{DeviceErrorKind, VmmEvent, EVENTS};
+use crate::logger::{error, info, warn, IncMetric, METRICS};
 use crate::rate_limiter::{BucketUpdate, RateLimiter};
+use crate::vmm_config::auto_pause::ActivitySignal;
 use crate::vmm_config::drive::BlockDeviceConfig;
 use crate::vmm_config::RateLimiterConfig;
 use crate::vstate::memory::GuestMemoryMmap;
@@ -60,21 +79,205 @@ impl FileEngineType {
     }
 }
 
+/// Requests smaller than this are not scanned for zeroes in `DetectZeroes::Nonzero` mode.
+pub const DETECT_ZEROES_MIN_SCAN_LEN: u32 = 64 << 10;
+
+/// How writes consisting of zeroes are handled by the block device.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectZeroes {
+    /// Write data to the backing file as it is.
+    #[default]
+    Off,
+    /// Scan every write and punch holes in the backing file for all-zero blocks.
+    Unmap,
+    /// Like `Unmap`, but only scan writes of at least `DETECT_ZEROES_MIN_SCAN_LEN` bytes.
+    Nonzero,
+}
+
+impl DetectZeroes {
+    /// Whether a write request of `data_len` bytes should be scanned for zeroes.
+    pub fn should_scan(&self, data_len: u32) -> bool {
+        match self {
+            Self::Off => false,
+            Self::Unmap => true,
+            Self::Nonzero => data_len >= DETECT_ZEROES_MIN_SCAN_LEN,
+        }
+    }
+}
+
+/// How the block device handles the writes failing because the host file system backing the
+/// drive ran out of space.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnHostEnospc {
+    /// Fail the requests with `VIRTIO_BLK_S_IOERR`.
+    #[default]
+    Report,
+    /// Keep the requests pending and stop processing the queues, until the requests are retried
+    /// through the API.
+    Pause,
+}
+
+/// How the rate limiter of a block device applies to its queues.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimiterScope {
+    /// The queues share the rate limiter, which limits the I/O of the whole device.
+    #[default]
+    Device,
+    /// Every queue has its own copy of the rate limiter, which limits the I/O of the queue.
+    Queue,
+}
+
+/// Block sizes of the disk advertised to the guest, when configured.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BlockTopology {
+    /// Smallest unit the disk can be addressed in, 512 bytes by default.
+    pub logical_block_size: Option<u32>,
+    /// Unit the disk is written in without a read-modify-write cycle, the logical block size by
+    /// default.
+    pub physical_block_size: Option<u32>,
+}
+
+impl BlockTopology {
+    /// Whether any of the block sizes is configured, and advertised to the guest.
+    pub fn is_configured(&self) -> bool {
+        self.logical_block_size.is_some() || self.physical_block_size.is_some()
+    }
+
+    /// The logical block size of the disk, in bytes.
+    pub fn logical_block_size(&self) -> u32 {
+        self.logical_block_size.unwrap_or(SECTOR_SIZE)
+    }
+
+    /// The physical block size of the disk, in bytes.
+    pub fn physical_block_size(&self) -> u32 {
+        self.physical_block_size
+            .unwrap_or_else(|| self.logical_block_size())
+    }
+
+    fn check(&self) -> Result<(), VirtioBlockError> {
+        let (logical, physical) = (self.logical_block_size(), self.physical_block_size());
+        let valid = logical.is_power_of_two()
+            && physical.is_power_of_two()
+            && (SECTOR_SIZE..=BLOCK_MAX_LOGICAL_BLOCK_SIZE).contains(&logical)
+            && (logical..=BLOCK_MAX_PHYSICAL_BLOCK_SIZE).contains(&physical);
+        if !valid {
+            return Err(VirtioBlockError::InvalidBlockSize(logical, physical));
+        }
+        Ok(())
+    }
+}
+
+// Offsets of the fields of the virtio block configuration space that the device sets.
+const CONFIG_CAPACITY: usize = 0;
+const CONFIG_BLK_SIZE: usize = 20;
+const CONFIG_PHYSICAL_BLOCK_EXP: usize = 24;
+const CONFIG_MIN_IO_SIZE: usize = 26;
+const CONFIG_NUM_QUEUES: usize = 34;
+const CONFIG_MAX_DISCARD_SECTORS: usize = 36;
+const CONFIG_MAX_DISCARD_SEG: usize = 40;
+const CONFIG_DISCARD_SECTOR_ALIGNMENT: usize = 44;
+const CONFIG_MAX_WRITE_ZEROES_SECTORS: usize = 48;
+const CONFIG_MAX_WRITE_ZEROES_SEG: usize = 52;
+const CONFIG_WRITE_ZEROES_MAY_UNMAP: usize = 56;
+
+/// Default maximum number of requests processed in a single pass over the queue.
+pub const DEFAULT_MAX_REQUESTS_PER_PASS: u16 = 256;
+
+/// Maximum number of requests popped from the queue at once.
+const POP_BATCH_SIZE: usize = 64;
+
+/// Options tuning how the block device feeds requests to its IO engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IoEngineOpts {
+    /// Maximum number of requests taken from the queue in one pass. Once reached, the device
+    /// yields to the other event loop subscribers and resumes processing in a later pass.
+    pub max_requests_per_pass: u16,
+}
+
+impl Default for IoEngineOpts {
+    fn default() -> Self {
+        Self {
+            max_requests_per_pass: DEFAULT_MAX_REQUESTS_PER_PASS,
+        }
+    }
+}
+
+/// Setup of the io_uring instance used by the `Async` IO engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IoUringConfig {
+    /// Number of entries of the submission queue. The completion queue has twice as many, which
+    /// bounds the number of requests in flight.
+    pub ring_size: u16,
+    /// Whether a kernel thread polls the submission queue, which saves the syscall submitting
+    /// the requests while it is awake.
+    pub sqpoll: bool,
+    /// Time the kernel thread keeps polling an idle submission queue before sleeping, in
+    /// milliseconds.
+    pub sqpoll_idle_ms: u32,
+}
+
+impl Default for IoUringConfig {
+    fn default() -> Self {
+        Self {
+            ring_size: IO_URING_NUM_ENTRIES,
+            sqpoll: false,
+            sqpoll_idle_ms: IO_URING_DEFAULT_SQPOLL_IDLE_MS,
+        }
+    }
+}
+
+impl IoUringConfig {
+    /// How long the kernel thread polls an idle submission queue, if it is polled.
+    pub fn sq_thread_idle_ms(&self) -> Option<u32> {
+        self.sqpoll.then_some(self.sqpoll_idle_ms)
+    }
+
+    fn check(&self, file_engine_type: FileEngineType) -> Result<(), VirtioBlockError> {
+        if file_engine_type != FileEngineType::Async && *self != Self::default() {
+            return Err(VirtioBlockError::IoUringEngine(file_engine_type));
+        }
+        if !self.ring_size.is_power_of_two()
+            || !(IO_URING_MIN_RING_SIZE..=IO_URING_MAX_RING_SIZE).contains(&self.ring_size)
+        {
+            return Err(VirtioBlockError::InvalidRingSize(self.ring_size));
+        }
+        Ok(())
+    }
+}
+
 /// Helper object for setting up all `Block` fields derived from its backing file.
 #[derive(Debug)]
 pub struct DiskProperties {
     pub file_path: String,
     pub file_engine: FileEngine<PendingRequest>,
+    pub detect_zeroes: DetectZeroes,
+    pub open_direct: bool,
+    pub io_uring: IoUringConfig,
+    pub read_only: bool,
+    pub serial: Option<String>,
+    pub topology: BlockTopology,
     pub nsectors: u64,
     pub image_id: [u8; VIRTIO_BLK_ID_BYTES as usize],
 }
 
 impl DiskProperties {
-    // Helper function that opens the file with the proper access permissions
-    fn open_file(disk_image_path: &str, is_disk_read_only: bool) -> Result<File, VirtioBlockError> {
+    // Helper function that opens the file with the proper access permissions, bypassing the host
+    // page cache with `O_DIRECT` if `open_direct` is set.
+    fn open_file(
+        disk_image_path: &str,
+        is_disk_read_only: bool,
+        open_direct: bool,
+    ) -> Result<File, VirtioBlockError> {
+        let custom_flags = if open_direct { libc::O_DIRECT } else { 0 };
         OpenOptions::new()
             .read(true)
             .write(!is_disk_read_only)
+            .custom_flags(custom_flags)
             .open(PathBuf::from(&disk_image_path))
             .map_err(|x| VirtioBlockError::BackingFile(x, disk_image_path.to_string()))
     }
@@ -99,19 +302,40 @@ impl DiskProperties {
     }
 
     /// Create a new file for the block device using a FileEngine
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         disk_image_path: String,
         is_disk_read_only: bool,
         file_engine_type: FileEngineType,
+        detect_zeroes: DetectZeroes,
+        open_direct: bool,
+        io_uring: IoUringConfig,
+        serial: Option<String>,
+        topology: BlockTopology,
     ) -> Result<Self, VirtioBlockError> {
-        let mut disk_image = Self::open_file(&disk_image_path, is_disk_read_only)?;
+        if detect_zeroes != DetectZeroes::Off && file_engine_type != FileEngineType::Sync {
+            return Err(VirtioBlockError::DetectZeroesEngine(file_engine_type));
+        }
+        io_uring.check(file_engine_type)?;
+        topology.check()?;
+
+        let mut disk_image = Self::open_file(&disk_image_path, is_disk_read_only, open_direct)?;
         let disk_size = Self::file_size(&disk_image_path, &mut disk_image)?;
-        let image_id = Self::build_disk_image_id(&disk_image);
+        let image_id = match &serial {
+            Some(serial) => Self::build_serial_image_id(serial)?,
+            None => Self::build_disk_image_id(&disk_image),
+        };
 
         Ok(Self {
             file_path: disk_image_path,
-            file_engine: FileEngine::from_file(disk_image, file_engine_type)
+            file_engine: FileEngine::from_file(disk_image, file_engine_type, io_uring)
                 .map_err(VirtioBlockError::FileEngine)?,
+            detect_zeroes,
+            open_direct,
+            io_uring,
+            read_only: is_disk_read_only,
+            serial,
+            topology,
             nsectors: disk_size >> SECTOR_SHIFT,
             image_id,
         })
@@ -123,15 +347,27 @@ impl DiskProperties {
         disk_image_path: String,
         is_disk_read_only: bool,
     ) -> Result<(), VirtioBlockError> {
-        let mut disk_image = Self::open_file(&disk_image_path, is_disk_read_only)?;
+        let mut disk_image =
+            Self::open_file(&disk_image_path, is_disk_read_only, self.open_direct)?;
         let disk_size = Self::file_size(&disk_image_path, &mut disk_image)?;
+        // The guest may be using the whole disk, so it can only grow.
+        if disk_size >> SECTOR_SHIFT < self.nsectors {
+            return Err(VirtioBlockError::DiskShrink(
+                self.nsectors,
+                disk_size >> SECTOR_SHIFT,
+            ));
+        }
 
-        self.image_id = Self::build_disk_image_id(&disk_image);
+        // The configured serial identifies the disk whatever its backing file.
+        if self.serial.is_none() {
+            self.image_id = Self::build_disk_image_id(&disk_image);
+        }
         self.file_engine
             .update_file_path(disk_image)
             .map_err(VirtioBlockError::FileEngine)?;
         self.nsectors = disk_size >> SECTOR_SHIFT;
         self.file_path = disk_image_path;
+        self.read_only = is_disk_read_only;
 
         Ok(())
     }
@@ -167,15 +403,62 @@ impl DiskProperties {
         default_id
     }
 
+    // Builds the ID of the disk from the serial configured for it, padded with zeroes.
+    fn build_serial_image_id(
+        serial: &str,
+    ) -> Result<[u8; VIRTIO_BLK_ID_BYTES as usize], VirtioBlockError> {
+        let valid_len = (1..=VIRTIO_BLK_ID_BYTES as usize).contains(&serial.len());
+        if !valid_len || !serial.bytes().all(|byte| byte.is_ascii_graphic()) {
+            return Err(VirtioBlockError::InvalidSerial(serial.to_string()));
+        }
+        let mut image_id = [0; VIRTIO_BLK_ID_BYTES as usize];
+        image_id[..serial.len()].copy_from_slice(serial.as_bytes());
+        Ok(image_id)
+    }
+
     /// Provides vec containing the virtio block configuration space
     /// buffer. The config space is populated with the disk size based
-    /// on the backing file size.
-    pub fn virtio_block_config_space(&self) -> Vec<u8> {
+    /// on the backing file size, the block sizes of the disk, the number
+    /// of queues of the device, and with the limits of the discard and
+    /// write zeroes requests.
+    pub fn virtio_block_config_space(&self, num_queues: u16) -> Vec<u8> {
         // The config space is little endian.
-        let mut config = Vec::with_capacity(BLOCK_CONFIG_SPACE_SIZE);
-        for i in 0..BLOCK_CONFIG_SPACE_SIZE {
-            config.push(((self.nsectors >> (8 * i)) & 0xff) as u8);
-        }
+        let mut config = vec![0u8; BLOCK_CONFIG_SPACE_SIZE];
+        write_le_u64(&mut config[CONFIG_CAPACITY..], self.nsectors);
+        // The physical block spans 2^physical_block_exp logical blocks, and is the minimal IO
+        // size. The disk has neither an alignment offset nor a preferred IO size.
+        let logical_block_size = self.topology.logical_block_size();
+        let logical_blocks_per_physical = self.topology.physical_block_size() / logical_block_size;
+        write_le_u32(&mut config[CONFIG_BLK_SIZE..], logical_block_size);
+        config[CONFIG_PHYSICAL_BLOCK_EXP] =
+            u8::try_from(logical_blocks_per_physical.trailing_zeros()).unwrap();
+        write_le_u16(
+            &mut config[CONFIG_MIN_IO_SIZE..],
+            u16::try_from(logical_blocks_per_physical).unwrap(),
+        );
+        write_le_u16(&mut config[CONFIG_NUM_QUEUES..], num_queues);
+        write_le_u32(
+            &mut config[CONFIG_MAX_DISCARD_SECTORS..],
+            BLOCK_MAX_DISCARD_SECTORS,
+        );
+        write_le_u32(
+            &mut config[CONFIG_MAX_DISCARD_SEG..],
+            BLOCK_MAX_DISCARD_SEGMENTS,
+        );
+        write_le_u32(
+            &mut config[CONFIG_DISCARD_SECTOR_ALIGNMENT..],
+            BLOCK_DISCARD_SECTOR_ALIGNMENT,
+        );
+        write_le_u32(
+            &mut config[CONFIG_MAX_WRITE_ZEROES_SECTORS..],
+            BLOCK_MAX_DISCARD_SECTORS,
+        );
+        write_le_u32(
+            &mut config[CONFIG_MAX_WRITE_ZEROES_SEG..],
+            BLOCK_MAX_DISCARD_SEGMENTS,
+        );
+        // Zeroed ranges can be unmapped from the backing file.
+        config[CONFIG_WRITE_ZEROES_MAY_UNMAP] = 1;
         config
     }
 }
@@ -197,6 +480,26 @@ pub struct VirtioBlockConfig {
     /// the guest driver.
     #[serde(default)]
     pub cache_type: CacheType,
+    /// Virtio features the features offered by the device are restricted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtio_features_pin: Option<u64>,
+    /// Maximal size of the virtio queue offered to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<u16>,
+    /// Number of virtio queues offered to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<u16>,
+
+    /// Serial of the drive, returned to the guest as its ID instead of one derived from the
+    /// backing file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    /// Logical block size of the disk advertised to the guest, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logical_block_size: Option<u32>,
+    /// Physical block size of the disk advertised to the guest, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical_block_size: Option<u32>,
 
     /// If set to true, the drive is opened in read-only mode. Otherwise, the
     /// drive is opened as read-write.
@@ -205,10 +508,28 @@ pub struct VirtioBlockConfig {
     pub path_on_host: String,
     /// Rate Limiter for I/O operations.
     pub rate_limiter: Option<RateLimiterConfig>,
+    /// Whether the rate limiter limits the I/O of the whole device, or of each queue.
+    #[serde(default)]
+    pub rate_limiter_scope: RateLimiterScope,
     /// The type of IO engine used by the device.
     #[serde(default)]
     #[serde(rename = "io_engine")]
     pub file_engine_type: FileEngineType,
+    /// How writes consisting of zeroes are handled.
+    #[serde(default)]
+    pub detect_zeroes: DetectZeroes,
+    /// If set to true, the backing file is opened with `O_DIRECT`, bypassing the host page cache.
+    #[serde(default)]
+    pub open_direct: bool,
+    /// Options of the IO engine used by the device.
+    #[serde(default)]
+    pub io_engine_opts: IoEngineOpts,
+    /// How writes failing because the host file system is out of space are handled.
+    #[serde(default)]
+    pub on_host_enospc: OnHostEnospc,
+    /// Setup of the io_uring instance of the `Async` IO engine.
+    #[serde(default)]
+    pub io_uring: IoUringConfig,
 }
 
 impl TryFrom<&BlockDeviceConfig> for VirtioBlockConfig {
@@ -221,11 +542,23 @@ impl TryFrom<&BlockDeviceConfig> for VirtioBlockConfig {
                 partuuid: value.partuuid.clone(),
                 is_root_device: value.is_root_device,
                 cache_type: value.cache_type,
+                virtio_features_pin: value.virtio_features_pin,
+                queue_size: value.queue_size,
+                num_queues: value.num_queues,
+                serial: value.serial.clone(),
+                logical_block_size: value.logical_block_size,
+                physical_block_size: value.physical_block_size,
 
                 is_read_only: value.is_read_only.unwrap_or(false),
                 path_on_host: value.path_on_host.as_ref().unwrap().clone(),
                 rate_limiter: value.rate_limiter,
+                rate_limiter_scope: value.rate_limiter_scope.unwrap_or_default(),
                 file_engine_type: value.file_engine_type.unwrap_or_default(),
+                detect_zeroes: value.detect_zeroes.unwrap_or_default(),
+                open_direct: value.open_direct.unwrap_or(false),
+                io_engine_opts: value.io_engine_opts.unwrap_or_default(),
+                on_host_enospc: value.on_host_enospc.unwrap_or_default(),
+                io_uring: value.io_uring.unwrap_or_default(),
             })
         } else {
             Err(VirtioBlockError::Config)
@@ -240,11 +573,26 @@ impl From<VirtioBlockConfig> for BlockDeviceConfig {
             partuuid: value.partuuid,
             is_root_device: value.is_root_device,
             cache_type: value.cache_type,
+            virtio_features_pin: value.virtio_features_pin,
+            negotiated_virtio_features: None,
 
             is_read_only: Some(value.is_read_only),
             path_on_host: Some(value.path_on_host),
             rate_limiter: value.rate_limiter,
+            rate_limiter_scope: (value.rate_limiter_scope != RateLimiterScope::Device)
+                .then_some(value.rate_limiter_scope),
             file_engine_type: Some(value.file_engine_type),
+            detect_zeroes: Some(value.detect_zeroes),
+            open_direct: value.open_direct.then_some(true),
+            io_engine_opts: Some(value.io_engine_opts),
+            queue_size: value.queue_size,
+            num_queues: value.num_queues,
+            serial: value.serial,
+            logical_block_size: value.logical_block_size,
+            physical_block_size: value.physical_block_size,
+            on_host_enospc: (value.on_host_enospc != OnHostEnospc::Report)
+                .then_some(value.on_host_enospc),
+            io_uring: (value.io_uring != IoUringConfig::default()).then_some(value.io_uring),
 
             socket: None,
         }
@@ -257,14 +605,20 @@ pub struct VirtioBlock {
     // Virtio fields.
     pub avail_features: u64,
     pub acked_features: u64,
+    pub virtio_features_pin: Option<u64>,
     pub config_space: Vec<u8>,
     pub activate_evt: EventFd,
 
     // Transport related fields.
     pub queues: Vec<Queue>,
-    pub queue_evts: [EventFd; 1],
+    // Configured maximal size of the queues, if not the default one.
+    pub queue_size: Option<u16>,
+    // Configured number of queues, if not the default one.
+    pub num_queues: Option<u16>,
+    pub queue_evts: Vec<EventFd>,
     pub device_state: DeviceState,
     pub irq_trigger: IrqTrigger,
+    pub needs_reset: bool,
 
     // Implementation specific fields.
     pub id: String,
@@ -275,8 +629,23 @@ pub struct VirtioBlock {
 
     // Host file and properties.
     pub disk: DiskProperties,
-    pub rate_limiter: RateLimiter,
-    pub is_io_engine_throttled: bool,
+    // The rate limiter shared by the queues, or the rate limiter of each queue.
+    pub rate_limiters: Vec<RateLimiter>,
+    pub rate_limiter_scope: RateLimiterScope,
+    // Whether each queue waits for the IO engine to complete requests before being processed.
+    pub is_io_engine_throttled: Vec<bool>,
+    pub io_engine_opts: IoEngineOpts,
+    // Number of passes over each queue since it was last drained.
+    pub passes_since_kick: Vec<u64>,
+    // Storage of the batches of requests popped from the queues, kept across the passes.
+    pub head_batch: Vec<DescriptorChain<'static>>,
+    // Requests of each queue completed in the current pass, handed to the guest at once at the
+    // end of it.
+    pub used_batches: Vec<Vec<(u16, u32)>>,
+    pub on_host_enospc: OnHostEnospc,
+    // Requests kept pending since the host file system ran out of space, with
+    // `OnHostEnospc::Pause`. The queues aren't processed while there are any.
+    pub retained_requests: Vec<PendingRequest>,
     pub metrics: Arc<BlockDeviceMetrics>,
 }
 
@@ -286,7 +655,7 @@ macro_rules! unwrap_async_file_engine_or_return {
             FileEngine::Async(engine) => engine,
             FileEngine::Sync(_) => {
                 error!("The block device doesn't use an async IO engine");
-                return;
+                return Ok(());
             }
         }
     };
@@ -297,20 +666,47 @@ impl VirtioBlock {
     ///
     /// The given file must be seekable and sizable.
     pub fn new(config: VirtioBlockConfig) -> Result<VirtioBlock, VirtioBlockError> {
+        if config.io_engine_opts.max_requests_per_pass == 0 {
+            return Err(VirtioBlockError::InvalidMaxRequestsPerPass);
+        }
+
+        let num_queues = config.num_queues.unwrap_or(BLOCK_NUM_QUEUES);
+        if num_queues == 0 || num_queues > BLOCK_MAX_NUM_QUEUES {
+            return Err(VirtioBlockError::NumQueues(num_queues));
+        }
+
         let disk_properties = DiskProperties::new(
             config.path_on_host,
             config.is_read_only,
             config.file_engine_type,
+            config.detect_zeroes,
+            config.open_direct,
+            config.io_uring,
+            config.serial,
+            BlockTopology {
+                logical_block_size: config.logical_block_size,
+                physical_block_size: config.physical_block_size,
+            },
         )?;
 
-        let rate_limiter = config
-            .rate_limiter
-            .map(RateLimiterConfig::try_into)
-            .transpose()
-            .map_err(VirtioBlockError::RateLimiter)?
-            .unwrap_or_default();
+        let num_rate_limiters = match config.rate_limiter_scope {
+            RateLimiterScope::Device => 1,
+            RateLimiterScope::Queue => num_queues,
+        };
+        let rate_limiters = (0..num_rate_limiters)
+            .map(|_| {
+                config
+                    .rate_limiter
+                    .map(RateLimiterConfig::try_into)
+                    .transpose()
+                    .map(Option::unwrap_or_default)
+                    .map_err(VirtioBlockError::RateLimiter)
+            })
+            .collect::<Result<Vec<RateLimiter>, _>>()?;
 
-        let mut avail_features = (1u64 << VIRTIO_F_VERSION_1) | (1u64 << VIRTIO_RING_F_EVENT_IDX);
+        let mut avail_features = (1u64 << VIRTIO_F_VERSION_1)
+            | (1u64 << VIRTIO_RING_F_EVENT_IDX)
+            | (1u64 << VIRTIO_RING_F_INDIRECT_DESC);
 
         if config.cache_type == CacheType::Writeback {
             avail_features |= 1u64 << VIRTIO_BLK_F_FLUSH;
@@ -318,22 +714,62 @@ impl VirtioBlock {
 
         if config.is_read_only {
             avail_features |= 1u64 << VIRTIO_BLK_F_RO;
+        } else {
+            avail_features |= (1u64 << VIRTIO_BLK_F_DISCARD) | (1u64 << VIRTIO_BLK_F_WRITE_ZEROES);
         };
 
-        let queue_evts = [EventFd::new(libc::EFD_NONBLOCK).map_err(VirtioBlockError::EventFd)?];
+        if num_queues > 1 {
+            avail_features |= 1u64 << VIRTIO_BLK_F_MQ;
+        }
+
+        if disk_properties.topology.is_configured() {
+            avail_features |= (1u64 << VIRTIO_BLK_F_BLK_SIZE) | (1u64 << VIRTIO_BLK_F_TOPOLOGY);
+        }
+
+        if let Some(pin) = config.virtio_features_pin {
+            avail_features = pin_virtio_features(&config.drive_id, avail_features, pin)
+                .map_err(VirtioBlockError::PinVirtioFeatures)?;
+            // The guest driver only sets up the first queue without the feature, and the device
+            // wouldn't get activated.
+            if num_queues > 1 && avail_features & (1u64 << VIRTIO_BLK_F_MQ) == 0 {
+                return Err(VirtioBlockError::MultiQueueNotPinned);
+            }
+            // The guest driver would address the disk in 512 bytes sectors.
+            if disk_properties.topology.logical_block_size() != SECTOR_SIZE
+                && avail_features & (1u64 << VIRTIO_BLK_F_BLK_SIZE) == 0
+            {
+                return Err(VirtioBlockError::BlockSizeNotPinned);
+            }
+        }
+
+        if let Some(size) = config.queue_size {
+            check_queue_size(size, BLOCK_MIN_QUEUE_SIZE, BLOCK_MAX_QUEUE_SIZE)
+                .map_err(VirtioBlockError::QueueSize)?;
+        }
+
+        let queue_evts = (0..num_queues)
+            .map(|_| EventFd::new(libc::EFD_NONBLOCK))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(VirtioBlockError::EventFd)?;
 
-        let queues = BLOCK_QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
+        let queues = (0..num_queues)
+            .map(|_| Queue::new(config.queue_size.unwrap_or(FIRECRACKER_MAX_QUEUE_SIZE)))
+            .collect();
 
         Ok(VirtioBlock {
             avail_features,
             acked_features: 0u64,
-            config_space: disk_properties.virtio_block_config_space(),
+            virtio_features_pin: config.virtio_features_pin,
+            config_space: disk_properties.virtio_block_config_space(num_queues),
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(VirtioBlockError::EventFd)?,
 
             queues,
+            queue_size: config.queue_size,
+            num_queues: config.num_queues,
             queue_evts,
             device_state: DeviceState::Inactive,
             irq_trigger: IrqTrigger::new().map_err(VirtioBlockError::IrqTrigger)?,
+            needs_reset: false,
 
             id: config.drive_id.clone(),
             partuuid: config.partuuid,
@@ -342,15 +778,23 @@ impl VirtioBlock {
             read_only: config.is_read_only,
 
             disk: disk_properties,
-            rate_limiter,
-            is_io_engine_throttled: false,
+            rate_limiters,
+            rate_limiter_scope: config.rate_limiter_scope,
+            is_io_engine_throttled: vec![false; usize::from(num_queues)],
+            io_engine_opts: config.io_engine_opts,
+            passes_since_kick: vec![0; usize::from(num_queues)],
+            head_batch: Vec::new(),
+            used_batches: vec![Vec::new(); usize::from(num_queues)],
+            on_host_enospc: config.on_host_enospc,
+            retained_requests: Vec::new(),
             metrics: BlockMetricsPerDevice::alloc(config.drive_id),
         })
     }
 
     /// Returns a copy of a device config
     pub fn config(&self) -> VirtioBlockConfig {
-        let rl: RateLimiterConfig = (&self.rate_limiter).into();
+        // All the rate limiters of the device share the same configuration.
+        let rl: RateLimiterConfig = (&self.rate_limiters[0]).into();
         VirtioBlockConfig {
             drive_id: self.id.clone(),
             path_on_host: self.disk.file_path.clone(),
@@ -358,132 +802,365 @@ impl VirtioBlock {
             partuuid: self.partuuid.clone(),
             is_read_only: self.read_only,
             cache_type: self.cache_type,
+            virtio_features_pin: self.virtio_features_pin,
+            queue_size: self.queue_size,
+            num_queues: self.num_queues,
+            serial: self.disk.serial.clone(),
+            logical_block_size: self.disk.topology.logical_block_size,
+            physical_block_size: self.disk.topology.physical_block_size,
             rate_limiter: rl.into_option(),
+            rate_limiter_scope: self.rate_limiter_scope,
             file_engine_type: self.file_engine_type(),
+            detect_zeroes: self.disk.detect_zeroes,
+            open_direct: self.disk.open_direct,
+            io_engine_opts: self.io_engine_opts,
+            on_host_enospc: self.on_host_enospc,
+            io_uring: self.disk.io_uring,
+        }
+    }
+
+    // The index of the rate limiter used by the queue at `queue_index`.
+    fn rate_limiter_index(&self, queue_index: usize) -> usize {
+        match self.rate_limiter_scope {
+            RateLimiterScope::Device => 0,
+            RateLimiterScope::Queue => queue_index,
+        }
+    }
+
+    // The queues using the rate limiter at `index`.
+    fn rate_limiter_queues(&self, index: usize) -> std::ops::Range<usize> {
+        match self.rate_limiter_scope {
+            RateLimiterScope::Device => 0..self.queues.len(),
+            RateLimiterScope::Queue => index..index + 1,
         }
     }
 
-    /// Process a single event in the Virtio queue.
+    /// Process a single event in a Virtio queue.
     ///
     /// This function is called by the event manager when the guest notifies us
     /// about new buffers in the queue.
-    pub(crate) fn process_queue_event(&mut self) {
+    pub(crate) fn process_queue_event(&mut self, queue_index: usize) -> Result<(), DeviceError> {
         self.metrics.queue_event_count.inc();
-        if let Err(err) = self.queue_evts[0].read() {
-            error!("Failed to get queue event: {:?}", err);
+        record_activity(ActivitySignal::Block);
+        if let Err(err) = self.queue_evts[queue_index].read() {
             self.metrics.event_fails.inc();
-        } else if self.rate_limiter.is_blocked() {
+            Err(DeviceError::EventFd(err))
+        } else if self.rate_limiters[self.rate_limiter_index(queue_index)].is_blocked() {
             self.metrics.rate_limiter_throttled_events.inc();
-        } else if self.is_io_engine_throttled {
+            Ok(())
+        } else if self.is_io_engine_throttled[queue_index] {
             self.metrics.io_engine_throttled_events.inc();
+            Ok(())
         } else {
-            self.process_virtio_queues();
+            let result = self.process_queue(queue_index);
+            Self::submit_io_engine(&mut self.disk, &self.metrics);
+            result
         }
     }
 
     /// Process device virtio queue(s).
     pub fn process_virtio_queues(&mut self) {
-        self.process_queue(0);
+        for queue_index in 0..self.queues.len() {
+            if let Err(err) = self.process_queue(queue_index) {
+                error!("Failed to process block queue {}: {}", queue_index, err);
+            }
+        }
+        Self::submit_io_engine(&mut self.disk, &self.metrics);
     }
 
-    pub(crate) fn process_rate_limiter_event(&mut self) {
+    /// Process the event of the rate limiter at `index`, the one of the queue of the same index
+    /// with `RateLimiterScope::Queue`.
+    pub(crate) fn process_rate_limiter_event(&mut self, index: usize) -> Result<(), DeviceError> {
         self.metrics.rate_limiter_event_count.inc();
         // Upon rate limiter event, call the rate limiter handler
-        // and restart processing the queue.
-        if self.rate_limiter.event_handler().is_ok() {
-            self.process_queue(0);
+        // and restart processing the queues using it.
+        self.rate_limiters[index]
+            .event_handler()
+            .map_err(DeviceError::RateLimiter)?;
+        let result = self
+            .rate_limiter_queues(index)
+            .try_for_each(|queue_index| self.process_queue(queue_index));
+        Self::submit_io_engine(&mut self.disk, &self.metrics);
+        result
+    }
+
+    // Submits the operations pushed to the io_uring submission queue, if any, with a single
+    // `io_uring_enter`. The event handlers only push the requests they take from the queues,
+    // and call this once they are done.
+    fn submit_io_engine(disk: &mut DiskProperties, metrics: &BlockDeviceMetrics) {
+        if let FileEngine::Async(ref mut engine) = disk.file_engine {
+            match engine.kick_submission_queue() {
+                Ok(0) => (),
+                Ok(submitted) => metrics.submissions_per_enter.record(u64::from(submitted)),
+                Err(err) => error!("BlockError submitting pending block requests: {:?}", err),
+            }
         }
     }
 
-    fn add_used_descriptor(
+    // Adds the requests completed in a pass to the used ring at once, emptying `used_batch`.
+    fn flush_used_batch(
         queue: &mut Queue,
-        index: u16,
-        len: u32,
+        used_batch: &mut Vec<(u16, u32)>,
         mem: &GuestMemoryMmap,
         irq_trigger: &IrqTrigger,
         block_metrics: &BlockDeviceMetrics,
-    ) {
-        queue.add_used(mem, index, len).unwrap_or_else(|err| {
-            error!("Failed to add available descriptor head {}: {}", index, err)
-        });
+    ) -> Result<(), DeviceError> {
+        if used_batch.is_empty() {
+            return Ok(());
+        }
+        let result = queue.add_used_batch(mem, used_batch);
+        used_batch.clear();
+        result.map_err(DeviceError::QueueError)?;
 
         if queue.prepare_kick(mem) {
-            irq_trigger.trigger_irq(IrqType::Vring).unwrap_or_else(|_| {
+            irq_trigger.trigger_irq(IrqType::Vring).map_err(|err| {
                 block_metrics.event_fails.inc();
+                DeviceError::FailedSignalingIrq(err)
+            })?;
+        }
+        Ok(())
+    }
+
+    // Hands the completed requests back to the queues they were taken from, even if signaling
+    // one of them fails.
+    fn flush_used_batches(&mut self) -> Result<(), DeviceError> {
+        // This is safe since the requests are only completed once the device is activated.
+        let mem = self.device_state.mem().unwrap();
+        let mut result = Ok(());
+        for (queue, used_batch) in self.queues.iter_mut().zip(self.used_batches.iter_mut()) {
+            result = result.and(Self::flush_used_batch(
+                queue,
+                used_batch,
+                mem,
+                &self.irq_trigger,
+                &self.metrics,
+            ));
+            self.metrics.add_queue_counters(queue.take_counters());
+        }
+        result
+    }
+
+    // Keeps `pending` to retry it once space is freed up on the host, which stops the processing
+    // of the queues.
+    fn retain_request(
+        id: &str,
+        retained_requests: &mut Vec<PendingRequest>,
+        pending: PendingRequest,
+        block_metrics: &BlockDeviceMetrics,
+    ) {
+        if retained_requests.is_empty() {
+            warn!(
+                "Block {}: the host file system is out of space, pausing the drive until its \
+                 pending requests are retried",
+                id
+            );
+            EVENTS.publish(VmmEvent::DeviceError {
+                device_type: TYPE_BLOCK,
+                kind: DeviceErrorKind::Io,
+                severity: ErrorSeverity::Degraded,
+                message: format!("drive {} paused: host file system out of space", id),
             });
         }
+        block_metrics.host_enospc_count.inc();
+        retained_requests.push(pending);
+    }
+
+    /// Whether requests are kept pending since the host file system ran out of space, in which
+    /// case the queues aren't processed until they are retried.
+    pub fn is_out_of_space(&self) -> bool {
+        !self.retained_requests.is_empty()
     }
 
     /// Device specific function for peaking inside a queue and processing descriptors.
-    pub fn process_queue(&mut self, queue_index: usize) {
+    ///
+    /// At most `io_engine_opts.max_requests_per_pass` requests are taken from the queue. If more
+    /// are pending, the queue event is signaled again so that processing resumes in a later pass,
+    /// after the other event loop subscribers had a chance to run.
+    ///
+    /// Failures to parse a request, or to execute it on the backend, are reported to the guest
+    /// through the status of the request. With `OnHostEnospc::Pause`, the requests running out of
+    /// space on the host are kept pending instead, and the queues aren't processed anymore until
+    /// they are retried.
+    ///
+    /// With the `Async` engine, the requests are pushed to the submission queue, and only submitted
+    /// when it is full. The event handlers submit the rest once done with the queues.
+    pub fn process_queue(&mut self, queue_index: usize) -> Result<(), DeviceError> {
+        #[cfg(feature = "memory-guards")]
+        let _canaries = crate::devices::virtio::canary::CanaryScope;
+        if self.is_out_of_space() {
+            self.metrics.host_enospc_stalled_events.inc();
+            return Ok(());
+        }
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
 
+        let rate_limiter_index = self.rate_limiter_index(queue_index);
         let queue = &mut self.queues[queue_index];
+        // The notifications are enabled again once the queue is drained. If the pass stops
+        // early, we get back to the queue when kicking ourselves, or when the pending requests
+        // complete or the rate limiter is replenished.
+        queue.disable_notification(mem);
+        let max_requests = u64::from(self.io_engine_opts.max_requests_per_pass);
         let mut used_any = false;
-
-        while let Some(head) = queue.pop_or_enable_notification(mem) {
-            self.metrics.remaining_reqs_count.add(queue.len(mem).into());
-            let processing_result = match Request::parse(&head, mem, self.disk.nsectors) {
-                Ok(request) => {
-                    if request.rate_limit(&mut self.rate_limiter) {
-                        // Stop processing the queue and return this descriptor chain to the
-                        // avail ring, for later processing.
-                        queue.undo_pop();
-                        self.metrics.rate_limiter_throttled_events.inc();
+        let mut result = Ok(());
+        let mut processed: u64 = 0;
+        let mut yielded = false;
+
+        let mut heads = recycle_chains(std::mem::take(&mut self.head_batch));
+
+        'pass: loop {
+            if processed == max_requests {
+                // Leave the remaining requests for the next pass. The notifications stay
+                // disabled since we will get back to the queue anyway. If we can't kick
+                // ourselves, keep going rather than leaving the requests behind.
+                match self.queue_evts[queue_index].write(1) {
+                    Ok(()) => {
+                        yielded = true;
                         break;
                     }
-
-                    used_any = true;
-                    request.process(&mut self.disk, head.index, mem, &self.metrics)
-                }
-                Err(err) => {
-                    error!("Failed to parse available descriptor chain: {:?}", err);
-                    self.metrics.execute_fails.inc();
-                    ProcessingResult::Executed(FinishedRequest {
-                        num_bytes_to_mem: 0,
-                        desc_idx: head.index,
-                    })
+                    Err(err) => {
+                        error!("Failed to signal queue event: {:?}", err);
+                        self.metrics.event_fails.inc();
+                    }
                 }
+            }
+            // Don't pop more requests than the pass has left to process.
+            let max_batch = match max_requests.checked_sub(processed) {
+                Some(left) if left > 0 => u64_to_usize(left).min(POP_BATCH_SIZE),
+                _ => POP_BATCH_SIZE,
             };
-
-            match processing_result {
-                ProcessingResult::Submitted => {}
-                ProcessingResult::Throttled => {
-                    queue.undo_pop();
-                    self.is_io_engine_throttled = true;
-                    break;
-                }
-                ProcessingResult::Executed(finished) => {
-                    Self::add_used_descriptor(
-                        queue,
-                        head.index,
-                        finished.num_bytes_to_mem,
-                        mem,
-                        &self.irq_trigger,
-                        &self.metrics,
-                    );
+            if queue.pop_batch(mem, max_batch, &mut heads) == 0 {
+                break;
+            }
+            // Reading the clock once per batch, and only if the latencies are recorded, keeps the
+            // cost per request negligible.
+            let popped_at_us = METRICS
+                .detailed()
+                .then(|| get_time_us(ClockType::Monotonic));
+
+            let in_ring = queue.len(mem);
+            let mut batch = heads.drain(..);
+            while let Some(head) = batch.next() {
+                self.metrics
+                    .remaining_reqs_count
+                    .add(u64::from(in_ring) + batch.len() as u64);
+                let processing_result = match Request::parse(
+                    &head,
+                    mem,
+                    self.disk.nsectors,
+                    self.disk.topology.logical_block_size(),
+                ) {
+                    Ok(request) => {
+                        if request.rate_limit(&mut self.rate_limiters[rate_limiter_index]) {
+                            // Stop processing the queue and return this descriptor chain and the
+                            // rest of the batch to the avail ring, for later processing.
+                            for _ in 0..=batch.len() {
+                                queue.undo_pop();
+                            }
+                            self.metrics.rate_limiter_throttled_events.inc();
+                            break 'pass;
+                        }
+
+                        used_any = true;
+                        // Submit early if the submission queue filled up during this event,
+                        // rather than throttling the queue.
+                        if matches!(&self.disk.file_engine, FileEngine::Async(engine)
+                            if engine.is_submission_queue_full().unwrap_or(false))
+                        {
+                            Self::submit_io_engine(&mut self.disk, &self.metrics);
+                        }
+                        request.process(
+                            &mut self.disk,
+                            queue_index,
+                            head.index,
+                            popped_at_us,
+                            mem,
+                            &self.metrics,
+                        )
+                    }
+                    Err(err) => {
+                        error!("Failed to parse available descriptor chain: {:?}", err);
+                        self.metrics.execute_fails.inc();
+                        ProcessingResult::Executed(FinishedRequest {
+                            num_bytes_to_mem: 0,
+                            desc_idx: head.index,
+                        })
+                    }
+                };
+
+                match processing_result {
+                    ProcessingResult::Submitted => {}
+                    ProcessingResult::Throttled => {
+                        for _ in 0..=batch.len() {
+                            queue.undo_pop();
+                        }
+                        self.is_io_engine_throttled[queue_index] = true;
+                        break 'pass;
+                    }
+                    ProcessingResult::Executed(finished) => {
+                        self.used_batches[queue_index].push((head.index, finished.num_bytes_to_mem))
+                    }
+                    ProcessingResult::OutOfSpace(pending, _)
+                        if self.on_host_enospc == OnHostEnospc::Pause =>
+                    {
+                        // Give the rest of the batch back to the queue, which is processed again
+                        // once the request is retried.
+                        for _ in 0..batch.len() {
+                            queue.undo_pop();
+                        }
+                        Self::retain_request(
+                            &self.id,
+                            &mut self.retained_requests,
+                            pending,
+                            &self.metrics,
+                        );
+                        break 'pass;
+                    }
+                    ProcessingResult::OutOfSpace(pending, res) => {
+                        let finished = pending.finish(mem, res, &self.metrics);
+                        self.used_batches[queue_index].push((head.index, finished.num_bytes_to_mem))
+                    }
                 }
+                processed += 1;
             }
         }
-
-        if let FileEngine::Async(ref mut engine) = self.disk.file_engine {
-            if let Err(err) = engine.kick_submission_queue() {
-                error!("BlockError submitting pending block requests: {:?}", err);
-            }
+        self.head_batch = recycle_chains(heads);
+
+        match Self::flush_used_batch(
+            queue,
+            &mut self.used_batches[queue_index],
+            mem,
+            &self.irq_trigger,
+            &self.metrics,
+        ) {
+            // The queue is still usable, so carry on with the pass.
+            Err(err @ DeviceError::FailedSignalingIrq(_)) => result = Err(err),
+            other => other?,
+        }
+        self.metrics.add_queue_counters(queue.take_counters());
+
+        self.metrics.requests_per_pass.record(processed);
+        self.passes_since_kick[queue_index] += 1;
+        if !yielded {
+            self.metrics
+                .passes_per_kick
+                .record(self.passes_since_kick[queue_index]);
+            self.passes_since_kick[queue_index] = 0;
         }
 
         if !used_any {
             self.metrics.no_avail_buffer.inc();
         }
+        result
     }
 
-    fn process_async_completion_queue(&mut self) {
+    fn process_async_completion_queue(&mut self) -> Result<(), DeviceError> {
+        #[cfg(feature = "memory-guards")]
+        let _canaries = crate::devices::virtio::canary::CanaryScope;
         let engine = unwrap_async_file_engine_or_return!(&mut self.disk.file_engine);
 
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
-        let queue = &mut self.queues[0];
 
         loop {
             match engine.pop(mem) {
@@ -505,51 +1182,170 @@ impl VirtioBlock {
                             ))),
                         ),
                     };
+                    if self.on_host_enospc == OnHostEnospc::Pause && pending.is_out_of_space(&res) {
+                        Self::retain_request(
+                            &self.id,
+                            &mut self.retained_requests,
+                            pending,
+                            &self.metrics,
+                        );
+                        continue;
+                    }
+                    let queue_index = pending.queue_index();
                     let finished = pending.finish(mem, res, &self.metrics);
-
-                    Self::add_used_descriptor(
-                        queue,
-                        finished.desc_idx,
-                        finished.num_bytes_to_mem,
-                        mem,
-                        &self.irq_trigger,
-                        &self.metrics,
-                    );
+                    self.used_batches[queue_index]
+                        .push((finished.desc_idx, finished.num_bytes_to_mem));
                 }
             }
         }
+
+        self.flush_used_batches()
     }
 
-    pub fn process_async_completion_event(&mut self) {
+    pub fn process_async_completion_event(&mut self) -> Result<(), DeviceError> {
         let engine = unwrap_async_file_engine_or_return!(&mut self.disk.file_engine);
 
-        if let Err(err) = engine.completion_evt().read() {
-            error!("Failed to get async completion event: {:?}", err);
-        } else {
-            self.process_async_completion_queue();
-
-            if self.is_io_engine_throttled {
-                self.is_io_engine_throttled = false;
-                self.process_queue(0);
+        engine
+            .completion_evt()
+            .read()
+            .map_err(DeviceError::EventFd)?;
+        self.process_async_completion_queue()?;
+
+        let result = (0..self.queues.len()).try_for_each(|queue_index| {
+            if self.is_io_engine_throttled[queue_index] {
+                self.is_io_engine_throttled[queue_index] = false;
+                self.process_queue(queue_index)
+            } else {
+                Ok(())
             }
-        }
+        });
+        Self::submit_io_engine(&mut self.disk, &self.metrics);
+        result
     }
 
     /// Update the backing file and the config space of the block device.
+    ///
+    /// The new backing file can be larger than the previous one, in which case the guest is
+    /// notified of the new capacity of the disk, but not smaller.
     pub fn update_disk_image(&mut self, disk_image_path: String) -> Result<(), VirtioBlockError> {
-        self.disk.update(disk_image_path, self.read_only)?;
-        self.config_space = self.disk.virtio_block_config_space();
+        let nsectors = self.disk.nsectors;
+        if let Err(err) = self.disk.update(disk_image_path, self.read_only) {
+            self.metrics.update_fails.inc();
+            return Err(err);
+        }
+        self.config_space = self
+            .disk
+            .virtio_block_config_space(self.num_queues.unwrap_or(BLOCK_NUM_QUEUES));
 
         // Kick the driver to pick up the changes.
         self.irq_trigger.trigger_irq(IrqType::Config).unwrap();
 
+        if self.disk.nsectors != nsectors {
+            self.metrics.capacity_change_count.inc();
+        }
+        self.metrics.update_count.inc();
+        Ok(())
+    }
+
+    /// Make the drive read-only, or keep it read-only.
+    ///
+    /// The guest already negotiated the features of the device, so `VIRTIO_BLK_F_RO` can't be
+    /// offered anymore. Instead, the requests modifying the disk fail from now on, while the ones
+    /// already submitted to the IO engine complete. Making a read-only drive writable again is
+    /// not supported.
+    pub fn update_read_only(&mut self, read_only: bool) -> Result<(), VirtioBlockError> {
+        if self.read_only && !read_only {
+            self.metrics.update_fails.inc();
+            return Err(VirtioBlockError::ReadWriteUpdate);
+        }
+        if read_only && !self.read_only {
+            self.read_only = true;
+            self.disk.read_only = true;
+            if self.is_activated() {
+                self.irq_trigger
+                    .trigger_irq(IrqType::Config)
+                    .map_err(VirtioBlockError::IrqTrigger)?;
+            }
+        }
         self.metrics.update_count.inc();
         Ok(())
     }
 
-    /// Updates the parameters for the rate limiter
+    /// Retries the requests kept pending since the host file system ran out of space, e.g. once
+    /// the file system was grown, then resumes the processing of the queues.
+    ///
+    /// The requests in flight in the IO engine are completed first, since they may run out of
+    /// space as well. If a request runs out of space again, it and the ones after it stay pending,
+    /// and the queues stay stalled.
+    pub fn retry_pending_requests(&mut self) -> Result<(), VirtioBlockError> {
+        if !self.is_out_of_space() {
+            return Ok(());
+        }
+        info!(
+            "Block {}: retrying {} pending requests",
+            self.id,
+            self.retained_requests.len()
+        );
+        if let FileEngine::Async(_) = self.disk.file_engine {
+            self.disk
+                .file_engine
+                .drain(false)
+                .map_err(VirtioBlockError::FileEngine)?;
+            self.process_async_completion_queue()
+                .map_err(VirtioBlockError::RetryPending)?;
+        }
+
+        // There are only pending requests once the device is activated.
+        let mem = self.device_state.mem().unwrap();
+        let mut retained = std::mem::take(&mut self.retained_requests).into_iter();
+        while let Some(pending) = retained.next() {
+            if matches!(&self.disk.file_engine, FileEngine::Async(engine)
+                if engine.is_submission_queue_full().unwrap_or(false))
+            {
+                Self::submit_io_engine(&mut self.disk, &self.metrics);
+            }
+            self.metrics.host_enospc_retries.inc();
+            let queue_index = pending.queue_index();
+            match pending.clone().retry(&mut self.disk, mem, &self.metrics) {
+                ProcessingResult::Submitted => {}
+                ProcessingResult::Executed(finished) => self.used_batches[queue_index]
+                    .push((finished.desc_idx, finished.num_bytes_to_mem)),
+                // The requests in flight were completed, so this only happens if the backing
+                // file runs out of space right away.
+                result @ (ProcessingResult::Throttled | ProcessingResult::OutOfSpace(..)) => {
+                    if let ProcessingResult::OutOfSpace(..) = result {
+                        self.metrics.host_enospc_count.inc();
+                    }
+                    self.retained_requests.push(pending);
+                    self.retained_requests.extend(retained);
+                    warn!(
+                        "Block {}: the host file system is still out of space, {} requests stay \
+                         pending",
+                        self.id,
+                        self.retained_requests.len()
+                    );
+                    break;
+                }
+            }
+        }
+        Self::submit_io_engine(&mut self.disk, &self.metrics);
+        self.flush_used_batches()
+            .map_err(VirtioBlockError::RetryPending)?;
+
+        if !self.is_out_of_space() {
+            (0..self.queues.len())
+                .try_for_each(|queue_index| self.process_queue(queue_index))
+                .map_err(VirtioBlockError::RetryPending)?;
+            Self::submit_io_engine(&mut self.disk, &self.metrics);
+        }
+        Ok(())
+    }
+
+    /// Updates the parameters for the rate limiters
     pub fn update_rate_limiter(&mut self, bytes: BucketUpdate, ops: BucketUpdate) {
-        self.rate_limiter.update_buckets(bytes, ops);
+        for rate_limiter in &mut self.rate_limiters {
+            rate_limiter.update_buckets(bytes.clone(), ops.clone());
+        }
     }
 
     /// Retrieve the file engine type.
@@ -577,6 +1373,79 @@ impl VirtioBlock {
             self.process_async_completion_queue();
         }
     }
+
+    /// Stops the device before it is unplugged from the microVM. The requests in flight in the IO
+    /// engine are completed, and the data written is flushed with `CacheType::Writeback`. The
+    /// requests kept pending since the host ran out of space, and the ones left in the queues,
+    /// aren't executed: they are handed back to the driver with the `VIRTIO_BLK_S_IOERR` status,
+    /// so that it doesn't wait for them. The driver is then asked to reset the device, which
+    /// doesn't process its events anymore.
+    pub fn quiesce(&mut self) {
+        let Some(mem) = self.device_state.mem().cloned() else {
+            return;
+        };
+        let res = match self.cache_type {
+            CacheType::Unsafe => self.disk.file_engine.drain(false),
+            CacheType::Writeback => self.disk.file_engine.drain_and_flush(false),
+        };
+        if let Err(err) = res {
+            error!(
+                "Block {}: failed to drain the IO engine: {:?}",
+                self.id, err
+            );
+        }
+        if let FileEngine::Async(_) = self.disk.file_engine {
+            if let Err(err) = self.process_async_completion_queue() {
+                error!(
+                    "Block {}: failed to complete the requests: {}",
+                    self.id, err
+                );
+            }
+        }
+
+        let mut used_any = false;
+        // The requests kept pending since the host file system ran out of space fail as well.
+        for pending in std::mem::take(&mut self.retained_requests) {
+            let len = pending.abort(&mem);
+            let queue = &mut self.queues[pending.queue_index()];
+            if let Err(err) = queue.add_used(&mem, pending.desc_idx(), len) {
+                error!(
+                    "Block {}: failed to return a descriptor chain: {}",
+                    self.id, err
+                );
+                continue;
+            }
+            used_any = true;
+        }
+        for queue in self.queues.iter_mut().filter(|queue| queue.ready) {
+            while let Some(head) = queue.pop(&mem) {
+                let len = match Request::parse(
+                    &head,
+                    &mem,
+                    self.disk.nsectors,
+                    self.disk.topology.logical_block_size(),
+                ) {
+                    Ok(request) => request.abort(&mem),
+                    Err(_) => 0,
+                };
+                if let Err(err) = queue.add_used(&mem, head.index, len) {
+                    error!(
+                        "Block {}: failed to return a descriptor chain: {}",
+                        self.id, err
+                    );
+                    break;
+                }
+                used_any = true;
+            }
+        }
+        if used_any {
+            let _ = self.irq_trigger.trigger_irq(IrqType::Vring);
+        }
+
+        self.needs_reset = true;
+        self.device_state = DeviceState::Inactive;
+        let _ = self.irq_trigger.trigger_irq(IrqType::Config);
+    }
 }
 
 impl VirtioDevice for VirtioBlock {
@@ -592,6 +1461,10 @@ impl VirtioDevice for VirtioBlock {
         self.acked_features = acked_features;
     }
 
+    fn virtio_features_pin(&self) -> Option<u64> {
+        self.virtio_features_pin
+    }
+
     fn device_type(&self) -> u32 {
         TYPE_BLOCK
     }
@@ -662,6 +1535,10 @@ impl VirtioDevice for VirtioBlock {
     fn is_activated(&self) -> bool {
         self.device_state.is_activated()
     }
+
+    fn needs_reset(&self) -> bool {
+        self.needs_reset
+    }
 }
 
 impl Drop for VirtioBlock {
@@ -684,21 +1561,29 @@ mod tests {
     use std::fs::metadata;
     use std::io::Read;
     use std::os::unix::ffi::OsStrExt;
+    use std::sync::atomic::Ordering;
+    use std::sync::Mutex;
     use std::time::Duration;
     use std::{thread, u32};
 
+    use utils::byte_order::{read_le_u16, read_le_u32, read_le_u64, write_le_u32};
     use utils::skip_if_io_uring_unsupported;
     use utils::tempfile::TempFile;
 
     use super::*;
     use crate::check_metric_after_block;
     use crate::devices::virtio::block::virtio::test_utils::{
-        default_block, default_engine_type_for_kv, read_blk_req_descriptors, set_queue,
-        set_rate_limiter, simulate_async_completion_event,
+        default_block, default_block_with_path, default_engine_type_for_kv,
+        read_blk_req_descriptors, set_queue, set_rate_limiter, simulate_async_completion_event,
         simulate_queue_and_async_completion_events, simulate_queue_event,
     };
     use crate::devices::virtio::block::virtio::IO_URING_NUM_ENTRIES;
-    use crate::devices::virtio::queue::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+    use crate::devices::virtio::device::QueueSizeError;
+    use crate::devices::virtio::device_status;
+    use crate::devices::virtio::mmio::MmioTransport;
+    use crate::devices::virtio::queue::{
+        VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE, VIRTQ_USED_F_NO_NOTIFY,
+    };
     use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
     use crate::rate_limiter::TokenType;
     use crate::vstate::memory::{Address, Bytes, GuestAddress};
@@ -714,9 +1599,22 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: Default::default(),
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         VirtioBlockConfig::try_from(&block_config).unwrap();
 
@@ -729,9 +1627,22 @@ mod tests {
             is_read_only: None,
             path_on_host: None,
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: Default::default(),
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: Some("sock".to_string()),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         VirtioBlockConfig::try_from(&block_config).unwrap_err();
 
@@ -744,60 +1655,255 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: Default::default(),
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: Some("sock".to_string()),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         VirtioBlockConfig::try_from(&block_config).unwrap_err();
     }
 
     #[test]
-    fn test_disk_backing_file_helper() {
-        let num_sectors = 2;
-        let f = TempFile::new().unwrap();
-        let size = u64::from(SECTOR_SIZE) * num_sectors;
-        f.as_file().set_len(size).unwrap();
+    fn test_detect_zeroes() {
+        assert!(!DetectZeroes::Off.should_scan(u32::MAX));
+        assert!(DetectZeroes::Unmap.should_scan(SECTOR_SIZE));
+        assert!(!DetectZeroes::Nonzero.should_scan(DETECT_ZEROES_MIN_SCAN_LEN - SECTOR_SIZE));
+        assert!(DetectZeroes::Nonzero.should_scan(DETECT_ZEROES_MIN_SCAN_LEN));
 
-        let disk_properties = DiskProperties::new(
-            String::from(f.as_path().to_str().unwrap()),
-            true,
-            default_engine_type_for_kv(),
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let path = f.as_path().to_str().unwrap().to_string();
+
+        let disk = DiskProperties::new(
+            path.clone(),
+            false,
+            FileEngineType::Sync,
+            DetectZeroes::Unmap,
+            false,
+            IoUringConfig::default(),
+            None,
+            BlockTopology::default(),
         )
         .unwrap();
+        assert_eq!(disk.detect_zeroes, DetectZeroes::Unmap);
+
+        // Zero detection is only implemented by the Sync engine.
+        assert!(matches!(
+            DiskProperties::new(
+                path,
+                false,
+                FileEngineType::Async,
+                DetectZeroes::Nonzero,
+                false,
+                IoUringConfig::default(),
+                None,
+                BlockTopology::default(),
+            ),
+            Err(VirtioBlockError::DetectZeroesEngine(FileEngineType::Async))
+        ));
+    }
 
-        assert_eq!(size, u64::from(SECTOR_SIZE) * num_sectors);
-        assert_eq!(disk_properties.nsectors, num_sectors);
-        let cfg = disk_properties.virtio_block_config_space();
-        assert_eq!(cfg.len(), BLOCK_CONFIG_SPACE_SIZE);
-        for (i, byte) in cfg.iter().enumerate() {
-            assert_eq!(*byte, ((num_sectors >> (8 * i)) & 0xff) as u8);
+    #[test]
+    fn test_io_uring_config() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let base_block = default_block_with_path(
+            f.as_path().to_str().unwrap().to_string(),
+            FileEngineType::Sync,
+        );
+        assert_eq!(base_block.config().io_uring, IoUringConfig::default());
+        assert_eq!(BlockDeviceConfig::from(base_block.config()).io_uring, None);
+
+        // The ring is only set up by the Async engine.
+        let mut config = base_block.config();
+        config.io_uring.sqpoll = true;
+        assert!(matches!(
+            VirtioBlock::new(config),
+            Err(VirtioBlockError::IoUringEngine(FileEngineType::Sync))
+        ));
+
+        // The ring size must be a power of two within bounds.
+        for ring_size in [0, 8, 100, 8192] {
+            let mut config = base_block.config();
+            config.file_engine_type = FileEngineType::Async;
+            config.io_uring.ring_size = ring_size;
+            assert!(
+                matches!(
+                    VirtioBlock::new(config),
+                    Err(VirtioBlockError::InvalidRingSize(size)) if size == ring_size
+                ),
+                "{ring_size}"
+            );
         }
-        // Testing `backing_file.virtio_block_disk_image_id()` implies
-        // duplicating that logic in tests, so skipping it.
 
-        let res = DiskProperties::new(
-            "invalid-disk-path".to_string(),
-            true,
-            default_engine_type_for_kv(),
-        );
-        assert!(
-            matches!(res, Err(VirtioBlockError::BackingFile(_, _))),
-            "{:?}",
-            res
-        );
+        skip_if_io_uring_unsupported!();
+        let mut config = base_block.config();
+        config.file_engine_type = FileEngineType::Async;
+        config.io_uring.ring_size = 512;
+        let block = VirtioBlock::new(config).unwrap();
+        assert_eq!(block.config().io_uring.ring_size, 512);
+        let config = BlockDeviceConfig::from(block.config());
+        assert_eq!(config.io_uring.unwrap().ring_size, 512);
     }
 
     #[test]
-    fn test_virtio_features() {
-        let mut block = default_block(default_engine_type_for_kv());
-
-        assert_eq!(block.device_type(), TYPE_BLOCK);
+    fn test_open_direct() {
+        use std::os::unix::fs::FileExt;
 
-        let features: u64 = (1u64 << VIRTIO_F_VERSION_1) | (1u64 << VIRTIO_RING_F_EVENT_IDX);
-
-        assert_eq!(
-            block.avail_features_by_page(0),
-            (features & 0xffffffff) as u32,
+        let f = TempFile::new().unwrap();
+        f.as_file().write_all_at(&[0xaa; 0x1000], 0).unwrap();
+        let mut config = default_block_with_path(
+            f.as_path().to_str().unwrap().to_string(),
+            FileEngineType::Sync,
+        )
+        .config();
+        config.open_direct = true;
+        config.file_engine_type = default_engine_type_for_kv();
+        let mut block = match VirtioBlock::new(config) {
+            Ok(block) => block,
+            // The file system of the backing file doesn't support `O_DIRECT`.
+            Err(VirtioBlockError::BackingFile(err, _))
+                if err.raw_os_error() == Some(libc::EINVAL) =>
+            {
+                return;
+            }
+            Err(err) => panic!("{err}"),
+        };
+        // Use private metrics, so that the other tests do not interfere with the checks below.
+        block.metrics = Arc::new(BlockDeviceMetrics::new());
+        assert!(block.config().open_direct);
+        assert_eq!(
+            BlockDeviceConfig::from(block.config()).open_direct,
+            Some(true)
+        );
+
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        block.activate(mem.clone()).unwrap();
+
+        // Reads the second sector of the disk into the guest memory at `data_addr`.
+        let read_sector = |block: &mut VirtioBlock, data_addr: u64| {
+            mem.write_obj(RequestHeader::new(VIRTIO_BLK_T_IN, 1), GuestAddress(0x1000))
+                .unwrap();
+            vq.dtable[0].set(0x1000, 16, VIRTQ_DESC_F_NEXT, 1);
+            vq.dtable[1].set(data_addr, 512, VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE, 2);
+            vq.dtable[2].set(0x1800, 1, VIRTQ_DESC_F_WRITE, 0);
+            vq.avail.ring[0].set(0);
+            vq.avail.idx.set(1);
+            vq.used.idx.set(0);
+            set_queue(block, 0, vq.create_queue());
+            simulate_queue_and_async_completion_events(block, true);
+            assert_eq!(vq.used.idx.get(), 1);
+            assert_eq!(
+                u32::from(mem.read_obj::<u8>(GuestAddress(0x1800)).unwrap()),
+                VIRTIO_BLK_S_OK
+            );
+            let mut data = [0u8; 512];
+            mem.read_slice(&mut data, GuestAddress(data_addr)).unwrap();
+            assert_eq!(data, [0xaa; 512]);
+        };
+
+        // Aligned guest buffers are read in place.
+        read_sector(&mut block, 0x2000);
+        assert_eq!(block.metrics.bounce_buffer_count.count(), 0);
+        // The other ones go through a bounce buffer.
+        read_sector(&mut block, 0x2100);
+        assert_eq!(block.metrics.bounce_buffer_count.count(), 1);
+    }
+
+    #[test]
+    fn test_disk_backing_file_helper() {
+        let num_sectors = 2;
+        let f = TempFile::new().unwrap();
+        let size = u64::from(SECTOR_SIZE) * num_sectors;
+        f.as_file().set_len(size).unwrap();
+
+        let disk_properties = DiskProperties::new(
+            String::from(f.as_path().to_str().unwrap()),
+            true,
+            default_engine_type_for_kv(),
+            DetectZeroes::Off,
+            false,
+            IoUringConfig::default(),
+            None,
+            BlockTopology::default(),
+        )
+        .unwrap();
+
+        assert_eq!(size, u64::from(SECTOR_SIZE) * num_sectors);
+        assert_eq!(disk_properties.nsectors, num_sectors);
+        let cfg = disk_properties.virtio_block_config_space(4);
+        assert_eq!(cfg.len(), BLOCK_CONFIG_SPACE_SIZE);
+        assert_eq!(read_le_u64(&cfg[CONFIG_CAPACITY..]), num_sectors);
+        assert_eq!(read_le_u16(&cfg[CONFIG_NUM_QUEUES..]), 4);
+        assert_eq!(
+            read_le_u32(&cfg[CONFIG_MAX_DISCARD_SECTORS..]),
+            BLOCK_MAX_DISCARD_SECTORS
+        );
+        assert_eq!(
+            read_le_u32(&cfg[CONFIG_MAX_DISCARD_SEG..]),
+            BLOCK_MAX_DISCARD_SEGMENTS
+        );
+        assert_eq!(
+            read_le_u32(&cfg[CONFIG_DISCARD_SECTOR_ALIGNMENT..]),
+            BLOCK_DISCARD_SECTOR_ALIGNMENT
+        );
+        assert_eq!(
+            read_le_u32(&cfg[CONFIG_MAX_WRITE_ZEROES_SECTORS..]),
+            BLOCK_MAX_DISCARD_SECTORS
+        );
+        assert_eq!(
+            read_le_u32(&cfg[CONFIG_MAX_WRITE_ZEROES_SEG..]),
+            BLOCK_MAX_DISCARD_SEGMENTS
+        );
+        assert_eq!(cfg[CONFIG_WRITE_ZEROES_MAY_UNMAP], 1);
+        // Testing `backing_file.virtio_block_disk_image_id()` implies
+        // duplicating that logic in tests, so skipping it.
+
+        let res = DiskProperties::new(
+            "invalid-disk-path".to_string(),
+            true,
+            default_engine_type_for_kv(),
+            DetectZeroes::Off,
+            false,
+            IoUringConfig::default(),
+            None,
+            BlockTopology::default(),
+        );
+        assert!(
+            matches!(res, Err(VirtioBlockError::BackingFile(_, _))),
+            "{:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_virtio_features() {
+        let mut block = default_block(default_engine_type_for_kv());
+
+        assert_eq!(block.device_type(), TYPE_BLOCK);
+
+        let features: u64 = (1u64 << VIRTIO_F_VERSION_1)
+            | (1u64 << VIRTIO_RING_F_EVENT_IDX)
+            | (1u64 << VIRTIO_RING_F_INDIRECT_DESC)
+            | (1u64 << VIRTIO_BLK_F_DISCARD)
+            | (1u64 << VIRTIO_BLK_F_WRITE_ZEROES);
+
+        assert_eq!(
+            block.avail_features_by_page(0),
+            (features & 0xffffffff) as u32,
         );
         assert_eq!(block.avail_features_by_page(1), (features >> 32) as u32);
 
@@ -815,18 +1921,21 @@ mod tests {
     fn test_virtio_read_config() {
         let block = default_block(default_engine_type_for_kv());
 
-        let mut actual_config_space = [0u8; BLOCK_CONFIG_SPACE_SIZE];
+        let mut actual_config_space = [0u8; 8];
         block.read_config(0, &mut actual_config_space);
         // This will read the number of sectors.
         // The block's backing file size is 0x1000, so there are 8 (4096/512) sectors.
         // The config space is little endian.
-        let expected_config_space: [u8; BLOCK_CONFIG_SPACE_SIZE] =
-            [0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let expected_config_space = [0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
         assert_eq!(actual_config_space, expected_config_space);
 
+        // The maximum number of sectors of a discard request.
+        let mut max_discard_sectors = [0u8; 4];
+        block.read_config(CONFIG_MAX_DISCARD_SECTORS as u64, &mut max_discard_sectors);
+        assert_eq!(read_le_u32(&max_discard_sectors), BLOCK_MAX_DISCARD_SECTORS);
+
         // Invalid read.
-        let expected_config_space: [u8; BLOCK_CONFIG_SPACE_SIZE] =
-            [0xd, 0xe, 0xa, 0xd, 0xb, 0xe, 0xe, 0xf];
+        let expected_config_space = [0xd, 0xe, 0xa, 0xd, 0xb, 0xe, 0xe, 0xf];
         actual_config_space = expected_config_space;
         block.read_config(BLOCK_CONFIG_SPACE_SIZE as u64 + 1, &mut actual_config_space);
 
@@ -838,11 +1947,10 @@ mod tests {
     fn test_virtio_write_config() {
         let mut block = default_block(default_engine_type_for_kv());
 
-        let expected_config_space: [u8; BLOCK_CONFIG_SPACE_SIZE] =
-            [0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let expected_config_space = [0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
         block.write_config(0, &expected_config_space);
 
-        let mut actual_config_space = [0u8; BLOCK_CONFIG_SPACE_SIZE];
+        let mut actual_config_space = [0u8; 8];
         block.read_config(0, &mut actual_config_space);
         assert_eq!(actual_config_space, expected_config_space);
 
@@ -856,8 +1964,11 @@ mod tests {
 
         // Invalid write.
         let new_config_space = [0xd, 0xe, 0xa, 0xd, 0xb, 0xe, 0xe, 0xf];
-        block.write_config(5, &new_config_space);
+        block.write_config(BLOCK_CONFIG_SPACE_SIZE as u64 - 5, &new_config_space);
         // Make sure nothing got written.
+        let mut tail = [0u8; 8];
+        block.read_config(BLOCK_CONFIG_SPACE_SIZE as u64 - 8, &mut tail);
+        assert_eq!(tail, [1, 0, 0, 0, 1, 0, 0, 0]);
         block.read_config(0, &mut actual_config_space);
         assert_eq!(actual_config_space, expected_config_space);
 
@@ -1433,6 +2544,238 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_flush_coalescing() {
+        let mut block = default_block(default_engine_type_for_kv());
+        // Use private metrics, so that the other tests do not interfere with the checks below.
+        block.metrics = Arc::new(BlockDeviceMetrics::new());
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        block.activate(mem.clone()).unwrap();
+
+        // Nothing was written to the disk, so the flushes complete without syncing it.
+        let requests = [VIRTIO_BLK_T_FLUSH, VIRTIO_BLK_T_IN, VIRTIO_BLK_T_FLUSH];
+        add_requests_batch(&mut block, &vq, &requests);
+        simulate_queue_and_async_completion_events(&mut block, true);
+        check_requests_batch(&vq, &requests);
+        assert_eq!(block.metrics.flush_count.count(), 2);
+        assert_eq!(block.metrics.flush_skipped_count.count(), 2);
+        assert!(!block.disk.file_engine.is_dirty());
+
+        // Each flush completes after the writes taken from the queue before it.
+        let requests = [
+            VIRTIO_BLK_T_OUT,
+            VIRTIO_BLK_T_FLUSH,
+            VIRTIO_BLK_T_OUT,
+            VIRTIO_BLK_T_FLUSH,
+            VIRTIO_BLK_T_FLUSH,
+        ];
+        add_requests_batch(&mut block, &vq, &requests);
+        simulate_queue_and_async_completion_events(&mut block, true);
+        let completed = check_requests_batch(&vq, &requests);
+        for (pos, index) in completed.iter().enumerate() {
+            if requests[*index] == VIRTIO_BLK_T_FLUSH {
+                assert!((0..*index)
+                    .filter(|i| requests[*i] == VIRTIO_BLK_T_OUT)
+                    .all(|i| completed[..pos].contains(&i)));
+            }
+        }
+        assert!(!block.disk.file_engine.is_dirty());
+        assert_eq!(block.metrics.flush_count.count(), 5);
+        match block.disk.file_engine {
+            // The last flush finds nothing left to sync.
+            FileEngine::Sync(_) => assert_eq!(block.metrics.flush_skipped_count.count(), 3),
+            // The 3 flushes are completed by a single `fsync`.
+            FileEngine::Async(_) => {
+                assert_eq!(block.metrics.flush_skipped_count.count(), 2);
+                assert_eq!(block.metrics.submissions_per_enter.le_4.count(), 1);
+            }
+        }
+
+        // The data written is synced, so flushing again is free.
+        let skipped = block.metrics.flush_skipped_count.count();
+        add_requests_batch(&mut block, &vq, &[VIRTIO_BLK_T_FLUSH]);
+        simulate_queue_and_async_completion_events(&mut block, true);
+        check_requests_batch(&vq, &[VIRTIO_BLK_T_FLUSH]);
+        assert_eq!(block.metrics.flush_count.count(), 6);
+        assert_eq!(block.metrics.flush_skipped_count.count(), skipped + 1);
+    }
+
+    #[test]
+    fn test_discard_write_zeroes() {
+        use std::os::unix::fs::FileExt;
+
+        // The backing file holds 32 sectors of data.
+        let f = TempFile::new().unwrap();
+        f.as_file().write_all_at(&[0xaa; 0x4000], 0).unwrap();
+        f.as_file().sync_all().unwrap();
+        let mut block = default_block_with_path(
+            f.as_path().to_str().unwrap().to_string(),
+            default_engine_type_for_kv(),
+        );
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        read_blk_req_descriptors(&vq);
+
+        let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+        let data_addr = GuestAddress(vq.dtable[1].addr.get());
+        let status_addr = GuestAddress(vq.dtable[2].addr.get());
+        vq.dtable[1].flags.set(VIRTQ_DESC_F_NEXT);
+        vq.dtable[1]
+            .len
+            .set(u32::try_from(std::mem::size_of::<DiscardWriteZeroesSegment>()).unwrap());
+
+        let allocated = |block: &VirtioBlock| {
+            block
+                .disk
+                .file_engine
+                .file()
+                .metadata()
+                .unwrap()
+                .st_blocks()
+                * 512
+        };
+        let read_back = |offset: u64| {
+            let mut buf = vec![0u8; 0x1000];
+            f.as_file().read_exact_at(&mut buf, offset).unwrap();
+            buf
+        };
+        // Sends a request with the given segment, returning its status.
+        let send =
+            |block: &mut VirtioBlock, request_type: u32, segment: DiscardWriteZeroesSegment| {
+                vq.used.idx.set(0);
+                set_queue(block, 0, vq.create_queue());
+                mem.write_obj::<u32>(request_type, request_type_addr)
+                    .unwrap();
+                mem.write_obj::<DiscardWriteZeroesSegment>(segment, data_addr)
+                    .unwrap();
+
+                simulate_queue_and_async_completion_events(block, true);
+                assert_eq!(vq.used.idx.get(), 1);
+                assert_eq!(vq.used.ring[0].get().id, 0);
+                assert_eq!(vq.used.ring[0].get().len, 1);
+                u32::from(mem.read_obj::<u8>(status_addr).unwrap())
+            };
+
+        let allocated_before = allocated(&block);
+        assert!(allocated_before >= 0x4000);
+
+        // A guest discard releases the blocks of the range from the backing file.
+        let segment = DiscardWriteZeroesSegment::new(8, 8, 0);
+        assert_eq!(
+            send(&mut block, VIRTIO_BLK_T_DISCARD, segment),
+            VIRTIO_BLK_S_OK
+        );
+        assert_eq!(allocated(&block), allocated_before - 0x1000);
+
+        // Zeroing a range while unmapping it releases its blocks too.
+        let segment = DiscardWriteZeroesSegment::new(16, 8, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP);
+        assert_eq!(
+            send(&mut block, VIRTIO_BLK_T_WRITE_ZEROES, segment),
+            VIRTIO_BLK_S_OK
+        );
+        assert_eq!(allocated(&block), allocated_before - 0x2000);
+        assert_eq!(read_back(0x2000), vec![0u8; 0x1000]);
+
+        // Zeroing a range without unmapping it.
+        let segment = DiscardWriteZeroesSegment::new(24, 8, 0);
+        assert_eq!(
+            send(&mut block, VIRTIO_BLK_T_WRITE_ZEROES, segment),
+            VIRTIO_BLK_S_OK
+        );
+        assert_eq!(read_back(0x3000), vec![0u8; 0x1000]);
+        assert_eq!(read_back(0), vec![0xaa; 0x1000]);
+        assert_eq!(block.metrics.discard_count.count(), 1);
+        assert_eq!(block.metrics.write_zeroes_count.count(), 2);
+
+        // Discards can't ask for unmapping.
+        let segment = DiscardWriteZeroesSegment::new(0, 8, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP);
+        assert_eq!(
+            send(&mut block, VIRTIO_BLK_T_DISCARD, segment),
+            VIRTIO_BLK_S_UNSUPP
+        );
+
+        // The range goes past the end of the disk.
+        let segment = DiscardWriteZeroesSegment::new(30, 4, 0);
+        assert_eq!(
+            send(&mut block, VIRTIO_BLK_T_WRITE_ZEROES, segment),
+            VIRTIO_BLK_S_IOERR
+        );
+        assert_eq!(read_back(0), vec![0xaa; 0x1000]);
+    }
+
+    #[test]
+    fn test_write_detect_zeroes() {
+        const DATA_LEN: u32 = 0x8000;
+
+        // The backing file is sparse, without any data allocated.
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x10000).unwrap();
+        let mut block = default_block_with_path(
+            f.as_path().to_str().unwrap().to_string(),
+            FileEngineType::Sync,
+        );
+        block.disk.detect_zeroes = DetectZeroes::Unmap;
+        // Use private metrics, so that the other tests do not interfere with the checks below.
+        block.metrics = Arc::new(BlockDeviceMetrics::new());
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        read_blk_req_descriptors(&vq);
+
+        let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+        let status_addr = GuestAddress(vq.dtable[2].addr.get());
+        let data_addr = GuestAddress(0x4000);
+        vq.dtable[1].addr.set(data_addr.0);
+        vq.dtable[1].flags.set(VIRTQ_DESC_F_NEXT);
+        vq.dtable[1].len.set(DATA_LEN);
+        mem.write_obj::<u32>(VIRTIO_BLK_T_OUT, request_type_addr)
+            .unwrap();
+
+        let allocated = |block: &VirtioBlock| {
+            block
+                .disk
+                .file_engine
+                .file()
+                .metadata()
+                .unwrap()
+                .st_blocks()
+                * 512
+        };
+        let write = |block: &mut VirtioBlock, byte: u8| {
+            mem.write_slice(&[byte; DATA_LEN as usize], data_addr)
+                .unwrap();
+            vq.used.idx.set(0);
+            set_queue(block, 0, vq.create_queue());
+            simulate_queue_and_async_completion_events(block, true);
+            assert_eq!(vq.used.idx.get(), 1);
+            assert_eq!(
+                u32::from(mem.read_obj::<u8>(status_addr).unwrap()),
+                VIRTIO_BLK_S_OK
+            );
+        };
+        let allocated_before = allocated(&block);
+
+        // Writing zeroes doesn't allocate any data.
+        write(&mut block, 0);
+        assert_eq!(allocated(&block), allocated_before);
+
+        // Overwriting data with zeroes releases its blocks.
+        write(&mut block, 0xaa);
+        assert!(allocated(&block) >= allocated_before + u64::from(DATA_LEN));
+        write(&mut block, 0);
+        assert_eq!(allocated(&block), allocated_before);
+
+        assert_eq!(block.metrics.write_bytes.count(), 3 * u64::from(DATA_LEN));
+        assert_eq!(
+            block.metrics.unmapped_bytes.count(),
+            2 * u64::from(DATA_LEN)
+        );
+    }
+
     #[test]
     fn test_get_device_id() {
         let mut block = default_block(default_engine_type_for_kv());
@@ -1495,11 +2838,197 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_serial() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let path = f.as_path().to_str().unwrap().to_string();
+        let disk_with_serial = |serial: &str| {
+            DiskProperties::new(
+                path.clone(),
+                false,
+                FileEngineType::Sync,
+                DetectZeroes::Off,
+                false,
+                IoUringConfig::default(),
+                Some(serial.to_string()),
+                BlockTopology::default(),
+            )
+        };
+
+        // Serials have to be non-empty, short enough for the ID and printable ASCII.
+        for serial in ["", "a".repeat(21).as_str(), "disk 1", "disk\n", "dîsk"] {
+            assert!(
+                matches!(
+                    disk_with_serial(serial),
+                    Err(VirtioBlockError::InvalidSerial(ref s)) if s == serial
+                ),
+                "{serial:?}"
+            );
+        }
+
+        // The ID is the serial, padded with zeroes.
+        let disk = disk_with_serial("disk-1").unwrap();
+        assert_eq!(&disk.image_id[..6], b"disk-1");
+        assert!(disk.image_id[6..].iter().all(|&b| b == 0));
+        // A serial of the maximum length fills the whole ID.
+        let disk = disk_with_serial(&"x".repeat(20)).unwrap();
+        assert_eq!(disk.image_id, [b'x'; VIRTIO_BLK_ID_BYTES as usize]);
+
+        // The serial is part of the device config, and outlives updates of the backing file.
+        let mut config = default_block_with_path(path.clone(), FileEngineType::Sync).config();
+        config.serial = Some("disk-1".to_string());
+        let mut block = VirtioBlock::new(config).unwrap();
+        assert_eq!(block.config().serial.as_deref(), Some("disk-1"));
+        let other = TempFile::new().unwrap();
+        other.as_file().set_len(0x1000).unwrap();
+        block
+            .update_disk_image(other.as_path().to_str().unwrap().to_string())
+            .unwrap();
+        assert_eq!(&block.disk.image_id[..7], b"disk-1\0");
+        assert_eq!(block.config().serial.as_deref(), Some("disk-1"));
+    }
+
+    #[test]
+    fn test_block_topology() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x4000).unwrap();
+        let base_block = default_block_with_path(
+            f.as_path().to_str().unwrap().to_string(),
+            FileEngineType::Sync,
+        );
+        let topology_features = (1u64 << VIRTIO_BLK_F_BLK_SIZE) | (1u64 << VIRTIO_BLK_F_TOPOLOGY);
+        // Without a configured topology, the features aren't offered and the config space
+        // describes 512 bytes blocks.
+        assert_eq!(base_block.avail_features & topology_features, 0);
+        let mut config_space = [0u8; 8];
+        base_block.read_config(CONFIG_BLK_SIZE as u64, &mut config_space);
+        assert_eq!(
+            config_space,
+            [0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]
+        );
+
+        // The block sizes must be powers of two within bounds.
+        for (logical, physical) in [
+            (Some(256), None),
+            (Some(1000), None),
+            (Some(8192), None),
+            (Some(4096), Some(512)),
+            (None, Some(1 << 17)),
+        ] {
+            let mut config = base_block.config();
+            config.logical_block_size = logical;
+            config.physical_block_size = physical;
+            assert!(
+                matches!(
+                    VirtioBlock::new(config),
+                    Err(VirtioBlockError::InvalidBlockSize(..))
+                ),
+                "{logical:?} {physical:?}"
+            );
+        }
+        // The guest driver would address the disk in 512 bytes sectors without the feature.
+        let mut config = base_block.config();
+        config.logical_block_size = Some(4096);
+        config.virtio_features_pin = Some(base_block.avail_features);
+        assert!(matches!(
+            VirtioBlock::new(config),
+            Err(VirtioBlockError::BlockSizeNotPinned)
+        ));
+
+        let mut config = base_block.config();
+        config.logical_block_size = Some(4096);
+        config.physical_block_size = Some(16384);
+        let mut block = VirtioBlock::new(config).unwrap();
+        assert_eq!(block.avail_features & topology_features, topology_features);
+        assert_eq!(block.config().logical_block_size, Some(4096));
+        assert_eq!(block.config().physical_block_size, Some(16384));
+        // A 4 KiB logical block, spanning 2^2 logical blocks per physical block.
+        block.read_config(CONFIG_BLK_SIZE as u64, &mut config_space);
+        assert_eq!(
+            config_space,
+            [0x00, 0x10, 0x00, 0x00, 0x02, 0x00, 0x04, 0x00]
+        );
+        // The capacity is still in 512 bytes sectors.
+        block.read_config(CONFIG_CAPACITY as u64, &mut config_space);
+        assert_eq!(read_le_u64(&config_space), 0x20);
+
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        read_blk_req_descriptors(&vq);
+        let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+        let status_addr = GuestAddress(vq.dtable[2].addr.get());
+
+        // A read at a sector aligned to 512 bytes but not to 4 KiB is rejected.
+        mem.write_obj(RequestHeader::new(VIRTIO_BLK_T_IN, 1), request_type_addr)
+            .unwrap();
+        simulate_queue_event(&mut block, Some(true));
+        assert_eq!(vq.used.idx.get(), 1);
+        assert_eq!(vq.used.ring[0].get().id, 0);
+        assert_eq!(vq.used.ring[0].get().len, 0);
+
+        // A read of a whole logical block succeeds.
+        vq.used.idx.set(0);
+        set_queue(&mut block, 0, vq.create_queue());
+        mem.write_obj(RequestHeader::new(VIRTIO_BLK_T_IN, 8), request_type_addr)
+            .unwrap();
+        simulate_queue_event(&mut block, Some(true));
+        assert_eq!(vq.used.idx.get(), 1);
+        assert_eq!(vq.used.ring[0].get().len, 0x1001);
+        assert_eq!(mem.read_obj::<u32>(status_addr).unwrap(), VIRTIO_BLK_S_OK);
+    }
+
+    #[test]
+    fn test_get_device_id_serial() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let path = f.as_path().to_str().unwrap().to_string();
+        let mut config = default_block_with_path(path, FileEngineType::Sync).config();
+        let serial = "s".repeat(VIRTIO_BLK_ID_BYTES as usize);
+        config.serial = Some(serial.clone());
+        let mut block = VirtioBlock::new(config).unwrap();
+
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        read_blk_req_descriptors(&vq);
+
+        let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+        let data_addr = GuestAddress(vq.dtable[1].addr.get());
+        let status_addr = GuestAddress(vq.dtable[2].addr.get());
+
+        // A serial of the maximum length fills the ID without a NUL terminator, and nothing
+        // is written past it.
+        vq.dtable[1].len.set(VIRTIO_BLK_ID_BYTES);
+        mem.write_slice(&[0xff; 32], data_addr).unwrap();
+        mem.write_obj::<u32>(VIRTIO_BLK_T_GET_ID, request_type_addr)
+            .unwrap();
+
+        simulate_queue_event(&mut block, Some(true));
+        assert_eq!(vq.used.idx.get(), 1);
+        assert_eq!(vq.used.ring[0].get().len, 21);
+        assert_eq!(mem.read_obj::<u32>(status_addr).unwrap(), VIRTIO_BLK_S_OK);
+
+        let mut buf = [0; 32];
+        mem.read_slice(&mut buf, data_addr).unwrap();
+        assert_eq!(&buf[..VIRTIO_BLK_ID_BYTES as usize], serial.as_bytes());
+        assert!(buf[VIRTIO_BLK_ID_BYTES as usize..]
+            .iter()
+            .all(|&b| b == 0xff));
+    }
+
     fn add_flush_requests_batch(block: &mut VirtioBlock, vq: &VirtQueue, count: u16) {
+        set_queue(block, 0, vq.create_queue());
+        write_flush_requests_batch(vq, count);
+    }
+
+    fn write_flush_requests_batch(vq: &VirtQueue, count: u16) {
         let mem = vq.memory();
         vq.avail.idx.set(0);
         vq.used.idx.set(0);
-        set_queue(block, 0, vq.create_queue());
 
         let hdr_addr = vq
             .end()
@@ -1553,12 +3082,88 @@ mod tests {
         }
     }
 
+    // Puts a chain of 3 descriptors in the queue for each type of `requests`, transferring one
+    // sector at the start of the disk, from or to the same guest memory.
+    fn add_requests_batch(block: &mut VirtioBlock, vq: &VirtQueue, requests: &[u32]) {
+        set_queue(block, 0, vq.create_queue());
+        let mem = vq.memory();
+        vq.avail.idx.set(0);
+        vq.used.idx.set(0);
+
+        let data_addr = vq
+            .end()
+            .checked_align_up(std::mem::align_of::<RequestHeader>() as u64)
+            .unwrap();
+        let mut hdr_addr = data_addr.unchecked_add(u64::from(SECTOR_SIZE));
+
+        for (i, request_type) in requests.iter().enumerate() {
+            let i = u16::try_from(i).unwrap();
+            let idx = i * 3;
+            let status_addr = hdr_addr.unchecked_add(std::mem::size_of::<RequestHeader>() as u64);
+            mem.write_obj(RequestHeader::new(*request_type, 0), hdr_addr)
+                .unwrap();
+            mem.write_obj(0xffu8, status_addr).unwrap();
+
+            let hdr_desc = &vq.dtable[usize::from(idx)];
+            hdr_desc.addr.set(hdr_addr.0);
+            hdr_desc.flags.set(VIRTQ_DESC_F_NEXT);
+            hdr_desc.next.set(idx + 1);
+
+            let data_desc = &vq.dtable[usize::from(idx) + 1];
+            data_desc.addr.set(data_addr.0);
+            data_desc.len.set(SECTOR_SIZE);
+            if *request_type == VIRTIO_BLK_T_IN {
+                data_desc.flags.set(VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE);
+            } else {
+                data_desc.flags.set(VIRTQ_DESC_F_NEXT);
+            }
+            data_desc.next.set(idx + 2);
+
+            let status_desc = &vq.dtable[usize::from(idx) + 2];
+            status_desc.addr.set(status_addr.0);
+            status_desc.flags.set(VIRTQ_DESC_F_WRITE);
+            status_desc.len.set(4);
+
+            vq.avail.ring[usize::from(i)].set(idx);
+            vq.avail.idx.set(i + 1);
+            hdr_addr = status_addr.unchecked_add(8);
+        }
+    }
+
+    // Checks that the requests added by `add_requests_batch` completed successfully, returning
+    // their indices in the order they were added to the used ring.
+    fn check_requests_batch(vq: &VirtQueue, requests: &[u32]) -> Vec<usize> {
+        assert_eq!(vq.used.idx.get(), u16::try_from(requests.len()).unwrap());
+
+        (0..requests.len())
+            .map(|i| {
+                let used = vq.used.ring[i].get();
+                let index = used.id as usize / 3;
+                let status_addr = vq.dtable[used.id as usize + 2].addr.get();
+                if requests[index] == VIRTIO_BLK_T_IN {
+                    assert_eq!(used.len, SECTOR_SIZE + 1);
+                } else {
+                    assert_eq!(used.len, 1);
+                }
+                assert_eq!(
+                    u32::from(
+                        vq.memory()
+                            .read_obj::<u8>(GuestAddress(status_addr))
+                            .unwrap(),
+                    ),
+                    VIRTIO_BLK_S_OK
+                );
+                index
+            })
+            .collect()
+    }
+
     #[test]
     fn test_io_engine_throttling() {
         // skip this test if kernel < 5.10 since in this case the sync engine will be used.
         skip_if_io_uring_unsupported!();
 
-        // FullSQueue BlockError
+        // A full submission queue doesn't throttle the device.
         {
             let mut block = default_block(FileEngineType::Async);
 
@@ -1566,29 +3171,18 @@ mod tests {
             let vq = VirtQueue::new(GuestAddress(0), &mem, IO_URING_NUM_ENTRIES * 4);
             block.activate(mem.clone()).unwrap();
 
-            // Run scenario that doesn't trigger FullSq BlockError: Add sq_size flush requests.
-            add_flush_requests_batch(&mut block, &vq, IO_URING_NUM_ENTRIES);
+            // Add sq_size + 10 read requests. The first sq_size ones are submitted as soon as
+            // the submission queue is full, the remaining 10 ones at the end of the event.
+            let reads = vec![VIRTIO_BLK_T_IN; usize::from(IO_URING_NUM_ENTRIES) + 10];
+            add_requests_batch(&mut block, &vq, &reads);
             simulate_queue_event(&mut block, Some(false));
-            assert!(!block.is_io_engine_throttled);
-            simulate_async_completion_event(&mut block, true);
-            check_flush_requests_batch(IO_URING_NUM_ENTRIES, &vq);
+            assert!(!block.is_io_engine_throttled[0]);
+            assert_eq!(block.metrics.submissions_per_enter.le_256.count(), 1);
+            assert_eq!(block.metrics.submissions_per_enter.le_16.count(), 1);
 
-            // Run scenario that triggers FullSqError : Add sq_size + 10 flush requests.
-            add_flush_requests_batch(&mut block, &vq, IO_URING_NUM_ENTRIES + 10);
-            simulate_queue_event(&mut block, Some(false));
-            assert!(block.is_io_engine_throttled);
-            // When the async_completion_event is triggered:
-            // 1. sq_size requests should be processed processed.
-            // 2. is_io_engine_throttled should be set back to false.
-            // 3. process_queue() should be called again.
             simulate_async_completion_event(&mut block, true);
-            assert!(!block.is_io_engine_throttled);
-            check_flush_requests_batch(IO_URING_NUM_ENTRIES, &vq);
-            // check that process_queue() was called again resulting in the processing of the
-            // remaining 10 ops.
-            simulate_async_completion_event(&mut block, true);
-            assert!(!block.is_io_engine_throttled);
-            check_flush_requests_batch(IO_URING_NUM_ENTRIES + 10, &vq);
+            assert!(!block.is_io_engine_throttled[0]);
+            check_requests_batch(&vq, &reads);
         }
 
         // FullCQueue BlockError
@@ -1601,22 +3195,270 @@ mod tests {
 
             // Run scenario that triggers FullCqError. Push 2 * IO_URING_NUM_ENTRIES and wait for
             // completion. Then try to push another entry.
-            add_flush_requests_batch(&mut block, &vq, IO_URING_NUM_ENTRIES);
+            let reads = vec![VIRTIO_BLK_T_IN; usize::from(IO_URING_NUM_ENTRIES)];
+            add_requests_batch(&mut block, &vq, &reads);
             simulate_queue_event(&mut block, Some(false));
-            assert!(!block.is_io_engine_throttled);
+            assert!(!block.is_io_engine_throttled[0]);
             thread::sleep(Duration::from_millis(150));
-            add_flush_requests_batch(&mut block, &vq, IO_URING_NUM_ENTRIES);
+            add_requests_batch(&mut block, &vq, &reads);
             simulate_queue_event(&mut block, Some(false));
-            assert!(!block.is_io_engine_throttled);
+            assert!(!block.is_io_engine_throttled[0]);
             thread::sleep(Duration::from_millis(150));
 
-            add_flush_requests_batch(&mut block, &vq, 1);
+            add_requests_batch(&mut block, &vq, &reads[..1]);
             simulate_queue_event(&mut block, Some(false));
-            assert!(block.is_io_engine_throttled);
+            assert!(block.is_io_engine_throttled[0]);
+            simulate_async_completion_event(&mut block, true);
+            assert!(!block.is_io_engine_throttled[0]);
+            check_requests_batch(&vq, &[reads.as_slice(), reads.as_slice()].concat());
+        }
+    }
+
+    #[test]
+    fn test_queue_size() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let base_block = default_block_with_path(
+            f.as_path().to_str().unwrap().to_string(),
+            FileEngineType::Sync,
+        );
+
+        // The queue size must be a power of two within the bounds of the block device.
+        for size in [0, 100, 1000, 8, 2048] {
+            let mut config = base_block.config();
+            config.queue_size = Some(size);
+            assert_eq!(
+                VirtioBlock::new(config).unwrap_err().to_string(),
+                VirtioBlockError::QueueSize(QueueSizeError::InvalidSize(size, 16, 1024))
+                    .to_string()
+            );
+        }
+
+        let mut config = base_block.config();
+        config.queue_size = Some(512);
+        let block = Arc::new(Mutex::new(VirtioBlock::new(config).unwrap()));
+        assert_eq!(block.lock().unwrap().config().queue_size, Some(512));
+
+        let mem = default_mem();
+        let mut transport = MmioTransport::new(mem.clone(), block.clone(), false);
+        // The device advertises the configured queue size as the maximal one.
+        let mut buf = [0; 4];
+        transport.bus_read(0x34, &mut buf);
+        assert_eq!(read_le_u32(&buf), 512);
+
+        // The driver sets up a smaller queue, and activates the device.
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 128);
+        let status = device_status::ACKNOWLEDGE | device_status::DRIVER;
+        for (offset, value) in [
+            (0x70, device_status::ACKNOWLEDGE),
+            (0x70, status),
+            (0x70, status | device_status::FEATURES_OK),
+            (0x38, 128),
+            (0x80, u32::try_from(vq.dtable_start().0).unwrap()),
+            (0x90, u32::try_from(vq.avail_start().0).unwrap()),
+            (0xa0, u32::try_from(vq.used_start().0).unwrap()),
+            (0x44, 1),
+            (
+                0x70,
+                status | device_status::FEATURES_OK | device_status::DRIVER_OK,
+            ),
+        ] {
+            write_le_u32(&mut buf, value);
+            transport.bus_write(offset, &buf);
+        }
+
+        let mut block = block.lock().unwrap();
+        assert!(block.is_activated());
+        assert_eq!(block.queues[0].max_size, 512);
+        assert_eq!(block.queues[0].size, 128);
+
+        // The device serves requests on the whole queue set up by the driver.
+        write_flush_requests_batch(&vq, 64);
+        simulate_queue_event(&mut block, Some(true));
+        check_flush_requests_batch(64, &vq);
+    }
+
+    #[test]
+    fn test_multi_queue() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let base_block = default_block_with_path(
+            f.as_path().to_str().unwrap().to_string(),
+            FileEngineType::Sync,
+        );
+        assert_eq!(base_block.queues.len(), 1);
+        assert_eq!(base_block.avail_features & (1u64 << VIRTIO_BLK_F_MQ), 0);
+
+        // The number of queues must be within the bounds of the block device.
+        for num_queues in [0, 17] {
+            let mut config = base_block.config();
+            config.num_queues = Some(num_queues);
+            assert!(matches!(
+                VirtioBlock::new(config),
+                Err(VirtioBlockError::NumQueues(n)) if n == num_queues
+            ));
+        }
+        // The guest driver would only set up the first queue without the multi-queue feature.
+        let mut config = base_block.config();
+        config.num_queues = Some(4);
+        config.virtio_features_pin = Some(base_block.avail_features);
+        assert!(matches!(
+            VirtioBlock::new(config),
+            Err(VirtioBlockError::MultiQueueNotPinned)
+        ));
+
+        let mut config = base_block.config();
+        config.num_queues = Some(4);
+        config.file_engine_type = default_engine_type_for_kv();
+        config.rate_limiter_scope = RateLimiterScope::Queue;
+        let mut block = VirtioBlock::new(config).unwrap();
+        // Use private metrics, so that the other tests do not interfere with the checks below.
+        block.metrics = Arc::new(BlockDeviceMetrics::new());
+        assert_ne!(block.avail_features & (1u64 << VIRTIO_BLK_F_MQ), 0);
+        assert_eq!(block.queues.len(), 4);
+        assert_eq!(block.queue_evts.len(), 4);
+        assert_eq!(block.rate_limiters.len(), 4);
+        assert_eq!(block.config().num_queues, Some(4));
+        assert_eq!(block.config().rate_limiter_scope, RateLimiterScope::Queue);
+        let mut num_queues = [0u8; 2];
+        block.read_config(CONFIG_NUM_QUEUES as u64, &mut num_queues);
+        assert_eq!(read_le_u16(&num_queues), 4);
+
+        let mem = default_mem();
+        let vqs = (0..4)
+            .map(|i| VirtQueue::new(GuestAddress(0x4000 + i * 0x1000), &mem, 16))
+            .collect::<Vec<_>>();
+        for (i, vq) in vqs.iter().enumerate() {
+            set_queue(&mut block, i, vq.create_queue());
+        }
+        block.activate(mem.clone()).unwrap();
+
+        // Makes a request of one sector available in a queue, with its header, data and status
+        // in the page of the queue. Returns the address of the data.
+        let add_request = |vq: &VirtQueue, request_type: u32, sector: u64| {
+            let base = vq.dtable_start().0;
+            let (header_addr, data_addr, status_addr) = (base + 0x800, base + 0xa00, base + 0xc00);
+            mem.write_obj(
+                RequestHeader::new(request_type, sector),
+                GuestAddress(header_addr),
+            )
+            .unwrap();
+            let data_flags = match request_type {
+                VIRTIO_BLK_T_IN => VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE,
+                _ => VIRTQ_DESC_F_NEXT,
+            };
+            vq.dtable[0].set(header_addr, 16, VIRTQ_DESC_F_NEXT, 1);
+            vq.dtable[1].set(data_addr, 512, data_flags, 2);
+            vq.dtable[2].set(status_addr, 1, VIRTQ_DESC_F_WRITE, 0);
+            vq.avail.ring[0].set(0);
+            vq.avail.idx.set(1);
+            vq.used.idx.set(0);
+            GuestAddress(data_addr)
+        };
+        let check_completed = |vq: &VirtQueue| {
+            assert_eq!(vq.used.idx.get(), 1);
+            assert_eq!(vq.used.ring[0].get().id, 0);
+            let status_addr = GuestAddress(vq.dtable[2].addr.get());
+            assert_eq!(
+                u32::from(mem.read_obj::<u8>(status_addr).unwrap()),
+                VIRTIO_BLK_S_OK
+            );
+        };
+
+        // Writes are in flight on two queues at once.
+        let data_addr = add_request(&vqs[1], VIRTIO_BLK_T_OUT, 0);
+        mem.write_slice(&[0x11; 512], data_addr).unwrap();
+        let data_addr = add_request(&vqs[3], VIRTIO_BLK_T_OUT, 1);
+        mem.write_slice(&[0x33; 512], data_addr).unwrap();
+        block.queue_evts[1].write(1).unwrap();
+        block.queue_evts[3].write(1).unwrap();
+        check_metric_after_block!(&block.metrics.write_count, 2, {
+            block.process_queue_event(1).unwrap();
+            block.process_queue_event(3).unwrap();
             simulate_async_completion_event(&mut block, true);
-            assert!(!block.is_io_engine_throttled);
-            check_flush_requests_batch(IO_URING_NUM_ENTRIES * 2, &vq);
+        });
+        // Each request completed on the queue it was taken from.
+        check_completed(&vqs[1]);
+        check_completed(&vqs[3]);
+        for i in [0, 2] {
+            assert_eq!(vqs[i].used.idx.get(), 0);
         }
+
+        // Each queue has its own rate limiter, which only throttles the queue.
+        let mut rl = RateLimiter::new(0, 0, 0, 1, 0, 100).unwrap();
+        assert!(rl.consume(1, TokenType::Ops));
+        block.rate_limiters[0] = rl;
+        let data_addr_0 = add_request(&vqs[0], VIRTIO_BLK_T_IN, 1);
+        let data_addr_2 = add_request(&vqs[2], VIRTIO_BLK_T_IN, 0);
+        block.queue_evts[0].write(1).unwrap();
+        block.queue_evts[2].write(1).unwrap();
+        block.process_queue_event(0).unwrap();
+        block.process_queue_event(2).unwrap();
+        simulate_async_completion_event(&mut block, true);
+        assert_eq!(vqs[0].used.idx.get(), 0);
+        check_completed(&vqs[2]);
+        let mut buf = [0u8; 512];
+        mem.read_slice(&mut buf, data_addr_2).unwrap();
+        assert_eq!(buf, [0x11; 512]);
+
+        // Once replenished, the rate limiter of the first queue resumes it.
+        thread::sleep(Duration::from_millis(150));
+        block.process_rate_limiter_event(0).unwrap();
+        simulate_async_completion_event(&mut block, true);
+        check_completed(&vqs[0]);
+        mem.read_slice(&mut buf, data_addr_0).unwrap();
+        assert_eq!(buf, [0x33; 512]);
+    }
+
+    #[test]
+    fn test_max_requests_per_pass() {
+        let mut block = default_block(FileEngineType::Sync);
+        // Use private metrics, so that the other tests do not interfere with the checks below.
+        block.metrics = Arc::new(BlockDeviceMetrics::new());
+        block.io_engine_opts.max_requests_per_pass = 4;
+
+        let mut config = block.config();
+        config.io_engine_opts.max_requests_per_pass = 0;
+        assert!(matches!(
+            VirtioBlock::new(config),
+            Err(VirtioBlockError::InvalidMaxRequestsPerPass)
+        ));
+
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 32);
+        block.activate(mem.clone()).unwrap();
+
+        // 10 requests are processed in passes of 4, 4 and 2 requests.
+        add_flush_requests_batch(&mut block, &vq, 10);
+        simulate_queue_event(&mut block, Some(true));
+        assert_eq!(vq.used.idx.get(), 4);
+        assert_eq!(block.passes_since_kick[0], 1);
+        // The guest doesn't need to notify us until the queue is drained.
+        assert_eq!(vq.used.flags.get(), VIRTQ_USED_F_NO_NOTIFY);
+
+        // The device kicked itself to get back to the queue in a later pass.
+        block.process_queue_event(0).unwrap();
+        assert_eq!(vq.used.idx.get(), 8);
+        block.process_queue_event(0).unwrap();
+        assert_eq!(vq.used.idx.get(), 10);
+        assert_eq!(block.passes_since_kick[0], 0);
+        // Once the queue is drained, there are no more self kicks, and the notifications are
+        // enabled again.
+        block.queue_evts[0].read().unwrap_err();
+        assert_eq!(vq.used.flags.get(), 0);
+
+        // Every request completed exactly once.
+        check_flush_requests_batch(10, &vq);
+        let mut ids = (0..10)
+            .map(|i| vq.used.ring[i].get().id)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert_eq!(ids, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+
+        assert_eq!(block.metrics.requests_per_pass.le_1.count(), 0);
+        assert_eq!(block.metrics.requests_per_pass.le_4.count(), 3);
+        assert_eq!(block.metrics.passes_per_kick.le_4.count(), 1);
+        assert_eq!(block.metrics.queue_event_count.count(), 3);
     }
 
     #[test]
@@ -1636,6 +3478,45 @@ mod tests {
         check_flush_requests_batch(5, &vq);
     }
 
+    #[test]
+    fn test_quiesce() {
+        skip_if_io_uring_unsupported!();
+
+        let mut block = default_block(FileEngineType::Async);
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        block.activate(mem.clone()).unwrap();
+
+        // Submit 3 read requests to io_uring, and leave 2 more in the queue.
+        add_requests_batch(&mut block, &vq, &[VIRTIO_BLK_T_IN; 5]);
+        vq.avail.idx.set(3);
+        simulate_queue_event(&mut block, Some(false));
+        vq.avail.idx.set(5);
+        block.quiesce();
+
+        // The requests in flight completed, the others were failed.
+        assert_eq!(vq.used.idx.get(), 5);
+        for i in 0..5 {
+            let used = vq.used.ring[i].get();
+            let status_addr = GuestAddress(vq.dtable[used.id as usize + 2].addr.get());
+            let (expected_status, expected_len) = if used.id < 9 {
+                (VIRTIO_BLK_S_OK, SECTOR_SIZE + 1)
+            } else {
+                (VIRTIO_BLK_S_IOERR, 1)
+            };
+            assert_eq!(used.len, expected_len);
+            assert_eq!(
+                u32::from(mem.read_obj::<u8>(status_addr).unwrap()),
+                expected_status
+            );
+        }
+        assert!(block.irq_trigger.has_pending_irq(IrqType::Vring));
+        // The driver is asked to reset the device, which stops processing its queues.
+        assert!(!block.is_activated());
+        assert!(block.needs_reset);
+        assert!(block.irq_trigger.has_pending_irq(IrqType::Config));
+    }
+
     #[test]
     fn test_bandwidth_rate_limiter() {
         let mut block = default_block(default_engine_type_for_kv());
@@ -1673,7 +3554,7 @@ mod tests {
             );
 
             // Assert that limiter is blocked.
-            assert!(block.rate_limiter.is_blocked());
+            assert!(block.rate_limiters[0].is_blocked());
             // Make sure the data is still queued for processing.
             assert_eq!(vq.used.idx.get(), 0);
         }
@@ -1687,10 +3568,10 @@ mod tests {
             check_metric_after_block!(
                 &block.metrics.rate_limiter_throttled_events,
                 0,
-                block.process_rate_limiter_event()
+                block.process_rate_limiter_event(0)
             );
             // Validate the rate_limiter is no longer blocked.
-            assert!(!block.rate_limiter.is_blocked());
+            assert!(!block.rate_limiters[0].is_blocked());
             // Complete async IO ops if needed
             simulate_async_completion_event(&mut block, true);
 
@@ -1739,7 +3620,7 @@ mod tests {
             );
 
             // Assert that limiter is blocked.
-            assert!(block.rate_limiter.is_blocked());
+            assert!(block.rate_limiters[0].is_blocked());
             // Make sure the data is still queued for processing.
             assert_eq!(vq.used.idx.get(), 0);
         }
@@ -1754,7 +3635,7 @@ mod tests {
             );
 
             // Assert that limiter is blocked.
-            assert!(block.rate_limiter.is_blocked());
+            assert!(block.rate_limiters[0].is_blocked());
             // Make sure the data is still queued for processing.
             assert_eq!(vq.used.idx.get(), 0);
         }
@@ -1768,10 +3649,10 @@ mod tests {
             check_metric_after_block!(
                 &block.metrics.rate_limiter_throttled_events,
                 0,
-                block.process_rate_limiter_event()
+                block.process_rate_limiter_event(0)
             );
             // Validate the rate_limiter is no longer blocked.
-            assert!(!block.rate_limiter.is_blocked());
+            assert!(!block.rate_limiters[0].is_blocked());
             // Complete async IO ops if needed
             simulate_async_completion_event(&mut block, true);
 
@@ -1787,6 +3668,7 @@ mod tests {
     fn test_update_disk_image() {
         let mut block = default_block(default_engine_type_for_kv());
         let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
         let path = f.as_path();
         let mdata = metadata(path).unwrap();
         let mut id = vec![0; VIRTIO_BLK_ID_BYTES as usize];
@@ -1805,4 +3687,225 @@ mod tests {
         );
         assert_eq!(block.disk.image_id, id.as_slice());
     }
+
+    #[test]
+    fn test_update_disk_capacity() {
+        let mut block = default_block(default_engine_type_for_kv());
+        // Use private metrics, so that the other tests do not interfere with the checks below.
+        block.metrics = Arc::new(BlockDeviceMetrics::new());
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        let capacity = |block: &VirtioBlock| {
+            let mut capacity = [0u8; 8];
+            block.read_config(CONFIG_CAPACITY as u64, &mut capacity);
+            read_le_u64(&capacity)
+        };
+        assert_eq!(capacity(&block), 8);
+
+        // A larger backing file grows the disk, and the guest is notified of its new capacity.
+        let larger = TempFile::new().unwrap();
+        larger.as_file().set_len(0x2000).unwrap();
+        block
+            .update_disk_image(larger.as_path().to_str().unwrap().to_string())
+            .unwrap();
+        assert_eq!(capacity(&block), 16);
+        assert!(block.irq_trigger.has_pending_irq(IrqType::Config));
+        assert_eq!(block.metrics.capacity_change_count.count(), 1);
+        assert_eq!(block.metrics.update_count.count(), 1);
+
+        // A backing file of the same size doesn't change the capacity.
+        block.irq_trigger.irq_status.store(0, Ordering::SeqCst);
+        let same = TempFile::new().unwrap();
+        same.as_file().set_len(0x2000).unwrap();
+        block
+            .update_disk_image(same.as_path().to_str().unwrap().to_string())
+            .unwrap();
+        assert_eq!(capacity(&block), 16);
+        assert!(block.irq_trigger.has_pending_irq(IrqType::Config));
+        assert_eq!(block.metrics.capacity_change_count.count(), 1);
+        assert_eq!(block.metrics.update_count.count(), 2);
+
+        // A smaller backing file is rejected, and the previous one is kept.
+        block.irq_trigger.irq_status.store(0, Ordering::SeqCst);
+        let smaller = TempFile::new().unwrap();
+        smaller.as_file().set_len(0x800).unwrap();
+        assert!(matches!(
+            block.update_disk_image(smaller.as_path().to_str().unwrap().to_string()),
+            Err(VirtioBlockError::DiskShrink(16, 4))
+        ));
+        assert_eq!(
+            block.disk.file_engine.file().metadata().unwrap().st_ino(),
+            same.as_file().metadata().unwrap().st_ino()
+        );
+        assert_eq!(block.disk.file_path, same.as_path().to_str().unwrap());
+        assert_eq!(capacity(&block), 16);
+        assert!(!block.irq_trigger.has_pending_irq(IrqType::Config));
+        assert_eq!(block.metrics.update_count.count(), 2);
+        assert_eq!(block.metrics.update_fails.count(), 1);
+    }
+
+    #[test]
+    fn test_update_read_only() {
+        let mut block = default_block(default_engine_type_for_kv());
+        // Use private metrics, so that the other tests do not interfere with the checks below.
+        block.metrics = Arc::new(BlockDeviceMetrics::new());
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        read_blk_req_descriptors(&vq);
+
+        let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+        let data_addr = GuestAddress(vq.dtable[1].addr.get());
+        let status_addr = GuestAddress(vq.dtable[2].addr.get());
+        let rand_data = utils::rand::rand_alphanumerics(512).as_bytes().to_vec();
+        let add_request = |block: &mut VirtioBlock, request_type, flags| {
+            vq.used.idx.set(0);
+            set_queue(block, 0, vq.create_queue());
+            mem.write_obj::<u32>(request_type, request_type_addr)
+                .unwrap();
+            vq.dtable[1].flags.set(flags);
+            vq.dtable[1].len.set(512);
+        };
+        let status = || {
+            assert_eq!(vq.used.idx.get(), 1);
+            mem.read_obj::<u32>(status_addr).unwrap()
+        };
+
+        // A write submitted before the drive is made read-only completes.
+        mem.write_slice(&rand_data, data_addr).unwrap();
+        add_request(&mut block, VIRTIO_BLK_T_OUT, VIRTQ_DESC_F_NEXT);
+        simulate_queue_event(&mut block, None);
+        assert!(!block.irq_trigger.has_pending_irq(IrqType::Config));
+        block.update_read_only(true).unwrap();
+        assert!(block.irq_trigger.has_pending_irq(IrqType::Config));
+        assert!(block.disk.read_only);
+        assert!(block.config().is_read_only);
+        simulate_async_completion_event(&mut block, true);
+        assert_eq!(status(), VIRTIO_BLK_S_OK);
+
+        // The writes and flushes fail from now on.
+        mem.write_slice(&[0; 512], data_addr).unwrap();
+        add_request(&mut block, VIRTIO_BLK_T_OUT, VIRTQ_DESC_F_NEXT);
+        simulate_queue_and_async_completion_events(&mut block, true);
+        assert_eq!(status(), VIRTIO_BLK_S_IOERR);
+        add_request(&mut block, VIRTIO_BLK_T_FLUSH, VIRTQ_DESC_F_NEXT);
+        simulate_queue_and_async_completion_events(&mut block, true);
+        assert_eq!(status(), VIRTIO_BLK_S_IOERR);
+        assert_eq!(block.metrics.read_only_fails.count(), 2);
+
+        // The reads are still served, and return the data written before.
+        add_request(
+            &mut block,
+            VIRTIO_BLK_T_IN,
+            VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE,
+        );
+        simulate_queue_and_async_completion_events(&mut block, true);
+        assert_eq!(status(), VIRTIO_BLK_S_OK);
+        let mut buf = [0u8; 512];
+        mem.read_slice(&mut buf, data_addr).unwrap();
+        assert_eq!(buf, rand_data.as_slice());
+
+        // A read-only drive can't be made writable again.
+        block.update_read_only(true).unwrap();
+        assert!(matches!(
+            block.update_read_only(false),
+            Err(VirtioBlockError::ReadWriteUpdate)
+        ));
+        assert!(block.read_only);
+        assert_eq!(block.metrics.update_count.count(), 2);
+        assert_eq!(block.metrics.update_fails.count(), 1);
+    }
+
+    #[test]
+    fn test_host_enospc() {
+        let mut block = default_block(default_engine_type_for_kv());
+        // Use private metrics, so that the other tests do not interfere with the checks below.
+        block.metrics = Arc::new(BlockDeviceMetrics::new());
+        block.on_host_enospc = OnHostEnospc::Pause;
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        read_blk_req_descriptors(&vq);
+
+        let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+        let data_addr = GuestAddress(vq.dtable[1].addr.get());
+        let status_addr = GuestAddress(vq.dtable[2].addr.get());
+        mem.write_obj::<u32>(VIRTIO_BLK_T_OUT, request_type_addr)
+            .unwrap();
+        vq.dtable[1].flags.set(VIRTQ_DESC_F_NEXT);
+        vq.dtable[1].len.set(512);
+        let rand_data = utils::rand::rand_alphanumerics(512).as_bytes().to_vec();
+        mem.write_slice(&rand_data, data_addr).unwrap();
+        mem.write_obj::<u32>(0xFF, status_addr).unwrap();
+
+        // Writes to /dev/full fail with ENOSPC.
+        let path = block.disk.file_path.clone();
+        let backing_file = || {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap()
+        };
+        let original = backing_file();
+        let full = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/full")
+            .unwrap();
+        block.disk.file_engine.update_file_path(full).unwrap();
+
+        // The write is kept pending, and the queue isn't processed anymore.
+        simulate_queue_event(&mut block, None);
+        simulate_async_completion_event(&mut block, false);
+        assert_eq!(vq.used.idx.get(), 0);
+        assert!(block.is_out_of_space());
+        assert_eq!(block.retained_requests.len(), 1);
+        assert_eq!(block.metrics.host_enospc_count.count(), 1);
+        simulate_queue_event(&mut block, Some(false));
+        assert_eq!(block.metrics.host_enospc_stalled_events.count(), 1);
+        assert_eq!(block.queues[0].next_avail.0, 1);
+
+        // Retrying while the host is still out of space keeps the write pending.
+        block.retry_pending_requests().unwrap();
+        simulate_async_completion_event(&mut block, false);
+        assert_eq!(vq.used.idx.get(), 0);
+        assert!(block.is_out_of_space());
+        assert_eq!(block.metrics.host_enospc_count.count(), 2);
+        assert_eq!(block.metrics.host_enospc_retries.count(), 1);
+
+        // Once space is available, the write completes and the queue is processed again.
+        block.disk.file_engine.update_file_path(original).unwrap();
+        block.retry_pending_requests().unwrap();
+        simulate_async_completion_event(&mut block, true);
+        assert_eq!(vq.used.idx.get(), 1);
+        assert_eq!(mem.read_obj::<u32>(status_addr).unwrap(), VIRTIO_BLK_S_OK);
+        assert!(!block.is_out_of_space());
+        assert_eq!(block.metrics.host_enospc_retries.count(), 2);
+        let mut buf = vec![0u8; 512];
+        backing_file().read_exact(&mut buf).unwrap();
+        assert_eq!(buf, rand_data);
+
+        // With the default policy, the write fails right away.
+        block.on_host_enospc = OnHostEnospc::Report;
+        let full = std::fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/full")
+            .unwrap();
+        block.disk.file_engine.update_file_path(full).unwrap();
+        vq.used.idx.set(0);
+        set_queue(&mut block, 0, vq.create_queue());
+        simulate_queue_and_async_completion_events(&mut block, true);
+        assert_eq!(vq.used.idx.get(), 1);
+        assert_eq!(
+            mem.read_obj::<u32>(status_addr).unwrap(),
+            VIRTIO_BLK_S_IOERR
+        );
+        assert!(!block.is_out_of_space());
+        assert_eq!(block.metrics.host_enospc_count.count(), 2);
+    }
 }
@@ -5,7 +5,7 @@ use utils::epoll::EventSet;
 
 use super::io::FileEngine;
 use crate::devices::virtio::block::virtio::device::VirtioBlock;
-use crate::devices::virtio::device::VirtioDevice;
+use crate::devices::virtio::device::{DeviceErrorHandler, VirtioDevice};
 use crate::logger::{error, warn};
 
 impl VirtioBlock {
@@ -14,28 +14,57 @@ impl VirtioBlock {
     const PROCESS_RATE_LIMITER: u32 = 2;
     const PROCESS_ASYNC_COMPLETION: u32 = 3;
 
-    fn register_runtime_events(&self, ops: &mut EventOps) {
-        if let Err(err) = ops.add(Events::with_data(
-            &self.queue_evts[0],
-            Self::PROCESS_QUEUE,
-            EventSet::IN,
-        )) {
-            error!("Failed to register queue event: {}", err);
+    // The data of the events of the queues and of the rate limiters holds the index of the queue
+    // or of the rate limiter in its upper bits.
+    const EVENT_INDEX_SHIFT: u32 = 16;
+    const EVENT_SOURCE_MASK: u32 = (1 << Self::EVENT_INDEX_SHIFT) - 1;
+
+    fn event_data(source: u32, index: usize) -> u32 {
+        // Safe to unwrap, the number of queues is bounded by `BLOCK_MAX_NUM_QUEUES`.
+        source | (u32::try_from(index).unwrap() << Self::EVENT_INDEX_SHIFT)
+    }
+
+    // The events registered once the device is activated, along with a description of their
+    // source.
+    fn runtime_events(&self) -> Vec<(Events, &'static str)> {
+        let mut events = Vec::new();
+        for (i, queue_evt) in self.queue_evts.iter().enumerate() {
+            events.push((
+                Events::with_data(
+                    queue_evt,
+                    Self::event_data(Self::PROCESS_QUEUE, i),
+                    EventSet::IN,
+                ),
+                "queue",
+            ));
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.rate_limiter,
-            Self::PROCESS_RATE_LIMITER,
-            EventSet::IN,
-        )) {
-            error!("Failed to register ratelimiter event: {}", err);
+        for (i, limiter) in self.rate_limiters.iter().enumerate() {
+            events.push((
+                Events::with_data(
+                    limiter,
+                    Self::event_data(Self::PROCESS_RATE_LIMITER, i),
+                    EventSet::IN,
+                ),
+                "ratelimiter",
+            ));
         }
         if let FileEngine::Async(ref engine) = self.disk.file_engine {
-            if let Err(err) = ops.add(Events::with_data(
-                engine.completion_evt(),
-                Self::PROCESS_ASYNC_COMPLETION,
-                EventSet::IN,
-            )) {
-                error!("Failed to register IO engine completion event: {}", err);
+            events.push((
+                Events::with_data(
+                    engine.completion_evt(),
+                    Self::PROCESS_ASYNC_COMPLETION,
+                    EventSet::IN,
+                ),
+                "IO engine completion",
+            ));
+        }
+        events
+    }
+
+    fn register_runtime_events(&self, ops: &mut EventOps) {
+        for (event, name) in self.runtime_events() {
+            if let Err(err) = ops.add(event) {
+                error!("Failed to register {} event: {}", name, err);
             }
         }
     }
@@ -65,6 +94,20 @@ impl VirtioBlock {
     }
 }
 
+impl DeviceErrorHandler for VirtioBlock {
+    fn set_needs_reset(&mut self) {
+        self.needs_reset = true;
+    }
+
+    fn unregister_runtime_events(&mut self, ops: &mut EventOps) {
+        for (event, name) in self.runtime_events() {
+            if let Err(err) = ops.remove(event) {
+                error!("Failed to un-register {} event: {}", name, err);
+            }
+        }
+    }
+}
+
 impl MutEventSubscriber for VirtioBlock {
     // Handle an event for queue or rate limiter.
     fn process(&mut self, event: Events, ops: &mut EventOps) {
@@ -83,12 +126,22 @@ impl MutEventSubscriber for VirtioBlock {
         }
 
         if self.is_activated() {
-            match source {
-                Self::PROCESS_ACTIVATE => self.process_activate_event(ops),
-                Self::PROCESS_QUEUE => self.process_queue_event(),
-                Self::PROCESS_RATE_LIMITER => self.process_rate_limiter_event(),
+            let index = (source >> Self::EVENT_INDEX_SHIFT) as usize;
+            let result = match source & Self::EVENT_SOURCE_MASK {
+                Self::PROCESS_ACTIVATE => {
+                    self.process_activate_event(ops);
+                    Ok(())
+                }
+                Self::PROCESS_QUEUE => self.process_queue_event(index),
+                Self::PROCESS_RATE_LIMITER => self.process_rate_limiter_event(index),
                 Self::PROCESS_ASYNC_COMPLETION => self.process_async_completion_event(),
-                _ => warn!("Block: Spurious event received: {:?}", source),
+                _ => {
+                    warn!("Block: Spurious event received: {:?}", source);
+                    Ok(())
+                }
+            };
+            if let Err(err) = result {
+                self.handle_error(ops, err);
             }
         } else {
             warn!(
@@ -123,8 +176,10 @@ mod tests {
         default_block, read_blk_req_descriptors, set_queue, simulate_async_completion_event,
     };
     use crate::devices::virtio::block::virtio::{VIRTIO_BLK_S_OK, VIRTIO_BLK_T_OUT};
-    use crate::devices::virtio::queue::VIRTQ_DESC_F_NEXT;
+    use crate::devices::virtio::queue::{QueueError, VIRTQ_DESC_F_NEXT};
+    use crate::devices::virtio::test_utils::test::check_error_handling;
     use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
+    use crate::devices::DeviceError;
     use crate::vstate::memory::{Bytes, GuestAddress};
 
     #[test]
@@ -179,4 +234,24 @@ mod tests {
         assert_eq!(vq.used.ring[0].get().len, 1);
         assert_eq!(mem.read_obj::<u32>(status_addr).unwrap(), VIRTIO_BLK_S_OK);
     }
+
+    #[test]
+    fn test_error_handling() {
+        let mut block = default_block(FileEngineType::default());
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+
+        // There is no pending queue event.
+        let transient = block.process_queue_event(0).unwrap_err();
+        check_error_handling(
+            Arc::new(Mutex::new(block)),
+            [
+                transient,
+                DeviceError::IoError(std::io::Error::from_raw_os_error(libc::EIO)),
+                DeviceError::QueueError(QueueError::DescIndexOutOfBounds(16)),
+            ],
+        );
+    }
 }
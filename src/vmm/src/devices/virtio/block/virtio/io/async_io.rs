@@ -1,6 +1,7 @@
 // Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::fs::File;
 use std::os::fd::RawFd;
@@ -9,13 +10,19 @@ use std::os::unix::io::AsRawFd;
 use utils::eventfd::EventFd;
 use vm_memory::GuestMemoryError;
 
-use crate::devices::virtio::block::virtio::io::UserDataError;
-use crate::devices::virtio::block::virtio::IO_URING_NUM_ENTRIES;
+use crate::devices::virtio::block::virtio::device::IoUringConfig;
+use crate::devices::virtio::block::virtio::io::sync_io::{write_zeroes_at, write_zeroes_mode};
+use crate::devices::virtio::block::virtio::io::{
+    is_direct, is_direct_io_aligned, BounceBuffer, UserDataError,
+};
+use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut, IoVecError, IoVecParts};
 use crate::io_uring::operation::{Cqe, OpCode, Operation};
 use crate::io_uring::restriction::Restriction;
 use crate::io_uring::{self, IoUring, IoUringError};
 use crate::logger::log_dev_preview_warning;
-use crate::vstate::memory::{GuestAddress, GuestMemory, GuestMemoryExtension, GuestMemoryMmap};
+use crate::vstate::memory::{
+    Bytes, GuestAddress, GuestMemory, GuestMemoryExtension, GuestMemoryMmap,
+};
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum AsyncIoError {
@@ -31,18 +38,69 @@ pub enum AsyncIoError {
     EventFd(std::io::Error),
     /// GuestMemory: {0}
     GuestMemory(GuestMemoryError),
+    /// IoVec: {0}
+    IoVec(IoVecError),
 }
 
 #[derive(Debug)]
 pub struct AsyncFileEngine<T> {
     file: File,
+    direct: bool,
+    io_uring: IoUringConfig,
     ring: IoUring<WrappedUserData<T>>,
     completion_evt: EventFd,
+    // Number of writes pushed to the ring, and how many of them were synced to the backing file.
+    pushed_writes: u64,
+    synced_writes: u64,
+    // Flushes requested since the last submission, completed together by a single `fsync`.
+    deferred_flushes: Vec<T>,
+    // Completions of the flushes coalesced into the `fsync` of another one, not popped yet.
+    coalesced_cqes: VecDeque<Cqe<T>>,
+}
+
+// How a `fallocate` operation is completed when the file system doesn't support its mode.
+#[derive(Debug, Clone, Copy)]
+enum FallocateFallback {
+    // Discards are only hints, the data is left in place.
+    Ignore,
+    // Write zeroes to the range instead of zeroing it with `fallocate`.
+    WriteZeroes { offset: u64, len: u64 },
+}
+
+// The flushes completed by an `fsync`, besides the one it was pushed for.
+#[derive(Debug)]
+struct CoalescedFlushes<T> {
+    // Number of writes pushed before the `fsync`, whose data it syncs.
+    pushed_writes: u64,
+    user_data: Vec<T>,
+}
+
+// The memory regions of a vectored operation, handed over to the kernel until it completes.
+#[derive(Debug)]
+enum SubmissionToken {
+    // Read into, and marked dirty with the bytes read on completion.
+    Readv(IoVecParts),
+    // Written from.
+    Writev(IoVecParts),
+}
+
+impl SubmissionToken {
+    fn parts(&self) -> &IoVecParts {
+        match self {
+            SubmissionToken::Readv(parts) | SubmissionToken::Writev(parts) => parts,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct WrappedUserData<T> {
     addr: Option<GuestAddress>,
+    fallback: Option<FallocateFallback>,
+    // Kept alive until the operation completes, since the kernel transfers from or to it.
+    bounce: Option<BounceBuffer>,
+    // Same for the `iovec`s of a vectored operation.
+    token: Option<SubmissionToken>,
+    flushes: Option<CoalescedFlushes<T>>,
     user_data: T,
 }
 
@@ -50,6 +108,10 @@ impl<T: Debug> WrappedUserData<T> {
     fn new(user_data: T) -> Self {
         WrappedUserData {
             addr: None,
+            fallback: None,
+            bounce: None,
+            token: None,
+            flushes: None,
             user_data,
         }
     }
@@ -57,13 +119,73 @@ impl<T: Debug> WrappedUserData<T> {
     fn new_with_dirty_tracking(addr: GuestAddress, user_data: T) -> Self {
         WrappedUserData {
             addr: Some(addr),
+            fallback: None,
+            bounce: None,
+            token: None,
+            flushes: None,
+            user_data,
+        }
+    }
+
+    fn new_with_fallback(fallback: FallocateFallback, user_data: T) -> Self {
+        WrappedUserData {
+            addr: None,
+            fallback: Some(fallback),
+            bounce: None,
+            token: None,
+            flushes: None,
             user_data,
         }
     }
 
+    fn new_flush(pushed_writes: u64, user_data: T, coalesced: Vec<T>) -> Self {
+        WrappedUserData {
+            addr: None,
+            fallback: None,
+            bounce: None,
+            token: None,
+            flushes: Some(CoalescedFlushes {
+                pushed_writes,
+                user_data: coalesced,
+            }),
+            user_data,
+        }
+    }
+
+    fn new_vectored(token: SubmissionToken, user_data: T) -> Self {
+        WrappedUserData {
+            addr: None,
+            fallback: None,
+            bounce: None,
+            token: Some(token),
+            flushes: None,
+            user_data,
+        }
+    }
+
+    fn with_bounce_buffer(mut self, bounce: Option<BounceBuffer>) -> Self {
+        self.bounce = bounce;
+        self
+    }
+
+    // Reads through a bounce buffer complete by copying the bytes read to guest memory, which
+    // marks it dirty. Vectored reads mark the bytes read dirty through their buffer, given back
+    // by their token.
     fn mark_dirty_mem_and_unwrap(self, mem: &GuestMemoryMmap, count: u32) -> T {
-        if let Some(addr) = self.addr {
-            mem.mark_dirty(addr, count as usize)
+        match (self.addr, &self.bounce, self.token) {
+            (Some(addr), Some(bounce), _) => {
+                let bytes_read = bounce.as_slice().get(..count as usize).unwrap_or_default();
+                // The memory was checked when the read was submitted.
+                let _ = mem.write_slice(bytes_read, addr);
+            }
+            (Some(addr), None, _) => mem.mark_dirty(addr, count as usize),
+            (None, _, Some(SubmissionToken::Readv(parts))) => {
+                // SAFETY: The parts were taken from a buffer over the guest memory of the device,
+                // which `mem` shares the mappings of.
+                let buffer = unsafe { IoVecBufferMut::from_parts(mem, parts) };
+                buffer.mark_written(count as usize);
+            }
+            _ => {}
         }
 
         self.user_data
@@ -74,9 +196,10 @@ impl<T: Debug> AsyncFileEngine<T> {
     fn new_ring(
         file: &File,
         completion_fd: RawFd,
+        config: &IoUringConfig,
     ) -> Result<IoUring<WrappedUserData<T>>, io_uring::IoUringError> {
-        IoUring::new(
-            u32::from(IO_URING_NUM_ENTRIES),
+        IoUring::new_with_sq_poll(
+            u32::from(config.ring_size),
             vec![file],
             vec![
                 // Make sure we only allow operations on pre-registered fds.
@@ -84,35 +207,75 @@ impl<T: Debug> AsyncFileEngine<T> {
                 // Allowlist of opcodes.
                 Restriction::AllowOpCode(OpCode::Read),
                 Restriction::AllowOpCode(OpCode::Write),
+                Restriction::AllowOpCode(OpCode::Readv),
+                Restriction::AllowOpCode(OpCode::Writev),
                 Restriction::AllowOpCode(OpCode::Fsync),
+                Restriction::AllowOpCode(OpCode::Fallocate),
+                // The flushes wait for the operations submitted before them.
+                Restriction::AllowDrain,
             ],
             Some(completion_fd),
+            config.sq_thread_idle_ms(),
         )
     }
 
-    pub fn from_file(file: File) -> Result<AsyncFileEngine<T>, AsyncIoError> {
+    pub fn from_file(
+        file: File,
+        io_uring: IoUringConfig,
+    ) -> Result<AsyncFileEngine<T>, AsyncIoError> {
         log_dev_preview_warning("Async file IO", Option::None);
 
         let completion_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(AsyncIoError::EventFd)?;
-        let ring =
-            Self::new_ring(&file, completion_evt.as_raw_fd()).map_err(AsyncIoError::IoUring)?;
+        let ring = Self::new_ring(&file, completion_evt.as_raw_fd(), &io_uring)
+            .map_err(AsyncIoError::IoUring)?;
 
         Ok(AsyncFileEngine {
+            direct: is_direct(&file),
             file,
+            io_uring,
             ring,
             completion_evt,
+            pushed_writes: 0,
+            synced_writes: 0,
+            deferred_flushes: Vec::new(),
+            coalesced_cqes: VecDeque::new(),
         })
     }
 
     pub fn update_file(&mut self, file: File) -> Result<(), AsyncIoError> {
-        let ring = Self::new_ring(&file, self.completion_evt.as_raw_fd())
+        let ring = Self::new_ring(&file, self.completion_evt.as_raw_fd(), &self.io_uring)
             .map_err(AsyncIoError::IoUring)?;
 
+        self.direct = is_direct(&file);
         self.file = file;
         self.ring = ring;
+        self.synced_writes = self.pushed_writes;
         Ok(())
     }
 
+    /// Whether the backing file was opened with `O_DIRECT`.
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    /// Whether writes were pushed since the backing file was last synced, including the writes
+    /// still in flight.
+    pub fn is_dirty(&self) -> bool {
+        self.pushed_writes != self.synced_writes
+    }
+
+    // Allocates the bounce buffer of a transfer to a backing file opened with `O_DIRECT` whose
+    // guest memory isn't aligned.
+    fn bounce_buffer(
+        &self,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> Option<BounceBuffer> {
+        (self.direct && !is_direct_io_aligned(mem, addr, count))
+            .then(|| BounceBuffer::new(count as usize))
+    }
+
     #[cfg(test)]
     pub fn file(&self) -> &File {
         &self.file
@@ -122,6 +285,9 @@ impl<T: Debug> AsyncFileEngine<T> {
         &self.completion_evt
     }
 
+    /// Reads `count` bytes from the backing file at `offset` into the guest memory at `addr`. The
+    /// memory is read into in place, with a vectored operation, unless it has to go through a
+    /// bounce buffer.
     pub fn push_read(
         &mut self,
         offset: u64,
@@ -130,17 +296,25 @@ impl<T: Debug> AsyncFileEngine<T> {
         count: u32,
         user_data: T,
     ) -> Result<(), UserDataError<T, AsyncIoError>> {
-        let buf = match mem.get_slice(addr, count as usize) {
-            Ok(slice) => slice.ptr_guard_mut().as_ptr(),
-            Err(err) => {
-                return Err(UserDataError {
+        let Some(bounce) = self.bounce_buffer(mem, addr, count) else {
+            return match IoVecBufferMut::from_guest_memory(mem, addr, count) {
+                Ok(buffer) => self.push_readv(offset, buffer, user_data),
+                Err(err) => Err(UserDataError {
                     user_data,
-                    error: AsyncIoError::GuestMemory(err),
-                });
-            }
+                    error: AsyncIoError::IoVec(err),
+                }),
+            };
         };
+        if let Err(err) = mem.get_slice(addr, count as usize) {
+            return Err(UserDataError {
+                user_data,
+                error: AsyncIoError::GuestMemory(err),
+            });
+        }
 
-        let wrapped_user_data = WrappedUserData::new_with_dirty_tracking(addr, user_data);
+        let buf = bounce.as_ptr();
+        let wrapped_user_data = WrappedUserData::new_with_dirty_tracking(addr, user_data)
+            .with_bounce_buffer(Some(bounce));
 
         self.ring
             .push(Operation::read(
@@ -156,6 +330,9 @@ impl<T: Debug> AsyncFileEngine<T> {
             })
     }
 
+    /// Writes the `count` bytes of guest memory at `addr` to the backing file at `offset`. The
+    /// memory is written from in place, with a vectored operation, unless it has to go through
+    /// a bounce buffer.
     pub fn push_write(
         &mut self,
         offset: u64,
@@ -164,17 +341,27 @@ impl<T: Debug> AsyncFileEngine<T> {
         count: u32,
         user_data: T,
     ) -> Result<(), UserDataError<T, AsyncIoError>> {
-        let buf = match mem.get_slice(addr, count as usize) {
-            Ok(slice) => slice.ptr_guard_mut().as_ptr(),
+        let Some(mut bounce) = self.bounce_buffer(mem, addr, count) else {
+            return match IoVecBuffer::from_guest_memory(mem, addr, count) {
+                Ok(buffer) => self.push_writev(offset, buffer, user_data),
+                Err(err) => Err(UserDataError {
+                    user_data,
+                    error: AsyncIoError::IoVec(err),
+                }),
+            };
+        };
+        match mem.get_slice(addr, count as usize) {
+            Ok(slice) => slice.copy_to(bounce.as_mut_slice()),
             Err(err) => {
                 return Err(UserDataError {
                     user_data,
                     error: AsyncIoError::GuestMemory(err),
                 });
             }
-        };
+        }
 
-        let wrapped_user_data = WrappedUserData::new(user_data);
+        let buf = bounce.as_ptr();
+        let wrapped_user_data = WrappedUserData::new(user_data).with_bounce_buffer(Some(bounce));
 
         self.ring
             .push(Operation::write(
@@ -187,25 +374,174 @@ impl<T: Debug> AsyncFileEngine<T> {
             .map_err(|(io_uring_error, data)| UserDataError {
                 user_data: data.user_data,
                 error: AsyncIoError::IoUring(io_uring_error),
-            })
+            })?;
+        self.pushed_writes += 1;
+        Ok(())
     }
 
-    pub fn push_flush(&mut self, user_data: T) -> Result<(), UserDataError<T, AsyncIoError>> {
-        let wrapped_user_data = WrappedUserData::new(user_data);
+    /// Reads from the backing file at `offset` into the memory regions of `buffer`, with a single
+    /// vectored operation. The buffer is handed over to the kernel until the operation
+    /// completes, which marks the bytes read dirty.
+    ///
+    /// The guest memory the buffer points into has to stay mapped until then, and be passed to
+    /// [`AsyncFileEngine::pop`].
+    pub fn push_readv(
+        &mut self,
+        offset: u64,
+        buffer: IoVecBufferMut<'_>,
+        user_data: T,
+    ) -> Result<(), UserDataError<T, AsyncIoError>> {
+        let token = SubmissionToken::Readv(buffer.into_parts());
+        self.push_vectored(offset, token, user_data)
+    }
+
+    /// Writes the memory regions of `buffer` to the backing file at `offset`, with a single
+    /// vectored operation. The buffer is handed over to the kernel until the operation
+    /// completes.
+    ///
+    /// The guest memory the buffer points into has to stay mapped until then.
+    pub fn push_writev(
+        &mut self,
+        offset: u64,
+        buffer: IoVecBuffer<'_>,
+        user_data: T,
+    ) -> Result<(), UserDataError<T, AsyncIoError>> {
+        let token = SubmissionToken::Writev(buffer.into_parts());
+        self.push_vectored(offset, token, user_data)?;
+        self.pushed_writes += 1;
+        Ok(())
+    }
+
+    fn push_vectored(
+        &mut self,
+        offset: u64,
+        token: SubmissionToken,
+        user_data: T,
+    ) -> Result<(), UserDataError<T, AsyncIoError>> {
+        let parts = token.parts();
+        // There are at most `IOV_MAX` `iovec`s, see the constructors of the buffers.
+        let iovec_count = u32::try_from(parts.iovec_count()).unwrap();
+        let iovecs = parts.as_iovec_ptr();
+        let operation = match token {
+            SubmissionToken::Readv(_) => Operation::readv,
+            SubmissionToken::Writev(_) => Operation::writev,
+        };
+        // The `iovec`s are on the heap, so they don't move with the token, which is kept in the
+        // user data until the operation completes.
+        let wrapped_user_data = WrappedUserData::new_vectored(token, user_data);
 
         self.ring
-            .push(Operation::fsync(0, wrapped_user_data))
+            .push(operation(0, iovecs, iovec_count, offset, wrapped_user_data))
             .map_err(|(io_uring_error, data)| UserDataError {
                 user_data: data.user_data,
                 error: AsyncIoError::IoUring(io_uring_error),
             })
     }
 
-    pub fn kick_submission_queue(&mut self) -> Result<(), AsyncIoError> {
+    /// Flushes the backing file. The flushes requested until the next submission are coalesced
+    /// into a single `fsync`, pushed with the submission.
+    pub fn push_flush(&mut self, user_data: T) -> Result<(), UserDataError<T, AsyncIoError>> {
+        self.deferred_flushes.push(user_data);
+        Ok(())
+    }
+
+    // Pushes the `fsync` completing all the deferred flushes. It only starts once the operations
+    // submitted before it completed, so that it syncs the data they wrote. The flushes stay
+    // deferred if it can't be pushed.
+    fn push_deferred_flushes(&mut self) -> Result<(), AsyncIoError> {
+        if self.deferred_flushes.is_empty() {
+            return Ok(());
+        }
+        let mut coalesced = std::mem::take(&mut self.deferred_flushes);
+        let user_data = coalesced.remove(0);
+        let mut operation = Operation::fsync(
+            0,
+            WrappedUserData::new_flush(self.pushed_writes, user_data, coalesced),
+        );
+        operation.set_drain();
+
+        self.ring.push(operation).map_err(|(io_uring_error, data)| {
+            self.deferred_flushes.push(data.user_data);
+            if let Some(flushes) = data.flushes {
+                self.deferred_flushes.extend(flushes.user_data);
+            }
+            AsyncIoError::IoUring(io_uring_error)
+        })
+    }
+
+    pub fn push_discard(
+        &mut self,
+        offset: u64,
+        len: u64,
+        user_data: T,
+    ) -> Result<(), UserDataError<T, AsyncIoError>> {
+        let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+        let wrapped_user_data =
+            WrappedUserData::new_with_fallback(FallocateFallback::Ignore, user_data);
+
+        self.push_fallocate(offset, len, mode, wrapped_user_data)
+    }
+
+    pub fn push_write_zeroes(
+        &mut self,
+        offset: u64,
+        len: u64,
+        unmap: bool,
+        user_data: T,
+    ) -> Result<(), UserDataError<T, AsyncIoError>> {
+        let wrapped_user_data = WrappedUserData::new_with_fallback(
+            FallocateFallback::WriteZeroes { offset, len },
+            user_data,
+        );
+
+        self.push_fallocate(offset, len, write_zeroes_mode(unmap), wrapped_user_data)
+    }
+
+    fn push_fallocate(
+        &mut self,
+        offset: u64,
+        len: u64,
+        mode: libc::c_int,
+        wrapped_user_data: WrappedUserData<T>,
+    ) -> Result<(), UserDataError<T, AsyncIoError>> {
+        // The `fallocate` modes are positive flags.
+        let mode = mode.unsigned_abs();
+
         self.ring
-            .submit()
-            .map(|_| ())
-            .map_err(AsyncIoError::IoUring)
+            .push(Operation::fallocate(
+                0,
+                offset,
+                len,
+                mode,
+                wrapped_user_data,
+            ))
+            .map_err(|(io_uring_error, data)| UserDataError {
+                user_data: data.user_data,
+                error: AsyncIoError::IoUring(io_uring_error),
+            })?;
+        self.pushed_writes += 1;
+        Ok(())
+    }
+
+    /// Whether the pushed operations have to be submitted before another one can be pushed.
+    pub fn is_submission_queue_full(&self) -> Result<bool, AsyncIoError> {
+        self.ring.is_squeue_full().map_err(AsyncIoError::IoUring)
+    }
+
+    /// Submits all the pushed operations, followed by the deferred flushes, with a single
+    /// `io_uring_enter`, returning how many were submitted.
+    pub fn kick_submission_queue(&mut self) -> Result<u32, AsyncIoError> {
+        let mut submitted = 0;
+        if !self.deferred_flushes.is_empty() && self.is_submission_queue_full()? {
+            submitted = self.ring.submit().map_err(AsyncIoError::IoUring)?;
+        }
+        match self.push_deferred_flushes() {
+            // With a full completion queue, the flushes are pushed by a later submission, once
+            // the operations in flight complete.
+            Err(AsyncIoError::IoUring(err)) if err.is_throttling_err() => (),
+            res => res?,
+        }
+        Ok(submitted + self.ring.submit().map_err(AsyncIoError::IoUring)?)
     }
 
     pub fn drain(&mut self, discard_cqes: bool) -> Result<(), AsyncIoError> {
@@ -217,11 +553,34 @@ impl<T: Debug> AsyncFileEngine<T> {
         if discard_cqes {
             // Drain the completion queue so that we may deallocate the user_data fields.
             while self.do_pop()?.is_some() {}
+            self.deferred_flushes.clear();
+            self.coalesced_cqes.clear();
+        } else {
+            self.complete_deferred_flushes();
         }
 
         Ok(())
     }
 
+    // Completes the deferred flushes right away, once all the operations in flight are done.
+    fn complete_deferred_flushes(&mut self) {
+        if self.deferred_flushes.is_empty() {
+            return;
+        }
+        let res = match self.file.sync_all() {
+            Ok(()) => {
+                self.synced_writes = self.pushed_writes;
+                0
+            }
+            Err(err) => -err.raw_os_error().unwrap_or(libc::EIO),
+        };
+        self.coalesced_cqes.extend(
+            self.deferred_flushes
+                .drain(..)
+                .map(|user_data| Cqe::new(res, user_data)),
+        );
+    }
+
     pub fn drain_and_flush(&mut self, discard_cqes: bool) -> Result<(), AsyncIoError> {
         self.drain(discard_cqes)?;
 
@@ -229,6 +588,7 @@ impl<T: Debug> AsyncFileEngine<T> {
         // We don't need to call flush first since all the ops are performed through io_uring
         // and Rust shouldn't manage any data in its internal buffers.
         self.file.sync_all().map_err(AsyncIoError::SyncAll)?;
+        self.synced_writes = self.pushed_writes;
 
         Ok(())
     }
@@ -237,14 +597,54 @@ impl<T: Debug> AsyncFileEngine<T> {
         self.ring.pop().map_err(AsyncIoError::IoUring)
     }
 
+    // Completes the `fallocate` operations whose mode isn't supported by the file system with
+    // their fallback.
+    fn apply_fallback(&self, cqe: Cqe<WrappedUserData<T>>) -> Cqe<WrappedUserData<T>> {
+        if !cqe.is_unsupported() {
+            return cqe;
+        }
+        let wrapped_user_data = cqe.user_data();
+        let res = match wrapped_user_data.fallback {
+            None => -libc::EOPNOTSUPP,
+            Some(FallocateFallback::Ignore) => 0,
+            Some(FallocateFallback::WriteZeroes { offset, len }) => {
+                match write_zeroes_at(&self.file, offset, len) {
+                    Ok(()) => 0,
+                    Err(err) => -err.raw_os_error().unwrap_or(libc::EIO),
+                }
+            }
+        };
+        Cqe::new(res, wrapped_user_data)
+    }
+
     pub fn pop(&mut self, mem: &GuestMemoryMmap) -> Result<Option<Cqe<T>>, AsyncIoError> {
-        let cqe = self.do_pop()?.map(|cqe| {
-            let count = cqe.count();
-            cqe.map_user_data(|wrapped_user_data| {
-                wrapped_user_data.mark_dirty_mem_and_unwrap(mem, count)
-            })
+        if let Some(cqe) = self.coalesced_cqes.pop_front() {
+            return Ok(Some(cqe));
+        }
+        let Some(cqe) = self.do_pop()? else {
+            return Ok(None);
+        };
+        let cqe = self.apply_fallback(cqe);
+        let count = cqe.count();
+        let mut flushes = None;
+        let cqe = cqe.map_user_data(|mut wrapped_user_data| {
+            flushes = wrapped_user_data.flushes.take();
+            wrapped_user_data.mark_dirty_mem_and_unwrap(mem, count)
         });
 
-        Ok(cqe)
+        // The flushes coalesced into this `fsync` complete with it.
+        if let Some(flushes) = flushes {
+            if cqe.result().is_ok() {
+                self.synced_writes = self.synced_writes.max(flushes.pushed_writes);
+            }
+            self.coalesced_cqes.extend(
+                flushes
+                    .user_data
+                    .into_iter()
+                    .map(|user_data| cqe.with_user_data(user_data)),
+            );
+        }
+
+        Ok(Some(cqe))
     }
 }
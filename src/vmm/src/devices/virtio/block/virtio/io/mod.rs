@@ -4,13 +4,91 @@
 pub mod async_io;
 pub mod sync_io;
 
+use std::alloc::{self, Layout};
 use std::fmt::Debug;
 use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::ptr::NonNull;
 
 pub use self::async_io::{AsyncFileEngine, AsyncIoError};
 pub use self::sync_io::{SyncFileEngine, SyncIoError};
-use crate::devices::virtio::block::virtio::device::FileEngineType;
-use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
+use crate::devices::virtio::block::virtio::device::{FileEngineType, IoUringConfig};
+use crate::devices::virtio::iovec::IoVecError;
+use crate::vstate::memory::{GuestAddress, GuestMemory, GuestMemoryMmap};
+
+/// Alignment of the memory buffers and of the lengths of the transfers to a backing file opened
+/// with `O_DIRECT`. Offsets are always aligned, since the guest addresses the disk in sectors.
+pub const DIRECT_IO_ALIGNMENT: usize = 512;
+
+/// Whether `file` was opened with `O_DIRECT`, so that its transfers have to be aligned.
+pub fn is_direct(file: &File) -> bool {
+    // SAFETY: `F_GETFL` only reads the status flags of the file descriptor owned by `file`,
+    // and we check its return value.
+    let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+    flags != -1 && flags & libc::O_DIRECT != 0
+}
+
+/// Whether the `count` bytes of guest memory at `addr` can be transferred in place from or to a
+/// backing file opened with `O_DIRECT`, i.e. whether both their host address and their length
+/// are aligned.
+///
+/// Memory that isn't mapped is reported as aligned, so that the transfer fails as usual.
+pub fn is_direct_io_aligned(mem: &GuestMemoryMmap, addr: GuestAddress, count: u32) -> bool {
+    mem.get_slice(addr, count as usize).map_or(true, |slice| {
+        let host_addr = slice.ptr_guard().as_ptr() as usize;
+        host_addr % DIRECT_IO_ALIGNMENT == 0 && count as usize % DIRECT_IO_ALIGNMENT == 0
+    })
+}
+
+/// An aligned heap buffer standing in for the guest memory of the transfers to a backing file
+/// opened with `O_DIRECT` whose guest buffers aren't aligned.
+#[derive(Debug)]
+pub struct BounceBuffer {
+    ptr: NonNull<u8>,
+    layout: Layout,
+    len: usize,
+}
+
+// SAFETY: The buffer is owned and only accessed through `&self` or `&mut self`.
+unsafe impl Send for BounceBuffer {}
+
+impl BounceBuffer {
+    /// Allocates a zeroed buffer of `len` bytes, aligned to [`DIRECT_IO_ALIGNMENT`].
+    pub fn new(len: usize) -> Self {
+        // Allocations can't be empty, and are rounded up to whole aligned blocks.
+        let size = len
+            .next_multiple_of(DIRECT_IO_ALIGNMENT)
+            .max(DIRECT_IO_ALIGNMENT);
+        let layout = Layout::from_size_align(size, DIRECT_IO_ALIGNMENT).unwrap();
+        // SAFETY: The layout has a non-zero size.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        BounceBuffer { ptr, layout, len }
+    }
+
+    /// Returns a pointer to the start of the buffer, e.g. to pass it to io_uring.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: The allocation holds at least `len` initialized bytes.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: The allocation holds at least `len` initialized bytes, borrowed mutably
+        // through `self`.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for BounceBuffer {
+    fn drop(&mut self) {
+        // SAFETY: The buffer was allocated with this layout in `new`.
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct UserDataOk<T> {
@@ -43,6 +121,19 @@ impl BlockIoError {
             _ => false,
         }
     }
+
+    /// Whether a write failed because the host file system ran out of space, or the disk quota
+    /// of the user running Firecracker was exceeded.
+    pub fn is_out_of_space(&self) -> bool {
+        let err = match self {
+            BlockIoError::Sync(SyncIoError::Transfer(IoVecError::FileIo(err)))
+            | BlockIoError::Sync(SyncIoError::Bounce(err))
+            | BlockIoError::Sync(SyncIoError::WriteZeroes(err))
+            | BlockIoError::Async(AsyncIoError::IO(err)) => err,
+            _ => return false,
+        };
+        matches!(err.raw_os_error(), Some(libc::ENOSPC | libc::EDQUOT))
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -60,9 +151,12 @@ pub enum FileEngine<T> {
 }
 
 impl<T: Debug> FileEngine<T> {
+    /// Creates the IO engine of the backing `file`. `io_uring` is the setup of the ring of the
+    /// `Async` engine.
     pub fn from_file(
         file: File,
         engine_type: FileEngineType,
+        io_uring: IoUringConfig,
     ) -> Result<FileEngine<T>, BlockIoError> {
         if !engine_type
             .is_supported()
@@ -72,7 +166,7 @@ impl<T: Debug> FileEngine<T> {
         }
         match engine_type {
             FileEngineType::Async => Ok(FileEngine::Async(
-                AsyncFileEngine::from_file(file).map_err(BlockIoError::Async)?,
+                AsyncFileEngine::from_file(file, io_uring).map_err(BlockIoError::Async)?,
             )),
             FileEngineType::Sync => Ok(FileEngine::Sync(SyncFileEngine::from_file(file))),
         }
@@ -95,6 +189,29 @@ impl<T: Debug> FileEngine<T> {
         }
     }
 
+    /// Whether data was written to the backing file since it was last synced.
+    pub fn is_dirty(&self) -> bool {
+        match self {
+            FileEngine::Async(engine) => engine.is_dirty(),
+            FileEngine::Sync(engine) => engine.is_dirty(),
+        }
+    }
+
+    /// Whether the transfer of the `count` bytes of guest memory at `addr` goes through a bounce
+    /// buffer, because the backing file was opened with `O_DIRECT` and the memory isn't aligned.
+    pub fn needs_bounce_buffer(
+        &self,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> bool {
+        let direct = match self {
+            FileEngine::Async(engine) => engine.is_direct(),
+            FileEngine::Sync(engine) => engine.is_direct(),
+        };
+        direct && !is_direct_io_aligned(mem, addr, count)
+    }
+
     pub fn read(
         &mut self,
         offset: u64,
@@ -176,6 +293,63 @@ impl<T: Debug> FileEngine<T> {
         }
     }
 
+    pub fn discard(
+        &mut self,
+        offset: u64,
+        len: u64,
+        user_data: T,
+    ) -> Result<FileEngineOk<T>, UserDataError<T, BlockIoError>> {
+        match self {
+            FileEngine::Async(engine) => match engine.push_discard(offset, len, user_data) {
+                Ok(_) => Ok(FileEngineOk::Submitted),
+                Err(err) => Err(UserDataError {
+                    user_data: err.user_data,
+                    error: BlockIoError::Async(err.error),
+                }),
+            },
+            FileEngine::Sync(engine) => match engine.discard(offset, len) {
+                Ok(_) => Ok(FileEngineOk::Executed(UserDataOk {
+                    user_data,
+                    count: 0,
+                })),
+                Err(err) => Err(UserDataError {
+                    user_data,
+                    error: BlockIoError::Sync(err),
+                }),
+            },
+        }
+    }
+
+    pub fn write_zeroes(
+        &mut self,
+        offset: u64,
+        len: u64,
+        unmap: bool,
+        user_data: T,
+    ) -> Result<FileEngineOk<T>, UserDataError<T, BlockIoError>> {
+        match self {
+            FileEngine::Async(engine) => {
+                match engine.push_write_zeroes(offset, len, unmap, user_data) {
+                    Ok(_) => Ok(FileEngineOk::Submitted),
+                    Err(err) => Err(UserDataError {
+                        user_data: err.user_data,
+                        error: BlockIoError::Async(err.error),
+                    }),
+                }
+            }
+            FileEngine::Sync(engine) => match engine.write_zeroes(offset, len, unmap) {
+                Ok(_) => Ok(FileEngineOk::Executed(UserDataOk {
+                    user_data,
+                    count: 0,
+                })),
+                Err(err) => Err(UserDataError {
+                    user_data,
+                    error: BlockIoError::Sync(err),
+                }),
+            },
+        }
+    }
+
     pub fn drain(&mut self, discard: bool) -> Result<(), BlockIoError> {
         match self {
             FileEngine::Async(engine) => engine.drain(discard).map_err(BlockIoError::Async),
@@ -196,6 +370,7 @@ impl<T: Debug> FileEngine<T> {
 #[cfg(test)]
 pub mod tests {
     #![allow(clippy::undocumented_unsafe_blocks)]
+    use std::fs::OpenOptions;
     use std::os::unix::ffi::OsStrExt;
     use std::os::unix::io::FromRawFd;
 
@@ -206,6 +381,9 @@ pub mod tests {
     use super::*;
     use crate::devices::virtio::block::virtio::device::FileEngineType;
     use crate::devices::virtio::block::virtio::request::PendingRequest;
+    use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut};
+    use crate::devices::virtio::queue::{Queue, VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+    use crate::devices::virtio::test_utils::VirtQueue;
     use crate::vmm_config::machine_config::HugePageConfig;
     use crate::vstate::memory::{Bitmap, Bytes, GuestMemory, GuestMemoryExtension};
 
@@ -282,7 +460,8 @@ pub mod tests {
         assert!(matches!(
             FileEngine::<PendingRequest>::from_file(
                 TempFile::new().unwrap().into_file(),
-                FileEngineType::Async
+                FileEngineType::Async,
+                IoUringConfig::default()
             ),
             Err(BlockIoError::UnsupportedEngine(FileEngineType::Async))
         ));
@@ -293,17 +472,26 @@ pub mod tests {
         // Check invalid file
         let mem = create_mem();
         let file = unsafe { File::from_raw_fd(-2) };
-        let mut engine = FileEngine::from_file(file, FileEngineType::Sync).unwrap();
-        let res = engine.read(0, &mem, GuestAddress(0), 0, ());
-        assert_err!(res, BlockIoError::Sync(sync_io::SyncIoError::Seek(_e)));
-        let res = engine.write(0, &mem, GuestAddress(0), 0, ());
-        assert_err!(res, BlockIoError::Sync(sync_io::SyncIoError::Seek(_e)));
+        let mut engine =
+            FileEngine::from_file(file, FileEngineType::Sync, IoUringConfig::default()).unwrap();
+        let res = engine.read(0, &mem, GuestAddress(0), 1, ());
+        assert_err!(
+            res,
+            BlockIoError::Sync(sync_io::SyncIoError::Transfer(IoVecError::FileIo(_e)))
+        );
+        let res = engine.write(0, &mem, GuestAddress(0), 1, ());
+        assert_err!(
+            res,
+            BlockIoError::Sync(sync_io::SyncIoError::Transfer(IoVecError::FileIo(_e)))
+        );
         let res = engine.flush(());
         assert_err!(res, BlockIoError::Sync(sync_io::SyncIoError::SyncAll(_e)));
 
         // Create backing file.
         let file = TempFile::new().unwrap().into_file();
-        let mut engine = FileEngine::from_file(file, FileEngineType::Sync).unwrap();
+        let mut engine =
+            FileEngine::from_file(file, FileEngineType::Sync, IoUringConfig::default()).unwrap();
+        assert!(!engine.is_dirty());
 
         let data = utils::rand::rand_alphanumerics(FILE_LEN as usize)
             .as_bytes()
@@ -359,23 +547,305 @@ pub mod tests {
         mem.read_slice(&mut buf, GuestAddress(0)).unwrap();
         assert_eq!(buf, data.as_slice());
 
+        // Read past the end of the file
+        assert_err!(
+            engine.read(u64::from(FILE_LEN) - 10, &mem, GuestAddress(0), 50, ()),
+            BlockIoError::Sync(sync_io::SyncIoError::PartialTransfer {
+                expected: 50,
+                completed: 10
+            })
+        );
+
         // Check other ops
+        assert!(engine.is_dirty());
         engine.flush(()).unwrap();
+        assert!(!engine.is_dirty());
         engine.drain(true).unwrap();
         engine.drain_and_flush(true).unwrap();
     }
 
+    #[test]
+    fn test_sync_detect_zeroes() {
+        use std::io::{Read, Seek, SeekFrom};
+        use std::os::linux::fs::MetadataExt;
+
+        use sync_io::DETECT_ZEROES_BLOCK_SIZE;
+
+        const DATA_LEN: u32 = 0x4000;
+        let allocated =
+            |engine: &SyncFileEngine| engine.file().metadata().unwrap().st_blocks() * 512;
+
+        let mem = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), 2 * DATA_LEN as usize)],
+            false,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        let file = TempFile::new().unwrap().into_file();
+        file.set_len(0x8000).unwrap();
+        let mut engine = SyncFileEngine::from_file(file);
+
+        // Non-zero data is written to the backing file as is.
+        let data = vec![0xaau8; DATA_LEN as usize];
+        mem.write(&data, GuestAddress(0)).unwrap();
+        let offset = 512;
+        assert_eq!(
+            engine
+                .write_detect_zeroes(offset, &mem, GuestAddress(0), DATA_LEN)
+                .unwrap(),
+            (DATA_LEN, 0)
+        );
+        let allocated_before = allocated(&engine);
+        assert!(allocated_before >= u64::from(DATA_LEN));
+
+        // Zero out everything except for the unaligned head and the last full block. Only the
+        // two aligned zero blocks in between can be unmapped, the unaligned zero tail is written.
+        let mut data = vec![0u8; DATA_LEN as usize];
+        let head = u64_to_usize(DETECT_ZEROES_BLOCK_SIZE - offset);
+        let last_block = head + 2 * u64_to_usize(DETECT_ZEROES_BLOCK_SIZE);
+        data[..head].fill(0x55);
+        data[last_block..last_block + u64_to_usize(DETECT_ZEROES_BLOCK_SIZE)].fill(0x55);
+        mem.write(&data, GuestAddress(0)).unwrap();
+        assert_eq!(
+            engine
+                .write_detect_zeroes(offset, &mem, GuestAddress(0), DATA_LEN)
+                .unwrap(),
+            (DATA_LEN, 2 * DETECT_ZEROES_BLOCK_SIZE)
+        );
+        assert_eq!(
+            allocated(&engine),
+            allocated_before - 2 * DETECT_ZEROES_BLOCK_SIZE
+        );
+
+        // The unmapped ranges read back as zeroes.
+        let mut buf = vec![0u8; DATA_LEN as usize];
+        let mut file = engine.file();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        // Requests shorter than a block are always written.
+        assert_eq!(
+            engine
+                .write_detect_zeroes(0, &mem, GuestAddress(DATA_LEN.into()), 512)
+                .unwrap(),
+            (512, 0)
+        );
+
+        // The zeroes are written when holes can't be punched in the backing file.
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/zero")
+            .unwrap();
+        let mut engine = SyncFileEngine::from_file(file);
+        assert_eq!(
+            engine
+                .write_detect_zeroes(0, &mem, GuestAddress(DATA_LEN.into()), DATA_LEN)
+                .unwrap(),
+            (DATA_LEN, 0)
+        );
+    }
+
+    fn assert_execution(
+        mem: &GuestMemoryMmap,
+        engine: &mut FileEngine<()>,
+        res: Result<FileEngineOk<()>, UserDataError<(), BlockIoError>>,
+        count: u32,
+    ) {
+        match res {
+            Ok(FileEngineOk::Submitted) => assert_async_execution(mem, engine, count),
+            res => assert_sync_execution!(res, count),
+        }
+    }
+
+    fn check_discard_write_zeroes(engine_type: FileEngineType) {
+        use std::os::linux::fs::MetadataExt;
+        use std::os::unix::fs::FileExt;
+
+        const DISK_LEN: usize = 0x10000;
+        let allocated =
+            |engine: &FileEngine<()>| engine.file().metadata().unwrap().st_blocks() * 512;
+        let read_back = |engine: &FileEngine<()>, offset: u64, len: usize| {
+            let mut buf = vec![0u8; len];
+            engine.file().read_exact_at(&mut buf, offset).unwrap();
+            buf
+        };
+
+        let mem = create_mem();
+        let file = TempFile::new().unwrap().into_file();
+        file.write_all_at(&vec![0xaa; DISK_LEN], 0).unwrap();
+        file.sync_all().unwrap();
+        let mut engine =
+            FileEngine::<()>::from_file(file, engine_type, IoUringConfig::default()).unwrap();
+        let allocated_before = allocated(&engine);
+        assert!(allocated_before >= DISK_LEN as u64);
+
+        // Discarding a range releases its blocks.
+        let res = engine.discard(0x2000, 0x4000, ());
+        assert_execution(&mem, &mut engine, res, 0);
+        assert_eq!(allocated(&engine), allocated_before - 0x4000);
+
+        // Zeroing a range without unmapping it.
+        let res = engine.write_zeroes(0x8000, 0x2000, false, ());
+        assert_execution(&mem, &mut engine, res, 0);
+        assert_eq!(read_back(&engine, 0x8000, 0x2000), vec![0u8; 0x2000]);
+
+        // Zeroing a range while unmapping it also releases its blocks.
+        let res = engine.write_zeroes(0xc000, 0x2000, true, ());
+        assert_execution(&mem, &mut engine, res, 0);
+        assert_eq!(read_back(&engine, 0xc000, 0x2000), vec![0u8; 0x2000]);
+        assert_eq!(allocated(&engine), allocated_before - 0x6000);
+
+        // The data around the ranges is left in place.
+        assert_eq!(read_back(&engine, 0, 0x2000), vec![0xaa; 0x2000]);
+        assert_eq!(read_back(&engine, 0xa000, 0x2000), vec![0xaa; 0x2000]);
+        assert_eq!(read_back(&engine, 0xe000, 0x2000), vec![0xaa; 0x2000]);
+    }
+
+    #[test]
+    fn test_sync_discard_write_zeroes() {
+        use std::os::unix::fs::FileExt;
+
+        check_discard_write_zeroes(FileEngineType::Sync);
+
+        // The fallback of the file systems that can't zero a range with `fallocate`, writing
+        // zeroes over several chunks.
+        let file = TempFile::new().unwrap().into_file();
+        file.write_all_at(&vec![0x55; 0x30000], 0).unwrap();
+        sync_io::write_zeroes_at(&file, 0x100, 0x20000).unwrap();
+        let mut buf = vec![0u8; 0x30000];
+        file.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf[..0x100].iter().all(|b| *b == 0x55));
+        assert!(buf[0x100..0x20100].iter().all(|b| *b == 0));
+        assert!(buf[0x20100..].iter().all(|b| *b == 0x55));
+    }
+
+    #[test]
+    fn test_async_discard_write_zeroes() {
+        skip_if_io_uring_unsupported!();
+
+        check_discard_write_zeroes(FileEngineType::Async);
+    }
+
+    // Opens the backing file again with `O_DIRECT`, or returns `None` when its file system doesn't
+    // support it, e.g. tmpfs before Linux 6.6.
+    fn reopen_direct(backing_file: &TempFile) -> Option<File> {
+        use std::fs::OpenOptions;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(backing_file.as_path())
+        {
+            Ok(file) => Some(file),
+            Err(err) if err.raw_os_error() == Some(libc::EINVAL) => None,
+            Err(err) => panic!("Failed to open the backing file with O_DIRECT: {err}"),
+        }
+    }
+
+    fn check_direct_io(engine_type: FileEngineType) {
+        use std::os::unix::fs::FileExt;
+
+        let backing_file = TempFile::new().unwrap();
+        backing_file.as_file().set_len(0x4000).unwrap();
+        let Some(file) = reopen_direct(&backing_file) else {
+            return;
+        };
+        let mut engine =
+            FileEngine::<()>::from_file(file, engine_type, IoUringConfig::default()).unwrap();
+        let data = utils::rand::rand_alphanumerics(0x1000).as_bytes().to_vec();
+
+        // Aligned guest memory is transferred in place.
+        let mem = create_mem();
+        let aligned = GuestAddress(0);
+        assert!(!engine.needs_bounce_buffer(&mem, aligned, 0x1000));
+        mem.write_slice(&data, aligned).unwrap();
+        let res = engine.write(0, &mem, aligned, 0x1000, ());
+        assert_execution(&mem, &mut engine, res, 0x1000);
+
+        // Guest memory that isn't aligned goes through a bounce buffer, which reads copy to guest
+        // memory, marking it dirty.
+        let mem = create_mem();
+        let misaligned = GuestAddress(0x1001);
+        assert!(engine.needs_bounce_buffer(&mem, misaligned, 0x800));
+        let res = engine.read(0x200, &mem, misaligned, 0x800, ());
+        assert_execution(&mem, &mut engine, res, 0x800);
+        let mut buf = vec![0u8; 0x800];
+        mem.read_slice(&mut buf, misaligned).unwrap();
+        assert_eq!(buf, data[0x200..0xa00]);
+        check_dirty_mem(&mem, misaligned, 0x800);
+        check_clean_mem(&mem, GuestAddress(0), 0x1000);
+
+        // And writes fill from guest memory.
+        let res = engine.write(0x2000, &mem, misaligned, 0x800, ());
+        assert_execution(&mem, &mut engine, res, 0x800);
+        let mut buf = vec![0u8; 0x800];
+        backing_file
+            .as_file()
+            .read_exact_at(&mut buf, 0x2000)
+            .unwrap();
+        assert_eq!(buf, data[0x200..0xa00]);
+
+        // Flushes still reach the backing file.
+        let res = engine.flush(());
+        assert_execution(&mem, &mut engine, res, 0);
+
+        // Transfers never go through a bounce buffer without `O_DIRECT`.
+        let file = backing_file.as_file().try_clone().unwrap();
+        let engine =
+            FileEngine::<()>::from_file(file, engine_type, IoUringConfig::default()).unwrap();
+        assert!(!engine.needs_bounce_buffer(&mem, misaligned, 0x800));
+    }
+
+    #[test]
+    fn test_sync_direct_io() {
+        check_direct_io(FileEngineType::Sync);
+    }
+
+    #[test]
+    fn test_async_direct_io() {
+        skip_if_io_uring_unsupported!();
+
+        check_direct_io(FileEngineType::Async);
+    }
+
+    #[test]
+    fn test_is_out_of_space() {
+        let mem = create_mem();
+        // Writes to /dev/full fail with ENOSPC.
+        let file = OpenOptions::new().write(true).open("/dev/full").unwrap();
+        let mut engine =
+            FileEngine::from_file(file, FileEngineType::Sync, IoUringConfig::default()).unwrap();
+        let err = engine
+            .write(0, &mem, GuestAddress(0), 512, ())
+            .unwrap_err()
+            .error;
+        assert!(err.is_out_of_space(), "{:?}", err);
+
+        let quota = std::io::Error::from_raw_os_error(libc::EDQUOT);
+        assert!(BlockIoError::Async(AsyncIoError::IO(quota)).is_out_of_space());
+        let interrupted = std::io::Error::from_raw_os_error(libc::EINTR);
+        assert!(!BlockIoError::Async(AsyncIoError::IO(interrupted)).is_out_of_space());
+        let flush = std::io::Error::from_raw_os_error(libc::ENOSPC);
+        assert!(!BlockIoError::Sync(SyncIoError::Flush(flush)).is_out_of_space());
+    }
+
     #[test]
     fn test_async() {
         skip_if_io_uring_unsupported!();
 
         // Check invalid file
         let file = unsafe { File::from_raw_fd(-2) };
-        FileEngine::<()>::from_file(file, FileEngineType::Async).unwrap_err();
+        FileEngine::<()>::from_file(file, FileEngineType::Async, IoUringConfig::default())
+            .unwrap_err();
 
         // Create backing file.
         let file = TempFile::new().unwrap().into_file();
-        let mut engine = FileEngine::<()>::from_file(file, FileEngineType::Async).unwrap();
+        let mut engine =
+            FileEngine::<()>::from_file(file, FileEngineType::Async, IoUringConfig::default())
+                .unwrap();
 
         let data = utils::rand::rand_alphanumerics(FILE_LEN as usize)
             .as_bytes()
@@ -422,10 +892,215 @@ pub mod tests {
         check_clean_mem(&mem, GuestAddress(4096), 4096);
 
         // Check other ops
+        assert!(engine.is_dirty());
         assert_queued!(engine.flush(()));
         assert_async_execution(&mem, &mut engine, 0);
+        assert!(!engine.is_dirty());
+
+        // The flushes requested before a submission are coalesced into a single `fsync`, which
+        // completes all of them after the write submitted before it.
+        assert_queued!(engine.write(0, &mem, addr, FILE_LEN, ()));
+        assert_queued!(engine.flush(()));
+        assert_queued!(engine.flush(()));
+        assert!(engine.is_dirty());
+        if let FileEngine::Async(ref mut engine) = engine {
+            assert_eq!(engine.kick_submission_queue().unwrap(), 2);
+            engine.drain(false).unwrap();
+            let results = (0..3)
+                .map(|_| engine.pop(&mem).unwrap().unwrap().result().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![FILE_LEN, 0, 0]);
+            assert!(engine.pop(&mem).unwrap().is_none());
+        }
+        assert!(!engine.is_dirty());
 
         engine.drain(true).unwrap();
         engine.drain_and_flush(true).unwrap();
     }
+
+    // Makes a descriptor chain of the `(address, length)` pairs available in a queue placed at the
+    // start of guest memory.
+    fn chain_of(
+        mem: &GuestMemoryMmap,
+        descs: &[(u64, u32)],
+        is_write_only: bool,
+    ) -> (Queue, VirtQueue) {
+        let vq = VirtQueue::new(GuestAddress(0), mem, 16);
+        let mut q = vq.create_queue();
+        q.ready = true;
+
+        let flags = if is_write_only {
+            VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE
+        } else {
+            VIRTQ_DESC_F_NEXT
+        };
+        for (j, &(addr, len)) in (0..).zip(descs) {
+            vq.dtable[usize::from(j)].set(addr, len, flags, j + 1);
+        }
+        vq.dtable[descs.len() - 1]
+            .flags
+            .set(flags & !VIRTQ_DESC_F_NEXT);
+        vq.avail.ring[0].set(0);
+        vq.avail.idx.set(1);
+
+        (q, vq)
+    }
+
+    #[test]
+    fn test_async_vectored() {
+        use std::io::Write;
+        use std::os::unix::fs::FileExt;
+
+        skip_if_io_uring_unsupported!();
+
+        let mem = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), 0x20000)],
+            true,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        let file = TempFile::new().unwrap().into_file();
+        let mut engine = AsyncFileEngine::<u32>::from_file(file, IoUringConfig::default()).unwrap();
+        let data = utils::rand::rand_alphanumerics(0x3000).as_bytes().to_vec();
+
+        // A write gathered from two segments, laid out in reverse order in guest memory.
+        let (mut q, _vq) = chain_of(&mem, &[(0x18000, 0x1000), (0x10000, 0x2000)], false);
+        mem.write_slice(&data[..0x1000], GuestAddress(0x18000))
+            .unwrap();
+        mem.write_slice(&data[0x1000..], GuestAddress(0x10000))
+            .unwrap();
+        let buffer = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        engine.push_writev(0x200, buffer, 1).unwrap();
+        assert!(engine.is_dirty());
+        engine.drain(false).unwrap();
+        let cqe = engine.pop(&mem).unwrap().unwrap();
+        assert_eq!(cqe.result().unwrap(), 0x3000);
+        assert_eq!(cqe.user_data(), 1);
+        let mut buf = vec![0u8; 0x3000];
+        engine.file().read_exact_at(&mut buf, 0x200).unwrap();
+        assert_eq!(buf, data);
+
+        // A read scattered to three segments. Its completion marks exactly those dirty.
+        let (mut q, _vq) = chain_of(
+            &mem,
+            &[(0x14000, 0x800), (0x1c000, 0x1000), (0x12000, 0x1800)],
+            true,
+        );
+        mem.reset_dirty();
+        let buffer = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        engine.push_readv(0x200, buffer, 2).unwrap();
+        engine.drain(false).unwrap();
+        let cqe = engine.pop(&mem).unwrap().unwrap();
+        assert_eq!(cqe.result().unwrap(), 0x3000);
+        assert_eq!(cqe.user_data(), 2);
+        let mut buf = vec![0u8; 0x3000];
+        mem.read_slice(&mut buf[..0x800], GuestAddress(0x14000))
+            .unwrap();
+        mem.read_slice(&mut buf[0x800..0x1800], GuestAddress(0x1c000))
+            .unwrap();
+        mem.read_slice(&mut buf[0x1800..], GuestAddress(0x12000))
+            .unwrap();
+        assert_eq!(buf, data);
+        check_dirty_mem(&mem, GuestAddress(0x12000), 0x1800);
+        check_dirty_mem(&mem, GuestAddress(0x14000), 0x800);
+        check_dirty_mem(&mem, GuestAddress(0x1c000), 0x1000);
+        check_clean_mem(&mem, GuestAddress(0x10000), 0x2000);
+        check_clean_mem(&mem, GuestAddress(0x18000), 0x1000);
+
+        // A read from an empty pipe stays in flight until data is written to it. Meanwhile, the
+        // engine keeps taking operations and completing them.
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        let (reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let mut engine =
+            AsyncFileEngine::<u32>::from_file(reader, IoUringConfig::default()).unwrap();
+        let (mut q, _vq) = chain_of(&mem, &[(0x14000, 0x800), (0x12000, 0x800)], true);
+        mem.reset_dirty();
+        let buffer = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        engine.push_readv(0, buffer, 3).unwrap();
+        assert_eq!(engine.kick_submission_queue().unwrap(), 1);
+        assert!(engine.pop(&mem).unwrap().is_none());
+
+        // Writing to the read end of the pipe fails right away.
+        let (mut q, _vq) = chain_of(&mem, &[(0x10000, 0x100)], false);
+        let buffer = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        engine.push_writev(0, buffer, 4).unwrap();
+        assert_eq!(engine.kick_submission_queue().unwrap(), 1);
+        let cqe = (0..100)
+            .find_map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                engine.pop(&mem).unwrap()
+            })
+            .unwrap();
+        assert_eq!(cqe.result().unwrap_err().raw_os_error(), Some(libc::EBADF));
+        assert_eq!(cqe.user_data(), 4);
+        check_clean_mem(&mem, GuestAddress(0x12000), 0x800);
+        check_clean_mem(&mem, GuestAddress(0x14000), 0x800);
+
+        (&writer).write_all(&data[..0x1000]).unwrap();
+        engine.drain(false).unwrap();
+        let cqe = engine.pop(&mem).unwrap().unwrap();
+        assert_eq!(cqe.result().unwrap(), 0x1000);
+        assert_eq!(cqe.user_data(), 3);
+        let mut buf = vec![0u8; 0x1000];
+        mem.read_slice(&mut buf[..0x800], GuestAddress(0x14000))
+            .unwrap();
+        mem.read_slice(&mut buf[0x800..], GuestAddress(0x12000))
+            .unwrap();
+        assert_eq!(buf, data[..0x1000]);
+        check_dirty_mem(&mem, GuestAddress(0x12000), 0x800);
+        check_dirty_mem(&mem, GuestAddress(0x14000), 0x800);
+        assert!(engine.pop(&mem).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_async_io_uring_config() {
+        skip_if_io_uring_unsupported!();
+
+        let data = utils::rand::rand_alphanumerics(FILE_LEN as usize)
+            .as_bytes()
+            .to_vec();
+        for sqpoll in [false, true] {
+            let config = IoUringConfig {
+                ring_size: 16,
+                sqpoll,
+                sqpoll_idle_ms: 10,
+            };
+            let file = TempFile::new().unwrap().into_file();
+            let mut engine = match FileEngine::<()>::from_file(file, FileEngineType::Async, config)
+            {
+                Ok(engine) => engine,
+                // Polling the submission queue requires CAP_SYS_ADMIN before Linux 5.11.
+                Err(BlockIoError::Async(AsyncIoError::IoUring(
+                    crate::io_uring::IoUringError::Setup(_),
+                ))) if sqpoll => return,
+                Err(err) => panic!("{:?}", err),
+            };
+
+            let mem = create_mem();
+            let addr = GuestAddress(0);
+            mem.write(&data, addr).unwrap();
+            assert_queued!(engine.write(0, &mem, addr, FILE_LEN, ()));
+            assert_async_execution(&mem, &mut engine, FILE_LEN);
+            // Let the kernel thread go idle, so that the next submission has to wake it up.
+            std::thread::sleep(std::time::Duration::from_millis(50));
+
+            let mem = create_mem();
+            assert_queued!(engine.read(0, &mem, addr, FILE_LEN, ()));
+            assert_async_execution(&mem, &mut engine, FILE_LEN);
+            let mut buf = vec![0u8; FILE_LEN as usize];
+            mem.read_slice(&mut buf, addr).unwrap();
+            assert_eq!(buf, data);
+
+            // The submission queue holds `ring_size` entries, unless a kernel thread consumes them.
+            if !sqpoll {
+                for _ in 0..config.ring_size {
+                    assert_queued!(engine.read(0, &mem, addr, FILE_LEN, ()));
+                }
+                let res = engine.read(0, &mem, addr, FILE_LEN, ());
+                assert!(res.unwrap_err().error.is_throttling_err());
+            }
+            engine.drain(true).unwrap();
+        }
+    }
 }
@@ -2,27 +2,76 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::fs::File;
-use std::io::{Seek, SeekFrom, Write};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
 
-use vm_memory::{GuestMemoryError, ReadVolatile, WriteVolatile};
+use utils::u64_to_usize;
 
-use crate::vstate::memory::{GuestAddress, GuestMemory, GuestMemoryMmap};
+use crate::devices::virtio::block::virtio::io::{is_direct, is_direct_io_aligned, BounceBuffer};
+use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut, IoVecError};
+use crate::vstate::memory::{Address, Bytes, GuestAddress, GuestMemoryMmap};
+
+/// Granularity at which write data is checked for zeroes and unmapped from the backing file.
+pub const DETECT_ZEROES_BLOCK_SIZE: u64 = 4096;
+
+// Zeroes written at once when the file system can't zero a range with `fallocate`, aligned so
+// that they can be written to backing files opened with `O_DIRECT`.
+#[repr(align(4096))]
+struct Zeroes([u8; 64 << 10]);
+static ZEROES: Zeroes = Zeroes([0; 64 << 10]);
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum SyncIoError {
+    /// Bounce buffer transfer: {0}
+    Bounce(std::io::Error),
     /// Flush: {0}
     Flush(std::io::Error),
-    /// Seek: {0}
-    Seek(std::io::Error),
+    /// Partial transfer of {completed} out of {expected} bytes
+    PartialTransfer { expected: u32, completed: usize },
+    /// PunchHole: {0}
+    PunchHole(std::io::Error),
     /// SyncAll: {0}
     SyncAll(std::io::Error),
     /// Transfer: {0}
-    Transfer(GuestMemoryError),
+    Transfer(IoVecError),
+    /// WriteZeroes: {0}
+    WriteZeroes(std::io::Error),
+}
+
+/// The `fallocate` mode zeroing a range of the backing file, unmapping it if `unmap` is set.
+pub fn write_zeroes_mode(unmap: bool) -> libc::c_int {
+    let mode = match unmap {
+        true => libc::FALLOC_FL_PUNCH_HOLE,
+        false => libc::FALLOC_FL_ZERO_RANGE,
+    };
+    mode | libc::FALLOC_FL_KEEP_SIZE
+}
+
+/// Whether an `fallocate` call failed because the file system doesn't support its mode.
+pub fn is_unsupported(err: &std::io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EOPNOTSUPP)
+}
+
+/// Writes `len` zeroes at `offset` of `file`, for the file systems that can't zero a range with
+/// `fallocate`.
+pub fn write_zeroes_at(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    let end = offset + len;
+    let mut pos = offset;
+    while pos < end {
+        let chunk = u64_to_usize(end - pos).min(ZEROES.0.len());
+        file.write_all_at(&ZEROES.0[..chunk], pos)?;
+        pos += chunk as u64;
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
 pub struct SyncFileEngine {
     file: File,
+    direct: bool,
+    // Whether data was written to the backing file since it was last synced.
+    dirty: bool,
 }
 
 // SAFETY: `File` is send and ultimately a POD.
@@ -30,7 +79,11 @@ unsafe impl Send for SyncFileEngine {}
 
 impl SyncFileEngine {
     pub fn from_file(file: File) -> SyncFileEngine {
-        SyncFileEngine { file }
+        SyncFileEngine {
+            direct: is_direct(&file),
+            file,
+            dirty: false,
+        }
     }
 
     #[cfg(test)]
@@ -40,7 +93,19 @@ impl SyncFileEngine {
 
     /// Update the backing file of the engine
     pub fn update_file(&mut self, file: File) {
-        self.file = file
+        self.direct = is_direct(&file);
+        self.file = file;
+        self.dirty = false;
+    }
+
+    /// Whether the backing file was opened with `O_DIRECT`.
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    /// Whether data was written to the backing file since it was last synced.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
     }
 
     pub fn read(
@@ -50,13 +115,13 @@ impl SyncFileEngine {
         addr: GuestAddress,
         count: u32,
     ) -> Result<u32, SyncIoError> {
-        self.file
-            .seek(SeekFrom::Start(offset))
-            .map_err(SyncIoError::Seek)?;
-        mem.get_slice(addr, count as usize)
-            .and_then(|mut slice| Ok(self.file.read_exact_volatile(&mut slice)?))
+        if self.direct && !is_direct_io_aligned(mem, addr, count) {
+            return self.read_bounced(offset, mem, addr, count);
+        }
+        let bytes_read = IoVecBufferMut::from_guest_memory(mem, addr, count)
+            .and_then(|mut buffer| buffer.read_from_file_at(&self.file, offset))
             .map_err(SyncIoError::Transfer)?;
-        Ok(count)
+        Self::check_transferred(count, bytes_read)
     }
 
     pub fn write(
@@ -66,19 +131,208 @@ impl SyncFileEngine {
         addr: GuestAddress,
         count: u32,
     ) -> Result<u32, SyncIoError> {
-        self.file
-            .seek(SeekFrom::Start(offset))
-            .map_err(SyncIoError::Seek)?;
-        mem.get_slice(addr, count as usize)
-            .and_then(|slice| Ok(self.file.write_all_volatile(&slice)?))
+        self.dirty = true;
+        if self.direct && !is_direct_io_aligned(mem, addr, count) {
+            return self.write_bounced(offset, mem, addr, count);
+        }
+        let bytes_written = IoVecBuffer::from_guest_memory(mem, addr, count)
+            .and_then(|buffer| buffer.write_to_file_at(&self.file, offset))
             .map_err(SyncIoError::Transfer)?;
+        Self::check_transferred(count, bytes_written)
+    }
+
+    // Reads into an aligned bounce buffer first, then copies the bytes read to guest memory.
+    fn read_bounced(
+        &mut self,
+        offset: u64,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> Result<u32, SyncIoError> {
+        let mut bounce = BounceBuffer::new(count as usize);
+        let buf = bounce.as_mut_slice();
+        let bytes_read = Self::transfer_bounced(buf.len(), |done| {
+            self.file.read_at(&mut buf[done..], offset + done as u64)
+        })?;
+        mem.write_slice(&bounce.as_slice()[..bytes_read], addr)
+            .map_err(|err| SyncIoError::Transfer(err.into()))?;
+        Self::check_transferred(count, bytes_read)
+    }
+
+    // Copies the guest memory to an aligned bounce buffer first, then writes it.
+    fn write_bounced(
+        &mut self,
+        offset: u64,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> Result<u32, SyncIoError> {
+        let mut bounce = BounceBuffer::new(count as usize);
+        mem.read_slice(bounce.as_mut_slice(), addr)
+            .map_err(|err| SyncIoError::Transfer(err.into()))?;
+        let buf = bounce.as_slice();
+        let bytes_written = Self::transfer_bounced(buf.len(), |done| {
+            self.file.write_at(&buf[done..], offset + done as u64)
+        })?;
+        Self::check_transferred(count, bytes_written)
+    }
+
+    // Repeats `op` on the rest of a bounce buffer of `len` bytes until it is all transferred, or
+    // until `op` transfers nothing, i.e. at the end of the file. Returns the bytes transferred.
+    fn transfer_bounced(
+        len: usize,
+        mut op: impl FnMut(usize) -> std::io::Result<usize>,
+    ) -> Result<usize, SyncIoError> {
+        let mut done = 0;
+        while done < len {
+            match op(done) {
+                Ok(0) => break,
+                Ok(bytes) => done += bytes,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(SyncIoError::Bounce(err)),
+            }
+        }
+        Ok(done)
+    }
+
+    // Fails the transfers stopping short of `count` bytes, i.e. reads reaching the end of the file.
+    fn check_transferred(count: u32, bytes: usize) -> Result<u32, SyncIoError> {
+        if bytes != count as usize {
+            return Err(SyncIoError::PartialTransfer {
+                expected: count,
+                completed: bytes,
+            });
+        }
         Ok(count)
     }
 
+    /// Writes `count` bytes like `write`, but punches holes in the backing file instead of
+    /// writing the block aligned ranges that only contain zeroes.
+    ///
+    /// Returns the number of bytes written and the number of bytes unmapped. If holes can't be
+    /// punched in the backing file, the zeroes are written instead.
+    pub fn write_detect_zeroes(
+        &mut self,
+        offset: u64,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> Result<(u32, u64), SyncIoError> {
+        let end = offset + u64::from(count);
+        let buffer =
+            IoVecBuffer::from_guest_memory(mem, addr, count).map_err(SyncIoError::Transfer)?;
+
+        // Collect the runs of zero blocks first, so that the data in between them can be
+        // written with as few calls as possible.
+        let mut holes: Vec<(u64, u64)> = Vec::new();
+        let mut block = offset.next_multiple_of(DETECT_ZEROES_BLOCK_SIZE);
+        while block + DETECT_ZEROES_BLOCK_SIZE <= end {
+            let block_end = block + DETECT_ZEROES_BLOCK_SIZE;
+            let is_zero_block = buffer
+                .is_zero_range(
+                    u64_to_usize(block - offset),
+                    u64_to_usize(DETECT_ZEROES_BLOCK_SIZE),
+                )
+                .map_err(|err| SyncIoError::Transfer(err.into()))?;
+            if is_zero_block {
+                match holes.last_mut() {
+                    Some((_, hole_end)) if *hole_end == block => *hole_end = block_end,
+                    _ => holes.push((block, block_end)),
+                }
+            }
+            block = block_end;
+        }
+
+        let mut unmapped = 0;
+        let mut pos = offset;
+        for (hole_start, hole_end) in holes {
+            self.write_range(pos, hole_start, offset, mem, addr)?;
+            // If the backing file can't be unmapped, write the rest of the request as it is.
+            if self.punch_hole(hole_start, hole_end - hole_start).is_err() {
+                break;
+            }
+            unmapped += hole_end - hole_start;
+            pos = hole_end;
+        }
+        self.write_range(pos, end, offset, mem, addr)?;
+
+        Ok((count, unmapped))
+    }
+
+    // Writes the file range `[start, end)` from the request buffer that starts at `addr` and is
+    // mapped at file offset `offset`.
+    fn write_range(
+        &mut self,
+        start: u64,
+        end: u64,
+        offset: u64,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+    ) -> Result<(), SyncIoError> {
+        if start == end {
+            return Ok(());
+        }
+        // The range is part of a request of at most `u32::MAX` bytes.
+        let len = u32::try_from(end - start).unwrap();
+        self.write(start, mem, addr.unchecked_add(start - offset), len)?;
+        Ok(())
+    }
+
+    // Applies `fallocate` with `mode` to the `len` bytes at `offset` of the backing file.
+    fn fallocate(&self, mode: libc::c_int, offset: u64, len: u64) -> std::io::Result<()> {
+        let to_off_t = |value: u64| {
+            libc::off_t::try_from(value)
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))
+        };
+        let (offset, len) = (to_off_t(offset)?, to_off_t(len)?);
+        // SAFETY: `fallocate` only operates on the file descriptor owned by `self.file`
+        // and we check its return value.
+        let ret = unsafe { libc::fallocate(self.file.as_raw_fd(), mode, offset, len) };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn punch_hole(&mut self, offset: u64, len: u64) -> Result<(), SyncIoError> {
+        self.dirty = true;
+        self.fallocate(
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset,
+            len,
+        )
+        .map_err(SyncIoError::PunchHole)
+    }
+
+    /// Discards the `len` bytes at `offset`, punching a hole in the backing file.
+    ///
+    /// Discards are only hints: when the file system can't punch holes, the data is left in place
+    /// and the discard still succeeds.
+    pub fn discard(&mut self, offset: u64, len: u64) -> Result<(), SyncIoError> {
+        match self.punch_hole(offset, len) {
+            Err(SyncIoError::PunchHole(err)) if is_unsupported(&err) => Ok(()),
+            res => res,
+        }
+    }
+
+    /// Zeroes the `len` bytes at `offset`, unmapping them from the backing file if `unmap` is set.
+    ///
+    /// Falls back to writing zeroes when the file system can't zero the range with `fallocate`.
+    pub fn write_zeroes(&mut self, offset: u64, len: u64, unmap: bool) -> Result<(), SyncIoError> {
+        self.dirty = true;
+        match self.fallocate(write_zeroes_mode(unmap), offset, len) {
+            Err(err) if is_unsupported(&err) => write_zeroes_at(&self.file, offset, len),
+            res => res,
+        }
+        .map_err(SyncIoError::WriteZeroes)
+    }
+
     pub fn flush(&mut self) -> Result<(), SyncIoError> {
         // flush() first to force any cached data out of rust buffers.
         self.file.flush().map_err(SyncIoError::Flush)?;
         // Sync data out to physical media on host.
-        self.file.sync_all().map_err(SyncIoError::SyncAll)
+        self.file.sync_all().map_err(SyncIoError::SyncAll)?;
+        self.dirty = false;
+        Ok(())
     }
 }
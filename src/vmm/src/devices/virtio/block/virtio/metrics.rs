@@ -83,7 +83,11 @@ use std::sync::{Arc, RwLock};
 use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
 
-use crate::logger::{IncMetric, LatencyAggregateMetrics, SharedIncMetric};
+use crate::devices::virtio::queue::QueueCounters;
+use crate::logger::{
+    HistogramMetrics, IncMetric, LatencyAggregateMetrics, Log2LatencyHistogramMetrics,
+    SharedIncMetric,
+};
 
 /// map of block drive id and metrics
 /// this should be protected by a lock before accessing.
@@ -156,26 +160,68 @@ pub struct BlockDeviceMetrics {
     pub invalid_reqs_count: SharedIncMetric,
     /// Number of flushes operation triggered on this block device.
     pub flush_count: SharedIncMetric,
+    /// Number of flushes completed without syncing the backing file, because nothing was written
+    /// to it since it was last synced.
+    pub flush_skipped_count: SharedIncMetric,
     /// Number of events triggered on the queue of this block device.
     pub queue_event_count: SharedIncMetric,
+    /// Number of descriptor chains popped from the queue.
+    pub queue_pops: SharedIncMetric,
+    /// Number of times the queue was found empty when popping from it.
+    pub queue_empty_pops: SharedIncMetric,
+    /// Number of entries added to the used ring of the queue.
+    pub queue_used: SharedIncMetric,
+    /// Number of times the guest needed to be notified of the entries used from the queue.
+    pub queue_notified: SharedIncMetric,
+    /// Number of times the notification of the entries used from the queue was suppressed by
+    /// the guest.
+    pub queue_suppressed: SharedIncMetric,
     /// Number of events ratelimiter-related.
     pub rate_limiter_event_count: SharedIncMetric,
     /// Number of update operation triggered on this block device.
     pub update_count: SharedIncMetric,
     /// Number of failures while doing update on this block device.
     pub update_fails: SharedIncMetric,
+    /// Number of updates of this block device changing the capacity of the disk.
+    pub capacity_change_count: SharedIncMetric,
     /// Number of bytes read by this block device.
     pub read_bytes: SharedIncMetric,
     /// Number of bytes written by this block device.
     pub write_bytes: SharedIncMetric,
+    /// Number of written bytes that were unmapped from the backing file because they were zero.
+    pub unmapped_bytes: SharedIncMetric,
     /// Number of successful read operations.
     pub read_count: SharedIncMetric,
     /// Number of successful write operations.
     pub write_count: SharedIncMetric,
+    /// Number of successful discard operations.
+    pub discard_count: SharedIncMetric,
+    /// Number of successful write zeroes operations.
+    pub write_zeroes_count: SharedIncMetric,
+    /// Number of write, flush, discard and write zeroes requests failed because the drive is
+    /// read-only.
+    pub read_only_fails: SharedIncMetric,
+    /// Number of write and write zeroes requests kept pending because the host file system ran
+    /// out of space, with the `pause` policy.
+    pub host_enospc_count: SharedIncMetric,
+    /// Number of pending requests retried through the API.
+    pub host_enospc_retries: SharedIncMetric,
+    /// Number of times the queues weren't processed because requests are kept pending.
+    pub host_enospc_stalled_events: SharedIncMetric,
+    /// Number of read and write operations going through a bounce buffer, because the backing
+    /// file is opened with `O_DIRECT` and their guest memory isn't aligned.
+    pub bounce_buffer_count: SharedIncMetric,
     /// Duration of all read operations.
     pub read_agg: LatencyAggregateMetrics,
     /// Duration of all write operations.
     pub write_agg: LatencyAggregateMetrics,
+    /// Latency of the read requests, from the time they are taken from the queue to their
+    /// completion. Only recorded with the `Detailed` metrics verbosity.
+    pub read_latency: Log2LatencyHistogramMetrics,
+    /// Latency of the write requests, like `read_latency`.
+    pub write_latency: Log2LatencyHistogramMetrics,
+    /// Latency of the flush requests, like `read_latency`.
+    pub flush_latency: Log2LatencyHistogramMetrics,
     /// Number of rate limiter throttling events.
     pub rate_limiter_throttled_events: SharedIncMetric,
     /// Number of virtio events throttled because of the IO engine.
@@ -183,6 +229,12 @@ pub struct BlockDeviceMetrics {
     pub io_engine_throttled_events: SharedIncMetric,
     /// Number of remaining requests in the queue.
     pub remaining_reqs_count: SharedIncMetric,
+    /// Number of requests taken from the queue in each pass.
+    pub requests_per_pass: HistogramMetrics,
+    /// Number of queue passes needed to drain the queue after each notification.
+    pub passes_per_kick: HistogramMetrics,
+    /// Number of io_uring operations submitted by each `io_uring_enter`.
+    pub submissions_per_enter: HistogramMetrics,
 }
 
 impl BlockDeviceMetrics {
@@ -191,10 +243,25 @@ impl BlockDeviceMetrics {
         Self {
             read_agg: LatencyAggregateMetrics::new(),
             write_agg: LatencyAggregateMetrics::new(),
+            read_latency: Log2LatencyHistogramMetrics::new(),
+            write_latency: Log2LatencyHistogramMetrics::new(),
+            flush_latency: Log2LatencyHistogramMetrics::new(),
+            requests_per_pass: HistogramMetrics::new(),
+            passes_per_kick: HistogramMetrics::new(),
+            submissions_per_enter: HistogramMetrics::new(),
             ..Default::default()
         }
     }
 
+    /// Adds the counts of the operations done on the queue.
+    pub fn add_queue_counters(&self, counters: QueueCounters) {
+        self.queue_pops.add(counters.pops);
+        self.queue_empty_pops.add(counters.empty_pops);
+        self.queue_used.add(counters.used);
+        self.queue_notified.add(counters.notified);
+        self.queue_suppressed.add(counters.suppressed);
+    }
+
     /// block metrics are SharedIncMetric where the diff of current vs
     /// old is serialized i.e. serialize_u64(current-old).
     /// So to have the aggregate serialized in same way we need to
@@ -209,32 +276,65 @@ impl BlockDeviceMetrics {
         self.invalid_reqs_count
             .add(other.invalid_reqs_count.fetch_diff());
         self.flush_count.add(other.flush_count.fetch_diff());
+        self.flush_skipped_count
+            .add(other.flush_skipped_count.fetch_diff());
         self.queue_event_count
             .add(other.queue_event_count.fetch_diff());
+        self.queue_pops.add(other.queue_pops.fetch_diff());
+        self.queue_empty_pops
+            .add(other.queue_empty_pops.fetch_diff());
+        self.queue_used.add(other.queue_used.fetch_diff());
+        self.queue_notified.add(other.queue_notified.fetch_diff());
+        self.queue_suppressed
+            .add(other.queue_suppressed.fetch_diff());
         self.rate_limiter_event_count
             .add(other.rate_limiter_event_count.fetch_diff());
         self.update_count.add(other.update_count.fetch_diff());
         self.update_fails.add(other.update_fails.fetch_diff());
+        self.capacity_change_count
+            .add(other.capacity_change_count.fetch_diff());
         self.read_bytes.add(other.read_bytes.fetch_diff());
         self.write_bytes.add(other.write_bytes.fetch_diff());
+        self.unmapped_bytes.add(other.unmapped_bytes.fetch_diff());
         self.read_count.add(other.read_count.fetch_diff());
         self.write_count.add(other.write_count.fetch_diff());
+        self.discard_count.add(other.discard_count.fetch_diff());
+        self.write_zeroes_count
+            .add(other.write_zeroes_count.fetch_diff());
+        self.read_only_fails.add(other.read_only_fails.fetch_diff());
+        self.host_enospc_count
+            .add(other.host_enospc_count.fetch_diff());
+        self.host_enospc_retries
+            .add(other.host_enospc_retries.fetch_diff());
+        self.host_enospc_stalled_events
+            .add(other.host_enospc_stalled_events.fetch_diff());
+        self.bounce_buffer_count
+            .add(other.bounce_buffer_count.fetch_diff());
         self.read_agg.sum_us.add(other.read_agg.sum_us.fetch_diff());
         self.write_agg
             .sum_us
             .add(other.write_agg.sum_us.fetch_diff());
+        self.read_latency.aggregate(&other.read_latency);
+        self.write_latency.aggregate(&other.write_latency);
+        self.flush_latency.aggregate(&other.flush_latency);
         self.rate_limiter_throttled_events
             .add(other.rate_limiter_throttled_events.fetch_diff());
         self.io_engine_throttled_events
             .add(other.io_engine_throttled_events.fetch_diff());
         self.remaining_reqs_count
             .add(other.remaining_reqs_count.fetch_diff());
+        self.requests_per_pass.aggregate(&other.requests_per_pass);
+        self.passes_per_kick.aggregate(&other.passes_per_kick);
+        self.submissions_per_enter
+            .aggregate(&other.submissions_per_enter);
     }
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
+    use crate::vstate::memory::GuestAddress;
 
     #[test]
     fn test_max_block_dev_metrics() {
@@ -323,4 +423,31 @@ pub mod tests {
         assert!(test_metrics.read_bytes.count() >= 5);
         assert!(test_metrics.read_bytes.count() <= 15);
     }
+
+    #[test]
+    fn test_queue_counters_metrics() {
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let mut queue = vq.create_queue();
+        queue.ready = true;
+
+        vq.dtable[0].set(0x1000, 0x100, 0, 0);
+        vq.avail.ring[0].set(0);
+        vq.avail.idx.set(1);
+
+        queue.pop(&mem).unwrap();
+        assert!(queue.pop(&mem).is_none());
+        queue.add_used(&mem, 0, 0x100).unwrap();
+        assert!(queue.prepare_kick(&mem));
+
+        let metrics = BlockDeviceMetrics::new();
+        metrics.add_queue_counters(queue.take_counters());
+
+        let serialized = serde_json::to_value(&metrics).unwrap();
+        assert_eq!(serialized["queue_pops"], 1);
+        assert_eq!(serialized["queue_empty_pops"], 1);
+        assert_eq!(serialized["queue_used"], 1);
+        assert_eq!(serialized["queue_notified"], 1);
+        assert_eq!(serialized["queue_suppressed"], 0);
+    }
 }
@@ -18,19 +18,44 @@ pub use self::request::*;
 pub use crate::devices::virtio::block::CacheType;
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
 
-/// Size of config space for block device.
-pub const BLOCK_CONFIG_SPACE_SIZE: usize = 8;
+/// Size of config space for block device, up to the limits of the write zeroes requests.
+pub const BLOCK_CONFIG_SPACE_SIZE: usize = 60;
 /// Sector shift for block device.
 pub const SECTOR_SHIFT: u8 = 9;
 /// Size of block sector.
 pub const SECTOR_SIZE: u32 = (0x01_u32) << SECTOR_SHIFT;
-/// The number of queues of block device.
-pub const BLOCK_NUM_QUEUES: usize = 1;
-pub const BLOCK_QUEUE_SIZES: [u16; BLOCK_NUM_QUEUES] = [FIRECRACKER_MAX_QUEUE_SIZE];
-// The virtio queue can hold up to 256 descriptors, but 1 request spreads across 2-3 descriptors.
-// So we can use 128 IO_URING entries without ever triggering a FullSq Error.
+/// Maximum number of sectors of a discard or write zeroes request (1 GiB).
+pub const BLOCK_MAX_DISCARD_SECTORS: u32 = (1 << 30) >> SECTOR_SHIFT;
+/// Maximum number of segments of a discard or write zeroes request.
+pub const BLOCK_MAX_DISCARD_SEGMENTS: u32 = 1;
+/// Alignment of the discarded ranges advertised to the guest, in sectors (4 KiB).
+pub const BLOCK_DISCARD_SECTOR_ALIGNMENT: u32 = 4096 >> SECTOR_SHIFT;
+/// Largest logical block size that can be configured for a block device.
+pub const BLOCK_MAX_LOGICAL_BLOCK_SIZE: u32 = 4096;
+/// Largest physical block size that can be configured for a block device.
+pub const BLOCK_MAX_PHYSICAL_BLOCK_SIZE: u32 = 64 << 10;
+/// The default number of queues of block device.
+pub const BLOCK_NUM_QUEUES: u16 = 1;
+/// Largest number of queues that can be configured for a block device.
+pub const BLOCK_MAX_NUM_QUEUES: u16 = 16;
+/// Smallest queue size that can be configured for a block device.
+pub const BLOCK_MIN_QUEUE_SIZE: u16 = 16;
+/// Largest queue size that can be configured for a block device.
+pub const BLOCK_MAX_QUEUE_SIZE: u16 = 1024;
+// The default virtio queue can hold up to 256 descriptors, but 1 request spreads across 2-3
+// descriptors, so 128 IO_URING entries hold all the requests of a default queue. A full submission
+// queue is submitted early while processing larger configured queues. The completion queue holds
+// twice as many entries, and bounds the number of in-flight requests: once it can't take more,
+// the device is throttled until the engine completes some requests.
 /// Maximum number of io uring entries we allow in the queue.
 pub const IO_URING_NUM_ENTRIES: u16 = 128;
+/// Smallest io_uring ring size that can be configured for a block device.
+pub const IO_URING_MIN_RING_SIZE: u16 = 16;
+/// Largest io_uring ring size that can be configured for a block device.
+pub const IO_URING_MAX_RING_SIZE: u16 = 4096;
+/// Default time the kernel thread polling the io_uring submission queue spins before sleeping,
+/// in milliseconds.
+pub const IO_URING_DEFAULT_SQPOLL_IDLE_MS: u32 = 1000;
 
 /// Errors the block device can trigger.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -45,18 +70,36 @@ pub enum VirtioBlockError {
     GetFileMetadata(std::io::Error),
     /// Guest gave us bad memory addresses.
     GuestMemory(GuestMemoryError),
+    /// Guest gave us an invalid descriptor chain: {0}
+    InvalidChain(crate::devices::virtio::queue::ChainError),
     /// The data length is invalid.
     InvalidDataLength,
     /// The requested operation would cause a seek beyond disk end.
     InvalidOffset,
+    /// The sector {0} is not aligned to the logical block size of the disk.
+    UnalignedSector(u64),
     /// Guest gave us a read only descriptor that protocol says to write to.
     UnexpectedReadOnlyDescriptor,
     /// Guest gave us a write only descriptor that protocol says to read from.
     UnexpectedWriteOnlyDescriptor,
     /// Error coming from the IO engine: {0}
     FileEngine(io::BlockIoError),
+    /// Zero detection is not supported with the {0:?} IO engine.
+    DetectZeroesEngine(device::FileEngineType),
+    /// The maximum number of requests processed per queue pass must be greater than 0.
+    InvalidMaxRequestsPerPass,
+    /// The io_uring setup is only supported with the Async IO engine, not {0:?}.
+    IoUringEngine(device::FileEngineType),
+    /// The io_uring ring size {0} is invalid: it must be a power of two between 16 and 4096.
+    InvalidRingSize(u16),
     /// Error manipulating the backing file: {0} {1}
     BackingFile(std::io::Error, String),
+    /// The serial {0:?} is invalid: it must have between 1 and 20 printable ASCII characters,
+    /// without spaces.
+    InvalidSerial(String),
+    /// The new backing file would shrink the disk from {0} to {1} sectors, which can corrupt the
+    /// file systems of the guest.
+    DiskShrink(u64, u64),
     /// Error opening eventfd: {0}
     EventFd(std::io::Error),
     /// Error creating an irqfd: {0}
@@ -65,4 +108,23 @@ pub enum VirtioBlockError {
     RateLimiter(std::io::Error),
     /// Persistence error: {0}
     Persist(crate::devices::virtio::persist::PersistError),
+    /// Cannot pin the virtio features: {0}
+    PinVirtioFeatures(crate::devices::virtio::device::VirtioFeaturesPinError),
+    /// Invalid queue size: {0}
+    QueueSize(crate::devices::virtio::device::QueueSizeError),
+    /// The number of queues {0} is invalid: it must be between 1 and 16.
+    NumQueues(u16),
+    /// Multiple queues can't be offered without pinning the VIRTIO_BLK_F_MQ feature.
+    MultiQueueNotPinned,
+    /// A read-only drive can't be made writable.
+    ReadWriteUpdate,
+    /// Cannot retry the pending requests: {0}
+    RetryPending(crate::devices::DeviceError),
+    /// The logical block size {0} and physical block size {1} are invalid: they must be powers
+    /// of two, with a logical block size between 512 and 4096 bytes, and a physical block size
+    /// between the logical block size and 64 KiB.
+    InvalidBlockSize(u32, u32),
+    /// A logical block size other than 512 bytes can't be configured without pinning the
+    /// VIRTIO_BLK_F_BLK_SIZE feature.
+    BlockSizeNotPinned,
 }
@@ -12,16 +12,19 @@ use utils::eventfd::EventFd;
 use super::device::DiskProperties;
 use super::*;
 use crate::devices::virtio::block::persist::BlockConstructorArgs;
-use crate::devices::virtio::block::virtio::device::FileEngineType;
+use crate::devices::virtio::block::virtio::device::{
+    BlockTopology, DetectZeroes, FileEngineType, IoEngineOpts, IoUringConfig, OnHostEnospc,
+    RateLimiterScope,
+};
 use crate::devices::virtio::block::virtio::metrics::BlockMetricsPerDevice;
-use crate::devices::virtio::device::{DeviceState, IrqTrigger};
-use crate::devices::virtio::gen::virtio_blk::VIRTIO_BLK_F_RO;
-use crate::devices::virtio::persist::VirtioDeviceState;
+use crate::devices::virtio::device::{check_queue_size, DeviceState, IrqTrigger};
+use crate::devices::virtio::persist::{PersistError, VirtioDeviceState};
 use crate::devices::virtio::TYPE_BLOCK;
 use crate::logger::warn;
 use crate::rate_limiter::persist::RateLimiterState;
 use crate::rate_limiter::RateLimiter;
 use crate::snapshot::Persist;
+use crate::vstate::memory::GuestAddress;
 
 /// Holds info about block's file engine type. Gets saved in snapshot.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
@@ -53,17 +56,82 @@ impl From<FileEngineTypeState> for FileEngineType {
     }
 }
 
+/// Holds info about a request kept pending since the host file system ran out of space. Gets
+/// saved in snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingRequestState {
+    request_type: u32,
+    data_len: u32,
+    status_addr: u64,
+    sector: u64,
+    data_addr: u64,
+    queue_index: usize,
+    desc_idx: u16,
+}
+
+impl Persist<'_> for PendingRequest {
+    type State = PendingRequestState;
+    type ConstructorArgs = ();
+    type Error = VirtioBlockError;
+
+    fn save(&self) -> Self::State {
+        PendingRequestState {
+            request_type: self.r#type.into(),
+            data_len: self.data_len,
+            status_addr: self.status_addr.0,
+            sector: self.sector,
+            data_addr: self.data_addr.0,
+            queue_index: self.queue_index,
+            desc_idx: self.desc_idx,
+        }
+    }
+
+    fn restore(_: Self::ConstructorArgs, state: &Self::State) -> Result<Self, Self::Error> {
+        // Only the requests writing to the disk are kept pending.
+        let r#type = RequestType::from(state.request_type);
+        if !matches!(r#type, RequestType::Out | RequestType::WriteZeroes) {
+            return Err(VirtioBlockError::Persist(PersistError::InvalidInput));
+        }
+        Ok(PendingRequest {
+            r#type,
+            data_len: state.data_len,
+            status_addr: GuestAddress(state.status_addr),
+            sector: state.sector,
+            data_addr: GuestAddress(state.data_addr),
+            queue_index: state.queue_index,
+            desc_idx: state.desc_idx,
+            popped_at_us: None,
+        })
+    }
+}
+
 /// Holds info about the block device. Gets saved in snapshot.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VirtioBlockState {
     id: String,
     partuuid: Option<String>,
     cache_type: CacheType,
-    root_device: bool,
-    disk_path: String,
+    pub(crate) root_device: bool,
+    pub(crate) disk_path: String,
+    serial: Option<String>,
+    topology: BlockTopology,
     virtio_state: VirtioDeviceState,
     rate_limiter_state: RateLimiterState,
     file_engine_type: FileEngineTypeState,
+    detect_zeroes: DetectZeroes,
+    open_direct: bool,
+    io_uring: IoUringConfig,
+    // The drive may have been made read-only after the features were negotiated.
+    read_only: bool,
+    io_engine_opts: IoEngineOpts,
+    queue_size: Option<u16>,
+    num_queues: Option<u16>,
+    rate_limiter_scope: RateLimiterScope,
+    // The rate limiters of the queues but the first one, with `RateLimiterScope::Queue`.
+    queue_rate_limiter_states: Vec<RateLimiterState>,
+    on_host_enospc: OnHostEnospc,
+    // The requests kept pending, retried through the API once the microVM is restored.
+    pending_requests: Vec<PendingRequestState>,
 }
 
 impl Persist<'_> for VirtioBlock {
@@ -79,9 +147,29 @@ impl Persist<'_> for VirtioBlock {
             cache_type: self.cache_type,
             root_device: self.root_device,
             disk_path: self.disk.file_path.clone(),
+            serial: self.disk.serial.clone(),
+            topology: self.disk.topology,
             virtio_state: VirtioDeviceState::from_device(self),
-            rate_limiter_state: self.rate_limiter.save(),
+            rate_limiter_state: self.rate_limiters[0].save(),
             file_engine_type: FileEngineTypeState::from(self.file_engine_type()),
+            detect_zeroes: self.disk.detect_zeroes,
+            open_direct: self.disk.open_direct,
+            io_uring: self.disk.io_uring,
+            read_only: self.read_only,
+            io_engine_opts: self.io_engine_opts,
+            queue_size: self.queue_size,
+            num_queues: self.num_queues,
+            rate_limiter_scope: self.rate_limiter_scope,
+            queue_rate_limiter_states: self.rate_limiters[1..]
+                .iter()
+                .map(|limiter| limiter.save())
+                .collect(),
+            on_host_enospc: self.on_host_enospc,
+            pending_requests: self
+                .retained_requests
+                .iter()
+                .map(|pending| pending.save())
+                .collect(),
         }
     }
 
@@ -89,14 +177,37 @@ impl Persist<'_> for VirtioBlock {
         constructor_args: Self::ConstructorArgs,
         state: &Self::State,
     ) -> Result<Self, Self::Error> {
-        let is_read_only = state.virtio_state.avail_features & (1u64 << VIRTIO_BLK_F_RO) != 0;
-        let rate_limiter = RateLimiter::restore((), &state.rate_limiter_state)
+        if state.io_engine_opts.max_requests_per_pass == 0 {
+            return Err(VirtioBlockError::InvalidMaxRequestsPerPass);
+        }
+        let num_queues = state.num_queues.unwrap_or(BLOCK_NUM_QUEUES);
+        if num_queues == 0 || num_queues > BLOCK_MAX_NUM_QUEUES {
+            return Err(VirtioBlockError::NumQueues(num_queues));
+        }
+        let num_rate_limiters = match state.rate_limiter_scope {
+            RateLimiterScope::Device => 1,
+            RateLimiterScope::Queue => usize::from(num_queues),
+        };
+        if state.queue_rate_limiter_states.len() + 1 != num_rate_limiters {
+            return Err(VirtioBlockError::Persist(PersistError::InvalidInput));
+        }
+
+        let is_read_only = state.read_only;
+        let rate_limiters = std::iter::once(&state.rate_limiter_state)
+            .chain(&state.queue_rate_limiter_states)
+            .map(|limiter_state| RateLimiter::restore((), limiter_state))
+            .collect::<Result<Vec<_>, _>>()
             .map_err(VirtioBlockError::RateLimiter)?;
 
         let disk_properties = DiskProperties::new(
             state.disk_path.clone(),
             is_read_only,
             state.file_engine_type.into(),
+            state.detect_zeroes,
+            state.open_direct,
+            state.io_uring,
+            state.serial.clone(),
+            state.topology,
         )
         .or_else(|err| match err {
             VirtioBlockError::FileEngine(io::BlockIoError::UnsupportedEngine(
@@ -108,23 +219,55 @@ impl Persist<'_> for VirtioBlock {
                      Defaulting to \"Sync\" mode.",
                     utils::kernel_version::min_kernel_version_for_io_uring()
                 );
-                DiskProperties::new(state.disk_path.clone(), is_read_only, FileEngineType::Sync)
+                DiskProperties::new(
+                    state.disk_path.clone(),
+                    is_read_only,
+                    FileEngineType::Sync,
+                    state.detect_zeroes,
+                    state.open_direct,
+                    IoUringConfig::default(),
+                    state.serial.clone(),
+                    state.topology,
+                )
             }
             other => Err(other),
         })?;
 
-        let queue_evts = [EventFd::new(libc::EFD_NONBLOCK).map_err(VirtioBlockError::EventFd)?];
+        let queue_evts = (0..num_queues)
+            .map(|_| EventFd::new(libc::EFD_NONBLOCK))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(VirtioBlockError::EventFd)?;
 
+        let queue_max_size = match state.queue_size {
+            Some(size) => check_queue_size(size, BLOCK_MIN_QUEUE_SIZE, BLOCK_MAX_QUEUE_SIZE)
+                .map_err(VirtioBlockError::QueueSize)?,
+            None => FIRECRACKER_MAX_QUEUE_SIZE,
+        };
         let queues = state
             .virtio_state
             .build_queues_checked(
                 &constructor_args.mem,
                 TYPE_BLOCK,
-                BLOCK_NUM_QUEUES,
-                FIRECRACKER_MAX_QUEUE_SIZE,
+                usize::from(num_queues),
+                queue_max_size,
             )
             .map_err(VirtioBlockError::Persist)?;
 
+        let retained_requests = state
+            .pending_requests
+            .iter()
+            .map(|pending_state| {
+                let pending = PendingRequest::restore((), pending_state)?;
+                let queue = queues
+                    .get(pending.queue_index())
+                    .ok_or(VirtioBlockError::Persist(PersistError::InvalidInput))?;
+                if pending.desc_idx() >= queue.actual_size() {
+                    return Err(VirtioBlockError::Persist(PersistError::InvalidInput));
+                }
+                Ok(pending)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
         let mut irq_trigger = IrqTrigger::new().map_err(VirtioBlockError::IrqTrigger)?;
         irq_trigger.irq_status = Arc::new(AtomicU32::new(state.virtio_state.interrupt_status));
 
@@ -140,13 +283,17 @@ impl Persist<'_> for VirtioBlock {
         Ok(VirtioBlock {
             avail_features,
             acked_features,
-            config_space: disk_properties.virtio_block_config_space(),
+            virtio_features_pin: state.virtio_state.virtio_features_pin,
+            config_space: disk_properties.virtio_block_config_space(num_queues),
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(VirtioBlockError::EventFd)?,
 
             queues,
+            queue_size: state.queue_size,
+            num_queues: state.num_queues,
             queue_evts,
             device_state,
             irq_trigger,
+            needs_reset: false,
 
             id: state.id.clone(),
             partuuid: state.partuuid.clone(),
@@ -155,8 +302,15 @@ impl Persist<'_> for VirtioBlock {
             read_only: is_read_only,
 
             disk: disk_properties,
-            rate_limiter,
-            is_io_engine_throttled: false,
+            rate_limiters,
+            rate_limiter_scope: state.rate_limiter_scope,
+            is_io_engine_throttled: vec![false; usize::from(num_queues)],
+            io_engine_opts: state.io_engine_opts,
+            passes_since_kick: vec![0; usize::from(num_queues)],
+            head_batch: Vec::new(),
+            used_batches: vec![Vec::new(); usize::from(num_queues)],
+            on_host_enospc: state.on_host_enospc,
+            retained_requests,
             metrics: BlockMetricsPerDevice::alloc(state.id.clone()),
         })
     }
@@ -170,7 +324,7 @@ mod tests {
 
     use super::*;
     use crate::devices::virtio::block::virtio::device::VirtioBlockConfig;
-    use crate::devices::virtio::device::VirtioDevice;
+    use crate::devices::virtio::device::{QueueSizeError, VirtioDevice};
     use crate::devices::virtio::test_utils::default_mem;
     use crate::snapshot::Snapshot;
 
@@ -188,7 +342,19 @@ mod tests {
             is_read_only: false,
             cache_type: CacheType::Writeback,
             rate_limiter: None,
+            rate_limiter_scope: Default::default(),
             file_engine_type: FileEngineType::default(),
+            detect_zeroes: Default::default(),
+            open_direct: false,
+            io_engine_opts: Default::default(),
+            virtio_features_pin: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: Default::default(),
+            io_uring: Default::default(),
         };
 
         let block = VirtioBlock::new(config).unwrap();
@@ -230,9 +396,21 @@ mod tests {
                 is_read_only: false,
                 cache_type: CacheType::Writeback,
                 rate_limiter: None,
+                rate_limiter_scope: Default::default(),
                 // Need to use Sync because it will otherwise return an error.
                 // We'll overwrite the state instead.
                 file_engine_type: FileEngineType::Sync,
+                detect_zeroes: Default::default(),
+                open_direct: false,
+                io_engine_opts: Default::default(),
+                virtio_features_pin: None,
+                queue_size: None,
+                num_queues: None,
+                serial: None,
+                logical_block_size: None,
+                physical_block_size: None,
+                on_host_enospc: Default::default(),
+                io_uring: Default::default(),
             };
 
             let block = VirtioBlock::new(config).unwrap();
@@ -273,7 +451,21 @@ mod tests {
             is_read_only: false,
             cache_type: CacheType::Unsafe,
             rate_limiter: None,
+            rate_limiter_scope: Default::default(),
             file_engine_type: FileEngineType::default(),
+            detect_zeroes: Default::default(),
+            open_direct: false,
+            io_engine_opts: IoEngineOpts {
+                max_requests_per_pass: 16,
+            },
+            virtio_features_pin: None,
+            queue_size: None,
+            num_queues: None,
+            serial: Some("disk-1".to_string()),
+            logical_block_size: Some(4096),
+            physical_block_size: None,
+            on_host_enospc: Default::default(),
+            io_uring: Default::default(),
         };
 
         let block = VirtioBlock::new(config).unwrap();
@@ -304,5 +496,133 @@ mod tests {
 
         // Test that block specific fields are the same.
         assert_eq!(restored_block.disk.file_path, block.disk.file_path);
+        assert_eq!(restored_block.io_engine_opts, block.io_engine_opts);
+        assert_eq!(restored_block.disk.serial, block.disk.serial);
+        assert_eq!(restored_block.disk.image_id, block.disk.image_id);
+        assert_eq!(restored_block.disk.topology, block.disk.topology);
+        assert_eq!(restored_block.config().logical_block_size, Some(4096));
+    }
+
+    #[test]
+    fn test_persistence_queue_size() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+
+        let config = VirtioBlockConfig {
+            drive_id: "test".to_string(),
+            path_on_host: f.as_path().to_str().unwrap().to_string(),
+            is_root_device: false,
+            partuuid: None,
+            is_read_only: false,
+            cache_type: CacheType::Unsafe,
+            rate_limiter: None,
+            rate_limiter_scope: Default::default(),
+            file_engine_type: FileEngineType::default(),
+            detect_zeroes: Default::default(),
+            open_direct: false,
+            io_engine_opts: Default::default(),
+            virtio_features_pin: None,
+            queue_size: Some(512),
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: Default::default(),
+            io_uring: Default::default(),
+        };
+        let block = VirtioBlock::new(config).unwrap();
+
+        let mut mem = vec![0; 4096];
+        Snapshot::serialize(&mut mem.as_mut_slice(), &block.save()).unwrap();
+        let mut state: VirtioBlockState = Snapshot::deserialize(&mut mem.as_slice()).unwrap();
+
+        let restored_block =
+            VirtioBlock::restore(BlockConstructorArgs { mem: default_mem() }, &state).unwrap();
+        assert_eq!(restored_block.queue_size, Some(512));
+        assert_eq!(restored_block.queues[0].max_size, 512);
+        assert_eq!(restored_block.config().queue_size, Some(512));
+
+        // The queues must have the size the device was configured with.
+        state.queue_size = None;
+        assert!(matches!(
+            VirtioBlock::restore(BlockConstructorArgs { mem: default_mem() }, &state),
+            Err(VirtioBlockError::Persist(PersistError::InvalidInput))
+        ));
+        state.queue_size = Some(500);
+        assert!(matches!(
+            VirtioBlock::restore(BlockConstructorArgs { mem: default_mem() }, &state),
+            Err(VirtioBlockError::QueueSize(QueueSizeError::InvalidSize(
+                500, 16, 1024
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_persistence_num_queues() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+
+        let config = VirtioBlockConfig {
+            drive_id: "test".to_string(),
+            path_on_host: f.as_path().to_str().unwrap().to_string(),
+            is_root_device: false,
+            partuuid: None,
+            is_read_only: false,
+            cache_type: CacheType::Unsafe,
+            rate_limiter: None,
+            rate_limiter_scope: RateLimiterScope::Queue,
+            file_engine_type: FileEngineType::default(),
+            detect_zeroes: Default::default(),
+            open_direct: false,
+            io_engine_opts: Default::default(),
+            virtio_features_pin: None,
+            queue_size: None,
+            num_queues: Some(4),
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: Default::default(),
+            io_uring: Default::default(),
+        };
+        let block = VirtioBlock::new(config).unwrap();
+
+        let mut mem = vec![0; 4096];
+        Snapshot::serialize(&mut mem.as_mut_slice(), &block.save()).unwrap();
+        let mut state: VirtioBlockState = Snapshot::deserialize(&mut mem.as_slice()).unwrap();
+        assert_eq!(state.queue_rate_limiter_states.len(), 3);
+
+        // Every queue is restored, along with its event and its rate limiter.
+        let restored_block =
+            VirtioBlock::restore(BlockConstructorArgs { mem: default_mem() }, &state).unwrap();
+        assert_eq!(restored_block.queues(), block.queues());
+        assert_eq!(restored_block.queue_evts.len(), 4);
+        assert_eq!(restored_block.rate_limiters.len(), 4);
+        assert_eq!(restored_block.avail_features(), block.avail_features());
+        assert_eq!(restored_block.config_space, block.config_space);
+        assert_eq!(restored_block.config().num_queues, Some(4));
+        assert_eq!(
+            restored_block.config().rate_limiter_scope,
+            RateLimiterScope::Queue
+        );
+
+        // The rate limiters must match the number of queues and the scope of the device.
+        state.num_queues = Some(2);
+        assert!(matches!(
+            VirtioBlock::restore(BlockConstructorArgs { mem: default_mem() }, &state),
+            Err(VirtioBlockError::Persist(PersistError::InvalidInput))
+        ));
+        state.num_queues = Some(4);
+        state.rate_limiter_scope = RateLimiterScope::Device;
+        assert!(matches!(
+            VirtioBlock::restore(BlockConstructorArgs { mem: default_mem() }, &state),
+            Err(VirtioBlockError::Persist(PersistError::InvalidInput))
+        ));
+        // So must the queues.
+        state.num_queues = None;
+        state.queue_rate_limiter_states.clear();
+        assert!(matches!(
+            VirtioBlock::restore(BlockConstructorArgs { mem: default_mem() }, &state),
+            Err(VirtioBlockError::Persist(PersistError::InvalidInput))
+        ));
     }
 }
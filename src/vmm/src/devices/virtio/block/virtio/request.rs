@@ -7,16 +7,21 @@
 
 use std::convert::From;
 
+use utils::time::{get_time_us, ClockType};
 use vm_memory::GuestMemoryError;
 
-use super::{io as block_io, VirtioBlockError, SECTOR_SHIFT, SECTOR_SIZE};
+use super::{io as block_io, VirtioBlockError, BLOCK_MAX_DISCARD_SECTORS, SECTOR_SHIFT};
 use crate::devices::virtio::block::virtio::device::DiskProperties;
 use crate::devices::virtio::block::virtio::metrics::BlockDeviceMetrics;
 pub use crate::devices::virtio::gen::virtio_blk::{
     VIRTIO_BLK_ID_BYTES, VIRTIO_BLK_S_IOERR, VIRTIO_BLK_S_OK, VIRTIO_BLK_S_UNSUPP,
-    VIRTIO_BLK_T_FLUSH, VIRTIO_BLK_T_GET_ID, VIRTIO_BLK_T_IN, VIRTIO_BLK_T_OUT,
+    VIRTIO_BLK_T_DISCARD, VIRTIO_BLK_T_FLUSH, VIRTIO_BLK_T_GET_ID, VIRTIO_BLK_T_IN,
+    VIRTIO_BLK_T_OUT, VIRTIO_BLK_T_WRITE_ZEROES, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP,
 };
-use crate::devices::virtio::queue::DescriptorChain;
+use crate::devices::virtio::queue::{ChainError, ChainLayout, DescriptorChain};
+use crate::devices::virtio::TYPE_BLOCK;
+use crate::devices::ErrorSeverity;
+use crate::events::{DeviceErrorKind, VmmEvent, EVENTS};
 use crate::logger::{error, IncMetric};
 use crate::rate_limiter::{RateLimiter, TokenType};
 use crate::vstate::memory::{ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
@@ -24,8 +29,19 @@ use crate::vstate::memory::{ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
 #[derive(Debug, derive_more::From)]
 pub enum IoErr {
     GetId(GuestMemoryError),
-    PartialTransfer { completed: u32, expected: u32 },
+    PartialTransfer {
+        completed: u32,
+        expected: u32,
+    },
     FileEngine(block_io::BlockIoError),
+    #[from(ignore)]
+    ReadSegment(GuestMemoryError),
+    SegmentOutOfRange {
+        sector: u64,
+        num_sectors: u32,
+    },
+    #[from(ignore)]
+    UnsupportedFlags(u32),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -34,6 +50,8 @@ pub enum RequestType {
     Out,
     Flush,
     GetDeviceID,
+    Discard,
+    WriteZeroes,
     Unsupported(u32),
 }
 
@@ -44,16 +62,35 @@ impl From<u32> for RequestType {
             VIRTIO_BLK_T_OUT => RequestType::Out,
             VIRTIO_BLK_T_FLUSH => RequestType::Flush,
             VIRTIO_BLK_T_GET_ID => RequestType::GetDeviceID,
+            VIRTIO_BLK_T_DISCARD => RequestType::Discard,
+            VIRTIO_BLK_T_WRITE_ZEROES => RequestType::WriteZeroes,
             t => RequestType::Unsupported(t),
         }
     }
 }
 
+impl From<RequestType> for u32 {
+    fn from(value: RequestType) -> Self {
+        match value {
+            RequestType::In => VIRTIO_BLK_T_IN,
+            RequestType::Out => VIRTIO_BLK_T_OUT,
+            RequestType::Flush => VIRTIO_BLK_T_FLUSH,
+            RequestType::GetDeviceID => VIRTIO_BLK_T_GET_ID,
+            RequestType::Discard => VIRTIO_BLK_T_DISCARD,
+            RequestType::WriteZeroes => VIRTIO_BLK_T_WRITE_ZEROES,
+            RequestType::Unsupported(t) => t,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ProcessingResult {
     Submitted,
     Throttled,
     Executed(FinishedRequest),
+    // The request ran out of space on the host, and can be retried once space is freed up. It
+    // is completed with its result otherwise.
+    OutOfSpace(PendingRequest, Result<u32, IoErr>),
 }
 
 #[derive(Debug)]
@@ -67,6 +104,8 @@ enum Status {
     Ok { num_bytes_to_mem: u32 },
     IoErr { num_bytes_to_mem: u32, err: IoErr },
     Unsupported { op: u32 },
+    UnsupportedFlags { flags: u32 },
+    ReadOnly,
 }
 
 impl Status {
@@ -89,15 +128,81 @@ impl Status {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PendingRequest {
-    r#type: RequestType,
-    data_len: u32,
-    status_addr: GuestAddress,
-    desc_idx: u16,
+    pub(super) r#type: RequestType,
+    pub(super) data_len: u32,
+    pub(super) status_addr: GuestAddress,
+    // Where the request is executed, to retry it.
+    pub(super) sector: u64,
+    pub(super) data_addr: GuestAddress,
+    pub(super) queue_index: usize,
+    pub(super) desc_idx: u16,
+    // When the request was taken from the queue, if its latency is recorded.
+    pub(super) popped_at_us: Option<u64>,
 }
 
 impl PendingRequest {
+    /// Index of the queue the request was taken from.
+    pub fn queue_index(&self) -> usize {
+        self.queue_index
+    }
+
+    /// Index of the descriptor chain of the request.
+    pub fn desc_idx(&self) -> u16 {
+        self.desc_idx
+    }
+
+    fn request(&self) -> Request {
+        Request {
+            r#type: self.r#type,
+            data_len: self.data_len,
+            status_addr: self.status_addr,
+            sector: self.sector,
+            data_addr: self.data_addr,
+        }
+    }
+
+    /// Whether the request failed with `res` because the host file system ran out of space, and
+    /// can be retried once space is freed up. Only the writes and the write zeroes requests are,
+    /// since a failed `fsync` may drop the data it didn't sync.
+    ///
+    /// The writes stopping short with the `Async` engine count as well: the kernel stops a write
+    /// short when it runs out of space part way, and retrying the whole write is harmless.
+    pub fn is_out_of_space(&self, res: &Result<u32, IoErr>) -> bool {
+        match (self.r#type, res) {
+            (RequestType::Out, Ok(count)) => *count < self.data_len,
+            (RequestType::Out | RequestType::WriteZeroes, Err(IoErr::FileEngine(err))) => {
+                err.is_out_of_space()
+            }
+            _ => false,
+        }
+    }
+
+    /// Executes the request again, or submits it to the IO engine, e.g. once space was freed up
+    /// on the host.
+    pub(crate) fn retry(
+        self,
+        disk: &mut DiskProperties,
+        mem: &GuestMemoryMmap,
+        block_metrics: &BlockDeviceMetrics,
+    ) -> ProcessingResult {
+        self.request().process(
+            disk,
+            self.queue_index,
+            self.desc_idx,
+            self.popped_at_us,
+            mem,
+            block_metrics,
+        )
+    }
+
+    /// Completes the request without executing it, with the `VIRTIO_BLK_S_IOERR` status.
+    /// Returns the number of bytes written to the guest memory, 0 if the status can't be written.
+    pub(crate) fn abort(&self, mem: &GuestMemoryMmap) -> u32 {
+        self.request().abort(mem)
+    }
+
     fn write_status_and_finish(
         self,
         status: &Status,
@@ -117,6 +222,12 @@ impl PendingRequest {
                     "Failed to execute {:?} virtio block request: {:?}",
                     self.r#type, err
                 );
+                EVENTS.publish(VmmEvent::DeviceError {
+                    device_type: TYPE_BLOCK,
+                    kind: DeviceErrorKind::Io,
+                    severity: ErrorSeverity::Degraded,
+                    message: format!("{:?} request failed: {:?}", self.r#type, err),
+                });
                 (*num_bytes_to_mem, u8::try_from(VIRTIO_BLK_S_IOERR).unwrap())
             }
             Status::Unsupported { op } => {
@@ -124,6 +235,18 @@ impl PendingRequest {
                 error!("Received unsupported virtio block request: {}", op);
                 (0, u8::try_from(VIRTIO_BLK_S_UNSUPP).unwrap())
             }
+            Status::UnsupportedFlags { flags } => {
+                block_metrics.invalid_reqs_count.inc();
+                error!(
+                    "Received {:?} virtio block request with unsupported flags: {:#x}",
+                    self.r#type, flags
+                );
+                (0, u8::try_from(VIRTIO_BLK_S_UNSUPP).unwrap())
+            }
+            Status::ReadOnly => {
+                block_metrics.read_only_fails.inc();
+                (0, u8::try_from(VIRTIO_BLK_S_IOERR).unwrap())
+            }
         };
 
         let num_bytes_to_mem = mem
@@ -150,6 +273,15 @@ impl PendingRequest {
         res: Result<u32, IoErr>,
         block_metrics: &BlockDeviceMetrics,
     ) -> FinishedRequest {
+        if let Some(popped_at_us) = self.popped_at_us {
+            let latency_us = get_time_us(ClockType::Monotonic).saturating_sub(popped_at_us);
+            match self.r#type {
+                RequestType::In => block_metrics.read_latency.record(latency_us),
+                RequestType::Out => block_metrics.write_latency.record(latency_us),
+                RequestType::Flush => block_metrics.flush_latency.record(latency_us),
+                _ => (),
+            }
+        }
         let status = match (res, self.r#type) {
             (Ok(transferred_data_len), RequestType::In) => {
                 let status = Status::from_data(self.data_len, transferred_data_len, true);
@@ -176,7 +308,20 @@ impl PendingRequest {
             (Ok(transferred_data_len), RequestType::GetDeviceID) => {
                 Status::from_data(self.data_len, transferred_data_len, true)
             }
+            (Ok(_), RequestType::Discard) => {
+                block_metrics.discard_count.inc();
+                Status::Ok {
+                    num_bytes_to_mem: 0,
+                }
+            }
+            (Ok(_), RequestType::WriteZeroes) => {
+                block_metrics.write_zeroes_count.inc();
+                Status::Ok {
+                    num_bytes_to_mem: 0,
+                }
+            }
             (_, RequestType::Unsupported(op)) => Status::Unsupported { op },
+            (Err(IoErr::UnsupportedFlags(flags)), _) => Status::UnsupportedFlags { flags },
             (Err(err), _) => Status::IoErr {
                 num_bytes_to_mem: 0,
                 err,
@@ -231,7 +376,47 @@ impl RequestHeader {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// The segment of a discard or write zeroes request, read from the data descriptor.
+///
+/// The segment contains the following fields:
+///   * sector: an u64 value representing the first sector of the range.
+///   * num_sectors: an u32 value representing the number of sectors of the range.
+///   * flags: an u32 value, in which only `VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP` is defined, for
+///     write zeroes requests.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct DiscardWriteZeroesSegment {
+    sector: u64,
+    num_sectors: u32,
+    flags: u32,
+}
+
+// SAFETY: Safe because DiscardWriteZeroesSegment only contains plain data.
+unsafe impl ByteValued for DiscardWriteZeroesSegment {}
+
+impl DiscardWriteZeroesSegment {
+    pub fn new(sector: u64, num_sectors: u32, flags: u32) -> DiscardWriteZeroesSegment {
+        DiscardWriteZeroesSegment {
+            sector,
+            num_sectors,
+            flags,
+        }
+    }
+
+    fn offset(&self) -> u64 {
+        self.sector << SECTOR_SHIFT
+    }
+
+    fn num_bytes(&self) -> u64 {
+        u64::from(self.num_sectors) << SECTOR_SHIFT
+    }
+
+    fn unmap(&self) -> bool {
+        self.flags & VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Request {
     pub r#type: RequestType,
     pub data_len: u32,
@@ -245,12 +430,19 @@ impl Request {
         avail_desc: &DescriptorChain,
         mem: &GuestMemoryMmap,
         num_disk_sectors: u64,
+        logical_block_size: u32,
     ) -> Result<Request, VirtioBlockError> {
         // The head contains the request type which MUST be readable.
         if avail_desc.is_write_only() {
             return Err(VirtioBlockError::UnexpectedWriteOnlyDescriptor);
         }
 
+        let layout = ChainLayout::scan(avail_desc).map_err(|err| match err {
+            // Past the head, only the data may be readable, not the status after it.
+            ChainError::ReadableAfterWritable(_) => VirtioBlockError::UnexpectedReadOnlyDescriptor,
+            err => VirtioBlockError::InvalidChain(err),
+        })?;
+
         let request_header = RequestHeader::read_from(mem, avail_desc.addr)?;
         let mut req = Request {
             r#type: RequestType::from(request_header.request_type),
@@ -260,46 +452,48 @@ impl Request {
             status_addr: GuestAddress(0),
         };
 
-        let data_desc;
-        let status_desc;
         let desc = avail_desc
             .next_descriptor()
             .ok_or(VirtioBlockError::DescriptorChainTooShort)?;
 
-        if !desc.has_next() {
-            status_desc = desc;
-            // Only flush requests are allowed to skip the data descriptor.
-            if req.r#type != RequestType::Flush {
-                return Err(VirtioBlockError::DescriptorChainTooShort);
-            }
-        } else {
-            data_desc = desc;
-            status_desc = data_desc
-                .next_descriptor()
-                .ok_or(VirtioBlockError::DescriptorChainTooShort)?;
-
-            if data_desc.is_write_only() && req.r#type == RequestType::Out {
-                return Err(VirtioBlockError::UnexpectedWriteOnlyDescriptor);
-            }
-            if !data_desc.is_write_only() && req.r#type == RequestType::In {
-                return Err(VirtioBlockError::UnexpectedReadOnlyDescriptor);
-            }
-            if !data_desc.is_write_only() && req.r#type == RequestType::GetDeviceID {
-                return Err(VirtioBlockError::UnexpectedReadOnlyDescriptor);
+        let status_desc = if desc.has_next() {
+            // The data is readable when it comes before the first writable descriptor.
+            let data_readable = layout.readable_count > 1;
+            match req.r#type {
+                RequestType::Out | RequestType::Discard | RequestType::WriteZeroes
+                    if !data_readable =>
+                {
+                    return Err(VirtioBlockError::UnexpectedWriteOnlyDescriptor);
+                }
+                RequestType::In | RequestType::GetDeviceID if data_readable => {
+                    return Err(VirtioBlockError::UnexpectedReadOnlyDescriptor);
+                }
+                _ => {}
             }
 
-            req.data_addr = data_desc.addr;
-            req.data_len = data_desc.len;
-        }
+            req.data_addr = desc.addr;
+            req.data_len = desc.len;
+            desc.next_descriptor()
+                .ok_or(VirtioBlockError::DescriptorChainTooShort)?
+        } else if req.r#type == RequestType::Flush {
+            // Only flush requests are allowed to skip the data descriptor.
+            desc
+        } else {
+            return Err(VirtioBlockError::DescriptorChainTooShort);
+        };
 
         // check request validity
         match req.r#type {
             RequestType::In | RequestType::Out => {
-                // Check that the data length is a multiple of 512 as specified in the virtio
-                // standard.
-                if req.data_len % SECTOR_SIZE != 0 {
+                // Check that the data length is a multiple of the logical block size, which is
+                // at least 512 as specified in the virtio standard, and that the request starts
+                // on a logical block.
+                if req.data_len % logical_block_size != 0 {
                     return Err(VirtioBlockError::InvalidDataLength);
                 }
+                if req.sector % u64::from(logical_block_size >> SECTOR_SHIFT) != 0 {
+                    return Err(VirtioBlockError::UnalignedSector(req.sector));
+                }
                 let top_sector = req
                     .sector
                     .checked_add(u64::from(req.data_len) >> SECTOR_SHIFT)
@@ -313,6 +507,14 @@ impl Request {
                     return Err(VirtioBlockError::InvalidDataLength);
                 }
             }
+            RequestType::Discard | RequestType::WriteZeroes => {
+                // The device takes a single segment per request, as advertised in the config
+                // space. The segment itself is checked when processing the request, so that the
+                // guest gets the status of the request.
+                if req.data_len as usize != std::mem::size_of::<DiscardWriteZeroesSegment>() {
+                    return Err(VirtioBlockError::InvalidDataLength);
+                }
+            }
             _ => {}
         }
 
@@ -327,6 +529,15 @@ impl Request {
 
         req.status_addr = status_desc.addr;
 
+        #[cfg(feature = "memory-guards")]
+        {
+            use crate::devices::virtio::canary::record_write;
+            if req.r#type == RequestType::In || req.r#type == RequestType::GetDeviceID {
+                record_write(mem, req.data_addr, req.data_len);
+            }
+            record_write(mem, req.status_addr, status_desc.len);
+        }
+
         Ok(req)
     }
 
@@ -354,23 +565,116 @@ impl Request {
         self.sector << SECTOR_SHIFT
     }
 
-    fn to_pending_request(&self, desc_idx: u16) -> PendingRequest {
+    // Reads the segment of a discard or write zeroes request, and checks it against the disk and
+    // the limits advertised in the config space.
+    fn read_segment(
+        &self,
+        mem: &GuestMemoryMmap,
+        num_disk_sectors: u64,
+    ) -> Result<DiscardWriteZeroesSegment, IoErr> {
+        let segment: DiscardWriteZeroesSegment =
+            mem.read_obj(self.data_addr).map_err(IoErr::ReadSegment)?;
+
+        // Ranges can only be unmapped explicitly when zeroing them.
+        let known_flags = match self.r#type {
+            RequestType::WriteZeroes => VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP,
+            _ => 0,
+        };
+        if segment.flags & !known_flags != 0 {
+            return Err(IoErr::UnsupportedFlags(segment.flags));
+        }
+
+        let in_range = segment
+            .sector
+            .checked_add(u64::from(segment.num_sectors))
+            .is_some_and(|top_sector| top_sector <= num_disk_sectors);
+        if !in_range || segment.num_sectors > BLOCK_MAX_DISCARD_SECTORS {
+            return Err(IoErr::SegmentOutOfRange {
+                sector: segment.sector,
+                num_sectors: segment.num_sectors,
+            });
+        }
+
+        Ok(segment)
+    }
+
+    fn to_pending_request(
+        &self,
+        queue_index: usize,
+        desc_idx: u16,
+        popped_at_us: Option<u64>,
+    ) -> PendingRequest {
         PendingRequest {
             r#type: self.r#type,
             data_len: self.data_len,
             status_addr: self.status_addr,
+            sector: self.sector,
+            data_addr: self.data_addr,
+            queue_index,
             desc_idx,
+            popped_at_us,
+        }
+    }
+
+    /// Completes the request without executing it, with the `VIRTIO_BLK_S_IOERR` status.
+    /// Returns the number of bytes written to the guest memory, 0 if the status can't be written.
+    pub(crate) fn abort(&self, mem: &GuestMemoryMmap) -> u32 {
+        match mem.write_obj(u8::try_from(VIRTIO_BLK_S_IOERR).unwrap(), self.status_addr) {
+            Ok(()) => 1,
+            Err(err) => {
+                error!("Failed to write virtio block status: {:?}", err);
+                0
+            }
+        }
+    }
+
+    // Completes a request executed right away with `res`, unless it ran out of space on the host.
+    fn executed(
+        pending: PendingRequest,
+        res: Result<u32, IoErr>,
+        mem: &GuestMemoryMmap,
+        block_metrics: &BlockDeviceMetrics,
+    ) -> ProcessingResult {
+        if pending.is_out_of_space(&res) {
+            return ProcessingResult::OutOfSpace(pending, res);
         }
+        ProcessingResult::Executed(pending.finish(mem, res, block_metrics))
     }
 
+    /// Executes the request, or submits it to the IO engine. `popped_at_us` is when it was taken
+    /// from the queue, to record its latency once completed.
     pub(crate) fn process(
         self,
         disk: &mut DiskProperties,
+        queue_index: usize,
         desc_idx: u16,
+        popped_at_us: Option<u64>,
         mem: &GuestMemoryMmap,
         block_metrics: &BlockDeviceMetrics,
     ) -> ProcessingResult {
-        let pending = self.to_pending_request(desc_idx);
+        let pending = self.to_pending_request(queue_index, desc_idx, popped_at_us);
+        if disk.read_only
+            && matches!(
+                self.r#type,
+                RequestType::Out
+                    | RequestType::Flush
+                    | RequestType::Discard
+                    | RequestType::WriteZeroes
+            )
+        {
+            return ProcessingResult::Executed(pending.write_status_and_finish(
+                &Status::ReadOnly,
+                mem,
+                block_metrics,
+            ));
+        }
+        if matches!(self.r#type, RequestType::In | RequestType::Out)
+            && disk
+                .file_engine
+                .needs_bounce_buffer(mem, self.data_addr, self.data_len)
+        {
+            block_metrics.bounce_buffer_count.inc();
+        }
         let res = match self.r#type {
             RequestType::In => {
                 let _metric = block_metrics.read_agg.record_latency_metrics();
@@ -379,10 +683,50 @@ impl Request {
             }
             RequestType::Out => {
                 let _metric = block_metrics.write_agg.record_latency_metrics();
+                if disk.detect_zeroes.should_scan(self.data_len) {
+                    if let block_io::FileEngine::Sync(engine) = &mut disk.file_engine {
+                        let res = engine
+                            .write_detect_zeroes(self.offset(), mem, self.data_addr, self.data_len)
+                            .map(|(count, unmapped)| {
+                                block_metrics.unmapped_bytes.add(unmapped);
+                                count
+                            })
+                            .map_err(|err| IoErr::FileEngine(block_io::BlockIoError::Sync(err)));
+                        return Self::executed(pending, res, mem, block_metrics);
+                    }
+                }
                 disk.file_engine
                     .write(self.offset(), mem, self.data_addr, self.data_len, pending)
             }
-            RequestType::Flush => disk.file_engine.flush(pending),
+            RequestType::Flush => {
+                // Nothing to sync if no data was written since the last flush.
+                if !disk.file_engine.is_dirty() {
+                    block_metrics.flush_skipped_count.inc();
+                    return ProcessingResult::Executed(pending.finish(mem, Ok(0), block_metrics));
+                }
+                disk.file_engine.flush(pending)
+            }
+            RequestType::Discard | RequestType::WriteZeroes => {
+                let segment = match self.read_segment(mem, disk.nsectors) {
+                    Ok(segment) if segment.num_sectors > 0 => segment,
+                    res => {
+                        let res = res.map(|_| 0);
+                        return ProcessingResult::Executed(pending.finish(mem, res, block_metrics));
+                    }
+                };
+                match self.r#type {
+                    RequestType::Discard => {
+                        disk.file_engine
+                            .discard(segment.offset(), segment.num_bytes(), pending)
+                    }
+                    _ => disk.file_engine.write_zeroes(
+                        segment.offset(),
+                        segment.num_bytes(),
+                        segment.unmap(),
+                        pending,
+                    ),
+                }
+            }
             RequestType::GetDeviceID => {
                 let res = mem
                     .write_slice(&disk.image_id, self.data_addr)
@@ -398,17 +742,18 @@ impl Request {
         match res {
             Ok(block_io::FileEngineOk::Submitted) => ProcessingResult::Submitted,
             Ok(block_io::FileEngineOk::Executed(res)) => {
-                ProcessingResult::Executed(res.user_data.finish(mem, Ok(res.count), block_metrics))
+                Self::executed(res.user_data, Ok(res.count), mem, block_metrics)
             }
             Err(err) => {
                 if err.error.is_throttling_err() {
                     ProcessingResult::Throttled
                 } else {
-                    ProcessingResult::Executed(err.user_data.finish(
-                        mem,
+                    Self::executed(
+                        err.user_data,
                         Err(IoErr::FileEngine(err.error)),
+                        mem,
                         block_metrics,
-                    ))
+                    )
                 }
             }
         }
@@ -420,6 +765,7 @@ mod tests {
     #![allow(clippy::undocumented_unsafe_blocks)]
 
     use super::*;
+    use crate::devices::virtio::block::virtio::SECTOR_SIZE;
     use crate::devices::virtio::queue::{Queue, VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
     use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
     use crate::vstate::memory::{Address, GuestAddress, GuestMemory};
@@ -438,6 +784,8 @@ mod tests {
             VIRTIO_BLK_T_OUT,
             VIRTIO_BLK_T_FLUSH,
             VIRTIO_BLK_T_GET_ID,
+            VIRTIO_BLK_T_DISCARD,
+            VIRTIO_BLK_T_WRITE_ZEROES,
         ];
 
         for request_type in supported_request_types {
@@ -464,6 +812,14 @@ mod tests {
             RequestType::from(VIRTIO_BLK_T_GET_ID),
             RequestType::GetDeviceID
         );
+        assert_eq!(
+            RequestType::from(VIRTIO_BLK_T_DISCARD),
+            RequestType::Discard
+        );
+        assert_eq!(
+            RequestType::from(VIRTIO_BLK_T_WRITE_ZEROES),
+            RequestType::WriteZeroes
+        );
         assert_eq!(RequestType::from(42), RequestType::Unsupported(42));
     }
 
@@ -473,7 +829,12 @@ mod tests {
             let memory = self.driver_queue.memory();
 
             assert!(matches!(
-                Request::parse(&q.pop(memory).unwrap(), memory, NUM_DISK_SECTORS),
+                Request::parse(
+                    &q.pop(memory).unwrap(),
+                    memory,
+                    NUM_DISK_SECTORS,
+                    SECTOR_SIZE
+                ),
                 Err(_e)
             ));
         }
@@ -481,8 +842,13 @@ mod tests {
         fn check_parse(&self, check_data: bool) {
             let mut q = self.driver_queue.create_queue();
             let memory = self.driver_queue.memory();
-            let request =
-                Request::parse(&q.pop(memory).unwrap(), memory, NUM_DISK_SECTORS).unwrap();
+            let request = Request::parse(
+                &q.pop(memory).unwrap(),
+                memory,
+                NUM_DISK_SECTORS,
+                SECTOR_SIZE,
+            )
+            .unwrap();
             let expected_header = self.header();
 
             assert_eq!(
@@ -618,6 +984,37 @@ mod tests {
         chain.check_parse(true);
     }
 
+    #[test]
+    fn test_parse_logical_block_size() {
+        let mem = &default_mem();
+        let queue = VirtQueue::new(GuestAddress(0), mem, 16);
+        let chain = RequestDescriptorChain::new(&queue);
+        chain
+            .data_desc
+            .flags
+            .set(VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE);
+        let parse = |sector, data_len| {
+            chain.set_header(RequestHeader::new(VIRTIO_BLK_T_IN, sector));
+            chain.data_desc.len.set(data_len);
+            let mut q = queue.create_queue();
+            Request::parse(&q.pop(mem).unwrap(), mem, NUM_DISK_SECTORS, 4096)
+        };
+
+        // Requests must start on a 4K logical block.
+        assert!(matches!(
+            parse(1, 4096),
+            Err(VirtioBlockError::UnalignedSector(1))
+        ));
+        // And span whole logical blocks.
+        assert!(matches!(
+            parse(8, 512),
+            Err(VirtioBlockError::InvalidDataLength)
+        ));
+        let request = parse(8, 8192).unwrap();
+        assert_eq!(request.sector, 8);
+        assert_eq!(request.data_len, 8192);
+    }
+
     #[test]
     fn test_parse_flush() {
         let mem = &default_mem();
@@ -659,6 +1056,152 @@ mod tests {
         chain.check_parse(true);
     }
 
+    #[test]
+    fn test_parse_discard_write_zeroes() {
+        let segment_len = u32::try_from(std::mem::size_of::<DiscardWriteZeroesSegment>()).unwrap();
+
+        for request_type in [VIRTIO_BLK_T_DISCARD, VIRTIO_BLK_T_WRITE_ZEROES] {
+            let mem = &default_mem();
+            let queue = VirtQueue::new(GuestAddress(0), mem, 16);
+            let chain = RequestDescriptorChain::new(&queue);
+            chain.set_header(RequestHeader::new(request_type, 0));
+
+            // Write only data descriptor.
+            chain
+                .data_desc
+                .flags
+                .set(VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE);
+            chain.check_parse_err(VirtioBlockError::UnexpectedWriteOnlyDescriptor);
+
+            // The data holds more than a single segment.
+            chain.data_desc.flags.set(VIRTQ_DESC_F_NEXT);
+            chain.data_desc.len.set(2 * segment_len);
+            chain.check_parse_err(VirtioBlockError::InvalidDataLength);
+
+            // The data is shorter than a segment.
+            chain.data_desc.len.set(segment_len - 1);
+            chain.check_parse_err(VirtioBlockError::InvalidDataLength);
+
+            chain.data_desc.len.set(segment_len);
+            chain.check_parse(true);
+        }
+    }
+
+    #[test]
+    fn test_read_segment() {
+        let mem = &default_mem();
+        let data_addr = GuestAddress(0x1000);
+        let request = |r#type: RequestType| Request {
+            r#type,
+            data_len: 16,
+            status_addr: GuestAddress(0x2000),
+            sector: 0,
+            data_addr,
+        };
+        let read = |r#type: RequestType, segment: DiscardWriteZeroesSegment| {
+            mem.write_obj(segment, data_addr).unwrap();
+            request(r#type).read_segment(mem, NUM_DISK_SECTORS)
+        };
+
+        // Segments within the disk.
+        let segment = read(
+            RequestType::Discard,
+            DiscardWriteZeroesSegment::new(8, 16, 0),
+        )
+        .unwrap();
+        assert_eq!(segment.offset(), 8 * u64::from(SECTOR_SIZE));
+        assert_eq!(segment.num_bytes(), 16 * u64::from(SECTOR_SIZE));
+        assert!(!segment.unmap());
+        let segment = read(
+            RequestType::WriteZeroes,
+            DiscardWriteZeroesSegment::new(0, 1024, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP),
+        )
+        .unwrap();
+        assert!(segment.unmap());
+
+        // Discards can't ask for unmapping, and no other flag is defined.
+        assert!(matches!(
+            read(
+                RequestType::Discard,
+                DiscardWriteZeroesSegment::new(0, 1, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP)
+            ),
+            Err(IoErr::UnsupportedFlags(VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP))
+        ));
+        assert!(matches!(
+            read(
+                RequestType::WriteZeroes,
+                DiscardWriteZeroesSegment::new(0, 1, 0x2)
+            ),
+            Err(IoErr::UnsupportedFlags(0x2))
+        ));
+
+        // Segments past the end of the disk.
+        assert!(matches!(
+            read(
+                RequestType::Discard,
+                DiscardWriteZeroesSegment::new(NUM_DISK_SECTORS - 1, 2, 0)
+            ),
+            Err(IoErr::SegmentOutOfRange { .. })
+        ));
+        assert!(matches!(
+            read(
+                RequestType::WriteZeroes,
+                DiscardWriteZeroesSegment::new(u64::MAX, 1, 0)
+            ),
+            Err(IoErr::SegmentOutOfRange { .. })
+        ));
+
+        // Segments larger than the advertised limit, on a disk large enough to hold them.
+        mem.write_obj(
+            DiscardWriteZeroesSegment::new(0, BLOCK_MAX_DISCARD_SECTORS + 1, 0),
+            data_addr,
+        )
+        .unwrap();
+        assert!(matches!(
+            request(RequestType::Discard).read_segment(mem, u64::MAX),
+            Err(IoErr::SegmentOutOfRange { .. })
+        ));
+
+        // The segment can't be read from guest memory.
+        let mut request = request(RequestType::Discard);
+        request.data_addr = mem.last_addr();
+        assert!(matches!(
+            request.read_segment(mem, NUM_DISK_SECTORS),
+            Err(IoErr::ReadSegment(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_chain_layout() {
+        let mem = &default_mem();
+        let queue = VirtQueue::new(GuestAddress(0), mem, 16);
+        let chain = RequestDescriptorChain::new(&queue);
+        chain.set_header(RequestHeader::new(VIRTIO_BLK_T_IN, 0));
+        let parse = || {
+            let mut q = queue.create_queue();
+            Request::parse(&q.pop(mem).unwrap(), mem, NUM_DISK_SECTORS, SECTOR_SIZE)
+        };
+        parse().unwrap();
+
+        // Read only status descriptor after the write only data descriptor.
+        chain.status_desc.flags.set(0);
+        assert!(matches!(
+            parse(),
+            Err(VirtioBlockError::UnexpectedReadOnlyDescriptor)
+        ));
+
+        // Status descriptor linking back to the header.
+        chain
+            .status_desc
+            .flags
+            .set(VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE);
+        chain.status_desc.next.set(0);
+        assert!(matches!(
+            parse(),
+            Err(VirtioBlockError::InvalidChain(ChainError::Cycle(0)))
+        ));
+    }
+
     use std::convert::TryInto;
 
     /// -------------------------------------
@@ -704,9 +1247,9 @@ mod tests {
                     1u32,
                     std::sync::Arc::new(Strategy::prop_map(any::<u32>(), |id| {
                         // Random unsupported requests for our implementation start at
-                        // VIRTIO_BLK_T_GET_ID + 1 = 9.
-                        // This can be further refined to include unsupported requests ids < 9.
-                        RequestType::Unsupported(id.checked_add(9).unwrap_or(9))
+                        // VIRTIO_BLK_T_WRITE_ZEROES + 1 = 14.
+                        // This can be further refined to include unsupported requests ids < 14.
+                        RequestType::Unsupported(id.checked_add(14).unwrap_or(14))
                     })),
                 ),
             ))
@@ -720,6 +1263,8 @@ mod tests {
                 RequestType::Out => VIRTIO_BLK_T_OUT,
                 RequestType::Flush => VIRTIO_BLK_T_FLUSH,
                 RequestType::GetDeviceID => VIRTIO_BLK_T_GET_ID,
+                RequestType::Discard => VIRTIO_BLK_T_DISCARD,
+                RequestType::WriteZeroes => VIRTIO_BLK_T_WRITE_ZEROES,
                 RequestType::Unsupported(id) => id,
             }
         }
@@ -732,6 +1277,7 @@ mod tests {
             RequestType::Out => VIRTQ_DESC_F_NEXT,
             RequestType::Flush => VIRTQ_DESC_F_NEXT,
             RequestType::GetDeviceID => VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE,
+            RequestType::Discard | RequestType::WriteZeroes => VIRTQ_DESC_F_NEXT,
             RequestType::Unsupported(_) => VIRTQ_DESC_F_NEXT,
         }
     }
@@ -949,7 +1495,7 @@ mod tests {
     fn parse_random_requests() {
         let cfg = ProptestConfig::with_cases(1000);
         proptest!(cfg, |(mut request in random_request_parse())| {
-            let result = Request::parse(&request.2.pop(&request.1).unwrap(), &request.1, NUM_DISK_SECTORS);
+            let result = Request::parse(&request.2.pop(&request.1).unwrap(), &request.1, NUM_DISK_SECTORS, SECTOR_SIZE);
             match result {
                 Ok(r) => prop_assert!(r == request.0.unwrap()),
                 Err(err) => {
@@ -968,4 +1514,42 @@ mod tests {
             }
         });
     }
+
+    #[test]
+    fn test_finish_latency() {
+        let mem = single_region_mem(0x1000);
+        let metrics = BlockDeviceMetrics::new();
+        let now_us = get_time_us(ClockType::Monotonic);
+        let pending = |r#type, popped_at_us| PendingRequest {
+            r#type,
+            data_len: 0,
+            status_addr: GuestAddress(0),
+            sector: 0,
+            data_addr: GuestAddress(0),
+            queue_index: 0,
+            desc_idx: 0,
+            popped_at_us,
+        };
+
+        // The requests not timestamped when popped aren't recorded.
+        pending(RequestType::In, None).finish(&mem, Ok(0), &metrics);
+        pending(RequestType::In, Some(now_us - 3_000)).finish(&mem, Ok(0), &metrics);
+        pending(RequestType::Out, Some(now_us - 3_000_000)).finish(&mem, Ok(0), &metrics);
+        pending(RequestType::Flush, Some(now_us)).finish(&mem, Ok(0), &metrics);
+        // Only reads, writes and flushes are recorded.
+        pending(RequestType::GetDeviceID, Some(now_us)).finish(&mem, Ok(0), &metrics);
+
+        let serialized = serde_json::to_value(&metrics).unwrap();
+        let read_latency = &serialized["read_latency"];
+        assert_eq!(read_latency["count"], 1);
+        assert!(read_latency["min_us"].as_u64().unwrap() >= 3_000);
+        // The request completes after at most a second.
+        assert_eq!(read_latency["buckets"]["le_2048us"], 0);
+        assert_eq!(read_latency["buckets"]["gt_1048576us"], 0);
+        let write_latency = &serialized["write_latency"];
+        assert_eq!(write_latency["count"], 1);
+        assert_eq!(write_latency["buckets"]["gt_1048576us"], 1);
+        assert!(write_latency["p50_us"].as_u64().unwrap() >= 3_000_000);
+        assert_eq!(serialized["flush_latency"]["count"], 1);
+    }
 }
@@ -20,7 +20,7 @@ use crate::devices::virtio::block::virtio::{CacheType, VirtioBlock};
 #[cfg(test)]
 use crate::devices::virtio::device::IrqType;
 use crate::devices::virtio::queue::{Queue, VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
-use crate::devices::virtio::test_utils::{VirtQueue, VirtqDesc};
+use crate::devices::virtio::test_utils::{enable_write_canaries, VirtQueue, VirtqDesc};
 use crate::rate_limiter::RateLimiter;
 use crate::vmm_config::{RateLimiterConfig, TokenBucketConfig};
 use crate::vstate::memory::{Bytes, GuestAddress};
@@ -65,9 +65,22 @@ pub fn default_block_with_path(path: String, file_engine_type: FileEngineType) -
                 refill_time: 10,
             }),
         }),
+        rate_limiter_scope: Default::default(),
         file_engine_type,
+        detect_zeroes: Default::default(),
+        open_direct: false,
+        io_engine_opts: Default::default(),
+        virtio_features_pin: None,
+        queue_size: None,
+        num_queues: None,
+        serial: None,
+        logical_block_size: None,
+        physical_block_size: None,
+        on_host_enospc: Default::default(),
+        io_uring: Default::default(),
     };
 
+    enable_write_canaries();
     // The default block device is read-write and non-root.
     VirtioBlock::new(config).unwrap()
 }
@@ -77,11 +90,11 @@ pub fn set_queue(blk: &mut VirtioBlock, idx: usize, q: Queue) {
 }
 
 pub fn set_rate_limiter(blk: &mut VirtioBlock, rl: RateLimiter) {
-    blk.rate_limiter = rl;
+    blk.rate_limiters[0] = rl;
 }
 
 pub fn rate_limiter(blk: &mut VirtioBlock) -> &RateLimiter {
-    &blk.rate_limiter
+    &blk.rate_limiters[0]
 }
 
 #[cfg(test)]
@@ -89,7 +102,7 @@ pub fn simulate_queue_event(b: &mut VirtioBlock, maybe_expected_irq: Option<bool
     // Trigger the queue event.
     b.queue_evts[0].write(1).unwrap();
     // Handle event.
-    b.process_queue_event();
+    b.process_queue_event(0).unwrap();
     // Validate the queue operation finished successfully.
     if let Some(expected_irq) = maybe_expected_irq {
         assert_eq!(b.irq_trigger.has_pending_irq(IrqType::Vring), expected_irq);
@@ -104,7 +117,7 @@ pub fn simulate_async_completion_event(b: &mut VirtioBlock, expected_irq: bool)
         // Wait for the async completion event to be sent.
         thread::sleep(Duration::from_millis(150));
         // Handle event.
-        b.process_async_completion_event();
+        let _ = b.process_async_completion_event();
     }
 
     // Validate if there are pending IRQs.
@@ -0,0 +1,213 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Canaries catching the devices writing guest memory outside of the buffers of the guest.
+//!
+//! With the `memory-guards` feature, the devices record the ranges of guest memory they are asked
+//! to write: the write-only descriptors of the guest, and the used rings. The bytes around every
+//! recorded range are captured, and compared once the device is done processing: a device bug
+//! writing past a buffer changes them. The verification is off unless turned on with [`enable`]
+//! by the unit tests of the devices, on their own thread:
+//!
+//! ```bash
+//! cargo test -p vmm --features memory-guards
+//! ```
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use utils::u64_to_usize;
+use vm_memory::VolatileSlice;
+
+use crate::vstate::memory::{
+    Address, GuestAddress, GuestMemory, GuestMemoryRegion, MemoryRegionAddress,
+};
+
+/// Number of bytes checked on each side of a recorded range.
+pub const CANARY_LEN: u64 = 64;
+
+// Host addresses of a range a device was asked to write, and of the bytes on each side of it
+// with their contents when the range was recorded.
+#[derive(Debug)]
+struct Canary {
+    written: Range<usize>,
+    sides: [(Range<usize>, Vec<u8>); 2],
+}
+
+thread_local! {
+    // Canaries recorded on the thread since the last verification, `None` until `enable`d.
+    static CANARIES: RefCell<Option<Vec<Canary>>> = const { RefCell::new(None) };
+}
+
+fn read_host(range: &Range<usize>) -> Vec<u8> {
+    let mut buf = vec![0; range.len()];
+    // SAFETY: The range was taken from a guest memory region by `record_write`, and the device
+    // holds the guest memory until the canaries are verified.
+    unsafe { VolatileSlice::new(range.start as *mut u8, range.len()) }.copy_to(&mut buf[..]);
+    buf
+}
+
+// Hashes the bytes of `side` which are not in any of the `written` ranges.
+fn hash_unwritten(side: &Range<usize>, bytes: &[u8], written: &[Range<usize>]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for (addr, byte) in side.clone().zip(bytes) {
+        if !written.iter().any(|range| range.contains(&addr)) {
+            (addr, byte).hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Turns on the canaries for the devices processed on the current thread, forgetting the ranges
+/// recorded so far.
+pub fn enable() {
+    CANARIES.with(|canaries| *canaries.borrow_mut() = Some(Vec::new()));
+}
+
+/// Records that a device was asked to write `len` bytes of guest memory at `addr`, capturing the
+/// bytes on each side of them, within the guest memory region.
+pub fn record_write<M: GuestMemory>(mem: &M, addr: GuestAddress, len: u32) {
+    CANARIES.with(|canaries| {
+        let mut canaries = canaries.borrow_mut();
+        let Some(canaries) = canaries.as_mut() else {
+            return;
+        };
+        let Some(region) = mem.find_region(addr) else {
+            return;
+        };
+
+        let region_start = region.start_addr().raw_value();
+        let region_end = region_start + region.len();
+        let start = addr.raw_value();
+        let end = start.saturating_add(u64::from(len)).min(region_end);
+        // The region was found, so its start has a host address.
+        let host_start = region.get_host_address(MemoryRegionAddress(0)).unwrap() as usize;
+        let host = |range: Range<u64>| {
+            host_start + u64_to_usize(range.start - region_start)
+                ..host_start + u64_to_usize(range.end - region_start)
+        };
+
+        let before = host(start.saturating_sub(CANARY_LEN).max(region_start)..start);
+        let after = host(end..end.saturating_add(CANARY_LEN).min(region_end));
+        canaries.push(Canary {
+            written: host(start..end),
+            sides: [
+                (before.clone(), read_host(&before)),
+                (after.clone(), read_host(&after)),
+            ],
+        });
+    });
+}
+
+/// Checks that the bytes around the ranges recorded on the current thread did not change, unless
+/// they are in another recorded range, and forgets the ranges.
+///
+/// # Panics
+///
+/// Panics if the bytes around a recorded range changed.
+pub fn verify_writes() {
+    let Some(canaries) =
+        CANARIES.with(|canaries| canaries.borrow_mut().as_mut().map(std::mem::take))
+    else {
+        return;
+    };
+
+    let written = canaries
+        .iter()
+        .map(|canary| canary.written.clone())
+        .collect::<Vec<_>>();
+    for canary in &canaries {
+        for (side, before) in &canary.sides {
+            assert_eq!(
+                hash_unwritten(side, before, &written),
+                hash_unwritten(side, &read_host(side), &written),
+                "Guest memory at host addresses {:#x}..{:#x} was written next to the range \
+                 {:#x}..{:#x}",
+                side.start,
+                side.end,
+                canary.written.start,
+                canary.written.end,
+            );
+        }
+    }
+}
+
+/// Verifies the canaries recorded on the current thread when dropped, at the end of the
+/// processing of a device.
+#[derive(Debug)]
+pub struct CanaryScope;
+
+impl Drop for CanaryScope {
+    fn drop(&mut self) {
+        // Don't hide the reason of an ongoing panic.
+        if !std::thread::panicking() {
+            verify_writes();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::test_utils::multi_region_mem;
+    use crate::vstate::memory::Bytes;
+
+    #[test]
+    fn test_disabled() {
+        let mem = multi_region_mem(&[(GuestAddress(0), 0x1000)]);
+        record_write(&mem, GuestAddress(0x100), 0x10);
+        mem.write_obj(1u8, GuestAddress(0x110)).unwrap();
+        verify_writes();
+        CANARIES.with(|canaries| assert!(canaries.borrow().is_none()));
+    }
+
+    #[test]
+    fn test_in_range_writes() {
+        enable();
+        let mem = multi_region_mem(&[(GuestAddress(0), 0x1000)]);
+
+        // Writes in the recorded ranges, including adjacent ones, are fine.
+        record_write(&mem, GuestAddress(0x100), 0x10);
+        record_write(&mem, GuestAddress(0x110), 0x4);
+        mem.write_slice(&[0xff; 0x14], GuestAddress(0x100)).unwrap();
+        // So are writes past the canaries.
+        mem.write_obj(1u8, GuestAddress(0x100 + 0x14 + CANARY_LEN))
+            .unwrap();
+        verify_writes();
+
+        // The canaries stop at the bounds of the region.
+        record_write(&mem, GuestAddress(0), 0x10);
+        record_write(&mem, GuestAddress(0xff0), 0x10);
+        record_write(&mem, GuestAddress(0xff8), 0x100);
+        mem.write_slice(&[0xff; 0x10], GuestAddress(0)).unwrap();
+        mem.write_slice(&[0xff; 0x10], GuestAddress(0xff0)).unwrap();
+        verify_writes();
+
+        // The ranges are forgotten once verified.
+        mem.write_obj(1u8, GuestAddress(0xfe0)).unwrap();
+        verify_writes();
+    }
+
+    #[test]
+    #[should_panic(expected = "was written next to the range")]
+    fn test_out_of_range_write_after() {
+        enable();
+        let mem = multi_region_mem(&[(GuestAddress(0), 0x1000)]);
+        record_write(&mem, GuestAddress(0x100), 0x10);
+        mem.write_slice(&[0xff; 0x11], GuestAddress(0x100)).unwrap();
+        verify_writes();
+    }
+
+    #[test]
+    #[should_panic(expected = "was written next to the range")]
+    fn test_out_of_range_write_before() {
+        enable();
+        let mem = multi_region_mem(&[(GuestAddress(0), 0x1000)]);
+        record_write(&mem, GuestAddress(0x100), 0x10);
+        mem.write_obj(1u8, GuestAddress(0x100 - CANARY_LEN))
+            .unwrap();
+        let _canaries = CanaryScope;
+    }
+}
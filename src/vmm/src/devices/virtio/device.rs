@@ -9,15 +9,72 @@ use std::fmt;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
+use event_manager::EventOps;
 use utils::eventfd::EventFd;
 
 use super::mmio::{VIRTIO_MMIO_INT_CONFIG, VIRTIO_MMIO_INT_VRING};
 use super::queue::Queue;
 use super::ActivateError;
 use crate::devices::virtio::AsAny;
-use crate::logger::{error, warn};
+use crate::devices::{DeviceError, ErrorSeverity};
+use crate::events::{DeviceErrorKind, VmmEvent, EVENTS};
+use crate::logger::{debug, error, warn, IncMetric, METRICS};
 use crate::vstate::memory::GuestMemoryMmap;
 
+/// Errors of the pinning of the virtio features offered by a device.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum VirtioFeaturesPinError {
+    /// The pinned virtio features {0:#x} cannot be offered by the device.
+    UnavailableFeatures(u64),
+}
+
+/// Restricts the virtio features offered by the device `id` to the ones of `pin`.
+///
+/// Pinning features the device cannot offer is an error. The features the device can offer, but
+/// which are not pinned, are logged since the guest drivers won't see them.
+pub fn pin_virtio_features(
+    id: &str,
+    avail_features: u64,
+    pin: u64,
+) -> Result<u64, VirtioFeaturesPinError> {
+    let unavailable_features = pin & !avail_features;
+    if unavailable_features != 0 {
+        return Err(VirtioFeaturesPinError::UnavailableFeatures(
+            unavailable_features,
+        ));
+    }
+
+    let masked_features = avail_features & !pin;
+    if masked_features != 0 {
+        warn!(
+            "The virtio features {:#x} of the device {} are not offered to the guest, since they \
+             are not pinned.",
+            masked_features, id
+        );
+    }
+    Ok(avail_features & pin)
+}
+
+/// Errors of the configuration of the size of the queues of a device.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum QueueSizeError {
+    /// The queue size {0} is invalid: it must be a power of two between {1} and {2}.
+    InvalidSize(u16, u16, u16),
+}
+
+/// Checks that `size` can be advertised as the maximal size of the queues of a device supporting
+/// queues of `min..=max` elements.
+///
+/// The virtio split rings require the queue sizes to be powers of two.
+pub fn check_queue_size(size: u16, min: u16, max: u16) -> Result<u16, QueueSizeError> {
+    if !size.is_power_of_two() || size < min || size > max {
+        return Err(QueueSizeError::InvalidSize(size, min, max));
+    }
+    Ok(size)
+}
+
 /// Enum that indicates if a VirtioDevice is inactive or has been activated
 /// and memory attached to it.
 #[derive(Debug)]
@@ -107,6 +164,16 @@ pub trait VirtioDevice: AsAny + Send {
         (self.acked_features() & 1 << feature) != 0
     }
 
+    /// Features the available features of the device are restricted to, if pinned.
+    fn virtio_features_pin(&self) -> Option<u64> {
+        None
+    }
+
+    /// Features negotiated with the driver, once the device is activated.
+    fn negotiated_features(&self) -> Option<u64> {
+        self.is_activated().then(|| self.acked_features())
+    }
+
     /// The virtio device type.
     fn device_type(&self) -> u32;
 
@@ -180,6 +247,62 @@ pub trait VirtioDevice: AsAny + Send {
     fn reset(&mut self) -> Option<(EventFd, Vec<EventFd>)> {
         None
     }
+
+    /// Checks if the device stopped after a fatal error, and needs to be reset by the driver.
+    fn needs_reset(&self) -> bool {
+        false
+    }
+}
+
+/// Trait for virtio devices whose event handler reports the errors of their events according to
+/// their severity.
+pub trait DeviceErrorHandler: VirtioDevice {
+    /// Marks the device as stopped after a fatal error.
+    fn set_needs_reset(&mut self);
+
+    /// Unregisters the runtime events of the device from the event manager.
+    fn unregister_runtime_events(&mut self, ops: &mut EventOps);
+
+    /// Handles an error returned while processing an event of the device:
+    /// * transient errors are logged at debug level;
+    /// * degraded errors are logged as warnings, and published on the event bus;
+    /// * fatal errors are logged as errors, and published on the event bus. The device is stopped:
+    ///   it stops listening to its events, and asks the driver for a reset.
+    ///
+    /// All errors are counted in the `device_errors` metrics.
+    fn handle_error(&mut self, ops: &mut EventOps, err: DeviceError) {
+        let severity = err.severity();
+        match severity {
+            ErrorSeverity::Transient => {
+                debug!("Virtio device type {}: {}", self.device_type(), err);
+                METRICS.device_errors.transient.inc();
+                return;
+            }
+            ErrorSeverity::Degraded => {
+                warn!("Virtio device type {}: {}", self.device_type(), err);
+                METRICS.device_errors.degraded.inc();
+            }
+            ErrorSeverity::Fatal => {
+                error!(
+                    "Virtio device type {}: {}. Stopping the device.",
+                    self.device_type(),
+                    err
+                );
+                METRICS.device_errors.fatal.inc();
+                self.set_needs_reset();
+                self.unregister_runtime_events(ops);
+                // Section 2.1.2 of the specification states that we need to send a device
+                // configuration change interrupt when setting DEVICE_NEEDS_RESET.
+                let _ = self.interrupt_trigger().trigger_irq(IrqType::Config);
+            }
+        }
+        EVENTS.publish(VmmEvent::DeviceError {
+            device_type: self.device_type(),
+            kind: DeviceErrorKind::Queue,
+            severity,
+            message: err.to_string(),
+        });
+    }
 }
 
 impl fmt::Debug for dyn VirtioDevice {
@@ -288,6 +411,51 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_pin_virtio_features() {
+        let avail_features = 0b1011;
+
+        assert_eq!(
+            pin_virtio_features("dev", avail_features, avail_features).unwrap(),
+            avail_features
+        );
+        assert_eq!(
+            pin_virtio_features("dev", avail_features, 0b0011).unwrap(),
+            0b0011
+        );
+        assert_eq!(pin_virtio_features("dev", avail_features, 0).unwrap(), 0);
+        assert_eq!(
+            pin_virtio_features("dev", avail_features, 0b0111).unwrap_err(),
+            VirtioFeaturesPinError::UnavailableFeatures(0b0100)
+        );
+        assert_eq!(
+            pin_virtio_features("dev", avail_features, 1 << 63 | 0b0001).unwrap_err(),
+            VirtioFeaturesPinError::UnavailableFeatures(1 << 63)
+        );
+    }
+
+    #[test]
+    fn test_check_queue_size() {
+        assert_eq!(check_queue_size(16, 16, 1024).unwrap(), 16);
+        assert_eq!(check_queue_size(256, 16, 1024).unwrap(), 256);
+        assert_eq!(check_queue_size(1024, 16, 1024).unwrap(), 1024);
+        for size in [0, 100, 255, 257, 1000] {
+            assert_eq!(
+                check_queue_size(size, 16, 1024).unwrap_err(),
+                QueueSizeError::InvalidSize(size, 16, 1024)
+            );
+        }
+        // Powers of two out of the bounds of the device.
+        assert_eq!(
+            check_queue_size(8, 16, 1024).unwrap_err(),
+            QueueSizeError::InvalidSize(8, 16, 1024)
+        );
+        assert_eq!(
+            check_queue_size(2048, 16, 1024).unwrap_err(),
+            QueueSizeError::InvalidSize(2048, 16, 1024)
+        );
+    }
+
     #[test]
     fn test_has_feature() {
         let mut device = MockVirtioDevice { acked_features: 0 };
@@ -15,4 +15,5 @@
     clippy::tests_outside_test_module
 )]
 
+pub const VIRTIO_RING_F_INDIRECT_DESC: u32 = 28;
 pub const VIRTIO_RING_F_EVENT_IDX: u32 = 29;
@@ -2,15 +2,21 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::io::ErrorKind;
+use std::marker::PhantomData;
+use std::os::unix::io::{AsRawFd, RawFd};
 
-use libc::{c_void, iovec, size_t};
+use libc::{c_int, c_void, iovec, msghdr, off_t, size_t, ssize_t};
 use smallvec::SmallVec;
+use utils::u64_to_usize;
 use vm_memory::{
     GuestMemoryError, ReadVolatile, VolatileMemoryError, VolatileSlice, WriteVolatile,
 };
 
-use crate::devices::virtio::queue::DescriptorChain;
-use crate::vstate::memory::{Bitmap, GuestMemory};
+use crate::devices::virtio::queue::{ChainError, DescriptorChain, QueueRings};
+use crate::logger::debug;
+use crate::vstate::memory::{
+    Address, Bitmap, ByteValued, GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion,
+};
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum IoVecError {
@@ -20,8 +26,36 @@ pub enum IoVecError {
     ReadOnlyDescriptor,
     /// Tried to create an `IoVec` or `IoVecMut` from a descriptor chain that was too large
     OverflowedDescriptor,
+    /// Tried to create an `IoVec` or `IoVecMut` longer than the {0} bytes of guest memory
+    DescriptorChainTooLong(u64),
+    /// Found a device-readable descriptor after a device-writable one in a descriptor chain
+    ReadableAfterWritable,
+    /// Tried to create an `IoVecMut` over guest memory at {0:#x} holding the rings of its queue
+    OverlapsQueue(u64),
+    /// Tried to create an `IoVec` or `IoVecMut` from a chain needing more than {0} iovecs
+    TooManyDescriptors(usize),
+    /// Invalid descriptor chain: {0}
+    InvalidChain(#[from] ChainError),
     /// Guest memory error: {0}
     GuestMemory(#[from] GuestMemoryError),
+    /// Tried to read an object of {size} bytes at offset {offset}, with {remaining} bytes left
+    ShortBuffer {
+        offset: usize,
+        size: usize,
+        remaining: usize,
+    },
+    /// Tried to write an object of {size} bytes at offset {offset}, with {remaining} bytes left
+    TruncatedWrite {
+        offset: usize,
+        size: usize,
+        remaining: usize,
+    },
+    /// Volatile memory error: {0}
+    VolatileMemory(#[from] VolatileMemoryError),
+    /// Error transferring the buffer to or from a file: {0}
+    FileIo(std::io::Error),
+    /// Transferred {completed} bytes out of {expected} before running out of data or room
+    PartialTransfer { expected: usize, completed: usize },
 }
 
 // Using SmallVec in the kani proofs causes kani to use unbounded amounts of memory
@@ -31,53 +65,647 @@ pub enum IoVecError {
 type IoVecVec = Vec<iovec>;
 #[cfg(not(kani))]
 type IoVecVec = SmallVec<[iovec; 4]>;
+#[cfg(kani)]
+type GuestAddressVec = Vec<GuestAddress>;
+#[cfg(not(kani))]
+type GuestAddressVec = SmallVec<[GuestAddress; 4]>;
+#[cfg(kani)]
+type ChainEndVec = Vec<u32>;
+#[cfg(not(kani))]
+type ChainEndVec = SmallVec<[u32; 1]>;
+
+/// Default maximum number of `iovec`s of an `IoVecBuffer` or `IoVecBufferMut`, matching the
+/// limit of the host on the number of `iovec`s passed to `readv`/`writev`.
+pub const IOV_MAX: usize = 1024;
+
+/// Whether a descriptor chain is covered entirely by a buffer built from it with a maximum
+/// length, see [`IoVecBuffer::from_descriptor_chain_capped`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainFit {
+    /// All the bytes of the chain are covered.
+    Whole,
+    /// The chain goes on past the maximum length, up to which it is covered.
+    Truncated,
+}
+
+/// Outcome of a transfer between an `IoVecBuffer` or `IoVecBufferMut` and a file descriptor that
+/// can be non-blocking, see [`IoVecBuffer::try_read_volatile_at`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transferred {
+    /// The transfer ran until the end of the range, or until the other end had no more bytes
+    /// or room, e.g. at the end of a file, after transferring the given number of bytes.
+    Complete(usize),
+    /// The other end would block after the given number of bytes were transferred. The rest of
+    /// the range can be transferred once it is ready again.
+    Blocked(usize),
+}
+
+impl Transferred {
+    /// Returns the number of bytes transferred.
+    pub fn bytes(self) -> usize {
+        match self {
+            Transferred::Complete(bytes) | Transferred::Blocked(bytes) => bytes,
+        }
+    }
+}
+
+// Appends a memory region to `vecs`, so that at most `max_iovecs` `iovec`s are used. If
+// `same_region` is set, i.e. the memory region is in the same guest memory region as the last
+// `iovec`, it is merged into the last `iovec` when both are contiguous.
+fn push_iovec(
+    vecs: &mut IoVecVec,
+    iov_base: *mut c_void,
+    iov_len: size_t,
+    same_region: bool,
+    max_iovecs: usize,
+) -> Result<(), IoVecError> {
+    if let Some(last) = vecs.last_mut().filter(|_| same_region) {
+        if last.iov_base.cast::<u8>().wrapping_add(last.iov_len) == iov_base.cast::<u8>() {
+            last.iov_len += iov_len;
+            return Ok(());
+        }
+    }
+    if vecs.len() >= max_iovecs {
+        return Err(IoVecError::TooManyDescriptors(max_iovecs));
+    }
+    vecs.push(iovec { iov_base, iov_len });
+    Ok(())
+}
+
+// Returns the total size of the guest memory, which bounds the length of a descriptor chain: a
+// longer one has to refer to some guest memory more than once.
+fn guest_memory_size(mem: &GuestMemoryMmap) -> u64 {
+    mem.iter().map(|region| region.len()).sum()
+}
+
+// Adds the length `len` of a descriptor to the length `total` of the buffer built from its chain,
+// checking that the buffer is no longer than the `mem_size` bytes of guest memory.
+fn add_descriptor_len(total: u32, len: u32, mem_size: u64) -> Result<u32, IoVecError> {
+    let total = total
+        .checked_add(len)
+        .ok_or(IoVecError::OverflowedDescriptor)?;
+    if u64::from(total) > mem_size {
+        return Err(IoVecError::DescriptorChainTooLong(mem_size));
+    }
+    Ok(total)
+}
+
+// Zero-length descriptors add nothing to a buffer, so they do not get an `iovec`. They are legal
+// but useless, and logged to spot the drivers posting them.
+fn skip_empty_descriptor(desc: &DescriptorChain) {
+    debug!(
+        "iovec: skipping zero-length descriptor {} at {:#x}",
+        desc.index,
+        desc.addr.raw_value()
+    );
+}
+
+// Position in an array of `iovec`s, used to walk two buffers at once.
+#[derive(Debug)]
+struct IoVecCursor<'v> {
+    vecs: &'v [iovec],
+    // Index of the current `iovec`
+    index: usize,
+    // Offset in the current `iovec`
+    offset: usize,
+}
+
+impl<'v> IoVecCursor<'v> {
+    fn new(vecs: &'v [iovec], offset: usize) -> Self {
+        let mut cursor = IoVecCursor {
+            vecs,
+            index: 0,
+            offset,
+        };
+        cursor.skip_exhausted();
+        cursor
+    }
+
+    // Moves to the first `iovec` with bytes left past the offset, skipping the empty ones.
+    fn skip_exhausted(&mut self) {
+        while let Some(iov) = self.vecs.get(self.index) {
+            if self.offset < iov.iov_len {
+                break;
+            }
+            self.offset -= iov.iov_len;
+            self.index += 1;
+        }
+    }
+
+    // Returns the bytes left in the current `iovec`, or `None` once all of them were walked.
+    fn current(&self) -> Option<VolatileSlice<'v>> {
+        let iov = self.vecs.get(self.index)?;
+        // SAFETY: the constructors of IoVecBuffer(Mut) ensure that all iovecs contained point
+        // towards valid ranges of guest memory, and the offset is within the iovec.
+        Some(unsafe {
+            VolatileSlice::new(
+                iov.iov_base.cast::<u8>().add(self.offset),
+                iov.iov_len - self.offset,
+            )
+        })
+    }
+
+    fn advance(&mut self, count: usize) {
+        self.offset += count;
+        self.skip_exhausted();
+    }
+}
+
+/// Iterator over the memory regions of an [`IoVecBuffer`] or an [`IoVecBufferMut`], skipping the
+/// empty ones.
+#[derive(Debug)]
+pub struct IoVecSlices<'v> {
+    cursor: IoVecCursor<'v>,
+    // Number of bytes left to yield
+    remaining: usize,
+}
+
+impl<'v> IoVecSlices<'v> {
+    fn new(vecs: &'v [iovec], offset: usize, len: usize) -> Self {
+        IoVecSlices {
+            cursor: IoVecCursor::new(vecs, offset),
+            remaining: len,
+        }
+    }
+}
+
+impl<'v> Iterator for IoVecSlices<'v> {
+    type Item = VolatileSlice<'v>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let mut slice = self.cursor.current()?;
+        if slice.len() > self.remaining {
+            // This cannot fail, the slice being longer than the bytes left.
+            slice = slice.subslice(0, self.remaining).ok()?;
+        }
+        self.cursor.advance(slice.len());
+        self.remaining -= slice.len();
+        Some(slice)
+    }
+}
+
+// Fills `buf` with the bytes at `offset` of the memory regions `vecs`, of total length `len`.
+fn read_exact_from(
+    vecs: &[iovec],
+    len: u32,
+    mut buf: &mut [u8],
+    offset: usize,
+) -> Result<(), VolatileMemoryError> {
+    if offset < len as usize {
+        let expected = buf.len();
+        let bytes_read = read_from(IoVecSlices::new(vecs, offset, expected), &mut buf)?;
+
+        if bytes_read != expected {
+            return Err(VolatileMemoryError::PartialBuffer {
+                expected,
+                completed: bytes_read,
+            });
+        }
+
+        Ok(())
+    } else {
+        // If `offset` is past size, there's nothing to read.
+        Err(VolatileMemoryError::OutOfBounds { addr: offset })
+    }
+}
+
+// Writes the bytes of `slices` to `dst`, until `dst` takes less than a whole slice.
+fn read_from<W: WriteVolatile>(
+    slices: IoVecSlices<'_>,
+    dst: &mut W,
+) -> Result<usize, VolatileMemoryError> {
+    let mut total_bytes_read = 0;
+
+    for slice in slices {
+        let bytes_read = loop {
+            match dst.write_volatile(&slice) {
+                Err(VolatileMemoryError::IOError(err)) if err.kind() == ErrorKind::Interrupted => {
+                    continue
+                }
+                Ok(bytes_read) => break bytes_read,
+                Err(volatile_memory_error) => return Err(volatile_memory_error),
+            }
+        };
+        total_bytes_read += bytes_read;
+
+        if bytes_read < slice.len() {
+            break;
+        }
+    }
+
+    Ok(total_bytes_read)
+}
+
+// Transfers the bytes of `slices` with `op`, a `read_volatile` or `write_volatile` call.
+// Interrupted calls are retried and short ones are resumed at the first byte not transferred yet,
+// until a call makes no progress, e.g. at the end of a file, or would block.
+fn try_transfer<'v>(
+    slices: IoVecSlices<'v>,
+    mut op: impl FnMut(VolatileSlice<'v>) -> Result<usize, VolatileMemoryError>,
+) -> Result<Transferred, VolatileMemoryError> {
+    let mut total_bytes = 0;
+
+    for slice in slices {
+        let mut slice_bytes = 0;
+        while slice_bytes < slice.len() {
+            match op(slice.offset(slice_bytes)?) {
+                Err(VolatileMemoryError::IOError(err)) if err.kind() == ErrorKind::Interrupted => {}
+                Err(VolatileMemoryError::IOError(err)) if err.kind() == ErrorKind::WouldBlock => {
+                    return Ok(Transferred::Blocked(total_bytes + slice_bytes));
+                }
+                Ok(0) => return Ok(Transferred::Complete(total_bytes + slice_bytes)),
+                Ok(bytes) => slice_bytes += bytes,
+                Err(volatile_memory_error) => return Err(volatile_memory_error),
+            }
+        }
+        total_bytes += slice_bytes;
+    }
+
+    Ok(Transferred::Complete(total_bytes))
+}
+
+// Same as `try_transfer`, for the other end being blocking: blocking is an error. Returns the
+// bytes transferred.
+fn transfer_all<'v>(
+    slices: IoVecSlices<'v>,
+    op: impl FnMut(VolatileSlice<'v>) -> Result<usize, VolatileMemoryError>,
+) -> Result<usize, VolatileMemoryError> {
+    match try_transfer(slices, op)? {
+        Transferred::Complete(bytes) => Ok(bytes),
+        Transferred::Blocked(_) => Err(VolatileMemoryError::IOError(std::io::Error::from(
+            ErrorKind::WouldBlock,
+        ))),
+    }
+}
+
+// Checks that `completed` bytes out of `expected` made a whole transfer.
+fn check_transfer(expected: usize, completed: usize) -> Result<(), IoVecError> {
+    if completed == expected {
+        Ok(())
+    } else {
+        Err(IoVecError::PartialTransfer {
+            expected,
+            completed,
+        })
+    }
+}
+
+// Signature of `preadv2` and `pwritev2`.
+type PositionedIoFn = unsafe extern "C" fn(c_int, *const iovec, c_int, off_t, c_int) -> ssize_t;
+
+// Transfers the memory regions `vecs` to or from the file `fd` starting at `file_offset` with
+// `op`, until all of them are transferred or the end of the file is reached. Interrupted calls are
+// retried, and short ones are resumed at the first byte not transferred yet, through a copy of the
+// `iovec`s adjusted to skip the bytes already transferred.
+fn transfer_at(
+    vecs: &[iovec],
+    fd: RawFd,
+    file_offset: u64,
+    op: PositionedIoFn,
+) -> Result<usize, IoVecError> {
+    let invalid_input = || IoVecError::FileIo(std::io::Error::from(ErrorKind::InvalidInput));
+    let len: usize = vecs.iter().map(|iov| iov.iov_len).sum();
+    let mut adjusted: Option<IoVecVec> = None;
+    let mut total_bytes = 0;
+
+    while total_bytes < len {
+        let remaining = adjusted.as_deref().unwrap_or(vecs);
+        let offset = u64::try_from(total_bytes)
+            .ok()
+            .and_then(|total_bytes| file_offset.checked_add(total_bytes))
+            .and_then(|offset| off_t::try_from(offset).ok())
+            .ok_or_else(invalid_input)?;
+        let iovcnt = c_int::try_from(remaining.len()).map_err(|_| invalid_input())?;
+
+        // SAFETY: the constructors of IoVecBuffer(Mut) ensure that all iovecs contained point
+        // towards valid ranges of guest memory, and `remaining` holds `iovcnt` of them. The return
+        // value is checked.
+        let ret = unsafe { op(fd, remaining.as_ptr(), iovcnt, offset, 0) };
+        let bytes = match usize::try_from(ret) {
+            // End of file
+            Ok(0) => break,
+            Ok(bytes) => bytes,
+            Err(_) => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(IoVecError::FileIo(err));
+            }
+        };
+
+        total_bytes += bytes;
+        if total_bytes < len {
+            skip_bytes(
+                adjusted.get_or_insert_with(|| vecs.iter().copied().collect()),
+                bytes,
+            );
+        }
+    }
+
+    Ok(total_bytes)
+}
+
+// Transfers the `len` bytes of the memory regions `vecs` starting at `offset` to or from a socket
+// with a single call to `op`, i.e. `sendmsg` or `recvmsg`, retrying it when interrupted. The first
+// and last `iovec`s are trimmed to the range in a copy of them, leaving `vecs` untouched.
+fn socket_transfer(
+    vecs: &[iovec],
+    offset: usize,
+    len: usize,
+    mut op: impl FnMut(&mut msghdr) -> ssize_t,
+) -> Result<usize, VolatileMemoryError> {
+    let mut trimmed: IoVecVec = IoVecSlices::new(vecs, offset, len)
+        .map(|slice| iovec {
+            iov_base: slice.ptr_guard_mut().as_ptr().cast::<c_void>(),
+            iov_len: slice.len(),
+        })
+        .collect();
+    // A transfer of no bytes would look like the peer closing the socket.
+    if trimmed.is_empty() {
+        return Ok(0);
+    }
+
+    // SAFETY: `msghdr` is a C struct, for which all zeroes is a valid value.
+    let mut msg: msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = trimmed.as_mut_ptr();
+    // There are at most as many `iovec`s as in `vecs`, which is bounded by `IOV_MAX`.
+    msg.msg_iovlen = trimmed.len().try_into().unwrap();
+
+    loop {
+        match usize::try_from(op(&mut msg)) {
+            Ok(bytes) => return Ok(bytes),
+            Err(_) => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != ErrorKind::Interrupted {
+                    return Err(VolatileMemoryError::IOError(err));
+                }
+            }
+        }
+    }
+}
+
+// Drops the first `count` bytes of the memory regions `vecs`.
+fn skip_bytes(vecs: &mut IoVecVec, mut count: usize) {
+    let mut skipped = 0;
+    while let Some(iov) = vecs.get(skipped).filter(|iov| iov.iov_len <= count) {
+        count -= iov.iov_len;
+        skipped += 1;
+    }
+    vecs.drain(..skipped);
+    if let Some(first) = vecs.first_mut() {
+        first.iov_base = first
+            .iov_base
+            .cast::<u8>()
+            .wrapping_add(count)
+            .cast::<c_void>();
+        first.iov_len -= count;
+    }
+}
 
 /// This is essentially a wrapper of a `Vec<libc::iovec>` which can be passed to `libc::writev`.
 ///
 /// It describes a buffer passed to us by the guest that is scattered across multiple
 /// memory regions. Additionally, this wrapper provides methods that allow reading arbitrary ranges
 /// of data from that buffer.
+///
+/// The `iovec`s are raw pointers into guest memory, so the buffer borrows the guest memory it
+/// was created from for `'a`. This guarantees that a buffer, including one kept by a device
+/// across event loop iterations, cannot outlive the memory mappings it points into:
+///
+/// ```compile_fail
+/// use vmm::devices::virtio::iovec::IoVecBuffer;
+/// use vmm::devices::virtio::queue::Queue;
+/// use vmm::vstate::memory::GuestMemoryMmap;
+///
+/// fn use_after_unmap(mem: GuestMemoryMmap, queue: &mut Queue) {
+///     let head = queue.pop(&mem).unwrap();
+///     let buffer = IoVecBuffer::from_descriptor_chain(head).unwrap();
+///     drop(mem);
+///     buffer.iovec_count();
+/// }
+/// ```
 #[derive(Debug)]
-pub struct IoVecBuffer {
+pub struct IoVecBuffer<'a> {
     // container of the memory regions included in this IO vector
     vecs: IoVecVec,
     // Total length of the IoVecBuffer
     len: u32,
+    // Guest memory the `iovec`s point into
+    _mem: PhantomData<&'a GuestMemoryMmap>,
 }
 
-impl IoVecBuffer {
-    /// Create an `IoVecBuffer` from a `DescriptorChain`
-    pub fn from_descriptor_chain(head: DescriptorChain) -> Result<Self, IoVecError> {
-        let mut vecs = IoVecVec::new();
-        let mut len = 0u32;
+// SAFETY: The `iovec`s only point into the guest memory borrowed for `'a`, which can be accessed
+// from any thread, and are only dereferenced through `&self` or `&mut self`.
+unsafe impl Send for IoVecBuffer<'_> {}
+
+impl Default for IoVecBuffer<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> IoVecBuffer<'a> {
+    /// Create an empty `IoVecBuffer`, to be filled with `load_descriptor_chain`
+    pub fn new() -> Self {
+        Self {
+            vecs: IoVecVec::new(),
+            len: 0,
+            _mem: PhantomData,
+        }
+    }
+
+    /// Create an `IoVecBuffer` from a `DescriptorChain`, with at most [`IOV_MAX`] `iovec`s
+    pub fn from_descriptor_chain(head: DescriptorChain<'a>) -> Result<Self, IoVecError> {
+        Self::from_descriptor_chain_with_limit(head, IOV_MAX)
+    }
 
-        let mut next_descriptor = Some(head);
-        while let Some(desc) = next_descriptor {
+    /// Create an `IoVecBuffer` from a `DescriptorChain`, with at most `max_iovecs` `iovec`s
+    pub fn from_descriptor_chain_with_limit(
+        head: DescriptorChain<'a>,
+        max_iovecs: usize,
+    ) -> Result<Self, IoVecError> {
+        let mut buffer = Self::new();
+        buffer.load_descriptor_chain_with_limit(head, max_iovecs)?;
+        Ok(buffer)
+    }
+
+    /// Create an `IoVecBuffer` covering at most the first `max_len` bytes of a `DescriptorChain`,
+    /// with at most [`IOV_MAX`] `iovec`s. The last descriptor covered is trimmed if needed, and
+    /// the ones past it are neither walked nor validated.
+    ///
+    /// Also returns whether the chain was covered entirely, or truncated at `max_len` bytes.
+    pub fn from_descriptor_chain_capped(
+        head: DescriptorChain<'a>,
+        max_len: usize,
+    ) -> Result<(Self, ChainFit), IoVecError> {
+        let max_len = u32::try_from(max_len).unwrap_or(u32::MAX);
+        let mut buffer = Self::new();
+        let fit = buffer.load_descriptors(head, IOV_MAX, Some(max_len))?;
+        Ok((buffer, fit))
+    }
+
+    /// Create an `IoVecBuffer` covering the `len` bytes of guest memory at `addr`, which have to
+    /// be in a single guest memory region.
+    pub fn from_guest_memory(
+        mem: &'a GuestMemoryMmap,
+        addr: GuestAddress,
+        len: u32,
+    ) -> Result<Self, IoVecError> {
+        let iov_base = mem
+            .get_slice(addr, len as usize)?
+            .ptr_guard_mut()
+            .as_ptr()
+            .cast::<c_void>();
+        let mut buffer = Self::new();
+        buffer.vecs.push(iovec {
+            iov_base,
+            iov_len: len as size_t,
+        });
+        buffer.len = len;
+        Ok(buffer)
+    }
+
+    /// Replaces the memory regions of the `IoVecBuffer` with the ones of a `DescriptorChain`.
+    ///
+    /// The storage of the `iovec`s is reused, so that loading chains no longer than the ones
+    /// loaded before does not allocate. On error, the `IoVecBuffer` is left empty.
+    ///
+    /// Descriptors contiguous in a guest memory region share an `iovec`, and chains still needing
+    /// more than [`IOV_MAX`] `iovec`s are rejected. Zero-length descriptors are skipped.
+    pub fn load_descriptor_chain(&mut self, head: DescriptorChain<'a>) -> Result<(), IoVecError> {
+        self.load_descriptor_chain_with_limit(head, IOV_MAX)
+    }
+
+    /// Same as `load_descriptor_chain`, rejecting chains needing more than `max_iovecs`
+    /// `iovec`s, for devices with stricter limits than [`IOV_MAX`].
+    pub fn load_descriptor_chain_with_limit(
+        &mut self,
+        head: DescriptorChain<'a>,
+        max_iovecs: usize,
+    ) -> Result<(), IoVecError> {
+        self.clear();
+        let result = self.load_descriptors(head, max_iovecs, None).map(|_| ());
+        if result.is_err() {
+            self.clear();
+        }
+        result
+    }
+
+    /// Removes all the memory regions of the `IoVecBuffer`, keeping the capacity of the storage
+    /// of the `iovec`s.
+    pub fn clear(&mut self) {
+        self.vecs.clear();
+        self.len = 0;
+    }
+
+    /// Empties the `IoVecBuffer` and releases its borrow of the guest memory, keeping the
+    /// capacity of the storage of the `iovec`s. This allows a device to keep the storage
+    /// across the processing of its queues, each one borrowing the guest memory anew.
+    pub fn recycle<'b>(mut self) -> IoVecBuffer<'b> {
+        self.clear();
+        IoVecBuffer {
+            vecs: self.vecs,
+            len: 0,
+            _mem: PhantomData,
+        }
+    }
+
+    /// Hands the memory regions of the `IoVecBuffer` over to an asynchronous transfer, e.g. an
+    /// io_uring `IORING_OP_WRITEV` operation reading from them until it completes.
+    pub fn into_parts(self) -> IoVecParts {
+        IoVecParts {
+            vecs: Box::from(&self.vecs[..]),
+            addrs: GuestAddressVec::new(),
+            len: self.len,
+            chain_ends: ChainEndVec::new(),
+        }
+    }
+
+    /// Turns the `parts` of an `IoVecBuffer` back into a buffer, e.g. once the transfer they
+    /// were handed over to completed.
+    ///
+    /// # Safety
+    ///
+    /// The guest memory the parts were taken from has to be borrowed for `'a`, so that it stays
+    /// mapped while the buffer is used.
+    pub unsafe fn from_parts(parts: IoVecParts) -> Self {
+        Self {
+            vecs: parts.vecs.iter().copied().collect(),
+            len: parts.len,
+            _mem: PhantomData,
+        }
+    }
+
+    // Walks the chain up to `max_len` bytes, if given.
+    fn load_descriptors(
+        &mut self,
+        head: DescriptorChain<'a>,
+        max_iovecs: usize,
+        max_len: Option<u32>,
+    ) -> Result<ChainFit, IoVecError> {
+        let mut last_region = None;
+        let mem_size = guest_memory_size(head.mem);
+        for desc in head.checked_iter() {
+            if max_len == Some(self.len) {
+                return Ok(ChainFit::Truncated);
+            }
+            let desc = desc?;
             if desc.is_write_only() {
                 return Err(IoVecError::WriteOnlyDescriptor);
             }
 
-            // We use get_slice instead of `get_host_address` here in order to have the whole
-            // range of the descriptor chain checked, i.e. [addr, addr + len) is a valid memory
-            // region in the GuestMemoryMmap.
-            let iov_base = desc
-                .mem
-                .get_slice(desc.addr, desc.len as usize)?
-                .ptr_guard_mut()
-                .as_ptr()
-                .cast::<c_void>();
-            vecs.push(iovec {
-                iov_base,
-                iov_len: desc.len as size_t,
-            });
-            len = len
-                .checked_add(desc.len)
-                .ok_or(IoVecError::OverflowedDescriptor)?;
+            let len = max_len.map_or(desc.len, |max_len| desc.len.min(max_len - self.len));
+            self.push_descriptor(&desc, len, mem_size, &mut last_region, max_iovecs)?;
+            if len < desc.len {
+                return Ok(ChainFit::Truncated);
+            }
+        }
+
+        Ok(ChainFit::Whole)
+    }
 
-            next_descriptor = desc.next_descriptor();
+    // Appends the first `len` bytes of `desc` to the buffer, of at most `mem_size` bytes.
+    // `last_region` holds the guest memory region of the previous descriptor, and is updated to
+    // the one of `desc`.
+    fn push_descriptor(
+        &mut self,
+        desc: &DescriptorChain<'a>,
+        len: u32,
+        mem_size: u64,
+        last_region: &mut Option<GuestAddress>,
+        max_iovecs: usize,
+    ) -> Result<(), IoVecError> {
+        if len == 0 {
+            skip_empty_descriptor(desc);
+            return Ok(());
         }
 
-        Ok(Self { vecs, len })
+        // We use get_slice instead of `get_host_address` here in order to have the whole
+        // range of the descriptor chain checked, i.e. [addr, addr + len) is a valid memory
+        // region in the GuestMemoryMmap.
+        let iov_base = desc
+            .mem
+            .get_slice(desc.addr, len as usize)?
+            .ptr_guard_mut()
+            .as_ptr()
+            .cast::<c_void>();
+        // Guest memory regions contiguous in the host address space are not merged, each
+        // one being mapped on its own.
+        let region = desc.mem.find_region(desc.addr).map(|r| r.start_addr());
+        push_iovec(
+            &mut self.vecs,
+            iov_base,
+            len as size_t,
+            region == *last_region,
+            max_iovecs,
+        )?;
+        *last_region = region;
+        self.len = add_descriptor_len(self.len, len, mem_size)?;
+        Ok(())
     }
 
     /// Get the total length of the memory regions covered by this `IoVecBuffer`
@@ -95,6 +723,56 @@ impl IoVecBuffer {
         self.vecs.len()
     }
 
+    /// Returns the `iovec` array, e.g. to pass it to `writev`.
+    pub fn as_slice(&self) -> &[iovec] {
+        &self.vecs
+    }
+
+    /// Returns the `iovec`s covering the `len` bytes of the `IoVecBuffer` starting at `offset`,
+    /// with the first and last ones trimmed to the range, or `None` if the range goes past the
+    /// end of the buffer.
+    pub fn sub_range(&self, offset: usize, len: usize) -> Option<IoVecSubRange<'_>> {
+        let end = offset.checked_add(len)?;
+        if end > self.len as usize {
+            return None;
+        }
+
+        let mut vecs = IoVecVec::new();
+        let mut iov_offset = 0;
+        for iov in self.vecs.iter() {
+            let iov_end = iov_offset + iov.iov_len;
+            if iov_offset.max(offset) < iov_end.min(end) {
+                let start = offset.saturating_sub(iov_offset);
+                vecs.push(iovec {
+                    iov_base: iov
+                        .iov_base
+                        .cast::<u8>()
+                        .wrapping_add(start)
+                        .cast::<c_void>(),
+                    iov_len: iov_end.min(end) - iov_offset - start,
+                });
+            }
+            iov_offset = iov_end;
+        }
+
+        Some(IoVecSubRange {
+            vecs,
+            _buffer: PhantomData,
+        })
+    }
+
+    /// Returns an iterator over the memory regions of the `IoVecBuffer`.
+    pub fn iter(&self) -> IoVecSlices<'_> {
+        IoVecSlices::new(&self.vecs, 0, self.len as usize)
+    }
+
+    /// Returns an iterator over the memory regions covering the `len` bytes of the `IoVecBuffer`
+    /// starting at `offset`, the first and last ones being trimmed to the range. Bytes past the
+    /// end of the `IoVecBuffer` are ignored.
+    pub fn range_iter(&self, offset: usize, len: usize) -> IoVecSlices<'_> {
+        IoVecSlices::new(&self.vecs, offset, len)
+    }
+
     /// Reads a number of bytes from the `IoVecBuffer` starting at a given offset.
     ///
     /// This will try to fill `buf` reading bytes from the `IoVecBuffer` starting from
@@ -107,25 +785,10 @@ impl IoVecBuffer {
     /// `Err(VolatileMemoryError::OutOfBounds)` if `offset >= self.len()`.
     pub fn read_exact_volatile_at(
         &self,
-        mut buf: &mut [u8],
+        buf: &mut [u8],
         offset: usize,
     ) -> Result<(), VolatileMemoryError> {
-        if offset < self.len() as usize {
-            let expected = buf.len();
-            let bytes_read = self.read_volatile_at(&mut buf, offset, expected)?;
-
-            if bytes_read != expected {
-                return Err(VolatileMemoryError::PartialBuffer {
-                    expected,
-                    completed: bytes_read,
-                });
-            }
-
-            Ok(())
-        } else {
-            // If `offset` is past size, there's nothing to read.
-            Err(VolatileMemoryError::OutOfBounds { addr: offset })
-        }
+        read_exact_from(&self.vecs, self.len, buf, offset)
     }
 
     /// Reads up to `len` bytes from the `IoVecBuffer` starting at the given offset.
@@ -134,99 +797,615 @@ impl IoVecBuffer {
     pub fn read_volatile_at<W: WriteVolatile>(
         &self,
         dst: &mut W,
-        mut offset: usize,
-        mut len: usize,
+        offset: usize,
+        len: usize,
     ) -> Result<usize, VolatileMemoryError> {
-        let mut total_bytes_read = 0;
+        read_from(self.range_iter(offset, len), dst)
+    }
 
-        for iov in &self.vecs {
-            if len == 0 {
-                break;
-            }
+    /// Writes exactly `len` bytes of the `IoVecBuffer` starting at `offset` to the given
+    /// [`WriteVolatile`], calling it again after short writes.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once all the bytes are written, and `Err(IoVecError::PartialTransfer)` if `dst`
+    /// stops taking bytes before that, or if the buffer holds less than `len` bytes past `offset`.
+    pub fn read_exact_volatile_to<W: WriteVolatile>(
+        &self,
+        dst: &mut W,
+        offset: usize,
+        len: usize,
+    ) -> Result<(), IoVecError> {
+        let bytes_read = transfer_all(self.range_iter(offset, len), |slice| {
+            dst.write_volatile(&slice)
+        })?;
+        check_transfer(len, bytes_read)
+    }
 
-            if offset >= iov.iov_len {
-                offset -= iov.iov_len;
-                continue;
-            }
+    /// Writes up to `len` bytes of the `IoVecBuffer` starting at `offset` to the given
+    /// [`WriteVolatile`], e.g. a non-blocking socket, calling it again after short writes.
+    ///
+    /// Unlike [`IoVecBuffer::read_volatile_at`], `dst` blocking is not an error: the bytes
+    /// written until then are returned as [`Transferred::Blocked`], so that the caller can write
+    /// the rest later.
+    pub fn try_read_volatile_at<W: WriteVolatile>(
+        &self,
+        dst: &mut W,
+        offset: usize,
+        len: usize,
+    ) -> Result<Transferred, VolatileMemoryError> {
+        try_transfer(self.range_iter(offset, len), |slice| {
+            dst.write_volatile(&slice)
+        })
+    }
 
-            let mut slice =
-                // SAFETY: the constructor IoVecBufferMut::from_descriptor_chain ensures that
-                // all iovecs contained point towards valid ranges of guest memory
-                unsafe { VolatileSlice::new(iov.iov_base.cast(), iov.iov_len).offset(offset)? };
-            offset = 0;
+    /// Writes the whole `IoVecBuffer` to the file `fd` starting at `file_offset`, with
+    /// `pwritev2` and without changing the position of the file.
+    ///
+    /// Interrupted writes are retried and short ones resumed where they stopped, so all the bytes
+    /// are written unless an error occurs.
+    pub fn write_to_file_at(
+        &self,
+        fd: &impl AsRawFd,
+        file_offset: u64,
+    ) -> Result<usize, IoVecError> {
+        transfer_at(&self.vecs, fd.as_raw_fd(), file_offset, libc::pwritev2)
+    }
 
-            if slice.len() > len {
-                slice = slice.subslice(0, len)?;
-            }
+    /// Sends `len` bytes of the `IoVecBuffer` starting at `offset` to the socket `fd` with a single
+    /// `sendmsg`, straight from guest memory.
+    ///
+    /// Fewer than `len` bytes are sent when the socket cannot take all of them, e.g. when it is
+    /// non-blocking, in which case the rest can be sent later by calling this again at the offset
+    /// reached.
+    pub fn send_to_socket(
+        &self,
+        fd: &impl AsRawFd,
+        offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError> {
+        let fd = fd.as_raw_fd();
+        socket_transfer(&self.vecs, offset, len, |msg| {
+            // SAFETY: `msg` only points to `iovec`s of guest memory, see the constructors of
+            // IoVecBuffer, and the return value is checked.
+            unsafe { libc::sendmsg(fd, msg, libc::MSG_NOSIGNAL) }
+        })
+    }
 
-            let bytes_read = loop {
-                match dst.write_volatile(&slice) {
-                    Err(VolatileMemoryError::IOError(err))
-                        if err.kind() == ErrorKind::Interrupted =>
-                    {
-                        continue
-                    }
-                    Ok(bytes_read) => break bytes_read,
-                    Err(volatile_memory_error) => return Err(volatile_memory_error),
-                }
-            };
-            total_bytes_read += bytes_read;
+    /// Reads an object of type `T` from the `IoVecBuffer` starting at the given offset.
+    ///
+    /// The object can straddle any number of the memory regions of the buffer. It is an error for
+    /// the buffer to hold less than `size_of::<T>()` bytes past `offset`.
+    pub fn read_obj<T: ByteValued>(&self, offset: usize) -> Result<T, IoVecError> {
+        let size = std::mem::size_of::<T>();
+        let remaining = (self.len() as usize).saturating_sub(offset);
+        if remaining < size {
+            return Err(IoVecError::ShortBuffer {
+                offset,
+                size,
+                remaining,
+            });
+        }
 
-            if bytes_read < slice.len() {
-                break;
+        let mut obj = T::default();
+        let mut buf = obj.as_mut_slice();
+        let bytes_read = self.read_volatile_at(&mut buf, offset, size)?;
+        if bytes_read < size {
+            return Err(IoVecError::ShortBuffer {
+                offset,
+                size,
+                remaining: bytes_read,
+            });
+        }
+        Ok(obj)
+    }
+
+    /// Computes the internet checksum (RFC 1071) of the `len` bytes of the `IoVecBuffer` starting
+    /// at `offset`, i.e. the ones' complement of the ones' complement sum of their 16-bit words
+    /// in network byte order, an odd last byte being padded with zero.
+    ///
+    /// The words can straddle the memory regions of the buffer, which can have odd lengths. The
+    /// checksum is to be stored in network byte order, see [`IoVecBufferMut::write_checksum_at`].
+    ///
+    /// # Returns
+    ///
+    /// `Err(VolatileMemoryError::OutOfBounds)` if the range goes past the end of the buffer.
+    pub fn compute_checksum(&self, offset: usize, len: usize) -> Result<u16, VolatileMemoryError> {
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= self.len as usize)
+            .ok_or(VolatileMemoryError::OutOfBounds { addr: offset })?;
+
+        let mut sum = 0u64;
+        // First byte of a word whose second byte is in the next chunk
+        let mut pending = None;
+        let mut chunk = [0u8; 512];
+        for slice in IoVecSlices::new(&self.vecs, offset, end - offset) {
+            let mut slice_offset = 0;
+            while slice_offset < slice.len() {
+                let count = chunk.len().min(slice.len() - slice_offset);
+                slice
+                    .subslice(slice_offset, count)?
+                    .copy_to(&mut chunk[..count]);
+                slice_offset += count;
+
+                let mut bytes = &chunk[..count];
+                if let Some(first) = pending.take() {
+                    // `bytes` holds at least one byte.
+                    sum += u64::from(u16::from_be_bytes([first, bytes[0]]));
+                    bytes = &bytes[1..];
+                }
+                let mut words = bytes.chunks_exact(2);
+                for word in &mut words {
+                    sum += u64::from(u16::from_be_bytes([word[0], word[1]]));
+                }
+                pending = words.remainder().first().copied();
             }
-            len -= bytes_read;
+        }
+        if let Some(first) = pending {
+            sum += u64::from(u16::from_be_bytes([first, 0]));
         }
 
-        Ok(total_bytes_read)
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        // The carries were folded back into the low 16 bits.
+        Ok(!u16::try_from(sum).unwrap())
     }
-}
+
+    /// Returns whether the `len` bytes of the `IoVecBuffer` starting at `offset` are all zeroes.
+    ///
+    /// The memory regions are scanned in place, one 64-bit word at a time, and the scan stops at
+    /// the first non-zero word.
+    ///
+    /// # Returns
+    ///
+    /// `Err(VolatileMemoryError::OutOfBounds)` if the range goes past the end of the buffer.
+    pub fn is_zero_range(&self, offset: usize, len: usize) -> Result<bool, VolatileMemoryError> {
+        offset
+            .checked_add(len)
+            .filter(|&end| end <= self.len as usize)
+            .ok_or(VolatileMemoryError::OutOfBounds { addr: offset })?;
+
+        Ok(IoVecSlices::new(&self.vecs, offset, len).all(|slice| is_zero_slice(&slice)))
+    }
+}
+
+// Checks the bytes of `slice` for zeroes, a byte at a time up to the first aligned 64-bit word and
+// after the last one, and a word at a time in between.
+fn is_zero_slice(slice: &VolatileSlice) -> bool {
+    let guard = slice.ptr_guard();
+    let ptr = guard.as_ptr();
+    let len = slice.len();
+    let head = ptr.align_offset(std::mem::align_of::<u64>()).min(len);
+    let words = (len - head) / std::mem::size_of::<u64>();
+    let tail = head + words * std::mem::size_of::<u64>();
+    let is_zero_byte = |i: usize| {
+        // SAFETY: `i < len`, and the `len` bytes at `ptr` are valid guest memory.
+        unsafe { ptr.add(i).read_volatile() == 0 }
+    };
+    let is_zero_word = |i: usize| {
+        // SAFETY: the `words` words at `ptr + head` are aligned and within the `len` bytes of
+        // valid guest memory at `ptr`.
+        unsafe { ptr.add(head).cast::<u64>().add(i).read_volatile() == 0 }
+    };
+    (0..head).all(is_zero_byte) && (0..words).all(is_zero_word) && (tail..len).all(is_zero_byte)
+}
+
+/// The `iovec`s covering a range of an [`IoVecBuffer`], returned by [`IoVecBuffer::sub_range`].
+///
+/// Only the `iovec`s of the range are copied, in a scratch array kept inline for the usual short
+/// ranges, so that the range can be passed to `writev` without rebuilding it by hand.
+#[derive(Debug)]
+pub struct IoVecSubRange<'b> {
+    vecs: IoVecVec,
+    // Buffer the `iovec`s were copied from
+    _buffer: PhantomData<&'b IoVecBuffer<'b>>,
+}
+
+impl IoVecSubRange<'_> {
+    /// Returns the `iovec` array of the range.
+    pub fn as_slice(&self) -> &[iovec] {
+        &self.vecs
+    }
+}
+
+/// The memory regions of an [`IoVecBuffer`] or [`IoVecBufferMut`] handed over to an asynchronous
+/// transfer, e.g. an io_uring `IORING_OP_READV` or `IORING_OP_WRITEV` operation, by
+/// `into_parts`.
+///
+/// The `iovec` array is kept on the heap, so that the pointer returned by
+/// [`IoVecParts::as_iovec_ptr`] stays valid wherever the parts are moved, until they are dropped.
+/// The parts don't borrow the guest memory though: whoever holds them has to keep it mapped until
+/// the transfer completes, and can then turn them back into a buffer with `from_parts`.
+#[derive(Debug)]
+pub struct IoVecParts {
+    vecs: Box<[iovec]>,
+    // Guest address of the first byte of every `iovec`, for an `IoVecBufferMut` backed by guest
+    // memory
+    addrs: GuestAddressVec,
+    len: u32,
+    // Offset of the end of every descriptor chain of an `IoVecBufferMut`
+    chain_ends: ChainEndVec,
+}
+
+// SAFETY: The `iovec`s only point into guest memory, see `IoVecBuffer` and `IoVecBufferMut`, and
+// are never dereferenced through the parts.
+unsafe impl Send for IoVecParts {}
+
+impl IoVecParts {
+    /// Returns a pointer to the `iovec` array, valid until the parts are dropped.
+    pub fn as_iovec_ptr(&self) -> *const iovec {
+        self.vecs.as_ptr()
+    }
+
+    /// Returns the length of the `iovec` array.
+    pub fn iovec_count(&self) -> usize {
+        self.vecs.len()
+    }
+
+    /// Returns the total length of the memory regions.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Whether the parts cover no memory at all.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
 
 /// This is essentially a wrapper of a `Vec<libc::iovec>` which can be passed to `libc::readv`.
 ///
 /// It describes a write-only buffer passed to us by the guest that is scattered across multiple
 /// memory regions. Additionally, this wrapper provides methods that allow reading arbitrary ranges
 /// of data from that buffer.
+///
+/// Like [`IoVecBuffer`], the buffer borrows the guest memory it was created from for `'a`, so it
+/// cannot be kept past the lifetime of the guest memory mappings:
+///
+/// ```compile_fail
+/// use vmm::devices::virtio::iovec::IoVecBufferMut;
+/// use vmm::devices::virtio::queue::DescriptorChain;
+///
+/// fn escape(head: DescriptorChain<'_>) -> IoVecBufferMut<'static> {
+///     IoVecBufferMut::from_descriptor_chain(head).unwrap()
+/// }
+/// ```
+///
+/// Only the bytes actually written through the buffer are marked dirty in the bitmap of the guest
+/// memory, when they are written, so that a diff snapshot does not include the parts of the buffer
+/// left untouched. Handing out the memory regions with [`IoVecBufferMut::iter_mut`] or
+/// [`IoVecBufferMut::range_iter_mut`] marks all of them dirty, as what is written through them is
+/// not known.
 #[derive(Debug)]
-pub struct IoVecBufferMut {
+pub struct IoVecBufferMut<'a> {
     // container of the memory regions included in this IO vector
     vecs: IoVecVec,
+    // Guest address of the first byte of every `iovec`, empty when the buffer is not backed by
+    // guest memory
+    addrs: GuestAddressVec,
     // Total length of the IoVecBufferMut
     len: u32,
+    // Offset of the end of every descriptor chain loaded or appended, in order
+    chain_ends: ChainEndVec,
+    // Guest memory the `iovec`s point into, used to mark the bytes written dirty
+    mem: Option<&'a GuestMemoryMmap>,
 }
 
-impl IoVecBufferMut {
-    /// Create an `IoVecBufferMut` from a `DescriptorChain`
-    pub fn from_descriptor_chain(head: DescriptorChain) -> Result<Self, IoVecError> {
-        let mut vecs = IoVecVec::new();
-        let mut len = 0u32;
+// SAFETY: The `iovec`s only point into the guest memory borrowed for `'a`, which can be accessed
+// from any thread, and are only dereferenced through `&self` or `&mut self`.
+unsafe impl Send for IoVecBufferMut<'_> {}
+
+impl Default for IoVecBufferMut<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> IoVecBufferMut<'a> {
+    /// Create an empty `IoVecBufferMut`, to be filled with `load_descriptor_chain`
+    pub fn new() -> Self {
+        Self {
+            vecs: IoVecVec::new(),
+            addrs: GuestAddressVec::new(),
+            len: 0,
+            chain_ends: ChainEndVec::new(),
+            mem: None,
+        }
+    }
+
+    /// Create an `IoVecBufferMut` from a `DescriptorChain`, with at most [`IOV_MAX`] `iovec`s
+    pub fn from_descriptor_chain(head: DescriptorChain<'a>) -> Result<Self, IoVecError> {
+        Self::from_descriptor_chain_with_limit(head, IOV_MAX)
+    }
+
+    /// Create an `IoVecBufferMut` from a `DescriptorChain`, with at most `max_iovecs` `iovec`s
+    pub fn from_descriptor_chain_with_limit(
+        head: DescriptorChain<'a>,
+        max_iovecs: usize,
+    ) -> Result<Self, IoVecError> {
+        let mut buffer = Self::new();
+        buffer.load_descriptor_chain_with_limit(head, max_iovecs)?;
+        Ok(buffer)
+    }
+
+    /// Create an `IoVecBufferMut` covering at most the first `max_len` bytes of a
+    /// `DescriptorChain`, with at most [`IOV_MAX`] `iovec`s. The last descriptor covered is
+    /// trimmed if needed, and the ones past it are neither walked nor validated.
+    ///
+    /// Also returns whether the chain was covered entirely, or truncated at `max_len` bytes.
+    pub fn from_descriptor_chain_capped(
+        head: DescriptorChain<'a>,
+        max_len: usize,
+    ) -> Result<(Self, ChainFit), IoVecError> {
+        let max_len = u32::try_from(max_len).unwrap_or(u32::MAX);
+        let mut buffer = Self::new();
+        let fit = buffer.load_descriptors(head, IOV_MAX, Some(max_len))?;
+        buffer.chain_ends.push(buffer.len);
+        Ok((buffer, fit))
+    }
+
+    /// Create an `IoVecBufferMut` covering the `len` bytes of guest memory at `addr`, which have
+    /// to be in a single guest memory region. Like for a `DescriptorChain`, the memory is marked
+    /// dirty as it is written.
+    pub fn from_guest_memory(
+        mem: &'a GuestMemoryMmap,
+        addr: GuestAddress,
+        len: u32,
+    ) -> Result<Self, IoVecError> {
+        let slice = mem.get_slice(addr, len as usize)?;
+
+        #[cfg(feature = "memory-guards")]
+        crate::devices::virtio::canary::record_write(mem, addr, len);
+
+        let mut buffer = Self::new();
+        buffer.vecs.push(iovec {
+            iov_base: slice.ptr_guard_mut().as_ptr().cast::<c_void>(),
+            iov_len: len as size_t,
+        });
+        buffer.addrs.push(addr);
+        buffer.len = len;
+        buffer.mem = Some(mem);
+        Ok(buffer)
+    }
+
+    /// Replaces the memory regions of the `IoVecBufferMut` with the ones of a `DescriptorChain`.
+    ///
+    /// The storage of the `iovec`s is reused, so that loading chains no longer than the ones
+    /// loaded before does not allocate. On error, the `IoVecBufferMut` is left empty.
+    ///
+    /// Descriptors contiguous in a guest memory region share an `iovec`, and chains still needing
+    /// more than [`IOV_MAX`] `iovec`s are rejected. Zero-length descriptors are skipped.
+    pub fn load_descriptor_chain(&mut self, head: DescriptorChain<'a>) -> Result<(), IoVecError> {
+        self.load_descriptor_chain_with_limit(head, IOV_MAX)
+    }
+
+    /// Same as `load_descriptor_chain`, rejecting chains needing more than `max_iovecs`
+    /// `iovec`s, for devices with stricter limits than [`IOV_MAX`].
+    pub fn load_descriptor_chain_with_limit(
+        &mut self,
+        head: DescriptorChain<'a>,
+        max_iovecs: usize,
+    ) -> Result<(), IoVecError> {
+        self.clear();
+        let result = self.load_descriptors(head, max_iovecs, None).map(|_| ());
+        match result {
+            Ok(()) => self.chain_ends.push(self.len),
+            Err(_) => self.clear(),
+        }
+        result
+    }
+
+    /// Same as `from_descriptor_chain_capped`, reusing the storage of the `iovec`s like
+    /// `load_descriptor_chain`. On error, the `IoVecBufferMut` is left empty.
+    pub fn load_descriptor_chain_capped(
+        &mut self,
+        head: DescriptorChain<'a>,
+        max_len: usize,
+    ) -> Result<ChainFit, IoVecError> {
+        let max_len = u32::try_from(max_len).unwrap_or(u32::MAX);
+        self.clear();
+        let result = self.load_descriptors(head, IOV_MAX, Some(max_len));
+        match result {
+            Ok(_) => self.chain_ends.push(self.len),
+            Err(_) => self.clear(),
+        }
+        result
+    }
+
+    /// Appends the memory regions of a `DescriptorChain` to the ones of the `IoVecBufferMut`,
+    /// e.g. to spread a network frame over several chains. The chains are written as a single
+    /// buffer, and [`IoVecBufferMut::chain_used_lens`] tells how many of the bytes written went
+    /// to each of them.
+    ///
+    /// Returns the number of bytes added. On error, the `IoVecBufferMut` is left as it was.
+    pub fn append_descriptor_chain(
+        &mut self,
+        head: DescriptorChain<'a>,
+    ) -> Result<usize, IoVecError> {
+        let (vecs_len, addrs_len, len) = (self.vecs.len(), self.addrs.len(), self.len);
+        if let Err(err) = self.load_descriptors(head, IOV_MAX, None) {
+            self.vecs.truncate(vecs_len);
+            self.addrs.truncate(addrs_len);
+            self.len = len;
+            return Err(err);
+        }
+        self.chain_ends.push(self.len);
+        Ok((self.len - len) as usize)
+    }
+
+    /// Returns the number of descriptor chains loaded in the `IoVecBufferMut`.
+    pub fn chain_count(&self) -> usize {
+        self.chain_ends.len()
+    }
+
+    /// Returns the offset of the end of every descriptor chain in the `IoVecBufferMut`, in the
+    /// order they were loaded and appended.
+    pub fn chain_ends(&self) -> &[u32] {
+        &self.chain_ends
+    }
+
+    /// Returns how many of the first `bytes_written` bytes of the `IoVecBufferMut` each of its
+    /// descriptor chains holds, for the used ring. Only the chains holding some of the bytes
+    /// are returned, their count being the number of buffers used.
+    pub fn chain_used_lens(&self, bytes_written: u32) -> impl Iterator<Item = u32> + '_ {
+        let chain_starts = std::iter::once(0).chain(self.chain_ends.iter().copied());
+        chain_starts
+            .zip(self.chain_ends.iter())
+            .take_while(move |&(start, _)| start < bytes_written)
+            .map(move |(start, &end)| end.min(bytes_written) - start)
+    }
+
+    /// Checks that none of the memory regions of the `IoVecBufferMut` overlaps the descriptor
+    /// table or the rings of the queue its descriptor chains come from, as writing to it would
+    /// change the queue under the feet of the device.
+    pub fn check_queue_overlap(&self, rings: &QueueRings) -> Result<(), IoVecError> {
+        match self
+            .addrs
+            .iter()
+            .zip(self.vecs.iter())
+            .find(|(addr, iov)| rings.overlaps(**addr, iov.iov_len))
+        {
+            Some((addr, _)) => Err(IoVecError::OverlapsQueue(addr.raw_value())),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes all the memory regions of the `IoVecBufferMut`, keeping the capacity of the
+    /// storage of the `iovec`s.
+    pub fn clear(&mut self) {
+        self.vecs.clear();
+        self.addrs.clear();
+        self.chain_ends.clear();
+        self.len = 0;
+    }
+
+    /// Empties the `IoVecBufferMut` and releases its borrow of the guest memory, keeping the
+    /// capacity of the storage of the `iovec`s.
+    pub fn recycle<'b>(mut self) -> IoVecBufferMut<'b> {
+        self.clear();
+        IoVecBufferMut {
+            vecs: self.vecs,
+            addrs: self.addrs,
+            len: 0,
+            chain_ends: self.chain_ends,
+            mem: None,
+        }
+    }
+
+    /// Hands the memory regions of the `IoVecBufferMut` over to an asynchronous transfer, e.g.
+    /// an io_uring `IORING_OP_READV` operation writing to them until it completes.
+    ///
+    /// Nothing is marked dirty: the buffer given back by [`IoVecBufferMut::from_parts`] once the
+    /// transfer completed marks the bytes written with [`IoVecBufferMut::mark_written`].
+    pub fn into_parts(self) -> IoVecParts {
+        IoVecParts {
+            vecs: Box::from(&self.vecs[..]),
+            // The addresses are only used with the guest memory, which isn't kept.
+            addrs: match self.mem {
+                Some(_) => self.addrs,
+                None => GuestAddressVec::new(),
+            },
+            len: self.len,
+            chain_ends: self.chain_ends,
+        }
+    }
+
+    /// Turns the `parts` of an `IoVecBufferMut` back into a buffer, e.g. once the transfer they
+    /// were handed over to completed. The bytes written through it are marked dirty in `mem`,
+    /// if the buffer was backed by guest memory.
+    ///
+    /// # Safety
+    ///
+    /// `mem` has to be the guest memory the parts were taken from, or share its mappings, so
+    /// that the buffer only points into memory borrowed for `'a`.
+    pub unsafe fn from_parts(mem: &'a GuestMemoryMmap, parts: IoVecParts) -> Self {
+        Self {
+            vecs: parts.vecs.iter().copied().collect(),
+            mem: (!parts.addrs.is_empty()).then_some(mem),
+            addrs: parts.addrs,
+            len: parts.len,
+            chain_ends: parts.chain_ends,
+        }
+    }
+
+    /// Marks the first `len` bytes of the `IoVecBufferMut` dirty, once they were written without
+    /// going through the buffer, e.g. by the kernel for an io_uring read into its parts.
+    pub fn mark_written(&self, len: usize) {
+        self.mark_dirty(0, len);
+    }
 
-        for desc in head {
+    // Walks the chain up to `max_len` bytes, if given.
+    fn load_descriptors(
+        &mut self,
+        head: DescriptorChain<'a>,
+        max_iovecs: usize,
+        max_len: Option<u32>,
+    ) -> Result<ChainFit, IoVecError> {
+        let mut last_region = None;
+        let mem_size = guest_memory_size(head.mem);
+        self.mem = Some(head.mem);
+        for desc in head.checked_iter() {
+            if max_len == Some(self.len) {
+                return Ok(ChainFit::Truncated);
+            }
+            let desc = desc?;
             if !desc.is_write_only() {
                 return Err(IoVecError::ReadOnlyDescriptor);
             }
 
-            // We use get_slice instead of `get_host_address` here in order to have the whole
-            // range of the descriptor chain checked, i.e. [addr, addr + len) is a valid memory
-            // region in the GuestMemoryMmap.
-            let slice = desc.mem.get_slice(desc.addr, desc.len as usize)?;
+            let len = max_len.map_or(desc.len, |max_len| desc.len.min(max_len - self.len));
+            self.push_descriptor(&desc, len, mem_size, &mut last_region, max_iovecs)?;
+            if len < desc.len {
+                return Ok(ChainFit::Truncated);
+            }
+        }
 
-            // We need to mark the area of guest memory that will be mutated through this
-            // IoVecBufferMut as dirty ahead of time, as we loose access to all
-            // vm-memory related information after converting down to iovecs.
-            slice.bitmap().mark_dirty(0, desc.len as usize);
+        Ok(ChainFit::Whole)
+    }
 
-            let iov_base = slice.ptr_guard_mut().as_ptr().cast::<c_void>();
-            vecs.push(iovec {
-                iov_base,
-                iov_len: desc.len as size_t,
-            });
-            len = len
-                .checked_add(desc.len)
-                .ok_or(IoVecError::OverflowedDescriptor)?;
+    // Appends the first `len` bytes of `desc` to the buffer, of at most `mem_size` bytes.
+    // `last_region` holds the guest memory region of the previous descriptor, and is updated to
+    // the one of `desc`.
+    fn push_descriptor(
+        &mut self,
+        desc: &DescriptorChain<'a>,
+        len: u32,
+        mem_size: u64,
+        last_region: &mut Option<GuestAddress>,
+        max_iovecs: usize,
+    ) -> Result<(), IoVecError> {
+        if len == 0 {
+            skip_empty_descriptor(desc);
+            return Ok(());
         }
 
-        Ok(Self { vecs, len })
+        // We use get_slice instead of `get_host_address` here in order to have the whole
+        // range of the descriptor chain checked, i.e. [addr, addr + len) is a valid memory
+        // region in the GuestMemoryMmap.
+        let slice = desc.mem.get_slice(desc.addr, len as usize)?;
+
+        #[cfg(feature = "memory-guards")]
+        crate::devices::virtio::canary::record_write(desc.mem, desc.addr, len);
+
+        let iov_base = slice.ptr_guard_mut().as_ptr().cast::<c_void>();
+        let region = desc.mem.find_region(desc.addr).map(|r| r.start_addr());
+        push_iovec(
+            &mut self.vecs,
+            iov_base,
+            len as size_t,
+            region == *last_region,
+            max_iovecs,
+        )?;
+        // Descriptors merged into the last `iovec` are contiguous in guest memory too, so
+        // only the address of the first one is kept.
+        if self.addrs.len() < self.vecs.len() {
+            self.addrs.push(desc.addr);
+        }
+        *last_region = region;
+        self.len = add_descriptor_len(self.len, len, mem_size)?;
+        Ok(())
     }
 
     /// Get the total length of the memory regions covered by this `IoVecBuffer`
@@ -234,6 +1413,177 @@ impl IoVecBufferMut {
         self.len
     }
 
+    /// Returns an iterator over the memory regions of the `IoVecBufferMut`, to be written to.
+    ///
+    /// All of them are marked dirty.
+    pub fn iter_mut(&mut self) -> IoVecSlices<'_> {
+        self.range_iter_mut(0, self.len as usize)
+    }
+
+    /// Returns an iterator over the memory regions covering the `len` bytes of the
+    /// `IoVecBufferMut` starting at `offset`, the first and last ones being trimmed to the range.
+    /// Bytes past the end of the `IoVecBufferMut` are ignored.
+    ///
+    /// All of them are marked dirty.
+    pub fn range_iter_mut(&mut self, offset: usize, len: usize) -> IoVecSlices<'_> {
+        self.mark_dirty(offset, len);
+        IoVecSlices::new(&self.vecs, offset, len)
+    }
+
+    // Marks the `len` bytes of the `IoVecBufferMut` starting at `offset` dirty, in the bitmaps of
+    // the guest memory regions they are in. Bytes past the end of the buffer are ignored.
+    fn mark_dirty(&self, mut offset: usize, mut len: usize) {
+        let Some(mem) = self.mem else {
+            return;
+        };
+        for (iov, addr) in self.vecs.iter().zip(&self.addrs) {
+            if len == 0 {
+                break;
+            }
+            if offset >= iov.iov_len {
+                offset -= iov.iov_len;
+                continue;
+            }
+            let count = len.min(iov.iov_len - offset);
+            // An `iovec` never spans guest memory regions, see `push_iovec`.
+            let region_addr = u64::try_from(offset)
+                .ok()
+                .and_then(|offset| addr.checked_add(offset))
+                .and_then(|addr| mem.to_region_addr(addr));
+            if let Some((region, region_addr)) = region_addr {
+                region
+                    .bitmap()
+                    .mark_dirty(u64_to_usize(region_addr.raw_value()), count);
+            }
+            offset = 0;
+            len -= count;
+        }
+    }
+
+    /// Reads back a number of bytes from the `IoVecBufferMut` starting at a given offset, e.g.
+    /// bytes written to it before.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if `buf` was filled by reading from this [`IoVecBufferMut`],
+    /// `Err(VolatileMemoryError::PartialBuffer)` if only part of `buf` could not be filled, and
+    /// `Err(VolatileMemoryError::OutOfBounds)` if `offset >= self.len()`.
+    pub fn read_exact_volatile_at(
+        &self,
+        buf: &mut [u8],
+        offset: usize,
+    ) -> Result<(), VolatileMemoryError> {
+        read_exact_from(&self.vecs, self.len, buf, offset)
+    }
+
+    /// Reads back up to `len` bytes from the `IoVecBufferMut` starting at the given offset.
+    ///
+    /// This will try to write to the given [`WriteVolatile`].
+    pub fn read_volatile_at<W: WriteVolatile>(
+        &self,
+        dst: &mut W,
+        offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError> {
+        read_from(IoVecSlices::new(&self.vecs, offset, len), dst)
+    }
+
+    /// Writes exactly `len` bytes of the `IoVecBufferMut` starting at `offset` back to the given
+    /// [`WriteVolatile`], calling it again after short writes.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once all the bytes are written, and `Err(IoVecError::PartialTransfer)` if `dst`
+    /// stops taking bytes before that, or if the buffer holds less than `len` bytes past `offset`.
+    pub fn read_exact_volatile_to<W: WriteVolatile>(
+        &self,
+        dst: &mut W,
+        offset: usize,
+        len: usize,
+    ) -> Result<(), IoVecError> {
+        let bytes_read = transfer_all(IoVecSlices::new(&self.vecs, offset, len), |slice| {
+            dst.write_volatile(&slice)
+        })?;
+        check_transfer(len, bytes_read)
+    }
+
+    /// Writes back up to `len` bytes of the `IoVecBufferMut` starting at `offset` to the given
+    /// [`WriteVolatile`], like [`IoVecBuffer::try_read_volatile_at`].
+    pub fn try_read_volatile_at<W: WriteVolatile>(
+        &self,
+        dst: &mut W,
+        offset: usize,
+        len: usize,
+    ) -> Result<Transferred, VolatileMemoryError> {
+        try_transfer(IoVecSlices::new(&self.vecs, offset, len), |slice| {
+            dst.write_volatile(&slice)
+        })
+    }
+
+    /// Fills the `IoVecBufferMut` from the file `fd` starting at `file_offset`, with `preadv2`
+    /// and without changing the position of the file.
+    ///
+    /// Interrupted reads are retried and short ones resumed where they stopped, so fewer bytes
+    /// than `self.len()` are only read when the end of the file is reached.
+    pub fn read_from_file_at(
+        &mut self,
+        fd: &impl AsRawFd,
+        file_offset: u64,
+    ) -> Result<usize, IoVecError> {
+        let result = transfer_at(&self.vecs, fd.as_raw_fd(), file_offset, libc::preadv2);
+        // How many bytes were read before a failed call is not known, so all of them might be.
+        let bytes_read = result.as_ref().map_or(self.len as usize, |&bytes| bytes);
+        self.mark_dirty(0, bytes_read);
+        result
+    }
+
+    /// Receives up to `len` bytes from the socket `fd` into the `IoVecBufferMut` starting at
+    /// `offset`, with a single `recvmsg` writing straight to guest memory.
+    ///
+    /// Returns 0 when the peer closed the socket, or when there is no room for any byte past
+    /// `offset`.
+    pub fn recv_from_socket(
+        &mut self,
+        fd: &impl AsRawFd,
+        offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError> {
+        let fd = fd.as_raw_fd();
+        let bytes_received = socket_transfer(&self.vecs, offset, len, |msg| {
+            // SAFETY: `msg` only points to `iovec`s of guest memory, see the constructors of
+            // IoVecBufferMut, and the return value is checked.
+            unsafe { libc::recvmsg(fd, msg, 0) }
+        })?;
+        self.mark_dirty(offset, bytes_received);
+        Ok(bytes_received)
+    }
+
+    /// Reads from `fd` into the `IoVecBufferMut` with a single `readv` writing straight to guest
+    /// memory, e.g. a whole frame from a tap. Interrupted reads are retried.
+    ///
+    /// Only the bytes read are marked dirty.
+    pub fn read_from_fd(&mut self, fd: &impl AsRawFd) -> std::io::Result<usize> {
+        // There are at most `IOV_MAX` `iovec`s, see the constructors of IoVecBufferMut.
+        let iovcnt = i32::try_from(self.vecs.len()).unwrap();
+        loop {
+            // SAFETY: the `iovec`s only point to guest memory, see the constructors of
+            // IoVecBufferMut, and the return value is checked.
+            let ret = unsafe { libc::readv(fd.as_raw_fd(), self.vecs.as_ptr(), iovcnt) };
+            match usize::try_from(ret) {
+                Ok(bytes_read) => {
+                    self.mark_dirty(0, bytes_read);
+                    return Ok(bytes_read);
+                }
+                Err(_) => {
+                    let err = std::io::Error::last_os_error();
+                    if err.kind() != ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+
     /// Writes a number of bytes into the `IoVecBufferMut` starting at a given offset.
     ///
     /// This will try to fill `IoVecBufferMut` writing bytes from the `buf` starting from
@@ -274,31 +1624,12 @@ impl IoVecBufferMut {
     pub fn write_volatile_at<W: ReadVolatile>(
         &mut self,
         src: &mut W,
-        mut offset: usize,
-        mut len: usize,
+        offset: usize,
+        len: usize,
     ) -> Result<usize, VolatileMemoryError> {
         let mut total_bytes_read = 0;
 
-        for iov in &self.vecs {
-            if len == 0 {
-                break;
-            }
-
-            if offset >= iov.iov_len {
-                offset -= iov.iov_len;
-                continue;
-            }
-
-            let mut slice =
-                // SAFETY: the constructor IoVecBufferMut::from_descriptor_chain ensures that
-                // all iovecs contained point towards valid ranges of guest memory
-                unsafe { VolatileSlice::new(iov.iov_base.cast(), iov.iov_len).offset(offset)? };
-            offset = 0;
-
-            if slice.len() > len {
-                slice = slice.subslice(0, len)?;
-            }
-
+        for mut slice in IoVecSlices::new(&self.vecs, offset, len) {
             let bytes_read = loop {
                 match src.read_volatile(&mut slice) {
                     Err(VolatileMemoryError::IOError(err))
@@ -310,69 +1641,329 @@ impl IoVecBufferMut {
                     Err(volatile_memory_error) => return Err(volatile_memory_error),
                 }
             };
+            self.mark_dirty(offset + total_bytes_read, bytes_read);
             total_bytes_read += bytes_read;
 
             if bytes_read < slice.len() {
                 break;
             }
-            len -= bytes_read;
         }
 
         Ok(total_bytes_read)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use libc::{c_void, iovec};
-    use vm_memory::VolatileMemoryError;
+    /// Fills up to `len` bytes of the `IoVecBufferMut` starting at `offset` from the given
+    /// [`ReadVolatile`], e.g. a non-blocking socket, calling it again after short reads.
+    ///
+    /// Unlike [`IoVecBufferMut::write_volatile_at`], `src` blocking is not an error: the bytes
+    /// read until then are returned as [`Transferred::Blocked`], so that the caller can read the
+    /// rest later. Only the bytes actually written are marked dirty.
+    pub fn try_write_volatile_at<R: ReadVolatile>(
+        &mut self,
+        src: &mut R,
+        offset: usize,
+        len: usize,
+    ) -> Result<Transferred, VolatileMemoryError> {
+        let mut bytes_written = 0;
+        try_transfer(IoVecSlices::new(&self.vecs, offset, len), |mut slice| {
+            let bytes = src.read_volatile(&mut slice)?;
+            self.mark_dirty(offset + bytes_written, bytes);
+            bytes_written += bytes;
+            Ok(bytes)
+        })
+    }
 
-    use super::{IoVecBuffer, IoVecBufferMut};
-    use crate::devices::virtio::queue::{Queue, VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
-    use crate::devices::virtio::test_utils::VirtQueue;
-    use crate::utilities::test_utils::multi_region_mem;
-    use crate::vstate::memory::{Bytes, GuestAddress, GuestMemoryMmap};
+    /// Fills exactly `len` bytes of the `IoVecBufferMut` starting at `offset` from the given
+    /// [`ReadVolatile`], calling it again after short reads.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once all the bytes are written, and `Err(IoVecError::PartialTransfer)` if `src`
+    /// reaches its end before that, or if the buffer holds less than `len` bytes past `offset`.
+    /// Only the bytes actually written are marked dirty, in both cases.
+    pub fn write_all_volatile_from<R: ReadVolatile>(
+        &mut self,
+        src: &mut R,
+        offset: usize,
+        len: usize,
+    ) -> Result<(), IoVecError> {
+        let mut bytes_written = 0;
+        let result = transfer_all(IoVecSlices::new(&self.vecs, offset, len), |mut slice| {
+            let bytes = src.read_volatile(&mut slice)?;
+            self.mark_dirty(offset + bytes_written, bytes);
+            bytes_written += bytes;
+            Ok(bytes)
+        });
+        check_transfer(len, result?)
+    }
 
-    impl<'a> From<&'a [u8]> for IoVecBuffer {
-        fn from(buf: &'a [u8]) -> Self {
-            Self {
-                vecs: vec![iovec {
-                    iov_base: buf.as_ptr() as *mut c_void,
-                    iov_len: buf.len(),
-                }]
-                .into(),
-                len: buf.len().try_into().unwrap(),
-            }
+    /// Fills up to `len` bytes of the `IoVecBufferMut` starting at `offset` with `byte`, directly
+    /// in every memory region covered, without going through an intermediate buffer.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes filled, which is less than `len` when the range goes past the end of
+    /// the buffer, and 0 when `offset` is past the end of the buffer.
+    pub fn fill(
+        &mut self,
+        byte: u8,
+        offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError> {
+        let mut total_bytes_filled = 0;
+
+        for slice in IoVecSlices::new(&self.vecs, offset, len) {
+            // SAFETY: the constructors of IoVecBufferMut ensure that all iovecs contained point
+            // towards valid ranges of guest memory, which `slice` is within.
+            unsafe { std::ptr::write_bytes(slice.ptr_guard_mut().as_ptr(), byte, slice.len()) };
+            self.mark_dirty(offset + total_bytes_filled, slice.len());
+            total_bytes_filled += slice.len();
         }
+
+        Ok(total_bytes_filled)
     }
 
-    impl<'a> From<Vec<&'a [u8]>> for IoVecBuffer {
-        fn from(buffer: Vec<&'a [u8]>) -> Self {
-            let mut len = 0_u32;
-            let vecs = buffer
-                .into_iter()
-                .map(|slice| {
-                    len += TryInto::<u32>::try_into(slice.len()).unwrap();
-                    iovec {
-                        iov_base: slice.as_ptr() as *mut c_void,
-                        iov_len: slice.len(),
-                    }
-                })
-                .collect();
+    /// Copies up to `len` bytes from `src` starting at `src_offset`, into the `IoVecBufferMut`
+    /// starting at `dst_offset`.
+    ///
+    /// Both buffers are walked together, every step copying the largest range which is contiguous
+    /// in both of them, so the data is not bounced through an intermediate buffer.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes copied, which is less than `len` when either buffer runs out, and 0 when
+    /// either offset is past the end of its buffer.
+    pub fn copy_from(
+        &mut self,
+        src: &IoVecBuffer,
+        src_offset: usize,
+        dst_offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError> {
+        let mut src_cursor = IoVecCursor::new(&src.vecs, src_offset);
+        let mut dst_cursor = IoVecCursor::new(&self.vecs, dst_offset);
+        let mut total_bytes_copied = 0;
+
+        while total_bytes_copied < len {
+            let (Some(src_slice), Some(dst_slice)) = (src_cursor.current(), dst_cursor.current())
+            else {
+                break;
+            };
+            let count = src_slice
+                .len()
+                .min(dst_slice.len())
+                .min(len - total_bytes_copied);
+            src_slice
+                .subslice(0, count)?
+                .copy_to_volatile_slice(dst_slice.subslice(0, count)?);
+            self.mark_dirty(dst_offset + total_bytes_copied, count);
 
-            Self { vecs, len }
+            src_cursor.advance(count);
+            dst_cursor.advance(count);
+            total_bytes_copied += count;
         }
+
+        Ok(total_bytes_copied)
     }
 
-    impl From<&mut [u8]> for IoVecBufferMut {
-        fn from(buf: &mut [u8]) -> Self {
-            Self {
-                vecs: vec![iovec {
+    /// Writes an object of type `T` into the `IoVecBufferMut` starting at the given offset.
+    ///
+    /// The object can straddle any number of the memory regions of the buffer. Nothing is written
+    /// if the buffer holds less than `size_of::<T>()` bytes past `offset`. Only the guest memory
+    /// behind the bytes of the object is marked dirty.
+    pub fn write_obj<T: ByteValued>(&mut self, obj: &T, offset: usize) -> Result<(), IoVecError> {
+        let size = std::mem::size_of::<T>();
+        let remaining = (self.len() as usize).saturating_sub(offset);
+        if remaining < size {
+            return Err(IoVecError::TruncatedWrite {
+                offset,
+                size,
+                remaining,
+            });
+        }
+
+        let mut buf = obj.as_slice();
+        let bytes_written = self.write_volatile_at(&mut buf, offset, size)?;
+        if bytes_written < size {
+            return Err(IoVecError::TruncatedWrite {
+                offset,
+                size,
+                remaining: bytes_written,
+            });
+        }
+        Ok(())
+    }
+
+    /// Writes a checksum computed by [`IoVecBuffer::compute_checksum`] into the `IoVecBufferMut`
+    /// at the given offset, in network byte order. Its two bytes can be in different memory
+    /// regions of the buffer.
+    pub fn write_checksum_at(
+        &mut self,
+        checksum: u16,
+        offset: usize,
+    ) -> Result<(), VolatileMemoryError> {
+        self.write_all_volatile_at(&checksum.to_be_bytes(), offset)
+    }
+}
+
+/// The two buffers of a descriptor chain made of device-readable descriptors followed by
+/// device-writable ones, e.g. a request followed by room for its response.
+#[derive(Debug)]
+pub struct DescriptorChainBuffers<'a> {
+    /// Buffer over the device-readable descriptors at the start of the chain.
+    pub readable: IoVecBuffer<'a>,
+    /// Buffer over the device-writable descriptors at the end of the chain.
+    pub writable: IoVecBufferMut<'a>,
+}
+
+impl<'a> DescriptorChainBuffers<'a> {
+    /// Splits a `DescriptorChain` into a buffer over its readable descriptors and a buffer over
+    /// its writable ones, walking it once. Each buffer has at most [`IOV_MAX`] `iovec`s, and is
+    /// empty when the chain has no descriptor of its kind.
+    ///
+    /// As required by the virtio specification, chains with a readable descriptor after a
+    /// writable one are rejected.
+    pub fn from_descriptor_chain(head: DescriptorChain<'a>) -> Result<Self, IoVecError> {
+        let mem_size = guest_memory_size(head.mem);
+        let mut readable = IoVecBuffer::new();
+        let mut writable = IoVecBufferMut::new();
+        writable.mem = Some(head.mem);
+        let mut readable_region = None;
+        let mut writable_region = None;
+        let mut seen_writable = false;
+
+        for desc in head.checked_iter() {
+            let desc = desc?;
+            if desc.is_write_only() {
+                seen_writable = true;
+                writable.push_descriptor(
+                    &desc,
+                    desc.len,
+                    mem_size,
+                    &mut writable_region,
+                    IOV_MAX,
+                )?;
+            } else if seen_writable {
+                return Err(IoVecError::ReadableAfterWritable);
+            } else {
+                readable.push_descriptor(
+                    &desc,
+                    desc.len,
+                    mem_size,
+                    &mut readable_region,
+                    IOV_MAX,
+                )?;
+            }
+        }
+
+        if seen_writable {
+            writable.chain_ends.push(writable.len);
+        }
+        Ok(Self { readable, writable })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{ErrorKind, Read, Seek, Write};
+    use std::marker::PhantomData;
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::os::unix::net::UnixStream;
+
+    use libc::{c_void, iovec};
+    use utils::tempfile::TempFile;
+    use vm_memory::bitmap::BitmapSlice;
+    use vm_memory::{ReadVolatile, VolatileMemoryError, VolatileSlice, WriteVolatile};
+
+    use super::{
+        skip_bytes, ChainEndVec, ChainFit, DescriptorChainBuffers, GuestAddressVec, IoVecBuffer,
+        IoVecBufferMut, IoVecError, IoVecVec, Transferred, IOV_MAX,
+    };
+    use crate::devices::virtio::gen::virtio_net::virtio_net_hdr_v1;
+    use crate::devices::virtio::queue::{ChainError, Queue, VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+    use crate::devices::virtio::test_utils::VirtQueue;
+    use crate::utilities::test_utils::multi_region_mem;
+    use crate::vmm_config::machine_config::HugePageConfig;
+    use crate::vstate::memory::{
+        Address, Bitmap, ByteValued, Bytes, GuestAddress, GuestMemory, GuestMemoryExtension,
+        GuestMemoryMmap, GuestRegionMmap, MmapRegionBuilder,
+    };
+    use crate::DirtyBitmap;
+
+    impl<'a> From<&'a [u8]> for IoVecBuffer<'a> {
+        fn from(buf: &'a [u8]) -> Self {
+            Self {
+                vecs: vec![iovec {
+                    iov_base: buf.as_ptr() as *mut c_void,
+                    iov_len: buf.len(),
+                }]
+                .into(),
+                len: buf.len().try_into().unwrap(),
+                _mem: PhantomData,
+            }
+        }
+    }
+
+    impl<'a> From<Vec<&'a [u8]>> for IoVecBuffer<'a> {
+        fn from(buffer: Vec<&'a [u8]>) -> Self {
+            let mut len = 0_u32;
+            let vecs = buffer
+                .into_iter()
+                .map(|slice| {
+                    len += TryInto::<u32>::try_into(slice.len()).unwrap();
+                    iovec {
+                        iov_base: slice.as_ptr() as *mut c_void,
+                        iov_len: slice.len(),
+                    }
+                })
+                .collect();
+
+            Self {
+                vecs,
+                len,
+                _mem: PhantomData,
+            }
+        }
+    }
+
+    impl<'a> From<&'a mut [u8]> for IoVecBufferMut<'a> {
+        fn from(buf: &'a mut [u8]) -> Self {
+            Self {
+                vecs: vec![iovec {
                     iov_base: buf.as_mut_ptr().cast::<c_void>(),
                     iov_len: buf.len(),
                 }]
                 .into(),
+                addrs: GuestAddressVec::new(),
                 len: buf.len().try_into().unwrap(),
+                chain_ends: ChainEndVec::new(),
+                mem: None,
+            }
+        }
+    }
+
+    impl<'a> From<Vec<&'a mut [u8]>> for IoVecBufferMut<'a> {
+        fn from(buffer: Vec<&'a mut [u8]>) -> Self {
+            let mut len = 0_u32;
+            let vecs = buffer
+                .into_iter()
+                .map(|slice| {
+                    len += TryInto::<u32>::try_into(slice.len()).unwrap();
+                    iovec {
+                        iov_base: slice.as_mut_ptr().cast::<c_void>(),
+                        iov_len: slice.len(),
+                    }
+                })
+                .collect();
+
+            Self {
+                vecs,
+                addrs: GuestAddressVec::new(),
+                len,
+                chain_ends: ChainEndVec::new(),
+                mem: None,
             }
         }
     }
@@ -409,213 +2000,1994 @@ mod tests {
         (q, vq)
     }
 
-    fn read_only_chain(mem: &GuestMemoryMmap) -> (Queue, VirtQueue) {
-        let v: Vec<u8> = (0..=255).collect();
-        mem.write_slice(&v, GuestAddress(0x20000)).unwrap();
+    fn read_only_chain(mem: &GuestMemoryMmap) -> (Queue, VirtQueue) {
+        let v: Vec<u8> = (0..=255).collect();
+        mem.write_slice(&v, GuestAddress(0x20000)).unwrap();
+
+        chain(mem, false)
+    }
+
+    fn write_only_chain(mem: &GuestMemoryMmap) -> (Queue, VirtQueue) {
+        let v = vec![0; 256];
+        mem.write_slice(&v, GuestAddress(0x20000)).unwrap();
+
+        chain(mem, true)
+    }
+
+    #[test]
+    fn test_access_mode() {
+        let mem = default_mem();
+        let (mut q, _) = read_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+        IoVecBuffer::from_descriptor_chain(head).unwrap();
+
+        let (mut q, _) = write_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+        IoVecBuffer::from_descriptor_chain(head).unwrap_err();
+
+        let (mut q, _) = read_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+        IoVecBufferMut::from_descriptor_chain(head).unwrap_err();
+
+        let (mut q, _) = write_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+        IoVecBufferMut::from_descriptor_chain(head).unwrap();
+    }
+
+    #[test]
+    fn test_iovec_length() {
+        let mem = default_mem();
+        let (mut q, _) = read_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+
+        let iovec = IoVecBuffer::from_descriptor_chain(head).unwrap();
+        assert_eq!(iovec.len(), 4 * 64);
+    }
+
+    #[test]
+    fn test_iovec_mut_length() {
+        let mem = default_mem();
+        let (mut q, _) = write_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+
+        let iovec = IoVecBufferMut::from_descriptor_chain(head).unwrap();
+        assert_eq!(iovec.len(), 4 * 64);
+    }
+
+    // Makes `num_chains` chains of `chain_len` descriptors available, all made of the same
+    // descriptors. The descriptors are not contiguous, so that each one needs its own iovec.
+    fn repeated_chain(
+        m: &GuestMemoryMmap,
+        chain_len: u16,
+        num_chains: u16,
+        is_write_only: bool,
+    ) -> (Queue, VirtQueue) {
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+
+        let mut q = vq.create_queue();
+        q.ready = true;
+
+        let flags = if is_write_only {
+            VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE
+        } else {
+            VIRTQ_DESC_F_NEXT
+        };
+
+        for j in 0..chain_len {
+            vq.dtable[j as usize].set(0x20000 + 128 * u64::from(j), 64, flags, j + 1);
+        }
+        vq.dtable[usize::from(chain_len) - 1]
+            .flags
+            .set(flags & !VIRTQ_DESC_F_NEXT);
+
+        for i in 0..num_chains {
+            vq.avail.ring[usize::from(i)].set(0);
+        }
+        vq.avail.idx.set(num_chains);
+
+        (q, vq)
+    }
+
+    #[test]
+    fn test_iovec_load_descriptor_chain() {
+        let mem = default_mem();
+        let (mut q, _) = repeated_chain(&mem, 8, 4, false);
+
+        let mut iovec = IoVecBuffer::new();
+        assert_eq!(iovec.len(), 0);
+        assert_eq!(iovec.iovec_count(), 0);
+
+        // The storage of the iovecs is allocated by the first load only.
+        iovec.load_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 8 * 64);
+        assert_eq!(iovec.iovec_count(), 8);
+        let iovec_ptr = iovec.as_iovec_ptr();
+        let capacity = iovec.vecs.capacity();
+        for _ in 0..3 {
+            iovec.load_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+            assert_eq!(iovec.len(), 8 * 64);
+            assert_eq!(iovec.iovec_count(), 8);
+            assert_eq!(iovec.as_iovec_ptr(), iovec_ptr);
+            assert_eq!(iovec.vecs.capacity(), capacity);
+        }
+
+        // Recycling the buffer keeps the storage.
+        let iovec = iovec.recycle();
+        assert_eq!(iovec.len(), 0);
+        assert_eq!(iovec.iovec_count(), 0);
+        assert_eq!(iovec.as_iovec_ptr(), iovec_ptr);
+
+        // A failed load leaves the buffer empty, and ready to be reused.
+        let mut iovec = iovec.recycle();
+        let (mut q, _) = repeated_chain(&mem, 8, 1, true);
+        iovec
+            .load_descriptor_chain(q.pop(&mem).unwrap())
+            .unwrap_err();
+        assert_eq!(iovec.len(), 0);
+        assert_eq!(iovec.iovec_count(), 0);
+
+        let (mut q, _) = repeated_chain(&mem, 8, 1, false);
+        iovec.load_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 8 * 64);
+        assert_eq!(iovec.as_iovec_ptr(), iovec_ptr);
+    }
+
+    #[test]
+    fn test_iovec_mut_load_descriptor_chain() {
+        let mem = default_mem();
+        let (mut q, _) = repeated_chain(&mem, 8, 4, true);
+
+        let mut iovec = IoVecBufferMut::new();
+        assert_eq!(iovec.len(), 0);
+
+        iovec.load_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 8 * 64);
+        let iovec_ptr = iovec.vecs.as_ptr();
+        let capacity = iovec.vecs.capacity();
+        for _ in 0..3 {
+            iovec.load_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+            assert_eq!(iovec.len(), 8 * 64);
+            assert_eq!(iovec.vecs.as_ptr(), iovec_ptr);
+            assert_eq!(iovec.vecs.capacity(), capacity);
+        }
+
+        let mut iovec = iovec.recycle();
+        assert_eq!(iovec.len(), 0);
+        let (mut q, _) = repeated_chain(&mem, 8, 1, false);
+        iovec
+            .load_descriptor_chain(q.pop(&mem).unwrap())
+            .unwrap_err();
+        assert_eq!(iovec.len(), 0);
+        assert!(iovec.vecs.is_empty());
+        assert_eq!(iovec.vecs.as_ptr(), iovec_ptr);
+    }
+
+    // Makes a chain of `chain_len` descriptors of one byte available, each one starting
+    // `stride` bytes after the previous one.
+    fn long_chain(
+        m: &GuestMemoryMmap,
+        chain_len: u16,
+        stride: u64,
+        is_write_only: bool,
+    ) -> (Queue, VirtQueue) {
+        let vq = VirtQueue::new(GuestAddress(0), m, 2048);
+
+        let mut q = vq.create_queue();
+        q.ready = true;
+
+        let flags = if is_write_only {
+            VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE
+        } else {
+            VIRTQ_DESC_F_NEXT
+        };
+
+        for j in 0..chain_len {
+            vq.dtable[j as usize].set(0x20000 + stride * u64::from(j), 1, flags, j + 1);
+        }
+        vq.dtable[usize::from(chain_len) - 1]
+            .flags
+            .set(flags & !VIRTQ_DESC_F_NEXT);
+
+        vq.avail.ring[0].set(0);
+        vq.avail.idx.set(1);
+
+        (q, vq)
+    }
+
+    #[test]
+    fn test_iovec_too_many_descriptors() {
+        let mem = default_mem();
+
+        let (mut q, _) = long_chain(&mem, 1025, 2, false);
+        assert!(matches!(
+            IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::TooManyDescriptors(IOV_MAX))
+        ));
+        let (mut q, _) = long_chain(&mem, 1025, 2, true);
+        assert!(matches!(
+            IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::TooManyDescriptors(IOV_MAX))
+        ));
+
+        let (mut q, _) = long_chain(&mem, 1024, 2, false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 1024);
+        assert_eq!(iovec.iovec_count(), 1024);
+        let (mut q, _) = long_chain(&mem, 1024, 2, true);
+        let iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 1024);
+        assert_eq!(iovec.vecs.len(), 1024);
+
+        // The limit can be lowered by the caller, and a rejected chain leaves the buffer empty.
+        let (mut q, _) = repeated_chain(&mem, 8, 2, false);
+        let mut iovec = IoVecBuffer::new();
+        assert!(matches!(
+            iovec.load_descriptor_chain_with_limit(q.pop(&mem).unwrap(), 7),
+            Err(IoVecError::TooManyDescriptors(7))
+        ));
+        assert_eq!(iovec.len(), 0);
+        assert_eq!(iovec.iovec_count(), 0);
+        iovec
+            .load_descriptor_chain_with_limit(q.pop(&mem).unwrap(), 8)
+            .unwrap();
+        assert_eq!(iovec.iovec_count(), 8);
+
+        let (mut q, _) = repeated_chain(&mem, 8, 2, true);
+        assert!(matches!(
+            IoVecBufferMut::from_descriptor_chain_with_limit(q.pop(&mem).unwrap(), 7),
+            Err(IoVecError::TooManyDescriptors(7))
+        ));
+        let iovec =
+            IoVecBufferMut::from_descriptor_chain_with_limit(q.pop(&mem).unwrap(), 8).unwrap();
+        assert_eq!(iovec.vecs.len(), 8);
+    }
+
+    #[test]
+    fn test_iovec_skip_empty_descriptors() {
+        let mem = default_mem();
+        let data = pattern(0x300);
+        mem.write_slice(&data, GuestAddress(0x20000)).unwrap();
+        // Zero-length descriptors alternate with 64 bytes ones, one of them in unmapped memory.
+        let descs = [
+            (0x20000, 0),
+            (0x20000, 64),
+            (0x30000, 0),
+            (0x20100, 64),
+            (0x20140, 0),
+            (0x20200, 64),
+            (0x20200, 0),
+        ];
+        let expected = [&data[..64], &data[0x100..0x140], &data[0x200..0x240]].concat();
+
+        let (mut q, _vq) = chain_of(&mem, &descs, false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 192);
+        assert_eq!(iovec.iovec_count(), 3);
+        assert_eq!(concat(iovec.iter()), expected);
+
+        let (mut q, _vq) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 192);
+        assert_eq!(iovec.vecs.len(), 3);
+        assert_eq!(iovec.addrs.len(), 3);
+        assert_eq!(concat(iovec.iter_mut()), expected);
+    }
+
+    #[test]
+    fn test_iovec_inline_storage() {
+        let mem = default_mem();
+        let data = pattern(5 * 128);
+        mem.write_slice(&data, GuestAddress(0x20000)).unwrap();
+        let descs: Vec<(u64, u32)> = (0..5).map(|j| (0x20000 + 128 * j, 64)).collect();
+
+        // Up to 4 `iovec`s are stored inline, and a 5th one moves them to the heap.
+        for (count, spilled) in [(4, false), (5, true)] {
+            let expected: Vec<u8> = data
+                .chunks(128)
+                .take(count)
+                .flat_map(|c| &c[..64])
+                .copied()
+                .collect();
+
+            let (mut q, _vq) = chain_of(&mem, &descs[..count], false);
+            let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+            assert_eq!(iovec.iovec_count(), count);
+            assert_eq!(iovec.vecs.spilled(), spilled);
+            assert_eq!(iovec.as_iovec_ptr(), iovec.vecs.as_ptr());
+            assert_eq!(concat(iovec.iter()), expected);
+
+            let (mut q, _vq) = chain_of(&mem, &descs[..count], true);
+            let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+            assert_eq!(iovec.vecs.len(), count);
+            assert_eq!(iovec.vecs.spilled(), spilled);
+            assert_eq!(concat(iovec.iter_mut()), expected);
+        }
+    }
+
+    #[test]
+    fn test_descriptor_chain_buffers() {
+        let mem = default_mem();
+        let descs = [(0x20000, 64), (0x20040, 64), (0x40000, 128), (0x40100, 32)];
+        let data = pattern(288);
+        mem.write_slice(&data[..128], GuestAddress(0x20000))
+            .unwrap();
+        mem.write_slice(&data[128..256], GuestAddress(0x40000))
+            .unwrap();
+        mem.write_slice(&data[256..], GuestAddress(0x40100))
+            .unwrap();
+
+        // All readable
+        let (mut q, _vq) = chain_of(&mem, &descs, false);
+        let buffers = DescriptorChainBuffers::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(buffers.readable.len(), 288);
+        assert_eq!(buffers.readable.iovec_count(), 3);
+        assert_eq!(concat(buffers.readable.iter()), data);
+        assert_eq!(buffers.writable.len(), 0);
+
+        // All writable
+        let (mut q, _vq) = chain_of(&mem, &descs, true);
+        let mut buffers =
+            DescriptorChainBuffers::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(buffers.readable.len(), 0);
+        assert_eq!(buffers.writable.len(), 288);
+        assert_eq!(buffers.writable.vecs.len(), 3);
+        assert_eq!(concat(buffers.writable.iter_mut()), data);
+
+        // Readable descriptors followed by writable ones
+        let (mut q, vq) = chain_of(&mem, &descs, false);
+        for desc in &vq.dtable[2..4] {
+            desc.flags.set(desc.flags.get() | VIRTQ_DESC_F_WRITE);
+        }
+        let mut buffers =
+            DescriptorChainBuffers::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(buffers.readable.len(), 128);
+        assert_eq!(concat(buffers.readable.iter()), data[..128]);
+        assert_eq!(buffers.writable.len(), 160);
+        assert_eq!(concat(buffers.writable.iter_mut()), data[128..]);
+
+        // A readable descriptor after a writable one
+        let (mut q, vq) = chain_of(&mem, &descs, false);
+        for desc in [&vq.dtable[1], &vq.dtable[3]] {
+            desc.flags.set(desc.flags.get() | VIRTQ_DESC_F_WRITE);
+        }
+        assert!(matches!(
+            DescriptorChainBuffers::from_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::ReadableAfterWritable)
+        ));
+    }
+
+    #[test]
+    fn test_iovec_chain_longer_than_memory() {
+        let mem = default_mem();
+        let mem_size = 3 * 0x10000;
+
+        // Referring four times to a whole region, the chain is longer than the guest memory.
+        let descs = [(0x20000, 0x10000); 4];
+        let (mut q, _vq) = chain_of(&mem, &descs, false);
+        assert!(matches!(
+            IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::DescriptorChainTooLong(size)) if size == mem_size
+        ));
+        let (mut q, _vq) = chain_of(&mem, &descs, true);
+        assert!(matches!(
+            IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::DescriptorChainTooLong(size)) if size == mem_size
+        ));
+
+        // Chains as long as the guest memory are accepted.
+        let (mut q, _vq) = chain_of(&mem, &descs[..3], false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(u64::from(iovec.len()), mem_size);
+        let (mut q, _vq) = chain_of(&mem, &descs[..3], true);
+        let iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(u64::from(iovec.len()), mem_size);
+    }
+
+    #[test]
+    fn test_iovec_coalesce_descriptors() {
+        let mem = default_mem();
+        let data = pattern(1025);
+        mem.write_slice(&data, GuestAddress(0x20000)).unwrap();
+
+        // Contiguous descriptors share an iovec, so the chain is not rejected.
+        let (mut q, _) = long_chain(&mem, 1025, 1, false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 1025);
+        assert_eq!(iovec.iovec_count(), 1);
+        let mut buf = vec![0u8; 1025];
+        iovec.read_exact_volatile_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, data);
+
+        let (mut q, _) = long_chain(&mem, 1025, 1, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 1025);
+        assert_eq!(iovec.vecs.len(), 1);
+        let zeros = vec![0u8; 1025];
+        iovec.write_all_volatile_at(&zeros, 0).unwrap();
+        mem.read_slice(&mut buf, GuestAddress(0x20000)).unwrap();
+        assert_eq!(buf, zeros);
+
+        // Even a limit of a single iovec fits them.
+        let (mut q, _) = read_only_chain(&mem);
+        let iovec = IoVecBuffer::from_descriptor_chain_with_limit(q.pop(&mem).unwrap(), 1).unwrap();
+        assert_eq!(iovec.len(), 4 * 64);
+        assert_eq!(iovec.iovec_count(), 1);
+    }
+
+    // Makes a chain of the given `(address, length)` descriptors available.
+    fn chain_of(
+        m: &GuestMemoryMmap,
+        descs: &[(u64, u32)],
+        is_write_only: bool,
+    ) -> (Queue, VirtQueue) {
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+
+        let mut q = vq.create_queue();
+        q.ready = true;
+
+        let flags = if is_write_only {
+            VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE
+        } else {
+            VIRTQ_DESC_F_NEXT
+        };
+
+        for (j, &(addr, len)) in (0..).zip(descs) {
+            vq.dtable[usize::from(j)].set(addr, len, flags, j + 1);
+        }
+        vq.dtable[descs.len() - 1]
+            .flags
+            .set(flags & !VIRTQ_DESC_F_NEXT);
+        vq.avail.ring[0].set(0);
+        vq.avail.idx.set(1);
+
+        (q, vq)
+    }
+
+    // Maps two guest memory regions of 0x10000 bytes at 0 and 0x10000, which are also contiguous
+    // in the host address space. The returned mapping is unmapped by the caller.
+    fn host_contiguous_mem() -> (GuestMemoryMmap, *mut c_void) {
+        const REGION_SIZE: usize = 0x10000;
+        // SAFETY: The result of the anonymous mapping is checked.
+        let host_addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                2 * REGION_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(host_addr, libc::MAP_FAILED);
+
+        let regions = [0, REGION_SIZE]
+            .into_iter()
+            .map(|offset| {
+                // SAFETY: The region is within the mapping, which outlives the guest memory.
+                let region = unsafe {
+                    MmapRegionBuilder::new_with_bitmap(REGION_SIZE, None)
+                        .with_raw_mmap_pointer(host_addr.cast::<u8>().add(offset))
+                        .build()
+                        .unwrap()
+                };
+                GuestRegionMmap::new(region, GuestAddress(u64::try_from(offset).unwrap())).unwrap()
+            })
+            .collect();
+        (GuestMemoryMmap::from_regions(regions).unwrap(), host_addr)
+    }
+
+    #[test]
+    fn test_iovec_coalesce_partially_contiguous() {
+        let mem = default_mem();
+        let data = pattern(0x200);
+        mem.write_slice(&data, GuestAddress(0x20000)).unwrap();
+
+        // The chain is made of two runs of contiguous descriptors, the second one starting with
+        // an empty descriptor.
+        let descs = [
+            (0x20000, 0x40),
+            (0x20040, 0x40),
+            (0x20080, 0x40),
+            (0x20100, 0),
+            (0x20100, 0x80),
+            (0x20180, 0x80),
+        ];
+        let (mut q, _) = chain_of(&mem, &descs, false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 0x1c0);
+        assert_eq!(iovec.iovec_count(), 2);
+        assert_eq!(iovec.vecs[0].iov_len, 0xc0);
+        assert_eq!(iovec.vecs[1].iov_len, 0x100);
+        let mut buf = vec![0u8; 0x1c0];
+        iovec.read_exact_volatile_at(&mut buf, 0).unwrap();
+        assert_eq!(buf[..0xc0], data[..0xc0]);
+        assert_eq!(buf[0xc0..], data[0x100..]);
+        // Reads spanning the merged descriptors are unchanged.
+        let mut buf = vec![0u8; 0x80];
+        iovec.read_exact_volatile_at(&mut buf, 0x80).unwrap();
+        assert_eq!(buf[..0x40], data[0x80..0xc0]);
+        assert_eq!(buf[0x40..], data[0x100..0x140]);
+
+        let (mut q, _) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.len(), 0x1c0);
+        assert_eq!(iovec.vecs.len(), 2);
+        iovec.write_all_volatile_at(&[0u8; 0x80], 0x80).unwrap();
+        let mut buf = vec![0u8; 0x200];
+        mem.read_slice(&mut buf, GuestAddress(0x20000)).unwrap();
+        assert_eq!(buf[..0x80], data[..0x80]);
+        assert!(buf[0x80..0xc0].iter().all(|&b| b == 0));
+        assert_eq!(buf[0xc0..0x100], data[0xc0..0x100]);
+        assert!(buf[0x100..0x140].iter().all(|&b| b == 0));
+        assert_eq!(buf[0x140..], data[0x140..]);
+    }
+
+    #[test]
+    fn test_iovec_coalesce_across_regions() {
+        let (mem, host_addr) = host_contiguous_mem();
+        {
+            let data = pattern(0x300);
+            mem.write_slice(&data, GuestAddress(0xff00)).unwrap();
+
+            // The descriptors are contiguous, both in the guest physical and the host address
+            // spaces, but the first one is in another guest memory region.
+            let descs = [(0xff00, 0x100), (0x10000, 0x100), (0x10100, 0x100)];
+            let (mut q, _) = chain_of(&mem, &descs, false);
+            let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+            assert_eq!(iovec.len(), 0x300);
+            assert_eq!(iovec.iovec_count(), 2);
+            assert_eq!(
+                iovec.vecs[0].iov_base.cast::<u8>().wrapping_add(0x100),
+                iovec.vecs[1].iov_base.cast::<u8>()
+            );
+            assert_eq!(iovec.vecs[1].iov_len, 0x200);
+            let mut buf = vec![0u8; 0x300];
+            iovec.read_exact_volatile_at(&mut buf, 0).unwrap();
+            assert_eq!(buf, data);
+
+            let (mut q, _) = chain_of(&mem, &descs, true);
+            let iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+            assert_eq!(iovec.len(), 0x300);
+            assert_eq!(iovec.vecs.len(), 2);
+        }
+        drop(mem);
+        // SAFETY: The guest memory using the mapping is gone.
+        unsafe { libc::munmap(host_addr, 0x20000) };
+    }
+
+    // Concatenates the bytes of the given slices.
+    fn concat<'s>(slices: impl Iterator<Item = VolatileSlice<'s>>) -> Vec<u8> {
+        let mut data = Vec::new();
+        for slice in slices {
+            let mut buf = vec![0u8; slice.len()];
+            assert_eq!(slice.copy_to(&mut buf[..]), slice.len());
+            data.extend(buf);
+        }
+        data
+    }
+
+    #[test]
+    fn test_iovec_capped() {
+        let mem = default_mem();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 64)];
+        let data = pattern(192);
+        for (chunk, &(addr, _)) in data.chunks(64).zip(&descs) {
+            mem.write_slice(chunk, GuestAddress(addr)).unwrap();
+        }
+
+        // Caps on a descriptor boundary, in the middle of a descriptor, and past the chain.
+        for (max_len, len, iovec_count, fit) in [
+            (128, 128, 2, ChainFit::Truncated),
+            (100, 100, 2, ChainFit::Truncated),
+            (192, 192, 3, ChainFit::Whole),
+            (1000, 192, 3, ChainFit::Whole),
+        ] {
+            let (mut q, _) = chain_of(&mem, &descs, false);
+            let (iovec, chain_fit) =
+                IoVecBuffer::from_descriptor_chain_capped(q.pop(&mem).unwrap(), max_len).unwrap();
+            assert_eq!(chain_fit, fit);
+            assert_eq!(iovec.len() as usize, len);
+            assert_eq!(iovec.iovec_count(), iovec_count);
+            assert_eq!(concat(iovec.iter()), data[..len]);
+
+            let (mut q, _) = chain_of(&mem, &descs, true);
+            let (mut iovec, chain_fit) =
+                IoVecBufferMut::from_descriptor_chain_capped(q.pop(&mem).unwrap(), max_len)
+                    .unwrap();
+            assert_eq!(chain_fit, fit);
+            assert_eq!(iovec.len() as usize, len);
+            assert_eq!(iovec.vecs.len(), iovec_count);
+            assert_eq!(concat(iovec.iter_mut()), data[..len]);
+        }
+
+        // The descriptors past the cap are not validated.
+        let descs = [(0x20000, 64), (0x30000, 64)];
+        let (mut q, _) = chain_of(&mem, &descs, false);
+        let (iovec, chain_fit) =
+            IoVecBuffer::from_descriptor_chain_capped(q.pop(&mem).unwrap(), 64).unwrap();
+        assert_eq!(chain_fit, ChainFit::Truncated);
+        assert_eq!(iovec.len(), 64);
+        let (mut q, _) = chain_of(&mem, &descs, false);
+        assert!(matches!(
+            IoVecBuffer::from_descriptor_chain_capped(q.pop(&mem).unwrap(), 65),
+            Err(IoVecError::GuestMemory(_))
+        ));
+        let (mut q, _) = chain_of(&mem, &descs, true);
+        let (iovec, chain_fit) =
+            IoVecBufferMut::from_descriptor_chain_capped(q.pop(&mem).unwrap(), 64).unwrap();
+        assert_eq!(chain_fit, ChainFit::Truncated);
+        assert_eq!(iovec.len(), 64);
+    }
+
+    #[test]
+    fn test_iovec_iter() {
+        let mem = default_mem();
+        let data = pattern(0x400);
+        mem.write_slice(&data, GuestAddress(0x20000)).unwrap();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 0), (0x20300, 64)];
+
+        let (mut q, _) = chain_of(&mem, &descs, false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        let mut expected = vec![0u8; 192];
+        iovec.read_exact_volatile_at(&mut expected, 0).unwrap();
+
+        // The empty descriptor is skipped.
+        let lens: Vec<_> = iovec.iter().map(|slice| slice.len()).collect();
+        assert_eq!(lens, [64, 64, 64]);
+        assert_eq!(concat(iovec.iter()), expected);
+
+        // The first and last slices of a range are trimmed.
+        let lens: Vec<_> = iovec.range_iter(10, 100).map(|slice| slice.len()).collect();
+        assert_eq!(lens, [54, 46]);
+        let lens: Vec<_> = iovec.range_iter(64, 64).map(|slice| slice.len()).collect();
+        assert_eq!(lens, [64]);
+
+        for offset in [0, 1, 63, 64, 65, 100, 128, 191, 192, 300] {
+            for len in [0, 1, 63, 64, 100, 192, 500] {
+                let start = offset.min(192);
+                let end = (offset + len).min(192);
+                assert_eq!(
+                    concat(iovec.range_iter(offset, len)),
+                    expected[start..end],
+                    "offset {offset} len {len}"
+                );
+            }
+        }
+
+        // The slices of an IoVecBufferMut write to the guest memory.
+        let (mut q, _) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        let lens: Vec<_> = iovec.iter_mut().map(|slice| slice.len()).collect();
+        assert_eq!(lens, [64, 64, 64]);
+        for slice in iovec.range_iter_mut(32, 64) {
+            slice.write_bytes(0);
+        }
+        let mut buf = vec![0u8; 0x400];
+        mem.read_slice(&mut buf, GuestAddress(0x20000)).unwrap();
+        assert_eq!(buf[..32], data[..32]);
+        assert!(buf[32..64].iter().all(|&b| b == 0));
+        assert_eq!(buf[64..0x100], data[64..0x100]);
+        assert!(buf[0x100..0x120].iter().all(|&b| b == 0));
+        assert_eq!(buf[0x120..], data[0x120..]);
+    }
+
+    #[test]
+    fn test_iovec_cyclic_chain() {
+        let mem = default_mem();
+
+        // The last descriptor links back to the head of the chain.
+        let (mut q, vq) = read_only_chain(&mem);
+        vq.dtable[3].flags.set(VIRTQ_DESC_F_NEXT);
+        vq.dtable[3].next.set(0);
+        assert!(matches!(
+            IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::InvalidChain(ChainError::Cycle(0)))
+        ));
+
+        // Two descriptors link to each other. The walk stops on the first one seen twice, rather
+        // than going around the cycle until the queue size.
+        let (mut q, vq) = read_only_chain(&mem);
+        vq.dtable[2].next.set(1);
+        let mut iovec = IoVecBuffer::new();
+        assert!(matches!(
+            iovec.load_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::InvalidChain(ChainError::Cycle(1)))
+        ));
+        assert_eq!(iovec.len(), 0);
+
+        // A descriptor links to itself.
+        let (mut q, vq) = write_only_chain(&mem);
+        vq.dtable[1].next.set(1);
+        let mut iovec = IoVecBufferMut::new();
+        assert!(matches!(
+            iovec.load_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::InvalidChain(ChainError::Cycle(1)))
+        ));
+        assert_eq!(iovec.len(), 0);
+    }
+
+    #[test]
+    fn test_iovec_read_at() {
+        let mem = default_mem();
+        let (mut q, _) = read_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+
+        let iovec = IoVecBuffer::from_descriptor_chain(head).unwrap();
+
+        let mut buf = vec![0u8; 257];
+        assert_eq!(
+            iovec
+                .read_volatile_at(&mut buf.as_mut_slice(), 0, 257)
+                .unwrap(),
+            256
+        );
+        assert_eq!(buf[0..256], (0..=255).collect::<Vec<_>>());
+        assert_eq!(buf[256], 0);
+
+        let mut buf = vec![0; 5];
+        iovec.read_exact_volatile_at(&mut buf[..4], 0).unwrap();
+        assert_eq!(buf, vec![0u8, 1, 2, 3, 0]);
+
+        iovec.read_exact_volatile_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, vec![0u8, 1, 2, 3, 4]);
+
+        iovec.read_exact_volatile_at(&mut buf, 1).unwrap();
+        assert_eq!(buf, vec![1u8, 2, 3, 4, 5]);
+
+        iovec.read_exact_volatile_at(&mut buf, 60).unwrap();
+        assert_eq!(buf, vec![60u8, 61, 62, 63, 64]);
+
+        assert_eq!(
+            iovec
+                .read_volatile_at(&mut buf.as_mut_slice(), 252, 5)
+                .unwrap(),
+            4
+        );
+        assert_eq!(buf[0..4], vec![252u8, 253, 254, 255]);
+
+        assert!(matches!(
+            iovec.read_exact_volatile_at(&mut buf, 252),
+            Err(VolatileMemoryError::PartialBuffer {
+                expected: 5,
+                completed: 4
+            })
+        ));
+        assert!(matches!(
+            iovec.read_exact_volatile_at(&mut buf, 256),
+            Err(VolatileMemoryError::OutOfBounds { addr: 256 })
+        ));
+    }
+
+    #[test]
+    fn test_iovec_read_obj() {
+        // A header split 1 byte into the first descriptor, and the rest into the second.
+        let hdr = virtio_net_hdr_v1 {
+            flags: 1,
+            gso_type: 2,
+            hdr_len: 0x0403,
+            gso_size: 0x0605,
+            csum_start: 0x0807,
+            csum_offset: 0x0a09,
+            num_buffers: 0x0c0b,
+        };
+        let bytes = hdr.as_slice();
+        let iovec = IoVecBuffer::from(vec![&bytes[..1], &bytes[1..]]);
+        assert_eq!(iovec.read_obj::<virtio_net_hdr_v1>(0).unwrap(), hdr);
+        assert!(matches!(
+            iovec.read_obj::<virtio_net_hdr_v1>(1),
+            Err(IoVecError::ShortBuffer {
+                offset: 1,
+                size: 12,
+                remaining: 11
+            })
+        ));
+
+        // Objects straddling several descriptors, at an offset.
+        let buf: Vec<u8> = (0..32).collect();
+        let iovec = IoVecBuffer::from(vec![&buf[..3], &buf[3..4], &buf[4..6], &buf[6..]]);
+        assert_eq!(
+            iovec.read_obj::<u32>(2).unwrap(),
+            u32::from_le_bytes([2, 3, 4, 5])
+        );
+        assert_eq!(
+            iovec.read_obj::<u64>(24).unwrap(),
+            u64::from_le_bytes([24, 25, 26, 27, 28, 29, 30, 31])
+        );
+        assert!(matches!(
+            iovec.read_obj::<u64>(25),
+            Err(IoVecError::ShortBuffer {
+                offset: 25,
+                size: 8,
+                remaining: 7
+            })
+        ));
+        assert!(matches!(
+            iovec.read_obj::<u32>(40),
+            Err(IoVecError::ShortBuffer {
+                offset: 40,
+                size: 4,
+                remaining: 0
+            })
+        ));
+
+        // Objects straddling the descriptors of a chain.
+        let mem = default_mem();
+        let (mut q, _) = read_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+        let iovec = IoVecBuffer::from_descriptor_chain(head).unwrap();
+        assert_eq!(
+            iovec.read_obj::<u32>(62).unwrap(),
+            u32::from_le_bytes([62, 63, 64, 65])
+        );
+        assert_eq!(
+            iovec.read_obj::<[u8; 16]>(120).unwrap(),
+            std::array::from_fn(|i| u8::try_from(120 + i).unwrap())
+        );
+        assert_eq!(
+            iovec.read_obj::<u32>(252).unwrap(),
+            u32::from_le_bytes([252, 253, 254, 255])
+        );
+        assert!(matches!(
+            iovec.read_obj::<u32>(253),
+            Err(IoVecError::ShortBuffer {
+                offset: 253,
+                size: 4,
+                remaining: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_iovec_mut_write_at() {
+        let mem = default_mem();
+        let (mut q, vq) = write_only_chain(&mem);
+
+        // This is a descriptor chain with 4 elements 64 bytes long each.
+        let head = q.pop(&mem).unwrap();
+
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(head).unwrap();
+        let buf = vec![0u8, 1, 2, 3, 4];
+
+        // One test vector for each part of the chain
+        let mut test_vec1 = vec![0u8; 64];
+        let mut test_vec2 = vec![0u8; 64];
+        let test_vec3 = vec![0u8; 64];
+        let mut test_vec4 = vec![0u8; 64];
+
+        // Control test: Initially all three regions should be zero
+        iovec.write_all_volatile_at(&test_vec1, 0).unwrap();
+        iovec.write_all_volatile_at(&test_vec2, 64).unwrap();
+        iovec.write_all_volatile_at(&test_vec3, 128).unwrap();
+        iovec.write_all_volatile_at(&test_vec4, 192).unwrap();
+        vq.dtable[0].check_data(&test_vec1);
+        vq.dtable[1].check_data(&test_vec2);
+        vq.dtable[2].check_data(&test_vec3);
+        vq.dtable[3].check_data(&test_vec4);
+
+        // Let's initialize test_vec1 with our buffer.
+        test_vec1[..buf.len()].copy_from_slice(&buf);
+        // And write just a part of it
+        iovec.write_all_volatile_at(&buf[..3], 0).unwrap();
+        // Not all 5 bytes from buf should be written in memory,
+        // just 3 of them.
+        vq.dtable[0].check_data(&[0u8, 1, 2, 0, 0]);
+        vq.dtable[1].check_data(&test_vec2);
+        vq.dtable[2].check_data(&test_vec3);
+        vq.dtable[3].check_data(&test_vec4);
+        // But if we write the whole `buf` in memory then all
+        // of it should be observable.
+        iovec.write_all_volatile_at(&buf, 0).unwrap();
+        vq.dtable[0].check_data(&test_vec1);
+        vq.dtable[1].check_data(&test_vec2);
+        vq.dtable[2].check_data(&test_vec3);
+        vq.dtable[3].check_data(&test_vec4);
+
+        // We are now writing with an offset of 1. So, initialize
+        // the corresponding part of `test_vec1`
+        test_vec1[1..buf.len() + 1].copy_from_slice(&buf);
+        iovec.write_all_volatile_at(&buf, 1).unwrap();
+        vq.dtable[0].check_data(&test_vec1);
+        vq.dtable[1].check_data(&test_vec2);
+        vq.dtable[2].check_data(&test_vec3);
+        vq.dtable[3].check_data(&test_vec4);
+
+        // Perform a write that traverses two of the underlying
+        // regions. Writing at offset 60 should write 4 bytes on the
+        // first region and one byte on the second
+        test_vec1[60..64].copy_from_slice(&buf[0..4]);
+        test_vec2[0] = 4;
+        iovec.write_all_volatile_at(&buf, 60).unwrap();
+        vq.dtable[0].check_data(&test_vec1);
+        vq.dtable[1].check_data(&test_vec2);
+        vq.dtable[2].check_data(&test_vec3);
+        vq.dtable[3].check_data(&test_vec4);
+
+        test_vec4[63] = 3;
+        test_vec4[62] = 2;
+        test_vec4[61] = 1;
+        // Now perform a write that does not fit in the buffer. Try writing
+        // 5 bytes at offset 252 (only 4 bytes left).
+        test_vec4[60..64].copy_from_slice(&buf[0..4]);
+        assert_eq!(
+            iovec.write_volatile_at(&mut &*buf, 252, buf.len()).unwrap(),
+            4
+        );
+        vq.dtable[0].check_data(&test_vec1);
+        vq.dtable[1].check_data(&test_vec2);
+        vq.dtable[2].check_data(&test_vec3);
+        vq.dtable[3].check_data(&test_vec4);
+
+        // Trying to add past the end of the buffer should not write anything
+        assert!(matches!(
+            iovec.write_all_volatile_at(&buf, 256),
+            Err(VolatileMemoryError::OutOfBounds { addr: 256 })
+        ));
+        vq.dtable[0].check_data(&test_vec1);
+        vq.dtable[1].check_data(&test_vec2);
+        vq.dtable[2].check_data(&test_vec3);
+        vq.dtable[3].check_data(&test_vec4);
+    }
+
+    #[test]
+    fn test_iovec_mut_write_obj() {
+        let mem = default_mem();
+        let (mut q, vq) = write_only_chain(&mem);
+        let head = q.pop(&mem).unwrap();
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(head).unwrap();
+
+        // An object written exactly across the boundary between the first two descriptors.
+        iovec
+            .write_obj(&u64::from_le_bytes([1, 2, 3, 4, 5, 6, 7, 8]), 60)
+            .unwrap();
+        let mut test_vec1 = vec![0u8; 64];
+        test_vec1[60..].copy_from_slice(&[1, 2, 3, 4]);
+        let mut test_vec2 = vec![0u8; 64];
+        test_vec2[..4].copy_from_slice(&[5, 6, 7, 8]);
+        vq.dtable[0].check_data(&test_vec1);
+        vq.dtable[1].check_data(&test_vec2);
+
+        // An object ending exactly at the end of the buffer.
+        iovec.write_obj(&[9u8; 16], 240).unwrap();
+        let mut test_vec4 = vec![0u8; 64];
+        test_vec4[48..].copy_from_slice(&[9; 16]);
+        vq.dtable[3].check_data(&test_vec4);
+
+        // An object whose tail would fall off the end of the buffer is not written at all.
+        assert!(matches!(
+            iovec.write_obj(&[10u8; 16], 244),
+            Err(IoVecError::TruncatedWrite {
+                offset: 244,
+                size: 16,
+                remaining: 12
+            })
+        ));
+        assert!(matches!(
+            iovec.write_obj(&0u32, 300),
+            Err(IoVecError::TruncatedWrite {
+                offset: 300,
+                size: 4,
+                remaining: 0
+            })
+        ));
+        vq.dtable[0].check_data(&test_vec1);
+        vq.dtable[1].check_data(&test_vec2);
+        vq.dtable[2].check_data(&[0u8; 64]);
+        vq.dtable[3].check_data(&test_vec4);
+    }
+
+    #[test]
+    fn test_iovec_mut_write_obj_dirty_bitmap() {
+        let mem = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), 0x10000), (GuestAddress(0x20000), 0x10000)],
+            true,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        let (mut q, _) = write_only_chain(&mem);
+        mem.reset_dirty();
+        let head = q.pop(&mem).unwrap();
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(head).unwrap();
+
+        // Nothing is marked dirty until an object is written, and a truncated write marks
+        // nothing either.
+        let region = mem.find_region(GuestAddress(0x20000)).unwrap();
+        assert!(!region.bitmap().dirty_at(0));
+        iovec.write_obj(&[1u8; 32], 240).unwrap_err();
+        assert!(!region.bitmap().dirty_at(0));
+        iovec.write_obj(&[1u8; 32], 200).unwrap();
+        assert!(region.bitmap().dirty_at(200));
+        assert!(!region.bitmap().dirty_at(0x1000));
+    }
+
+    #[test]
+    fn test_iovec_mut_dirty_only_written() {
+        let page_size = utils::get_page_size().unwrap();
+        let mem = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), 0x10000), (GuestAddress(0x20000), 0x10000)],
+            true,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        mem.write_slice(&vec![0xff; 0x10000], GuestAddress(0x20000))
+            .unwrap();
+        // A 64KiB chain covering the second region, with its halves swapped so that the buffer
+        // has two `iovec`s.
+        let (mut q, _vq) = chain_of(&mem, &[(0x28000, 0x8000), (0x20000, 0x8000)], true);
+        mem.reset_dirty();
+        let head = q.pop(&mem).unwrap();
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(head).unwrap();
+        assert_eq!(iovec.len(), 0x10000);
+
+        // 100 bytes straddling the halves, i.e. the end of the region and its start.
+        iovec
+            .write_all_volatile_at(&pattern(100), 0x8000 - 50)
+            .unwrap();
+
+        let dirty_bitmap: DirtyBitmap = (0..2).map(|slot| (slot, vec![0])).collect();
+        let mut file = TempFile::new().unwrap().into_file();
+        mem.dump_dirty(&mut file, &dirty_bitmap).unwrap();
+        let mut diff = Vec::new();
+        file.rewind().unwrap();
+        file.read_to_end(&mut diff).unwrap();
+        diff.resize(0x20000, 0);
+
+        // Only the pages holding the 100 bytes are in the diff snapshot, the other ones being
+        // holes in the file.
+        let dirty_pages = [0, 0x10000 / page_size - 1];
+        for (page, contents) in diff[0x10000..].chunks(page_size).enumerate() {
+            let dumped = contents.iter().any(|&byte| byte != 0);
+            assert_eq!(dumped, dirty_pages.contains(&page), "page {page}");
+        }
+    }
+
+    #[test]
+    fn test_iovec_mut_read_from_fd() {
+        let page_size = utils::get_page_size().unwrap();
+        let mem = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), 0x10000), (GuestAddress(0x20000), 0x10000)],
+            true,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        let (mut q, _vq) = chain_of(&mem, &[(0x28000, 0x8000), (0x20000, 0x8000)], true);
+        mem.reset_dirty();
+        let head = q.pop(&mem).unwrap();
+        let mut iovec = IoVecBufferMut::new();
+        assert_eq!(
+            iovec.load_descriptor_chain_capped(head, 0x9000).unwrap(),
+            ChainFit::Truncated
+        );
+        assert_eq!(iovec.len(), 0x9000);
+
+        // A datagram spanning both `iovec`s.
+        let (sender, receiver) = std::os::unix::net::UnixDatagram::pair().unwrap();
+        let datagram = pattern(0x8000 + 100);
+        sender.send(&datagram).unwrap();
+        assert_eq!(iovec.read_from_fd(&receiver).unwrap(), datagram.len());
+
+        let mut read = vec![0; datagram.len()];
+        iovec.read_exact_volatile_at(&mut read, 0).unwrap();
+        assert_eq!(read, datagram);
+
+        // Only the pages holding the datagram are dirty.
+        let region = mem.find_region(GuestAddress(0x20000)).unwrap();
+        for page in 0..0x10000 / page_size {
+            let dirty = page == 0 || page >= 0x8000 / page_size;
+            assert_eq!(
+                region.bitmap().dirty_at(page * page_size),
+                dirty,
+                "page {page}"
+            );
+        }
+
+        // Nothing left to read on the non-blocking socket.
+        receiver.set_nonblocking(true).unwrap();
+        assert_eq!(
+            iovec.read_from_fd(&receiver).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn test_iovec_parts() {
+        let page_size = utils::get_page_size().unwrap();
+        let mem = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), 0x10000), (GuestAddress(0x20000), 0x10000)],
+            true,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        let (mut q, _vq) = chain_of(&mem, &[(0x28000, 0x8000), (0x20000, 0x8000)], true);
+        mem.reset_dirty();
+        let head = q.pop(&mem).unwrap();
+        let iovec = IoVecBufferMut::from_descriptor_chain(head).unwrap();
+
+        let parts = iovec.into_parts();
+        assert_eq!(parts.len(), 0x10000);
+        assert_eq!(parts.iovec_count(), 2);
+        // The `iovec`s don't move with the parts.
+        let iovecs = parts.as_iovec_ptr();
+        let parts = Box::new(parts);
+        assert_eq!(parts.as_iovec_ptr(), iovecs);
+
+        // Data spanning both `iovec`s, read through the parts only, as the kernel would.
+        let data = pattern(0x8000 + 100);
+        let file = TempFile::new().unwrap().into_file();
+        file.write_all_at(&data, 0).unwrap();
+        // SAFETY: The `iovec`s point into the guest memory, alive for the whole call.
+        let bytes_read = unsafe { libc::preadv(file.as_raw_fd(), iovecs, 2, 0) };
+        assert_eq!(usize::try_from(bytes_read).unwrap(), data.len());
+        let region = mem.find_region(GuestAddress(0x20000)).unwrap();
+        assert!(!region.bitmap().dirty_at(0x8000));
+
+        // The buffer given back holds the data, and marks the bytes read dirty.
+        // SAFETY: The parts were taken from a buffer over `mem`.
+        let iovec = unsafe { IoVecBufferMut::from_parts(&mem, *parts) };
+        assert_eq!(iovec.len(), 0x10000);
+        assert_eq!(iovec.chain_ends(), &[0x10000]);
+        iovec.mark_written(data.len());
+        let mut read = vec![0; data.len()];
+        iovec.read_exact_volatile_at(&mut read, 0).unwrap();
+        assert_eq!(read, data);
+        for page in 0..0x10000 / page_size {
+            let dirty = page == 0 || page >= 0x8000 / page_size;
+            assert_eq!(
+                region.bitmap().dirty_at(page * page_size),
+                dirty,
+                "page {page}"
+            );
+        }
+
+        // Same for a buffer to be read from.
+        let (mut q, _vq) = chain_of(&mem, &[(0x28000, 0x8000), (0x20000, 0x8000)], false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        let vecs: Vec<_> = iovec
+            .as_slice()
+            .iter()
+            .map(|iov| (iov.iov_base, iov.iov_len))
+            .collect();
+        // SAFETY: The parts were taken from a buffer over `mem`.
+        let iovec = unsafe { IoVecBuffer::from_parts(iovec.into_parts()) };
+        assert_eq!(iovec.len(), 0x10000);
+        assert_eq!(
+            iovec
+                .as_slice()
+                .iter()
+                .map(|iov| (iov.iov_base, iov.iov_len))
+                .collect::<Vec<_>>(),
+            vecs
+        );
+        let mut read = vec![0; data.len()];
+        iovec.read_exact_volatile_at(&mut read, 0).unwrap();
+        assert_eq!(read, data);
+    }
+
+    // Bytes `0..len` with a different value for every byte.
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| u8::try_from(i % 251).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_iovec_mut_read_back() {
+        let mem = default_mem();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 64)];
+        let (mut q, _) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+
+        // Write across the boundaries of the three segments, and read the same range back.
+        let data = pattern(100);
+        iovec.write_all_volatile_at(&data, 50).unwrap();
+        let mut buf = vec![0u8; 100];
+        iovec.read_exact_volatile_at(&mut buf, 50).unwrap();
+        assert_eq!(buf, data);
+
+        let mut buf = vec![0u8; 100];
+        assert_eq!(
+            iovec
+                .read_volatile_at(&mut buf.as_mut_slice(), 50, 100)
+                .unwrap(),
+            100
+        );
+        assert_eq!(buf, data);
+
+        // Reading back goes through the guest memory.
+        let mut buf = vec![0u8; 14];
+        mem.read_slice(&mut buf, GuestAddress(0x20032)).unwrap();
+        assert_eq!(buf, data[..14]);
+        let mut buf = vec![0u8; 22];
+        mem.read_slice(&mut buf, GuestAddress(0x20200)).unwrap();
+        assert_eq!(buf, data[78..]);
+
+        // Reads past the end are short, or fail.
+        let mut buf = vec![0u8; 100];
+        assert!(matches!(
+            iovec.read_exact_volatile_at(&mut buf, 150),
+            Err(VolatileMemoryError::PartialBuffer {
+                expected: 100,
+                completed: 42
+            })
+        ));
+        assert!(matches!(
+            iovec.read_exact_volatile_at(&mut buf, 192),
+            Err(VolatileMemoryError::OutOfBounds { addr: 192 })
+        ));
+        assert_eq!(
+            iovec
+                .read_volatile_at(&mut buf.as_mut_slice(), 192, 10)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_iovec_skip_bytes() {
+        let data = pattern(64);
+        let iovec = IoVecBuffer::from(vec![&data[..10], &data[10..10], &data[10..30], &data[30..]]);
+
+        // Whole segments are dropped, including the empty ones, and the first one left is
+        // trimmed.
+        for (count, iovec_count) in [(0, 4), (5, 4), (10, 2), (20, 2), (30, 1), (63, 1), (64, 0)] {
+            let mut vecs: IoVecVec = iovec.vecs.clone();
+            skip_bytes(&mut vecs, count);
+            let rest = IoVecBuffer {
+                vecs,
+                len: u32::try_from(data.len() - count).unwrap(),
+                _mem: PhantomData,
+            };
+            assert_eq!(rest.iovec_count(), iovec_count);
+            assert_eq!(concat(rest.iter()), data[count..]);
+        }
+    }
+
+    #[test]
+    fn test_iovec_file_io() {
+        let mem = default_mem();
+        let mut file = TempFile::new().unwrap().into_file();
+
+        // Chains of 1, 4 and `IOV_MAX` segments of one byte, none of them contiguous.
+        for chain_len in [1, 4, u16::try_from(IOV_MAX).unwrap()] {
+            let data = pattern(usize::from(chain_len));
+            for (j, byte) in (0..chain_len).zip(&data) {
+                mem.write_obj(*byte, GuestAddress(0x20000 + 2 * u64::from(j)))
+                    .unwrap();
+            }
+            let (mut q, _) = long_chain(&mem, chain_len, 2, false);
+            let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+            assert_eq!(iovec.iovec_count(), usize::from(chain_len));
+            assert_eq!(iovec.write_to_file_at(&file, 100).unwrap(), data.len());
+
+            let mut buf = vec![0u8; data.len()];
+            file.read_exact_at(&mut buf, 100).unwrap();
+            assert_eq!(buf, data);
+
+            // Read the data back, over the data written.
+            mem.write_slice(&vec![0u8; 2 * data.len()], GuestAddress(0x20000))
+                .unwrap();
+            let (mut q, _) = long_chain(&mem, chain_len, 2, true);
+            let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+            assert_eq!(iovec.read_from_file_at(&file, 100).unwrap(), data.len());
+            assert_eq!(concat(iovec.iter_mut()), data);
+
+            // The position of the file is left untouched.
+            assert_eq!(file.stream_position().unwrap(), 0);
+        }
+
+        // Reads stop at the end of the file, here within the second of three segments.
+        let file = TempFile::new().unwrap().into_file();
+        let data = pattern(100);
+        file.write_all_at(&data, 0).unwrap();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 64)];
+        let (mut q, _) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.read_from_file_at(&file, 0).unwrap(), 100);
+        let mut buf = vec![0u8; 100];
+        iovec.read_exact_volatile_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, data);
+        assert_eq!(iovec.read_from_file_at(&file, 50).unwrap(), 50);
+        assert_eq!(iovec.read_from_file_at(&file, 100).unwrap(), 0);
+
+        // Errors of the file are reported.
+        let tmp = TempFile::new().unwrap();
+        let read_only = File::open(tmp.as_path()).unwrap();
+        let (mut q, _) = chain_of(&mem, &descs, false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert!(matches!(
+            iovec.write_to_file_at(&read_only, 0),
+            Err(IoVecError::FileIo(_))
+        ));
+    }
+
+    #[test]
+    fn test_iovec_socket_io() {
+        let mem = default_mem();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 64)];
+        let data = pattern(192);
+        for (chunk, &(addr, _)) in data.chunks(64).zip(&descs) {
+            mem.write_slice(chunk, GuestAddress(addr)).unwrap();
+        }
+        let (mut tx, mut rx) = UnixStream::pair().unwrap();
+
+        // The range is trimmed within the first and last segments.
+        let (mut q, _) = chain_of(&mem, &descs, false);
+        let iovec = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.send_to_socket(&tx, 10, 150).unwrap(), 150);
+        let mut buf = vec![0u8; 150];
+        rx.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[10..160]);
+        // The `iovec`s of the buffer are left untouched.
+        assert_eq!(iovec.iovec_count(), 3);
+        assert_eq!(concat(iovec.iter()), data);
+
+        // Sends are resumed at the offset reached, and stop at the end of the buffer.
+        assert_eq!(iovec.send_to_socket(&tx, 0, 100).unwrap(), 100);
+        assert_eq!(iovec.send_to_socket(&tx, 100, 100).unwrap(), 92);
+        assert_eq!(iovec.send_to_socket(&tx, 192, 10).unwrap(), 0);
+        let mut buf = vec![0u8; 192];
+        rx.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        // Receives start within the second segment, and only fill the room left.
+        let (mut q, _) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        let payload: Vec<u8> = data.iter().rev().take(100).copied().collect();
+        tx.write_all(&payload).unwrap();
+        assert_eq!(iovec.recv_from_socket(&rx, 70, 100).unwrap(), 100);
+        let mut buf = vec![0u8; 100];
+        iovec.read_exact_volatile_at(&mut buf, 70).unwrap();
+        assert_eq!(buf, payload);
+
+        tx.write_all(&payload).unwrap();
+        assert_eq!(iovec.recv_from_socket(&rx, 150, 100).unwrap(), 42);
+        let mut buf = vec![0u8; 42];
+        iovec.read_exact_volatile_at(&mut buf, 150).unwrap();
+        assert_eq!(buf, payload[..42]);
+        let mut buf = vec![0u8; 58];
+        rx.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, payload[42..]);
+
+        // Receiving from an empty non-blocking socket fails, and from a closed one reads nothing.
+        rx.set_nonblocking(true).unwrap();
+        assert!(matches!(
+            iovec.recv_from_socket(&rx, 0, 10),
+            Err(VolatileMemoryError::IOError(err)) if err.kind() == ErrorKind::WouldBlock
+        ));
+        drop(tx);
+        assert_eq!(iovec.recv_from_socket(&rx, 0, 10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_iovec_from_guest_memory() {
+        let mem = default_mem();
+        let data = pattern(64);
+        mem.write_slice(&data, GuestAddress(0x20000)).unwrap();
+
+        let iovec = IoVecBuffer::from_guest_memory(&mem, GuestAddress(0x20000), 64).unwrap();
+        assert_eq!(iovec.len(), 64);
+        assert_eq!(concat(iovec.iter()), data);
+
+        let mut iovec = IoVecBufferMut::from_guest_memory(&mem, GuestAddress(0x20000), 64).unwrap();
+        assert_eq!(iovec.len(), 64);
+        assert_eq!(concat(iovec.iter_mut()), data);
+
+        // The range has to be in a single guest memory region.
+        IoVecBuffer::from_guest_memory(&mem, GuestAddress(0xfff0), 32).unwrap_err();
+        IoVecBufferMut::from_guest_memory(&mem, GuestAddress(0xfff0), 32).unwrap_err();
+    }
+
+    #[test]
+    fn test_iovec_mut_copy_from() {
+        let data = pattern(64);
+        // Segments of the source and of the destination end at different offsets, and both have
+        // empty segments.
+        let src = IoVecBuffer::from(vec![
+            &data[..5],
+            &data[5..5],
+            &data[5..17],
+            &data[17..40],
+            &data[40..64],
+        ]);
+        for (src_offset, dst_offset, len) in [(0, 0, 64), (3, 0, 61), (0, 7, 57), (13, 22, 30)] {
+            let mut buf = [0u8; 64];
+            let (first, rest) = buf.split_at_mut(9);
+            let (second, third) = rest.split_at_mut(21);
+            let mut dst = IoVecBufferMut::from(vec![first, &mut [][..], second, third]);
+
+            assert_eq!(
+                dst.copy_from(&src, src_offset, dst_offset, len).unwrap(),
+                len
+            );
+            let mut expected = [0u8; 64];
+            expected[dst_offset..dst_offset + len]
+                .copy_from_slice(&data[src_offset..src_offset + len]);
+            assert_eq!(buf, expected, "{} {} {}", src_offset, dst_offset, len);
+        }
+    }
+
+    #[test]
+    fn test_iovec_mut_copy_from_short() {
+        let data = pattern(32);
+        let src = IoVecBuffer::from(vec![&data[..10], &data[10..32]]);
+
+        // The source runs out.
+        let mut buf = [0u8; 64];
+        let mut dst = IoVecBufferMut::from(&mut buf[..]);
+        assert_eq!(dst.copy_from(&src, 4, 0, 64).unwrap(), 28);
+        assert_eq!(&buf[..28], &data[4..]);
+        assert_eq!(&buf[28..], &[0u8; 36]);
+
+        // The destination runs out.
+        let mut buf = [0u8; 16];
+        let (first, second) = buf.split_at_mut(3);
+        let mut dst = IoVecBufferMut::from(vec![first, second]);
+        assert_eq!(dst.copy_from(&src, 0, 6, 32).unwrap(), 10);
+        assert_eq!(&buf[6..], &data[..10]);
+
+        // Nothing is copied with an offset past the end of either buffer, or with no length.
+        let mut buf = [0u8; 16];
+        let mut dst = IoVecBufferMut::from(&mut buf[..]);
+        assert_eq!(dst.copy_from(&src, 32, 0, 8).unwrap(), 0);
+        assert_eq!(dst.copy_from(&src, 100, 0, 8).unwrap(), 0);
+        assert_eq!(dst.copy_from(&src, 0, 16, 8).unwrap(), 0);
+        assert_eq!(dst.copy_from(&src, 0, 0, 0).unwrap(), 0);
+        assert_eq!(buf, [0u8; 16]);
+
+        // Empty buffers.
+        let mut dst = IoVecBufferMut::new();
+        assert_eq!(dst.copy_from(&src, 0, 0, 8).unwrap(), 0);
+        let mut buf = [0u8; 16];
+        let mut dst = IoVecBufferMut::from(&mut buf[..]);
+        assert_eq!(dst.copy_from(&IoVecBuffer::new(), 0, 0, 8).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_iovec_mut_copy_from_guest_memory() {
+        let mem = default_mem();
+        let (mut q, _vq) = read_only_chain(&mem);
+        let data = pattern(256);
+        mem.write_slice(&data, GuestAddress(0x20000)).unwrap();
+        let src = IoVecBuffer::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+
+        // The chain has 4 descriptors of 64 bytes, which the destination segments straddle.
+        let mut buf = [0u8; 256];
+        let (first, rest) = buf.split_at_mut(100);
+        let (second, third) = rest.split_at_mut(1);
+        let mut dst = IoVecBufferMut::from(vec![first, second, third]);
+        assert_eq!(dst.copy_from(&src, 10, 0, 256).unwrap(), 246);
+        assert_eq!(&buf[..246], &data[10..]);
+        assert_eq!(&buf[246..], &[0u8; 10]);
+    }
+
+    #[test]
+    fn test_iovec_mut_fill() {
+        let mem = default_mem();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 64), (0x20300, 64)];
+        let (mut q, vq) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.vecs.len(), 4);
+
+        // The fill spans the 2nd to the 4th memory region.
+        assert_eq!(iovec.fill(0xab, 100, 120).unwrap(), 120);
+        vq.dtable[0].check_data(&[0u8; 64]);
+        vq.dtable[1].check_data(&[[0u8; 36], [0xab; 28]].concat());
+        vq.dtable[2].check_data(&[0xab; 64]);
+        vq.dtable[3].check_data(&[[0xab; 28], [0u8; 36]].concat());
+
+        // Fills stop at the end of the buffer.
+        assert_eq!(iovec.fill(0xcd, 250, 100).unwrap(), 6);
+        vq.dtable[3].check_data(&[[0xab; 28], [0u8; 30], [0xcd; 6]].concat());
+        assert_eq!(iovec.fill(0xcd, 256, 10).unwrap(), 0);
+        assert_eq!(iovec.fill(0xcd, 1000, 10).unwrap(), 0);
+        assert_eq!(iovec.fill(0xcd, 0, 0).unwrap(), 0);
+        vq.dtable[0].check_data(&[0u8; 64]);
+    }
+
+    // Straightforward internet checksum of contiguous bytes.
+    fn reference_checksum(data: &[u8]) -> u16 {
+        let mut sum = 0u32;
+        for word in data.chunks(2) {
+            sum += u32::from(u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]));
+        }
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !u16::try_from(sum).unwrap()
+    }
+
+    #[test]
+    fn test_iovec_checksum() {
+        // Example of RFC 1071.
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        let iovec = IoVecBuffer::from(&data[..]);
+        assert_eq!(iovec.compute_checksum(0, 8).unwrap(), !0xddf2);
+        assert_eq!(reference_checksum(&data), !0xddf2);
+
+        // Segments of odd lengths, and one longer than the chunks the bytes are summed by.
+        let data: Vec<u8> = pattern(2000).iter().map(|byte| byte ^ 0xa5).collect();
+        let splits = [0, 1, 4, 7, 8, 1300, 1301, 1999, 2000];
+        let segments: Vec<&[u8]> = splits.windows(2).map(|w| &data[w[0]..w[1]]).collect();
+        let iovec = IoVecBuffer::from(segments);
+        for (offset, len) in [(0, 2000), (1, 1999), (3, 7), (7, 1), (5, 1296), (1299, 3)] {
+            assert_eq!(
+                iovec.compute_checksum(offset, len).unwrap(),
+                reference_checksum(&data[offset..offset + len]),
+                "offset {offset}, len {len}"
+            );
+        }
+        assert_eq!(iovec.compute_checksum(2000, 0).unwrap(), 0xffff);
+        assert!(matches!(
+            iovec.compute_checksum(1000, 1001),
+            Err(VolatileMemoryError::OutOfBounds { addr: 1000 })
+        ));
+        assert!(matches!(
+            iovec.compute_checksum(usize::MAX, 2),
+            Err(VolatileMemoryError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_iovec_is_zero_range() {
+        // Segments of odd lengths, and one long enough to hold aligned words.
+        fn segments(data: &[u8]) -> Vec<&[u8]> {
+            [0, 3, 4, 11, 150, 200]
+                .windows(2)
+                .map(|w| &data[w[0]..w[1]])
+                .collect()
+        }
+
+        let mut data = vec![0u8; 200];
+        // A single non-zero byte is found in the unaligned bytes as well as in the words.
+        for pos in [0, 3, 5, 10, 11, 12, 80, 148, 149, 150, 199] {
+            data.fill(0);
+            data[pos] = 1;
+            let iovec = IoVecBuffer::from(segments(&data));
+            assert!(!iovec.is_zero_range(0, 200).unwrap(), "pos {pos}");
+            assert!(iovec.is_zero_range(0, pos).unwrap(), "pos {pos}");
+            assert!(
+                iovec.is_zero_range(pos + 1, 199 - pos).unwrap(),
+                "pos {pos}"
+            );
+        }
+
+        data.fill(0);
+        let iovec = IoVecBuffer::from(segments(&data));
+        assert!(iovec.is_zero_range(0, 200).unwrap());
+        assert!(iovec.is_zero_range(200, 0).unwrap());
+        assert!(matches!(
+            iovec.is_zero_range(100, 101),
+            Err(VolatileMemoryError::OutOfBounds { addr: 100 })
+        ));
+        assert!(matches!(
+            iovec.is_zero_range(usize::MAX, 2),
+            Err(VolatileMemoryError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_iovec_mut_write_checksum() {
+        // Header with a checksum field at offset 6, straddling the first two segments.
+        let mut data = pattern(101);
+        data[6..8].fill(0);
+        let checksum = IoVecBuffer::from(&data[..])
+            .compute_checksum(0, 101)
+            .unwrap();
+
+        let mut buf = data.clone();
+        let (first, rest) = buf.split_at_mut(7);
+        let (second, third) = rest.split_at_mut(33);
+        let mut iovec = IoVecBufferMut::from(vec![first, second, third]);
+        iovec.write_checksum_at(checksum, 6).unwrap();
+        assert!(matches!(
+            iovec.write_checksum_at(checksum, 100),
+            Err(VolatileMemoryError::PartialBuffer {
+                expected: 2,
+                completed: 1
+            })
+        ));
+        assert_eq!(buf[6..8], checksum.to_be_bytes());
+
+        // The checksum of data including its checksum is 0.
+        buf[100] = data[100];
+        assert_eq!(reference_checksum(&buf), 0);
+    }
+
+    // Sink taking at most `chunk` bytes per call, and `capacity` bytes in total.
+    struct ChunkedSink {
+        data: Vec<u8>,
+        chunk: usize,
+        capacity: usize,
+        calls: usize,
+    }
+
+    impl ChunkedSink {
+        fn new(chunk: usize, capacity: usize) -> Self {
+            Self {
+                data: Vec::new(),
+                chunk,
+                capacity,
+                calls: 0,
+            }
+        }
+    }
+
+    impl WriteVolatile for ChunkedSink {
+        fn write_volatile<B: BitmapSlice>(
+            &mut self,
+            buf: &VolatileSlice<B>,
+        ) -> Result<usize, VolatileMemoryError> {
+            let count = buf
+                .len()
+                .min(self.chunk)
+                .min(self.capacity - self.data.len());
+            let start = self.data.len();
+            self.data.resize(start + count, 0);
+            buf.subslice(0, count)?.copy_to(&mut self.data[start..]);
+            self.calls += 1;
+            Ok(count)
+        }
+    }
+
+    // Source giving at most `chunk` bytes of `data` per call, then EOF.
+    struct ChunkedSource {
+        data: Vec<u8>,
+        pos: usize,
+        chunk: usize,
+    }
+
+    impl ReadVolatile for ChunkedSource {
+        fn read_volatile<B: BitmapSlice>(
+            &mut self,
+            buf: &mut VolatileSlice<B>,
+        ) -> Result<usize, VolatileMemoryError> {
+            let count = buf.len().min(self.chunk).min(self.data.len() - self.pos);
+            buf.copy_from(&self.data[self.pos..self.pos + count]);
+            self.pos += count;
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn test_iovec_read_exact_volatile_to() {
+        let data = pattern(100);
+        let iovec = IoVecBuffer::from(vec![&data[..7], &data[7..57], &data[57..]]);
 
-        chain(mem, false)
-    }
+        // A single pass stops at the first short write.
+        let mut sink = ChunkedSink::new(8, usize::MAX);
+        assert_eq!(iovec.read_volatile_at(&mut sink, 3, 90).unwrap(), 12);
 
-    fn write_only_chain(mem: &GuestMemoryMmap) -> (Queue, VirtQueue) {
-        let v = vec![0; 256];
-        mem.write_slice(&v, GuestAddress(0x20000)).unwrap();
+        // Short writes are resumed until every byte is taken.
+        let mut sink = ChunkedSink::new(8, usize::MAX);
+        iovec.read_exact_volatile_to(&mut sink, 3, 90).unwrap();
+        assert_eq!(sink.data, &data[3..93]);
+        assert_eq!(sink.calls, 13);
 
-        chain(mem, true)
+        // The sink stops taking bytes.
+        let mut sink = ChunkedSink::new(8, 20);
+        assert!(matches!(
+            iovec.read_exact_volatile_to(&mut sink, 3, 90),
+            Err(IoVecError::PartialTransfer {
+                expected: 90,
+                completed: 20
+            })
+        ));
+        assert_eq!(sink.data, &data[3..23]);
+
+        // The buffer is too short.
+        let mut sink = ChunkedSink::new(8, usize::MAX);
+        assert!(matches!(
+            iovec.read_exact_volatile_to(&mut sink, 50, 60),
+            Err(IoVecError::PartialTransfer {
+                expected: 60,
+                completed: 50
+            })
+        ));
+        iovec.read_exact_volatile_to(&mut sink, 0, 0).unwrap();
     }
 
     #[test]
-    fn test_access_mode() {
+    fn test_iovec_mut_write_all_volatile_from() {
         let mem = default_mem();
-        let (mut q, _) = read_only_chain(&mem);
-        let head = q.pop(&mem).unwrap();
-        IoVecBuffer::from_descriptor_chain(head).unwrap();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 64)];
+        let (mut q, vq) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        let data = pattern(150);
 
-        let (mut q, _) = write_only_chain(&mem);
-        let head = q.pop(&mem).unwrap();
-        IoVecBuffer::from_descriptor_chain(head).unwrap_err();
+        // Short reads are resumed until every byte is written.
+        let mut src = ChunkedSource {
+            data: data.clone(),
+            pos: 0,
+            chunk: 7,
+        };
+        iovec.write_all_volatile_from(&mut src, 20, 150).unwrap();
+        vq.dtable[0].check_data(&[&[0u8; 20][..], &data[..44]].concat());
+        vq.dtable[1].check_data(&data[44..108]);
+        vq.dtable[2].check_data(&[&data[108..], &[0u8; 22][..]].concat());
 
-        let (mut q, _) = read_only_chain(&mem);
-        let head = q.pop(&mem).unwrap();
-        IoVecBufferMut::from_descriptor_chain(head).unwrap_err();
+        let mut sink = ChunkedSink::new(5, usize::MAX);
+        iovec.read_exact_volatile_to(&mut sink, 20, 150).unwrap();
+        assert_eq!(sink.data, data);
 
-        let (mut q, _) = write_only_chain(&mem);
-        let head = q.pop(&mem).unwrap();
-        IoVecBufferMut::from_descriptor_chain(head).unwrap();
+        // The source reaches EOF.
+        let mut src = ChunkedSource {
+            data: vec![0xff; 30],
+            pos: 0,
+            chunk: 7,
+        };
+        assert!(matches!(
+            iovec.write_all_volatile_from(&mut src, 0, 100),
+            Err(IoVecError::PartialTransfer {
+                expected: 100,
+                completed: 30
+            })
+        ));
+        vq.dtable[0].check_data(&[&[0xff; 30][..], &data[10..44]].concat());
+
+        // The buffer is too short.
+        let mut src = ChunkedSource {
+            data: vec![0xee; 100],
+            pos: 0,
+            chunk: 100,
+        };
+        assert!(matches!(
+            iovec.write_all_volatile_from(&mut src, 150, 100),
+            Err(IoVecError::PartialTransfer {
+                expected: 100,
+                completed: 42
+            })
+        ));
+        vq.dtable[2].check_data(&[&data[108..130], &[0xee; 42][..]].concat());
     }
 
-    #[test]
-    fn test_iovec_length() {
-        let mem = default_mem();
-        let (mut q, _) = read_only_chain(&mem);
-        let head = q.pop(&mem).unwrap();
+    // Writes the `iovec`s to a new file with `writev` and returns its content.
+    fn writev_to_file(iovecs: &[iovec]) -> Vec<u8> {
+        let mut file = TempFile::new().unwrap().into_file();
+        let iovcnt = i32::try_from(iovecs.len()).unwrap();
+        // SAFETY: The `iovec`s point into buffers alive for the whole call.
+        let ret = unsafe { libc::writev(file.as_raw_fd(), iovecs.as_ptr(), iovcnt) };
+        assert!(ret >= 0);
 
-        let iovec = IoVecBuffer::from_descriptor_chain(head).unwrap();
-        assert_eq!(iovec.len(), 4 * 64);
+        let mut content = Vec::new();
+        file.rewind().unwrap();
+        file.read_to_end(&mut content).unwrap();
+        content
     }
 
     #[test]
-    fn test_iovec_mut_length() {
-        let mem = default_mem();
-        let (mut q, _) = write_only_chain(&mem);
-        let head = q.pop(&mem).unwrap();
+    fn test_iovec_sub_range() {
+        let data = pattern(100);
+        let iovec = IoVecBuffer::from(vec![&data[..10], &data[10..10], &data[10..60], &data[60..]]);
+        assert_eq!(iovec.as_slice().len(), 4);
+        assert_eq!(writev_to_file(iovec.as_slice()), data);
 
-        let iovec = IoVecBufferMut::from_descriptor_chain(head).unwrap();
-        assert_eq!(iovec.len(), 4 * 64);
+        for (offset, len) in [
+            (0, 100),
+            (12, 30),
+            (5, 70),
+            (10, 50),
+            (60, 40),
+            (99, 1),
+            (30, 0),
+        ] {
+            let sub_range = iovec.sub_range(offset, len).unwrap();
+            let mut expected = vec![0u8; len];
+            iovec.read_exact_volatile_at(&mut expected, offset).unwrap();
+            assert_eq!(writev_to_file(sub_range.as_slice()), expected);
+            // Only the non-empty `iovec`s of the range are kept.
+            assert!(sub_range.as_slice().iter().all(|iov| iov.iov_len > 0));
+        }
+        assert_eq!(iovec.sub_range(12, 30).unwrap().as_slice().len(), 1);
+        assert_eq!(iovec.sub_range(5, 70).unwrap().as_slice().len(), 3);
+        assert!(iovec.sub_range(30, 0).unwrap().as_slice().is_empty());
+
+        // The range goes past the end of the buffer.
+        assert!(iovec.sub_range(60, 41).is_none());
+        assert!(iovec.sub_range(101, 0).is_none());
+        assert!(iovec.sub_range(1, usize::MAX).is_none());
     }
 
     #[test]
-    fn test_iovec_read_at() {
+    fn test_iovec_mut_append_descriptor_chain() {
         let mem = default_mem();
-        let (mut q, _) = read_only_chain(&mem);
-        let head = q.pop(&mem).unwrap();
-
-        let iovec = IoVecBuffer::from_descriptor_chain(head).unwrap();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 64), (0x20300, 64)];
+        let (mut q, vq) = chain_of(&mem, &descs, true);
+        // Split the descriptors in two chains of 64 bytes x 2, followed by a read-only chain.
+        vq.dtable[1].flags.set(VIRTQ_DESC_F_WRITE);
+        vq.dtable[4].set(0x20400, 64, 0, 0);
+        vq.avail.ring[1].set(2);
+        vq.avail.ring[2].set(4);
+        vq.avail.idx.set(3);
 
-        let mut buf = vec![0u8; 257];
+        let mut iovec = IoVecBufferMut::new();
         assert_eq!(
-            iovec
-                .read_volatile_at(&mut buf.as_mut_slice(), 0, 257)
-                .unwrap(),
-            256
+            iovec.append_descriptor_chain(q.pop(&mem).unwrap()).unwrap(),
+            128
         );
-        assert_eq!(buf[0..256], (0..=255).collect::<Vec<_>>());
-        assert_eq!(buf[256], 0);
+        assert_eq!(
+            iovec.append_descriptor_chain(q.pop(&mem).unwrap()).unwrap(),
+            128
+        );
+        assert_eq!(iovec.len(), 256);
+        assert_eq!(iovec.chain_count(), 2);
+        assert_eq!(iovec.chain_ends(), &[128, 256]);
 
-        let mut buf = vec![0; 5];
-        iovec.read_exact_volatile_at(&mut buf[..4], 0).unwrap();
-        assert_eq!(buf, vec![0u8, 1, 2, 3, 0]);
+        // A chain failing to be appended leaves the buffer as it was.
+        assert!(matches!(
+            iovec.append_descriptor_chain(q.pop(&mem).unwrap()),
+            Err(IoVecError::ReadOnlyDescriptor)
+        ));
+        assert_eq!(iovec.len(), 256);
+        assert_eq!(iovec.vecs.len(), 4);
+        assert_eq!(iovec.chain_ends(), &[128, 256]);
 
-        iovec.read_exact_volatile_at(&mut buf, 0).unwrap();
-        assert_eq!(buf, vec![0u8, 1, 2, 3, 4]);
+        // The chains are written as a single buffer.
+        let payload = pattern(200);
+        iovec.write_all_volatile_at(&payload, 0).unwrap();
+        vq.dtable[0].check_data(&payload[..64]);
+        vq.dtable[1].check_data(&payload[64..128]);
+        vq.dtable[2].check_data(&payload[128..192]);
+        vq.dtable[3].check_data(&[&payload[192..], &[0u8; 56][..]].concat());
 
-        iovec.read_exact_volatile_at(&mut buf, 1).unwrap();
-        assert_eq!(buf, vec![1u8, 2, 3, 4, 5]);
+        assert_eq!(iovec.chain_used_lens(200).collect::<Vec<_>>(), [128, 72]);
+        assert_eq!(iovec.chain_used_lens(128).collect::<Vec<_>>(), [128]);
+        assert_eq!(iovec.chain_used_lens(100).collect::<Vec<_>>(), [100]);
+        assert_eq!(iovec.chain_used_lens(256).collect::<Vec<_>>(), [128, 128]);
+        assert_eq!(iovec.chain_used_lens(0).count(), 0);
 
-        iovec.read_exact_volatile_at(&mut buf, 60).unwrap();
-        assert_eq!(buf, vec![60u8, 61, 62, 63, 64]);
+        // Loading a chain replaces all the appended ones.
+        let (mut q, _) = chain_of(&mem, &descs[..1], true);
+        iovec.load_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        assert_eq!(iovec.chain_ends(), &[64]);
+        iovec.clear();
+        assert_eq!(iovec.chain_count(), 0);
+    }
 
+    // Returns the reading and writing ends of a non-blocking pipe.
+    fn nonblocking_pipe() -> (File, File) {
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two file descriptors, and the result is checked.
         assert_eq!(
-            iovec
-                .read_volatile_at(&mut buf.as_mut_slice(), 252, 5)
-                .unwrap(),
-            4
+            unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK) },
+            0
         );
-        assert_eq!(buf[0..4], vec![252u8, 253, 254, 255]);
+        // SAFETY: Both file descriptors were just created and are owned by nothing else.
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+    }
 
-        assert!(matches!(
-            iovec.read_exact_volatile_at(&mut buf, 252),
-            Err(VolatileMemoryError::PartialBuffer {
-                expected: 5,
-                completed: 4
-            })
-        ));
-        assert!(matches!(
-            iovec.read_exact_volatile_at(&mut buf, 256),
-            Err(VolatileMemoryError::OutOfBounds { addr: 256 })
-        ));
+    // Reads all the bytes available in a non-blocking pipe.
+    fn drain_pipe(reader: &mut File) -> Vec<u8> {
+        let mut data = Vec::new();
+        let err = reader.read_to_end(&mut data).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        data
     }
 
     #[test]
-    fn test_iovec_mut_write_at() {
-        let mem = default_mem();
-        let (mut q, vq) = write_only_chain(&mem);
+    fn test_iovec_try_read_volatile_at() {
+        let (mut reader, mut writer) = nonblocking_pipe();
+        // SAFETY: The file descriptor is valid.
+        let capacity = unsafe { libc::fcntl(writer.as_raw_fd(), libc::F_GETPIPE_SZ) };
+        let capacity = usize::try_from(capacity).unwrap();
+        let data = pattern(capacity + 1000);
+        let iovec = IoVecBuffer::from(vec![
+            &data[..100],
+            &data[100..capacity + 500],
+            &data[capacity + 500..],
+        ]);
 
-        // This is a descriptor chain with 4 elements 64 bytes long each.
-        let head = q.pop(&mem).unwrap();
-
-        let mut iovec = IoVecBufferMut::from_descriptor_chain(head).unwrap();
-        let buf = vec![0u8, 1, 2, 3, 4];
+        // The pipe fills up part way through the buffer, and the bytes written are reported.
+        let Transferred::Blocked(written) = iovec
+            .try_read_volatile_at(&mut writer, 0, data.len())
+            .unwrap()
+        else {
+            panic!("the pipe should be full");
+        };
+        assert!(written > 0 && written < data.len());
 
-        // One test vector for each part of the chain
-        let mut test_vec1 = vec![0u8; 64];
-        let mut test_vec2 = vec![0u8; 64];
-        let test_vec3 = vec![0u8; 64];
-        let mut test_vec4 = vec![0u8; 64];
+        // Nothing can be written to the full pipe, which the other variants report as an error.
+        assert_eq!(
+            iovec
+                .try_read_volatile_at(&mut writer, written, data.len() - written)
+                .unwrap(),
+            Transferred::Blocked(0)
+        );
+        match iovec.read_volatile_at(&mut writer, written, data.len() - written) {
+            Err(VolatileMemoryError::IOError(err)) => assert_eq!(err.kind(), ErrorKind::WouldBlock),
+            res => panic!("unexpected result {res:?}"),
+        }
 
-        // Control test: Initially all three regions should be zero
-        iovec.write_all_volatile_at(&test_vec1, 0).unwrap();
-        iovec.write_all_volatile_at(&test_vec2, 64).unwrap();
-        iovec.write_all_volatile_at(&test_vec3, 128).unwrap();
-        iovec.write_all_volatile_at(&test_vec4, 192).unwrap();
-        vq.dtable[0].check_data(&test_vec1);
-        vq.dtable[1].check_data(&test_vec2);
-        vq.dtable[2].check_data(&test_vec3);
-        vq.dtable[3].check_data(&test_vec4);
+        // The rest is written once the pipe is drained.
+        let mut received = drain_pipe(&mut reader);
+        assert_eq!(received.len(), written);
+        assert_eq!(
+            iovec
+                .try_read_volatile_at(&mut writer, written, data.len() - written)
+                .unwrap(),
+            Transferred::Complete(data.len() - written)
+        );
+        received.extend(drain_pipe(&mut reader));
+        assert_eq!(received, data);
+    }
 
-        // Let's initialize test_vec1 with our buffer.
-        test_vec1[..buf.len()].copy_from_slice(&buf);
-        // And write just a part of it
-        iovec.write_all_volatile_at(&buf[..3], 0).unwrap();
-        // Not all 5 bytes from buf should be written in memory,
-        // just 3 of them.
-        vq.dtable[0].check_data(&[0u8, 1, 2, 0, 0]);
-        vq.dtable[1].check_data(&test_vec2);
-        vq.dtable[2].check_data(&test_vec3);
-        vq.dtable[3].check_data(&test_vec4);
-        // But if we write the whole `buf` in memory then all
-        // of it should be observable.
-        iovec.write_all_volatile_at(&buf, 0).unwrap();
-        vq.dtable[0].check_data(&test_vec1);
-        vq.dtable[1].check_data(&test_vec2);
-        vq.dtable[2].check_data(&test_vec3);
-        vq.dtable[3].check_data(&test_vec4);
+    #[test]
+    fn test_iovec_mut_try_write_volatile_at() {
+        let mem = default_mem();
+        let descs = [(0x20000, 64), (0x20100, 64), (0x20200, 64)];
+        let (mut q, vq) = chain_of(&mem, &descs, true);
+        let mut iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        let (mut reader, mut writer) = nonblocking_pipe();
+        let data = pattern(192);
 
-        // We are now writing with an offset of 1. So, initialize
-        // the corresponding part of `test_vec1`
-        test_vec1[1..buf.len() + 1].copy_from_slice(&buf);
-        iovec.write_all_volatile_at(&buf, 1).unwrap();
-        vq.dtable[0].check_data(&test_vec1);
-        vq.dtable[1].check_data(&test_vec2);
-        vq.dtable[2].check_data(&test_vec3);
-        vq.dtable[3].check_data(&test_vec4);
+        // The pipe runs out of bytes part way through the buffer.
+        writer.write_all(&data[..100]).unwrap();
+        assert_eq!(
+            iovec.try_write_volatile_at(&mut reader, 0, 192).unwrap(),
+            Transferred::Blocked(100)
+        );
+        assert_eq!(
+            iovec.try_write_volatile_at(&mut reader, 100, 92).unwrap(),
+            Transferred::Blocked(0)
+        );
 
-        // Perform a write that traverses two of the underlying
-        // regions. Writing at offset 60 should write 4 bytes on the
-        // first region and one byte on the second
-        test_vec1[60..64].copy_from_slice(&buf[0..4]);
-        test_vec2[0] = 4;
-        iovec.write_all_volatile_at(&buf, 60).unwrap();
-        vq.dtable[0].check_data(&test_vec1);
-        vq.dtable[1].check_data(&test_vec2);
-        vq.dtable[2].check_data(&test_vec3);
-        vq.dtable[3].check_data(&test_vec4);
+        writer.write_all(&data[100..]).unwrap();
+        assert_eq!(
+            iovec.try_write_volatile_at(&mut reader, 100, 92).unwrap(),
+            Transferred::Complete(92)
+        );
+        vq.dtable[0].check_data(&data[..64]);
+        vq.dtable[1].check_data(&data[64..128]);
+        vq.dtable[2].check_data(&data[128..]);
 
-        test_vec4[63] = 3;
-        test_vec4[62] = 2;
-        test_vec4[61] = 1;
-        // Now perform a write that does not fit in the buffer. Try writing
-        // 5 bytes at offset 252 (only 4 bytes left).
-        test_vec4[60..64].copy_from_slice(&buf[0..4]);
+        // The pipe is closed.
+        drop(writer);
         assert_eq!(
-            iovec.write_volatile_at(&mut &*buf, 252, buf.len()).unwrap(),
-            4
+            iovec.try_write_volatile_at(&mut reader, 0, 192).unwrap(),
+            Transferred::Complete(0)
         );
-        vq.dtable[0].check_data(&test_vec1);
-        vq.dtable[1].check_data(&test_vec2);
-        vq.dtable[2].check_data(&test_vec3);
-        vq.dtable[3].check_data(&test_vec4);
+    }
 
-        // Trying to add past the end of the buffer should not write anything
+    #[test]
+    fn test_iovec_mut_queue_overlap() {
+        let mem = default_mem();
+        let descs = [(0x20000, 64), (0x20100, 64)];
+
+        let (mut q, _) = chain_of(&mem, &descs, true);
+        let rings = q.rings();
+        let iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
+        iovec.check_queue_overlap(&rings).unwrap();
+
+        // The second descriptor points into the used ring.
+        let (mut q, vq) = chain_of(&mem, &descs, true);
+        let used_elem = vq.used_start().unchecked_add(8);
+        vq.dtable[1].set(used_elem.raw_value(), 16, VIRTQ_DESC_F_WRITE, 0);
+        let rings = q.rings();
+        let iovec = IoVecBufferMut::from_descriptor_chain(q.pop(&mem).unwrap()).unwrap();
         assert!(matches!(
-            iovec.write_all_volatile_at(&buf, 256),
-            Err(VolatileMemoryError::OutOfBounds { addr: 256 })
+            iovec.check_queue_overlap(&rings),
+            Err(IoVecError::OverlapsQueue(addr)) if addr == used_elem.raw_value()
         ));
-        vq.dtable[0].check_data(&test_vec1);
-        vq.dtable[1].check_data(&test_vec2);
-        vq.dtable[2].check_data(&test_vec3);
-        vq.dtable[3].check_data(&test_vec4);
+
+        // Buffers not built from guest memory are not checked.
+        let mut buf = [0u8; 16];
+        IoVecBufferMut::from(&mut buf[..])
+            .check_queue_overlap(&rings)
+            .unwrap();
     }
 }
 
 #[cfg(kani)]
 mod verification {
+    use std::marker::PhantomData;
     use std::mem::ManuallyDrop;
 
     use libc::{c_void, iovec};
     use vm_memory::bitmap::BitmapSlice;
     use vm_memory::VolatileSlice;
 
-    use super::{IoVecBuffer, IoVecBufferMut, IoVecVec};
+    use super::{
+        add_descriptor_len, push_iovec, ChainEndVec, GuestAddressVec, IoVecBuffer, IoVecBufferMut,
+        IoVecError, IoVecVec,
+    };
+    use crate::devices::virtio::queue::QueueRings;
+    use crate::vstate::memory::GuestAddress;
 
     // Maximum memory size to use for our buffers. For the time being 1KB.
     const GUEST_MEMORY_SIZE: usize = 1 << 10;
@@ -636,6 +4008,8 @@ mod verification {
             // `Descriptor` in the chain is a valid, i.e. it is memory with then guest's memory
             // mmap. The assumption, here, that the last address is within the memory object's
             // bound substitutes these checks that `IoVecBuffer(Mut)::new() performs.`
+            // Zero-length `iovec`s are not built from descriptor chains, but still can be by the
+            // test-only constructors, so the proofs cover them too.
             let addr: usize = kani::any();
             let iov_len: usize =
                 kani::any_where(|&len| matches!(addr.checked_add(len), Some(x) if x <= size));
@@ -648,17 +4022,22 @@ mod verification {
         (vecs, len)
     }
 
-    impl kani::Arbitrary for IoVecBuffer {
+    // The guest memory objects backing the buffers are leaked, hence the `'static` lifetimes.
+    impl kani::Arbitrary for IoVecBuffer<'static> {
         fn any() -> Self {
             // We only read from `IoVecBuffer`, so create here a guest memory object, with arbitrary
             // contents and size up to GUEST_MEMORY_SIZE.
             let mut mem = ManuallyDrop::new(kani::vec::exact_vec::<u8, GUEST_MEMORY_SIZE>());
             let (vecs, len) = create_iovecs(mem.as_mut_ptr(), mem.len());
-            Self { vecs, len }
+            Self {
+                vecs,
+                len,
+                _mem: PhantomData,
+            }
         }
     }
 
-    impl kani::Arbitrary for IoVecBufferMut {
+    impl kani::Arbitrary for IoVecBufferMut<'static> {
         fn any() -> Self {
             // We only write into `IoVecBufferMut` objects, so we can simply create a guest memory
             // object initialized to zeroes, trying to be nice to Kani.
@@ -670,7 +4049,13 @@ mod verification {
             };
 
             let (vecs, len) = create_iovecs(mem, GUEST_MEMORY_SIZE);
-            Self { vecs, len }
+            Self {
+                vecs,
+                addrs: GuestAddressVec::new(),
+                len,
+                chain_ends: ChainEndVec::new(),
+                mem: None,
+            }
         }
     }
 
@@ -739,6 +4124,67 @@ mod verification {
         );
     }
 
+    #[kani::proof]
+    #[kani::unwind(5)]
+    #[kani::solver(cadical)]
+    fn verify_read_obj_from_iovec() {
+        let iov: IoVecBuffer = kani::any();
+        let offset: u32 = kani::any();
+        let remaining = iov.len().saturating_sub(offset) as usize;
+
+        // The memory regions of `iov` lie within the guest memory object, so Kani flags any read
+        // outside of them. The object is read if, and only if, all its bytes are in the buffer.
+        match iov.read_obj::<u64>(offset as usize) {
+            Ok(_) => assert!(remaining >= 8),
+            Err(IoVecError::ShortBuffer {
+                offset: err_offset,
+                size: 8,
+                remaining: err_remaining,
+            }) => {
+                assert_eq!(err_offset, offset as usize);
+                assert_eq!(err_remaining, remaining);
+                assert!(remaining < 8);
+            }
+            Err(_) => panic!("unexpected error reading an object from the buffer"),
+        }
+    }
+
+    #[kani::proof]
+    #[kani::unwind(5)]
+    #[kani::solver(cadical)]
+    fn verify_descriptor_lengths() {
+        // Mimics the way descriptors are added to a buffer, some of them being merged with the
+        // previous one: as long as the chain is accepted, the length of the buffer is exactly the
+        // sum of the lengths of its `iovec`s, and it does not exceed the size of guest memory.
+        let mem_size: u64 = kani::any();
+        let mut vecs = IoVecVec::new();
+        let mut len = 0u32;
+        for _ in 0..MAX_DESC_LENGTH {
+            let iov_base = kani::any::<usize>() as *mut c_void;
+            let desc_len: u32 = kani::any();
+            if push_iovec(
+                &mut vecs,
+                iov_base,
+                desc_len as usize,
+                kani::any(),
+                MAX_DESC_LENGTH,
+            )
+            .is_err()
+            {
+                return;
+            }
+            match add_descriptor_len(len, desc_len, mem_size) {
+                Ok(total) => len = total,
+                Err(_) => return,
+            }
+            assert!(u64::from(len) <= mem_size);
+            assert_eq!(
+                vecs.iter().map(|iov| iov.iov_len).sum::<usize>(),
+                len as usize
+            );
+        }
+    }
+
     #[kani::proof]
     #[kani::unwind(5)]
     #[kani::solver(cadical)]
@@ -768,4 +4214,89 @@ mod verification {
             buf.len().min(iov_mut.len().saturating_sub(offset) as usize)
         );
     }
+
+    #[kani::proof]
+    #[kani::unwind(5)]
+    #[kani::solver(cadical)]
+    fn verify_write_obj_to_iovec() {
+        let mut iov_mut: IoVecBufferMut = kani::any();
+        let obj: u64 = kani::any();
+        let offset: u32 = kani::any();
+        let remaining = iov_mut.len().saturating_sub(offset) as usize;
+
+        // The object is written if, and only if, all its bytes fit in the buffer.
+        match iov_mut.write_obj(&obj, offset as usize) {
+            Ok(()) => assert!(remaining >= 8),
+            Err(IoVecError::TruncatedWrite {
+                offset: err_offset,
+                size: 8,
+                remaining: err_remaining,
+            }) => {
+                assert_eq!(err_offset, offset as usize);
+                assert_eq!(err_remaining, remaining);
+                assert!(remaining < 8);
+            }
+            Err(_) => panic!("unexpected error writing an object to the buffer"),
+        }
+    }
+
+    // Picks an arbitrary range of the guest memory, e.g. holding one of the rings of a queue.
+    fn any_ring() -> (GuestAddress, usize) {
+        let start: usize = kani::any_where(|&start| start < GUEST_MEMORY_SIZE);
+        let len: usize = kani::any_where(|&len| start + len <= GUEST_MEMORY_SIZE);
+        (GuestAddress(start as u64), len)
+    }
+
+    #[kani::proof]
+    #[kani::unwind(5)]
+    #[kani::solver(cadical)]
+    fn verify_write_outside_queue() {
+        // Guest memory mapped at guest address 0, initialized to zeroes.
+        let mem = unsafe {
+            std::alloc::alloc_zeroed(std::alloc::Layout::from_size_align_unchecked(
+                GUEST_MEMORY_SIZE,
+                16,
+            ))
+        };
+        let (vecs, len) = create_iovecs(mem, GUEST_MEMORY_SIZE);
+        let addrs = vecs
+            .iter()
+            .map(|iov| GuestAddress((iov.iov_base as usize - mem as usize) as u64))
+            .collect();
+        let mut iov_mut = IoVecBufferMut {
+            vecs,
+            addrs,
+            len,
+            chain_ends: ChainEndVec::new(),
+            mem: None,
+        };
+
+        // The queue is anywhere in guest memory, as long as the buffer does not overlap it.
+        let rings = QueueRings {
+            desc_table: any_ring(),
+            avail_ring: any_ring(),
+            used_ring: any_ring(),
+        };
+        kani::assume(iov_mut.check_queue_overlap(&rings).is_ok());
+
+        let mut buf = vec![0xff; GUEST_MEMORY_SIZE];
+        let offset: u32 = kani::any();
+        iov_mut
+            .write_volatile_at(
+                &mut KaniBuffer(&mut buf),
+                offset as usize,
+                GUEST_MEMORY_SIZE,
+            )
+            .unwrap();
+
+        // Whatever is written, none of the bytes of the queue changes.
+        let (start, size) = match kani::any_where(|&ring: &u8| ring < 3) {
+            0 => rings.desc_table,
+            1 => rings.avail_ring,
+            _ => rings.used_ring,
+        };
+        let byte: usize =
+            kani::any_where(|&byte| byte >= start.0 as usize && byte < start.0 as usize + size);
+        assert_eq!(unsafe { *mem.add(byte) }, 0);
+    }
 }
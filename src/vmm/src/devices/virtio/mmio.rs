@@ -14,6 +14,8 @@ use utils::byte_order;
 use crate::devices::virtio::device::{IrqType, VirtioDevice};
 use crate::devices::virtio::device_status;
 use crate::devices::virtio::queue::Queue;
+use crate::devices::ErrorSeverity;
+use crate::events::{DeviceErrorKind, VmmEvent, EVENTS};
 use crate::logger::{error, warn};
 use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
 
@@ -92,6 +94,11 @@ impl MmioTransport {
         self.device.clone()
     }
 
+    /// Gets the guest memory the device is given access to.
+    pub fn mem(&self) -> &GuestMemoryMmap {
+        &self.mem
+    }
+
     fn check_device_status(&self, set: u32, clr: u32) -> bool {
         self.device_status & (set | clr) == set
     }
@@ -199,7 +206,13 @@ impl MmioTransport {
                             .interrupt_trigger()
                             .trigger_irq(IrqType::Config);
 
-                        error!("Failed to activate virtio device: {}", err)
+                        error!("Failed to activate virtio device: {}", err);
+                        EVENTS.publish(VmmEvent::DeviceError {
+                            device_type: self.locked_device().device_type(),
+                            kind: DeviceErrorKind::Activation,
+                            severity: ErrorSeverity::Fatal,
+                            message: err.to_string(),
+                        });
                     }
                 }
             }
@@ -280,7 +293,14 @@ impl MmioTransport {
                             VIRTIO_MMIO_INT_VRING
                         }
                     }
-                    0x70 => self.device_status,
+                    0x70 => {
+                        // The device stops on its own when it hits a fatal error while
+                        // processing its events.
+                        if self.locked_device().needs_reset() {
+                            self.device_status |= device_status::DEVICE_NEEDS_RESET;
+                        }
+                        self.device_status
+                    }
                     0xfc => self.config_generation,
                     _ => {
                         warn!("unknown virtio mmio register read: 0x{:x}", offset);
@@ -372,6 +392,36 @@ impl MmioTransport {
     }
 }
 
+/// An empty virtio-over-MMIO slot, which a device can be plugged in after boot.
+///
+/// It reports the virtio device ID 0, which guest drivers treat as a placeholder without function,
+/// so that the guest knows about the slot without probing a device in it.
+#[derive(Debug, Default)]
+pub struct MmioPlaceholder;
+
+impl MmioPlaceholder {
+    pub fn bus_read(&mut self, offset: u64, data: &mut [u8]) {
+        let v = match offset {
+            0x0 => MMIO_MAGIC_VALUE,
+            0x04 => MMIO_VERSION,
+            _ => 0,
+        };
+        if data.len() == 4 {
+            byte_order::write_le_u32(data, v);
+        } else {
+            data.fill(0);
+        }
+    }
+
+    pub fn bus_write(&mut self, offset: u64, data: &[u8]) {
+        warn!(
+            "virtio mmio write to an empty slot: 0x{:x}:0x{:x}",
+            offset,
+            data.len()
+        );
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use utils::byte_order::{read_le_u32, write_le_u32};
@@ -395,6 +445,7 @@ pub(crate) mod tests {
         device_activated: bool,
         config_bytes: [u8; 0xeff],
         activate_should_error: bool,
+        needs_reset: bool,
     }
 
     impl DummyDevice {
@@ -411,6 +462,7 @@ pub(crate) mod tests {
                 device_activated: false,
                 config_bytes: [0; 0xeff],
                 activate_should_error: false,
+                needs_reset: false,
             }
         }
 
@@ -474,6 +526,10 @@ pub(crate) mod tests {
         fn is_activated(&self) -> bool {
             self.device_activated
         }
+
+        fn needs_reset(&self) -> bool {
+            self.needs_reset
+        }
     }
 
     fn set_device_status(d: &mut MmioTransport, status: u32) {
@@ -514,12 +570,37 @@ pub(crate) mod tests {
         assert!(!d.are_queues_valid());
     }
 
+    #[test]
+    fn test_placeholder() {
+        let mut placeholder = MmioPlaceholder;
+        let mut buf = [0xffu8, 0, 0xfe, 0];
+
+        placeholder.bus_read(0x00, &mut buf[..]);
+        assert_eq!(read_le_u32(&buf[..]), MMIO_MAGIC_VALUE);
+        placeholder.bus_read(0x04, &mut buf[..]);
+        assert_eq!(read_le_u32(&buf[..]), MMIO_VERSION);
+        // The device ID of a placeholder, and everything else, is 0.
+        for offset in [0x08, 0x0c, 0x10, 0x70, 0x100] {
+            write_le_u32(&mut buf[..], 0xdead_beef);
+            placeholder.bus_read(offset, &mut buf[..]);
+            assert_eq!(read_le_u32(&buf[..]), 0);
+        }
+        let mut byte = [0xffu8];
+        placeholder.bus_read(0x100, &mut byte);
+        assert_eq!(byte, [0]);
+
+        // Writes are ignored.
+        placeholder.bus_write(0x70, &[0xf, 0, 0, 0]);
+        placeholder.bus_read(0x70, &mut buf[..]);
+        assert_eq!(read_le_u32(&buf[..]), 0);
+    }
+
     #[test]
     fn test_bus_device_read() {
         let m = single_region_mem(0x1000);
         let mut d = MmioTransport::new(m, Arc::new(Mutex::new(DummyDevice::new())), false);
 
-        let mut buf = vec![0xff, 0, 0xfe, 0];
+        let mut buf = [0xffu8, 0, 0xfe, 0];
         let buf_copy = buf.to_vec();
 
         // The following read shouldn't be valid, because the length of the buf is not 4.
@@ -961,6 +1042,24 @@ pub(crate) mod tests {
         assert!(d.locked_device().is_activated());
     }
 
+    #[test]
+    fn test_bus_device_needs_reset() {
+        let m = single_region_mem(0x1000);
+        let device = Arc::new(Mutex::new(DummyDevice::new()));
+        let mut d = MmioTransport::new(m, device.clone(), false);
+        let mut buf = [0; 4];
+        activate_device(&mut d);
+
+        d.bus_read(0x70, &mut buf[..]);
+        assert_eq!(read_le_u32(&buf[..]) & DEVICE_NEEDS_RESET, 0);
+
+        // The device stopped after a fatal error.
+        device.lock().unwrap().needs_reset = true;
+        d.bus_read(0x70, &mut buf[..]);
+        assert_ne!(read_le_u32(&buf[..]) & DEVICE_NEEDS_RESET, 0);
+        assert_ne!(d.device_status & DEVICE_NEEDS_RESET, 0);
+    }
+
     #[test]
     fn test_get_avail_features() {
         let dummy_dev = DummyDevice::new();
@@ -11,6 +11,8 @@ use std::any::Any;
 
 pub mod balloon;
 pub mod block;
+#[cfg(feature = "memory-guards")]
+pub mod canary;
 pub mod device;
 pub mod gen;
 pub mod iovec;
@@ -18,6 +20,7 @@ pub mod mmio;
 pub mod net;
 pub mod persist;
 pub mod queue;
+pub mod queue_metrics;
 pub mod rng;
 pub mod test_utils;
 pub mod vhost_user;
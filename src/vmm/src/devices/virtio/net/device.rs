@@ -7,31 +7,50 @@
 
 #[cfg(not(test))]
 use std::io::Read;
-use std::mem;
 use std::net::Ipv4Addr;
+use std::os::unix::io::RawFd;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{io, mem};
 
-use libc::EAGAIN;
+use libc::{EAGAIN, EBADFD, EIO};
 use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
 use utils::eventfd::EventFd;
-use utils::net::mac::MacAddr;
+use utils::net::mac::{MacAddr, MAC_ADDR_LEN};
 use utils::u64_to_usize;
 use vm_memory::GuestMemoryError;
 
-use crate::devices::virtio::device::{DeviceState, IrqTrigger, IrqType, VirtioDevice};
+use crate::auto_pause::record_activity;
+use crate::devices::virtio::device::{
+    check_queue_size, pin_virtio_features, DeviceState, IrqTrigger, IrqType, QueueSizeError,
+    VirtioDevice, VirtioFeaturesPinError,
+};
 use crate::devices::virtio::gen::virtio_blk::VIRTIO_F_VERSION_1;
 use crate::devices::virtio::gen::virtio_net::{
-    virtio_net_hdr_v1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM, VIRTIO_NET_F_GUEST_TSO4,
-    VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC,
+    virtio_net_hdr_v1, VIRTIO_NET_CTRL_ANNOUNCE, VIRTIO_NET_CTRL_ANNOUNCE_ACK, VIRTIO_NET_CTRL_MAC,
+    VIRTIO_NET_CTRL_MAC_TABLE_SET, VIRTIO_NET_CTRL_MQ, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MIN,
+    VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET, VIRTIO_NET_CTRL_RX, VIRTIO_NET_CTRL_RX_ALLMULTI,
+    VIRTIO_NET_CTRL_RX_PROMISC, VIRTIO_NET_ERR, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_CTRL_RX,
+    VIRTIO_NET_F_CTRL_VQ, VIRTIO_NET_F_GUEST_ANNOUNCE, VIRTIO_NET_F_GUEST_CSUM,
+    VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_UFO,
+    VIRTIO_NET_F_MAC, VIRTIO_NET_F_MQ, VIRTIO_NET_F_MRG_RXBUF, VIRTIO_NET_F_MTU,
+    VIRTIO_NET_F_STATUS, VIRTIO_NET_OK, VIRTIO_NET_S_ANNOUNCE, VIRTIO_NET_S_LINK_UP,
+};
+use crate::devices::virtio::gen::virtio_ring::{
+    VIRTIO_RING_F_EVENT_IDX, VIRTIO_RING_F_INDIRECT_DESC,
 };
-use crate::devices::virtio::gen::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
-use crate::devices::virtio::iovec::IoVecBuffer;
+use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut, IoVecError};
 use crate::devices::virtio::net::metrics::{NetDeviceMetrics, NetMetricsPerDevice};
 use crate::devices::virtio::net::tap::Tap;
 use crate::devices::virtio::net::{
-    gen, NetError, NetQueue, MAX_BUFFER_SIZE, NET_QUEUE_SIZES, RX_INDEX, TX_INDEX,
+    gen, NetError, NetQueue, MAX_BUFFER_SIZE, NET_MAX_MTU, NET_MAX_QUEUE_PAIRS, NET_MAX_QUEUE_SIZE,
+    NET_MIN_MTU, NET_MIN_QUEUE_SIZE, NET_NUM_QUEUES, RX_INDEX, TX_INDEX,
+};
+use crate::devices::virtio::queue::{
+    recycle_chains, ChainLayout, DescriptorChain, Queue, QueueRings, FIRECRACKER_MAX_QUEUE_SIZE,
 };
-use crate::devices::virtio::queue::{DescriptorChain, Queue};
 use crate::devices::virtio::{ActivateError, TYPE_NET};
 use crate::devices::{report_net_event_fail, DeviceError};
 use crate::dumbo::pdu::arp::ETH_IPV4_FRAME_LEN;
@@ -40,10 +59,44 @@ use crate::logger::{IncMetric, METRICS};
 use crate::mmds::data_store::Mmds;
 use crate::mmds::ns::MmdsNetworkStack;
 use crate::rate_limiter::{BucketUpdate, RateLimiter, TokenType};
-use crate::vstate::memory::{ByteValued, Bytes, GuestMemoryMmap};
+use crate::vmm_config::auto_pause::ActivitySignal;
+use crate::vmm_config::RateLimiterConfig;
+use crate::vstate::memory::{ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
 
 const FRAME_HEADER_MAX_LEN: usize = PAYLOAD_OFFSET + ETH_IPV4_FRAME_LEN;
 
+/// Delay before reading again from a tap that returned a fatal error. The delay doubles with
+/// every consecutive fatal error, up to `TAP_READ_BACKOFF_MAX`.
+const TAP_READ_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const TAP_READ_BACKOFF_MAX: Duration = Duration::from_secs(2);
+/// Minimum interval between two logs of unexpected tap read errors.
+const TAP_READ_ERROR_LOG_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval at which the MMDS network stack is polled while guest requests to the MMDS wait for
+/// their parked response.
+const MMDS_WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Maximum number of TX frames popped from the queue at once.
+const TX_POP_BATCH_SIZE: usize = 64;
+/// Maximum number of addresses of each MAC table of the RX filter.
+const RX_FILTER_MAC_TABLE_LEN: usize = 64;
+/// Maximum length of the commands read from the control queue, the longest one handled being
+/// `VIRTIO_NET_CTRL_MAC_TABLE_SET` with full tables: the class and the command, then two tables
+/// made of the number of their addresses and of the addresses themselves.
+const CTRL_COMMAND_MAX_LEN: usize =
+    2 + 2 * (mem::size_of::<u32>() + RX_FILTER_MAC_TABLE_LEN * mem::size_of::<MacAddr>());
+
+// Outcome of reading a frame of the tap straight into an RX descriptor chain.
+#[derive(Debug, PartialEq, Eq)]
+enum DirectRx {
+    /// The frame was handed to the guest.
+    Delivered,
+    /// The rate limiter has no budget for the frame, which waits in `rx_frame_buf`.
+    Deferred,
+    /// The next chain can not take a frame of any size, or there is none.
+    NoChain,
+    /// The frame was dropped by the RX filter, and the chain given back.
+    Filtered,
+}
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 enum FrontendError {
     /// Add user.
@@ -54,8 +107,8 @@ enum FrontendError {
     EmptyQueue,
     /// Guest memory error: {0}
     GuestMemory(GuestMemoryError),
-    /// Read only descriptor.
-    ReadOnlyDescriptor,
+    /// Invalid descriptor chain: {0}
+    InvalidChain(IoVecError),
 }
 
 pub(crate) const fn vnet_hdr_len() -> usize {
@@ -97,37 +150,503 @@ fn init_vnet_hdr(buf: &mut [u8]) {
 #[repr(C)]
 pub struct ConfigSpace {
     pub guest_mac: MacAddr,
+    // The link status, only valid with `VIRTIO_NET_F_STATUS`. The link is always up, and
+    // `VIRTIO_NET_S_ANNOUNCE` is set while the driver is asked to announce the guest.
+    status: u16,
+    /// The number of pairs of RX and TX queues, only set with `VIRTIO_NET_F_MQ`.
+    pub max_virtqueue_pairs: u16,
+    /// The MTU offered to the guest, only set with `VIRTIO_NET_F_MTU`.
+    pub mtu: u16,
 }
 
 // SAFETY: `ConfigSpace` contains only PODs in `repr(C)` or `repr(transparent)`, without padding.
 unsafe impl ByteValued for ConfigSpace {}
 
+// SAFETY: `virtio_net_hdr_v1` contains only integers in `repr(C)`, without padding.
+unsafe impl ByteValued for virtio_net_hdr_v1 {}
+
+/// Backoff applied to tap reads after the tap returned a fatal error.
+///
+/// While backing off, the tap is deregistered from the event manager and no frames are read from
+/// it, so that a broken tap (e.g. a flapping macvtap) does not keep the RX path spinning.
+#[derive(Debug)]
+pub(crate) struct TapReadBackoff {
+    /// Timer expiring when the tap should be read again.
+    pub(crate) timer: TimerFd,
+    /// Delay before the next attempt to read from the tap.
+    delay: Duration,
+    /// Whether tap reads are suspended until the timer expires.
+    active: bool,
+    /// Whether the tap is registered with the event manager for reads.
+    pub(crate) tap_registered: bool,
+    /// Last time an unexpected tap read error was logged.
+    last_error_log: Option<Instant>,
+}
+
+impl TapReadBackoff {
+    fn new() -> Result<Self, io::Error> {
+        Ok(TapReadBackoff {
+            timer: TimerFd::new_custom(ClockId::Monotonic, true, true)?,
+            delay: TAP_READ_BACKOFF_MIN,
+            active: false,
+            tap_registered: false,
+            last_error_log: None,
+        })
+    }
+
+    /// Whether tap reads are currently suspended.
+    pub(crate) fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Suspends tap reads for the current delay, which is returned, and doubles the delay of the
+    /// next backoff.
+    fn start(&mut self) -> Duration {
+        let delay = self.delay;
+        self.active = true;
+        self.timer
+            .set_state(TimerState::Oneshot(delay), SetTimeFlags::Default);
+        self.delay = std::cmp::min(delay * 2, TAP_READ_BACKOFF_MAX);
+        delay
+    }
+
+    /// Resumes tap reads once the timer expired.
+    fn expire(&mut self) {
+        self.timer.read();
+        self.active = false;
+    }
+
+    /// Resets the delay after the tap was read successfully.
+    fn reset(&mut self) {
+        self.delay = TAP_READ_BACKOFF_MIN;
+    }
+
+    /// Whether an unexpected error should be logged, at most once per
+    /// `TAP_READ_ERROR_LOG_INTERVAL`.
+    fn should_log_error(&mut self) -> bool {
+        let now = Instant::now();
+        match self.last_error_log {
+            Some(last) if now.duration_since(last) < TAP_READ_ERROR_LOG_INTERVAL => false,
+            _ => {
+                self.last_error_log = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Timer polling the MMDS network stack while guest requests to the MMDS wait for their parked
+/// response, e.g. until the MMDS data store changes.
+#[derive(Debug)]
+pub(crate) struct MmdsWatchPoll {
+    /// Timer expiring when the MMDS network stack should be polled.
+    pub(crate) timer: TimerFd,
+    /// Whether the timer is armed.
+    armed: bool,
+}
+
+impl MmdsWatchPoll {
+    fn new() -> Result<Self, io::Error> {
+        Ok(MmdsWatchPoll {
+            timer: TimerFd::new_custom(ClockId::Monotonic, true, true)?,
+            armed: false,
+        })
+    }
+
+    /// Whether the timer is armed.
+    pub(crate) fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Arms the timer while there are parked responses, and disarms it otherwise.
+    fn update(&mut self, parked_responses: bool) {
+        if parked_responses && !self.armed {
+            self.timer.set_state(
+                TimerState::Oneshot(MMDS_WATCH_POLL_INTERVAL),
+                SetTimeFlags::Default,
+            );
+            self.armed = true;
+        } else if !parked_responses && self.armed {
+            self.timer
+                .set_state(TimerState::Disarmed, SetTimeFlags::Default);
+            self.armed = false;
+        }
+    }
+
+    /// Consumes the expiration of the timer.
+    fn expire(&mut self) {
+        self.timer.read();
+        self.armed = false;
+    }
+}
+
+/// How the rate limiters of a network device apply to its pairs of RX and TX queues.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimiterScope {
+    /// The pairs share the rate limiters, which limit the traffic of the whole device.
+    #[default]
+    Device,
+    /// Every pair has its own copy of the rate limiters, which limit the traffic of the pair.
+    QueuePair,
+}
+
+/// The offloads a network device offers to the guest, all of them by default.
+///
+/// The segmentation offloads need the checksum offload of the same direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetOffloads {
+    /// The guest receives frames with a partial checksum (`VIRTIO_NET_F_GUEST_CSUM`).
+    pub guest_csum: bool,
+    /// The guest receives TCPv4 segmentation offload frames (`VIRTIO_NET_F_GUEST_TSO4`).
+    pub guest_tso4: bool,
+    /// The guest receives UDP fragmentation offload frames (`VIRTIO_NET_F_GUEST_UFO`).
+    pub guest_ufo: bool,
+    /// The guest sends frames with a partial checksum (`VIRTIO_NET_F_CSUM`).
+    pub csum: bool,
+    /// The guest sends TCPv4 segmentation offload frames (`VIRTIO_NET_F_HOST_TSO4`).
+    pub host_tso4: bool,
+    /// The guest sends UDP fragmentation offload frames (`VIRTIO_NET_F_HOST_UFO`).
+    pub host_ufo: bool,
+}
+
+impl Default for NetOffloads {
+    fn default() -> Self {
+        NetOffloads {
+            guest_csum: true,
+            guest_tso4: true,
+            guest_ufo: true,
+            csum: true,
+            host_tso4: true,
+            host_ufo: true,
+        }
+    }
+}
+
+impl NetOffloads {
+    // Checks that the segmentation offloads enabled have their checksum offload enabled too.
+    fn validate(&self) -> Result<(), NetError> {
+        let dependencies = [
+            (self.guest_tso4, "guest_tso4", self.guest_csum, "guest_csum"),
+            (self.guest_ufo, "guest_ufo", self.guest_csum, "guest_csum"),
+            (self.host_tso4, "host_tso4", self.csum, "csum"),
+            (self.host_ufo, "host_ufo", self.csum, "csum"),
+        ];
+        for (enabled, offload, dependency_enabled, dependency) in dependencies {
+            if enabled && !dependency_enabled {
+                return Err(NetError::OffloadDependency(offload, dependency));
+            }
+        }
+        Ok(())
+    }
+
+    // The virtio features of the disabled offloads.
+    fn disabled_features(&self) -> u64 {
+        [
+            (self.guest_csum, VIRTIO_NET_F_GUEST_CSUM),
+            (self.guest_tso4, VIRTIO_NET_F_GUEST_TSO4),
+            (self.guest_ufo, VIRTIO_NET_F_GUEST_UFO),
+            (self.csum, VIRTIO_NET_F_CSUM),
+            (self.host_tso4, VIRTIO_NET_F_HOST_TSO4),
+            (self.host_ufo, VIRTIO_NET_F_HOST_UFO),
+        ]
+        .into_iter()
+        .filter(|&(enabled, _)| !enabled)
+        .fold(0, |features, (_, feature)| features | 1 << feature)
+    }
+
+    // The offload flags of the taps, telling the kernel which frames the guest can receive. The
+    // taps have always been set up for TCPv6 segmentation offload along with TCPv4.
+    pub(crate) fn tap_flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.guest_csum {
+            flags |= gen::TUN_F_CSUM;
+        }
+        if self.guest_tso4 {
+            flags |= gen::TUN_F_TSO4 | gen::TUN_F_TSO6;
+        }
+        if self.guest_ufo {
+            flags |= gen::TUN_F_UFO;
+        }
+        flags
+    }
+}
+
+/// The filter the driver sets on the frames it receives, through the control queue, with
+/// `VIRTIO_NET_F_CTRL_RX`.
+///
+/// The tap passes all the frames sent to it, which are filtered before being written to the
+/// guest. The broadcast frames, and the ones sent to the MAC address of the guest, are always
+/// received.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct RxFilter {
+    /// All the frames are received.
+    pub(crate) promisc: bool,
+    /// All the multicast frames are received.
+    pub(crate) all_multi: bool,
+    /// The other unicast addresses received, all of them if the driver set more addresses than
+    /// the table holds.
+    pub(crate) unicast: Option<Vec<MacAddr>>,
+    /// The multicast addresses received, all of them if the driver set more addresses than the
+    /// table holds.
+    pub(crate) multicast: Option<Vec<MacAddr>>,
+}
+
+impl Default for RxFilter {
+    // The device is promiscuous until the driver sets the filter.
+    fn default() -> Self {
+        RxFilter {
+            promisc: true,
+            all_multi: false,
+            unicast: Some(Vec::new()),
+            multicast: Some(Vec::new()),
+        }
+    }
+}
+
+impl RxFilter {
+    // Whether the frame starting with `frame`, VNET header included, is received by a guest of
+    // MAC address `guest_mac`. Without a MAC address, the unicast frames are all received, the
+    // device not knowing the address the driver picked. The frames too short to have a
+    // destination address are left for the guest to drop.
+    fn accepts(&self, frame: &[u8], guest_mac: Option<MacAddr>) -> bool {
+        let Some(dst_mac) = frame_bytes_from_buf(frame)
+            .ok()
+            .and_then(|bytes| EthernetFrame::from_bytes(bytes).ok())
+            .map(|eth_frame| eth_frame.dst_mac())
+        else {
+            return true;
+        };
+        let matches = |table: &Option<Vec<MacAddr>>| {
+            table
+                .as_ref()
+                .map_or(true, |addresses| addresses.contains(&dst_mac))
+        };
+
+        if self.promisc || dst_mac == MacAddr::from([0xff; 6]) || Some(dst_mac) == guest_mac {
+            true
+        } else if dst_mac.get_bytes()[0] & 1 != 0 {
+            self.all_multi || matches(&self.multicast)
+        } else {
+            guest_mac.is_none() || matches(&self.unicast)
+        }
+    }
+
+    // Reads a MAC table of a `VIRTIO_NET_CTRL_MAC_TABLE_SET` command, returning its addresses and
+    // the rest of the command, or `None` if the command is too short. The addresses are `None` if
+    // there are more than the filter holds, in which case the rest of the command is not read, the
+    // table possibly being cut short at `CTRL_COMMAND_MAX_LEN` bytes.
+    fn read_mac_table(command: &[u8]) -> Option<(Option<Vec<MacAddr>>, &[u8])> {
+        let (entries, command) = command.split_at(command.len().min(mem::size_of::<u32>()));
+        let entries = usize::try_from(u32::from_le_bytes(entries.try_into().ok()?)).ok()?;
+        if entries > RX_FILTER_MAC_TABLE_LEN {
+            return Some((None, &[]));
+        }
+
+        let len = entries * usize::from(MAC_ADDR_LEN);
+        let addresses = command
+            .get(..len)?
+            .chunks_exact(usize::from(MAC_ADDR_LEN))
+            .map(MacAddr::from_bytes_unchecked)
+            .collect();
+        Some((Some(addresses), &command[len..]))
+    }
+}
+
+// The RX descriptor chains a frame is written across with `VIRTIO_NET_F_MRG_RXBUF`, as a single
+// buffer. The header at the start of the first chain tells the driver how many chains the frame
+// spans.
+#[derive(Debug)]
+struct MergedRxBuffers<'a> {
+    buffer: IoVecBufferMut<'a>,
+    // The index of the head of every chain, in the order they were popped.
+    heads: Vec<u16>,
+}
+
+impl<'a> MergedRxBuffers<'a> {
+    // Pops chains off the `queue` until they hold `len` bytes, and at least the header, loading
+    // them into `buffer`.
+    //
+    // If the queue runs out of chains first, the chains popped are given back, the frame waiting
+    // for the driver to add more. If one of the chains is invalid, it is added to the used ring
+    // along with the chains popped before it, with no bytes written.
+    fn pop(
+        mut buffer: IoVecBufferMut<'a>,
+        queue: &mut Queue,
+        mem: &'a GuestMemoryMmap,
+        len: usize,
+    ) -> Result<Self, FrontendError> {
+        let rings = queue.rings();
+        let len = len.max(vnet_hdr_len());
+        buffer.clear();
+        let mut buffers = MergedRxBuffers {
+            buffer,
+            heads: Vec::new(),
+        };
+        while (buffers.buffer.len() as usize) < len {
+            let Some(head) = queue.pop_or_enable_notification(mem) else {
+                for _ in 0..buffers.heads.len() {
+                    queue.undo_pop();
+                }
+                return Err(FrontendError::EmptyQueue);
+            };
+            buffers.heads.push(head.index);
+            if let Err(err) = buffers.buffer.append_descriptor_chain(head) {
+                buffers.add_used(queue, mem, 0)?;
+                return Err(FrontendError::InvalidChain(err));
+            }
+        }
+        if let Err(err) = buffers.buffer.check_queue_overlap(&rings) {
+            buffers.add_used(queue, mem, 0)?;
+            return Err(FrontendError::InvalidChain(err));
+        }
+        Ok(buffers)
+    }
+
+    // Writes the `frame` across the chains, along with the number of chains it spans.
+    fn write_frame(&mut self, frame: &[u8]) -> Result<(), FrontendError> {
+        self.buffer.write_all_volatile_at(frame, 0).map_err(|err| {
+            error!("Failed to write frame: {:?}", err);
+            FrontendError::GuestMemory(GuestMemoryError::from(err))
+        })?;
+        // Ok to unwrap, there are at most as many chains as the size of the queue, and they hold
+        // the header.
+        let num_buffers = u16::try_from(self.heads.len()).unwrap();
+        self.buffer
+            .write_obj(&num_buffers, vnet_hdr_len() - mem::size_of::<u16>())
+            .unwrap();
+        Ok(())
+    }
+
+    // Adds the chains to the used ring, with the number of the first `len` bytes written to each
+    // of them.
+    fn add_used(
+        &self,
+        queue: &mut Queue,
+        mem: &GuestMemoryMmap,
+        len: u32,
+    ) -> Result<(), FrontendError> {
+        let used_lens = self.buffer.chain_used_lens(len).chain(std::iter::repeat(0));
+        for (&head_index, used_len) in self.heads.iter().zip(used_lens) {
+            queue.add_used(mem, head_index, used_len).map_err(|err| {
+                error!("Failed to add available descriptor {}: {}", head_index, err);
+                FrontendError::AddUsed
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// A pair of RX and TX queues of the network device, along with the queue of the tap it exchanges
+/// frames with.
+#[derive(Debug)]
+pub(crate) struct QueuePair {
+    /// The backend of the pair: a tap, or one of its queues if the device has several pairs.
+    pub(crate) tap: Tap,
+
+    pub(crate) rx_deferred_frame: bool,
+    pub(crate) tap_read_backoff: TapReadBackoff,
+    /// Whether the tap could not take a TX frame, which is sent again once the tap is writable.
+    pub(crate) tap_write_blocked: bool,
+    /// Whether the tap is registered with the event manager for writes.
+    pub(crate) tap_write_registered: bool,
+
+    rx_bytes_read: usize,
+    rx_frame_buf: [u8; MAX_BUFFER_SIZE],
+
+    // Storage of the iovecs of the RX descriptor chains the frames of the tap are read into,
+    // kept across frames.
+    rx_buffer: IoVecBufferMut<'static>,
+
+    tx_frame_headers: [u8; frame_hdr_len()],
+    // Storage of the iovecs of the TX frames, kept across the TX processing passes.
+    tx_buffer: IoVecBuffer<'static>,
+    // Storage of the batches of TX frames popped from the queue, kept across the TX processing
+    // passes.
+    tx_heads: Vec<DescriptorChain<'static>>,
+    // Frames sent in the current TX pass, handed back to the guest at once at the end of it.
+    tx_used_batch: Vec<(u16, u32)>,
+
+    /// The metrics of the pair. The first pair uses the metrics of the device.
+    pub(crate) metrics: Arc<NetDeviceMetrics>,
+}
+
+impl QueuePair {
+    fn new(tap: Tap, metrics: Arc<NetDeviceMetrics>) -> Result<Self, NetError> {
+        Ok(QueuePair {
+            tap,
+            rx_deferred_frame: false,
+            tap_read_backoff: TapReadBackoff::new().map_err(NetError::TimerFd)?,
+            tap_write_blocked: false,
+            tap_write_registered: false,
+            rx_bytes_read: 0,
+            rx_frame_buf: [0u8; MAX_BUFFER_SIZE],
+            rx_buffer: IoVecBufferMut::new(),
+            tx_frame_headers: [0u8; frame_hdr_len()],
+            tx_buffer: IoVecBuffer::new(),
+            tx_heads: Vec::new(),
+            tx_used_batch: Vec::new(),
+            metrics,
+        })
+    }
+
+    #[cfg(not(test))]
+    fn read_tap(&mut self) -> std::io::Result<usize> {
+        self.tap.read(&mut self.rx_frame_buf)
+    }
+
+    #[cfg(not(test))]
+    fn read_tap_to(&mut self, buffer: &mut IoVecBufferMut) -> std::io::Result<usize> {
+        self.tap.read_iovec(buffer)
+    }
+}
+
 /// VirtIO network device.
 ///
 /// It emulates a network device able to exchange L2 frames between the guest
 /// and a host-side tap device.
+///
+/// The device has one or more pairs of RX and TX queues, and a control queue after them through
+/// which the driver sets the filter of the frames it receives. With several pairs, it offers
+/// `VIRTIO_NET_F_MQ`, the driver setting how many pairs it uses through the control queue too.
+/// Each pair exchanges frames with a queue of a multi-queue tap.
 #[derive(Debug)]
 pub struct Net {
     pub(crate) id: String,
 
-    /// The backend for this device: a tap.
-    pub tap: Tap,
-
     pub(crate) avail_features: u64,
     pub(crate) acked_features: u64,
+    pub(crate) virtio_features_pin: Option<u64>,
 
     pub(crate) queues: Vec<Queue>,
+    // Configured maximal size of the queues, if not the default one.
+    pub(crate) queue_size: Option<u16>,
     pub(crate) queue_evts: Vec<EventFd>,
 
-    pub(crate) rx_rate_limiter: RateLimiter,
-    pub(crate) tx_rate_limiter: RateLimiter,
-
-    pub(crate) rx_deferred_frame: bool,
-
-    rx_bytes_read: usize,
-    rx_frame_buf: [u8; MAX_BUFFER_SIZE],
-
-    tx_frame_headers: [u8; frame_hdr_len()],
+    /// The pairs of RX and TX queues, and their backends.
+    pub(crate) pairs: Vec<QueuePair>,
+    /// The file descriptor the tap was opened from, if it was not opened by name.
+    pub(crate) tap_fd: Option<RawFd>,
+    /// The number of pairs used by the driver, the first ones.
+    pub(crate) active_pairs: usize,
+
+    pub(crate) rate_limiter_scope: RateLimiterScope,
+    /// The offloads offered to the guest.
+    pub(crate) offloads: NetOffloads,
+    /// The MTU offered to the guest, if any.
+    pub(crate) mtu: Option<u16>,
+    /// The filter of the frames received by the guest, see `RxFilter`.
+    pub(crate) rx_filter: RxFilter,
+    // The MTU of the tap, read when the MTU offered to the guest is set, since the tap can not be
+    // queried anymore once the seccomp filters are installed.
+    pub(crate) tap_mtu: Option<u32>,
+    // The rate limiters, a single one shared by the pairs or one per pair, see
+    // `RateLimiterScope`.
+    pub(crate) rx_rate_limiters: Vec<RateLimiter>,
+    pub(crate) tx_rate_limiters: Vec<RateLimiter>,
+
+    pub(crate) mmds_watch_poll: MmdsWatchPoll,
 
     pub(crate) irq_trigger: IrqTrigger,
 
@@ -136,11 +655,16 @@ pub struct Net {
 
     pub(crate) device_state: DeviceState,
     pub(crate) activate_evt: EventFd,
+    pub(crate) needs_reset: bool,
 
     /// The MMDS stack corresponding to this interface.
     /// Only if MMDS transport has been associated with it.
     pub mmds_ns: Option<MmdsNetworkStack>,
     pub(crate) metrics: Arc<NetDeviceMetrics>,
+
+    /// Called with the TX rate limiter at the start and at the end of each TX processing pass.
+    #[cfg(test)]
+    pub(crate) tx_pass_hook: Option<fn(&RateLimiter)>,
 }
 
 impl Net {
@@ -151,6 +675,18 @@ impl Net {
         guest_mac: Option<MacAddr>,
         rx_rate_limiter: RateLimiter,
         tx_rate_limiter: RateLimiter,
+    ) -> Result<Self, NetError> {
+        Self::new_with_taps(id, vec![tap], guest_mac, rx_rate_limiter, tx_rate_limiter)
+    }
+
+    /// Create a new virtio network device with a pair of RX and TX queues for each of the given
+    /// TAP interface queues.
+    fn new_with_taps(
+        id: String,
+        taps: Vec<Tap>,
+        guest_mac: Option<MacAddr>,
+        rx_rate_limiter: RateLimiter,
+        tx_rate_limiter: RateLimiter,
     ) -> Result<Self, NetError> {
         let mut avail_features = 1 << VIRTIO_NET_F_GUEST_CSUM
             | 1 << VIRTIO_NET_F_CSUM
@@ -158,47 +694,199 @@ impl Net {
             | 1 << VIRTIO_NET_F_GUEST_UFO
             | 1 << VIRTIO_NET_F_HOST_TSO4
             | 1 << VIRTIO_NET_F_HOST_UFO
+            | 1 << VIRTIO_NET_F_MRG_RXBUF
+            | 1 << VIRTIO_NET_F_STATUS
+            | 1 << VIRTIO_NET_F_CTRL_VQ
+            | 1 << VIRTIO_NET_F_CTRL_RX
+            | 1 << VIRTIO_NET_F_GUEST_ANNOUNCE
             | 1 << VIRTIO_F_VERSION_1
-            | 1 << VIRTIO_RING_F_EVENT_IDX;
+            | 1 << VIRTIO_RING_F_EVENT_IDX
+            | 1 << VIRTIO_RING_F_INDIRECT_DESC;
 
-        let mut config_space = ConfigSpace::default();
+        let mut config_space = ConfigSpace {
+            status: u16::try_from(VIRTIO_NET_S_LINK_UP).unwrap(),
+            ..Default::default()
+        };
         if let Some(mac) = guest_mac {
             config_space.guest_mac = mac;
             // Enabling feature for MAC address configuration
             // If not set, the driver will generates a random MAC address
             avail_features |= 1 << VIRTIO_NET_F_MAC;
         }
+        if taps.len() > 1 {
+            avail_features |= 1 << VIRTIO_NET_F_MQ;
+            config_space.max_virtqueue_pairs = u16::try_from(taps.len()).unwrap();
+        }
+
+        let metrics = NetMetricsPerDevice::alloc(id.clone());
+        let mut pairs = Vec::with_capacity(taps.len());
+        for (i, tap) in taps.into_iter().enumerate() {
+            let pair_metrics = match i {
+                0 => metrics.clone(),
+                _ => NetMetricsPerDevice::alloc(format!("{}_pair{}", id, i)),
+            };
+            pairs.push(QueuePair::new(tap, pair_metrics)?);
+        }
 
-        let mut queue_evts = Vec::new();
-        let mut queues = Vec::new();
-        for size in NET_QUEUE_SIZES {
+        let num_queues = Self::num_queues(pairs.len(), true);
+        let mut queue_evts = Vec::with_capacity(num_queues);
+        for _ in 0..num_queues {
             queue_evts.push(EventFd::new(libc::EFD_NONBLOCK).map_err(NetError::EventFd)?);
-            queues.push(Queue::new(size));
         }
 
         Ok(Net {
-            id: id.clone(),
-            tap,
+            id,
             avail_features,
             acked_features: 0u64,
-            queues,
+            virtio_features_pin: None,
+            queues: vec![Queue::new(FIRECRACKER_MAX_QUEUE_SIZE); num_queues],
+            queue_size: None,
             queue_evts,
-            rx_rate_limiter,
-            tx_rate_limiter,
-            rx_deferred_frame: false,
-            rx_bytes_read: 0,
-            rx_frame_buf: [0u8; MAX_BUFFER_SIZE],
-            tx_frame_headers: [0u8; frame_hdr_len()],
+            pairs,
+            tap_fd: None,
+            active_pairs: 1,
+            rate_limiter_scope: RateLimiterScope::Device,
+            offloads: NetOffloads::default(),
+            mtu: None,
+            rx_filter: RxFilter::default(),
+            tap_mtu: None,
+            rx_rate_limiters: vec![rx_rate_limiter],
+            tx_rate_limiters: vec![tx_rate_limiter],
+            mmds_watch_poll: MmdsWatchPoll::new().map_err(NetError::TimerFd)?,
             irq_trigger: IrqTrigger::new().map_err(NetError::EventFd)?,
             config_space,
             guest_mac,
             device_state: DeviceState::Inactive,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(NetError::EventFd)?,
+            needs_reset: false,
             mmds_ns: None,
-            metrics: NetMetricsPerDevice::alloc(id),
+            metrics,
+            #[cfg(test)]
+            tx_pass_hook: None,
         })
     }
 
+    /// Restricts the virtio features offered to the guest to the ones of `pin`.
+    ///
+    /// The control queue is dropped if `VIRTIO_NET_F_CTRL_VQ` is not pinned, since the driver
+    /// doesn't set it up then.
+    pub fn pin_virtio_features(&mut self, pin: u64) -> Result<(), VirtioFeaturesPinError> {
+        self.avail_features = pin_virtio_features(&self.id, self.avail_features, pin)?;
+        self.virtio_features_pin = Some(pin);
+        self.drop_unoffered_ctrl_queue();
+        Ok(())
+    }
+
+    // Drops the control queue, and its event, if `VIRTIO_NET_F_CTRL_VQ` is not offered.
+    pub(crate) fn drop_unoffered_ctrl_queue(&mut self) {
+        let num_queues = Self::num_queues(self.pairs.len(), self.offers_ctrl_queue());
+        self.queues.truncate(num_queues);
+        self.queue_evts.truncate(num_queues);
+    }
+
+    /// Sets the maximal size of the queues offered to the guest.
+    ///
+    /// The queues are recreated, so this must be called before the device is activated.
+    pub fn set_queue_size(&mut self, size: u16) -> Result<(), QueueSizeError> {
+        check_queue_size(size, NET_MIN_QUEUE_SIZE, NET_MAX_QUEUE_SIZE)?;
+        self.queues = vec![Queue::new(size); self.queue_evts.len()];
+        self.queue_size = Some(size);
+        Ok(())
+    }
+
+    /// Provides the configured maximal size of the queues, if not the default one.
+    pub fn queue_size(&self) -> Option<u16> {
+        self.queue_size
+    }
+
+    /// Sets how the rate limiters apply to the pairs of RX and TX queues.
+    ///
+    /// With `RateLimiterScope::QueuePair`, every pair gets its own copy of the rate limiters the
+    /// device was created with, starting with a full budget.
+    pub fn set_rate_limiter_scope(&mut self, scope: RateLimiterScope) -> Result<(), io::Error> {
+        let num_limiters = match scope {
+            RateLimiterScope::Device => 1,
+            RateLimiterScope::QueuePair => self.pairs.len(),
+        };
+        for limiters in [&mut self.rx_rate_limiters, &mut self.tx_rate_limiters] {
+            let config = RateLimiterConfig::from(&limiters[0]);
+            limiters.truncate(1);
+            while limiters.len() < num_limiters {
+                limiters.push(config.try_into()?);
+            }
+        }
+        self.rate_limiter_scope = scope;
+        Ok(())
+    }
+
+    /// Provides how the rate limiters apply to the pairs of RX and TX queues.
+    pub fn rate_limiter_scope(&self) -> RateLimiterScope {
+        self.rate_limiter_scope
+    }
+
+    /// Sets the offloads offered to the guest, and the offload flags of the taps accordingly.
+    ///
+    /// This must be called before the device is activated. The virtio features of the offloads
+    /// disabled are not offered again by later calls.
+    pub fn set_offloads(&mut self, offloads: NetOffloads) -> Result<(), NetError> {
+        offloads.validate()?;
+        for pair in &self.pairs {
+            pair.tap
+                .set_offload(offloads.tap_flags())
+                .map_err(NetError::TapSetOffload)?;
+        }
+        self.avail_features &= !offloads.disabled_features();
+        self.offloads = offloads;
+        Ok(())
+    }
+
+    /// Provides the offloads offered to the guest.
+    pub fn offloads(&self) -> NetOffloads {
+        self.offloads
+    }
+
+    /// Sets the MTU offered to the guest with `VIRTIO_NET_F_MTU`.
+    ///
+    /// This must be called before the device is activated. The MTU of the tap is read here and
+    /// checked against the one offered when the device is activated.
+    pub fn set_mtu(&mut self, mtu: u16) -> Result<(), NetError> {
+        if !(NET_MIN_MTU..=NET_MAX_MTU).contains(&mtu) {
+            return Err(NetError::InvalidMtu(mtu, NET_MIN_MTU, NET_MAX_MTU));
+        }
+        self.tap_mtu = match self.pairs[0].tap.mtu() {
+            Ok(tap_mtu) => Some(tap_mtu),
+            Err(err) => {
+                warn!("{}: Failed to get the MTU of the tap: {}", self.id, err);
+                None
+            }
+        };
+        self.config_space.mtu = mtu;
+        self.avail_features |= 1 << VIRTIO_NET_F_MTU;
+        self.mtu = Some(mtu);
+        Ok(())
+    }
+
+    /// Provides the MTU offered to the guest, if any.
+    pub fn mtu(&self) -> Option<u16> {
+        self.mtu
+    }
+
+    // Warns when the tap can not carry the frames of the MTU negotiated by the driver.
+    fn check_tap_mtu(&self) {
+        if !self.has_feature(u64::from(VIRTIO_NET_F_MTU)) {
+            return;
+        }
+        if let (Some(mtu), Some(tap_mtu)) = (self.mtu, self.tap_mtu) {
+            if tap_mtu < u32::from(mtu) {
+                warn!(
+                    "{}: The MTU of the tap ({}) is smaller than the one offered to the guest ({})",
+                    self.id, tap_mtu, mtu
+                );
+                self.metrics.tap_mtu_too_small.inc();
+            }
+        }
+    }
+
     /// Create a new virtio network device given the interface name.
     pub fn new(
         id: String,
@@ -207,17 +895,127 @@ impl Net {
         rx_rate_limiter: RateLimiter,
         tx_rate_limiter: RateLimiter,
     ) -> Result<Self, NetError> {
-        let tap = Tap::open_named(tap_if_name).map_err(NetError::TapOpen)?;
+        Self::new_multi_queue(
+            id,
+            tap_if_name,
+            1,
+            guest_mac,
+            rx_rate_limiter,
+            tx_rate_limiter,
+        )
+    }
 
-        // Set offload flags to match the virtio features below.
-        tap.set_offload(gen::TUN_F_CSUM | gen::TUN_F_UFO | gen::TUN_F_TSO4 | gen::TUN_F_TSO6)
+    /// Create a new virtio network device with `num_pairs` pairs of RX and TX queues given the
+    /// interface name.
+    ///
+    /// With more than one pair, the tap is opened in multi-queue mode, with a queue per pair. Only
+    /// the first pair is used until the driver sets the number of pairs it uses, so the queues of
+    /// the others are disabled.
+    pub fn new_multi_queue(
+        id: String,
+        tap_if_name: &str,
+        num_pairs: u16,
+        guest_mac: Option<MacAddr>,
+        rx_rate_limiter: RateLimiter,
+        tx_rate_limiter: RateLimiter,
+    ) -> Result<Self, NetError> {
+        if !(1..=NET_MAX_QUEUE_PAIRS).contains(&num_pairs) {
+            return Err(NetError::QueuePairs(num_pairs, NET_MAX_QUEUE_PAIRS));
+        }
+        let taps = if num_pairs == 1 {
+            vec![Tap::open_named(tap_if_name).map_err(NetError::TapOpen)?]
+        } else {
+            Tap::open_queues(tap_if_name, usize::from(num_pairs)).map_err(NetError::TapOpen)?
+        };
+
+        for tap in &taps {
+            Self::configure_tap(tap)?;
+        }
+        for tap in taps.iter().skip(1) {
+            tap.set_queue_enabled(false)
+                .map_err(NetError::TapSetQueue)?;
+        }
+
+        Self::new_with_taps(id, taps, guest_mac, rx_rate_limiter, tx_rate_limiter)
+    }
+
+    /// Create a new virtio network device given the file descriptor of an already open tap,
+    /// e.g. inherited from the parent process. The file descriptor is duplicated, and stays
+    /// owned by the caller.
+    pub fn new_with_tap_fd(
+        id: String,
+        tap_fd: RawFd,
+        guest_mac: Option<MacAddr>,
+        rx_rate_limiter: RateLimiter,
+        tx_rate_limiter: RateLimiter,
+    ) -> Result<Self, NetError> {
+        let tap = Tap::from_fd(tap_fd).map_err(NetError::TapOpen)?;
+        Self::configure_tap(&tap)?;
+
+        let mut net = Self::new_with_tap(id, tap, guest_mac, rx_rate_limiter, tx_rate_limiter)?;
+        net.tap_fd = Some(tap_fd);
+        Ok(net)
+    }
+
+    // Sets the offloads and the size of the vnet header of a tap to match the virtio features
+    // offered.
+    fn configure_tap(tap: &Tap) -> Result<(), NetError> {
+        tap.set_offload(NetOffloads::default().tap_flags())
             .map_err(NetError::TapSetOffload)?;
 
         let vnet_hdr_size = i32::try_from(vnet_hdr_len()).unwrap();
         tap.set_vnet_hdr_size(vnet_hdr_size)
-            .map_err(NetError::TapSetVnetHdrSize)?;
+            .map_err(NetError::TapSetVnetHdrSize)
+    }
+
+    /// Provides the number of pairs of RX and TX queues of this net device.
+    pub fn num_pairs(&self) -> u16 {
+        // Safe to unwrap, the number of pairs is checked at creation time.
+        u16::try_from(self.pairs.len()).unwrap()
+    }
+
+    /// The number of queues of a device with `num_pairs` pairs of RX and TX queues, including
+    /// the control queue if the device has one.
+    fn num_queues(num_pairs: usize, ctrl_queue: bool) -> usize {
+        num_pairs * NET_NUM_QUEUES + usize::from(ctrl_queue)
+    }
 
-        Self::new_with_tap(id, tap, guest_mac, rx_rate_limiter, tx_rate_limiter)
+    // Whether the control queue is offered, which it is unless the virtio features are pinned
+    // without it.
+    fn offers_ctrl_queue(&self) -> bool {
+        self.avail_features & (1 << VIRTIO_NET_F_CTRL_VQ) != 0
+    }
+
+    /// The index of the control queue, if the device offers it.
+    pub(crate) fn ctrl_queue_index(&self) -> Option<usize> {
+        self.offers_ctrl_queue()
+            .then(|| self.pairs.len() * NET_NUM_QUEUES)
+    }
+
+    // The index of the RX queue of the `pair`.
+    pub(crate) fn rx_queue_index(pair: usize) -> usize {
+        pair * NET_NUM_QUEUES + RX_INDEX
+    }
+
+    // The index of the TX queue of the `pair`.
+    pub(crate) fn tx_queue_index(pair: usize) -> usize {
+        pair * NET_NUM_QUEUES + TX_INDEX
+    }
+
+    // The index of the rate limiters used by the `pair`.
+    fn rate_limiter_index(&self, pair: usize) -> usize {
+        match self.rate_limiter_scope {
+            RateLimiterScope::Device => 0,
+            RateLimiterScope::QueuePair => pair,
+        }
+    }
+
+    // The pairs using the rate limiters at `index`.
+    fn rate_limiter_pairs(&self, index: usize) -> std::ops::Range<usize> {
+        match self.rate_limiter_scope {
+            RateLimiterScope::Device => 0..self.pairs.len(),
+            RateLimiterScope::QueuePair => index..index + 1,
+        }
     }
 
     /// Provides the ID of this net device.
@@ -230,9 +1028,65 @@ impl Net {
         self.guest_mac.as_ref()
     }
 
+    /// Sets the MAC address of the guest.
+    ///
+    /// The guest driver is notified of the change with a configuration change interrupt if the
+    /// device is activated, in which case the driver must have negotiated `VIRTIO_NET_F_MAC`.
+    /// Otherwise the feature is offered, unless the virtio features pinned exclude it.
+    pub fn set_guest_mac(&mut self, mac: MacAddr) -> Result<(), NetError> {
+        if self.is_activated() {
+            if !self.has_feature(u64::from(VIRTIO_NET_F_MAC)) {
+                return Err(NetError::GuestMacNotNegotiated);
+            }
+        } else if self
+            .virtio_features_pin
+            .is_some_and(|pin| pin & (1 << VIRTIO_NET_F_MAC) == 0)
+        {
+            return Err(NetError::GuestMacNotNegotiated);
+        } else {
+            self.avail_features |= 1 << VIRTIO_NET_F_MAC;
+        }
+
+        self.config_space.guest_mac = mac;
+        self.guest_mac = Some(mac);
+        if self.is_activated() {
+            self.irq_trigger
+                .trigger_irq(IrqType::Config)
+                .map_err(NetError::EventFd)?;
+        }
+        Ok(())
+    }
+
+    /// Asks the guest driver to announce the guest on the network, e.g. with gratuitous ARP
+    /// packets, so that its peers learn where it now is after the microVM is restored on another
+    /// host. The driver is notified with a configuration change interrupt, and acknowledges the
+    /// request through the control queue.
+    ///
+    /// Returns whether the driver was asked, which requires the device to be activated with
+    /// `VIRTIO_NET_F_STATUS` and `VIRTIO_NET_F_GUEST_ANNOUNCE` negotiated.
+    pub fn announce(&mut self) -> Result<bool, NetError> {
+        if !self.is_activated()
+            || !self.has_feature(u64::from(VIRTIO_NET_F_STATUS))
+            || !self.has_feature(u64::from(VIRTIO_NET_F_GUEST_ANNOUNCE))
+        {
+            return Ok(false);
+        }
+
+        self.config_space.status |= u16::try_from(VIRTIO_NET_S_ANNOUNCE).unwrap();
+        self.irq_trigger
+            .trigger_irq(IrqType::Config)
+            .map_err(NetError::EventFd)?;
+        Ok(true)
+    }
+
     /// Provides the host IFACE name of this net device.
     pub fn iface_name(&self) -> String {
-        self.tap.if_name_as_str().to_string()
+        self.pairs[0].tap.if_name_as_str().to_string()
+    }
+
+    /// Provides the file descriptor the tap of this net device was opened from, if any.
+    pub fn tap_fd(&self) -> Option<RawFd> {
+        self.tap_fd
     }
 
     /// Provides the MmdsNetworkStack of this net device.
@@ -256,29 +1110,42 @@ impl Net {
     }
 
     /// Provides a reference to the configured RX rate limiter.
+    ///
+    /// With `RateLimiterScope::QueuePair`, this is the one of the first pair, which has the same
+    /// configuration as the ones of the other pairs.
     pub fn rx_rate_limiter(&self) -> &RateLimiter {
-        &self.rx_rate_limiter
+        &self.rx_rate_limiters[0]
     }
 
     /// Provides a reference to the configured TX rate limiter.
+    ///
+    /// With `RateLimiterScope::QueuePair`, this is the one of the first pair, which has the same
+    /// configuration as the ones of the other pairs.
     pub fn tx_rate_limiter(&self) -> &RateLimiter {
-        &self.tx_rate_limiter
+        &self.tx_rate_limiters[0]
     }
 
-    fn signal_used_queue(&mut self, queue_type: NetQueue) -> Result<(), DeviceError> {
+    fn signal_used_queue(&mut self, pair: usize, queue_type: NetQueue) -> Result<(), DeviceError> {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
 
         let queue = match queue_type {
-            NetQueue::Rx => &mut self.queues[RX_INDEX],
-            NetQueue::Tx => &mut self.queues[TX_INDEX],
+            NetQueue::Rx => &mut self.queues[Self::rx_queue_index(pair)],
+            NetQueue::Tx => &mut self.queues[Self::tx_queue_index(pair)],
         };
 
-        if queue.prepare_kick(mem) {
+        let metrics = &self.pairs[pair].metrics;
+        let notify = queue.prepare_kick(mem);
+        let counters = queue.take_counters();
+        match queue_type {
+            NetQueue::Rx => metrics.add_rx_queue_counters(counters),
+            NetQueue::Tx => metrics.add_tx_queue_counters(counters),
+        }
+        if notify {
             self.irq_trigger
                 .trigger_irq(IrqType::Vring)
                 .map_err(|err| {
-                    self.metrics.event_fails.inc();
+                    metrics.event_fails.inc();
                     DeviceError::FailedSignalingIrq(err)
                 })?;
         }
@@ -309,19 +1176,21 @@ impl Net {
     // Attempts to copy a single frame into the guest if there is enough
     // rate limiting budget.
     // Returns true on successful frame delivery.
-    fn rate_limited_rx_single_frame(&mut self) -> bool {
-        if !Self::rate_limiter_consume_op(&mut self.rx_rate_limiter, self.rx_bytes_read as u64) {
-            self.metrics.rx_rate_limiter_throttled.inc();
+    fn rate_limited_rx_single_frame(&mut self, pair: usize) -> bool {
+        let limiter = self.rate_limiter_index(pair);
+        let size = self.pairs[pair].rx_bytes_read as u64;
+        if !Self::rate_limiter_consume_op(&mut self.rx_rate_limiters[limiter], size) {
+            self.pairs[pair].metrics.rx_rate_limiter_throttled.inc();
             return false;
         }
 
         // Attempt frame delivery.
-        let success = self.write_frame_to_guest();
+        let success = self.write_frame_to_guest(pair);
 
         // Undo the tokens consumption if guest delivery failed.
         if !success {
             // revert the rate limiting budget consumption
-            Self::rate_limiter_replenish_op(&mut self.rx_rate_limiter, self.rx_bytes_read as u64);
+            Self::rate_limiter_replenish_op(&mut self.rx_rate_limiters[limiter], size);
         }
 
         success
@@ -331,77 +1200,68 @@ impl Net {
     ///
     /// # Errors
     ///
-    /// Returns an error if the descriptor chain is too short or
+    /// Returns an error if the descriptor chain is too short,
     /// an inappropriate (read only) descriptor is found in the chain
+    /// or the chain points into the `rings` of its queue
     fn write_to_descriptor_chain(
-        mem: &GuestMemoryMmap,
         data: &[u8],
         head: DescriptorChain,
+        rings: &QueueRings,
         net_metrics: &NetDeviceMetrics,
     ) -> Result<(), FrontendError> {
-        let mut chunk = data;
-        let mut next_descriptor = Some(head);
-
-        while let Some(descriptor) = &next_descriptor {
-            if !descriptor.is_write_only() {
-                return Err(FrontendError::ReadOnlyDescriptor);
-            }
-
-            let len = std::cmp::min(chunk.len(), descriptor.len as usize);
-            match mem.write_slice(&chunk[..len], descriptor.addr) {
-                Ok(()) => {
-                    net_metrics.rx_count.inc();
-                    chunk = &chunk[len..];
-                }
-                Err(err) => {
-                    error!("Failed to write slice: {:?}", err);
-                    if let GuestMemoryError::PartialBuffer { .. } = err {
-                        net_metrics.rx_partial_writes.inc();
-                    }
-                    return Err(FrontendError::GuestMemory(err));
-                }
-            }
-
-            // If chunk is empty we are done here.
-            if chunk.is_empty() {
-                let len = data.len() as u64;
-                net_metrics.rx_bytes_count.add(len);
-                net_metrics.rx_packets_count.inc();
-                return Ok(());
-            }
-
-            next_descriptor = descriptor.next_descriptor();
+        // Only the descriptors holding the frame are walked, however long the chain is.
+        let (mut buffer, _) = IoVecBufferMut::from_descriptor_chain_capped(head, data.len())
+            .map_err(FrontendError::InvalidChain)?;
+        buffer
+            .check_queue_overlap(rings)
+            .map_err(FrontendError::InvalidChain)?;
+        if (buffer.len() as usize) < data.len() {
+            warn!("Receiving buffer is too small to hold frame of current size");
+            return Err(FrontendError::DescriptorChainTooSmall);
         }
 
-        warn!("Receiving buffer is too small to hold frame of current size");
-        Err(FrontendError::DescriptorChainTooSmall)
+        buffer.write_all_volatile_at(data, 0).map_err(|err| {
+            error!("Failed to write frame: {:?}", err);
+            FrontendError::GuestMemory(GuestMemoryError::from(err))
+        })?;
+        net_metrics.rx_count.inc();
+        net_metrics.rx_bytes_count.add(data.len() as u64);
+        net_metrics.rx_packets_count.inc();
+        Ok(())
     }
 
-    // Copies a single frame from `self.rx_frame_buf` into the guest.
-    fn do_write_frame_to_guest(&mut self) -> Result<(), FrontendError> {
+    // Copies a single frame from the `rx_frame_buf` of the `pair` into the guest.
+    fn do_write_frame_to_guest(&mut self, pair: usize) -> Result<(), FrontendError> {
+        if self.has_feature(u64::from(VIRTIO_NET_F_MRG_RXBUF)) {
+            return self.do_write_merged_frame_to_guest(pair);
+        }
+        #[cfg(feature = "memory-guards")]
+        let _canaries = crate::devices::virtio::canary::CanaryScope;
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
 
-        let queue = &mut self.queues[RX_INDEX];
+        let queue = &mut self.queues[Self::rx_queue_index(pair)];
+        let pair = &self.pairs[pair];
+        let rings = queue.rings();
         let head_descriptor = queue.pop_or_enable_notification(mem).ok_or_else(|| {
-            self.metrics.no_rx_avail_buffer.inc();
+            pair.metrics.no_rx_avail_buffer.inc();
             FrontendError::EmptyQueue
         })?;
         let head_index = head_descriptor.index;
 
         let result = Self::write_to_descriptor_chain(
-            mem,
-            &self.rx_frame_buf[..self.rx_bytes_read],
+            &pair.rx_frame_buf[..pair.rx_bytes_read],
             head_descriptor,
-            &self.metrics,
+            &rings,
+            &pair.metrics,
         );
         // Mark the descriptor chain as used. If an error occurred, skip the descriptor chain.
         let used_len = if result.is_err() {
-            self.metrics.rx_fails.inc();
+            pair.metrics.rx_fails.inc();
             0
         } else {
             // Safe to unwrap because a frame must be smaller than 2^16 bytes.
-            u32::try_from(self.rx_bytes_read).unwrap()
+            u32::try_from(pair.rx_bytes_read).unwrap()
         };
         queue.add_used(mem, head_index, used_len).map_err(|err| {
             error!("Failed to add available descriptor {}: {}", head_index, err);
@@ -411,12 +1271,53 @@ impl Net {
         result
     }
 
-    // Copies a single frame from `self.rx_frame_buf` into the guest. In case of an error retries
-    // the operation if possible. Returns true if the operation was successfull.
-    fn write_frame_to_guest(&mut self) -> bool {
-        let max_iterations = self.queues[RX_INDEX].actual_size();
+    // Copies a single frame from the `rx_frame_buf` of the `pair` into the guest, across as many
+    // RX descriptor chains as it takes.
+    fn do_write_merged_frame_to_guest(&mut self, pair: usize) -> Result<(), FrontendError> {
+        #[cfg(feature = "memory-guards")]
+        let _canaries = crate::devices::virtio::canary::CanaryScope;
+        // This is safe since we checked in the event handler that the device is activated.
+        let mem = self.device_state.mem().unwrap();
+
+        let queue = &mut self.queues[Self::rx_queue_index(pair)];
+        let pair = &mut self.pairs[pair];
+        let frame = &pair.rx_frame_buf[..pair.rx_bytes_read];
+        let buffer = std::mem::take(&mut pair.rx_buffer).recycle();
+        let mut buffers = MergedRxBuffers::pop(buffer, queue, mem, frame.len()).map_err(|err| {
+            match err {
+                FrontendError::EmptyQueue => pair.metrics.no_rx_avail_buffer.inc(),
+                _ => pair.metrics.rx_fails.inc(),
+            }
+            err
+        })?;
+
+        let result = buffers.write_frame(frame);
+        // Mark the descriptor chains as used. If an error occurred, skip them.
+        let used_len = match result {
+            Ok(()) => {
+                pair.metrics.rx_count.inc();
+                pair.metrics.rx_bytes_count.add(frame.len() as u64);
+                pair.metrics.rx_packets_count.inc();
+                // Safe to unwrap because a frame must be smaller than 2^16 bytes.
+                u32::try_from(frame.len()).unwrap()
+            }
+            Err(_) => {
+                pair.metrics.rx_fails.inc();
+                0
+            }
+        };
+        buffers.add_used(queue, mem, used_len)?;
+        pair.rx_buffer = buffers.buffer.recycle();
+
+        result
+    }
+
+    // Copies a single frame from the `rx_frame_buf` of the `pair` into the guest. In case of an
+    // error retries the operation if possible. Returns true if the operation was successfull.
+    fn write_frame_to_guest(&mut self, pair: usize) -> bool {
+        let max_iterations = self.queues[Self::rx_queue_index(pair)].actual_size();
         for _ in 0..max_iterations {
-            match self.do_write_frame_to_guest() {
+            match self.do_write_frame_to_guest(pair) {
                 Ok(()) => return true,
                 Err(FrontendError::EmptyQueue) | Err(FrontendError::AddUsed) => {
                     return false;
@@ -431,9 +1332,11 @@ impl Net {
         false
     }
 
-    // Tries to detour the frame to MMDS and if MMDS doesn't accept it, sends it on the host TAP.
+    // Tries to detour the frame to MMDS and if MMDS doesn't accept it, sends it on the host TAP,
+    // straight from the guest memory.
     //
-    // Returns whether MMDS consumed the frame.
+    // Returns whether MMDS consumed the frame, or a `WouldBlock` IO error if the TAP could not
+    // take the frame for now.
     fn write_to_mmds_or_tap(
         mmds_ns: Option<&mut MmdsNetworkStack>,
         rate_limiter: &mut RateLimiter,
@@ -491,12 +1394,26 @@ impl Net {
 
         let _metric = net_metrics.tap_write_agg.record_latency_metrics();
         match Self::write_tap(tap, frame_iovec) {
-            Ok(_) => {
+            Ok(count) if count == frame_iovec.len() as usize => {
                 let len = u64::from(frame_iovec.len());
                 net_metrics.tx_bytes_count.add(len);
                 net_metrics.tx_packets_count.inc();
                 net_metrics.tx_count.inc();
             }
+            // The TAP takes whole frames, so the rest of a truncated frame can not be sent on its
+            // own.
+            Ok(count) => {
+                error!(
+                    "Short write to tap: {} of {} bytes",
+                    count,
+                    frame_iovec.len()
+                );
+                net_metrics.tap_write_fails.inc();
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                net_metrics.tap_write_deferred.inc();
+                return Err(NetError::IO(err));
+            }
             Err(err) => {
                 error!("Failed to write to tap: {:?}", err);
                 net_metrics.tap_write_fails.inc();
@@ -505,46 +1422,251 @@ impl Net {
         Ok(false)
     }
 
-    // We currently prioritize packets from the MMDS over regular network packets.
-    fn read_from_mmds_or_tap(&mut self) -> Result<usize, NetError> {
-        if let Some(ns) = self.mmds_ns.as_mut() {
+    // We currently prioritize packets from the MMDS over regular network packets. The MMDS
+    // frames are all sent to the guest through the first pair.
+    fn read_from_mmds_or_tap(&mut self, pair: usize) -> Result<usize, NetError> {
+        match self.read_from_mmds(pair)? {
+            Some(count) => Ok(count),
+            None => self.read_from_tap(pair),
+        }
+    }
+
+    // Writes the next frame of the MMDS, if any, to the `rx_frame_buf` of the `pair`.
+    fn read_from_mmds(&mut self, pair: usize) -> Result<Option<usize>, NetError> {
+        let mmds_ns = self.mmds_ns.as_mut().filter(|_| pair == 0);
+        let pair = &mut self.pairs[pair];
+        if let Some(ns) = mmds_ns {
             if let Some(len) =
-                ns.write_next_frame(frame_bytes_from_buf_mut(&mut self.rx_frame_buf)?)
+                ns.write_next_frame(frame_bytes_from_buf_mut(&mut pair.rx_frame_buf)?)
             {
                 let len = len.get();
                 METRICS.mmds.tx_frames.inc();
                 METRICS.mmds.tx_bytes.add(len as u64);
-                init_vnet_hdr(&mut self.rx_frame_buf);
-                return Ok(vnet_hdr_len() + len);
+                init_vnet_hdr(&mut pair.rx_frame_buf);
+                return Ok(Some(vnet_hdr_len() + len));
             }
         }
+        Ok(None)
+    }
+
+    // Reads the next frame of the tap of the `pair` to its `rx_frame_buf`.
+    fn read_from_tap(&mut self, pair: usize) -> Result<usize, NetError> {
+        let pair = &mut self.pairs[pair];
+        // No frames are read from the tap while backing off from a fatal read error.
+        if pair.tap_read_backoff.is_active() {
+            return Err(NetError::IO(io::Error::from_raw_os_error(EAGAIN)));
+        }
+
+        let count = pair.read_tap().map_err(NetError::IO)?;
+        pair.tap_read_backoff.reset();
+        Ok(count)
+    }
+
+    // Reads the next frame of the tap of the `pair` straight into the next RX descriptor chain,
+    // sparing the copy through `rx_frame_buf`. This is only done when the chain can hold a frame
+    // of any size, the tap dropping the end of the frames not fitting in the buffer read to.
+    fn read_tap_to_guest(&mut self, pair: usize) -> Result<DirectRx, NetError> {
+        #[cfg(feature = "memory-guards")]
+        let _canaries = crate::devices::virtio::canary::CanaryScope;
+        // This is safe since we checked in the event handler that the device is activated.
+        let mem = self.device_state.mem().unwrap();
+        let limiter = self.rate_limiter_index(pair);
+        let rx_filter = self
+            .has_feature(u64::from(VIRTIO_NET_F_CTRL_RX))
+            .then_some(&self.rx_filter);
+        let guest_mac = self.guest_mac;
+        // The header tells the driver the frame spans a single chain, with
+        // `VIRTIO_NET_F_MRG_RXBUF`.
+        let num_buffers = u16::from(self.has_feature(u64::from(VIRTIO_NET_F_MRG_RXBUF)));
+        let queue = &mut self.queues[Self::rx_queue_index(pair)];
+        let rx_rate_limiter = &mut self.rx_rate_limiters[limiter];
+        let queue_pair = &mut self.pairs[pair];
+
+        // No frames are read from the tap while backing off from a fatal read error.
+        if queue_pair.tap_read_backoff.is_active() {
+            return Err(NetError::IO(io::Error::from_raw_os_error(EAGAIN)));
+        }
+        let rings = queue.rings();
+        // Finding the queue empty is left to the copy, which enables the notifications.
+        if queue.is_empty(mem) {
+            return Ok(DirectRx::NoChain);
+        }
+        let Some(head) = queue.pop(mem) else {
+            return Ok(DirectRx::NoChain);
+        };
+        let head_index = head.index;
+        let mut buffer = std::mem::take(&mut queue_pair.rx_buffer).recycle();
+        let fits = buffer
+            .load_descriptor_chain_capped(head, MAX_BUFFER_SIZE)
+            .is_ok()
+            && buffer.check_queue_overlap(&rings).is_ok()
+            && buffer.len() as usize == MAX_BUFFER_SIZE;
+
+        let result = if !fits {
+            // The frame is copied, which skips the invalid chains and the ones too short for it.
+            queue.undo_pop();
+            Ok(DirectRx::NoChain)
+        } else {
+            match queue_pair.read_tap_to(&mut buffer) {
+                Ok(count) if !Self::chain_frame_passes(rx_filter, guest_mac, &buffer, count) => {
+                    queue_pair.tap_read_backoff.reset();
+                    queue_pair.metrics.rx_count.inc();
+                    queue_pair.metrics.rx_filtered_frames.inc();
+                    queue.undo_pop();
+                    Ok(DirectRx::Filtered)
+                }
+                Ok(count) => {
+                    queue_pair.tap_read_backoff.reset();
+                    queue_pair.metrics.rx_count.inc();
+                    // The tap skips `num_buffers`, the last field of the header, which the frames
+                    // read to `rx_frame_buf` have zeroed, and is only set for the driver with
+                    // `VIRTIO_NET_F_MRG_RXBUF`. Ok to unwrap, the buffer is longer than the
+                    // header.
+                    buffer
+                        .write_obj(&num_buffers, vnet_hdr_len() - mem::size_of::<u16>())
+                        .unwrap();
+                    let delivered = if Self::rate_limiter_consume_op(rx_rate_limiter, count as u64)
+                    {
+                        // Safe to unwrap, the frame is at most `MAX_BUFFER_SIZE` bytes long.
+                        match queue.add_used(mem, head_index, u32::try_from(count).unwrap()) {
+                            Ok(()) => true,
+                            Err(err) => {
+                                error!(
+                                    "Failed to add available descriptor {}: {}",
+                                    head_index, err
+                                );
+                                Self::rate_limiter_replenish_op(rx_rate_limiter, count as u64);
+                                false
+                            }
+                        }
+                    } else {
+                        queue_pair.metrics.rx_rate_limiter_throttled.inc();
+                        false
+                    };
+
+                    if delivered {
+                        queue_pair.metrics.rx_count.inc();
+                        queue_pair.metrics.rx_bytes_count.add(count as u64);
+                        queue_pair.metrics.rx_packets_count.inc();
+                        Ok(DirectRx::Delivered)
+                    } else {
+                        // The frame waits in `rx_frame_buf` like the frames read there, and the
+                        // chain is given back. Ok to unwrap, the chain holds the `count` bytes
+                        // read.
+                        buffer
+                            .read_exact_volatile_at(&mut queue_pair.rx_frame_buf[..count], 0)
+                            .unwrap();
+                        queue_pair.rx_bytes_read = count;
+                        queue.undo_pop();
+                        Ok(DirectRx::Deferred)
+                    }
+                }
+                Err(err) => {
+                    queue.undo_pop();
+                    Err(NetError::IO(err))
+                }
+            }
+        };
+        queue_pair.rx_buffer = buffer.recycle();
+        result
+    }
+
+    // Whether the frame of `count` bytes read to `buffer` goes through the `rx_filter`, if the
+    // driver set one.
+    fn chain_frame_passes(
+        rx_filter: Option<&RxFilter>,
+        guest_mac: Option<MacAddr>,
+        buffer: &IoVecBufferMut,
+        count: usize,
+    ) -> bool {
+        let Some(rx_filter) = rx_filter else {
+            return true;
+        };
+        let mut frame_start = [0u8; vnet_hdr_len() + PAYLOAD_OFFSET];
+        let len = count.min(frame_start.len());
+        // Ok to unwrap, the buffer holds the `count` bytes read.
+        buffer
+            .read_exact_volatile_at(&mut frame_start[..len], 0)
+            .unwrap();
+        rx_filter.accepts(&frame_start[..len], guest_mac)
+    }
+
+    // Whether the frame of `count` bytes in the `rx_frame_buf` of the `pair` goes through the RX
+    // filter, if the driver set one.
+    fn rx_frame_buf_passes(&self, pair: usize, count: usize) -> bool {
+        !self.has_feature(u64::from(VIRTIO_NET_F_CTRL_RX))
+            || self
+                .rx_filter
+                .accepts(&self.pairs[pair].rx_frame_buf[..count], self.guest_mac)
+    }
 
-        self.read_tap().map_err(NetError::IO)
+    // Classifies the errors returned when reading from the tap. The tap device is non-blocking,
+    // so any error aside from EAGAIN is unexpected. EBADFD and EIO are returned while the
+    // underlying link is down, in which case we stop reading from the tap for a while.
+    fn handle_tap_read_error(&mut self, pair: usize, err: &io::Error) {
+        let pair = &mut self.pairs[pair];
+        match err.raw_os_error() {
+            Some(EAGAIN) => (),
+            Some(EBADFD) | Some(EIO) => {
+                pair.metrics.tap_read_fails.inc();
+                pair.metrics.tap_read_fatal.inc();
+                let delay = pair.tap_read_backoff.start();
+                warn!(
+                    "Failed to read tap: {:?}. Suspending tap reads for {:?}.",
+                    err, delay
+                );
+            }
+            _ => {
+                pair.metrics.tap_read_fails.inc();
+                pair.metrics.tap_read_unknown_fails.inc();
+                if pair.tap_read_backoff.should_log_error() {
+                    error!("Failed to read tap: {:?}", err);
+                }
+            }
+        }
     }
 
-    fn process_rx(&mut self) -> Result<(), DeviceError> {
-        // Read as many frames as possible.
+    fn process_rx(&mut self, pair: usize) -> Result<(), DeviceError> {
+        // This is safe since we checked in the event handler that the device is activated.
+        let mem = self.device_state.mem().unwrap();
+        // The guest doesn't need to notify us of the RX buffers it adds while we are writing
+        // frames. The notifications are enabled again once we run out of buffers.
+        self.queues[Self::rx_queue_index(pair)].disable_notification(mem);
+
+        // Read as many frames as possible. The frames of the tap go straight to the guest when
+        // the next RX descriptor chain can hold any frame, the other ones through `rx_frame_buf`.
         loop {
-            match self.read_from_mmds_or_tap() {
+            let read = match self.read_from_mmds(pair).transpose() {
+                Some(read) => read,
+                None => match self.read_tap_to_guest(pair) {
+                    Ok(DirectRx::Delivered) => continue,
+                    Ok(DirectRx::Deferred) => {
+                        self.pairs[pair].rx_deferred_frame = true;
+                        break;
+                    }
+                    Ok(DirectRx::Filtered) => continue,
+                    Ok(DirectRx::NoChain) => match self.read_from_tap(pair) {
+                        Ok(count) if !self.rx_frame_buf_passes(pair, count) => {
+                            self.pairs[pair].metrics.rx_count.inc();
+                            self.pairs[pair].metrics.rx_filtered_frames.inc();
+                            continue;
+                        }
+                        read => read,
+                    },
+                    Err(err) => Err(err),
+                },
+            };
+            match read {
                 Ok(count) => {
-                    self.rx_bytes_read = count;
-                    self.metrics.rx_count.inc();
-                    if !self.rate_limited_rx_single_frame() {
-                        self.rx_deferred_frame = true;
+                    self.pairs[pair].rx_bytes_read = count;
+                    self.pairs[pair].metrics.rx_count.inc();
+                    if !self.rate_limited_rx_single_frame(pair) {
+                        self.pairs[pair].rx_deferred_frame = true;
                         break;
                     }
                 }
                 Err(NetError::IO(err)) => {
-                    // The tap device is non-blocking, so any error aside from EAGAIN is
-                    // unexpected.
-                    match err.raw_os_error() {
-                        Some(err) if err == EAGAIN => (),
-                        _ => {
-                            error!("Failed to read tap: {:?}", err);
-                            self.metrics.tap_read_fails.inc();
-                            return Err(DeviceError::FailedReadTap);
-                        }
-                    };
+                    self.handle_tap_read_error(pair, &err);
                     break;
                 }
                 Err(err) => {
@@ -555,32 +1677,39 @@ impl Net {
 
         // At this point we processed as many Rx frames as possible.
         // We have to wake the guest if at least one descriptor chain has been used.
-        self.signal_used_queue(NetQueue::Rx)
+        self.signal_used_queue(pair, NetQueue::Rx)
     }
 
     // Process the deferred frame first, then continue reading from tap.
-    fn handle_deferred_frame(&mut self) -> Result<(), DeviceError> {
-        if self.rate_limited_rx_single_frame() {
-            self.rx_deferred_frame = false;
+    fn handle_deferred_frame(&mut self, pair: usize) -> Result<(), DeviceError> {
+        if self.rate_limited_rx_single_frame(pair) {
+            self.pairs[pair].rx_deferred_frame = false;
             // process_rx() was interrupted possibly before consuming all
             // packets in the tap; try continuing now.
-            return self.process_rx();
+            return self.process_rx(pair);
         }
 
-        self.signal_used_queue(NetQueue::Rx)
+        self.signal_used_queue(pair, NetQueue::Rx)
     }
 
-    fn resume_rx(&mut self) -> Result<(), DeviceError> {
-        if self.rx_deferred_frame {
-            self.handle_deferred_frame()
+    fn resume_rx(&mut self, pair: usize) -> Result<(), DeviceError> {
+        if self.pairs[pair].rx_deferred_frame {
+            self.handle_deferred_frame(pair)
         } else {
             Ok(())
         }
     }
 
-    fn process_tx(&mut self) -> Result<(), DeviceError> {
+    fn process_tx(&mut self, pair: usize) -> Result<(), DeviceError> {
+        // The frames wait for the tap to be writable again.
+        if self.pairs[pair].tap_write_blocked {
+            return Ok(());
+        }
+        #[cfg(feature = "memory-guards")]
+        let _canaries = crate::devices::virtio::canary::CanaryScope;
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
+        let limiter = self.rate_limiter_index(pair);
 
         // The MMDS network stack works like a state machine, based on synchronous calls, and
         // without being added to any event loop. If any frame is accepted by the MMDS, we also
@@ -588,77 +1717,294 @@ impl Net {
         // with the MMDS network stack.
         let mut process_rx_for_mmds = false;
         let mut used_any = false;
-        let tx_queue = &mut self.queues[TX_INDEX];
+        #[cfg(test)]
+        if let Some(hook) = self.tx_pass_hook {
+            hook(&self.tx_rate_limiters[limiter]);
+        }
+        let tx_queue = &mut self.queues[Self::tx_queue_index(pair)];
+        let tx_rate_limiter = &mut self.tx_rate_limiters[limiter];
+        let queue_pair = &mut self.pairs[pair];
+        // The notifications are enabled again once the queue is drained. If the pass stops
+        // early, we get back to the queue when the rate limiter is replenished.
+        tx_queue.disable_notification(mem);
+        // The buffer is only given back if the pass completes, an error dropping its storage.
+        let mut buffer = std::mem::take(&mut queue_pair.tx_buffer).recycle();
+
+        let mut heads = recycle_chains(std::mem::take(&mut queue_pair.tx_heads));
+
+        'pass: while tx_queue.pop_batch(mem, TX_POP_BATCH_SIZE, &mut heads) > 0 {
+            let in_ring = tx_queue.len(mem);
+            let mut batch = heads.drain(..);
+            while let Some(head) = batch.next() {
+                queue_pair
+                    .metrics
+                    .tx_remaining_reqs_count
+                    .add(u64::from(in_ring) + batch.len() as u64);
+                let head_index = head.index;
+                // Parse IoVecBuffer from descriptor head
+                if buffer.load_descriptor_chain(head).is_err() {
+                    queue_pair.metrics.tx_fails.inc();
+                    queue_pair.tx_used_batch.push((head_index, 0));
+                    continue;
+                }
 
-        while let Some(head) = tx_queue.pop_or_enable_notification(mem) {
-            self.metrics
-                .tx_remaining_reqs_count
-                .add(tx_queue.len(mem).into());
-            let head_index = head.index;
-            // Parse IoVecBuffer from descriptor head
-            let buffer = match IoVecBuffer::from_descriptor_chain(head) {
-                Ok(buffer) => buffer,
-                Err(_) => {
-                    self.metrics.tx_fails.inc();
-                    tx_queue
-                        .add_used(mem, head_index, 0)
-                        .map_err(DeviceError::QueueError)?;
+                // We only handle frames that are up to MAX_BUFFER_SIZE
+                if buffer.len() as usize > MAX_BUFFER_SIZE {
+                    error!("net: received too big frame from driver");
+                    queue_pair.metrics.tx_malformed_frames.inc();
+                    queue_pair.tx_used_batch.push((head_index, 0));
                     continue;
                 }
-            };
 
-            // We only handle frames that are up to MAX_BUFFER_SIZE
-            if buffer.len() as usize > MAX_BUFFER_SIZE {
-                error!("net: received too big frame from driver");
-                self.metrics.tx_malformed_frames.inc();
-                tx_queue
-                    .add_used(mem, head_index, 0)
-                    .map_err(DeviceError::QueueError)?;
-                continue;
-            }
+                if !Self::rate_limiter_consume_op(tx_rate_limiter, u64::from(buffer.len())) {
+                    // Give back this frame and the rest of the batch.
+                    for _ in 0..=batch.len() {
+                        tx_queue.undo_pop();
+                    }
+                    queue_pair.metrics.tx_rate_limiter_throttled.inc();
+                    break 'pass;
+                }
 
-            if !Self::rate_limiter_consume_op(&mut self.tx_rate_limiter, u64::from(buffer.len())) {
-                tx_queue.undo_pop();
-                self.metrics.tx_rate_limiter_throttled.inc();
-                break;
-            }
+                // MMDS requests may come from any pair, their responses are sent through the
+                // first one.
+                match Self::write_to_mmds_or_tap(
+                    self.mmds_ns.as_mut(),
+                    tx_rate_limiter,
+                    &mut queue_pair.tx_frame_headers,
+                    &buffer,
+                    &mut queue_pair.tap,
+                    self.guest_mac,
+                    &queue_pair.metrics,
+                ) {
+                    // MMDS consumed this frame/request, let's also try to process the response.
+                    Ok(frame_consumed_by_mmds) => process_rx_for_mmds |= frame_consumed_by_mmds,
+                    Err(NetError::IO(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                        // Give back this frame and the rest of the batch, the pass resumes once
+                        // the tap is writable.
+                        for _ in 0..=batch.len() {
+                            tx_queue.undo_pop();
+                        }
+                        Self::rate_limiter_replenish_op(tx_rate_limiter, u64::from(buffer.len()));
+                        queue_pair.tap_write_blocked = true;
+                        queue_pair.metrics.tx_backpressure.inc();
+                        break 'pass;
+                    }
+                    Err(_) => (),
+                }
 
-            let frame_consumed_by_mmds = Self::write_to_mmds_or_tap(
-                self.mmds_ns.as_mut(),
-                &mut self.tx_rate_limiter,
-                &mut self.tx_frame_headers,
-                &buffer,
-                &mut self.tap,
-                self.guest_mac,
-                &self.metrics,
-            )
-            .unwrap_or(false);
-            if frame_consumed_by_mmds && !self.rx_deferred_frame {
-                // MMDS consumed this frame/request, let's also try to process the response.
-                process_rx_for_mmds = true;
+                queue_pair.tx_used_batch.push((head_index, 0));
+                used_any = true;
             }
-
-            tx_queue
-                .add_used(mem, head_index, 0)
-                .map_err(DeviceError::QueueError)?;
-            used_any = true;
         }
+        queue_pair.tx_heads = recycle_chains(heads);
+        queue_pair.tx_buffer = buffer.recycle();
+
+        let result = tx_queue.add_used_batch(mem, &queue_pair.tx_used_batch);
+        queue_pair.tx_used_batch.clear();
+        result.map_err(DeviceError::QueueError)?;
 
         if !used_any {
-            self.metrics.no_tx_avail_buffer.inc();
+            queue_pair.metrics.no_tx_avail_buffer.inc();
         }
 
-        self.signal_used_queue(NetQueue::Tx)?;
+        self.signal_used_queue(pair, NetQueue::Tx)?;
+
+        #[cfg(test)]
+        if let Some(hook) = self.tx_pass_hook {
+            hook(&self.tx_rate_limiters[limiter]);
+        }
 
         // An incoming frame for the MMDS may trigger the transmission of a new message.
-        if process_rx_for_mmds {
-            self.process_rx()
+        if process_rx_for_mmds && !self.pairs[0].rx_deferred_frame {
+            self.process_rx(0)
         } else {
             Ok(())
         }
     }
 
-    /// Updates the parameters for the rate limiters
+    // Reads the command of a control queue chain, returning it along with the address of the
+    // byte the device acknowledges it with, or `None` if the chain is malformed.
+    fn read_ctrl_command(
+        head: &DescriptorChain,
+    ) -> Option<([u8; CTRL_COMMAND_MAX_LEN], usize, GuestAddress)> {
+        let mut command = [0u8; CTRL_COMMAND_MAX_LEN];
+        let mut len = 0;
+        ChainLayout::scan(head).ok()?;
+        for desc in head.clone().checked_iter() {
+            let desc = desc.ok()?;
+            if desc.is_write_only() {
+                return (desc.len > 0).then_some((command, len, desc.addr));
+            }
+            // The bytes past the longest command handled are ignored.
+            let count = (desc.len as usize).min(CTRL_COMMAND_MAX_LEN - len);
+            desc.mem
+                .read_slice(&mut command[len..len + count], desc.addr)
+                .ok()?;
+            len += count;
+        }
+        None
+    }
+
+    // Executes a command of the control queue, returning its acknowledgement.
+    fn handle_ctrl_command(&mut self, command: &[u8]) -> u32 {
+        match *command {
+            [class, cmd, pairs_low, pairs_high]
+                if u32::from(class) == VIRTIO_NET_CTRL_MQ
+                    && u32::from(cmd) == VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET =>
+            {
+                let num_pairs = u16::from_le_bytes([pairs_low, pairs_high]);
+                if !self.has_feature(u64::from(VIRTIO_NET_F_MQ)) {
+                    warn!("Net queue pairs set without VIRTIO_NET_F_MQ");
+                    return VIRTIO_NET_ERR;
+                }
+                match self.set_active_pairs(num_pairs) {
+                    Ok(()) => VIRTIO_NET_OK,
+                    Err(err) => {
+                        warn!("Failed to set the net queue pairs: {}", err);
+                        VIRTIO_NET_ERR
+                    }
+                }
+            }
+            [class, cmd, on]
+                if u32::from(class) == VIRTIO_NET_CTRL_RX
+                    && (u32::from(cmd) == VIRTIO_NET_CTRL_RX_PROMISC
+                        || u32::from(cmd) == VIRTIO_NET_CTRL_RX_ALLMULTI) =>
+            {
+                if !self.has_feature(u64::from(VIRTIO_NET_F_CTRL_RX)) {
+                    warn!("Net RX mode set without VIRTIO_NET_F_CTRL_RX");
+                    return VIRTIO_NET_ERR;
+                }
+                if u32::from(cmd) == VIRTIO_NET_CTRL_RX_PROMISC {
+                    self.rx_filter.promisc = on != 0;
+                } else {
+                    self.rx_filter.all_multi = on != 0;
+                }
+                VIRTIO_NET_OK
+            }
+            [class, cmd, ref tables @ ..]
+                if u32::from(class) == VIRTIO_NET_CTRL_MAC
+                    && u32::from(cmd) == VIRTIO_NET_CTRL_MAC_TABLE_SET =>
+            {
+                if !self.has_feature(u64::from(VIRTIO_NET_F_CTRL_RX)) {
+                    warn!("Net MAC table set without VIRTIO_NET_F_CTRL_RX");
+                    return VIRTIO_NET_ERR;
+                }
+                // The multicast table follows the unicast one. Past a unicast table with more
+                // addresses than the filter holds, all the multicast frames are received too.
+                let tables = match RxFilter::read_mac_table(tables) {
+                    Some((Some(unicast), rest)) => RxFilter::read_mac_table(rest)
+                        .map(|(multicast, _)| (Some(unicast), multicast)),
+                    Some((None, _)) => Some((None, None)),
+                    None => None,
+                };
+                let Some((unicast, multicast)) = tables else {
+                    warn!("Malformed net MAC table set command");
+                    return VIRTIO_NET_ERR;
+                };
+                self.rx_filter.unicast = unicast;
+                self.rx_filter.multicast = multicast;
+                VIRTIO_NET_OK
+            }
+            [class, cmd]
+                if u32::from(class) == VIRTIO_NET_CTRL_ANNOUNCE
+                    && u32::from(cmd) == VIRTIO_NET_CTRL_ANNOUNCE_ACK =>
+            {
+                if !self.has_feature(u64::from(VIRTIO_NET_F_GUEST_ANNOUNCE)) {
+                    warn!("Net announce acknowledged without VIRTIO_NET_F_GUEST_ANNOUNCE");
+                    return VIRTIO_NET_ERR;
+                }
+                self.config_space.status &= !u16::try_from(VIRTIO_NET_S_ANNOUNCE).unwrap();
+                VIRTIO_NET_OK
+            }
+            _ => {
+                warn!("Unsupported net control command: {:?}", command);
+                VIRTIO_NET_ERR
+            }
+        }
+    }
+
+    fn process_ctrl_queue(&mut self) -> Result<(), DeviceError> {
+        let Some(index) = self.ctrl_queue_index() else {
+            return Ok(());
+        };
+
+        loop {
+            // This is safe since we checked in the event handler that the device is activated.
+            let mem = self.device_state.mem().unwrap();
+            let Some(head) = self.queues[index].pop_or_enable_notification(mem) else {
+                break;
+            };
+            let head_index = head.index;
+            let used_len = match Self::read_ctrl_command(&head) {
+                Some((command, len, ack_addr)) => {
+                    // Safe to unwrap, the acknowledgements are single bytes.
+                    let ack = u8::try_from(self.handle_ctrl_command(&command[..len])).unwrap();
+                    let mem = self.device_state.mem().unwrap();
+                    match mem.write_obj(ack, ack_addr) {
+                        Ok(()) => 1,
+                        Err(err) => {
+                            error!("Failed to acknowledge net control command: {:?}", err);
+                            0
+                        }
+                    }
+                }
+                None => {
+                    warn!("Malformed net control command");
+                    0
+                }
+            };
+
+            let mem = self.device_state.mem().unwrap();
+            self.queues[index]
+                .add_used(mem, head_index, used_len)
+                .map_err(DeviceError::QueueError)?;
+        }
+
+        let mem = self.device_state.mem().unwrap();
+        if self.queues[index].prepare_kick(mem) {
+            self.irq_trigger
+                .trigger_irq(IrqType::Vring)
+                .map_err(|err| {
+                    self.metrics.event_fails.inc();
+                    DeviceError::FailedSignalingIrq(err)
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the number of pairs used by the driver, the first ones, enabling the queues of the
+    /// tap of these pairs and disabling the others.
+    ///
+    /// The frames waiting to be received on the pairs which are no longer used are dropped.
+    pub(crate) fn set_active_pairs(&mut self, num_pairs: u16) -> Result<(), NetError> {
+        if !(VIRTIO_NET_CTRL_MQ_VQ_PAIRS_MIN..=u32::from(self.num_pairs()))
+            .contains(&u32::from(num_pairs))
+        {
+            return Err(NetError::QueuePairs(num_pairs, self.num_pairs()));
+        }
+
+        let num_pairs = usize::from(num_pairs);
+        for (i, pair) in self.pairs.iter_mut().enumerate().skip(1) {
+            let active = i < num_pairs;
+            if active != (i < self.active_pairs) {
+                pair.tap
+                    .set_queue_enabled(active)
+                    .map_err(NetError::TapSetQueue)?;
+                pair.rx_deferred_frame &= active;
+            }
+        }
+        self.active_pairs = num_pairs;
+        Ok(())
+    }
+
+    /// Updates the parameters for the rate limiters.
+    ///
+    /// The new configuration of all rate limiters is built before any of them is changed, and
+    /// then swapped in at once. Since the device is locked for the whole update, as it is for each
+    /// processing pass, no pass sees a mix of the old and the new configuration. The updated
+    /// buckets carry over the budget of the buckets they replace, see
+    /// `TokenBucket::carry_over()`.
     pub fn patch_rate_limiters(
         &mut self,
         rx_bytes: BucketUpdate,
@@ -666,13 +2012,23 @@ impl Net {
         tx_bytes: BucketUpdate,
         tx_ops: BucketUpdate,
     ) {
-        self.rx_rate_limiter.update_buckets(rx_bytes, rx_ops);
-        self.tx_rate_limiter.update_buckets(tx_bytes, tx_ops);
-    }
-
-    #[cfg(not(test))]
-    fn read_tap(&mut self) -> std::io::Result<usize> {
-        self.tap.read(&mut self.rx_frame_buf)
+        let rx_updates: Vec<_> = self
+            .rx_rate_limiters
+            .iter()
+            .map(|limiter| limiter.build_update(rx_bytes.clone(), rx_ops.clone()))
+            .collect();
+        let tx_updates: Vec<_> = self
+            .tx_rate_limiters
+            .iter()
+            .map(|limiter| limiter.build_update(tx_bytes.clone(), tx_ops.clone()))
+            .collect();
+        for (limiter, update) in self.rx_rate_limiters.iter_mut().zip(rx_updates) {
+            limiter.apply_update(update);
+        }
+        for (limiter, update) in self.tx_rate_limiters.iter_mut().zip(tx_updates) {
+            limiter.apply_update(update);
+        }
+        self.metrics.rate_limiter_updates.inc();
     }
 
     #[cfg(not(test))]
@@ -680,116 +2036,261 @@ impl Net {
         tap.write_iovec(buf)
     }
 
-    /// Process a single RX queue event.
+    /// Process a single RX queue event of the `pair`.
     ///
     /// This is called by the event manager responding to the guest adding a new
     /// buffer in the RX queue.
-    pub fn process_rx_queue_event(&mut self) {
-        self.metrics.rx_queue_event_count.inc();
+    pub fn process_rx_queue_event(&mut self, pair: usize) -> Result<(), DeviceError> {
+        self.pairs[pair].metrics.rx_queue_event_count.inc();
+        record_activity(ActivitySignal::Net);
 
-        if let Err(err) = self.queue_evts[RX_INDEX].read() {
+        if let Err(err) = self.queue_evts[Self::rx_queue_index(pair)].read() {
             // rate limiters present but with _very high_ allowed rate
-            error!("Failed to get rx queue event: {:?}", err);
-            self.metrics.event_fails.inc();
-        } else if self.rx_rate_limiter.is_blocked() {
-            self.metrics.rx_rate_limiter_throttled.inc();
+            self.pairs[pair].metrics.event_fails.inc();
+            Err(DeviceError::EventFd(err))
+        } else if self.rx_rate_limiters[self.rate_limiter_index(pair)].is_blocked() {
+            self.pairs[pair].metrics.rx_rate_limiter_throttled.inc();
+            Ok(())
         } else {
             // If the limiter is not blocked, resume the receiving of bytes.
-            self.resume_rx()
-                .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+            self.resume_rx(pair)
+                .map_err(|err| report_net_event_fail(&self.pairs[pair].metrics, err))
         }
     }
 
-    pub fn process_tap_rx_event(&mut self) {
+    /// Process the frames received on the tap queue of the `pair`.
+    ///
+    /// Failures to read from the tap are not reported here: the device backs off from reading
+    /// the tap instead.
+    pub fn process_tap_rx_event(&mut self, pair: usize) -> Result<(), DeviceError> {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
-        self.metrics.rx_tap_event_count.inc();
+        self.pairs[pair].metrics.rx_tap_event_count.inc();
+        // Frames arriving on the tap resume an auto-paused microVM.
+        record_activity(ActivitySignal::Net);
 
         // While there are no available RX queue buffers and there's a deferred_frame
         // don't process any more incoming. Otherwise start processing a frame. In the
         // process the deferred_frame flag will be set in order to avoid freezing the
         // RX queue.
-        if self.queues[RX_INDEX].is_empty(mem) && self.rx_deferred_frame {
-            self.metrics.no_rx_avail_buffer.inc();
-            return;
+        if self.queues[Self::rx_queue_index(pair)].is_empty(mem)
+            && self.pairs[pair].rx_deferred_frame
+        {
+            self.pairs[pair].metrics.no_rx_avail_buffer.inc();
+            return Ok(());
         }
 
         // While limiter is blocked, don't process any more incoming.
-        if self.rx_rate_limiter.is_blocked() {
-            self.metrics.rx_rate_limiter_throttled.inc();
-            return;
+        if self.rx_rate_limiters[self.rate_limiter_index(pair)].is_blocked() {
+            self.pairs[pair].metrics.rx_rate_limiter_throttled.inc();
+            return Ok(());
         }
 
-        if self.rx_deferred_frame
+        if self.pairs[pair].rx_deferred_frame
         // Process a deferred frame first if available. Don't read from tap again
         // until we manage to receive this deferred frame.
         {
-            self.handle_deferred_frame()
-                .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+            self.handle_deferred_frame(pair)
+                .map_err(|err| report_net_event_fail(&self.pairs[pair].metrics, err))
+        } else {
+            self.process_rx(pair)
+                .map_err(|err| report_net_event_fail(&self.pairs[pair].metrics, err))
+        }
+    }
+
+    /// Process the expiration of the tap read backoff of the `pair`.
+    ///
+    /// Tap reads are resumed, and the frames that were queued in the tap in the meantime are
+    /// read right away.
+    pub fn process_tap_read_backoff_event(&mut self, pair: usize) -> Result<(), DeviceError> {
+        self.pairs[pair].tap_read_backoff.expire();
+        self.process_tap_rx_event(pair)
+    }
+
+    /// Process the expiration of the MMDS watch poll timer.
+    ///
+    /// The responses parked by the MMDS network stack which completed in the meantime are sent
+    /// to the guest, through the first pair.
+    pub fn process_mmds_watch_poll_event(&mut self) -> Result<(), DeviceError> {
+        self.mmds_watch_poll.expire();
+
+        if self.rx_rate_limiters[0].is_blocked() {
+            self.metrics.rx_rate_limiter_throttled.inc();
+            return Ok(());
+        }
+
+        if self.pairs[0].rx_deferred_frame {
+            self.handle_deferred_frame(0)
+                .map_err(|err| report_net_event_fail(&self.metrics, err))
         } else {
-            self.process_rx()
-                .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+            self.process_rx(0)
+                .map_err(|err| report_net_event_fail(&self.metrics, err))
         }
     }
 
-    /// Process a single TX queue event.
+    /// Arms the MMDS watch poll timer while the MMDS network stack has parked responses.
+    pub(crate) fn update_mmds_watch_poll(&mut self) {
+        let parked_responses = self
+            .mmds_ns
+            .as_ref()
+            .is_some_and(|ns| ns.has_parked_responses());
+        self.mmds_watch_poll.update(parked_responses);
+    }
+
+    /// Process a single TX queue event of the `pair`.
     ///
     /// This is called by the event manager responding to the guest adding a new
     /// buffer in the TX queue.
-    pub fn process_tx_queue_event(&mut self) {
-        self.metrics.tx_queue_event_count.inc();
-        if let Err(err) = self.queue_evts[TX_INDEX].read() {
-            error!("Failed to get tx queue event: {:?}", err);
-            self.metrics.event_fails.inc();
-        } else if !self.tx_rate_limiter.is_blocked()
+    pub fn process_tx_queue_event(&mut self, pair: usize) -> Result<(), DeviceError> {
+        self.pairs[pair].metrics.tx_queue_event_count.inc();
+        record_activity(ActivitySignal::Net);
+        if let Err(err) = self.queue_evts[Self::tx_queue_index(pair)].read() {
+            self.pairs[pair].metrics.event_fails.inc();
+            Err(DeviceError::EventFd(err))
+        } else if !self.tx_rate_limiters[self.rate_limiter_index(pair)].is_blocked()
         // If the limiter is not blocked, continue transmitting bytes.
         {
-            self.process_tx()
-                .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+            self.process_tx(pair)
+                .map_err(|err| report_net_event_fail(&self.pairs[pair].metrics, err))
         } else {
-            self.metrics.tx_rate_limiter_throttled.inc();
+            self.pairs[pair].metrics.tx_rate_limiter_throttled.inc();
+            Ok(())
+        }
+    }
+
+    /// Process the tap of the `pair` becoming writable, after it could not take a TX frame.
+    pub fn process_tap_tx_event(&mut self, pair: usize) -> Result<(), DeviceError> {
+        if !self.pairs[pair].tap_write_blocked {
+            return Ok(());
+        }
+        self.pairs[pair].tap_write_blocked = false;
+        if self.tx_rate_limiters[self.rate_limiter_index(pair)].is_blocked() {
+            // The TX is resumed by the rate limiter event.
+            self.pairs[pair].metrics.tx_rate_limiter_throttled.inc();
+            return Ok(());
+        }
+        self.process_tx(pair)
+            .map_err(|err| report_net_event_fail(&self.pairs[pair].metrics, err))
+    }
+
+    /// Process a single control queue event.
+    ///
+    /// This is called by the event manager responding to the guest adding a new
+    /// command in the control queue.
+    pub fn process_ctrl_queue_event(&mut self) -> Result<(), DeviceError> {
+        let Some(index) = self.ctrl_queue_index() else {
+            return Ok(());
+        };
+        if let Err(err) = self.queue_evts[index].read() {
+            self.metrics.event_fails.inc();
+            Err(DeviceError::EventFd(err))
+        } else {
+            self.process_ctrl_queue()
+                .map_err(|err| report_net_event_fail(&self.metrics, err))
         }
     }
 
-    pub fn process_rx_rate_limiter_event(&mut self) {
+    /// Process the event of the RX rate limiter at `index`, the one of the pair of the same
+    /// index with `RateLimiterScope::QueuePair`.
+    pub fn process_rx_rate_limiter_event(&mut self, index: usize) -> Result<(), DeviceError> {
         self.metrics.rx_event_rate_limiter_count.inc();
         // Upon rate limiter event, call the rate limiter handler
         // and restart processing the queue.
 
-        match self.rx_rate_limiter.event_handler() {
+        match self.rx_rate_limiters[index].event_handler() {
             Ok(_) => {
-                // There might be enough budget now to receive the frame.
-                self.resume_rx()
-                    .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+                // There might be enough budget now to receive the frames.
+                let mut result = Ok(());
+                for pair in self.rate_limiter_pairs(index) {
+                    let pair_result = self
+                        .resume_rx(pair)
+                        .map_err(|err| report_net_event_fail(&self.pairs[pair].metrics, err));
+                    result = result.and(pair_result);
+                }
+                result
             }
             Err(err) => {
-                error!("Failed to get rx rate-limiter event: {:?}", err);
                 self.metrics.event_fails.inc();
+                Err(DeviceError::RateLimiter(err))
             }
         }
     }
 
-    pub fn process_tx_rate_limiter_event(&mut self) {
+    /// Process the event of the TX rate limiter at `index`, the one of the pair of the same
+    /// index with `RateLimiterScope::QueuePair`.
+    pub fn process_tx_rate_limiter_event(&mut self, index: usize) -> Result<(), DeviceError> {
         self.metrics.tx_rate_limiter_event_count.inc();
         // Upon rate limiter event, call the rate limiter handler
         // and restart processing the queue.
-        match self.tx_rate_limiter.event_handler() {
+        match self.tx_rate_limiters[index].event_handler() {
             Ok(_) => {
-                // There might be enough budget now to send the frame.
-                self.process_tx()
-                    .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+                // There might be enough budget now to send the frames.
+                let mut result = Ok(());
+                for pair in self.rate_limiter_pairs(index) {
+                    let pair_result = self
+                        .process_tx(pair)
+                        .map_err(|err| report_net_event_fail(&self.pairs[pair].metrics, err));
+                    result = result.and(pair_result);
+                }
+                result
             }
             Err(err) => {
-                error!("Failed to get tx rate-limiter event: {:?}", err);
                 self.metrics.event_fails.inc();
+                Err(DeviceError::RateLimiter(err))
             }
         }
     }
 
     /// Process device virtio queue(s).
     pub fn process_virtio_queues(&mut self) {
-        let _ = self.resume_rx();
-        let _ = self.process_tx();
+        for pair in 0..self.pairs.len() {
+            let _ = self.resume_rx(pair);
+            let _ = self.process_tx(pair);
+        }
+        let _ = self.process_ctrl_queue();
+    }
+
+    /// Stops the device before it is unplugged from the microVM. The TX frames the tap accepts
+    /// are sent, and the descriptor chains left in the queues are handed back to the driver as
+    /// used with a length of 0, so that it doesn't leak their buffers. The driver is then asked to
+    /// reset the device, which doesn't process its events anymore.
+    pub fn quiesce(&mut self) {
+        let Some(mem) = self.device_state.mem().cloned() else {
+            return;
+        };
+        for pair in 0..self.pairs.len() {
+            if !self.queues[Self::tx_queue_index(pair)].ready {
+                continue;
+            }
+            if let Err(err) = self.process_tx(pair) {
+                warn!(
+                    "Net {}: failed to send the pending TX frames: {}",
+                    self.id, err
+                );
+            }
+        }
+
+        let mut used_any = false;
+        for queue in self.queues.iter_mut().filter(|queue| queue.ready) {
+            while let Some(head) = queue.pop(&mem) {
+                let head_index = head.index;
+                if let Err(err) = queue.add_used(&mem, head_index, 0) {
+                    error!(
+                        "Net {}: failed to return a descriptor chain: {}",
+                        self.id, err
+                    );
+                    break;
+                }
+                used_any = true;
+            }
+        }
+        if used_any {
+            let _ = self.irq_trigger.trigger_irq(IrqType::Vring);
+        }
+
+        self.needs_reset = true;
+        self.device_state = DeviceState::Inactive;
+        let _ = self.irq_trigger.trigger_irq(IrqType::Config);
     }
 }
 
@@ -806,6 +2307,10 @@ impl VirtioDevice for Net {
         self.acked_features = acked_features;
     }
 
+    fn virtio_features_pin(&self) -> Option<u64> {
+        self.virtio_features_pin
+    }
+
     fn device_type(&self) -> u32 {
         TYPE_NET
     }
@@ -836,7 +2341,8 @@ impl VirtioDevice for Net {
     }
 
     fn write_config(&mut self, offset: u64, data: &[u8]) {
-        let config_space_bytes = self.config_space.as_mut_slice();
+        // Only the MAC address is writable by the driver.
+        let config_space_bytes = &mut self.config_space.as_mut_slice()[..usize::from(MAC_ADDR_LEN)];
         let start = usize::try_from(offset).ok();
         let end = start.and_then(|s| s.checked_add(data.len()));
         let Some(dst) = start
@@ -861,6 +2367,8 @@ impl VirtioDevice for Net {
             }
         }
 
+        self.check_tap_mtu();
+
         if self.activate_evt.write(1).is_err() {
             self.metrics.activate_fails.inc();
             return Err(ActivateError::EventFd);
@@ -872,6 +2380,10 @@ impl VirtioDevice for Net {
     fn is_activated(&self) -> bool {
         self.device_state.is_activated()
     }
+
+    fn needs_reset(&self) -> bool {
+        self.needs_reset
+    }
 }
 
 #[cfg(test)]
@@ -887,18 +2399,24 @@ pub mod tests {
 
     use super::*;
     use crate::check_metric_after_block;
-    use crate::devices::virtio::gen::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
+    use crate::devices::virtio::gen::virtio_net::VIRTIO_NET_F_MQ;
+    use crate::devices::virtio::gen::virtio_ring::{
+        VIRTIO_RING_F_EVENT_IDX, VIRTIO_RING_F_INDIRECT_DESC,
+    };
     use crate::devices::virtio::iovec::IoVecBuffer;
     use crate::devices::virtio::net::device::{
         frame_bytes_from_buf, frame_bytes_from_buf_mut, frame_hdr_len, init_vnet_hdr, vnet_hdr_len,
     };
     use crate::devices::virtio::net::test_utils::test::TestHelper;
     use crate::devices::virtio::net::test_utils::{
-        default_net, if_index, inject_tap_tx_frame, set_mac, NetEvent, NetQueue, ReadTapMock,
-        TapTrafficSimulator, WriteTapMock,
+        default_guest_mac, default_net, default_net_multi_queue, if_index, inject_tap_tx_frame,
+        pipe_tap, set_mac, NetEvent, NetQueue, ReadTapMock, TapTrafficSimulator, WriteTapMock,
     };
     use crate::devices::virtio::net::NET_QUEUE_SIZES;
-    use crate::devices::virtio::queue::VIRTQ_DESC_F_WRITE;
+    use crate::devices::virtio::queue::{
+        VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE, VIRTQ_USED_F_NO_NOTIFY,
+    };
+    use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
     use crate::dumbo::pdu::arp::{EthIPv4ArpFrame, ETH_IPV4_FRAME_LEN};
     use crate::dumbo::pdu::ethernet::ETHERTYPE_ARP;
     use crate::dumbo::EthernetFrame;
@@ -906,7 +2424,7 @@ pub mod tests {
     use crate::rate_limiter::{BucketUpdate, RateLimiter, TokenBucket, TokenType};
     use crate::vstate::memory::{Address, GuestMemory};
 
-    impl Net {
+    impl QueuePair {
         pub(crate) fn read_tap(&mut self) -> io::Result<usize> {
             match &self.tap.mocks.read_tap {
                 ReadTapMock::MockFrame(frame) => {
@@ -917,10 +2435,28 @@ pub mod tests {
                     io::ErrorKind::Other,
                     "Read tap synthetically failed.",
                 )),
+                ReadTapMock::Errno(errno) => Err(io::Error::from_raw_os_error(*errno)),
                 ReadTapMock::TapFrame => self.tap.read(&mut self.rx_frame_buf),
             }
         }
 
+        pub(crate) fn read_tap_to(&mut self, buffer: &mut IoVecBufferMut) -> io::Result<usize> {
+            match &self.tap.mocks.read_tap {
+                ReadTapMock::MockFrame(frame) => {
+                    buffer.write_all_volatile_at(frame, 0).unwrap();
+                    Ok(frame.len())
+                }
+                ReadTapMock::Failure => Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "Read tap synthetically failed.",
+                )),
+                ReadTapMock::Errno(errno) => Err(io::Error::from_raw_os_error(*errno)),
+                ReadTapMock::TapFrame => self.tap.read_iovec(buffer),
+            }
+        }
+    }
+
+    impl Net {
         pub(crate) fn write_tap(tap: &mut Tap, buf: &IoVecBuffer) -> io::Result<usize> {
             match tap.mocks.write_tap {
                 WriteTapMock::Success => tap.write_iovec(buf),
@@ -928,6 +2464,8 @@ pub mod tests {
                     io::ErrorKind::Other,
                     "Write tap mock failure.",
                 )),
+                WriteTapMock::WouldBlock => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+                WriteTapMock::ShortWrite => Ok(buf.len() as usize - 1),
             }
         }
     }
@@ -979,23 +2517,148 @@ pub mod tests {
             | 1 << VIRTIO_NET_F_GUEST_UFO
             | 1 << VIRTIO_NET_F_HOST_TSO4
             | 1 << VIRTIO_NET_F_HOST_UFO
+            | 1 << VIRTIO_NET_F_MRG_RXBUF
+            | 1 << VIRTIO_NET_F_STATUS
+            | 1 << VIRTIO_NET_F_CTRL_VQ
+            | 1 << VIRTIO_NET_F_CTRL_RX
+            | 1 << VIRTIO_NET_F_GUEST_ANNOUNCE
             | 1 << VIRTIO_F_VERSION_1
-            | 1 << VIRTIO_RING_F_EVENT_IDX;
+            | 1 << VIRTIO_RING_F_EVENT_IDX
+            | 1 << VIRTIO_RING_F_INDIRECT_DESC;
+
+        assert_eq!(
+            net.avail_features_by_page(0),
+            (features & 0xFFFFFFFF) as u32,
+        );
+        assert_eq!(net.avail_features_by_page(1), (features >> 32) as u32);
+        for i in 2..10 {
+            assert_eq!(net.avail_features_by_page(i), 0u32);
+        }
+
+        for i in 0..10 {
+            net.ack_features_by_page(i, std::u32::MAX);
+        }
+
+        assert_eq!(net.acked_features, features);
+    }
+
+    #[test]
+    fn test_virtio_features_pin() {
+        let mut net = default_net();
+        assert_eq!(net.virtio_features_pin(), None);
+        assert_eq!(net.negotiated_features(), None);
+
+        // Pin a reduced feature set, without offloads.
+        let pin = 1 << VIRTIO_F_VERSION_1 | 1 << VIRTIO_RING_F_EVENT_IDX;
+        net.pin_virtio_features(pin).unwrap();
+        assert_eq!(net.virtio_features_pin(), Some(pin));
+        assert_eq!(net.avail_features, pin);
+
+        // The driver can only negotiate the pinned features.
+        net.ack_features_by_page(0, u32::MAX);
+        net.ack_features_by_page(1, u32::MAX);
+        assert_eq!(net.acked_features, pin);
+        net.activate(default_mem()).unwrap();
+        assert_eq!(net.negotiated_features(), Some(pin));
+        assert!(net.has_feature(u64::from(VIRTIO_RING_F_EVENT_IDX)));
+        assert!(!net.has_feature(u64::from(VIRTIO_NET_F_CSUM)));
+        assert!(!net.has_feature(u64::from(VIRTIO_NET_F_HOST_TSO4)));
+    }
+
+    #[test]
+    fn test_virtio_features_pin_unavailable() {
+        let mut net = default_net();
+        let avail_features = net.avail_features;
+
+        // The net device does not offer multiple queues, nor the bits past the known features.
+        for pin in [
+            avail_features | 1 << VIRTIO_NET_F_MQ,
+            1 << VIRTIO_F_VERSION_1 | 1 << 63,
+        ] {
+            assert_eq!(
+                net.pin_virtio_features(pin).unwrap_err(),
+                VirtioFeaturesPinError::UnavailableFeatures(pin & !avail_features)
+            );
+            // The features and the pin are left unchanged.
+            assert_eq!(net.avail_features, avail_features);
+            assert_eq!(net.virtio_features_pin(), None);
+        }
+    }
 
+    #[test]
+    fn test_offloads() {
+        let mut net = default_net();
+        let avail_features = net.avail_features;
+        assert_eq!(net.offloads(), NetOffloads::default());
         assert_eq!(
-            net.avail_features_by_page(0),
-            (features & 0xFFFFFFFF) as u32,
+            NetOffloads::default().tap_flags(),
+            gen::TUN_F_CSUM | gen::TUN_F_TSO4 | gen::TUN_F_TSO6 | gen::TUN_F_UFO
         );
-        assert_eq!(net.avail_features_by_page(1), (features >> 32) as u32);
-        for i in 2..10 {
-            assert_eq!(net.avail_features_by_page(i), 0u32);
-        }
 
-        for i in 0..10 {
-            net.ack_features_by_page(i, std::u32::MAX);
+        // The segmentation offloads need the checksum offload of their direction.
+        for (offloads, err) in [
+            (
+                NetOffloads {
+                    guest_csum: false,
+                    ..Default::default()
+                },
+                "The guest_tso4 offload requires the guest_csum offload",
+            ),
+            (
+                NetOffloads {
+                    csum: false,
+                    host_tso4: false,
+                    ..Default::default()
+                },
+                "The host_ufo offload requires the csum offload",
+            ),
+        ] {
+            assert_eq!(net.set_offloads(offloads).unwrap_err().to_string(), err);
+            assert_eq!(net.offloads(), NetOffloads::default());
+            assert_eq!(net.avail_features, avail_features);
         }
 
-        assert_eq!(net.acked_features, features);
+        // Disable the segmentation offloads of the RX path, and the UDP one of the TX path.
+        let offloads = NetOffloads {
+            guest_tso4: false,
+            guest_ufo: false,
+            host_ufo: false,
+            ..Default::default()
+        };
+        net.set_offloads(offloads).unwrap();
+        assert_eq!(net.offloads(), offloads);
+        assert_eq!(offloads.tap_flags(), gen::TUN_F_CSUM);
+        let disabled =
+            1 << VIRTIO_NET_F_GUEST_TSO4 | 1 << VIRTIO_NET_F_GUEST_UFO | 1 << VIRTIO_NET_F_HOST_UFO;
+        assert_eq!(net.avail_features, avail_features & !disabled);
+
+        // The driver can only negotiate the offloads offered.
+        net.ack_features_by_page(0, u32::MAX);
+        net.ack_features_by_page(1, u32::MAX);
+        assert_eq!(net.acked_features, avail_features & !disabled);
+        assert!(net.has_feature(u64::from(VIRTIO_NET_F_GUEST_CSUM)));
+        assert!(!net.has_feature(u64::from(VIRTIO_NET_F_GUEST_TSO4)));
+        assert!(net.has_feature(u64::from(VIRTIO_NET_F_HOST_TSO4)));
+        assert!(!net.has_feature(u64::from(VIRTIO_NET_F_HOST_UFO)));
+
+        // Without offloads, the tap only sends the guest whole frames with their checksum.
+        let no_offloads = NetOffloads {
+            guest_csum: false,
+            guest_tso4: false,
+            guest_ufo: false,
+            csum: false,
+            host_tso4: false,
+            host_ufo: false,
+        };
+        assert_eq!(no_offloads.tap_flags(), 0);
+        let mut net = default_net_multi_queue(2);
+        let avail_features = net.avail_features;
+        net.set_offloads(no_offloads).unwrap();
+        assert_eq!(
+            net.avail_features,
+            avail_features & !no_offloads.disabled_features()
+        );
+        assert_eq!(no_offloads.disabled_features().count_ones(), 6);
     }
 
     #[test]
@@ -1011,7 +2674,7 @@ pub mod tests {
 
         // Invalid read.
         config_mac = [0u8; MAC_ADDR_LEN as usize];
-        net.read_config(u64::from(MAC_ADDR_LEN), &mut config_mac);
+        net.read_config(mem::size_of::<ConfigSpace>() as u64, &mut config_mac);
         assert_eq!(config_mac, [0u8, 0u8, 0u8, 0u8, 0u8, 0u8]);
     }
 
@@ -1054,6 +2717,128 @@ pub mod tests {
         assert_eq!(new_config, new_config_read);
     }
 
+    #[test]
+    fn test_set_guest_mac() {
+        let mac = MacAddr::from_str("11:22:33:44:55:66").unwrap();
+
+        // Before activation, the MAC feature is offered along with the MAC.
+        let mut net = default_net();
+        net.avail_features &= !(1 << VIRTIO_NET_F_MAC);
+        net.set_guest_mac(mac).unwrap();
+        assert_eq!(net.guest_mac(), Some(&mac));
+        assert_ne!(net.avail_features & (1 << VIRTIO_NET_F_MAC), 0);
+        let mut config_mac = [0u8; MAC_ADDR_LEN as usize];
+        net.read_config(0, &mut config_mac);
+        assert_eq!(&config_mac, mac.get_bytes());
+
+        // Unless the pinned features exclude it.
+        let mut net = default_net();
+        net.pin_virtio_features(net.avail_features & !(1 << VIRTIO_NET_F_MAC))
+            .unwrap();
+        assert_eq!(
+            net.set_guest_mac(mac).unwrap_err().to_string(),
+            NetError::GuestMacNotNegotiated.to_string()
+        );
+        assert_eq!(net.guest_mac(), Some(&default_guest_mac()));
+
+        // Once activated, the driver is notified of the change.
+        let mut th = TestHelper::get_default();
+        th.net().acked_features = 1 << VIRTIO_NET_F_MAC;
+        th.activate_net();
+        th.net().set_guest_mac(mac).unwrap();
+        assert_eq!(th.net().guest_mac(), Some(&mac));
+        assert!(th.net().irq_trigger.has_pending_irq(IrqType::Config));
+
+        // A driver which did not negotiate the MAC feature would not read it.
+        th.net().acked_features = 0;
+        th.net().set_guest_mac(default_guest_mac()).unwrap_err();
+        assert_eq!(th.net().guest_mac(), Some(&mac));
+        assert!(!th.net().irq_trigger.has_pending_irq(IrqType::Config));
+    }
+
+    #[test]
+    fn test_mtu_config_space() {
+        // The MTU follows the MAC, the link status and the number of pairs.
+        assert_eq!(mem::size_of::<ConfigSpace>(), 12);
+
+        let mut net = default_net();
+        let mut mtu = [0u8; 2];
+        net.read_config(10, &mut mtu);
+        assert_eq!(u16::from_le_bytes(mtu), 0);
+
+        net.set_mtu(9000).unwrap();
+        net.read_config(10, &mut mtu);
+        assert_eq!(u16::from_le_bytes(mtu), 9000);
+
+        // The driver can't change the MTU through the config space.
+        net.write_config(10, &[0xdc, 0x05]);
+        net.read_config(10, &mut mtu);
+        assert_eq!(u16::from_le_bytes(mtu), 9000);
+    }
+
+    #[test]
+    fn test_mtu_features() {
+        // Without an MTU, the feature is not offered and the driver can't negotiate it.
+        let mut net = default_net();
+        assert_eq!(net.mtu(), None);
+        assert_eq!(net.avail_features & (1 << VIRTIO_NET_F_MTU), 0);
+        net.ack_features_by_page(0, u32::MAX);
+        assert!(!net.has_feature(u64::from(VIRTIO_NET_F_MTU)));
+
+        // Out of range MTUs are rejected.
+        for mtu in [0, NET_MIN_MTU - 1, NET_MAX_MTU + 1, u16::MAX] {
+            assert_eq!(
+                net.set_mtu(mtu).unwrap_err().to_string(),
+                format!("Invalid MTU {}, must be between 68 and 65532", mtu)
+            );
+            assert_eq!(net.mtu(), None);
+            assert_eq!(net.avail_features & (1 << VIRTIO_NET_F_MTU), 0);
+        }
+
+        // With an MTU, the feature is offered along with it.
+        let mut net = default_net();
+        let avail_features = net.avail_features;
+        for mtu in [NET_MIN_MTU, NET_MAX_MTU, 1500] {
+            net.set_mtu(mtu).unwrap();
+            assert_eq!(net.mtu(), Some(mtu));
+            assert_eq!(net.avail_features, avail_features | 1 << VIRTIO_NET_F_MTU);
+        }
+        // The MTU of the tap is read along with it.
+        assert_eq!(net.tap_mtu, Some(1500));
+        net.ack_features_by_page(0, u32::MAX);
+        assert!(net.has_feature(u64::from(VIRTIO_NET_F_MTU)));
+    }
+
+    #[test]
+    fn test_mtu_tap_mismatch() {
+        // A tap MTU matching the one offered is fine.
+        let mut th = TestHelper::get_default();
+        th.net().set_mtu(1500).unwrap();
+        th.net().acked_features = 1 << VIRTIO_NET_F_MTU;
+        th.activate_net();
+        assert_eq!(th.net().metrics.tap_mtu_too_small.count(), 0);
+
+        // A smaller tap MTU is reported, when the driver negotiated the feature.
+        let mut th = TestHelper::get_default();
+        th.net().set_mtu(9000).unwrap();
+        th.net().acked_features = 1 << VIRTIO_NET_F_MTU;
+        th.activate_net();
+        assert_eq!(th.net().metrics.tap_mtu_too_small.count(), 1);
+
+        let mut th = TestHelper::get_default();
+        th.net().set_mtu(9000).unwrap();
+        th.activate_net();
+        assert_eq!(th.net().metrics.tap_mtu_too_small.count(), 0);
+
+        // As the MTU of the tap can't always be read, there is nothing to check without it.
+        let mut th = TestHelper::get_default();
+        th.net().set_mtu(9000).unwrap();
+        th.net().tap_mtu = None;
+        th.net().acked_features = 1 << VIRTIO_NET_F_MTU;
+        th.activate_net();
+        assert_eq!(th.net().metrics.tap_mtu_too_small.count(), 0);
+    }
+
     #[test]
     fn test_rx_missing_queue_signal() {
         let mut th = TestHelper::get_default();
@@ -1130,7 +2915,10 @@ pub mod tests {
     fn test_rx_retry() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        th.net().tap.mocks.set_read_tap(ReadTapMock::TapFrame);
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
 
         // Add invalid descriptor chain - read only descriptor.
         th.add_desc_chain(
@@ -1170,7 +2958,7 @@ pub mod tests {
         th.rxq.check_used_elem(1, 3, 0);
         th.rxq.check_used_elem(2, 4, 0);
         // Check that the frame wasn't deferred.
-        assert!(!th.net().rx_deferred_frame);
+        assert!(!th.net().pairs[0].rx_deferred_frame);
         // Check that the frame has been written successfully to the valid Rx descriptor chain.
         th.rxq
             .check_used_elem(3, 5, frame.len().try_into().unwrap());
@@ -1181,7 +2969,10 @@ pub mod tests {
     fn test_rx_complex_desc_chain() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        th.net().tap.mocks.set_read_tap(ReadTapMock::TapFrame);
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
 
         // Create a valid Rx avail descriptor chain with multiple descriptors.
         th.add_desc_chain(
@@ -1204,7 +2995,7 @@ pub mod tests {
         );
 
         // Check that the frame wasn't deferred.
-        assert!(!th.net().rx_deferred_frame);
+        assert!(!th.net().pairs[0].rx_deferred_frame);
         // Check that the used queue has advanced.
         assert_eq!(th.rxq.used.idx.get(), 1);
         assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
@@ -1216,11 +3007,49 @@ pub mod tests {
         th.rxq.dtable[11].check_data(&frame[150..]);
     }
 
+    #[test]
+    fn test_rx_desc_chain_longer_than_frame() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
+
+        // The frame ends in the middle of the second descriptor. The descriptors past it are not
+        // walked, so that the read-only one is not noticed.
+        th.add_desc_chain(
+            NetQueue::Rx,
+            0,
+            &[
+                (0, 500, VIRTQ_DESC_F_WRITE),
+                (1, 1000, VIRTQ_DESC_F_WRITE),
+                (2, 100, 0),
+            ],
+        );
+        let frame = inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            th.net().metrics.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        assert!(!th.net().pairs[0].rx_deferred_frame);
+        assert_eq!(th.rxq.used.idx.get(), 1);
+        th.rxq
+            .check_used_elem(0, 0, frame.len().try_into().unwrap());
+        th.rxq.dtable[0].check_data(&frame[..500]);
+        th.rxq.dtable[1].check_data(&frame[500..]);
+    }
+
     #[test]
     fn test_rx_multiple_frames() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        th.net().tap.mocks.set_read_tap(ReadTapMock::TapFrame);
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
 
         // Create 2 valid Rx avail descriptor chains. Each one has enough space to fit the
         // following 2 frames. But only 1 frame has to be written to each chain.
@@ -1244,9 +3073,11 @@ pub mod tests {
         );
 
         // Check that the frames weren't deferred.
-        assert!(!th.net().rx_deferred_frame);
+        assert!(!th.net().pairs[0].rx_deferred_frame);
         // Check that the used queue has advanced.
         assert_eq!(th.rxq.used.idx.get(), 2);
+        // The device didn't run out of buffers, so the notifications stay disabled.
+        assert_eq!(th.rxq.used.flags.get(), VIRTQ_USED_F_NO_NOTIFY);
         assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
         // Check that the 1st frame was written successfully to the 1st Rx descriptor chain.
         th.rxq
@@ -1260,11 +3091,80 @@ pub mod tests {
         th.rxq.dtable[3].check_data(&[0; 500]);
     }
 
+    #[test]
+    fn test_rx_mergeable_buffers() {
+        const CHAIN_LEN: u16 = 14000;
+
+        let mut th = TestHelper::get_default();
+        th.net().acked_features = 1 << VIRTIO_NET_F_MRG_RXBUF;
+        th.activate_net();
+        // A GSO frame spanning 5 chains.
+        let frame = utils::rand::rand_alphanumerics(65000).as_bytes().to_vec();
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::MockFrame(frame.clone()));
+        let mut expected = frame.clone();
+        expected[vnet_hdr_len() - mem::size_of::<u16>()..vnet_hdr_len()]
+            .copy_from_slice(&5u16.to_le_bytes());
+        // The chains of a frame don't overlap, 5 of them fitting in the guest memory.
+        let add_chains = |th: &mut TestHelper, indexes: std::ops::Range<u16>| {
+            for index in indexes {
+                th.add_desc_chain(
+                    NetQueue::Rx,
+                    u64::from(CHAIN_LEN) * u64::from(index % 5),
+                    &[(index, u32::from(CHAIN_LEN), VIRTQ_DESC_F_WRITE)],
+                );
+            }
+        };
+        // Checks that the frame was written across the chains starting at `used_index`.
+        let check_frame = |th: &TestHelper, used_index: u16| {
+            for (i, chunk) in expected.chunks(usize::from(CHAIN_LEN)).enumerate() {
+                let index = used_index + u16::try_from(i).unwrap();
+                th.rxq
+                    .check_used_elem(index, index, u32::try_from(chunk.len()).unwrap());
+                th.rxq.dtable[usize::from(index)].check_data(chunk);
+            }
+        };
+
+        add_chains(&mut th, 0..5);
+        check_metric_after_block!(
+            th.net().metrics.rx_packets_count,
+            1,
+            th.simulate_event(NetEvent::Tap)
+        );
+        assert_eq!(th.rxq.used.idx.get(), 5);
+        check_frame(&th, 0);
+        // The next frame waits for more chains.
+        assert!(th.net().pairs[0].rx_deferred_frame);
+
+        // Two chains can't hold the frame, and are given back.
+        add_chains(&mut th, 5..7);
+        check_metric_after_block!(
+            th.net().metrics.no_rx_avail_buffer,
+            1,
+            th.simulate_event(NetEvent::RxQueue)
+        );
+        assert!(th.net().pairs[0].rx_deferred_frame);
+        assert_eq!(th.rxq.used.idx.get(), 5);
+        assert_eq!(th.net().queues[RX_INDEX].next_avail.0, 5);
+
+        // The frame is written once the driver adds enough chains.
+        add_chains(&mut th, 7..10);
+        check_metric_after_block!(
+            th.net().metrics.rx_packets_count,
+            1,
+            th.simulate_event(NetEvent::RxQueue)
+        );
+        assert_eq!(th.rxq.used.idx.get(), 10);
+        check_frame(&th, 5);
+    }
+
     #[test]
     fn test_tx_missing_queue_signal() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
 
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 4096, 0)]);
         th.net().queue_evts[TX_INDEX].read().unwrap();
@@ -1284,7 +3184,7 @@ pub mod tests {
     fn test_tx_writeable_descriptor() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
 
         let desc_list = [(0, 100, 0), (1, 100, VIRTQ_DESC_F_WRITE), (2, 500, 0)];
         th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
@@ -1299,11 +3199,37 @@ pub mod tests {
         assert!(!tap_traffic_simulator.pop_rx_packet(&mut []));
     }
 
+    #[test]
+    fn test_tx_cyclic_descriptor_chain() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
+
+        // The last descriptor links back to the head of the chain.
+        let desc_list = [(0, 100, 0), (1, 100, 0), (2, 500, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        th.write_tx_frame(&desc_list, 700);
+        th.txq.dtable[2].flags.set(VIRTQ_DESC_F_NEXT);
+        th.txq.dtable[2].next.set(0);
+        check_metric_after_block!(
+            th.net().metrics.tx_fails,
+            1,
+            th.event_manager.run_with_timeout(100)
+        );
+
+        // Check that the used queue advanced.
+        assert_eq!(th.txq.used.idx.get(), 1);
+        assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
+        th.txq.check_used_elem(0, 0, 0);
+        // Check that the frame was skipped.
+        assert!(!tap_traffic_simulator.pop_rx_packet(&mut []));
+    }
+
     #[test]
     fn test_tx_short_frame() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
 
         // Send an invalid frame (too small, VNET header missing).
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 1, 0)]);
@@ -1325,7 +3251,7 @@ pub mod tests {
     fn test_tx_big_frame() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
 
         // Send an invalid frame (too big, maximum buffer is MAX_BUFFER_SIZE).
         th.add_desc_chain(
@@ -1351,7 +3277,7 @@ pub mod tests {
     fn test_tx_empty_frame() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
 
         // Send an invalid frame (too small, VNET header missing).
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 0, 0)]);
@@ -1373,7 +3299,7 @@ pub mod tests {
     fn test_tx_retry() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
 
         // Add invalid descriptor chain - writeable descriptor.
         th.add_desc_chain(
@@ -1391,73 +3317,298 @@ pub mod tests {
         th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
         let frame = th.write_tx_frame(&desc_list, 1000);
 
-        // One frame is valid, one will not be handled because it includes write-only memory
-        // so that leaves us with 2 malformed (no vnet header) frames.
-        check_metric_after_block!(
-            th.net().metrics.tx_malformed_frames,
-            2,
-            th.event_manager.run_with_timeout(100)
+        // One frame is valid, one will not be handled because it includes write-only memory
+        // so that leaves us with 2 malformed (no vnet header) frames.
+        check_metric_after_block!(
+            th.net().metrics.tx_malformed_frames,
+            2,
+            th.event_manager.run_with_timeout(100)
+        );
+
+        // Check that the used queue advanced.
+        assert_eq!(th.txq.used.idx.get(), 4);
+        assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
+        th.txq.check_used_elem(3, 4, 0);
+        // Check that the valid frame was sent to the tap.
+        let mut buf = vec![0; 1000];
+        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
+        assert_eq!(&buf, &frame);
+        // Check that no other frame was sent to the tap.
+        assert!(!tap_traffic_simulator.pop_rx_packet(&mut []));
+    }
+
+    #[test]
+    fn test_tx_complex_descriptor() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
+
+        // Add gaps between the descriptor ids in order to ensure that we follow
+        // the `next` field.
+        let desc_list = [(3, 100, 0), (5, 50, 0), (11, 850, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        let frame = th.write_tx_frame(&desc_list, 1000);
+
+        check_metric_after_block!(
+            th.net().metrics.tx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // Check that the used queue advanced.
+        assert_eq!(th.txq.used.idx.get(), 1);
+        assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
+        th.txq.check_used_elem(0, 3, 0);
+        // Check that the frame was sent to the tap.
+        let mut buf = vec![0; 1000];
+        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
+        assert_eq!(&buf[..1000], &frame[..1000]);
+    }
+
+    #[test]
+    fn test_tx_tap_failure() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_write_tap(WriteTapMock::Failure);
+
+        let desc_list = [(0, 1000, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        let _ = th.write_tx_frame(&desc_list, 1000);
+
+        check_metric_after_block!(
+            th.net().metrics.tap_write_fails,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // Check that the used queue advanced.
+        assert_eq!(th.txq.used.idx.get(), 1);
+        assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
+        th.txq.check_used_elem(0, 0, 0);
+    }
+
+    #[test]
+    fn test_tx_tap_deferred() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_write_tap(WriteTapMock::WouldBlock);
+
+        let desc_list = [(0, 100, 0), (1, 50, 0), (2, 850, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        let frame = th.write_tx_frame(&desc_list, 1000);
+
+        check_metric_after_block!(
+            th.net().metrics.tap_write_deferred,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // The frame is kept in the queue until the tap is writable.
+        assert!(th.net().pairs[0].tap_write_blocked);
+        assert!(th.net().pairs[0].tap_write_registered);
+        assert_eq!(th.txq.used.idx.get(), 0);
+        assert_eq!(th.net().metrics.tap_write_fails.count(), 0);
+        assert!(!tap_traffic_simulator.pop_rx_packet(&mut []));
+
+        // The tap takes the frame once it is writable again.
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_write_tap(WriteTapMock::Success);
+        check_metric_after_block!(
+            th.net().metrics.tx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        assert!(!th.net().pairs[0].tap_write_blocked);
+        assert!(!th.net().pairs[0].tap_write_registered);
+        assert!(th.net().pairs[0].tap_read_backoff.tap_registered);
+        assert_eq!(th.txq.used.idx.get(), 1);
+        th.txq.check_used_elem(0, 0, 0);
+        // Check that the same bytes were sent to the tap.
+        let mut buf = vec![0; 1000];
+        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
+        assert_eq!(&buf, &frame);
+        assert!(!tap_traffic_simulator.pop_rx_packet(&mut []));
+    }
+
+    #[test]
+    fn test_tx_tap_deferred_rate_limiter() {
+        let mut th = TestHelper::get_default();
+        // A TX ops rate limiter with the budget of a single frame, slow enough not to refill
+        // during the test.
+        th.net().tx_rate_limiters[0] = RateLimiter::new(0, 0, 0, 1, 0, 100_000).unwrap();
+        th.activate_net();
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_write_tap(WriteTapMock::WouldBlock);
+
+        th.add_desc_chain(NetQueue::Tx, 0, &[(0, 1000, 0)]);
+        th.event_manager.run_with_timeout(100).unwrap();
+        assert!(th.net().pairs[0].tap_write_blocked);
+        // The frame gave its token back.
+        assert_eq!(th.net().tx_rate_limiters[0].ops().unwrap().budget(), 1);
+
+        // The driver adding frames while the tap is blocked doesn't make the device try the tap
+        // again.
+        th.add_desc_chain(NetQueue::Tx, 1000, &[(1, 1000, 0)]);
+        check_metric_after_block!(
+            th.net().metrics.tap_write_deferred,
+            0,
+            th.net().process_tx_queue_event(0).unwrap()
+        );
+        assert_eq!(th.txq.used.idx.get(), 0);
+
+        // Once the tap is writable, the deferred frame is sent with the token it gave back, the
+        // next one being throttled.
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_write_tap(WriteTapMock::Success);
+        check_metric_after_block!(
+            th.net().metrics.tx_rate_limiter_throttled,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.net().metrics.tx_packets_count.count(), 1);
+        assert_eq!(th.net().metrics.tap_write_deferred.count(), 1);
+        assert_eq!(th.txq.used.idx.get(), 1);
+        th.txq.check_used_elem(0, 0, 0);
+    }
+
+    #[test]
+    fn test_tx_tap_backpressure() {
+        const FRAME_LEN: u32 = 1000;
+        const FRAME_COUNT: u16 = 10;
+
+        let mut th = TestHelper::get_default();
+        // The pipe only takes a few of the frames at once.
+        let (tap, mut reader) = pipe_tap();
+        th.net().pairs[0].tap = tap;
+        th.activate_net();
+
+        let mut frames = Vec::new();
+        for i in 0..FRAME_COUNT {
+            let desc_list = [(i, FRAME_LEN, 0)];
+            th.add_desc_chain(
+                NetQueue::Tx,
+                u64::from(i) * u64::from(FRAME_LEN),
+                &desc_list,
+            );
+            frames.extend(th.write_tx_frame(&desc_list, FRAME_LEN as usize));
+        }
+
+        let mut sent = Vec::new();
+        let mut drain = |sent: &mut Vec<u8>| {
+            let mut buf = [0; 4096];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(count) => sent.extend_from_slice(&buf[..count]),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) => panic!("Failed to read from the pipe: {err}"),
+                }
+            }
+        };
+
+        th.event_manager.run_with_timeout(100).unwrap();
+        let mut blocked_passes = 0;
+        while th.txq.used.idx.get() < FRAME_COUNT {
+            // The pass stopped on a full pipe, and the device waits for it to be writable
+            // without spinning.
+            assert!(th.net().pairs[0].tap_write_blocked);
+            assert!(th.net().pairs[0].tap_write_registered);
+            assert_eq!(th.event_manager.run_with_timeout(50).unwrap(), 0);
+            blocked_passes += 1;
+            assert_eq!(th.net().metrics.tx_backpressure.count(), blocked_passes);
+
+            // Emptying the pipe resumes the pass from the frame it stopped on.
+            let used_idx = th.txq.used.idx.get();
+            drain(&mut sent);
+            th.event_manager.run_with_timeout(100).unwrap();
+            assert!(th.txq.used.idx.get() > used_idx);
+        }
+        drain(&mut sent);
+
+        // The tap got every frame once, in order.
+        assert!(blocked_passes > 0);
+        assert!(!th.net().pairs[0].tap_write_blocked);
+        assert_eq!(sent, frames);
+        assert_eq!(
+            th.net().metrics.tx_packets_count.count(),
+            u64::from(FRAME_COUNT)
         );
-
-        // Check that the used queue advanced.
-        assert_eq!(th.txq.used.idx.get(), 4);
-        assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
-        th.txq.check_used_elem(3, 4, 0);
-        // Check that the valid frame was sent to the tap.
-        let mut buf = vec![0; 1000];
-        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
-        assert_eq!(&buf, &frame);
-        // Check that no other frame was sent to the tap.
-        assert!(!tap_traffic_simulator.pop_rx_packet(&mut []));
+        assert_eq!(th.net().metrics.tap_write_fails.count(), 0);
+        assert_eq!(th.net().metrics.tx_backpressure.count(), blocked_passes);
     }
 
     #[test]
-    fn test_tx_complex_descriptor() {
+    fn test_quiesce() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_write_tap(WriteTapMock::WouldBlock);
 
-        // Add gaps between the descriptor ids in order to ensure that we follow
-        // the `next` field.
-        let desc_list = [(3, 100, 0), (5, 50, 0), (11, 850, 0)];
+        let desc_list = [(0, 100, 0), (1, 50, 0), (2, 850, 0)];
         th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
-        let frame = th.write_tx_frame(&desc_list, 1000);
+        th.write_tx_frame(&desc_list, 1000);
+        th.add_desc_chain(NetQueue::Rx, 0, &[(0, 4096, VIRTQ_DESC_F_WRITE)]);
 
-        check_metric_after_block!(
-            th.net().metrics.tx_packets_count,
-            1,
-            th.event_manager.run_with_timeout(100).unwrap()
-        );
+        th.net().quiesce();
 
-        // Check that the used queue advanced.
+        // The chains are handed back to the driver, the TX frame being left unsent as the tap
+        // can't take it.
+        assert_eq!(th.net().metrics.tap_write_deferred.count(), 1);
+        assert_eq!(th.net().metrics.tx_packets_count.count(), 0);
         assert_eq!(th.txq.used.idx.get(), 1);
-        assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
-        th.txq.check_used_elem(0, 3, 0);
-        // Check that the frame was sent to the tap.
-        let mut buf = vec![0; 1000];
-        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
-        assert_eq!(&buf[..1000], &frame[..1000]);
+        th.txq.check_used_elem(0, 0, 0);
+        assert_eq!(th.rxq.used.idx.get(), 1);
+        th.rxq.check_used_elem(0, 0, 0);
+        assert!(th.net().irq_trigger.has_pending_irq(IrqType::Vring));
+
+        // The device is stopped, and asks the driver for a reset.
+        assert!(th.net().needs_reset);
+        assert!(!th.net().is_activated());
+        assert!(th.net().irq_trigger.has_pending_irq(IrqType::Config));
+
+        // Quiescing it again has no effect.
+        th.net().quiesce();
+        assert_eq!(th.txq.used.idx.get(), 1);
+        assert_eq!(th.rxq.used.idx.get(), 1);
     }
 
     #[test]
-    fn test_tx_tap_failure() {
+    fn test_tx_tap_short_write() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        th.net().tap.mocks.set_write_tap(WriteTapMock::Failure);
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_write_tap(WriteTapMock::ShortWrite);
 
         let desc_list = [(0, 1000, 0)];
         th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
         let _ = th.write_tx_frame(&desc_list, 1000);
 
+        // A truncated frame is not sent again.
         check_metric_after_block!(
             th.net().metrics.tap_write_fails,
             1,
             th.event_manager.run_with_timeout(100).unwrap()
         );
-
-        // Check that the used queue advanced.
+        assert_eq!(th.net().metrics.tx_packets_count.count(), 0);
+        assert!(!th.net().pairs[0].tap_write_blocked);
         assert_eq!(th.txq.used.idx.get(), 1);
-        assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
         th.txq.check_used_elem(0, 0, 0);
     }
 
@@ -1465,7 +3616,7 @@ pub mod tests {
     fn test_tx_multiple_frame() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().pairs[0].tap));
 
         // Write the first frame to the Tx queue
         let desc_list = [(0, 50, 0), (1, 100, 0), (2, 150, 0)];
@@ -1485,6 +3636,8 @@ pub mod tests {
         // Check that the used queue advanced.
         assert_eq!(th.txq.used.idx.get(), 2);
         assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
+        // The queue was drained, so the notifications are enabled again.
+        assert_eq!(th.txq.used.flags.get(), 0);
         th.txq.check_used_elem(0, 0, 0);
         th.txq.check_used_elem(1, 3, 0);
         // Check that the first frame was sent to the tap.
@@ -1551,10 +3704,10 @@ pub mod tests {
             1,
             assert!(Net::write_to_mmds_or_tap(
                 net.mmds_ns.as_mut(),
-                &mut net.tx_rate_limiter,
+                &mut net.tx_rate_limiters[0],
                 &mut headers,
                 &buffer,
-                &mut net.tap,
+                &mut net.pairs[0].tap,
                 Some(src_mac),
                 &net.metrics,
             )
@@ -1565,7 +3718,7 @@ pub mod tests {
         check_metric_after_block!(
             &METRICS.mmds.tx_frames,
             1,
-            net.read_from_mmds_or_tap().unwrap()
+            net.read_from_mmds_or_tap(0).unwrap()
         );
     }
 
@@ -1589,10 +3742,10 @@ pub mod tests {
             0,
             Net::write_to_mmds_or_tap(
                 net.mmds_ns.as_mut(),
-                &mut net.tx_rate_limiter,
+                &mut net.tx_rate_limiters[0],
                 &mut headers,
                 &buffer,
-                &mut net.tap,
+                &mut net.pairs[0].tap,
                 Some(guest_mac),
                 &net.metrics,
             )
@@ -1604,10 +3757,10 @@ pub mod tests {
             1,
             Net::write_to_mmds_or_tap(
                 net.mmds_ns.as_mut(),
-                &mut net.tx_rate_limiter,
+                &mut net.tx_rate_limiters[0],
                 &mut headers,
                 &buffer,
-                &mut net.tap,
+                &mut net.pairs[0].tap,
                 Some(not_guest_mac),
                 &net.metrics,
             )
@@ -1643,10 +3796,13 @@ pub mod tests {
     fn test_read_tap_fail_event_handler() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        th.net().tap.mocks.set_read_tap(ReadTapMock::Failure);
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::Failure);
 
         // The RX queue is empty and rx_deffered_frame is set.
-        th.net().rx_deferred_frame = true;
+        th.net().pairs[0].rx_deferred_frame = true;
         check_metric_after_block!(
             th.net().metrics.no_rx_avail_buffer,
             1,
@@ -1656,7 +3812,7 @@ pub mod tests {
         // We need to set this here to false, otherwise the device will try to
         // handle a deferred frame, it will fail and will never try to read from
         // the tap.
-        th.net().rx_deferred_frame = false;
+        th.net().pairs[0].rx_deferred_frame = false;
 
         // Fake an avail buffer; this time, tap reading should error out.
         th.rxq.avail.idx.set(1);
@@ -1667,11 +3823,159 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_read_tap_error_classification() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        th.add_desc_chain(NetQueue::Rx, 0, &[(0, 4096, VIRTQ_DESC_F_WRITE)]);
+
+        // EAGAIN just means there are no frames in the tap.
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::Errno(EAGAIN));
+        th.simulate_event(NetEvent::Tap);
+        assert_eq!(th.net().metrics.tap_read_fails.count(), 0);
+        assert!(!th.net().pairs[0].tap_read_backoff.is_active());
+
+        // Unknown errors are counted, but don't suspend tap reads.
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::Failure);
+        th.simulate_event(NetEvent::Tap);
+        th.simulate_event(NetEvent::Tap);
+        assert_eq!(th.net().metrics.tap_read_fails.count(), 2);
+        assert_eq!(th.net().metrics.tap_read_unknown_fails.count(), 2);
+        assert_eq!(th.net().metrics.tap_read_fatal.count(), 0);
+        assert!(!th.net().pairs[0].tap_read_backoff.is_active());
+
+        // Fatal errors suspend tap reads.
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::Errno(EIO));
+        th.simulate_event(NetEvent::Tap);
+        assert_eq!(th.net().metrics.tap_read_fails.count(), 3);
+        assert_eq!(th.net().metrics.tap_read_fatal.count(), 1);
+        assert!(th.net().pairs[0].tap_read_backoff.is_active());
+
+        // No frames are read from the tap while backing off.
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
+        inject_tap_tx_frame(&th.net(), 1000);
+        let rx_count = th.net().metrics.rx_count.count();
+        th.simulate_event(NetEvent::Tap);
+        assert_eq!(th.net().metrics.rx_count.count(), rx_count);
+        assert_eq!(th.rxq.used.idx.get(), 0);
+
+        // Once the backoff expires, frames are read again.
+        th.net().pairs[0].tap_read_backoff.expire();
+        th.simulate_event(NetEvent::Tap);
+        assert_eq!(th.net().metrics.rx_count.count(), rx_count + 1);
+        assert_eq!(th.rxq.used.idx.get(), 1);
+    }
+
+    #[test]
+    fn test_tap_read_backoff_delay() {
+        let mut backoff = TapReadBackoff::new().unwrap();
+        let delays: Vec<Duration> = (0..10).map(|_| backoff.start()).collect();
+        assert_eq!(delays[0], TAP_READ_BACKOFF_MIN);
+        assert_eq!(delays[1], TAP_READ_BACKOFF_MIN * 2);
+        assert_eq!(delays[2], TAP_READ_BACKOFF_MIN * 4);
+        assert_eq!(delays[9], TAP_READ_BACKOFF_MAX);
+        assert!(delays.windows(2).all(|w| w[0] <= w[1]));
+
+        // A successful read restores the initial delay.
+        backoff.reset();
+        assert_eq!(backoff.start(), TAP_READ_BACKOFF_MIN);
+
+        // Unexpected errors are logged at most once per interval.
+        assert!(backoff.should_log_error());
+        assert!(!backoff.should_log_error());
+    }
+
+    #[test]
+    fn test_mmds_watch_poll() {
+        let mut poll = MmdsWatchPoll::new().unwrap();
+        poll.update(false);
+        assert!(!poll.is_armed());
+
+        // The timer is armed while there are parked responses, until it expires.
+        poll.update(true);
+        assert!(poll.is_armed());
+        assert!(matches!(poll.timer.get_state(), TimerState::Oneshot(_)));
+        poll.update(true);
+        poll.expire();
+        assert!(!poll.is_armed());
+
+        // It is disarmed once there are no more parked responses.
+        poll.update(true);
+        poll.update(false);
+        assert!(!poll.is_armed());
+        assert!(matches!(poll.timer.get_state(), TimerState::Disarmed));
+    }
+
+    #[test]
+    fn test_tap_read_backoff_event_handler() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        assert!(th.net().pairs[0].tap_read_backoff.tap_registered);
+
+        // A fatal error deregisters the tap.
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::Errno(EBADFD));
+        inject_tap_tx_frame(&th.net(), 1000);
+        th.event_manager.run_with_timeout(100).unwrap();
+        assert_eq!(th.net().metrics.tap_read_fatal.count(), 1);
+        assert!(th.net().pairs[0].tap_read_backoff.is_active());
+        assert!(!th.net().pairs[0].tap_read_backoff.tap_registered);
+
+        // New frames don't generate tap events while the tap is deregistered.
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
+        inject_tap_tx_frame(&th.net(), 1000);
+        th.add_desc_chain(NetQueue::Rx, 0, &[(0, 4096, VIRTQ_DESC_F_WRITE)]);
+        th.add_desc_chain(NetQueue::Rx, 4096, &[(1, 4096, VIRTQ_DESC_F_WRITE)]);
+
+        // The backoff expires: the tap is registered again and the queued frames are received.
+        while th.net().pairs[0].tap_read_backoff.is_active() {
+            th.event_manager.run_with_timeout(100).unwrap();
+        }
+        assert!(th.net().pairs[0].tap_read_backoff.tap_registered);
+        assert_eq!(th.net().metrics.tap_read_fatal.count(), 1);
+        assert_eq!(th.rxq.used.idx.get(), 2);
+
+        // Full throughput is restored: further frames are received as soon as they arrive.
+        assert_eq!(
+            th.net().pairs[0].tap_read_backoff.delay,
+            TAP_READ_BACKOFF_MIN
+        );
+        th.add_desc_chain(NetQueue::Rx, 8192, &[(2, 4096, VIRTQ_DESC_F_WRITE)]);
+        th.event_manager.run_with_timeout(100).unwrap();
+        inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            th.net().metrics.rx_tap_event_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.rxq.used.idx.get(), 3);
+    }
+
     #[test]
     fn test_deferred_frame() {
         let mut th = TestHelper::get_default();
         th.activate_net();
-        th.net().tap.mocks.set_read_tap(ReadTapMock::TapFrame);
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
 
         let rx_packets_count = th.net().metrics.rx_packets_count.count();
         let _ = inject_tap_tx_frame(&th.net(), 1000);
@@ -1684,8 +3988,10 @@ pub mod tests {
         );
         // The frame we read from the tap should be deferred now and
         // no frames should have been transmitted
-        assert!(th.net().rx_deferred_frame);
+        assert!(th.net().pairs[0].rx_deferred_frame);
         assert_eq!(th.net().metrics.rx_packets_count.count(), rx_packets_count);
+        // The guest has to notify us of the buffers it adds.
+        assert_eq!(th.rxq.used.flags.get(), 0);
 
         // Let's add a second frame, which should really have the same
         // fate.
@@ -1701,7 +4007,7 @@ pub mod tests {
             th.simulate_event(NetEvent::Tap)
         );
         // We should still have a deferred frame
-        assert!(th.net().rx_deferred_frame);
+        assert!(th.net().pairs[0].rx_deferred_frame);
         // However, we should have delivered the first frame
         assert_eq!(
             th.net().metrics.rx_packets_count.count(),
@@ -1717,7 +4023,87 @@ pub mod tests {
         );
 
         // We should be done with any deferred frame
-        assert!(!th.net().rx_deferred_frame);
+        assert!(!th.net().pairs[0].rx_deferred_frame);
+    }
+
+    #[test]
+    fn test_rx_zero_copy() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
+
+        // A chain able to hold a frame of any size, over memory which is not zeroed.
+        let max_len = u32::try_from(MAX_BUFFER_SIZE).unwrap();
+        th.add_desc_chain(
+            NetQueue::Rx,
+            0,
+            &[
+                (0, 100, VIRTQ_DESC_F_WRITE),
+                (1, max_len - 100, VIRTQ_DESC_F_WRITE),
+            ],
+        );
+        let addr = GuestAddress(th.rxq.dtable[0].addr.get());
+        th.mem.write_slice(&[0xff; 100], addr).unwrap();
+
+        let frame = inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            th.net().metrics.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // The frame was read straight into the chain, without going through `rx_frame_buf`.
+        assert!(!th.net().pairs[0].rx_deferred_frame);
+        assert!(th.net().pairs[0].rx_frame_buf.iter().all(|&byte| byte == 0));
+        assert_eq!(th.rxq.used.idx.get(), 1);
+        th.rxq.check_used_elem(0, 0, 1000);
+        // The header is at the start of the chain, along with the `num_buffers` field the tap
+        // does not write.
+        th.rxq.dtable[0].check_data(&frame[..100]);
+        th.rxq.dtable[1].check_data(&frame[100..]);
+    }
+
+    #[test]
+    fn test_rx_zero_copy_rate_limited() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
+
+        // A bandwidth rate limiter allowing 4096 bytes every 100ms, its budget used up.
+        let mut rl = RateLimiter::new(0x1000, 0, 100, 0, 0, 0).unwrap();
+        assert!(rl.consume(0x1000, TokenType::Bytes));
+        th.net().rx_rate_limiters[0] = rl;
+
+        let max_len = u32::try_from(MAX_BUFFER_SIZE).unwrap();
+        th.add_desc_chain(NetQueue::Rx, 0, &[(0, max_len, VIRTQ_DESC_F_WRITE)]);
+        let frame = inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            th.net().metrics.rx_rate_limiter_throttled,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // The frame waits in `rx_frame_buf`, and the chain is kept for it.
+        assert!(th.net().pairs[0].rx_deferred_frame);
+        assert_eq!(&th.net().pairs[0].rx_frame_buf[..1000], &frame);
+        assert_eq!(th.rxq.used.idx.get(), 0);
+
+        // The frame is received once the rate limiter is replenished.
+        check_metric_after_block!(
+            th.net().metrics.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(500).unwrap()
+        );
+        assert!(!th.net().pairs[0].rx_deferred_frame);
+        assert_eq!(th.rxq.used.idx.get(), 1);
+        th.rxq.check_used_elem(0, 0, 1000);
+        th.rxq.dtable[0].check_data(&frame);
     }
 
     #[test]
@@ -1725,7 +4111,7 @@ pub mod tests {
         let mut th = TestHelper::get_default();
         th.activate_net();
 
-        th.net().rx_rate_limiter = RateLimiter::new(0, 0, 0, 0, 0, 0).unwrap();
+        th.net().rx_rate_limiters[0] = RateLimiter::new(0, 0, 0, 0, 0, 0).unwrap();
         // There is no actual event on the rate limiter's timerfd.
         check_metric_after_block!(
             th.net().metrics.event_fails,
@@ -1739,7 +4125,7 @@ pub mod tests {
         let mut th = TestHelper::get_default();
         th.activate_net();
 
-        th.net().tx_rate_limiter = RateLimiter::new(0, 0, 0, 0, 0, 0).unwrap();
+        th.net().tx_rate_limiters[0] = RateLimiter::new(0, 0, 0, 0, 0, 0).unwrap();
         th.simulate_event(NetEvent::TxRateLimiter);
         // There is no actual event on the rate limiter's timerfd.
         check_metric_after_block!(
@@ -1762,7 +4148,7 @@ pub mod tests {
             assert!(rl.consume(0x1000, TokenType::Bytes));
 
             // set this tx rate limiter to be used
-            th.net().tx_rate_limiter = rl;
+            th.net().tx_rate_limiters[0] = rl;
 
             // try doing TX
             // following TX procedure should fail because of bandwidth rate limiting
@@ -1772,10 +4158,13 @@ pub mod tests {
                 th.simulate_event(NetEvent::TxQueue);
 
                 // assert that limiter is blocked
-                assert!(th.net().tx_rate_limiter.is_blocked());
+                assert!(th.net().tx_rate_limiters[0].is_blocked());
                 assert_eq!(th.net().metrics.tx_rate_limiter_throttled.count(), 1);
                 // make sure the data is still queued for processing
                 assert_eq!(th.txq.used.idx.get(), 0);
+                // the frame is sent once the limiter is replenished, so the guest doesn't
+                // need to notify us meanwhile
+                assert_eq!(th.txq.used.flags.get(), VIRTQ_USED_F_NO_NOTIFY);
             }
 
             // A second TX queue event should be throttled too
@@ -1801,7 +4190,7 @@ pub mod tests {
                 );
                 // This should be still blocked. We managed to send the first frame, but
                 // not enough budget for the second
-                assert!(th.net().tx_rate_limiter.is_blocked());
+                assert!(th.net().tx_rate_limiters[0].is_blocked());
                 // make sure the data queue advanced
                 assert_eq!(th.txq.used.idx.get(), 1);
             }
@@ -1817,9 +4206,11 @@ pub mod tests {
                     th.simulate_event(NetEvent::TxRateLimiter)
                 );
                 // validate the rate_limiter is no longer blocked
-                assert!(!th.net().tx_rate_limiter.is_blocked());
+                assert!(!th.net().tx_rate_limiters[0].is_blocked());
                 // make sure the data queue advance one more place
                 assert_eq!(th.txq.used.idx.get(), 2);
+                // the queue was drained, so notifications are enabled again
+                assert_eq!(th.txq.used.flags.get(), 0);
             }
         }
 
@@ -1831,10 +4222,10 @@ pub mod tests {
             assert!(rl.consume(0x1000, TokenType::Bytes));
 
             // set this rx rate limiter to be used
-            th.net().rx_rate_limiter = rl;
+            th.net().rx_rate_limiters[0] = rl;
 
             // set up RX
-            assert!(!th.net().rx_deferred_frame);
+            assert!(!th.net().pairs[0].rx_deferred_frame);
             th.add_desc_chain(NetQueue::Rx, 0, &[(0, 4096, VIRTQ_DESC_F_WRITE)]);
 
             // following RX procedure should fail because of bandwidth rate limiting
@@ -1843,9 +4234,9 @@ pub mod tests {
                 th.simulate_event(NetEvent::Tap);
 
                 // assert that limiter is blocked
-                assert!(th.net().rx_rate_limiter.is_blocked());
+                assert!(th.net().rx_rate_limiters[0].is_blocked());
                 assert_eq!(th.net().metrics.rx_rate_limiter_throttled.count(), 1);
-                assert!(th.net().rx_deferred_frame);
+                assert!(th.net().pairs[0].rx_deferred_frame);
                 // assert that no operation actually completed (limiter blocked it)
                 assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
                 // make sure the data is still queued for processing
@@ -1866,7 +4257,7 @@ pub mod tests {
 
             // following RX procedure should succeed because bandwidth should now be available
             {
-                let frame = &th.net().tap.mocks.read_tap.mock_frame();
+                let frame = &th.net().pairs[0].tap.mocks.read_tap.mock_frame();
                 // no longer throttled
                 check_metric_after_block!(
                     th.net().metrics.rx_rate_limiter_throttled,
@@ -1874,7 +4265,7 @@ pub mod tests {
                     th.simulate_event(NetEvent::RxRateLimiter)
                 );
                 // validate the rate_limiter is no longer blocked
-                assert!(!th.net().rx_rate_limiter.is_blocked());
+                assert!(!th.net().rx_rate_limiters[0].is_blocked());
                 // make sure the virtio queue operation completed this time
                 assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
                 // make sure the data queue advanced
@@ -1899,7 +4290,7 @@ pub mod tests {
             assert!(rl.consume(1, TokenType::Ops));
 
             // set this tx rate limiter to be used
-            th.net().tx_rate_limiter = rl;
+            th.net().tx_rate_limiters[0] = rl;
 
             // try doing TX
             // following TX procedure should fail because of ops rate limiting
@@ -1913,7 +4304,7 @@ pub mod tests {
                 );
 
                 // assert that limiter is blocked
-                assert!(th.net().tx_rate_limiter.is_blocked());
+                assert!(th.net().tx_rate_limiters[0].is_blocked());
                 // make sure the data is still queued for processing
                 assert_eq!(th.txq.used.idx.get(), 0);
             }
@@ -1931,7 +4322,7 @@ pub mod tests {
                     th.simulate_event(NetEvent::TxRateLimiter)
                 );
                 // validate the rate_limiter is no longer blocked
-                assert!(!th.net().tx_rate_limiter.is_blocked());
+                assert!(!th.net().tx_rate_limiters[0].is_blocked());
                 // make sure the data queue advanced
                 assert_eq!(th.txq.used.idx.get(), 1);
             }
@@ -1945,10 +4336,10 @@ pub mod tests {
             assert!(rl.consume(1, TokenType::Ops));
 
             // set this rx rate limiter to be used
-            th.net().rx_rate_limiter = rl;
+            th.net().rx_rate_limiters[0] = rl;
 
             // set up RX
-            assert!(!th.net().rx_deferred_frame);
+            assert!(!th.net().pairs[0].rx_deferred_frame);
             th.add_desc_chain(NetQueue::Rx, 0, &[(0, 4096, VIRTQ_DESC_F_WRITE)]);
 
             // following RX procedure should fail because of ops rate limiting
@@ -1961,9 +4352,9 @@ pub mod tests {
                 );
 
                 // assert that limiter is blocked
-                assert!(th.net().rx_rate_limiter.is_blocked());
+                assert!(th.net().rx_rate_limiters[0].is_blocked());
                 assert!(th.net().metrics.rx_rate_limiter_throttled.count() >= 1);
-                assert!(th.net().rx_deferred_frame);
+                assert!(th.net().pairs[0].rx_deferred_frame);
                 // assert that no operation actually completed (limiter blocked it)
                 assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
                 // make sure the data is still queued for processing
@@ -1983,7 +4374,7 @@ pub mod tests {
 
             // following RX procedure should succeed because ops should now be available
             {
-                let frame = &th.net().tap.mocks.read_tap.mock_frame();
+                let frame = &th.net().pairs[0].tap.mocks.read_tap.mock_frame();
                 th.simulate_event(NetEvent::RxRateLimiter);
                 // make sure the virtio queue operation completed this time
                 assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
@@ -2001,8 +4392,8 @@ pub mod tests {
         let mut th = TestHelper::get_default();
         th.activate_net();
 
-        th.net().rx_rate_limiter = RateLimiter::new(10, 0, 10, 2, 0, 2).unwrap();
-        th.net().tx_rate_limiter = RateLimiter::new(10, 0, 10, 2, 0, 2).unwrap();
+        th.net().rx_rate_limiters[0] = RateLimiter::new(10, 0, 10, 2, 0, 2).unwrap();
+        th.net().tx_rate_limiters[0] = RateLimiter::new(10, 0, 10, 2, 0, 2).unwrap();
 
         let rx_bytes = TokenBucket::new(1000, 1001, 1002).unwrap();
         let rx_ops = TokenBucket::new(1003, 1004, 1005).unwrap();
@@ -2017,13 +4408,16 @@ pub mod tests {
         );
         let compare_buckets = |a: &TokenBucket, b: &TokenBucket| {
             assert_eq!(a.capacity(), b.capacity());
-            assert_eq!(a.one_time_burst(), b.one_time_burst());
+            assert_eq!(a.initial_one_time_burst(), b.initial_one_time_burst());
             assert_eq!(a.refill_time_ms(), b.refill_time_ms());
+            // The previous buckets had no one time burst left.
+            assert_eq!(a.one_time_burst(), 0);
         };
-        compare_buckets(th.net().rx_rate_limiter.bandwidth().unwrap(), &rx_bytes);
-        compare_buckets(th.net().rx_rate_limiter.ops().unwrap(), &rx_ops);
-        compare_buckets(th.net().tx_rate_limiter.bandwidth().unwrap(), &tx_bytes);
-        compare_buckets(th.net().tx_rate_limiter.ops().unwrap(), &tx_ops);
+        compare_buckets(th.net().rx_rate_limiters[0].bandwidth().unwrap(), &rx_bytes);
+        compare_buckets(th.net().rx_rate_limiters[0].ops().unwrap(), &rx_ops);
+        compare_buckets(th.net().tx_rate_limiters[0].bandwidth().unwrap(), &tx_bytes);
+        compare_buckets(th.net().tx_rate_limiters[0].ops().unwrap(), &tx_ops);
+        assert_eq!(th.net().metrics.rate_limiter_updates.count(), 1);
 
         th.net().patch_rate_limiters(
             BucketUpdate::Disabled,
@@ -2031,10 +4425,123 @@ pub mod tests {
             BucketUpdate::Disabled,
             BucketUpdate::Disabled,
         );
-        assert!(th.net().rx_rate_limiter.bandwidth().is_none());
-        assert!(th.net().rx_rate_limiter.ops().is_none());
-        assert!(th.net().tx_rate_limiter.bandwidth().is_none());
-        assert!(th.net().tx_rate_limiter.ops().is_none());
+        assert!(th.net().rx_rate_limiters[0].bandwidth().is_none());
+        assert!(th.net().rx_rate_limiters[0].ops().is_none());
+        assert!(th.net().tx_rate_limiters[0].bandwidth().is_none());
+        assert!(th.net().tx_rate_limiters[0].ops().is_none());
+        assert_eq!(th.net().metrics.rate_limiter_updates.count(), 2);
+    }
+
+    // Bytes per op of the TX rate limiter configurations of
+    // `test_patch_rate_limiters_under_traffic()`.
+    const BYTES_PER_OP: u64 = 4096;
+    // Number of ops of the TX rate limiter seen at the start and at the end of each TX pass.
+    static TX_PASS_OPS: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+    fn check_tx_pass_rate_limiter(rate_limiter: &RateLimiter) {
+        let bandwidth = rate_limiter.bandwidth().unwrap().capacity();
+        let ops = rate_limiter.ops().unwrap().capacity();
+        assert_eq!(
+            bandwidth,
+            ops * BYTES_PER_OP,
+            "mixed rate limiter configuration"
+        );
+        TX_PASS_OPS.lock().unwrap().push(ops);
+    }
+
+    #[test]
+    fn test_patch_rate_limiters_under_traffic() {
+        const UPDATES: u64 = 500;
+        const PASSES: u16 = 2000;
+
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+
+        // Each configuration of the TX rate limiter is large enough not to throttle the traffic,
+        // and has `BYTES_PER_OP` bytes per op, so that a mix of two configurations is detected.
+        let config = |k: u64| {
+            (
+                TokenBucket::new(1000 * k * BYTES_PER_OP, 0, 1000).unwrap(),
+                TokenBucket::new(1000 * k, 0, 1000).unwrap(),
+            )
+        };
+        let (bandwidth, ops) = config(1);
+        th.net().tx_rate_limiters[0]
+            .update_buckets(BucketUpdate::Update(bandwidth), BucketUpdate::Update(ops));
+        th.net().tx_pass_hook = Some(check_tx_pass_rate_limiter);
+
+        let net = th.net.clone();
+        let updater = thread::spawn(move || {
+            for k in 2..=UPDATES + 1 {
+                let (bandwidth, ops) = config(k);
+                net.lock().unwrap().patch_rate_limiters(
+                    BucketUpdate::None,
+                    BucketUpdate::None,
+                    BucketUpdate::Update(bandwidth),
+                    BucketUpdate::Update(ops),
+                );
+            }
+        });
+
+        // Keep making the same TX frame available again, and process it.
+        th.add_desc_chain(NetQueue::Tx, 0, &[(0, 1024, 0)]);
+        for _ in 0..PASSES {
+            th.net().process_tx(0).unwrap();
+            let avail_idx = th.txq.avail.idx.get();
+            th.txq.avail.ring[usize::from(avail_idx % th.txq.size())].set(0);
+            th.txq.avail.idx.set(avail_idx.wrapping_add(1));
+        }
+        updater.join().unwrap();
+
+        // All the frames were processed.
+        assert_eq!(th.txq.used.idx.get(), PASSES);
+        assert_eq!(th.net().metrics.rate_limiter_updates.count(), UPDATES);
+        assert_eq!(
+            th.net().tx_rate_limiters[0].ops().unwrap().capacity(),
+            1000 * (UPDATES + 1)
+        );
+        // No configuration was swapped in during a pass.
+        let pass_ops = TX_PASS_OPS.lock().unwrap();
+        assert_eq!(pass_ops.len(), 2 * usize::from(PASSES));
+        for pass in pass_ops.chunks(2) {
+            assert_eq!(pass[0], pass[1]);
+        }
+    }
+
+    #[test]
+    fn test_patch_rate_limiters_no_burst() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+
+        // A TX ops rate limiter of 10 ops, slow enough not to refill during the test.
+        th.net().tx_rate_limiters[0] = RateLimiter::new(0, 0, 0, 10, 0, 100_000).unwrap();
+        // Makes the same TX frame available `count` times, processing it after each one.
+        let send_frames = |th: &mut TestHelper, count: u16| {
+            for _ in 0..count {
+                th.add_desc_chain(NetQueue::Tx, 0, &[(0, 1024, 0)]);
+                th.net().process_tx(0).unwrap();
+            }
+        };
+
+        // Use up half of the budget.
+        send_frames(&mut th, 5);
+        assert_eq!(th.txq.used.idx.get(), 5);
+
+        // Doubling the size of the bucket doubles the budget left, which doesn't refill it.
+        th.net().patch_rate_limiters(
+            BucketUpdate::None,
+            BucketUpdate::None,
+            BucketUpdate::None,
+            BucketUpdate::Update(TokenBucket::new(20, 0, 100_000).unwrap()),
+        );
+        assert_eq!(th.net().tx_rate_limiters[0].ops().unwrap().budget(), 10);
+        check_metric_after_block!(
+            th.net().metrics.tx_rate_limiter_throttled,
+            1,
+            send_frames(&mut th, 11)
+        );
+        assert_eq!(th.txq.used.idx.get(), 15);
+        assert!(th.net().tx_rate_limiters[0].is_blocked());
     }
 
     #[test]
@@ -2043,14 +4550,14 @@ pub mod tests {
         th.activate_net();
         let net = th.net.lock().unwrap();
 
-        // Test queues count (TX and RX).
+        // Test queues count (TX and RX, and the control queue).
         let queues = net.queues();
-        assert_eq!(queues.len(), NET_QUEUE_SIZES.len());
+        assert_eq!(queues.len(), NET_QUEUE_SIZES.len() + 1);
         assert_eq!(queues[RX_INDEX].size, th.rxq.size());
         assert_eq!(queues[TX_INDEX].size, th.txq.size());
 
         // Test corresponding queues events.
-        assert_eq!(net.queue_events().len(), NET_QUEUE_SIZES.len());
+        assert_eq!(net.queue_events().len(), NET_QUEUE_SIZES.len() + 1);
 
         // Test interrupts.
         assert!(!&net.irq_trigger.has_pending_irq(IrqType::Vring));
@@ -2069,4 +4576,482 @@ pub mod tests {
         assert!(queues[RX_INDEX].uses_notif_suppression);
         assert!(queues[TX_INDEX].uses_notif_suppression);
     }
+
+    #[test]
+    fn test_multi_queue_activation() {
+        let mut net = default_net_multi_queue(2);
+        assert_eq!(net.num_pairs(), 2);
+        // The RX and TX queues of both pairs, and the control queue.
+        assert_eq!(net.queues().len(), 5);
+        assert_eq!(net.queue_events().len(), 5);
+        assert_eq!(net.ctrl_queue_index(), Some(4));
+        let mq_features = 1 << VIRTIO_NET_F_MQ | 1 << VIRTIO_NET_F_CTRL_VQ;
+        assert_eq!(net.avail_features() & mq_features, mq_features);
+
+        let mut max_virtqueue_pairs = [0u8; 2];
+        net.read_config(8, &mut max_virtqueue_pairs);
+        assert_eq!(u16::from_le_bytes(max_virtqueue_pairs), 2);
+        // The driver can't change the number of pairs through the config space.
+        net.write_config(8, &[4, 0]);
+        net.read_config(8, &mut max_virtqueue_pairs);
+        assert_eq!(u16::from_le_bytes(max_virtqueue_pairs), 2);
+
+        // Only the first pair is used until the driver sets the number of pairs, the queue of the
+        // tap of the second one is disabled.
+        assert_eq!(net.active_pairs, 1);
+        net.pairs[1].tap.set_queue_enabled(false).unwrap_err();
+
+        net.set_acked_features(net.avail_features());
+        net.activate(default_mem()).unwrap();
+        assert!(net.is_activated());
+
+        // A device with a single pair has a control queue too, but doesn't offer
+        // `VIRTIO_NET_F_MQ`.
+        let mut net = default_net();
+        assert_eq!(net.queues().len(), NET_NUM_QUEUES + 1);
+        assert_eq!(net.ctrl_queue_index(), Some(2));
+        assert_eq!(
+            net.avail_features() & mq_features,
+            1 << VIRTIO_NET_F_CTRL_VQ
+        );
+
+        // The control queue is dropped if the virtio features are pinned without it.
+        net.pin_virtio_features(net.avail_features() & !mq_features)
+            .unwrap();
+        assert_eq!(net.queues().len(), NET_NUM_QUEUES);
+        assert_eq!(net.queue_events().len(), NET_NUM_QUEUES);
+        assert_eq!(net.ctrl_queue_index(), None);
+        net.set_queue_size(64).unwrap();
+        assert_eq!(net.queues().len(), NET_NUM_QUEUES);
+    }
+
+    #[test]
+    fn test_ctrl_queue_vq_pairs_set() {
+        let mem = default_mem();
+        let ctrlq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let data_addr = ctrlq.end().raw_value();
+
+        let mut net = default_net_multi_queue(3);
+        let ctrl_index = net.ctrl_queue_index().unwrap();
+        net.queues[ctrl_index] = ctrlq.create_queue();
+        net.set_acked_features(net.avail_features());
+        net.activate(mem.clone()).unwrap();
+
+        // Sends a `VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET` command through the control queue, and returns
+        // the acknowledgement of the device.
+        let vq_pairs_set = |net: &mut Net, num_pairs: u16| {
+            let header = [
+                u8::try_from(VIRTIO_NET_CTRL_MQ).unwrap(),
+                u8::try_from(VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET).unwrap(),
+            ];
+            mem.write_slice(&header, GuestAddress(data_addr)).unwrap();
+            mem.write_slice(&num_pairs.to_le_bytes(), GuestAddress(data_addr + 2))
+                .unwrap();
+            mem.write_obj(0xffu8, GuestAddress(data_addr + 4)).unwrap();
+            ctrlq.dtable[0].set(data_addr, 2, VIRTQ_DESC_F_NEXT, 1);
+            ctrlq.dtable[1].set(data_addr + 2, 2, VIRTQ_DESC_F_NEXT, 2);
+            ctrlq.dtable[2].set(data_addr + 4, 1, VIRTQ_DESC_F_WRITE, 0);
+            let avail_idx = ctrlq.avail.idx.get();
+            ctrlq.avail.ring[usize::from(avail_idx)].set(0);
+            ctrlq.avail.idx.set(avail_idx + 1);
+
+            net.queue_evts[ctrl_index].write(1).unwrap();
+            net.process_ctrl_queue_event().unwrap();
+            ctrlq.check_used_elem(avail_idx, 0, 1);
+            u32::from(mem.read_obj::<u8>(GuestAddress(data_addr + 4)).unwrap())
+        };
+
+        assert_eq!(vq_pairs_set(&mut net, 2), VIRTIO_NET_OK);
+        assert_eq!(net.active_pairs, 2);
+        // The queue of the tap of the second pair is enabled, the one of the third is not.
+        net.pairs[1].tap.set_queue_enabled(true).unwrap_err();
+        net.pairs[2].tap.set_queue_enabled(false).unwrap_err();
+
+        // The number of pairs is between 1 and the number of pairs of the device.
+        for num_pairs in [0, 4] {
+            assert_eq!(vq_pairs_set(&mut net, num_pairs), VIRTIO_NET_ERR);
+            assert_eq!(net.active_pairs, 2);
+        }
+
+        assert_eq!(vq_pairs_set(&mut net, 3), VIRTIO_NET_OK);
+        assert_eq!(net.active_pairs, 3);
+        assert_eq!(vq_pairs_set(&mut net, 1), VIRTIO_NET_OK);
+        assert_eq!(net.active_pairs, 1);
+        net.pairs[1].tap.set_queue_enabled(false).unwrap_err();
+        net.pairs[2].tap.set_queue_enabled(false).unwrap_err();
+
+        // The number of pairs can't be set if the driver did not negotiate `VIRTIO_NET_F_MQ`.
+        net.acked_features &= !(1 << VIRTIO_NET_F_MQ);
+        assert_eq!(vq_pairs_set(&mut net, 2), VIRTIO_NET_ERR);
+        assert_eq!(net.active_pairs, 1);
+    }
+
+    #[test]
+    fn test_rx_filter() {
+        let guest_mac = Some(MacAddr::from_str("06:00:00:00:00:01").unwrap());
+        let unicast = MacAddr::from_str("06:00:00:00:00:02").unwrap();
+        let multicast = MacAddr::from_str("01:00:5e:00:00:12").unwrap();
+        // The start of a frame sent to `dst_mac`, VNET header included.
+        let frame_to = |dst_mac: &str| {
+            let mut frame = vec![0u8; vnet_hdr_len() + PAYLOAD_OFFSET];
+            frame[vnet_hdr_len()..][..usize::from(MAC_ADDR_LEN)]
+                .copy_from_slice(MacAddr::from_str(dst_mac).unwrap().get_bytes());
+            frame
+        };
+
+        // All the frames go through the default filter.
+        let mut rx_filter = RxFilter::default();
+        for dst_mac in ["06:00:00:00:00:03", "01:00:5e:00:00:13"] {
+            assert!(rx_filter.accepts(&frame_to(dst_mac), guest_mac));
+        }
+
+        rx_filter.promisc = false;
+        rx_filter.unicast = Some(vec![unicast]);
+        rx_filter.multicast = Some(vec![multicast]);
+        for (dst_mac, accepted) in [
+            ("06:00:00:00:00:01", true),
+            ("06:00:00:00:00:02", true),
+            ("06:00:00:00:00:03", false),
+            ("01:00:5e:00:00:12", true),
+            ("01:00:5e:00:00:13", false),
+            ("ff:ff:ff:ff:ff:ff", true),
+        ] {
+            assert_eq!(rx_filter.accepts(&frame_to(dst_mac), guest_mac), accepted);
+        }
+        // Without a MAC address, the unicast frames are all received.
+        assert!(rx_filter.accepts(&frame_to("06:00:00:00:00:03"), None));
+        assert!(!rx_filter.accepts(&frame_to("01:00:5e:00:00:13"), None));
+        // So are the frames too short to have a destination address.
+        let frame = frame_to("06:00:00:00:00:03");
+        assert!(rx_filter.accepts(&frame[..vnet_hdr_len() + 4], guest_mac));
+
+        // The overflowing tables let all the frames of their kind through.
+        rx_filter.unicast = None;
+        assert!(rx_filter.accepts(&frame_to("06:00:00:00:00:03"), guest_mac));
+        assert!(!rx_filter.accepts(&frame_to("01:00:5e:00:00:13"), guest_mac));
+        rx_filter.all_multi = true;
+        assert!(rx_filter.accepts(&frame_to("01:00:5e:00:00:13"), guest_mac));
+    }
+
+    // Builds a `VIRTIO_NET_CTRL_MAC_TABLE_SET` command with the given tables.
+    fn mac_table_set_command(unicast: &[MacAddr], multicast: &[MacAddr]) -> Vec<u8> {
+        let mut command = vec![
+            u8::try_from(VIRTIO_NET_CTRL_MAC).unwrap(),
+            u8::try_from(VIRTIO_NET_CTRL_MAC_TABLE_SET).unwrap(),
+        ];
+        for table in [unicast, multicast] {
+            command.extend_from_slice(&u32::try_from(table.len()).unwrap().to_le_bytes());
+            for mac in table {
+                command.extend_from_slice(mac.get_bytes());
+            }
+        }
+        command
+    }
+
+    #[test]
+    fn test_ctrl_queue_rx_filter() {
+        let mem = default_mem();
+        let ctrlq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let data_addr = ctrlq.end().raw_value();
+
+        let mut net = default_net();
+        let ctrl_index = net.ctrl_queue_index().unwrap();
+        net.queues[ctrl_index] = ctrlq.create_queue();
+        net.set_acked_features(1 << VIRTIO_NET_F_CTRL_VQ | 1 << VIRTIO_NET_F_CTRL_RX);
+        net.activate(mem.clone()).unwrap();
+
+        // Sends a command through the control queue, its header and its data in two descriptors,
+        // and returns the acknowledgement of the device.
+        let send_command = |net: &mut Net, command: &[u8]| {
+            let len = u32::try_from(command.len()).unwrap();
+            let ack_addr = data_addr + u64::from(len);
+            mem.write_slice(command, GuestAddress(data_addr)).unwrap();
+            mem.write_obj(0xffu8, GuestAddress(ack_addr)).unwrap();
+            ctrlq.dtable[0].set(data_addr, 2, VIRTQ_DESC_F_NEXT, 1);
+            ctrlq.dtable[1].set(data_addr + 2, len - 2, VIRTQ_DESC_F_NEXT, 2);
+            ctrlq.dtable[2].set(ack_addr, 1, VIRTQ_DESC_F_WRITE, 0);
+            let avail_idx = ctrlq.avail.idx.get();
+            ctrlq.avail.ring[usize::from(avail_idx)].set(0);
+            ctrlq.avail.idx.set(avail_idx + 1);
+
+            net.queue_evts[ctrl_index].write(1).unwrap();
+            net.process_ctrl_queue_event().unwrap();
+            ctrlq.check_used_elem(avail_idx, 0, 1);
+            u32::from(mem.read_obj::<u8>(GuestAddress(ack_addr)).unwrap())
+        };
+        let rx_command = |cmd: u32, on: bool| {
+            vec![
+                u8::try_from(VIRTIO_NET_CTRL_RX).unwrap(),
+                u8::try_from(cmd).unwrap(),
+                u8::from(on),
+            ]
+        };
+
+        // The device is promiscuous until the driver sets the filter.
+        assert_eq!(net.rx_filter, RxFilter::default());
+        assert!(net.rx_filter.promisc);
+        assert_eq!(
+            send_command(&mut net, &rx_command(VIRTIO_NET_CTRL_RX_PROMISC, false)),
+            VIRTIO_NET_OK
+        );
+        assert!(!net.rx_filter.promisc);
+        assert_eq!(
+            send_command(&mut net, &rx_command(VIRTIO_NET_CTRL_RX_ALLMULTI, true)),
+            VIRTIO_NET_OK
+        );
+        assert!(net.rx_filter.all_multi);
+
+        let unicast = MacAddr::from_str("06:00:00:00:00:02").unwrap();
+        let multicast = MacAddr::from_str("01:00:5e:00:00:12").unwrap();
+        assert_eq!(
+            send_command(
+                &mut net,
+                &mac_table_set_command(&[unicast], &[multicast, multicast])
+            ),
+            VIRTIO_NET_OK
+        );
+        assert_eq!(net.rx_filter.unicast, Some(vec![unicast]));
+        assert_eq!(net.rx_filter.multicast, Some(vec![multicast, multicast]));
+
+        // The tables with more addresses than the filter holds let all the frames of their kind
+        // through, including when the command is cut short.
+        let many = vec![unicast; RX_FILTER_MAC_TABLE_LEN + 1];
+        assert_eq!(
+            send_command(&mut net, &mac_table_set_command(&[], &many)),
+            VIRTIO_NET_OK
+        );
+        assert_eq!(net.rx_filter.unicast, Some(vec![]));
+        assert_eq!(net.rx_filter.multicast, None);
+        let many = vec![unicast; RX_FILTER_MAC_TABLE_LEN * 2];
+        assert_eq!(
+            send_command(&mut net, &mac_table_set_command(&many, &[multicast])),
+            VIRTIO_NET_OK
+        );
+        assert_eq!(net.rx_filter.unicast, None);
+        assert_eq!(net.rx_filter.multicast, None);
+
+        // A command too short for its tables is rejected, leaving the filter unchanged.
+        let mut command = mac_table_set_command(&[unicast], &[multicast]);
+        command.truncate(command.len() - 1);
+        let rx_filter = net.rx_filter.clone();
+        assert_eq!(send_command(&mut net, &command), VIRTIO_NET_ERR);
+        assert_eq!(net.rx_filter, rx_filter);
+
+        // The filter can't be set if the driver did not negotiate `VIRTIO_NET_F_CTRL_RX`.
+        net.acked_features &= !(1 << VIRTIO_NET_F_CTRL_RX);
+        assert_eq!(
+            send_command(&mut net, &rx_command(VIRTIO_NET_CTRL_RX_PROMISC, true)),
+            VIRTIO_NET_ERR
+        );
+        assert_eq!(
+            send_command(&mut net, &mac_table_set_command(&[], &[])),
+            VIRTIO_NET_ERR
+        );
+        assert_eq!(net.rx_filter, rx_filter);
+    }
+
+    #[test]
+    fn test_guest_announce() {
+        let mem = default_mem();
+        let ctrlq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let data_addr = ctrlq.end().raw_value();
+        let announce_features =
+            1 << VIRTIO_NET_F_STATUS | 1 << VIRTIO_NET_F_CTRL_VQ | 1 << VIRTIO_NET_F_GUEST_ANNOUNCE;
+        let link_up = u16::try_from(VIRTIO_NET_S_LINK_UP).unwrap();
+        let announce = u16::try_from(VIRTIO_NET_S_ANNOUNCE).unwrap();
+        // The status follows the MAC in the config space.
+        let status = |net: &Net| {
+            let mut status = [0u8; 2];
+            net.read_config(6, &mut status);
+            u16::from_le_bytes(status)
+        };
+
+        let mut net = default_net();
+        assert_eq!(net.avail_features() & announce_features, announce_features);
+        assert_eq!(status(&net), link_up);
+        // The driver is only asked to announce the guest once the device is activated.
+        assert!(!net.announce().unwrap());
+        assert_eq!(status(&net), link_up);
+
+        let ctrl_index = net.ctrl_queue_index().unwrap();
+        net.queues[ctrl_index] = ctrlq.create_queue();
+        net.set_acked_features(announce_features);
+        net.activate(mem.clone()).unwrap();
+        assert!(net.announce().unwrap());
+        assert_eq!(status(&net), link_up | announce);
+        assert!(net.irq_trigger.has_pending_irq(IrqType::Config));
+
+        // Sends a `VIRTIO_NET_CTRL_ANNOUNCE_ACK` command through the control queue, and returns
+        // the acknowledgement of the device.
+        let announce_ack = |net: &mut Net| {
+            let header = [
+                u8::try_from(VIRTIO_NET_CTRL_ANNOUNCE).unwrap(),
+                u8::try_from(VIRTIO_NET_CTRL_ANNOUNCE_ACK).unwrap(),
+            ];
+            mem.write_slice(&header, GuestAddress(data_addr)).unwrap();
+            mem.write_obj(0xffu8, GuestAddress(data_addr + 2)).unwrap();
+            ctrlq.dtable[0].set(data_addr, 2, VIRTQ_DESC_F_NEXT, 1);
+            ctrlq.dtable[1].set(data_addr + 2, 1, VIRTQ_DESC_F_WRITE, 0);
+            let avail_idx = ctrlq.avail.idx.get();
+            ctrlq.avail.ring[usize::from(avail_idx)].set(0);
+            ctrlq.avail.idx.set(avail_idx + 1);
+
+            net.queue_evts[ctrl_index].write(1).unwrap();
+            net.process_ctrl_queue_event().unwrap();
+            ctrlq.check_used_elem(avail_idx, 0, 1);
+            u32::from(mem.read_obj::<u8>(GuestAddress(data_addr + 2)).unwrap())
+        };
+
+        // The acknowledgement of the driver clears the request.
+        assert_eq!(announce_ack(&mut net), VIRTIO_NET_OK);
+        assert_eq!(status(&net), link_up);
+
+        // A driver which did not negotiate `VIRTIO_NET_F_GUEST_ANNOUNCE` is not asked.
+        net.acked_features &= !(1 << VIRTIO_NET_F_GUEST_ANNOUNCE);
+        assert!(!net.announce().unwrap());
+        assert_eq!(status(&net), link_up);
+        assert_eq!(announce_ack(&mut net), VIRTIO_NET_ERR);
+    }
+
+    #[test]
+    fn test_rx_filtered_frames() {
+        let mut th = TestHelper::get_default();
+        let guest_mac = default_guest_mac();
+        let unicast = MacAddr::from_str("06:00:00:00:00:02").unwrap();
+        let multicast = MacAddr::from_str("01:00:5e:00:00:12").unwrap();
+        th.net()
+            .set_acked_features(1 << VIRTIO_NET_F_CTRL_VQ | 1 << VIRTIO_NET_F_CTRL_RX);
+        th.activate_net();
+        th.net().pairs[0]
+            .tap
+            .mocks
+            .set_read_tap(ReadTapMock::TapFrame);
+        th.net().rx_filter = RxFilter {
+            promisc: false,
+            all_multi: false,
+            unicast: Some(vec![unicast]),
+            multicast: Some(vec![multicast]),
+        };
+
+        // Sends a frame to `dst_mac` through the tap, and returns it as received by the guest.
+        let inject_frame_to = |net: &Net, dst_mac: &str| {
+            let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&net.pairs[0].tap));
+            let mut frame = vec![0u8; 100];
+            frame[..usize::from(MAC_ADDR_LEN)]
+                .copy_from_slice(MacAddr::from_str(dst_mac).unwrap().get_bytes());
+            frame[usize::from(MAC_ADDR_LEN)..][..usize::from(MAC_ADDR_LEN)]
+                .copy_from_slice(MacAddr::from_str("06:00:00:00:00:ff").unwrap().get_bytes());
+            tap_traffic_simulator.push_tx_packet(&frame);
+            frame.splice(0..0, vec![b'\0'; vnet_hdr_len()]);
+            frame
+        };
+
+        // The frames are copied to chains too short for a frame of any size.
+        for i in 0..4 {
+            th.add_desc_chain(
+                NetQueue::Rx,
+                u64::from(i) * 500,
+                &[(i, 500, VIRTQ_DESC_F_WRITE)],
+            );
+        }
+        let frames: Vec<_> = [
+            (guest_mac.to_string(), true),
+            ("06:00:00:00:00:03".to_string(), false),
+            (unicast.to_string(), true),
+            ("01:00:5e:00:00:13".to_string(), false),
+            (multicast.to_string(), true),
+            ("ff:ff:ff:ff:ff:ff".to_string(), true),
+        ]
+        .into_iter()
+        .map(|(dst_mac, accepted)| (inject_frame_to(&th.net(), &dst_mac), accepted))
+        .collect();
+        check_metric_after_block!(
+            th.net().metrics.rx_filtered_frames,
+            2,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.rxq.used.idx.get(), 4);
+        let received = frames.iter().filter(|(_, accepted)| *accepted);
+        for (i, (frame, _)) in (0..4).zip(received) {
+            th.rxq
+                .check_used_elem(i, i, frame.len().try_into().unwrap());
+            th.rxq.dtable[usize::from(i)].check_data(frame);
+        }
+
+        // The frames dropped straight from a chain able to hold a frame of any size leave it
+        // available for the next frame.
+        let max_len = u32::try_from(MAX_BUFFER_SIZE).unwrap();
+        th.add_desc_chain(NetQueue::Rx, 0, &[(4, max_len, VIRTQ_DESC_F_WRITE)]);
+        inject_frame_to(&th.net(), "06:00:00:00:00:03");
+        let frame = inject_frame_to(&th.net(), &unicast.to_string());
+        check_metric_after_block!(
+            th.net().metrics.rx_filtered_frames,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.rxq.used.idx.get(), 5);
+        th.rxq
+            .check_used_elem(4, 4, frame.len().try_into().unwrap());
+        th.rxq.dtable[4].check_data(&frame);
+
+        // Without `VIRTIO_NET_F_CTRL_RX`, the frames are not filtered.
+        th.net().acked_features &= !(1 << VIRTIO_NET_F_CTRL_RX);
+        th.add_desc_chain(NetQueue::Rx, 0, &[(5, 500, VIRTQ_DESC_F_WRITE)]);
+        let frame = inject_frame_to(&th.net(), "06:00:00:00:00:03");
+        check_metric_after_block!(
+            th.net().metrics.rx_filtered_frames,
+            0,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.rxq.used.idx.get(), 6);
+        th.rxq.dtable[5].check_data(&frame);
+    }
+
+    #[test]
+    fn test_rate_limiter_scope() {
+        let mut net = default_net_multi_queue(2);
+        net.rx_rate_limiters[0] = RateLimiter::new(1000, 0, 100, 10, 0, 100).unwrap();
+        net.tx_rate_limiters[0] = RateLimiter::new(2000, 0, 100, 20, 0, 100).unwrap();
+        assert_eq!(net.rate_limiter_scope(), RateLimiterScope::Device);
+        assert_eq!(net.rate_limiter_index(1), 0);
+        assert_eq!(net.rate_limiter_pairs(0), 0..2);
+
+        // Each pair gets a copy of the rate limiters.
+        net.set_rate_limiter_scope(RateLimiterScope::QueuePair)
+            .unwrap();
+        assert_eq!(net.rate_limiter_index(1), 1);
+        assert_eq!(net.rate_limiter_pairs(1), 1..2);
+        for limiters in [&net.rx_rate_limiters, &net.tx_rate_limiters] {
+            assert_eq!(limiters.len(), 2);
+            assert_eq!(
+                RateLimiterConfig::from(&limiters[1]),
+                RateLimiterConfig::from(&limiters[0])
+            );
+        }
+
+        // The updates apply to the rate limiters of all the pairs, each keeping its own budget.
+        assert!(net.rx_rate_limiters[1].consume(500, TokenType::Bytes));
+        net.patch_rate_limiters(
+            BucketUpdate::Update(TokenBucket::new(3000, 0, 100).unwrap()),
+            BucketUpdate::None,
+            BucketUpdate::Disabled,
+            BucketUpdate::None,
+        );
+        assert_eq!(net.rx_rate_limiters[0].bandwidth().unwrap().budget(), 3000);
+        assert!(net.rx_rate_limiters[1].bandwidth().unwrap().budget() < 3000);
+        for limiter in &net.rx_rate_limiters {
+            assert_eq!(limiter.bandwidth().unwrap().capacity(), 3000);
+            assert_eq!(limiter.ops().unwrap().capacity(), 10);
+        }
+        for limiter in &net.tx_rate_limiters {
+            assert!(limiter.bandwidth().is_none());
+            assert_eq!(limiter.ops().unwrap().capacity(), 20);
+        }
+
+        net.set_rate_limiter_scope(RateLimiterScope::Device)
+            .unwrap();
+        assert_eq!(net.rx_rate_limiters.len(), 1);
+        assert_eq!(net.tx_rate_limiters.len(), 1);
+        assert_eq!(net.rate_limiter_index(1), 0);
+    }
 }
@@ -4,54 +4,158 @@
 use event_manager::{EventOps, Events, MutEventSubscriber};
 use utils::epoll::EventSet;
 
-use crate::devices::virtio::device::VirtioDevice;
-use crate::devices::virtio::net::device::Net;
-use crate::devices::virtio::net::{RX_INDEX, TX_INDEX};
+use crate::devices::virtio::device::{DeviceErrorHandler, VirtioDevice};
+use crate::devices::virtio::net::device::{Net, QueuePair};
 use crate::logger::{error, warn, IncMetric};
 
 impl Net {
     const PROCESS_ACTIVATE: u32 = 0;
     const PROCESS_VIRTQ_RX: u32 = 1;
     const PROCESS_VIRTQ_TX: u32 = 2;
-    const PROCESS_TAP_RX: u32 = 3;
+    const PROCESS_TAP: u32 = 3;
     const PROCESS_RX_RATE_LIMITER: u32 = 4;
     const PROCESS_TX_RATE_LIMITER: u32 = 5;
+    const PROCESS_TAP_READ_BACKOFF: u32 = 6;
+    const PROCESS_MMDS_WATCH_POLL: u32 = 7;
+    const PROCESS_VIRTQ_CTRL: u32 = 8;
 
-    fn register_runtime_events(&self, ops: &mut EventOps) {
-        if let Err(err) = ops.add(Events::with_data(
-            &self.queue_evts[RX_INDEX],
-            Self::PROCESS_VIRTQ_RX,
-            EventSet::IN,
-        )) {
-            error!("Failed to register rx queue event: {}", err);
+    // The data of the events of the pairs and of the rate limiters holds the index of the pair or
+    // of the rate limiter in its upper bits.
+    const EVENT_INDEX_SHIFT: u32 = 16;
+    const EVENT_SOURCE_MASK: u32 = (1 << Self::EVENT_INDEX_SHIFT) - 1;
+
+    fn event_data(source: u32, index: usize) -> u32 {
+        // Safe to unwrap, the number of pairs is bounded by `NET_MAX_QUEUE_PAIRS`.
+        source | (u32::try_from(index).unwrap() << Self::EVENT_INDEX_SHIFT)
+    }
+
+    // The events registered once the device is activated, aside from the ones of the taps, along
+    // with a description of their source.
+    fn runtime_events(&self) -> Vec<(Events, &'static str)> {
+        let mut events = Vec::new();
+        for (i, pair) in self.pairs.iter().enumerate() {
+            events.push((
+                Events::with_data(
+                    &self.queue_evts[Self::rx_queue_index(i)],
+                    Self::event_data(Self::PROCESS_VIRTQ_RX, i),
+                    EventSet::IN,
+                ),
+                "rx queue",
+            ));
+            events.push((
+                Events::with_data(
+                    &self.queue_evts[Self::tx_queue_index(i)],
+                    Self::event_data(Self::PROCESS_VIRTQ_TX, i),
+                    EventSet::IN,
+                ),
+                "tx queue",
+            ));
+            events.push((
+                Events::with_data(
+                    &pair.tap_read_backoff.timer,
+                    Self::event_data(Self::PROCESS_TAP_READ_BACKOFF, i),
+                    EventSet::IN,
+                ),
+                "tap read backoff",
+            ));
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.queue_evts[TX_INDEX],
-            Self::PROCESS_VIRTQ_TX,
-            EventSet::IN,
-        )) {
-            error!("Failed to register tx queue event: {}", err);
+        for (i, limiter) in self.rx_rate_limiters.iter().enumerate() {
+            events.push((
+                Events::with_data(
+                    limiter,
+                    Self::event_data(Self::PROCESS_RX_RATE_LIMITER, i),
+                    EventSet::IN,
+                ),
+                "rx rate limiter",
+            ));
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.rx_rate_limiter,
-            Self::PROCESS_RX_RATE_LIMITER,
-            EventSet::IN,
-        )) {
-            error!("Failed to register rx queue event: {}", err);
+        for (i, limiter) in self.tx_rate_limiters.iter().enumerate() {
+            events.push((
+                Events::with_data(
+                    limiter,
+                    Self::event_data(Self::PROCESS_TX_RATE_LIMITER, i),
+                    EventSet::IN,
+                ),
+                "tx rate limiter",
+            ));
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.tx_rate_limiter,
-            Self::PROCESS_TX_RATE_LIMITER,
-            EventSet::IN,
-        )) {
-            error!("Failed to register tx queue event: {}", err);
+        if let Some(index) = self.ctrl_queue_index() {
+            events.push((
+                Events::with_data(
+                    &self.queue_evts[index],
+                    Self::PROCESS_VIRTQ_CTRL,
+                    EventSet::IN,
+                ),
+                "ctrl queue",
+            ));
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.tap,
-            Self::PROCESS_TAP_RX,
-            EventSet::IN | EventSet::EDGE_TRIGGERED,
-        )) {
-            error!("Failed to register tap event: {}", err);
+        events.push((
+            Events::with_data(
+                &self.mmds_watch_poll.timer,
+                Self::PROCESS_MMDS_WATCH_POLL,
+                EventSet::IN,
+            ),
+            "MMDS watch poll",
+        ));
+        events
+    }
+
+    fn register_runtime_events(&mut self, ops: &mut EventOps) {
+        for (event, name) in self.runtime_events() {
+            if let Err(err) = ops.add(event) {
+                error!("Failed to register {} event: {}", name, err);
+            }
+        }
+        self.update_tap_registration(ops);
+    }
+
+    fn tap_event(pair: &QueuePair, index: usize, event_set: EventSet) -> Events {
+        Events::with_data(
+            &pair.tap,
+            Self::event_data(Self::PROCESS_TAP, index),
+            event_set | EventSet::EDGE_TRIGGERED,
+        )
+    }
+
+    // The events the tap of a pair is registered for.
+    fn registered_tap_events(pair: &QueuePair) -> EventSet {
+        let mut event_set = EventSet::empty();
+        if pair.tap_read_backoff.tap_registered {
+            event_set |= EventSet::IN;
+        }
+        if pair.tap_write_registered {
+            event_set |= EventSet::OUT;
+        }
+        event_set
+    }
+
+    // The tap of a pair is registered for reads unless they are backed off, until the backoff
+    // expires, and for writes while a TX frame waits for it to be writable.
+    fn update_tap_registration(&mut self, ops: &mut EventOps) {
+        for (i, pair) in self.pairs.iter_mut().enumerate() {
+            let mut wanted = EventSet::empty();
+            if !pair.tap_read_backoff.is_active() {
+                wanted |= EventSet::IN;
+            }
+            if pair.tap_write_blocked {
+                wanted |= EventSet::OUT;
+            }
+            let registered = Self::registered_tap_events(pair);
+            if wanted == registered {
+                continue;
+            }
+            let result = if registered.is_empty() {
+                ops.add(Self::tap_event(pair, i, wanted))
+            } else if wanted.is_empty() {
+                ops.remove(Self::tap_event(pair, i, registered))
+            } else {
+                ops.modify(Self::tap_event(pair, i, wanted))
+            };
+            if let Err(err) = result {
+                error!("Failed to update the tap event registration: {}", err);
+            }
+            pair.tap_read_backoff.tap_registered = wanted.contains(EventSet::IN);
+            pair.tap_write_registered = wanted.contains(EventSet::OUT);
         }
     }
 
@@ -65,7 +169,7 @@ impl Net {
         }
     }
 
-    fn process_activate_event(&self, ops: &mut EventOps) {
+    fn process_activate_event(&mut self, ops: &mut EventOps) {
         if let Err(err) = self.activate_evt.read() {
             error!("Failed to consume net activate event: {:?}", err);
         }
@@ -80,6 +184,30 @@ impl Net {
     }
 }
 
+impl DeviceErrorHandler for Net {
+    fn set_needs_reset(&mut self) {
+        self.needs_reset = true;
+    }
+
+    fn unregister_runtime_events(&mut self, ops: &mut EventOps) {
+        for (event, name) in self.runtime_events() {
+            if let Err(err) = ops.remove(event) {
+                error!("Failed to un-register {} event: {}", name, err);
+            }
+        }
+        for (i, pair) in self.pairs.iter_mut().enumerate() {
+            let registered = Self::registered_tap_events(pair);
+            if !registered.is_empty() {
+                if let Err(err) = ops.remove(Self::tap_event(pair, i, registered)) {
+                    error!("Failed to un-register tap event: {}", err);
+                }
+                pair.tap_read_backoff.tap_registered = false;
+                pair.tap_write_registered = false;
+            }
+        }
+    }
+}
+
 impl MutEventSubscriber for Net {
     fn process(&mut self, event: Events, ops: &mut EventOps) {
         let source = event.data();
@@ -87,7 +215,7 @@ impl MutEventSubscriber for Net {
 
         // TODO: also check for errors. Pending high level discussions on how we want
         // to handle errors in devices.
-        let supported_events = EventSet::IN;
+        let supported_events = EventSet::IN | EventSet::OUT;
         if !supported_events.contains(event_set) {
             warn!(
                 "Received unknown event: {:?} from source: {:?}",
@@ -97,17 +225,46 @@ impl MutEventSubscriber for Net {
         }
 
         if self.is_activated() {
-            match source {
-                Self::PROCESS_ACTIVATE => self.process_activate_event(ops),
-                Self::PROCESS_VIRTQ_RX => self.process_rx_queue_event(),
-                Self::PROCESS_VIRTQ_TX => self.process_tx_queue_event(),
-                Self::PROCESS_TAP_RX => self.process_tap_rx_event(),
-                Self::PROCESS_RX_RATE_LIMITER => self.process_rx_rate_limiter_event(),
-                Self::PROCESS_TX_RATE_LIMITER => self.process_tx_rate_limiter_event(),
+            let index = (source >> Self::EVENT_INDEX_SHIFT) as usize;
+            let result = match source & Self::EVENT_SOURCE_MASK {
+                Self::PROCESS_ACTIVATE => {
+                    self.process_activate_event(ops);
+                    Ok(())
+                }
+                Self::PROCESS_VIRTQ_RX => self.process_rx_queue_event(index),
+                Self::PROCESS_VIRTQ_TX => self.process_tx_queue_event(index),
+                Self::PROCESS_TAP => {
+                    // The tap may have become writable and readable at once.
+                    let mut result = Ok(());
+                    if event_set.contains(EventSet::OUT) {
+                        result = self.process_tap_tx_event(index);
+                    }
+                    if event_set.contains(EventSet::IN) {
+                        result = result.and(self.process_tap_rx_event(index));
+                    }
+                    result
+                }
+                Self::PROCESS_RX_RATE_LIMITER => self.process_rx_rate_limiter_event(index),
+                Self::PROCESS_TX_RATE_LIMITER => self.process_tx_rate_limiter_event(index),
+                Self::PROCESS_TAP_READ_BACKOFF => self.process_tap_read_backoff_event(index),
+                Self::PROCESS_MMDS_WATCH_POLL => self.process_mmds_watch_poll_event(),
+                Self::PROCESS_VIRTQ_CTRL => self.process_ctrl_queue_event(),
                 _ => {
                     warn!("Net: Spurious event received: {:?}", source);
                     self.metrics.event_fails.inc();
+                    Ok(())
                 }
+            };
+            if let Err(err) = result {
+                self.handle_error(ops, err);
+            }
+            // Any RX processing above may have started or ended a tap read backoff, any TX
+            // processing may have found the tap full, and any request to the MMDS may have been
+            // parked or completed. The events of the device are
+            // not registered again once it stopped.
+            if !self.needs_reset {
+                self.update_tap_registration(ops);
+                self.update_mmds_watch_poll();
             }
         } else {
             warn!(
@@ -135,6 +292,9 @@ pub mod tests {
     use crate::devices::virtio::net::test_utils::test::TestHelper;
     use crate::devices::virtio::net::test_utils::NetQueue;
     use crate::devices::virtio::net::TX_INDEX;
+    use crate::devices::virtio::queue::QueueError;
+    use crate::devices::virtio::test_utils::test::check_error_handling;
+    use crate::devices::DeviceError;
 
     #[test]
     fn test_event_handler() {
@@ -164,4 +324,21 @@ pub mod tests {
         // Make sure the data queue advanced.
         assert_eq!(th.txq.used.idx.get(), 1);
     }
+
+    #[test]
+    fn test_error_handling() {
+        let mut th = TestHelper::get_default();
+        th.activate_net();
+
+        // There is no pending queue event.
+        let transient = th.net().process_tx_queue_event(0).unwrap_err();
+        check_error_handling(
+            th.net.clone(),
+            [
+                transient,
+                DeviceError::FailedSignalingIrq(std::io::Error::from_raw_os_error(libc::EAGAIN)),
+                DeviceError::QueueError(QueueError::DescIndexOutOfBounds(256)),
+            ],
+        );
+    }
 }
@@ -119,6 +119,8 @@ pub const IFF_TAP: u32 = 2;
 pub const IFF_NO_PI: u32 = 4096;
 pub const IFF_VNET_HDR: u32 = 16384;
 pub const IFF_MULTI_QUEUE: u32 = 256;
+pub const IFF_ATTACH_QUEUE: u32 = 512;
+pub const IFF_DETACH_QUEUE: u32 = 1024;
 pub const TUN_TX_TIMESTAMP: u32 = 1;
 pub const TUN_F_CSUM: u32 = 1;
 pub const TUN_F_TSO4: u32 = 2;
@@ -50,6 +50,9 @@
 //! `net_eth1` represent metrics for the endpoint "/network-interfaces/eth1", and
 //! `net_iface_id` represent metrics for the endpoint "/network-interfaces/{iface_id}"
 //! network device respectively and `net` is the aggregate of all the per device metrics.
+//! The metrics of the traffic of the second and next pairs of RX and TX queues of a multi-queue
+//! device are in separate entries, e.g. `net_eth0_pair1`, the ones of the first pair being in
+//! the entry of the device.
 //!
 //! # Limitations
 //! Network device currently do not have `vmm::logger::metrics::StoreMetrics` so aggregate
@@ -85,6 +88,7 @@ use std::sync::{Arc, RwLock};
 use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
 
+use crate::devices::virtio::queue::QueueCounters;
 use crate::logger::{IncMetric, LatencyAggregateMetrics, SharedIncMetric};
 
 /// map of network interface id and metrics
@@ -123,14 +127,20 @@ static METRICS: RwLock<NetMetricsPerDevice> = RwLock::new(NetMetricsPerDevice {
 /// This function facilitates aggregation and serialization of
 /// per net device metrics.
 pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
-    let net_metrics = METRICS.read().unwrap();
-    let metrics_len = net_metrics.metrics.len();
+    serialize_metrics(&METRICS.read().unwrap().metrics, serializer)
+}
+
+// Serializes the metrics of each device, keyed by "net_$iface_id", and their aggregate as "net".
+fn serialize_metrics<S: Serializer>(
+    metrics: &BTreeMap<String, Arc<NetDeviceMetrics>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
     // +1 to accomodate aggregate net metrics
-    let mut seq = serializer.serialize_map(Some(1 + metrics_len))?;
+    let mut seq = serializer.serialize_map(Some(1 + metrics.len()))?;
 
     let mut net_aggregated: NetDeviceMetrics = NetDeviceMetrics::default();
 
-    for (name, metrics) in net_metrics.metrics.iter() {
+    for (name, metrics) in metrics.iter() {
         let devn = format!("net_{}", name);
         // serialization will flush the metrics so aggregate before it.
         let m: &NetDeviceMetrics = metrics;
@@ -150,6 +160,8 @@ pub struct NetDeviceMetrics {
     pub cfg_fails: SharedIncMetric,
     /// Number of times the mac address was updated through the config space.
     pub mac_address_updates: SharedIncMetric,
+    /// Number of times the rate limiters were replaced through the API.
+    pub rate_limiter_updates: SharedIncMetric,
     /// No available buffer for the net device rx queue.
     pub no_rx_avail_buffer: SharedIncMetric,
     /// No available buffer for the net device tx queue.
@@ -158,12 +170,26 @@ pub struct NetDeviceMetrics {
     pub event_fails: SharedIncMetric,
     /// Number of events associated with the receiving queue.
     pub rx_queue_event_count: SharedIncMetric,
+    /// Number of descriptor chains popped from the receiving queue.
+    pub rx_queue_pops: SharedIncMetric,
+    /// Number of times the receiving queue was found empty when popping from it.
+    pub rx_queue_empty_pops: SharedIncMetric,
+    /// Number of entries added to the used ring of the receiving queue.
+    pub rx_queue_used: SharedIncMetric,
+    /// Number of times the guest needed to be notified of the entries used from the receiving
+    /// queue.
+    pub rx_queue_notified: SharedIncMetric,
+    /// Number of times the notification of the entries used from the receiving queue was
+    /// suppressed by the guest.
+    pub rx_queue_suppressed: SharedIncMetric,
     /// Number of events associated with the rate limiter installed on the receiving path.
     pub rx_event_rate_limiter_count: SharedIncMetric,
     /// Number of RX partial writes to guest.
     pub rx_partial_writes: SharedIncMetric,
     /// Number of RX rate limiter throttling events.
     pub rx_rate_limiter_throttled: SharedIncMetric,
+    /// Number of frames dropped by the RX filter set by the guest.
+    pub rx_filtered_frames: SharedIncMetric,
     /// Number of events received on the associated tap.
     pub rx_tap_event_count: SharedIncMetric,
     /// Number of bytes received.
@@ -174,10 +200,18 @@ pub struct NetDeviceMetrics {
     pub rx_fails: SharedIncMetric,
     /// Number of successful read operations while receiving data.
     pub rx_count: SharedIncMetric,
+    /// Number of activations with a TAP MTU smaller than the MTU offered to the guest.
+    pub tap_mtu_too_small: SharedIncMetric,
     /// Number of times reading from TAP failed.
     pub tap_read_fails: SharedIncMetric,
+    /// Number of TAP read failures that suspended reading from the TAP for a while.
+    pub tap_read_fatal: SharedIncMetric,
+    /// Number of TAP read failures with an unexpected error.
+    pub tap_read_unknown_fails: SharedIncMetric,
     /// Number of times writing to TAP failed.
     pub tap_write_fails: SharedIncMetric,
+    /// Number of times writing to TAP was deferred until the TAP could take the frame.
+    pub tap_write_deferred: SharedIncMetric,
     /// Duration of all tap write operations.
     pub tap_write_agg: LatencyAggregateMetrics,
     /// Number of transmitted bytes.
@@ -194,10 +228,24 @@ pub struct NetDeviceMetrics {
     pub tx_partial_reads: SharedIncMetric,
     /// Number of events associated with the transmitting queue.
     pub tx_queue_event_count: SharedIncMetric,
+    /// Number of descriptor chains popped from the transmitting queue.
+    pub tx_queue_pops: SharedIncMetric,
+    /// Number of times the transmitting queue was found empty when popping from it.
+    pub tx_queue_empty_pops: SharedIncMetric,
+    /// Number of entries added to the used ring of the transmitting queue.
+    pub tx_queue_used: SharedIncMetric,
+    /// Number of times the guest needed to be notified of the entries used from the transmitting
+    /// queue.
+    pub tx_queue_notified: SharedIncMetric,
+    /// Number of times the notification of the entries used from the transmitting queue was
+    /// suppressed by the guest.
+    pub tx_queue_suppressed: SharedIncMetric,
     /// Number of events associated with the rate limiter installed on the transmitting path.
     pub tx_rate_limiter_event_count: SharedIncMetric,
     /// Number of RX rate limiter throttling events.
     pub tx_rate_limiter_throttled: SharedIncMetric,
+    /// Number of times TX processing stopped until the TAP could take frames again.
+    pub tx_backpressure: SharedIncMetric,
     /// Number of packets with a spoofed mac, sent by the guest.
     pub tx_spoofed_mac_count: SharedIncMetric,
     /// Number of remaining requests in the TX queue.
@@ -213,6 +261,24 @@ impl NetDeviceMetrics {
         }
     }
 
+    /// Adds the counts of the operations done on the receiving queue.
+    pub fn add_rx_queue_counters(&self, counters: QueueCounters) {
+        self.rx_queue_pops.add(counters.pops);
+        self.rx_queue_empty_pops.add(counters.empty_pops);
+        self.rx_queue_used.add(counters.used);
+        self.rx_queue_notified.add(counters.notified);
+        self.rx_queue_suppressed.add(counters.suppressed);
+    }
+
+    /// Adds the counts of the operations done on the transmitting queue.
+    pub fn add_tx_queue_counters(&self, counters: QueueCounters) {
+        self.tx_queue_pops.add(counters.pops);
+        self.tx_queue_empty_pops.add(counters.empty_pops);
+        self.tx_queue_used.add(counters.used);
+        self.tx_queue_notified.add(counters.notified);
+        self.tx_queue_suppressed.add(counters.suppressed);
+    }
+
     /// Net metrics are SharedIncMetric where the diff of current vs
     /// old is serialized i.e. serialize_u64(current-old).
     /// So to have the aggregate serialized in same way we need to
@@ -223,6 +289,8 @@ impl NetDeviceMetrics {
         self.cfg_fails.add(other.cfg_fails.fetch_diff());
         self.mac_address_updates
             .add(other.mac_address_updates.fetch_diff());
+        self.rate_limiter_updates
+            .add(other.rate_limiter_updates.fetch_diff());
         self.no_rx_avail_buffer
             .add(other.no_rx_avail_buffer.fetch_diff());
         self.no_tx_avail_buffer
@@ -230,12 +298,22 @@ impl NetDeviceMetrics {
         self.event_fails.add(other.event_fails.fetch_diff());
         self.rx_queue_event_count
             .add(other.rx_queue_event_count.fetch_diff());
+        self.rx_queue_pops.add(other.rx_queue_pops.fetch_diff());
+        self.rx_queue_empty_pops
+            .add(other.rx_queue_empty_pops.fetch_diff());
+        self.rx_queue_used.add(other.rx_queue_used.fetch_diff());
+        self.rx_queue_notified
+            .add(other.rx_queue_notified.fetch_diff());
+        self.rx_queue_suppressed
+            .add(other.rx_queue_suppressed.fetch_diff());
         self.rx_event_rate_limiter_count
             .add(other.rx_event_rate_limiter_count.fetch_diff());
         self.rx_partial_writes
             .add(other.rx_partial_writes.fetch_diff());
         self.rx_rate_limiter_throttled
             .add(other.rx_rate_limiter_throttled.fetch_diff());
+        self.rx_filtered_frames
+            .add(other.rx_filtered_frames.fetch_diff());
         self.rx_tap_event_count
             .add(other.rx_tap_event_count.fetch_diff());
         self.rx_bytes_count.add(other.rx_bytes_count.fetch_diff());
@@ -243,8 +321,15 @@ impl NetDeviceMetrics {
             .add(other.rx_packets_count.fetch_diff());
         self.rx_fails.add(other.rx_fails.fetch_diff());
         self.rx_count.add(other.rx_count.fetch_diff());
+        self.tap_mtu_too_small
+            .add(other.tap_mtu_too_small.fetch_diff());
         self.tap_read_fails.add(other.tap_read_fails.fetch_diff());
+        self.tap_read_fatal.add(other.tap_read_fatal.fetch_diff());
+        self.tap_read_unknown_fails
+            .add(other.tap_read_unknown_fails.fetch_diff());
         self.tap_write_fails.add(other.tap_write_fails.fetch_diff());
+        self.tap_write_deferred
+            .add(other.tap_write_deferred.fetch_diff());
         self.tap_write_agg
             .sum_us
             .add(other.tap_write_agg.sum_us.fetch_diff());
@@ -259,10 +344,19 @@ impl NetDeviceMetrics {
             .add(other.tx_partial_reads.fetch_diff());
         self.tx_queue_event_count
             .add(other.tx_queue_event_count.fetch_diff());
+        self.tx_queue_pops.add(other.tx_queue_pops.fetch_diff());
+        self.tx_queue_empty_pops
+            .add(other.tx_queue_empty_pops.fetch_diff());
+        self.tx_queue_used.add(other.tx_queue_used.fetch_diff());
+        self.tx_queue_notified
+            .add(other.tx_queue_notified.fetch_diff());
+        self.tx_queue_suppressed
+            .add(other.tx_queue_suppressed.fetch_diff());
         self.tx_rate_limiter_event_count
             .add(other.tx_rate_limiter_event_count.fetch_diff());
         self.tx_rate_limiter_throttled
             .add(other.tx_rate_limiter_throttled.fetch_diff());
+        self.tx_backpressure.add(other.tx_backpressure.fetch_diff());
         self.tx_spoofed_mac_count
             .add(other.tx_spoofed_mac_count.fetch_diff());
         self.tx_remaining_reqs_count
@@ -442,4 +536,78 @@ pub mod tests {
                 >= 5
         );
     }
+
+    #[test]
+    fn test_serialize_metrics() {
+        // Use metrics which are not registered, so that other tests can't update them.
+        let eth0 = Arc::new(NetDeviceMetrics::default());
+        let eth1 = Arc::new(NetDeviceMetrics::default());
+        let metrics = BTreeMap::from([
+            (String::from("eth0"), eth0.clone()),
+            (String::from("eth1"), eth1.clone()),
+        ]);
+        eth0.rx_bytes_count.add(10);
+        eth0.tap_read_fails.inc();
+        eth1.rx_bytes_count.add(32);
+        eth1.rx_rate_limiter_throttled.add(2);
+
+        let mut serialized = Vec::new();
+        serialize_metrics(&metrics, &mut serde_json::Serializer::new(&mut serialized)).unwrap();
+        let serialized: serde_json::Value = serde_json::from_slice(&serialized).unwrap();
+        assert_eq!(serialized.as_object().unwrap().len(), 3);
+
+        // The metrics of each interface are reported along with their aggregate.
+        assert_eq!(serialized["net_eth0"]["rx_bytes_count"], 10);
+        assert_eq!(serialized["net_eth0"]["tap_read_fails"], 1);
+        assert_eq!(serialized["net_eth0"]["rx_rate_limiter_throttled"], 0);
+        assert_eq!(serialized["net_eth1"]["rx_bytes_count"], 32);
+        assert_eq!(serialized["net_eth1"]["tap_read_fails"], 0);
+        assert_eq!(serialized["net_eth1"]["rx_rate_limiter_throttled"], 2);
+        assert_eq!(serialized["net"]["rx_bytes_count"], 42);
+        assert_eq!(serialized["net"]["tap_read_fails"], 1);
+        assert_eq!(serialized["net"]["rx_rate_limiter_throttled"], 2);
+        for (name, value) in serialized["net"].as_object().unwrap() {
+            let Some(total) = value.as_u64() else {
+                continue;
+            };
+            let sum = serialized["net_eth0"][name].as_u64().unwrap()
+                + serialized["net_eth1"][name].as_u64().unwrap();
+            assert_eq!(total, sum, "{}", name);
+        }
+
+        // Flushing resets the metrics, of the interfaces and of their aggregate.
+        eth1.rx_bytes_count.add(8);
+        let mut serialized = Vec::new();
+        serialize_metrics(&metrics, &mut serde_json::Serializer::new(&mut serialized)).unwrap();
+        let serialized: serde_json::Value = serde_json::from_slice(&serialized).unwrap();
+        assert_eq!(serialized["net_eth0"]["rx_bytes_count"], 0);
+        assert_eq!(serialized["net_eth1"]["rx_bytes_count"], 8);
+        assert_eq!(serialized["net"]["rx_bytes_count"], 8);
+        assert_eq!(serialized["net"]["tap_read_fails"], 0);
+    }
+
+    #[test]
+    fn test_queue_counters_metrics() {
+        let metrics = NetDeviceMetrics::new();
+        metrics.add_rx_queue_counters(QueueCounters {
+            pops: 3,
+            empty_pops: 1,
+            used: 3,
+            notified: 1,
+            suppressed: 2,
+        });
+        metrics.add_tx_queue_counters(QueueCounters {
+            pops: 2,
+            ..Default::default()
+        });
+
+        let serialized = serde_json::to_value(&metrics).unwrap();
+        assert_eq!(serialized["rx_queue_pops"], 3);
+        assert_eq!(serialized["rx_queue_empty_pops"], 1);
+        assert_eq!(serialized["rx_queue_used"], 3);
+        assert_eq!(serialized["rx_queue_notified"], 1);
+        assert_eq!(serialized["rx_queue_suppressed"], 2);
+        assert_eq!(serialized["tx_queue_pops"], 2);
+        assert_eq!(serialized["tx_queue_used"], 0);
+    }
 }
@@ -9,9 +9,20 @@ use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
 
 /// Maximum size of the frame buffers handled by this device.
 pub const MAX_BUFFER_SIZE: usize = 65562;
-/// The number of queues of the network device.
+/// The smallest MTU which can be offered to the guest, the minimal one of IPv4.
+pub const NET_MIN_MTU: u16 = 68;
+/// The largest MTU which can be offered to the guest, whose frames fit in `MAX_BUFFER_SIZE` along
+/// with their VNET header, Ethernet header and VLAN tag.
+pub const NET_MAX_MTU: u16 = 65532;
+/// The number of queues of a network device with a single pair of RX and TX queues.
 pub const NET_NUM_QUEUES: usize = 2;
 pub const NET_QUEUE_SIZES: [u16; NET_NUM_QUEUES] = [FIRECRACKER_MAX_QUEUE_SIZE; NET_NUM_QUEUES];
+/// Smallest queue size that can be configured for a network device.
+pub const NET_MIN_QUEUE_SIZE: u16 = 64;
+/// Largest queue size that can be configured for a network device.
+pub const NET_MAX_QUEUE_SIZE: u16 = 1024;
+/// Largest number of pairs of RX and TX queues of a network device.
+pub const NET_MAX_QUEUE_PAIRS: u16 = 16;
 /// The index of the rx queue from Net device queues/queues_evts vector.
 pub const RX_INDEX: usize = 0;
 /// The index of the tx queue from Net device queues/queues_evts vector.
@@ -28,7 +39,7 @@ mod gen;
 
 pub use tap::{Tap, TapError};
 
-pub use self::device::Net;
+pub use self::device::{Net, NetOffloads, RateLimiterScope};
 
 /// Enum representing the Net device queue types
 #[derive(Debug)]
@@ -48,8 +59,20 @@ pub enum NetError {
     TapSetOffload(TapError),
     /// Setting vnet header size failed: {0}
     TapSetVnetHdrSize(TapError),
+    /// Enabling or disabling a tap queue failed: {0}
+    TapSetQueue(TapError),
+    /// Invalid number of queue pairs {0}, must be between 1 and {1}
+    QueuePairs(u16, u16),
+    /// The {0} offload requires the {1} offload
+    OffloadDependency(&'static str, &'static str),
+    /// The guest MAC address can not be set without the VIRTIO_NET_F_MAC feature
+    GuestMacNotNegotiated,
+    /// Invalid MTU {0}, must be between {1} and {2}
+    InvalidMtu(u16, u16, u16),
     /// EventFd error: {0}
     EventFd(io::Error),
+    /// TimerFd error: {0}
+    TimerFd(io::Error),
     /// IO error: {0}
     IO(io::Error),
     /// The VNET header is missing from the frame
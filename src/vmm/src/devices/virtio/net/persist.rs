@@ -4,15 +4,15 @@
 //! Defines the structures needed for saving/restoring net devices.
 
 use std::io;
+use std::os::unix::io::RawFd;
 use std::sync::atomic::AtomicU32;
 use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 use utils::net::mac::MacAddr;
 
-use super::device::Net;
-use super::NET_NUM_QUEUES;
-use crate::devices::virtio::device::DeviceState;
+use super::device::{Net, NetOffloads, RateLimiterScope, RxFilter};
+use crate::devices::virtio::device::{DeviceState, QueueSizeError};
 use crate::devices::virtio::persist::{PersistError as VirtioStateError, VirtioDeviceState};
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
 use crate::devices::virtio::TYPE_NET;
@@ -36,13 +36,27 @@ pub struct NetConfigSpaceState {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetState {
     id: String,
-    tap_if_name: String,
+    pub(crate) tap_if_name: String,
+    // The fd of the tap to restore the device with instead of opening `tap_if_name`, set when
+    // loading the snapshot. The fd of the saved device is not valid in another process.
+    #[serde(skip)]
+    pub(crate) tap_fd: Option<RawFd>,
     rx_rate_limiter_state: RateLimiterState,
     tx_rate_limiter_state: RateLimiterState,
     /// The associated MMDS network stack.
     pub mmds_ns: Option<MmdsNetworkStackState>,
     config_space: NetConfigSpaceState,
     virtio_state: VirtioDeviceState,
+    queue_size: Option<u16>,
+    num_queue_pairs: u16,
+    active_queue_pairs: u16,
+    rate_limiter_scope: RateLimiterScope,
+    // The rate limiters of the pairs after the first one, with `RateLimiterScope::QueuePair`.
+    pair_rx_rate_limiter_states: Vec<RateLimiterState>,
+    pair_tx_rate_limiter_states: Vec<RateLimiterState>,
+    offloads: NetOffloads,
+    mtu: Option<u16>,
+    rx_filter: RxFilter,
 }
 
 /// Auxiliary structure for creating a device when resuming from a snapshot.
@@ -63,8 +77,12 @@ pub enum NetPersistError {
     CreateRateLimiter(#[from] io::Error),
     /// Failed to re-create the virtio state (i.e queues etc): {0}
     VirtioState(#[from] VirtioStateError),
+    /// Invalid queue size: {0}
+    QueueSize(#[from] QueueSizeError),
     /// Indicator that no MMDS is associated with this device.
     NoMmdsDataStore,
+    /// A network interface restored with a tap fd can only have a single pair of queues.
+    TapFdMultiQueue,
 }
 
 impl Persist<'_> for Net {
@@ -76,13 +94,30 @@ impl Persist<'_> for Net {
         NetState {
             id: self.id().clone(),
             tap_if_name: self.iface_name(),
-            rx_rate_limiter_state: self.rx_rate_limiter.save(),
-            tx_rate_limiter_state: self.tx_rate_limiter.save(),
+            tap_fd: None,
+            rx_rate_limiter_state: self.rx_rate_limiters[0].save(),
+            tx_rate_limiter_state: self.tx_rate_limiters[0].save(),
             mmds_ns: self.mmds_ns.as_ref().map(|mmds| mmds.save()),
             config_space: NetConfigSpaceState {
                 guest_mac: self.guest_mac,
             },
             virtio_state: VirtioDeviceState::from_device(self),
+            queue_size: self.queue_size,
+            num_queue_pairs: self.num_pairs(),
+            // Safe to unwrap, there are at most as many active pairs as pairs.
+            active_queue_pairs: u16::try_from(self.active_pairs).unwrap(),
+            rate_limiter_scope: self.rate_limiter_scope,
+            pair_rx_rate_limiter_states: self.rx_rate_limiters[1..]
+                .iter()
+                .map(|limiter| limiter.save())
+                .collect(),
+            pair_tx_rate_limiter_states: self.tx_rate_limiters[1..]
+                .iter()
+                .map(|limiter| limiter.save())
+                .collect(),
+            offloads: self.offloads,
+            mtu: self.mtu,
+            rx_filter: self.rx_filter.clone(),
         }
     }
 
@@ -93,13 +128,40 @@ impl Persist<'_> for Net {
         // RateLimiter::restore() can fail at creating a timerfd.
         let rx_rate_limiter = RateLimiter::restore((), &state.rx_rate_limiter_state)?;
         let tx_rate_limiter = RateLimiter::restore((), &state.tx_rate_limiter_state)?;
-        let mut net = Net::new(
-            state.id.clone(),
-            &state.tap_if_name,
-            state.config_space.guest_mac,
-            rx_rate_limiter,
-            tx_rate_limiter,
-        )?;
+        let mut net = match state.tap_fd {
+            Some(_) if state.num_queue_pairs > 1 => return Err(NetPersistError::TapFdMultiQueue),
+            Some(tap_fd) => Net::new_with_tap_fd(
+                state.id.clone(),
+                tap_fd,
+                state.config_space.guest_mac,
+                rx_rate_limiter,
+                tx_rate_limiter,
+            )?,
+            None => Net::new_multi_queue(
+                state.id.clone(),
+                &state.tap_if_name,
+                state.num_queue_pairs,
+                state.config_space.guest_mac,
+                rx_rate_limiter,
+                tx_rate_limiter,
+            )?,
+        };
+        // The offload flags of the taps are not part of the tap state we keep across snapshots.
+        net.set_offloads(state.offloads)?;
+        // This also reads the MTU of the tap again, as it may have changed.
+        if let Some(mtu) = state.mtu {
+            net.set_mtu(mtu)?;
+        }
+        net.rate_limiter_scope = state.rate_limiter_scope;
+        net.rx_filter = state.rx_filter.clone();
+        for limiter_state in &state.pair_rx_rate_limiter_states {
+            net.rx_rate_limiters
+                .push(RateLimiter::restore((), limiter_state)?);
+        }
+        for limiter_state in &state.pair_tx_rate_limiter_states {
+            net.tx_rate_limiters
+                .push(RateLimiter::restore((), limiter_state)?);
+        }
 
         // We trust the MMIODeviceManager::restore to pass us an MMDS data store reference if
         // there is at least one net device having the MMDS NS present and/or the mmds version was
@@ -118,15 +180,21 @@ impl Persist<'_> for Net {
             );
         }
 
+        net.avail_features = state.virtio_state.avail_features;
+        net.acked_features = state.virtio_state.acked_features;
+        net.virtio_features_pin = state.virtio_state.virtio_features_pin;
+        net.drop_unoffered_ctrl_queue();
+        if let Some(size) = state.queue_size {
+            net.set_queue_size(size)?;
+        }
         net.queues = state.virtio_state.build_queues_checked(
             &constructor_args.mem,
             TYPE_NET,
-            NET_NUM_QUEUES,
-            FIRECRACKER_MAX_QUEUE_SIZE,
+            net.queues.len(),
+            state.queue_size.unwrap_or(FIRECRACKER_MAX_QUEUE_SIZE),
         )?;
         net.irq_trigger.irq_status = Arc::new(AtomicU32::new(state.virtio_state.interrupt_status));
-        net.avail_features = state.virtio_state.avail_features;
-        net.acked_features = state.virtio_state.acked_features;
+        net.set_active_pairs(state.active_queue_pairs)?;
 
         if state.virtio_state.activated {
             net.device_state = DeviceState::Activated(constructor_args.mem);
@@ -142,7 +210,10 @@ mod tests {
 
     use super::*;
     use crate::devices::virtio::device::VirtioDevice;
-    use crate::devices::virtio::net::test_utils::{default_net, default_net_no_mmds};
+    use crate::devices::virtio::gen::virtio_net::VIRTIO_NET_F_CTRL_VQ;
+    use crate::devices::virtio::net::test_utils::{
+        assign_queues, default_net, default_net_multi_queue, default_net_no_mmds, virtqueues,
+    };
     use crate::devices::virtio::test_utils::default_mem;
     use crate::snapshot::Snapshot;
 
@@ -155,6 +226,13 @@ mod tests {
         let has_mmds_ns;
         let allow_mmds_requests;
         let virtio_state;
+        let num_pairs;
+        let active_pairs;
+        let rate_limiter_scope;
+        let num_limiters;
+        let offloads;
+        let mtu;
+        let rx_filter;
 
         // Create and save the net device.
         {
@@ -166,6 +244,13 @@ mod tests {
             has_mmds_ns = net.mmds_ns.is_some();
             allow_mmds_requests = has_mmds_ns && mmds_ds.is_some();
             virtio_state = VirtioDeviceState::from_device(&net);
+            num_pairs = net.num_pairs();
+            active_pairs = net.active_pairs;
+            rate_limiter_scope = net.rate_limiter_scope;
+            num_limiters = net.rx_rate_limiters.len();
+            offloads = net.offloads;
+            mtu = net.mtu;
+            rx_filter = net.rx_filter.clone();
         }
 
         // Drop the initial net device so that we don't get an error when trying to recreate the
@@ -195,8 +280,23 @@ mod tests {
                     assert_eq!(&restored_net.id, &id);
                     assert_eq!(&restored_net.iface_name(), &tap_if_name);
                     assert_eq!(restored_net.mmds_ns.is_some(), allow_mmds_requests);
-                    assert_eq!(restored_net.rx_rate_limiter, RateLimiter::default());
-                    assert_eq!(restored_net.tx_rate_limiter, RateLimiter::default());
+                    assert_eq!(restored_net.num_pairs(), num_pairs);
+                    assert_eq!(restored_net.active_pairs, active_pairs);
+                    assert_eq!(restored_net.rate_limiter_scope, rate_limiter_scope);
+                    assert_eq!(restored_net.rx_rate_limiters.len(), num_limiters);
+                    assert_eq!(restored_net.tx_rate_limiters.len(), num_limiters);
+                    assert_eq!(restored_net.offloads, offloads);
+                    assert_eq!(restored_net.mtu, mtu);
+                    assert_eq!(restored_net.config_space.mtu, mtu.unwrap_or(0));
+                    assert_eq!(restored_net.rx_filter, rx_filter);
+                    assert_eq!(restored_net.queues().len(), virtio_state.queues.len());
+                    for limiter in restored_net
+                        .rx_rate_limiters
+                        .iter()
+                        .chain(&restored_net.tx_rate_limiters)
+                    {
+                        assert_eq!(limiter, &RateLimiter::default());
+                    }
                 }
                 Err(NetPersistError::NoMmdsDataStore) => {
                     assert!(has_mmds_ns && !allow_mmds_requests)
@@ -221,4 +321,109 @@ mod tests {
         // data store. This will return an error.
         validate_save_and_restore(default_net(), None);
     }
+
+    #[test]
+    fn test_persistence_multi_queue() {
+        let mut net = default_net_multi_queue(2);
+        validate_save_and_restore(net, None);
+
+        net = default_net_multi_queue(3);
+        net.set_rate_limiter_scope(RateLimiterScope::QueuePair)
+            .unwrap();
+        net.set_active_pairs(2).unwrap();
+        validate_save_and_restore(net, None);
+
+        net = default_net_multi_queue(2);
+        net.set_offloads(NetOffloads {
+            guest_tso4: false,
+            guest_ufo: false,
+            host_ufo: false,
+            ..Default::default()
+        })
+        .unwrap();
+        validate_save_and_restore(net, None);
+
+        net = default_net_multi_queue(2);
+        net.set_mtu(9000).unwrap();
+        validate_save_and_restore(net, None);
+    }
+
+    #[test]
+    fn test_persistence_unused_queues() {
+        // The driver only set up the first pair, without negotiating the control queue nor
+        // multiple pairs.
+        let mem = default_mem();
+        let mut net = default_net_multi_queue(2);
+        let (rxq, txq) = virtqueues(&mem);
+        assign_queues(&mut net, rxq.create_queue(), txq.create_queue());
+        net.activate(mem.clone()).unwrap();
+        let state = net.save();
+        drop(net);
+
+        let restored_net = Net::restore(NetConstructorArgs { mem, mmds: None }, &state).unwrap();
+        assert!(restored_net.is_activated());
+        let ready: Vec<_> = restored_net.queues().iter().map(|q| q.ready).collect();
+        assert_eq!(ready, [true, true, false, false, false]);
+    }
+
+    #[test]
+    fn test_persistence_rx_filter() {
+        let mut net = default_net_no_mmds();
+        net.rx_filter = RxFilter {
+            promisc: false,
+            all_multi: false,
+            unicast: None,
+            multicast: Some(vec![MacAddr::from([0x01, 0x00, 0x5e, 0x00, 0x00, 0x12])]),
+        };
+        validate_save_and_restore(net, None);
+
+        // The control queue is not part of the state of a device pinned without it.
+        let mut net = default_net_no_mmds();
+        net.pin_virtio_features(net.avail_features() & !(1 << VIRTIO_NET_F_CTRL_VQ))
+            .unwrap();
+        assert_eq!(net.queues().len(), 2);
+        validate_save_and_restore(net, None);
+    }
+
+    #[test]
+    fn test_persistence_tap_fd() {
+        use std::os::unix::io::AsRawFd;
+
+        use crate::devices::virtio::net::Tap;
+
+        let net = default_net_no_mmds();
+        let mut state = net.save();
+        assert_eq!(state.tap_fd, None);
+        drop(net);
+
+        // The device is restored with the fd of the tap it is given.
+        let tap = Tap::open_named("fdtap%d").unwrap();
+        state.tap_fd = Some(tap.as_raw_fd());
+        let restored_net = Net::restore(
+            NetConstructorArgs {
+                mem: default_mem(),
+                mmds: None,
+            },
+            &state,
+        )
+        .unwrap();
+        assert_eq!(restored_net.iface_name(), tap.if_name_as_str());
+        assert_eq!(restored_net.tap_fd(), Some(tap.as_raw_fd()));
+        // The fd is not saved, as it is not valid in another process.
+        assert_eq!(restored_net.save().tap_fd, None);
+
+        let net = default_net_multi_queue(2);
+        let mut state = net.save();
+        state.tap_fd = Some(tap.as_raw_fd());
+        assert!(matches!(
+            Net::restore(
+                NetConstructorArgs {
+                    mem: default_mem(),
+                    mmds: None,
+                },
+                &state,
+            ),
+            Err(NetPersistError::TapFdMultiQueue)
+        ));
+    }
 }
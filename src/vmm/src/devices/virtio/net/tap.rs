@@ -12,9 +12,9 @@ use std::os::raw::*;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 
 use utils::ioctl::{ioctl_with_mut_ref, ioctl_with_ref, ioctl_with_val};
-use utils::{ioctl_ioc_nr, ioctl_iow_nr};
+use utils::{ioctl_ioc_nr, ioctl_ior_nr, ioctl_iow_nr};
 
-use crate::devices::virtio::iovec::IoVecBuffer;
+use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut};
 use crate::devices::virtio::net::gen;
 #[cfg(test)]
 use crate::devices::virtio::net::test_utils::Mocks;
@@ -37,12 +37,24 @@ pub enum TapError {
     SetOffloadFlags(IoError),
     /// Error while setting size of the vnet header: {0}
     SetSizeOfVnetHdr(IoError),
+    /// Error while enabling or disabling a queue of the tap: {0}
+    SetQueue(IoError),
+    /// Error while getting the MTU of the tap: {0}
+    GetMtu(IoError),
+    /// Invalid tap file descriptor: {0}
+    InvalidFd(IoError),
+    /// The file descriptor is not a tap: {0}
+    NotATap(IoError),
+    /// The tap must be opened with the IFF_TAP, IFF_NO_PI and IFF_VNET_HDR flags, got {0:#x}
+    TapFlags(u32),
 }
 
 const TUNTAP: ::std::os::raw::c_uint = 84;
 ioctl_iow_nr!(TUNSETIFF, TUNTAP, 202, ::std::os::raw::c_int);
 ioctl_iow_nr!(TUNSETOFFLOAD, TUNTAP, 208, ::std::os::raw::c_uint);
 ioctl_iow_nr!(TUNSETVNETHDRSZ, TUNTAP, 216, ::std::os::raw::c_int);
+ioctl_iow_nr!(TUNSETQUEUE, TUNTAP, 217, ::std::os::raw::c_int);
+ioctl_ior_nr!(TUNGETIFF, TUNTAP, 210, ::std::os::raw::c_uint);
 
 /// Handle for a network tap interface.
 ///
@@ -123,6 +135,96 @@ impl Tap {
     ///
     /// * `if_name` - the name of the interface.
     pub fn open_named(if_name: &str) -> Result<Tap, TapError> {
+        Self::open(if_name, gen::IFF_TAP | gen::IFF_NO_PI | gen::IFF_VNET_HDR)
+    }
+
+    /// Open `num_queues` queues of a multi-queue TUN/TAP device given the interface name.
+    ///
+    /// The first queue creates the device if it doesn't exist yet, and the next ones are attached
+    /// to the same device.
+    /// # Arguments
+    ///
+    /// * `if_name` - the name of the interface.
+    /// * `num_queues` - the number of queues to open.
+    pub fn open_queues(if_name: &str, num_queues: usize) -> Result<Vec<Tap>, TapError> {
+        let flags = gen::IFF_TAP | gen::IFF_NO_PI | gen::IFF_VNET_HDR | gen::IFF_MULTI_QUEUE;
+        let first = Self::open(if_name, flags)?;
+        // The kernel may have picked the name of the device, e.g. if `if_name` contains `%d`.
+        let if_name = first.if_name_as_str().to_owned();
+        let mut taps = vec![first];
+        for _ in 1..num_queues {
+            taps.push(Self::open(&if_name, flags)?);
+        }
+        Ok(taps)
+    }
+
+    /// Create a TUN/TAP device from the file descriptor of an already open tap, e.g. inherited
+    /// from the parent process. The file descriptor is duplicated, and the caller keeps
+    /// ownership of `fd`.
+    ///
+    /// The tap must have been opened with the `IFF_TAP`, `IFF_NO_PI` and `IFF_VNET_HDR` flags.
+    /// # Arguments
+    ///
+    /// * `fd` - the file descriptor of the tap.
+    pub fn from_fd(fd: RawFd) -> Result<Tap, TapError> {
+        // SAFETY: `fcntl` is safe with any fd, and we check the return value.
+        let dup_fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+        if dup_fd < 0 {
+            return Err(TapError::InvalidFd(IoError::last_os_error()));
+        }
+        // SAFETY: We just checked that the fd is valid, and nothing else owns the duplicate.
+        let tuntap = unsafe { File::from_raw_fd(dup_fd) };
+
+        let ifreq = IfReqBuilder::new()
+            .execute(&tuntap, TUNGETIFF())
+            .map_err(TapError::NotATap)?;
+        // SAFETY: Using this union variant is safe since `TUNGETIFF` returns the flags.
+        let flags = unsafe { ifreq.ifr_ifru.ifru_flags };
+        let flags = u32::from(u16::from_ne_bytes(flags.to_ne_bytes()));
+        let required_flags = gen::IFF_NO_PI | gen::IFF_VNET_HDR;
+        if flags & gen::TUN_TYPE_MASK != gen::IFF_TAP || flags & required_flags != required_flags {
+            return Err(TapError::TapFlags(flags));
+        }
+
+        // The fd may have been opened in blocking mode. The mode is shared with `fd`.
+        // SAFETY: `fcntl` is safe with a valid fd, and we check the return value.
+        let status_flags = unsafe { libc::fcntl(tuntap.as_raw_fd(), libc::F_GETFL) };
+        if status_flags < 0 {
+            return Err(TapError::InvalidFd(IoError::last_os_error()));
+        }
+        // SAFETY: `fcntl` is safe with a valid fd, and we check the return value.
+        let ret = unsafe {
+            libc::fcntl(
+                tuntap.as_raw_fd(),
+                libc::F_SETFL,
+                status_flags | libc::O_NONBLOCK,
+            )
+        };
+        if ret < 0 {
+            return Err(TapError::InvalidFd(IoError::last_os_error()));
+        }
+
+        Ok(Tap {
+            tap_file: tuntap,
+            // SAFETY: Safe since only the name is accessed, and it's cloned out.
+            if_name: unsafe { ifreq.ifr_ifrn.ifrn_name },
+
+            #[cfg(test)]
+            mocks: Mocks::default(),
+        })
+    }
+
+    /// Wraps a file standing in for the tap device, e.g. the write end of a pipe.
+    #[cfg(test)]
+    pub(crate) fn from_file(file: File) -> Tap {
+        Tap {
+            tap_file: file,
+            if_name: [0; IFACE_NAME_MAX_LEN],
+            mocks: Mocks::default(),
+        }
+    }
+
+    fn open(if_name: &str, flags: u32) -> Result<Tap, TapError> {
         // SAFETY: Open calls are safe because we give a constant null-terminated
         // string and verify the result.
         let fd = unsafe {
@@ -141,7 +243,7 @@ impl Tap {
         let terminated_if_name = build_terminated_if_name(if_name)?;
         let ifreq = IfReqBuilder::new()
             .if_name(&terminated_if_name)
-            .flags(i16::try_from(gen::IFF_TAP | gen::IFF_NO_PI | gen::IFF_VNET_HDR).unwrap())
+            .flags(i16::try_from(flags).unwrap())
             .execute(&tuntap, TUNSETIFF())
             .map_err(|io_error| TapError::IfreqExecuteError(io_error, if_name.to_owned()))?;
 
@@ -185,19 +287,63 @@ impl Tap {
         Ok(())
     }
 
+    /// Enable or disable the queue of a multi-queue tap. The kernel only queues the frames sent
+    /// to the interface on its enabled queues.
+    ///
+    /// Enabling a queue which is already enabled, or disabling a disabled one, fails.
+    pub fn set_queue_enabled(&self, enabled: bool) -> Result<(), TapError> {
+        let flags = if enabled {
+            gen::IFF_ATTACH_QUEUE
+        } else {
+            gen::IFF_DETACH_QUEUE
+        };
+        IfReqBuilder::new()
+            .flags(i16::try_from(flags).unwrap())
+            .execute(&self.tap_file, TUNSETQUEUE())
+            .map_err(TapError::SetQueue)?;
+
+        Ok(())
+    }
+
+    /// Provides the MTU of the tap interface.
+    pub fn mtu(&self) -> Result<u32, TapError> {
+        // The MTU is only available through a socket, not through the tap file.
+        // SAFETY: This is safe since we check the return value.
+        let socket =
+            unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if socket < 0 {
+            return Err(TapError::GetMtu(IoError::last_os_error()));
+        }
+        // SAFETY: This is safe; nothing else will use or hold onto the raw socket fd.
+        let socket = unsafe { File::from_raw_fd(socket) };
+        let ifreq = IfReqBuilder::new()
+            .if_name(&self.if_name)
+            .execute(&socket, c_ulong::from(gen::sockios::SIOCGIFMTU))
+            .map_err(TapError::GetMtu)?;
+
+        // SAFETY: Using this union variant is safe since `SIOCGIFMTU` returns the MTU.
+        let mtu = unsafe { ifreq.ifr_ifru.ifru_mtu };
+        u32::try_from(mtu).map_err(|_| TapError::GetMtu(IoError::from_raw_os_error(libc::EINVAL)))
+    }
+
     /// Write an `IoVecBuffer` to tap
     pub(crate) fn write_iovec(&mut self, buffer: &IoVecBuffer) -> Result<usize, IoError> {
-        let iovcnt = i32::try_from(buffer.iovec_count()).unwrap();
-        let iov = buffer.as_iovec_ptr();
+        let iovecs = buffer.as_slice();
+        let iovcnt = i32::try_from(iovecs.len()).unwrap();
 
-        // SAFETY: `writev` is safe. Called with a valid tap fd, the iovec pointer and length
-        // is provide by the `IoVecBuffer` implementation and we check the return value.
-        let ret = unsafe { libc::writev(self.tap_file.as_raw_fd(), iov, iovcnt) };
+        // SAFETY: `writev` is safe. Called with a valid tap fd, the iovec array is provided by
+        // the `IoVecBuffer` implementation and we check the return value.
+        let ret = unsafe { libc::writev(self.tap_file.as_raw_fd(), iovecs.as_ptr(), iovcnt) };
         if ret == -1 {
             return Err(IoError::last_os_error());
         }
         Ok(usize::try_from(ret).unwrap())
     }
+
+    /// Read a frame from tap to an `IoVecBufferMut`
+    pub(crate) fn read_iovec(&mut self, buffer: &mut IoVecBufferMut) -> Result<usize, IoError> {
+        buffer.read_from_fd(self)
+    }
 }
 
 impl Read for Tap {
@@ -278,6 +424,55 @@ pub mod tests {
         Tap::open_named("exclusivetap").unwrap_err();
     }
 
+    #[test]
+    fn test_tap_queues() {
+        let taps = Tap::open_queues("mqtap%d", 3).unwrap();
+        assert_eq!(taps.len(), 3);
+        let if_name = taps[0].if_name_as_str();
+        assert_ne!(if_name, "mqtap%d");
+        for tap in &taps[1..] {
+            assert_eq!(tap.if_name_as_str(), if_name);
+        }
+
+        taps[1].set_queue_enabled(false).unwrap();
+        taps[1].set_queue_enabled(false).unwrap_err();
+        taps[1].set_queue_enabled(true).unwrap();
+        taps[2].set_queue_enabled(true).unwrap_err();
+
+        // A multi-queue tap can't be opened as a single-queue one.
+        Tap::open_named(if_name).unwrap_err();
+    }
+
+    #[test]
+    fn test_tap_from_fd() {
+        assert!(matches!(Tap::from_fd(-1), Err(TapError::InvalidFd(_))));
+
+        // A file which isn't a tap.
+        let file = utils::tempfile::TempFile::new().unwrap();
+        assert!(matches!(
+            Tap::from_fd(file.as_file().as_raw_fd()),
+            Err(TapError::NotATap(_))
+        ));
+
+        let tap = Tap::open_named("fdtap%d").unwrap();
+        let tap_from_fd = Tap::from_fd(tap.as_raw_fd()).unwrap();
+        assert_ne!(tap_from_fd.as_raw_fd(), tap.as_raw_fd());
+        assert_eq!(tap_from_fd.if_name_as_str(), tap.if_name_as_str());
+        // The tap can be configured through the duplicate.
+        tap_from_fd.set_vnet_hdr_size(16).unwrap();
+
+        // The caller keeps ownership of the fd.
+        drop(tap_from_fd);
+        tap.set_vnet_hdr_size(16).unwrap();
+
+        // A tap without a vnet header.
+        let tap = Tap::open("fdtap%d", gen::IFF_TAP | gen::IFF_NO_PI).unwrap();
+        assert!(matches!(
+            Tap::from_fd(tap.as_raw_fd()),
+            Err(TapError::TapFlags(flags)) if flags & gen::IFF_VNET_HDR == 0
+        ));
+    }
+
     #[test]
     fn test_set_options() {
         // This line will fail to provide an initialized FD if the test is not run as root.
@@ -300,6 +495,23 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_tap_mtu() {
+        let tap = Tap::open_named("").unwrap();
+        // Newly created taps use the default Ethernet MTU.
+        assert_eq!(tap.mtu().unwrap(), 1500);
+
+        // The interface does not exist anymore once its tap is closed.
+        let if_name = tap.if_name;
+        drop(tap);
+        let faulty_tap = Tap {
+            tap_file: unsafe { File::from_raw_fd(-2) },
+            if_name,
+            mocks: Default::default(),
+        };
+        assert!(matches!(faulty_tap.mtu(), Err(TapError::GetMtu(_))));
+    }
+
     #[test]
     fn test_raw_fd() {
         let tap = Tap::open_named("").unwrap();
@@ -323,6 +535,27 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_read_iovec() {
+        let mut tap = Tap::open_named("").unwrap();
+        enable(&tap);
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&tap));
+
+        let packet = utils::rand::rand_alphanumerics(PAYLOAD_SIZE);
+        tap_traffic_simulator.push_tx_packet(packet.as_bytes());
+
+        let mut fragment1 = [0u8; VNET_HDR_SIZE + 100];
+        let mut fragment2 = [0u8; PACKET_SIZE];
+        let mut scattered =
+            IoVecBufferMut::from(vec![fragment1.as_mut_slice(), fragment2.as_mut_slice()]);
+        assert_eq!(
+            tap.read_iovec(&mut scattered).unwrap(),
+            PAYLOAD_SIZE + VNET_HDR_SIZE
+        );
+        assert_eq!(&fragment1[VNET_HDR_SIZE..], &packet.as_bytes()[..100]);
+        assert_eq!(&fragment2[..PAYLOAD_SIZE - 100], &packet.as_bytes()[100..]);
+    }
+
     #[test]
     fn test_write() {
         let mut tap = Tap::open_named("").unwrap();
@@ -18,9 +18,9 @@ use utils::net::mac::MacAddr;
 #[cfg(test)]
 use crate::devices::virtio::net::device::vnet_hdr_len;
 use crate::devices::virtio::net::tap::{IfReqBuilder, Tap};
-use crate::devices::virtio::net::Net;
+use crate::devices::virtio::net::{Net, RX_INDEX, TX_INDEX};
 use crate::devices::virtio::queue::{Queue, QueueError};
-use crate::devices::virtio::test_utils::VirtQueue;
+use crate::devices::virtio::test_utils::{enable_write_canaries, VirtQueue};
 use crate::devices::DeviceError;
 use crate::mmds::data_store::Mmds;
 use crate::mmds::ns::MmdsNetworkStack;
@@ -53,7 +53,8 @@ pub fn default_net() -> Net {
         MmdsNetworkStack::default_ipv4_addr(),
         Arc::new(Mutex::new(Mmds::default())),
     );
-    enable(&net.tap);
+    enable(&net.pairs[0].tap);
+    enable_write_canaries();
 
     net
 }
@@ -72,7 +73,29 @@ pub fn default_net_no_mmds() -> Net {
         RateLimiter::default(),
     )
     .unwrap();
-    enable(&net.tap);
+    enable(&net.pairs[0].tap);
+    enable_write_canaries();
+
+    net
+}
+
+pub fn default_net_multi_queue(num_pairs: u16) -> Net {
+    let next_tap = NEXT_INDEX.fetch_add(1, Ordering::SeqCst);
+    let tap_device_id = format!("net-device{}", next_tap);
+
+    let guest_mac = default_guest_mac();
+
+    let net = Net::new_multi_queue(
+        tap_device_id,
+        "net-device%d",
+        num_pairs,
+        Some(guest_mac),
+        RateLimiter::default(),
+        RateLimiter::default(),
+    )
+    .unwrap();
+    enable(&net.pairs[0].tap);
+    enable_write_canaries();
 
     net
 }
@@ -80,6 +103,7 @@ pub fn default_net_no_mmds() -> Net {
 #[derive(Debug)]
 pub enum ReadTapMock {
     Failure,
+    Errno(i32),
     MockFrame(Vec<u8>),
     TapFrame,
 }
@@ -97,6 +121,10 @@ impl ReadTapMock {
 pub enum WriteTapMock {
     Failure,
     Success,
+    /// The tap can not take the frame for now.
+    WouldBlock,
+    /// The tap takes all but the last byte of the frame.
+    ShortWrite,
 }
 
 // Used to simulate tap read and write fails in tests.
@@ -250,6 +278,29 @@ pub fn create_socket() -> File {
     unsafe { File::from_raw_fd(socket) }
 }
 
+/// Creates a tap standing in for the host one, backed by the write end of a small non-blocking
+/// pipe, and returns it along with the read end of the pipe.
+#[cfg(test)]
+pub(crate) fn pipe_tap() -> (Tap, File) {
+    let mut fds = [0; 2];
+    // SAFETY: This is safe since `fds` can hold the two file descriptors and we check the
+    // return value.
+    let ret = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+    if ret < 0 {
+        panic!("Unable to create pipe");
+    }
+
+    // SAFETY: This is safe; nothing else will use or hold onto the raw pipe fds.
+    let (reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    // SAFETY: `fcntl` is safe with a valid fd, and we check the return value.
+    let ret = unsafe { libc::fcntl(writer.as_raw_fd(), libc::F_SETPIPE_SZ, 4096) };
+    if ret < 0 {
+        panic!("Unable to set the pipe size");
+    }
+
+    (Tap::from_file(writer), reader)
+}
+
 // Returns handles to virtio queues creation/activation and manipulation.
 pub fn virtqueues(mem: &GuestMemoryMmap) -> (VirtQueue, VirtQueue) {
     let rxq = VirtQueue::new(GuestAddress(0), mem, 16);
@@ -299,7 +350,7 @@ pub fn enable(tap: &Tap) {
 #[cfg(test)]
 pub(crate) fn inject_tap_tx_frame(net: &Net, len: usize) -> Vec<u8> {
     assert!(len >= vnet_hdr_len());
-    let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&net.tap));
+    let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&net.pairs[0].tap));
     let mut frame = utils::rand::rand_alphanumerics(len - vnet_hdr_len())
         .as_bytes()
         .to_vec();
@@ -339,9 +390,8 @@ pub fn set_mac(net: &mut Net, mac: MacAddr) {
 
 // Assigns "guest virtio driver" activated queues to the net device.
 pub fn assign_queues(net: &mut Net, rxq: Queue, txq: Queue) {
-    net.queues.clear();
-    net.queues.push(rxq);
-    net.queues.push(txq);
+    net.queues[RX_INDEX] = rxq;
+    net.queues[TX_INDEX] = txq;
 }
 
 #[cfg(test)]
@@ -433,12 +483,12 @@ pub mod test {
         }
 
         pub fn simulate_event(&mut self, event: NetEvent) {
-            match event {
-                NetEvent::RxQueue => self.net().process_rx_queue_event(),
-                NetEvent::RxRateLimiter => self.net().process_rx_rate_limiter_event(),
-                NetEvent::Tap => self.net().process_tap_rx_event(),
-                NetEvent::TxQueue => self.net().process_tx_queue_event(),
-                NetEvent::TxRateLimiter => self.net().process_tx_rate_limiter_event(),
+            let _ = match event {
+                NetEvent::RxQueue => self.net().process_rx_queue_event(0),
+                NetEvent::RxRateLimiter => self.net().process_rx_rate_limiter_event(0),
+                NetEvent::Tap => self.net().process_tap_rx_event(0),
+                NetEvent::TxQueue => self.net().process_tx_queue_event(0),
+                NetEvent::TxRateLimiter => self.net().process_tx_rate_limiter_event(0),
             };
         }
 
@@ -487,7 +537,10 @@ pub mod test {
 
         /// Generate a tap frame of `frame_len` and check that it is deferred
         pub fn check_rx_deferred_frame(&mut self, frame_len: usize) -> Vec<u8> {
-            self.net().tap.mocks.set_read_tap(ReadTapMock::TapFrame);
+            self.net().pairs[0]
+                .tap
+                .mocks
+                .set_read_tap(ReadTapMock::TapFrame);
             let used_idx = self.rxq.used.idx.get();
 
             // Inject frame to tap and run epoll.
@@ -498,7 +551,7 @@ pub mod test {
                 self.event_manager.run_with_timeout(100).unwrap()
             );
             // Check that the frame has been deferred.
-            assert!(self.net().rx_deferred_frame);
+            assert!(self.net().pairs[0].rx_deferred_frame);
             // Check that the descriptor chain has been discarded.
             assert_eq!(self.rxq.used.idx.get(), used_idx + 1);
             assert!(&self.net().irq_trigger.has_pending_irq(IrqType::Vring));
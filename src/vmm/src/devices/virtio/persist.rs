@@ -12,7 +12,7 @@ use serde::{Deserialize, Serialize};
 use crate::devices::virtio::device::VirtioDevice;
 use crate::devices::virtio::gen::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
 use crate::devices::virtio::mmio::MmioTransport;
-use crate::devices::virtio::queue::Queue;
+use crate::devices::virtio::queue::{Queue, QueueCounters, QueueValidationError};
 use crate::snapshot::Persist;
 use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
 
@@ -21,6 +21,10 @@ use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
 pub enum PersistError {
     /// Snapshot state contains invalid queue info.
     InvalidInput,
+    /// Snapshot state contains the virtio features {0:#x}, which are not pinned.
+    UnpinnedFeatures(u64),
+    /// Snapshot state contains an invalid queue {0}: {1}
+    InvalidQueue(usize, QueueValidationError),
 }
 
 /// Queue information saved in snapshot.
@@ -82,6 +86,8 @@ impl Persist<'_> for Queue {
             next_used: state.next_used,
             uses_notif_suppression: false,
             num_added: state.num_added,
+            pending_hwm: 0,
+            counters: QueueCounters::default(),
         })
     }
 }
@@ -101,6 +107,8 @@ pub struct VirtioDeviceState {
     pub interrupt_status: u32,
     /// Flag for activated status.
     pub activated: bool,
+    /// Features the available features were restricted to.
+    pub virtio_features_pin: Option<u64>,
 }
 
 impl VirtioDeviceState {
@@ -113,6 +121,7 @@ impl VirtioDeviceState {
             queues: device.queues().iter().map(Persist::save).collect(),
             interrupt_status: device.interrupt_status().load(Ordering::Relaxed),
             activated: device.is_activated(),
+            virtio_features_pin: device.virtio_features_pin(),
         }
     }
 
@@ -135,6 +144,13 @@ impl VirtioDeviceState {
         {
             return Err(PersistError::InvalidInput);
         }
+        // The available and negotiated features of a device are within its pin.
+        if let Some(pin) = self.virtio_features_pin {
+            let unpinned_features = (self.avail_features | self.acked_features) & !pin;
+            if unpinned_features != 0 {
+                return Err(PersistError::UnpinnedFeatures(unpinned_features));
+            }
+        }
 
         let uses_notif_suppression = (self.acked_features & 1u64 << VIRTIO_RING_F_EVENT_IDX) != 0;
         let queues: Vec<Queue> = self
@@ -150,7 +166,7 @@ impl VirtioDeviceState {
             })
             .collect();
 
-        for q in &queues {
+        for (i, q) in queues.iter().enumerate() {
             // Sanity check queue size and queue max size.
             if q.max_size != expected_queue_max_size || q.size > expected_queue_max_size {
                 return Err(PersistError::InvalidInput);
@@ -158,9 +174,11 @@ impl VirtioDeviceState {
             // Snapshot can happen at any time, including during device configuration/activation
             // when fields are only partially configured.
             //
-            // Only if the device was activated, check `q.is_valid()`.
-            if self.activated && !q.is_valid(mem) {
-                return Err(PersistError::InvalidInput);
+            // Only if the device was activated, validate the queue. Queues the driver didn't set
+            // up, like the ones of features it didn't negotiate, are left as they are.
+            if self.activated && q.ready {
+                q.validate(mem)
+                    .map_err(|err| PersistError::InvalidQueue(i, err))?;
             }
         }
         Ok(queues)
@@ -225,18 +243,34 @@ impl Persist<'_> for MmioTransport {
 
 #[cfg(test)]
 mod tests {
+    use serde::de::DeserializeOwned;
+    use utils::epoll::EventSet;
     use utils::tempfile::TempFile;
 
     use super::*;
+    use crate::devices::virtio::balloon::persist::BalloonConstructorArgs;
+    use crate::devices::virtio::balloon::Balloon;
+    use crate::devices::virtio::block::persist::BlockConstructorArgs;
     use crate::devices::virtio::block::virtio::device::FileEngineType;
     use crate::devices::virtio::block::virtio::test_utils::default_block_with_path;
     use crate::devices::virtio::block::virtio::VirtioBlock;
     use crate::devices::virtio::mmio::tests::DummyDevice;
+    use crate::devices::virtio::net::persist::NetConstructorArgs;
     use crate::devices::virtio::net::test_utils::default_net;
-    use crate::devices::virtio::net::Net;
-    use crate::devices::virtio::test_utils::default_mem;
+    use crate::devices::virtio::net::{Net, TX_INDEX};
+    use crate::devices::virtio::queue::VIRTQ_DESC_F_WRITE;
+    use crate::devices::virtio::rng::persist::EntropyConstructorArgs;
+    use crate::devices::virtio::rng::Entropy;
+    use crate::devices::virtio::test_utils::{default_mem, VirtQueue, VirtqDesc};
+    use crate::devices::virtio::vsock::persist::VsockConstructorArgs;
+    use crate::devices::virtio::vsock::test_utils::TestBackend;
     use crate::devices::virtio::vsock::{Vsock, VsockUnixBackend};
+    use crate::devices::virtio::{balloon, rng, vsock};
+    use crate::logger::IncMetric;
+    use crate::mmds::data_store::Mmds;
+    use crate::rate_limiter::RateLimiter;
     use crate::snapshot::Snapshot;
+    use crate::vstate::memory::{Address, GuestMemory};
 
     const DEFAULT_QUEUE_MAX_SIZE: u16 = 256;
     impl Default for QueueState {
@@ -276,6 +310,28 @@ mod tests {
             .build_queues_checked(&mem, 0, 0, max_size)
             .unwrap_err();
 
+        // Features within the pin.
+        let mut state = VirtioDeviceState {
+            avail_features: 0b011,
+            acked_features: 0b001,
+            virtio_features_pin: Some(0b111),
+            ..Default::default()
+        };
+        state.build_queues_checked(&mem, 0, 0, max_size).unwrap();
+        // Available features outside of the pin.
+        state.virtio_features_pin = Some(0b001);
+        assert!(matches!(
+            state.build_queues_checked(&mem, 0, 0, max_size),
+            Err(PersistError::UnpinnedFeatures(0b010))
+        ));
+        // Negotiated features outside of the pin.
+        state.avail_features = 0b001;
+        state.virtio_features_pin = Some(0b000);
+        assert!(matches!(
+            state.build_queues_checked(&mem, 0, 0, max_size),
+            Err(PersistError::UnpinnedFeatures(0b001))
+        ));
+
         // Validate queue sanity checks.
         let mut state = VirtioDeviceState::default();
         let good_q = QueueState::default();
@@ -306,7 +362,10 @@ mod tests {
             .unwrap_err();
 
         // activated && !q.is_valid()
-        let bad_q = QueueState::default();
+        let bad_q = QueueState {
+            ready: true,
+            ..Default::default()
+        };
         state.queues = vec![bad_q];
         state.activated = true;
         state
@@ -314,6 +373,143 @@ mod tests {
             .unwrap_err();
     }
 
+    #[test]
+    fn test_restore_invalid_queue() {
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let good_q = vq.create_queue().save();
+        let restore = |queue: QueueState| {
+            let state = VirtioDeviceState {
+                queues: vec![queue],
+                activated: true,
+                ..Default::default()
+            };
+            match state.build_queues_checked(&mem, 0, 1, 16) {
+                Ok(_) => Ok(()),
+                Err(PersistError::InvalidQueue(0, err)) => Err(err),
+                Err(err) => panic!("unexpected error: {err}"),
+            }
+        };
+        restore(good_q.clone()).unwrap();
+
+        // Queues not set up by the driver aren't validated.
+        let unused_q = QueueState {
+            ready: false,
+            size: 12,
+            desc_table: 1,
+            ..good_q.clone()
+        };
+        restore(unused_q).unwrap();
+
+        // Size not a power of two.
+        let bad_q = QueueState {
+            size: 12,
+            ..good_q.clone()
+        };
+        assert_eq!(
+            restore(bad_q),
+            Err(QueueValidationError::InvalidSize(12, 16))
+        );
+
+        // Misaligned rings.
+        let bad_q = QueueState {
+            desc_table: good_q.desc_table + 8,
+            ..good_q.clone()
+        };
+        assert_eq!(
+            restore(bad_q),
+            Err(QueueValidationError::Misaligned(
+                "descriptor table",
+                good_q.desc_table + 8,
+                16
+            ))
+        );
+        let bad_q = QueueState {
+            avail_ring: good_q.avail_ring + 1,
+            ..good_q.clone()
+        };
+        assert_eq!(
+            restore(bad_q),
+            Err(QueueValidationError::Misaligned(
+                "available ring",
+                good_q.avail_ring + 1,
+                2
+            ))
+        );
+        let bad_q = QueueState {
+            used_ring: good_q.used_ring + 2,
+            ..good_q.clone()
+        };
+        assert_eq!(
+            restore(bad_q),
+            Err(QueueValidationError::Misaligned(
+                "used ring",
+                good_q.used_ring + 2,
+                4
+            ))
+        );
+
+        // Rings out of guest memory.
+        let mem_end = mem.last_addr().raw_value() + 1;
+        let bad_q = QueueState {
+            used_ring: mem_end - 4,
+            ..good_q.clone()
+        };
+        assert_eq!(
+            restore(bad_q),
+            Err(QueueValidationError::OutOfBounds(
+                "used ring",
+                mem_end - 4,
+                6 + 8 * 16
+            ))
+        );
+
+        // Overlapping rings.
+        let bad_q = QueueState {
+            avail_ring: good_q.desc_table + 0x80,
+            ..good_q.clone()
+        };
+        assert_eq!(
+            restore(bad_q),
+            Err(QueueValidationError::Overlap(
+                "descriptor table",
+                "available ring"
+            ))
+        );
+        let bad_q = QueueState {
+            used_ring: good_q.avail_ring,
+            ..good_q.clone()
+        };
+        assert_eq!(
+            restore(bad_q),
+            Err(QueueValidationError::Overlap("available ring", "used ring"))
+        );
+
+        // More chains made available than the queue size.
+        vq.avail.idx.set(17);
+        assert_eq!(
+            restore(good_q.clone()),
+            Err(QueueValidationError::AvailIndex(17, 0))
+        );
+        vq.avail.idx.set(0);
+
+        // Used ring index not matching the device state.
+        vq.used.idx.set(1);
+        assert_eq!(
+            restore(good_q.clone()),
+            Err(QueueValidationError::UsedIndex(1, 0))
+        );
+        vq.used.idx.set(0);
+
+        // More chains in flight than the queue size.
+        vq.avail.idx.set(17);
+        let bad_q = QueueState {
+            next_avail: Wrapping(17),
+            ..good_q.clone()
+        };
+        assert_eq!(restore(bad_q), Err(QueueValidationError::InFlight(17, 16)));
+    }
+
     #[test]
     fn test_queue_persistence() {
         let queue = Queue::new(128);
@@ -439,4 +635,221 @@ mod tests {
         let (mmio_transport, mem, vsock) = default_vsock();
         generic_mmiotransport_persistence_test(mmio_transport, mem, vsock);
     }
+
+    /// Behavior of a device which a snapshot must preserve.
+    #[derive(Debug, PartialEq, Eq)]
+    struct DeviceFingerprint {
+        device_type: u32,
+        avail_features: u64,
+        acked_features: u64,
+        activated: bool,
+        // Next available and used indices of each queue.
+        queue_indices: Vec<(u16, u16)>,
+        config_space: Vec<u8>,
+    }
+
+    impl DeviceFingerprint {
+        fn new(device: &impl VirtioDevice, config_len: usize) -> Self {
+            let mut config_space = vec![0; config_len];
+            device.read_config(0, &mut config_space);
+            DeviceFingerprint {
+                device_type: device.device_type(),
+                avail_features: device.avail_features(),
+                acked_features: device.acked_features(),
+                activated: device.is_activated(),
+                queue_indices: device
+                    .queues()
+                    .iter()
+                    .map(|queue| (queue.next_avail.0, queue.next_used.0))
+                    .collect(),
+                config_space,
+            }
+        }
+    }
+
+    /// Requests the guest sends to a device under test, one descriptor at a time.
+    struct Traffic<D> {
+        /// Queue the requests are sent on.
+        queue: usize,
+        /// Length and flags of the request descriptors.
+        len: u32,
+        flags: u16,
+        /// Has the device process the requests available on its queues.
+        process: fn(&mut D),
+        /// Metric the device increments once for each request.
+        metric: fn(&D) -> u64,
+    }
+
+    /// Sends one request to `device`, and returns the resulting increment of the traffic metric.
+    fn send_request<D>(
+        device: &mut D,
+        traffic: &Traffic<D>,
+        vq: &VirtQueue,
+        data_addr: u64,
+    ) -> u64 {
+        let avail_idx = vq.avail.idx.get();
+        let desc_index = avail_idx % vq.size();
+        vq.dtable[desc_index as usize].set(data_addr, traffic.len, traffic.flags, 0);
+        vq.avail.ring[desc_index as usize].set(desc_index);
+        vq.avail.idx.set(avail_idx.wrapping_add(1));
+
+        let before = (traffic.metric)(device);
+        (traffic.process)(device);
+        (traffic.metric)(device) - before
+    }
+
+    /// Checks that `device` behaves the same after being snapshotted and restored.
+    ///
+    /// The device is activated and processes a few requests, then its state is saved and restored
+    /// into a new instance, built from the arguments returned by `constructor_args`. The restored
+    /// device must have the same features, queue indices and config space (the first `config_len`
+    /// bytes of it), and must then process one more request just like the original device would.
+    fn check_persist_conformance<D>(
+        mut device: D,
+        config_len: usize,
+        traffic: Traffic<D>,
+        constructor_args: impl FnOnce(GuestMemoryMmap) -> D::ConstructorArgs,
+    ) where
+        D: VirtioDevice + Persist<'static>,
+        D::State: Serialize + DeserializeOwned + std::fmt::Debug,
+        D::Error: std::fmt::Debug,
+    {
+        const QUEUE_SIZE: u16 = 16;
+        const REQUESTS_BEFORE_SNAPSHOT: u16 = 3;
+
+        // Emulate the driver: set up the queues in guest memory, negotiate the features and
+        // activate the device.
+        let mem = default_mem();
+        let mut next_addr = GuestAddress(0);
+        let virtqueues = device
+            .queues()
+            .iter()
+            .map(|_| {
+                let vq = VirtQueue::new(next_addr, &mem, QUEUE_SIZE);
+                next_addr = vq.end().unchecked_align_up(VirtqDesc::ALIGNMENT);
+                vq
+            })
+            .collect::<Vec<_>>();
+        let data_addr = next_addr.0;
+        for (queue, vq) in device.queues_mut().iter_mut().zip(&virtqueues) {
+            let max_size = queue.max_size;
+            *queue = vq.create_queue();
+            queue.max_size = max_size;
+        }
+        device.set_acked_features(device.avail_features());
+        device.activate(mem.clone()).unwrap();
+
+        let vq = &virtqueues[traffic.queue];
+        for _ in 0..REQUESTS_BEFORE_SNAPSHOT {
+            assert_eq!(send_request(&mut device, &traffic, vq, data_addr), 1);
+        }
+        assert_eq!(vq.used.idx.get(), REQUESTS_BEFORE_SNAPSHOT);
+
+        let fingerprint = DeviceFingerprint::new(&device, config_len);
+        let mut buf = vec![0; 4096];
+        Snapshot::serialize(&mut buf.as_mut_slice(), &device.save()).unwrap();
+        // Some devices hold host resources (e.g. a TAP interface) which the restored device needs.
+        drop(device);
+
+        let mut restored = D::restore(
+            constructor_args(mem.clone()),
+            &Snapshot::deserialize(&mut buf.as_slice()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(DeviceFingerprint::new(&restored, config_len), fingerprint);
+
+        // The restored device picks up the queue where the original device left it.
+        assert_eq!(send_request(&mut restored, &traffic, vq, data_addr), 1);
+        assert_eq!(vq.used.idx.get(), REQUESTS_BEFORE_SNAPSHOT + 1);
+        let mut expected = fingerprint;
+        expected.queue_indices[traffic.queue].0 += 1;
+        expected.queue_indices[traffic.queue].1 += 1;
+        assert_eq!(DeviceFingerprint::new(&restored, config_len), expected);
+    }
+
+    #[test]
+    fn test_block_persist_conformance() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let block = default_block_with_path(
+            f.as_path().to_str().unwrap().to_string(),
+            FileEngineType::default(),
+        );
+        // A lone request header is a malformed request, which is completed right away.
+        let traffic = Traffic {
+            queue: 0,
+            len: 16,
+            flags: 0,
+            process: VirtioBlock::process_virtio_queues,
+            metric: |block| block.metrics.execute_fails.count(),
+        };
+        // The config space holds the capacity of the disk.
+        check_persist_conformance(block, 8, traffic, |mem| BlockConstructorArgs { mem });
+    }
+
+    #[test]
+    fn test_net_persist_conformance() {
+        // Frames can not be sent from write-only buffers, so the requests are dropped.
+        let traffic = Traffic {
+            queue: TX_INDEX,
+            len: 16,
+            flags: VIRTQ_DESC_F_WRITE,
+            process: Net::process_virtio_queues,
+            metric: |net| net.metrics.tx_fails.count(),
+        };
+        // The config space holds the MAC address of the guest.
+        check_persist_conformance(default_net(), 6, traffic, |mem| NetConstructorArgs {
+            mem,
+            mmds: Some(Arc::new(Mutex::new(Mmds::default()))),
+        });
+    }
+
+    #[test]
+    fn test_vsock_persist_conformance() {
+        let vsock = Vsock::new(52, TestBackend::new()).unwrap();
+        // Packets can not be read from write-only buffers, so the requests are dropped.
+        let traffic = Traffic {
+            queue: 1,
+            len: 16,
+            flags: VIRTQ_DESC_F_WRITE,
+            process: |vsock: &mut Vsock<TestBackend>| {
+                vsock.queue_events()[1].write(1).unwrap();
+                vsock.handle_txq_event(EventSet::IN).unwrap();
+            },
+            metric: |_| vsock::metrics::METRICS.tx_queue_event_count.count(),
+        };
+        // The config space holds the CID of the guest.
+        check_persist_conformance(vsock, 8, traffic, |mem| VsockConstructorArgs {
+            mem,
+            backend: TestBackend::new(),
+        });
+    }
+
+    #[test]
+    fn test_balloon_persist_conformance() {
+        let balloon = Balloon::new(0, false, 0, false).unwrap();
+        // Requests which are not a whole number of page frame numbers are skipped.
+        let traffic = Traffic {
+            queue: 0,
+            len: 3,
+            flags: 0,
+            process: Balloon::process_virtio_queues,
+            metric: |_| balloon::metrics::METRICS.inflate_count.count(),
+        };
+        // The config space holds the target and actual sizes of the balloon.
+        check_persist_conformance(balloon, 8, traffic, |mem| BalloonConstructorArgs { mem });
+    }
+
+    #[test]
+    fn test_entropy_persist_conformance() {
+        let entropy = Entropy::new(RateLimiter::default()).unwrap();
+        let traffic = Traffic {
+            queue: 0,
+            len: 16,
+            flags: VIRTQ_DESC_F_WRITE,
+            process: Entropy::process_virtio_queues,
+            metric: |_| rng::metrics::METRICS.entropy_event_count.count(),
+        };
+        check_persist_conformance(entropy, 0, traffic, EntropyConstructorArgs::new);
+    }
 }
@@ -9,6 +9,9 @@ use std::cmp::min;
 use std::num::Wrapping;
 use std::sync::atomic::{fence, Ordering};
 
+use smallvec::SmallVec;
+
+use crate::devices::virtio::queue_metrics::{QueueDepthSample, QueueMetricsPerDevice};
 use crate::logger::error;
 use crate::vstate::memory::{
     Address, ByteValued, Bytes, GuestAddress, GuestMemory, GuestMemoryMmap,
@@ -16,6 +19,9 @@ use crate::vstate::memory::{
 
 pub(super) const VIRTQ_DESC_F_NEXT: u16 = 0x1;
 pub(super) const VIRTQ_DESC_F_WRITE: u16 = 0x2;
+pub(super) const VIRTQ_DESC_F_INDIRECT: u16 = 0x4;
+
+pub(super) const VIRTQ_USED_F_NO_NOTIFY: u16 = 0x1;
 
 /// Max size of virtio queues offered by firecracker's virtio devices.
 pub(super) const FIRECRACKER_MAX_QUEUE_SIZE: u16 = 256;
@@ -36,6 +42,64 @@ pub enum QueueError {
     UsedRing(#[from] vm_memory::GuestMemoryError),
 }
 
+/// Errors found while walking a descriptor chain.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum ChainError {
+    /// Descriptor {0} appears twice in the chain
+    Cycle(u16),
+    /// Descriptor {0} of the chain is invalid
+    InvalidDescriptor(u16),
+    /// Device-readable descriptor {0} follows a device-writable one
+    ReadableAfterWritable(u16),
+}
+
+/// Inconsistencies found in the state of a queue, e.g. when restoring it from a snapshot.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum QueueValidationError {
+    /// Queue is not ready
+    NotReady,
+    /// Queue size {0} is not a power of two no larger than the maximum size {1}
+    InvalidSize(u16, u16),
+    /// The {0} at {1:#x} is not aligned to {2} bytes
+    Misaligned(&'static str, u64, u64),
+    /// The {0} at {1:#x} of {2} bytes is out of guest memory
+    OutOfBounds(&'static str, u64, usize),
+    /// The {0} overlaps the {1}
+    Overlap(&'static str, &'static str),
+    /// The available ring index {0} is more than the queue size ahead of the next index {1}
+    AvailIndex(u16, u16),
+    /// The used ring index {0} differs from the next used index {1}
+    UsedIndex(u16, u16),
+    /// {0} descriptor chains are in flight, more than the queue size {1}
+    InFlight(u16, u16),
+}
+
+/// Guest memory ranges of the descriptor table and of the rings of a queue, as start address and
+/// length in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueRings {
+    /// Descriptor table.
+    pub desc_table: (GuestAddress, usize),
+    /// Available ring, including the `used_event` field.
+    pub avail_ring: (GuestAddress, usize),
+    /// Used ring, including the `avail_event` field.
+    pub used_ring: (GuestAddress, usize),
+}
+
+impl QueueRings {
+    /// Whether the `len` bytes of guest memory at `addr` overlap the descriptor table or one of
+    /// the rings.
+    pub fn overlaps(&self, addr: GuestAddress, len: usize) -> bool {
+        let end = addr.raw_value().saturating_add(len as u64);
+        [self.desc_table, self.avail_ring, self.used_ring]
+            .into_iter()
+            .any(|(start, size)| {
+                let ring_end = start.raw_value().saturating_add(size as u64);
+                addr.raw_value() < ring_end && start.raw_value() < end
+            })
+    }
+}
+
 /// A virtio descriptor constraints with C representative.
 #[repr(C)]
 #[derive(Default, Clone, Copy)]
@@ -50,16 +114,19 @@ struct Descriptor {
 unsafe impl ByteValued for Descriptor {}
 
 /// A virtio descriptor chain.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DescriptorChain<'a, M: GuestMemory = GuestMemoryMmap> {
     desc_table: GuestAddress,
     queue_size: u16,
-    ttl: u16, // used to prevent infinite chain cycles
+    ttl: u16,         // used to prevent infinite chain cycles
+    table_index: u16, // index into the table the descriptor was read from
+    indirect: bool,   // whether the descriptor was read from an indirect table
 
     /// Reference to guest memory
     pub mem: &'a M,
 
-    /// Index into the descriptor table
+    /// Index into the descriptor table of the queue. The descriptors of an indirect table all
+    /// have the index of the descriptor referring to the table.
     pub index: u16,
 
     /// Guest physical address of device specific data
@@ -106,6 +173,8 @@ impl<'a, M: GuestMemory> DescriptorChain<'a, M> {
             desc_table,
             queue_size,
             ttl: queue_size,
+            table_index: index,
+            indirect: false,
             index,
             addr: GuestAddress(desc.addr),
             len: desc.len,
@@ -137,24 +206,172 @@ impl<'a, M: GuestMemory> DescriptorChain<'a, M> {
         self.flags & VIRTQ_DESC_F_WRITE != 0
     }
 
+    /// If the driver designated this as a descriptor referring to an indirect table of
+    /// descriptors, rather than to a buffer.
+    pub fn is_indirect(&self) -> bool {
+        self.flags & VIRTQ_DESC_F_INDIRECT != 0
+    }
+
     /// Gets the next descriptor in this descriptor chain, if there is one.
     ///
     /// Note that this is distinct from the next descriptor chain returned by `AvailIter`, which is
     /// the head of the next _available_ descriptor chain.
     pub fn next_descriptor(&self) -> Option<Self> {
         if self.has_next() {
-            DescriptorChain::checked_new(self.mem, self.desc_table, self.queue_size, self.next).map(
-                |mut c| {
-                    c.ttl = self.ttl - 1;
-                    c
-                },
-            )
+            self.sibling(self.next).map(|mut c| {
+                c.ttl = self.ttl - 1;
+                c
+            })
         } else {
             None
         }
     }
+
+    // Reads the descriptor at `index` in the table this descriptor was read from.
+    fn sibling(&self, index: u16) -> Option<Self> {
+        let mut desc = Self::checked_new(self.mem, self.desc_table, self.queue_size, index)?;
+        if self.indirect {
+            // Indirect tables can't refer to other indirect tables.
+            if desc.is_indirect() {
+                return None;
+            }
+            desc.indirect = true;
+            desc.index = self.index;
+        }
+        Some(desc)
+    }
+
+    // Returns the first descriptor of the indirect table this head descriptor refers to, which
+    // stands for the chain in place of the head. Fails if the table is invalid.
+    fn resolve_indirect(self) -> Option<Self> {
+        // The table can't be chained to other descriptors, and holds at most as many descriptors
+        // as a chain may, which is the queue size.
+        if self.flags & VIRTQ_DESC_F_NEXT != 0 || self.len == 0 || self.len % 16 != 0 {
+            return None;
+        }
+        let table_size = u16::try_from(self.len / 16).ok()?;
+        if table_size > self.queue_size {
+            return None;
+        }
+        self.mem
+            .checked_offset(self.addr, usize::try_from(self.len - 1).ok()?)?;
+
+        let mut desc = Self::checked_new(self.mem, self.addr, table_size, 0)?;
+        if desc.is_indirect() {
+            return None;
+        }
+        desc.indirect = true;
+        desc.index = self.index;
+        Some(desc)
+    }
+}
+
+impl<'a> DescriptorChain<'a> {
+    /// Returns an iterator over the descriptors of this chain, failing on the first descriptor
+    /// visited twice or which is invalid, where the infallible iterator silently ends the chain.
+    pub fn checked_iter(self) -> CheckedDescriptorIterator<'a> {
+        CheckedDescriptorIterator {
+            next: Some(Ok(self)),
+            visited: SmallVec::new(),
+        }
+    }
 }
 
+/// Iterator over the descriptors of a chain, ending after the first descriptor visited twice.
+///
+/// Prefer this to `DescriptorIterator`, which cuts chains at `queue_size` descriptors and
+/// cannot tell a cyclic chain from a long one.
+#[derive(Debug)]
+pub struct CheckedDescriptorIterator<'a> {
+    next: Option<Result<DescriptorChain<'a>, ChainError>>,
+    // Bitset of the indices of the descriptors visited so far
+    visited: SmallVec<[u64; 4]>,
+}
+
+impl CheckedDescriptorIterator<'_> {
+    // Marks `index` as visited, returning whether it already was.
+    fn visit(&mut self, index: u16) -> bool {
+        let (word, bit) = (usize::from(index / 64), index % 64);
+        if self.visited.len() <= word {
+            self.visited.resize(word + 1, 0);
+        }
+        let visited = self.visited[word] & (1 << bit) != 0;
+        self.visited[word] |= 1 << bit;
+        visited
+    }
+}
+
+impl<'a> Iterator for CheckedDescriptorIterator<'a> {
+    type Item = Result<DescriptorChain<'a>, ChainError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let desc = match self.next.take()? {
+            Ok(desc) => desc,
+            Err(err) => {
+                QueueMetricsPerDevice::invalid_chain();
+                return Some(Err(err));
+            }
+        };
+        if self.visit(desc.table_index) {
+            QueueMetricsPerDevice::invalid_chain();
+            return Some(Err(ChainError::Cycle(desc.table_index)));
+        }
+        // Unlike `next_descriptor`, ignore the ttl: a chain longer than its table necessarily
+        // visits a descriptor twice.
+        if desc.flags & VIRTQ_DESC_F_NEXT != 0 {
+            self.next = Some(
+                desc.sibling(desc.next)
+                    .ok_or(ChainError::InvalidDescriptor(desc.next)),
+            );
+        }
+        Some(Ok(desc))
+    }
+}
+
+/// Shape of a descriptor chain: its device-readable descriptors, followed by its device-writable
+/// ones.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChainLayout {
+    /// Number of device-readable descriptors, which is also the position of the first
+    /// device-writable one in the chain.
+    pub readable_count: u16,
+    /// Number of device-writable descriptors.
+    pub writable_count: u16,
+    /// Total length of the device-readable descriptors.
+    pub readable_len: u64,
+    /// Total length of the device-writable descriptors.
+    pub writable_len: u64,
+}
+
+impl ChainLayout {
+    /// Walks the chain starting at `head` once, failing if the chain is invalid or if a
+    /// device-readable descriptor follows a device-writable one.
+    pub fn scan(head: &DescriptorChain) -> Result<Self, ChainError> {
+        let mut layout = ChainLayout::default();
+        for desc in head.clone().checked_iter() {
+            let desc = desc?;
+            if desc.is_write_only() {
+                layout.writable_count += 1;
+                layout.writable_len += u64::from(desc.len);
+            } else if layout.writable_count == 0 {
+                layout.readable_count += 1;
+                layout.readable_len += u64::from(desc.len);
+            } else {
+                return Err(ChainError::ReadableAfterWritable(desc.table_index));
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Number of descriptors in the chain.
+    pub fn desc_count(&self) -> u32 {
+        u32::from(self.readable_count) + u32::from(self.writable_count)
+    }
+}
+
+/// Infallible iterator over the descriptors of a chain, ending it after `queue_size` descriptors
+/// or on an invalid descriptor, which only shows in the `invalid_chains` queue metric. New code
+/// should use `DescriptorChain::checked_iter` instead.
 #[derive(Debug)]
 pub struct DescriptorIterator<'a>(Option<DescriptorChain<'a>>);
 
@@ -173,11 +390,32 @@ impl<'a> Iterator for DescriptorIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         self.0.take().map(|desc| {
             self.0 = desc.next_descriptor();
+            if self.0.is_none() && desc.flags & VIRTQ_DESC_F_NEXT != 0 {
+                // The chain was cut, at the queue size or on an invalid descriptor.
+                QueueMetricsPerDevice::invalid_chain();
+            }
             desc
         })
     }
 }
 
+/// Counts of the operations done on a queue, since the device last took them to report them in
+/// its metrics.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueueCounters {
+    /// Descriptor chains popped from the available ring, less the ones given back.
+    pub pops: u64,
+    /// Pops which found the available ring empty.
+    pub empty_pops: u64,
+    /// Entries added to the used ring.
+    pub used: u64,
+    /// Checks which found that the driver needs to be notified of the used entries.
+    pub notified: u64,
+    /// Checks which found that the driver doesn't need to be notified of the used entries, the
+    /// notification being suppressed through `used_event`.
+    pub suppressed: u64,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 /// A virtio queue's parameters.
 pub struct Queue {
@@ -206,6 +444,12 @@ pub struct Queue {
     pub(crate) uses_notif_suppression: bool,
     /// The number of added used buffers since last guest kick
     pub(crate) num_added: Wrapping<u16>,
+
+    /// The highest number of pending descriptor chains seen since the last depth sample
+    pub(crate) pending_hwm: u16,
+
+    /// The operations done on the queue since the device last took the counters
+    pub(crate) counters: QueueCounters,
 }
 
 #[allow(clippy::len_without_is_empty)]
@@ -223,6 +467,8 @@ impl Queue {
             next_used: Wrapping(0),
             uses_notif_suppression: false,
             num_added: Wrapping(0),
+            pending_hwm: 0,
+            counters: QueueCounters::default(),
         }
     }
 
@@ -237,15 +483,23 @@ impl Queue {
         min(self.size, self.max_size)
     }
 
+    /// Returns the guest memory ranges of the descriptor table and of the rings of the queue.
+    pub fn rings(&self) -> QueueRings {
+        let queue_size = usize::from(self.actual_size());
+        QueueRings {
+            desc_table: (self.desc_table, 16 * queue_size),
+            avail_ring: (self.avail_ring, 6 + 2 * queue_size),
+            used_ring: (self.used_ring, 6 + 8 * queue_size),
+        }
+    }
+
     /// Validates the queue's in-memory layout is correct.
     pub fn is_layout_valid<M: GuestMemory>(&self, mem: &M) -> bool {
-        let queue_size = usize::from(self.actual_size());
-        let desc_table = self.desc_table;
-        let desc_table_size = 16 * queue_size;
-        let avail_ring = self.avail_ring;
-        let avail_ring_size = 6 + 2 * queue_size;
-        let used_ring = self.used_ring;
-        let used_ring_size = 6 + 8 * queue_size;
+        let QueueRings {
+            desc_table: (desc_table, desc_table_size),
+            avail_ring: (avail_ring, avail_ring_size),
+            used_ring: (used_ring, used_ring_size),
+        } = self.rings();
 
         if !self.ready {
             error!("attempt to use virtio queue that is not marked ready");
@@ -306,6 +560,76 @@ impl Queue {
         }
     }
 
+    /// Checks the state of the queue against the virtio spec and guest memory, reporting the first
+    /// inconsistency found.
+    ///
+    /// Unlike [`Queue::is_valid`], this doesn't trust the queue indexes nor the placement of the
+    /// rings relative to each other, which only matters for state coming from outside the VMM,
+    /// such as a snapshot.
+    pub fn validate<M: GuestMemory>(&self, mem: &M) -> Result<(), QueueValidationError> {
+        if !self.ready {
+            return Err(QueueValidationError::NotReady);
+        }
+        if self.size > self.max_size || !self.size.is_power_of_two() {
+            return Err(QueueValidationError::InvalidSize(self.size, self.max_size));
+        }
+
+        let rings = self.rings();
+        let areas = [
+            ("descriptor table", rings.desc_table, 16),
+            ("available ring", rings.avail_ring, 2),
+            ("used ring", rings.used_ring, 4),
+        ];
+        for (name, (addr, len), align) in areas {
+            if addr.raw_value() % align != 0 {
+                return Err(QueueValidationError::Misaligned(
+                    name,
+                    addr.raw_value(),
+                    align,
+                ));
+            }
+            if mem.get_slice(addr, len).is_err() {
+                return Err(QueueValidationError::OutOfBounds(
+                    name,
+                    addr.raw_value(),
+                    len,
+                ));
+            }
+        }
+        for (i, (name, (addr, len), _)) in areas.iter().enumerate() {
+            for (other_name, (other_addr, other_len), _) in &areas[i + 1..] {
+                let end = addr.raw_value().saturating_add(*len as u64);
+                let other_end = other_addr.raw_value().saturating_add(*other_len as u64);
+                if addr.raw_value() < other_end && other_addr.raw_value() < end {
+                    return Err(QueueValidationError::Overlap(name, other_name));
+                }
+            }
+        }
+
+        let avail_idx = self.avail_idx(mem);
+        if (avail_idx - self.next_avail).0 > self.actual_size() {
+            return Err(QueueValidationError::AvailIndex(
+                avail_idx.0,
+                self.next_avail.0,
+            ));
+        }
+        // The device publishes the used ring index along with the used buffers, so it always
+        // matches `next_used` outside of `add_used_batch`.
+        let used_idx: u16 = mem.read_obj(self.used_ring.unchecked_add(2)).unwrap();
+        if used_idx != self.next_used.0 {
+            return Err(QueueValidationError::UsedIndex(used_idx, self.next_used.0));
+        }
+        // The driver can't make more buffers available than there are entries in the ring.
+        let in_flight = (self.next_avail - self.next_used).0;
+        if in_flight > self.actual_size() {
+            return Err(QueueValidationError::InFlight(
+                in_flight,
+                self.actual_size(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Returns the number of yet-to-be-popped descriptor chains in the avail ring.
     pub fn len<M: GuestMemory>(&self, mem: &M) -> u16 {
         debug_assert!(self.is_layout_valid(mem));
@@ -337,8 +661,10 @@ impl Queue {
         }
 
         if len == 0 {
+            self.counters.empty_pops += 1;
             return None;
         }
+        self.pending_hwm = self.pending_hwm.max(len);
 
         self.do_pop_unchecked(mem)
     }
@@ -349,17 +675,60 @@ impl Queue {
         &mut self,
         mem: &'b M,
     ) -> Option<DescriptorChain<'b, M>> {
-        if !self.uses_notif_suppression {
-            return self.pop(mem);
-        }
-
-        if self.try_enable_notification(mem) {
+        if self.enable_notification(mem) {
+            self.counters.empty_pops += 1;
             return None;
         }
 
         self.do_pop_unchecked(mem)
     }
 
+    /// Pops up to `max` available descriptor chains, appending them to `out` in ring order, and
+    /// returns how many were popped.
+    ///
+    /// The avail index is read once for the whole batch, and a single fence orders the reads of
+    /// the chains after it, where popping them one by one pays for both on each chain. As with
+    /// `pop`, the batch ends early on an invalid chain head. If no chain is available, this
+    /// enables the notifications like `pop_or_enable_notification` does.
+    ///
+    /// A caller unable to consume the last `n` chains of the batch gives them back by calling
+    /// `undo_pop` `n` times, after which they are the next ones popped.
+    pub fn pop_batch<'b, M: GuestMemory>(
+        &mut self,
+        mem: &'b M,
+        max: usize,
+        out: &mut Vec<DescriptorChain<'b, M>>,
+    ) -> usize {
+        debug_assert!(self.is_layout_valid(mem));
+
+        let mut len = self.len(mem);
+        if len == 0 {
+            if self.enable_notification(mem) {
+                self.counters.empty_pops += 1;
+                return 0;
+            }
+            len = self.len(mem);
+        }
+        // See `pop`.
+        if len > self.actual_size() {
+            panic!("The number of available virtio descriptors is greater than queue size!");
+        }
+        self.pending_hwm = self.pending_hwm.max(len);
+
+        // This fence ensures the reads of all the chains of the batch see the updated driver
+        // writes.
+        fence(Ordering::Acquire);
+
+        let popped = out.len();
+        for _ in 0..usize::from(len).min(max) {
+            match self.pop_head(mem) {
+                Some(head) => out.push(head),
+                None => break,
+            }
+        }
+        out.len() - popped
+    }
+
     /// Pop the first available descriptor chain from the avail ring.
     ///
     /// # Important
@@ -372,6 +741,12 @@ impl Queue {
         // This fence ensures all subsequent reads see the updated driver writes.
         fence(Ordering::Acquire);
 
+        self.pop_head(mem)
+    }
+
+    // Pops the next available chain head, relying on the caller for the fence ordering the read
+    // of the avail ring after the one of the avail index.
+    fn pop_head<'b, M: GuestMemory>(&mut self, mem: &'b M) -> Option<DescriptorChain<'b, M>> {
         // We'll need to find the first available descriptor, that we haven't yet popped.
         // In a naive notation, that would be:
         // `descriptor_table[avail_ring[next_avail]]`.
@@ -395,27 +770,38 @@ impl Queue {
         // We are now looking for the offset of `ring[self.next_avail % self.actual_size()]`.
         // `ring` starts after `flags` and `idx` (4 bytes into `struct virtq_avail`), and holds
         // 2-byte items, so the offset will be:
-        let index_offset = 4 + 2 * (self.next_avail.0 % self.actual_size());
+        let index_offset = 4 + 2 * u64::from(self.next_avail.0 % self.actual_size());
 
         // `self.is_valid()` already performed all the bound checks on the descriptor table
         // and virtq rings, so it's safe to unwrap guest memory reads and to use unchecked
         // offsets.
         let desc_index: u16 = mem
-            .read_obj(self.avail_ring.unchecked_add(u64::from(index_offset)))
+            .read_obj(self.avail_ring.unchecked_add(index_offset))
             .unwrap();
 
-        DescriptorChain::checked_new(mem, self.desc_table, self.actual_size(), desc_index).map(
-            |dc| {
+        // The chain of a head referring to an indirect table is the chain of the table.
+        DescriptorChain::checked_new(mem, self.desc_table, self.actual_size(), desc_index)
+            .and_then(|dc| {
+                if dc.is_indirect() {
+                    dc.resolve_indirect()
+                } else {
+                    Some(dc)
+                }
+            })
+            .map(|dc| {
                 self.next_avail += Wrapping(1);
+                self.counters.pops += 1;
                 dc
-            },
-        )
+            })
     }
 
     /// Undo the effects of the last `self.pop()` call.
     /// The caller can use this, if it was unable to consume the last popped descriptor chain.
+    /// Each call gives back one more chain, so that it also undoes the pop of the last chains of a
+    /// `self.pop_batch()` call.
     pub fn undo_pop(&mut self) {
         self.next_avail -= Wrapping(1);
+        self.counters.pops = self.counters.pops.saturating_sub(1);
     }
 
     /// Puts an available descriptor head into the used ring for use by the guest.
@@ -427,6 +813,39 @@ impl Queue {
     ) -> Result<(), QueueError> {
         debug_assert!(self.is_layout_valid(mem));
 
+        self.write_used_elem(mem, desc_index, len)?;
+        self.publish_used(mem)
+    }
+
+    /// Puts a batch of available descriptor heads, with the number of bytes written to each of
+    /// them, into the used ring for use by the guest. The used index is only updated once, after
+    /// all the elements are written.
+    ///
+    /// On error, the elements written before the failing one are still handed to the guest.
+    pub fn add_used_batch<M: GuestMemory>(
+        &mut self,
+        mem: &M,
+        entries: &[(u16, u32)],
+    ) -> Result<(), QueueError> {
+        debug_assert!(self.is_layout_valid(mem));
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let result = entries
+            .iter()
+            .try_for_each(|&(desc_index, len)| self.write_used_elem(mem, desc_index, len));
+        self.publish_used(mem)?;
+        result
+    }
+
+    // Writes the next element of the used ring, without making it visible to the guest.
+    fn write_used_elem<M: GuestMemory>(
+        &mut self,
+        mem: &M,
+        desc_index: u16,
+        len: u32,
+    ) -> Result<(), QueueError> {
         if desc_index >= self.actual_size() {
             error!(
                 "attempted to add out of bounds descriptor to used ring: {}",
@@ -435,9 +854,11 @@ impl Queue {
             return Err(QueueError::DescIndexOutOfBounds(desc_index));
         }
 
-        let used_ring = self.used_ring;
         let next_used = u64::from(self.next_used.0 % self.actual_size());
-        let used_elem = used_ring.unchecked_add(4 + next_used * 8);
+        let used_elem = self.used_ring.unchecked_add(4 + next_used * 8);
+
+        #[cfg(feature = "memory-guards")]
+        crate::devices::virtio::canary::record_write(mem, used_elem, 8);
 
         mem.write_obj(u32::from(desc_index), used_elem)?;
 
@@ -446,11 +867,20 @@ impl Queue {
 
         self.num_added += Wrapping(1);
         self.next_used += Wrapping(1);
+        self.counters.used += 1;
+        Ok(())
+    }
+
+    // Makes the elements of the used ring written so far visible to the guest.
+    fn publish_used<M: GuestMemory>(&self, mem: &M) -> Result<(), QueueError> {
+        let next_used_addr = self.used_ring.unchecked_add(2);
+
+        #[cfg(feature = "memory-guards")]
+        crate::devices::virtio::canary::record_write(mem, next_used_addr, 2);
 
         // This fence ensures all descriptor writes are visible before the index update is.
         fence(Ordering::Release);
 
-        let next_used_addr = used_ring.unchecked_add(2);
         mem.write_obj(self.next_used.0, next_used_addr)
             .map_err(QueueError::UsedRing)
     }
@@ -476,7 +906,7 @@ impl Queue {
         // We need to find the `used_event` field from the avail ring.
         let used_event_addr = self
             .avail_ring
-            .unchecked_add(u64::from(4 + 2 * self.actual_size()));
+            .unchecked_add(4 + 2 * u64::from(self.actual_size()));
 
         Wrapping(mem.read_obj::<u16>(used_event_addr).unwrap())
     }
@@ -487,11 +917,67 @@ impl Queue {
 
         let avail_event_addr = self
             .used_ring
-            .unchecked_add(u64::from(4 + 8 * self.actual_size()));
+            .unchecked_add(4 + 8 * u64::from(self.actual_size()));
 
+        #[cfg(feature = "memory-guards")]
+        crate::devices::virtio::canary::record_write(mem, avail_event_addr, 2);
         mem.write_obj(val, avail_event_addr).unwrap();
     }
 
+    /// Helper method that writes `val` to the `flags` field of the used ring.
+    fn set_used_flags<M: GuestMemory>(&mut self, val: u16, mem: &M) {
+        debug_assert!(self.is_layout_valid(mem));
+
+        #[cfg(feature = "memory-guards")]
+        crate::devices::virtio::canary::record_write(mem, self.used_ring, 2);
+        mem.write_obj(val, self.used_ring).unwrap();
+    }
+
+    /// Asks the guest driver not to notify the device of the descriptor chains it makes available,
+    /// by setting `VIRTQ_USED_F_NO_NOTIFY` in the used ring. Devices call this when entering their
+    /// processing loop, as they will see the new chains anyway. Notifications are enabled again
+    /// by `enable_notification`, which `pop_or_enable_notification` and `pop_batch` call once the
+    /// avail ring is drained. A device stopping early (e.g. rate limited) must resume processing
+    /// on its own.
+    ///
+    /// When notification suppression is in use, the specification requires the flags to stay 0,
+    /// so this does nothing: the driver then only notifies when making available the chain at
+    /// `avail_event`, which is only moved past the popped chains when the avail ring is drained.
+    pub fn disable_notification<M: GuestMemory>(&mut self, mem: &M) {
+        if !self.uses_notif_suppression {
+            self.set_used_flags(VIRTQ_USED_F_NO_NOTIFY, mem);
+        }
+    }
+
+    /// Enables notification events from the guest driver, whether notification suppression is in
+    /// use or not. Returns true if notifications were successfully enabled. Otherwise one or more
+    /// descriptors can still be consumed from the available ring, which the driver may have made
+    /// available before seeing notifications enabled, so without notifying. In this case the
+    /// caller must consume them and call this method again.
+    pub fn enable_notification<M: GuestMemory>(&mut self, mem: &M) -> bool {
+        if self.uses_notif_suppression {
+            return self.try_enable_notification(mem);
+        }
+
+        let len = self.len(mem);
+        if len != 0 {
+            // See `pop`.
+            if len > self.actual_size() {
+                panic!("The number of available virtio descriptors is greater than queue size!");
+            }
+            self.pending_hwm = self.pending_hwm.max(len);
+            return false;
+        }
+
+        self.set_used_flags(0, mem);
+
+        // Make sure the avail index is read again after clearing the flags, as the driver may have
+        // made a descriptor chain available since the check above without notifying.
+        fence(Ordering::SeqCst);
+
+        self.next_avail.0 == self.avail_idx(mem).0
+    }
+
     /// Try to enable notification events from the guest driver. Returns true if notifications were
     /// successfully enabled. Otherwise it means that one or more descriptors can still be consumed
     /// from the available ring and we can't guarantee that there will be a notification. In this
@@ -516,6 +1002,7 @@ impl Queue {
                 // the log system.
                 panic!("The number of available virtio descriptors is greater than queue size!");
             }
+            self.pending_hwm = self.pending_hwm.max(len);
             return false;
         }
 
@@ -530,6 +1017,28 @@ impl Queue {
         self.next_avail.0 == self.avail_idx(mem).0
     }
 
+    /// Samples the depth of the queue: the number of descriptor chains made available by the
+    /// driver and not yet processed by the device, and the number of used descriptor chains not
+    /// yet seen by the driver. The latter is only known when notification suppression is in use,
+    /// as the driver then publishes the used index it has seen in the `used_event` field.
+    ///
+    /// Also returns the high-watermark of pending descriptor chains since the previous sample,
+    /// and resets it.
+    pub fn sample_depth<M: GuestMemory>(&mut self, mem: &M) -> QueueDepthSample {
+        let pending = self.len(mem);
+        let unconsumed = self
+            .uses_notif_suppression
+            .then(|| (self.next_used - self.used_event(mem)).0);
+        let pending_hwm = self.pending_hwm.max(pending);
+        self.pending_hwm = 0;
+
+        QueueDepthSample {
+            pending,
+            unconsumed,
+            pending_hwm,
+        }
+    }
+
     /// Enable notification suppression.
     pub fn enable_notif_suppression(&mut self) {
         self.uses_notif_suppression = true;
@@ -541,12 +1050,14 @@ impl Queue {
     /// driver will actually be notified, and won't return `true` again until the driver
     /// updates `used_event` and/or the notification conditions hold once more.
     ///
-    /// This is similar to the `vring_need_event()` method implemented by the Linux kernel.
+    /// The decision is the one of [`needs_notification`], for the used buffers added since the
+    /// last call.
     pub fn prepare_kick<M: GuestMemory>(&mut self, mem: &M) -> bool {
         debug_assert!(self.is_layout_valid(mem));
 
         // If the device doesn't use notification suppression, always return true
         if !self.uses_notif_suppression {
+            self.counters.notified += 1;
             return true;
         }
 
@@ -559,10 +1070,45 @@ impl Queue {
 
         self.num_added = Wrapping(0);
 
-        new - used_event - Wrapping(1) < new - old
+        let notify = needs_notification(used_event.0, new.0, old.0);
+        if notify {
+            self.counters.notified += 1;
+        } else if new != old {
+            self.counters.suppressed += 1;
+        }
+        notify
+    }
+
+    /// Returns the counts of the operations done on the queue since the previous call, and resets
+    /// them.
+    pub fn take_counters(&mut self) -> QueueCounters {
+        std::mem::take(&mut self.counters)
     }
 }
 
+/// Empties `chains` and releases its borrow of the guest memory, keeping its capacity. This allows
+/// a device to keep the storage of the batches of chains it pops across the processing of its
+/// queues, each one borrowing the guest memory anew.
+pub fn recycle_chains<'b, M: GuestMemory>(
+    mut chains: Vec<DescriptorChain<'_, M>>,
+) -> Vec<DescriptorChain<'b, M>> {
+    chains.clear();
+    // Collecting the elements of a vector into one of a type of the same layout reuses its
+    // allocation.
+    chains.into_iter().map(|_| unreachable!()).collect()
+}
+
+/// Whether moving a ring index from `old` to `new` crosses the event index `event_idx` set by the
+/// other side of the queue, which then asked to be notified.
+///
+/// The index crosses `event_idx` when it takes the value `event_idx + 1`, that is when
+/// `event_idx` is one of the `new - old` values `old..new`. All the arithmetic wraps around at
+/// 2^16, as the indices do. This is the `vring_need_event()` formula of the virtio
+/// specification, and of the Linux kernel.
+pub fn needs_notification(event_idx: u16, new: u16, old: u16) -> bool {
+    new.wrapping_sub(event_idx).wrapping_sub(1) < new.wrapping_sub(old)
+}
+
 #[cfg(kani)]
 #[allow(dead_code)]
 mod verification {
@@ -575,7 +1121,9 @@ mod verification {
     use crate::devices::virtio::queue::{
         Descriptor, DescriptorChain, Queue, FIRECRACKER_MAX_QUEUE_SIZE, VIRTQ_DESC_F_NEXT,
     };
-    use crate::vstate::memory::{Bytes, FileOffset, GuestAddress, GuestMemory, MmapRegion};
+    use crate::vstate::memory::{
+        Address, Bytes, FileOffset, GuestAddress, GuestMemory, MmapRegion,
+    };
 
     /// A made-for-kani version of `vm_memory::GuestMemoryMmap`. Unlike the real
     /// `GuestMemoryMmap`, which manages a list of regions and then does a binary
@@ -1064,12 +1612,100 @@ mod verification {
             }
         }
     }
+
+    #[kani::proof]
+    #[kani::unwind(0)]
+    #[kani::solver(cadical)]
+    fn verify_pop_desc_table_bounds() {
+        let ProofContext(mut queue, mem) = ProofContext::bounded_queue();
+
+        // See verify_pop for explanation
+        kani::assume(queue.len(&mem) <= queue.actual_size());
+
+        // Whatever descriptor index the guest wrote in the avail ring, the popped head is in the
+        // descriptor table, the next descriptor of its chain is in the table of the head, which
+        // is the descriptor table or an indirect table no larger, and the chain can be walked
+        // for at most the queue size descriptors (see verify_next_descriptor_ttl).
+        if let Some(head) = queue.pop(&mem) {
+            assert!(head.index < queue.actual_size());
+            assert!(head.queue_size <= queue.actual_size());
+            assert_eq!(head.ttl, head.queue_size);
+
+            if let Some(desc) = head.next_descriptor() {
+                assert_eq!(desc.table_index, head.next);
+                assert!(desc.table_index < head.queue_size);
+                assert!(!head.indirect || desc.index == head.index);
+            }
+        }
+    }
+
+    #[kani::proof]
+    #[kani::unwind(0)]
+    #[kani::solver(cadical)]
+    fn verify_next_descriptor_ttl() {
+        let ProofContext(queue, mem) = ProofContext::bounded_queue();
+
+        // Walking a descriptor chain terminates, even if the guest linked the descriptors in a
+        // cycle: the head has a ttl of the queue size (verify_pop_desc_table_bounds), each next
+        // descriptor has a ttl lower by one, and a descriptor with a ttl of one has no next
+        // descriptor. By induction, a chain has at most the queue size descriptors.
+        let ttl: u16 = kani::any();
+        kani::assume(0 < ttl && ttl <= queue.actual_size());
+
+        if let Some(mut desc) =
+            DescriptorChain::checked_new(&mem, queue.desc_table, queue.actual_size(), kani::any())
+        {
+            desc.ttl = ttl;
+
+            match desc.next_descriptor() {
+                Some(next) => {
+                    assert!(ttl > 1);
+                    assert_eq!(next.ttl, ttl - 1);
+                    assert!(next.index < queue.actual_size());
+                }
+                None => assert!(ttl == 1 || desc.flags & VIRTQ_DESC_F_NEXT == 0),
+            }
+        }
+    }
+
+    #[kani::proof]
+    #[kani::unwind(0)]
+    #[kani::solver(cadical)]
+    fn verify_add_used() {
+        let ProofContext(mut queue, mem) = ProofContext::bounded_queue();
+
+        // Any byte of guest memory outside of the used ring keeps its value.
+        let used_ring_end = queue
+            .used_ring
+            .unchecked_add(6 + 8 * u64::from(queue.actual_size()));
+        let addr = GuestAddress(kani::any());
+        kani::assume(mem.address_in_range(addr));
+        kani::assume(addr < queue.used_ring || addr >= used_ring_end);
+        let val: u8 = mem.read_obj(addr).unwrap();
+
+        let next_used = queue.next_used;
+        let desc_index = kani::any();
+
+        match queue.add_used(&mem, desc_index, kani::any()) {
+            Ok(()) => {
+                assert!(desc_index < queue.actual_size());
+                assert_eq!(queue.next_used, next_used + Wrapping(1));
+            }
+            Err(_) => {
+                assert!(desc_index >= queue.actual_size());
+                assert_eq!(queue.next_used, next_used);
+            }
+        }
+
+        assert_eq!(mem.read_obj::<u8>(addr).unwrap(), val);
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     pub use super::*;
+    use crate::devices::virtio::iovec::IoVecBuffer;
     use crate::devices::virtio::queue::QueueError::{DescIndexOutOfBounds, UsedRing};
     use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
     use crate::utilities::test_utils::{multi_region_mem, single_region_mem};
@@ -1079,7 +1715,7 @@ mod tests {
         fn avail_event(&self, mem: &GuestMemoryMmap) -> u16 {
             let avail_event_addr = self
                 .used_ring
-                .unchecked_add(u64::from(4 + 8 * self.actual_size()));
+                .unchecked_add(4 + 8 * u64::from(self.actual_size()));
 
             mem.read_obj::<u16>(avail_event_addr).unwrap()
         }
@@ -1131,6 +1767,295 @@ mod tests {
         }
     }
 
+    // Returns the indices of the descriptors of the chain popped from `q`, ending with the error
+    // of the chain if any.
+    fn walk_checked(q: &mut Queue, m: &GuestMemoryMmap) -> (Vec<u16>, Option<ChainError>) {
+        let mut indices = Vec::new();
+        let mut error = None;
+        let mut iter = q.pop(m).unwrap().checked_iter();
+        for desc in iter.by_ref() {
+            match desc {
+                Ok(desc) => indices.push(desc.index),
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
+        // The iterator ends after an error.
+        assert!(iter.next().is_none());
+        (indices, error)
+    }
+
+    #[test]
+    fn test_checked_iter() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 4);
+        let mut q = vq.create_queue();
+
+        let make_available = |head: u16| {
+            let idx = vq.avail.idx.get();
+            vq.avail.ring[usize::from(idx % 4)].set(head);
+            vq.avail.idx.set(idx.wrapping_add(1));
+        };
+
+        // A valid chain is walked entirely: 0 -> 2 -> 1.
+        vq.dtable[0].set(0x1000, 0x100, VIRTQ_DESC_F_NEXT, 2);
+        vq.dtable[2].set(0x2000, 0x100, VIRTQ_DESC_F_NEXT, 1);
+        vq.dtable[1].set(0x3000, 0x100, 0, 0);
+        make_available(0);
+        assert_eq!(walk_checked(&mut q, m), (vec![0, 2, 1], None));
+
+        // A descriptor linking to itself.
+        vq.dtable[1].set(0x3000, 0x100, VIRTQ_DESC_F_NEXT, 1);
+        make_available(0);
+        assert_eq!(
+            walk_checked(&mut q, m),
+            (vec![0, 2, 1], Some(ChainError::Cycle(1)))
+        );
+
+        // A cycle through all the descriptors of the queue, which the infallible iterator cuts
+        // at the queue size without telling.
+        vq.dtable[0].set(0x1000, 0x100, VIRTQ_DESC_F_NEXT, 1);
+        vq.dtable[1].set(0x2000, 0x100, VIRTQ_DESC_F_NEXT, 2);
+        vq.dtable[2].set(0x3000, 0x100, VIRTQ_DESC_F_NEXT, 3);
+        vq.dtable[3].set(0x4000, 0x100, VIRTQ_DESC_F_NEXT, 0);
+        make_available(0);
+        assert_eq!(q.pop(m).unwrap().into_iter().count(), 4);
+        q.undo_pop();
+        assert_eq!(
+            walk_checked(&mut q, m),
+            (vec![0, 1, 2, 3], Some(ChainError::Cycle(0)))
+        );
+
+        // A chain visiting a descriptor twice without coming back to the head.
+        vq.dtable[3].set(0x4000, 0x100, VIRTQ_DESC_F_NEXT, 2);
+        make_available(0);
+        assert_eq!(
+            walk_checked(&mut q, m),
+            (vec![0, 1, 2, 3], Some(ChainError::Cycle(2)))
+        );
+
+        // A descriptor linking to one whose next index is out of the queue.
+        vq.dtable[1].set(0x2000, 0x100, VIRTQ_DESC_F_NEXT, 4);
+        make_available(0);
+        assert_eq!(
+            walk_checked(&mut q, m),
+            (vec![0], Some(ChainError::InvalidDescriptor(1)))
+        );
+    }
+
+    #[test]
+    fn test_chain_layout() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 4);
+        let mut q = vq.create_queue();
+
+        let mut scan = |head: u16| {
+            let idx = vq.avail.idx.get();
+            vq.avail.ring[usize::from(idx % 4)].set(head);
+            vq.avail.idx.set(idx.wrapping_add(1));
+            ChainLayout::scan(&q.pop(m).unwrap())
+        };
+
+        // A block request: header, data to read from the disk and status byte.
+        vq.dtable[0].set(0x1000, 0x10, VIRTQ_DESC_F_NEXT, 1);
+        vq.dtable[1].set(0x2000, 0x200, VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE, 2);
+        vq.dtable[2].set(0x3000, 0x1, VIRTQ_DESC_F_WRITE, 0);
+        let layout = scan(0).unwrap();
+        assert_eq!(
+            layout,
+            ChainLayout {
+                readable_count: 1,
+                writable_count: 2,
+                readable_len: 0x10,
+                writable_len: 0x201,
+            }
+        );
+        assert_eq!(layout.desc_count(), 3);
+
+        // A chain of a single status byte.
+        assert_eq!(
+            scan(2).unwrap(),
+            ChainLayout {
+                readable_count: 0,
+                writable_count: 1,
+                readable_len: 0,
+                writable_len: 0x1,
+            }
+        );
+
+        // A chain without writable descriptors.
+        vq.dtable[1].set(0x2000, 0x200, 0, 0);
+        assert_eq!(
+            scan(0).unwrap(),
+            ChainLayout {
+                readable_count: 2,
+                writable_count: 0,
+                readable_len: 0x210,
+                writable_len: 0,
+            }
+        );
+
+        // A readable descriptor following a writable one.
+        vq.dtable[1].set(0x2000, 0x200, VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE, 3);
+        vq.dtable[3].set(0x4000, 0x100, 0, 0);
+        assert_eq!(scan(0), Err(ChainError::ReadableAfterWritable(3)));
+
+        // An invalid chain.
+        vq.dtable[3].set(0x4000, 0x100, VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE, 0);
+        assert_eq!(scan(0), Err(ChainError::Cycle(0)));
+    }
+
+    // Writes the descriptors of an indirect table at `table`, chaining each to the next one.
+    fn write_indirect_table(m: &GuestMemoryMmap, table: u64, descs: &[(u64, u32, u16)]) {
+        for (i, &(addr, len, flags)) in (0u16..).zip(descs) {
+            let next = if usize::from(i) + 1 < descs.len() {
+                VIRTQ_DESC_F_NEXT
+            } else {
+                0
+            };
+            let desc = Descriptor {
+                addr,
+                len,
+                flags: flags | next,
+                next: i + 1,
+            };
+            m.write_obj(desc, GuestAddress(table + 16 * u64::from(i)))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_indirect_descriptor_chain() {
+        // The indirect table spans the boundary between the two regions.
+        let m = &multi_region_mem(&[(GuestAddress(0), 0x10000), (GuestAddress(0x10000), 0x10000)]);
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+        let mut q = vq.create_queue();
+
+        write_indirect_table(
+            m,
+            0xffe0,
+            &[
+                (0x2000, 0x100, 0),
+                (0x3000, 0x100, 0),
+                (0x4000, 0x100, 0),
+                (0x5000, 0x100, 0),
+            ],
+        );
+        vq.dtable[3].set(0xffe0, 64, VIRTQ_DESC_F_INDIRECT, 0);
+        vq.avail.ring[0].set(3);
+        vq.avail.idx.set(1);
+
+        // The chain is the one of the table, under the index of the head referring to it.
+        let head = q.pop(m).unwrap();
+        assert_eq!(head.index, 3);
+        assert!(!head.is_indirect());
+        let descs: Vec<_> = head
+            .into_iter()
+            .map(|desc| (desc.index, desc.addr.raw_value(), desc.len))
+            .collect();
+        assert_eq!(
+            descs,
+            vec![
+                (3, 0x2000, 0x100),
+                (3, 0x3000, 0x100),
+                (3, 0x4000, 0x100),
+                (3, 0x5000, 0x100)
+            ]
+        );
+        q.undo_pop();
+        assert_eq!(walk_checked(&mut q, m), (vec![3, 3, 3, 3], None));
+
+        // Buffers are built from the chain as from a direct one.
+        q.undo_pop();
+        let buffer = IoVecBuffer::from_descriptor_chain(q.pop(m).unwrap()).unwrap();
+        assert_eq!(buffer.len(), 0x400);
+
+        // A cycle in the table is reported with the index in the table.
+        m.write_obj(3u16, GuestAddress(0xffe0 + 3 * 16 + 14))
+            .unwrap();
+        m.write_obj(VIRTQ_DESC_F_NEXT, GuestAddress(0xffe0 + 3 * 16 + 12))
+            .unwrap();
+        q.undo_pop();
+        assert_eq!(
+            walk_checked(&mut q, m),
+            (vec![3, 3, 3, 3], Some(ChainError::Cycle(3)))
+        );
+    }
+
+    #[test]
+    fn test_invalid_indirect_descriptor_chain() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+        let mut q = vq.create_queue();
+        vq.avail.ring[0].set(0);
+        vq.avail.idx.set(1);
+        write_indirect_table(m, 0x1000, &[(0x2000, 0x100, 0), (0x3000, 0x100, 0)]);
+
+        // A table whose length isn't a multiple of the size of a descriptor.
+        vq.dtable[0].set(0x1000, 24, VIRTQ_DESC_F_INDIRECT, 0);
+        assert!(q.pop(m).is_none());
+        // An empty table.
+        vq.dtable[0].set(0x1000, 0, VIRTQ_DESC_F_INDIRECT, 0);
+        assert!(q.pop(m).is_none());
+        // A table larger than the queue.
+        vq.dtable[0].set(0x1000, 17 * 16, VIRTQ_DESC_F_INDIRECT, 0);
+        assert!(q.pop(m).is_none());
+        // A table out of guest memory.
+        let end = m.last_addr().raw_value() + 1;
+        vq.dtable[0].set(end - 16, 32, VIRTQ_DESC_F_INDIRECT, 0);
+        assert!(q.pop(m).is_none());
+        vq.dtable[0].set(u64::MAX - 15, 32, VIRTQ_DESC_F_INDIRECT, 0);
+        assert!(q.pop(m).is_none());
+        // A table chained to another descriptor.
+        vq.dtable[0].set(0x1000, 32, VIRTQ_DESC_F_INDIRECT | VIRTQ_DESC_F_NEXT, 1);
+        assert!(q.pop(m).is_none());
+
+        // Nested indirect tables, either first in the table or later in it.
+        write_indirect_table(
+            m,
+            0x1000,
+            &[(0x1000, 32, VIRTQ_DESC_F_INDIRECT), (0x3000, 0x100, 0)],
+        );
+        vq.dtable[0].set(0x1000, 32, VIRTQ_DESC_F_INDIRECT, 0);
+        assert!(q.pop(m).is_none());
+        write_indirect_table(
+            m,
+            0x1000,
+            &[(0x2000, 0x100, 0), (0x1000, 32, VIRTQ_DESC_F_INDIRECT)],
+        );
+        assert_eq!(
+            walk_checked(&mut q, m),
+            (vec![0], Some(ChainError::InvalidDescriptor(1)))
+        );
+        q.undo_pop();
+        assert_eq!(q.pop(m).unwrap().into_iter().count(), 1);
+
+        // A table with a next index out of it.
+        write_indirect_table(
+            m,
+            0x1000,
+            &[(0x2000, 0x100, 0), (0x3000, 0x100, 0), (0x4000, 0x100, 0)],
+        );
+        m.write_obj(3u16, GuestAddress(0x1000 + 16 + 14)).unwrap();
+        vq.dtable[0].set(0x1000, 48, VIRTQ_DESC_F_INDIRECT, 0);
+        q.undo_pop();
+        assert_eq!(
+            walk_checked(&mut q, m),
+            (vec![0], Some(ChainError::InvalidDescriptor(1)))
+        );
+
+        // A table of descriptors pointing out of guest memory is walked, but no buffer can be
+        // built from it.
+        write_indirect_table(m, 0x1000, &[(0x2000, 0x100, 0), (end, 0x100, 0)]);
+        vq.dtable[0].set(0x1000, 32, VIRTQ_DESC_F_INDIRECT, 0);
+        q.undo_pop();
+        assert_eq!(q.pop(m).unwrap().into_iter().count(), 2);
+        q.undo_pop();
+        IoVecBuffer::from_descriptor_chain(q.pop(m).unwrap()).unwrap_err();
+    }
+
     #[test]
     fn test_queue_validation() {
         let m = &default_mem();
@@ -1421,6 +2346,34 @@ mod tests {
         assert_eq!(vq.used.event.get(), u16::MAX);
     }
 
+    #[test]
+    fn test_max_size_ring_offsets() {
+        // The ring offsets of the largest queues don't fit in a u16.
+        let m = &single_region_mem(0x10_0000);
+        let vq = VirtQueue::new(GuestAddress(0), m, 1 << 15);
+        assert!(vq.end().0 < 0x10_0000);
+
+        let mut q = vq.create_queue();
+        assert!(q.is_valid(m));
+
+        let last: u16 = (1 << 15) - 1;
+        vq.dtable[usize::from(last)].set(0x1000, 0x1000, 0, 0);
+        vq.avail.ring[usize::from(last)].set(last);
+        vq.avail.idx.set(1 << 15);
+        vq.avail.event.set(10);
+        q.next_avail = Wrapping(last);
+        q.next_used = Wrapping(last);
+
+        assert_eq!(q.used_event(m), Wrapping(10));
+        assert_eq!(q.pop(m).unwrap().index, last);
+        q.add_used(m, last, 0x1000).unwrap();
+        vq.check_used_elem(last, last, 0x1000);
+        // The avail event is after the last used element, it doesn't overwrite the first one.
+        q.set_avail_event(20, m);
+        assert_eq!(vq.used.event.get(), 20);
+        assert_eq!(vq.used.ring[0].get().id, 0);
+    }
+
     #[test]
     fn test_needs_kick() {
         let m = &default_mem();
@@ -1469,6 +2422,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_needs_notification_wrap() {
+        // The event index is crossed when it is one of the `new - old` indices written since
+        // `old`, for any `old`, including when the indices wrap around.
+        let steps = [0u16, 1, 2, 5, 0x7fff, 0x8000, 0x8001, u16::MAX];
+        let offsets = [0u16, 1, 4, 5, 6, 0x7fff, 0x8000, u16::MAX - 1, u16::MAX];
+        for old in 0..=u16::MAX {
+            for step in steps {
+                let new = old.wrapping_add(step);
+                for offset in offsets {
+                    let event_idx = old.wrapping_add(offset);
+                    assert_eq!(
+                        needs_notification(event_idx, new, old),
+                        offset < step,
+                        "event_idx {event_idx}, new {new}, old {old}"
+                    );
+                }
+            }
+        }
+
+        assert!(needs_notification(u16::MAX, 2, u16::MAX - 1));
+        assert!(needs_notification(1, 2, u16::MAX - 1));
+        assert!(!needs_notification(u16::MAX - 2, 2, u16::MAX - 1));
+        assert!(!needs_notification(2, 2, u16::MAX - 1));
+        assert!(needs_notification(0, 1, u16::MAX));
+        assert!(!needs_notification(0, u16::MAX, u16::MAX));
+    }
+
+    #[test]
+    fn test_notification_suppressed_until_used_event() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+        let mut q = vq.create_queue();
+        q.ready = true;
+        q.enable_notif_suppression();
+
+        // Start right before the used index wraps around, the guest waiting for the next buffer.
+        q.next_used = Wrapping(u16::MAX - 1);
+        vq.avail.event.set(u16::MAX - 1);
+        q.add_used(m, 0, 0x100).unwrap();
+        assert!(q.prepare_kick(m));
+
+        // As long as the guest doesn't move `used_event`, it isn't notified again, even once the
+        // used index wrapped around.
+        for _ in 0..20 {
+            q.add_used(m, 0, 0x100).unwrap();
+            assert!(!q.prepare_kick(m));
+        }
+        assert_eq!(vq.used.idx.get(), 19);
+
+        // The guest moves `used_event` to the last used index it saw.
+        vq.avail.event.set(19);
+        q.add_used(m, 0, 0x100).unwrap();
+        assert!(q.prepare_kick(m));
+        assert!(!q.prepare_kick(m));
+
+        // A batch of used buffers crossing `used_event` notifies the guest once.
+        vq.avail.event.set(21);
+        q.add_used_batch(m, &[(0, 0x100), (1, 0x100), (2, 0x100)])
+            .unwrap();
+        assert!(q.prepare_kick(m));
+        assert!(!q.prepare_kick(m));
+    }
+
     #[test]
     fn test_try_enable_notification() {
         let m = &default_mem();
@@ -1500,6 +2517,196 @@ mod tests {
         assert_eq!(q.avail_event(m), 1);
     }
 
+    #[test]
+    fn test_disable_notification() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+        let mut q = vq.create_queue();
+
+        q.ready = true;
+        vq.dtable[0].set(0x1000_u64, 0x1000, 0, 0);
+        vq.avail.ring[0].set(0);
+        vq.avail.ring[1].set(0);
+
+        // Notifications stay disabled until the device finds the avail ring empty.
+        q.disable_notification(m);
+        assert_eq!(vq.used.flags.get(), VIRTQ_USED_F_NO_NOTIFY);
+        vq.avail.idx.set(1);
+        assert!(!q.enable_notification(m));
+        assert_eq!(vq.used.flags.get(), VIRTQ_USED_F_NO_NOTIFY);
+        assert!(q.pop_or_enable_notification(m).is_some());
+        assert_eq!(vq.used.flags.get(), VIRTQ_USED_F_NO_NOTIFY);
+        assert!(q.pop_or_enable_notification(m).is_none());
+        assert_eq!(vq.used.flags.get(), 0);
+        assert_eq!(q.counters.empty_pops, 1);
+
+        // The same goes for `pop_batch`.
+        let mut heads = Vec::new();
+        q.disable_notification(m);
+        vq.avail.idx.set(2);
+        assert_eq!(q.pop_batch(m, 4, &mut heads), 1);
+        assert_eq!(vq.used.flags.get(), VIRTQ_USED_F_NO_NOTIFY);
+        assert_eq!(q.pop_batch(m, 4, &mut heads), 0);
+        assert_eq!(vq.used.flags.get(), 0);
+
+        // With notification suppression, the flags must stay 0 and `avail_event` is used instead.
+        q.enable_notif_suppression();
+        q.disable_notification(m);
+        assert_eq!(vq.used.flags.get(), 0);
+        assert!(q.enable_notification(m));
+        assert_eq!(q.avail_event(m), 2);
+    }
+
+    #[test]
+    fn test_enable_notification_recheck() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+        let mut q = vq.create_queue();
+
+        q.ready = true;
+        for i in 0..4 {
+            vq.dtable[i].set(0x1000 * (i as u64 + 1), 0x1000, 0, 0);
+            vq.avail.ring[i].set(u16::try_from(i).unwrap());
+        }
+
+        for uses_notif_suppression in [false, true] {
+            q.uses_notif_suppression = uses_notif_suppression;
+            q.disable_notification(m);
+
+            // The driver makes two chains available while the device processes the queue. It
+            // doesn't notify, as the notifications are disabled, so the device must still pop
+            // them when trying to enable the notifications again.
+            let next = vq.avail.idx.get();
+            vq.avail.idx.set(next + 1);
+            assert!(q.pop_or_enable_notification(m).is_some());
+            vq.avail.idx.set(next + 2);
+            assert!(!q.enable_notification(m));
+            assert_eq!(q.pop_or_enable_notification(m).unwrap().index, next + 1);
+            assert!(q.pop_or_enable_notification(m).is_none());
+            assert_eq!(vq.used.flags.get(), 0);
+            assert!(q.enable_notification(m));
+        }
+    }
+
+    #[test]
+    fn test_pop_batch() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 4);
+        let mut q = vq.create_queue();
+        q.ready = true;
+        let mut heads = Vec::new();
+
+        // Nothing is available.
+        assert_eq!(q.pop_batch(m, 4, &mut heads), 0);
+        assert!(heads.is_empty());
+
+        // Four chains are available, the avail index wrapping around after the first two.
+        for (i, addr) in [0x1000, 0x2000, 0x3000, 0x4000].into_iter().enumerate() {
+            vq.dtable[i].set(addr, 0x100, 0, 0);
+        }
+        q.next_avail = Wrapping(u16::MAX - 1);
+        for (idx, head) in [(u16::MAX - 1, 3), (u16::MAX, 2), (0, 1), (1, 0)] {
+            vq.avail.ring[usize::from(idx % 4)].set(head);
+        }
+        vq.avail.idx.set(2);
+
+        // The batch is capped by `max`, and appended to `out`.
+        assert_eq!(q.pop_batch(m, 3, &mut heads), 3);
+        assert_eq!(q.next_avail, Wrapping(1));
+        assert_eq!(
+            heads.iter().map(|head| head.index).collect::<Vec<_>>(),
+            [3, 2, 1]
+        );
+
+        // Giving back the last two chains of the batch, which straddle the wrap around.
+        heads.truncate(1);
+        q.undo_pop();
+        q.undo_pop();
+        assert_eq!(q.next_avail, Wrapping(u16::MAX));
+        assert_eq!(q.len(m), 3);
+
+        // The batch is capped by the chains available.
+        assert_eq!(q.pop_batch(m, 8, &mut heads), 3);
+        assert_eq!(
+            heads.iter().map(|head| head.index).collect::<Vec<_>>(),
+            [3, 2, 1, 0]
+        );
+        assert_eq!(q.next_avail, Wrapping(2));
+        assert_eq!(q.pop_batch(m, 8, &mut heads), 0);
+        assert_eq!(heads.len(), 4);
+
+        // With notification suppression, an empty queue enables the notifications.
+        q.enable_notif_suppression();
+        assert_eq!(q.pop_batch(m, 8, &mut heads), 0);
+        assert_eq!(q.avail_event(m), 2);
+
+        // The storage of the batch outlives the borrow of the guest memory.
+        let heads: Vec<DescriptorChain<'static>> = recycle_chains(heads);
+        assert!(heads.is_empty());
+        assert!(heads.capacity() >= 4);
+    }
+
+    #[test]
+    fn test_queue_counters() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+        let mut q = vq.create_queue();
+        q.ready = true;
+
+        // Nothing is available yet.
+        assert!(q.pop(m).is_none());
+
+        vq.dtable[0].set(0x1000, 0x100, 0, 0);
+        vq.dtable[1].set(0x2000, 0x100, 0, 0);
+        vq.avail.ring[0].set(0);
+        vq.avail.ring[1].set(1);
+        vq.avail.idx.set(2);
+
+        // An undone pop isn't counted.
+        q.pop(m).unwrap();
+        q.undo_pop();
+        q.pop(m).unwrap();
+        q.pop(m).unwrap();
+        assert!(q.pop(m).is_none());
+
+        q.add_used(m, 0, 0x100).unwrap();
+        q.add_used(m, 1, 0x100).unwrap();
+        assert!(q.prepare_kick(m));
+
+        assert_eq!(
+            q.take_counters(),
+            QueueCounters {
+                pops: 2,
+                empty_pops: 2,
+                used: 2,
+                notified: 1,
+                suppressed: 0,
+            }
+        );
+        // Taking the counters resets them.
+        assert_eq!(q.take_counters(), QueueCounters::default());
+
+        // The guest doesn't want to be notified past the first used buffer.
+        q.enable_notif_suppression();
+        vq.avail.event.set(0);
+        assert!(q.pop_or_enable_notification(m).is_none());
+        q.add_used(m, 0, 0x100).unwrap();
+        assert!(!q.prepare_kick(m));
+        // Kicking without new used buffers isn't a suppressed notification.
+        assert!(!q.prepare_kick(m));
+
+        assert_eq!(
+            q.take_counters(),
+            QueueCounters {
+                pops: 0,
+                empty_pops: 1,
+                used: 1,
+                notified: 0,
+                suppressed: 1,
+            }
+        );
+    }
+
     #[test]
     fn test_queue_error_display() {
         let err = UsedRing(vm_memory::GuestMemoryError::InvalidGuestAddress(
@@ -1510,4 +2717,67 @@ mod tests {
         let err = DescIndexOutOfBounds(1);
         let _ = format!("{}{:?}", err, err);
     }
+
+    #[test]
+    fn test_queue_rings() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+        let q = vq.create_queue();
+
+        let rings = q.rings();
+        assert_eq!(rings.desc_table, (vq.dtable_start(), 16 * 16));
+        assert_eq!(rings.avail_ring, (vq.avail_start(), 6 + 2 * 16));
+        assert_eq!(rings.used_ring, (vq.used_start(), 6 + 8 * 16));
+
+        // The rings and the descriptor table.
+        assert!(rings.overlaps(vq.dtable_start(), 1));
+        assert!(rings.overlaps(vq.avail_start().unchecked_add(20), 4));
+        assert!(rings.overlaps(vq.used_start().unchecked_sub(8), 16));
+        assert!(rings.overlaps(GuestAddress(0), 0x10000));
+        // Right before and right after them.
+        assert!(!rings.overlaps(vq.end(), 0x1000));
+        assert!(!rings.overlaps(GuestAddress(0x10000), 0x1000));
+        assert!(!rings.overlaps(vq.dtable_start(), 0));
+        assert!(!rings.overlaps(GuestAddress(u64::MAX), usize::MAX));
+    }
+
+    #[test]
+    fn test_add_used_batch() {
+        let m = &default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+        let mut q = vq.create_queue();
+
+        // The batch wraps around the end of the used ring, and the used index around u16::MAX.
+        q.next_used = Wrapping(u16::MAX - 1);
+        vq.used.idx.set(u16::MAX - 1);
+        let entries = [(3, 0x100), (5, 0x200), (7, 0x300), (9, 0x400)];
+        q.add_used_batch(m, &entries).unwrap();
+        assert_eq!(vq.used.idx.get(), 2);
+        assert_eq!(q.next_used, Wrapping(2));
+        assert_eq!(q.num_added, Wrapping(4));
+        for (&(id, len), slot) in entries.iter().zip([14, 15, 0, 1]) {
+            let elem = vq.used.ring[slot].get();
+            assert_eq!((elem.id, elem.len), (u32::from(id), len));
+        }
+
+        // The elements are written before the guest-visible index is moved, once.
+        q.write_used_elem(m, 11, 0x500).unwrap();
+        q.write_used_elem(m, 12, 0x600).unwrap();
+        assert_eq!(vq.used.idx.get(), 2);
+        q.publish_used(m).unwrap();
+        assert_eq!(vq.used.idx.get(), 4);
+
+        // An empty batch changes nothing.
+        q.add_used_batch(m, &[]).unwrap();
+        assert_eq!(vq.used.idx.get(), 4);
+
+        // The elements before an invalid one are still handed to the guest.
+        match q.add_used_batch(m, &[(1, 0x10), (16, 0x20), (2, 0x30)]) {
+            Err(DescIndexOutOfBounds(16)) => (),
+            _ => unreachable!(),
+        }
+        assert_eq!(vq.used.idx.get(), 5);
+        assert_eq!(q.next_used, Wrapping(5));
+        assert_eq!(vq.used.ring[4].get().id, 1);
+    }
 }
@@ -0,0 +1,388 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines the depth gauges of the virtio queues.
+//!
+//! # Metrics format
+//! The metrics are flushed in JSON when requested by vmm::logger::metrics::METRICS.write().
+//!
+//! ## JSON example with metrics:
+//! ```json
+//! {
+//!  "virtio_queues": {
+//!     "skipped": "SharedIncMetric",
+//!     "invalid_chains": "SharedIncMetric",
+//!     "devices": {
+//!         "block_rootfs": {
+//!             "0": { "pending": 3, "unconsumed": 1, "pending_hwm": 12 }
+//!         },
+//!         "net_eth0": {
+//!             "0": { "pending": 0, "unconsumed": 0, "pending_hwm": 4 },
+//!             "1": { "pending": 1, "unconsumed": 0, "pending_hwm": 2 }
+//!         },
+//!         ...
+//!     }
+//!  }
+//! }
+//! ```
+//! Each queue of an activated virtio device is sampled at flush time:
+//! * `pending` is the number of descriptor chains made available by the driver and not yet
+//!   processed by the device;
+//! * `unconsumed` is the number of used descriptor chains not yet seen by the driver. It is only
+//!   reported for queues using notification suppression (VIRTIO_F_EVENT_IDX), since otherwise the
+//!   driver does not publish the used index it has seen;
+//! * `pending_hwm` is the highest number of pending descriptor chains seen by the device since the
+//!   previous flush.
+//!
+//! `invalid_chains` counts the descriptor chains of all devices rejected for being cyclic or
+//! holding an invalid descriptor, or cut for this reason by the walks which can't reject them.
+//!
+//! # Design
+//! * Sampling must not delay the flush, so devices are only locked with `try_lock`. Devices which
+//!   are busy at flush time are not reported, and counted in `skipped` instead.
+//! * The high-watermark is maintained by `Queue` whenever it computes the number of pending
+//!   descriptor chains while processing the queue, so keeping it only costs a `max`.
+//! * Devices are keyed by "$device_type_$device_id" so that queues of devices of different types
+//!   sharing an id are not mixed up.
+//! * vhost-user devices are not registered, since their queues are processed by the backend.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+use crate::devices::virtio::device::VirtioDevice;
+use crate::devices::virtio::vsock::TYPE_VSOCK;
+use crate::devices::virtio::{TYPE_BALLOON, TYPE_BLOCK, TYPE_NET, TYPE_RNG};
+use crate::logger::{IncMetric, SharedIncMetric};
+use crate::vstate::memory::GuestMemoryMmap;
+
+/// Depth of a virtio queue, sampled at metrics flush time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct QueueDepthSample {
+    /// Number of descriptor chains made available by the driver and not yet processed.
+    pub pending: u16,
+    /// Number of used descriptor chains not yet seen by the driver, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unconsumed: Option<u16>,
+    /// Highest number of pending descriptor chains since the previous sample.
+    pub pending_hwm: u16,
+}
+
+/// A virtio device whose queues are sampled at flush time.
+#[derive(Debug)]
+struct QueueMetricsSource {
+    device: Weak<Mutex<dyn VirtioDevice>>,
+    mem: GuestMemoryMmap,
+}
+
+impl QueueMetricsSource {
+    /// Samples the queues of the device, indexed by queue index. Returns `None` if the device is
+    /// gone or busy, in which case `skipped` is incremented, or if it is not activated yet.
+    fn sample(&self, skipped: &SharedIncMetric) -> Option<BTreeMap<usize, QueueDepthSample>> {
+        let device = self.device.upgrade()?;
+        let Ok(mut locked_device) = device.try_lock() else {
+            skipped.inc();
+            return None;
+        };
+        if !locked_device.is_activated() {
+            return None;
+        }
+
+        Some(
+            locked_device
+                .queues_mut()
+                .iter_mut()
+                .enumerate()
+                .filter(|(_, queue)| queue.ready)
+                .map(|(index, queue)| (index, queue.sample_depth(&self.mem)))
+                .collect(),
+        )
+    }
+}
+
+/// Map of the virtio devices whose queues are sampled, keyed by "$device_type_$device_id".
+/// This should be protected by a lock before accessing.
+#[derive(Debug)]
+pub struct QueueMetricsPerDevice {
+    sources: BTreeMap<String, QueueMetricsSource>,
+    /// Number of devices which could not be sampled because they were busy.
+    pub skipped: SharedIncMetric,
+    /// Number of descriptor chains rejected for being cyclic or holding an invalid descriptor.
+    pub invalid_chains: SharedIncMetric,
+}
+
+impl QueueMetricsPerDevice {
+    /// Registers the queues of `device`, with id `device_id`, to be sampled at flush time.
+    /// Registering a device with the same type and id as a previous one replaces it.
+    pub fn register(device_id: &str, device: &Arc<Mutex<dyn VirtioDevice>>, mem: GuestMemoryMmap) {
+        let device_type = device.lock().expect("Poisoned lock").device_type();
+        let name = format!("{}_{}", device_type_name(device_type), device_id);
+        METRICS.write().unwrap().sources.insert(
+            name,
+            QueueMetricsSource {
+                device: Arc::downgrade(device),
+                mem,
+            },
+        );
+    }
+
+    /// Counts a descriptor chain rejected for being cyclic or holding an invalid descriptor.
+    pub fn invalid_chain() {
+        METRICS.read().unwrap().invalid_chains.inc();
+    }
+}
+
+fn device_type_name(device_type: u32) -> &'static str {
+    match device_type {
+        TYPE_NET => "net",
+        TYPE_BLOCK => "block",
+        TYPE_RNG => "rng",
+        TYPE_BALLOON => "balloon",
+        TYPE_VSOCK => "vsock",
+        _ => "virtio",
+    }
+}
+
+/// Pool of the sampled virtio devices behind a lock to keep things thread safe. Since the lock is
+/// initialized here it is safe to unwrap it without any check.
+static METRICS: RwLock<QueueMetricsPerDevice> = RwLock::new(QueueMetricsPerDevice {
+    sources: BTreeMap::new(),
+    skipped: SharedIncMetric::new(),
+    invalid_chains: SharedIncMetric::new(),
+});
+
+/// This function samples the queues of the registered devices and serializes them.
+pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
+    // Take the write lock so that devices which are gone can be forgotten.
+    let mut queue_metrics = METRICS.write().unwrap();
+    queue_metrics
+        .sources
+        .retain(|_, source| source.device.strong_count() > 0);
+
+    let devices: BTreeMap<&String, BTreeMap<usize, QueueDepthSample>> = queue_metrics
+        .sources
+        .iter()
+        .filter_map(|(name, source)| {
+            source
+                .sample(&queue_metrics.skipped)
+                .map(|queues| (name, queues))
+        })
+        .collect();
+
+    let mut seq = serializer.serialize_map(Some(3))?;
+    seq.serialize_entry("skipped", &queue_metrics.skipped)?;
+    seq.serialize_entry("invalid_chains", &queue_metrics.invalid_chains)?;
+    seq.serialize_entry("devices", &devices)?;
+    seq.end()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::devices::virtio::queue::{Queue, VIRTQ_DESC_F_NEXT};
+    use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
+    use crate::devices::virtio::vsock::test_utils::{EventHandlerContext, TestContext};
+    use crate::vstate::memory::GuestAddress;
+
+    // Makes `count` descriptor chains of one descriptor available in the avail ring of `vq`.
+    fn make_available(vq: &VirtQueue, count: u16) {
+        let avail_idx = vq.avail.idx.get();
+        for i in 0..count {
+            let desc_index = (avail_idx + i) % vq.size();
+            vq.dtable[usize::from(desc_index)].set(0x1000, 0x100, 0, 0);
+            vq.avail.ring[usize::from(desc_index)].set(desc_index);
+        }
+        vq.avail.idx.set(avail_idx + count);
+    }
+
+    // Pops and adds to the used ring `count` descriptor chains of `queue`.
+    fn process(queue: &mut Queue, mem: &GuestMemoryMmap, count: u16) {
+        for _ in 0..count {
+            let index = queue.pop(mem).unwrap().index;
+            queue.add_used(mem, index, 0).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_queue_depth_sample() {
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let mut queue = vq.create_queue();
+
+        assert_eq!(queue.sample_depth(&mem), QueueDepthSample::default());
+
+        // 10 chains are made available, 4 of them are processed.
+        make_available(&vq, 10);
+        process(&mut queue, &mem, 4);
+        assert_eq!(
+            queue.sample_depth(&mem),
+            QueueDepthSample {
+                pending: 6,
+                unconsumed: None,
+                pending_hwm: 10,
+            }
+        );
+
+        // The high-watermark is reset by sampling.
+        process(&mut queue, &mem, 2);
+        assert_eq!(
+            queue.sample_depth(&mem),
+            QueueDepthSample {
+                pending: 4,
+                unconsumed: None,
+                pending_hwm: 6,
+            }
+        );
+
+        // The high-watermark covers the pending chains not seen by the device yet.
+        make_available(&vq, 5);
+        assert_eq!(
+            queue.sample_depth(&mem),
+            QueueDepthSample {
+                pending: 9,
+                unconsumed: None,
+                pending_hwm: 9,
+            }
+        );
+
+        // With notification suppression, the chains used but not yet seen by the driver are
+        // reported too.
+        queue.enable_notif_suppression();
+        process(&mut queue, &mem, 9);
+        assert!(queue.pop_or_enable_notification(&mem).is_none());
+        vq.avail.event.set(12);
+        assert_eq!(
+            queue.sample_depth(&mem),
+            QueueDepthSample {
+                pending: 0,
+                unconsumed: Some(3),
+                pending_hwm: 9,
+            }
+        );
+
+        make_available(&vq, 2);
+        assert!(queue.pop_or_enable_notification(&mem).is_some());
+        vq.avail.event.set(15);
+        assert_eq!(
+            queue.sample_depth(&mem),
+            QueueDepthSample {
+                pending: 1,
+                unconsumed: Some(0),
+                pending_hwm: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_flush_metrics() {
+        let ctx = TestContext::new();
+        let mut handler_ctx = ctx.create_event_handler_context();
+        handler_ctx.mock_activate(ctx.mem.clone());
+        let EventHandlerContext {
+            device,
+            guest_rxvq,
+            guest_txvq,
+            ..
+        } = handler_ctx;
+        let device: Arc<Mutex<dyn VirtioDevice>> = Arc::new(Mutex::new(device));
+
+        // The test context makes one descriptor chain available in both the RX and TX queues.
+        make_available(&guest_rxvq, 2);
+        make_available(&guest_txvq, 1);
+        process(&mut device.lock().unwrap().queues_mut()[1], &ctx.mem, 1);
+
+        // Other tests may flush the metrics concurrently, so sample a source which is not
+        // registered to check the values.
+        let source = QueueMetricsSource {
+            device: Arc::downgrade(&device),
+            mem: ctx.mem.clone(),
+        };
+        let skipped = SharedIncMetric::new();
+        let queues = source.sample(&skipped).unwrap();
+        assert_eq!(queues.len(), 3);
+        assert_eq!(
+            queues[&0],
+            QueueDepthSample {
+                pending: 3,
+                unconsumed: None,
+                pending_hwm: 3,
+            }
+        );
+        assert_eq!(
+            queues[&1],
+            QueueDepthSample {
+                pending: 1,
+                unconsumed: None,
+                pending_hwm: 2,
+            }
+        );
+        assert_eq!(queues[&2], QueueDepthSample::default());
+
+        // A busy device is skipped instead of blocking the flush.
+        {
+            let _locked_device = device.lock().unwrap();
+            assert!(source.sample(&skipped).is_none());
+        }
+        assert_eq!(skipped.count(), 1);
+
+        // Registered devices are reported per device and queue index.
+        QueueMetricsPerDevice::register("queue_metrics_test", &device, ctx.mem.clone());
+        let flushed = serde_json::to_value(FlushProxy).unwrap();
+        let queues = &flushed["devices"]["vsock_queue_metrics_test"];
+        assert_eq!(queues["0"]["pending"], 3);
+        assert_eq!(queues["1"]["pending"], 1);
+        assert!(queues["1"].get("unconsumed").is_none());
+        assert!(flushed["skipped"].is_u64());
+        assert!(flushed["invalid_chains"].is_u64());
+
+        // Devices which are gone are forgotten.
+        drop(device);
+        assert!(source.sample(&skipped).is_none());
+        serde_json::to_value(FlushProxy).unwrap();
+        assert!(!METRICS
+            .read()
+            .unwrap()
+            .sources
+            .contains_key("vsock_queue_metrics_test"));
+    }
+
+    #[test]
+    fn test_invalid_chains() {
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let mut queue = vq.create_queue();
+
+        // A descriptor linking to itself.
+        vq.dtable[0].set(0x1000, 0x100, VIRTQ_DESC_F_NEXT, 0);
+        vq.avail.ring[0].set(0);
+        vq.avail.idx.set(1);
+
+        // Other tests may reject chains concurrently.
+        let invalid_chains = METRICS.read().unwrap().invalid_chains.count();
+        let mut descriptors = queue.pop(&mem).unwrap().checked_iter();
+        descriptors.next().unwrap().unwrap();
+        descriptors.next().unwrap().unwrap_err();
+        assert!(METRICS.read().unwrap().invalid_chains.count() > invalid_chains);
+
+        // Two descriptors linking to each other, which the infallible walk cuts at the queue
+        // size.
+        vq.dtable[0].set(0x1000, 0x100, VIRTQ_DESC_F_NEXT, 1);
+        vq.dtable[1].set(0x2000, 0x100, VIRTQ_DESC_F_NEXT, 0);
+        vq.avail.ring[1].set(0);
+        vq.avail.idx.set(2);
+
+        let invalid_chains = METRICS.read().unwrap().invalid_chains.count();
+        assert_eq!(queue.pop(&mem).unwrap().into_iter().count(), 16);
+        assert!(METRICS.read().unwrap().invalid_chains.count() > invalid_chains);
+    }
+
+    struct FlushProxy;
+
+    impl Serialize for FlushProxy {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            flush_metrics(serializer)
+        }
+    }
+}
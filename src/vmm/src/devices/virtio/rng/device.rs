@@ -13,7 +13,7 @@ use super::metrics::METRICS;
 use super::{RNG_NUM_QUEUES, RNG_QUEUE};
 use crate::devices::virtio::device::{DeviceState, IrqTrigger, IrqType, VirtioDevice};
 use crate::devices::virtio::gen::virtio_rng::VIRTIO_F_VERSION_1;
-use crate::devices::virtio::iovec::IoVecBufferMut;
+use crate::devices::virtio::iovec::{ChainFit, IoVecBufferMut};
 use crate::devices::virtio::queue::{Queue, FIRECRACKER_MAX_QUEUE_SIZE};
 use crate::devices::virtio::{ActivateError, TYPE_RNG};
 use crate::devices::DeviceError;
@@ -23,6 +23,10 @@ use crate::vstate::memory::GuestMemoryMmap;
 
 pub const ENTROPY_DEV_ID: &str = "rng";
 
+/// Maximum number of random bytes provided for a single request, the device being allowed to
+/// provide fewer bytes than requested.
+pub const MAX_ENTROPY_REQUEST_LEN: usize = 64 * 1024;
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum EntropyError {
     /// Error while handling an Event file descriptor: {0}
@@ -45,6 +49,7 @@ pub struct Entropy {
     queues: Vec<Queue>,
     queue_events: Vec<EventFd>,
     irq_trigger: IrqTrigger,
+    needs_reset: bool,
 
     // Device specific fields
     rate_limiter: RateLimiter,
@@ -74,6 +79,7 @@ impl Entropy {
             queues,
             queue_events,
             irq_trigger,
+            needs_reset: false,
             rate_limiter,
         })
     }
@@ -123,25 +129,37 @@ impl Entropy {
         Ok(iovec.len())
     }
 
-    fn process_entropy_queue(&mut self) {
+    // Failures to parse a request or to get random bytes from the host are transient: the
+    // request is completed without any entropy, and the guest moves on to the next one.
+    fn process_entropy_queue(&mut self) -> Result<(), DeviceError> {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
 
         let mut used_any = false;
+        let rings = self.queues[RNG_QUEUE].rings();
         while let Some(desc) = self.queues[RNG_QUEUE].pop(mem) {
             let index = desc.index;
             METRICS.entropy_event_count.inc();
 
-            let bytes = match IoVecBufferMut::from_descriptor_chain(desc) {
-                Ok(mut iovec) => {
+            let request =
+                IoVecBufferMut::from_descriptor_chain_capped(desc, MAX_ENTROPY_REQUEST_LEN)
+                    .and_then(|(iovec, fit)| {
+                        iovec.check_queue_overlap(&rings)?;
+                        Ok((iovec, fit))
+                    });
+            let bytes = match request {
+                Ok((mut iovec, fit)) => {
                     debug!(
                         "entropy: guest request for {} bytes of entropy",
                         iovec.len()
                     );
+                    if fit == ChainFit::Truncated {
+                        debug!("entropy: request capped to {MAX_ENTROPY_REQUEST_LEN} bytes");
+                    }
 
                     // Check for available rate limiting budget.
-                    // If not enough budget is available, leave the request descriptor in the queue
-                    // to handle once we do have budget.
+                    // If not enough budget is available, leave the request descriptor in the
+                    // queue to handle once we do have budget.
                     if !Self::rate_limit_request(&mut self.rate_limiter, u64::from(iovec.len())) {
                         debug!("entropy: throttling entropy queue");
                         METRICS.entropy_rate_limiter_throttled.inc();
@@ -168,53 +186,56 @@ impl Entropy {
                     METRICS.entropy_bytes.add(bytes.into());
                 }
                 Err(err) => {
-                    error!("entropy: Could not add used descriptor to queue: {err}");
                     Self::rate_limit_replenish_request(&mut self.rate_limiter, bytes.into());
                     METRICS.entropy_event_fails.inc();
                     // If we are not able to add a buffer to the used queue, something
                     // is probably seriously wrong, so just stop processing additional
                     // buffers
-                    break;
+                    return Err(DeviceError::QueueError(err));
                 }
             }
         }
 
         if used_any {
-            self.signal_used_queue().unwrap_or_else(|err| {
-                error!("entropy: {err:?}");
-                METRICS.entropy_event_fails.inc()
-            });
+            self.signal_used_queue().map_err(|err| {
+                METRICS.entropy_event_fails.inc();
+                err
+            })?;
         }
+        Ok(())
     }
 
-    pub(crate) fn process_entropy_queue_event(&mut self) {
+    pub(crate) fn process_entropy_queue_event(&mut self) -> Result<(), DeviceError> {
         if let Err(err) = self.queue_events[RNG_QUEUE].read() {
-            error!("Failed to read entropy queue event: {err}");
             METRICS.entropy_event_fails.inc();
+            Err(DeviceError::EventFd(err))
         } else if !self.rate_limiter.is_blocked() {
             // We are not throttled, handle the entropy queue
-            self.process_entropy_queue();
+            self.process_entropy_queue()
         } else {
             METRICS.rate_limiter_event_count.inc();
+            Ok(())
         }
     }
 
-    pub(crate) fn process_rate_limiter_event(&mut self) {
+    pub(crate) fn process_rate_limiter_event(&mut self) -> Result<(), DeviceError> {
         METRICS.rate_limiter_event_count.inc();
         match self.rate_limiter.event_handler() {
             Ok(_) => {
                 // There might be enough budget now to process entropy requests.
-                self.process_entropy_queue();
+                self.process_entropy_queue()
             }
             Err(err) => {
-                error!("entropy: Failed to handle rate-limiter event: {err:?}");
                 METRICS.entropy_event_fails.inc();
+                Err(DeviceError::RateLimiter(err))
             }
         }
     }
 
     pub fn process_virtio_queues(&mut self) {
-        self.process_entropy_queue();
+        if let Err(err) = self.process_entropy_queue() {
+            error!("entropy: {err}");
+        }
     }
 
     pub fn rate_limiter(&self) -> &RateLimiter {
@@ -283,6 +304,10 @@ impl VirtioDevice for Entropy {
         self.device_state.is_activated()
     }
 
+    fn needs_reset(&self) -> bool {
+        self.needs_reset
+    }
+
     fn activate(&mut self, mem: GuestMemoryMmap) -> Result<(), ActivateError> {
         self.activate_event.write(1).map_err(|_| {
             METRICS.activate_fails.inc();
@@ -295,15 +320,17 @@ impl VirtioDevice for Entropy {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
     use std::time::Duration;
 
     use super::*;
     use crate::check_metric_after_block;
     use crate::devices::virtio::device::VirtioDevice;
-    use crate::devices::virtio::queue::VIRTQ_DESC_F_WRITE;
+    use crate::devices::virtio::queue::{QueueError, VIRTQ_DESC_F_WRITE};
     use crate::devices::virtio::test_utils::test::{
-        create_virtio_mem, VirtioTestDevice, VirtioTestHelper,
+        check_error_handling, create_virtio_mem, VirtioTestDevice, VirtioTestHelper,
     };
+    use crate::utilities::test_utils::single_region_mem;
 
     impl VirtioTestDevice for Entropy {
         fn set_queues(&mut self, queues: Vec<Queue>) {
@@ -493,6 +520,41 @@ mod tests {
         assert_eq!(METRICS.host_rng_fails.count(), host_rng_fails);
     }
 
+    #[test]
+    fn test_entropy_request_cap() {
+        let mem = single_region_mem(4 * MAX_ENTROPY_REQUEST_LEN);
+        let mut th = VirtioTestHelper::<Entropy>::new(&mem, default_entropy());
+        th.activate_device(&mem);
+
+        // Requests past the cap get as many bytes as the cap, whether it lands on a descriptor
+        // boundary or in the middle of a descriptor.
+        let max_len = u32::try_from(MAX_ENTROPY_REQUEST_LEN).unwrap();
+        th.add_desc_chain(
+            RNG_QUEUE,
+            0,
+            &[
+                (0, max_len, VIRTQ_DESC_F_WRITE),
+                (1, 64, VIRTQ_DESC_F_WRITE),
+            ],
+        );
+        th.add_desc_chain(
+            RNG_QUEUE,
+            0,
+            &[
+                (2, max_len - 64, VIRTQ_DESC_F_WRITE),
+                (3, 128, VIRTQ_DESC_F_WRITE),
+            ],
+        );
+        // Requests within the cap are served entirely.
+        th.add_desc_chain(RNG_QUEUE, 0, &[(4, max_len, VIRTQ_DESC_F_WRITE)]);
+        assert_eq!(th.emulate_for_msec(100).unwrap(), 1);
+
+        let vq = th.virtqueue(RNG_QUEUE);
+        vq.check_used_elem(0, 0, max_len);
+        vq.check_used_elem(1, 2, max_len);
+        vq.check_used_elem(2, 4, max_len);
+    }
+
     #[test]
     fn test_bad_rate_limiter_event() {
         let mem = create_virtio_mem();
@@ -508,6 +570,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_error_handling() {
+        let mem = create_virtio_mem();
+        let mut entropy = default_entropy();
+        entropy.activate(mem).unwrap();
+
+        // The rate limiter has no budget to replenish.
+        let transient = entropy.process_rate_limiter_event().unwrap_err();
+        check_error_handling(
+            Arc::new(Mutex::new(entropy)),
+            [
+                transient,
+                DeviceError::FailedSignalingIrq(io::Error::from_raw_os_error(libc::EAGAIN)),
+                DeviceError::QueueError(QueueError::DescIndexOutOfBounds(256)),
+            ],
+        );
+    }
+
     #[test]
     fn test_bandwidth_rate_limiter() {
         let mem = create_virtio_mem();
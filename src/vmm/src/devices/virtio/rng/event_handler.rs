@@ -5,7 +5,7 @@ use event_manager::{EventOps, Events, MutEventSubscriber};
 use utils::epoll::EventSet;
 
 use super::{Entropy, RNG_QUEUE};
-use crate::devices::virtio::device::VirtioDevice;
+use crate::devices::virtio::device::{DeviceErrorHandler, VirtioDevice};
 use crate::logger::{error, warn};
 
 impl Entropy {
@@ -59,6 +59,29 @@ impl Entropy {
     }
 }
 
+impl DeviceErrorHandler for Entropy {
+    fn set_needs_reset(&mut self) {
+        self.needs_reset = true;
+    }
+
+    fn unregister_runtime_events(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.remove(Events::with_data(
+            &self.queue_events()[RNG_QUEUE],
+            Self::PROCESS_ENTROPY_QUEUE,
+            EventSet::IN,
+        )) {
+            error!("entropy: Failed to un-register queue event: {err}");
+        }
+        if let Err(err) = ops.remove(Events::with_data(
+            self.rate_limiter(),
+            Self::PROCESS_RATE_LIMITER,
+            EventSet::IN,
+        )) {
+            error!("entropy: Failed to un-register rate-limiter event: {err}");
+        }
+    }
+}
+
 impl MutEventSubscriber for Entropy {
     fn init(&mut self, ops: &mut event_manager::EventOps) {
         // This function can be called during different points in the device lifetime:
@@ -86,13 +109,20 @@ impl MutEventSubscriber for Entropy {
             return;
         }
 
-        match source {
-            Self::PROCESS_ACTIVATE => self.process_activate_event(ops),
+        let result = match source {
+            Self::PROCESS_ACTIVATE => {
+                self.process_activate_event(ops);
+                Ok(())
+            }
             Self::PROCESS_ENTROPY_QUEUE => self.process_entropy_queue_event(),
             Self::PROCESS_RATE_LIMITER => self.process_rate_limiter_event(),
             _ => {
                 warn!("entropy: Unknown event received: {source}");
+                Ok(())
             }
+        };
+        if let Err(err) = result {
+            self.handle_error(ops, err);
         }
     }
 }
@@ -83,6 +83,7 @@ mod tests {
     use crate::devices::virtio::device::VirtioDevice;
     use crate::devices::virtio::rng::device::ENTROPY_DEV_ID;
     use crate::devices::virtio::test_utils::test::create_virtio_mem;
+    use crate::rate_limiter::TokenType;
     use crate::snapshot::Snapshot;
 
     #[test]
@@ -109,4 +110,29 @@ mod tests {
             entropy.interrupt_status().load(Ordering::Relaxed)
         );
     }
+
+    #[test]
+    fn test_persistence_blocked_rate_limiter() {
+        let mut rate_limiter = RateLimiter::new(0, 0, 0, 1, 0, 100_000).unwrap();
+        assert!(rate_limiter.consume(1, TokenType::Ops));
+        assert!(!rate_limiter.consume(1, TokenType::Ops));
+        let entropy = Entropy::new(rate_limiter).unwrap();
+
+        let mut mem = vec![0u8; 4096];
+        Snapshot::serialize(&mut mem.as_mut_slice(), &entropy.save()).unwrap();
+        let restored = Entropy::restore(
+            EntropyConstructorArgs(create_virtio_mem()),
+            &Snapshot::deserialize(&mut mem.as_slice()).unwrap(),
+        )
+        .unwrap();
+
+        // The guest does not get a fresh budget by being snapshotted.
+        assert!(restored.rate_limiter().is_blocked());
+        assert!(restored
+            .rate_limiter()
+            .ops()
+            .unwrap()
+            .partial_eq(entropy.rate_limiter().ops().unwrap()));
+        assert!(restored.rate_limiter().bandwidth().is_none());
+    }
 }
@@ -29,6 +29,14 @@ pub fn default_mem() -> GuestMemoryMmap {
     single_region_mem(0x10000)
 }
 
+/// Turns on the verification of the guest memory written by the devices processed on the current
+/// thread, when built with the `memory-guards` feature: the devices must not write next to the
+/// buffers they are given.
+pub fn enable_write_canaries() {
+    #[cfg(feature = "memory-guards")]
+    crate::devices::virtio::canary::enable();
+}
+
 #[derive(Debug)]
 pub struct InputData {
     pub data: Vec<u8>,
@@ -319,10 +327,15 @@ pub(crate) mod test {
 
     use event_manager::{EventManager, MutEventSubscriber, SubscriberId, SubscriberOps};
 
-    use crate::devices::virtio::device::VirtioDevice;
+    use crate::devices::virtio::device::{DeviceErrorHandler, IrqType, VirtioDevice};
     use crate::devices::virtio::net::MAX_BUFFER_SIZE;
     use crate::devices::virtio::queue::{Queue, VIRTQ_DESC_F_NEXT};
     use crate::devices::virtio::test_utils::{VirtQueue, VirtqDesc};
+    use crate::devices::{DeviceError, ErrorSeverity};
+    use crate::events::{
+        EventSubscription, StreamItem, VmmEvent, DEFAULT_SUBSCRIBER_CAPACITY, EVENTS,
+    };
+    use crate::logger::{IncMetric, SharedIncMetric, METRICS};
     use crate::utilities::test_utils::single_region_mem;
     use crate::vstate::memory::{Address, GuestAddress, GuestMemoryMmap};
 
@@ -330,6 +343,100 @@ pub(crate) mod test {
         single_region_mem(MAX_BUFFER_SIZE)
     }
 
+    // Checks whether a `device_error` event was published for `message`, with `severity`.
+    fn device_error_published(
+        events: &EventSubscription,
+        message: &str,
+        severity: ErrorSeverity,
+    ) -> bool {
+        events.take().into_iter().any(|item| {
+            matches!(
+                item,
+                StreamItem::Event(record) if matches!(
+                    &record.event,
+                    VmmEvent::DeviceError { message: m, severity: s, .. }
+                        if m == message && *s == severity
+                )
+            )
+        })
+    }
+
+    // Hands `err` to the error handler of `device`, and checks that it was counted in `metric`.
+    fn handle_error<T>(
+        event_manager: &mut EventManager<Arc<Mutex<T>>>,
+        id: SubscriberId,
+        device: &Arc<Mutex<T>>,
+        err: DeviceError,
+        metric: &SharedIncMetric,
+    ) where
+        T: DeviceErrorHandler + MutEventSubscriber,
+    {
+        // Other tests may hit device errors concurrently, so the metric can grow by more.
+        let before = metric.count();
+        let mut ops = event_manager.event_ops(id).unwrap();
+        device.lock().unwrap().handle_error(&mut ops, err);
+        assert!(metric.count() > before);
+    }
+
+    /// Checks that the event handler of `device` handles `errors` according to their severity.
+    ///
+    /// `device` must be activated, and `errors` must hold a transient, a degraded and a fatal
+    /// error, in this order.
+    pub fn check_error_handling<T>(device: Arc<Mutex<T>>, errors: [DeviceError; 3])
+    where
+        T: DeviceErrorHandler + MutEventSubscriber,
+    {
+        let [transient, degraded, fatal] = errors;
+        assert_eq!(transient.severity(), ErrorSeverity::Transient);
+        assert_eq!(degraded.severity(), ErrorSeverity::Degraded);
+        assert_eq!(fatal.severity(), ErrorSeverity::Fatal);
+
+        let mut event_manager = EventManager::new().unwrap();
+        let id = event_manager.add_subscriber(device.clone());
+        let events = EVENTS.subscribe(DEFAULT_SUBSCRIBER_CAPACITY).unwrap();
+
+        // Transient errors are only counted.
+        let message = transient.to_string();
+        let metric = &METRICS.device_errors.transient;
+        handle_error(&mut event_manager, id, &device, transient, metric);
+        assert!(!device_error_published(
+            &events,
+            &message,
+            ErrorSeverity::Transient
+        ));
+        assert!(!device.lock().unwrap().needs_reset());
+
+        // Degraded errors are also published, and the device keeps working.
+        let message = degraded.to_string();
+        let metric = &METRICS.device_errors.degraded;
+        handle_error(&mut event_manager, id, &device, degraded, metric);
+        assert!(device_error_published(
+            &events,
+            &message,
+            ErrorSeverity::Degraded
+        ));
+        assert!(!device.lock().unwrap().needs_reset());
+
+        // Fatal errors stop the device.
+        let message = fatal.to_string();
+        let metric = &METRICS.device_errors.fatal;
+        handle_error(&mut event_manager, id, &device, fatal, metric);
+        assert!(device_error_published(
+            &events,
+            &message,
+            ErrorSeverity::Fatal
+        ));
+        let device = device.lock().unwrap();
+        assert!(device.needs_reset());
+        assert!(device.interrupt_trigger().has_pending_irq(IrqType::Config));
+        // The queue events of the device are not processed anymore.
+        for queue_evt in device.queue_events() {
+            queue_evt.write(1).unwrap();
+        }
+        drop(device);
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 0);
+    }
+
     /// Provides functionality necessary for testing a VirtIO device with
     /// [`VirtioTestHelper`](VirtioTestHelper)
     pub trait VirtioTestDevice: VirtioDevice {
@@ -419,6 +526,11 @@ pub(crate) mod test {
             assert_eq!(ev_count, 1);
         }
 
+        /// Get the virtqueue backing one of the device's queues, e.g. to check its used ring
+        pub fn virtqueue(&self, queue: usize) -> &VirtQueue<'a> {
+            &self.virtqueues[queue]
+        }
+
         /// Get the start of the data region
         ///
         /// The first address that can be used for data in the guest memory mmap
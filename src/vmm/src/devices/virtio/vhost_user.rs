@@ -6,8 +6,11 @@
 
 use std::os::fd::AsRawFd;
 use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
 
+use serde::Serialize;
 use utils::eventfd::EventFd;
+use utils::time::{get_time_us, ClockType};
 use vhost::vhost_user::message::*;
 use vhost::vhost_user::{Frontend, VhostUserFrontend};
 use vhost::{Error as VhostError, VhostBackend, VhostUserMemoryRegionInfo, VringConfigData};
@@ -24,8 +27,12 @@ pub enum VhostUserError {
     AvailAddress(GuestMemoryError),
     /// Failed to connect to UDS Unix stream: {0}
     Connect(#[from] std::io::Error),
+    /// Failed to set the timeout of the control socket: {0}
+    ControlSocketTimeout(std::io::Error),
     /// Invalid descriptor table address
     DescriptorTableAddress(GuestMemoryError),
+    /// Backend did not reply to the ping within {0} ms
+    PingTimeout(u128),
     /// Get features failed: {0}
     VhostUserGetFeatures(VhostError),
     /// Get protocol features failed: {0}
@@ -261,19 +268,54 @@ impl VhostUserHandleBackend for Frontend {
     }
 }
 
+/// Summary of the negotiation of a vhost-user device with its backend.
+///
+/// Timestamps are the wall clock time of each step, in microseconds since the epoch.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct VhostUserNegotiation {
+    /// Virtio features advertised by the backend.
+    pub backend_features: u64,
+    /// Protocol features advertised by the backend, if protocol features are negotiated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend_protocol_features: Option<u64>,
+    /// Virtio features supported by both the frontend and the backend.
+    pub negotiated_features: u64,
+    /// Protocol features supported by both the frontend and the backend.
+    pub negotiated_protocol_features: u64,
+    /// Virtio features acked by the guest driver, set on the backend at activation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver_features: Option<u64>,
+    /// Sizes of the queues set up on the backend at activation.
+    pub queue_sizes: Vec<u16>,
+    /// Number of guest memory regions shared with the backend at activation.
+    pub memory_regions: usize,
+    /// When the frontend connected to the backend.
+    pub connected_at_us: u64,
+    /// When the features were negotiated with the backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub negotiated_at_us: Option<u64>,
+    /// When the memory and the queues were set up on the backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub set_up_at_us: Option<u64>,
+}
+
 pub type VhostUserHandle = VhostUserHandleImpl<Frontend>;
 
 /// vhost-user socket handle
-#[derive(Clone)]
 pub struct VhostUserHandleImpl<T: VhostUserHandleBackend> {
     pub vu: T,
     pub socket_path: String,
+    // Same socket as the one of `vu`, kept to set timeouts on the requests to the backend.
+    pub control_socket: UnixStream,
+    pub negotiation: VhostUserNegotiation,
 }
 
 impl<T: VhostUserHandleBackend> std::fmt::Debug for VhostUserHandleImpl<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("VhostUserHandle")
             .field("socket_path", &self.socket_path)
+            .field("control_socket", &self.control_socket)
+            .field("negotiation", &self.negotiation)
             .finish()
     }
 }
@@ -283,6 +325,7 @@ impl<T: VhostUserHandleBackend> VhostUserHandleImpl<T> {
     /// owner of the session.
     pub fn new(socket_path: &str, num_queues: u64) -> Result<Self, VhostUserError> {
         let stream = UnixStream::connect(socket_path).map_err(VhostUserError::Connect)?;
+        let control_socket = stream.try_clone().map_err(VhostUserError::Connect)?;
 
         let vu = T::from_stream(stream, num_queues);
         vu.set_owner().map_err(VhostUserError::VhostUserSetOwner)?;
@@ -290,14 +333,45 @@ impl<T: VhostUserHandleBackend> VhostUserHandleImpl<T> {
         Ok(Self {
             vu,
             socket_path: socket_path.to_string(),
+            control_socket,
+            negotiation: VhostUserNegotiation {
+                connected_at_us: get_time_us(ClockType::Real),
+                ..Default::default()
+            },
         })
     }
 
     /// Set vhost-user features to the backend.
-    pub fn set_features(&self, features: u64) -> Result<(), VhostUserError> {
+    pub fn set_features(&mut self, features: u64) -> Result<(), VhostUserError> {
         self.vu
             .set_features(features)
-            .map_err(VhostUserError::VhostUserSetFeatures)
+            .map_err(VhostUserError::VhostUserSetFeatures)?;
+        self.negotiation.driver_features = Some(features);
+        Ok(())
+    }
+
+    /// Checks that the backend replies to a `GET_FEATURES` request within `timeout`, returning
+    /// the time it took to reply.
+    ///
+    /// The request does not change the state of the backend, so the queues keep being processed.
+    /// A reply arriving after `timeout` is left on the socket, and fails the next request to the
+    /// backend.
+    pub fn ping(&self, timeout: Duration) -> Result<Duration, VhostUserError> {
+        self.control_socket
+            .set_read_timeout(Some(timeout))
+            .map_err(VhostUserError::ControlSocketTimeout)?;
+        let start = Instant::now();
+        let result = self.vu.get_features();
+        let latency = start.elapsed();
+        self.control_socket
+            .set_read_timeout(None)
+            .map_err(VhostUserError::ControlSocketTimeout)?;
+
+        match result {
+            Ok(_) => Ok(latency),
+            Err(_) if latency >= timeout => Err(VhostUserError::PingTimeout(timeout.as_millis())),
+            Err(err) => Err(VhostUserError::VhostUserGetFeatures(err)),
+        }
     }
 
     /// Set vhost-user protocol features to the backend.
@@ -337,14 +411,20 @@ impl<T: VhostUserHandleBackend> VhostUserHandleImpl<T> {
             .map_err(VhostUserError::VhostUserGetFeatures)?;
         let acked_features = avail_features & backend_features;
 
-        let acked_protocol_features =
-            // If frontend can negotiate protocol features.
+        // If frontend can negotiate protocol features.
+        let backend_protocol_features =
             if acked_features & VhostUserVirtioFeatures::PROTOCOL_FEATURES.bits() != 0 {
-                let backend_protocol_features = self
-                    .vu
-                    .get_protocol_features()
-                    .map_err(VhostUserError::VhostUserGetProtocolFeatures)?;
+                Some(
+                    self.vu
+                        .get_protocol_features()
+                        .map_err(VhostUserError::VhostUserGetProtocolFeatures)?,
+                )
+            } else {
+                None
+            };
 
+        let acked_protocol_features = match backend_protocol_features {
+            Some(backend_protocol_features) => {
                 let acked_protocol_features = avail_protocol_features & backend_protocol_features;
 
                 self.vu
@@ -352,19 +432,25 @@ impl<T: VhostUserHandleBackend> VhostUserHandleImpl<T> {
                     .map_err(VhostUserError::VhostUserSetProtocolFeatures)?;
 
                 acked_protocol_features
-            } else {
-                VhostUserProtocolFeatures::empty()
-            };
+            }
+            None => VhostUserProtocolFeatures::empty(),
+        };
 
         if acked_protocol_features.contains(VhostUserProtocolFeatures::REPLY_ACK) {
             self.vu.set_hdr_flags(VhostUserHeaderFlag::NEED_REPLY);
         }
 
+        self.negotiation.backend_features = backend_features;
+        self.negotiation.backend_protocol_features = backend_protocol_features.map(|f| f.bits());
+        self.negotiation.negotiated_features = acked_features;
+        self.negotiation.negotiated_protocol_features = acked_protocol_features.bits();
+        self.negotiation.negotiated_at_us = Some(get_time_us(ClockType::Real));
+
         Ok((acked_features, acked_protocol_features.bits()))
     }
 
     /// Update guest memory table to the backend.
-    fn update_mem_table(&self, mem: &GuestMemoryMmap) -> Result<(), VhostUserError> {
+    fn update_mem_table(&mut self, mem: &GuestMemoryMmap) -> Result<(), VhostUserError> {
         let mut regions: Vec<VhostUserMemoryRegionInfo> = Vec::new();
 
         for region in mem.iter() {
@@ -390,6 +476,7 @@ impl<T: VhostUserHandleBackend> VhostUserHandleImpl<T> {
         self.vu
             .set_mem_table(regions.as_slice())
             .map_err(VhostUserError::VhostUserSetMemTable)?;
+        self.negotiation.memory_regions = regions.len();
 
         Ok(())
     }
@@ -454,6 +541,12 @@ impl<T: VhostUserHandleBackend> VhostUserHandleImpl<T> {
                 .map_err(VhostUserError::VhostUserSetVringEnable)?;
         }
 
+        self.negotiation.queue_sizes = queues
+            .iter()
+            .map(|(_, queue, _)| queue.actual_size())
+            .collect();
+        self.negotiation.set_up_at_us = Some(get_time_us(ClockType::Real));
+
         Ok(())
     }
 }
@@ -466,6 +559,7 @@ mod tests {
 
     use super::*;
     use crate::utilities::test_utils::create_tmp_socket;
+    use crate::vmm_config::machine_config::HugePageConfig;
     use crate::vstate::memory::{FileOffset, GuestAddress, GuestMemoryExtension};
 
     #[test]
@@ -512,6 +606,16 @@ mod tests {
         );
         assert_eq!(vuh.vu.max_queue_num, max_queue_num);
         assert!(unsafe { *vuh.vu.is_owner.get() });
+
+        // The control socket is the socket connected to the backend, and the connection is
+        // recorded.
+        assert_eq!(
+            vuh.control_socket.peer_addr().unwrap().as_pathname(),
+            vuh.vu.sock.peer_addr().unwrap().as_pathname(),
+        );
+        assert_ne!(vuh.negotiation.connected_at_us, 0);
+        assert_eq!(vuh.negotiation.negotiated_at_us, None);
+        assert_eq!(vuh.negotiation.set_up_at_us, None);
     }
 
     #[test]
@@ -528,12 +632,15 @@ mod tests {
         }
 
         // VhostUserHandleImpl can correctly set backend features.
-        let vuh = VhostUserHandleImpl {
+        let mut vuh = VhostUserHandleImpl {
             vu: MockFrontend { features: 0.into() },
             socket_path: "".to_string(),
+            control_socket: UnixStream::pair().unwrap().0,
+            negotiation: VhostUserNegotiation::default(),
         };
         vuh.set_features(0x69).unwrap();
         assert_eq!(unsafe { *vuh.vu.features.get() }, 0x69);
+        assert_eq!(vuh.negotiation.driver_features, Some(0x69));
     }
 
     #[test]
@@ -563,6 +670,8 @@ mod tests {
                 hdr_flags: std::cell::UnsafeCell::new(VhostUserHeaderFlag::empty()),
             },
             socket_path: "".to_string(),
+            control_socket: UnixStream::pair().unwrap().0,
+            negotiation: VhostUserNegotiation::default(),
         };
 
         // No protocol features are set if acked_features do not have PROTOCOL_FEATURES bit
@@ -648,6 +757,8 @@ mod tests {
                 hdr_flags: std::cell::UnsafeCell::new(VhostUserHeaderFlag::empty()),
             },
             socket_path: "".to_string(),
+            control_socket: UnixStream::pair().unwrap().0,
+            negotiation: VhostUserNegotiation::default(),
         };
 
         // If nothing is available, nothing is negotiated
@@ -683,6 +794,11 @@ mod tests {
             unsafe { &*vuh.vu.hdr_flags.get() }.bits(),
             VhostUserHeaderFlag::empty().bits()
         );
+        // The backend is not asked for its protocol features.
+        assert_eq!(vuh.negotiation.backend_features, avail_features.bits());
+        assert_eq!(vuh.negotiation.backend_protocol_features, None);
+        assert_eq!(vuh.negotiation.negotiated_features, avail_features.bits());
+        assert_eq!(vuh.negotiation.negotiated_protocol_features, 0);
 
         // If PROTOCOL_FEATURES is negotiated, but REPLY_ACK is not, headers are not set
         let avail_features = VhostUserVirtioFeatures::all();
@@ -707,6 +823,18 @@ mod tests {
             unsafe { &*vuh.vu.hdr_flags.get() }.bits(),
             VhostUserHeaderFlag::empty().bits()
         );
+        // Both what the backend advertised and what was negotiated are recorded.
+        assert_eq!(vuh.negotiation.backend_features, avail_features.bits());
+        assert_eq!(
+            vuh.negotiation.backend_protocol_features,
+            Some(backend_protocol_features.bits())
+        );
+        assert_eq!(vuh.negotiation.negotiated_features, avail_features.bits());
+        assert_eq!(
+            vuh.negotiation.negotiated_protocol_features,
+            avail_protocol_features.bits()
+        );
+        assert!(vuh.negotiation.negotiated_at_us.is_some());
 
         // If PROTOCOL_FEATURES and REPLY_ACK are negotiated
         let avail_features = VhostUserVirtioFeatures::all();
@@ -747,11 +875,13 @@ mod tests {
             }
         }
 
-        let vuh = VhostUserHandleImpl {
+        let mut vuh = VhostUserHandleImpl {
             vu: MockFrontend {
                 regions: std::cell::UnsafeCell::new(vec![]),
             },
             socket_path: "".to_string(),
+            control_socket: UnixStream::pair().unwrap().0,
+            negotiation: VhostUserNegotiation::default(),
         };
 
         let region_size = 0x10000;
@@ -771,7 +901,9 @@ mod tests {
             ),
         ];
 
-        let guest_memory = GuestMemoryMmap::from_raw_regions_file(regions, false, false).unwrap();
+        let guest_memory =
+            GuestMemoryMmap::from_raw_regions_file(regions, false, false, HugePageConfig::None)
+                .unwrap();
 
         vuh.update_mem_table(&guest_memory).unwrap();
 
@@ -787,6 +919,7 @@ mod tests {
             })
             .collect::<Vec<_>>();
 
+        assert_eq!(vuh.negotiation.memory_regions, 2);
         for (region, expected) in (unsafe { &*vuh.vu.regions.get() })
             .iter()
             .zip(expected_regions)
@@ -878,6 +1011,8 @@ mod tests {
                 vrings: std::cell::UnsafeCell::new(vec![]),
             },
             socket_path: "".to_string(),
+            control_socket: UnixStream::pair().unwrap().0,
+            negotiation: VhostUserNegotiation::default(),
         };
 
         let region_size = 0x10000;
@@ -889,7 +1024,9 @@ mod tests {
             region_size,
         )];
 
-        let guest_memory = GuestMemoryMmap::from_raw_regions_file(regions, false, false).unwrap();
+        let guest_memory =
+            GuestMemoryMmap::from_raw_regions_file(regions, false, false, HugePageConfig::None)
+                .unwrap();
 
         let queue = Queue::new(69);
         let event_fd = EventFd::new(0).unwrap();
@@ -920,6 +1057,10 @@ mod tests {
             enable: true,
         };
 
+        assert_eq!(vuh.negotiation.memory_regions, 1);
+        assert_eq!(vuh.negotiation.queue_sizes, vec![0]);
+        assert!(vuh.negotiation.set_up_at_us.is_some());
+
         let result = unsafe { &*vuh.vu.vrings.get() };
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].index, expected_config.index);
@@ -954,4 +1095,59 @@ mod tests {
         assert_eq!(result[0].kick, expected_config.kick);
         assert_eq!(result[0].enable, expected_config.enable);
     }
+
+    #[test]
+    fn test_ping() {
+        struct MockFrontend {
+            sock: UnixStream,
+        }
+
+        impl VhostUserHandleBackend for MockFrontend {
+            fn from_stream(sock: UnixStream, _max_queue_num: u64) -> Self {
+                Self { sock }
+            }
+
+            fn set_owner(&self) -> Result<(), vhost::Error> {
+                Ok(())
+            }
+
+            fn get_features(&self) -> Result<u64, vhost::Error> {
+                let mut reply = [0u8; 8];
+                std::io::Read::read_exact(&mut &self.sock, &mut reply)
+                    .map_err(vhost::Error::SocketError)?;
+                Ok(u64::from_le_bytes(reply))
+            }
+        }
+
+        let (_tmp_dir, tmp_socket_path) = create_tmp_socket();
+        let vuh = VhostUserHandleImpl::<MockFrontend>::new(&tmp_socket_path, 1).unwrap();
+
+        // The backend never replies, so the ping times out.
+        let timeout = Duration::from_millis(50);
+        assert!(matches!(
+            vuh.ping(timeout),
+            Err(VhostUserError::PingTimeout(50))
+        ));
+        // The timeout is removed once the ping is over.
+        assert_eq!(vuh.control_socket.read_timeout().unwrap(), None);
+
+        // The backend replies in time.
+        let (sock, mut backend) = UnixStream::pair().unwrap();
+        let vuh = VhostUserHandleImpl {
+            control_socket: sock.try_clone().unwrap(),
+            vu: MockFrontend { sock },
+            socket_path: "".to_string(),
+            negotiation: VhostUserNegotiation::default(),
+        };
+        std::io::Write::write_all(&mut backend, &0x69u64.to_le_bytes()).unwrap();
+        assert!(vuh.ping(Duration::from_secs(5)).unwrap() < Duration::from_secs(5));
+        assert_eq!(vuh.control_socket.read_timeout().unwrap(), None);
+
+        // The backend closed the socket.
+        drop(backend);
+        assert!(matches!(
+            vuh.ping(Duration::from_secs(5)),
+            Err(VhostUserError::VhostUserGetFeatures(_))
+        ));
+    }
 }
@@ -83,25 +83,78 @@ use std::os::unix::io::{AsRawFd, RawFd};
 use std::time::{Duration, Instant};
 
 use log::{debug, error, info, warn};
+use serde::Serialize;
 use utils::epoll::EventSet;
 use utils::wrap_usize_to_u32;
 use vm_memory::io::{ReadVolatile, WriteVolatile};
-use vm_memory::GuestMemoryError;
+use vm_memory::{GuestMemoryError, VolatileMemoryError, VolatileSlice};
 
 use super::super::defs::uapi;
 use super::super::packet::VsockPacket;
 use super::super::{VsockChannel, VsockEpollListener, VsockError};
 use super::txbuf::TxBuf;
 use super::{defs, ConnState, PendingRx, PendingRxSet, VsockCsmError};
+use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut, Transferred};
 use crate::devices::virtio::vsock::metrics::METRICS;
+use crate::devices::virtio::vsock::persist::{
+    VsockConnectionConstructorArgs, VsockConnectionState,
+};
 use crate::logger::IncMetric;
+use crate::snapshot::Persist;
 
 /// Trait that vsock connection backends need to implement.
 ///
 /// Used as an alias for `ReadVolatile + Write + WriteVolatile + AsRawFd`
 /// (sadly, trait aliases are not supported,
 /// <https://github.com/rust-lang/rfcs/pull/1733#issuecomment-243840014>).
-pub trait VsockConnectionBackend: ReadVolatile + Write + WriteVolatile + AsRawFd {}
+///
+/// Packet data is moved through `send_iovec` and `recv_iovec`, which go through the memory
+/// regions of the packet one at a time by default, and can be overridden by backends able to
+/// transfer all of them at once. The stream blocking part way through a packet is not an error:
+/// the bytes transferred until then are returned, and a `WouldBlock` error only when there are
+/// none.
+pub trait VsockConnectionBackend: ReadVolatile + Write + WriteVolatile + AsRawFd {
+    /// Writes up to `len` bytes of `buf` starting at `offset` to the stream, returning the number
+    /// of bytes written.
+    fn send_iovec(
+        &mut self,
+        buf: &IoVecBuffer,
+        offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError>
+    where
+        Self: Sized,
+    {
+        buf.try_read_volatile_at(self, offset, len)
+            .and_then(progress_or_would_block)
+    }
+
+    /// Reads up to `len` bytes from the stream into `buf` starting at `offset`, returning the
+    /// number of bytes read.
+    fn recv_iovec(
+        &mut self,
+        buf: &mut IoVecBufferMut,
+        offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError>
+    where
+        Self: Sized,
+    {
+        buf.try_write_volatile_at(self, offset, len)
+            .and_then(progress_or_would_block)
+    }
+}
+
+// Returns the number of bytes transferred to or from a stream, or a `WouldBlock` error if it
+// blocked before any, as 0 bytes read means the stream was closed.
+fn progress_or_would_block(transferred: Transferred) -> Result<usize, VolatileMemoryError> {
+    match transferred {
+        Transferred::Blocked(0) => Err(VolatileMemoryError::IOError(std::io::Error::from(
+            ErrorKind::WouldBlock,
+        ))),
+        transferred => Ok(transferred.bytes()),
+    }
+}
 
 /// A self-managing connection object, that handles communication between a guest-side AF_VSOCK
 /// socket and a host-side `ReadVolatile + Write + WriteVolatile + AsRawFd` stream.
@@ -139,6 +192,41 @@ pub struct VsockConnection<S: VsockConnectionBackend> {
     /// Instant when this connection should be scheduled for immediate termination, due to some
     /// timeout condition having been fulfilled.
     expiry: Option<Instant>,
+    /// Total number of bytes sent to the peer. Unlike `rx_cnt`, this doesn't wrap around.
+    rx_bytes: u64,
+    /// Total number of bytes written to `self.stream`. Unlike `fwd_cnt`, this doesn't wrap
+    /// around.
+    tx_bytes: u64,
+    /// Number of times data couldn't be sent to the peer, for lack of credit.
+    credit_stalls: u64,
+    /// Host data read ahead from `self.stream`, to be sent to the peer before any more is read
+    /// from the stream. This only holds the data read by `drain_stream()`, or restored from a
+    /// snapshot.
+    rx_backlog: Vec<u8>,
+    /// Whether the last call to `drain_stream()` read all the data available on the host
+    /// stream, so that the connection can be kept in a snapshot.
+    drained: bool,
+}
+
+/// Snapshot of a vsock connection, as reported by the connection table diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VsockConnectionInfo {
+    /// The local (host) port.
+    pub local_port: u32,
+    /// The peer (guest) port.
+    pub peer_port: u32,
+    /// The current connection state.
+    pub state: ConnState,
+    /// Number of bytes sent to the guest.
+    pub rx_bytes: u64,
+    /// Number of bytes forwarded from the guest to the host stream.
+    pub tx_bytes: u64,
+    /// Number of bytes that can be sent to the guest before it has to free up buffer space.
+    pub peer_credit: u32,
+    /// Number of guest bytes buffered, waiting for the host stream to become writable.
+    pub tx_buf_bytes: u32,
+    /// Number of times data couldn't be sent to the guest, for lack of credit.
+    pub credit_stalls: u64,
 }
 
 impl<S> VsockChannel for VsockConnection<S>
@@ -209,6 +297,8 @@ where
             // Oh wait, before we start bringing in the big data, can our peer handle receiving so
             // much bytey goodness?
             if self.need_credit_update_from_peer() {
+                self.credit_stalls += 1;
+                METRICS.credit_stalls.inc();
                 self.last_fwd_cnt_to_peer = self.fwd_cnt;
                 pkt.set_op(uapi::VSOCK_OP_CREDIT_REQUEST);
                 return Ok(());
@@ -218,8 +308,13 @@ where
             // the peer available buffer space.
             let max_len = std::cmp::min(pkt.buf_size(), self.peer_avail_credit());
 
+            // Data read ahead from the stream goes first, so that the byte stream stays in order.
+            if !self.rx_backlog.is_empty() {
+                return self.recv_backlog(pkt, max_len);
+            }
+
             // Read data from the stream straight to the RX buffer, for maximum throughput.
-            match pkt.read_at_offset_from(&mut self.stream, 0, max_len) {
+            match pkt.recv_at_offset_from(&mut self.stream, 0, max_len) {
                 Ok(read_cnt) => {
                     if read_cnt == 0 {
                         // A 0-length read means the host stream was closed down. In that case,
@@ -239,6 +334,7 @@ where
                         // by self.peer_avail_credit(), a u32 internally.
                         pkt.set_op(uapi::VSOCK_OP_RW)
                             .set_len(u32::try_from(read_cnt).unwrap());
+                        self.rx_bytes += read_cnt as u64;
                         METRICS.rx_bytes_count.add(read_cnt as u64);
                     }
                     self.rx_cnt += Wrapping(pkt.len());
@@ -397,6 +493,14 @@ where
             }
         };
 
+        // No event on the host stream announces the data read ahead from it, so it has to be
+        // scheduled again, once the peer has room for it.
+        if !self.rx_backlog.is_empty() && !self.need_credit_update_from_peer() {
+            if let ConnState::Established | ConnState::PeerClosed(false, _) = self.state {
+                self.pending_rx.insert(PendingRx::Rw);
+            }
+        }
+
         Ok(())
     }
 
@@ -483,6 +587,7 @@ where
                     0
                 });
             self.fwd_cnt += wrap_usize_to_u32(flushed);
+            self.tx_bytes += flushed as u64;
             METRICS.tx_bytes_count.add(flushed as u64);
 
             // If this connection was shutting down, but is waiting to drain the TX buffer
@@ -526,6 +631,11 @@ where
             last_fwd_cnt_to_peer: Wrapping(0),
             pending_rx: PendingRxSet::from(PendingRx::Response),
             expiry: None,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            credit_stalls: 0,
+            rx_backlog: Vec::new(),
+            drained: false,
         }
     }
 
@@ -552,6 +662,11 @@ where
             last_fwd_cnt_to_peer: Wrapping(0),
             pending_rx: PendingRxSet::from(PendingRx::Request),
             expiry: None,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            credit_stalls: 0,
+            rx_backlog: Vec::new(),
+            drained: false,
         }
     }
 
@@ -590,6 +705,71 @@ where
         self.state
     }
 
+    /// Take a snapshot of the connection state and counters.
+    pub fn info(&self) -> VsockConnectionInfo {
+        VsockConnectionInfo {
+            local_port: self.local_port,
+            peer_port: self.peer_port,
+            state: self.state,
+            rx_bytes: self.rx_bytes,
+            tx_bytes: self.tx_bytes,
+            // Safe to unwrap because the peer credit is computed as a u32.
+            peer_credit: u32::try_from(self.peer_avail_credit()).unwrap(),
+            // Safe to unwrap because the TX buffer holds at most `CONN_TX_BUF_SIZE` bytes.
+            tx_buf_bytes: u32::try_from(self.tx_buf.len()).unwrap(),
+            credit_stalls: self.credit_stalls,
+        }
+    }
+
+    /// Read the data available on the host stream ahead of a snapshot, so that it can be saved
+    /// along with the connection, and sent to the peer either way.
+    ///
+    /// Only established connections are drained. If the host stream holds more than
+    /// `CONN_RX_BACKLOG_SIZE` bytes, or was closed, the connection can't be saved, and is reset
+    /// on restore.
+    pub fn drain_stream(&mut self) {
+        self.drained = false;
+        if self.state != ConnState::Established {
+            return;
+        }
+
+        let had_backlog = !self.rx_backlog.is_empty();
+        let limit = defs::CONN_RX_BACKLOG_SIZE as usize;
+        while self.rx_backlog.len() < limit {
+            let len = self.rx_backlog.len();
+            self.rx_backlog.resize(limit, 0);
+            let res = self
+                .stream
+                .read_volatile(&mut VolatileSlice::from(&mut self.rx_backlog[len..]));
+            match res {
+                Ok(read_cnt) if read_cnt > 0 => self.rx_backlog.truncate(len + read_cnt),
+                Err(VolatileMemoryError::IOError(err)) if err.kind() == ErrorKind::WouldBlock => {
+                    self.rx_backlog.truncate(len);
+                    self.drained = true;
+                    break;
+                }
+                res => {
+                    // The host stream was closed, or failed. Whatever was read until then is
+                    // still sent to the peer, and the stream tells the rest on the next read.
+                    self.rx_backlog.truncate(len);
+                    if let Err(err) = res {
+                        warn!(
+                            "vsock: error draining backing stream: lp={}, pp={}, err={:?}",
+                            self.local_port, self.peer_port, err
+                        );
+                    }
+                    break;
+                }
+            }
+        }
+
+        // Only keep the memory the data read ahead needs.
+        self.rx_backlog.shrink_to_fit();
+        if !had_backlog && !self.rx_backlog.is_empty() {
+            self.pending_rx.insert(PendingRx::Rw);
+        }
+    }
+
     /// Send some raw, untracked, data straight to the underlying connected stream.
     /// Returns: number of bytes written, or the error describing the write failure.
     ///
@@ -618,7 +798,7 @@ where
         }
 
         // The TX buffer is empty, so we can try to write straight to the host stream.
-        let written = match pkt.write_from_offset_to(&mut self.stream, 0, len) {
+        let written = match pkt.send_from_offset_to(&mut self.stream, 0, len) {
             Ok(cnt) => cnt,
             Err(VsockError::GuestMemoryMmap(GuestMemoryError::IOError(err)))
                 if err.kind() == ErrorKind::WouldBlock =>
@@ -636,6 +816,7 @@ where
         // Move the "forwarded bytes" counter ahead by how much we were able to send out.
         // Safe to unwrap because the maximum value is pkt.len(), which is a u32.
         self.fwd_cnt += wrap_usize_to_u32(written);
+        self.tx_bytes += written as u64;
         METRICS.tx_bytes_count.add(written as u64);
 
         // If we couldn't write the whole slice, we'll need to push the remaining data to our
@@ -647,6 +828,27 @@ where
         Ok(())
     }
 
+    /// Fill in a data packet with up to `max_len` bytes of the RX backlog.
+    fn recv_backlog(&mut self, pkt: &mut VsockPacket, max_len: usize) -> Result<(), VsockError> {
+        let len = std::cmp::min(max_len, self.rx_backlog.len());
+        pkt.read_at_offset_from(&mut &self.rx_backlog[..len], 0, len)?;
+        self.rx_backlog = self.rx_backlog.split_off(len);
+
+        // Safe to unwrap because len is no more than max_len, which is bounded by
+        // self.peer_avail_credit(), a u32 internally.
+        pkt.set_op(uapi::VSOCK_OP_RW)
+            .set_len(u32::try_from(len).unwrap());
+        self.rx_bytes += len as u64;
+        METRICS.rx_bytes_count.add(len as u64);
+        self.rx_cnt += Wrapping(pkt.len());
+        self.last_fwd_cnt_to_peer = self.fwd_cnt;
+
+        if !self.rx_backlog.is_empty() {
+            self.pending_rx.insert(PendingRx::Rw);
+        }
+        Ok(())
+    }
+
     /// Check if the credit information the peer has last received from us is outdated.
     fn peer_needs_credit_update(&self) -> bool {
         let peer_seen_free_buf =
@@ -667,7 +869,7 @@ where
     }
 
     /// Prepare a packet header for transmission to our peer.
-    fn init_pkt<'a>(&self, pkt: &'a mut VsockPacket) -> &'a mut VsockPacket {
+    fn init_pkt<'a, 'mem>(&self, pkt: &'a mut VsockPacket<'mem>) -> &'a mut VsockPacket<'mem> {
         pkt.set_src_cid(self.local_cid)
             .set_dst_cid(self.peer_cid)
             .set_src_port(self.local_port)
@@ -678,6 +880,72 @@ where
     }
 }
 
+impl<S> Persist<'_> for VsockConnection<S>
+where
+    S: VsockConnectionBackend + Debug,
+{
+    type State = VsockConnectionState;
+    type ConstructorArgs = VsockConnectionConstructorArgs<S>;
+    type Error = VsockCsmError;
+
+    fn save(&self) -> Self::State {
+        if self.state != ConnState::Established || !self.drained {
+            return VsockConnectionState {
+                local_port: self.local_port,
+                peer_port: self.peer_port,
+                ..Default::default()
+            };
+        }
+
+        VsockConnectionState {
+            local_port: self.local_port,
+            peer_port: self.peer_port,
+            resumable: true,
+            fwd_cnt: self.fwd_cnt.0,
+            peer_buf_alloc: self.peer_buf_alloc,
+            peer_fwd_cnt: self.peer_fwd_cnt.0,
+            rx_cnt: self.rx_cnt.0,
+            last_fwd_cnt_to_peer: self.last_fwd_cnt_to_peer.0,
+            tx_data: self.tx_buf.to_vec(),
+            rx_data: self.rx_backlog.clone(),
+        }
+    }
+
+    fn restore(
+        constructor_args: Self::ConstructorArgs,
+        state: &Self::State,
+    ) -> Result<Self, Self::Error> {
+        let mut conn = Self {
+            local_cid: constructor_args.local_cid,
+            peer_cid: constructor_args.peer_cid,
+            local_port: state.local_port,
+            peer_port: state.peer_port,
+            stream: constructor_args.stream,
+            state: ConnState::Established,
+            tx_buf: TxBuf::from_bytes(&state.tx_data)?,
+            fwd_cnt: Wrapping(state.fwd_cnt),
+            peer_buf_alloc: state.peer_buf_alloc,
+            peer_fwd_cnt: Wrapping(state.peer_fwd_cnt),
+            rx_cnt: Wrapping(state.rx_cnt),
+            last_fwd_cnt_to_peer: Wrapping(state.last_fwd_cnt_to_peer),
+            pending_rx: PendingRxSet::default(),
+            expiry: None,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            credit_stalls: 0,
+            rx_backlog: state.rx_data.clone(),
+            drained: false,
+        };
+        if !conn.rx_backlog.is_empty() {
+            conn.pending_rx.insert(PendingRx::Rw);
+        }
+        if conn.peer_needs_credit_update() {
+            conn.pending_rx.insert(PendingRx::CreditUpdate);
+        }
+        Ok(conn)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::{Error as IoError, ErrorKind, Write};
@@ -823,7 +1091,11 @@ mod tests {
         }
     }
 
-    fn init_pkt(pkt: &mut VsockPacket, op: u16, len: u32) -> &mut VsockPacket {
+    fn init_pkt<'a, 'mem>(
+        pkt: &'a mut VsockPacket<'mem>,
+        op: u16,
+        len: u32,
+    ) -> &'a mut VsockPacket<'mem> {
         pkt.set_src_cid(PEER_CID)
             .set_dst_cid(LOCAL_CID)
             .set_src_port(PEER_PORT)
@@ -836,29 +1108,26 @@ mod tests {
 
     // This is the connection state machine test context: a helper struct to provide CSM testing
     // primitives. A single `VsockPacket` object will be enough for our testing needs. We'll be
-    // using it for simulating both packet sends and packet receives. We need to keep the vsock
-    // testing context alive, since `VsockPacket` is just a pointer-wrapper over some data that
-    // resides in guest memory. The vsock test context owns the `GuestMemoryMmap` object, so we'll
-    // make it a member here, in order to make sure that guest memory outlives our testing
-    // packet.  A single `VsockConnection` object will also suffice for our testing needs. We'll
-    // be using a specially crafted `Read + Write + AsRawFd` object as a backing stream, so that
-    // we can control the various error conditions that might arise.
+    // using it for simulating both packet sends and packet receives. `VsockPacket` borrows the
+    // guest memory its data resides in, which is owned by the vsock testing context, so each test
+    // keeps that context alive for as long as our testing packet. A single `VsockConnection`
+    // object will also suffice for our testing needs. We'll be using a specially crafted
+    // `Read + Write + AsRawFd` object as a backing stream, so that we can control the various
+    // error conditions that might arise.
     #[derive(Debug)]
-    struct CsmTestContext {
-        _vsock_test_ctx: TestContext,
+    struct CsmTestContext<'a> {
         // Two views of the same in-memory packet. rx-view for writing, tx-view for reading
-        rx_pkt: VsockPacket,
-        tx_pkt: VsockPacket,
+        rx_pkt: VsockPacket<'a>,
+        tx_pkt: VsockPacket<'a>,
         conn: VsockConnection<TestStream>,
     }
 
-    impl CsmTestContext {
-        fn new_established() -> Self {
-            Self::new(ConnState::Established)
+    impl<'a> CsmTestContext<'a> {
+        fn new_established(vsock_test_ctx: &'a TestContext) -> Self {
+            Self::new(vsock_test_ctx, ConnState::Established)
         }
 
-        fn new(conn_state: ConnState) -> Self {
-            let vsock_test_ctx = TestContext::new();
+        fn new(vsock_test_ctx: &'a TestContext, conn_state: ConnState) -> Self {
             let mut handler_ctx = vsock_test_ctx.create_event_handler_context();
             let stream = TestStream::new();
             let mut rx_pkt = VsockPacket::from_rx_virtq_head(
@@ -903,7 +1172,6 @@ mod tests {
             };
             assert_eq!(conn.state, conn_state);
             Self {
-                _vsock_test_ctx: vsock_test_ctx,
                 rx_pkt,
                 tx_pkt,
                 conn,
@@ -938,11 +1206,11 @@ mod tests {
             self.conn.notify(EventSet::OUT);
         }
 
-        fn init_tx_pkt(&mut self, op: u16, len: u32) -> &mut VsockPacket {
+        fn init_tx_pkt(&mut self, op: u16, len: u32) -> &mut VsockPacket<'a> {
             init_pkt(&mut self.tx_pkt, op, len)
         }
 
-        fn init_data_tx_pkt(&mut self, mut data: &[u8]) -> &VsockPacket {
+        fn init_data_tx_pkt(&mut self, mut data: &[u8]) -> &VsockPacket<'a> {
             assert!(data.len() <= self.tx_pkt.buf_size());
             self.init_tx_pkt(uapi::VSOCK_OP_RW, u32::try_from(data.len()).unwrap());
 
@@ -954,7 +1222,8 @@ mod tests {
 
     #[test]
     fn test_peer_request() {
-        let mut ctx = CsmTestContext::new(ConnState::PeerInit);
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new(&vsock_test_ctx, ConnState::PeerInit);
         assert!(ctx.conn.has_pending_rx());
         ctx.recv();
         // For peer-initiated requests, our connection should always yield a vsock reponse packet,
@@ -973,7 +1242,8 @@ mod tests {
 
     #[test]
     fn test_local_request() {
-        let mut ctx = CsmTestContext::new(ConnState::LocalInit);
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new(&vsock_test_ctx, ConnState::LocalInit);
         // Host-initiated connections should first yield a connection request packet.
         assert!(ctx.conn.has_pending_rx());
         // Before yielding the connection request packet, the timeout kill timer shouldn't be
@@ -994,7 +1264,8 @@ mod tests {
 
     #[test]
     fn test_local_request_timeout() {
-        let mut ctx = CsmTestContext::new(ConnState::LocalInit);
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new(&vsock_test_ctx, ConnState::LocalInit);
         ctx.recv();
         assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_REQUEST);
         assert!(ctx.conn.will_expire());
@@ -1007,7 +1278,8 @@ mod tests {
 
     #[test]
     fn test_rx_data() {
-        let mut ctx = CsmTestContext::new_established();
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
         let data = &[1, 2, 3, 4];
         ctx.set_stream(TestStream::new_with_read_buf(data));
         assert_eq!(ctx.conn.as_raw_fd(), ctx.conn.stream.as_raw_fd());
@@ -1018,6 +1290,7 @@ mod tests {
 
         let buf = test_utils::read_packet_data(&ctx.tx_pkt, 4);
         assert_eq!(&buf, data);
+        assert_eq!(ctx.conn.info().rx_bytes, data.len() as u64);
 
         // There's no more data in the stream, so `recv_pkt` should yield `VsockError::NoData`.
         match ctx.conn.recv_pkt(&mut ctx.tx_pkt) {
@@ -1034,7 +1307,8 @@ mod tests {
 
     #[test]
     fn test_local_close() {
-        let mut ctx = CsmTestContext::new_established();
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
         let mut stream = TestStream::new();
         stream.read_state = StreamState::Closed;
         ctx.set_stream(stream);
@@ -1060,7 +1334,8 @@ mod tests {
         // Test that send/recv shutdown indications are handled correctly.
         // I.e. once set, an indication cannot be reset.
         {
-            let mut ctx = CsmTestContext::new_established();
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
 
             ctx.init_tx_pkt(uapi::VSOCK_OP_SHUTDOWN, 0)
                 .set_flags(uapi::VSOCK_FLAGS_SHUTDOWN_RCV);
@@ -1079,7 +1354,8 @@ mod tests {
         // - writing data should have no effect.
         {
             let data = &[1, 2, 3, 4];
-            let mut ctx = CsmTestContext::new_established();
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
             ctx.set_stream(TestStream::new_with_read_buf(data));
             ctx.init_tx_pkt(uapi::VSOCK_OP_SHUTDOWN, 0)
                 .set_flags(uapi::VSOCK_FLAGS_SHUTDOWN_SEND);
@@ -1101,7 +1377,8 @@ mod tests {
         // - writing data to a no-more-recv connection should work; and
         // - attempting to read data from it should yield an RST packet.
         {
-            let mut ctx = CsmTestContext::new_established();
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
             ctx.init_tx_pkt(uapi::VSOCK_OP_SHUTDOWN, 0)
                 .set_flags(uapi::VSOCK_FLAGS_SHUTDOWN_RCV);
             ctx.send();
@@ -1118,7 +1395,8 @@ mod tests {
         // Test case: setting both no-more-send and no-more-recv indications should have the
         // connection confirm termination (i.e. yield an RST).
         {
-            let mut ctx = CsmTestContext::new_established();
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
             ctx.init_tx_pkt(uapi::VSOCK_OP_SHUTDOWN, 0)
                 .set_flags(uapi::VSOCK_FLAGS_SHUTDOWN_RCV | uapi::VSOCK_FLAGS_SHUTDOWN_SEND);
             ctx.send();
@@ -1130,7 +1408,8 @@ mod tests {
 
     #[test]
     fn test_local_read_error() {
-        let mut ctx = CsmTestContext::new_established();
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
         let mut stream = TestStream::new();
         stream.read_state = StreamState::Error(ErrorKind::PermissionDenied);
         ctx.set_stream(stream);
@@ -1141,16 +1420,21 @@ mod tests {
 
     #[test]
     fn test_credit_request_to_peer() {
-        let mut ctx = CsmTestContext::new_established();
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
         ctx.set_peer_credit(0);
+        let credit_stalls = METRICS.credit_stalls.count();
         ctx.notify_epollin();
         ctx.recv();
         assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_CREDIT_REQUEST);
+        assert_eq!(ctx.conn.info().credit_stalls, 1);
+        assert!(METRICS.credit_stalls.count() > credit_stalls);
     }
 
     #[test]
     fn test_credit_request_from_peer() {
-        let mut ctx = CsmTestContext::new_established();
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
         ctx.init_tx_pkt(uapi::VSOCK_OP_CREDIT_REQUEST, 0);
         ctx.send();
         assert!(ctx.conn.has_pending_rx());
@@ -1162,7 +1446,8 @@ mod tests {
 
     #[test]
     fn test_credit_update_to_peer() {
-        let mut ctx = CsmTestContext::new_established();
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
 
         // Force a stale state, where the peer hasn't been updated on our credit situation.
         ctx.conn.last_fwd_cnt_to_peer = Wrapping(0);
@@ -1204,7 +1489,8 @@ mod tests {
         // - when the CSM is notified that it can write to the backing stream, it should flush the
         //   TX buf.
         {
-            let mut ctx = CsmTestContext::new_established();
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
 
             let mut stream = TestStream::new();
             stream.write_state = StreamState::WouldBlock;
@@ -1220,6 +1506,8 @@ mod tests {
             // can write to its backing stream.
             assert!(ctx.conn.get_polled_evset().contains(EventSet::OUT));
             assert_eq!(ctx.conn.tx_buf.len(), data.len());
+            assert_eq!(ctx.conn.info().tx_buf_bytes as usize, data.len());
+            assert_eq!(ctx.conn.info().tx_bytes, 0);
 
             // Unlock the write stream and notify the connection it can now write its bufferred
             // data.
@@ -1227,6 +1515,8 @@ mod tests {
             ctx.conn.notify(EventSet::OUT);
             assert!(ctx.conn.tx_buf.is_empty());
             assert_eq!(ctx.conn.stream.write_buf, data);
+            assert_eq!(ctx.conn.info().tx_buf_bytes, 0);
+            assert_eq!(ctx.conn.info().tx_bytes, data.len() as u64);
         }
     }
 
@@ -1234,7 +1524,8 @@ mod tests {
     fn test_stream_write_error() {
         // Test case: sending a data packet to a broken / closed backing stream should kill it.
         {
-            let mut ctx = CsmTestContext::new_established();
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
             let mut stream = TestStream::new();
             stream.write_state = StreamState::Closed;
             ctx.set_stream(stream);
@@ -1252,7 +1543,8 @@ mod tests {
         // Test case: notifying a connection that it can flush its TX buffer to a broken stream
         // should kill the connection.
         {
-            let mut ctx = CsmTestContext::new_established();
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
 
             let mut stream = TestStream::new();
             stream.write_state = StreamState::WouldBlock;
@@ -1275,9 +1567,99 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_save_restore() {
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
+        let rx_data = &[1, 2, 3, 4];
+        let tx_data = &[5, 6, 7, 8];
+
+        // Leave some guest data in the TX buffer, and some host data in the stream.
+        let mut stream = TestStream::new_with_read_buf(rx_data);
+        stream.write_state = StreamState::WouldBlock;
+        ctx.set_stream(stream);
+        ctx.init_data_tx_pkt(tx_data);
+        ctx.send();
+
+        ctx.conn.drain_stream();
+        assert!(ctx.conn.stream.read_buf.is_empty());
+        assert!(ctx.conn.has_pending_rx());
+        let state = ctx.conn.save();
+        assert!(state.resumable);
+        assert_eq!(state.rx_data, rx_data);
+        assert_eq!(state.tx_data, tx_data);
+        assert_eq!(state.rx_cnt, ctx.conn.rx_cnt.0);
+
+        // The restored connection sends the host data to the guest before reading any more from
+        // its new stream, and flushes the guest data to it.
+        ctx.conn = VsockConnection::restore(
+            VsockConnectionConstructorArgs {
+                stream: TestStream::new_with_read_buf(&[9]),
+                local_cid: LOCAL_CID,
+                peer_cid: PEER_CID,
+            },
+            &state,
+        )
+        .unwrap();
+        assert_eq!(ctx.conn.state(), ConnState::Established);
+        assert!(ctx.conn.get_polled_evset().contains(EventSet::OUT));
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.len() as usize, rx_data.len());
+        assert_eq!(&test_utils::read_packet_data(&ctx.tx_pkt, 4), rx_data);
+        assert_eq!(ctx.rx_pkt.fwd_cnt(), state.fwd_cnt);
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.len(), 1);
+        ctx.notify_epollout();
+        assert_eq!(ctx.conn.stream.write_buf, tx_data);
+        assert_eq!(ctx.conn.fwd_cnt.0, state.fwd_cnt + 4);
+    }
+
+    #[test]
+    fn test_save_unresumable() {
+        // Test case: a connection which isn't established can't be resumed.
+        {
+            let vsock_test_ctx = TestContext::new();
+            let ctx = CsmTestContext::new(&vsock_test_ctx, ConnState::LocalInit);
+            let state = ctx.conn.save();
+            assert!(!state.resumable);
+            assert_eq!(state.local_port, LOCAL_PORT);
+            assert_eq!(state.peer_port, PEER_PORT);
+        }
+
+        // Test case: nor can a connection whose host stream was closed.
+        {
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
+            let mut stream = TestStream::new();
+            stream.read_state = StreamState::Closed;
+            ctx.set_stream(stream);
+            ctx.conn.drain_stream();
+            assert!(!ctx.conn.save().resumable);
+        }
+
+        // Test case: nor can a connection with too much host data to read ahead. The data read
+        // ahead is still sent to the guest, before the rest.
+        {
+            let vsock_test_ctx = TestContext::new();
+            let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
+            let data = vec![1u8; csm_defs::CONN_RX_BACKLOG_SIZE as usize + 1];
+            ctx.set_stream(TestStream::new_with_read_buf(&data));
+            ctx.conn.drain_stream();
+            assert!(!ctx.conn.save().resumable);
+            assert_eq!(ctx.conn.stream.read_buf.len(), 1);
+            assert!(ctx.conn.has_pending_rx());
+            ctx.recv();
+            assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RW);
+            assert_eq!(ctx.conn.stream.read_buf.len(), 1);
+        }
+    }
+
     #[test]
     fn test_peer_credit_misbehavior() {
-        let mut ctx = CsmTestContext::new_established();
+        let vsock_test_ctx = TestContext::new();
+        let mut ctx = CsmTestContext::new_established(&vsock_test_ctx);
 
         let mut stream = TestStream::new();
         stream.write_state = StreamState::WouldBlock;
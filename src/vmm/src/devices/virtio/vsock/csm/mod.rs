@@ -6,12 +6,16 @@
 mod connection;
 mod txbuf;
 
-pub use connection::{VsockConnection, VsockConnectionBackend};
+pub use connection::{VsockConnection, VsockConnectionBackend, VsockConnectionInfo};
+use serde::{Serialize, Serializer};
 
 pub mod defs {
     /// Vsock connection TX buffer capacity.
     pub const CONN_TX_BUF_SIZE: u32 = 64 * 1024;
 
+    /// Maximum amount of host data read ahead of a snapshot, for a connection to be kept in it.
+    pub const CONN_RX_BACKLOG_SIZE: u32 = 64 * 1024;
+
     /// When the guest thinks we have less than this amount of free buffer space,
     /// we will send them a credit update packet.
     pub const CONN_CREDIT_UPDATE_THRESHOLD: u32 = 4 * 1024;
@@ -52,6 +56,19 @@ pub enum ConnState {
     Killed,
 }
 
+impl Serialize for ConnState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            ConnState::LocalInit => "local_init",
+            ConnState::PeerInit => "peer_init",
+            ConnState::Established => "established",
+            ConnState::LocalClosed => "local_closed",
+            ConnState::PeerClosed(..) => "peer_closed",
+            ConnState::Killed => "killed",
+        })
+    }
+}
+
 /// An RX indication, used by `VsockConnection` to schedule future `recv_pkt()` responses.
 /// For instance, after being notified that there is available data to be read from the host stream
 /// (via `notify()`), the connection will store a `PendingRx::Rw` to be later inspected by
@@ -77,7 +94,7 @@ impl PendingRx {
 }
 
 /// A set of RX indications (`PendingRx` items).
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct PendingRxSet {
     data: u16,
 }
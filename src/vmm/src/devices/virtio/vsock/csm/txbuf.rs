@@ -135,6 +135,35 @@ impl TxBuf {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Build a ring-buffer holding `bytes`, as saved by `to_vec()`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VsockCsmError> {
+        let mut buf = Self::new();
+        if bytes.is_empty() {
+            return Ok(buf);
+        }
+        if bytes.len() > Self::SIZE {
+            return Err(VsockCsmError::TxBufFull);
+        }
+
+        let mut data = vec![0u8; Self::SIZE].into_boxed_slice();
+        data[..bytes.len()].copy_from_slice(bytes);
+        buf.data = Some(data);
+        buf.head = wrap_usize_to_u32(bytes.len());
+        Ok(buf)
+    }
+
+    /// Copy out the data that hasn't yet been flushed out, oldest first.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.len());
+        if let Some(data) = self.data.as_ref() {
+            let tail_ofs = self.tail.0 as usize % Self::SIZE;
+            let len = std::cmp::min(Self::SIZE - tail_ofs, self.len());
+            bytes.extend_from_slice(&data[tail_ofs..(tail_ofs + len)]);
+            bytes.extend_from_slice(&data[..(self.len() - len)]);
+        }
+        bytes
+    }
 }
 
 impl WriteVolatile for TxBuf {
@@ -258,6 +287,36 @@ mod tests {
         assert_eq!(sink.data, [5, 6, 7, 8]);
     }
 
+    #[test]
+    fn test_save_restore() {
+        let mut txbuf = TxBuf::new();
+        let mut sink = TestSink::new();
+        assert!(txbuf.to_vec().is_empty());
+        assert!(TxBuf::from_bytes(&[]).unwrap().data.is_none());
+
+        // Leave some data wrapped around the end of the buffer.
+        let mut tmp: Vec<u8> = vec![0; TxBuf::SIZE - 2];
+        txbuf
+            .push(&VolatileSlice::from(tmp.as_mut_slice()))
+            .unwrap();
+        txbuf.flush_to(&mut sink).unwrap();
+        txbuf
+            .push(&VolatileSlice::from([1, 2, 3, 4].as_mut_slice()))
+            .unwrap();
+        assert_eq!(txbuf.to_vec(), [1, 2, 3, 4]);
+
+        let mut restored = TxBuf::from_bytes(&txbuf.to_vec()).unwrap();
+        assert_eq!(restored.len(), 4);
+        sink.clear();
+        assert_eq!(restored.flush_to(&mut sink).unwrap(), 4);
+        assert_eq!(sink.data, [1, 2, 3, 4]);
+
+        match TxBuf::from_bytes(&vec![0; TxBuf::SIZE + 1]) {
+            Err(VsockCsmError::TxBufFull) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
     #[test]
     fn test_push_error() {
         let mut txbuf = TxBuf::new();
@@ -30,7 +30,11 @@ use super::super::super::DeviceError;
 use super::defs::uapi;
 use super::packet::{VsockPacket, VSOCK_PKT_HDR_SIZE};
 use super::{defs, VsockBackend};
-use crate::devices::virtio::device::{DeviceState, IrqTrigger, IrqType, VirtioDevice};
+use crate::devices::virtio::device::{
+    check_queue_size, pin_virtio_features, DeviceState, IrqTrigger, IrqType, QueueSizeError,
+    VirtioDevice, VirtioFeaturesPinError,
+};
+use crate::devices::virtio::gen::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
 use crate::devices::virtio::queue::Queue as VirtQueue;
 use crate::devices::virtio::vsock::metrics::METRICS;
 use crate::devices::virtio::vsock::VsockError;
@@ -48,18 +52,26 @@ pub(crate) const VIRTIO_VSOCK_EVENT_TRANSPORT_RESET: u32 = 0;
 /// - VIRTIO_F_VERSION_1: the device conforms to at least version 1.0 of the VirtIO spec.
 /// - VIRTIO_F_IN_ORDER: the device returns used buffers in the same order that the driver makes
 ///   them available.
-pub(crate) const AVAIL_FEATURES: u64 =
-    1 << uapi::VIRTIO_F_VERSION_1 as u64 | 1 << uapi::VIRTIO_F_IN_ORDER as u64;
+/// - VIRTIO_RING_F_EVENT_IDX: the device and the driver only notify each other when the other side
+///   asked to, through the event indices of the rings.
+/// - VIRTIO_VSOCK_F_DGRAM: the device exchanges datagrams, besides stream connections.
+pub(crate) const AVAIL_FEATURES: u64 = 1 << uapi::VIRTIO_F_VERSION_1 as u64
+    | 1 << uapi::VIRTIO_F_IN_ORDER as u64
+    | 1 << VIRTIO_RING_F_EVENT_IDX as u64
+    | 1 << uapi::VIRTIO_VSOCK_F_DGRAM as u64;
 
 /// Structure representing the vsock device.
 #[derive(Debug)]
 pub struct Vsock<B> {
     cid: u64,
     pub(crate) queues: Vec<VirtQueue>,
+    // Configured maximal size of the queues, if not the default one.
+    pub(crate) queue_size: Option<u16>,
     pub(crate) queue_events: Vec<EventFd>,
     pub(crate) backend: B,
     pub(crate) avail_features: u64,
     pub(crate) acked_features: u64,
+    pub(crate) virtio_features_pin: Option<u64>,
     pub(crate) irq_trigger: IrqTrigger,
     // This EventFd is the only one initially registered for a vsock device, and is used to convert
     // a VirtioDevice::activate call into an EventHandler read event which allows the other events
@@ -68,6 +80,7 @@ pub struct Vsock<B> {
     // continuous triggers from happening before the device gets activated.
     pub(crate) activate_evt: EventFd,
     pub(crate) device_state: DeviceState,
+    pub(crate) needs_reset: bool,
 }
 
 // TODO: Detect / handle queue deadlock:
@@ -94,13 +107,16 @@ where
         Ok(Vsock {
             cid,
             queues,
+            queue_size: None,
             queue_events,
             backend,
             avail_features: AVAIL_FEATURES,
             acked_features: 0,
+            virtio_features_pin: None,
             irq_trigger: IrqTrigger::new().map_err(VsockError::EventFd)?,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(VsockError::EventFd)?,
             device_state: DeviceState::Inactive,
+            needs_reset: false,
         })
     }
 
@@ -113,6 +129,28 @@ where
         Self::with_queues(cid, backend, queues)
     }
 
+    /// Restricts the virtio features offered to the guest to the ones of `pin`.
+    pub fn pin_virtio_features(&mut self, pin: u64) -> Result<(), VirtioFeaturesPinError> {
+        self.avail_features = pin_virtio_features(self.id(), self.avail_features, pin)?;
+        self.virtio_features_pin = Some(pin);
+        Ok(())
+    }
+
+    /// Sets the maximal size of the queues offered to the guest.
+    ///
+    /// The queues are recreated, so this must be called before the device is activated.
+    pub fn set_queue_size(&mut self, size: u16) -> Result<(), QueueSizeError> {
+        check_queue_size(size, defs::VSOCK_MIN_QUEUE_SIZE, defs::VSOCK_MAX_QUEUE_SIZE)?;
+        self.queues = vec![VirtQueue::new(size); defs::VSOCK_NUM_QUEUES];
+        self.queue_size = Some(size);
+        Ok(())
+    }
+
+    /// Provides the configured maximal size of the queues, if not the default one.
+    pub fn queue_size(&self) -> Option<u16> {
+        self.queue_size
+    }
+
     /// Provides the ID of this vsock device as used in MMIO device identification.
     pub fn id(&self) -> &str {
         defs::VSOCK_DEV_ID
@@ -128,6 +166,11 @@ where
         &self.backend
     }
 
+    /// Mutably access the backend behind the device.
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
     /// Signal the guest driver that we've used some virtio buffers that it had previously made
     /// available.
     pub fn signal_used_queue(&self) -> Result<(), DeviceError> {
@@ -137,17 +180,22 @@ where
     }
 
     /// Walk the driver-provided RX queue buffers and attempt to fill them up with any data that we
-    /// have pending. Return `true` if descriptors have been added to the used ring, and `false`
-    /// otherwise.
-    pub fn process_rx(&mut self) -> bool {
+    /// have pending. Return `true` if descriptors have been added to the used ring and the driver
+    /// needs to be notified, and `false` otherwise.
+    ///
+    /// Malformed buffers are given back to the driver, and the packet they held is dropped.
+    pub fn process_rx(&mut self) -> Result<bool, DeviceError> {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
 
         let mut have_used = false;
+        let rings = self.queues[RXQ_INDEX].rings();
 
-        while let Some(head) = self.queues[RXQ_INDEX].pop(mem) {
+        while let Some(head) = self.queues[RXQ_INDEX].pop_or_enable_notification(mem) {
             let index = head.index;
-            let used_len = match VsockPacket::from_rx_virtq_head(head) {
+            let pkt = VsockPacket::from_rx_virtq_head(head)
+                .and_then(|pkt| pkt.check_queue_overlap(&rings).map(|()| pkt));
+            let used_len = match pkt {
                 Ok(mut pkt) => {
                     if self.backend.recv_pkt(&mut pkt).is_ok() {
                         match pkt.commit_hdr() {
@@ -181,24 +229,24 @@ where
             have_used = true;
             self.queues[RXQ_INDEX]
                 .add_used(mem, index, used_len)
-                .unwrap_or_else(|err| {
-                    error!("Failed to add available descriptor {}: {}", index, err)
-                });
+                .map_err(DeviceError::QueueError)?;
         }
 
-        have_used
+        Ok(have_used && self.queues[RXQ_INDEX].prepare_kick(mem))
     }
 
     /// Walk the driver-provided TX queue buffers, package them up as vsock packets, and send them
     /// to the backend for processing. Return `true` if descriptors have been added to the used
-    /// ring, and `false` otherwise.
-    pub fn process_tx(&mut self) -> bool {
+    /// ring and the driver needs to be notified, and `false` otherwise.
+    ///
+    /// Malformed buffers are given back to the driver, and the packet they held is dropped.
+    pub fn process_tx(&mut self) -> Result<bool, DeviceError> {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
 
         let mut have_used = false;
 
-        while let Some(head) = self.queues[TXQ_INDEX].pop(mem) {
+        while let Some(head) = self.queues[TXQ_INDEX].pop_or_enable_notification(mem) {
             let index = head.index;
             let pkt = match VsockPacket::from_tx_virtq_head(head) {
                 Ok(pkt) => pkt,
@@ -207,9 +255,7 @@ where
                     have_used = true;
                     self.queues[TXQ_INDEX]
                         .add_used(mem, index, 0)
-                        .unwrap_or_else(|err| {
-                            error!("Failed to add available descriptor {}: {}", index, err);
-                        });
+                        .map_err(DeviceError::QueueError)?;
                     continue;
                 }
             };
@@ -222,12 +268,30 @@ where
             have_used = true;
             self.queues[TXQ_INDEX]
                 .add_used(mem, index, 0)
-                .unwrap_or_else(|err| {
-                    error!("Failed to add available descriptor {}: {}", index, err);
-                });
+                .map_err(DeviceError::QueueError)?;
         }
 
-        have_used
+        Ok(have_used && self.queues[TXQ_INDEX].prepare_kick(mem))
+    }
+
+    /// Process the packets left in the TX queue, then fill the RX queue with the packets the
+    /// backend holds for the guest, such as those of connections restored from a snapshot,
+    /// which no event announces.
+    pub fn process_virtio_queues(&mut self) {
+        let mut raise_irq = self.process_tx().unwrap_or_else(|err| {
+            error!("vsock: error processing TX queue: {:?}", err);
+            false
+        });
+        if self.backend.has_pending_rx() {
+            raise_irq |= self.process_rx().unwrap_or_else(|err| {
+                error!("vsock: error processing RX queue: {:?}", err);
+                false
+            });
+        }
+        if raise_irq {
+            self.signal_used_queue()
+                .unwrap_or_else(|err| error!("vsock: error signaling used queue: {:?}", err));
+        }
     }
 
     // Send TRANSPORT_RESET_EVENT to driver. According to specs, the driver shuts down established
@@ -251,7 +315,9 @@ where
                 error!("Failed to add used descriptor {}: {}", head.index, err);
             });
 
-        self.signal_used_queue()?;
+        if self.queues[EVQ_INDEX].prepare_kick(mem) {
+            self.signal_used_queue()?;
+        }
 
         Ok(())
     }
@@ -273,6 +339,10 @@ where
         self.acked_features = acked_features
     }
 
+    fn virtio_features_pin(&self) -> Option<u64> {
+        self.virtio_features_pin
+    }
+
     fn device_type(&self) -> u32 {
         uapi::VIRTIO_ID_VSOCK
     }
@@ -331,6 +401,12 @@ where
             });
         }
 
+        if self.has_feature(u64::from(VIRTIO_RING_F_EVENT_IDX)) {
+            for queue in &mut self.queues {
+                queue.enable_notif_suppression();
+            }
+        }
+
         if self.activate_evt.write(1).is_err() {
             METRICS.activate_fails.inc();
             return Err(ActivateError::EventFd);
@@ -344,6 +420,10 @@ where
     fn is_activated(&self) -> bool {
         self.device_state.is_activated()
     }
+
+    fn needs_reset(&self) -> bool {
+        self.needs_reset
+    }
 }
 
 #[cfg(test)]
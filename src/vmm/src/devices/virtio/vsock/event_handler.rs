@@ -32,9 +32,12 @@ use utils::epoll::EventSet;
 
 use super::device::{Vsock, EVQ_INDEX, RXQ_INDEX, TXQ_INDEX};
 use super::VsockBackend;
-use crate::devices::virtio::device::VirtioDevice;
+use crate::auto_pause::record_activity;
+use crate::devices::virtio::device::{DeviceErrorHandler, VirtioDevice};
 use crate::devices::virtio::vsock::metrics::METRICS;
+use crate::devices::DeviceError;
 use crate::logger::IncMetric;
+use crate::vmm_config::auto_pause::ActivitySignal;
 
 impl<B> Vsock<B>
 where
@@ -46,75 +49,77 @@ where
     const PROCESS_EVQ: u32 = 3;
     const PROCESS_NOTIFY_BACKEND: u32 = 4;
 
-    pub fn handle_rxq_event(&mut self, evset: EventSet) -> bool {
+    pub fn handle_rxq_event(&mut self, evset: EventSet) -> Result<bool, DeviceError> {
         if evset != EventSet::IN {
             warn!("vsock: rxq unexpected event {:?}", evset);
             METRICS.rx_queue_event_fails.inc();
-            return false;
+            return Ok(false);
         }
 
+        record_activity(ActivitySignal::Vsock);
         let mut raise_irq = false;
         if let Err(err) = self.queue_events[RXQ_INDEX].read() {
-            error!("Failed to get vsock rx queue event: {:?}", err);
             METRICS.rx_queue_event_fails.inc();
+            return Err(DeviceError::EventFd(err));
         } else if self.backend.has_pending_rx() {
-            raise_irq |= self.process_rx();
+            raise_irq |= self.process_rx()?;
             METRICS.rx_queue_event_count.inc();
         }
-        raise_irq
+        Ok(raise_irq)
     }
 
-    pub fn handle_txq_event(&mut self, evset: EventSet) -> bool {
+    pub fn handle_txq_event(&mut self, evset: EventSet) -> Result<bool, DeviceError> {
         if evset != EventSet::IN {
             warn!("vsock: txq unexpected event {:?}", evset);
             METRICS.tx_queue_event_fails.inc();
-            return false;
+            return Ok(false);
         }
 
-        let mut raise_irq = false;
+        record_activity(ActivitySignal::Vsock);
         if let Err(err) = self.queue_events[TXQ_INDEX].read() {
-            error!("Failed to get vsock tx queue event: {:?}", err);
             METRICS.tx_queue_event_fails.inc();
-        } else {
-            raise_irq |= self.process_tx();
-            METRICS.tx_queue_event_count.inc();
-            // The backend may have queued up responses to the packets we sent during
-            // TX queue processing. If that happened, we need to fetch those responses
-            // and place them into RX buffers.
-            if self.backend.has_pending_rx() {
-                raise_irq |= self.process_rx();
-            }
+            return Err(DeviceError::EventFd(err));
         }
-        raise_irq
+        let mut raise_irq = self.process_tx()?;
+        METRICS.tx_queue_event_count.inc();
+        // The backend may have queued up responses to the packets we sent during
+        // TX queue processing. If that happened, we need to fetch those responses
+        // and place them into RX buffers.
+        if self.backend.has_pending_rx() {
+            raise_irq |= self.process_rx()?;
+        }
+        Ok(raise_irq)
     }
 
-    pub fn handle_evq_event(&mut self, evset: EventSet) -> bool {
+    pub fn handle_evq_event(&mut self, evset: EventSet) -> Result<bool, DeviceError> {
         if evset != EventSet::IN {
             warn!("vsock: evq unexpected event {:?}", evset);
             METRICS.ev_queue_event_fails.inc();
-            return false;
+            return Ok(false);
         }
 
         if let Err(err) = self.queue_events[EVQ_INDEX].read() {
-            error!("Failed to consume vsock evq event: {:?}", err);
             METRICS.ev_queue_event_fails.inc();
+            return Err(DeviceError::EventFd(err));
         }
-        false
+        Ok(false)
     }
 
     /// Notify backend of new events.
-    pub fn notify_backend(&mut self, evset: EventSet) -> bool {
+    pub fn notify_backend(&mut self, evset: EventSet) -> Result<bool, DeviceError> {
+        // Connections and data from the host resume an auto-paused microVM.
+        record_activity(ActivitySignal::Vsock);
         self.backend.notify(evset);
         // After the backend has been kicked, it might've freed up some resources, so we
         // can attempt to send it more data to process.
         // In particular, if `self.backend.send_pkt()` halted the TX queue processing (by
         // returning an error) at some point in the past, now is the time to try walking the
         // TX queue again.
-        let mut raise_irq = self.process_tx();
+        let mut raise_irq = self.process_tx()?;
         if self.backend.has_pending_rx() {
-            raise_irq |= self.process_rx();
+            raise_irq |= self.process_rx()?;
         }
-        raise_irq
+        Ok(raise_irq)
     }
 
     fn register_runtime_events(&self, ops: &mut EventOps) {
@@ -173,6 +178,46 @@ where
     }
 }
 
+impl<B> DeviceErrorHandler for Vsock<B>
+where
+    B: Debug + VsockBackend + 'static,
+{
+    fn set_needs_reset(&mut self) {
+        self.needs_reset = true;
+    }
+
+    fn unregister_runtime_events(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.remove(Events::with_data(
+            &self.queue_events[RXQ_INDEX],
+            Self::PROCESS_RXQ,
+            EventSet::IN,
+        )) {
+            error!("Failed to un-register rx queue event: {}", err);
+        }
+        if let Err(err) = ops.remove(Events::with_data(
+            &self.queue_events[TXQ_INDEX],
+            Self::PROCESS_TXQ,
+            EventSet::IN,
+        )) {
+            error!("Failed to un-register tx queue event: {}", err);
+        }
+        if let Err(err) = ops.remove(Events::with_data(
+            &self.queue_events[EVQ_INDEX],
+            Self::PROCESS_EVQ,
+            EventSet::IN,
+        )) {
+            error!("Failed to un-register ev queue event: {}", err);
+        }
+        if let Err(err) = ops.remove(Events::with_data(
+            &self.backend,
+            Self::PROCESS_NOTIFY_BACKEND,
+            self.backend.get_polled_evset(),
+        )) {
+            error!("Failed to un-register vsock backend event: {}", err);
+        }
+    }
+}
+
 impl<B> MutEventSubscriber for Vsock<B>
 where
     B: Debug + VsockBackend + 'static,
@@ -182,17 +227,27 @@ where
         let evset = event.event_set();
 
         if self.is_activated() {
-            let mut raise_irq = false;
-            match source {
-                Self::PROCESS_ACTIVATE => self.handle_activate_event(ops),
-                Self::PROCESS_RXQ => raise_irq = self.handle_rxq_event(evset),
-                Self::PROCESS_TXQ => raise_irq = self.handle_txq_event(evset),
-                Self::PROCESS_EVQ => raise_irq = self.handle_evq_event(evset),
-                Self::PROCESS_NOTIFY_BACKEND => raise_irq = self.notify_backend(evset),
-                _ => warn!("Unexpected vsock event received: {:?}", source),
-            }
-            if raise_irq {
-                self.signal_used_queue().unwrap_or_default();
+            let result = match source {
+                Self::PROCESS_ACTIVATE => {
+                    self.handle_activate_event(ops);
+                    Ok(false)
+                }
+                Self::PROCESS_RXQ => self.handle_rxq_event(evset),
+                Self::PROCESS_TXQ => self.handle_txq_event(evset),
+                Self::PROCESS_EVQ => self.handle_evq_event(evset),
+                Self::PROCESS_NOTIFY_BACKEND => self.notify_backend(evset),
+                _ => {
+                    warn!("Unexpected vsock event received: {:?}", source);
+                    Ok(false)
+                }
+            };
+            let result = match result {
+                Ok(true) => self.signal_used_queue(),
+                Ok(false) => Ok(()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                self.handle_error(ops, err);
             }
         } else {
             warn!(
@@ -223,6 +278,9 @@ mod tests {
 
     use super::super::*;
     use super::*;
+    use crate::devices::virtio::gen::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
+    use crate::devices::virtio::queue::QueueError;
+    use crate::devices::virtio::test_utils::test::check_error_handling;
     use crate::devices::virtio::vsock::packet::VSOCK_PKT_HDR_SIZE;
     use crate::devices::virtio::vsock::test_utils::{EventHandlerContext, TestContext};
     use crate::utilities::test_utils::multi_region_mem;
@@ -304,10 +362,46 @@ mod tests {
             let mut ctx = test_ctx.create_event_handler_context();
             ctx.mock_activate(test_ctx.mem.clone());
 
-            assert!(!ctx.device.handle_txq_event(EventSet::IN));
+            assert!(matches!(
+                ctx.device.handle_txq_event(EventSet::IN),
+                Err(DeviceError::EventFd(_))
+            ));
         }
     }
 
+    #[test]
+    fn test_event_idx() {
+        let test_ctx = TestContext::new();
+        let mut ctx = test_ctx.create_event_handler_context();
+        ctx.device.acked_features = 1 << VIRTIO_RING_F_EVENT_IDX;
+        ctx.mock_activate(test_ctx.mem.clone());
+
+        // The guest waits for the first used TX buffer.
+        ctx.guest_txvq.avail.event.set(0);
+        ctx.device.queue_events[TXQ_INDEX].write(1).unwrap();
+        assert!(ctx.device.handle_txq_event(EventSet::IN).unwrap());
+        assert_eq!(ctx.guest_txvq.used.idx.get(), 1);
+        // The TX queue was emptied, so the device asks to be kicked for the next buffer.
+        assert_eq!(ctx.guest_txvq.used.event.get(), 1);
+
+        // Until the guest moves `used_event`, the buffers it makes available are used without
+        // interrupting it.
+        for idx in 1..4 {
+            ctx.guest_txvq.avail.ring[usize::from(idx)].set(0);
+            ctx.guest_txvq.avail.idx.set(idx + 1);
+            ctx.device.queue_events[TXQ_INDEX].write(1).unwrap();
+            assert!(!ctx.device.handle_txq_event(EventSet::IN).unwrap());
+            assert_eq!(ctx.guest_txvq.used.idx.get(), idx + 1);
+        }
+
+        ctx.guest_txvq.avail.event.set(4);
+        ctx.guest_txvq.avail.ring[4].set(0);
+        ctx.guest_txvq.avail.idx.set(5);
+        ctx.device.queue_events[TXQ_INDEX].write(1).unwrap();
+        assert!(ctx.device.handle_txq_event(EventSet::IN).unwrap());
+        assert_eq!(ctx.guest_txvq.used.idx.get(), 5);
+    }
+
     #[test]
     fn test_rxq_event() {
         // Test case:
@@ -354,7 +448,7 @@ mod tests {
             ctx.guest_rxvq.dtable[1].len.set(0);
 
             // The chain should've been processed, without employing the backend.
-            assert!(ctx.device.process_rx());
+            assert!(ctx.device.process_rx().unwrap());
             assert_eq!(ctx.guest_rxvq.used.idx.get(), 1);
             assert_eq!(ctx.device.backend.rx_ok_cnt, 0);
         }
@@ -365,7 +459,10 @@ mod tests {
             let mut ctx = test_ctx.create_event_handler_context();
             ctx.mock_activate(test_ctx.mem.clone());
             ctx.device.backend.set_pending_rx(false);
-            assert!(!ctx.device.handle_rxq_event(EventSet::IN));
+            assert!(matches!(
+                ctx.device.handle_rxq_event(EventSet::IN),
+                Err(DeviceError::EventFd(_))
+            ));
         }
     }
 
@@ -376,7 +473,10 @@ mod tests {
             let test_ctx = TestContext::new();
             let mut ctx = test_ctx.create_event_handler_context();
             ctx.device.backend.set_pending_rx(false);
-            assert!(!ctx.device.handle_evq_event(EventSet::IN));
+            assert!(matches!(
+                ctx.device.handle_evq_event(EventSet::IN),
+                Err(DeviceError::EventFd(_))
+            ));
         }
     }
 
@@ -391,7 +491,7 @@ mod tests {
             ctx.mock_activate(test_ctx.mem.clone());
 
             ctx.device.backend.set_pending_rx(true);
-            ctx.device.notify_backend(EventSet::IN);
+            ctx.device.notify_backend(EventSet::IN).unwrap();
 
             // The backend should've received this event.
             assert_eq!(ctx.device.backend.evset, Some(EventSet::IN));
@@ -410,7 +510,7 @@ mod tests {
             ctx.mock_activate(test_ctx.mem.clone());
 
             ctx.device.backend.set_pending_rx(false);
-            ctx.device.notify_backend(EventSet::IN);
+            ctx.device.notify_backend(EventSet::IN).unwrap();
 
             // The backend should've received this event.
             assert_eq!(ctx.device.backend.evset, Some(EventSet::IN));
@@ -575,4 +675,24 @@ mod tests {
             assert_eq!(guest_txvq.used.idx.get(), 1);
         }
     }
+
+    #[test]
+    fn test_error_handling() {
+        let test_ctx = TestContext::new();
+        let mut ctx = test_ctx.create_event_handler_context();
+        ctx.mock_activate(test_ctx.mem.clone());
+
+        // There is no pending queue event.
+        let transient = ctx.device.handle_evq_event(EventSet::IN).unwrap_err();
+        // The driver did not provide any buffer for the event.
+        let degraded = ctx.device.send_transport_reset_event().unwrap_err();
+        check_error_handling(
+            Arc::new(Mutex::new(ctx.device)),
+            [
+                transient,
+                degraded,
+                DeviceError::QueueError(QueueError::DescIndexOutOfBounds(256)),
+            ],
+        );
+    }
 }
@@ -94,6 +94,20 @@ pub(super) struct VsockDeviceMetrics {
     pub tx_write_fails: SharedIncMetric,
     /// Number of times read() has failed.
     pub rx_read_fails: SharedIncMetric,
+    /// Number of datagrams delivered to the guest.
+    pub dgram_rx_packets_count: SharedIncMetric,
+    /// Number of datagrams sent by the guest to the host.
+    pub dgram_tx_packets_count: SharedIncMetric,
+    /// Number of host datagrams dropped before reaching the guest.
+    pub dgram_rx_drops: SharedIncMetric,
+    /// Number of guest datagrams dropped before reaching the host.
+    pub dgram_tx_drops: SharedIncMetric,
+    /// Number of times a connection had data for the guest, but no credit to send it.
+    pub credit_stalls: SharedIncMetric,
+    /// Number of RST packets sent to the guest.
+    pub rst_sent: SharedIncMetric,
+    /// Number of connections refused because the connection table was full.
+    pub conn_table_full: SharedIncMetric,
 }
 
 impl VsockDeviceMetrics {
@@ -121,6 +135,13 @@ impl VsockDeviceMetrics {
             tx_flush_fails: SharedIncMetric::new(),
             tx_write_fails: SharedIncMetric::new(),
             rx_read_fails: SharedIncMetric::new(),
+            dgram_rx_packets_count: SharedIncMetric::new(),
+            dgram_tx_packets_count: SharedIncMetric::new(),
+            dgram_rx_drops: SharedIncMetric::new(),
+            dgram_tx_drops: SharedIncMetric::new(),
+            credit_stalls: SharedIncMetric::new(),
+            rst_sent: SharedIncMetric::new(),
+            conn_table_full: SharedIncMetric::new(),
         }
     }
 }
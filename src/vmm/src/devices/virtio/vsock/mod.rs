@@ -26,12 +26,16 @@ use packet::VsockPacket;
 use utils::epoll::EventSet;
 use vm_memory::GuestMemoryError;
 
+pub use self::csm::VsockConnectionInfo;
 pub use self::defs::uapi::VIRTIO_ID_VSOCK as TYPE_VSOCK;
 pub use self::defs::VSOCK_DEV_ID;
 pub use self::device::Vsock;
 pub use self::unix::{VsockUnixBackend, VsockUnixBackendError};
+use crate::devices::virtio::device::QueueSizeError;
 use crate::devices::virtio::iovec::IoVecError;
 use crate::devices::virtio::persist::PersistError as VirtioStateError;
+use crate::devices::virtio::queue::ChainError;
+use crate::devices::ErrorSeverity;
 
 mod defs {
     use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
@@ -50,6 +54,10 @@ mod defs {
         FIRECRACKER_MAX_QUEUE_SIZE,
         FIRECRACKER_MAX_QUEUE_SIZE,
     ];
+    /// Smallest queue size that can be configured for the vsock device.
+    pub const VSOCK_MIN_QUEUE_SIZE: u16 = 16;
+    /// Largest queue size that can be configured for the vsock device.
+    pub const VSOCK_MAX_QUEUE_SIZE: u16 = 1024;
 
     /// Max vsock packet data/buffer size.
     pub const MAX_PKT_BUF_SIZE: u32 = 64 * 1024;
@@ -64,6 +72,8 @@ mod defs {
         pub const VIRTIO_F_IN_ORDER: usize = 35;
         /// The device conforms to the virtio spec version 1.0.
         pub const VIRTIO_F_VERSION_1: u32 = 32;
+        /// The device supports datagram (VSOCK_TYPE_DGRAM) sockets.
+        pub const VIRTIO_VSOCK_F_DGRAM: u32 = 3;
 
         /// Virtio vsock device ID.
         /// Defined in `include/uapi/linux/virtio_ids.h`.
@@ -98,8 +108,10 @@ mod defs {
         /// Vsock packet type.
         /// Defined in `/include/uapi/linux/virtio_vsock.h`.
         ///
-        /// Stream / connection-oriented packet (the only currently valid type).
+        /// Stream / connection-oriented packet.
         pub const VSOCK_TYPE_STREAM: u16 = 1;
+        /// Datagram / connectionless packet, only valid with VIRTIO_VSOCK_F_DGRAM.
+        pub const VSOCK_TYPE_DGRAM: u16 = 3;
 
         pub const VSOCK_HOST_CID: u64 = 2;
     }
@@ -124,6 +136,8 @@ pub enum VsockError {
     DescChainTooShortForHeader(usize),
     /// The descriptor chain length was greater than the max ([u32::MAX])
     DescChainOverflow,
+    /// Invalid descriptor chain: {0}
+    InvalidChain(ChainError),
     /// The vsock header `len` field holds an invalid value: {0}
     InvalidPktLen(u32),
     /// A data fetch was attempted when no data was available.
@@ -134,19 +148,64 @@ pub enum VsockError {
     UnreadableDescriptor,
     /// Encountered an unexpected read-only virtio descriptor.
     UnwritableDescriptor,
+    /// Invalid queue size: {0}
+    QueueSize(QueueSizeError),
     /// Invalid virtio configuration: {0}
     VirtioState(VirtioStateError),
     /// Vsock uds backend error: {0}
     VsockUdsBackend(VsockUnixBackendError),
 }
 
+impl VsockError {
+    /// Classifies the error by how badly it affects the vsock device.
+    pub fn severity(&self) -> ErrorSeverity {
+        match self {
+            // The packet is dropped, and the guest moves on to the next one.
+            Self::DescChainTooShortForPacket(..)
+            | Self::DescChainTooShortForHeader(_)
+            | Self::DescChainOverflow
+            | Self::InvalidChain(_)
+            | Self::GuestMemoryMmap(_)
+            | Self::GuestMemoryBounds
+            | Self::InvalidPktLen(_)
+            | Self::NoData
+            | Self::PktBufMissing
+            | Self::UnreadableDescriptor
+            | Self::UnwritableDescriptor => ErrorSeverity::Transient,
+            // A lost queue or backend wakeup: the next event catches up with the work left
+            // behind.
+            Self::EventFd(_) => ErrorSeverity::Transient,
+            // The guest missed an event, e.g. the reset of its connections, or the backend
+            // failed to serve a connection, but other connections keep working.
+            Self::EmptyQueue
+            | Self::QueueSize(_)
+            | Self::VirtioState(_)
+            | Self::VsockUdsBackend(_) => ErrorSeverity::Degraded,
+        }
+    }
+}
+
 impl From<IoVecError> for VsockError {
     fn from(value: IoVecError) -> Self {
         match value {
             IoVecError::WriteOnlyDescriptor => VsockError::UnreadableDescriptor,
-            IoVecError::ReadOnlyDescriptor => VsockError::UnwritableDescriptor,
+            IoVecError::ReadOnlyDescriptor | IoVecError::ReadableAfterWritable => {
+                VsockError::UnwritableDescriptor
+            }
             IoVecError::GuestMemory(err) => VsockError::GuestMemoryMmap(err),
-            IoVecError::OverflowedDescriptor => VsockError::DescChainOverflow,
+            IoVecError::OverflowedDescriptor
+            | IoVecError::DescriptorChainTooLong(_)
+            | IoVecError::TooManyDescriptors(_) => VsockError::DescChainOverflow,
+            IoVecError::InvalidChain(err) => VsockError::InvalidChain(err),
+            IoVecError::ShortBuffer { remaining, .. }
+            | IoVecError::TruncatedWrite { remaining, .. } => {
+                VsockError::DescChainTooShortForHeader(remaining)
+            }
+            IoVecError::OverlapsQueue(_) => VsockError::GuestMemoryBounds,
+            // Packets are never transferred to or from files, nor as a whole.
+            IoVecError::VolatileMemory(_)
+            | IoVecError::FileIo(_)
+            | IoVecError::PartialTransfer { .. } => VsockError::GuestMemoryBounds,
         }
     }
 }
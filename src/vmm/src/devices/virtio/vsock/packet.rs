@@ -20,9 +20,10 @@ use std::fmt::Debug;
 use vm_memory::volatile_memory::Error;
 use vm_memory::{GuestMemoryError, ReadVolatile, WriteVolatile};
 
+use super::csm::VsockConnectionBackend;
 use super::{defs, VsockError};
 use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut};
-use crate::devices::virtio::queue::DescriptorChain;
+use crate::devices::virtio::queue::{DescriptorChain, QueueRings};
 use crate::vstate::memory::ByteValued;
 
 // The vsock packet header is defined by the C struct:
@@ -88,29 +89,42 @@ unsafe impl ByteValued for VsockPacketHeader {}
 /// Read and write permissions are statically enforced by using the correct `IoVecBuffer[Mut]`
 /// abstraction
 #[derive(Debug)]
-pub enum VsockPacketBuffer {
+pub enum VsockPacketBuffer<'a> {
     /// Buffer holds a read-only guest-to-host (TX) packet
-    Tx(IoVecBuffer),
+    Tx(IoVecBuffer<'a>),
     /// Buffer holds a write-only host-to-guest (RX) packet
-    Rx(IoVecBufferMut),
+    Rx(IoVecBufferMut<'a>),
+}
+
+// Checks that `count` bytes of packet data starting at `offset` fit in a buffer of `buffer_len`
+// bytes, which also holds the header.
+fn check_data_range(buffer_len: u32, offset: usize, count: usize) -> Result<(), VsockError> {
+    if count
+        > (buffer_len as usize)
+            .saturating_sub(VSOCK_PKT_HDR_SIZE as usize)
+            .saturating_sub(offset)
+    {
+        return Err(VsockError::GuestMemoryBounds);
+    }
+    Ok(())
 }
 
 /// Struct describing a single vsock packet.
 ///
 /// Encapsulates the virtio descriptor chain containing the packet through the `IoVecBuffer[Mut]`
-/// abstractions.
+/// abstractions, and therefore borrows the guest memory the chain resides in for `'a`.
 #[derive(Debug)]
-pub struct VsockPacket {
+pub struct VsockPacket<'a> {
     /// A copy of the vsock packet's 44-byte header, held in hypervisor memory
     /// to minimize the number of accesses to guest memory. Can be written back
     /// to geust memory using [`VsockPacket::commit_hdr`] (only for RX buffers).
     hdr: VsockPacketHeader,
     /// The raw buffer, as it is contained in guest memory (containing both
     /// header and payload)
-    buffer: VsockPacketBuffer,
+    buffer: VsockPacketBuffer<'a>,
 }
 
-impl VsockPacket {
+impl<'a> VsockPacket<'a> {
     /// Create the packet wrapper from a TX virtq chain head.
     ///
     /// ## Errors
@@ -123,7 +137,7 @@ impl VsockPacket {
     ///   length would exceed [`defs::MAX_PKT_BUR_SIZE`].
     /// - [`VsockError::DescChainTooShortForPacket`] if the contained vsock header describes a vsock
     ///   packet whose length exceeds the descriptor chain's actual total buffer length.
-    pub fn from_tx_virtq_head(chain: DescriptorChain) -> Result<Self, VsockError> {
+    pub fn from_tx_virtq_head(chain: DescriptorChain<'a>) -> Result<Self, VsockError> {
         let buffer = IoVecBuffer::from_descriptor_chain(chain)?;
 
         let mut hdr = VsockPacketHeader::default();
@@ -157,7 +171,7 @@ impl VsockPacket {
     /// ## Errors
     /// Returns [`VsockError::DescChainTooShortForHeader`] if the descriptor chain's total buffer
     /// length is insufficient to hold the 44 byte vsock header
-    pub fn from_rx_virtq_head(chain: DescriptorChain) -> Result<Self, VsockError> {
+    pub fn from_rx_virtq_head(chain: DescriptorChain<'a>) -> Result<Self, VsockError> {
         let buffer = IoVecBufferMut::from_descriptor_chain(chain)?;
 
         if buffer.len() < VSOCK_PKT_HDR_SIZE {
@@ -174,6 +188,15 @@ impl VsockPacket {
         })
     }
 
+    /// Checks that the buffer of an RX packet does not overlap the `rings` of its queue, which
+    /// writing the packet would corrupt.
+    pub fn check_queue_overlap(&self, rings: &QueueRings) -> Result<(), VsockError> {
+        match self.buffer {
+            VsockPacketBuffer::Tx(_) => Ok(()),
+            VsockPacketBuffer::Rx(ref buffer) => Ok(buffer.check_queue_overlap(rings)?),
+        }
+    }
+
     /// Provides in-place access to the local copy of the vsock packet header.
     pub fn hdr(&self) -> &VsockPacketHeader {
         &self.hdr
@@ -224,14 +247,7 @@ impl VsockPacket {
         match self.buffer {
             VsockPacketBuffer::Tx(_) => Err(VsockError::UnwritableDescriptor),
             VsockPacketBuffer::Rx(ref mut buffer) => {
-                if count
-                    > (buffer.len() as usize)
-                        .saturating_sub(VSOCK_PKT_HDR_SIZE as usize)
-                        .saturating_sub(offset)
-                {
-                    return Err(VsockError::GuestMemoryBounds);
-                }
-
+                check_data_range(buffer.len(), offset, count)?;
                 buffer
                     .write_volatile_at(src, offset + VSOCK_PKT_HDR_SIZE as usize, count)
                     .map_err(|err| VsockError::GuestMemoryMmap(GuestMemoryError::from(err)))
@@ -247,14 +263,7 @@ impl VsockPacket {
     ) -> Result<usize, VsockError> {
         match self.buffer {
             VsockPacketBuffer::Tx(ref buffer) => {
-                if count
-                    > (buffer.len() as usize)
-                        .saturating_sub(VSOCK_PKT_HDR_SIZE as usize)
-                        .saturating_sub(offset)
-                {
-                    return Err(VsockError::GuestMemoryBounds);
-                }
-
+                check_data_range(buffer.len(), offset, count)?;
                 buffer
                     .read_volatile_at(dst, offset + VSOCK_PKT_HDR_SIZE as usize, count)
                     .map_err(|err| VsockError::GuestMemoryMmap(GuestMemoryError::from(err)))
@@ -263,6 +272,44 @@ impl VsockPacket {
         }
     }
 
+    /// Receives up to `count` bytes from the connection backend `stream` into the packet data
+    /// starting at `offset`, through [`VsockConnectionBackend::recv_iovec`].
+    pub fn recv_at_offset_from<S: VsockConnectionBackend>(
+        &mut self,
+        stream: &mut S,
+        offset: usize,
+        count: usize,
+    ) -> Result<usize, VsockError> {
+        match self.buffer {
+            VsockPacketBuffer::Tx(_) => Err(VsockError::UnwritableDescriptor),
+            VsockPacketBuffer::Rx(ref mut buffer) => {
+                check_data_range(buffer.len(), offset, count)?;
+                stream
+                    .recv_iovec(buffer, offset + VSOCK_PKT_HDR_SIZE as usize, count)
+                    .map_err(|err| VsockError::GuestMemoryMmap(GuestMemoryError::from(err)))
+            }
+        }
+    }
+
+    /// Sends up to `count` bytes of the packet data starting at `offset` to the connection
+    /// backend `stream`, through [`VsockConnectionBackend::send_iovec`].
+    pub fn send_from_offset_to<S: VsockConnectionBackend>(
+        &self,
+        stream: &mut S,
+        offset: usize,
+        count: usize,
+    ) -> Result<usize, VsockError> {
+        match self.buffer {
+            VsockPacketBuffer::Tx(ref buffer) => {
+                check_data_range(buffer.len(), offset, count)?;
+                stream
+                    .send_iovec(buffer, offset + VSOCK_PKT_HDR_SIZE as usize, count)
+                    .map_err(|err| VsockError::GuestMemoryMmap(GuestMemoryError::from(err)))
+            }
+            VsockPacketBuffer::Rx(_) => Err(VsockError::UnreadableDescriptor),
+        }
+    }
+
     pub fn src_cid(&self) -> u64 {
         u64::from_le(self.hdr.src_cid)
     }
@@ -364,7 +411,7 @@ mod tests {
     use vm_memory::Bytes;
 
     use super::*;
-    use crate::devices::virtio::queue::VIRTQ_DESC_F_WRITE;
+    use crate::devices::virtio::queue::{ChainError, VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
     use crate::devices::virtio::test_utils::VirtqDesc as GuestQDesc;
     use crate::devices::virtio::vsock::defs::MAX_PKT_BUF_SIZE;
     use crate::devices::virtio::vsock::device::{RXQ_INDEX, TXQ_INDEX};
@@ -516,6 +563,33 @@ mod tests {
                 VsockError::DescChainTooShortForPacket(4140, 8192)
             );
         }
+
+        // Test case: the buffer descriptor links back to the header descriptor.
+        {
+            create_context!(test_ctx, handler_ctx);
+            handler_ctx.guest_txvq.dtable[1]
+                .flags
+                .set(VIRTQ_DESC_F_NEXT);
+            handler_ctx.guest_txvq.dtable[1].next.set(0);
+            expect_asm_error!(
+                tx,
+                test_ctx,
+                handler_ctx,
+                VsockError::InvalidChain(ChainError::Cycle(0))
+            );
+        }
+
+        // Test case: the header descriptor links to itself.
+        {
+            create_context!(test_ctx, handler_ctx);
+            handler_ctx.guest_txvq.dtable[0].next.set(0);
+            expect_asm_error!(
+                tx,
+                test_ctx,
+                handler_ctx,
+                VsockError::InvalidChain(ChainError::Cycle(0))
+            );
+        }
     }
 
     #[test]
@@ -556,6 +630,21 @@ mod tests {
                 VsockError::DescChainTooShortForHeader(_)
             );
         }
+
+        // Test case: the buffer descriptor links back to the header descriptor.
+        {
+            create_context!(test_ctx, handler_ctx);
+            handler_ctx.guest_rxvq.dtable[1]
+                .flags
+                .set(VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE);
+            handler_ctx.guest_rxvq.dtable[1].next.set(0);
+            expect_asm_error!(
+                rx,
+                test_ctx,
+                handler_ctx,
+                VsockError::InvalidChain(ChainError::Cycle(0))
+            );
+        }
     }
 
     #[test]
@@ -10,7 +10,7 @@ use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 
 use super::*;
-use crate::devices::virtio::device::DeviceState;
+use crate::devices::virtio::device::{check_queue_size, DeviceState};
 use crate::devices::virtio::persist::VirtioDeviceState;
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
 use crate::devices::virtio::vsock::TYPE_VSOCK;
@@ -32,6 +32,7 @@ pub struct VsockFrontendState {
     /// Context IDentifier.
     pub cid: u64,
     virtio_state: VirtioDeviceState,
+    queue_size: Option<u16>,
 }
 
 /// An enum for the serializable backend state types.
@@ -46,6 +47,47 @@ pub enum VsockBackendState {
 pub struct VsockUdsState {
     /// The path for the UDS socket.
     pub(crate) path: String,
+    /// Whether connections are kept across snapshots.
+    pub(crate) keep_connections: bool,
+    /// The connections to re-establish on restore, if `keep_connections` is set.
+    pub(crate) connections: Vec<VsockConnectionState>,
+}
+
+/// The serializable state of a vsock connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VsockConnectionState {
+    /// The local (host) port.
+    pub local_port: u32,
+    /// The peer (guest) port.
+    pub peer_port: u32,
+    /// Whether the connection can be resumed. Connections which can't are reset on restore,
+    /// and none of their other fields are saved.
+    pub resumable: bool,
+    /// Total number of bytes forwarded to the host stream.
+    pub fwd_cnt: u32,
+    /// The amount of buffer space that the guest has allocated for this connection.
+    pub peer_buf_alloc: u32,
+    /// The total number of bytes that the guest has forwarded away.
+    pub peer_fwd_cnt: u32,
+    /// The total number of bytes sent to the guest.
+    pub rx_cnt: u32,
+    /// `fwd_cnt`, as last sent to the guest.
+    pub last_fwd_cnt_to_peer: u32,
+    /// Guest data not yet written to the host stream.
+    pub tx_data: Vec<u8>,
+    /// Host data read from the host stream, not yet sent to the guest.
+    pub rx_data: Vec<u8>,
+}
+
+/// The constructor arguments of a vsock connection restored from a snapshot.
+#[derive(Debug)]
+pub struct VsockConnectionConstructorArgs<S> {
+    /// The host-side stream, connected anew.
+    pub stream: S,
+    /// The local CID.
+    pub local_cid: u64,
+    /// The peer (guest) CID.
+    pub peer_cid: u64,
 }
 
 /// A helper structure that holds the constructor arguments for VsockUnixBackend
@@ -72,6 +114,12 @@ impl Persist<'_> for VsockUnixBackend {
     fn save(&self) -> Self::State {
         VsockBackendState::Uds(VsockUdsState {
             path: self.host_sock_path.clone(),
+            keep_connections: self.keep_connections(),
+            connections: if self.keep_connections() {
+                self.save_connections()
+            } else {
+                Vec::new()
+            },
         })
     }
 
@@ -80,10 +128,13 @@ impl Persist<'_> for VsockUnixBackend {
         state: &Self::State,
     ) -> Result<Self, Self::Error> {
         match state {
-            VsockBackendState::Uds(uds_state) => Ok(VsockUnixBackend::new(
-                constructor_args.cid,
-                uds_state.path.clone(),
-            )?),
+            VsockBackendState::Uds(uds_state) => {
+                let mut backend =
+                    VsockUnixBackend::new(constructor_args.cid, uds_state.path.clone())?;
+                backend.set_keep_connections(uds_state.keep_connections);
+                backend.restore_connections(&uds_state.connections);
+                Ok(backend)
+            }
         }
     }
 }
@@ -100,6 +151,7 @@ where
         VsockFrontendState {
             cid: self.cid(),
             virtio_state: VirtioDeviceState::from_device(self),
+            queue_size: self.queue_size(),
         }
     }
 
@@ -108,19 +160,28 @@ where
         state: &Self::State,
     ) -> Result<Self, Self::Error> {
         // Restore queues.
+        let queue_max_size = match state.queue_size {
+            Some(size) => {
+                check_queue_size(size, defs::VSOCK_MIN_QUEUE_SIZE, defs::VSOCK_MAX_QUEUE_SIZE)
+                    .map_err(VsockError::QueueSize)?
+            }
+            None => FIRECRACKER_MAX_QUEUE_SIZE,
+        };
         let queues = state
             .virtio_state
             .build_queues_checked(
                 &constructor_args.mem,
                 TYPE_VSOCK,
                 defs::VSOCK_NUM_QUEUES,
-                FIRECRACKER_MAX_QUEUE_SIZE,
+                queue_max_size,
             )
             .map_err(VsockError::VirtioState)?;
         let mut vsock = Self::with_queues(state.cid, constructor_args.backend, queues)?;
+        vsock.queue_size = state.queue_size;
 
         vsock.acked_features = state.virtio_state.acked_features;
         vsock.avail_features = state.virtio_state.avail_features;
+        vsock.virtio_features_pin = state.virtio_state.virtio_features_pin;
         vsock.irq_trigger.irq_status =
             Arc::new(AtomicU32::new(state.virtio_state.interrupt_status));
         vsock.device_state = if state.virtio_state.activated {
@@ -151,6 +212,8 @@ pub(crate) mod tests {
         fn save(&self) -> Self::State {
             VsockBackendState::Uds(VsockUdsState {
                 path: "test".to_owned(),
+                keep_connections: false,
+                connections: Vec::new(),
             })
         }
 
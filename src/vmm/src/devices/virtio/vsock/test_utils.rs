@@ -197,11 +197,11 @@ impl<'a> EventHandlerContext<'a> {
 
     pub fn signal_txq_event(&mut self) {
         self.device.queue_events[TXQ_INDEX].write(1).unwrap();
-        self.device.handle_txq_event(EventSet::IN);
+        self.device.handle_txq_event(EventSet::IN).unwrap();
     }
     pub fn signal_rxq_event(&mut self) {
         self.device.queue_events[RXQ_INDEX].write(1).unwrap();
-        self.device.handle_rxq_event(EventSet::IN);
+        self.device.handle_rxq_event(EventSet::IN).unwrap();
     }
 }
 
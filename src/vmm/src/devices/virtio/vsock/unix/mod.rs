@@ -8,11 +8,14 @@
 /// handling vsock connection states.
 /// Check out `muxer.rs` for a more detailed explanation of the inner workings of this backend.
 mod muxer;
+mod muxer_dgram;
 mod muxer_killq;
 mod muxer_rxq;
 
 pub use muxer::VsockMuxer as VsockUnixBackend;
+use vm_memory::VolatileMemoryError;
 
+use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut};
 use crate::devices::virtio::vsock::csm::VsockConnectionBackend;
 
 mod defs {
@@ -24,6 +27,12 @@ mod defs {
 
     /// Size of the muxer connection kill queue.
     pub const MUXER_KILLQ_SIZE: u32 = 128;
+
+    /// Maximum number of guest ports that we can exchange datagrams with.
+    pub const MAX_DGRAM_PORTS: usize = 64;
+
+    /// Size of the queue holding the host datagrams of a guest port, until the guest reads them.
+    pub const MUXER_DGRAM_RXQ_SIZE: usize = 64;
 }
 
 /// Vsock backend related errors.
@@ -43,10 +52,36 @@ pub enum VsockUnixBackendError {
     UnixConnect(std::io::Error),
     /// Error reading from host-side Unix socket: {0}
     UnixRead(std::io::Error),
+    /// Error sending a datagram to a host-side Unix socket: {0}
+    UnixSend(std::io::Error),
+    /// Error restoring a connection from a snapshot: {0}
+    RestoreConnection(super::csm::VsockCsmError),
     /// Muxer connection limit reached.
     TooManyConnections,
+    /// Muxer datagram port limit reached.
+    TooManyDgramPorts,
 }
 
 type MuxerConnection = super::csm::VsockConnection<std::os::unix::net::UnixStream>;
 
-impl VsockConnectionBackend for std::os::unix::net::UnixStream {}
+// Unix sockets take and fill all the memory regions of a packet with a single `sendmsg` or
+// `recvmsg`.
+impl VsockConnectionBackend for std::os::unix::net::UnixStream {
+    fn send_iovec(
+        &mut self,
+        buf: &IoVecBuffer,
+        offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError> {
+        buf.send_to_socket(self, offset, len)
+    }
+
+    fn recv_iovec(
+        &mut self,
+        buf: &mut IoVecBufferMut,
+        offset: usize,
+        len: usize,
+    ) -> Result<usize, VolatileMemoryError> {
+        buf.recv_from_socket(self, offset, len)
+    }
+}
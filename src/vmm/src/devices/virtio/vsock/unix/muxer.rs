@@ -29,7 +29,15 @@
 ///    other pollable FDs are then registered under this nested epoll FD.
 ///    To route all these events to their handlers, the muxer uses another `HashMap` object,
 ///    mapping `RawFd`s to `EpollListener`s.
-use std::collections::{HashMap, HashSet};
+///
+/// Datagrams (VSOCK_TYPE_DGRAM packets) bypass connections altogether: they are exchanged
+/// through a `MuxerDgramPort` per guest port, see `muxer_dgram.rs`.
+///
+/// When asked to keep connections across snapshots, the muxer saves the established ones, and
+/// re-establishes their host side on restore by connecting to `<uds_path>_<host port>`, for
+/// host-initiated connections too. The guest is sent an RST for the connections that cannot be
+/// re-established.
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
 use std::io::Read;
 use std::os::unix::io::{AsRawFd, RawFd};
@@ -38,15 +46,18 @@ use std::os::unix::net::{UnixListener, UnixStream};
 use log::{debug, error, info, warn};
 use utils::epoll::{ControlOperation, Epoll, EpollEvent, EventSet};
 
-use super::super::csm::ConnState;
+use super::super::csm::{ConnState, VsockConnectionInfo};
 use super::super::defs::uapi;
 use super::super::packet::VsockPacket;
+use super::super::persist::{VsockConnectionConstructorArgs, VsockConnectionState};
 use super::super::{VsockBackend, VsockChannel, VsockEpollListener, VsockError};
+use super::muxer_dgram::MuxerDgramPort;
 use super::muxer_killq::MuxerKillQ;
 use super::muxer_rxq::MuxerRxQ;
 use super::{defs, MuxerConnection, VsockUnixBackendError};
 use crate::devices::virtio::vsock::metrics::METRICS;
 use crate::logger::IncMetric;
+use crate::snapshot::Persist;
 
 /// A unique identifier of a `MuxerConnection` object. Connections are stored in a hash map,
 /// keyed by a `ConnMapKey` object.
@@ -77,6 +88,8 @@ enum EpollListener {
     /// A listener interested in reading host `connect <port>` commands from a freshly
     /// connected host socket.
     LocalStream(UnixStream),
+    /// A listener interested in the host datagrams sent to the guest datagram port it holds.
+    DgramPort(u32),
 }
 
 /// The vsock connection multiplexer.
@@ -107,6 +120,16 @@ pub struct VsockMuxer {
     local_port_set: HashSet<u32>,
     /// The last used host-side port.
     local_port_last: u32,
+    /// A hash map used to store the host-side sockets of guest datagram ports, keyed by guest
+    /// port.
+    dgram_map: HashMap<u32, MuxerDgramPort>,
+    /// The guest datagram ports holding host datagrams, in the order in which they are served.
+    dgram_rxq: VecDeque<u32>,
+    /// Whether the next RX packet is looked for among the datagrams first. Datagrams and
+    /// connection packets take turns, so that neither can starve the other.
+    dgram_rx_turn: bool,
+    /// Whether connections are kept across snapshots, instead of being reset.
+    keep_connections: bool,
 }
 
 impl VsockChannel for VsockMuxer {
@@ -116,68 +139,18 @@ impl VsockChannel for VsockMuxer {
     /// - `Ok(())`: `pkt` has been successfully filled in; or
     /// - `Err(VsockError::NoData)`: there was no available data with which to fill in the packet.
     fn recv_pkt(&mut self, pkt: &mut VsockPacket) -> Result<(), VsockError> {
-        // We'll look for instructions on how to build the RX packet in the RX queue. If the
-        // queue is empty, that doesn't necessarily mean we don't have any pending RX, since
-        // the queue might be out-of-sync. If that's the case, we'll attempt to sync it first,
-        // and then try to pop something out again.
-        if self.rxq.is_empty() && !self.rxq.is_synced() {
-            self.rxq = MuxerRxQ::from_conn_map(&self.conn_map);
-        }
-
-        while let Some(rx) = self.rxq.peek() {
-            let res = match rx {
-                // We need to build an RST packet, going from `local_port` to `peer_port`.
-                MuxerRx::RstPkt {
-                    local_port,
-                    peer_port,
-                } => {
-                    pkt.set_op(uapi::VSOCK_OP_RST)
-                        .set_src_cid(uapi::VSOCK_HOST_CID)
-                        .set_dst_cid(self.cid)
-                        .set_src_port(local_port)
-                        .set_dst_port(peer_port)
-                        .set_len(0)
-                        .set_type(uapi::VSOCK_TYPE_STREAM)
-                        .set_flags(0)
-                        .set_buf_alloc(0)
-                        .set_fwd_cnt(0);
-                    self.rxq.pop().unwrap();
-                    return Ok(());
-                }
-
-                // We'll defer building the packet to this connection, since it has something
-                // to say.
-                MuxerRx::ConnRx(key) => {
-                    let mut conn_res = Err(VsockError::NoData);
-                    let mut do_pop = true;
-                    self.apply_conn_mutation(key, |conn| {
-                        conn_res = conn.recv_pkt(pkt);
-                        do_pop = !conn.has_pending_rx();
-                    });
-                    if do_pop {
-                        self.rxq.pop().unwrap();
-                    }
-                    conn_res
-                }
-            };
-
-            if res.is_ok() {
-                // Inspect traffic, looking for RST packets, since that means we have to
-                // terminate and remove this connection from the active connection pool.
-                //
-                if pkt.op() == uapi::VSOCK_OP_RST {
-                    self.remove_connection(ConnMapKey {
-                        local_port: pkt.src_port(),
-                        peer_port: pkt.dst_port(),
-                    });
-                }
-
-                debug!("vsock muxer: RX pkt: {:?}", pkt.hdr());
-                return Ok(());
+        self.dgram_rx_turn = !self.dgram_rx_turn;
+        if self.dgram_rx_turn {
+            match self.recv_dgram_pkt(pkt) {
+                Err(VsockError::NoData) => self.recv_conn_pkt(pkt),
+                res => res,
+            }
+        } else {
+            match self.recv_conn_pkt(pkt) {
+                Err(VsockError::NoData) => self.recv_dgram_pkt(pkt),
+                res => res,
             }
         }
-
-        Err(VsockError::NoData)
     }
 
     /// Deliver a guest-generated packet to its destination in the vsock backend.
@@ -200,7 +173,22 @@ impl VsockChannel for VsockMuxer {
             pkt.hdr()
         );
 
-        // If this packet has an unsupported type (!=stream), we must send back an RST.
+        // Datagrams are unreliable, so the ones that cannot be delivered are dropped without
+        // letting the guest know. There is no connection to reset, either.
+        if pkt.type_() == uapi::VSOCK_TYPE_DGRAM {
+            if pkt.op() != uapi::VSOCK_OP_RW || pkt.dst_cid() != uapi::VSOCK_HOST_CID {
+                METRICS.dgram_tx_drops.inc();
+                return Ok(());
+            }
+            if let Err(err) = self.send_dgram_pkt(pkt) {
+                debug!("vsock: dropping guest datagram {:?}: {:?}", pkt.hdr(), err);
+                METRICS.dgram_tx_drops.inc();
+            }
+            return Ok(());
+        }
+
+        // If this packet has an unsupported type (not stream, nor datagram), we must send back an
+        // RST.
         //
         if pkt.type_() != uapi::VSOCK_TYPE_STREAM {
             self.enq_rst(pkt.dst_port(), pkt.src_port());
@@ -251,7 +239,7 @@ impl VsockChannel for VsockMuxer {
     /// Check if the muxer has any pending RX data, with which to fill a guest-provided RX
     /// buffer.
     fn has_pending_rx(&self) -> bool {
-        !self.rxq.is_empty() || !self.rxq.is_synced()
+        !self.rxq.is_empty() || !self.rxq.is_synced() || !self.dgram_rxq.is_empty()
     }
 }
 
@@ -319,6 +307,10 @@ impl VsockMuxer {
             killq: MuxerKillQ::new(),
             local_port_last: (1u32 << 30) - 1,
             local_port_set: HashSet::with_capacity(defs::MAX_CONNECTIONS),
+            dgram_map: HashMap::with_capacity(defs::MAX_DGRAM_PORTS),
+            dgram_rxq: VecDeque::with_capacity(defs::MAX_DGRAM_PORTS),
+            dgram_rx_turn: false,
+            keep_connections: false,
         };
 
         // Listen on the host initiated socket, for incoming connections.
@@ -331,6 +323,167 @@ impl VsockMuxer {
         &self.host_sock_path
     }
 
+    /// Take a snapshot of the connection table, sorted by local port, then by peer port.
+    ///
+    /// Only the in-memory state of the connections is read, so that this stays cheap enough to
+    /// be called from the event loop, even with a full connection table.
+    pub fn connections(&self) -> Vec<VsockConnectionInfo> {
+        let mut conns: Vec<VsockConnectionInfo> =
+            self.conn_map.values().map(|conn| conn.info()).collect();
+        conns.sort_unstable_by_key(|conn| (conn.local_port, conn.peer_port));
+        conns
+    }
+
+    /// Whether connections are kept across snapshots, instead of being reset.
+    pub fn keep_connections(&self) -> bool {
+        self.keep_connections
+    }
+
+    /// Set whether connections are kept across snapshots.
+    pub fn set_keep_connections(&mut self, keep_connections: bool) {
+        self.keep_connections = keep_connections;
+    }
+
+    /// Read the host data pending on the connections ahead of a snapshot, so that it can be
+    /// saved along with them.
+    ///
+    /// This doesn't disturb the connections if the microVM carries on after the snapshot: the
+    /// data read ahead is sent to the guest before any more is read from the host.
+    pub fn drain_connections(&mut self) {
+        let keys: Vec<ConnMapKey> = self.conn_map.keys().copied().collect();
+        for key in keys {
+            self.apply_conn_mutation(key, |conn| conn.drain_stream());
+        }
+    }
+
+    /// Save the state of the connections, sorted by local port, then by peer port.
+    pub fn save_connections(&self) -> Vec<VsockConnectionState> {
+        let mut states: Vec<VsockConnectionState> =
+            self.conn_map.values().map(|conn| conn.save()).collect();
+        states.sort_unstable_by_key(|state| (state.local_port, state.peer_port));
+        states
+    }
+
+    /// Re-establish the host side of the connections saved in a snapshot.
+    ///
+    /// The connections which cannot be resumed, or whose host-side listener is gone, are reset,
+    /// so that the guest learns about it as soon as it resumes.
+    pub fn restore_connections(&mut self, states: &[VsockConnectionState]) {
+        for state in states {
+            if state.resumable {
+                match self.restore_connection(state) {
+                    Ok(()) => continue,
+                    Err(err) => warn!(
+                        "vsock: unable to restore connection (lp={}, pp={}): {:?}",
+                        state.local_port, state.peer_port, err
+                    ),
+                }
+            }
+            self.enq_rst(state.local_port, state.peer_port);
+        }
+    }
+
+    /// Connect to the host-side Unix socket listening at `<host_sock_path>_<local port>`, and
+    /// resume the saved connection over it.
+    fn restore_connection(
+        &mut self,
+        state: &VsockConnectionState,
+    ) -> Result<(), VsockUnixBackendError> {
+        let port_path = format!("{}_{}", self.host_sock_path, state.local_port);
+        let stream = UnixStream::connect(port_path)
+            .and_then(|stream| stream.set_nonblocking(true).map(|_| stream))
+            .map_err(VsockUnixBackendError::UnixConnect)?;
+        let conn = MuxerConnection::restore(
+            VsockConnectionConstructorArgs {
+                stream,
+                local_cid: uapi::VSOCK_HOST_CID,
+                peer_cid: self.cid,
+            },
+            state,
+        )
+        .map_err(VsockUnixBackendError::RestoreConnection)?;
+
+        self.add_connection(
+            ConnMapKey {
+                local_port: state.local_port,
+                peer_port: state.peer_port,
+            },
+            conn,
+        )?;
+        // Host-initiated connections keep their local port, which is freed again when they are
+        // removed.
+        self.local_port_set.insert(state.local_port);
+        Ok(())
+    }
+
+    /// Fill `pkt` with the next packet of a connection, or with an RST queued by the muxer.
+    fn recv_conn_pkt(&mut self, pkt: &mut VsockPacket) -> Result<(), VsockError> {
+        // We'll look for instructions on how to build the RX packet in the RX queue. If the
+        // queue is empty, that doesn't necessarily mean we don't have any pending RX, since
+        // the queue might be out-of-sync. If that's the case, we'll attempt to sync it first,
+        // and then try to pop something out again.
+        if self.rxq.is_empty() && !self.rxq.is_synced() {
+            self.rxq = MuxerRxQ::from_conn_map(&self.conn_map);
+        }
+
+        while let Some(rx) = self.rxq.peek() {
+            let res = match rx {
+                // We need to build an RST packet, going from `local_port` to `peer_port`.
+                MuxerRx::RstPkt {
+                    local_port,
+                    peer_port,
+                } => {
+                    pkt.set_op(uapi::VSOCK_OP_RST)
+                        .set_src_cid(uapi::VSOCK_HOST_CID)
+                        .set_dst_cid(self.cid)
+                        .set_src_port(local_port)
+                        .set_dst_port(peer_port)
+                        .set_len(0)
+                        .set_type(uapi::VSOCK_TYPE_STREAM)
+                        .set_flags(0)
+                        .set_buf_alloc(0)
+                        .set_fwd_cnt(0);
+                    self.rxq.pop().unwrap();
+                    METRICS.rst_sent.inc();
+                    return Ok(());
+                }
+
+                // We'll defer building the packet to this connection, since it has something
+                // to say.
+                MuxerRx::ConnRx(key) => {
+                    let mut conn_res = Err(VsockError::NoData);
+                    let mut do_pop = true;
+                    self.apply_conn_mutation(key, |conn| {
+                        conn_res = conn.recv_pkt(pkt);
+                        do_pop = !conn.has_pending_rx();
+                    });
+                    if do_pop {
+                        self.rxq.pop().unwrap();
+                    }
+                    conn_res
+                }
+            };
+
+            if res.is_ok() {
+                // Inspect traffic, looking for RST packets, since that means we have to
+                // terminate and remove this connection from the active connection pool.
+                //
+                if pkt.op() == uapi::VSOCK_OP_RST {
+                    METRICS.rst_sent.inc();
+                    self.remove_connection(ConnMapKey {
+                        local_port: pkt.src_port(),
+                        peer_port: pkt.dst_port(),
+                    });
+                }
+
+                debug!("vsock muxer: RX pkt: {:?}", pkt.hdr());
+                return Ok(());
+            }
+        }
+
+        Err(VsockError::NoData)
+    }
+
     /// Handle/dispatch an epoll event to its listener.
     fn handle_event(&mut self, fd: RawFd, event_set: EventSet) {
         debug!(
@@ -358,6 +511,7 @@ impl VsockMuxer {
                     // If we're already maxed-out on connections, we'll just accept and
                     // immediately discard this potentially new one.
                     warn!("vsock: connection limit reached; refusing new host connection");
+                    METRICS.conn_table_full.inc();
                     self.host_sock.accept().map(|_| 0).unwrap_or(0);
                     return;
                 }
@@ -409,6 +563,19 @@ impl VsockMuxer {
                 }
             }
 
+            // Host datagrams were sent to a guest datagram port.
+            Some(EpollListener::DgramPort(peer_port)) => {
+                let peer_port = *peer_port;
+                let host_dgram_prefix = format!("{}_dgram_", self.host_sock_path);
+                if let Some(port) = self.dgram_map.get_mut(&peer_port) {
+                    let had_rx = port.has_pending_rx();
+                    port.fill_rxq(&host_dgram_prefix);
+                    if !had_rx && port.has_pending_rx() {
+                        self.dgram_rxq.push_back(peer_port);
+                    }
+                }
+            }
+
             _ => {
                 info!(
                     "vsock: unexpected event: fd={:?}, evset={:?}",
@@ -486,6 +653,7 @@ impl VsockMuxer {
                 "vsock: muxer connection limit reached ({})",
                 defs::MAX_CONNECTIONS
             );
+            METRICS.conn_table_full.inc();
             return Err(VsockUnixBackendError::TooManyConnections);
         }
 
@@ -548,6 +716,7 @@ impl VsockMuxer {
             EpollListener::Connection { evset, .. } => evset,
             EpollListener::LocalStream(_) => EventSet::IN,
             EpollListener::HostSock => EventSet::IN,
+            EpollListener::DgramPort(_) => EventSet::IN,
         };
 
         self.epoll
@@ -633,6 +802,96 @@ impl VsockMuxer {
             .unwrap_or_else(|_| self.enq_rst(pkt.dst_port(), pkt.src_port()));
     }
 
+    /// Forward a guest datagram to the host-side Unix socket expected to be bound at the file
+    /// system path corresponding to the destination port.
+    ///
+    /// The datagram is sent from the socket of its source guest port, so that the host can reply
+    /// to it.
+    fn send_dgram_pkt(&mut self, pkt: &VsockPacket) -> Result<(), VsockError> {
+        let len = pkt.len() as usize;
+        let mut data = vec![0u8; len];
+        pkt.write_from_offset_to(&mut data.as_mut_slice(), 0, len)?;
+
+        let dst_path = format!("{}_dgram_{}", self.host_sock_path, pkt.dst_port());
+        self.dgram_port(pkt.src_port())
+            .and_then(|port| port.send_to(&data, &dst_path))
+            .map_err(VsockError::VsockUdsBackend)?;
+        METRICS.dgram_tx_packets_count.inc();
+        Ok(())
+    }
+
+    /// Fill `pkt` with the next host datagram waiting for the guest, if any.
+    ///
+    /// Guest ports take turns, one datagram at a time, so datagrams sent to different ports may
+    /// reach the guest in another order than the one they were received in.
+    fn recv_dgram_pkt(&mut self, pkt: &mut VsockPacket) -> Result<(), VsockError> {
+        while let Some(peer_port) = self.dgram_rxq.pop_front() {
+            let Some(port) = self.dgram_map.get_mut(&peer_port) else {
+                continue;
+            };
+            let Some(dgram) = port.pop_rx() else {
+                continue;
+            };
+            if port.has_pending_rx() {
+                self.dgram_rxq.push_back(peer_port);
+            }
+
+            // A datagram cannot be split across packets.
+            if dgram.data.len() > pkt.buf_size() {
+                METRICS.dgram_rx_drops.inc();
+                continue;
+            }
+
+            pkt.set_op(uapi::VSOCK_OP_RW)
+                .set_src_cid(uapi::VSOCK_HOST_CID)
+                .set_dst_cid(self.cid)
+                .set_src_port(dgram.host_port)
+                .set_dst_port(peer_port)
+                .set_len(u32::try_from(dgram.data.len()).unwrap())
+                .set_type(uapi::VSOCK_TYPE_DGRAM)
+                .set_flags(0)
+                .set_buf_alloc(0)
+                .set_fwd_cnt(0);
+            pkt.read_at_offset_from(&mut dgram.data.as_slice(), 0, dgram.data.len())?;
+            METRICS.dgram_rx_packets_count.inc();
+
+            debug!("vsock muxer: RX dgram: {:?}", pkt.hdr());
+            return Ok(());
+        }
+
+        Err(VsockError::NoData)
+    }
+
+    /// Get the host-side socket of the guest datagram port `peer_port`, binding it on first use.
+    fn dgram_port(&mut self, peer_port: u32) -> Result<&MuxerDgramPort, VsockUnixBackendError> {
+        if !self.dgram_map.contains_key(&peer_port) {
+            self.add_dgram_port(peer_port)?;
+        }
+        Ok(&self.dgram_map[&peer_port])
+    }
+
+    /// Bind the host-side socket of the guest datagram port `peer_port`, at
+    /// `<host_sock_path>_dgram_guest_<peer_port>`, and listen for the datagrams the host sends
+    /// to it.
+    ///
+    /// Ports are kept for the lifetime of the muxer, since datagrams don't tell when the guest
+    /// is done with a port.
+    fn add_dgram_port(&mut self, peer_port: u32) -> Result<(), VsockUnixBackendError> {
+        if self.dgram_map.len() >= defs::MAX_DGRAM_PORTS {
+            info!(
+                "vsock: muxer datagram port limit reached ({})",
+                defs::MAX_DGRAM_PORTS
+            );
+            return Err(VsockUnixBackendError::TooManyDgramPorts);
+        }
+
+        let port =
+            MuxerDgramPort::new(format!("{}_dgram_guest_{}", self.host_sock_path, peer_port))?;
+        self.add_listener(port.as_raw_fd(), EpollListener::DgramPort(peer_port))?;
+        self.dgram_map.insert(peer_port, port);
+        Ok(())
+    }
+
     /// Perform an action that might mutate a connection's state.
     ///
     /// This is used as shorthand for repetitive tasks that need to be performed after a
@@ -786,14 +1045,15 @@ impl VsockMuxer {
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Read, Write};
+    use std::io::{ErrorKind, Read, Write};
     use std::ops::Drop;
-    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
     use std::path::{Path, PathBuf};
 
     use utils::tempfile::TempFile;
 
     use super::super::super::csm::defs as csm_defs;
+    use super::super::super::persist::{VsockBackendState, VsockUdsConstructorArgs};
     use super::*;
     use crate::devices::virtio::vsock::device::{RXQ_INDEX, TXQ_INDEX};
     use crate::devices::virtio::vsock::test_utils;
@@ -803,15 +1063,15 @@ mod tests {
     const PEER_BUF_ALLOC: u32 = 64 * 1024;
 
     #[derive(Debug)]
-    struct MuxerTestContext {
-        _vsock_test_ctx: VsockTestContext,
-        // Two views of the same in-memory packet. rx-view for writing, tx-view for reading
-        rx_pkt: VsockPacket,
-        tx_pkt: VsockPacket,
+    struct MuxerTestContext<'a> {
+        // Two views of the same in-memory packet. rx-view for writing, tx-view for reading. The
+        // packets borrow the guest memory of the vsock test context, which the test keeps alive.
+        rx_pkt: VsockPacket<'a>,
+        tx_pkt: VsockPacket<'a>,
         muxer: VsockMuxer,
     }
 
-    impl Drop for MuxerTestContext {
+    impl Drop for MuxerTestContext<'_> {
         fn drop(&mut self) {
             std::fs::remove_file(self.muxer.host_sock_path.as_str()).unwrap();
         }
@@ -828,9 +1088,8 @@ mod tests {
             .to_owned()
     }
 
-    impl MuxerTestContext {
-        fn new(name: &str) -> Self {
-            let vsock_test_ctx = VsockTestContext::new();
+    impl<'a> MuxerTestContext<'a> {
+        fn new(vsock_test_ctx: &'a VsockTestContext, name: &str) -> Self {
             let mut handler_ctx = vsock_test_ctx.create_event_handler_context();
             let rx_pkt = VsockPacket::from_rx_virtq_head(
                 handler_ctx.device.queues[RXQ_INDEX]
@@ -847,14 +1106,18 @@ mod tests {
 
             let muxer = VsockMuxer::new(PEER_CID, get_file(name)).unwrap();
             Self {
-                _vsock_test_ctx: vsock_test_ctx,
                 rx_pkt,
                 tx_pkt,
                 muxer,
             }
         }
 
-        fn init_tx_pkt(&mut self, local_port: u32, peer_port: u32, op: u16) -> &mut VsockPacket {
+        fn init_tx_pkt(
+            &mut self,
+            local_port: u32,
+            peer_port: u32,
+            op: u16,
+        ) -> &mut VsockPacket<'a> {
             self.tx_pkt
                 .set_type(uapi::VSOCK_TYPE_STREAM)
                 .set_src_cid(PEER_CID)
@@ -870,7 +1133,7 @@ mod tests {
             local_port: u32,
             peer_port: u32,
             mut data: &[u8],
-        ) -> &mut VsockPacket {
+        ) -> &mut VsockPacket<'a> {
             assert!(data.len() <= self.tx_pkt.buf_size());
             self.init_tx_pkt(local_port, peer_port, uapi::VSOCK_OP_RW)
                 .set_len(u32::try_from(data.len()).unwrap());
@@ -882,6 +1145,16 @@ mod tests {
             &mut self.tx_pkt
         }
 
+        fn init_dgram_tx_pkt(
+            &mut self,
+            local_port: u32,
+            peer_port: u32,
+            data: &[u8],
+        ) -> &mut VsockPacket<'a> {
+            self.init_data_tx_pkt(local_port, peer_port, data)
+                .set_type(uapi::VSOCK_TYPE_DGRAM)
+        }
+
         fn send(&mut self) {
             self.muxer.send_pkt(&self.tx_pkt).unwrap();
         }
@@ -911,6 +1184,14 @@ mod tests {
             LocalListener::new(format!("{}_{}", self.muxer.host_sock_path, port))
         }
 
+        fn create_local_dgram(&self, port: u32) -> LocalDgram {
+            LocalDgram::new(format!("{}_dgram_{}", self.muxer.host_sock_path, port))
+        }
+
+        fn guest_dgram_path(&self, peer_port: u32) -> String {
+            format!("{}_dgram_guest_{}", self.muxer.host_sock_path, peer_port)
+        }
+
         fn local_connect(&mut self, peer_port: u32) -> (UnixStream, u32) {
             let (init_local_lsn_count, init_conn_lsn_count) = self.count_epoll_listeners();
 
@@ -992,16 +1273,36 @@ mod tests {
         }
     }
 
+    #[derive(Debug)]
+    struct LocalDgram {
+        path: String,
+        sock: UnixDatagram,
+    }
+    impl LocalDgram {
+        fn new(path: String) -> Self {
+            let sock = UnixDatagram::bind(&path).unwrap();
+            sock.set_nonblocking(true).unwrap();
+            Self { path, sock }
+        }
+    }
+    impl Drop for LocalDgram {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.path).unwrap();
+        }
+    }
+
     #[test]
     fn test_muxer_epoll_listener() {
-        let ctx = MuxerTestContext::new("muxer_epoll_listener");
+        let vsock_test_ctx = VsockTestContext::new();
+        let ctx = MuxerTestContext::new(&vsock_test_ctx, "muxer_epoll_listener");
         assert_eq!(ctx.muxer.as_raw_fd(), ctx.muxer.epoll.as_raw_fd());
         assert_eq!(ctx.muxer.get_polled_evset(), EventSet::IN);
     }
 
     #[test]
     fn test_muxer_epoll_listener_regression() {
-        let mut ctx = MuxerTestContext::new("muxer_epoll_listener");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "muxer_epoll_listener");
         ctx.local_connect(1025);
 
         let (_, conn) = ctx.muxer.conn_map.iter().next().unwrap();
@@ -1021,15 +1322,16 @@ mod tests {
     fn test_bad_peer_pkt() {
         const LOCAL_PORT: u32 = 1026;
         const PEER_PORT: u32 = 1025;
-        const SOCK_DGRAM: u16 = 2;
+        const SOCK_SEQPACKET: u16 = 2;
 
-        let mut ctx = MuxerTestContext::new("bad_peer_pkt");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "bad_peer_pkt");
         ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
-            .set_type(SOCK_DGRAM);
+            .set_type(SOCK_SEQPACKET);
         ctx.send();
 
-        // The guest sent a SOCK_DGRAM packet. Per the vsock spec, we need to reply with an RST
-        // packet, since vsock only supports stream sockets.
+        // The guest sent a SOCK_SEQPACKET packet. Per the vsock spec, we need to reply with an RST
+        // packet, since the muxer only supports stream and datagram sockets.
         assert!(ctx.muxer.has_pending_rx());
         ctx.recv();
         assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RST);
@@ -1070,7 +1372,8 @@ mod tests {
         const LOCAL_PORT: u32 = 1026;
         const PEER_PORT: u32 = 1025;
 
-        let mut ctx = MuxerTestContext::new("peer_connection");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "peer_connection");
 
         // Test peer connection refused.
         ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
@@ -1135,7 +1438,8 @@ mod tests {
     #[test]
     fn test_local_connection() {
         // Test guest -> host data flow.
-        let mut ctx = MuxerTestContext::new("local_connection");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "local_connection");
         let peer_port = 1025;
         let (mut stream, local_port) = ctx.local_connect(peer_port);
 
@@ -1148,7 +1452,8 @@ mod tests {
         assert_eq!(buf.as_slice(), &data);
 
         // Test host -> guest data flow.
-        let mut ctx = MuxerTestContext::new("local_connection");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "local_connection");
         let peer_port = 1025;
         let (mut stream, local_port) = ctx.local_connect(peer_port);
 
@@ -1169,7 +1474,8 @@ mod tests {
     #[test]
     fn test_local_close() {
         let peer_port = 1025;
-        let mut ctx = MuxerTestContext::new("local_close");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "local_close");
         let local_port;
         {
             let (_stream, local_port_) = ctx.local_connect(peer_port);
@@ -1203,7 +1509,8 @@ mod tests {
     fn test_peer_close() {
         let peer_port = 1025;
         let local_port = 1026;
-        let mut ctx = MuxerTestContext::new("peer_close");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "peer_close");
 
         let mut sock = ctx.create_local_listener(local_port);
         ctx.init_tx_pkt(local_port, peer_port, uapi::VSOCK_OP_REQUEST);
@@ -1246,7 +1553,8 @@ mod tests {
 
     #[test]
     fn test_muxer_rxq() {
-        let mut ctx = MuxerTestContext::new("muxer_rxq");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "muxer_rxq");
         let local_port = 1026;
         let peer_port_first = 1025;
         let mut listener = ctx.create_local_listener(local_port);
@@ -1310,7 +1618,8 @@ mod tests {
 
     #[test]
     fn test_muxer_killq() {
-        let mut ctx = MuxerTestContext::new("muxer_killq");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "muxer_killq");
         let local_port = 1026;
         let peer_port_first = 1025;
         let peer_port_last = peer_port_first + defs::MUXER_KILLQ_SIZE;
@@ -1406,7 +1715,8 @@ mod tests {
         // Address one of the issues found while fixing the following issue:
         // https://github.com/firecracker-microvm/firecracker/issues/1751
         // This test checks that the handshake message is not accounted for
-        let mut ctx = MuxerTestContext::new("regression_handshake");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "regression_handshake");
         let peer_port = 1025;
 
         // Create a local connection.
@@ -1429,7 +1739,8 @@ mod tests {
         // https://github.com/firecracker-microvm/firecracker/issues/1751
         // This test checks that a connection is not popped out of the muxer
         // rxq when multiple flags are set
-        let mut ctx = MuxerTestContext::new("regression_rxq_pop");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "regression_rxq_pop");
         let peer_port = 1025;
         let (mut stream, local_port) = ctx.local_connect(peer_port);
 
@@ -1473,7 +1784,8 @@ mod tests {
         let conns_removed = METRICS.conns_removed.count();
 
         // Create a basic connection.
-        let mut ctx = MuxerTestContext::new("vsock_basic_metrics");
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "vsock_basic_metrics");
         let peer_port = 1025;
         let (mut stream, local_port) = ctx.local_connect(peer_port);
 
@@ -1528,4 +1840,325 @@ mod tests {
         // Check that the connection was removed.
         assert_eq!(METRICS.conns_removed.count(), conns_removed + 1);
     }
+    #[test]
+    fn test_dgram_exchange() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "dgram_exchange");
+        let local = ctx.create_local_dgram(LOCAL_PORT);
+        let guest_path = ctx.guest_dgram_path(PEER_PORT);
+
+        // Test guest -> host datagrams. They don't open any connection, nor get any reply.
+        let data = [1u8, 2, 3, 4];
+        ctx.init_dgram_tx_pkt(LOCAL_PORT, PEER_PORT, &data);
+        ctx.send();
+        assert!(ctx.muxer.conn_map.is_empty());
+        assert!(!ctx.muxer.has_pending_rx());
+
+        // The datagram comes from the socket of the guest port, so that the host can reply.
+        let mut buf = [0u8; 16];
+        let (len, addr) = local.sock.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &data);
+        assert_eq!(addr.as_pathname().unwrap(), Path::new(&guest_path));
+
+        // Test host -> guest datagrams.
+        let data = [5u8, 6, 7, 8, 9];
+        local.sock.send_to(&data, &guest_path).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.type_(), uapi::VSOCK_TYPE_DGRAM);
+        assert_eq!(ctx.rx_pkt.src_cid(), uapi::VSOCK_HOST_CID);
+        assert_eq!(ctx.rx_pkt.dst_cid(), PEER_CID);
+        assert_eq!(ctx.rx_pkt.src_port(), LOCAL_PORT);
+        assert_eq!(ctx.rx_pkt.dst_port(), PEER_PORT);
+        assert_eq!(ctx.rx_pkt.len(), 5);
+        let buf = test_utils::read_packet_data(&ctx.tx_pkt, data.len());
+        assert_eq!(&buf, &data);
+        assert!(!ctx.muxer.has_pending_rx());
+
+        // Host datagrams can't be routed if their sender isn't bound at a port path.
+        let rx_drops = METRICS.dgram_rx_drops.count();
+        let unbound = UnixDatagram::unbound().unwrap();
+        unbound.send_to(&data, &guest_path).unwrap();
+        ctx.notify_muxer();
+        assert!(!ctx.muxer.has_pending_rx());
+        assert!(METRICS.dgram_rx_drops.count() > rx_drops);
+
+        // Guest datagrams are dropped when nothing is bound at the path of their destination
+        // port, and when they hold anything else than data.
+        let tx_drops = METRICS.dgram_tx_drops.count();
+        ctx.init_dgram_tx_pkt(LOCAL_PORT + 1, PEER_PORT, &data);
+        ctx.send();
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_DGRAM);
+        ctx.send();
+        assert!(!ctx.muxer.has_pending_rx());
+        assert_eq!(METRICS.dgram_tx_drops.count(), tx_drops + 2);
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            local.sock.recv_from(&mut buf).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn test_dgram_rxq_overflow() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORTS: [u32; 2] = [1025, 1027];
+        const EXTRA: usize = 8;
+
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "dgram_rxq_overflow");
+        let local = ctx.create_local_dgram(LOCAL_PORT);
+
+        // The guest ports send a datagram first, so that the host can reply to them.
+        let mut buf = [0u8; 4];
+        for peer_port in PEER_PORTS {
+            ctx.init_dgram_tx_pkt(LOCAL_PORT, peer_port, &[0]);
+            ctx.send();
+            local.sock.recv(&mut buf).unwrap();
+        }
+
+        // Overflow the queue of the first guest port, which the guest doesn't read from. The
+        // muxer is notified after each datagram, since the host socket only holds a few.
+        let rx_drops = METRICS.dgram_rx_drops.count();
+        let first_path = ctx.guest_dgram_path(PEER_PORTS[0]);
+        for i in 0..defs::MUXER_DGRAM_RXQ_SIZE + EXTRA {
+            let data = u32::try_from(i).unwrap().to_le_bytes();
+            local.sock.send_to(&data, &first_path).unwrap();
+            ctx.notify_muxer();
+        }
+        assert!(METRICS.dgram_rx_drops.count() >= rx_drops + EXTRA as u64);
+
+        // A datagram for the second guest port still gets queued.
+        let second_path = ctx.guest_dgram_path(PEER_PORTS[1]);
+        local
+            .sock
+            .send_to(&u32::MAX.to_le_bytes(), &second_path)
+            .unwrap();
+        ctx.notify_muxer();
+
+        let mut first_port_dgrams = Vec::new();
+        let mut second_port_pos = None;
+        for pos in 0..=defs::MUXER_DGRAM_RXQ_SIZE {
+            ctx.recv();
+            assert_eq!(ctx.rx_pkt.type_(), uapi::VSOCK_TYPE_DGRAM);
+            let data = test_utils::read_packet_data(&ctx.tx_pkt, 4);
+            let value = u32::from_le_bytes(data.try_into().unwrap());
+            if ctx.rx_pkt.dst_port() == PEER_PORTS[1] {
+                assert_eq!(value, u32::MAX);
+                second_port_pos = Some(pos);
+            } else {
+                assert_eq!(ctx.rx_pkt.dst_port(), PEER_PORTS[0]);
+                first_port_dgrams.push(value);
+            }
+        }
+        assert!(!ctx.muxer.has_pending_rx());
+
+        // The first port got the datagrams that fit in its queue, in order, and lost the others.
+        let expected: Vec<u32> = (0..u32::try_from(defs::MUXER_DGRAM_RXQ_SIZE).unwrap()).collect();
+        assert_eq!(first_port_dgrams, expected);
+        // The ports take turns, so the datagram of the second port doesn't wait for all the ones
+        // of the first port, even though it was received last.
+        assert!(second_port_pos.unwrap() < defs::MUXER_DGRAM_RXQ_SIZE);
+    }
+
+    #[test]
+    fn test_connection_table() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+        const LOCAL_INIT_PEER_PORT: u32 = 1027;
+
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "connection_table");
+        assert!(ctx.muxer.connections().is_empty());
+
+        // A guest-initiated connection, with some data going each way.
+        let mut listener = ctx.create_local_listener(LOCAL_PORT);
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        let mut stream = listener.accept();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RESPONSE);
+
+        let data = [1, 2, 3, 4];
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &data);
+        ctx.send();
+        let mut buf = vec![0; data.len()];
+        stream.read_exact(buf.as_mut_slice()).unwrap();
+
+        stream.write_all(&data[..2]).unwrap();
+        ctx.notify_muxer();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RW);
+
+        // A host-initiated connection, which didn't exchange any data yet.
+        let (_local_stream, local_port) = ctx.local_connect(LOCAL_INIT_PEER_PORT);
+
+        let conns = serde_json::to_value(ctx.muxer.connections()).unwrap();
+        assert_eq!(
+            conns,
+            serde_json::json!([
+                {
+                    "local_port": LOCAL_PORT,
+                    "peer_port": PEER_PORT,
+                    "state": "established",
+                    "rx_bytes": 2,
+                    "tx_bytes": 4,
+                    "peer_credit": PEER_BUF_ALLOC - 2,
+                    "tx_buf_bytes": 0,
+                    "credit_stalls": 0
+                },
+                {
+                    "local_port": local_port,
+                    "peer_port": LOCAL_INIT_PEER_PORT,
+                    "state": "established",
+                    "rx_bytes": 0,
+                    "tx_bytes": 0,
+                    "peer_credit": PEER_BUF_ALLOC,
+                    "tx_buf_bytes": 0,
+                    "credit_stalls": 0
+                }
+            ])
+        );
+
+        // The guest resets the first connection, which drops out of the table.
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_RST);
+        ctx.send();
+        let conns = ctx.muxer.connections();
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0].local_port, local_port);
+
+        // Packets for connections which aren't in the table get an RST back.
+        let rst_sent = METRICS.rst_sent.count();
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &data);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RST);
+        assert!(METRICS.rst_sent.count() > rst_sent);
+    }
+
+    #[test]
+    fn test_connection_credit_stalls() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "connection_credit_stalls");
+        let mut listener = ctx.create_local_listener(LOCAL_PORT);
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        let mut stream = listener.accept();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RESPONSE);
+
+        // Host data becomes available, but the guest runs out of buffer space before it gets to
+        // read it, so the data has to wait for a credit update.
+        stream.write_all(&[1, 2, 3, 4]).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_CREDIT_UPDATE)
+            .set_buf_alloc(0);
+        ctx.send();
+
+        let credit_stalls = METRICS.credit_stalls.count();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_CREDIT_REQUEST);
+        assert!(METRICS.credit_stalls.count() > credit_stalls);
+
+        let conns = ctx.muxer.connections();
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0].peer_credit, 0);
+        assert_eq!(conns[0].credit_stalls, 1);
+        assert_eq!(conns[0].rx_bytes, 0);
+    }
+
+    #[test]
+    fn test_keep_connections() {
+        const LOCAL_PORT: u32 = 1100;
+        const PEER_PORT: u32 = 1025;
+
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "keep_connections");
+        ctx.muxer.set_keep_connections(true);
+
+        // A host-initiated connection, in the middle of a transfer both ways.
+        let (mut stream, local_port) = ctx.local_connect(PEER_PORT);
+        stream.write_all(b"hello").unwrap();
+        ctx.notify_muxer();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(test_utils::read_packet_data(&ctx.tx_pkt, 5), b"hello");
+        ctx.init_data_tx_pkt(local_port, PEER_PORT, b"ping");
+        ctx.send();
+        // The muxer hasn't read this yet when the snapshot is taken.
+        stream.write_all(b" world").unwrap();
+
+        // A guest-initiated connection, whose host-side listener goes away before the restore.
+        let mut listener = ctx.create_local_listener(LOCAL_PORT);
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        let _peer_stream = listener.accept();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RESPONSE);
+        drop(listener);
+
+        ctx.muxer.drain_connections();
+        let state = ctx.muxer.save();
+        let mut host_listener = ctx.create_local_listener(local_port);
+        std::fs::remove_file(&ctx.muxer.host_sock_path).unwrap();
+        ctx.muxer = VsockMuxer::restore(VsockUdsConstructorArgs { cid: PEER_CID }, &state).unwrap();
+        assert!(ctx.muxer.keep_connections());
+
+        // The host got everything the guest sent before the snapshot, on its old stream.
+        let mut buf = Vec::new();
+        stream.set_nonblocking(false).unwrap();
+        stream.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"ping");
+
+        // The connection which couldn't be re-established is reset.
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.rx_pkt.src_port(), LOCAL_PORT);
+        assert_eq!(ctx.rx_pkt.dst_port(), PEER_PORT);
+
+        // The other one carries on over a new stream, without losing nor repeating any data.
+        let mut stream = host_listener.accept();
+        assert!(ctx.muxer.local_port_set.contains(&local_port));
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.src_port(), local_port);
+        assert_eq!(ctx.rx_pkt.dst_port(), PEER_PORT);
+        assert_eq!(test_utils::read_packet_data(&ctx.tx_pkt, 6), b" world");
+        assert!(!ctx.muxer.has_pending_rx());
+
+        stream.write_all(b"!").unwrap();
+        ctx.notify_muxer();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(test_utils::read_packet_data(&ctx.tx_pkt, 1), b"!");
+
+        ctx.init_data_tx_pkt(local_port, PEER_PORT, b"pong");
+        ctx.send();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[test]
+    fn test_connections_not_kept() {
+        const PEER_PORT: u32 = 1025;
+
+        // Connections aren't saved unless they are kept.
+        let vsock_test_ctx = VsockTestContext::new();
+        let mut ctx = MuxerTestContext::new(&vsock_test_ctx, "connections_not_kept");
+        let (_stream, _) = ctx.local_connect(PEER_PORT);
+        let VsockBackendState::Uds(state) = ctx.muxer.save();
+        assert!(!state.keep_connections);
+        assert!(state.connections.is_empty());
+    }
 }
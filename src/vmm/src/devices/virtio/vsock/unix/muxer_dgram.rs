@@ -0,0 +1,142 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+
+/// `MuxerDgramPort` is the host-side end of a guest datagram (VSOCK_TYPE_DGRAM) port.
+///
+/// Datagrams don't need connections, nor flow control: each one is forwarded on its own, and
+/// dropped when it cannot be delivered. The muxer binds a Unix datagram socket for every guest
+/// port that sent datagrams, at `<uds_path>_dgram_guest_<guest port>`:
+/// - the guest datagrams sent to host port `<port>` are sent from that socket to the host
+///   socket bound at `<uds_path>_dgram_<port>`; and
+/// - the host datagrams received by that socket are forwarded to the guest port, from the host
+///   port of the socket which sent them, which must be bound at `<uds_path>_dgram_<port>`.
+///
+/// Host datagrams are stored in a bounded queue until the guest provides RX buffers for them.
+/// Datagrams arriving while the queue is full are dropped, so that a guest which doesn't read
+/// its datagrams cannot stall the muxer.
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+
+use log::warn;
+
+use super::super::defs::MAX_PKT_BUF_SIZE;
+use super::{defs, VsockUnixBackendError};
+use crate::devices::virtio::vsock::metrics::METRICS;
+use crate::logger::IncMetric;
+
+/// A datagram received from the host, waiting to be delivered to the guest.
+#[derive(Debug)]
+pub struct HostDgram {
+    /// The host port which sent the datagram.
+    pub host_port: u32,
+    /// The datagram payload.
+    pub data: Vec<u8>,
+}
+
+/// The host-side socket of a guest datagram port.
+#[derive(Debug)]
+pub struct MuxerDgramPort {
+    /// The socket, bound at `path`.
+    sock: UnixDatagram,
+    /// The file system path of the socket, removed when the port is dropped.
+    path: String,
+    /// The host datagrams waiting for guest RX buffers.
+    rxq: VecDeque<HostDgram>,
+}
+
+impl MuxerDgramPort {
+    const RXQ_SIZE: usize = defs::MUXER_DGRAM_RXQ_SIZE;
+
+    /// Binds a non-blocking datagram socket at `path`, replacing any stale socket left there.
+    pub fn new(path: String) -> Result<Self, VsockUnixBackendError> {
+        // The path is owned by the muxer, so anything found there is left over by a previous
+        // muxer which didn't get to clean up after itself.
+        let _ = std::fs::remove_file(&path);
+        let sock = UnixDatagram::bind(&path)
+            .and_then(|sock| sock.set_nonblocking(true).map(|_| sock))
+            .map_err(VsockUnixBackendError::UnixBind)?;
+
+        Ok(Self {
+            sock,
+            path,
+            rxq: VecDeque::with_capacity(Self::RXQ_SIZE),
+        })
+    }
+
+    /// Sends `data` as a single datagram to the host socket bound at `dst_path`.
+    pub fn send_to(&self, data: &[u8], dst_path: &str) -> Result<(), VsockUnixBackendError> {
+        self.sock
+            .send_to(data, dst_path)
+            .map(|_| ())
+            .map_err(VsockUnixBackendError::UnixSend)
+    }
+
+    /// Reads all the datagrams pending on the socket into the RX queue.
+    ///
+    /// Datagrams are dropped when the queue is full, when they are too large for a vsock
+    /// packet, or when their sender isn't bound at `<host_dgram_prefix><port>`.
+    pub fn fill_rxq(&mut self, host_dgram_prefix: &str) {
+        // One byte more than the largest packet, to tell the datagrams which don't fit in one.
+        let mut buf = vec![0u8; MAX_PKT_BUF_SIZE as usize + 1];
+
+        loop {
+            let (len, addr) = match self.sock.recv_from(&mut buf) {
+                Ok(res) => res,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!(
+                        "vsock: error reading datagram from {}: {:?}",
+                        self.path, err
+                    );
+                    METRICS.rx_read_fails.inc();
+                    break;
+                }
+            };
+
+            let host_port = addr
+                .as_pathname()
+                .and_then(|path| path.to_str())
+                .and_then(|path| path.strip_prefix(host_dgram_prefix))
+                .and_then(|port| port.parse::<u32>().ok());
+            match host_port {
+                Some(host_port) if len <= MAX_PKT_BUF_SIZE as usize && !self.is_full() => {
+                    self.rxq.push_back(HostDgram {
+                        host_port,
+                        data: buf[..len].to_vec(),
+                    });
+                }
+                _ => METRICS.dgram_rx_drops.inc(),
+            }
+        }
+    }
+
+    /// Pops the oldest datagram of the RX queue.
+    pub fn pop_rx(&mut self) -> Option<HostDgram> {
+        self.rxq.pop_front()
+    }
+
+    /// Checks whether there are datagrams waiting for guest RX buffers.
+    pub fn has_pending_rx(&self) -> bool {
+        !self.rxq.is_empty()
+    }
+
+    /// Checks whether the RX queue is full, so that new datagrams are dropped.
+    pub fn is_full(&self) -> bool {
+        self.rxq.len() >= Self::RXQ_SIZE
+    }
+}
+
+impl AsRawFd for MuxerDgramPort {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+impl Drop for MuxerDgramPort {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -21,7 +21,7 @@ use crate::dumbo::pdu::bytes::NetworkBytes;
 use crate::dumbo::pdu::tcp::TcpSegment;
 use crate::dumbo::pdu::Incomplete;
 use crate::dumbo::tcp::connection::{Connection, PassiveOpenError, RecvStatusFlags};
-use crate::dumbo::tcp::{seq_after, NextSegmentStatus, MAX_WINDOW_SIZE};
+use crate::dumbo::tcp::{seq_after, NextSegmentStatus, ParkedResponse, Reply, MAX_WINDOW_SIZE};
 use crate::logger::{IncMetric, METRICS};
 
 // TODO: These are currently expressed in cycles. Normally, they would be the equivalent of a
@@ -50,6 +50,9 @@ pub struct Endpoint {
     receive_buf_left: usize,
     // This is filled with the HTTP response bytes after we parse a request and generate the reply.
     response_buf: Vec<u8>,
+    // The reply to the current request, when it is not ready yet. No other request is parsed
+    // until it completes and fills `response_buf`.
+    parked_response: Option<Box<dyn ParkedResponse>>,
     // Initial response sequence, used to track if the entire `response_buf` was sent.
     initial_response_seq: Wrapping<u32>,
     // Represents the sequence number associated with the first byte from response_buf.
@@ -79,6 +82,8 @@ pub struct Endpoint {
 // internal logic is concerned. It's going to be used by the connection handler when trying to
 // find a new slot for incoming connections if none are free (when replacing an existing connection
 // is the only option).
+// - When the request callback parks its reply, poll_parked_response() has to be called until it
+// returns true, after which write_next_segment() sends the response.
 
 impl Endpoint {
     /// Creates a new Endpoint from a [`crate::tcp::connection::Connection`]
@@ -115,6 +120,7 @@ impl Endpoint {
             receive_buf: [0u8; RCV_BUF_MAX_SIZE as usize],
             receive_buf_left: 0,
             response_buf: Vec::new(),
+            parked_response: None,
             // TODO: Using first_not_sent() makes sense here because a connection is currently
             // created via passive open only, so this points to the sequence number right after
             // the SYNACK. It might stop working like that if/when the implementation changes.
@@ -139,11 +145,12 @@ impl Endpoint {
         )
     }
 
-    pub fn receive_segment<T: NetworkBytes + Debug, F: FnOnce(Request) -> Response>(
-        &mut self,
-        s: &TcpSegment<T>,
-        callback: F,
-    ) {
+    pub fn receive_segment<T, F, R>(&mut self, s: &TcpSegment<T>, callback: F)
+    where
+        T: NetworkBytes + Debug,
+        F: FnOnce(Request) -> R,
+        R: Into<Reply>,
+    {
         if self.stop_receiving {
             return;
         }
@@ -199,7 +206,7 @@ impl Endpoint {
             self.response_buf.clear();
         }
 
-        if self.response_buf.is_empty() {
+        if self.response_buf.is_empty() && self.parked_response.is_none() {
             // There's no pending response currently, so we're back to waiting for a request to be
             // available in self.receive_buf.
 
@@ -221,14 +228,20 @@ impl Endpoint {
                         };
 
                         // We found a potential request, let's parse it.
-                        let response = parse_request_bytes(&b[..end], callback);
-
-                        // The unwrap is safe because a Vec will allocate more space until all the
-                        // writes succeed.
-                        response.write_all(&mut self.response_buf).unwrap();
-
-                        // Sanity check because the current logic operates under this assumption.
-                        assert!(self.response_buf.len() < u32::MAX as usize);
+                        match parse_request_bytes(&b[..end], callback) {
+                            Reply::Ready(response) => {
+                                // The unwrap is safe because a Vec will allocate more space until
+                                // all the writes succeed.
+                                response.write_all(&mut self.response_buf).unwrap();
+
+                                // Sanity check because the current logic operates under this
+                                // assumption.
+                                assert!(self.response_buf.len() < u32::MAX as usize);
+                            }
+                            Reply::Parked(parked_response) => {
+                                self.parked_response = Some(parked_response)
+                            }
+                        }
 
                         // We have to remove the bytes up to end from receive_buf, by shifting the
                         // others to the beginning of the buffer, and updating receive_buf_left.
@@ -255,11 +268,33 @@ impl Endpoint {
 
         // We close the connection after receiving a FIN, and making sure there are no more
         // responses to send.
-        if self.connection.fin_received() && self.response_buf.is_empty() {
+        if self.connection.fin_received()
+            && self.response_buf.is_empty()
+            && self.parked_response.is_none()
+        {
             self.connection.close();
         }
     }
 
+    /// Completes the parked response to the current request once it is ready, returning `true`
+    /// if it was just completed and has to be sent.
+    pub fn poll_parked_response(&mut self) -> bool {
+        let Some(response_bytes) = self.parked_response.as_mut().and_then(|p| p.poll()) else {
+            return false;
+        };
+        self.parked_response = None;
+        // The response buffer is empty while a response is parked.
+        self.response_buf = response_bytes;
+        // Sanity check because the current logic operates under this assumption.
+        assert!(self.response_buf.len() < u32::MAX as usize);
+        true
+    }
+
+    #[inline]
+    pub fn has_parked_response(&self) -> bool {
+        self.parked_response.is_some()
+    }
+
     pub fn write_next_segment<'a>(
         &mut self,
         buf: &'a mut [u8],
@@ -329,14 +364,14 @@ fn build_response(status_code: StatusCode, body: Body) -> Response {
     response
 }
 
-/// Parses the request bytes and builds a `micro_http::Response` by the given callback function.
-fn parse_request_bytes<F: FnOnce(Request) -> Response>(
+/// Parses the request bytes and builds the reply to the request by the given callback function.
+fn parse_request_bytes<F: FnOnce(Request) -> R, R: Into<Reply>>(
     byte_stream: &[u8],
     callback: F,
-) -> Response {
+) -> Reply {
     let request = Request::try_from(byte_stream, None);
-    match request {
-        Ok(request) => callback(request),
+    let response = match request {
+        Ok(request) => return callback(request).into(),
         Err(err) => match err {
             RequestError::BodyWithoutPendingRequest
             | RequestError::HeadersWithoutPendingRequest
@@ -362,12 +397,15 @@ fn parse_request_bytes<F: FnOnce(Request) -> Response>(
                 build_response(StatusCode::PayloadTooLarge, Body::new(err.to_string()))
             }
         },
-    }
+    };
+    Reply::Ready(response)
 }
 
 #[cfg(test)]
 mod tests {
     use std::str::from_utf8;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
 
     use super::*;
     use crate::dumbo::pdu::tcp::Flags as TcpFlags;
@@ -380,6 +418,31 @@ mod tests {
         }
     }
 
+    fn parse_response(byte_stream: &[u8]) -> Response {
+        match parse_request_bytes(byte_stream, mock_callback) {
+            Reply::Ready(response) => response,
+            Reply::Parked(_) => panic!("unexpected parked response"),
+        }
+    }
+
+    // Parked response which completes once `ready` is set.
+    #[derive(Debug)]
+    struct MockParkedResponse {
+        ready: Arc<AtomicBool>,
+    }
+
+    impl ParkedResponse for MockParkedResponse {
+        fn poll(&mut self) -> Option<Vec<u8>> {
+            self.ready.load(Ordering::SeqCst).then(|| {
+                let mut buf = Vec::new();
+                Response::new(Version::Http11, StatusCode::NoContent)
+                    .write_all(&mut buf)
+                    .unwrap();
+                buf
+            })
+        }
+    }
+
     #[test]
     #[allow(clippy::cognitive_complexity)]
     fn test_endpoint() {
@@ -583,20 +646,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parked_response() {
+        let mut buf1 = [0u8; 500];
+        let mut buf2 = [0u8; 500];
+        let mut write_buf = [0u8; RCV_BUF_MAX_SIZE as usize + 100];
+        let mut t = ConnectionTester::new();
+
+        let syn = t.write_syn(buf1.as_mut());
+        let remote_isn = syn.sequence_number();
+        let mut endpoint = Endpoint::new_with_defaults(&syn).unwrap();
+        let endpoint_isn = endpoint
+            .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+            .unwrap()
+            .inner()
+            .sequence_number();
+        let mut ctrl = t.write_ctrl(buf2.as_mut());
+        ctrl.set_flags_after_ns(TcpFlags::ACK);
+        ctrl.set_ack_number(endpoint_isn.wrapping_add(1));
+        endpoint.receive_segment(&ctrl, mock_callback);
+        assert!(endpoint.connection.is_established());
+
+        // Two pipelined requests, the first of which gets a parked response.
+        let requests = b"GET /watch HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+        let ready = Arc::new(AtomicBool::new(false));
+        {
+            let mut data = t.write_data(write_buf.as_mut(), requests.as_ref());
+            data.set_flags_after_ns(TcpFlags::ACK);
+            data.set_sequence_number(remote_isn.wrapping_add(1));
+            data.set_ack_number(endpoint_isn.wrapping_add(1));
+            let ready = ready.clone();
+            endpoint.receive_segment(&data, |_| {
+                Reply::Parked(Box::new(MockParkedResponse { ready }))
+            });
+        }
+        assert!(endpoint.has_parked_response());
+        assert!(!endpoint.poll_parked_response());
+
+        // The requests get ACKed, but no response is sent while it is parked, and the second
+        // request is left in the receive buffer.
+        {
+            let s = endpoint
+                .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+                .unwrap();
+            assert_eq!(s.inner().payload_len(), 0);
+        }
+        assert_eq!(endpoint.next_segment_status(), NextSegmentStatus::Nothing);
+        assert_eq!(endpoint.receive_buf_left, 18);
+
+        // The response is sent once it completes.
+        ready.store(true, Ordering::SeqCst);
+        assert!(endpoint.poll_parked_response());
+        assert!(!endpoint.has_parked_response());
+        assert!(!endpoint.poll_parked_response());
+        assert_eq!(endpoint.next_segment_status(), NextSegmentStatus::Available);
+        let s = endpoint
+            .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+            .unwrap();
+        let response = from_utf8(s.inner().payload()).unwrap();
+        assert!(response.starts_with("HTTP/1.1 204"));
+    }
+
     #[test]
     fn test_parse_request_bytes_error() {
         // Test unsupported HTTP version.
         let request_bytes = b"GET http://169.254.169.255/ HTTP/2.0\r\n\r\n";
         let mut expected_response = Response::new(Version::Http11, StatusCode::NotImplemented);
         expected_response.set_body(Body::new("Unsupported HTTP version.".to_string()));
-        let actual_response = parse_request_bytes(request_bytes, mock_callback);
+        let actual_response = parse_response(request_bytes);
         assert_eq!(actual_response, expected_response);
 
         // Test invalid URI (empty URI).
         let request_bytes = b"GET   HTTP/1.0\r\n\r\n";
         let mut expected_response = Response::new(Version::Http11, StatusCode::BadRequest);
         expected_response.set_body(Body::new("Empty URI not allowed.".to_string()));
-        let actual_response = parse_request_bytes(request_bytes, mock_callback);
+        let actual_response = parse_response(request_bytes);
         assert_eq!(actual_response, expected_response);
 
         // Test invalid HTTP methods.
@@ -605,7 +729,7 @@ mod tests {
             let request_bytes = format!("{} http://169.254.169.255/ HTTP/1.0\r\n\r\n", method);
             let mut expected_response = Response::new(Version::Http11, StatusCode::NotImplemented);
             expected_response.set_body(Body::new("Unsupported HTTP method.".to_string()));
-            let actual_response = parse_request_bytes(request_bytes.as_bytes(), mock_callback);
+            let actual_response = parse_response(request_bytes.as_bytes());
             assert_eq!(actual_response, expected_response);
         }
 
@@ -614,7 +738,7 @@ mod tests {
         for method in valid_methods.iter() {
             let request_bytes = format!("{} http://169.254.169.255/ HTTP/1.0\r\n\r\n", method);
             let expected_response = Response::new(Version::Http11, StatusCode::OK);
-            let actual_response = parse_request_bytes(request_bytes.as_bytes(), mock_callback);
+            let actual_response = parse_response(request_bytes.as_bytes());
             assert_eq!(actual_response, expected_response);
         }
 
@@ -622,7 +746,7 @@ mod tests {
         let request_bytes = b"GET / HTTP/1.1\r\n";
         let mut expected_response = Response::new(Version::Http11, StatusCode::BadRequest);
         expected_response.set_body(Body::new("Invalid request.".to_string()));
-        let actual_response = parse_request_bytes(request_bytes, mock_callback);
+        let actual_response = parse_response(request_bytes);
         assert_eq!(actual_response, expected_response);
 
         // Test invalid HTTP headers.
@@ -630,9 +754,7 @@ mod tests {
                                  Expect: 100-continue\r\n\
                                  Transfer-Encoding: identity; q=0\r\n\
                                  Content-Length: 26\r\n\r\nthis is not\n\r\na json \nbody";
-        assert!(parse_request_bytes(request_bytes, mock_callback)
-            .body()
-            .is_none());
+        assert!(parse_response(request_bytes).body().is_none());
 
         let request_bytes = b"PATCH http://localhost/home HTTP/1.1\r\n\
                                  Expect: 100-continue\r\n\
@@ -642,7 +764,7 @@ mod tests {
         expected_response.set_body(Body::new(
             "Invalid value. Key:Content-Length; Value: alpha".to_string(),
         ));
-        let actual_response = parse_request_bytes(request_bytes, mock_callback);
+        let actual_response = parse_response(request_bytes);
         assert_eq!(actual_response, expected_response);
 
         let request_bytes = b"PATCH http://localhost/home HTTP/1.1\r\n\
@@ -654,7 +776,7 @@ mod tests {
         expected_response.set_body(Body::new(
             "Invalid value. Key:Accept-Encoding; Value: *;q=0".to_string(),
         ));
-        let actual_response = parse_request_bytes(request_bytes, mock_callback);
+        let actual_response = parse_response(request_bytes);
         assert_eq!(actual_response, expected_response);
     }
 }
@@ -10,13 +10,13 @@ use std::fmt::Debug;
 use std::net::Ipv4Addr;
 use std::num::NonZeroUsize;
 
-use micro_http::{Request, Response};
+use micro_http::Request;
 
 use crate::dumbo::pdu::bytes::NetworkBytes;
 use crate::dumbo::pdu::ipv4::{IPv4Packet, Ipv4Error as IPv4PacketError, PROTOCOL_TCP};
 use crate::dumbo::pdu::tcp::{Flags as TcpFlags, TcpError as TcpSegmentError, TcpSegment};
 use crate::dumbo::tcp::endpoint::Endpoint;
-use crate::dumbo::tcp::{NextSegmentStatus, RstConfig};
+use crate::dumbo::tcp::{NextSegmentStatus, Reply, RstConfig};
 
 // TODO: This is currently IPv4 specific. Maybe change it to a more generic implementation.
 
@@ -206,11 +206,16 @@ impl TcpIPv4Handler {
     /// Contains logic for handling incoming segments.
     ///
     /// Any changes to the state of the handler are communicated through an `Ok(RecvEvent)`.
-    pub fn receive_packet<T: NetworkBytes + Debug, F: FnOnce(Request) -> Response>(
+    pub fn receive_packet<T, F, R>(
         &mut self,
         packet: &IPv4Packet<T>,
         callback: F,
-    ) -> Result<RecvEvent, RecvError> {
+    ) -> Result<RecvEvent, RecvError>
+    where
+        T: NetworkBytes + Debug,
+        F: FnOnce(Request) -> R,
+        R: Into<Reply>,
+    {
         // TODO: We skip verifying the checksum, just in case the device model relies on offloading
         // checksum computation from the guest to some other entity. Clear this up at some point!
         // (Issue #520)
@@ -487,6 +492,26 @@ impl TcpIPv4Handler {
         Ok((len, event))
     }
 
+    /// Completes the parked responses of the connections which are ready, so that they get sent.
+    pub fn poll_parked_responses(&mut self) {
+        let completed = self
+            .connections
+            .iter_mut()
+            .filter_map(|(tuple, endpoint)| endpoint.poll_parked_response().then_some(*tuple))
+            .collect::<Vec<_>>();
+        for tuple in completed {
+            let status = self.connections[&tuple].next_segment_status();
+            self.check_next_segment_status(tuple, status);
+        }
+    }
+
+    /// Returns whether some connections wait for their parked response to complete.
+    pub fn has_parked_responses(&self) -> bool {
+        self.connections
+            .values()
+            .any(|endpoint| endpoint.has_parked_response())
+    }
+
     /// Describes the status of the next segment to be sent by the handler.
     #[inline]
     pub fn next_segment_status(&self) -> NextSegmentStatus {
@@ -10,6 +10,8 @@ pub mod handler;
 use std::fmt::Debug;
 use std::num::Wrapping;
 
+use micro_http::Response;
+
 use crate::dumbo::pdu::bytes::NetworkBytes;
 use crate::dumbo::pdu::tcp::{Flags as TcpFlags, TcpSegment};
 
@@ -20,6 +22,28 @@ pub const MAX_WINDOW_SIZE: u32 = 1_073_725_440;
 /// over the initial handshake.
 pub const MSS_DEFAULT: u16 = 536;
 
+/// Produces the response to an HTTP request which cannot be answered right away.
+pub trait ParkedResponse: Debug + Send {
+    /// Returns the bytes of the response, once it is ready to be sent.
+    fn poll(&mut self) -> Option<Vec<u8>>;
+}
+
+/// Describes how the callback handling an HTTP request received over a connection replies to it.
+#[derive(Debug)]
+pub enum Reply {
+    /// The response is sent right away.
+    Ready(Response),
+    /// The connection is held open, without handling further requests, until the response is
+    /// ready.
+    Parked(Box<dyn ParkedResponse>),
+}
+
+impl From<Response> for Reply {
+    fn from(response: Response) -> Self {
+        Reply::Ready(response)
+    }
+}
+
 /// Describes whether a particular entity (a [`Connection`] for example) has segments to send.
 ///
 /// [`Connection`]: connection/struct.Connection.html
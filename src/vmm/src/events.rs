@@ -0,0 +1,442 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Publishes VMM lifecycle and device events to their subscribers.
+//!
+//! Events are published through the global [`EVENTS`] bus. Every subscriber owns a bounded
+//! queue of events, so a slow consumer never makes the bus buffer an unbounded amount of data:
+//! once its queue is full, further events are dropped for that subscriber only and are reported
+//! as a single [`StreamItem::Gap`] once it catches up.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use utils::eventfd::EventFd;
+use utils::time::{get_time_us, ClockType};
+
+use crate::devices::ErrorSeverity;
+use crate::logger::error;
+use crate::vmm_config::auto_pause::ActivitySignal;
+use crate::vmm_config::console_scanner::ConsoleMatchAction;
+
+/// Default number of events buffered for a subscriber before events start being dropped.
+pub const DEFAULT_SUBSCRIBER_CAPACITY: usize = 256;
+
+/// Global event bus.
+pub static EVENTS: EventBus = EventBus::new();
+
+/// Snapshot operation reported by the snapshot events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotOperation {
+    /// Creating a snapshot of the microVM.
+    Create,
+    /// Loading the microVM from a snapshot.
+    Load,
+    /// Cloning the microVM into another Firecracker process.
+    Clone,
+}
+
+/// Kind of error reported by a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceErrorKind {
+    /// The device could not be activated by the guest driver.
+    Activation,
+    /// A request of the guest could not be executed by the device backend.
+    Io,
+    /// The device failed to process its queues or the events of its backend.
+    Queue,
+}
+
+/// Event emitted by the VMM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum VmmEvent {
+    /// The vCPUs of the microVM are being started.
+    Starting,
+    /// The microVM finished booting and is running.
+    Running,
+    /// The microVM was paused.
+    Paused,
+    /// The microVM was resumed.
+    Resumed,
+    /// A snapshot operation started.
+    SnapshotStart {
+        /// The snapshot operation.
+        operation: SnapshotOperation,
+    },
+    /// A snapshot operation finished.
+    SnapshotEnd {
+        /// The snapshot operation.
+        operation: SnapshotOperation,
+        /// Whether the operation succeeded.
+        success: bool,
+    },
+    /// The guest driver adjusted the balloon to its target size.
+    BalloonConverged {
+        /// Size of the balloon, in MiB.
+        amount_mib: u32,
+    },
+    /// A device encountered an error.
+    DeviceError {
+        /// Virtio device type of the device.
+        device_type: u32,
+        /// What kind of error occurred.
+        kind: DeviceErrorKind,
+        /// How badly the error affects the device.
+        severity: ErrorSeverity,
+        /// Description of the error.
+        message: String,
+    },
+    /// The console scanner found a pattern in the output of the guest console.
+    ConsolePatternMatched {
+        /// The pattern found.
+        pattern: String,
+        /// The action taken.
+        action: ConsoleMatchAction,
+    },
+    /// The guest passed its readiness probe.
+    Ready {
+        /// Number of attempts of the probe it took.
+        attempts: u32,
+    },
+    /// The microVM was paused because it was idle.
+    AutoPaused {
+        /// Time the microVM was idle for, in seconds.
+        idle_seconds: u64,
+    },
+    /// The auto-paused microVM was resumed by activity.
+    AutoResumed {
+        /// The activity which resumed the microVM.
+        trigger: ActivitySignal,
+    },
+    /// The VMM thread started a blocking API request and stops serving the API and the devices
+    /// until it is done, in single-threaded API mode.
+    ApiBlocked {
+        /// The blocking API request.
+        action: String,
+    },
+    /// The blocking API request is done and the VMM thread serves the API and the devices again.
+    ApiUnblocked {
+        /// The blocking API request.
+        action: String,
+    },
+}
+
+impl VmmEvent {
+    /// Name of the event, as used for the `event` field of its serialized form.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Starting => "starting",
+            Self::Running => "running",
+            Self::Paused => "paused",
+            Self::Resumed => "resumed",
+            Self::SnapshotStart { .. } => "snapshot_start",
+            Self::SnapshotEnd { .. } => "snapshot_end",
+            Self::BalloonConverged { .. } => "balloon_converged",
+            Self::DeviceError { .. } => "device_error",
+            Self::ConsolePatternMatched { .. } => "console_pattern_matched",
+            Self::Ready { .. } => "ready",
+            Self::AutoPaused { .. } => "auto_paused",
+            Self::AutoResumed { .. } => "auto_resumed",
+            Self::ApiBlocked { .. } => "api_blocked",
+            Self::ApiUnblocked { .. } => "api_unblocked",
+        }
+    }
+}
+
+/// An event together with its position in the event sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EventRecord {
+    /// Sequence number of the event. Sequence numbers increase by one for each published event.
+    pub seq: u64,
+    /// Wall clock time at which the event was published, in microseconds.
+    pub timestamp_us: u64,
+    /// The event.
+    #[serde(flatten)]
+    pub event: VmmEvent,
+}
+
+/// Item delivered to a subscriber.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamItem {
+    /// A published event.
+    Event(EventRecord),
+    /// Events that were dropped because the subscriber did not keep up.
+    Gap {
+        /// Number of events dropped.
+        dropped: u64,
+    },
+}
+
+#[derive(Debug)]
+struct SubscriberQueue {
+    records: VecDeque<EventRecord>,
+    capacity: usize,
+    // Number of events dropped since the subscriber last took its events.
+    dropped: u64,
+}
+
+impl SubscriberQueue {
+    fn push(&mut self, record: EventRecord) {
+        // Events are only dropped while the queue is full, and the gap is reported by `take`
+        // after the queued events, so gaps always end up at the right position in the stream.
+        if self.records.len() >= self.capacity {
+            self.dropped += 1;
+        } else {
+            self.records.push_back(record);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    queue: Arc<Mutex<SubscriberQueue>>,
+    notifier: EventFd,
+}
+
+/// Subscription to the events published on an [`EventBus`].
+///
+/// The subscription is cancelled when this object is dropped.
+#[derive(Debug)]
+pub struct EventSubscription {
+    queue: Arc<Mutex<SubscriberQueue>>,
+    notifier: EventFd,
+}
+
+impl EventSubscription {
+    /// Event fd that is written whenever new items are available.
+    pub fn notifier(&self) -> &EventFd {
+        &self.notifier
+    }
+
+    /// Takes all the items that are currently available, in order.
+    pub fn take(&self) -> Vec<StreamItem> {
+        // The notifier is non-blocking, so this fails if nothing was published in the meantime.
+        let _ = self.notifier.read();
+        let mut queue = self.queue.lock().expect("Poisoned lock");
+        let mut items: Vec<StreamItem> = queue.records.drain(..).map(StreamItem::Event).collect();
+        if queue.dropped > 0 {
+            items.push(StreamItem::Gap {
+                dropped: queue.dropped,
+            });
+            queue.dropped = 0;
+        }
+        items
+    }
+}
+
+#[derive(Debug)]
+struct EventBusInner {
+    next_seq: u64,
+    subscribers: Vec<Subscriber>,
+}
+
+/// Distributes the published events to all subscribers.
+#[derive(Debug)]
+pub struct EventBus {
+    inner: Mutex<EventBusInner>,
+}
+
+impl EventBus {
+    /// Creates an event bus without subscribers.
+    pub const fn new() -> Self {
+        EventBus {
+            inner: Mutex::new(EventBusInner {
+                next_seq: 0,
+                subscribers: Vec::new(),
+            }),
+        }
+    }
+
+    /// Subscribes to the events published from now on, buffering at most `capacity` events.
+    pub fn subscribe(&self, capacity: usize) -> Result<EventSubscription, std::io::Error> {
+        let notifier = EventFd::new(libc::EFD_NONBLOCK)?;
+        let queue = Arc::new(Mutex::new(SubscriberQueue {
+            records: VecDeque::new(),
+            capacity,
+            dropped: 0,
+        }));
+        self.inner
+            .lock()
+            .expect("Poisoned lock")
+            .subscribers
+            .push(Subscriber {
+                queue: queue.clone(),
+                notifier: notifier.try_clone()?,
+            });
+        Ok(EventSubscription { queue, notifier })
+    }
+
+    /// Publishes an event to all the current subscribers.
+    pub fn publish(&self, event: VmmEvent) {
+        let mut inner = self.inner.lock().expect("Poisoned lock");
+        // Forget the subscriptions that were dropped.
+        inner
+            .subscribers
+            .retain(|subscriber| Arc::strong_count(&subscriber.queue) > 1);
+
+        let record = EventRecord {
+            seq: inner.next_seq,
+            timestamp_us: get_time_us(ClockType::Real),
+            event,
+        };
+        inner.next_seq += 1;
+
+        for subscriber in inner.subscribers.iter() {
+            subscriber
+                .queue
+                .lock()
+                .expect("Poisoned lock")
+                .push(record.clone());
+            if let Err(err) = subscriber.notifier.write(1) {
+                error!("Failed to notify event subscriber: {}", err);
+            }
+        }
+    }
+
+    /// Number of active subscriptions.
+    pub fn subscriber_count(&self) -> usize {
+        let inner = self.inner.lock().expect("Poisoned lock");
+        inner
+            .subscribers
+            .iter()
+            .filter(|subscriber| Arc::strong_count(&subscriber.queue) > 1)
+            .count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(items: &[StreamItem]) -> Vec<(u64, VmmEvent)> {
+        items
+            .iter()
+            .filter_map(|item| match item {
+                StreamItem::Event(record) => Some((record.seq, record.event.clone())),
+                StreamItem::Gap { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_publish_order() {
+        let bus = EventBus::new();
+        // Events published without subscribers are lost, but still consume a sequence number.
+        bus.publish(VmmEvent::Starting);
+
+        let sub = bus.subscribe(DEFAULT_SUBSCRIBER_CAPACITY).unwrap();
+        assert!(sub.take().is_empty());
+        bus.publish(VmmEvent::Running);
+        bus.publish(VmmEvent::Paused);
+        bus.publish(VmmEvent::SnapshotStart {
+            operation: SnapshotOperation::Create,
+        });
+
+        assert_eq!(sub.notifier().read().unwrap(), 3);
+        assert_eq!(
+            events(&sub.take()),
+            vec![
+                (1, VmmEvent::Running),
+                (2, VmmEvent::Paused),
+                (
+                    3,
+                    VmmEvent::SnapshotStart {
+                        operation: SnapshotOperation::Create
+                    }
+                ),
+            ]
+        );
+        assert!(sub.take().is_empty());
+    }
+
+    #[test]
+    fn test_gap_marker() {
+        let bus = EventBus::new();
+        let slow = bus.subscribe(2).unwrap();
+        let fast = bus.subscribe(2).unwrap();
+
+        for _ in 0..5 {
+            bus.publish(VmmEvent::Paused);
+            fast.take();
+        }
+        // The slow subscriber only kept the first two events, the others are reported as a gap.
+        let items = slow.take();
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            events(&items[..2]),
+            vec![(0, VmmEvent::Paused), (1, VmmEvent::Paused)]
+        );
+        assert_eq!(items[2], StreamItem::Gap { dropped: 3 });
+
+        // Once it catches up, the gap is placed before the next delivered event.
+        bus.publish(VmmEvent::Resumed);
+        bus.publish(VmmEvent::Resumed);
+        bus.publish(VmmEvent::Resumed);
+        let items = slow.take();
+        assert_eq!(
+            events(&items),
+            vec![(5, VmmEvent::Resumed), (6, VmmEvent::Resumed)]
+        );
+        assert_eq!(items[2], StreamItem::Gap { dropped: 1 });
+        bus.publish(VmmEvent::Running);
+        assert_eq!(events(&slow.take()), vec![(8, VmmEvent::Running)]);
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let bus = EventBus::new();
+        let sub = bus.subscribe(DEFAULT_SUBSCRIBER_CAPACITY).unwrap();
+        assert_eq!(bus.subscriber_count(), 1);
+        drop(sub);
+        assert_eq!(bus.subscriber_count(), 0);
+        bus.publish(VmmEvent::Running);
+        assert!(bus.inner.lock().unwrap().subscribers.is_empty());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let record = EventRecord {
+            seq: 7,
+            timestamp_us: 42,
+            event: VmmEvent::DeviceError {
+                device_type: 2,
+                kind: DeviceErrorKind::Activation,
+                severity: ErrorSeverity::Fatal,
+                message: "failed".to_string(),
+            },
+        };
+        assert_eq!(record.event.name(), "device_error");
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"seq":7,"timestamp_us":42,"event":"device_error","device_type":2,"kind":"activation","severity":"fatal","message":"failed"}"#
+        );
+
+        let event = VmmEvent::AutoResumed {
+            trigger: ActivitySignal::Net,
+        };
+        assert_eq!(event.name(), "auto_resumed");
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"auto_resumed","trigger":"net"}"#
+        );
+
+        let event = VmmEvent::ApiBlocked {
+            action: "create_snapshot".to_string(),
+        };
+        assert_eq!(event.name(), "api_blocked");
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"api_blocked","action":"create_snapshot"}"#
+        );
+    }
+}
@@ -27,7 +27,7 @@ use restriction::Restriction;
 use utils::syscall::SyscallReturnCode;
 
 // IO_uring operations that we require to be supported by the host kernel.
-const REQUIRED_OPS: [OpCode; 2] = [OpCode::Read, OpCode::Write];
+const REQUIRED_OPS: [OpCode; 4] = [OpCode::Read, OpCode::Write, OpCode::Readv, OpCode::Writev];
 // Taken from linux/fs/io_uring.c
 const IORING_MAX_FIXED_FILES: usize = 1 << 15;
 
@@ -109,6 +109,21 @@ impl<T: Debug> IoUring<T> {
         files: Vec<&File>,
         restrictions: Vec<Restriction>,
         eventfd: Option<RawFd>,
+    ) -> Result<Self, IoUringError> {
+        Self::new_with_sq_poll(num_entries, files, restrictions, eventfd, None)
+    }
+
+    /// Create a new instance, whose submission queue is polled by a kernel thread if
+    /// `sq_thread_idle_ms` is set. The thread sleeps once the queue was idle for
+    /// `sq_thread_idle_ms` milliseconds, and is woken up by the next submission.
+    ///
+    /// See [`IoUring::new`] for the other arguments.
+    pub fn new_with_sq_poll(
+        num_entries: u32,
+        files: Vec<&File>,
+        restrictions: Vec<Restriction>,
+        eventfd: Option<RawFd>,
+        sq_thread_idle_ms: Option<u32>,
     ) -> Result<Self, IoUringError> {
         let mut params = io_uring_params {
             // Create the ring as disabled, so that we may register restrictions.
@@ -116,6 +131,10 @@ impl<T: Debug> IoUring<T> {
 
             ..Default::default()
         };
+        if let Some(idle_ms) = sq_thread_idle_ms {
+            params.flags |= bindings::IORING_SETUP_SQPOLL;
+            params.sq_thread_idle = idle_ms;
+        }
 
         // SAFETY: Safe because values are valid and we check the return value.
         let fd = SyscallReturnCode(unsafe {
@@ -240,6 +259,11 @@ impl<T: Debug> IoUring<T> {
         self.squeue.pending().map_err(IoUringError::SQueue)
     }
 
+    /// Whether the submission queue has no room left for another operation.
+    pub fn is_squeue_full(&self) -> Result<bool, IoUringError> {
+        Ok(self.pending_sqes()? >= self.squeue.count())
+    }
+
     /// A total of the number of ops in the submission and completion queues, as well as the
     /// in-flight ops.
     pub fn num_ops(&self) -> u32 {
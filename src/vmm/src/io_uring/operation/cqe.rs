@@ -38,6 +38,19 @@ impl<T: Debug> Cqe<T> {
         }
     }
 
+    /// Whether the operation failed because it is not supported, e.g. by the file system.
+    pub fn is_unsupported(&self) -> bool {
+        self.res == -libc::EOPNOTSUPP
+    }
+
+    /// Create a new Cqe with the same result, for another user_data.
+    pub fn with_user_data<U: Debug>(&self, user_data: U) -> Cqe<U> {
+        Cqe {
+            res: self.res,
+            user_data,
+        }
+    }
+
     /// Create a new Cqe, applying the passed function to the user_data.
     pub fn map_user_data<U: Debug, F: FnOnce(T) -> U>(self, op: F) -> Cqe<U> {
         Cqe {
@@ -78,6 +91,13 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_is_unsupported() {
+        assert!(Cqe::new(-libc::EOPNOTSUPP, 0_u8).is_unsupported());
+        assert!(!Cqe::new(-libc::EINVAL, 0_u8).is_unsupported());
+        assert!(!Cqe::new(0, 0_u8).is_unsupported());
+    }
+
     #[test]
     fn test_user_data() {
         let user_data = 10_u8;
@@ -94,4 +114,16 @@ mod tests {
 
         assert_eq!(cqe.user_data(), 11);
     }
+
+    #[test]
+    fn test_with_user_data() {
+        let cqe: Cqe<u8> = Cqe::new(-libc::EIO, 10_u8);
+        let other = cqe.with_user_data(20_u16);
+
+        assert_eq!(
+            other.result().unwrap_err().kind(),
+            cqe.result().unwrap_err().kind()
+        );
+        assert_eq!(other.user_data(), 20);
+    }
 }
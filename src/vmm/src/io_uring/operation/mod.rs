@@ -27,8 +27,14 @@ pub enum OpCode {
     Read = bindings::IORING_OP_READ as u8,
     /// Write operation.
     Write = bindings::IORING_OP_WRITE as u8,
+    /// Vectored read operation.
+    Readv = bindings::IORING_OP_READV as u8,
+    /// Vectored write operation.
+    Writev = bindings::IORING_OP_WRITEV as u8,
     /// Fsync operation.
     Fsync = bindings::IORING_OP_FSYNC as u8,
+    /// Fallocate operation.
+    Fallocate = bindings::IORING_OP_FALLOCATE as u8,
 }
 
 // Useful for outputting errors.
@@ -37,7 +43,10 @@ impl From<OpCode> for &'static str {
         match opcode {
             OpCode::Read => "read",
             OpCode::Write => "write",
+            OpCode::Readv => "readv",
+            OpCode::Writev => "writev",
             OpCode::Fsync => "fsync",
+            OpCode::Fallocate => "fallocate",
         }
     }
 }
@@ -100,6 +109,46 @@ impl<T: Debug> Operation<T> {
         }
     }
 
+    /// Construct a vectored read operation, into the `iovec_count` `iovec`s at `iovecs`. The
+    /// `iovec`s have to stay valid until the operation completes.
+    pub fn readv(
+        fd: FixedFd,
+        iovecs: *const libc::iovec,
+        iovec_count: u32,
+        offset: u64,
+        user_data: T,
+    ) -> Self {
+        Self {
+            fd,
+            opcode: OpCode::Readv,
+            addr: Some(iovecs as usize),
+            len: Some(iovec_count),
+            flags: 0,
+            offset: Some(offset),
+            user_data,
+        }
+    }
+
+    /// Construct a vectored write operation, from the `iovec_count` `iovec`s at `iovecs`. The
+    /// `iovec`s have to stay valid until the operation completes.
+    pub fn writev(
+        fd: FixedFd,
+        iovecs: *const libc::iovec,
+        iovec_count: u32,
+        offset: u64,
+        user_data: T,
+    ) -> Self {
+        Self {
+            fd,
+            opcode: OpCode::Writev,
+            addr: Some(iovecs as usize),
+            len: Some(iovec_count),
+            flags: 0,
+            offset: Some(offset),
+            user_data,
+        }
+    }
+
     /// Construct a fsync operation.
     pub fn fsync(fd: FixedFd, user_data: T) -> Self {
         Self {
@@ -113,10 +162,30 @@ impl<T: Debug> Operation<T> {
         }
     }
 
+    /// Construct a fallocate operation, applying `mode` to the `len` bytes at `offset`.
+    pub fn fallocate(fd: FixedFd, offset: u64, len: u64, mode: u32, user_data: T) -> Self {
+        Self {
+            fd,
+            opcode: OpCode::Fallocate,
+            // The length of the range is passed in the address field of the sqe, and the mode in
+            // its length field.
+            addr: Some(utils::u64_to_usize(len)),
+            len: Some(mode),
+            flags: 0,
+            offset: Some(offset),
+            user_data,
+        }
+    }
+
     pub(crate) fn fd(&self) -> FixedFd {
         self.fd
     }
 
+    /// Only start the operation once all the operations submitted before it completed.
+    pub fn set_drain(&mut self) {
+        self.flags |= 1 << bindings::IOSQE_IO_DRAIN_BIT;
+    }
+
     // Needed for proptesting.
     #[cfg(test)]
     pub(crate) fn set_linked(&mut self) {
@@ -6,7 +6,7 @@ use std::io::Error as IOError;
 use std::mem;
 use std::num::Wrapping;
 use std::os::unix::io::RawFd;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{fence, Ordering};
 
 use utils::syscall::SyscallReturnCode;
 use vm_memory::{VolatileMemory, VolatileMemoryError};
@@ -36,6 +36,7 @@ pub(crate) struct SubmissionQueue {
     // Offsets.
     head_off: usize,
     tail_off: usize,
+    flags_off: usize,
 
     // Cached values.
     ring_mask: u32,
@@ -49,6 +50,8 @@ pub(crate) struct SubmissionQueue {
 
     // Number of ops yet to be submitted.
     to_submit: u32,
+    // Whether a kernel thread polls the queue for ops, with `IORING_SETUP_SQPOLL`.
+    sq_poll: bool,
 }
 
 impl SubmissionQueue {
@@ -72,6 +75,7 @@ impl SubmissionQueue {
             io_uring_fd,
             head_off: params.sq_off.head as usize,
             tail_off: params.sq_off.tail as usize,
+            flags_off: params.sq_off.flags as usize,
             ring_mask,
             count: params.sq_entries,
             // We can init this to 0 and cache it because we are the only ones modifying it.
@@ -79,6 +83,7 @@ impl SubmissionQueue {
             ring,
             sqes,
             to_submit: 0,
+            sq_poll: (params.flags & bindings::IORING_SETUP_SQPOLL) != 0,
         })
     }
 
@@ -130,6 +135,22 @@ impl SubmissionQueue {
         if min_complete > 0 {
             flags |= bindings::IORING_ENTER_GETEVENTS;
         }
+        if self.sq_poll {
+            // The kernel thread picks the ops up on its own, unless it went to sleep after being
+            // idle. The fence orders the store of the tail before the load of the flags, so that
+            // either the thread sees the new ops before sleeping, or we see that it sleeps.
+            fence(Ordering::SeqCst);
+            let sq_flags = self
+                .ring
+                .as_volatile_slice()
+                .load::<u32>(self.flags_off, Ordering::Relaxed)?;
+            if (sq_flags & bindings::IORING_SQ_NEED_WAKEUP) != 0 {
+                flags |= bindings::IORING_ENTER_SQ_WAKEUP;
+            }
+            if flags == 0 {
+                return Ok(mem::take(&mut self.to_submit));
+            }
+        }
         // SAFETY: Safe because values are valid and we check the return value.
         let submitted = SyscallReturnCode(unsafe {
             libc::syscall(
@@ -187,6 +208,10 @@ impl SubmissionQueue {
 
         Ok((self.unmasked_tail - Wrapping(unmasked_head)).0)
     }
+
+    pub(crate) fn count(&self) -> u32 {
+        self.count
+    }
 }
 
 impl Drop for SubmissionQueue {
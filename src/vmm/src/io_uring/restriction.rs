@@ -22,6 +22,8 @@ pub enum Restriction {
     AllowOpCode(OpCode),
     /// Only allow operations on pre-registered fds.
     RequireFixedFds,
+    /// Allow operations that only start once all the previously submitted ones completed.
+    AllowDrain,
 }
 
 impl From<&Restriction> for bindings::io_uring_restriction {
@@ -41,6 +43,11 @@ impl From<&Restriction> for bindings::io_uring_restriction {
                     u16::try_from(bindings::IORING_RESTRICTION_SQE_FLAGS_REQUIRED).unwrap();
                 instance.__bindgen_anon_1.sqe_flags = 1 << bindings::IOSQE_FIXED_FILE_BIT;
             }
+            AllowDrain => {
+                instance.opcode =
+                    u16::try_from(bindings::IORING_RESTRICTION_SQE_FLAGS_ALLOWED).unwrap();
+                instance.__bindgen_anon_1.sqe_flags = 1 << bindings::IOSQE_IO_DRAIN_BIT;
+            }
         };
 
         instance
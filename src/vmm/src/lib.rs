@@ -73,10 +73,14 @@ pub mod io_uring;
 /// needs to be called by the user on every event on the rate limiter's `AsRawFd` FD.
 pub mod rate_limiter;
 
+/// Guest pages touched by restored microVMs, to estimate their working set.
+pub mod access_profile;
 /// Module for handling ACPI tables.
 /// Currently, we only use ACPI on x86 microVMs.
 #[cfg(target_arch = "x86_64")]
 pub mod acpi;
+/// Automatic pause of idle microVMs.
+pub mod auto_pause;
 /// Handles setup and initialization a `Vmm` object.
 pub mod builder;
 /// Types for guest configuration.
@@ -87,12 +91,16 @@ pub(crate) mod device_manager;
 pub mod devices;
 /// minimalist HTTP/TCP/IPv4 stack named DUMBO
 pub mod dumbo;
+/// VMM lifecycle and device events.
+pub mod events;
 /// Logger
 pub mod logger;
 /// microVM Metadata Service MMDS
 pub mod mmds;
 /// Save/restore utilities.
 pub mod persist;
+/// Probe telling when the guest is ready.
+pub mod readiness_probe;
 /// Resource store for configured microVM resources.
 pub mod resources;
 /// microVM RPC API adapters.
@@ -111,6 +119,7 @@ pub mod vmm_config;
 pub mod vstate;
 
 use std::collections::HashMap;
+use std::fmt::Debug;
 use std::io;
 use std::os::unix::io::AsRawFd;
 use std::sync::mpsc::RecvTimeoutError;
@@ -122,32 +131,48 @@ use device_manager::acpi::ACPIDeviceManager;
 use device_manager::resources::ResourceAllocator;
 #[cfg(target_arch = "x86_64")]
 use devices::acpi::vmgenid::VmGenIdError;
-use event_manager::{EventManager as BaseEventManager, EventOps, Events, MutEventSubscriber};
+use event_manager::{
+    EventManager as BaseEventManager, EventOps, Events, MutEventSubscriber, SubscriberId,
+    SubscriberOps,
+};
 use seccompiler::BpfProgram;
 use userfaultfd::Uffd;
 use utils::epoll::EventSet;
 use utils::eventfd::EventFd;
+use utils::net::mac::MacAddr;
 use utils::terminal::Terminal;
 use utils::u64_to_usize;
 use vstate::vcpu::{self, KvmVcpuConfigureError, StartThreadedError, VcpuSendEventError};
 
+use crate::access_profile::{AccessProfile, AccessTracker};
 use crate::arch::DeviceType;
+use crate::auto_pause::AutoPause;
 use crate::cpu_config::templates::CpuConfiguration;
 #[cfg(target_arch = "x86_64")]
 use crate::device_manager::legacy::PortIODeviceManager;
-use crate::device_manager::mmio::MMIODeviceManager;
+use crate::device_manager::mmio::{MMIODeviceManager, MmioError};
+use crate::devices::legacy::console_scanner::ConsoleScanner;
 use crate::devices::legacy::{IER_RDA_BIT, IER_RDA_OFFSET};
 use crate::devices::virtio::balloon::{
     Balloon, BalloonConfig, BalloonError, BalloonStats, BALLOON_DEV_ID,
 };
 use crate::devices::virtio::block::device::Block;
+use crate::devices::virtio::device::VirtioDevice;
+use crate::devices::virtio::mmio::MmioTransport;
 use crate::devices::virtio::net::Net;
+use crate::devices::virtio::vhost_user::VhostUserNegotiation;
+use crate::devices::virtio::vsock::{Vsock, VsockUnixBackend, TYPE_VSOCK, VSOCK_DEV_ID};
 use crate::devices::virtio::{TYPE_BALLOON, TYPE_BLOCK, TYPE_NET};
-use crate::logger::{error, info, warn, MetricsError, METRICS};
+use crate::events::{VmmEvent, EVENTS};
+use crate::logger::{error, info, warn, IncMetric, MetricsError, METRICS};
 use crate::persist::{MicrovmState, MicrovmStateError, VmInfo};
 use crate::rate_limiter::BucketUpdate;
+use crate::readiness_probe::{ReadinessProbe, ReadinessProbeState};
 use crate::snapshot::Persist;
+use crate::vmm_config::console_scanner::ConsoleScannerConfig;
 use crate::vmm_config::instance_info::{InstanceInfo, VmState};
+use crate::vmm_config::net::NetworkInterfaceError;
+use crate::vmm_config::vsock::VsockConnections;
 use crate::vstate::memory::{
     GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryRegion,
 };
@@ -229,6 +254,8 @@ pub enum VmmError {
     SeccompFilters(seccompiler::InstallationError),
     /// Error writing to the serial console: {0}
     Serial(io::Error),
+    /// Cannot open the output of the serial port {0}: {1}
+    SerialPortOutput(String, io::Error),
     /// Error creating timer fd: {0}
     TimerFd(io::Error),
     /// Error configuring the vcpu for boot: {0}
@@ -317,6 +344,19 @@ pub struct Vmm {
     vcpus_handles: Vec<VcpuHandle>,
     // Used by Vcpus and devices to initiate teardown; Vmm should never write here.
     vcpus_exit_evt: EventFd,
+    // Written by the console scanner to request the microVM to be paused.
+    console_pause_evt: EventFd,
+    // Probe telling when the guest is ready, if configured.
+    readiness_probe: Option<Arc<Mutex<ReadinessProbe>>>,
+    // Automatic pause of the microVM once idle, if configured.
+    auto_pause: Option<AutoPause>,
+    // Tracking of the guest pages touched after a snapshot restore, if requested.
+    access_tracker: Option<AccessTracker>,
+    // Devices hot-plugged since the event manager last ran, which it has yet to subscribe.
+    hotplugged_devices: Vec<((DeviceType, String), Arc<Mutex<dyn MutEventSubscriber>>)>,
+    // Subscribers of the devices unplugged since the event manager last ran, which it has yet to
+    // forget.
+    unplugged_subscribers: Vec<SubscriberId>,
 
     // Allocator for guest resrouces
     resource_allocator: ResourceAllocator,
@@ -336,7 +376,30 @@ impl Vmm {
 
     /// Gets Vmm instance info.
     pub fn instance_info(&self) -> InstanceInfo {
-        self.instance_info.clone()
+        let readiness_probe = self
+            .readiness_probe
+            .as_ref()
+            .map(|probe| probe.lock().expect("Poisoned lock").status());
+        let mut out_of_space_drives = Vec::new();
+        let _: Result<(), MmioError> =
+            self.mmio_device_manager
+                .for_each_virtio_device(|virtio_type, id, _info, dev| {
+                    if virtio_type == TYPE_BLOCK {
+                        let mut virtio = dev.lock().expect("Poisoned lock");
+                        let block = virtio.as_mut_any().downcast_mut::<Block>().unwrap();
+                        if block.is_out_of_space() {
+                            out_of_space_drives.push(id.clone());
+                        }
+                    }
+                    Ok(())
+                });
+        InstanceInfo {
+            ready: readiness_probe
+                .map_or(true, |status| status.state == ReadinessProbeState::Ready),
+            readiness_probe,
+            out_of_space_drives,
+            ..self.instance_info.clone()
+        }
     }
 
     /// Provides the Vmm shutdown exit code if there is one.
@@ -365,6 +428,7 @@ impl Vmm {
         mut vcpus: Vec<Vcpu>,
         vcpu_seccomp_filter: Arc<BpfProgram>,
     ) -> Result<(), StartVcpusError> {
+        EVENTS.publish(VmmEvent::Starting);
         let vcpu_count = vcpus.len();
         let barrier = Arc::new(Barrier::new(vcpu_count + 1));
 
@@ -404,6 +468,10 @@ impl Vmm {
 
     /// Sends a resume command to the vCPUs.
     pub fn resume_vm(&mut self) -> Result<(), VmmError> {
+        // The pages mapped so far are recorded before the guest gets to touch any.
+        if let Some(access_tracker) = self.access_tracker.as_mut() {
+            access_tracker.on_resume();
+        }
         self.mmio_device_manager.kick_devices();
 
         // Send the events.
@@ -423,11 +491,20 @@ impl Vmm {
         }
 
         self.instance_info.state = VmState::Running;
+        if let Some(auto_pause) = self.auto_pause.as_mut() {
+            auto_pause.on_resumed();
+        }
         Ok(())
     }
 
     /// Sends a pause command to the vCPUs.
+    ///
+    /// An auto-paused microVM paused again is no longer resumed by activity.
     pub fn pause_vm(&mut self) -> Result<(), VmmError> {
+        if let Some(auto_pause) = self.auto_pause.as_ref() {
+            auto_pause.disarm();
+        }
+
         // Send the events.
         self.vcpus_handles
             .iter()
@@ -453,7 +530,7 @@ impl Vmm {
         &self.guest_memory
     }
 
-    /// Sets RDA bit in serial console
+    /// Sets RDA bit in the serial ports
     pub fn emulate_serial_init(&self) -> Result<(), EmulateSerialInitError> {
         // When restoring from a previously saved state, there is no serial
         // driver initialization, therefore the RDA (Received Data Available)
@@ -463,13 +540,17 @@ impl Vmm {
         // serialization. For now we set that bit manually
 
         #[cfg(target_arch = "aarch64")]
-        {
-            let serial_bus_device = self.get_bus_device(DeviceType::Serial, "Serial");
-            if serial_bus_device.is_none() {
-                return Ok(());
-            }
-            let mut serial_device_locked =
-                serial_bus_device.unwrap().lock().expect("Poisoned lock");
+        let serial_bus_devices = self.mmio_device_manager.get_serial_devices();
+        #[cfg(target_arch = "x86_64")]
+        let serial_bus_devices: Vec<&Mutex<devices::bus::BusDevice>> = self
+            .pio_device_manager
+            .serial_ports
+            .iter()
+            .map(|serial| serial.as_ref())
+            .collect();
+
+        for serial_bus_device in serial_bus_devices {
+            let mut serial_device_locked = serial_bus_device.lock().expect("Poisoned lock");
             let serial = serial_device_locked
                 .serial_mut()
                 .expect("Unexpected BusDeviceType");
@@ -478,23 +559,85 @@ impl Vmm {
                 .serial
                 .write(IER_RDA_OFFSET, IER_RDA_BIT)
                 .map_err(|_| EmulateSerialInitError(std::io::Error::last_os_error()))?;
-            Ok(())
         }
+        Ok(())
+    }
+
+    /// Attaches a scanner looking for the configured patterns in the output of the serial console.
+    pub fn attach_console_scanner(&self, config: &ConsoleScannerConfig) -> Result<(), VmmError> {
+        let scanner = ConsoleScanner::new(
+            config,
+            self.console_pause_evt
+                .try_clone()
+                .map_err(VmmError::EventFd)?,
+        );
 
+        #[cfg(target_arch = "aarch64")]
+        let Some(serial_bus_device) = self
+            .mmio_device_manager
+            .get_serial_devices()
+            .into_iter()
+            .next()
+        else {
+            warn!("The console scanner is not attached: the serial console is disabled.");
+            return Ok(());
+        };
         #[cfg(target_arch = "x86_64")]
-        {
-            let mut guard = self
-                .pio_device_manager
-                .stdio_serial
-                .lock()
-                .expect("Poisoned lock");
-            let serial = guard.serial_mut().unwrap();
+        let serial_bus_device = self.pio_device_manager.console_serial();
 
-            serial
-                .serial
-                .write(IER_RDA_OFFSET, IER_RDA_BIT)
-                .map_err(|_| EmulateSerialInitError(std::io::Error::last_os_error()))?;
-            Ok(())
+        let mut serial_device_locked = serial_bus_device.lock().expect("Poisoned lock");
+        let serial = serial_device_locked
+            .serial_mut()
+            .expect("Unexpected BusDeviceType");
+        serial.serial.writer_mut().scanner = Some(scanner);
+        Ok(())
+    }
+
+    // Pauses the running microVM once it has been idle for long enough.
+    fn process_auto_pause_timer(&mut self) {
+        let running = self.instance_info.state == VmState::Running;
+        let Some(auto_pause) = self.auto_pause.as_mut() else {
+            return;
+        };
+        if !auto_pause.check_idle(running) {
+            return;
+        }
+        let idle_seconds = auto_pause.idle_seconds();
+        match self.pause_vm() {
+            Ok(()) => {
+                if let Some(auto_pause) = self.auto_pause.as_ref() {
+                    auto_pause.arm();
+                }
+                info!("Paused the microVM after {} idle seconds.", idle_seconds);
+                METRICS.vmm.auto_pauses.inc();
+                EVENTS.publish(VmmEvent::AutoPaused { idle_seconds });
+            }
+            Err(err) => {
+                METRICS.vmm.auto_pause_fails.inc();
+                error!("Failed to pause the idle microVM: {}", err);
+            }
+        }
+    }
+
+    // Resumes the auto-paused microVM on activity.
+    fn process_auto_pause_wake(&mut self) {
+        let Some(trigger) = self.auto_pause.as_mut().and_then(AutoPause::take_wake) else {
+            return;
+        };
+        // The microVM may have been resumed through the API in the meantime.
+        if self.instance_info.state != VmState::Paused {
+            return;
+        }
+        match self.resume_vm() {
+            Ok(()) => {
+                info!("Resumed the auto-paused microVM on {:?} activity.", trigger);
+                METRICS.vmm.auto_resumes.inc();
+                EVENTS.publish(VmmEvent::AutoResumed { trigger });
+            }
+            Err(err) => {
+                METRICS.vmm.auto_pause_fails.inc();
+                error!("Failed to resume the auto-paused microVM: {}", err);
+            }
         }
     }
 
@@ -659,6 +802,33 @@ impl Vmm {
             .map_err(VmmError::DeviceManager)
     }
 
+    /// Makes the block device with `drive_id` id read-only, or keeps it read-only.
+    pub fn update_block_read_only(
+        &mut self,
+        drive_id: &str,
+        read_only: bool,
+    ) -> Result<(), VmmError> {
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_BLOCK, drive_id, |block: &mut Block| {
+                block
+                    .update_read_only(read_only)
+                    .map_err(|err| err.to_string())
+            })
+            .map_err(VmmError::DeviceManager)
+    }
+
+    /// Retries the writes the block device with `drive_id` id holds back since the host ran out
+    /// of space.
+    pub fn retry_block_pending_requests(&mut self, drive_id: &str) -> Result<(), VmmError> {
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_BLOCK, drive_id, |block: &mut Block| {
+                block
+                    .retry_pending_requests()
+                    .map_err(|err| err.to_string())
+            })
+            .map_err(VmmError::DeviceManager)
+    }
+
     /// Updates the rate limiter parameters for block device with `drive_id` id.
     pub fn update_block_rate_limiter(
         &mut self,
@@ -684,6 +854,62 @@ impl Vmm {
             .map_err(VmmError::DeviceManager)
     }
 
+    /// Returns the guest pages touched since the restored microVM resumed, when the snapshot was
+    /// loaded with memory access tracking.
+    pub fn access_profile(&self) -> Option<AccessProfile> {
+        self.access_tracker.as_ref().map(AccessTracker::profile)
+    }
+
+    /// Returns the summary of the negotiation of the vhost-user device with id `id` with its
+    /// backend. Only block devices can be vhost-user devices for now.
+    pub fn vhost_user_negotiation(&self, id: &str) -> Result<VhostUserNegotiation, VmmError> {
+        let mut negotiation = VhostUserNegotiation::default();
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_BLOCK, id, |block: &mut Block| {
+                negotiation = block
+                    .vhost_user_negotiation()
+                    .map_err(|err| err.to_string())?;
+                Ok(())
+            })
+            .map_err(VmmError::DeviceManager)?;
+        Ok(negotiation)
+    }
+
+    /// Checks that the backend of the vhost-user device with id `id` replies within `timeout`,
+    /// returning the time it took to reply.
+    pub fn ping_vhost_user_backend(
+        &self,
+        id: &str,
+        timeout: Duration,
+    ) -> Result<Duration, VmmError> {
+        let mut latency = Duration::ZERO;
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_BLOCK, id, |block: &mut Block| {
+                latency = block
+                    .ping_vhost_user_backend(timeout)
+                    .map_err(|err| err.to_string())?;
+                Ok(())
+            })
+            .map_err(VmmError::DeviceManager)?;
+        Ok(latency)
+    }
+
+    /// Returns a snapshot of the connection table of the vsock device.
+    pub fn vsock_connections(&self) -> Result<VsockConnections, VmmError> {
+        let mut connections = Vec::new();
+        self.mmio_device_manager
+            .with_virtio_device_with_id(
+                TYPE_VSOCK,
+                VSOCK_DEV_ID,
+                |vsock: &mut Vsock<VsockUnixBackend>| {
+                    connections = vsock.backend().connections();
+                    Ok(())
+                },
+            )
+            .map_err(VmmError::DeviceManager)?;
+        Ok(VsockConnections { connections })
+    }
+
     /// Updates the rate limiter parameters for net device with `net_id` id.
     pub fn update_net_rate_limiters(
         &mut self,
@@ -701,6 +927,168 @@ impl Vmm {
             .map_err(VmmError::DeviceManager)
     }
 
+    /// Updates the guest MAC address of the net device with `net_id` id.
+    ///
+    /// The guest driver is notified of the change by a configuration change interrupt, which it
+    /// handles once the microVM is resumed, so the microVM must be paused.
+    pub fn update_net_guest_mac(
+        &mut self,
+        net_id: &str,
+        mac: MacAddr,
+    ) -> Result<(), NetworkInterfaceError> {
+        if self.instance_info.state != VmState::Paused {
+            return Err(NetworkInterfaceError::GuestMacUpdateRunning);
+        }
+
+        let mut mac_in_use = false;
+        let _: Result<(), MmioError> =
+            self.mmio_device_manager
+                .for_each_virtio_device(|virtio_type, id, _info, dev| {
+                    if virtio_type == TYPE_NET && id != net_id {
+                        let mut virtio = dev.lock().expect("Poisoned lock");
+                        let net = virtio.as_mut_any().downcast_mut::<Net>().unwrap();
+                        mac_in_use |= net.guest_mac() == Some(&mac);
+                    }
+                    Ok(())
+                });
+        if mac_in_use {
+            return Err(NetworkInterfaceError::GuestMacAddressInUse(mac.to_string()));
+        }
+
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_NET, net_id, |net: &mut Net| {
+                net.set_guest_mac(mac).map_err(|err| err.to_string())
+            })
+            .map_err(VmmError::DeviceManager)
+            .map_err(NetworkInterfaceError::DeviceUpdate)
+    }
+
+    /// Asks the guest to announce itself on the network through its net devices, except the
+    /// `skipped_ids` ones, e.g. once the microVM is restored on another host. The drivers handle
+    /// the request once the microVM is resumed.
+    pub fn announce_net_devices(&self, skipped_ids: &[&str]) {
+        let _: Result<(), MmioError> =
+            self.mmio_device_manager
+                .for_each_virtio_device(|virtio_type, id, _info, dev| {
+                    if virtio_type == TYPE_NET && !skipped_ids.contains(&id.as_str()) {
+                        let mut virtio = dev.lock().expect("Poisoned lock");
+                        let net = virtio.as_mut_any().downcast_mut::<Net>().unwrap();
+                        if let Err(err) = net.announce() {
+                            warn!(
+                                "Failed to ask the guest to announce itself on {}: {}",
+                                id, err
+                            );
+                        }
+                    }
+                    Ok(())
+                });
+    }
+
+    /// Whether a slot is left to hot-plug a device in.
+    pub fn has_free_hotplug_slot(&self) -> bool {
+        !self.mmio_device_manager.hotplug_slots.is_empty()
+    }
+
+    // Plugs `device` in the first free hot-plug slot, and leaves it for the event manager to
+    // subscribe.
+    fn hotplug_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber>(
+        &mut self,
+        id: String,
+        device: Arc<Mutex<T>>,
+        is_vhost_user: bool,
+    ) -> Result<(), VmmError> {
+        let identifier = (
+            DeviceType::Virtio(device.lock().expect("Poisoned lock").device_type()),
+            id.clone(),
+        );
+        // The device mutex mustn't be locked here otherwise it will deadlock.
+        let transport =
+            MmioTransport::new(self.guest_memory.clone(), device.clone(), is_vhost_user);
+        self.mmio_device_manager
+            .hotplug_mmio_virtio(self.vm.fd(), id, transport)
+            .map_err(VmmError::DeviceManager)?;
+        let subscriber: Arc<Mutex<dyn MutEventSubscriber>> = device;
+        self.hotplugged_devices.push((identifier, subscriber));
+        Ok(())
+    }
+
+    /// Plugs the net device `net` in the first free hot-plug slot. Linux guests only find it
+    /// once they probe the slot again, as virtio-mmio has no way to notify them of new devices.
+    ///
+    /// The device has to be subscribed to the event manager afterwards, see
+    /// [`Vmm::update_event_subscribers`].
+    pub fn hotplug_net_device(&mut self, net: Arc<Mutex<Net>>) -> Result<(), VmmError> {
+        let id = net.lock().expect("Poisoned lock").id().clone();
+        self.hotplug_virtio_device(id, net, false)
+    }
+
+    /// Plugs the block device `block` in the first free hot-plug slot, like
+    /// [`Vmm::hotplug_net_device`].
+    pub fn hotplug_block_device(&mut self, block: Arc<Mutex<Block>>) -> Result<(), VmmError> {
+        let (id, is_vhost_user) = {
+            let locked = block.lock().expect("Poisoned lock");
+            (locked.id().to_string(), locked.is_vhost_user())
+        };
+        self.hotplug_virtio_device(id, block, is_vhost_user)
+    }
+
+    // Unplugs the `virtio_type` device `id` after running `quiesce` on it, and leaves it for the
+    // event manager to unsubscribe.
+    fn unplug_virtio_device<T, F>(
+        &mut self,
+        virtio_type: u32,
+        id: &str,
+        quiesce: F,
+    ) -> Result<(), VmmError>
+    where
+        T: 'static + VirtioDevice + Debug,
+        F: FnOnce(&mut T),
+    {
+        self.mmio_device_manager
+            .unplug_mmio_virtio::<T, _>(self.vm.fd(), virtio_type, id, quiesce)
+            .map_err(VmmError::DeviceManager)?;
+        let identifier = (DeviceType::Virtio(virtio_type), id.to_string());
+        if let Some(subscriber_id) = self.mmio_device_manager.subscriber_ids.remove(&identifier) {
+            self.unplugged_subscribers.push(subscriber_id);
+        }
+        // The device may have been hot-plugged since the event manager last ran.
+        self.hotplugged_devices
+            .retain(|(hotplugged, _)| hotplugged != &identifier);
+        Ok(())
+    }
+
+    /// Unplugs the net device `net_id` from the microVM, and frees its slot for hot-plugging
+    /// another device. The device is stopped first, see [`Net::quiesce`].
+    ///
+    /// The device has to be unsubscribed from the event manager afterwards, see
+    /// [`Vmm::update_event_subscribers`].
+    pub fn remove_net_device(&mut self, net_id: &str) -> Result<(), VmmError> {
+        self.unplug_virtio_device::<Net, _>(TYPE_NET, net_id, Net::quiesce)
+    }
+
+    /// Unplugs the block device `drive_id` from the microVM, like [`Vmm::remove_net_device`].
+    /// Its in-flight requests are completed first, and the ones left are failed, see
+    /// [`Block::quiesce`]. The backing file is closed once the device is dropped.
+    pub fn remove_block_device(&mut self, drive_id: &str) -> Result<(), VmmError> {
+        self.unplug_virtio_device::<Block, _>(TYPE_BLOCK, drive_id, Block::quiesce)
+    }
+
+    /// Subscribes the devices hot-plugged since the last call to `event_manager`, and
+    /// unsubscribes the ones unplugged.
+    pub fn update_event_subscribers(&mut self, event_manager: &mut EventManager) {
+        for (identifier, device) in std::mem::take(&mut self.hotplugged_devices) {
+            let subscriber_id = event_manager.add_subscriber(device);
+            self.mmio_device_manager
+                .subscriber_ids
+                .insert(identifier, subscriber_id);
+        }
+        for subscriber_id in self.unplugged_subscribers.drain(..) {
+            if let Err(err) = event_manager.remove_subscriber(subscriber_id) {
+                error!("Failed to unsubscribe an unplugged device: {:?}", err);
+            }
+        }
+    }
+
     /// Returns a reference to the balloon device if present.
     pub fn balloon_config(&self) -> Result<BalloonConfig, BalloonError> {
         if let Some(busdev) = self.get_bus_device(DeviceType::Virtio(TYPE_BALLOON), BALLOON_DEV_ID)
@@ -955,6 +1343,44 @@ impl MutEventSubscriber for Vmm {
                 FcExitCode::Ok
             };
             self.stop(exit_code);
+        } else if source == self.console_pause_evt.as_raw_fd() && event_set == EventSet::IN {
+            let _ = self.console_pause_evt.read();
+            // The microVM may have been paused through the API in the meantime.
+            if self.instance_info.state == VmState::Running {
+                match self.pause_vm() {
+                    Ok(()) => {
+                        info!("Paused the microVM after a console pattern match.");
+                        EVENTS.publish(VmmEvent::Paused);
+                    }
+                    Err(err) => error!(
+                        "Failed to pause the microVM after a console pattern match: {}",
+                        err
+                    ),
+                }
+            }
+        } else if self
+            .auto_pause
+            .as_ref()
+            .is_some_and(|auto_pause| source == auto_pause.timer_fd().as_raw_fd())
+            && event_set == EventSet::IN
+        {
+            self.process_auto_pause_timer();
+        } else if self
+            .auto_pause
+            .as_ref()
+            .is_some_and(|auto_pause| source == auto_pause.wake_evt().as_raw_fd())
+            && event_set == EventSet::IN
+        {
+            self.process_auto_pause_wake();
+        } else if self
+            .access_tracker
+            .as_ref()
+            .is_some_and(|tracker| source == tracker.timer_fd().as_raw_fd())
+            && event_set == EventSet::IN
+        {
+            if let Some(access_tracker) = self.access_tracker.as_mut() {
+                access_tracker.process_timer();
+            }
         } else {
             error!("Spurious EventManager event for handler: Vmm");
         }
@@ -964,5 +1390,24 @@ impl MutEventSubscriber for Vmm {
         if let Err(err) = ops.add(Events::new(&self.vcpus_exit_evt, EventSet::IN)) {
             error!("Failed to register vmm exit event: {}", err);
         }
+        if let Err(err) = ops.add(Events::new(&self.console_pause_evt, EventSet::IN)) {
+            error!("Failed to register vmm console pause event: {}", err);
+        }
+        if let Some(auto_pause) = self.auto_pause.as_ref() {
+            if let Err(err) = ops.add(Events::new(auto_pause.timer_fd(), EventSet::IN)) {
+                error!("Failed to register vmm auto-pause timer: {}", err);
+            }
+            if let Err(err) = ops.add(Events::new(auto_pause.wake_evt(), EventSet::IN)) {
+                error!("Failed to register vmm auto-pause wake event: {}", err);
+            }
+        }
+        if let Some(access_tracker) = self.access_tracker.as_ref() {
+            if let Err(err) = ops.add(Events::new(access_tracker.timer_fd(), EventSet::IN)) {
+                error!(
+                    "Failed to register vmm memory access tracking timer: {}",
+                    err
+                );
+            }
+        }
     }
 }
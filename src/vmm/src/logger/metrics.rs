@@ -61,12 +61,14 @@
 //! If if turns out this approach is not really what we want, it's pretty easy to resort to
 //! something else, while working behind the same interface.
 
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::io::Write;
 use std::ops::Deref;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
+use serde::ser::{SerializeMap, SerializeStruct};
 use serde::{Serialize, Serializer};
 
 use super::FcLineWriter;
@@ -75,8 +77,8 @@ use crate::devices::virtio::balloon::metrics as balloon_metrics;
 use crate::devices::virtio::block::virtio::metrics as block_metrics;
 use crate::devices::virtio::net::metrics as net_metrics;
 use crate::devices::virtio::rng::metrics as entropy_metrics;
-use crate::devices::virtio::vhost_user_metrics;
 use crate::devices::virtio::vsock::metrics as vsock_metrics;
+use crate::devices::virtio::{queue_metrics, vhost_user_metrics};
 
 /// Static instance used for handling metrics.
 pub static METRICS: Metrics<FirecrackerMetrics, FcLineWriter> =
@@ -89,6 +91,8 @@ pub static METRICS: Metrics<FirecrackerMetrics, FcLineWriter> =
 pub struct Metrics<T: Serialize, M: Write + Send> {
     // Metrics will get flushed here.
     metrics_buf: OnceLock<Mutex<M>>,
+    // Whether the metrics too costly to record by default are recorded.
+    detailed: AtomicBool,
     pub app_metrics: T,
 }
 
@@ -97,10 +101,24 @@ impl<T: Serialize + Debug, M: Write + Send + Debug> Metrics<T, M> {
     pub const fn new(app_metrics: T) -> Metrics<T, M> {
         Metrics {
             metrics_buf: OnceLock::new(),
+            detailed: AtomicBool::new(false),
             app_metrics,
         }
     }
 
+    /// Sets whether the metrics too costly to record by default, such as the latency of each
+    /// block request, are recorded.
+    pub fn set_detailed(&self, detailed: bool) {
+        self.detailed.store(detailed, Ordering::Relaxed);
+    }
+
+    /// Whether the metrics too costly to record by default are recorded. This is cheap enough to
+    /// be checked on the hot path, before recording them.
+    #[inline]
+    pub fn detailed(&self) -> bool {
+        self.detailed.load(Ordering::Relaxed)
+    }
+
     /// Initialize metrics system (once and only once).
     /// Every call made after the first will have no effect besides returning `Ok` or `Err`.
     ///
@@ -358,6 +376,8 @@ pub struct ApiServerMetrics {
     pub sync_response_fails: SharedIncMetric,
     /// Number of timeouts during communication with the VMM.
     pub sync_vmm_send_timeout_count: SharedIncMetric,
+    /// Latencies of the API requests, per endpoint and method.
+    pub endpoints: ApiEndpointsMetrics,
 }
 impl ApiServerMetrics {
     /// Const default construction.
@@ -367,10 +387,53 @@ impl ApiServerMetrics {
             process_startup_time_cpu_us: SharedStoreMetric::new(),
             sync_response_fails: SharedIncMetric::new(),
             sync_vmm_send_timeout_count: SharedIncMetric::new(),
+            endpoints: ApiEndpointsMetrics::new(),
         }
     }
 }
 
+/// Latencies of the requests served on an API endpoint, from the moment the HTTP request is
+/// parsed by the HTTP server to the moment its response is handed back to it.
+#[derive(Debug, Default, Serialize)]
+pub struct ApiEndpointMetrics {
+    /// Distribution of the total latency of the requests.
+    pub total: LatencyHistogramMetrics,
+    /// Distribution of the time spent parsing the requests into VMM actions.
+    pub parse: LatencyHistogramMetrics,
+    /// Distribution of the time spent between sending the VMM actions to the VMM thread and
+    /// receiving their outcome. Requests rejected while being parsed are not counted.
+    pub vmm_action: LatencyHistogramMetrics,
+    /// Distribution of the time spent turning the outcome of the requests into HTTP responses.
+    pub serialize: LatencyHistogramMetrics,
+}
+
+/// Map of the metrics of the API endpoints, keyed by "$method $path_template", e.g.
+/// "PUT /drives/{drive_id}". Endpoints are only reported after serving their first request.
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct ApiEndpointsMetrics {
+    metrics: RwLock<BTreeMap<String, Arc<ApiEndpointMetrics>>>,
+}
+impl ApiEndpointsMetrics {
+    /// Const default construction.
+    pub const fn new() -> Self {
+        Self {
+            metrics: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the metrics of the requests with `method` on the endpoint with `path_template`,
+    /// allocating them if they do not exist yet. The lock is always initialized so it is safe
+    /// to unwrap it without a check.
+    pub fn get(&self, method: &str, path_template: &str) -> Arc<ApiEndpointMetrics> {
+        let key = format!("{} {}", method, path_template);
+        if let Some(metrics) = self.metrics.read().unwrap().get(&key) {
+            return Arc::clone(metrics);
+        }
+        Arc::clone(self.metrics.write().unwrap().entry(key).or_default())
+    }
+}
+
 /// Metrics specific to GET API Requests for counting user triggered actions and/or failures.
 #[derive(Debug, Default, Serialize)]
 pub struct GetRequestsMetrics {
@@ -521,6 +584,27 @@ impl DeprecatedApiMetrics {
     }
 }
 
+/// Metrics related to the errors encountered by the devices while processing their events.
+#[derive(Debug, Default, Serialize)]
+pub struct DeviceErrorMetrics {
+    /// Number of errors the guest recovers from on its own.
+    pub transient: SharedIncMetric,
+    /// Number of errors which leave a device working, but possibly failing requests.
+    pub degraded: SharedIncMetric,
+    /// Number of errors which stopped a device until it is reset by the guest driver.
+    pub fatal: SharedIncMetric,
+}
+impl DeviceErrorMetrics {
+    /// Const default construction.
+    pub const fn new() -> Self {
+        Self {
+            transient: SharedIncMetric::new(),
+            degraded: SharedIncMetric::new(),
+            fatal: SharedIncMetric::new(),
+        }
+    }
+}
+
 /// Metrics for the logging subsystem.
 #[derive(Debug, Default, Serialize)]
 pub struct LoggerSystemMetrics {
@@ -610,6 +694,8 @@ pub struct PerformanceMetrics {
     pub pause_vm: SharedStoreMetric,
     /// Measures the microVM resuming duration, at the API (user) level, in microseconds.
     pub resume_vm: SharedStoreMetric,
+    /// Measures the microVM cloning time, at the API (user) level, in microseconds.
+    pub clone_microvm: SharedStoreMetric,
     /// Measures the snapshot full create time, at the VMM level, in microseconds.
     pub vmm_full_create_snapshot: SharedStoreMetric,
     /// Measures the snapshot diff create time, at the VMM level, in microseconds.
@@ -620,6 +706,8 @@ pub struct PerformanceMetrics {
     pub vmm_pause_vm: SharedStoreMetric,
     /// Measures the microVM resuming duration, at the VMM level, in microseconds.
     pub vmm_resume_vm: SharedStoreMetric,
+    /// Measures the microVM cloning time, at the VMM level, in microseconds.
+    pub vmm_clone_microvm: SharedStoreMetric,
 }
 impl PerformanceMetrics {
     /// Const default construction.
@@ -630,11 +718,13 @@ impl PerformanceMetrics {
             load_snapshot: SharedStoreMetric::new(),
             pause_vm: SharedStoreMetric::new(),
             resume_vm: SharedStoreMetric::new(),
+            clone_microvm: SharedStoreMetric::new(),
             vmm_full_create_snapshot: SharedStoreMetric::new(),
             vmm_diff_create_snapshot: SharedStoreMetric::new(),
             vmm_load_snapshot: SharedStoreMetric::new(),
             vmm_pause_vm: SharedStoreMetric::new(),
             vmm_resume_vm: SharedStoreMetric::new(),
+            vmm_clone_microvm: SharedStoreMetric::new(),
         }
     }
 }
@@ -757,6 +847,247 @@ impl LatencyAggregateMetrics {
     }
 }
 
+/// Used to record the distribution of a value, by counting the values falling in power of 4
+/// buckets. The name of each bucket is its (inclusive) upper bound.
+#[derive(Debug, Default, Serialize)]
+pub struct HistogramMetrics {
+    /// Number of values up to 1.
+    pub le_1: SharedIncMetric,
+    /// Number of values between 2 and 4.
+    pub le_4: SharedIncMetric,
+    /// Number of values between 5 and 16.
+    pub le_16: SharedIncMetric,
+    /// Number of values between 17 and 64.
+    pub le_64: SharedIncMetric,
+    /// Number of values between 65 and 256.
+    pub le_256: SharedIncMetric,
+    /// Number of values between 257 and 1024.
+    pub le_1024: SharedIncMetric,
+    /// Number of values above 1024.
+    pub gt_1024: SharedIncMetric,
+}
+impl HistogramMetrics {
+    /// Const default construction.
+    pub const fn new() -> Self {
+        Self {
+            le_1: SharedIncMetric::new(),
+            le_4: SharedIncMetric::new(),
+            le_16: SharedIncMetric::new(),
+            le_64: SharedIncMetric::new(),
+            le_256: SharedIncMetric::new(),
+            le_1024: SharedIncMetric::new(),
+            gt_1024: SharedIncMetric::new(),
+        }
+    }
+
+    /// Counts `value` in its bucket.
+    pub fn record(&self, value: u64) {
+        match value {
+            0..=1 => self.le_1.inc(),
+            2..=4 => self.le_4.inc(),
+            5..=16 => self.le_16.inc(),
+            17..=64 => self.le_64.inc(),
+            65..=256 => self.le_256.inc(),
+            257..=1024 => self.le_1024.inc(),
+            _ => self.gt_1024.inc(),
+        }
+    }
+
+    /// Adds the values recorded in `other` since the last flush, bucket by bucket.
+    pub fn aggregate(&self, other: &Self) {
+        self.le_1.add(other.le_1.fetch_diff());
+        self.le_4.add(other.le_4.fetch_diff());
+        self.le_16.add(other.le_16.fetch_diff());
+        self.le_64.add(other.le_64.fetch_diff());
+        self.le_256.add(other.le_256.fetch_diff());
+        self.le_1024.add(other.le_1024.fetch_diff());
+        self.gt_1024.add(other.gt_1024.fetch_diff());
+    }
+}
+
+/// Used to record the distribution of latencies, by counting the latencies falling in power of 10
+/// buckets. The name of each bucket is its (inclusive) upper bound.
+#[derive(Debug, Default, Serialize)]
+pub struct LatencyHistogramMetrics {
+    /// Number of latencies up to 100 us.
+    pub le_100us: SharedIncMetric,
+    /// Number of latencies between 100 us and 1 ms.
+    pub le_1ms: SharedIncMetric,
+    /// Number of latencies between 1 ms and 10 ms.
+    pub le_10ms: SharedIncMetric,
+    /// Number of latencies between 10 ms and 100 ms.
+    pub le_100ms: SharedIncMetric,
+    /// Number of latencies between 100 ms and 1 s.
+    pub le_1s: SharedIncMetric,
+    /// Number of latencies above 1 s.
+    pub gt_1s: SharedIncMetric,
+}
+impl LatencyHistogramMetrics {
+    /// Const default construction.
+    pub const fn new() -> Self {
+        Self {
+            le_100us: SharedIncMetric::new(),
+            le_1ms: SharedIncMetric::new(),
+            le_10ms: SharedIncMetric::new(),
+            le_100ms: SharedIncMetric::new(),
+            le_1s: SharedIncMetric::new(),
+            gt_1s: SharedIncMetric::new(),
+        }
+    }
+
+    /// Counts `latency_us`, in microseconds, in its bucket.
+    pub fn record(&self, latency_us: u64) {
+        match latency_us {
+            0..=100 => self.le_100us.inc(),
+            101..=1_000 => self.le_1ms.inc(),
+            1_001..=10_000 => self.le_10ms.inc(),
+            10_001..=100_000 => self.le_100ms.inc(),
+            100_001..=1_000_000 => self.le_1s.inc(),
+            _ => self.gt_1s.inc(),
+        }
+    }
+}
+
+/// Number of power of 2 buckets of `Log2LatencyHistogramMetrics`, the last one being for the
+/// latencies above 2^20 us (about 1 s).
+const LOG2_LATENCY_BUCKETS: usize = 22;
+
+/// Percentiles summarizing `Log2LatencyHistogramMetrics`, in per mille, with their names.
+const LATENCY_PERCENTILES: [(u64, &str); 3] = [(500, "p50_us"), (900, "p90_us"), (990, "p99_us")];
+
+/// Used to record the distribution of latencies finely, by counting the latencies falling in power
+/// of 2 buckets, from 1 us to about 1 s. Along with the buckets, the number of latencies, their
+/// minimum and maximum, and percentiles estimated from the buckets are serialized. Like the other
+/// metrics, they only cover the latencies recorded since the last flush.
+#[derive(Debug)]
+pub struct Log2LatencyHistogramMetrics {
+    // Bucket `i` counts the latencies up to 2^i us, above the ones of bucket `i - 1`.
+    buckets: [SharedIncMetric; LOG2_LATENCY_BUCKETS],
+    // `u64::MAX` while no latency is recorded.
+    min_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl Default for Log2LatencyHistogramMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Log2LatencyHistogramMetrics {
+    /// Const default construction.
+    pub const fn new() -> Self {
+        const BUCKET: SharedIncMetric = SharedIncMetric::new();
+        Self {
+            buckets: [BUCKET; LOG2_LATENCY_BUCKETS],
+            min_us: AtomicU64::new(u64::MAX),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    // Index of the bucket counting `latency_us`.
+    fn bucket(latency_us: u64) -> usize {
+        // Rounds the base 2 logarithm of the latency up.
+        let log2 = u64::BITS - latency_us.saturating_sub(1).leading_zeros();
+        usize::try_from(log2).unwrap().min(LOG2_LATENCY_BUCKETS - 1)
+    }
+
+    // Inclusive upper bound of bucket `i`, in microseconds.
+    fn upper_bound_us(i: usize) -> u64 {
+        1 << i
+    }
+
+    /// Counts `latency_us`, in microseconds, in its bucket.
+    pub fn record(&self, latency_us: u64) {
+        self.buckets[Self::bucket(latency_us)].inc();
+        self.min_us.fetch_min(latency_us, Ordering::Relaxed);
+        self.max_us.fetch_max(latency_us, Ordering::Relaxed);
+    }
+
+    /// Adds the latencies recorded in `other` since the last flush.
+    pub fn aggregate(&self, other: &Self) {
+        for (bucket, other) in self.buckets.iter().zip(other.buckets.iter()) {
+            bucket.add(other.fetch_diff());
+        }
+        self.min_us
+            .fetch_min(other.min_us.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.max_us
+            .fetch_max(other.max_us.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+// The buckets of a `Log2LatencyHistogramMetrics` flush, named after their upper bound.
+struct Log2LatencyBuckets<'a>(&'a [u64; LOG2_LATENCY_BUCKETS]);
+
+impl<'a> Serialize for Log2LatencyBuckets<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(LOG2_LATENCY_BUCKETS))?;
+        let (last, buckets) = self.0.split_last().unwrap();
+        for (i, count) in buckets.iter().enumerate() {
+            map.serialize_entry(
+                &format!("le_{}us", Log2LatencyHistogramMetrics::upper_bound_us(i)),
+                count,
+            )?;
+        }
+        map.serialize_entry(
+            &format!(
+                "gt_{}us",
+                Log2LatencyHistogramMetrics::upper_bound_us(LOG2_LATENCY_BUCKETS - 2)
+            ),
+            last,
+        )?;
+        map.end()
+    }
+}
+
+impl Serialize for Log2LatencyHistogramMetrics {
+    /// Resets the metrics, like `SharedIncMetric`.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let snapshots: [u64; LOG2_LATENCY_BUCKETS] =
+            std::array::from_fn(|i| self.buckets[i].0.load(Ordering::Relaxed));
+        let counts: [u64; LOG2_LATENCY_BUCKETS] =
+            std::array::from_fn(|i| snapshots[i] - self.buckets[i].1.load(Ordering::Relaxed));
+        let count: u64 = counts.iter().sum();
+        let (min_us, max_us) = match count {
+            0 => (0, 0),
+            _ => (
+                self.min_us.load(Ordering::Relaxed),
+                self.max_us.load(Ordering::Relaxed),
+            ),
+        };
+
+        let mut fields = serializer.serialize_struct("Log2LatencyHistogramMetrics", 7)?;
+        fields.serialize_field("count", &count)?;
+        fields.serialize_field("min_us", &min_us)?;
+        fields.serialize_field("max_us", &max_us)?;
+        for (per_mille, name) in LATENCY_PERCENTILES {
+            // The upper bound of the bucket holding the percentile, which can't exceed the
+            // maximum.
+            let rank = (count * per_mille).div_ceil(1000);
+            let mut cumulated = 0;
+            let percentile_us = counts
+                .iter()
+                .position(|bucket_count| {
+                    cumulated += bucket_count;
+                    cumulated >= rank
+                })
+                .map_or(0, |i| Self::upper_bound_us(i).min(max_us));
+            fields.serialize_field(name, &percentile_us)?;
+        }
+        fields.serialize_field("buckets", &Log2LatencyBuckets(&counts))?;
+        let res = fields.end();
+
+        if res.is_ok() {
+            for (bucket, snapshot) in self.buckets.iter().zip(snapshots) {
+                bucket.1.store(snapshot, Ordering::Relaxed);
+            }
+            self.min_us.store(u64::MAX, Ordering::Relaxed);
+            self.max_us.store(0, Ordering::Relaxed);
+        }
+        res
+    }
+}
+
 /// Structure provides Metrics specific to VCPUs' mode of functioning.
 /// Sample_count or number of kvm exits for IO and MMIO VM exits are covered by:
 /// `exit_io_in`, `exit_io_out`, `exit_mmio_read` and , `exit_mmio_write`.
@@ -780,6 +1111,12 @@ pub struct VcpuMetrics {
     pub exit_mmio_write: SharedIncMetric,
     /// Number of errors during this VCPU's run.
     pub failures: SharedIncMetric,
+    /// Number of kick signals received by the vCPU threads.
+    pub kicks: SharedIncMetric,
+    /// Number of times KVM_RUN was interrupted, or not entered, because of a kick.
+    pub immediate_exits: SharedIncMetric,
+    /// Maximum number of consecutive immediate exits of a vCPU with no event to handle.
+    pub immediate_exit_max_streak: SharedStoreMetric,
     /// Provides Min/max/sum for KVM exits handling input IO.
     pub exit_io_in_agg: LatencyAggregateMetrics,
     /// Provides Min/max/sum for KVM exits handling output IO.
@@ -798,6 +1135,9 @@ impl VcpuMetrics {
             exit_mmio_read: SharedIncMetric::new(),
             exit_mmio_write: SharedIncMetric::new(),
             failures: SharedIncMetric::new(),
+            kicks: SharedIncMetric::new(),
+            immediate_exits: SharedIncMetric::new(),
+            immediate_exit_max_streak: SharedStoreMetric::new(),
             exit_io_in_agg: LatencyAggregateMetrics::new(),
             exit_io_out_agg: LatencyAggregateMetrics::new(),
             exit_mmio_read_agg: LatencyAggregateMetrics::new(),
@@ -813,6 +1153,12 @@ pub struct VmmMetrics {
     pub device_events: SharedIncMetric,
     /// Metric for signaling a panic has occurred.
     pub panic_count: SharedStoreMetric,
+    /// Number of times the microVM was paused because it was idle.
+    pub auto_pauses: SharedIncMetric,
+    /// Number of times an auto-paused microVM was resumed by activity.
+    pub auto_resumes: SharedIncMetric,
+    /// Number of failures to automatically pause or resume the microVM.
+    pub auto_pause_fails: SharedIncMetric,
 }
 impl VmmMetrics {
     /// Const default construction.
@@ -820,6 +1166,9 @@ impl VmmMetrics {
         Self {
             device_events: SharedIncMetric::new(),
             panic_count: SharedStoreMetric::new(),
+            auto_pauses: SharedIncMetric::new(),
+            auto_resumes: SharedIncMetric::new(),
+            auto_pause_fails: SharedIncMetric::new(),
         }
     }
 }
@@ -866,6 +1215,7 @@ macro_rules! create_serialize_proxy {
 create_serialize_proxy!(BlockMetricsSerializeProxy, block_metrics);
 create_serialize_proxy!(NetMetricsSerializeProxy, net_metrics);
 create_serialize_proxy!(VhostUserMetricsSerializeProxy, vhost_user_metrics);
+create_serialize_proxy!(QueueMetricsSerializeProxy, queue_metrics);
 create_serialize_proxy!(BalloonMetricsSerializeProxy, balloon_metrics);
 create_serialize_proxy!(EntropyMetricsSerializeProxy, entropy_metrics);
 create_serialize_proxy!(VsockMetricsSerializeProxy, vsock_metrics);
@@ -885,6 +1235,8 @@ pub struct FirecrackerMetrics {
     pub block_ser: BlockMetricsSerializeProxy,
     /// Metrics related to deprecated API calls.
     pub deprecated_api: DeprecatedApiMetrics,
+    /// Metrics related to the errors encountered by the devices.
+    pub device_errors: DeviceErrorMetrics,
     /// Metrics related to API GET requests.
     pub get_api_requests: GetRequestsMetrics,
     #[serde(flatten)]
@@ -920,6 +1272,8 @@ pub struct FirecrackerMetrics {
     #[serde(flatten)]
     /// Vhost-user device related metrics.
     pub vhost_user_ser: VhostUserMetricsSerializeProxy,
+    /// Depth of the virtio device queues.
+    pub virtio_queues: QueueMetricsSerializeProxy,
 }
 impl FirecrackerMetrics {
     /// Const default construction.
@@ -930,6 +1284,7 @@ impl FirecrackerMetrics {
             balloon_ser: BalloonMetricsSerializeProxy {},
             block_ser: BlockMetricsSerializeProxy {},
             deprecated_api: DeprecatedApiMetrics::new(),
+            device_errors: DeviceErrorMetrics::new(),
             get_api_requests: GetRequestsMetrics::new(),
             legacy_dev_ser: LegacyDevMetricsSerializeProxy {},
             latencies_us: PerformanceMetrics::new(),
@@ -945,6 +1300,7 @@ impl FirecrackerMetrics {
             vsock_ser: VsockMetricsSerializeProxy {},
             entropy_ser: EntropyMetricsSerializeProxy {},
             vhost_user_ser: VhostUserMetricsSerializeProxy {},
+            virtio_queues: QueueMetricsSerializeProxy {},
         }
     }
 }
@@ -1028,6 +1384,122 @@ mod tests {
         assert_eq!(1, m1.fetch());
     }
 
+    #[test]
+    fn test_histogram_metrics() {
+        let m = HistogramMetrics::new();
+        for value in [0, 1, 2, 4, 5, 16, 64, 256, 257, 1024, 1025, u64::MAX] {
+            m.record(value);
+        }
+        assert_eq!(m.le_1.count(), 2);
+        assert_eq!(m.le_4.count(), 2);
+        assert_eq!(m.le_16.count(), 2);
+        assert_eq!(m.le_64.count(), 1);
+        assert_eq!(m.le_256.count(), 1);
+        assert_eq!(m.le_1024.count(), 2);
+        assert_eq!(m.gt_1024.count(), 2);
+
+        let total = HistogramMetrics::new();
+        total.aggregate(&m);
+        assert_eq!(total.le_1.count(), 2);
+        assert_eq!(total.gt_1024.count(), 2);
+    }
+
+    #[test]
+    fn test_latency_histogram_metrics() {
+        let m = LatencyHistogramMetrics::new();
+        for latency_us in [0, 100, 101, 1_000, 10_000, 10_001, 1_000_000, 1_000_001] {
+            m.record(latency_us);
+        }
+        assert_eq!(m.le_100us.count(), 2);
+        assert_eq!(m.le_1ms.count(), 2);
+        assert_eq!(m.le_10ms.count(), 1);
+        assert_eq!(m.le_100ms.count(), 1);
+        assert_eq!(m.le_1s.count(), 1);
+        assert_eq!(m.gt_1s.count(), 1);
+    }
+
+    #[test]
+    fn test_log2_latency_histogram_metrics() {
+        let m = Log2LatencyHistogramMetrics::new();
+        // Nothing recorded yet.
+        let serialized = serde_json::to_value(&m).unwrap();
+        assert_eq!(serialized["count"], 0);
+        assert_eq!(serialized["min_us"], 0);
+        assert_eq!(serialized["p50_us"], 0);
+
+        for latency_us in [0, 1, 2, 3, 4, 5, 1_000, 1_024, 1_025, 1_048_576, 1_048_577] {
+            m.record(latency_us);
+        }
+        let serialized = serde_json::to_value(&m).unwrap();
+        let buckets = serialized["buckets"].as_object().unwrap();
+        assert_eq!(buckets.len(), 22);
+        assert_eq!(buckets["le_1us"], 2);
+        assert_eq!(buckets["le_2us"], 1);
+        assert_eq!(buckets["le_4us"], 2);
+        assert_eq!(buckets["le_8us"], 1);
+        assert_eq!(buckets["le_16us"], 0);
+        assert_eq!(buckets["le_1024us"], 2);
+        assert_eq!(buckets["le_2048us"], 1);
+        assert_eq!(buckets["le_1048576us"], 1);
+        assert_eq!(buckets["gt_1048576us"], 1);
+        assert_eq!(serialized["count"], 11);
+        assert_eq!(serialized["min_us"], 0);
+        assert_eq!(serialized["max_us"], 1_048_577);
+        // The 6th latency is in the `le_8us` bucket, the 10th in the `le_1048576us` one, and the
+        // 11th in the last one, bounded by the maximum.
+        assert_eq!(serialized["p50_us"], 8);
+        assert_eq!(serialized["p90_us"], 1_048_576);
+        assert_eq!(serialized["p99_us"], 1_048_577);
+
+        // The metrics are reset once flushed.
+        m.record(100);
+        let serialized = serde_json::to_value(&m).unwrap();
+        assert_eq!(serialized["count"], 1);
+        assert_eq!(serialized["min_us"], 100);
+        assert_eq!(serialized["max_us"], 100);
+        assert_eq!(serialized["p50_us"], 100);
+        assert_eq!(serialized["buckets"]["le_128us"], 1);
+        assert_eq!(serialized["buckets"]["le_1us"], 0);
+
+        let other = Log2LatencyHistogramMetrics::new();
+        m.record(10);
+        other.record(3_000);
+        let total = Log2LatencyHistogramMetrics::new();
+        total.aggregate(&m);
+        total.aggregate(&other);
+        let serialized = serde_json::to_value(&total).unwrap();
+        assert_eq!(serialized["count"], 2);
+        assert_eq!(serialized["min_us"], 10);
+        assert_eq!(serialized["max_us"], 3_000);
+        assert_eq!(serialized["buckets"]["le_16us"], 1);
+        assert_eq!(serialized["buckets"]["le_4096us"], 1);
+    }
+
+    #[test]
+    fn test_api_endpoints_metrics() {
+        let endpoints = ApiEndpointsMetrics::new();
+        endpoints
+            .get("PUT", "/drives/{drive_id}")
+            .total
+            .record(2_000);
+        // The metrics of an endpoint are allocated once.
+        endpoints
+            .get("PUT", "/drives/{drive_id}")
+            .vmm_action
+            .record(1_500);
+        endpoints.get("PATCH", "/drives/{drive_id}");
+
+        let serialized = serde_json::to_value(endpoints).unwrap();
+        let endpoints = serialized.as_object().unwrap();
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints["PUT /drives/{drive_id}"]["total"]["le_10ms"], 1);
+        assert_eq!(
+            endpoints["PUT /drives/{drive_id}"]["vmm_action"]["le_10ms"],
+            1
+        );
+        assert_eq!(endpoints["PATCH /drives/{drive_id}"]["total"]["le_10ms"], 0);
+    }
+
     #[test]
     fn test_serialize() {
         let s = serde_json::to_string(&FirecrackerMetrics::default());
@@ -15,8 +15,9 @@ pub use logging::{
     DEFAULT_INSTANCE_ID, DEFAULT_LEVEL, INSTANCE_ID, LOGGER,
 };
 pub use metrics::{
-    IncMetric, LatencyAggregateMetrics, MetricsError, ProcessTimeReporter, SharedIncMetric,
-    SharedStoreMetric, StoreMetric, METRICS,
+    ApiEndpointMetrics, HistogramMetrics, IncMetric, LatencyAggregateMetrics,
+    LatencyHistogramMetrics, Log2LatencyHistogramMetrics, MetricsError, ProcessTimeReporter,
+    SharedIncMetric, SharedStoreMetric, StoreMetric, METRICS,
 };
 
 /// Alias for `std::io::LineWriter<std::fs::File>`.
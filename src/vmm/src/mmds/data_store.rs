@@ -1,11 +1,13 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::fmt;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::{fmt, io};
 
 use serde::{Deserialize, Serialize};
-use serde_json::{to_vec, Value};
+use serde_json::{Map, Value};
 
 use crate::mmds::token::{MmdsTokenError as TokenError, TokenAuthority};
 
@@ -17,6 +19,38 @@ pub struct Mmds {
     token_authority: Option<TokenAuthority>,
     is_initialized: bool,
     data_store_limit: usize,
+    // Serialized size of `data_store`, kept up to date on every PUT/PATCH.
+    data_store_size: usize,
+    // Top level key of `data_store` the guest is allowed to write to, if any.
+    guest_writable_key: Option<String>,
+    // Bumped on every PUT/PATCH of the data store.
+    data_store_version: u64,
+    // JSON pointers of the subtrees changed by the PUT/PATCH requests, with the version of the
+    // data store which last changed them.
+    changed_paths: BTreeMap<String, u64>,
+    // Shared with the guest watches parked by the MMDS network stacks, to bound their number.
+    watch_slots: Arc<()>,
+}
+
+/// Maximum number of guest watches which can be parked at the same time.
+pub const MAX_PARKED_WATCHES: usize = 4;
+// Maximum number of changed subtrees remembered by the data store. Past it, the whole data store
+// is considered changed.
+const MAX_CHANGED_PATHS: usize = 1024;
+
+/// Slot taken by a guest watch parked until the data store changes, released when dropped.
+#[derive(Debug)]
+pub struct WatchSlot {
+    _slot: Arc<()>,
+}
+
+/// Host-side view of the MMDS data store usage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MmdsDataStoreInfo {
+    /// Serialized size of the data store contents, in bytes.
+    pub used_bytes: usize,
+    /// Maximum size the data store contents are allowed to reach, in bytes.
+    pub limit_bytes: usize,
 }
 
 /// MMDS version.
@@ -50,8 +84,12 @@ pub enum OutputFormat {
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 /// MMDS data store errors
 pub enum MmdsDatastoreError {
-    /// The MMDS patch request doesn't fit.
-    DataStoreLimitExceeded,
+    /// The MMDS data store would grow to {0} bytes, over its {1} bytes limit.
+    DataStoreLimitExceeded(usize, usize),
+    /// The MMDS data store limit ({0} bytes) cannot be lower than its current size ({1} bytes).
+    DataStoreLimitBelowUsage(usize, usize),
+    /// The MMDS data store size accounting is inconsistent with its contents.
+    DataStoreSizeInconsistent,
     /// The MMDS resource does not exist.
     NotFound,
     /// The MMDS data store is not initialized.
@@ -77,6 +115,11 @@ impl Mmds {
             token_authority: None,
             is_initialized: false,
             data_store_limit,
+            data_store_size: 0,
+            guest_writable_key: None,
+            data_store_version: 0,
+            changed_paths: BTreeMap::new(),
+            watch_slots: Arc::new(()),
         }
     }
 
@@ -140,20 +183,46 @@ impl Mmds {
             .and_then(|ta| ta.generate_token_secret(ttl_seconds))
     }
 
-    /// set MMDS data store limit to `data_store_limit`
-    pub fn set_data_store_limit(&mut self, data_store_limit: usize) {
+    /// Set MMDS data store limit to `data_store_limit`. The limit cannot be lowered below the
+    /// current size of the data store.
+    pub fn set_data_store_limit(
+        &mut self,
+        data_store_limit: usize,
+    ) -> Result<(), MmdsDatastoreError> {
+        if data_store_limit < self.data_store_size {
+            return Err(MmdsDatastoreError::DataStoreLimitBelowUsage(
+                data_store_limit,
+                self.data_store_size,
+            ));
+        }
         self.data_store_limit = data_store_limit;
+        Ok(())
+    }
+
+    /// Returns the current usage and limit of the MMDS data store.
+    pub fn data_store_info(&self) -> MmdsDataStoreInfo {
+        MmdsDataStoreInfo {
+            used_bytes: self.data_store_size,
+            limit_bytes: self.data_store_limit,
+        }
     }
 
     /// put `data` in MMDS data store
     pub fn put_data(&mut self, data: Value) -> Result<(), MmdsDatastoreError> {
-        // It is safe to unwrap because any map keys are all strings and
-        // we are using default serializer which does not return error.
-        if to_vec(&data).unwrap().len() > self.data_store_limit {
-            Err(MmdsDatastoreError::DataStoreLimitExceeded)
+        let size = serialized_len(&data);
+        if size > self.data_store_limit {
+            Err(MmdsDatastoreError::DataStoreLimitExceeded(
+                size,
+                self.data_store_limit,
+            ))
         } else {
             self.data_store = data;
+            self.data_store_size = size;
             self.is_initialized = true;
+            self.data_store_version += 1;
+            self.changed_paths.clear();
+            self.changed_paths
+                .insert(String::new(), self.data_store_version);
 
             Ok(())
         }
@@ -162,18 +231,107 @@ impl Mmds {
     /// patch update MMDS data store with `patch_data`
     pub fn patch_data(&mut self, patch_data: Value) -> Result<(), MmdsDatastoreError> {
         self.check_data_store_initialized()?;
-        let mut data_store_clone = self.data_store.clone();
 
-        super::json_patch(&mut data_store_clone, &patch_data);
-        // It is safe to unwrap because our data store keys are all strings and
-        // we are using default serializer which does not return error.
-        if to_vec(&data_store_clone).unwrap().len() > self.data_store_limit {
-            return Err(MmdsDatastoreError::DataStoreLimitExceeded);
+        // Only the subtrees touched by the patch are measured, so the check does not
+        // depend on the size of the whole data store.
+        let delta = json_patch_size_delta(&self.data_store, &patch_data);
+        // The data store size is kept consistent with its contents, so the resulting size
+        // cannot be negative unless the accounting is broken.
+        let size = self
+            .data_store_size
+            .checked_add_signed(delta)
+            .ok_or(MmdsDatastoreError::DataStoreSizeInconsistent)?;
+        if size > self.data_store_limit {
+            return Err(MmdsDatastoreError::DataStoreLimitExceeded(
+                size,
+                self.data_store_limit,
+            ));
+        }
+        let mut changed_paths = Vec::new();
+        json_patch_changed_paths(
+            &self.data_store,
+            &patch_data,
+            &mut String::new(),
+            &mut changed_paths,
+        );
+        super::json_patch(&mut self.data_store, &patch_data);
+        self.data_store_size = size;
+
+        self.data_store_version += 1;
+        for path in changed_paths {
+            self.changed_paths.insert(path, self.data_store_version);
+        }
+        if self.changed_paths.len() > MAX_CHANGED_PATHS {
+            self.changed_paths.clear();
+            self.changed_paths
+                .insert(String::new(), self.data_store_version);
         }
-        self.data_store = data_store_clone;
         Ok(())
     }
 
+    /// Returns the version of the data store, bumped on every PUT/PATCH.
+    pub fn data_store_version(&self) -> u64 {
+        self.data_store_version
+    }
+
+    /// Checks whether the subtree at the JSON pointer `path` changed after the data store
+    /// `version`.
+    pub fn changed_since(&self, path: &str, version: u64) -> bool {
+        let path = path.trim_end_matches('/');
+        self.changed_paths.iter().any(|(changed, changed_version)| {
+            *changed_version > version
+                && (is_subtree_of(path, changed) || is_subtree_of(changed, path))
+        })
+    }
+
+    /// Returns the subtree at the JSON pointer `path`, if any.
+    pub fn subtree(&self, path: &str) -> Option<&Value> {
+        self.data_store.pointer(path.trim_end_matches('/'))
+    }
+
+    /// Takes a slot for a guest watch to be parked until the data store changes, unless
+    /// `MAX_PARKED_WATCHES` watches are already parked.
+    pub fn take_watch_slot(&self) -> Option<WatchSlot> {
+        // The data store holds one of the references.
+        (Arc::strong_count(&self.watch_slots) <= MAX_PARKED_WATCHES).then(|| WatchSlot {
+            _slot: self.watch_slots.clone(),
+        })
+    }
+
+    /// Sets the top level key of the data store which the guest is allowed to write to with
+    /// PATCH requests. No key is writable by the guest by default.
+    pub fn set_guest_writable_key(&mut self, key: Option<String>) {
+        self.guest_writable_key = key;
+    }
+
+    /// Returns the top level key of the data store which the guest is allowed to write to.
+    pub fn guest_writable_key(&self) -> Option<&str> {
+        self.guest_writable_key.as_deref()
+    }
+
+    /// Stores `value`, written by the guest, under the guest writable key. Like any other
+    /// update, it counts towards the data store limit.
+    pub fn write_guest_value(&mut self, value: Value) -> Result<(), MmdsDatastoreError> {
+        let key = self
+            .guest_writable_key
+            .clone()
+            .ok_or(MmdsDatastoreError::NotFound)?;
+        let patch = Value::Object(Map::from_iter([(key, value)]));
+        if self.is_initialized {
+            self.patch_data(patch)
+        } else {
+            self.put_data(patch)
+        }
+    }
+
+    /// Returns the value written by the guest under the guest writable key, if any.
+    pub fn guest_value(&self) -> Option<&Value> {
+        self.guest_writable_key
+            .as_ref()
+            .and_then(|key| self.data_store.get(key))
+            .filter(|value| !value.is_null())
+    }
+
     /// return MMDS data store value
     /// We do not check size of data_store before returning a result because due
     /// to limit from put/patch the data_store can not be bigger than the limit
@@ -276,6 +434,119 @@ impl Mmds {
     }
 }
 
+/// `io::Write` sink which only counts the bytes written to it.
+#[derive(Debug, Default)]
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the length of the compact JSON serialization of `value`.
+fn serialized_len<T: Serialize + ?Sized>(value: &T) -> usize {
+    let mut counter = ByteCounter::default();
+    // It is safe to unwrap because any map keys are all strings, we are using the default
+    // serializer and the `ByteCounter` writer never fails.
+    serde_json::to_writer(&mut counter, value).unwrap();
+    counter.0
+}
+
+/// Same as `serialized_len`, but as a signed value to ease computing size differences.
+fn signed_serialized_len<T: Serialize + ?Sized>(value: &T) -> isize {
+    // A serialized value cannot be larger than the address space.
+    isize::try_from(serialized_len(value)).unwrap()
+}
+
+/// Number of `,` separators in a serialized JSON object with `entries` entries.
+fn separators(entries: usize) -> isize {
+    isize::try_from(entries.saturating_sub(1)).unwrap()
+}
+
+// Checks whether the JSON pointer `path` is `ancestor` or one of its descendants.
+fn is_subtree_of(path: &str, ancestor: &str) -> bool {
+    path.strip_prefix(ancestor)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Collects in `changed` the JSON pointers of the subtrees of `target` changed by applying
+/// `patch` with `json_patch`, `path` being the JSON pointer of `target`.
+fn json_patch_changed_paths(
+    target: &Value,
+    patch: &Value,
+    path: &mut String,
+    changed: &mut Vec<String>,
+) {
+    let (Some(patch_map), Some(target_map)) = (patch.as_object(), target.as_object()) else {
+        changed.push(path.clone());
+        return;
+    };
+
+    for (key, value) in patch_map {
+        let old_value = target_map.get(key);
+        if old_value == Some(value) || (old_value.is_none() && value.is_null()) {
+            continue;
+        }
+
+        let len = path.len();
+        path.push('/');
+        path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+        match old_value {
+            Some(old_value) if value.is_object() => {
+                json_patch_changed_paths(old_value, value, path, changed)
+            }
+            _ => changed.push(path.clone()),
+        }
+        path.truncate(len);
+    }
+}
+
+/// Computes the difference between the serialized size of `target` after and before applying
+/// `patch` with `json_patch`, without modifying `target`. Only the parts of `target` which are
+/// removed or replaced by the patch get measured.
+fn json_patch_size_delta(target: &Value, patch: &Value) -> isize {
+    let Some(patch_map) = patch.as_object() else {
+        return signed_serialized_len(patch) - signed_serialized_len(target);
+    };
+    let Some(target_map) = target.as_object() else {
+        // `json_patch` replaces a non-object target with an empty object before patching it.
+        let empty = Value::Object(Map::new());
+        return signed_serialized_len(&empty) - signed_serialized_len(target)
+            + json_patch_size_delta(&empty, patch);
+    };
+
+    let mut delta = 0;
+    let mut entries = target_map.len();
+    for (key, value) in patch_map {
+        match (target_map.get(key), value.is_null()) {
+            (Some(old_value), true) => {
+                // Removed entry: `"key":value`.
+                delta -= signed_serialized_len(key) + 1 + signed_serialized_len(old_value);
+                entries -= 1;
+            }
+            (Some(old_value), false) => delta += json_patch_size_delta(old_value, value),
+            // Removing a missing key is a no-op.
+            (None, true) => {}
+            (None, false) => {
+                // New entry: `json_patch` inserts a null placeholder and then patches it.
+                delta += signed_serialized_len(key)
+                    + 1
+                    + signed_serialized_len(&Value::Null)
+                    + json_patch_size_delta(&Value::Null, value);
+                entries += 1;
+            }
+        }
+    }
+
+    delta + separators(entries) - separators(target_map.len())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -548,9 +819,10 @@ mod tests {
         let data_store: Value = serde_json::from_str(data).unwrap();
         assert_eq!(
             mmds.patch_data(data_store).unwrap_err().to_string(),
-            MmdsDatastoreError::DataStoreLimitExceeded.to_string()
+            MmdsDatastoreError::DataStoreLimitExceeded(51218, 51200).to_string()
         );
         assert!(!mmds.get_data_str().contains("smth"));
+        assert_eq!(mmds.data_store_info().used_bytes, mmds.get_data_str().len());
 
         let data = "{\"new_key\" : \"smth\"}";
         let data_store: Value = serde_json::from_str(data).unwrap();
@@ -563,6 +835,7 @@ mod tests {
         mmds.patch_data(data_store).unwrap();
         assert!(mmds.get_data_str().contains("smth2"));
         assert_eq!(mmds.get_data_str().len(), 72);
+        assert_eq!(mmds.data_store_info().used_bytes, 72);
     }
 
     #[test]
@@ -575,10 +848,198 @@ mod tests {
 
         assert_eq!(
             mmds.put_data(data_store).unwrap_err().to_string(),
-            MmdsDatastoreError::DataStoreLimitExceeded.to_string()
+            "The MMDS data store would grow to 51310 bytes, over its 51200 bytes limit."
         );
 
         assert_eq!(mmds.get_data_str().len(), 2);
+        assert_eq!(mmds.data_store_info().used_bytes, 0);
+    }
+
+    #[test]
+    fn test_data_store_limit() {
+        let mut mmds = Mmds::default();
+        assert_eq!(
+            mmds.data_store_info(),
+            MmdsDataStoreInfo {
+                used_bytes: 0,
+                limit_bytes: 51200
+            }
+        );
+
+        let data = r#"{"key": "value"}"#;
+        mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
+        assert_eq!(mmds.data_store_info().used_bytes, 15);
+
+        // Shrinking the limit below the current usage is rejected.
+        assert_eq!(
+            mmds.set_data_store_limit(14).unwrap_err().to_string(),
+            MmdsDatastoreError::DataStoreLimitBelowUsage(14, 15).to_string()
+        );
+        assert_eq!(mmds.data_store_info().limit_bytes, 51200);
+
+        // Shrinking down to the current usage is fine.
+        mmds.set_data_store_limit(15).unwrap();
+        assert_eq!(
+            mmds.data_store_info(),
+            MmdsDataStoreInfo {
+                used_bytes: 15,
+                limit_bytes: 15
+            }
+        );
+        let data = r#"{"key2": "v"}"#;
+        assert_eq!(
+            mmds.patch_data(serde_json::from_str(data).unwrap())
+                .unwrap_err()
+                .to_string(),
+            MmdsDatastoreError::DataStoreLimitExceeded(26, 15).to_string()
+        );
+
+        // Growing the limit lets the same patch through.
+        mmds.set_data_store_limit(1 << 20).unwrap();
+        mmds.patch_data(serde_json::from_str(data).unwrap())
+            .unwrap();
+        assert_eq!(mmds.data_store_info().used_bytes, 26);
+    }
+
+    #[test]
+    fn test_guest_writable_key() {
+        let mut mmds = Mmds::default_with_limit(32);
+        // No key is writable by the guest by default.
+        assert!(matches!(
+            mmds.write_guest_value(Value::from("up")),
+            Err(MmdsDatastoreError::NotFound)
+        ));
+        assert!(mmds.guest_value().is_none());
+
+        mmds.set_guest_writable_key(Some("ready".to_string()));
+        assert_eq!(mmds.guest_writable_key(), Some("ready"));
+        // The guest can write to an uninitialized data store.
+        mmds.write_guest_value(Value::from("up")).unwrap();
+        assert_eq!(mmds.guest_value(), Some(&Value::from("up")));
+        assert_eq!(mmds.get_data_str(), r#"{"ready":"up"}"#);
+
+        // The other keys are left untouched.
+        mmds.patch_data(serde_json::from_str(r#"{"a": 1}"#).unwrap())
+            .unwrap();
+        mmds.write_guest_value(Value::from(true)).unwrap();
+        assert_eq!(mmds.get_data_str(), r#"{"a":1,"ready":true}"#);
+        assert_eq!(mmds.data_store_info().used_bytes, mmds.get_data_str().len());
+
+        // Guest writes count towards the data store limit.
+        assert!(matches!(
+            mmds.write_guest_value(Value::from("a".repeat(32))),
+            Err(MmdsDatastoreError::DataStoreLimitExceeded(..))
+        ));
+        assert_eq!(mmds.guest_value(), Some(&Value::from(true)));
+
+        // Writing null removes the value.
+        mmds.write_guest_value(Value::Null).unwrap();
+        assert!(mmds.guest_value().is_none());
+    }
+
+    #[test]
+    fn test_incremental_size_accounting() {
+        let mut mmds = Mmds::default_with_limit(1 << 20);
+        let large_subtree = (0..100)
+            .map(|i| format!("\"key{i}\": {{\"nested\": [{i}, \"value\", null, true]}}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let data = format!(r#"{{"a": {{{large_subtree}}}, "b": "text", "c": [1, 2]}}"#);
+        mmds.put_data(serde_json::from_str(&data).unwrap()).unwrap();
+        assert_eq!(mmds.data_store_info().used_bytes, mmds.get_data_str().len());
+
+        let patches = [
+            // Replace a large subtree with a scalar.
+            r#"{"a": "small"}"#.to_string(),
+            // Replace a scalar with a large subtree.
+            format!(r#"{{"b": {{{large_subtree}}}}}"#),
+            // Replace an object with an object, removing and adding keys.
+            r#"{"b": {"key0": null, "key1": {"nested": "x"}, "new": {"k": null}}}"#.to_string(),
+            // Remove a key which does not exist.
+            r#"{"missing": null}"#.to_string(),
+            // Replace an array with an object containing null values.
+            r#"{"c": {"x": null, "y": {"z": 1}}}"#.to_string(),
+            // Remove all but one key.
+            r#"{"a": null, "c": null}"#.to_string(),
+            // Remove the last key.
+            r#"{"b": null}"#.to_string(),
+            // Escaped characters in keys and values.
+            r#"{"\"quoted\"\n": "tab\t\u0001"}"#.to_string(),
+        ];
+        for patch in patches {
+            mmds.patch_data(serde_json::from_str(&patch).unwrap())
+                .unwrap();
+            assert_eq!(mmds.data_store_info().used_bytes, mmds.get_data_str().len());
+        }
+
+        // A patch replacing the whole data store with a scalar.
+        mmds.patch_data(Value::from(42)).unwrap();
+        assert_eq!(mmds.data_store_info().used_bytes, 2);
+
+        // Broken accounting is reported instead of underflowing, and leaves the data store as is.
+        mmds.put_data(serde_json::from_str(r#"{"key": "value"}"#).unwrap())
+            .unwrap();
+        mmds.data_store_size = 0;
+        assert_eq!(
+            mmds.patch_data(serde_json::from_str(r#"{"key": null}"#).unwrap())
+                .unwrap_err()
+                .to_string(),
+            MmdsDatastoreError::DataStoreSizeInconsistent.to_string()
+        );
+        assert_eq!(mmds.get_data_str(), r#"{"key":"value"}"#);
+    }
+
+    #[test]
+    fn test_data_store_changes() {
+        let mut mmds = Mmds::default();
+        assert_eq!(mmds.data_store_version(), 0);
+        assert!(!mmds.changed_since("/", 0));
+
+        mmds.put_data(serde_json::json!({"a": {"b": "1", "c": "2"}, "d/e": "3"}))
+            .unwrap();
+        assert_eq!(mmds.data_store_version(), 1);
+        assert!(mmds.changed_since("", 0));
+        assert!(mmds.changed_since("/a/b", 0));
+        assert!(!mmds.changed_since("/a/b", 1));
+
+        // Only the subtrees touched by a patch change.
+        mmds.patch_data(serde_json::json!({"a": {"b": "4", "c": "2"}}))
+            .unwrap();
+        assert_eq!(mmds.data_store_version(), 2);
+        assert!(mmds.changed_since("/", 1));
+        assert!(mmds.changed_since("/a", 1));
+        assert!(mmds.changed_since("/a/b/", 1));
+        assert!(!mmds.changed_since("/a/c", 1));
+        assert!(!mmds.changed_since("/ab", 1));
+        assert!(!mmds.changed_since("/a/b", 2));
+
+        // Removed and escaped keys.
+        mmds.patch_data(serde_json::json!({"d/e": null, "x": null}))
+            .unwrap();
+        assert!(mmds.changed_since("/d~1e", 2));
+        assert!(!mmds.changed_since("/x", 2));
+        assert!(!mmds.changed_since("/a", 2));
+        assert_eq!(mmds.subtree("/a/b/"), Some(&Value::from("4")));
+        assert_eq!(mmds.subtree("/d~1e"), None);
+
+        // Past the changes the data store remembers, everything changed.
+        let patch = (0..=MAX_CHANGED_PATHS)
+            .map(|i| (i.to_string(), Value::from(i)))
+            .collect::<Map<_, _>>();
+        mmds.patch_data(Value::Object(patch)).unwrap();
+        assert!(mmds.changed_since("/a/c", 3));
+        assert_eq!(mmds.changed_paths.len(), 1);
+    }
+
+    #[test]
+    fn test_watch_slots() {
+        let mmds = Mmds::default();
+        let slots = (0..MAX_PARKED_WATCHES)
+            .map(|_| mmds.take_watch_slot().unwrap())
+            .collect::<Vec<_>>();
+        assert!(mmds.take_watch_slot().is_none());
+        drop(slots);
+        assert!(mmds.take_watch_slot().is_some());
     }
 
     #[test]
@@ -18,8 +18,12 @@ use micro_http::{
 };
 use serde_json::{Map, Value};
 use token_headers::TokenHeaders;
+use utils::time::{get_time_ms, ClockType};
 
-use crate::mmds::data_store::{Mmds, MmdsDatastoreError as MmdsError, MmdsVersion, OutputFormat};
+use crate::dumbo::tcp::{ParkedResponse, Reply};
+use crate::mmds::data_store::{
+    Mmds, MmdsDatastoreError as MmdsError, MmdsVersion, OutputFormat, WatchSlot,
+};
 use crate::mmds::token::PATH_TO_TOKEN;
 use crate::mmds::token_headers::REJECTED_HEADER;
 
@@ -31,6 +35,8 @@ pub enum VmmMmdsError {
     InvalidToken,
     /// Invalid URI.
     InvalidURI,
+    /// The request body must be a JSON value.
+    InvalidBody,
     /// Not allowed HTTP method.
     MethodNotAllowed,
     /// No MMDS token provided. Use `X-metadata-token` header to specify the session token.
@@ -39,8 +45,19 @@ pub enum VmmMmdsError {
     NoTtlProvided,
     /// Resource not found: {0}.
     ResourceNotFound(String),
+    /// Invalid watch query parameter: {0}.
+    InvalidWatchQuery(String),
+    /// Too many watches are waiting for the MMDS data store to change.
+    TooManyWatches,
 }
 
+/// Path of the long-poll endpoint notifying the guest of the changes of the data store.
+const WATCH_PATH: &str = "/latest/watch";
+/// Time after which a watch completes if the data store does not change, in seconds.
+const DEFAULT_WATCH_TIMEOUT_S: u64 = 30;
+/// Maximum timeout of a watch, in seconds.
+const MAX_WATCH_TIMEOUT_S: u64 = 300;
+
 impl From<MediaType> for OutputFormat {
     fn from(media_type: MediaType) -> Self {
         match media_type {
@@ -112,15 +129,169 @@ pub fn convert_to_response(mmds: Arc<Mutex<Mmds>>, request: Request) -> Response
     let mut mmds_guard = mmds.lock().expect("Poisoned lock");
 
     match mmds_guard.version() {
-        MmdsVersion::V1 => respond_to_request_mmdsv1(&mmds_guard, request),
+        MmdsVersion::V1 => respond_to_request_mmdsv1(&mut mmds_guard, request),
         MmdsVersion::V2 => respond_to_request_mmdsv2(&mut mmds_guard, request),
     }
 }
 
-fn respond_to_request_mmdsv1(mmds: &Mmds, request: Request) -> Response {
-    // Allow only GET requests.
+/// Build the reply to `request`. Unlike the other requests, the watch requests get parked until
+/// the watched subtree of the data store changes, or the watch times out.
+pub fn convert_to_reply(mmds: Arc<Mutex<Mmds>>, request: Request) -> Reply {
+    let uri = request.uri().get_abs_path();
+    let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+    if request.method() != Method::Get
+        || sanitize_uri(path.to_string()).trim_end_matches('/') != WATCH_PATH
+    {
+        return convert_to_response(mmds, request).into();
+    }
+
+    let mmds_guard = mmds.lock().expect("Poisoned lock");
+    if mmds_guard.version() == MmdsVersion::V2 {
+        let token_headers = match TokenHeaders::try_from(request.headers.custom_entries()) {
+            Ok(token_headers) => token_headers,
+            Err(err) => {
+                return build_response(
+                    request.http_version(),
+                    StatusCode::BadRequest,
+                    Body::new(err.to_string()),
+                )
+                .into()
+            }
+        };
+        if let Err(response) = check_token(&mmds_guard, &request, &token_headers) {
+            return response.into();
+        }
+    }
+
+    let watch = match WatchQuery::parse(query) {
+        Ok(watch) => watch,
+        Err(err) => {
+            return build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                Body::new(err.to_string()),
+            )
+            .into()
+        }
+    };
+
+    // Changes which happened since the version known by the guest are returned right away.
+    if mmds_guard.changed_since(&watch.path, watch.since) {
+        return watch_response(request.http_version(), &mmds_guard, &watch.path).into();
+    }
+
+    match mmds_guard.take_watch_slot() {
+        Some(slot) => Reply::Parked(Box::new(ParkedWatch {
+            mmds: mmds.clone(),
+            http_version: request.http_version(),
+            deadline_ms: get_time_ms(ClockType::Monotonic) + watch.timeout_s * 1000,
+            watch,
+            _slot: slot,
+        })),
+        None => build_response(
+            request.http_version(),
+            StatusCode::ServiceUnavailable,
+            Body::new(VmmMmdsError::TooManyWatches.to_string()),
+        )
+        .into(),
+    }
+}
+
+// Parameters of a watch request, given in its query string.
+#[derive(Debug, PartialEq, Eq)]
+struct WatchQuery {
+    // JSON pointer of the watched subtree.
+    path: String,
+    // Time after which the watch completes with no change, in seconds.
+    timeout_s: u64,
+    // Version of the data store last seen by the guest.
+    since: u64,
+}
+
+impl WatchQuery {
+    fn parse(query: &str) -> Result<Self, VmmMmdsError> {
+        let mut watch = WatchQuery {
+            path: String::from("/"),
+            timeout_s: DEFAULT_WATCH_TIMEOUT_S,
+            since: 0,
+        };
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let invalid = || VmmMmdsError::InvalidWatchQuery(param.to_string());
+            let (key, value) = param.split_once('=').ok_or_else(invalid)?;
+            match key {
+                "path" if value.starts_with('/') => watch.path = sanitize_uri(value.to_string()),
+                "timeout_s" => {
+                    watch.timeout_s = value
+                        .parse()
+                        .ok()
+                        .filter(|timeout_s| (1..=MAX_WATCH_TIMEOUT_S).contains(timeout_s))
+                        .ok_or_else(invalid)?
+                }
+                "since" => watch.since = value.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(watch)
+    }
+}
+
+// Watch request waiting for its subtree of the data store to change.
+#[derive(Debug)]
+struct ParkedWatch {
+    mmds: Arc<Mutex<Mmds>>,
+    http_version: Version,
+    watch: WatchQuery,
+    // Monotonic time at which the watch times out, in milliseconds.
+    deadline_ms: u64,
+    // Keeps the watch counted towards the parked watches of the data store.
+    _slot: WatchSlot,
+}
+
+impl ParkedResponse for ParkedWatch {
+    fn poll(&mut self) -> Option<Vec<u8>> {
+        let mmds = self.mmds.lock().expect("Poisoned lock");
+        if mmds.changed_since(&self.watch.path, self.watch.since) {
+            let mut buf = Vec::new();
+            // The unwrap is safe because a Vec will allocate more space until all the writes
+            // succeed.
+            watch_response(self.http_version, &mmds, &self.watch.path)
+                .write_all(&mut buf)
+                .unwrap();
+            Some(buf)
+        } else if get_time_ms(ClockType::Monotonic) >= self.deadline_ms {
+            Some(not_modified_response(self.http_version))
+        } else {
+            None
+        }
+    }
+}
+
+// Builds the response to a watch, with the current version of the data store and the watched
+// subtree, which is `null` if it does not exist.
+fn watch_response(http_version: Version, mmds: &Mmds, path: &str) -> Response {
+    let body = serde_json::json!({
+        "version": mmds.data_store_version(),
+        "value": mmds.subtree(path),
+    });
+    build_response(http_version, StatusCode::OK, Body::new(body.to_string()))
+}
+
+// `micro_http` has no status code for `304 Not Modified`, so that response is written by hand.
+fn not_modified_response(http_version: Version) -> Vec<u8> {
+    let http_version = match http_version {
+        Version::Http10 => "HTTP/1.0",
+        Version::Http11 => "HTTP/1.1",
+    };
+    format!("{http_version} 304 Not Modified\r\nServer: Firecracker API\r\n\r\n").into_bytes()
+}
+
+fn respond_to_request_mmdsv1(mmds: &mut Mmds, request: Request) -> Response {
+    // Allow only GET requests, and PATCH requests when a key is writable by the guest.
     match request.method() {
         Method::Get => respond_to_get_request_unchecked(mmds, request),
+        Method::Patch if mmds.guest_writable_key().is_some() => {
+            respond_to_patch_request(mmds, request)
+        }
         _ => {
             let mut response = build_response(
                 request.http_version(),
@@ -128,6 +299,9 @@ fn respond_to_request_mmdsv1(mmds: &Mmds, request: Request) -> Response {
                 Body::new(VmmMmdsError::MethodNotAllowed.to_string()),
             );
             response.allow_method(Method::Get);
+            if mmds.guest_writable_key().is_some() {
+                response.allow_method(Method::Patch);
+            }
             response
         }
     }
@@ -146,10 +320,19 @@ fn respond_to_request_mmdsv2(mmds: &mut Mmds, request: Request) -> Response {
         }
     };
 
-    // Allow only GET and PUT requests.
+    // Allow only GET and PUT requests, and PATCH requests when a key is writable by the guest.
     match request.method() {
-        Method::Get => respond_to_get_request_checked(mmds, request, token_headers),
+        Method::Get => match check_token(mmds, &request, &token_headers) {
+            Ok(()) => respond_to_get_request_unchecked(mmds, request),
+            Err(response) => response,
+        },
         Method::Put => respond_to_put_request(mmds, request, token_headers),
+        Method::Patch if mmds.guest_writable_key().is_some() => {
+            match check_token(mmds, &request, &token_headers) {
+                Ok(()) => respond_to_patch_request(mmds, request),
+                Err(response) => response,
+            }
+        }
         _ => {
             let mut response = build_response(
                 request.http_version(),
@@ -158,41 +341,66 @@ fn respond_to_request_mmdsv2(mmds: &mut Mmds, request: Request) -> Response {
             );
             response.allow_method(Method::Get);
             response.allow_method(Method::Put);
+            if mmds.guest_writable_key().is_some() {
+                response.allow_method(Method::Patch);
+            }
             response
         }
     }
 }
 
-fn respond_to_get_request_checked(
+// Checks the session token of a request, returning the response rejecting it if it is not valid.
+fn check_token(
     mmds: &Mmds,
-    request: Request,
-    token_headers: TokenHeaders,
-) -> Response {
+    request: &Request,
+    token_headers: &TokenHeaders,
+) -> Result<(), Response> {
     // Get MMDS token from custom headers.
     let token = match token_headers.x_metadata_token() {
         Some(token) => token,
         None => {
             let error_msg = VmmMmdsError::NoTokenProvided.to_string();
-            return build_response(
+            return Err(build_response(
                 request.http_version(),
                 StatusCode::Unauthorized,
                 Body::new(error_msg),
-            );
+            ));
         }
     };
 
     // Validate MMDS token.
     match mmds.is_valid_token(token) {
-        Ok(true) => respond_to_get_request_unchecked(mmds, request),
-        Ok(false) => build_response(
+        Ok(true) => Ok(()),
+        Ok(false) => Err(build_response(
             request.http_version(),
             StatusCode::Unauthorized,
             Body::new(VmmMmdsError::InvalidToken.to_string()),
-        ),
+        )),
         Err(_) => unreachable!(),
     }
 }
 
+// Rejects the requests which are not sent by the guest itself, i.e. which contain an
+// `X-Forwarded-For` header.
+fn check_not_forwarded(request: &Request) -> Result<(), Response> {
+    if request
+        .headers
+        .custom_entries()
+        .contains_key(REJECTED_HEADER)
+    {
+        let error_msg = RequestError::HeaderError(HttpHeaderError::UnsupportedName(
+            REJECTED_HEADER.to_string(),
+        ))
+        .to_string();
+        return Err(build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new(error_msg),
+        ));
+    }
+    Ok(())
+}
+
 fn respond_to_get_request_unchecked(mmds: &Mmds, request: Request) -> Response {
     let uri = request.uri().get_abs_path();
 
@@ -220,7 +428,7 @@ fn respond_to_get_request_unchecked(mmds: &Mmds, request: Request) -> Response {
                 StatusCode::NotImplemented,
                 Body::new(err.to_string()),
             ),
-            MmdsError::DataStoreLimitExceeded => build_response(
+            MmdsError::DataStoreLimitExceeded(..) => build_response(
                 request.http_version(),
                 StatusCode::PayloadTooLarge,
                 Body::new(err.to_string()),
@@ -236,20 +444,8 @@ fn respond_to_put_request(
     token_headers: TokenHeaders,
 ) -> Response {
     // Reject `PUT` requests that contain `X-Forwarded-For` header.
-    if request
-        .headers
-        .custom_entries()
-        .contains_key(REJECTED_HEADER)
-    {
-        let error_msg = RequestError::HeaderError(HttpHeaderError::UnsupportedName(
-            REJECTED_HEADER.to_string(),
-        ))
-        .to_string();
-        return build_response(
-            request.http_version(),
-            StatusCode::BadRequest,
-            Body::new(error_msg),
-        );
+    if let Err(response) = check_not_forwarded(&request) {
+        return response;
     }
 
     let uri = request.uri().get_abs_path();
@@ -295,6 +491,55 @@ fn respond_to_put_request(
     }
 }
 
+// Stores the JSON value in the body of a guest `PATCH` request under the guest writable key,
+// which is the only path the guest is allowed to write to.
+fn respond_to_patch_request(mmds: &mut Mmds, request: Request) -> Response {
+    // Reject `PATCH` requests that contain `X-Forwarded-For` header.
+    if let Err(response) = check_not_forwarded(&request) {
+        return response;
+    }
+
+    let uri = request.uri().get_abs_path();
+    let json_path = sanitize_uri(uri.to_string());
+    if json_path.trim_end_matches('/').strip_prefix('/') != mmds.guest_writable_key() {
+        let error_msg = VmmMmdsError::ResourceNotFound(String::from(uri)).to_string();
+        return build_response(
+            request.http_version(),
+            StatusCode::NotFound,
+            Body::new(error_msg),
+        );
+    }
+
+    let value = match request
+        .body
+        .as_ref()
+        .map(|body| serde_json::from_slice::<Value>(body.raw()))
+    {
+        Some(Ok(value)) => value,
+        _ => {
+            return build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                Body::new(VmmMmdsError::InvalidBody.to_string()),
+            )
+        }
+    };
+
+    match mmds.write_guest_value(value) {
+        Ok(()) => Response::new(request.http_version(), StatusCode::NoContent),
+        Err(err @ MmdsError::DataStoreLimitExceeded(..)) => build_response(
+            request.http_version(),
+            StatusCode::PayloadTooLarge,
+            Body::new(err.to_string()),
+        ),
+        Err(err) => build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new(err.to_string()),
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -623,6 +868,141 @@ mod tests {
         }
     }
 
+    fn patch_request(path: &str, body: &str, headers: &str) -> Request {
+        let request_bytes = format!(
+            "PATCH http://169.254.169.254{} HTTP/1.0\r\n{}Content-Length: {}\r\n\r\n{}",
+            path,
+            headers,
+            body.len(),
+            body
+        );
+        Request::try_from(request_bytes.as_bytes(), None).unwrap()
+    }
+
+    #[test]
+    fn test_guest_patch_request_mmdsv1() {
+        let mmds = populate_mmds();
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_guest_writable_key(Some("ready".to_string()));
+
+        // Only the guest writable key can be written to.
+        for path in ["/", "/age", "/ready/nested", "/read"] {
+            let request = patch_request(path, "true", "");
+            let mut expected_response = Response::new(Version::Http10, StatusCode::NotFound);
+            expected_response.set_body(Body::new(
+                VmmMmdsError::ResourceNotFound(String::from(path)).to_string(),
+            ));
+            let actual_response = convert_to_response(mmds.clone(), request);
+            assert_eq!(actual_response, expected_response);
+        }
+
+        // The body must be a JSON value.
+        for body in ["", "{\"status\""] {
+            let request = patch_request("/ready", body, "");
+            let mut expected_response = Response::new(Version::Http10, StatusCode::BadRequest);
+            expected_response.set_body(Body::new(VmmMmdsError::InvalidBody.to_string()));
+            let actual_response = convert_to_response(mmds.clone(), request);
+            assert_eq!(actual_response, expected_response);
+        }
+
+        // Forwarded requests are rejected.
+        let request = patch_request("/ready", "true", "X-Forwarded-For: 203.0.113.195\r\n");
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response.status(), StatusCode::BadRequest);
+        assert!(mmds.lock().expect("Poisoned lock").guest_value().is_none());
+
+        // Test Ok path.
+        let request = patch_request("//ready/", r#"{"status": "up"}"#, "");
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(
+            actual_response,
+            Response::new(Version::Http10, StatusCode::NoContent)
+        );
+        let request_bytes = b"GET http://169.254.169.254/ready/status HTTP/1.0\r\n\
+                                    Accept: application/json\r\n\r\n";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let mut expected_response = Response::new(Version::Http10, StatusCode::OK);
+        expected_response.set_body(Body::new("\"up\""));
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response, expected_response);
+
+        // Guest writes count towards the data store limit.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_data_store_limit(200)
+            .unwrap();
+        let body = format!("\"{}\"", "a".repeat(200));
+        let request = patch_request("/ready", &body, "");
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response.status(), StatusCode::PayloadTooLarge);
+
+        // The other methods are still not allowed.
+        let request_bytes = b"PUT http://169.254.169.254/ready HTTP/1.0\r\n\r\n";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let mut expected_response = Response::new(Version::Http10, StatusCode::MethodNotAllowed);
+        expected_response.set_body(Body::new(VmmMmdsError::MethodNotAllowed.to_string()));
+        expected_response.allow_method(Method::Get);
+        expected_response.allow_method(Method::Patch);
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response, expected_response);
+    }
+
+    #[test]
+    fn test_guest_patch_request_mmdsv2() {
+        let mmds = populate_mmds();
+        {
+            let mut mmds = mmds.lock().expect("Poisoned lock");
+            mmds.set_version(MmdsVersion::V2).unwrap();
+            mmds.set_guest_writable_key(Some("ready".to_string()));
+        }
+
+        // A valid token is required.
+        let request = patch_request("/ready", "true", "");
+        let mut expected_response = Response::new(Version::Http10, StatusCode::Unauthorized);
+        expected_response.set_body(Body::new(VmmMmdsError::NoTokenProvided.to_string()));
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response, expected_response);
+
+        let request = patch_request("/ready", "true", "X-metadata-token: foo\r\n");
+        let mut expected_response = Response::new(Version::Http10, StatusCode::Unauthorized);
+        expected_response.set_body(Body::new(VmmMmdsError::InvalidToken.to_string()));
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response, expected_response);
+        assert!(mmds.lock().expect("Poisoned lock").guest_value().is_none());
+
+        // Test Ok path.
+        let request_bytes = b"PUT http://169.254.169.254/latest/api/token HTTP/1.0\r\n\
+                                    X-metadata-token-ttl-seconds: 60\r\n\r\n";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let actual_response = convert_to_response(mmds.clone(), request);
+        let token = String::from_utf8(actual_response.body().unwrap().body).unwrap();
+        let headers = format!("X-metadata-token: {}\r\n", token);
+        let request = patch_request("/ready", "true", &headers);
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(
+            actual_response,
+            Response::new(Version::Http10, StatusCode::NoContent)
+        );
+        assert_eq!(
+            mmds.lock().expect("Poisoned lock").guest_value(),
+            Some(&Value::Bool(true))
+        );
+
+        // Only the guest writable key can be written to.
+        let request = patch_request("/name", "true", &headers);
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response.status(), StatusCode::NotFound);
+
+        // Without a guest writable key, PATCH requests are not allowed.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_guest_writable_key(None);
+        let request = patch_request("/ready", "true", &headers);
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response.status(), StatusCode::MethodNotAllowed);
+    }
+
     #[test]
     fn test_json_patch() {
         let mut data = serde_json::json!({
@@ -686,6 +1066,11 @@ mod tests {
 
         assert_eq!(VmmMmdsError::InvalidURI.to_string(), "Invalid URI.");
 
+        assert_eq!(
+            VmmMmdsError::InvalidBody.to_string(),
+            "The request body must be a JSON value."
+        );
+
         assert_eq!(
             VmmMmdsError::MethodNotAllowed.to_string(),
             "Not allowed HTTP method."
@@ -705,6 +1090,169 @@ mod tests {
         assert_eq!(
             VmmMmdsError::ResourceNotFound(String::from("invalid/")).to_string(),
             "Resource not found: invalid/."
-        )
+        );
+
+        assert_eq!(
+            VmmMmdsError::InvalidWatchQuery(String::from("since=x")).to_string(),
+            "Invalid watch query parameter: since=x."
+        );
+
+        assert_eq!(
+            VmmMmdsError::TooManyWatches.to_string(),
+            "Too many watches are waiting for the MMDS data store to change."
+        );
+    }
+
+    fn watch_request(query: &str, headers: &str) -> Request {
+        let request_bytes = format!("GET /latest/watch{query} HTTP/1.1\r\n{headers}\r\n");
+        Request::try_from(request_bytes.as_bytes(), None).unwrap()
+    }
+
+    fn reply_response(reply: Reply) -> Response {
+        match reply {
+            Reply::Ready(response) => response,
+            Reply::Parked(_) => panic!("unexpected parked watch"),
+        }
+    }
+
+    #[test]
+    fn test_watch_query() {
+        assert_eq!(
+            WatchQuery::parse("").unwrap(),
+            WatchQuery {
+                path: String::from("/"),
+                timeout_s: DEFAULT_WATCH_TIMEOUT_S,
+                since: 0,
+            }
+        );
+        assert_eq!(
+            WatchQuery::parse("path=//name//first&timeout_s=300&since=7&").unwrap(),
+            WatchQuery {
+                path: String::from("/name/first"),
+                timeout_s: 300,
+                since: 7,
+            }
+        );
+
+        for query in [
+            "path",
+            "path=name",
+            "timeout_s=0",
+            "timeout_s=301",
+            "since=-1",
+            "recursive=true",
+        ] {
+            assert_eq!(
+                WatchQuery::parse(query).unwrap_err().to_string(),
+                VmmMmdsError::InvalidWatchQuery(query.to_string()).to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_watch_request() {
+        let mmds = populate_mmds();
+        assert_eq!(mmds.lock().expect("Poisoned lock").data_store_version(), 1);
+
+        // Invalid query.
+        let request = watch_request("?since=x", "");
+        let response = reply_response(convert_to_reply(mmds.clone(), request));
+        assert_eq!(response.status(), StatusCode::BadRequest);
+
+        // The changes after the version known by the guest are returned right away.
+        let request = watch_request("?path=/name&since=0", "");
+        let mut expected_response = Response::new(Version::Http11, StatusCode::OK);
+        expected_response.set_body(Body::new(
+            r#"{"value":{"first":"John","second":"Doe"},"version":1}"#,
+        ));
+        let response = reply_response(convert_to_reply(mmds.clone(), request));
+        assert_eq!(response, expected_response);
+
+        // Other requests are not parked.
+        let request = Request::try_from(b"GET /name/first HTTP/1.1\r\n\r\n", None).unwrap();
+        let response = reply_response(convert_to_reply(mmds.clone(), request));
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Otherwise the watches are parked, up to a limit.
+        let mut watches = (0..MAX_PARKED_WATCHES)
+            .map(
+                |_| match convert_to_reply(mmds.clone(), watch_request("?since=1", "")) {
+                    Reply::Parked(watch) => watch,
+                    Reply::Ready(response) => panic!("unexpected response {response:?}"),
+                },
+            )
+            .collect::<Vec<_>>();
+        let request = watch_request("/?path=/age&since=1", "");
+        let response = reply_response(convert_to_reply(mmds.clone(), request));
+        assert_eq!(response.status(), StatusCode::ServiceUnavailable);
+        assert!(watches[0].poll().is_none());
+
+        // The parked watches complete once the data store changes.
+        mmds.lock()
+            .unwrap()
+            .patch_data(serde_json::json!({"age": 44}))
+            .unwrap();
+        let response = String::from_utf8(watches[0].poll().unwrap()).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(r#""version":2"#));
+
+        // A slot is available again once a watch is dropped.
+        watches.pop();
+        let request = watch_request("?path=/name&since=1", "");
+        assert!(matches!(
+            convert_to_reply(mmds.clone(), request),
+            Reply::Parked(_)
+        ));
+    }
+
+    #[test]
+    fn test_watch_timeout() {
+        let mmds = populate_mmds();
+        let slot = mmds
+            .lock()
+            .expect("Poisoned lock")
+            .take_watch_slot()
+            .unwrap();
+        let mut watch = ParkedWatch {
+            mmds: mmds.clone(),
+            http_version: Version::Http10,
+            watch: WatchQuery {
+                path: String::from("/"),
+                timeout_s: DEFAULT_WATCH_TIMEOUT_S,
+                since: 1,
+            },
+            deadline_ms: get_time_ms(ClockType::Monotonic) + 60_000,
+            _slot: slot,
+        };
+        assert!(watch.poll().is_none());
+
+        watch.deadline_ms = 0;
+        assert_eq!(
+            watch.poll().unwrap(),
+            b"HTTP/1.0 304 Not Modified\r\nServer: Firecracker API\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_watch_request_mmdsv2() {
+        let mmds = populate_mmds();
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_version(MmdsVersion::V2)
+            .unwrap();
+
+        // A valid token is needed.
+        let request = watch_request("?since=0", "");
+        let response = reply_response(convert_to_reply(mmds.clone(), request));
+        assert_eq!(response.status(), StatusCode::Unauthorized);
+
+        let token = mmds
+            .lock()
+            .expect("Poisoned lock")
+            .generate_token(60)
+            .unwrap();
+        let request = watch_request("?since=0", &format!("X-metadata-token: {token}\r\n"));
+        let response = reply_response(convert_to_reply(mmds.clone(), request));
+        assert_eq!(response.status(), StatusCode::OK);
     }
 }
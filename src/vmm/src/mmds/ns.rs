@@ -189,7 +189,7 @@ impl MmdsNetworkStack {
                 self.remote_mac_addr = eth.src_mac();
                 let mmds_instance = self.mmds.clone();
                 match &mut self.tcp_handler.receive_packet(&ip, move |request| {
-                    super::convert_to_response(mmds_instance, request)
+                    super::convert_to_reply(mmds_instance, request)
                 }) {
                     Ok(event) => {
                         METRICS.mmds.rx_count.inc();
@@ -238,6 +238,7 @@ impl MmdsNetworkStack {
                 }
             };
         } else {
+            self.tcp_handler.poll_parked_responses();
             let call_write = match self.tcp_handler.next_segment_status() {
                 NextSegmentStatus::Available => true,
                 NextSegmentStatus::Timeout(value) => timestamp_cycles() >= value,
@@ -260,6 +261,12 @@ impl MmdsNetworkStack {
         None
     }
 
+    /// Returns whether some guest requests wait for their parked response, which is completed
+    /// by `write_next_frame`.
+    pub fn has_parked_responses(&self) -> bool {
+        self.tcp_handler.has_parked_responses()
+    }
+
     fn prepare_eth_unsized<'a>(
         &self,
         buf: &'a mut [u8],
@@ -364,6 +371,18 @@ mod tests {
             buf: &mut [u8],
             addr: Ipv4Addr,
             flags: TcpFlags,
+        ) -> usize {
+            self.write_incoming_tcp_data(buf, addr, SEQ_NUMBER, 1234, flags, &[])
+        }
+
+        fn write_incoming_tcp_data(
+            &self,
+            buf: &mut [u8],
+            addr: Ipv4Addr,
+            seq_number: u32,
+            ack_number: u32,
+            flags: TcpFlags,
+            payload: &[u8],
         ) -> usize {
             let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV4).unwrap();
             let packet_len = {
@@ -377,13 +396,13 @@ mod tests {
 
                 let segment_len = TcpSegment::write_incomplete_segment::<[u8]>(
                     packet.inner_mut().payload_mut(),
-                    SEQ_NUMBER,
-                    1234,
+                    seq_number,
+                    ack_number,
                     flags,
                     10000,
                     None,
-                    0,
-                    None,
+                    1000,
+                    (!payload.is_empty()).then_some((payload, payload.len())),
                 )
                 .unwrap()
                 .finalize(REMOTE_PORT, MMDS_PORT, Some((REMOTE_ADDR, addr)))
@@ -538,6 +557,79 @@ mod tests {
         assert!(ns.write_next_frame(buf.as_mut()).is_none());
     }
 
+    #[test]
+    fn test_ns_watch() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        mmds.lock()
+            .expect("Poisoned lock")
+            .put_data(serde_json::json!({"config": {"a": "1"}, "other": "x"}))
+            .unwrap();
+        let mut ns = MmdsNetworkStack::new_with_defaults(None, mmds.clone());
+        let mut buf = [0u8; 2000];
+        let mmds_addr = ns.ipv4_addr;
+
+        // Open a connection.
+        let len = ns.write_incoming_tcp_segment(buf.as_mut(), mmds_addr, TcpFlags::SYN);
+        assert!(ns.detour_frame(&buf[..len]));
+        let mmds_isn = {
+            let ip = ns.next_frame_as_ipv4_packet(buf.as_mut());
+            TcpSegment::from_bytes(ip.payload(), None)
+                .unwrap()
+                .sequence_number()
+        };
+        let len = ns.write_incoming_tcp_data(
+            buf.as_mut(),
+            mmds_addr,
+            SEQ_NUMBER.wrapping_add(1),
+            mmds_isn.wrapping_add(1),
+            TcpFlags::ACK,
+            &[],
+        );
+        assert!(ns.detour_frame(&buf[..len]));
+
+        // Watch the changes of the "config" subtree after the current version.
+        let request = b"GET /latest/watch?path=/config&since=1 HTTP/1.1\r\n\r\n";
+        let len = ns.write_incoming_tcp_data(
+            buf.as_mut(),
+            mmds_addr,
+            SEQ_NUMBER.wrapping_add(1),
+            mmds_isn.wrapping_add(1),
+            TcpFlags::ACK,
+            request,
+        );
+        assert!(ns.detour_frame(&buf[..len]));
+        assert!(ns.has_parked_responses());
+
+        // The request is only ACKed while the watch is parked.
+        {
+            let ip = ns.next_frame_as_ipv4_packet(buf.as_mut());
+            let s = TcpSegment::from_bytes(ip.payload(), None).unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::ACK);
+            assert_eq!(s.payload_len(), 0);
+        }
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Changes outside the watched subtree don't complete the watch.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .patch_data(serde_json::json!({"other": "y"}))
+            .unwrap();
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        assert!(ns.has_parked_responses());
+
+        // A host PATCH of the watched subtree completes the watch.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .patch_data(serde_json::json!({"config": {"a": "2"}}))
+            .unwrap();
+        let ip = ns.next_frame_as_ipv4_packet(buf.as_mut());
+        let s = TcpSegment::from_bytes(ip.payload(), None).unwrap();
+        let response = std::str::from_utf8(s.payload()).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with(r#"{"value":{"a":"2"},"version":3}"#));
+        assert!(!ns.has_parked_responses());
+    }
+
     #[test]
     fn test_set_ipv4_addr() {
         let mut ns =
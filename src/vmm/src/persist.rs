@@ -5,9 +5,9 @@
 
 use std::fmt::Debug;
 use std::fs::{File, OpenOptions};
-use std::io::{self, Write};
-use std::os::unix::io::AsRawFd;
-use std::os::unix::net::UnixStream;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
@@ -26,17 +26,24 @@ use crate::cpu_config::templates::StaticCpuTemplate;
 use crate::cpu_config::x86_64::cpuid::common::get_vendor_id_from_host;
 #[cfg(target_arch = "x86_64")]
 use crate::cpu_config::x86_64::cpuid::CpuidTrait;
+use crate::device_manager::mmio::MMIODeviceInfo;
 #[cfg(target_arch = "x86_64")]
 use crate::device_manager::persist::ACPIDeviceManagerState;
 use crate::device_manager::persist::{DevicePersistError, DeviceStates};
+use crate::devices::virtio::block::persist::BlockState;
 use crate::logger::{info, warn};
 use crate::resources::VmResources;
 use crate::snapshot::Snapshot;
+use crate::vmm_config::auto_pause::AutoPauseConfig;
 use crate::vmm_config::boot_source::BootSourceConfig;
 use crate::vmm_config::instance_info::InstanceInfo;
-use crate::vmm_config::machine_config::{HugePageConfig, MachineConfigUpdate, VmConfigError};
+use crate::vmm_config::machine_config::{
+    GicConfig, HugePageConfig, MachineConfigUpdate, SmbiosConfig, VmConfigError,
+};
+use crate::vmm_config::serial::SerialPortConfig;
 use crate::vmm_config::snapshot::{
-    CreateSnapshotParams, LoadSnapshotParams, MemBackendType, SnapshotType,
+    CloneMicrovmParams, CloneNetworkOverride, CreateSnapshotParams, LoadNetworkOverride,
+    LoadSnapshotParams, MemBackendType, SnapshotType, TrackAccessConfig,
 };
 use crate::vstate::memory::{
     GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryState, MemoryError,
@@ -58,6 +65,14 @@ pub struct VmInfo {
     pub boot_source: BootSourceConfig,
     /// Huge page configuration
     pub huge_pages: HugePageConfig,
+    /// SMBIOS configuration, with the UUID the microVM was booted with
+    pub smbios: Option<SmbiosConfig>,
+    /// Configuration of the aarch64 interrupt controller
+    pub gic: Option<GicConfig>,
+    /// Serial ports configuration
+    pub serial_ports: Option<Vec<SerialPortConfig>>,
+    /// Auto-pause configuration
+    pub auto_pause: Option<AutoPauseConfig>,
 }
 
 impl From<&VmResources> for VmInfo {
@@ -68,6 +83,10 @@ impl From<&VmResources> for VmInfo {
             cpu_template: StaticCpuTemplate::from(&value.vm_config.cpu_template),
             boot_source: value.boot_source_config().clone(),
             huge_pages: value.vm_config.huge_pages,
+            smbios: value.vm_config.smbios.clone(),
+            gic: value.vm_config.gic,
+            serial_ports: value.vm_config.serial_ports.clone(),
+            auto_pause: value.vm_config.auto_pause.clone(),
         }
     }
 }
@@ -90,6 +109,226 @@ pub struct MicrovmState {
     pub acpi_dev_state: ACPIDeviceManagerState,
 }
 
+/// A virtio device saved in a microVM state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedDevice {
+    /// Type of the device.
+    pub device_type: &'static str,
+    /// Device identifier.
+    pub device_id: String,
+    /// Address of the MMIO slot of the device.
+    pub mmio_addr: u64,
+    /// Interrupt lines of the device.
+    pub irqs: Vec<u32>,
+    /// Path of the backing file of block devices.
+    pub disk_path: Option<String>,
+    /// Whether the device is the root block device.
+    pub root_device: bool,
+}
+
+impl SavedDevice {
+    fn new(device_type: &'static str, device_id: &str, device_info: &MMIODeviceInfo) -> Self {
+        Self {
+            device_type,
+            device_id: device_id.to_string(),
+            mmio_addr: device_info.addr,
+            irqs: device_info.irqs.clone(),
+            disk_path: None,
+            root_device: false,
+        }
+    }
+}
+
+/// Errors associated with editing a saved microVM state.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum EditMicrovmStateError {
+    /// No device has id {0}.
+    DeviceNotFound(String),
+    /// Several devices have id {0}.
+    AmbiguousDeviceId(String),
+    /// No drive has id {0}.
+    DriveNotFound(String),
+    /// Drive {0} is a vhost-user drive, which has no backing file.
+    VhostUserDrive(String),
+    /// The path of the backing file of drive {0} cannot be empty.
+    EmptyDrivePath(String),
+    /// No network interface has id {0}.
+    NetNotFound(String),
+    /// The tap device of network interface {0} cannot be empty.
+    EmptyTapName(String),
+    /// Devices {0} and {1} use the same MMIO slot at {2:#x}.
+    MmioSlotConflict(String, String, u64),
+}
+
+impl MicrovmState {
+    /// Returns the virtio devices in the state, in the order they are restored.
+    pub fn devices(&self) -> Vec<SavedDevice> {
+        let states = &self.device_states;
+        let mut devices = Vec::new();
+        if let Some(balloon) = &states.balloon_device {
+            devices.push(SavedDevice::new(
+                "balloon",
+                &balloon.device_id,
+                &balloon.device_info,
+            ));
+        }
+        devices.extend(states.block_devices.iter().map(|block| SavedDevice {
+            disk_path: block.device_state.disk_path().map(str::to_string),
+            root_device: block.device_state.is_root_device(),
+            ..SavedDevice::new("block", &block.device_id, &block.device_info)
+        }));
+        devices.extend(
+            states
+                .net_devices
+                .iter()
+                .map(|net| SavedDevice::new("net", &net.device_id, &net.device_info)),
+        );
+        if let Some(vsock) = &states.vsock_device {
+            devices.push(SavedDevice::new(
+                "vsock",
+                &vsock.device_id,
+                &vsock.device_info,
+            ));
+        }
+        if let Some(entropy) = &states.entropy_device {
+            devices.push(SavedDevice::new(
+                "entropy",
+                &entropy.device_id,
+                &entropy.device_info,
+            ));
+        }
+        devices
+    }
+
+    /// Removes the virtio device with id `device_id`, releasing its MMIO slot. The other
+    /// devices keep their slots, since the guest knows them by address.
+    /// If no remaining network device serves MMDS, the MMDS version is dropped too so that
+    /// no data store is created on restore.
+    pub fn remove_device(&mut self, device_id: &str) -> Result<SavedDevice, EditMicrovmStateError> {
+        let removed = self.find_device(device_id)?;
+        let states = &mut self.device_states;
+        match removed.device_type {
+            "balloon" => states.balloon_device = None,
+            "block" => states
+                .block_devices
+                .retain(|block| block.device_id != device_id),
+            "net" => {
+                states.net_devices.retain(|net| net.device_id != device_id);
+                if !states
+                    .net_devices
+                    .iter()
+                    .any(|net| net.device_state.mmds_ns.is_some())
+                {
+                    states.mmds_version = None;
+                }
+            }
+            "vsock" => states.vsock_device = None,
+            "entropy" => states.entropy_device = None,
+            _ => unreachable!(),
+        }
+        Ok(removed)
+    }
+
+    /// Replaces the path of the backing file of drive `drive_id`. Returns the previous path.
+    pub fn set_drive_path(
+        &mut self,
+        drive_id: &str,
+        path: String,
+    ) -> Result<String, EditMicrovmStateError> {
+        if path.is_empty() {
+            return Err(EditMicrovmStateError::EmptyDrivePath(drive_id.to_string()));
+        }
+        let block = self
+            .device_states
+            .block_devices
+            .iter_mut()
+            .find(|block| block.device_id == drive_id)
+            .ok_or_else(|| EditMicrovmStateError::DriveNotFound(drive_id.to_string()))?;
+        match &mut block.device_state {
+            BlockState::Virtio(state) => Ok(std::mem::replace(&mut state.disk_path, path)),
+            BlockState::VhostUser(_) => {
+                Err(EditMicrovmStateError::VhostUserDrive(drive_id.to_string()))
+            }
+        }
+    }
+
+    /// Replaces the name of the tap device of network interface `iface_id`. Returns the previous
+    /// name.
+    pub fn set_net_tap_name(
+        &mut self,
+        iface_id: &str,
+        tap_name: String,
+    ) -> Result<String, EditMicrovmStateError> {
+        if tap_name.is_empty() {
+            return Err(EditMicrovmStateError::EmptyTapName(iface_id.to_string()));
+        }
+        let net = self
+            .device_states
+            .net_devices
+            .iter_mut()
+            .find(|net| net.device_id == iface_id)
+            .ok_or_else(|| EditMicrovmStateError::NetNotFound(iface_id.to_string()))?;
+        Ok(std::mem::replace(
+            &mut net.device_state.tap_if_name,
+            tap_name,
+        ))
+    }
+
+    /// Sets the fd of an already open tap to restore network interface `iface_id` with, instead
+    /// of opening its tap device by name.
+    pub fn set_net_tap_fd(
+        &mut self,
+        iface_id: &str,
+        tap_fd: RawFd,
+    ) -> Result<(), EditMicrovmStateError> {
+        let net = self
+            .device_states
+            .net_devices
+            .iter_mut()
+            .find(|net| net.device_id == iface_id)
+            .ok_or_else(|| EditMicrovmStateError::NetNotFound(iface_id.to_string()))?;
+        net.device_state.tap_fd = Some(tap_fd);
+        Ok(())
+    }
+
+    /// Checks that the device ids are unique and that no two devices use the same MMIO slot,
+    /// which would prevent restoring the state.
+    pub fn check_devices(&self) -> Result<(), EditMicrovmStateError> {
+        let devices = self.devices();
+        for (index, device) in devices.iter().enumerate() {
+            for other in &devices[index + 1..] {
+                if device.device_id == other.device_id {
+                    return Err(EditMicrovmStateError::AmbiguousDeviceId(
+                        device.device_id.clone(),
+                    ));
+                }
+                if device.mmio_addr == other.mmio_addr {
+                    return Err(EditMicrovmStateError::MmioSlotConflict(
+                        device.device_id.clone(),
+                        other.device_id.clone(),
+                        device.mmio_addr,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn find_device(&self, device_id: &str) -> Result<SavedDevice, EditMicrovmStateError> {
+        let mut matching = self
+            .devices()
+            .into_iter()
+            .filter(|device| device.device_id == device_id);
+        match (matching.next(), matching.next()) {
+            (Some(device), None) => Ok(device),
+            (Some(_), Some(_)) => Err(EditMicrovmStateError::AmbiguousDeviceId(
+                device_id.to_string(),
+            )),
+            (None, _) => Err(EditMicrovmStateError::DeviceNotFound(device_id.to_string())),
+        }
+    }
+}
+
 /// This describes the mapping between Firecracker base virtual address and
 /// offset in the buffer or file backend for a guest memory region. It is used
 /// to tell an external process/thread where to populate the guest memory data
@@ -160,7 +399,7 @@ pub enum CreateSnapshotError {
 }
 
 /// Snapshot version
-pub const SNAPSHOT_VERSION: Version = Version::new(2, 0, 0);
+pub const SNAPSHOT_VERSION: Version = Version::new(3, 0, 0);
 
 /// Creates a Microvm snapshot.
 pub fn create_snapshot(
@@ -381,15 +620,32 @@ pub enum RestoreFromSnapshotError {
     GuestMemory(#[from] RestoreFromSnapshotGuestMemoryError),
     /// Failed to build microVM from snapshot: {0}
     Build(#[from] BuildMicrovmFromSnapshotError),
+    /// Invalid network override: {0}
+    NetworkOverride(#[from] EditMicrovmStateError),
 }
-/// Sub-Error type for [`restore_from_snapshot`] to contain either [`GuestMemoryFromFileError`] or
-/// [`GuestMemoryFromUffdError`] within [`RestoreFromSnapshotError`].
+/// Sub-Error type for [`restore_from_snapshot`] to contain either [`GuestMemoryFromFileError`],
+/// [`GuestMemoryFromUffdError`] or the error of mapping a memfd within
+/// [`RestoreFromSnapshotError`].
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum RestoreFromSnapshotGuestMemoryError {
     /// Error creating guest memory from file: {0}
     File(#[from] GuestMemoryFromFileError),
     /// Error creating guest memory from uffd: {0}
     Uffd(#[from] GuestMemoryFromUffdError),
+    /// Error creating guest memory from memfd: {0}
+    Memfd(MemoryError),
+}
+
+/// Backend populating the guest memory of a restored microVM.
+#[derive(Debug)]
+enum GuestMemoryBackend<'a> {
+    /// File containing the guest memory, mapped privately.
+    File(&'a Path),
+    /// UDS of the process serving the guest memory page faults through UFFD.
+    Uffd(&'a Path),
+    /// Sealed memfd containing the guest memory, received over SCM_RIGHTS from the microVM being
+    /// cloned, and mapped privately so that its pages are copied on write.
+    Memfd(&'a File),
 }
 
 /// Loads a Microvm snapshot producing a 'paused' Microvm.
@@ -400,9 +656,72 @@ pub fn restore_from_snapshot(
     params: &LoadSnapshotParams,
     vm_resources: &mut VmResources,
 ) -> Result<Arc<Mutex<Vmm>>, RestoreFromSnapshotError> {
-    let microvm_state = snapshot_state_from_file(&params.snapshot_path)?;
-    let track_dirty_pages = params.enable_diff_snapshots;
+    let mut microvm_state = snapshot_state_from_file(&params.snapshot_path)?;
+    set_load_network_overrides(&mut microvm_state, &params.network_overrides)?;
+    let mem_backend_path = &params.mem_backend.backend_path;
+    let mem_backend = match params.mem_backend.backend_type {
+        MemBackendType::File => GuestMemoryBackend::File(mem_backend_path),
+        MemBackendType::Uffd => GuestMemoryBackend::Uffd(mem_backend_path),
+    };
+    let vmm = restore_from_state(
+        instance_info,
+        event_manager,
+        seccomp_filters,
+        microvm_state,
+        mem_backend,
+        params.enable_diff_snapshots,
+        vm_resources,
+        params.track_access.as_ref(),
+    )?;
 
+    // The microVM may be restored on another host, or its taps attached elsewhere: the peers of
+    // the guest learn where it is from its announcements.
+    let skipped_ids: Vec<&str> = params
+        .network_overrides
+        .iter()
+        .filter(|net_override| !net_override.guest_announce)
+        .map(|net_override| net_override.iface_id.as_str())
+        .collect();
+    vmm.lock()
+        .expect("Poisoned lock")
+        .announce_net_devices(&skipped_ids);
+    Ok(vmm)
+}
+
+// Applies the network overrides of a snapshot load request to the state of the microVM.
+fn set_load_network_overrides(
+    microvm_state: &mut MicrovmState,
+    network_overrides: &[LoadNetworkOverride],
+) -> Result<(), EditMicrovmStateError> {
+    for net_override in network_overrides {
+        let iface_id = &net_override.iface_id;
+        match net_override.tap_fd {
+            Some(tap_fd) => microvm_state.set_net_tap_fd(iface_id, tap_fd)?,
+            None if !microvm_state
+                .device_states
+                .net_devices
+                .iter()
+                .any(|net| &net.device_id == iface_id) =>
+            {
+                return Err(EditMicrovmStateError::NetNotFound(iface_id.clone()));
+            }
+            None => (),
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn restore_from_state(
+    instance_info: &InstanceInfo,
+    event_manager: &mut EventManager,
+    seccomp_filters: &BpfThreadMap,
+    microvm_state: MicrovmState,
+    mem_backend: GuestMemoryBackend,
+    track_dirty_pages: bool,
+    vm_resources: &mut VmResources,
+    track_access: Option<&TrackAccessConfig>,
+) -> Result<Arc<Mutex<Vmm>>, RestoreFromSnapshotError> {
     let vcpu_count = microvm_state
         .vcpu_states
         .len()
@@ -418,19 +737,25 @@ pub fn restore_from_snapshot(
             cpu_template: Some(microvm_state.vm_info.cpu_template),
             track_dirty_pages: Some(track_dirty_pages),
             huge_pages: Some(microvm_state.vm_info.huge_pages),
+            smbios: microvm_state.vm_info.smbios.clone(),
+            gic: microvm_state.vm_info.gic,
+            // The guest was ready when it was snapshotted, it is not probed again.
+            readiness_probe: None,
+            serial_ports: microvm_state.vm_info.serial_ports.clone(),
+            auto_pause: microvm_state.vm_info.auto_pause.clone(),
+            hotplug_slots: None,
         })
         .map_err(BuildMicrovmFromSnapshotError::VmUpdateConfig)?;
 
     // Some sanity checks before building the microvm.
     snapshot_state_sanity_check(&microvm_state)?;
 
-    let mem_backend_path = &params.mem_backend.backend_path;
     let mem_state = &microvm_state.memory_state;
 
-    let (guest_memory, uffd) = match params.mem_backend.backend_type {
-        MemBackendType::File => (
+    let (guest_memory, uffd) = match mem_backend {
+        GuestMemoryBackend::File(mem_file_path) => (
             guest_memory_from_file(
-                mem_backend_path,
+                mem_file_path,
                 mem_state,
                 track_dirty_pages,
                 vm_resources.vm_config.huge_pages,
@@ -438,8 +763,8 @@ pub fn restore_from_snapshot(
             .map_err(RestoreFromSnapshotGuestMemoryError::File)?,
             None,
         ),
-        MemBackendType::Uffd => guest_memory_from_uffd(
-            mem_backend_path,
+        GuestMemoryBackend::Uffd(mem_uds_path) => guest_memory_from_uffd(
+            mem_uds_path,
             mem_state,
             track_dirty_pages,
             // We enable the UFFD_FEATURE_EVENT_REMOVE feature only if a balloon device
@@ -448,6 +773,16 @@ pub fn restore_from_snapshot(
             vm_resources.vm_config.huge_pages,
         )
         .map_err(RestoreFromSnapshotGuestMemoryError::Uffd)?,
+        GuestMemoryBackend::Memfd(memfd) => (
+            GuestMemoryMmap::from_state(
+                Some(memfd),
+                mem_state,
+                track_dirty_pages,
+                vm_resources.vm_config.huge_pages,
+            )
+            .map_err(RestoreFromSnapshotGuestMemoryError::Memfd)?,
+            None,
+        ),
     };
     builder::build_microvm_from_snapshot(
         instance_info,
@@ -457,6 +792,7 @@ pub fn restore_from_snapshot(
         uffd,
         seccomp_filters,
         vm_resources,
+        track_access,
     )
     .map_err(RestoreFromSnapshotError::Build)
 }
@@ -624,10 +960,228 @@ fn send_uffd_handshake(
     Ok(())
 }
 
+/// Magic value starting the message which sends a microVM to its clone.
+const CLONE_MAGIC: [u8; 8] = *b"FCCLONE1";
+/// Length of the header of the message: the magic value, followed by the length of the
+/// serialized microVM state as a little endian u64.
+const CLONE_HEADER_LEN: usize = 16;
+/// Maximum length of the serialized microVM state accepted by a clone.
+const MAX_CLONE_STATE_LEN: u64 = 64 << 20;
+/// Maximum length of the reply of a clone.
+const MAX_CLONE_REPLY_LEN: u64 = 4096;
+/// Reply of a clone once the microVM is restored. Otherwise, it replies with `ERR <reason>\n`.
+const CLONE_REPLY_OK: &[u8] = b"OK\n";
+
+/// Errors associated with cloning a microVM.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CloneMicrovmError {
+    /// Cannot clone a microVM whose guest memory is backed by hugetlbfs pages.
+    HugePages,
+    /// Network interface {0} needs a new tap device in the clone.
+    MissingNetworkOverride(String),
+    /// Cannot edit the microVM state of the clone: {0}
+    EditState(#[from] EditMicrovmStateError),
+    /// Cannot save the microVM state: {0}
+    MicrovmState(MicrovmStateError),
+    /// Cannot serialize the microVM state: {0}
+    SerializeMicrovmState(crate::snapshot::SnapshotError),
+    /// Cannot copy the guest memory: {0}
+    Memory(MemoryError),
+    /// Cannot connect to the clone: {0}
+    Connect(io::Error),
+    /// Cannot send the guest memory to the clone: {0}
+    SendMemfd(utils::errno::Error),
+    /// Cannot communicate with the clone: {0}
+    Io(io::Error),
+    /// The clone failed to restore the microVM: {0}
+    Restore(String),
+}
+
+/// Clones the paused microVM into the Firecracker process listening on `params.socket`, which
+/// was started with `--await-clone`. The guest memory is copied to a sealed memfd that the clone
+/// maps privately, so that neither microVM sees the writes of the other. Returns once the clone
+/// is restored.
+pub fn clone_microvm(
+    vmm: &mut Vmm,
+    vm_info: &VmInfo,
+    params: &CloneMicrovmParams,
+) -> Result<(), CloneMicrovmError> {
+    use self::CloneMicrovmError::*;
+
+    if vm_info.huge_pages.is_hugetlbfs() {
+        return Err(HugePages);
+    }
+
+    let mut microvm_state = vmm.save_state(vm_info).map_err(MicrovmState)?;
+    set_clone_taps(&mut microvm_state, &params.network_overrides)?;
+    let mut state = Vec::new();
+    Snapshot::new(SNAPSHOT_VERSION)
+        .save(&mut state, &microvm_state)
+        .map_err(SerializeMicrovmState)?;
+
+    let memfd = vmm.guest_memory().dump_to_sealed_memfd().map_err(Memory)?;
+    let socket = UnixStream::connect(&params.socket).map_err(Connect)?;
+    send_clone(socket, &state, &memfd)
+}
+
+fn set_clone_taps(
+    microvm_state: &mut MicrovmState,
+    network_overrides: &[CloneNetworkOverride],
+) -> Result<(), CloneMicrovmError> {
+    for net_override in network_overrides {
+        microvm_state
+            .set_net_tap_name(&net_override.iface_id, net_override.host_dev_name.clone())?;
+    }
+    // The tap devices of the microVM stay in use, so every interface needs a new one.
+    match microvm_state.device_states.net_devices.iter().find(|net| {
+        !network_overrides
+            .iter()
+            .any(|net_override| net_override.iface_id == net.device_id)
+    }) {
+        Some(net) => Err(CloneMicrovmError::MissingNetworkOverride(
+            net.device_id.clone(),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn send_clone(mut socket: UnixStream, state: &[u8], memfd: &File) -> Result<(), CloneMicrovmError> {
+    use self::CloneMicrovmError::*;
+
+    let mut header = [0u8; CLONE_HEADER_LEN];
+    header[..CLONE_MAGIC.len()].copy_from_slice(&CLONE_MAGIC);
+    header[CLONE_MAGIC.len()..].copy_from_slice(&(state.len() as u64).to_le_bytes());
+    let sent = socket
+        .send_with_fd(&header[..], memfd.as_raw_fd())
+        .map_err(SendMemfd)?;
+    socket
+        .write_all(&header[sent..])
+        .and_then(|()| socket.write_all(state))
+        .map_err(Io)?;
+
+    // The clone closes the connection after replying.
+    let mut reply = Vec::new();
+    socket
+        .take(MAX_CLONE_REPLY_LEN)
+        .read_to_end(&mut reply)
+        .map_err(Io)?;
+    if reply == CLONE_REPLY_OK {
+        return Ok(());
+    }
+    let reason = match reply.strip_prefix(b"ERR ") {
+        Some(reason) => String::from_utf8_lossy(reason).trim_end().to_string(),
+        None => "no reply".to_string(),
+    };
+    Err(Restore(reason))
+}
+
+/// Errors associated with restoring a microVM sent by [`clone_microvm`].
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum RestoreFromCloneError {
+    /// Cannot listen on the clone socket: {0}
+    Bind(io::Error),
+    /// Cannot accept the connection of the microVM being cloned: {0}
+    Accept(io::Error),
+    /// Cannot receive the guest memory: {0}
+    ReceiveMemfd(utils::errno::Error),
+    /// The guest memory was not received.
+    MissingMemfd,
+    /// The guest memory is not in a memfd sealed against writes and resizing.
+    UnsealedMemfd,
+    /// Cannot receive the microVM state: {0}
+    Receive(io::Error),
+    /// Invalid header of the clone message.
+    InvalidHeader,
+    /// Cannot deserialize the microVM state: {0}
+    DeserializeMicrovmState(crate::snapshot::SnapshotError),
+    /// Failed to restore the microVM: {0}
+    Restore(#[from] RestoreFromSnapshotError),
+}
+
+/// Waits on `socket_path` for a microVM sent by [`clone_microvm`] and restores it, producing a
+/// 'paused' microVM. The microVM being cloned is told whether the restore succeeded.
+pub fn restore_from_clone(
+    instance_info: &InstanceInfo,
+    event_manager: &mut EventManager,
+    seccomp_filters: &BpfThreadMap,
+    socket_path: &Path,
+    vm_resources: &mut VmResources,
+) -> Result<Arc<Mutex<Vmm>>, RestoreFromCloneError> {
+    let listener = UnixListener::bind(socket_path).map_err(RestoreFromCloneError::Bind)?;
+    let (mut socket, _) = listener.accept().map_err(RestoreFromCloneError::Accept)?;
+
+    let result = receive_clone(&mut socket).and_then(|(microvm_state, memfd)| {
+        restore_from_state(
+            instance_info,
+            event_manager,
+            seccomp_filters,
+            microvm_state,
+            GuestMemoryBackend::Memfd(&memfd),
+            // Dirty pages are not tracked in clones.
+            false,
+            vm_resources,
+            None,
+        )
+        .map_err(RestoreFromCloneError::Restore)
+    });
+
+    let reply = match &result {
+        Ok(_) => CLONE_REPLY_OK.to_vec(),
+        Err(err) => format!("ERR {}\n", err).into_bytes(),
+    };
+    if let Err(err) = socket.write_all(&reply) {
+        warn!("Cannot reply to the microVM being cloned: {}", err);
+    }
+    result
+}
+
+fn receive_clone(socket: &mut UnixStream) -> Result<(MicrovmState, File), RestoreFromCloneError> {
+    use self::RestoreFromCloneError::*;
+
+    let mut header = [0u8; CLONE_HEADER_LEN];
+    let (received, memfd) = socket.recv_with_fd(&mut header[..]).map_err(ReceiveMemfd)?;
+    let memfd = check_clone_memfd(memfd.ok_or(MissingMemfd)?)?;
+    socket
+        .read_exact(&mut header[received..])
+        .map_err(Receive)?;
+
+    let (magic, state_len) = header.split_at(CLONE_MAGIC.len());
+    // The slice is 8 bytes long, the conversion cannot fail.
+    let state_len = u64::from_le_bytes(state_len.try_into().unwrap());
+    if magic != CLONE_MAGIC || state_len > MAX_CLONE_STATE_LEN {
+        return Err(InvalidHeader);
+    }
+
+    let mut state = vec![0u8; u64_to_usize(state_len)];
+    socket.read_exact(&mut state).map_err(Receive)?;
+    let microvm_state = Snapshot::new(SNAPSHOT_VERSION)
+        .load_with_version_check(&mut state.as_slice(), state.len())
+        .map_err(DeserializeMicrovmState)?;
+
+    Ok((microvm_state, memfd))
+}
+
+fn check_clone_memfd(memfd: File) -> Result<File, RestoreFromCloneError> {
+    // The pages which the clone did not write yet are read from the memfd, so the microVM being
+    // cloned must not be able to change it.
+    let memfd =
+        memfd::Memfd::try_from_file(memfd).map_err(|_| RestoreFromCloneError::UnsealedMemfd)?;
+    let seals = memfd
+        .seals()
+        .map_err(|_| RestoreFromCloneError::UnsealedMemfd)?;
+    let required = [
+        memfd::FileSeal::SealShrink,
+        memfd::FileSeal::SealGrow,
+        memfd::FileSeal::SealWrite,
+    ];
+    if !required.iter().all(|seal| seals.contains(seal)) {
+        return Err(RestoreFromCloneError::UnsealedMemfd);
+    }
+    Ok(memfd.into_file())
+}
+
 #[cfg(test)]
 mod tests {
-    use std::os::unix::net::UnixListener;
-
     use utils::tempfile::TempFile;
 
     use super::*;
@@ -639,12 +1193,13 @@ mod tests {
     };
     #[cfg(target_arch = "aarch64")]
     use crate::construct_kvm_mpidrs;
+    use crate::device_manager::persist::MmdsVersionState;
     use crate::devices::virtio::block::CacheType;
     use crate::snapshot::Persist;
     use crate::vmm_config::balloon::BalloonDeviceConfig;
     use crate::vmm_config::net::NetworkInterfaceConfig;
     use crate::vmm_config::vsock::tests::default_config;
-    use crate::vstate::memory::GuestMemoryRegionState;
+    use crate::vstate::memory::{Bytes, GuestAddress, GuestMemoryRegionState};
     use crate::Vmm;
 
     fn default_vmm_with_devices() -> Vmm {
@@ -657,6 +1212,8 @@ mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_config);
 
@@ -675,9 +1232,17 @@ mod tests {
         let network_interface = NetworkInterfaceConfig {
             iface_id: String::from("netif"),
             host_dev_name: String::from("hostname"),
+            tap_fd: None,
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
         };
         insert_net_device(
             &mut vmm,
@@ -721,6 +1286,12 @@ mod tests {
             vcpu_states,
             vm_info: VmInfo {
                 mem_size_mib: 1u64,
+                smbios: Some(SmbiosConfig {
+                    product_name: Some("microvm".to_string()),
+                    uuid: Some("00112233-4455-6677-8899-aabbccddeeff".to_string()),
+                    oem_strings: vec!["oem".to_string()],
+                    ..Default::default()
+                }),
                 ..Default::default()
             },
             #[cfg(target_arch = "aarch64")]
@@ -744,6 +1315,323 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_edit_microvm_state() {
+        let vmm = default_vmm_with_devices();
+        let mut microvm_state = MicrovmState {
+            device_states: vmm.mmio_device_manager.save(),
+            ..Default::default()
+        };
+        microvm_state.device_states.mmds_version = Some(MmdsVersionState::V1);
+
+        let devices = microvm_state.devices();
+        let ids: Vec<_> = devices
+            .iter()
+            .map(|device| (device.device_type, device.device_id.as_str()))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("balloon", "balloon"),
+                ("block", "root"),
+                ("net", "netif"),
+                ("vsock", "vsock")
+            ]
+        );
+        assert!(devices[1].root_device);
+        let old_path = devices[1].disk_path.clone().unwrap();
+        microvm_state.check_devices().unwrap();
+
+        // Replace the tap device of the network interface.
+        assert_eq!(
+            microvm_state
+                .set_net_tap_name("netif", "tap1".to_string())
+                .unwrap(),
+            "hostname"
+        );
+        assert_eq!(
+            microvm_state.device_states.net_devices[0]
+                .device_state
+                .tap_if_name,
+            "tap1"
+        );
+        assert_eq!(
+            microvm_state
+                .set_net_tap_name("root", "tap1".to_string())
+                .unwrap_err(),
+            EditMicrovmStateError::NetNotFound("root".to_string())
+        );
+        assert_eq!(
+            microvm_state
+                .set_net_tap_name("netif", String::new())
+                .unwrap_err(),
+            EditMicrovmStateError::EmptyTapName("netif".to_string())
+        );
+        microvm_state.set_net_tap_fd("netif", 3).unwrap();
+        assert_eq!(
+            microvm_state.device_states.net_devices[0]
+                .device_state
+                .tap_fd,
+            Some(3)
+        );
+        assert_eq!(
+            microvm_state.set_net_tap_fd("root", 3).unwrap_err(),
+            EditMicrovmStateError::NetNotFound("root".to_string())
+        );
+        let net_override = |iface_id: &str, tap_fd| LoadNetworkOverride {
+            iface_id: iface_id.to_string(),
+            tap_fd,
+            guest_announce: false,
+        };
+        set_load_network_overrides(&mut microvm_state, &[net_override("netif", Some(4))]).unwrap();
+        assert_eq!(
+            microvm_state.device_states.net_devices[0]
+                .device_state
+                .tap_fd,
+            Some(4)
+        );
+        set_load_network_overrides(&mut microvm_state, &[net_override("netif", None)]).unwrap();
+        for tap_fd in [None, Some(4)] {
+            assert_eq!(
+                set_load_network_overrides(&mut microvm_state, &[net_override("root", tap_fd)])
+                    .unwrap_err(),
+                EditMicrovmStateError::NetNotFound("root".to_string())
+            );
+        }
+
+        // Remove the network device, which releases its MMIO slot. No remaining network device
+        // serves MMDS, so the MMDS version goes away too.
+        let removed = microvm_state.remove_device("netif").unwrap();
+        assert_eq!(removed, devices[2]);
+        assert!(microvm_state.device_states.net_devices.is_empty());
+        assert!(microvm_state.device_states.mmds_version.is_none());
+        assert_eq!(
+            microvm_state.remove_device("netif").unwrap_err(),
+            EditMicrovmStateError::DeviceNotFound("netif".to_string())
+        );
+
+        // Replace the backing file of the drive.
+        assert_eq!(
+            microvm_state
+                .set_drive_path("root", "/new/rootfs".to_string())
+                .unwrap(),
+            old_path
+        );
+        assert_eq!(
+            microvm_state
+                .set_drive_path("vsock", "/new/rootfs".to_string())
+                .unwrap_err(),
+            EditMicrovmStateError::DriveNotFound("vsock".to_string())
+        );
+        assert_eq!(
+            microvm_state
+                .set_drive_path("root", String::new())
+                .unwrap_err(),
+            EditMicrovmStateError::EmptyDrivePath("root".to_string())
+        );
+
+        // The edited state round-trips through a snapshot file.
+        let mut buf = Vec::new();
+        Snapshot::new(SNAPSHOT_VERSION)
+            .save(&mut buf, &microvm_state)
+            .unwrap();
+        let (restored_state, version): (MicrovmState, _) =
+            Snapshot::load(&mut buf.as_slice(), buf.len()).unwrap();
+        assert_eq!(version, SNAPSHOT_VERSION);
+        let restored_devices = restored_state.devices();
+        assert_eq!(restored_devices, microvm_state.devices());
+        assert_eq!(restored_devices.len(), 3);
+        assert_eq!(
+            restored_devices[1].disk_path.as_deref(),
+            Some("/new/rootfs")
+        );
+        assert!(restored_state.device_states.mmds_version.is_none());
+        restored_state.check_devices().unwrap();
+
+        // States which cannot be restored are detected.
+        let mut block = microvm_state.device_states.block_devices[0].clone();
+        block.device_id = "other".to_string();
+        microvm_state.device_states.block_devices.push(block);
+        assert_eq!(
+            microvm_state.check_devices().unwrap_err(),
+            EditMicrovmStateError::MmioSlotConflict(
+                "root".to_string(),
+                "other".to_string(),
+                devices[1].mmio_addr
+            )
+        );
+        microvm_state.device_states.block_devices[1].device_id = "vsock".to_string();
+        assert_eq!(
+            microvm_state.remove_device("vsock").unwrap_err(),
+            EditMicrovmStateError::AmbiguousDeviceId("vsock".to_string())
+        );
+    }
+
+    #[test]
+    fn test_set_clone_taps() {
+        let vmm = default_vmm_with_devices();
+        let mut microvm_state = MicrovmState {
+            device_states: vmm.mmio_device_manager.save(),
+            ..Default::default()
+        };
+        let net_override = |iface_id: &str| CloneNetworkOverride {
+            iface_id: iface_id.to_string(),
+            host_dev_name: "tap1".to_string(),
+        };
+
+        assert!(matches!(
+            set_clone_taps(&mut microvm_state, &[]),
+            Err(CloneMicrovmError::MissingNetworkOverride(iface_id)) if iface_id == "netif"
+        ));
+        assert!(matches!(
+            set_clone_taps(&mut microvm_state, &[net_override("netif"), net_override("eth1")]),
+            Err(CloneMicrovmError::EditState(EditMicrovmStateError::NetNotFound(iface_id)))
+                if iface_id == "eth1"
+        ));
+        set_clone_taps(&mut microvm_state, &[net_override("netif")]).unwrap();
+        assert_eq!(
+            microvm_state.device_states.net_devices[0]
+                .device_state
+                .tap_if_name,
+            "tap1"
+        );
+
+        microvm_state.remove_device("netif").unwrap();
+        set_clone_taps(&mut microvm_state, &[]).unwrap();
+    }
+
+    fn clone_test_state() -> (GuestMemoryMmap, Vec<u8>) {
+        let guest_memory = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), 0x10000), (GuestAddress(0x20000), 0x10000)],
+            false,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        guest_memory.write(&[1u8; 0x100], GuestAddress(0)).unwrap();
+        let microvm_state = MicrovmState {
+            vm_info: VmInfo {
+                mem_size_mib: 1,
+                ..Default::default()
+            },
+            memory_state: guest_memory.describe(),
+            ..Default::default()
+        };
+        let mut state = Vec::new();
+        Snapshot::new(SNAPSHOT_VERSION)
+            .save(&mut state, &microvm_state)
+            .unwrap();
+        (guest_memory, state)
+    }
+
+    #[test]
+    fn test_clone_protocol() {
+        let (guest_memory, state) = clone_test_state();
+        let memfd = guest_memory.dump_to_sealed_memfd().unwrap();
+
+        for (reply, expected_err) in [
+            (CLONE_REPLY_OK, None),
+            (
+                b"ERR cannot open tap1\n".as_slice(),
+                Some("cannot open tap1"),
+            ),
+            (b"".as_slice(), Some("no reply")),
+        ] {
+            let (source, mut target) = UnixStream::pair().unwrap();
+            let sender = {
+                let state = state.clone();
+                let memfd = memfd.try_clone().unwrap();
+                std::thread::spawn(move || send_clone(source, &state, &memfd))
+            };
+
+            let (microvm_state, clone_memfd) = receive_clone(&mut target).unwrap();
+            assert_eq!(microvm_state.vm_info.mem_size_mib, 1);
+            assert_eq!(microvm_state.memory_state, guest_memory.describe());
+            target.write_all(reply).unwrap();
+            drop(target);
+
+            match (sender.join().unwrap(), expected_err) {
+                (Ok(()), None) => (),
+                (Err(CloneMicrovmError::Restore(reason)), Some(expected)) => {
+                    assert_eq!(reason, expected)
+                }
+                (result, _) => panic!("Unexpected result: {:?}", result),
+            }
+
+            // The clone maps the memory it received privately: its writes are not seen by the
+            // source.
+            let clone_memory = GuestMemoryMmap::from_state(
+                Some(&clone_memfd),
+                &microvm_state.memory_state,
+                false,
+                HugePageConfig::None,
+            )
+            .unwrap();
+            let mut buf = [0u8; 0x100];
+            clone_memory.read(&mut buf, GuestAddress(0)).unwrap();
+            assert_eq!(buf, [1u8; 0x100]);
+            clone_memory.write(&[2u8; 0x100], GuestAddress(0)).unwrap();
+            clone_memory
+                .write(&[3u8; 0x100], GuestAddress(0x20000))
+                .unwrap();
+            guest_memory.read(&mut buf, GuestAddress(0)).unwrap();
+            assert_eq!(buf, [1u8; 0x100]);
+            guest_memory.read(&mut buf, GuestAddress(0x20000)).unwrap();
+            assert_eq!(buf, [0u8; 0x100]);
+        }
+    }
+
+    #[test]
+    fn test_receive_invalid_clone() {
+        let (guest_memory, state) = clone_test_state();
+        let memfd = guest_memory.dump_to_sealed_memfd().unwrap();
+        let mut header = [0u8; CLONE_HEADER_LEN];
+        header[..CLONE_MAGIC.len()].copy_from_slice(&CLONE_MAGIC);
+        header[CLONE_MAGIC.len()..].copy_from_slice(&(state.len() as u64).to_le_bytes());
+
+        // No memfd.
+        let (mut source, mut target) = UnixStream::pair().unwrap();
+        source.write_all(&header).unwrap();
+        assert!(matches!(
+            receive_clone(&mut target),
+            Err(RestoreFromCloneError::MissingMemfd)
+        ));
+
+        // A file which is not a sealed memfd.
+        let file = TempFile::new().unwrap();
+        let (source, mut target) = UnixStream::pair().unwrap();
+        source
+            .send_with_fd(&header[..], file.as_file().as_raw_fd())
+            .unwrap();
+        assert!(matches!(
+            receive_clone(&mut target),
+            Err(RestoreFromCloneError::UnsealedMemfd)
+        ));
+
+        // Invalid magic value, and state too long.
+        let mut invalid_magic = header;
+        invalid_magic[0] = b'X';
+        let mut too_long = header;
+        too_long[CLONE_MAGIC.len()..].copy_from_slice(&(MAX_CLONE_STATE_LEN + 1).to_le_bytes());
+        for header in [invalid_magic, too_long] {
+            let (source, mut target) = UnixStream::pair().unwrap();
+            source.send_with_fd(&header[..], memfd.as_raw_fd()).unwrap();
+            assert!(matches!(
+                receive_clone(&mut target),
+                Err(RestoreFromCloneError::InvalidHeader)
+            ));
+        }
+
+        // Truncated state.
+        let (mut source, mut target) = UnixStream::pair().unwrap();
+        source.send_with_fd(&header[..], memfd.as_raw_fd()).unwrap();
+        source.write_all(&state[..state.len() / 2]).unwrap();
+        drop(source);
+        assert!(matches!(
+            receive_clone(&mut target),
+            Err(RestoreFromCloneError::Receive(_))
+        ));
+    }
+
     #[test]
     fn test_create_guest_memory() {
         let mem_state = GuestMemoryState {
@@ -222,6 +222,25 @@ impl TokenBucket {
         BucketReduction::Success
     }
 
+    /// Carries the state of `previous`, the bucket this one replaces, over to this bucket.
+    ///
+    /// The budget is carried over proportionally to the size of the buckets: a bucket that was
+    /// half full stays half full. The remaining one time burst is kept, up to the one time burst
+    /// of this bucket, so that replacing a bucket never grants a new burst.
+    pub fn carry_over(&mut self, previous: &TokenBucket) {
+        let mut previous = previous.clone();
+        previous.auto_replenish();
+
+        // The budget is at most the size of the previous bucket, so the result is at most
+        // `self.size` and fits into a u64.
+        #[allow(clippy::cast_possible_truncation)]
+        let budget = (u128::from(previous.budget) * u128::from(self.size)
+            / u128::from(previous.size)) as u64;
+        self.budget = std::cmp::min(budget, self.size);
+        self.one_time_burst = std::cmp::min(previous.one_time_burst, self.initial_one_time_burst);
+        self.last_update = Instant::now();
+    }
+
     /// "Manually" adds tokens to bucket.
     pub fn force_replenish(&mut self, tokens: u64) {
         // This means we are still during the burst interval.
@@ -274,7 +293,7 @@ pub enum TokenType {
 }
 
 /// Enum that describes the type of token bucket update.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum BucketUpdate {
     /// No Update - same as before.
     None,
@@ -284,6 +303,14 @@ pub enum BucketUpdate {
     Update(TokenBucket),
 }
 
+/// New token buckets of a `RateLimiter`, built by `RateLimiter::build_update()` and applied at
+/// once by `RateLimiter::apply_update()`.
+#[derive(Debug)]
+pub struct RateLimiterUpdate {
+    bandwidth: Option<TokenBucket>,
+    ops: Option<TokenBucket>,
+}
+
 /// Rate Limiter that works on both bandwidth and ops/s limiting.
 ///
 /// Bandwidth (bytes/s) and ops/s limiting can be used at the same time or individually.
@@ -482,19 +509,42 @@ impl RateLimiter {
         }
     }
 
+    // Builds the bucket replacing `current` according to `update`.
+    fn updated_bucket(current: Option<&TokenBucket>, update: BucketUpdate) -> Option<TokenBucket> {
+        match update {
+            BucketUpdate::None => current.cloned(),
+            BucketUpdate::Disabled => None,
+            BucketUpdate::Update(mut tb) => {
+                // A bucket that was disabled starts off full, as a new bucket.
+                if let Some(current) = current {
+                    tb.carry_over(current);
+                }
+                Some(tb)
+            }
+        }
+    }
+
+    /// Builds the new token buckets of this RateLimiter, without applying them.
+    ///
+    /// The updated buckets carry over the budget and one time burst of the buckets they replace,
+    /// see `TokenBucket::carry_over()`.
+    pub fn build_update(&self, bytes: BucketUpdate, ops: BucketUpdate) -> RateLimiterUpdate {
+        RateLimiterUpdate {
+            bandwidth: Self::updated_bucket(self.bandwidth.as_ref(), bytes),
+            ops: Self::updated_bucket(self.ops.as_ref(), ops),
+        }
+    }
+
+    /// Replaces both token buckets of this RateLimiter at once.
+    pub fn apply_update(&mut self, update: RateLimiterUpdate) {
+        self.bandwidth = update.bandwidth;
+        self.ops = update.ops;
+    }
+
     /// Updates the parameters of the token buckets associated with this RateLimiter.
-    // TODO: Please note that, right now, the buckets become full after being updated.
     pub fn update_buckets(&mut self, bytes: BucketUpdate, ops: BucketUpdate) {
-        match bytes {
-            BucketUpdate::Disabled => self.bandwidth = None,
-            BucketUpdate::Update(tb) => self.bandwidth = Some(tb),
-            BucketUpdate::None => (),
-        };
-        match ops {
-            BucketUpdate::Disabled => self.ops = None,
-            BucketUpdate::Update(tb) => self.ops = Some(tb),
-            BucketUpdate::None => (),
-        };
+        let update = self.build_update(bytes, ops);
+        self.apply_update(update);
     }
 
     /// Returns an immutable view of the inner bandwidth token bucket.
@@ -1162,7 +1212,7 @@ pub(crate) mod tests {
         assert_eq!(x.ops, initial_ops);
 
         let new_bw = TokenBucket::new(123, 0, 57).unwrap();
-        let new_ops = TokenBucket::new(321, 12346, 89).unwrap();
+        let mut new_ops = TokenBucket::new(321, 12346, 89).unwrap();
         x.update_buckets(
             BucketUpdate::Update(new_bw.clone()),
             BucketUpdate::Update(new_ops.clone()),
@@ -1174,12 +1224,58 @@ pub(crate) mod tests {
         x.bandwidth.as_mut().unwrap().last_update = new_bw.last_update;
         x.ops.as_mut().unwrap().last_update = new_ops.last_update;
 
+        // The full buckets stay full, and the remaining one time burst is carried over.
+        new_ops.one_time_burst = 20;
         assert_eq!(x.bandwidth, Some(new_bw));
         assert_eq!(x.ops, Some(new_ops));
 
         x.update_buckets(BucketUpdate::Disabled, BucketUpdate::Disabled);
         assert_eq!(x.bandwidth, None);
         assert_eq!(x.ops, None);
+
+        // Buckets that were disabled start off full.
+        let new_bw = TokenBucket::new(1000, 100, 1000).unwrap();
+        x.update_buckets(BucketUpdate::Update(new_bw), BucketUpdate::None);
+        assert_eq!(x.bandwidth.as_ref().unwrap().budget(), 1000);
+        assert_eq!(x.bandwidth.as_ref().unwrap().one_time_burst(), 100);
+        assert_eq!(x.ops, None);
+    }
+
+    #[test]
+    fn test_update_buckets_carry_over() {
+        // A bucket that takes a day to refill, so that it does not replenish during the test.
+        let refill_time = 24 * 60 * 60 * 1000;
+        let mut x = RateLimiter::new(1000, 0, refill_time, 100, 50, refill_time).unwrap();
+        // Use up 3/4 of the bandwidth budget and all of the ops one time burst plus 10 ops.
+        assert!(x.consume(750, TokenType::Bytes));
+        assert!(x.consume(60, TokenType::Ops));
+
+        // Both buckets are replaced at once, keeping their budget in proportion to their size.
+        let update = x.build_update(
+            BucketUpdate::Update(TokenBucket::new(4000, 0, refill_time).unwrap()),
+            BucketUpdate::Update(TokenBucket::new(10, 5, refill_time).unwrap()),
+        );
+        // Building the update leaves the rate limiter untouched.
+        assert_eq!(x.bandwidth().unwrap().capacity(), 1000);
+        assert_eq!(x.ops().unwrap().capacity(), 100);
+        x.apply_update(update);
+
+        let bandwidth = x.bandwidth().unwrap();
+        assert_eq!(bandwidth.capacity(), 4000);
+        assert_eq!(bandwidth.budget(), 1000);
+        let ops = x.ops().unwrap();
+        assert_eq!(ops.capacity(), 10);
+        assert_eq!(ops.budget(), 9);
+        // The used up one time burst is not granted again.
+        assert_eq!(ops.one_time_burst(), 0);
+
+        // Shrinking a bucket keeps its budget in proportion too.
+        x.update_buckets(
+            BucketUpdate::Update(TokenBucket::new(400, 0, refill_time).unwrap()),
+            BucketUpdate::None,
+        );
+        assert_eq!(x.bandwidth().unwrap().budget(), 100);
+        assert_eq!(x.ops().unwrap().budget(), 9);
     }
 
     #[test]
@@ -56,6 +56,8 @@ impl Persist<'_> for TokenBucket {
 pub struct RateLimiterState {
     ops: Option<TokenBucketState>,
     bandwidth: Option<TokenBucketState>,
+    // Whether the limiter was waiting for its timer to refill the buckets.
+    blocked: bool,
 }
 
 impl Persist<'_> for RateLimiter {
@@ -67,11 +69,12 @@ impl Persist<'_> for RateLimiter {
         RateLimiterState {
             ops: self.ops.as_ref().map(|ops| ops.save()),
             bandwidth: self.bandwidth.as_ref().map(|bw| bw.save()),
+            blocked: self.timer_active,
         }
     }
 
     fn restore(_: Self::ConstructorArgs, state: &Self::State) -> Result<Self, Self::Error> {
-        let rate_limiter = RateLimiter {
+        let mut rate_limiter = RateLimiter {
             ops: if let Some(ops) = state.ops.as_ref() {
                 Some(TokenBucket::restore((), ops)?)
             } else {
@@ -85,6 +88,12 @@ impl Persist<'_> for RateLimiter {
             timer_fd: TimerFd::new_custom(ClockId::Monotonic, true, true)?,
             timer_active: false,
         };
+        // Keep a blocked limiter blocked, so that restoring a snapshot does not reset the limits.
+        // Its timer unblocks it after a refill interval, at which point the buckets are checked
+        // again.
+        if state.blocked {
+            rate_limiter.activate_timer(TIMER_REFILL_STATE);
+        }
 
         Ok(rate_limiter)
     }
@@ -166,6 +175,7 @@ mod tests {
 
         // Check that RateLimiter restores correctly after totally consuming tokens.
         rate_limiter.consume(1000, TokenType::Bytes);
+        assert!(rate_limiter.is_blocked());
         let restored_rate_limiter =
             RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
 
@@ -177,6 +187,12 @@ mod tests {
             .bandwidth()
             .unwrap()
             .partial_eq(restored_rate_limiter.bandwidth().unwrap()));
+        // The limiter stays blocked until its timer fires.
+        assert!(restored_rate_limiter.is_blocked());
+        assert!(matches!(
+            restored_rate_limiter.timer_fd.get_state(),
+            TimerState::Oneshot(_)
+        ));
 
         // Test serialization.
         let mut mem = vec![0; 4096];
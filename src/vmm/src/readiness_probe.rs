@@ -0,0 +1,512 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tells when the guest is ready, by running a check against it after the microVM started.
+//!
+//! The probe is driven by a timer registered with the event manager: every `timeout_ms`, it
+//! looks at the outcome of the running attempt and starts a new one if it did not succeed, until
+//! the check succeeds or `max_attempts` attempts failed. Checks never block the VMM thread.
+
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use event_manager::{EventOps, Events, MutEventSubscriber};
+use serde::Serialize;
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+use utils::epoll::EventSet;
+
+use crate::events::{VmmEvent, EVENTS};
+use crate::logger::{debug, error, info, warn};
+use crate::mmds::data_store::Mmds;
+use crate::vmm_config::readiness_probe::{ReadinessCheck, ReadinessProbeConfig};
+
+// Bytes kept from the vsock connection. The acknowledgement of the vsock muxer is much shorter.
+const MAX_VSOCK_RESPONSE_LEN: usize = 64;
+
+/// State of the readiness probe.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessProbeState {
+    /// The probe is still running.
+    #[default]
+    Pending,
+    /// The guest passed the probe.
+    Ready,
+    /// The guest did not pass the probe within the configured number of attempts.
+    Failed,
+}
+
+/// Status of the readiness probe, reported by the instance information.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ReadinessProbeStatus {
+    /// State of the probe.
+    pub state: ReadinessProbeState,
+    /// Number of attempts of the check started so far.
+    pub attempts: u32,
+}
+
+/// Errors associated with the readiness probe.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ReadinessProbeError {
+    /// The vsock readiness probe requires a vsock device.
+    MissingVsock,
+    /// The MMDS readiness probe requires the MMDS to be configured.
+    MissingMmds,
+    /// Failed to create the readiness probe timer: {0}
+    Timer(io::Error),
+}
+
+// Check run against the guest, with the state of its running attempt.
+#[derive(Debug)]
+enum Check {
+    Vsock {
+        uds_path: String,
+        port: u32,
+        connection: Option<UnixStream>,
+        response: Vec<u8>,
+    },
+    Mmds {
+        mmds: Arc<Mutex<Mmds>>,
+    },
+}
+
+impl Check {
+    // Starts a new attempt of the check.
+    fn start(&mut self) {
+        if let Check::Vsock {
+            uds_path,
+            port,
+            connection,
+            response,
+        } = self
+        {
+            response.clear();
+            *connection = match connect_vsock(uds_path, *port) {
+                Ok(stream) => Some(stream),
+                Err(err) => {
+                    debug!(
+                        "Readiness probe failed to connect to the vsock device: {}",
+                        err
+                    );
+                    None
+                }
+            };
+        }
+    }
+
+    // Returns whether the running attempt succeeded.
+    fn poll(&mut self) -> bool {
+        match self {
+            Check::Vsock {
+                connection,
+                response,
+                ..
+            } => {
+                let Some(stream) = connection.as_mut() else {
+                    return false;
+                };
+                let mut buf = [0u8; MAX_VSOCK_RESPONSE_LEN];
+                while response.len() < MAX_VSOCK_RESPONSE_LEN {
+                    let len = MAX_VSOCK_RESPONSE_LEN - response.len();
+                    match stream.read(&mut buf[..len]) {
+                        // The guest did not accept the connection, or closed it.
+                        Ok(0) => break,
+                        Ok(count) => response.extend_from_slice(&buf[..count]),
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(err) => {
+                            debug!("Readiness probe failed to read from the vsock: {}", err);
+                            break;
+                        }
+                    }
+                }
+                is_banner_received(response)
+            }
+            Check::Mmds { mmds } => mmds.lock().expect("Poisoned lock").guest_value().is_some(),
+        }
+    }
+
+    // Releases the resources held by the running attempt.
+    fn stop(&mut self) {
+        if let Check::Vsock { connection, .. } = self {
+            *connection = None;
+        }
+    }
+}
+
+// Connects to `port` in the guest through the host side of the vsock device.
+fn connect_vsock(uds_path: &str, port: u32) -> io::Result<UnixStream> {
+    let mut stream = UnixStream::connect(uds_path)?;
+    stream.write_all(format!("CONNECT {}\n", port).as_bytes())?;
+    stream.set_nonblocking(true)?;
+    Ok(stream)
+}
+
+// The vsock device acknowledges the connection with `OK <host port>\n`, everything after it is
+// sent by the guest.
+fn is_banner_received(response: &[u8]) -> bool {
+    match response.iter().position(|byte| *byte == b'\n') {
+        Some(end) => response.starts_with(b"OK ") && response.len() > end + 1,
+        None => false,
+    }
+}
+
+/// Probe telling when the guest is ready.
+#[derive(Debug)]
+pub struct ReadinessProbe {
+    check: Check,
+    interval: Duration,
+    max_attempts: u32,
+    timer_fd: TimerFd,
+    status: ReadinessProbeStatus,
+}
+
+impl ReadinessProbe {
+    /// Creates the probe described by the (validated) `config`. The vsock check connects to
+    /// the host socket of the vsock device, at `vsock_uds_path`, and the MMDS check makes the
+    /// configured key of `mmds` writable by the guest.
+    pub fn new(
+        config: &ReadinessProbeConfig,
+        vsock_uds_path: Option<&str>,
+        mmds: Option<Arc<Mutex<Mmds>>>,
+    ) -> Result<Self, ReadinessProbeError> {
+        let check = match &config.check {
+            ReadinessCheck::Vsock { port } => Check::Vsock {
+                uds_path: vsock_uds_path
+                    .ok_or(ReadinessProbeError::MissingVsock)?
+                    .to_string(),
+                port: *port,
+                connection: None,
+                response: Vec::new(),
+            },
+            ReadinessCheck::Mmds { key } => {
+                let mmds = mmds.ok_or(ReadinessProbeError::MissingMmds)?;
+                mmds.lock()
+                    .expect("Poisoned lock")
+                    .set_guest_writable_key(Some(key.clone()));
+                Check::Mmds { mmds }
+            }
+        };
+        let timer_fd = TimerFd::new_custom(ClockId::Monotonic, true, true)
+            .map_err(ReadinessProbeError::Timer)?;
+
+        Ok(ReadinessProbe {
+            check,
+            interval: Duration::from_millis(config.timeout_ms),
+            max_attempts: config.max_attempts,
+            timer_fd,
+            status: ReadinessProbeStatus::default(),
+        })
+    }
+
+    /// Returns the status of the probe.
+    pub fn status(&self) -> ReadinessProbeStatus {
+        self.status
+    }
+
+    // Starts the first attempt of the check, and the timer ending each attempt.
+    fn start(&mut self) {
+        self.status.attempts = 1;
+        self.check.start();
+        self.timer_fd.set_state(
+            TimerState::Periodic {
+                current: self.interval,
+                interval: self.interval,
+            },
+            SetTimeFlags::Default,
+        );
+    }
+
+    // Ends the running attempt, and starts the next one if it did not succeed.
+    fn process_timer(&mut self) {
+        if self.status.state != ReadinessProbeState::Pending {
+            return;
+        }
+        if self.check.poll() {
+            info!(
+                "The guest passed its readiness probe after {} attempt(s).",
+                self.status.attempts
+            );
+            self.finish(ReadinessProbeState::Ready);
+            EVENTS.publish(VmmEvent::Ready {
+                attempts: self.status.attempts,
+            });
+        } else if self.status.attempts >= self.max_attempts {
+            error!(
+                "The guest failed its readiness probe after {} attempt(s).",
+                self.status.attempts
+            );
+            self.finish(ReadinessProbeState::Failed);
+        } else {
+            self.status.attempts += 1;
+            self.check.start();
+        }
+    }
+
+    fn finish(&mut self, state: ReadinessProbeState) {
+        self.status.state = state;
+        self.check.stop();
+        self.timer_fd
+            .set_state(TimerState::Disarmed, SetTimeFlags::Default);
+    }
+}
+
+impl MutEventSubscriber for ReadinessProbe {
+    fn process(&mut self, event: Events, _: &mut EventOps) {
+        if !event.event_set().contains(EventSet::IN) {
+            warn!(
+                "Received unknown event: {:?} from source: {:?}",
+                event.event_set(),
+                event.fd()
+            );
+            return;
+        }
+        // Reading the timer resets it, the number of expirations does not matter.
+        self.timer_fd.read();
+        self.process_timer();
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.add(Events::new(&self.timer_fd, EventSet::IN)) {
+            error!("Failed to register the readiness probe timer: {}", err);
+            return;
+        }
+        self.start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+
+    use utils::tempfile::TempFile;
+
+    use super::*;
+
+    fn probe_config(check: ReadinessCheck, max_attempts: u32) -> ReadinessProbeConfig {
+        ReadinessProbeConfig {
+            check,
+            timeout_ms: 100,
+            max_attempts,
+        }
+    }
+
+    // Listens on a host socket like the vsock device, which acknowledges the connections to
+    // guest ports.
+    struct MockMuxer {
+        listener: UnixListener,
+        path: String,
+        _file: TempFile,
+    }
+
+    impl MockMuxer {
+        fn new() -> Self {
+            let mut file = TempFile::new().unwrap();
+            file.remove().unwrap();
+            let path = file.as_path().to_str().unwrap().to_string();
+            MockMuxer {
+                listener: UnixListener::bind(&path).unwrap(),
+                path,
+                _file: file,
+            }
+        }
+
+        // Accepts the connection of the probe to `port`, and acknowledges it.
+        fn accept(&self, port: u32) -> UnixStream {
+            let (mut stream, _) = self.listener.accept().unwrap();
+            let request = format!("CONNECT {}\n", port);
+            let mut buf = vec![0u8; request.len()];
+            stream.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, request.as_bytes());
+            stream.write_all(b"OK 1073741824\n").unwrap();
+            stream
+        }
+    }
+
+    #[test]
+    fn test_is_banner_received() {
+        assert!(!is_banner_received(b""));
+        assert!(!is_banner_received(b"OK 1073741824"));
+        assert!(!is_banner_received(b"OK 1073741824\n"));
+        assert!(!is_banner_received(b"ERR\nx"));
+        assert!(is_banner_received(b"OK 1073741824\nx"));
+    }
+
+    #[test]
+    fn test_missing_resources() {
+        let config = probe_config(ReadinessCheck::Vsock { port: 52 }, 1);
+        assert!(matches!(
+            ReadinessProbe::new(&config, None, None),
+            Err(ReadinessProbeError::MissingVsock)
+        ));
+        let config = probe_config(
+            ReadinessCheck::Mmds {
+                key: "ready".to_string(),
+            },
+            1,
+        );
+        assert!(matches!(
+            ReadinessProbe::new(&config, Some("/tmp/v.sock"), None),
+            Err(ReadinessProbeError::MissingMmds)
+        ));
+    }
+
+    #[test]
+    fn test_vsock_probe() {
+        let muxer = MockMuxer::new();
+        let config = probe_config(ReadinessCheck::Vsock { port: 52 }, 3);
+        let mut probe = ReadinessProbe::new(&config, Some(&muxer.path), None).unwrap();
+        assert_eq!(probe.status(), ReadinessProbeStatus::default());
+
+        probe.start();
+        // The guest accepts the connection, but does not send anything yet.
+        let _stream = muxer.accept(52);
+        probe.process_timer();
+        assert_eq!(
+            probe.status(),
+            ReadinessProbeStatus {
+                state: ReadinessProbeState::Pending,
+                attempts: 2,
+            }
+        );
+
+        // The next attempt receives the banner.
+        let mut stream = muxer.accept(52);
+        stream.write_all(b"ready\n").unwrap();
+        probe.process_timer();
+        assert_eq!(
+            probe.status(),
+            ReadinessProbeStatus {
+                state: ReadinessProbeState::Ready,
+                attempts: 2,
+            }
+        );
+        // The connection is closed once the probe is done.
+        let mut buf = [0u8; 1];
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+
+        // Further timer events are ignored.
+        probe.process_timer();
+        assert_eq!(probe.status().attempts, 2);
+    }
+
+    #[test]
+    fn test_vsock_probe_timeout() {
+        let muxer = MockMuxer::new();
+        let config = probe_config(ReadinessCheck::Vsock { port: 52 }, 2);
+        let mut probe = ReadinessProbe::new(&config, Some(&muxer.path), None).unwrap();
+
+        probe.start();
+        let _first = muxer.accept(52);
+        probe.process_timer();
+        // The guest closes the connection without sending anything.
+        drop(muxer.accept(52));
+        probe.process_timer();
+        assert_eq!(
+            probe.status(),
+            ReadinessProbeStatus {
+                state: ReadinessProbeState::Failed,
+                attempts: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_vsock_probe_no_device() {
+        let muxer = MockMuxer::new();
+        let path = muxer.path.clone();
+        // Nothing listens on the host socket.
+        drop(muxer);
+        let config = probe_config(ReadinessCheck::Vsock { port: 52 }, 3);
+        let mut probe = ReadinessProbe::new(&config, Some(&path), None).unwrap();
+
+        probe.start();
+        for _ in 0..3 {
+            probe.process_timer();
+        }
+        assert_eq!(
+            probe.status(),
+            ReadinessProbeStatus {
+                state: ReadinessProbeState::Failed,
+                attempts: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_mmds_probe() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        let config = probe_config(
+            ReadinessCheck::Mmds {
+                key: "ready".to_string(),
+            },
+            3,
+        );
+        let mut probe = ReadinessProbe::new(&config, None, Some(mmds.clone())).unwrap();
+        // The key is made writable by the guest.
+        assert_eq!(
+            mmds.lock().expect("Poisoned lock").guest_writable_key(),
+            Some("ready")
+        );
+
+        probe.start();
+        probe.process_timer();
+        assert_eq!(probe.status().state, ReadinessProbeState::Pending);
+
+        mmds.lock()
+            .expect("Poisoned lock")
+            .write_guest_value(serde_json::Value::from("up"))
+            .unwrap();
+        probe.process_timer();
+        assert_eq!(
+            probe.status(),
+            ReadinessProbeStatus {
+                state: ReadinessProbeState::Ready,
+                attempts: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_mmds_probe_timeout() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        let config = probe_config(
+            ReadinessCheck::Mmds {
+                key: "ready".to_string(),
+            },
+            2,
+        );
+        let mut probe = ReadinessProbe::new(&config, None, Some(mmds.clone())).unwrap();
+
+        probe.start();
+        // Values written by the host elsewhere do not count.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .put_data(serde_json::json!({"other": "up"}))
+            .unwrap();
+        probe.process_timer();
+        probe.process_timer();
+        assert_eq!(
+            probe.status(),
+            ReadinessProbeStatus {
+                state: ReadinessProbeState::Failed,
+                attempts: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_status_serialization() {
+        let status = ReadinessProbeStatus {
+            state: ReadinessProbeState::Pending,
+            attempts: 3,
+        };
+        assert_eq!(
+            serde_json::to_string(&status).unwrap(),
+            r#"{"state":"pending","attempts":3}"#
+        );
+    }
+}
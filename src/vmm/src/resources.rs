@@ -6,18 +6,20 @@ use std::path::PathBuf;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use utils::net::ipv4addr::is_link_local_valid;
 
 use crate::cpu_config::templates::CustomCpuTemplate;
 use crate::device_manager::persist::SharedDeviceType;
-use crate::logger::{info, log_dev_preview_warning};
-use crate::mmds;
+use crate::logger::{info, log_dev_preview_warning, warn};
 use crate::mmds::data_store::{Mmds, MmdsVersion};
 use crate::mmds::ns::MmdsNetworkStack;
 use crate::vmm_config::balloon::*;
 use crate::vmm_config::boot_source::{
     BootConfig, BootSource, BootSourceConfig, BootSourceConfigError,
 };
+use crate::vmm_config::config_schema::{migrate_config, ConfigSchemaError, SCHEMA_VERSION_KEY};
+use crate::vmm_config::console_scanner::{ConsoleScannerConfig, ConsoleScannerConfigError};
 use crate::vmm_config::drive::*;
 use crate::vmm_config::entropy::*;
 use crate::vmm_config::instance_info::InstanceInfo;
@@ -28,6 +30,7 @@ use crate::vmm_config::metrics::{init_metrics, MetricsConfig, MetricsConfigError
 use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use crate::vmm_config::net::*;
 use crate::vmm_config::vsock::*;
+use crate::{mmds, Vmm};
 
 /// Errors encountered when configuring microVM resources.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -38,6 +41,8 @@ pub enum ResourcesError {
     BlockDevice(#[from] DriveError),
     /// Boot source error: {0}
     BootSource(#[from] BootSourceConfigError),
+    /// Configuration file schema error: {0}
+    ConfigSchema(#[from] ConfigSchemaError),
     /// File operation error: {0}
     File(#[from] std::io::Error),
     /// Invalid JSON: {0}
@@ -58,6 +63,8 @@ pub enum ResourcesError {
     VsockDevice(#[from] VsockConfigError),
     /// Entropy device error: {0}
     EntropyDevice(#[from] EntropyDeviceError),
+    /// Console scanner config error: {0}
+    ConsoleScanner(#[from] ConsoleScannerConfigError),
 }
 
 /// Used for configuring a vmm from one single json passed to the Firecracker process.
@@ -85,6 +92,22 @@ pub struct VmmConfig {
     vsock_device: Option<VsockDeviceConfig>,
     #[serde(rename = "entropy")]
     entropy_device: Option<EntropyDeviceConfig>,
+    #[serde(rename = "console-scanner")]
+    console_scanner: Option<ConsoleScannerConfig>,
+}
+
+impl VmmConfig {
+    /// Parses the configuration file `config_json`, migrating it to the current schema version.
+    /// Returns the configuration along with the warnings about its migration.
+    pub fn from_json(config_json: &str) -> Result<(Self, Vec<String>), ResourcesError> {
+        let mut migrated = migrate_config(serde_json::from_str(config_json)?)?;
+        let warnings = migrated.warnings();
+        // The schema version describes the format of the file, not the microVM.
+        if let Value::Object(config) = &mut migrated.config {
+            config.remove(SCHEMA_VERSION_KEY);
+        }
+        Ok((serde_json::from_value(migrated.config)?, warnings))
+    }
 }
 
 /// A data structure that encapsulates the device configurations
@@ -105,6 +128,8 @@ pub struct VmResources {
     pub net_builder: NetBuilder,
     /// The entropy device builder.
     pub entropy: EntropyDeviceBuilder,
+    /// The configuration of the scanner of the guest console output.
+    pub console_scanner: Option<ConsoleScannerConfig>,
     /// The optional Mmds data store.
     // This is initialised on demand (if ever used), so that we don't allocate it unless it's
     // actually used.
@@ -123,11 +148,14 @@ impl VmResources {
         mmds_size_limit: usize,
         metadata_json: Option<&str>,
     ) -> Result<Self, ResourcesError> {
-        let vmm_config = serde_json::from_str::<VmmConfig>(config_json)?;
+        let (vmm_config, warnings) = VmmConfig::from_json(config_json)?;
 
         if let Some(logger_config) = vmm_config.logger {
             crate::logger::LOGGER.update(logger_config)?;
         }
+        for warning in warnings {
+            warn!("{warning}");
+        }
 
         if let Some(metrics) = vmm_config.metrics {
             init_metrics(metrics)?;
@@ -183,6 +211,10 @@ impl VmResources {
             resources.build_entropy_device(entropy_device_config)?;
         }
 
+        if let Some(console_scanner_config) = vmm_config.console_scanner {
+            resources.set_console_scanner(console_scanner_config)?;
+        }
+
         Ok(resources)
     }
 
@@ -217,10 +249,6 @@ impl VmResources {
 
             SharedDeviceType::Balloon(balloon) => {
                 self.balloon.set_device(balloon);
-
-                if self.vm_config.huge_pages != HugePageConfig::None {
-                    return Err(ResourcesError::BalloonDevice(BalloonConfigError::HugePages));
-                }
             }
 
             SharedDeviceType::Vsock(vsock) => {
@@ -239,6 +267,14 @@ impl VmResources {
         self.vm_config.track_dirty_pages
     }
 
+    /// Returns whether any of the block devices is backed by a vhost-user backend.
+    pub fn has_vhost_user_drives(&self) -> bool {
+        self.block
+            .devices
+            .iter()
+            .any(|drive| drive.lock().expect("Poisoned lock").is_vhost_user())
+    }
+
     /// Add a custom CPU template to the VM resources
     /// to configure vCPUs.
     pub fn set_custom_cpu_template(&mut self, cpu_template: CustomCpuTemplate) {
@@ -266,13 +302,7 @@ impl VmResources {
             return Err(VmConfigError::IncompatibleBalloonSize);
         }
 
-        if self.balloon.get().is_some() && updated.huge_pages != HugePageConfig::None {
-            return Err(VmConfigError::BalloonAndHugePages);
-        }
-
-        if self.boot_source.config.initrd_path.is_some()
-            && updated.huge_pages != HugePageConfig::None
-        {
+        if self.boot_source.config.has_initrd() && updated.huge_pages != HugePageConfig::None {
             return Err(VmConfigError::InitrdAndHugePages);
         }
 
@@ -296,10 +326,18 @@ impl VmResources {
             .collect();
 
         if !net_devs_with_mmds.is_empty() {
+            let mmds = mmds.lock().expect("Poisoned lock");
+            let limit_bytes = mmds.data_store_info().limit_bytes;
             let mut inner_mmds_config = MmdsConfig {
-                version: mmds.lock().expect("Poisoned lock").version(),
+                version: mmds.version(),
                 network_interfaces: vec![],
                 ipv4_address: None,
+                // Only limits set through the MMDS config are expressed in MiB.
+                data_store_limit_mib: if limit_bytes % (1 << 20) == 0 {
+                    u32::try_from(limit_bytes >> 20).ok()
+                } else {
+                    None
+                },
             };
 
             for net_dev in net_devs_with_mmds {
@@ -340,10 +378,6 @@ impl VmResources {
             return Err(BalloonConfigError::TooManyPagesRequested);
         }
 
-        if self.vm_config.huge_pages != HugePageConfig::None {
-            return Err(BalloonConfigError::HugePages);
-        }
-
         self.balloon.set(config)
     }
 
@@ -352,9 +386,7 @@ impl VmResources {
         &mut self,
         boot_source_cfg: BootSourceConfig,
     ) -> Result<(), BootSourceConfigError> {
-        if boot_source_cfg.initrd_path.is_some()
-            && self.vm_config.huge_pages != HugePageConfig::None
-        {
+        if boot_source_cfg.has_initrd() && self.vm_config.huge_pages != HugePageConfig::None {
             return Err(BootSourceConfigError::HugePagesAndInitRd);
         }
 
@@ -378,6 +410,49 @@ impl VmResources {
         self.block.insert(block_device_config)
     }
 
+    /// Builds a block device and hot-plugs it in the running microVM. The microVM is left
+    /// untouched if the device can't be built or plugged.
+    pub fn hotplug_block_device(
+        &mut self,
+        block_device_config: BlockDeviceConfig,
+        vmm: &mut Vmm,
+    ) -> Result<(), DriveError> {
+        // Check for a free slot first, so that the backing file isn't opened in vain.
+        if !vmm.has_free_hotplug_slot() {
+            return Err(DriveError::NoHotplugSlot);
+        }
+        let block = self.block.build_hotplug(block_device_config)?;
+        vmm.hotplug_block_device(block.clone())
+            .map_err(DriveError::Hotplug)?;
+        self.block.add_virtio_device(block);
+        Ok(())
+    }
+
+    /// Removes a block device before the VM starts.
+    pub fn remove_block_device(&mut self, drive_id: &str) -> Result<(), DriveError> {
+        self.block.remove(drive_id)?;
+        Ok(())
+    }
+
+    /// Unplugs a block device from the running microVM, which completes or fails its pending
+    /// requests and frees its slot for hot-plugging another device.
+    pub fn unplug_block_device(&mut self, drive_id: &str, vmm: &mut Vmm) -> Result<(), DriveError> {
+        // Check before unplugging, so that the device is left untouched on error.
+        let block = self
+            .block
+            .devices
+            .iter()
+            .find(|block| block.lock().expect("Poisoned lock").id() == drive_id)
+            .ok_or_else(|| DriveError::InvalidDriveId(drive_id.to_string()))?;
+        if block.lock().expect("Poisoned lock").root_device() {
+            return Err(DriveError::RemoveRootDevice);
+        }
+        vmm.remove_block_device(drive_id)
+            .map_err(DriveError::Unplug)?;
+        self.block.remove(drive_id)?;
+        Ok(())
+    }
+
     /// Builds a network device to be attached when the VM starts.
     pub fn build_net_device(
         &mut self,
@@ -387,6 +462,58 @@ impl VmResources {
         Ok(())
     }
 
+    /// Builds a network device and hot-plugs it in the running microVM. The microVM is left
+    /// untouched if the device can't be built or plugged.
+    pub fn hotplug_net_device(
+        &mut self,
+        body: NetworkInterfaceConfig,
+        vmm: &mut Vmm,
+    ) -> Result<(), NetworkInterfaceError> {
+        // Check for a free slot first, so that the tap isn't opened in vain.
+        if !vmm.has_free_hotplug_slot() {
+            return Err(NetworkInterfaceError::NoHotplugSlot);
+        }
+        let net = self.net_builder.build_hotplug(body)?;
+        vmm.hotplug_net_device(net.clone())
+            .map_err(NetworkInterfaceError::Hotplug)?;
+        self.net_builder.add_device(net);
+        Ok(())
+    }
+
+    /// Removes a network device before the VM starts.
+    pub fn remove_net_device(&mut self, iface_id: &str) -> Result<(), NetworkInterfaceError> {
+        self.net_builder.remove(iface_id)?;
+        Ok(())
+    }
+
+    /// Unplugs a network device from the running microVM, which stops it and frees its slot for
+    /// hot-plugging another device.
+    pub fn unplug_net_device(
+        &mut self,
+        iface_id: &str,
+        vmm: &mut Vmm,
+    ) -> Result<(), NetworkInterfaceError> {
+        if !self
+            .net_builder
+            .iter()
+            .any(|net| net.lock().expect("Poisoned lock").id() == iface_id)
+        {
+            return Err(NetworkInterfaceError::InvalidIfaceId(iface_id.to_string()));
+        }
+        vmm.remove_net_device(iface_id)
+            .map_err(NetworkInterfaceError::Unplug)?;
+        self.net_builder.remove(iface_id)?;
+        Ok(())
+    }
+
+    /// Updates a network device before the VM starts.
+    pub fn update_net_device(
+        &mut self,
+        update: NetworkInterfaceUpdateConfig,
+    ) -> Result<(), NetworkInterfaceError> {
+        self.net_builder.update(&update)
+    }
+
     /// Sets a vsock device to be attached when the VM starts.
     pub fn set_vsock_device(&mut self, config: VsockDeviceConfig) -> Result<(), VsockConfigError> {
         self.vsock.insert(config)
@@ -400,18 +527,70 @@ impl VmResources {
         self.entropy.insert(body)
     }
 
+    /// Sets the configuration of the scanner of the guest console output.
+    pub fn set_console_scanner(
+        &mut self,
+        config: ConsoleScannerConfig,
+    ) -> Result<(), ConsoleScannerConfigError> {
+        config.validate()?;
+        self.console_scanner = Some(config);
+        Ok(())
+    }
+
     /// Setter for mmds config.
     pub fn set_mmds_config(
         &mut self,
         config: MmdsConfig,
         instance_id: &str,
     ) -> Result<(), MmdsConfigError> {
+        if let Some(limit) = config.data_store_limit() {
+            self.set_mmds_data_store_limit(limit)?;
+        }
         self.set_mmds_network_stack_config(&config)?;
         self.set_mmds_version(config.version, instance_id)?;
 
         Ok(())
     }
 
+    /// Updates the mmds config after the microVM has started. Only the data store limit can
+    /// change, the rest of the configuration must match the one already in place.
+    pub fn update_mmds_config(&mut self, config: MmdsConfig) -> Result<(), MmdsConfigError> {
+        let limit = config
+            .data_store_limit()
+            .ok_or(MmdsConfigError::UnsupportedPostBootUpdate)?;
+        let current = self
+            .mmds_config()
+            .ok_or(MmdsConfigError::UnsupportedPostBootUpdate)?;
+
+        let mut network_interfaces = config.network_interfaces();
+        network_interfaces.sort();
+        let mut current_network_interfaces = current.network_interfaces();
+        current_network_interfaces.sort();
+        if config.version() != current.version()
+            || network_interfaces != current_network_interfaces
+            || config
+                .ipv4_addr()
+                .unwrap_or(MmdsNetworkStack::default_ipv4_addr())
+                != current
+                    .ipv4_addr()
+                    .unwrap_or(MmdsNetworkStack::default_ipv4_addr())
+        {
+            return Err(MmdsConfigError::UnsupportedPostBootUpdate);
+        }
+
+        self.set_mmds_data_store_limit(limit)
+    }
+
+    /// Updates the MMDS data store limit, in bytes.
+    pub fn set_mmds_data_store_limit(&mut self, limit: usize) -> Result<(), MmdsConfigError> {
+        self.locked_mmds_or_default()
+            .set_data_store_limit(limit)
+            .map_err(MmdsConfigError::DataStoreLimit)?;
+        self.mmds_size_limit = limit;
+
+        Ok(())
+    }
+
     /// Updates MMDS version.
     pub fn set_mmds_version(
         &mut self,
@@ -489,6 +668,7 @@ impl From<&VmResources> for VmmConfig {
             net_devices: resources.net_builder.configs(),
             vsock_device: resources.vsock.config(),
             entropy_device: resources.entropy.config(),
+            console_scanner: resources.console_scanner.clone(),
         }
     }
 }
@@ -533,9 +713,17 @@ mod tests {
                 .to_str()
                 .unwrap()
                 .to_string(),
+            tap_fd: None,
             guest_mac: Some(MacAddr::from_str("01:23:45:67:89:0a").unwrap()),
             rx_rate_limiter: Some(RateLimiterConfig::default()),
             tx_rate_limiter: Some(RateLimiterConfig::default()),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
         }
     }
 
@@ -558,9 +746,22 @@ mod tests {
                 is_read_only: Some(false),
                 path_on_host: Some(tmp_file.as_path().to_str().unwrap().to_string()),
                 rate_limiter: Some(RateLimiterConfig::default()),
+                rate_limiter_scope: None,
                 file_engine_type: None,
+                detect_zeroes: None,
+                open_direct: None,
+                io_engine_opts: None,
+                queue_size: None,
+                num_queues: None,
+                serial: None,
+                logical_block_size: None,
+                physical_block_size: None,
+                on_host_enospc: None,
+                io_uring: None,
 
                 socket: None,
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
             },
             tmp_file,
         )
@@ -599,6 +800,7 @@ mod tests {
             boot_timer: false,
             mmds_size_limit: HTTP_MAX_PAYLOAD_SIZE,
             entropy: Default::default(),
+            console_scanner: None,
         }
     }
 
@@ -1036,6 +1238,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_vmm_config_from_json_schema_version() {
+        let (v2_config, warnings) = VmmConfig::from_json(include_str!(
+            "vmm_config/config_schema/test_fixtures/v2.json"
+        ))
+        .unwrap();
+        assert!(warnings.is_empty());
+
+        // The configuration files of the previous schema version are migrated.
+        let (v1_config, warnings) = VmmConfig::from_json(include_str!(
+            "vmm_config/config_schema/test_fixtures/v1.json"
+        ))
+        .unwrap();
+        assert_eq!(v1_config, v2_config);
+        assert_eq!(warnings.len(), 5);
+        assert!(v1_config.machine_config.is_some());
+        assert_eq!(
+            v1_config.mmds_config.unwrap().network_interfaces,
+            vec!["eth0".to_string()]
+        );
+
+        let (unversioned_config, warnings) = VmmConfig::from_json(include_str!(
+            "vmm_config/config_schema/test_fixtures/v1_unversioned.json"
+        ))
+        .unwrap();
+        assert_eq!(unversioned_config, v2_config);
+        assert_eq!(warnings.len(), 6);
+
+        // The configuration files of an unknown schema version are rejected.
+        let error = VmmConfig::from_json(r#"{ "schema_version": 3 }"#).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                ResourcesError::ConfigSchema(ConfigSchemaError::FutureSchemaVersion(3, 2))
+            ),
+            "{:?}",
+            error
+        );
+    }
+
     #[test]
     fn test_cpu_config_from_invalid_json() {
         // Invalid cpu config file path.
@@ -1162,7 +1404,10 @@ mod tests {
                         "mem_size_mib": 1024,
                         "smt": false
                     }},
-                    "entropy": {{}}
+                    "entropy": {{}},
+                    "console-scanner": {{
+                        "on_match": "pause"
+                    }}
             }}"#,
                 kernel_file.as_path().to_str().unwrap(),
                 rootfs_file.as_path().to_str().unwrap(),
@@ -1332,6 +1577,12 @@ mod tests {
             cpu_template: Some(StaticCpuTemplate::V1N1),
             track_dirty_pages: Some(false),
             huge_pages: Some(HugePageConfig::None),
+            smbios: None,
+            gic: None,
+            readiness_probe: None,
+            serial_ports: None,
+            auto_pause: None,
+            hotplug_slots: None,
         };
 
         assert_ne!(
@@ -1389,6 +1640,8 @@ mod tests {
                 amount_mib: 100,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 0,
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
             })
             .unwrap();
         aux_vm_config.mem_size_mib = Some(90);
@@ -1412,9 +1665,6 @@ mod tests {
         if KernelVersion::get().unwrap() >= KernelVersion::new(5, 10, 0) {
             // mem_size_mib compatible with huge page configuration
             aux_vm_config.mem_size_mib = Some(2048);
-            // Remove the balloon device config that's added by `default_vm_resources` as it would
-            // trigger the "ballooning incompatible with huge pages" check.
-            vm_resources.balloon = BalloonBuilder::new();
             vm_resources.update_vm_config(&aux_vm_config).unwrap();
         }
     }
@@ -1427,6 +1677,8 @@ mod tests {
             amount_mib: 100,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         assert!(vm_resources.balloon.get().is_none());
         vm_resources
@@ -1453,7 +1705,7 @@ mod tests {
     }
 
     #[test]
-    fn test_negative_restore_balloon_device_with_huge_pages() {
+    fn test_restore_balloon_device_with_huge_pages() {
         if KernelVersion::get().unwrap() >= KernelVersion::new(4, 16, 0) {
             let mut vm_resources = default_vm_resources();
             vm_resources.balloon = BalloonBuilder::new();
@@ -1463,19 +1715,12 @@ mod tests {
                     ..Default::default()
                 })
                 .unwrap();
-            let err = vm_resources
+            vm_resources
                 .update_from_restored_device(SharedDeviceType::Balloon(Arc::new(Mutex::new(
                     Balloon::new(128, false, 0, true).unwrap(),
                 ))))
-                .unwrap_err();
-            assert!(
-                matches!(
-                    err,
-                    ResourcesError::BalloonDevice(BalloonConfigError::HugePages)
-                ),
-                "{:?}",
-                err
-            );
+                .unwrap();
+            assert!(vm_resources.balloon.get().is_some());
         }
     }
 
@@ -1494,6 +1739,27 @@ mod tests {
         assert_eq!(actual_entropy_cfg, entropy_device_cfg);
     }
 
+    #[test]
+    fn test_set_console_scanner() {
+        let mut vm_resources = default_vm_resources();
+        let config = ConsoleScannerConfig {
+            patterns: Some(vec![String::new()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            vm_resources.set_console_scanner(config).unwrap_err(),
+            ConsoleScannerConfigError::InvalidPattern(String::new())
+        );
+        assert!(vm_resources.console_scanner.is_none());
+
+        let config = ConsoleScannerConfig {
+            patterns: Some(vec!["Kernel panic".to_string()]),
+            ..Default::default()
+        };
+        vm_resources.set_console_scanner(config.clone()).unwrap();
+        assert_eq!(vm_resources.console_scanner, Some(config));
+    }
+
     #[test]
     fn test_boot_config() {
         let vm_resources = default_vm_resources();
@@ -1511,6 +1777,7 @@ mod tests {
             kernel_image_path: String::from(tmp_file.as_path().to_str().unwrap()),
             initrd_path: Some(String::from(tmp_file.as_path().to_str().unwrap())),
             boot_args: Some(cmdline.to_string()),
+            ..Default::default()
         };
 
         let mut vm_resources = default_vm_resources();
@@ -1578,6 +1845,20 @@ mod tests {
         assert_eq!(vm_resources.block.devices.len(), 2);
     }
 
+    #[test]
+    fn test_remove_block_device() {
+        let mut vm_resources = default_vm_resources();
+        assert_eq!(vm_resources.block.devices.len(), 1);
+
+        vm_resources.remove_block_device("block1").unwrap();
+        assert!(vm_resources.block.devices.is_empty());
+        // Removing it again fails cleanly.
+        assert!(matches!(
+            vm_resources.remove_block_device("block1"),
+            Err(DriveError::InvalidDriveId(_))
+        ));
+    }
+
     #[test]
     fn test_set_vsock_device() {
         let mut vm_resources = default_vm_resources();
@@ -1604,4 +1885,79 @@ mod tests {
         vm_resources.build_net_device(new_net_device_cfg).unwrap();
         assert_eq!(vm_resources.net_builder.len(), 2);
     }
+
+    #[test]
+    fn test_remove_net_device() {
+        let mut vm_resources = default_vm_resources();
+        assert_eq!(vm_resources.net_builder.len(), 1);
+
+        vm_resources.remove_net_device("net_if1").unwrap();
+        assert!(vm_resources.net_builder.is_empty());
+        // Removing it again fails cleanly.
+        assert!(matches!(
+            vm_resources.remove_net_device("net_if1"),
+            Err(NetworkInterfaceError::InvalidIfaceId(_))
+        ));
+    }
+
+    #[test]
+    fn test_set_mmds_data_store_limit() {
+        let mut vm_resources = default_vm_resources();
+        let mut mmds_config = MmdsConfig {
+            version: MmdsVersion::V2,
+            network_interfaces: vec!["net_if1".to_string()],
+            ipv4_address: Some(MmdsNetworkStack::default_ipv4_addr()),
+            data_store_limit_mib: Some(1),
+        };
+        vm_resources
+            .set_mmds_config(mmds_config.clone(), "instance")
+            .unwrap();
+        assert_eq!(vm_resources.mmds_size_limit, 1 << 20);
+        assert_eq!(
+            vm_resources
+                .locked_mmds_or_default()
+                .data_store_info()
+                .limit_bytes,
+            1 << 20
+        );
+        assert_eq!(vm_resources.mmds_config(), Some(mmds_config.clone()));
+
+        // Grow the data store past 1 MiB after raising the limit.
+        mmds_config.data_store_limit_mib = Some(2);
+        vm_resources
+            .update_mmds_config(mmds_config.clone())
+            .unwrap();
+        let filling = "X".repeat(1 << 20);
+        vm_resources
+            .locked_mmds_or_default()
+            .put_data(serde_json::json!({ "key": filling }))
+            .unwrap();
+
+        // Shrinking below the current usage is rejected and leaves the limit untouched.
+        mmds_config.data_store_limit_mib = Some(1);
+        assert_eq!(
+            vm_resources
+                .update_mmds_config(mmds_config.clone())
+                .unwrap_err()
+                .to_string(),
+            "The MMDS data store limit could not be updated: The MMDS data store limit (1048576 \
+             bytes) cannot be lower than its current size (1048586 bytes)."
+        );
+        assert_eq!(vm_resources.mmds_size_limit, 2 << 20);
+
+        // Only the limit can change post-boot.
+        mmds_config.data_store_limit_mib = Some(3);
+        mmds_config.version = MmdsVersion::V1;
+        assert!(matches!(
+            vm_resources.update_mmds_config(mmds_config.clone()),
+            Err(MmdsConfigError::UnsupportedPostBootUpdate)
+        ));
+        mmds_config.version = MmdsVersion::V2;
+        mmds_config.data_store_limit_mib = None;
+        assert!(matches!(
+            vm_resources.update_mmds_config(mmds_config),
+            Err(MmdsConfigError::UnsupportedPostBootUpdate)
+        ));
+        assert_eq!(vm_resources.mmds_size_limit, 2 << 20);
+    }
 }
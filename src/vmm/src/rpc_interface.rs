@@ -8,27 +8,31 @@ use seccompiler::BpfThreadMap;
 use serde_json::Value;
 #[cfg(test)]
 use tests::{
-    build_and_boot_microvm, create_snapshot, restore_from_snapshot, MockVmRes as VmResources,
-    MockVmm as Vmm,
+    build_and_boot_microvm, clone_microvm, create_snapshot, restore_from_snapshot,
+    MockVmRes as VmResources, MockVmm as Vmm,
 };
 
 use super::VmmError;
 #[cfg(not(test))]
 use super::{
-    builder::build_and_boot_microvm, persist::create_snapshot, persist::restore_from_snapshot,
-    resources::VmResources, Vmm,
+    builder::build_and_boot_microvm, persist::clone_microvm, persist::create_snapshot,
+    persist::restore_from_snapshot, resources::VmResources, Vmm,
 };
+use crate::access_profile::AccessProfile;
 use crate::builder::StartMicrovmError;
 use crate::cpu_config::templates::{CustomCpuTemplate, GuestConfigError};
+use crate::devices::virtio::vhost_user::VhostUserNegotiation;
+use crate::events::{SnapshotOperation, VmmEvent, EVENTS};
 use crate::logger::{info, warn, LoggerConfig, *};
-use crate::mmds::data_store::{self, Mmds};
-use crate::persist::{CreateSnapshotError, RestoreFromSnapshotError, VmInfo};
+use crate::mmds::data_store::{self, Mmds, MmdsDataStoreInfo};
+use crate::persist::{CloneMicrovmError, CreateSnapshotError, RestoreFromSnapshotError, VmInfo};
 use crate::resources::VmmConfig;
 use crate::vmm_config::balloon::{
     BalloonConfigError, BalloonDeviceConfig, BalloonStats, BalloonUpdateConfig,
     BalloonUpdateStatsConfig,
 };
 use crate::vmm_config::boot_source::{BootSourceConfig, BootSourceConfigError};
+use crate::vmm_config::console_scanner::{ConsoleScannerConfig, ConsoleScannerConfigError};
 use crate::vmm_config::drive::{BlockDeviceConfig, BlockDeviceUpdateConfig, DriveError};
 use crate::vmm_config::entropy::{EntropyDeviceConfig, EntropyDeviceError};
 use crate::vmm_config::instance_info::InstanceInfo;
@@ -38,8 +42,14 @@ use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use crate::vmm_config::net::{
     NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceUpdateConfig,
 };
-use crate::vmm_config::snapshot::{CreateSnapshotParams, LoadSnapshotParams, SnapshotType};
-use crate::vmm_config::vsock::{VsockConfigError, VsockDeviceConfig};
+use crate::vmm_config::snapshot::{
+    CloneMicrovmParams, CreateSnapshotParams, LoadSnapshotParams, SnapshotType,
+    TrackAccessConfigError,
+};
+use crate::vmm_config::vhost_user::{
+    VhostUserConfigError, VhostUserPingConfig, VhostUserPingResult,
+};
+use crate::vmm_config::vsock::{VsockConfigError, VsockConnections, VsockDeviceConfig};
 use crate::vmm_config::{self, RateLimiterUpdate};
 use crate::EventManager;
 
@@ -47,6 +57,10 @@ use crate::EventManager;
 /// bits of information (ids, paths, etc.).
 #[derive(Debug, PartialEq, Eq)]
 pub enum VmmAction {
+    /// Clone the microVM into another Firecracker process using as input the
+    /// `CloneMicrovmParams`. This action can only be called after the microVM has booted and only
+    /// when the microVM is in `Paused` state.
+    CloneMicrovm(CloneMicrovmParams),
     /// Configure the boot source of the microVM using as input the `ConfigureBootSource`. This
     /// action can only be called before the microVM has booted.
     ConfigureBootSource(BootSourceConfig),
@@ -59,6 +73,9 @@ pub enum VmmAction {
     /// Create a snapshot using as input the `CreateSnapshotParams`. This action can only be called
     /// after the microVM has booted and only when the microVM is in `Paused` state.
     CreateSnapshot(CreateSnapshotParams),
+    /// Get the guest pages touched after the restored microVM resumed. This action can only be
+    /// called after a snapshot was loaded with memory access tracking.
+    GetAccessProfile,
     /// Get the balloon device configuration.
     GetBalloonConfig,
     /// Get the ballon device latest statistics.
@@ -67,20 +84,29 @@ pub enum VmmAction {
     GetFullVmConfig,
     /// Get MMDS contents.
     GetMMDS,
+    /// Get the MMDS data store usage and limit.
+    GetMmdsInfo,
+    /// Get the summary of the negotiation of the vhost-user device with the given id with its
+    /// backend. This action can only be called after the microVM has booted.
+    GetVhostUserInfo(String),
     /// Get the machine configuration of the microVM.
     GetVmMachineConfig,
     /// Get microVM instance information.
     GetVmInstanceInfo,
     /// Get microVM version.
     GetVmmVersion,
+    /// Get a snapshot of the connection table of the vsock device. This action can only be
+    /// called after the microVM has booted.
+    GetVsockConnections,
     /// Flush the metrics. This action can only be called after the logger has been configured.
     FlushMetrics,
     /// Add a new block device or update one that already exists using the `BlockDeviceConfig` as
-    /// input. This action can only be called before the microVM has booted.
+    /// input. After the microVM has booted, this action hot-plugs a new block device in one of the
+    /// slots reserved with `hotplug_slots`.
     InsertBlockDevice(BlockDeviceConfig),
     /// Add a new network interface config or update one that already exists using the
-    /// `NetworkInterfaceConfig` as input. This action can only be called before the microVM has
-    /// booted.
+    /// `NetworkInterfaceConfig` as input. After the microVM has booted, this action hot-plugs a
+    /// new network interface in one of the slots reserved with `hotplug_slots`.
     InsertNetworkDevice(NetworkInterfaceConfig),
     /// Load the microVM state using as input the `LoadSnapshotParams`. This action can only be
     /// called before the microVM has booted. If this action is successful, the loaded microVM will
@@ -90,8 +116,18 @@ pub enum VmmAction {
     PatchMMDS(Value),
     /// Pause the guest, by pausing the microVM VCPUs.
     Pause,
+    /// Check that the backend of a vhost-user device replies to requests, using as input the
+    /// `VhostUserPingConfig`. This action can only be called after the microVM has booted.
+    PingVhostUser(VhostUserPingConfig),
     /// Repopulate the MMDS contents.
     PutMMDS(Value),
+    /// Remove the block device with the given ID. Before the microVM has booted, this drops its
+    /// config. After, this unplugs it, freeing its slot for hot-plugging another device. The root
+    /// block device can't be removed.
+    RemoveBlockDevice(String),
+    /// Remove the network interface with the given ID. Before the microVM has booted, this drops
+    /// its config. After, this unplugs it, freeing its slot for hot-plugging another interface.
+    RemoveNetworkDevice(String),
     /// Configure the guest vCPU features.
     PutCpuConfiguration(CustomCpuTemplate),
     /// Resume the guest, by resuming the microVM VCPUs.
@@ -100,7 +136,11 @@ pub enum VmmAction {
     /// `BalloonDeviceConfig` as input. This action can only be called before the microVM
     /// has booted.
     SetBalloonDevice(BalloonDeviceConfig),
-    /// Set the MMDS configuration.
+    /// Set the configuration of the scanner of the guest console output. This action can only
+    /// be called before the microVM has booted or has been loaded from a snapshot.
+    SetConsoleScanner(ConsoleScannerConfig),
+    /// Set the MMDS configuration. After the microVM has booted, only the data store limit can
+    /// be updated.
     SetMmdsConfiguration(MmdsConfig),
     /// Set the vsock device or update the one that already exists using the
     /// `VsockDeviceConfig` as input. This action can only be called before the microVM has
@@ -121,8 +161,9 @@ pub enum VmmAction {
     UpdateBalloonStatistics(BalloonUpdateStatsConfig),
     /// Update existing block device properties such as `path_on_host` or `rate_limiter`.
     UpdateBlockDevice(BlockDeviceUpdateConfig),
-    /// Update a network interface, after microVM start. Currently, the only updatable properties
-    /// are the RX and TX rate limiters.
+    /// Update a network interface. Currently, the only updatable properties are the RX and TX
+    /// rate limiters and the guest MAC address, which can not be updated while the microVM is
+    /// running.
     UpdateNetworkInterface(NetworkInterfaceUpdateConfig),
     /// Update the microVM configuration (memory & vcpu) using `VmUpdateConfig` as input. This
     /// action can only be called before the microVM has booted.
@@ -132,14 +173,20 @@ pub enum VmmAction {
 /// Wrapper for all errors associated with VMM actions.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum VmmActionError {
+    /// The memory accesses are not tracked: the snapshot was not loaded with `track_access`.
+    AccessProfileNotTracked,
     /// Balloon config error: {0}
     BalloonConfig(#[from] BalloonConfigError),
     /// Boot source error: {0}
     BootSource(#[from] BootSourceConfigError),
+    /// Clone microVM error: {0}
+    CloneMicrovm(#[from] CloneMicrovmError),
     /// Create snapshot error: {0}
     CreateSnapshot(#[from] CreateSnapshotError),
     /// Configure CPU error: {0}
     ConfigureCpu(#[from] GuestConfigError),
+    /// Console scanner config error: {0}
+    ConsoleScanner(#[from] ConsoleScannerConfigError),
     /// Drive config error: {0}
     DriveConfig(#[from] DriveError),
     /// Entropy device error: {0}
@@ -172,6 +219,8 @@ pub enum VmmActionError {
     OperationNotSupportedPreBoot,
     /// Start microvm error: {0}
     StartMicrovm(#[from] StartMicrovmError),
+    /// Vhost-user device error: {0}
+    VhostUser(#[from] VhostUserConfigError),
     /// Vsock config error: {0}
     VsockConfig(#[from] VsockConfigError),
 }
@@ -181,6 +230,8 @@ pub enum VmmActionError {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, PartialEq, Eq)]
 pub enum VmmData {
+    /// The guest pages touched after the restored microVM resumed.
+    AccessProfile(AccessProfile),
     /// The balloon device configuration.
     BalloonConfig(BalloonDeviceConfig),
     /// The latest balloon device statistics.
@@ -193,10 +244,18 @@ pub enum VmmData {
     MachineConfiguration(MachineConfig),
     /// Mmds contents.
     MmdsValue(serde_json::Value),
+    /// Mmds data store usage and limit.
+    MmdsInfo(MmdsDataStoreInfo),
     /// The microVM instance information.
     InstanceInformation(InstanceInfo),
+    /// The summary of the negotiation of a vhost-user device with its backend.
+    VhostUserInfo(VhostUserNegotiation),
+    /// The outcome of a health check of the backend of a vhost-user device.
+    VhostUserPing(VhostUserPingResult),
     /// The microVM version.
     VmmVersion(String),
+    /// The connection table of the vsock device.
+    VsockConnections(VsockConnections),
 }
 
 /// Trait used for deduplicating the MMDS request handling across the two ApiControllers.
@@ -209,15 +268,17 @@ trait MmdsRequestHandler {
         Ok(VmmData::MmdsValue(self.mmds().data_store_value()))
     }
 
+    fn get_mmds_info(&mut self) -> Result<VmmData, VmmActionError> {
+        Ok(VmmData::MmdsInfo(self.mmds().data_store_info()))
+    }
+
     fn patch_mmds(&mut self, value: serde_json::Value) -> Result<VmmData, VmmActionError> {
         self.mmds()
             .patch_data(value)
             .map(|()| VmmData::Empty)
             .map_err(|err| match err {
-                data_store::MmdsDatastoreError::DataStoreLimitExceeded => {
-                    VmmActionError::MmdsLimitExceeded(
-                        data_store::MmdsDatastoreError::DataStoreLimitExceeded,
-                    )
+                data_store::MmdsDatastoreError::DataStoreLimitExceeded(..) => {
+                    VmmActionError::MmdsLimitExceeded(err)
                 }
                 _ => VmmActionError::Mmds(err),
             })
@@ -228,10 +289,8 @@ trait MmdsRequestHandler {
             .put_data(value)
             .map(|()| VmmData::Empty)
             .map_err(|err| match err {
-                data_store::MmdsDatastoreError::DataStoreLimitExceeded => {
-                    VmmActionError::MmdsLimitExceeded(
-                        data_store::MmdsDatastoreError::DataStoreLimitExceeded,
-                    )
+                data_store::MmdsDatastoreError::DataStoreLimitExceeded(..) => {
+                    VmmActionError::MmdsLimitExceeded(err)
                 }
                 _ => VmmActionError::Mmds(err),
             })
@@ -279,6 +338,8 @@ impl MmdsRequestHandler for PrebootApiController<'_> {
 pub enum LoadSnapshotError {
     /// Loading a microVM snapshot not allowed after configuring boot-specific resources.
     LoadSnapshotNotAllowed,
+    /// Invalid memory access tracking configuration: {0}
+    TrackAccess(#[from] TrackAccessConfigError),
     /// Failed to restore from snapshot: {0}
     RestoreFromSnapshot(#[from] RestoreFromSnapshotError),
     /// Failed to resume microVM: {0}
@@ -335,6 +396,50 @@ impl<'a> PrebootApiController<'a> {
         mmds_size_limit: usize,
         metadata_json: Option<&str>,
     ) -> Result<(VmResources, Arc<Mutex<Vmm>>), BuildMicrovmFromRequestsError> {
+        Self::build_microvm_from_served_requests(
+            seccomp_filters,
+            event_manager,
+            instance_info,
+            boot_timer_enabled,
+            mmds_size_limit,
+            metadata_json,
+            |preboot_controller| {
+                // Get request
+                let req = from_api
+                    .recv()
+                    .expect("The channel's sending half was disconnected. Cannot receive data.");
+
+                // Also consume the API event along with the message. It is safe to unwrap()
+                // because this event_fd is blocking.
+                api_event_fd
+                    .read()
+                    .expect("VMM: Failed to read the API event_fd");
+
+                // Process the request.
+                let res = preboot_controller.handle_preboot_request(*req);
+
+                // Send back the response.
+                to_api.send(Box::new(res)).expect("one-shot channel closed");
+            },
+        )
+    }
+
+    /// Builds and starts a microVM from the API requests served by `serve_request`, which
+    /// handles the next API request with the given controller every time it is called.
+    ///
+    /// Returns a populated `VmResources` object and a running `Vmm` object.
+    pub fn build_microvm_from_served_requests<F>(
+        seccomp_filters: &BpfThreadMap,
+        event_manager: &mut EventManager,
+        instance_info: InstanceInfo,
+        boot_timer_enabled: bool,
+        mmds_size_limit: usize,
+        metadata_json: Option<&str>,
+        mut serve_request: F,
+    ) -> Result<(VmResources, Arc<Mutex<Vmm>>), BuildMicrovmFromRequestsError>
+    where
+        F: FnMut(&mut PrebootApiController<'_>),
+    {
         let mut vm_resources = VmResources::default();
         // Silence false clippy warning. Clippy suggests using
         // VmResources { boot_timer: boot_timer_enabled, ..Default::default() }; but this will
@@ -365,22 +470,7 @@ impl<'a> PrebootApiController<'a> {
         // Iterate through API calls to configure microVm.
         // The loop breaks when a microVM is successfully started, and a running Vmm is built.
         while preboot_controller.built_vmm.is_none() {
-            // Get request
-            let req = from_api
-                .recv()
-                .expect("The channel's sending half was disconnected. Cannot receive data.");
-
-            // Also consume the API event along with the message. It is safe to unwrap()
-            // because this event_fd is blocking.
-            api_event_fd
-                .read()
-                .expect("VMM: Failed to read the API event_fd");
-
-            // Process the request.
-            let res = preboot_controller.handle_preboot_request(*req);
-
-            // Send back the response.
-            to_api.send(Box::new(res)).expect("one-shot channel closed");
+            serve_request(&mut preboot_controller);
 
             // If any fatal errors were encountered, break the loop.
             if let Some(preboot_error) = preboot_controller.fatal_error {
@@ -420,10 +510,15 @@ impl<'a> PrebootApiController<'a> {
                 Ok(VmmData::FullVmConfig((&*self.vm_resources).into()))
             }
             GetMMDS => self.get_mmds(),
-            GetVmMachineConfig => Ok(VmmData::MachineConfiguration(MachineConfig::from(
-                &self.vm_resources.vm_config,
-            ))),
-            GetVmInstanceInfo => Ok(VmmData::InstanceInformation(self.instance_info.clone())),
+            GetMmdsInfo => self.get_mmds_info(),
+            GetVmMachineConfig => Ok(VmmData::MachineConfiguration(MachineConfig {
+                memory_regions: self.vm_resources.vm_config.memory_layout().ok(),
+                ..MachineConfig::from(&self.vm_resources.vm_config)
+            })),
+            GetVmInstanceInfo => Ok(VmmData::InstanceInformation(InstanceInfo {
+                uuid: self.vm_resources.vm_config.smbios_uuid(),
+                ..self.instance_info.clone()
+            })),
             GetVmmVersion => Ok(VmmData::VmmVersion(self.instance_info.vmm_version.clone())),
             InsertBlockDevice(config) => self.insert_block_device(config),
             InsertNetworkDevice(config) => self.insert_net_device(config),
@@ -435,22 +530,30 @@ impl<'a> PrebootApiController<'a> {
                 self.set_custom_cpu_template(custom_cpu_template)
             }
             PutMMDS(value) => self.put_mmds(value),
+            RemoveBlockDevice(drive_id) => self.remove_block_device(&drive_id),
+            RemoveNetworkDevice(iface_id) => self.remove_net_device(&iface_id),
             SetBalloonDevice(config) => self.set_balloon_device(config),
+            SetConsoleScanner(config) => self.set_console_scanner(config),
             SetVsockDevice(config) => self.set_vsock_device(config),
             SetMmdsConfiguration(config) => self.set_mmds_config(config),
             StartMicroVm => self.start_microvm(),
+            UpdateNetworkInterface(netif_update) => self.update_net_device(netif_update),
             UpdateVmConfiguration(config) => self.update_vm_config(config),
             SetEntropyDevice(config) => self.set_entropy_device(config),
             // Operations not allowed pre-boot.
-            CreateSnapshot(_)
+            CloneMicrovm(_)
+            | CreateSnapshot(_)
             | FlushMetrics
             | Pause
             | Resume
+            | GetAccessProfile
             | GetBalloonStats
+            | GetVhostUserInfo(_)
+            | GetVsockConnections
+            | PingVhostUser(_)
             | UpdateBalloon(_)
             | UpdateBalloonStatistics(_)
-            | UpdateBlockDevice(_)
-            | UpdateNetworkInterface(_) => Err(VmmActionError::OperationNotSupportedPreBoot),
+            | UpdateBlockDevice(_) => Err(VmmActionError::OperationNotSupportedPreBoot),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => Err(VmmActionError::OperationNotSupportedPreBoot),
         }
@@ -483,6 +586,30 @@ impl<'a> PrebootApiController<'a> {
             .map_err(VmmActionError::NetworkConfig)
     }
 
+    fn remove_block_device(&mut self, drive_id: &str) -> Result<VmmData, VmmActionError> {
+        self.vm_resources
+            .remove_block_device(drive_id)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::DriveConfig)
+    }
+
+    fn remove_net_device(&mut self, iface_id: &str) -> Result<VmmData, VmmActionError> {
+        self.vm_resources
+            .remove_net_device(iface_id)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::NetworkConfig)
+    }
+
+    fn update_net_device(
+        &mut self,
+        update: NetworkInterfaceUpdateConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        self.vm_resources
+            .update_net_device(update)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::NetworkConfig)
+    }
+
     fn set_balloon_device(&mut self, cfg: BalloonDeviceConfig) -> Result<VmmData, VmmActionError> {
         self.boot_path = true;
         self.vm_resources
@@ -537,6 +664,16 @@ impl<'a> PrebootApiController<'a> {
         Ok(VmmData::Empty)
     }
 
+    // The console scanner applies to microVMs loaded from snapshots too, so this is not a boot
+    // specific resource.
+    fn set_console_scanner(
+        &mut self,
+        cfg: ConsoleScannerConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        self.vm_resources.set_console_scanner(cfg)?;
+        Ok(VmmData::Empty)
+    }
+
     // On success, this command will end the pre-boot stage and this controller
     // will be replaced by a runtime controller.
     fn start_microvm(&mut self) -> Result<VmmData, VmmActionError> {
@@ -568,16 +705,26 @@ impl<'a> PrebootApiController<'a> {
             info!("{}", err);
             return Err(err);
         }
+        if let Some(config) = load_params.track_access.as_ref() {
+            config.validate()?;
+        }
 
         // Restore VM from snapshot
-        let vmm = restore_from_snapshot(
+        EVENTS.publish(VmmEvent::SnapshotStart {
+            operation: SnapshotOperation::Load,
+        });
+        let restore_result = restore_from_snapshot(
             &self.instance_info,
             self.event_manager,
             self.seccomp_filters,
             load_params,
             self.vm_resources,
-        )
-        .map_err(|err| {
+        );
+        EVENTS.publish(VmmEvent::SnapshotEnd {
+            operation: SnapshotOperation::Load,
+            success: restore_result.is_ok(),
+        });
+        let vmm = restore_result.map_err(|err| {
             // If restore fails, we consider the process is too dirty to recover.
             self.fatal_error = Some(BuildMicrovmFromRequestsError::Restore);
             err
@@ -592,6 +739,7 @@ impl<'a> PrebootApiController<'a> {
                     self.fatal_error = Some(BuildMicrovmFromRequestsError::Resume);
                     err
                 })?;
+            EVENTS.publish(VmmEvent::Resumed);
         }
         // Set the VM
         self.built_vmm = Some(vmm);
@@ -630,8 +778,16 @@ impl RuntimeApiController {
         use self::VmmAction::*;
         match request {
             // Supported operations allowed post-boot.
+            CloneMicrovm(clone_cfg) => self.clone_microvm(&clone_cfg),
             CreateSnapshot(snapshot_create_cfg) => self.create_snapshot(&snapshot_create_cfg),
             FlushMetrics => self.flush_metrics(),
+            GetAccessProfile => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .access_profile()
+                .map(VmmData::AccessProfile)
+                .ok_or(VmmActionError::AccessProfileNotTracked),
             GetBalloonConfig => self
                 .vmm
                 .lock()
@@ -648,21 +804,44 @@ impl RuntimeApiController {
                 .map_err(|err| VmmActionError::BalloonConfig(BalloonConfigError::from(err))),
             GetFullVmConfig => Ok(VmmData::FullVmConfig((&self.vm_resources).into())),
             GetMMDS => self.get_mmds(),
-            GetVmMachineConfig => Ok(VmmData::MachineConfiguration(MachineConfig::from(
-                &self.vm_resources.vm_config,
-            ))),
-            GetVmInstanceInfo => Ok(VmmData::InstanceInformation(
-                self.vmm.lock().expect("Poisoned lock").instance_info(),
-            )),
+            GetMmdsInfo => self.get_mmds_info(),
+            GetVmMachineConfig => Ok(VmmData::MachineConfiguration(MachineConfig {
+                memory_regions: self.vm_resources.vm_config.memory_layout().ok(),
+                ..MachineConfig::from(&self.vm_resources.vm_config)
+            })),
+            GetVhostUserInfo(id) => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .vhost_user_negotiation(&id)
+                .map(VmmData::VhostUserInfo)
+                .map_err(|err| VmmActionError::VhostUser(VhostUserConfigError::Device(err))),
+            GetVmInstanceInfo => Ok(VmmData::InstanceInformation(InstanceInfo {
+                uuid: self.vm_resources.vm_config.smbios_uuid(),
+                ..self.vmm.lock().expect("Poisoned lock").instance_info()
+            })),
             GetVmmVersion => Ok(VmmData::VmmVersion(
                 self.vmm.lock().expect("Poisoned lock").version(),
             )),
+            GetVsockConnections => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .vsock_connections()
+                .map(VmmData::VsockConnections)
+                .map_err(VmmActionError::InternalVmm),
             PatchMMDS(value) => self.patch_mmds(value),
             Pause => self.pause(),
+            PingVhostUser(ping_cfg) => self.ping_vhost_user(&ping_cfg),
             PutMMDS(value) => self.put_mmds(value),
             Resume => self.resume(),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => self.send_ctrl_alt_del(),
+            SetMmdsConfiguration(mmds_config) => self
+                .vm_resources
+                .update_mmds_config(mmds_config)
+                .map(|()| VmmData::Empty)
+                .map_err(VmmActionError::MmdsConfig),
             UpdateBalloon(balloon_update) => self
                 .vmm
                 .lock()
@@ -678,19 +857,21 @@ impl RuntimeApiController {
                 .map(|_| VmmData::Empty)
                 .map_err(|err| VmmActionError::BalloonConfig(BalloonConfigError::from(err))),
             UpdateBlockDevice(new_cfg) => self.update_block_device(new_cfg),
-            UpdateNetworkInterface(netif_update) => self.update_net_rate_limiters(netif_update),
+            InsertBlockDevice(config) => self.hotplug_block_device(config),
+            InsertNetworkDevice(config) => self.hotplug_net_device(config),
+            RemoveBlockDevice(drive_id) => self.unplug_block_device(&drive_id),
+            RemoveNetworkDevice(iface_id) => self.unplug_net_device(&iface_id),
+            UpdateNetworkInterface(netif_update) => self.update_net_device(netif_update),
 
             // Operations not allowed post-boot.
             ConfigureBootSource(_)
             | ConfigureLogger(_)
             | ConfigureMetrics(_)
-            | InsertBlockDevice(_)
-            | InsertNetworkDevice(_)
             | LoadSnapshot(_)
             | PutCpuConfiguration(_)
             | SetBalloonDevice(_)
+            | SetConsoleScanner(_)
             | SetVsockDevice(_)
-            | SetMmdsConfiguration(_)
             | SetEntropyDevice(_)
             | StartMicroVm
             | UpdateVmConfiguration(_) => Err(VmmActionError::OperationNotSupportedPostBoot),
@@ -707,6 +888,7 @@ impl RuntimeApiController {
         let pause_start_us = utils::time::get_time_us(utils::time::ClockType::Monotonic);
 
         self.vmm.lock().expect("Poisoned lock").pause_vm()?;
+        EVENTS.publish(VmmEvent::Paused);
 
         let elapsed_time_us =
             update_metric_with_elapsed_time(&METRICS.latencies_us.vmm_pause_vm, pause_start_us);
@@ -720,6 +902,7 @@ impl RuntimeApiController {
         let resume_start_us = utils::time::get_time_us(utils::time::ClockType::Monotonic);
 
         self.vmm.lock().expect("Poisoned lock").resume_vm()?;
+        EVENTS.publish(VmmEvent::Resumed);
 
         let elapsed_time_us =
             update_metric_with_elapsed_time(&METRICS.latencies_us.vmm_resume_vm, resume_start_us);
@@ -767,12 +950,25 @@ impl RuntimeApiController {
                     .to_string(),
             ));
         }
+        if self.vm_resources.has_vhost_user_drives() {
+            return Err(VmmActionError::NotSupported(
+                "Snapshots are not supported on uVMs with vhost-user drives.".to_string(),
+            ));
+        }
 
         let mut locked_vmm = self.vmm.lock().unwrap();
         let vm_info = VmInfo::from(&self.vm_resources);
         let create_start_us = utils::time::get_time_us(utils::time::ClockType::Monotonic);
 
-        create_snapshot(&mut locked_vmm, &vm_info, create_params)?;
+        EVENTS.publish(VmmEvent::SnapshotStart {
+            operation: SnapshotOperation::Create,
+        });
+        let create_result = create_snapshot(&mut locked_vmm, &vm_info, create_params);
+        EVENTS.publish(VmmEvent::SnapshotEnd {
+            operation: SnapshotOperation::Create,
+            success: create_result.is_ok(),
+        });
+        create_result?;
 
         match create_params.snapshot_type {
             SnapshotType::Full => {
@@ -799,10 +995,46 @@ impl RuntimeApiController {
         Ok(VmmData::Empty)
     }
 
+    fn clone_microvm(
+        &mut self,
+        clone_params: &CloneMicrovmParams,
+    ) -> Result<VmmData, VmmActionError> {
+        log_dev_preview_warning("Virtual machine cloning", None);
+
+        if self.vm_resources.has_vhost_user_drives() {
+            return Err(VmmActionError::NotSupported(
+                "Cloning is not supported on uVMs with vhost-user drives.".to_string(),
+            ));
+        }
+
+        let mut locked_vmm = self.vmm.lock().unwrap();
+        let vm_info = VmInfo::from(&self.vm_resources);
+        let clone_start_us = utils::time::get_time_us(utils::time::ClockType::Monotonic);
+
+        EVENTS.publish(VmmEvent::SnapshotStart {
+            operation: SnapshotOperation::Clone,
+        });
+        let clone_result = clone_microvm(&mut locked_vmm, &vm_info, clone_params);
+        EVENTS.publish(VmmEvent::SnapshotEnd {
+            operation: SnapshotOperation::Clone,
+            success: clone_result.is_ok(),
+        });
+        clone_result?;
+
+        let elapsed_time_us = update_metric_with_elapsed_time(
+            &METRICS.latencies_us.vmm_clone_microvm,
+            clone_start_us,
+        );
+        info!("'clone microvm' VMM action took {} us.", elapsed_time_us);
+        Ok(VmmData::Empty)
+    }
+
     /// Updates block device properties:
     ///  - path of the host file backing the emulated block device, update the disk image on the
     ///    device and its virtio configuration
-    ///  - rate limiter configuration.
+    ///  - rate limiter configuration
+    ///  - making the drive read-only
+    ///  - retrying the writes held back since the host ran out of space.
     fn update_block_device(
         &mut self,
         new_cfg: BlockDeviceUpdateConfig,
@@ -810,7 +1042,11 @@ impl RuntimeApiController {
         let mut vmm = self.vmm.lock().expect("Poisoned lock");
 
         // vhost-user-block updates
-        if new_cfg.path_on_host.is_none() && new_cfg.rate_limiter.is_none() {
+        if new_cfg.path_on_host.is_none()
+            && new_cfg.rate_limiter.is_none()
+            && new_cfg.is_read_only.is_none()
+            && new_cfg.retry_pending.is_none()
+        {
             vmm.update_vhost_user_block_config(&new_cfg.drive_id)
                 .map(|()| VmmData::Empty)
                 .map_err(DriveError::DeviceUpdate)?;
@@ -831,28 +1067,91 @@ impl RuntimeApiController {
             .map(|()| VmmData::Empty)
             .map_err(DriveError::DeviceUpdate)?;
         }
+        if let Some(is_read_only) = new_cfg.is_read_only {
+            vmm.update_block_read_only(&new_cfg.drive_id, is_read_only)
+                .map_err(DriveError::DeviceUpdate)?;
+        }
+        if new_cfg.retry_pending == Some(true) {
+            vmm.retry_block_pending_requests(&new_cfg.drive_id)
+                .map_err(DriveError::DeviceUpdate)?;
+        }
         Ok(VmmData::Empty)
     }
 
-    /// Updates configuration for an emulated net device as described in `new_cfg`.
-    fn update_net_rate_limiters(
+    /// Checks that the backend of a vhost-user device replies within the timeout of `ping_cfg`.
+    fn ping_vhost_user(
         &mut self,
-        new_cfg: NetworkInterfaceUpdateConfig,
+        ping_cfg: &VhostUserPingConfig,
     ) -> Result<VmmData, VmmActionError> {
+        let timeout = ping_cfg.timeout()?;
         self.vmm
             .lock()
             .expect("Poisoned lock")
-            .update_net_rate_limiters(
-                &new_cfg.iface_id,
-                RateLimiterUpdate::from(new_cfg.rx_rate_limiter).bandwidth,
-                RateLimiterUpdate::from(new_cfg.rx_rate_limiter).ops,
-                RateLimiterUpdate::from(new_cfg.tx_rate_limiter).bandwidth,
-                RateLimiterUpdate::from(new_cfg.tx_rate_limiter).ops,
-            )
+            .ping_vhost_user_backend(&ping_cfg.id, timeout)
+            .map(|latency| VmmData::VhostUserPing(VhostUserPingResult::from(latency)))
+            .map_err(|err| VmmActionError::VhostUser(VhostUserConfigError::Device(err)))
+    }
+
+    /// Hot-plugs a new block device as described in `cfg`.
+    fn hotplug_block_device(&mut self, cfg: BlockDeviceConfig) -> Result<VmmData, VmmActionError> {
+        let mut vmm = self.vmm.lock().expect("Poisoned lock");
+        self.vm_resources
+            .hotplug_block_device(cfg, &mut vmm)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::DriveConfig)
+    }
+
+    /// Hot-plugs a new emulated net device as described in `cfg`.
+    fn hotplug_net_device(
+        &mut self,
+        cfg: NetworkInterfaceConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        let mut vmm = self.vmm.lock().expect("Poisoned lock");
+        self.vm_resources
+            .hotplug_net_device(cfg, &mut vmm)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::NetworkConfig)
+    }
+
+    /// Unplugs the block device `drive_id`.
+    fn unplug_block_device(&mut self, drive_id: &str) -> Result<VmmData, VmmActionError> {
+        let mut vmm = self.vmm.lock().expect("Poisoned lock");
+        self.vm_resources
+            .unplug_block_device(drive_id, &mut vmm)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::DriveConfig)
+    }
+
+    /// Unplugs the emulated net device `iface_id`.
+    fn unplug_net_device(&mut self, iface_id: &str) -> Result<VmmData, VmmActionError> {
+        let mut vmm = self.vmm.lock().expect("Poisoned lock");
+        self.vm_resources
+            .unplug_net_device(iface_id, &mut vmm)
             .map(|()| VmmData::Empty)
-            .map_err(NetworkInterfaceError::DeviceUpdate)
             .map_err(VmmActionError::NetworkConfig)
     }
+
+    /// Updates configuration for an emulated net device as described in `new_cfg`.
+    fn update_net_device(
+        &mut self,
+        new_cfg: NetworkInterfaceUpdateConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        let mut vmm = self.vmm.lock().expect("Poisoned lock");
+        if let Some(mac) = new_cfg.guest_mac {
+            vmm.update_net_guest_mac(&new_cfg.iface_id, mac)
+                .map_err(VmmActionError::NetworkConfig)?;
+        }
+        vmm.update_net_rate_limiters(
+            &new_cfg.iface_id,
+            RateLimiterUpdate::from(new_cfg.rx_rate_limiter).bandwidth,
+            RateLimiterUpdate::from(new_cfg.rx_rate_limiter).ops,
+            RateLimiterUpdate::from(new_cfg.tx_rate_limiter).bandwidth,
+            RateLimiterUpdate::from(new_cfg.tx_rate_limiter).ops,
+        )
+        .map(|()| VmmData::Empty)
+        .map_err(NetworkInterfaceError::DeviceUpdate)
+        .map_err(VmmActionError::NetworkConfig)
+    }
 }
 
 #[cfg(test)]
@@ -861,8 +1160,10 @@ mod tests {
     use std::path::PathBuf;
 
     use seccompiler::BpfThreadMap;
+    use utils::net::mac::MacAddr;
 
     use super::*;
+    use crate::access_profile::{AccessRun, AccessTrackingState};
     use crate::cpu_config::templates::test_utils::build_test_template;
     use crate::cpu_config::templates::{CpuTemplateType, StaticCpuTemplate};
     use crate::devices::virtio::balloon::{BalloonConfig, BalloonError};
@@ -872,7 +1173,8 @@ mod tests {
     use crate::mmds::data_store::MmdsVersion;
     use crate::vmm_config::balloon::BalloonBuilder;
     use crate::vmm_config::machine_config::VmConfig;
-    use crate::vmm_config::snapshot::{MemBackendConfig, MemBackendType};
+    use crate::vmm_config::metrics::MetricsVerbosity;
+    use crate::vmm_config::snapshot::{MemBackendConfig, MemBackendType, TrackAccessConfig};
     use crate::vmm_config::vsock::VsockBuilder;
     use crate::HTTP_MAX_PAYLOAD_SIZE;
 
@@ -881,8 +1183,11 @@ mod tests {
             use VmmActionError::*;
             matches!(
                 (self, other),
-                (BalloonConfig(_), BalloonConfig(_))
+                (AccessProfileNotTracked, AccessProfileNotTracked)
+                    | (BalloonConfig(_), BalloonConfig(_))
                     | (BootSource(_), BootSource(_))
+                    | (CloneMicrovm(_), CloneMicrovm(_))
+                    | (ConsoleScanner(_), ConsoleScanner(_))
                     | (CreateSnapshot(_), CreateSnapshot(_))
                     | (DriveConfig(_), DriveConfig(_))
                     | (InternalVmm(_), InternalVmm(_))
@@ -897,6 +1202,7 @@ mod tests {
                     | (OperationNotSupportedPostBoot, OperationNotSupportedPostBoot)
                     | (OperationNotSupportedPreBoot, OperationNotSupportedPreBoot)
                     | (StartMicrovm(_), StartMicrovm(_))
+                    | (VhostUser(_), VhostUser(_))
                     | (VsockConfig(_), VsockConfig(_))
                     | (EntropyDevice(_), EntropyDevice(_))
             )
@@ -914,9 +1220,13 @@ mod tests {
         boot_src: BootSourceConfig,
         boot_cfg_set: bool,
         block_set: bool,
+        block_removed: bool,
         vsock_set: bool,
         net_set: bool,
+        net_updated: bool,
+        net_removed: bool,
         entropy_set: bool,
+        console_scanner_set: bool,
         pub mmds: Option<Arc<Mutex<Mmds>>>,
         pub mmds_size_limit: usize,
         pub boot_timer: bool,
@@ -937,6 +1247,10 @@ mod tests {
             self.vm_config.track_dirty_pages
         }
 
+        pub fn has_vhost_user_drives(&self) -> bool {
+            false
+        }
+
         pub fn set_track_dirty_pages(&mut self, dirty_page_tracking: bool) {
             self.vm_config.track_dirty_pages = dirty_page_tracking;
         }
@@ -1002,6 +1316,81 @@ mod tests {
             Ok(())
         }
 
+        pub fn update_net_device(
+            &mut self,
+            _: NetworkInterfaceUpdateConfig,
+        ) -> Result<(), NetworkInterfaceError> {
+            if self.force_errors {
+                return Err(NetworkInterfaceError::InvalidIfaceId(String::new()));
+            }
+            self.net_updated = true;
+            Ok(())
+        }
+
+        pub fn hotplug_block_device(
+            &mut self,
+            _: BlockDeviceConfig,
+            vmm: &mut MockVmm,
+        ) -> Result<(), DriveError> {
+            if self.force_errors || vmm.force_errors {
+                return Err(DriveError::NoHotplugSlot);
+            }
+            vmm.hotplug_block_device_called = true;
+            Ok(())
+        }
+
+        pub fn hotplug_net_device(
+            &mut self,
+            _: NetworkInterfaceConfig,
+            vmm: &mut MockVmm,
+        ) -> Result<(), NetworkInterfaceError> {
+            if self.force_errors || vmm.force_errors {
+                return Err(NetworkInterfaceError::NoHotplugSlot);
+            }
+            vmm.hotplug_net_device_called = true;
+            Ok(())
+        }
+
+        pub fn remove_block_device(&mut self, _: &str) -> Result<(), DriveError> {
+            if self.force_errors {
+                return Err(DriveError::InvalidDriveId(String::new()));
+            }
+            self.block_removed = true;
+            Ok(())
+        }
+
+        pub fn unplug_block_device(
+            &mut self,
+            _: &str,
+            vmm: &mut MockVmm,
+        ) -> Result<(), DriveError> {
+            if self.force_errors || vmm.force_errors {
+                return Err(DriveError::InvalidDriveId(String::new()));
+            }
+            vmm.unplug_block_device_called = true;
+            Ok(())
+        }
+
+        pub fn remove_net_device(&mut self, _: &str) -> Result<(), NetworkInterfaceError> {
+            if self.force_errors {
+                return Err(NetworkInterfaceError::InvalidIfaceId(String::new()));
+            }
+            self.net_removed = true;
+            Ok(())
+        }
+
+        pub fn unplug_net_device(
+            &mut self,
+            _: &str,
+            vmm: &mut MockVmm,
+        ) -> Result<(), NetworkInterfaceError> {
+            if self.force_errors || vmm.force_errors {
+                return Err(NetworkInterfaceError::InvalidIfaceId(String::new()));
+            }
+            vmm.unplug_net_device_called = true;
+            Ok(())
+        }
+
         pub fn set_vsock_device(&mut self, _: VsockDeviceConfig) -> Result<(), VsockConfigError> {
             if self.force_errors {
                 return Err(VsockConfigError::CreateVsockDevice(
@@ -1025,6 +1414,18 @@ mod tests {
             Ok(())
         }
 
+        pub fn set_console_scanner(
+            &mut self,
+            config: ConsoleScannerConfig,
+        ) -> Result<(), ConsoleScannerConfigError> {
+            if self.force_errors {
+                return Err(ConsoleScannerConfigError::TooManyPatterns(0));
+            }
+            config.validate()?;
+            self.console_scanner_set = true;
+            Ok(())
+        }
+
         pub fn set_mmds_config(
             &mut self,
             mmds_config: MmdsConfig,
@@ -1040,6 +1441,20 @@ mod tests {
             Ok(())
         }
 
+        pub fn update_mmds_config(
+            &mut self,
+            mmds_config: MmdsConfig,
+        ) -> Result<(), MmdsConfigError> {
+            let limit = mmds_config
+                .data_store_limit()
+                .ok_or(MmdsConfigError::UnsupportedPostBootUpdate)?;
+            self.locked_mmds_or_default()
+                .set_data_store_limit(limit)
+                .map_err(MmdsConfigError::DataStoreLimit)?;
+            self.mmds_size_limit = limit;
+            Ok(())
+        }
+
         /// If not initialised, create the mmds data store with the default config.
         pub fn mmds_or_default(&mut self) -> &Arc<Mutex<Mmds>> {
             self.mmds
@@ -1074,6 +1489,10 @@ mod tests {
                 cpu_template: StaticCpuTemplate::from(&value.vm_config.cpu_template),
                 boot_source: value.boot_source_config().clone(),
                 huge_pages: value.vm_config.huge_pages,
+                smbios: value.vm_config.smbios.clone(),
+                gic: value.vm_config.gic,
+                serial_ports: value.vm_config.serial_ports.clone(),
+                auto_pause: value.vm_config.auto_pause.clone(),
             }
         }
     }
@@ -1081,6 +1500,7 @@ mod tests {
     // Mock `Vmm` used for testing.
     #[derive(Debug, Default, PartialEq, Eq)]
     pub struct MockVmm {
+        pub access_profile_called: bool,
         pub balloon_config_called: bool,
         pub latest_balloon_stats_called: bool,
         pub pause_called: bool,
@@ -1090,13 +1510,45 @@ mod tests {
         pub update_balloon_config_called: bool,
         pub update_balloon_stats_config_called: bool,
         pub update_block_device_path_called: bool,
+        pub update_block_read_only_called: bool,
+        pub retry_block_pending_requests_called: bool,
         pub update_block_device_vhost_user_config_called: bool,
         pub update_net_rate_limiters_called: bool,
+        pub update_net_guest_mac_called: bool,
+        pub hotplug_block_device_called: bool,
+        pub hotplug_net_device_called: bool,
+        pub unplug_block_device_called: bool,
+        pub unplug_net_device_called: bool,
+        pub vhost_user_negotiation_called: bool,
+        pub ping_vhost_user_backend_called: bool,
+        pub vsock_connections_called: bool,
         // when `true`, all self methods are forced to fail
         pub force_errors: bool,
     }
 
     impl MockVmm {
+        pub fn test_access_profile() -> AccessProfile {
+            AccessProfile {
+                state: AccessTrackingState::Complete,
+                duration_s: 10,
+                page_size: 4096,
+                touched_pages: 2,
+                runs: vec![AccessRun {
+                    guest_addr: 0x1000,
+                    file_offset: 0x1000,
+                    num_pages: 2,
+                }],
+            }
+        }
+
+        pub fn access_profile(&mut self) -> Option<AccessProfile> {
+            if self.force_errors {
+                return None;
+            }
+            self.access_profile_called = true;
+            Some(Self::test_access_profile())
+        }
+
         pub fn resume_vm(&mut self) -> Result<(), VmmError> {
             if self.force_errors {
                 return Err(VmmError::VcpuResume);
@@ -1175,6 +1627,26 @@ mod tests {
             Ok(())
         }
 
+        pub fn update_block_read_only(&mut self, _: &str, _: bool) -> Result<(), VmmError> {
+            if self.force_errors {
+                return Err(VmmError::DeviceManager(
+                    crate::device_manager::mmio::MmioError::InvalidDeviceType,
+                ));
+            }
+            self.update_block_read_only_called = true;
+            Ok(())
+        }
+
+        pub fn retry_block_pending_requests(&mut self, _: &str) -> Result<(), VmmError> {
+            if self.force_errors {
+                return Err(VmmError::DeviceManager(
+                    crate::device_manager::mmio::MmioError::InvalidDeviceType,
+                ));
+            }
+            self.retry_block_pending_requests_called = true;
+            Ok(())
+        }
+
         pub fn update_vhost_user_block_config(&mut self, _: &str) -> Result<(), VmmError> {
             if self.force_errors {
                 return Err(VmmError::DeviceManager(
@@ -1202,6 +1674,55 @@ mod tests {
             Ok(())
         }
 
+        pub fn update_net_guest_mac(
+            &mut self,
+            _: &str,
+            _: MacAddr,
+        ) -> Result<(), NetworkInterfaceError> {
+            if self.force_errors {
+                return Err(NetworkInterfaceError::GuestMacUpdateRunning);
+            }
+            self.update_net_guest_mac_called = true;
+            Ok(())
+        }
+
+        pub fn vhost_user_negotiation(
+            &mut self,
+            _: &str,
+        ) -> Result<VhostUserNegotiation, VmmError> {
+            if self.force_errors {
+                return Err(VmmError::DeviceManager(
+                    crate::device_manager::mmio::MmioError::DeviceNotFound,
+                ));
+            }
+            self.vhost_user_negotiation_called = true;
+            Ok(VhostUserNegotiation::default())
+        }
+
+        pub fn ping_vhost_user_backend(
+            &mut self,
+            _: &str,
+            _: std::time::Duration,
+        ) -> Result<std::time::Duration, VmmError> {
+            if self.force_errors {
+                return Err(VmmError::DeviceManager(
+                    crate::device_manager::mmio::MmioError::DeviceNotFound,
+                ));
+            }
+            self.ping_vhost_user_backend_called = true;
+            Ok(std::time::Duration::from_micros(42))
+        }
+
+        pub fn vsock_connections(&mut self) -> Result<VsockConnections, VmmError> {
+            if self.force_errors {
+                return Err(VmmError::DeviceManager(
+                    crate::device_manager::mmio::MmioError::DeviceNotFound,
+                ));
+            }
+            self.vsock_connections_called = true;
+            Ok(VsockConnections::default())
+        }
+
         pub fn instance_info(&self) -> InstanceInfo {
             InstanceInfo::default()
         }
@@ -1232,6 +1753,16 @@ mod tests {
         Ok(())
     }
 
+    // Need to redefine this since the non-test one uses real Vmm
+    // instead of our mocks.
+    pub fn clone_microvm(
+        _: &mut Vmm,
+        _: &VmInfo,
+        _: &CloneMicrovmParams,
+    ) -> Result<(), CloneMicrovmError> {
+        Ok(())
+    }
+
     // Need to redefine this since the non-test one uses real Vmm
     // instead of our mocks.
     pub fn restore_from_snapshot(
@@ -1317,7 +1848,10 @@ mod tests {
     #[test]
     fn test_preboot_get_vm_config() {
         let req = VmmAction::GetVmMachineConfig;
-        let expected_cfg = MachineConfig::default();
+        let expected_cfg = MachineConfig {
+            memory_regions: Some(VmConfig::default().memory_layout().unwrap()),
+            ..Default::default()
+        };
         check_preboot_request(req, |result, _| {
             assert_eq!(result, Ok(VmmData::MachineConfiguration(expected_cfg)))
         });
@@ -1397,9 +1931,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(String::new()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         let req = VmmAction::InsertBlockDevice(config.clone());
         check_preboot_request(req, |result, vm_res| {
@@ -1419,9 +1966,17 @@ mod tests {
         let req = VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
             iface_id: String::new(),
             host_dev_name: String::new(),
+            tap_fd: None,
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
         });
         check_preboot_request(req, |result, vm_res| {
             assert_eq!(result, Ok(VmmData::Empty));
@@ -1431,9 +1986,17 @@ mod tests {
         let req = VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
             iface_id: String::new(),
             host_dev_name: String::new(),
+            tap_fd: None,
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
         });
         check_preboot_request_err(
             req,
@@ -1444,14 +2007,69 @@ mod tests {
     }
 
     #[test]
-    fn test_preboot_set_vsock_dev() {
-        let req = VmmAction::SetVsockDevice(VsockDeviceConfig {
-            vsock_id: Some(String::new()),
-            guest_cid: 0,
-            uds_path: String::new(),
-        });
-        check_preboot_request(req, |result, vm_res| {
-            assert_eq!(result, Ok(VmmData::Empty));
+    fn test_preboot_update_net_dev() {
+        let update = || NetworkInterfaceUpdateConfig {
+            iface_id: String::new(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            guest_mac: Some(MacAddr::from_bytes_unchecked(&[0x06, 0, 0, 0, 0, 0x01])),
+        };
+        check_preboot_request(
+            VmmAction::UpdateNetworkInterface(update()),
+            |result, vm_res| {
+                assert_eq!(result, Ok(VmmData::Empty));
+                assert!(vm_res.net_updated)
+            },
+        );
+        check_preboot_request_err(
+            VmmAction::UpdateNetworkInterface(update()),
+            VmmActionError::NetworkConfig(NetworkInterfaceError::InvalidIfaceId(String::new())),
+        );
+    }
+
+    #[test]
+    fn test_preboot_remove_block_dev() {
+        check_preboot_request(
+            VmmAction::RemoveBlockDevice(String::new()),
+            |result, vm_res| {
+                assert_eq!(result, Ok(VmmData::Empty));
+                assert!(vm_res.block_removed)
+            },
+        );
+        check_preboot_request_err(
+            VmmAction::RemoveBlockDevice(String::new()),
+            VmmActionError::DriveConfig(DriveError::InvalidDriveId(String::new())),
+        );
+    }
+
+    #[test]
+    fn test_preboot_remove_net_dev() {
+        check_preboot_request(
+            VmmAction::RemoveNetworkDevice(String::new()),
+            |result, vm_res| {
+                assert_eq!(result, Ok(VmmData::Empty));
+                assert!(vm_res.net_removed)
+            },
+        );
+        check_preboot_request_err(
+            VmmAction::RemoveNetworkDevice(String::new()),
+            VmmActionError::NetworkConfig(NetworkInterfaceError::InvalidIfaceId(String::new())),
+        );
+    }
+
+    #[test]
+    fn test_preboot_set_vsock_dev() {
+        let req = VmmAction::SetVsockDevice(VsockDeviceConfig {
+            vsock_id: Some(String::new()),
+            guest_cid: 0,
+            uds_path: String::new(),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            keep_connections: None,
+        });
+        check_preboot_request(req, |result, vm_res| {
+            assert_eq!(result, Ok(VmmData::Empty));
             assert!(vm_res.vsock_set)
         });
 
@@ -1459,6 +2077,10 @@ mod tests {
             vsock_id: Some(String::new()),
             guest_cid: 0,
             uds_path: String::new(),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            keep_connections: None,
         });
         check_preboot_request_err(
             req,
@@ -1477,12 +2099,28 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_preboot_set_console_scanner() {
+        let req = VmmAction::SetConsoleScanner(ConsoleScannerConfig::default());
+        check_preboot_request(req, |result, vm_res| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vm_res.console_scanner_set);
+        });
+
+        let req = VmmAction::SetConsoleScanner(ConsoleScannerConfig::default());
+        check_preboot_request_err(
+            req,
+            VmmActionError::ConsoleScanner(ConsoleScannerConfigError::TooManyPatterns(0)),
+        );
+    }
+
     #[test]
     fn test_preboot_set_mmds_config() {
         let req = VmmAction::SetMmdsConfiguration(MmdsConfig {
             ipv4_address: None,
             version: MmdsVersion::V2,
             network_interfaces: Vec::new(),
+            data_store_limit_mib: None,
         });
         check_preboot_request(req, |result, vm_res| {
             assert_eq!(result, Ok(VmmData::Empty));
@@ -1496,6 +2134,7 @@ mod tests {
             ipv4_address: None,
             version: MmdsVersion::default(),
             network_interfaces: Vec::new(),
+            data_store_limit_mib: None,
         });
         check_preboot_request_err(
             req,
@@ -1517,6 +2156,95 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_preboot_get_mmds_info() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        mmds.lock()
+            .unwrap()
+            .put_data(serde_json::from_str(r#"{"key": "value"}"#).unwrap())
+            .unwrap();
+        check_preboot_request_with_mmds(VmmAction::GetMmdsInfo, mmds, |result, _| {
+            assert_eq!(
+                result,
+                Ok(VmmData::MmdsInfo(MmdsDataStoreInfo {
+                    used_bytes: 15,
+                    limit_bytes: 51200,
+                }))
+            );
+        });
+    }
+
+    #[test]
+    fn test_runtime_get_mmds_info() {
+        check_runtime_request(VmmAction::GetMmdsInfo, |result, _| {
+            assert_eq!(
+                result,
+                Ok(VmmData::MmdsInfo(MmdsDataStoreInfo {
+                    used_bytes: 0,
+                    limit_bytes: 0,
+                }))
+            );
+        });
+    }
+
+    #[test]
+    fn test_runtime_set_mmds_config() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        mmds.lock()
+            .unwrap()
+            .put_data(serde_json::json!({ "key": "X".repeat(1 << 20) }))
+            .unwrap_err();
+
+        let mut mmds_config = MmdsConfig {
+            ipv4_address: None,
+            version: MmdsVersion::V2,
+            network_interfaces: vec!["net0".to_string()],
+            data_store_limit_mib: Some(2),
+        };
+        check_runtime_request_with_mmds(
+            VmmAction::SetMmdsConfiguration(mmds_config.clone()),
+            mmds.clone(),
+            |result, _| {
+                assert_eq!(result, Ok(VmmData::Empty));
+            },
+        );
+        mmds.lock()
+            .unwrap()
+            .put_data(serde_json::json!({ "key": "X".repeat(1 << 20) }))
+            .unwrap();
+
+        // Shrinking below the current usage is rejected.
+        mmds_config.data_store_limit_mib = Some(1);
+        check_runtime_request_with_mmds(
+            VmmAction::SetMmdsConfiguration(mmds_config.clone()),
+            mmds.clone(),
+            |result, _| {
+                assert!(matches!(
+                    result,
+                    Err(VmmActionError::MmdsConfig(MmdsConfigError::DataStoreLimit(
+                        data_store::MmdsDatastoreError::DataStoreLimitBelowUsage(1048576, 1048586)
+                    )))
+                ));
+            },
+        );
+
+        // Nothing but the data store limit can be updated post-boot.
+        mmds_config.data_store_limit_mib = None;
+        check_runtime_request_with_mmds(
+            VmmAction::SetMmdsConfiguration(mmds_config),
+            mmds.clone(),
+            |result, _| {
+                assert!(matches!(
+                    result,
+                    Err(VmmActionError::MmdsConfig(
+                        MmdsConfigError::UnsupportedPostBootUpdate
+                    ))
+                ));
+            },
+        );
+        assert_eq!(mmds.lock().unwrap().data_store_info().limit_bytes, 2 << 20);
+    }
+
     #[test]
     fn test_preboot_put_mmds() {
         let mmds = Arc::new(Mutex::new(Mmds::default()));
@@ -1728,6 +2456,26 @@ mod tests {
         let seccomp_filters = BpfThreadMap::new();
         let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
 
+        // An invalid tracking duration is rejected before restoring the microVM.
+        let req = VmmAction::LoadSnapshot(LoadSnapshotParams {
+            snapshot_path: PathBuf::new(),
+            mem_backend: MemBackendConfig {
+                backend_type: MemBackendType::File,
+                backend_path: PathBuf::new(),
+            },
+            enable_diff_snapshots: false,
+            resume_vm: false,
+            track_access: Some(TrackAccessConfig { duration_s: 0 }),
+            network_overrides: vec![],
+        });
+        assert_eq!(
+            preboot.handle_preboot_request(req),
+            Err(VmmActionError::LoadSnapshot(
+                LoadSnapshotError::TrackAccess(TrackAccessConfigError::InvalidDuration)
+            ))
+        );
+        assert!(preboot.built_vmm.is_none());
+
         // Without resume.
         let req = VmmAction::LoadSnapshot(LoadSnapshotParams {
             snapshot_path: PathBuf::new(),
@@ -1737,6 +2485,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: false,
+            track_access: None,
+            network_overrides: vec![],
         });
         // Request should succeed.
         preboot.handle_preboot_request(req).unwrap();
@@ -1753,6 +2503,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: true,
+            track_access: None,
+            network_overrides: vec![],
         });
         // Request should succeed.
         preboot.handle_preboot_request(req).unwrap();
@@ -1796,11 +2548,19 @@ mod tests {
             VmmActionError::OperationNotSupportedPreBoot,
         );
         check_preboot_request_err(
-            VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
-                iface_id: String::new(),
-                rx_rate_limiter: None,
-                tx_rate_limiter: None,
-            }),
+            VmmAction::GetAccessProfile,
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
+        check_preboot_request_err(
+            VmmAction::GetVhostUserInfo(String::new()),
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
+        check_preboot_request_err(
+            VmmAction::PingVhostUser(VhostUserPingConfig::default()),
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
+        check_preboot_request_err(
+            VmmAction::GetVsockConnections,
             VmmActionError::OperationNotSupportedPreBoot,
         );
         check_preboot_request_err(
@@ -1811,6 +2571,13 @@ mod tests {
             }),
             VmmActionError::OperationNotSupportedPreBoot,
         );
+        check_preboot_request_err(
+            VmmAction::CloneMicrovm(CloneMicrovmParams {
+                socket: PathBuf::new(),
+                network_overrides: vec![],
+            }),
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
         #[cfg(target_arch = "x86_64")]
         check_preboot_request_err(
             VmmAction::SendCtrlAltDel,
@@ -1862,7 +2629,10 @@ mod tests {
         check_runtime_request(req, |result, _| {
             assert_eq!(
                 result,
-                Ok(VmmData::MachineConfiguration(MachineConfig::default()))
+                Ok(VmmData::MachineConfiguration(MachineConfig {
+                    memory_regions: Some(VmConfig::default().memory_layout().unwrap()),
+                    ..Default::default()
+                }))
             );
         });
     }
@@ -1891,6 +2661,17 @@ mod tests {
         check_runtime_request_err(req, VmmActionError::InternalVmm(VmmError::VcpuResume));
     }
 
+    #[test]
+    fn test_runtime_clone_microvm() {
+        let req = VmmAction::CloneMicrovm(CloneMicrovmParams {
+            socket: PathBuf::from("/tmp/clone.sock"),
+            network_overrides: vec![],
+        });
+        check_runtime_request(req, |result, _| {
+            assert_eq!(result, Ok(VmmData::Empty));
+        });
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_runtime_ctrl_alt_del() {
@@ -1999,6 +2780,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_runtime_update_block_read_only() {
+        let req = VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig {
+            is_read_only: Some(true),
+            ..Default::default()
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vmm.update_block_read_only_called);
+            assert!(!vmm.update_block_device_vhost_user_config_called);
+        });
+
+        let req = VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig {
+            is_read_only: Some(true),
+            ..Default::default()
+        });
+        check_runtime_request_err(
+            req,
+            VmmActionError::DriveConfig(DriveError::DeviceUpdate(VmmError::DeviceManager(
+                crate::device_manager::mmio::MmioError::InvalidDeviceType,
+            ))),
+        );
+    }
+
+    #[test]
+    fn test_runtime_retry_block_pending_requests() {
+        let req = VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig {
+            retry_pending: Some(true),
+            ..Default::default()
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vmm.retry_block_pending_requests_called);
+            assert!(!vmm.update_block_device_vhost_user_config_called);
+        });
+
+        let req = VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig {
+            retry_pending: Some(true),
+            ..Default::default()
+        });
+        check_runtime_request_err(
+            req,
+            VmmActionError::DriveConfig(DriveError::DeviceUpdate(VmmError::DeviceManager(
+                crate::device_manager::mmio::MmioError::InvalidDeviceType,
+            ))),
+        );
+    }
+
     #[test]
     fn test_runtime_update_block_device_vhost_user_config() {
         let req = VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig {
@@ -2020,22 +2849,121 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_runtime_get_access_profile() {
+        check_runtime_request(VmmAction::GetAccessProfile, |result, vmm| {
+            assert_eq!(
+                result,
+                Ok(VmmData::AccessProfile(MockVmm::test_access_profile()))
+            );
+            assert!(vmm.access_profile_called)
+        });
+
+        check_runtime_request_err(
+            VmmAction::GetAccessProfile,
+            VmmActionError::AccessProfileNotTracked,
+        );
+    }
+
+    #[test]
+    fn test_runtime_get_vhost_user_info() {
+        let req = VmmAction::GetVhostUserInfo("drive".to_string());
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(
+                result,
+                Ok(VmmData::VhostUserInfo(VhostUserNegotiation::default()))
+            );
+            assert!(vmm.vhost_user_negotiation_called)
+        });
+
+        let req = VmmAction::GetVhostUserInfo("drive".to_string());
+        check_runtime_request_err(
+            req,
+            VmmActionError::VhostUser(VhostUserConfigError::Device(VmmError::DeviceManager(
+                crate::device_manager::mmio::MmioError::DeviceNotFound,
+            ))),
+        );
+    }
+
+    #[test]
+    fn test_runtime_get_vsock_connections() {
+        let req = VmmAction::GetVsockConnections;
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(
+                result,
+                Ok(VmmData::VsockConnections(VsockConnections::default()))
+            );
+            assert!(vmm.vsock_connections_called)
+        });
+
+        let req = VmmAction::GetVsockConnections;
+        check_runtime_request_err(
+            req,
+            VmmActionError::InternalVmm(VmmError::DeviceManager(
+                crate::device_manager::mmio::MmioError::DeviceNotFound,
+            )),
+        );
+    }
+
+    #[test]
+    fn test_runtime_ping_vhost_user() {
+        let req = VmmAction::PingVhostUser(VhostUserPingConfig {
+            id: "drive".to_string(),
+            timeout_ms: Some(100),
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(
+                result,
+                Ok(VmmData::VhostUserPing(VhostUserPingResult {
+                    latency_us: 42
+                }))
+            );
+            assert!(vmm.ping_vhost_user_backend_called)
+        });
+
+        // The timeout is checked before reaching the device.
+        let req = VmmAction::PingVhostUser(VhostUserPingConfig {
+            id: "drive".to_string(),
+            timeout_ms: Some(0),
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert!(matches!(
+                result,
+                Err(VmmActionError::VhostUser(
+                    VhostUserConfigError::InvalidPingTimeout
+                ))
+            ));
+            assert!(!vmm.ping_vhost_user_backend_called)
+        });
+
+        let req = VmmAction::PingVhostUser(VhostUserPingConfig::default());
+        check_runtime_request_err(
+            req,
+            VmmActionError::VhostUser(VhostUserConfigError::Device(VmmError::DeviceManager(
+                crate::device_manager::mmio::MmioError::DeviceNotFound,
+            ))),
+        );
+    }
+
     #[test]
     fn test_runtime_update_net_rate_limiters() {
         let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
             iface_id: String::new(),
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            guest_mac: None,
         });
         check_runtime_request(req, |result, vmm| {
             assert_eq!(result, Ok(VmmData::Empty));
-            assert!(vmm.update_net_rate_limiters_called)
+            assert!(vmm.update_net_rate_limiters_called);
+            assert!(!vmm.update_net_guest_mac_called);
         });
 
         let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
             iface_id: String::new(),
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            guest_mac: None,
         });
         check_runtime_request_err(
             req,
@@ -2045,6 +2973,127 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_runtime_update_net_guest_mac() {
+        let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
+            iface_id: String::new(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            guest_mac: Some(MacAddr::from_bytes_unchecked(&[0x06, 0, 0, 0, 0, 0x01])),
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vmm.update_net_guest_mac_called);
+            assert!(vmm.update_net_rate_limiters_called);
+        });
+
+        let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
+            iface_id: String::new(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            guest_mac: Some(MacAddr::from_bytes_unchecked(&[0x06, 0, 0, 0, 0, 0x01])),
+        });
+        check_runtime_request_err(
+            req,
+            VmmActionError::NetworkConfig(NetworkInterfaceError::GuestMacUpdateRunning),
+        );
+    }
+
+    #[test]
+    fn test_runtime_hotplug_block_device() {
+        let config = || BlockDeviceConfig {
+            drive_id: String::new(),
+            partuuid: None,
+            is_root_device: false,
+            cache_type: CacheType::Unsafe,
+
+            is_read_only: Some(false),
+            path_on_host: Some(String::new()),
+            rate_limiter: None,
+            rate_limiter_scope: None,
+            file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
+
+            socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+        };
+        check_runtime_request(VmmAction::InsertBlockDevice(config()), |result, vmm| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vmm.hotplug_block_device_called);
+        });
+        check_runtime_request_err(
+            VmmAction::InsertBlockDevice(config()),
+            VmmActionError::DriveConfig(DriveError::NoHotplugSlot),
+        );
+    }
+
+    #[test]
+    fn test_runtime_hotplug_net_device() {
+        let netif = || NetworkInterfaceConfig {
+            iface_id: String::new(),
+            host_dev_name: String::new(),
+            tap_fd: None,
+            guest_mac: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
+        };
+        check_runtime_request(VmmAction::InsertNetworkDevice(netif()), |result, vmm| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vmm.hotplug_net_device_called);
+        });
+        check_runtime_request_err(
+            VmmAction::InsertNetworkDevice(netif()),
+            VmmActionError::NetworkConfig(NetworkInterfaceError::NoHotplugSlot),
+        );
+    }
+
+    #[test]
+    fn test_runtime_unplug_block_device() {
+        check_runtime_request(
+            VmmAction::RemoveBlockDevice(String::new()),
+            |result, vmm| {
+                assert_eq!(result, Ok(VmmData::Empty));
+                assert!(vmm.unplug_block_device_called);
+            },
+        );
+        check_runtime_request_err(
+            VmmAction::RemoveBlockDevice(String::new()),
+            VmmActionError::DriveConfig(DriveError::InvalidDriveId(String::new())),
+        );
+    }
+
+    #[test]
+    fn test_runtime_unplug_net_device() {
+        check_runtime_request(
+            VmmAction::RemoveNetworkDevice(String::new()),
+            |result, vmm| {
+                assert_eq!(result, Ok(VmmData::Empty));
+                assert!(vmm.unplug_net_device_called);
+            },
+        );
+        check_runtime_request_err(
+            VmmAction::RemoveNetworkDevice(String::new()),
+            VmmActionError::NetworkConfig(NetworkInterfaceError::InvalidIfaceId(String::new())),
+        );
+    }
+
     #[test]
     fn test_runtime_disallowed() {
         check_runtime_request_err(
@@ -2064,32 +3113,7 @@ mod tests {
         check_runtime_request_err(
             VmmAction::ConfigureMetrics(MetricsConfig {
                 metrics_path: PathBuf::new(),
-            }),
-            VmmActionError::OperationNotSupportedPostBoot,
-        );
-        check_runtime_request_err(
-            VmmAction::InsertBlockDevice(BlockDeviceConfig {
-                drive_id: String::new(),
-                partuuid: None,
-                is_root_device: false,
-                cache_type: CacheType::Unsafe,
-
-                is_read_only: Some(false),
-                path_on_host: Some(String::new()),
-                rate_limiter: None,
-                file_engine_type: None,
-
-                socket: None,
-            }),
-            VmmActionError::OperationNotSupportedPostBoot,
-        );
-        check_runtime_request_err(
-            VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
-                iface_id: String::new(),
-                host_dev_name: String::new(),
-                guest_mac: None,
-                rx_rate_limiter: None,
-                tx_rate_limiter: None,
+                verbosity: MetricsVerbosity::Default,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
@@ -2098,6 +3122,10 @@ mod tests {
                 vsock_id: Some(String::new()),
                 guest_cid: 0,
                 uds_path: String::new(),
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
+                queue_size: None,
+                keep_connections: None,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
@@ -2110,14 +3138,10 @@ mod tests {
                 vsock_id: Some(String::new()),
                 guest_cid: 0,
                 uds_path: String::new(),
-            }),
-            VmmActionError::OperationNotSupportedPostBoot,
-        );
-        check_runtime_request_err(
-            VmmAction::SetMmdsConfiguration(MmdsConfig {
-                ipv4_address: None,
-                version: MmdsVersion::default(),
-                network_interfaces: Vec::new(),
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
+                queue_size: None,
+                keep_connections: None,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
@@ -2134,6 +3158,8 @@ mod tests {
                 },
                 enable_diff_snapshots: false,
                 resume_vm: false,
+                track_access: None,
+                network_overrides: vec![],
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
@@ -2141,6 +3167,10 @@ mod tests {
             VmmAction::SetEntropyDevice(EntropyDeviceConfig::default()),
             VmmActionError::OperationNotSupportedPostBoot,
         );
+        check_runtime_request_err(
+            VmmAction::SetConsoleScanner(ConsoleScannerConfig::default()),
+            VmmActionError::OperationNotSupportedPostBoot,
+        );
     }
 
     fn verify_load_snap_disallowed_after_boot_resources(res: VmmAction, res_name: &str) {
@@ -2160,6 +3190,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: false,
+            track_access: None,
+            network_overrides: vec![],
         });
         let err = preboot.handle_preboot_request(req);
         assert_eq!(
@@ -2187,9 +3219,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(String::new()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let req = VmmAction::InsertBlockDevice(config);
@@ -2198,9 +3243,17 @@ mod tests {
         let req = VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
             iface_id: String::new(),
             host_dev_name: String::new(),
+            tap_fd: None,
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
         });
         verify_load_snap_disallowed_after_boot_resources(req, "InsertNetworkDevice");
 
@@ -2211,6 +3264,10 @@ mod tests {
             vsock_id: Some(String::new()),
             guest_cid: 0,
             uds_path: String::new(),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            keep_connections: None,
         });
         verify_load_snap_disallowed_after_boot_resources(req, "SetVsockDevice");
 
@@ -2222,6 +3279,7 @@ mod tests {
             ipv4_address: None,
             version: MmdsVersion::default(),
             network_interfaces: Vec::new(),
+            data_store_limit_mib: None,
         });
         verify_load_snap_disallowed_after_boot_resources(req, "SetMmdsConfiguration");
     }
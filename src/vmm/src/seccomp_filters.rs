@@ -10,5 +10,6 @@ pub fn get_empty_filters() -> BpfThreadMap {
     map.insert("vmm".to_string(), Arc::new(vec![]));
     map.insert("api".to_string(), Arc::new(vec![]));
     map.insert("vcpu".to_string(), Arc::new(vec![]));
+    map.insert("vmm_api".to_string(), Arc::new(vec![]));
     map
 }
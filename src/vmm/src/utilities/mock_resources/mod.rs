@@ -43,7 +43,9 @@ impl MockBootSourceConfig {
     pub fn new() -> MockBootSourceConfig {
         MockBootSourceConfig(BootSourceConfig {
             kernel_image_path: kernel_image_path(None),
+            kernel_fd: None,
             initrd_path: None,
+            initrd_fd: None,
             boot_args: None,
         })
     }
@@ -30,15 +30,124 @@ pub fn single_region_mem_at(at: u64, size: usize) -> GuestMemoryMmap {
 }
 
 /// Creates a [`GuestMemoryMmap`] with multiple regions and without dirty page tracking.
+///
+/// With the `memory-guards` feature, every region is mapped between two inaccessible guard pages.
 pub fn multi_region_mem(regions: &[(GuestAddress, usize)]) -> GuestMemoryMmap {
-    GuestMemoryMmap::from_raw_regions(regions, false, HugePageConfig::None)
-        .expect("Cannot initialize memory")
+    let map = || {
+        GuestMemoryMmap::from_raw_regions(regions, false, HugePageConfig::None)
+            .expect("Cannot initialize memory")
+    };
+    #[cfg(feature = "memory-guards")]
+    let map = || memory_guards::guarded_mem(map);
+    map()
+}
+
+#[cfg(feature = "memory-guards")]
+mod memory_guards {
+    use std::collections::BTreeSet;
+    use std::sync::Mutex;
+
+    use crate::vstate::memory::{GuestMemory, GuestMemoryMmap};
+
+    /// Number of memories mapped next to something else than a guard page before giving up.
+    const MAX_ATTEMPTS: usize = 16;
+
+    // Host addresses of the guard pages. They are never unmapped, so that a region mapped again
+    // at the same place, or next to another region, is still guarded.
+    static GUARD_PAGES: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+    // Maps an inaccessible page at `addr`, unless it is already a guard page. Returns whether
+    // `addr` holds a guard page.
+    fn map_guard_page(addr: usize, page_size: usize) -> bool {
+        let mut guard_pages = GUARD_PAGES.lock().unwrap();
+        if guard_pages.contains(&addr) {
+            return true;
+        }
+
+        // SAFETY: With `MAP_FIXED_NOREPLACE`, the mapping does not replace anything mapped at
+        // `addr`.
+        let ptr = unsafe {
+            libc::mmap(
+                addr as *mut libc::c_void,
+                page_size,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE
+                    | libc::MAP_ANONYMOUS
+                    | libc::MAP_NORESERVE
+                    | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return false;
+        }
+        // Kernels older than 4.17 take the address as a hint.
+        if ptr as usize != addr {
+            // SAFETY: The page was just mapped, and is not used.
+            unsafe { libc::munmap(ptr, page_size) };
+            return false;
+        }
+        guard_pages.insert(addr);
+        true
+    }
+
+    /// Maps a [`GuestMemoryMmap`] with `map`, and maps a guard page on each side of its regions.
+    ///
+    /// The guard page after a region follows the end of its last page.
+    pub(super) fn guarded_mem(map: impl Fn() -> GuestMemoryMmap) -> GuestMemoryMmap {
+        let page_size = utils::get_page_size().unwrap();
+        // Memories which could not be guarded are kept until the end, so that the next attempts
+        // get mapped at other places.
+        let mut unguarded = Vec::new();
+
+        for _ in 0..MAX_ATTEMPTS {
+            let mem = map();
+            let guarded = mem.iter().fold(true, |guarded, region| {
+                let start = region.as_ptr() as usize;
+                let end = start + region.size().next_multiple_of(page_size);
+                let before = map_guard_page(start - page_size, page_size);
+                let after = map_guard_page(end, page_size);
+                guarded && before && after
+            });
+            if guarded {
+                return mem;
+            }
+            unguarded.push(mem);
+        }
+        panic!("Cannot map guard pages around the guest memory");
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use vm_memory::GuestAddress;
+
+        use super::*;
+        use crate::utilities::test_utils::multi_region_mem;
+
+        #[test]
+        fn test_guarded_mem() {
+            let page_size = utils::get_page_size().unwrap();
+            let mem = multi_region_mem(&[
+                (GuestAddress(0), page_size),
+                (GuestAddress(0x10_0000), page_size + 0x10),
+            ]);
+
+            let guard_pages = GUARD_PAGES.lock().unwrap();
+            for region in mem.iter() {
+                let start = region.as_ptr() as usize;
+                assert!(guard_pages.contains(&(start - page_size)));
+                let end = start + region.size().next_multiple_of(page_size);
+                assert!(guard_pages.contains(&end));
+            }
+        }
+    }
 }
 
 /// Creates a [`GuestMemoryMmap`] of the given size with the contained regions laid out in
 /// accordance with the requirements of the architecture on which the tests are being run.
 pub fn arch_mem(mem_size_bytes: usize) -> GuestMemoryMmap {
-    multi_region_mem(&crate::arch::arch_memory_regions(mem_size_bytes))
+    multi_region_mem(&crate::arch::arch_memory_regions(mem_size_bytes).unwrap())
 }
 
 pub fn create_vmm(
@@ -0,0 +1,132 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum time the microVM can stay idle before being paused, in seconds (one week).
+pub const MAX_AUTO_PAUSE_IDLE_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Source of activity of the microVM watched by the auto-pause.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivitySignal {
+    /// Requests of the guest to the virtio block devices.
+    Block,
+    /// Frames exchanged by the network interfaces, in either direction.
+    Net,
+    /// Packets exchanged by the vsock device, and connections from the host.
+    Vsock,
+    /// The vCPUs spend less than 90% of their time halted.
+    VcpuHaltPct,
+}
+
+impl ActivitySignal {
+    /// All the activity signals.
+    pub const ALL: [ActivitySignal; 4] = [Self::Block, Self::Net, Self::Vsock, Self::VcpuHaltPct];
+
+    /// Whether activity of this signal can be detected while the vCPUs are paused, and then
+    /// resumes an auto-paused microVM.
+    pub fn can_resume(&self) -> bool {
+        matches!(self, Self::Net | Self::Vsock)
+    }
+}
+
+/// Configuration of the automatic pause of the microVM once it is idle.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoPauseConfig {
+    /// Time all the selected signals must have been idle for before the microVM is paused, in
+    /// seconds.
+    pub idle_seconds: u64,
+    /// Sources of activity telling whether the microVM is idle.
+    pub signals: Vec<ActivitySignal>,
+}
+
+/// Errors associated with the auto-pause configuration.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum AutoPauseConfigError {
+    /// The idle time of the auto-pause must be 1 to 604800 seconds (one week).
+    InvalidIdleSeconds,
+    /// The auto-pause needs at least one activity signal.
+    NoSignals,
+    /// The activity signal {0:?} of the auto-pause is selected more than once.
+    DuplicateSignal(ActivitySignal),
+}
+
+impl AutoPauseConfig {
+    /// Checks that the auto-pause can be set up.
+    pub fn validate(&self) -> Result<(), AutoPauseConfigError> {
+        if self.idle_seconds == 0 || self.idle_seconds > MAX_AUTO_PAUSE_IDLE_SECONDS {
+            return Err(AutoPauseConfigError::InvalidIdleSeconds);
+        }
+        if self.signals.is_empty() {
+            return Err(AutoPauseConfigError::NoSignals);
+        }
+        for (idx, signal) in self.signals.iter().enumerate() {
+            if self.signals[..idx].contains(signal) {
+                return Err(AutoPauseConfigError::DuplicateSignal(*signal));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = AutoPauseConfig {
+            idle_seconds: 300,
+            signals: ActivitySignal::ALL.to_vec(),
+        };
+        config.validate().unwrap();
+
+        config.idle_seconds = 0;
+        assert_eq!(
+            config.validate().unwrap_err(),
+            AutoPauseConfigError::InvalidIdleSeconds
+        );
+        config.idle_seconds = MAX_AUTO_PAUSE_IDLE_SECONDS + 1;
+        assert_eq!(
+            config.validate().unwrap_err(),
+            AutoPauseConfigError::InvalidIdleSeconds
+        );
+
+        config.idle_seconds = 300;
+        config.signals = vec![];
+        assert_eq!(
+            config.validate().unwrap_err(),
+            AutoPauseConfigError::NoSignals
+        );
+        config.signals = vec![
+            ActivitySignal::Net,
+            ActivitySignal::Block,
+            ActivitySignal::Net,
+        ];
+        assert_eq!(
+            config.validate().unwrap_err(),
+            AutoPauseConfigError::DuplicateSignal(ActivitySignal::Net)
+        );
+    }
+
+    #[test]
+    fn test_deserialization() {
+        let config: AutoPauseConfig = serde_json::from_str(
+            r#"{"idle_seconds": 300, "signals": ["block", "net", "vsock", "vcpu_halt_pct"]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            AutoPauseConfig {
+                idle_seconds: 300,
+                signals: ActivitySignal::ALL.to_vec(),
+            }
+        );
+        serde_json::from_str::<AutoPauseConfig>(r#"{"idle_seconds": 300, "signals": ["disk"]}"#)
+            .unwrap_err();
+        serde_json::from_str::<AutoPauseConfig>(r#"{"idle_seconds": 300}"#).unwrap_err();
+    }
+}
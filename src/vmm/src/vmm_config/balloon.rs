@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 pub use crate::devices::virtio::balloon::device::BalloonStats;
 pub use crate::devices::virtio::balloon::BALLOON_DEV_ID;
 use crate::devices::virtio::balloon::{Balloon, BalloonConfig};
+use crate::devices::virtio::device::VirtioDevice;
 
 type MutexBalloon = Arc<Mutex<Balloon>>;
 
@@ -28,8 +29,6 @@ pub enum BalloonConfigError {
     CreateFailure(crate::devices::virtio::balloon::BalloonError),
     /// Error updating the balloon device configuration: {0}
     UpdateFailure(std::io::Error),
-    /// Firecracker's huge pages support is incompatible with memory ballooning.
-    HugePages,
 }
 
 /// This struct represents the strongly typed equivalent of the json body
@@ -44,6 +43,12 @@ pub struct BalloonDeviceConfig {
     /// Interval in seconds between refreshing statistics.
     #[serde(default)]
     pub stats_polling_interval_s: u16,
+    /// Virtio features the features offered by the device are restricted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtio_features_pin: Option<u64>,
+    /// Virtio features negotiated with the guest driver, once the device is activated.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub negotiated_virtio_features: Option<u64>,
 }
 
 impl From<BalloonConfig> for BalloonDeviceConfig {
@@ -52,6 +57,8 @@ impl From<BalloonConfig> for BalloonDeviceConfig {
             amount_mib: state.amount_mib,
             deflate_on_oom: state.deflate_on_oom,
             stats_polling_interval_s: state.stats_polling_interval_s,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         }
     }
 }
@@ -92,14 +99,18 @@ impl BalloonBuilder {
     /// Inserts a Balloon device in the store.
     /// If an entry already exists, it will overwrite it.
     pub fn set(&mut self, cfg: BalloonDeviceConfig) -> Result<(), BalloonConfigError> {
-        self.inner = Some(Arc::new(Mutex::new(Balloon::new(
+        let mut balloon = Balloon::new(
             cfg.amount_mib,
             cfg.deflate_on_oom,
             cfg.stats_polling_interval_s,
             // `restored` flag is false because this code path
             // is never called by snapshot restore functionality.
             false,
-        )?)));
+        )?;
+        if let Some(pin) = cfg.virtio_features_pin {
+            balloon.pin_virtio_features(pin)?;
+        }
+        self.inner = Some(Arc::new(Mutex::new(balloon)));
 
         Ok(())
     }
@@ -118,8 +129,14 @@ impl BalloonBuilder {
     pub fn get_config(&self) -> Result<BalloonDeviceConfig, BalloonConfigError> {
         self.get()
             .ok_or(BalloonConfigError::DeviceNotFound)
-            .map(|balloon_mutex| balloon_mutex.lock().expect("Poisoned lock").config())
-            .map(BalloonDeviceConfig::from)
+            .map(|balloon_mutex| {
+                let balloon = balloon_mutex.lock().expect("Poisoned lock");
+                BalloonDeviceConfig {
+                    virtio_features_pin: balloon.virtio_features_pin(),
+                    negotiated_virtio_features: balloon.negotiated_features(),
+                    ..BalloonDeviceConfig::from(balloon.config())
+                }
+            })
     }
 }
 
@@ -141,6 +158,8 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         }
     }
 
@@ -151,6 +170,8 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         assert_eq!(default_balloon_config, balloon_config);
         let mut builder = BalloonBuilder::new();
@@ -172,6 +193,8 @@ pub(crate) mod tests {
             amount_mib: 5,
             deflate_on_oom: false,
             stats_polling_interval_s: 3,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let actual_balloon_config = BalloonDeviceConfig::from(BalloonConfig {
@@ -183,6 +206,53 @@ pub(crate) mod tests {
         assert_eq!(expected_balloon_config, actual_balloon_config);
     }
 
+    #[test]
+    fn test_virtio_features_pin() {
+        let mut builder = BalloonBuilder::new();
+        let version_1 = 1 << 32;
+        let stats_vq = 1 << 1;
+
+        // Pin the features without the deflate on OOM.
+        let balloon_config = BalloonDeviceConfig {
+            deflate_on_oom: true,
+            virtio_features_pin: Some(version_1),
+            ..default_config()
+        };
+        builder.set(balloon_config).unwrap();
+        assert_eq!(
+            builder.get().unwrap().lock().unwrap().avail_features(),
+            version_1
+        );
+        let config = builder.get_config().unwrap();
+        assert_eq!(config.virtio_features_pin, Some(version_1));
+        assert_eq!(config.negotiated_virtio_features, None);
+        // The deflate on OOM is not offered to the guest.
+        assert!(!config.deflate_on_oom);
+
+        // The statistics queue is offered when the statistics are enabled.
+        let balloon_config = BalloonDeviceConfig {
+            stats_polling_interval_s: 1,
+            virtio_features_pin: Some(version_1),
+            ..default_config()
+        };
+        assert_eq!(
+            builder.set(balloon_config).unwrap_err().to_string(),
+            "Error creating the balloon device: Statistics are enabled, but the statistics queue \
+             feature is not pinned."
+        );
+
+        // Features the device cannot offer, like the statistics queue when they are disabled.
+        let balloon_config = BalloonDeviceConfig {
+            virtio_features_pin: Some(version_1 | stats_vq),
+            ..default_config()
+        };
+        assert_eq!(
+            builder.set(balloon_config).unwrap_err().to_string(),
+            "Error creating the balloon device: Cannot pin the virtio features: The pinned virtio \
+             features 0x2 cannot be offered by the device."
+        );
+    }
+
     #[test]
     fn test_set_device() {
         let mut builder = BalloonBuilder::new();
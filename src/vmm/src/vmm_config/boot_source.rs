@@ -3,6 +3,7 @@
 
 use std::fs::File;
 use std::io;
+use std::os::unix::io::{FromRawFd, RawFd};
 
 use serde::{Deserialize, Serialize};
 
@@ -25,9 +26,16 @@ pub const DEFAULT_KERNEL_CMDLINE: &str = "reboot=k panic=1 pci=off nomodule 8250
 #[serde(deny_unknown_fields)]
 pub struct BootSourceConfig {
     /// Path of the kernel image.
+    #[serde(default)]
     pub kernel_image_path: String,
+    /// File descriptor, inherited from the parent process, of a memfd sealed against writes
+    /// holding the kernel image. Used instead of `kernel_image_path`.
+    pub kernel_fd: Option<RawFd>,
     /// Path of the initrd, if there is one.
     pub initrd_path: Option<String>,
+    /// File descriptor, inherited from the parent process, of a memfd sealed against writes
+    /// holding the initrd. Used instead of `initrd_path`.
+    pub initrd_fd: Option<RawFd>,
     /// The boot arguments to pass to the kernel. If this field is uninitialized,
     /// DEFAULT_KERNEL_CMDLINE is used.
     pub boot_args: Option<String>,
@@ -44,6 +52,25 @@ pub enum BootSourceConfigError {
     InvalidKernelCommandLine(String),
     /// Firecracker's huge pages support is incompatible with initrds.
     HugePagesAndInitRd,
+    /// The kernel image path and file descriptor cannot be both set.
+    KernelPathAndFd,
+    /// The initrd path and file descriptor cannot be both set.
+    InitrdPathAndFd,
+    /// The kernel file descriptor is invalid: {0}
+    InvalidKernelFd(io::Error),
+    /// The initrd file descriptor is invalid: {0}
+    InvalidInitrdFd(io::Error),
+    /// The kernel file descriptor does not refer to a memfd sealed against writes.
+    UnsealedKernelFd,
+    /// The initrd file descriptor does not refer to a memfd sealed against writes.
+    UnsealedInitrdFd,
+}
+
+impl BootSourceConfig {
+    /// Whether the microVM boots with an initrd.
+    pub fn has_initrd(&self) -> bool {
+        self.initrd_path.is_some() || self.initrd_fd.is_some()
+    }
 }
 
 /// Holds the kernel specification (both configuration as well as runtime details).
@@ -71,14 +98,28 @@ impl BootConfig {
     /// Creates the BootConfig based on a given configuration.
     pub fn new(cfg: &BootSourceConfig) -> Result<Self, BootSourceConfigError> {
         use self::BootSourceConfigError::{
-            InvalidInitrdPath, InvalidKernelCommandLine, InvalidKernelPath,
+            InitrdPathAndFd, InvalidInitrdFd, InvalidInitrdPath, InvalidKernelCommandLine,
+            InvalidKernelFd, InvalidKernelPath, KernelPathAndFd, UnsealedInitrdFd,
+            UnsealedKernelFd,
         };
 
         // Validate boot source config.
-        let kernel_file = File::open(&cfg.kernel_image_path).map_err(InvalidKernelPath)?;
-        let initrd_file: Option<File> = match &cfg.initrd_path {
-            Some(path) => Some(File::open(path).map_err(InvalidInitrdPath)?),
-            None => None,
+        let kernel_file = match cfg.kernel_fd {
+            Some(_) if !cfg.kernel_image_path.is_empty() => return Err(KernelPathAndFd),
+            Some(fd) => {
+                let file = dup_fd(fd).map_err(InvalidKernelFd)?;
+                check_sealed_memfd(file).ok_or(UnsealedKernelFd)?
+            }
+            None => File::open(&cfg.kernel_image_path).map_err(InvalidKernelPath)?,
+        };
+        let initrd_file: Option<File> = match (&cfg.initrd_path, cfg.initrd_fd) {
+            (Some(_), Some(_)) => return Err(InitrdPathAndFd),
+            (Some(path), None) => Some(File::open(path).map_err(InvalidInitrdPath)?),
+            (None, Some(fd)) => {
+                let file = dup_fd(fd).map_err(InvalidInitrdFd)?;
+                Some(check_sealed_memfd(file).ok_or(UnsealedInitrdFd)?)
+            }
+            (None, None) => None,
         };
 
         let cmdline_str = match cfg.boot_args.as_ref() {
@@ -97,12 +138,52 @@ impl BootConfig {
     }
 }
 
+/// Duplicates the inherited file descriptor `fd`, so that the configuration can be applied more
+/// than once without closing it.
+fn dup_fd(fd: RawFd) -> Result<File, io::Error> {
+    // SAFETY: `fcntl` does not access memory, and fails with `EBADF` if `fd` is not open.
+    let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if dup < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `dup` is a file descriptor that we just opened, and that nothing else owns.
+    Ok(unsafe { File::from_raw_fd(dup) })
+}
+
+/// Returns `file` if it is a memfd sealed against writes, which guarantees that the image it holds
+/// cannot be changed by the parent process after it is validated.
+fn check_sealed_memfd(file: File) -> Option<File> {
+    let memfd = memfd::Memfd::try_from_file(file).ok()?;
+    let seals = memfd.seals().ok()?;
+    seals
+        .contains(&memfd::FileSeal::SealWrite)
+        .then(|| memfd.into_file())
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
     use utils::tempfile::TempFile;
 
     use super::*;
     use crate::snapshot::Snapshot;
+    use crate::utilities::mock_resources::kernel_image_path;
+
+    // Creates a memfd holding the test kernel image, sealed against writes if `sealed` is set.
+    fn kernel_memfd(sealed: bool) -> memfd::Memfd {
+        let memfd = memfd::MemfdOptions::default()
+            .allow_sealing(true)
+            .create("kernel")
+            .unwrap();
+        let image = std::fs::read(kernel_image_path(None)).unwrap();
+        memfd.as_file().write_all(&image).unwrap();
+        if sealed {
+            memfd.add_seal(memfd::FileSeal::SealWrite).unwrap();
+        }
+        memfd
+    }
 
     #[test]
     fn test_boot_config() {
@@ -113,6 +194,7 @@ pub(crate) mod tests {
             boot_args: None,
             initrd_path: None,
             kernel_image_path: kernel_path,
+            ..Default::default()
         };
 
         let boot_cfg = BootConfig::new(&boot_src_cfg).unwrap();
@@ -123,12 +205,105 @@ pub(crate) mod tests {
         );
     }
 
+    #[test]
+    fn test_boot_config_from_fd() {
+        let kernel = kernel_memfd(true);
+        let initrd = kernel_memfd(true);
+        let boot_src_cfg = BootSourceConfig {
+            kernel_fd: Some(kernel.as_raw_fd()),
+            initrd_fd: Some(initrd.as_raw_fd()),
+            ..Default::default()
+        };
+        let boot_cfg = BootConfig::new(&boot_src_cfg).unwrap();
+        // The loader reads the image from a duplicate of the inherited file descriptor.
+        assert_ne!(boot_cfg.kernel_file.as_raw_fd(), kernel.as_raw_fd());
+        assert_eq!(
+            boot_cfg.kernel_file.metadata().unwrap().len(),
+            kernel.as_file().metadata().unwrap().len()
+        );
+        assert!(boot_cfg.initrd_file.is_some());
+        // The configuration can be applied again.
+        BootConfig::new(&boot_src_cfg).unwrap();
+
+        // Both a path and a file descriptor.
+        let cfg = BootSourceConfig {
+            kernel_image_path: kernel_image_path(None),
+            kernel_fd: Some(kernel.as_raw_fd()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&cfg),
+            Err(BootSourceConfigError::KernelPathAndFd)
+        ));
+        let cfg = BootSourceConfig {
+            kernel_fd: Some(kernel.as_raw_fd()),
+            initrd_path: Some(kernel_image_path(None)),
+            initrd_fd: Some(initrd.as_raw_fd()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&cfg),
+            Err(BootSourceConfigError::InitrdPathAndFd)
+        ));
+
+        // A file descriptor which is not open.
+        let cfg = BootSourceConfig {
+            kernel_fd: Some(-1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&cfg),
+            Err(BootSourceConfigError::InvalidKernelFd(_))
+        ));
+        let cfg = BootSourceConfig {
+            kernel_fd: Some(kernel.as_raw_fd()),
+            initrd_fd: Some(-1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&cfg),
+            Err(BootSourceConfigError::InvalidInitrdFd(_))
+        ));
+
+        // A memfd which is not sealed against writes.
+        let unsealed = kernel_memfd(false);
+        let cfg = BootSourceConfig {
+            kernel_fd: Some(unsealed.as_raw_fd()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&cfg),
+            Err(BootSourceConfigError::UnsealedKernelFd)
+        ));
+        let cfg = BootSourceConfig {
+            kernel_fd: Some(kernel.as_raw_fd()),
+            initrd_fd: Some(unsealed.as_raw_fd()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&cfg),
+            Err(BootSourceConfigError::UnsealedInitrdFd)
+        ));
+
+        // A regular file.
+        let file = TempFile::new().unwrap();
+        let cfg = BootSourceConfig {
+            kernel_fd: Some(file.as_file().as_raw_fd()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&cfg),
+            Err(BootSourceConfigError::UnsealedKernelFd)
+        ));
+    }
+
     #[test]
     fn test_serde() {
         let boot_src_cfg = BootSourceConfig {
             boot_args: Some(DEFAULT_KERNEL_CMDLINE.to_string()),
             initrd_path: Some("/tmp/initrd".to_string()),
             kernel_image_path: "./vmlinux.bin".to_string(),
+            ..Default::default()
         };
 
         let mut snapshot_data = vec![0u8; 1000];
@@ -0,0 +1,427 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Versions of the format of the configuration file, and the migrations between them.
+//!
+//! The schema version of a configuration file is given by its top-level `schema_version`
+//! field. The changes made to the format by each version are:
+//!
+//! - Version 1: the format of the configuration files written for Firecracker v0.x.
+//! - Version 2:
+//!   - `machine-config.ht_enabled` is renamed to `machine-config.smt`.
+//!   - `logger.log_fifo` is renamed to `logger.log_path`.
+//!   - `metrics.metrics_fifo` is renamed to `metrics.metrics_path`.
+//!   - `network-interfaces[].allow_mmds_requests` is replaced by the list of the network interfaces
+//!     in `mmds-config.network_interfaces`.
+
+use serde_json::{Map, Value};
+
+/// Name of the top-level field holding the schema version of a configuration file.
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+/// Oldest schema version of the configuration file which can be migrated.
+pub const MIN_SCHEMA_VERSION: u64 = 1;
+/// Schema version of the configuration file understood by this Firecracker.
+pub const CURRENT_SCHEMA_VERSION: u64 = 2;
+
+/// Fields of the schema version 1 which no longer exist, used to recognize the configuration
+/// files without a schema version written in that format.
+const V1_FIELDS: [(&str, &str); 4] = [
+    ("machine-config", "ht_enabled"),
+    ("logger", "log_fifo"),
+    ("metrics", "metrics_fifo"),
+    ("network-interfaces", "allow_mmds_requests"),
+];
+
+/// Errors associated with the migration of a configuration file.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum ConfigSchemaError {
+    /// The configuration file must hold a JSON object.
+    NotAnObject,
+    /// The schema_version must be a positive integer.
+    InvalidSchemaVersion,
+    /// Schema version {0} is not supported, the oldest supported schema version is {1}.
+    ObsoleteSchemaVersion(u64, u64),
+    /// Schema version {0} is newer than the schema version {1} supported by this Firecracker, which must be upgraded to use this configuration file.
+    FutureSchemaVersion(u64, u64),
+    /// Both `{0}.{1}` and `{0}.{2}` are set, the former being the old name of the latter.
+    RenameConflict(&'static str, &'static str, &'static str),
+    /// `{0}` must hold a JSON object.
+    InvalidSection(&'static str),
+}
+
+/// Migration applied to a configuration file.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, displaydoc::Display)]
+pub enum ConfigMigration {
+    /// Renamed `{0}.{1}` to `{0}.{2}`.
+    RenamedField(&'static str, &'static str, &'static str),
+    /// Moved `allow_mmds_requests` of network interface `{0}` to `mmds-config.network_interfaces`.
+    MovedMmdsInterface(String),
+    /// Removed `allow_mmds_requests` of network interface `{0}`, which did not allow MMDS requests.
+    RemovedMmdsInterface(String),
+}
+
+/// Configuration file migrated to the current schema version.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MigratedConfig {
+    /// The configuration, holding the current schema version.
+    pub config: Value,
+    /// The schema version of the configuration file before the migration.
+    pub from_version: u64,
+    /// Whether the configuration file had no schema version, which was then guessed from its
+    /// fields.
+    pub sniffed: bool,
+    /// The migrations applied to the configuration file, in order.
+    pub migrations: Vec<ConfigMigration>,
+}
+
+impl MigratedConfig {
+    /// Returns the warnings to give about the migration of the configuration file.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.sniffed {
+            warnings.push(format!(
+                "The configuration file has no `{SCHEMA_VERSION_KEY}`, assuming schema version \
+                 {}. Configuration files without a schema version are deprecated.",
+                self.from_version
+            ));
+        }
+        warnings.extend(self.migrations.iter().map(|migration| {
+            format!(
+                "Configuration file migrated from schema version {}: {migration}",
+                self.from_version
+            )
+        }));
+        warnings
+    }
+}
+
+/// Migrates the configuration file `config` to the current schema version.
+pub fn migrate_config(config: Value) -> Result<MigratedConfig, ConfigSchemaError> {
+    let Value::Object(mut config) = config else {
+        return Err(ConfigSchemaError::NotAnObject);
+    };
+
+    let (from_version, sniffed) = match config.get(SCHEMA_VERSION_KEY) {
+        Some(version) => (
+            version
+                .as_u64()
+                .filter(|&version| version > 0)
+                .ok_or(ConfigSchemaError::InvalidSchemaVersion)?,
+            false,
+        ),
+        None => (sniff_schema_version(&config), true),
+    };
+    if from_version < MIN_SCHEMA_VERSION {
+        return Err(ConfigSchemaError::ObsoleteSchemaVersion(
+            from_version,
+            MIN_SCHEMA_VERSION,
+        ));
+    }
+    if from_version > CURRENT_SCHEMA_VERSION {
+        return Err(ConfigSchemaError::FutureSchemaVersion(
+            from_version,
+            CURRENT_SCHEMA_VERSION,
+        ));
+    }
+
+    let mut migrations = Vec::new();
+    if from_version < 2 {
+        migrate_v1_to_v2(&mut config, &mut migrations)?;
+    }
+    config.insert(
+        SCHEMA_VERSION_KEY.to_string(),
+        Value::from(CURRENT_SCHEMA_VERSION),
+    );
+
+    Ok(MigratedConfig {
+        config: Value::Object(config),
+        from_version,
+        sniffed,
+        migrations,
+    })
+}
+
+/// Guesses the schema version of a configuration file without one: the files holding fields
+/// which only exist in the schema version 1 are of that version, and all the others are
+/// assumed to be of the current version.
+fn sniff_schema_version(config: &Map<String, Value>) -> u64 {
+    let has_field = |section: &str, field: &str| match config.get(section) {
+        Some(Value::Object(section)) => section.contains_key(field),
+        Some(Value::Array(items)) => items.iter().any(|item| {
+            item.as_object()
+                .is_some_and(|item| item.contains_key(field))
+        }),
+        _ => false,
+    };
+    if V1_FIELDS
+        .iter()
+        .any(|(section, field)| has_field(section, field))
+    {
+        1
+    } else {
+        CURRENT_SCHEMA_VERSION
+    }
+}
+
+fn migrate_v1_to_v2(
+    config: &mut Map<String, Value>,
+    migrations: &mut Vec<ConfigMigration>,
+) -> Result<(), ConfigSchemaError> {
+    rename_field(config, "machine-config", "ht_enabled", "smt", migrations)?;
+    rename_field(config, "logger", "log_fifo", "log_path", migrations)?;
+    rename_field(
+        config,
+        "metrics",
+        "metrics_fifo",
+        "metrics_path",
+        migrations,
+    )?;
+
+    let mut mmds_interfaces = Vec::new();
+    if let Some(Value::Array(ifaces)) = config.get_mut("network-interfaces") {
+        for iface in ifaces.iter_mut() {
+            let Some(iface) = iface.as_object_mut() else {
+                continue;
+            };
+            let Some(allow_mmds_requests) = iface.remove("allow_mmds_requests") else {
+                continue;
+            };
+            let iface_id = iface
+                .get("iface_id")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            if allow_mmds_requests.as_bool() == Some(true) {
+                mmds_interfaces.push(iface_id.clone());
+                migrations.push(ConfigMigration::MovedMmdsInterface(iface_id));
+            } else {
+                migrations.push(ConfigMigration::RemovedMmdsInterface(iface_id));
+            }
+        }
+    }
+    if !mmds_interfaces.is_empty() {
+        let mmds_config = config
+            .entry("mmds-config")
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .ok_or(ConfigSchemaError::InvalidSection("mmds-config"))?;
+        let network_interfaces = mmds_config
+            .entry("network_interfaces")
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .ok_or(ConfigSchemaError::InvalidSection(
+                "mmds-config.network_interfaces",
+            ))?;
+        for iface_id in mmds_interfaces {
+            let iface_id = Value::String(iface_id);
+            if !network_interfaces.contains(&iface_id) {
+                network_interfaces.push(iface_id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Renames the field `from` of the section `section` of the configuration file to `to`.
+fn rename_field(
+    config: &mut Map<String, Value>,
+    section: &'static str,
+    from: &'static str,
+    to: &'static str,
+    migrations: &mut Vec<ConfigMigration>,
+) -> Result<(), ConfigSchemaError> {
+    let Some(Value::Object(section_fields)) = config.get_mut(section) else {
+        return Ok(());
+    };
+    let Some(value) = section_fields.remove(from) else {
+        return Ok(());
+    };
+    if section_fields.contains_key(to) {
+        return Err(ConfigSchemaError::RenameConflict(section, from, to));
+    }
+    section_fields.insert(to.to_string(), value);
+    migrations.push(ConfigMigration::RenamedField(section, from, to));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_CONFIG: &str = include_str!("test_fixtures/v1.json");
+    const V1_UNVERSIONED_CONFIG: &str = include_str!("test_fixtures/v1_unversioned.json");
+    const V2_CONFIG: &str = include_str!("test_fixtures/v2.json");
+
+    fn v1_migrations() -> Vec<ConfigMigration> {
+        vec![
+            ConfigMigration::RenamedField("machine-config", "ht_enabled", "smt"),
+            ConfigMigration::RenamedField("logger", "log_fifo", "log_path"),
+            ConfigMigration::RenamedField("metrics", "metrics_fifo", "metrics_path"),
+            ConfigMigration::MovedMmdsInterface("eth0".to_string()),
+            ConfigMigration::RemovedMmdsInterface("eth1".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let migrated = migrate_config(serde_json::from_str(V1_CONFIG).unwrap()).unwrap();
+        assert_eq!(
+            migrated,
+            MigratedConfig {
+                config: serde_json::from_str(V2_CONFIG).unwrap(),
+                from_version: 1,
+                sniffed: false,
+                migrations: v1_migrations(),
+            }
+        );
+        assert_eq!(
+            migrated.warnings(),
+            vec![
+                "Configuration file migrated from schema version 1: Renamed \
+                 `machine-config.ht_enabled` to `machine-config.smt`.",
+                "Configuration file migrated from schema version 1: Renamed `logger.log_fifo` to \
+                 `logger.log_path`.",
+                "Configuration file migrated from schema version 1: Renamed \
+                 `metrics.metrics_fifo` to `metrics.metrics_path`.",
+                "Configuration file migrated from schema version 1: Moved `allow_mmds_requests` \
+                 of network interface `eth0` to `mmds-config.network_interfaces`.",
+                "Configuration file migrated from schema version 1: Removed `allow_mmds_requests` \
+                 of network interface `eth1`, which did not allow MMDS requests.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_migrate_unversioned() {
+        // A file holding fields of the schema version 1 is recognized as such.
+        let migrated =
+            migrate_config(serde_json::from_str(V1_UNVERSIONED_CONFIG).unwrap()).unwrap();
+        assert_eq!(
+            migrated,
+            MigratedConfig {
+                config: serde_json::from_str(V2_CONFIG).unwrap(),
+                from_version: 1,
+                sniffed: true,
+                migrations: v1_migrations(),
+            }
+        );
+        assert_eq!(
+            migrated.warnings()[0],
+            "The configuration file has no `schema_version`, assuming schema version 1. \
+             Configuration files without a schema version are deprecated."
+        );
+        assert_eq!(migrated.warnings().len(), 6);
+
+        // Any other file is assumed to be of the current version.
+        let mut config: Value = serde_json::from_str(V2_CONFIG).unwrap();
+        config.as_object_mut().unwrap().remove(SCHEMA_VERSION_KEY);
+        let migrated = migrate_config(config).unwrap();
+        assert_eq!(
+            migrated,
+            MigratedConfig {
+                config: serde_json::from_str(V2_CONFIG).unwrap(),
+                from_version: CURRENT_SCHEMA_VERSION,
+                sniffed: true,
+                migrations: vec![],
+            }
+        );
+        assert_eq!(migrated.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_migrate_current() {
+        let migrated = migrate_config(serde_json::from_str(V2_CONFIG).unwrap()).unwrap();
+        assert_eq!(
+            migrated,
+            MigratedConfig {
+                config: serde_json::from_str(V2_CONFIG).unwrap(),
+                from_version: CURRENT_SCHEMA_VERSION,
+                sniffed: false,
+                migrations: vec![],
+            }
+        );
+        assert!(migrated.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_migrate_mmds_config() {
+        // The network interfaces allowing MMDS requests are appended to the existing ones.
+        let config = serde_json::json!({
+            "schema_version": 1,
+            "network-interfaces": [
+                { "iface_id": "eth0", "host_dev_name": "tap0", "allow_mmds_requests": true },
+                { "iface_id": "eth1", "host_dev_name": "tap1", "allow_mmds_requests": true }
+            ],
+            "mmds-config": { "version": "V2", "network_interfaces": ["eth1"] }
+        });
+        let migrated = migrate_config(config).unwrap();
+        assert_eq!(
+            migrated.config,
+            serde_json::json!({
+                "schema_version": 2,
+                "network-interfaces": [
+                    { "iface_id": "eth0", "host_dev_name": "tap0" },
+                    { "iface_id": "eth1", "host_dev_name": "tap1" }
+                ],
+                "mmds-config": { "version": "V2", "network_interfaces": ["eth1", "eth0"] }
+            })
+        );
+
+        let config = serde_json::json!({
+            "schema_version": 1,
+            "network-interfaces": [
+                { "iface_id": "eth0", "host_dev_name": "tap0", "allow_mmds_requests": true }
+            ],
+            "mmds-config": []
+        });
+        assert_eq!(
+            migrate_config(config).unwrap_err(),
+            ConfigSchemaError::InvalidSection("mmds-config")
+        );
+    }
+
+    #[test]
+    fn test_migrate_errors() {
+        assert_eq!(
+            migrate_config(serde_json::json!([])).unwrap_err(),
+            ConfigSchemaError::NotAnObject
+        );
+        for version in [
+            serde_json::json!(0),
+            serde_json::json!(-1),
+            serde_json::json!(1.5),
+            serde_json::json!("2"),
+        ] {
+            assert_eq!(
+                migrate_config(serde_json::json!({ "schema_version": version })).unwrap_err(),
+                ConfigSchemaError::InvalidSchemaVersion
+            );
+        }
+
+        let err =
+            migrate_config(serde_json::json!({ "schema_version": CURRENT_SCHEMA_VERSION + 1 }))
+                .unwrap_err();
+        assert_eq!(
+            err,
+            ConfigSchemaError::FutureSchemaVersion(3, CURRENT_SCHEMA_VERSION)
+        );
+        assert_eq!(
+            err.to_string(),
+            "Schema version 3 is newer than the schema version 2 supported by this Firecracker, \
+             which must be upgraded to use this configuration file."
+        );
+
+        // The old and the new name of a field cannot be both set.
+        let config = serde_json::json!({
+            "schema_version": 1,
+            "machine-config": { "vcpu_count": 2, "mem_size_mib": 1024, "ht_enabled": true, "smt": false }
+        });
+        assert_eq!(
+            migrate_config(config).unwrap_err(),
+            ConfigSchemaError::RenameConflict("machine-config", "ht_enabled", "smt")
+        );
+    }
+}
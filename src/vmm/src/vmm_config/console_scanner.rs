@@ -0,0 +1,138 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of patterns the console scanner looks for.
+pub const MAX_CONSOLE_PATTERNS: usize = 8;
+/// Maximum length of a console pattern, in bytes.
+pub const MAX_CONSOLE_PATTERN_LEN: usize = 64;
+/// Patterns looked for when none are configured: the messages printed by Linux guests when they
+/// crash.
+pub const DEFAULT_CONSOLE_PATTERNS: [&str; 3] = [
+    "Kernel panic - not syncing",
+    "BUG: kernel NULL pointer dereference",
+    "Internal error: Oops",
+];
+
+/// Action taken when the console scanner finds a pattern in the guest console output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsoleMatchAction {
+    /// Only report the match, through the metrics, the logs and the event bus.
+    #[default]
+    Log,
+    /// Report the match and pause the microVM.
+    Pause,
+}
+
+/// Configuration of the scanner looking for patterns, such as kernel panic messages, in the
+/// output the guest writes on the serial console.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConsoleScannerConfig {
+    /// Patterns to look for. When not specified, [`DEFAULT_CONSOLE_PATTERNS`] are used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patterns: Option<Vec<String>>,
+    /// Action taken when a pattern is found.
+    #[serde(default)]
+    pub on_match: ConsoleMatchAction,
+}
+
+/// Errors associated with the console scanner configuration.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum ConsoleScannerConfigError {
+    /// At most 8 console patterns can be configured, got {0}.
+    TooManyPatterns(usize),
+    /// Console patterns must be 1 to 64 bytes long: {0:?}.
+    InvalidPattern(String),
+}
+
+impl ConsoleScannerConfig {
+    /// Checks that the patterns can be looked for by the console scanner.
+    pub fn validate(&self) -> Result<(), ConsoleScannerConfigError> {
+        let Some(patterns) = self.patterns.as_ref() else {
+            return Ok(());
+        };
+        if patterns.len() > MAX_CONSOLE_PATTERNS {
+            return Err(ConsoleScannerConfigError::TooManyPatterns(patterns.len()));
+        }
+        match patterns
+            .iter()
+            .find(|pattern| pattern.is_empty() || pattern.len() > MAX_CONSOLE_PATTERN_LEN)
+        {
+            Some(pattern) => Err(ConsoleScannerConfigError::InvalidPattern(pattern.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the patterns to look for.
+    pub fn patterns(&self) -> Vec<String> {
+        match self.patterns.as_ref() {
+            Some(patterns) => patterns.clone(),
+            None => DEFAULT_CONSOLE_PATTERNS.map(String::from).to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let config = ConsoleScannerConfig::default();
+        config.validate().unwrap();
+        assert_eq!(config.patterns(), DEFAULT_CONSOLE_PATTERNS);
+
+        let config = ConsoleScannerConfig {
+            patterns: Some(vec![
+                "a".repeat(MAX_CONSOLE_PATTERN_LEN);
+                MAX_CONSOLE_PATTERNS
+            ]),
+            on_match: ConsoleMatchAction::Pause,
+        };
+        config.validate().unwrap();
+        assert_eq!(config.patterns().len(), MAX_CONSOLE_PATTERNS);
+
+        let config = ConsoleScannerConfig {
+            patterns: Some(vec!["a".to_string(); MAX_CONSOLE_PATTERNS + 1]),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate().unwrap_err(),
+            ConsoleScannerConfigError::TooManyPatterns(MAX_CONSOLE_PATTERNS + 1)
+        );
+
+        for pattern in [String::new(), "a".repeat(MAX_CONSOLE_PATTERN_LEN + 1)] {
+            let config = ConsoleScannerConfig {
+                patterns: Some(vec!["panic".to_string(), pattern.clone()]),
+                ..Default::default()
+            };
+            assert_eq!(
+                config.validate().unwrap_err(),
+                ConsoleScannerConfigError::InvalidPattern(pattern)
+            );
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let config: ConsoleScannerConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, ConsoleScannerConfig::default());
+
+        let config: ConsoleScannerConfig =
+            serde_json::from_str(r#"{"patterns": ["Oops"], "on_match": "pause"}"#).unwrap();
+        assert_eq!(
+            config,
+            ConsoleScannerConfig {
+                patterns: Some(vec!["Oops".to_string()]),
+                on_match: ConsoleMatchAction::Pause,
+            }
+        );
+
+        serde_json::from_str::<ConsoleScannerConfig>(r#"{"on_match": "reboot"}"#).unwrap_err();
+        serde_json::from_str::<ConsoleScannerConfig>(r#"{"pattern": ["Oops"]}"#).unwrap_err();
+    }
+}
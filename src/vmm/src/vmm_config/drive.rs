@@ -9,7 +9,9 @@ use serde::{Deserialize, Serialize};
 
 use super::RateLimiterConfig;
 use crate::devices::virtio::block::device::Block;
-pub use crate::devices::virtio::block::virtio::device::FileEngineType;
+pub use crate::devices::virtio::block::virtio::device::{
+    DetectZeroes, FileEngineType, IoEngineOpts, IoUringConfig, OnHostEnospc, RateLimiterScope,
+};
 use crate::devices::virtio::block::{BlockError, CacheType};
 use crate::VmmError;
 
@@ -22,8 +24,23 @@ pub enum DriveError {
     CreateRateLimiter(io::Error),
     /// Unable to patch the block device: {0} Please verify the request arguments.
     DeviceUpdate(VmmError),
+    /// The drive ID is already in use: {0}
+    DriveIdInUse(String),
+    /// Cannot hot-plug the block device: {0}
+    Hotplug(VmmError),
+    /// A root block device can't be hot-plugged.
+    HotplugRootDevice,
+    /// Invalid drive ID: {0}
+    InvalidDriveId(String),
+    /// No free slot to hot-plug the block device in. Slots are reserved with `hotplug_slots` in
+    /// the machine configuration.
+    NoHotplugSlot,
+    /// The root block device can't be removed.
+    RemoveRootDevice,
     /// A root block device already exists!
     RootBlockDeviceAlreadyAdded,
+    /// Cannot unplug the block device: {0}
+    Unplug(VmmError),
 }
 
 /// Use this structure to set up the Block Device before booting the kernel.
@@ -43,6 +60,12 @@ pub struct BlockDeviceConfig {
     /// the guest driver.
     #[serde(default)]
     pub cache_type: CacheType,
+    /// Virtio features the features offered by the device are restricted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtio_features_pin: Option<u64>,
+    /// Virtio features negotiated with the guest driver, once the device is activated.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub negotiated_virtio_features: Option<u64>,
 
     // VirtioBlock specific fields
     /// If set to true, the drive is opened in read-only mode. Otherwise, the
@@ -52,12 +75,43 @@ pub struct BlockDeviceConfig {
     pub path_on_host: Option<String>,
     /// Rate Limiter for I/O operations.
     pub rate_limiter: Option<RateLimiterConfig>,
+    /// Whether the rate limiter limits the I/O of the whole device, or of each virtio queue.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limiter_scope: Option<RateLimiterScope>,
     /// The type of IO engine used by the device.
     // #[serde(default)]
     // #[serde(rename = "io_engine")]
     // pub file_engine_type: FileEngineType,
     #[serde(rename = "io_engine")]
     pub file_engine_type: Option<FileEngineType>,
+    /// How writes consisting of zeroes are handled by the device.
+    pub detect_zeroes: Option<DetectZeroes>,
+    /// If set to true, the backing file is opened with `O_DIRECT`, bypassing the host page cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_direct: Option<bool>,
+    /// Options of the IO engine used by the device.
+    pub io_engine_opts: Option<IoEngineOpts>,
+    /// Maximal size of the virtio queue offered to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<u16>,
+    /// Number of virtio queues offered to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<u16>,
+    /// Serial of the drive, returned to the guest as its ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    /// Logical block size of the disk advertised to the guest, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logical_block_size: Option<u32>,
+    /// Physical block size of the disk advertised to the guest, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical_block_size: Option<u32>,
+    /// How writes failing because the host file system is out of space are handled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_host_enospc: Option<OnHostEnospc>,
+    /// Setup of the io_uring instance of the `Async` IO engine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_uring: Option<IoUringConfig>,
 
     // VhostUserBlock specific fields
     /// Path to the vhost-user socket.
@@ -77,6 +131,11 @@ pub struct BlockDeviceUpdateConfig {
     pub path_on_host: Option<String>,
     /// New rate limiter config.
     pub rate_limiter: Option<RateLimiterConfig>,
+    /// Makes the drive read-only. A read-only drive can't be made writable.
+    pub is_read_only: Option<bool>,
+    /// Retries the requests kept pending since the host file system ran out of space, and
+    /// resumes the processing of the queues.
+    pub retry_pending: Option<bool>,
 }
 
 /// Wrapper for the collection that holds all the Block Devices
@@ -166,6 +225,38 @@ impl BlockBuilder {
         Ok(())
     }
 
+    /// Builds a block device to hot-plug in the running microVM, based on a block device config.
+    /// Unlike [`BlockBuilder::insert`], existing devices can't be replaced, and the device is
+    /// only kept in the builder's list once added with [`BlockBuilder::add_virtio_device`].
+    pub fn build_hotplug(
+        &self,
+        config: BlockDeviceConfig,
+    ) -> Result<Arc<Mutex<Block>>, DriveError> {
+        // The kernel cmdline telling the guest about its root device can't change after boot.
+        if config.is_root_device {
+            return Err(DriveError::HotplugRootDevice);
+        }
+        if self.get_index_of_drive_id(&config.drive_id).is_some() {
+            return Err(DriveError::DriveIdInUse(config.drive_id));
+        }
+
+        Ok(Arc::new(Mutex::new(
+            Block::new(config).map_err(DriveError::CreateBlockDevice)?,
+        )))
+    }
+
+    /// Removes the device `drive_id` from the list, and returns it. The root device can't be
+    /// removed, since the kernel cmdline may point the guest at it.
+    pub fn remove(&mut self, drive_id: &str) -> Result<Arc<Mutex<Block>>, DriveError> {
+        let index = self
+            .get_index_of_drive_id(drive_id)
+            .ok_or_else(|| DriveError::InvalidDriveId(drive_id.to_string()))?;
+        if index == 0 && self.has_root_device() {
+            return Err(DriveError::RemoveRootDevice);
+        }
+        Ok(self.devices.remove(index).unwrap())
+    }
+
     /// Returns a vec with the structures used to configure the devices.
     pub fn configs(&self) -> Vec<BlockDeviceConfig> {
         self.devices
@@ -181,6 +272,7 @@ mod tests {
 
     use super::*;
     use crate::devices::virtio::block::virtio::VirtioBlockError;
+    use crate::devices::virtio::device::QueueSizeError;
 
     impl PartialEq for DriveError {
         fn eq(&self, other: &DriveError) -> bool {
@@ -201,9 +293,22 @@ mod tests {
 
                 path_on_host: self.path_on_host.clone(),
                 rate_limiter: self.rate_limiter,
+                rate_limiter_scope: self.rate_limiter_scope,
                 file_engine_type: self.file_engine_type,
+                detect_zeroes: self.detect_zeroes,
+                open_direct: self.open_direct,
+                io_engine_opts: self.io_engine_opts,
+                queue_size: self.queue_size,
+                num_queues: self.num_queues,
+                serial: self.serial.clone(),
+                logical_block_size: self.logical_block_size,
+                physical_block_size: self.physical_block_size,
+                on_host_enospc: self.on_host_enospc,
+                io_uring: self.io_uring,
 
                 socket: self.socket.clone(),
+                virtio_features_pin: None,
+                negotiated_virtio_features: None,
             }
         }
     }
@@ -228,9 +333,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let mut block_devs = BlockBuilder::new();
@@ -260,9 +378,22 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some(dummy_path),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let mut block_devs = BlockBuilder::new();
@@ -289,9 +420,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let dummy_file_2 = TempFile::new().unwrap();
@@ -305,9 +449,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let mut block_devs = BlockBuilder::new();
@@ -332,9 +489,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let dummy_file_2 = TempFile::new().unwrap();
@@ -348,9 +518,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let dummy_file_3 = TempFile::new().unwrap();
@@ -364,9 +547,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_3),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let mut block_devs = BlockBuilder::new();
@@ -405,9 +601,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let dummy_file_2 = TempFile::new().unwrap();
@@ -421,9 +630,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let dummy_file_3 = TempFile::new().unwrap();
@@ -437,9 +659,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_3),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let mut block_devs = BlockBuilder::new();
@@ -479,9 +714,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1.clone()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let dummy_file_2 = TempFile::new().unwrap();
@@ -495,9 +743,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2.clone()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let mut block_devs = BlockBuilder::new();
@@ -559,9 +820,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
         // Switch roots and add a PARTUUID for the new one.
         let mut root_block_device_old = root_block_device;
@@ -575,9 +849,22 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         block_devs.insert(root_block_device_old).unwrap();
@@ -601,9 +888,22 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some(dummy_file.as_path().to_str().unwrap().to_string()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: Some(FileEngineType::Sync),
+            detect_zeroes: Some(DetectZeroes::Off),
+            open_direct: None,
+            io_engine_opts: Some(IoEngineOpts::default()),
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let mut block_devs = BlockBuilder::new();
@@ -614,6 +914,66 @@ mod tests {
         assert_eq!(configs.first().unwrap(), &dummy_block_device);
     }
 
+    #[test]
+    fn test_block_queue_size() {
+        let dummy_file = TempFile::new().unwrap();
+
+        let mut dummy_block_device = BlockDeviceConfig {
+            drive_id: String::from("1"),
+            partuuid: None,
+            is_root_device: false,
+            cache_type: CacheType::Unsafe,
+
+            is_read_only: Some(false),
+            path_on_host: Some(dummy_file.as_path().to_str().unwrap().to_string()),
+            rate_limiter: None,
+            rate_limiter_scope: None,
+            file_engine_type: Some(FileEngineType::Sync),
+            detect_zeroes: Some(DetectZeroes::Off),
+            open_direct: None,
+            io_engine_opts: Some(IoEngineOpts::default()),
+            queue_size: Some(512),
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
+
+            socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+        };
+
+        let mut block_devs = BlockBuilder::new();
+        block_devs.insert(dummy_block_device.clone()).unwrap();
+        assert_eq!(block_devs.configs(), vec![dummy_block_device.clone()]);
+
+        // The queue size must be a power of two within the bounds of the block device.
+        for size in [100, 8, 2048] {
+            dummy_block_device.queue_size = Some(size);
+            assert_eq!(
+                block_devs.insert(dummy_block_device.clone()).unwrap_err(),
+                DriveError::CreateBlockDevice(BlockError::VirtioBackend(
+                    VirtioBlockError::QueueSize(QueueSizeError::InvalidSize(size, 16, 1024))
+                ))
+            );
+        }
+
+        // The queue size is not supported by the vhost-user block devices.
+        dummy_block_device.queue_size = Some(256);
+        dummy_block_device.is_read_only = None;
+        dummy_block_device.path_on_host = None;
+        dummy_block_device.file_engine_type = None;
+        dummy_block_device.detect_zeroes = None;
+        dummy_block_device.io_engine_opts = None;
+        dummy_block_device.socket = Some("sock".to_string());
+        assert_eq!(
+            block_devs.insert(dummy_block_device).unwrap_err(),
+            DriveError::CreateBlockDevice(BlockError::InvalidBlockConfig)
+        );
+    }
+
     #[test]
     fn test_add_device() {
         let mut block_devs = BlockBuilder::new();
@@ -629,9 +989,22 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
             rate_limiter: None,
+            rate_limiter_scope: None,
             file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
 
             socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
         };
 
         let block = Block::new(config).unwrap();
@@ -643,4 +1016,113 @@ mod tests {
             block_id
         );
     }
+
+    #[test]
+    fn test_build_hotplug() {
+        let mut block_devs = BlockBuilder::new();
+        let backing_file = TempFile::new().unwrap();
+        let config = |drive_id: &str, is_root_device: bool| BlockDeviceConfig {
+            drive_id: drive_id.to_string(),
+            partuuid: None,
+            is_root_device,
+            cache_type: CacheType::default(),
+
+            is_read_only: Some(false),
+            path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
+            rate_limiter: None,
+            rate_limiter_scope: None,
+            file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
+
+            socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+        };
+        block_devs.insert(config("1", true)).unwrap();
+
+        // Error Case: the root device can't be hot-plugged.
+        assert_eq!(
+            block_devs.build_hotplug(config("2", true)).unwrap_err(),
+            DriveError::HotplugRootDevice
+        );
+
+        // Error Case: the drive ID is already in use.
+        assert_eq!(
+            block_devs.build_hotplug(config("1", false)).unwrap_err(),
+            DriveError::DriveIdInUse("1".to_string())
+        );
+
+        // The device is only kept by the builder once added.
+        let block = block_devs.build_hotplug(config("2", false)).unwrap();
+        assert_eq!(block.lock().unwrap().id(), "2");
+        assert_eq!(block_devs.devices.len(), 1);
+        block_devs.add_virtio_device(block);
+        assert_eq!(block_devs.devices.len(), 2);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut block_devs = BlockBuilder::new();
+        let backing_file = TempFile::new().unwrap();
+        let config = |drive_id: &str, is_root_device: bool| BlockDeviceConfig {
+            drive_id: drive_id.to_string(),
+            partuuid: None,
+            is_root_device,
+            cache_type: CacheType::default(),
+
+            is_read_only: Some(false),
+            path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
+            rate_limiter: None,
+            rate_limiter_scope: None,
+            file_engine_type: None,
+            detect_zeroes: None,
+            open_direct: None,
+            io_engine_opts: None,
+            queue_size: None,
+            num_queues: None,
+            serial: None,
+            logical_block_size: None,
+            physical_block_size: None,
+            on_host_enospc: None,
+            io_uring: None,
+
+            socket: None,
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+        };
+        block_devs.insert(config("1", true)).unwrap();
+        block_devs.insert(config("2", false)).unwrap();
+        block_devs.insert(config("3", false)).unwrap();
+
+        // Error Case: the root device can't be removed.
+        assert_eq!(
+            block_devs.remove("1").unwrap_err(),
+            DriveError::RemoveRootDevice
+        );
+        // Error Case: the drive doesn't exist.
+        assert_eq!(
+            block_devs.remove("4").unwrap_err(),
+            DriveError::InvalidDriveId("4".to_string())
+        );
+        assert_eq!(block_devs.devices.len(), 3);
+
+        let block = block_devs.remove("2").unwrap();
+        assert_eq!(block.lock().unwrap().id(), "2");
+        assert_eq!(block_devs.devices.len(), 2);
+        assert_eq!(block_devs.get_index_of_drive_id("2"), None);
+        assert_eq!(block_devs.get_index_of_drive_id("3"), Some(1));
+
+        // The drive ID is free again.
+        block_devs.insert(config("2", false)).unwrap();
+        assert_eq!(block_devs.devices.len(), 3);
+    }
 }
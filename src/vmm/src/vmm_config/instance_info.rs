@@ -4,6 +4,8 @@ use std::fmt::{self, Display, Formatter};
 
 use serde::{ser, Serialize};
 
+use crate::readiness_probe::ReadinessProbeStatus;
+
 /// Enumerates microVM runtime states.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub enum VmState {
@@ -46,4 +48,15 @@ pub struct InstanceInfo {
     pub vmm_version: String,
     /// The name of the application that runs the microVM.
     pub app_name: String,
+    /// The UUID exposed to the guest through the SMBIOS tables, if configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    /// Whether the microVM started and, if a readiness probe is configured, the guest passed it.
+    pub ready: bool,
+    /// Status of the readiness probe, if configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readiness_probe: Option<ReadinessProbeStatus>,
+    /// IDs of the drives holding guest writes back since the host ran out of space.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub out_of_space_drives: Vec<String>,
 }
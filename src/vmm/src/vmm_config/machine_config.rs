@@ -6,13 +6,21 @@ use serde::{Deserialize, Serialize};
 use utils::kernel_version;
 use utils::kernel_version::KernelVersion;
 
+use crate::arch::memory_layout::{MemoryLayoutError, MemoryRange};
 use crate::cpu_config::templates::{CpuTemplateType, CustomCpuTemplate, StaticCpuTemplate};
+use crate::vmm_config::auto_pause::{AutoPauseConfig, AutoPauseConfigError};
+use crate::vmm_config::readiness_probe::{ReadinessProbeConfig, ReadinessProbeConfigError};
+use crate::vmm_config::serial::{validate_serial_ports, SerialPortConfig, SerialPortsConfigError};
 
 /// The default memory size of the VM, in MiB.
 pub const DEFAULT_MEM_SIZE_MIB: usize = 128;
 /// Firecracker aims to support small scale workloads only, so limit the maximum
 /// vCPUs supported.
 pub const MAX_SUPPORTED_VCPUS: u8 = 32;
+/// Maximum length of the strings exposed in the SMBIOS tables.
+pub const SMBIOS_MAX_STRING_LEN: usize = 64;
+/// Maximum number of OEM strings exposed in the SMBIOS tables.
+pub const SMBIOS_MAX_OEM_STRINGS: usize = 16;
 
 /// Errors associated with configuring the microVM.
 #[rustfmt::skip]
@@ -22,6 +30,11 @@ pub enum VmConfigError {
     IncompatibleBalloonSize,
     /// The memory size (MiB) is either 0, or not a multiple of the configured page size.
     InvalidMemorySize,
+    /// The memory size (MiB) must be greater than 1, as the first MiB of guest memory is reserved for the system.
+    #[cfg(target_arch = "x86_64")]
+    MemorySizeTooSmall,
+    /// Invalid guest memory layout: {0}
+    InvalidMemoryLayout(#[from] MemoryLayoutError),
     /// The number of vCPUs must be greater than 0, less than {MAX_SUPPORTED_VCPUS:} and must be 1 or an even number if SMT is enabled.
     InvalidVcpuCount,
     /// Could not get the configuration of the previously installed balloon device to validate the memory size.
@@ -33,10 +46,36 @@ pub enum VmConfigError {
     KernelVersion,
     /// Firecracker's hugetlbfs support requires at least host kernel 5.10.
     HugetlbfsNotSupported,
-    /// Firecracker's huge pages support is incompatible with memory ballooning.
-    BalloonAndHugePages,
     /// Firecracker's huge pages support is incompatible with initrds.
     InitrdAndHugePages,
+    /// Invalid SMBIOS configuration: {0}
+    InvalidSmbios(#[from] SmbiosConfigError),
+    /// SMBIOS tables are not supported on aarch64.
+    #[cfg(target_arch = "aarch64")]
+    SmbiosNotSupported,
+    /// Configuring the GIC is only supported on aarch64.
+    #[cfg(target_arch = "x86_64")]
+    GicNotSupported,
+    /// Invalid readiness probe configuration: {0}
+    InvalidReadinessProbe(#[from] ReadinessProbeConfigError),
+    /// Invalid serial ports configuration: {0}
+    InvalidSerialPorts(#[from] SerialPortsConfigError),
+    /// Invalid auto-pause configuration: {0}
+    InvalidAutoPause(#[from] AutoPauseConfigError),
+}
+
+/// Errors associated with the SMBIOS configuration.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum SmbiosConfigError {
+    /// The {0} must be between 1 and {SMBIOS_MAX_STRING_LEN:} printable ASCII characters long.
+    InvalidString(&'static str),
+    /// At most {SMBIOS_MAX_OEM_STRINGS:} OEM strings can be configured.
+    TooManyOemStrings,
+    /// The UUID must be formatted as xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx, with hexadecimal digits.
+    InvalidUuid,
+    /// Failed to generate a random UUID.
+    UuidGeneration,
 }
 
 // We cannot do a `KernelVersion(kernel_version::Error)` variant because `kernel_version::Error`
@@ -103,6 +142,131 @@ impl From<HugePageConfig> for Option<memfd::HugetlbSize> {
     }
 }
 
+/// Configuration of the aarch64 interrupt controller (GIC).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GicConfig {
+    /// Adds a GICv3 Interrupt Translation Service (ITS), used by devices signaling MSIs.
+    #[serde(default)]
+    pub its: bool,
+}
+
+/// Identity of the microVM exposed to the guest through the SMBIOS tables.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmbiosConfig {
+    /// Manufacturer of the system and of its chassis.
+    #[serde(default)]
+    pub manufacturer: Option<String>,
+    /// Product name of the system.
+    #[serde(default)]
+    pub product_name: Option<String>,
+    /// Serial number of the system and of its chassis.
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    /// UUID of the system. A random UUID is generated when none is configured.
+    #[serde(default)]
+    pub uuid: Option<String>,
+    /// Free-form strings defined by the OEM.
+    #[serde(default)]
+    pub oem_strings: Vec<String>,
+}
+
+impl SmbiosConfig {
+    /// Validates the configuration, and generates a random UUID if none is configured.
+    #[cfg_attr(target_arch = "aarch64", allow(dead_code))]
+    fn resolve(&self) -> Result<SmbiosConfig, SmbiosConfigError> {
+        let strings = [
+            ("manufacturer", &self.manufacturer),
+            ("product_name", &self.product_name),
+            ("serial_number", &self.serial_number),
+        ];
+        for (name, value) in strings {
+            if value
+                .as_deref()
+                .is_some_and(|value| !is_valid_smbios_string(value))
+            {
+                return Err(SmbiosConfigError::InvalidString(name));
+            }
+        }
+        if self.oem_strings.len() > SMBIOS_MAX_OEM_STRINGS {
+            return Err(SmbiosConfigError::TooManyOemStrings);
+        }
+        if !self.oem_strings.iter().all(|s| is_valid_smbios_string(s)) {
+            return Err(SmbiosConfigError::InvalidString("oem_strings"));
+        }
+
+        let uuid = match &self.uuid {
+            Some(uuid) => {
+                parse_uuid(uuid).ok_or(SmbiosConfigError::InvalidUuid)?;
+                uuid.to_lowercase()
+            }
+            None => format_uuid(&random_uuid()?),
+        };
+
+        Ok(SmbiosConfig {
+            uuid: Some(uuid),
+            ..self.clone()
+        })
+    }
+
+    /// Returns the bytes of the configured UUID, if it is valid.
+    pub fn uuid_bytes(&self) -> Option<[u8; 16]> {
+        self.uuid.as_deref().and_then(parse_uuid)
+    }
+}
+
+fn is_valid_smbios_string(value: &str) -> bool {
+    (1..=SMBIOS_MAX_STRING_LEN).contains(&value.len())
+        && value.bytes().all(|b| b.is_ascii_graphic() || b == b' ')
+}
+
+/// Parses a UUID in its `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` textual form.
+fn parse_uuid(uuid: &str) -> Option<[u8; 16]> {
+    const GROUP_LENGTHS: [usize; 5] = [8, 4, 4, 4, 12];
+
+    let groups: Vec<&str> = uuid.split('-').collect();
+    if groups.len() != GROUP_LENGTHS.len()
+        || groups
+            .iter()
+            .zip(GROUP_LENGTHS)
+            .any(|(group, len)| group.len() != len)
+    {
+        return None;
+    }
+
+    let digits = groups.concat();
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(digits.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn format_uuid(bytes: &[u8; 16]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Generates a random (version 4) UUID.
+fn random_uuid() -> Result<[u8; 16], SmbiosConfigError> {
+    let mut bytes = [0u8; 16];
+    aws_lc_rs::rand::fill(&mut bytes).map_err(|_| SmbiosConfigError::UuidGeneration)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Ok(bytes)
+}
+
 /// Struct used in PUT `/machine-config` API call.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -123,6 +287,30 @@ pub struct MachineConfig {
     /// Configures what page size Firecracker should use to back guest memory.
     #[serde(default)]
     pub huge_pages: HugePageConfig,
+    /// Identity of the microVM exposed to the guest through the SMBIOS tables.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smbios: Option<SmbiosConfig>,
+    /// Configuration of the aarch64 interrupt controller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gic: Option<GicConfig>,
+    /// Probe telling when the guest is ready, after the microVM started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readiness_probe: Option<ReadinessProbeConfig>,
+    /// Serial ports of the microVM. A single serial port, reading the standard input and writing
+    /// to the standard output of Firecracker, is set up when not configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_ports: Option<Vec<SerialPortConfig>>,
+    /// Automatic pause of the microVM once it is idle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_pause: Option<AutoPauseConfig>,
+    /// Number of empty virtio-over-MMIO slots reserved at boot, which network interfaces and
+    /// drives can be hot-plugged in once the microVM runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hotplug_slots: Option<u8>,
+    /// Regions of guest RAM laid out for the configured memory size. Only reported by GET
+    /// `/machine-config` for debugging purposes, it cannot be configured.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub memory_regions: Option<Vec<MemoryRange>>,
 }
 
 impl Default for MachineConfig {
@@ -158,6 +346,25 @@ pub struct MachineConfigUpdate {
     /// Configures what page size Firecracker should use to back guest memory.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub huge_pages: Option<HugePageConfig>,
+    /// Identity of the microVM exposed to the guest through the SMBIOS tables.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smbios: Option<SmbiosConfig>,
+    /// Configuration of the aarch64 interrupt controller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gic: Option<GicConfig>,
+    /// Probe telling when the guest is ready, after the microVM started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readiness_probe: Option<ReadinessProbeConfig>,
+    /// Serial ports of the microVM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_ports: Option<Vec<SerialPortConfig>>,
+    /// Automatic pause of the microVM once it is idle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_pause: Option<AutoPauseConfig>,
+    /// Number of empty virtio-over-MMIO slots reserved at boot, which network interfaces and
+    /// drives can be hot-plugged in once the microVM runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hotplug_slots: Option<u8>,
 }
 
 impl MachineConfigUpdate {
@@ -178,6 +385,12 @@ impl From<MachineConfig> for MachineConfigUpdate {
             cpu_template: cfg.cpu_template,
             track_dirty_pages: Some(cfg.track_dirty_pages),
             huge_pages: Some(cfg.huge_pages),
+            smbios: cfg.smbios,
+            gic: cfg.gic,
+            readiness_probe: cfg.readiness_probe,
+            serial_ports: cfg.serial_ports,
+            auto_pause: cfg.auto_pause,
+            hotplug_slots: cfg.hotplug_slots,
         }
     }
 }
@@ -197,6 +410,19 @@ pub struct VmConfig {
     pub track_dirty_pages: bool,
     /// Configures what page size Firecracker should use to back guest memory.
     pub huge_pages: HugePageConfig,
+    /// Identity of the microVM exposed to the guest through the SMBIOS tables, with its UUID
+    /// always set.
+    pub smbios: Option<SmbiosConfig>,
+    /// Configuration of the aarch64 interrupt controller.
+    pub gic: Option<GicConfig>,
+    /// Probe telling when the guest is ready, after the microVM started.
+    pub readiness_probe: Option<ReadinessProbeConfig>,
+    /// Serial ports of the microVM, or `None` for the default serial console.
+    pub serial_ports: Option<Vec<SerialPortConfig>>,
+    /// Automatic pause of the microVM once it is idle.
+    pub auto_pause: Option<AutoPauseConfig>,
+    /// Number of empty virtio-over-MMIO slots reserved at boot for hot-plugging devices.
+    pub hotplug_slots: Option<u8>,
 }
 
 impl VmConfig {
@@ -237,6 +463,16 @@ impl VmConfig {
             return Err(VmConfigError::InvalidMemorySize);
         }
 
+        let mem_size = mem_size_mib
+            .checked_mul(1 << 20)
+            .ok_or(VmConfigError::InvalidMemorySize)?;
+        // The guest kernel is loaded right after the system memory.
+        #[cfg(target_arch = "x86_64")]
+        if mem_size as u64 <= crate::arch::get_kernel_start() {
+            return Err(VmConfigError::MemorySizeTooSmall);
+        }
+        crate::arch::arch_memory_layout(mem_size)?;
+
         let cpu_template = match update.cpu_template {
             None => self.cpu_template.clone(),
             Some(StaticCpuTemplate::None) => None,
@@ -247,6 +483,46 @@ impl VmConfig {
             return Err(VmConfigError::HugetlbfsNotSupported);
         }
 
+        let smbios = match &update.smbios {
+            None => self.smbios.clone(),
+            #[cfg(target_arch = "aarch64")]
+            Some(_) => return Err(VmConfigError::SmbiosNotSupported),
+            #[cfg(target_arch = "x86_64")]
+            Some(smbios) => Some(smbios.resolve()?),
+        };
+
+        let gic = match update.gic {
+            None => self.gic,
+            #[cfg(target_arch = "x86_64")]
+            Some(_) => return Err(VmConfigError::GicNotSupported),
+            #[cfg(target_arch = "aarch64")]
+            Some(gic) => Some(gic),
+        };
+
+        let readiness_probe = match &update.readiness_probe {
+            None => self.readiness_probe.clone(),
+            Some(probe) => {
+                probe.validate()?;
+                Some(probe.clone())
+            }
+        };
+
+        let serial_ports = match &update.serial_ports {
+            None => self.serial_ports.clone(),
+            Some(ports) => {
+                validate_serial_ports(ports)?;
+                Some(ports.clone())
+            }
+        };
+
+        let auto_pause = match &update.auto_pause {
+            None => self.auto_pause.clone(),
+            Some(auto_pause) => {
+                auto_pause.validate()?;
+                Some(auto_pause.clone())
+            }
+        };
+
         Ok(VmConfig {
             vcpu_count,
             mem_size_mib,
@@ -254,10 +530,33 @@ impl VmConfig {
             cpu_template,
             track_dirty_pages: update.track_dirty_pages.unwrap_or(self.track_dirty_pages),
             huge_pages: page_config,
+            smbios,
+            gic,
+            readiness_probe,
+            serial_ports,
+            auto_pause,
+            hotplug_slots: update.hotplug_slots.or(self.hotplug_slots),
         })
     }
 }
 
+impl VmConfig {
+    /// Returns the regions of guest RAM laid out for the configured memory size.
+    pub fn memory_layout(&self) -> Result<Vec<MemoryRange>, MemoryLayoutError> {
+        crate::arch::arch_memory_layout(self.mem_size_mib << 20)
+    }
+
+    /// Returns whether the GIC must have an ITS.
+    pub fn gic_its(&self) -> bool {
+        self.gic.is_some_and(|gic| gic.its)
+    }
+
+    /// Returns the UUID exposed to the guest through the SMBIOS tables, if configured.
+    pub fn smbios_uuid(&self) -> Option<String> {
+        self.smbios.as_ref().and_then(|smbios| smbios.uuid.clone())
+    }
+}
+
 impl Default for VmConfig {
     fn default() -> Self {
         Self {
@@ -267,6 +566,12 @@ impl Default for VmConfig {
             cpu_template: None,
             track_dirty_pages: false,
             huge_pages: HugePageConfig::None,
+            smbios: None,
+            gic: None,
+            readiness_probe: None,
+            serial_ports: None,
+            auto_pause: None,
+            hotplug_slots: None,
         }
     }
 }
@@ -280,6 +585,13 @@ impl From<&VmConfig> for MachineConfig {
             cpu_template: value.cpu_template.as_ref().map(|template| template.into()),
             track_dirty_pages: value.track_dirty_pages,
             huge_pages: value.huge_pages,
+            smbios: value.smbios.clone(),
+            gic: value.gic,
+            readiness_probe: value.readiness_probe.clone(),
+            serial_ports: value.serial_ports.clone(),
+            auto_pause: value.auto_pause.clone(),
+            hotplug_slots: value.hotplug_slots,
+            memory_regions: None,
         }
     }
 }
@@ -288,9 +600,18 @@ impl From<&VmConfig> for MachineConfig {
 mod tests {
     use utils::kernel_version::KernelVersion;
 
+    use crate::arch::memory_layout::total_size;
+    #[cfg(target_arch = "aarch64")]
+    use crate::arch::memory_layout::MemoryLayoutError;
+    use crate::vmm_config::auto_pause::{ActivitySignal, AutoPauseConfig, AutoPauseConfigError};
     use crate::vmm_config::machine_config::{
-        HugePageConfig, MachineConfigUpdate, VmConfig, VmConfigError,
+        parse_uuid, GicConfig, HugePageConfig, MachineConfig, MachineConfigUpdate, SmbiosConfig,
+        SmbiosConfigError, VmConfig, VmConfigError, SMBIOS_MAX_OEM_STRINGS, SMBIOS_MAX_STRING_LEN,
+    };
+    use crate::vmm_config::readiness_probe::{
+        ReadinessCheck, ReadinessProbeConfig, ReadinessProbeConfigError,
     };
+    use crate::vmm_config::serial::{SerialPortConfig, SerialPortsConfigError};
 
     #[test]
     fn test_hugetlbfs_not_supported_4_14() {
@@ -306,4 +627,336 @@ mod tests {
             assert_eq!(err, VmConfigError::HugetlbfsNotSupported)
         }
     }
+
+    #[test]
+    fn test_memory_size_validation() {
+        let base_config = VmConfig::default();
+        let update = |mem_size_mib: usize| {
+            base_config.update(&MachineConfigUpdate {
+                mem_size_mib: Some(mem_size_mib),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(update(0).unwrap_err(), VmConfigError::InvalidMemorySize);
+        assert_eq!(
+            update(usize::MAX >> 10).unwrap_err(),
+            VmConfigError::InvalidMemorySize
+        );
+        // Sizes which are not a multiple of 128MiB are laid out around the MMIO gap.
+        for mem_size_mib in [2, 1000, 3327, 3328, 3329, 4097] {
+            let config = update(mem_size_mib).unwrap();
+            assert_eq!(
+                total_size(&config.memory_layout().unwrap()),
+                u64::try_from(mem_size_mib << 20).unwrap()
+            );
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(update(1).unwrap_err(), VmConfigError::MemorySizeTooSmall);
+        #[cfg(target_arch = "aarch64")]
+        {
+            update(1).unwrap();
+            // Memory which does not fit in the DRAM is rejected.
+            let max_mib = crate::arch::aarch64::layout::DRAM_MEM_MAX_SIZE >> 20;
+            update(max_mib).unwrap();
+            assert!(matches!(
+                update(max_mib + 1).unwrap_err(),
+                VmConfigError::InvalidMemoryLayout(MemoryLayoutError::TooLarge(_, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_uuid() {
+        assert_eq!(
+            parse_uuid("00112233-4455-6677-8899-aabbccddeeff").unwrap(),
+            [
+                0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+                0xee, 0xff
+            ]
+        );
+        assert!(parse_uuid("00112233-4455-6677-8899-AABBCCDDEEFF").is_some());
+        assert!(parse_uuid("00112233445566778899aabbccddeeff").is_none());
+        assert!(parse_uuid("00112233-4455-6677-8899-aabbccddeef").is_none());
+        assert!(parse_uuid("0011223-34455-6677-8899-aabbccddeeff").is_none());
+        assert!(parse_uuid("00112233-4455-6677-8899-aabbccddeefg").is_none());
+        assert!(parse_uuid("+0112233-4455-6677-8899-aabbccddeeff").is_none());
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_smbios_config() {
+        let base_config = VmConfig::default();
+        let update = |smbios: SmbiosConfig| {
+            base_config.update(&MachineConfigUpdate {
+                smbios: Some(smbios),
+                ..Default::default()
+            })
+        };
+
+        // A random version 4 UUID is generated when none is configured.
+        let config = update(SmbiosConfig::default()).unwrap();
+        let uuid = config.smbios.as_ref().unwrap().uuid_bytes().unwrap();
+        assert_eq!(uuid[6] >> 4, 4);
+        assert_eq!(uuid[8] >> 6, 2);
+        // The UUID is kept by later updates.
+        let config = config
+            .update(&MachineConfigUpdate {
+                vcpu_count: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(config.smbios.unwrap().uuid_bytes().unwrap(), uuid);
+
+        let smbios = SmbiosConfig {
+            manufacturer: Some("ACME".to_string()),
+            product_name: Some("Widget 3000".to_string()),
+            serial_number: Some("SN-42".to_string()),
+            uuid: Some("00112233-4455-6677-8899-AABBCCDDEEFF".to_string()),
+            oem_strings: vec!["a".to_string(); SMBIOS_MAX_OEM_STRINGS],
+        };
+        let config = update(smbios.clone()).unwrap();
+        assert_eq!(
+            config.smbios.unwrap(),
+            SmbiosConfig {
+                uuid: Some("00112233-4455-6677-8899-aabbccddeeff".to_string()),
+                ..smbios.clone()
+            }
+        );
+
+        let invalid = [
+            (
+                SmbiosConfig {
+                    manufacturer: Some(String::new()),
+                    ..smbios.clone()
+                },
+                SmbiosConfigError::InvalidString("manufacturer"),
+            ),
+            (
+                SmbiosConfig {
+                    product_name: Some("a".repeat(SMBIOS_MAX_STRING_LEN + 1)),
+                    ..smbios.clone()
+                },
+                SmbiosConfigError::InvalidString("product_name"),
+            ),
+            (
+                SmbiosConfig {
+                    serial_number: Some("line\nbreak".to_string()),
+                    ..smbios.clone()
+                },
+                SmbiosConfigError::InvalidString("serial_number"),
+            ),
+            (
+                SmbiosConfig {
+                    oem_strings: vec!["é".to_string()],
+                    ..smbios.clone()
+                },
+                SmbiosConfigError::InvalidString("oem_strings"),
+            ),
+            (
+                SmbiosConfig {
+                    oem_strings: vec!["a".to_string(); SMBIOS_MAX_OEM_STRINGS + 1],
+                    ..smbios.clone()
+                },
+                SmbiosConfigError::TooManyOemStrings,
+            ),
+            (
+                SmbiosConfig {
+                    uuid: Some("not-a-uuid".to_string()),
+                    ..smbios.clone()
+                },
+                SmbiosConfigError::InvalidUuid,
+            ),
+        ];
+        for (smbios, err) in invalid {
+            assert_eq!(
+                update(smbios).unwrap_err(),
+                VmConfigError::InvalidSmbios(err)
+            );
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_smbios_not_supported() {
+        let update = MachineConfigUpdate {
+            smbios: Some(SmbiosConfig::default()),
+            ..Default::default()
+        };
+        assert_eq!(
+            VmConfig::default().update(&update).unwrap_err(),
+            VmConfigError::SmbiosNotSupported
+        );
+    }
+
+    #[test]
+    fn test_gic_config() {
+        let update = MachineConfigUpdate {
+            gic: Some(GicConfig { its: true }),
+            ..Default::default()
+        };
+        assert!(!VmConfig::default().gic_its());
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            let config = VmConfig::default().update(&update).unwrap();
+            assert!(config.gic_its());
+            // The GIC configuration is kept when updating other fields.
+            let config = config
+                .update(&MachineConfigUpdate {
+                    vcpu_count: Some(2),
+                    ..Default::default()
+                })
+                .unwrap();
+            assert!(config.gic_its());
+        }
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(
+            VmConfig::default().update(&update).unwrap_err(),
+            VmConfigError::GicNotSupported
+        );
+    }
+
+    #[test]
+    fn test_readiness_probe_config() {
+        let probe = ReadinessProbeConfig {
+            check: ReadinessCheck::Vsock { port: 52 },
+            timeout_ms: 100,
+            max_attempts: 3,
+        };
+        let config = VmConfig::default()
+            .update(&MachineConfigUpdate {
+                readiness_probe: Some(probe.clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(config.readiness_probe, Some(probe.clone()));
+        assert_eq!(MachineConfig::from(&config).readiness_probe, Some(probe));
+        // The probe is kept when updating other fields.
+        let config = config
+            .update(&MachineConfigUpdate {
+                vcpu_count: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(config.readiness_probe.is_some());
+
+        let update = MachineConfigUpdate {
+            readiness_probe: Some(ReadinessProbeConfig {
+                check: ReadinessCheck::Vsock { port: 52 },
+                timeout_ms: 0,
+                max_attempts: 3,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            VmConfig::default().update(&update).unwrap_err(),
+            VmConfigError::InvalidReadinessProbe(ReadinessProbeConfigError::InvalidSchedule)
+        );
+    }
+
+    #[test]
+    fn test_serial_ports_config() {
+        let ports = vec![
+            SerialPortConfig {
+                id: "console".to_string(),
+                output: Some("stdout".to_string()),
+                input: true,
+            },
+            SerialPortConfig {
+                id: "mgmt".to_string(),
+                output: None,
+                input: false,
+            },
+        ];
+        let config = VmConfig::default()
+            .update(&MachineConfigUpdate {
+                serial_ports: Some(ports.clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(config.serial_ports, Some(ports.clone()));
+        assert_eq!(MachineConfig::from(&config).serial_ports, Some(ports));
+        // The serial ports are kept when updating other fields.
+        let config = config
+            .update(&MachineConfigUpdate {
+                vcpu_count: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(config.serial_ports.is_some());
+
+        let update = MachineConfigUpdate {
+            serial_ports: Some(vec![]),
+            ..Default::default()
+        };
+        assert_eq!(
+            VmConfig::default().update(&update).unwrap_err(),
+            VmConfigError::InvalidSerialPorts(SerialPortsConfigError::InvalidPortCount)
+        );
+    }
+
+    #[test]
+    fn test_auto_pause_config() {
+        let auto_pause = AutoPauseConfig {
+            idle_seconds: 300,
+            signals: vec![ActivitySignal::Net, ActivitySignal::VcpuHaltPct],
+        };
+        let config = VmConfig::default()
+            .update(&MachineConfigUpdate {
+                auto_pause: Some(auto_pause.clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(config.auto_pause, Some(auto_pause.clone()));
+        assert_eq!(MachineConfig::from(&config).auto_pause, Some(auto_pause));
+        // The auto-pause is kept when updating other fields.
+        let config = config
+            .update(&MachineConfigUpdate {
+                vcpu_count: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(config.auto_pause.is_some());
+
+        let update = MachineConfigUpdate {
+            auto_pause: Some(AutoPauseConfig {
+                idle_seconds: 0,
+                signals: vec![ActivitySignal::Net],
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            VmConfig::default().update(&update).unwrap_err(),
+            VmConfigError::InvalidAutoPause(AutoPauseConfigError::InvalidIdleSeconds)
+        );
+    }
+
+    #[test]
+    fn test_hotplug_slots_config() {
+        let config = VmConfig::default()
+            .update(&MachineConfigUpdate {
+                hotplug_slots: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(config.hotplug_slots, Some(2));
+        assert_eq!(MachineConfig::from(&config).hotplug_slots, Some(2));
+        // The slots are kept when updating other fields.
+        let config = config
+            .update(&MachineConfigUpdate {
+                vcpu_count: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(config.hotplug_slots, Some(2));
+
+        let machine_config: MachineConfig =
+            serde_json::from_str(r#"{"vcpu_count": 1, "mem_size_mib": 128}"#).unwrap();
+        assert_eq!(machine_config.hotplug_slots, None);
+        assert!(!serde_json::to_string(&machine_config)
+            .unwrap()
+            .contains("hotplug_slots"));
+    }
 }
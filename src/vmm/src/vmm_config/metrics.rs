@@ -9,11 +9,25 @@ use serde::{Deserialize, Serialize};
 use super::open_file_nonblock;
 use crate::logger::{FcLineWriter, METRICS};
 
+/// Which metrics are recorded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MetricsVerbosity {
+    /// Only the metrics cheap enough to record on the hot paths.
+    #[default]
+    Default,
+    /// The default metrics, along with the more costly ones, e.g. the latency histograms of the
+    /// block devices.
+    Detailed,
+}
+
 /// Strongly typed structure used to describe the metrics system.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct MetricsConfig {
     /// Named pipe or file used as output for metrics.
     pub metrics_path: PathBuf,
+    /// Which metrics are recorded.
+    #[serde(default)]
+    pub verbosity: MetricsVerbosity,
 }
 
 /// Errors associated with actions on the `MetricsConfig`.
@@ -31,7 +45,9 @@ pub fn init_metrics(metrics_cfg: MetricsConfig) -> Result<(), MetricsConfigError
     );
     METRICS
         .init(writer)
-        .map_err(|err| MetricsConfigError::InitializationFailure(err.to_string()))
+        .map_err(|err| MetricsConfigError::InitializationFailure(err.to_string()))?;
+    METRICS.set_detailed(metrics_cfg.verbosity == MetricsVerbosity::Detailed);
+    Ok(())
 }
 
 #[cfg(test)]
@@ -45,16 +61,22 @@ mod tests {
         // Error case: initializing metrics with invalid pipe returns error.
         let desc = MetricsConfig {
             metrics_path: PathBuf::from("not_found_file_metrics"),
+            verbosity: MetricsVerbosity::Default,
         };
         init_metrics(desc).unwrap_err();
 
         // Initializing metrics with valid pipe is ok.
         let metrics_file = TempFile::new().unwrap();
-        let desc = MetricsConfig {
+        let mut desc = MetricsConfig {
             metrics_path: metrics_file.as_path().to_path_buf(),
+            verbosity: MetricsVerbosity::Detailed,
         };
 
         init_metrics(desc.clone()).unwrap();
+        assert!(METRICS.detailed());
+        // The verbosity can't change once the metrics are initialized.
+        desc.verbosity = MetricsVerbosity::Default;
         init_metrics(desc).unwrap_err();
+        assert!(METRICS.detailed());
     }
 }
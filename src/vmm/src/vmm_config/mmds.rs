@@ -18,6 +18,9 @@ pub struct MmdsConfig {
     pub network_interfaces: Vec<String>,
     /// MMDS IPv4 configured address.
     pub ipv4_address: Option<Ipv4Addr>,
+    /// Maximum size of the MMDS data store, in MiB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_store_limit_mib: Option<u32>,
 }
 
 impl MmdsConfig {
@@ -36,6 +39,13 @@ impl MmdsConfig {
     pub fn ipv4_addr(&self) -> Option<Ipv4Addr> {
         self.ipv4_address
     }
+
+    /// Returns the MMDS data store limit in bytes if one was configured.
+    /// Otherwise returns None.
+    pub fn data_store_limit(&self) -> Option<usize> {
+        self.data_store_limit_mib
+            .map(|limit_mib| usize::try_from(limit_mib).unwrap() << 20)
+    }
 }
 
 /// MMDS configuration related errors.
@@ -50,4 +60,8 @@ pub enum MmdsConfigError {
     InvalidNetworkInterfaceId,
     /// The MMDS could not be configured to version {0}: {1}
     MmdsVersion(MmdsVersion, data_store::MmdsDatastoreError),
+    /// The MMDS data store limit could not be updated: {0}
+    DataStoreLimit(data_store::MmdsDatastoreError),
+    /// Only the MMDS data store limit can be updated after the microVM has started.
+    UnsupportedPostBootUpdate,
 }
@@ -12,10 +12,16 @@ use serde::{Deserialize, Serialize};
 
 use crate::rate_limiter::{BucketUpdate, RateLimiter, TokenBucket};
 
+/// Wrapper for configuring the automatic pause of idle microVMs.
+pub mod auto_pause;
 /// Wrapper for configuring the balloon device.
 pub mod balloon;
 /// Wrapper for configuring the microVM boot source.
 pub mod boot_source;
+/// Versions of the format of the configuration file, and the migrations between them.
+pub mod config_schema;
+/// Wrapper for configuring the scanner of the guest console output.
+pub mod console_scanner;
 /// Wrapper for configuring the block devices.
 pub mod drive;
 /// Wrapper for configuring the entropy device attached to the microVM.
@@ -30,8 +36,14 @@ pub mod metrics;
 pub mod mmds;
 /// Wrapper for configuring the network devices attached to the microVM.
 pub mod net;
+/// Wrapper for configuring the probe telling when the guest is ready.
+pub mod readiness_probe;
+/// Wrapper for configuring the serial ports of the microVM.
+pub mod serial;
 /// Wrapper for configuring microVM snapshots and the microVM state.
 pub mod snapshot;
+/// Wrapper for the operations on the vhost-user devices attached to the microVM.
+pub mod vhost_user;
 /// Wrapper for configuring the vsock devices attached to the microVM.
 pub mod vsock;
 
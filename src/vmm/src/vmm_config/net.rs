@@ -3,13 +3,15 @@
 
 use std::convert::TryInto;
 use std::ops::Deref;
+use std::os::unix::io::RawFd;
 use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 use utils::net::mac::MacAddr;
 
-use super::RateLimiterConfig;
-use crate::devices::virtio::net::{Net, TapError};
+use super::{RateLimiterConfig, RateLimiterUpdate};
+use crate::devices::virtio::device::{QueueSizeError, VirtioDevice, VirtioFeaturesPinError};
+use crate::devices::virtio::net::{Net, NetOffloads, RateLimiterScope, TapError};
 use crate::VmmError;
 
 /// This struct represents the strongly typed equivalent of the json body from net iface
@@ -20,13 +22,42 @@ pub struct NetworkInterfaceConfig {
     /// ID of the guest network interface.
     pub iface_id: String,
     /// Host level path for the guest network interface.
+    #[serde(default)]
     pub host_dev_name: String,
+    /// File descriptor of an already open tap device, inherited by the Firecracker process, to
+    /// use instead of opening `host_dev_name`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tap_fd: Option<RawFd>,
     /// Guest MAC address.
     pub guest_mac: Option<MacAddr>,
     /// Rate Limiter for received packages.
     pub rx_rate_limiter: Option<RateLimiterConfig>,
     /// Rate Limiter for transmitted packages.
     pub tx_rate_limiter: Option<RateLimiterConfig>,
+    /// Virtio features the features offered by the device are restricted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtio_features_pin: Option<u64>,
+    /// Virtio features negotiated with the guest driver, once the device is activated.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub negotiated_virtio_features: Option<u64>,
+    /// Maximal size of the RX and TX virtio queues offered to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<u16>,
+    /// Number of pairs of RX and TX virtio queues offered to the guest. With more than one pair,
+    /// the host device is opened as a multi-queue tap, with a queue per pair.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<u16>,
+    /// Whether the rate limiters limit the traffic of the whole device, or of each pair of RX and
+    /// TX queues.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limiter_scope: Option<RateLimiterScope>,
+    /// Checksum and segmentation offloads offered to the guest. All of them are offered when
+    /// absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offloads: Option<NetOffloads>,
+    /// MTU offered to the guest with `VIRTIO_NET_F_MTU`. The feature is not offered when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtu: Option<u16>,
 }
 
 impl From<&Net> for NetworkInterfaceConfig {
@@ -35,16 +66,29 @@ impl From<&Net> for NetworkInterfaceConfig {
         let tx_rl: RateLimiterConfig = net.tx_rate_limiter().into();
         NetworkInterfaceConfig {
             iface_id: net.id().clone(),
-            host_dev_name: net.iface_name(),
+            // The interface is configured again by its tap fd, not by the name of the tap.
+            host_dev_name: match net.tap_fd() {
+                Some(_) => String::new(),
+                None => net.iface_name(),
+            },
+            tap_fd: net.tap_fd(),
             guest_mac: net.guest_mac().copied(),
             rx_rate_limiter: rx_rl.into_option(),
             tx_rate_limiter: tx_rl.into_option(),
+            virtio_features_pin: net.virtio_features_pin(),
+            negotiated_virtio_features: net.negotiated_features(),
+            queue_size: net.queue_size(),
+            num_queues: (net.num_pairs() > 1).then_some(net.num_pairs()),
+            rate_limiter_scope: (net.rate_limiter_scope() != RateLimiterScope::Device)
+                .then_some(net.rate_limiter_scope()),
+            offloads: (net.offloads() != NetOffloads::default()).then_some(net.offloads()),
+            mtu: net.mtu(),
         }
     }
 }
 
 /// The data fed into a network iface update request. Currently, only the RX and TX rate limiters
-/// can be updated.
+/// and the guest MAC address can be updated.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct NetworkInterfaceUpdateConfig {
@@ -56,6 +100,8 @@ pub struct NetworkInterfaceUpdateConfig {
     /// New TX rate limiter config. Only provided data will be updated. I.e. if any optional data
     /// is missing, it will not be nullified, but left unchanged.
     pub tx_rate_limiter: Option<RateLimiterConfig>,
+    /// New guest MAC address. It can not be updated while the microVM is running.
+    pub guest_mac: Option<MacAddr>,
 }
 
 /// Errors associated with the operations allowed on a net device.
@@ -69,8 +115,33 @@ pub enum NetworkInterfaceError {
     DeviceUpdate(#[from] VmmError),
     /// The MAC address is already in use: {0}
     GuestMacAddressInUse(String),
+    /// Cannot update the guest MAC address: {0}
+    GuestMacUpdate(crate::devices::virtio::net::NetError),
+    /// The guest MAC address can only be updated while the microVM is paused
+    GuestMacUpdateRunning,
+    /// Cannot hot-plug the network device: {0}
+    Hotplug(VmmError),
+    /// The network interface ID is already in use: {0}
+    IfaceIdInUse(String),
+    /// Invalid network interface ID: {0}
+    InvalidIfaceId(String),
+    /// No free slot to hot-plug the network device in. Slots are reserved with `hotplug_slots`
+    /// in the machine configuration.
+    NoHotplugSlot,
+    /// Cannot pin the virtio features of the network device: {0}
+    PinVirtioFeatures(#[from] VirtioFeaturesPinError),
+    /// Cannot set the queue size of the network device: {0}
+    QueueSize(#[from] QueueSizeError),
+    /// Cannot unplug the network device: {0}
+    Unplug(VmmError),
     /// Cannot open/create the tap device: {0}
     OpenTap(#[from] TapError),
+    /// Only one of the tap device name and the tap fd can be set.
+    TapNameAndFd,
+    /// Either the tap device name or the tap fd must be set.
+    NoTap,
+    /// A network interface configured with a tap fd can only have a single pair of queues.
+    TapFdMultiQueue,
 }
 
 /// Builder for a list of network devices.
@@ -110,19 +181,9 @@ impl NetBuilder {
         netif_config: NetworkInterfaceConfig,
     ) -> Result<Arc<Mutex<Net>>, NetworkInterfaceError> {
         if let Some(ref mac_address) = netif_config.guest_mac {
-            let mac_conflict = |net: &Arc<Mutex<Net>>| {
-                let net = net.lock().expect("Poisoned lock");
-                // Check if another net dev has same MAC.
-                Some(mac_address) == net.guest_mac() && &netif_config.iface_id != net.id()
-            };
-            // Validate there is no Mac conflict.
             // No need to validate host_dev_name conflict. In such a case,
             // an error will be thrown during device creation anyway.
-            if self.net_devices.iter().any(mac_conflict) {
-                return Err(NetworkInterfaceError::GuestMacAddressInUse(
-                    mac_address.to_string(),
-                ));
-            }
+            self.check_guest_mac(&netif_config.iface_id, mac_address)?;
         }
 
         // If this is an update, just remove the old one.
@@ -141,6 +202,85 @@ impl NetBuilder {
         Ok(net)
     }
 
+    /// Builds a network device to hot-plug in the running microVM, based on a network interface
+    /// config. Unlike [`NetBuilder::build`], existing devices can't be replaced, and the device
+    /// is only kept in the builder's internal list once added with [`NetBuilder::add_device`].
+    pub fn build_hotplug(
+        &self,
+        netif_config: NetworkInterfaceConfig,
+    ) -> Result<Arc<Mutex<Net>>, NetworkInterfaceError> {
+        if self
+            .net_devices
+            .iter()
+            .any(|net| net.lock().expect("Poisoned lock").id() == &netif_config.iface_id)
+        {
+            return Err(NetworkInterfaceError::IfaceIdInUse(netif_config.iface_id));
+        }
+        if let Some(ref mac_address) = netif_config.guest_mac {
+            self.check_guest_mac(&netif_config.iface_id, mac_address)?;
+        }
+
+        Ok(Arc::new(Mutex::new(Self::create_net(netif_config)?)))
+    }
+
+    /// Removes the network device `iface_id` from the builder's internal list, and returns it.
+    pub fn remove(&mut self, iface_id: &str) -> Result<Arc<Mutex<Net>>, NetworkInterfaceError> {
+        let index = self
+            .net_devices
+            .iter()
+            .position(|net| net.lock().expect("Poisoned lock").id() == iface_id)
+            .ok_or_else(|| NetworkInterfaceError::InvalidIfaceId(iface_id.to_string()))?;
+        Ok(self.net_devices.remove(index))
+    }
+
+    /// Updates a network device before boot, as described by `update_config`.
+    pub fn update(
+        &mut self,
+        update_config: &NetworkInterfaceUpdateConfig,
+    ) -> Result<(), NetworkInterfaceError> {
+        let net = self
+            .net_devices
+            .iter()
+            .find(|net| net.lock().expect("Poisoned lock").id() == &update_config.iface_id)
+            .ok_or_else(|| NetworkInterfaceError::InvalidIfaceId(update_config.iface_id.clone()))?;
+        if let Some(ref mac_address) = update_config.guest_mac {
+            self.check_guest_mac(&update_config.iface_id, mac_address)?;
+        }
+
+        let mut net = net.lock().expect("Poisoned lock");
+        if let Some(mac_address) = update_config.guest_mac {
+            net.set_guest_mac(mac_address)
+                .map_err(NetworkInterfaceError::GuestMacUpdate)?;
+        }
+        let rx_update = RateLimiterUpdate::from(update_config.rx_rate_limiter);
+        let tx_update = RateLimiterUpdate::from(update_config.tx_rate_limiter);
+        net.patch_rate_limiters(
+            rx_update.bandwidth,
+            rx_update.ops,
+            tx_update.bandwidth,
+            tx_update.ops,
+        );
+        Ok(())
+    }
+
+    // Validates that no other network device than `iface_id` has the MAC address `mac_address`.
+    fn check_guest_mac(
+        &self,
+        iface_id: &str,
+        mac_address: &MacAddr,
+    ) -> Result<(), NetworkInterfaceError> {
+        let mac_conflict = |net: &Arc<Mutex<Net>>| {
+            let net = net.lock().expect("Poisoned lock");
+            Some(mac_address) == net.guest_mac() && net.id() != iface_id
+        };
+        if self.net_devices.iter().any(mac_conflict) {
+            return Err(NetworkInterfaceError::GuestMacAddressInUse(
+                mac_address.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Creates a Net device from a NetworkInterfaceConfig.
     pub fn create_net(cfg: NetworkInterfaceConfig) -> Result<Net, NetworkInterfaceError> {
         let rx_rate_limiter = cfg
@@ -155,14 +295,49 @@ impl NetBuilder {
             .map_err(NetworkInterfaceError::CreateRateLimiter)?;
 
         // Create and return the Net device
-        crate::devices::virtio::net::Net::new(
-            cfg.iface_id,
-            &cfg.host_dev_name,
-            cfg.guest_mac,
-            rx_rate_limiter.unwrap_or_default(),
-            tx_rate_limiter.unwrap_or_default(),
-        )
-        .map_err(NetworkInterfaceError::CreateNetworkDevice)
+        let mut net = match cfg.tap_fd {
+            Some(_) if !cfg.host_dev_name.is_empty() => {
+                return Err(NetworkInterfaceError::TapNameAndFd)
+            }
+            Some(_) if cfg.num_queues.unwrap_or(1) > 1 => {
+                return Err(NetworkInterfaceError::TapFdMultiQueue)
+            }
+            Some(tap_fd) => crate::devices::virtio::net::Net::new_with_tap_fd(
+                cfg.iface_id,
+                tap_fd,
+                cfg.guest_mac,
+                rx_rate_limiter.unwrap_or_default(),
+                tx_rate_limiter.unwrap_or_default(),
+            ),
+            None if cfg.host_dev_name.is_empty() => return Err(NetworkInterfaceError::NoTap),
+            None => crate::devices::virtio::net::Net::new_multi_queue(
+                cfg.iface_id,
+                &cfg.host_dev_name,
+                cfg.num_queues.unwrap_or(1),
+                cfg.guest_mac,
+                rx_rate_limiter.unwrap_or_default(),
+                tx_rate_limiter.unwrap_or_default(),
+            ),
+        }
+        .map_err(NetworkInterfaceError::CreateNetworkDevice)?;
+        if let Some(offloads) = cfg.offloads {
+            net.set_offloads(offloads)
+                .map_err(NetworkInterfaceError::CreateNetworkDevice)?;
+        }
+        if let Some(mtu) = cfg.mtu {
+            net.set_mtu(mtu)
+                .map_err(NetworkInterfaceError::CreateNetworkDevice)?;
+        }
+        if let Some(pin) = cfg.virtio_features_pin {
+            net.pin_virtio_features(pin)?;
+        }
+        if let Some(size) = cfg.queue_size {
+            net.set_queue_size(size)?;
+        }
+        if let Some(scope) = cfg.rate_limiter_scope {
+            net.set_rate_limiter_scope(scope)?;
+        }
+        Ok(net)
     }
 
     /// Returns a vec with the structures used to configure the net devices.
@@ -196,9 +371,17 @@ mod tests {
         NetworkInterfaceConfig {
             iface_id: String::from(id),
             host_dev_name: String::from(name),
+            tap_fd: None,
             guest_mac: Some(MacAddr::from_str(mac).unwrap()),
             rx_rate_limiter: RateLimiterConfig::default().into_option(),
             tx_rate_limiter: RateLimiterConfig::default().into_option(),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            num_queues: None,
+            rate_limiter_scope: None,
+            offloads: None,
+            mtu: None,
         }
     }
 
@@ -207,9 +390,17 @@ mod tests {
             NetworkInterfaceConfig {
                 iface_id: self.iface_id.clone(),
                 host_dev_name: self.host_dev_name.clone(),
+                tap_fd: self.tap_fd,
                 guest_mac: self.guest_mac,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                virtio_features_pin: self.virtio_features_pin,
+                negotiated_virtio_features: None,
+                queue_size: self.queue_size,
+                num_queues: self.num_queues,
+                rate_limiter_scope: self.rate_limiter_scope,
+                offloads: self.offloads,
+                mtu: self.mtu,
             }
         }
     }
@@ -308,6 +499,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_update() {
+        let mut net_builder = NetBuilder::new();
+        let netif_1 = create_netif("id_1", "dev9", "01:23:45:67:89:0a");
+        let netif_2 = create_netif("id_2", "dev10", "01:23:45:67:89:0b");
+        net_builder.build(netif_1).unwrap();
+        net_builder.build(netif_2).unwrap();
+
+        let update = |iface_id: &str, guest_mac: &str| NetworkInterfaceUpdateConfig {
+            iface_id: iface_id.to_string(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            guest_mac: Some(MacAddr::from_str(guest_mac).unwrap()),
+        };
+
+        // The MAC address is updated in place, along with the configuration space.
+        net_builder
+            .update(&update("id_1", "01:23:45:67:89:0c"))
+            .unwrap();
+        let configs = net_builder.configs();
+        assert_eq!(
+            configs[0].guest_mac,
+            Some(MacAddr::from_str("01:23:45:67:89:0c").unwrap())
+        );
+        assert_eq!(configs[0].host_dev_name, "dev9");
+
+        // The MAC address of another interface can not be used.
+        assert_eq!(
+            net_builder
+                .update(&update("id_1", "01:23:45:67:89:0b"))
+                .unwrap_err()
+                .to_string(),
+            NetworkInterfaceError::GuestMacAddressInUse("01:23:45:67:89:0b".to_string())
+                .to_string()
+        );
+        // Only the existing interfaces can be updated.
+        assert_eq!(
+            net_builder
+                .update(&update("id_3", "01:23:45:67:89:0d"))
+                .unwrap_err()
+                .to_string(),
+            NetworkInterfaceError::InvalidIfaceId("id_3".to_string()).to_string()
+        );
+    }
+
     #[test]
     fn test_net_config() {
         let net_id = "id";
@@ -329,6 +565,240 @@ mod tests {
         assert_eq!(configs.first().unwrap(), &net_if_cfg);
     }
 
+    #[test]
+    fn test_virtio_features_pin() {
+        let mut net_builder = NetBuilder::new();
+        let avail_features = net_builder
+            .build(create_netif("id", "dev5", "01:23:45:67:89:0c"))
+            .unwrap()
+            .lock()
+            .unwrap()
+            .avail_features();
+
+        // Pin a reduced feature set.
+        let pin = avail_features & 0xffff_ffff_0000_0000;
+        let mut net_if_cfg = create_netif("id", "dev5", "01:23:45:67:89:0c");
+        net_if_cfg.virtio_features_pin = Some(pin);
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(net.lock().unwrap().avail_features(), pin);
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+
+        // Pin features the device cannot offer.
+        let mut net_if_cfg = create_netif("id", "dev5", "01:23:45:67:89:0c");
+        net_if_cfg.virtio_features_pin = Some(pin | 1 << 63);
+        assert_eq!(
+            net_builder.build(net_if_cfg).unwrap_err().to_string(),
+            NetworkInterfaceError::PinVirtioFeatures(VirtioFeaturesPinError::UnavailableFeatures(
+                1 << 63
+            ))
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_queue_size() {
+        let mut net_builder = NetBuilder::new();
+
+        let mut net_if_cfg = create_netif("id", "dev6", "01:23:45:67:89:0d");
+        net_if_cfg.queue_size = Some(1024);
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert!(net
+            .lock()
+            .unwrap()
+            .queues()
+            .iter()
+            .all(|queue| queue.max_size == 1024));
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+
+        // The queue size must be a power of two within the bounds of the network device.
+        for size in [0, 500, 32, 2048] {
+            let mut net_if_cfg = create_netif("id", "dev6", "01:23:45:67:89:0d");
+            net_if_cfg.queue_size = Some(size);
+            assert_eq!(
+                net_builder.build(net_if_cfg).unwrap_err().to_string(),
+                NetworkInterfaceError::QueueSize(QueueSizeError::InvalidSize(size, 64, 1024))
+                    .to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_num_queues() {
+        let mut net_builder = NetBuilder::new();
+
+        let mut net_if_cfg = create_netif("id", "dev7", "01:23:45:67:89:0e");
+        net_if_cfg.num_queues = Some(2);
+        net_if_cfg.rate_limiter_scope = Some(RateLimiterScope::QueuePair);
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        {
+            let net = net.lock().unwrap();
+            assert_eq!(net.num_pairs(), 2);
+            // The RX and TX queues of both pairs, and the control queue.
+            assert_eq!(net.queues().len(), 5);
+            assert_eq!(net.rate_limiter_scope(), RateLimiterScope::QueuePair);
+        }
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+
+        for num_queues in [0, 17] {
+            let mut net_if_cfg = create_netif("id", "dev7", "01:23:45:67:89:0e");
+            net_if_cfg.num_queues = Some(num_queues);
+            assert_eq!(
+                net_builder.build(net_if_cfg).unwrap_err().to_string(),
+                NetworkInterfaceError::CreateNetworkDevice(
+                    crate::devices::virtio::net::NetError::QueuePairs(num_queues, 16)
+                )
+                .to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_offloads() {
+        let mut net_builder = NetBuilder::new();
+
+        let mut net_if_cfg = create_netif("id", "dev8", "01:23:45:67:89:0f");
+        let offloads = NetOffloads {
+            guest_tso4: false,
+            guest_ufo: false,
+            ..Default::default()
+        };
+        net_if_cfg.offloads = Some(offloads);
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(net.lock().unwrap().offloads(), offloads);
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+
+        let mut net_if_cfg = create_netif("id", "dev8", "01:23:45:67:89:0f");
+        net_if_cfg.offloads = Some(NetOffloads {
+            csum: false,
+            ..Default::default()
+        });
+        assert_eq!(
+            net_builder.build(net_if_cfg).unwrap_err().to_string(),
+            NetworkInterfaceError::CreateNetworkDevice(
+                crate::devices::virtio::net::NetError::OffloadDependency("host_tso4", "csum")
+            )
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_mtu() {
+        let mut net_builder = NetBuilder::new();
+
+        let mut net_if_cfg = create_netif("id", "dev9", "01:23:45:67:89:0f");
+        net_if_cfg.mtu = Some(9000);
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(net.lock().unwrap().mtu(), Some(9000));
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+
+        let mut net_if_cfg = create_netif("id", "dev9", "01:23:45:67:89:0f");
+        net_if_cfg.mtu = Some(67);
+        assert_eq!(
+            net_builder.build(net_if_cfg).unwrap_err().to_string(),
+            NetworkInterfaceError::CreateNetworkDevice(
+                crate::devices::virtio::net::NetError::InvalidMtu(67, 68, 65532)
+            )
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_tap_fd() {
+        use std::os::unix::io::AsRawFd;
+
+        use crate::devices::virtio::net::Tap;
+
+        let mut net_builder = NetBuilder::new();
+        let tap = Tap::open_named("fdtap%d").unwrap();
+
+        let mut net_if_cfg = create_netif("id", "", "01:23:45:67:89:10");
+        net_if_cfg.tap_fd = Some(tap.as_raw_fd());
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(net.lock().unwrap().iface_name(), tap.if_name_as_str());
+        assert_eq!(net.lock().unwrap().tap_fd(), Some(tap.as_raw_fd()));
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+
+        // Error Case: both the tap name and the tap fd are set.
+        let mut net_if_cfg = create_netif("id", "dev17", "01:23:45:67:89:10");
+        net_if_cfg.tap_fd = Some(tap.as_raw_fd());
+        assert!(matches!(
+            net_builder.build(net_if_cfg),
+            Err(NetworkInterfaceError::TapNameAndFd)
+        ));
+
+        // Error Case: neither is set.
+        assert!(matches!(
+            net_builder.build(create_netif("id", "", "01:23:45:67:89:10")),
+            Err(NetworkInterfaceError::NoTap)
+        ));
+
+        // Error Case: a single fd can't back several pairs of queues.
+        let mut net_if_cfg = create_netif("id", "", "01:23:45:67:89:10");
+        net_if_cfg.tap_fd = Some(tap.as_raw_fd());
+        net_if_cfg.num_queues = Some(2);
+        assert!(matches!(
+            net_builder.build(net_if_cfg),
+            Err(NetworkInterfaceError::TapFdMultiQueue)
+        ));
+
+        // Error Case: the fd is not valid.
+        let mut net_if_cfg = create_netif("id", "", "01:23:45:67:89:10");
+        net_if_cfg.tap_fd = Some(-1);
+        assert!(matches!(
+            net_builder.build(net_if_cfg),
+            Err(NetworkInterfaceError::CreateNetworkDevice(
+                crate::devices::virtio::net::NetError::TapOpen(TapError::InvalidFd(_))
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_build_hotplug() {
+        let mut net_builder = NetBuilder::new();
+        net_builder
+            .build(create_netif("id_1", "dev15", "01:23:45:67:89:0a"))
+            .unwrap();
+
+        // Error Case: the interface ID is already in use.
+        let err = net_builder
+            .build_hotplug(create_netif("id_1", "dev16", "01:23:45:67:89:0b"))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            NetworkInterfaceError::IfaceIdInUse("id_1".to_string()).to_string()
+        );
+
+        // Error Case: the MAC address is already in use.
+        let err = net_builder
+            .build_hotplug(create_netif("id_2", "dev16", "01:23:45:67:89:0a"))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            NetworkInterfaceError::GuestMacAddressInUse("01:23:45:67:89:0a".to_string())
+                .to_string()
+        );
+
+        // Error Case: the tap is already in use.
+        let err = net_builder
+            .build_hotplug(create_netif("id_2", "dev15", "01:23:45:67:89:0b"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            NetworkInterfaceError::CreateNetworkDevice(
+                crate::devices::virtio::net::NetError::TapOpen(_)
+            )
+        ));
+
+        // The device is only kept by the builder once added.
+        let net = net_builder
+            .build_hotplug(create_netif("id_2", "dev16", "01:23:45:67:89:0b"))
+            .unwrap();
+        assert_eq!(net.lock().unwrap().id(), "id_2");
+        assert_eq!(net_builder.len(), 1);
+        net_builder.add_device(net);
+        assert_eq!(net_builder.len(), 2);
+    }
+
     #[test]
     fn test_add_device() {
         let mut net_builder = NetBuilder::new();
@@ -359,4 +829,29 @@ mod tests {
             net_id
         );
     }
+
+    #[test]
+    fn test_remove() {
+        let mut net_builder = NetBuilder::new();
+        net_builder
+            .build(create_netif("id_1", "dev17", "01:23:45:67:89:0a"))
+            .unwrap();
+        net_builder
+            .build(create_netif("id_2", "dev18", "01:23:45:67:89:0b"))
+            .unwrap();
+
+        let net = net_builder.remove("id_1").unwrap();
+        assert_eq!(net.lock().unwrap().id(), "id_1");
+        assert_eq!(net_builder.len(), 1);
+        assert!(matches!(
+            net_builder.remove("id_1"),
+            Err(NetworkInterfaceError::InvalidIfaceId(_))
+        ));
+        assert_eq!(net_builder.len(), 1);
+
+        // The MAC address of the removed device is free again.
+        net_builder
+            .build(create_netif("id_3", "dev19", "01:23:45:67:89:0a"))
+            .unwrap();
+    }
 }
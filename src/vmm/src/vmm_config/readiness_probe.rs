@@ -0,0 +1,158 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+/// Default time given to each attempt of the readiness probe, in milliseconds.
+pub const DEFAULT_PROBE_TIMEOUT_MS: u64 = 500;
+/// Default number of attempts of the readiness probe before it fails.
+pub const DEFAULT_PROBE_MAX_ATTEMPTS: u32 = 60;
+/// Maximum length of the MMDS key written by the guest to signal it is ready.
+pub const MAX_PROBE_MMDS_KEY_LEN: usize = 64;
+
+/// Check run against the guest by the readiness probe.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum ReadinessCheck {
+    /// The guest accepts a vsock connection on `port`, and sends at least one byte on it.
+    Vsock {
+        /// Guest vsock port to connect to.
+        port: u32,
+    },
+    /// The guest writes a value under `key` at the root of the MMDS data store. This is the only
+    /// key the guest can write to.
+    Mmds {
+        /// Top level MMDS key written by the guest.
+        key: String,
+    },
+}
+
+/// Configuration of the probe telling when the guest is ready, after the microVM started.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadinessProbeConfig {
+    /// Check run against the guest.
+    pub check: ReadinessCheck,
+    /// Time given to each attempt of the check, in milliseconds.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Number of attempts of the check before the probe fails.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_PROBE_TIMEOUT_MS
+}
+
+fn default_max_attempts() -> u32 {
+    DEFAULT_PROBE_MAX_ATTEMPTS
+}
+
+/// Errors associated with the readiness probe configuration.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum ReadinessProbeConfigError {
+    /// The timeout and the number of attempts of the readiness probe must be greater than 0.
+    InvalidSchedule,
+    /// The MMDS key of the readiness probe must be 1 to 64 bytes long and must not contain '/': {0:?}.
+    InvalidMmdsKey(String),
+}
+
+impl ReadinessProbeConfig {
+    /// Checks that the probe can be run.
+    pub fn validate(&self) -> Result<(), ReadinessProbeConfigError> {
+        if self.timeout_ms == 0 || self.max_attempts == 0 {
+            return Err(ReadinessProbeConfigError::InvalidSchedule);
+        }
+        match &self.check {
+            ReadinessCheck::Mmds { key }
+                if key.is_empty() || key.len() > MAX_PROBE_MMDS_KEY_LEN || key.contains('/') =>
+            {
+                Err(ReadinessProbeConfigError::InvalidMmdsKey(key.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = ReadinessProbeConfig {
+            check: ReadinessCheck::Vsock { port: 52 },
+            timeout_ms: 100,
+            max_attempts: 3,
+        };
+        config.validate().unwrap();
+
+        config.timeout_ms = 0;
+        assert_eq!(
+            config.validate().unwrap_err(),
+            ReadinessProbeConfigError::InvalidSchedule
+        );
+        config.timeout_ms = 100;
+        config.max_attempts = 0;
+        assert_eq!(
+            config.validate().unwrap_err(),
+            ReadinessProbeConfigError::InvalidSchedule
+        );
+        config.max_attempts = 3;
+
+        config.check = ReadinessCheck::Mmds {
+            key: "a".repeat(MAX_PROBE_MMDS_KEY_LEN),
+        };
+        config.validate().unwrap();
+        for key in [
+            String::new(),
+            "a".repeat(MAX_PROBE_MMDS_KEY_LEN + 1),
+            "ready/now".to_string(),
+        ] {
+            config.check = ReadinessCheck::Mmds { key: key.clone() };
+            assert_eq!(
+                config.validate().unwrap_err(),
+                ReadinessProbeConfigError::InvalidMmdsKey(key)
+            );
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let config: ReadinessProbeConfig =
+            serde_json::from_str(r#"{"check": {"vsock": {"port": 52}}}"#).unwrap();
+        assert_eq!(
+            config,
+            ReadinessProbeConfig {
+                check: ReadinessCheck::Vsock { port: 52 },
+                timeout_ms: DEFAULT_PROBE_TIMEOUT_MS,
+                max_attempts: DEFAULT_PROBE_MAX_ATTEMPTS,
+            }
+        );
+
+        let config: ReadinessProbeConfig = serde_json::from_str(
+            r#"{"check": {"mmds": {"key": "ready"}}, "timeout_ms": 200, "max_attempts": 10}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            ReadinessProbeConfig {
+                check: ReadinessCheck::Mmds {
+                    key: "ready".to_string()
+                },
+                timeout_ms: 200,
+                max_attempts: 10,
+            }
+        );
+
+        serde_json::from_str::<ReadinessProbeConfig>("{}").unwrap_err();
+        serde_json::from_str::<ReadinessProbeConfig>(r#"{"check": {"http": {"port": 80}}}"#)
+            .unwrap_err();
+        serde_json::from_str::<ReadinessProbeConfig>(
+            r#"{"check": {"vsock": {"port": 52}}, "retries": 3}"#,
+        )
+        .unwrap_err();
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of serial ports of the microVM. On x86_64, they are placed at the standard
+/// COM1-4 I/O ports.
+pub const MAX_SERIAL_PORTS: usize = 4;
+/// Maximum length of the identifier of a serial port.
+pub const MAX_SERIAL_PORT_ID_LEN: usize = 64;
+/// Value of `output` sending the output of a serial port to the standard output of Firecracker.
+pub const SERIAL_OUTPUT_STDOUT: &str = "stdout";
+
+/// Configuration of a serial port of the microVM.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SerialPortConfig {
+    /// Identifier of the serial port, used to name its metrics.
+    pub id: String,
+    /// Where the output of the serial port goes: `stdout` for the standard output of
+    /// Firecracker, or the path of a file or named pipe it is appended to. The output is
+    /// discarded when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Whether the serial port reads its input from the standard input of Firecracker.
+    #[serde(default)]
+    pub input: bool,
+}
+
+impl SerialPortConfig {
+    /// Whether the output of the serial port goes to the standard output of Firecracker.
+    pub fn is_stdout(&self) -> bool {
+        self.output.as_deref() == Some(SERIAL_OUTPUT_STDOUT)
+    }
+}
+
+/// Errors associated with the serial ports configuration.
+#[rustfmt::skip]
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum SerialPortsConfigError {
+    /// At least 1 and at most {MAX_SERIAL_PORTS:} serial ports can be configured.
+    InvalidPortCount,
+    /// The id of a serial port must be 1 to 64 alphanumeric characters, hyphens or underscores long: {0:?}.
+    InvalidId(String),
+    /// The id of a serial port is used more than once: {0:?}.
+    DuplicateId(String),
+    /// At most one serial port can read the standard input of Firecracker.
+    MultipleInputs,
+    /// At most one serial port can write to the standard output of Firecracker.
+    MultipleStdoutOutputs,
+    /// The output of a serial port must not be empty: {0:?}.
+    EmptyOutput(String),
+}
+
+/// Checks that the serial ports can be set up.
+pub fn validate_serial_ports(ports: &[SerialPortConfig]) -> Result<(), SerialPortsConfigError> {
+    if ports.is_empty() || ports.len() > MAX_SERIAL_PORTS {
+        return Err(SerialPortsConfigError::InvalidPortCount);
+    }
+    let mut ids = HashSet::new();
+    for port in ports {
+        if port.id.is_empty()
+            || port.id.len() > MAX_SERIAL_PORT_ID_LEN
+            || !port
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(SerialPortsConfigError::InvalidId(port.id.clone()));
+        }
+        if !ids.insert(port.id.as_str()) {
+            return Err(SerialPortsConfigError::DuplicateId(port.id.clone()));
+        }
+        if port.output.as_deref() == Some("") {
+            return Err(SerialPortsConfigError::EmptyOutput(port.id.clone()));
+        }
+    }
+    if ports.iter().filter(|port| port.input).count() > 1 {
+        return Err(SerialPortsConfigError::MultipleInputs);
+    }
+    if ports.iter().filter(|port| port.is_stdout()).count() > 1 {
+        return Err(SerialPortsConfigError::MultipleStdoutOutputs);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(id: &str, output: Option<&str>, input: bool) -> SerialPortConfig {
+        SerialPortConfig {
+            id: id.to_string(),
+            output: output.map(str::to_string),
+            input,
+        }
+    }
+
+    #[test]
+    fn test_validate_serial_ports() {
+        validate_serial_ports(&[
+            port("console", Some("stdout"), true),
+            port("mgmt", Some("/tmp/mgmt.log"), false),
+            port("logs_2", None, false),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            validate_serial_ports(&[]).unwrap_err(),
+            SerialPortsConfigError::InvalidPortCount
+        );
+        let too_many: Vec<_> = (0..=MAX_SERIAL_PORTS)
+            .map(|i| port(&format!("ttyS{}", i), None, false))
+            .collect();
+        assert_eq!(
+            validate_serial_ports(&too_many).unwrap_err(),
+            SerialPortsConfigError::InvalidPortCount
+        );
+
+        for id in [
+            String::new(),
+            "a".repeat(MAX_SERIAL_PORT_ID_LEN + 1),
+            "con sole".to_string(),
+        ] {
+            assert_eq!(
+                validate_serial_ports(&[port(&id, None, false)]).unwrap_err(),
+                SerialPortsConfigError::InvalidId(id)
+            );
+        }
+        assert_eq!(
+            validate_serial_ports(&[port("a", None, false), port("a", None, false)]).unwrap_err(),
+            SerialPortsConfigError::DuplicateId("a".to_string())
+        );
+        assert_eq!(
+            validate_serial_ports(&[port("a", Some(""), false)]).unwrap_err(),
+            SerialPortsConfigError::EmptyOutput("a".to_string())
+        );
+        assert_eq!(
+            validate_serial_ports(&[port("a", None, true), port("b", None, true)]).unwrap_err(),
+            SerialPortsConfigError::MultipleInputs
+        );
+        assert_eq!(
+            validate_serial_ports(&[
+                port("a", Some("stdout"), false),
+                port("b", Some("stdout"), false)
+            ])
+            .unwrap_err(),
+            SerialPortsConfigError::MultipleStdoutOutputs
+        );
+    }
+}
@@ -3,6 +3,7 @@
 
 //! Configurations used in the snapshotting context.
 
+use std::os::unix::io::RawFd;
 use std::path::PathBuf;
 
 /// For crates that depend on `vmm` we export.
@@ -60,6 +61,10 @@ pub struct LoadSnapshotParams {
     /// When set to true, the vm is also resumed if the snapshot load
     /// is successful.
     pub resume_vm: bool,
+    /// Records the guest pages touched after the microVM resumes, when set.
+    pub track_access: Option<TrackAccessConfig>,
+    /// Options of the restore of the network interfaces.
+    pub network_overrides: Vec<LoadNetworkOverride>,
 }
 
 /// Stores the configuration for loading a snapshot that is provided by the user.
@@ -82,6 +87,62 @@ pub struct LoadSnapshotConfig {
     /// Whether or not to resume the vm post snapshot load.
     #[serde(default)]
     pub resume_vm: bool,
+    /// Configuration of the tracking of the guest pages touched after the microVM resumes.
+    #[serde(default)]
+    pub track_access: Option<TrackAccessConfig>,
+    /// Options of the restore of the network interfaces: the taps, given by fd, they are
+    /// restored with instead of the taps they were using when snapshotted, and whether the guest
+    /// announces itself through them.
+    #[serde(default)]
+    pub network_overrides: Vec<LoadNetworkOverride>,
+}
+
+/// Options of the restore of a network interface of a microVM.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoadNetworkOverride {
+    /// Id of the network interface.
+    pub iface_id: String,
+    /// File descriptor of the tap device, inherited by the Firecracker process, used instead of
+    /// the tap the interface was using when snapshotted.
+    #[serde(default)]
+    pub tap_fd: Option<RawFd>,
+    /// Whether the guest is asked to announce itself on the network through the interface once
+    /// restored, with `VIRTIO_NET_F_GUEST_ANNOUNCE`.
+    #[serde(default = "default_guest_announce")]
+    pub guest_announce: bool,
+}
+
+fn default_guest_announce() -> bool {
+    true
+}
+
+/// Maximum time during which the guest memory accesses can be tracked, in seconds.
+pub const MAX_TRACK_ACCESS_SECONDS: u64 = 3600;
+
+/// Configuration of the tracking of the guest pages touched after a restored microVM resumes.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrackAccessConfig {
+    /// Time during which the accesses are recorded after the first resume, in seconds.
+    pub duration_s: u64,
+}
+
+/// Errors associated with the configuration of the memory access tracking.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum TrackAccessConfigError {
+    /// The duration of the memory access tracking must be 1 to 3600 seconds.
+    InvalidDuration,
+}
+
+impl TrackAccessConfig {
+    /// Checks that the memory accesses can be tracked for the configured duration.
+    pub fn validate(&self) -> Result<(), TrackAccessConfigError> {
+        match self.duration_s {
+            1..=MAX_TRACK_ACCESS_SECONDS => Ok(()),
+            _ => Err(TrackAccessConfigError::InvalidDuration),
+        }
+    }
 }
 
 /// Stores the configuration used for managing snapshot memory.
@@ -94,6 +155,29 @@ pub struct MemBackendConfig {
     pub backend_type: MemBackendType,
 }
 
+/// Tap device used by a network interface of a cloned microVM.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloneNetworkOverride {
+    /// Id of the network interface.
+    pub iface_id: String,
+    /// Host level name of the tap device used by the clone.
+    pub host_dev_name: String,
+}
+
+/// Stores the configuration used for cloning a paused microVM into another Firecracker process.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloneMicrovmParams {
+    /// Path to the socket on which the Firecracker process receiving the clone, started with
+    /// `--await-clone`, is listening.
+    pub socket: PathBuf,
+    /// Tap devices used by the clone, which are required for every network interface since the
+    /// tap devices of the microVM stay in use.
+    #[serde(default)]
+    pub network_overrides: Vec<CloneNetworkOverride>,
+}
+
 /// The microVM state options.
 #[derive(Debug, Deserialize, Serialize)]
 pub enum VmState {
@@ -110,3 +194,21 @@ pub struct Vm {
     /// The microVM state, which can be `paused` or `resumed`.
     pub state: VmState,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_access_validate() {
+        for duration_s in [1, 10, MAX_TRACK_ACCESS_SECONDS] {
+            TrackAccessConfig { duration_s }.validate().unwrap();
+        }
+        for duration_s in [0, MAX_TRACK_ACCESS_SECONDS + 1] {
+            assert_eq!(
+                TrackAccessConfig { duration_s }.validate(),
+                Err(TrackAccessConfigError::InvalidDuration)
+            );
+        }
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::VmmError;
+
+/// Time waited for the reply of a vhost-user backend to a ping when none is given, in
+/// milliseconds.
+pub const DEFAULT_PING_TIMEOUT_MS: u64 = 1000;
+/// Maximum time waited for the reply of a vhost-user backend to a ping, in milliseconds.
+pub const MAX_PING_TIMEOUT_MS: u64 = 60_000;
+
+/// Errors associated with the operations allowed on a vhost-user device.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum VhostUserConfigError {
+    /// The ping timeout must be between 1 and 60000 ms.
+    InvalidPingTimeout,
+    /// Unable to reach the vhost-user device: {0}
+    Device(VmmError),
+}
+
+/// Health check of the backend of a vhost-user device.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VhostUserPingConfig {
+    /// Identifier of the vhost-user device, taken from the path of the request.
+    #[serde(skip)]
+    pub id: String,
+    /// Time to wait for the reply of the backend, in milliseconds.
+    pub timeout_ms: Option<u64>,
+}
+
+impl VhostUserPingConfig {
+    /// Returns the time to wait for the reply of the backend.
+    pub fn timeout(&self) -> Result<Duration, VhostUserConfigError> {
+        match self.timeout_ms.unwrap_or(DEFAULT_PING_TIMEOUT_MS) {
+            timeout_ms @ 1..=MAX_PING_TIMEOUT_MS => Ok(Duration::from_millis(timeout_ms)),
+            _ => Err(VhostUserConfigError::InvalidPingTimeout),
+        }
+    }
+}
+
+/// Outcome of a successful health check of the backend of a vhost-user device.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct VhostUserPingResult {
+    /// Time the backend took to reply, in microseconds.
+    pub latency_us: u64,
+}
+
+impl From<Duration> for VhostUserPingResult {
+    fn from(latency: Duration) -> Self {
+        Self {
+            latency_us: u64::try_from(latency.as_micros()).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_timeout() {
+        let config = VhostUserPingConfig::default();
+        assert_eq!(
+            config.timeout().unwrap(),
+            Duration::from_millis(DEFAULT_PING_TIMEOUT_MS)
+        );
+
+        let config = VhostUserPingConfig {
+            id: "drive".to_string(),
+            timeout_ms: Some(MAX_PING_TIMEOUT_MS),
+        };
+        assert_eq!(
+            config.timeout().unwrap(),
+            Duration::from_millis(MAX_PING_TIMEOUT_MS)
+        );
+
+        for timeout_ms in [0, MAX_PING_TIMEOUT_MS + 1] {
+            let config = VhostUserPingConfig {
+                id: "drive".to_string(),
+                timeout_ms: Some(timeout_ms),
+            };
+            assert!(matches!(
+                config.timeout(),
+                Err(VhostUserConfigError::InvalidPingTimeout)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_ping_result() {
+        assert_eq!(
+            VhostUserPingResult::from(Duration::from_micros(1234)),
+            VhostUserPingResult { latency_us: 1234 }
+        );
+        assert_eq!(
+            serde_json::to_string(&VhostUserPingResult { latency_us: 42 }).unwrap(),
+            r#"{"latency_us":42}"#
+        );
+    }
+}
@@ -6,7 +6,10 @@ use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
-use crate::devices::virtio::vsock::{Vsock, VsockError, VsockUnixBackend, VsockUnixBackendError};
+use crate::devices::virtio::device::{QueueSizeError, VirtioDevice, VirtioFeaturesPinError};
+use crate::devices::virtio::vsock::{
+    Vsock, VsockConnectionInfo, VsockError, VsockUnixBackend, VsockUnixBackendError,
+};
 
 type MutexVsockUnix = Arc<Mutex<Vsock<VsockUnixBackend>>>;
 
@@ -17,6 +20,10 @@ pub enum VsockConfigError {
     CreateVsockBackend(VsockUnixBackendError),
     /// Cannot create vsock device: {0}
     CreateVsockDevice(VsockError),
+    /// Cannot pin the virtio features of the vsock device: {0}
+    PinVirtioFeatures(VirtioFeaturesPinError),
+    /// Cannot set the queue size of the vsock device: {0}
+    QueueSize(QueueSizeError),
 }
 
 /// This struct represents the strongly typed equivalent of the json body
@@ -32,6 +39,25 @@ pub struct VsockDeviceConfig {
     pub guest_cid: u32,
     /// Path to local unix socket.
     pub uds_path: String,
+    /// Virtio features the features offered by the device are restricted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtio_features_pin: Option<u64>,
+    /// Virtio features negotiated with the guest driver, once the device is activated.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub negotiated_virtio_features: Option<u64>,
+    /// Maximal size of the virtio queues offered to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<u16>,
+    /// Whether connections are kept across snapshots, instead of being reset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_connections: Option<bool>,
+}
+
+/// The connection table of the vsock device.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct VsockConnections {
+    /// The active connections, sorted by host port, then by guest port.
+    pub connections: Vec<VsockConnectionInfo>,
 }
 
 #[derive(Debug)]
@@ -47,6 +73,10 @@ impl From<&VsockAndUnixPath> for VsockDeviceConfig {
             vsock_id: None,
             guest_cid: u32::try_from(vsock_lock.cid()).unwrap(),
             uds_path: vsock.uds_path.clone(),
+            virtio_features_pin: vsock_lock.virtio_features_pin(),
+            negotiated_virtio_features: vsock_lock.negotiated_features(),
+            queue_size: vsock_lock.queue_size(),
+            keep_connections: vsock_lock.backend().keep_connections().then_some(true),
         }
     }
 }
@@ -99,9 +129,18 @@ impl VsockBuilder {
     pub fn create_unixsock_vsock(
         cfg: VsockDeviceConfig,
     ) -> Result<Vsock<VsockUnixBackend>, VsockConfigError> {
-        let backend = VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path)?;
+        let mut backend = VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path)?;
+        backend.set_keep_connections(cfg.keep_connections.unwrap_or(false));
 
-        Vsock::new(u64::from(cfg.guest_cid), backend).map_err(VsockConfigError::CreateVsockDevice)
+        let mut vsock = Vsock::new(u64::from(cfg.guest_cid), backend)
+            .map_err(VsockConfigError::CreateVsockDevice)?;
+        if let Some(pin) = cfg.virtio_features_pin {
+            vsock.pin_virtio_features(pin)?;
+        }
+        if let Some(size) = cfg.queue_size {
+            vsock.set_queue_size(size)?;
+        }
+        Ok(vsock)
     }
 
     /// Returns the structure used to configure the vsock device.
@@ -122,6 +161,10 @@ pub(crate) mod tests {
             vsock_id: None,
             guest_cid: 3,
             uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+            virtio_features_pin: None,
+            negotiated_virtio_features: None,
+            queue_size: None,
+            keep_connections: None,
         }
     }
 
@@ -164,6 +207,56 @@ pub(crate) mod tests {
         assert_eq!(config.unwrap(), vsock_config);
     }
 
+    #[test]
+    fn test_vsock_queue_size() {
+        let mut vsock_builder = VsockBuilder::new();
+        let mut tmp_sock_file = TempFile::new().unwrap();
+        tmp_sock_file.remove().unwrap();
+        let mut vsock_config = default_config(&tmp_sock_file);
+        vsock_config.queue_size = Some(64);
+        vsock_builder.insert(vsock_config.clone()).unwrap();
+        assert!(vsock_builder
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .queues()
+            .iter()
+            .all(|queue| queue.max_size == 64));
+        assert_eq!(vsock_builder.config().unwrap(), vsock_config);
+
+        // The queue size must be a power of two within the bounds of the vsock device.
+        for size in [48, 8, 4096] {
+            tmp_sock_file.remove().ok();
+            vsock_config.queue_size = Some(size);
+            assert_eq!(
+                VsockBuilder::create_unixsock_vsock(vsock_config.clone())
+                    .unwrap_err()
+                    .to_string(),
+                VsockConfigError::QueueSize(QueueSizeError::InvalidSize(size, 16, 1024))
+                    .to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_vsock_keep_connections() {
+        let mut vsock_builder = VsockBuilder::new();
+        let mut tmp_sock_file = TempFile::new().unwrap();
+        tmp_sock_file.remove().unwrap();
+        let mut vsock_config = default_config(&tmp_sock_file);
+        vsock_config.keep_connections = Some(true);
+        vsock_builder.insert(vsock_config.clone()).unwrap();
+        assert!(vsock_builder
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .backend()
+            .keep_connections());
+        assert_eq!(vsock_builder.config().unwrap(), vsock_config);
+    }
+
     #[test]
     fn test_set_device() {
         let mut vsock_builder = VsockBuilder::new();
@@ -19,6 +19,7 @@ pub use vm_memory::{
 };
 use vm_memory::{Error as VmMemoryError, GuestMemoryError, WriteVolatile};
 
+use crate::arch::memory_layout::MemoryLayoutError;
 use crate::vmm_config::machine_config::HugePageConfig;
 use crate::DirtyBitmap;
 
@@ -52,6 +53,8 @@ pub enum MemoryError {
     MemfdSetLen(std::io::Error),
     /// Cannot restore hugetlbfs backed snapshot by mapping the memory file. Please use uffd.
     HugetlbfsSnapshot,
+    /// Invalid guest memory layout: {0}
+    Layout(#[from] MemoryLayoutError),
 }
 
 /// Defines the interface for snapshotting memory.
@@ -78,6 +81,7 @@ where
         regions: Vec<(FileOffset, GuestAddress, usize)>,
         track_dirty_pages: bool,
         shared: bool,
+        huge_pages: HugePageConfig,
     ) -> Result<Self, MemoryError>;
 
     /// Creates a GuestMemoryMmap given a `file` containing the data
@@ -105,6 +109,9 @@ where
         dirty_bitmap: &DirtyBitmap,
     ) -> Result<(), MemoryError>;
 
+    /// Dumps all contents of GuestMemoryMmap to a new memfd, sealed so that it never changes.
+    fn dump_to_sealed_memfd(&self) -> Result<File, MemoryError>;
+
     /// Resets all the memory region bitmaps
     fn reset_dirty(&self);
 
@@ -142,7 +149,7 @@ impl GuestMemoryExtension for GuestMemoryMmap {
         let memfd_file = create_memfd(mem_size_mib, huge_pages.into())?.into_file();
 
         let mut offset: u64 = 0;
-        let regions = crate::arch::arch_memory_regions(mem_size_mib << 20)
+        let regions = crate::arch::arch_memory_regions(mem_size_mib << 20)?
             .iter()
             .map(|(guest_address, region_size)| {
                 let file_clone = memfd_file.try_clone().map_err(MemoryError::FileError)?;
@@ -152,7 +159,7 @@ impl GuestMemoryExtension for GuestMemoryMmap {
             })
             .collect::<Result<Vec<_>, MemoryError>>()?;
 
-        Self::from_raw_regions_file(regions, track_dirty_pages, true)
+        Self::from_raw_regions_file(regions, track_dirty_pages, true, huge_pages)
     }
 
     /// Creates a GuestMemoryMmap from raw regions backed by anonymous memory.
@@ -180,6 +187,7 @@ impl GuestMemoryExtension for GuestMemoryMmap {
                 let region = MmapRegionBuilder::new_with_bitmap(*region_size, bitmap)
                     .with_mmap_prot(prot)
                     .with_mmap_flags(flags)
+                    .with_hugetlbfs(huge_pages.is_hugetlbfs())
                     .build()
                     .map_err(MemoryError::MmapRegionError)?;
 
@@ -195,6 +203,7 @@ impl GuestMemoryExtension for GuestMemoryMmap {
         regions: Vec<(FileOffset, GuestAddress, usize)>,
         track_dirty_pages: bool,
         shared: bool,
+        huge_pages: HugePageConfig,
     ) -> Result<Self, MemoryError> {
         let prot = libc::PROT_READ | libc::PROT_WRITE;
         let flags = if shared {
@@ -213,6 +222,7 @@ impl GuestMemoryExtension for GuestMemoryMmap {
                     .with_mmap_prot(prot)
                     .with_mmap_flags(flags)
                     .with_file_offset(file_offset)
+                    .with_hugetlbfs(huge_pages.is_hugetlbfs())
                     .build()
                     .map_err(MemoryError::MmapRegionError)?;
 
@@ -249,7 +259,7 @@ impl GuestMemoryExtension for GuestMemoryMmap {
                     .collect::<Result<Vec<_>, std::io::Error>>()
                     .map_err(MemoryError::FileError)?;
 
-                Self::from_raw_regions_file(regions, track_dirty_pages, false)
+                Self::from_raw_regions_file(regions, track_dirty_pages, false, huge_pages)
             }
             None => {
                 let regions = state
@@ -357,6 +367,36 @@ impl GuestMemoryExtension for GuestMemoryMmap {
         write_result.map_err(MemoryError::WriteMemory)
     }
 
+    /// Dumps all contents of GuestMemoryMmap to a new memfd, sealed so that it never changes.
+    fn dump_to_sealed_memfd(&self) -> Result<File, MemoryError> {
+        let mem_file = memfd::MemfdOptions::default()
+            .allow_sealing(true)
+            .create("guest_mem_clone")
+            .map_err(MemoryError::Memfd)?;
+        let mem_size = self.iter().map(|region| region.len()).sum();
+        mem_file
+            .as_file()
+            .set_len(mem_size)
+            .map_err(MemoryError::MemfdSetLen)?;
+        let mut writer = mem_file
+            .as_file()
+            .try_clone()
+            .map_err(MemoryError::FileError)?;
+        self.dump(&mut writer)?;
+        drop(writer);
+
+        // The memfd is mapped privately, so the pages which were not written through a mapping
+        // are still read from it: no change may ever reach them.
+        let mut seals = memfd::SealsHashSet::new();
+        seals.insert(memfd::FileSeal::SealShrink);
+        seals.insert(memfd::FileSeal::SealGrow);
+        seals.insert(memfd::FileSeal::SealWrite);
+        seals.insert(memfd::FileSeal::SealSeal);
+        mem_file.add_seals(&seals).map_err(MemoryError::Memfd)?;
+
+        Ok(mem_file.into_file())
+    }
+
     /// Resets all the memory region bitmaps
     fn reset_dirty(&self) {
         self.iter().for_each(|region| {
@@ -423,7 +463,7 @@ mod tests {
     #![allow(clippy::undocumented_unsafe_blocks)]
 
     use std::collections::HashMap;
-    use std::io::{Read, Seek};
+    use std::io::{Read, Seek, Write};
 
     use utils::get_page_size;
     use utils::tempfile::TempFile;
@@ -501,8 +541,13 @@ mod tests {
 
         // Test that all regions are guarded.
         {
-            let guest_memory =
-                GuestMemoryMmap::from_raw_regions_file(regions.clone(), false, false).unwrap();
+            let guest_memory = GuestMemoryMmap::from_raw_regions_file(
+                regions.clone(),
+                false,
+                false,
+                HugePageConfig::None,
+            )
+            .unwrap();
             guest_memory.iter().for_each(|region| {
                 assert_eq!(region.size(), region_size);
                 assert!(region.file_offset().is_some());
@@ -512,8 +557,13 @@ mod tests {
 
         // Check dirty page tracking is off.
         {
-            let guest_memory =
-                GuestMemoryMmap::from_raw_regions_file(regions.clone(), false, false).unwrap();
+            let guest_memory = GuestMemoryMmap::from_raw_regions_file(
+                regions.clone(),
+                false,
+                false,
+                HugePageConfig::None,
+            )
+            .unwrap();
             guest_memory.iter().for_each(|region| {
                 assert!(region.bitmap().is_none());
             });
@@ -522,7 +572,8 @@ mod tests {
         // Check dirty page tracking is on.
         {
             let guest_memory =
-                GuestMemoryMmap::from_raw_regions_file(regions, true, false).unwrap();
+                GuestMemoryMmap::from_raw_regions_file(regions, true, false, HugePageConfig::None)
+                    .unwrap();
             guest_memory.iter().for_each(|region| {
                 assert!(region.bitmap().is_some());
             });
@@ -748,6 +799,67 @@ mod tests {
         assert_eq!(second_region, restored_region);
     }
 
+    #[test]
+    fn test_dump_to_sealed_memfd() {
+        let page_size = get_page_size().unwrap();
+
+        // Two regions of two pages each, with a one page gap between them.
+        let region_1_address = GuestAddress(0);
+        let region_2_address = GuestAddress(page_size as u64 * 3);
+        let region_size = page_size * 2;
+        let mem_regions = [
+            (region_1_address, region_size),
+            (region_2_address, region_size),
+        ];
+        let guest_memory =
+            GuestMemoryMmap::from_raw_regions(&mem_regions, false, HugePageConfig::None).unwrap();
+        guest_memory
+            .write(&vec![1u8; region_size], region_1_address)
+            .unwrap();
+        guest_memory
+            .write(&vec![2u8; region_size], region_2_address)
+            .unwrap();
+
+        let mut memfd = guest_memory.dump_to_sealed_memfd().unwrap();
+        assert_eq!(memfd.metadata().unwrap().len(), (region_size * 2) as u64);
+        memfd.write_all(&[0u8; 8]).unwrap_err();
+        memfd.set_len(0).unwrap_err();
+
+        // Map the memfd privately twice, as two clones of the microVM would.
+        let memory_state = guest_memory.describe();
+        let clone_1 =
+            GuestMemoryMmap::from_state(Some(&memfd), &memory_state, false, HugePageConfig::None)
+                .unwrap();
+        let clone_2 =
+            GuestMemoryMmap::from_state(Some(&memfd), &memory_state, false, HugePageConfig::None)
+                .unwrap();
+        let read_page = |memory: &GuestMemoryMmap, address| {
+            let mut page = vec![0u8; page_size];
+            memory.read(&mut page, address).unwrap();
+            page
+        };
+        assert_eq!(read_page(&clone_1, region_1_address), vec![1u8; page_size]);
+        assert_eq!(read_page(&clone_1, region_2_address), vec![2u8; page_size]);
+
+        // Writes in a clone are not seen by the source, nor by the other clone.
+        clone_1
+            .write(&vec![3u8; page_size], region_2_address)
+            .unwrap();
+        assert_eq!(read_page(&clone_1, region_2_address), vec![3u8; page_size]);
+        assert_eq!(
+            read_page(&guest_memory, region_2_address),
+            vec![2u8; page_size]
+        );
+        assert_eq!(read_page(&clone_2, region_2_address), vec![2u8; page_size]);
+
+        // Writes in the source are not seen by the clones either.
+        guest_memory
+            .write(&vec![4u8; page_size], region_1_address)
+            .unwrap();
+        assert_eq!(read_page(&clone_1, region_1_address), vec![1u8; page_size]);
+        assert_eq!(read_page(&clone_2, region_1_address), vec![1u8; page_size]);
+    }
+
     #[test]
     fn test_dump_dirty() {
         let page_size = get_page_size().unwrap();
@@ -313,7 +313,7 @@ mod tests {
         let (mut vm, vm_mem) = setup_vm(mem_size);
         let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
         vcpu.init(&[]).unwrap();
-        vm.setup_irqchip(1).unwrap();
+        vm.setup_irqchip(1, false).unwrap();
 
         (vm, vcpu, vm_mem)
     }
@@ -367,7 +367,7 @@ mod tests {
     fn test_init_vcpu() {
         let (mut vm, _vm_mem) = setup_vm(0x1000);
         let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
-        vm.setup_irqchip(1).unwrap();
+        vm.setup_irqchip(1, false).unwrap();
 
         // KVM_ARM_VCPU_PSCI_0_2 is set by default.
         // we check if we can remove it.
@@ -386,7 +386,7 @@ mod tests {
     fn test_vcpu_save_restore_state() {
         let (mut vm, _vm_mem) = setup_vm(0x1000);
         let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
-        vm.setup_irqchip(1).unwrap();
+        vm.setup_irqchip(1, false).unwrap();
 
         // Calling KVM_GET_REGLIST before KVM_VCPU_INIT will result in error.
         let res = vcpu.save_state();
@@ -430,7 +430,7 @@ mod tests {
         // https://elixir.bootlin.com/linux/v5.10.176/source/arch/arm64/kvm/arm.c#L1165
         let (mut vm, _vm_mem) = setup_vm(0x1000);
         let vcpu = KvmVcpu::new(0, &vm).unwrap();
-        vm.setup_irqchip(1).unwrap();
+        vm.setup_irqchip(1, false).unwrap();
 
         vcpu.dump_cpu_config().unwrap_err();
     }
@@ -440,7 +440,7 @@ mod tests {
         // Test `dump_cpu_config()` after `KVM_VCPU_INIT`.
         let (mut vm, _vm_mem) = setup_vm(0x1000);
         let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
-        vm.setup_irqchip(1).unwrap();
+        vm.setup_irqchip(1, false).unwrap();
         vcpu.init(&[]).unwrap();
 
         vcpu.dump_cpu_config().unwrap();
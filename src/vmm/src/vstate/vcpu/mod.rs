@@ -22,7 +22,7 @@ use utils::signal::{register_signal_handler, sigrtmin, Killable};
 use utils::sm::StateMachine;
 
 use crate::cpu_config::templates::{CpuConfiguration, GuestConfigError};
-use crate::logger::{IncMetric, METRICS};
+use crate::logger::{IncMetric, StoreMetric, METRICS};
 use crate::vstate::vm::Vm;
 use crate::FcExitCode;
 
@@ -41,6 +41,10 @@ pub use x86_64::{KvmVcpuError, *};
 /// Signal number (SIGRTMIN) used to kick Vcpus.
 pub const VCPU_RTSIG_OFFSET: i32 = 0;
 
+/// Number of consecutive immediate exits out of `KVM_RUN`, with no event to handle in between,
+/// after which a vCPU stops re-entering `KVM_RUN` and pauses itself until it is resumed.
+pub const MAX_IMMEDIATE_EXIT_STREAK: u32 = 1000;
+
 /// Errors associated with the wrappers over KVM ioctls.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum VcpuError {
@@ -97,6 +101,8 @@ pub struct Vcpu {
     response_receiver: Option<Receiver<VcpuResponse>>,
     /// The transmitting end of the responses channel owned by the vcpu side.
     response_sender: Sender<VcpuResponse>,
+    /// Number of consecutive immediate exits out of `KVM_RUN` with no event to handle.
+    immediate_exit_streak: u32,
 }
 
 impl Vcpu {
@@ -114,6 +120,16 @@ impl Vcpu {
             }
             cell.set(Some(self as *mut Vcpu));
             Ok(())
+        })?;
+        debug_assert!(self.is_thread_local());
+        Ok(())
+    }
+
+    /// Whether `self` is the `Vcpu` associated with the current thread.
+    fn is_thread_local(&self) -> bool {
+        Self::TLS_VCPU_PTR.with(|cell: &VcpuCell| {
+            cell.get()
+                .is_some_and(|vcpu_ptr| std::ptr::eq(vcpu_ptr, self))
         })
     }
 
@@ -134,7 +150,10 @@ impl Vcpu {
                 }
             }
             Err(VcpuError::VcpuTlsNotPresent)
-        })
+        })?;
+        // A kick signal received from now on must not reach this `Vcpu` anymore.
+        debug_assert!(!self.is_thread_local());
+        Ok(())
     }
 
     /// Runs `func` for the `Vcpu` associated with the current thread.
@@ -168,6 +187,8 @@ impl Vcpu {
     /// kick the vcpu running on the current thread, if there is one.
     pub fn register_kick_signal_handler() {
         extern "C" fn handle_signal(_: c_int, _: *mut siginfo_t, _: *mut c_void) {
+            // Incrementing the metric is a single atomic operation, which is async-signal-safe.
+            METRICS.vcpu.kicks.inc();
             // SAFETY: This is safe because it's temporarily aliasing the `Vcpu` object, but we are
             // only reading `vcpu.fd` which does not change for the lifetime of the `Vcpu`.
             unsafe {
@@ -200,6 +221,7 @@ impl Vcpu {
             event_sender: Some(event_sender),
             response_receiver: Some(response_receiver),
             response_sender,
+            immediate_exit_streak: 0,
             kvm_vcpu,
         })
     }
@@ -252,6 +274,14 @@ impl Vcpu {
             );
         }
 
+        // The kick signal handler reaches the vCPU through the TLS, so it must be associated with
+        // this thread before the vCPU can be kicked out of `KVM_RUN`.
+        debug_assert!(
+            self.is_thread_local(),
+            "vCPU {} run without being associated with its thread",
+            self.kvm_vcpu.index
+        );
+
         // Start running the machine state in the `Paused` state.
         StateMachine::run(self, Self::paused);
     }
@@ -263,7 +293,7 @@ impl Vcpu {
         loop {
             match self.run_emulation() {
                 // Emulation ran successfully, continue.
-                Ok(VcpuEmulation::Handled) => (),
+                Ok(VcpuEmulation::Handled) => self.immediate_exit_streak = 0,
                 // Emulation was interrupted, check external events.
                 Ok(VcpuEmulation::Interrupted) => break,
                 // If the guest was rebooted or halted:
@@ -275,57 +305,86 @@ impl Vcpu {
                 Err(_) => return self.exit(FcExitCode::GenericError),
             }
         }
+        METRICS.vcpu.immediate_exits.inc();
 
-        // By default don't change state.
-        let mut state = StateMachine::next(Self::running);
-
-        // Break this emulation loop on any transition request/external event.
-        match self.event_receiver.try_recv() {
-            // Running ---- Pause ----> Paused
-            Ok(VcpuEvent::Pause) => {
-                // Nothing special to do.
-                self.response_sender
-                    .send(VcpuResponse::Paused)
-                    .expect("vcpu channel unexpectedly closed");
+        // Handle all the events sent before the kick(s) which interrupted the emulation, before
+        // entering `KVM_RUN` again. A kick whose event was already handled by an earlier exit
+        // finds the channel empty.
+        let mut handled_event = false;
+        loop {
+            let event = match self.event_receiver.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => break,
+                // Unhandled exit of the other end.
+                Err(TryRecvError::Disconnected) => {
+                    // Move to 'exited' state.
+                    return self.exit(FcExitCode::GenericError);
+                }
+            };
+            handled_event = true;
+
+            match event {
+                // Running ---- Pause ----> Paused
+                VcpuEvent::Pause => {
+                    // Nothing special to do.
+                    self.response_sender
+                        .send(VcpuResponse::Paused)
+                        .expect("vcpu channel unexpectedly closed");
+
+                    // TODO: we should call `KVM_KVMCLOCK_CTRL` here to make sure
+                    // TODO continued: the guest soft lockup watchdog does not panic on Resume.
+
+                    // Move to 'paused' state, which handles the remaining events.
+                    self.immediate_exit_streak = 0;
+                    return StateMachine::next(Self::paused);
+                }
+                VcpuEvent::Resume => {
+                    self.response_sender
+                        .send(VcpuResponse::Resumed)
+                        .expect("vcpu channel unexpectedly closed");
+                }
+                // SaveState cannot be performed on a running Vcpu.
+                VcpuEvent::SaveState => {
+                    self.response_sender
+                        .send(VcpuResponse::NotAllowed(String::from(
+                            "save/restore unavailable while running",
+                        )))
+                        .expect("vcpu channel unexpectedly closed");
+                }
+                // DumpCpuConfig cannot be performed on a running Vcpu.
+                VcpuEvent::DumpCpuConfig => {
+                    self.response_sender
+                        .send(VcpuResponse::NotAllowed(String::from(
+                            "cpu config dump is unavailable while running",
+                        )))
+                        .expect("vcpu channel unexpectedly closed");
+                }
+                VcpuEvent::Finish => return StateMachine::finish(),
+            }
+        }
 
-                // TODO: we should call `KVM_KVMCLOCK_CTRL` here to make sure
-                // TODO continued: the guest soft lockup watchdog does not panic on Resume.
+        if handled_event {
+            self.immediate_exit_streak = 0;
+            return StateMachine::next(Self::running);
+        }
 
-                // Move to 'paused' state.
-                state = StateMachine::next(Self::paused);
-            }
-            Ok(VcpuEvent::Resume) => {
-                self.response_sender
-                    .send(VcpuResponse::Resumed)
-                    .expect("vcpu channel unexpectedly closed");
-            }
-            // SaveState cannot be performed on a running Vcpu.
-            Ok(VcpuEvent::SaveState) => {
-                self.response_sender
-                    .send(VcpuResponse::NotAllowed(String::from(
-                        "save/restore unavailable while running",
-                    )))
-                    .expect("vcpu channel unexpectedly closed");
-            }
-            // DumpCpuConfig cannot be performed on a running Vcpu.
-            Ok(VcpuEvent::DumpCpuConfig) => {
-                self.response_sender
-                    .send(VcpuResponse::NotAllowed(String::from(
-                        "cpu config dump is unavailable while running",
-                    )))
-                    .expect("vcpu channel unexpectedly closed");
-            }
-            Ok(VcpuEvent::Finish) => return StateMachine::finish(),
-            // Unhandled exit of the other end.
-            Err(TryRecvError::Disconnected) => {
-                // Move to 'exited' state.
-                state = self.exit(FcExitCode::GenericError);
-            }
-            // All other events or lack thereof have no effect on current 'running' state.
-            Err(TryRecvError::Empty) => (),
+        // Nothing kicked the vCPU on purpose. Do not let it spin in and out of `KVM_RUN` forever.
+        self.immediate_exit_streak += 1;
+        let streak = u64::from(self.immediate_exit_streak);
+        if streak > METRICS.vcpu.immediate_exit_max_streak.fetch() {
+            METRICS.vcpu.immediate_exit_max_streak.store(streak);
+        }
+        if self.immediate_exit_streak >= MAX_IMMEDIATE_EXIT_STREAK {
+            warn!(
+                "vCPU {} exited KVM_RUN immediately {} times in a row without any event to \
+                 handle. Pausing it until it is resumed.",
+                self.kvm_vcpu.index, self.immediate_exit_streak
+            );
+            self.immediate_exit_streak = 0;
+            return StateMachine::next(Self::paused);
         }
 
-        state
+        StateMachine::next(Self::running)
     }
 
     // This is the main loop of the `Paused` state.
@@ -859,7 +918,7 @@ pub mod tests {
         let vcpu = {
             let mut vcpu = Vcpu::new(1, &vm, exit_evt).unwrap();
             vcpu.kvm_vcpu.init(&[]).unwrap();
-            vm.setup_irqchip(1).unwrap();
+            vm.setup_irqchip(1, false).unwrap();
             vcpu
         };
         #[cfg(target_arch = "x86_64")]
@@ -1081,6 +1140,109 @@ pub mod tests {
         )
     }
 
+    #[test]
+    fn test_immediate_exit_streak() {
+        let (_vm, mut vcpu, _) = setup_vcpu(0x1000);
+        let running = format!("{:?}", StateMachine::<Vcpu>::next(Vcpu::running));
+        let paused = format!("{:?}", StateMachine::<Vcpu>::next(Vcpu::paused));
+
+        // The vCPU enters `KVM_RUN` again after immediate exits with no event to handle...
+        for streak in 1..MAX_IMMEDIATE_EXIT_STREAK {
+            vcpu.kvm_vcpu.fd.set_kvm_immediate_exit(1);
+            assert_eq!(format!("{:?}", vcpu.running()), running);
+            assert_eq!(vcpu.immediate_exit_streak, streak);
+        }
+        // ...until there are too many of them in a row.
+        vcpu.kvm_vcpu.fd.set_kvm_immediate_exit(1);
+        assert_eq!(format!("{:?}", vcpu.running()), paused);
+        assert_eq!(vcpu.immediate_exit_streak, 0);
+        assert!(
+            METRICS.vcpu.immediate_exit_max_streak.fetch() >= u64::from(MAX_IMMEDIATE_EXIT_STREAK)
+        );
+
+        // Handling an event resets the streak.
+        vcpu.kvm_vcpu.fd.set_kvm_immediate_exit(1);
+        let _ = vcpu.running();
+        assert_eq!(vcpu.immediate_exit_streak, 1);
+        let event_sender = vcpu.event_sender.take().unwrap();
+        let response_receiver = vcpu.response_receiver.take().unwrap();
+        event_sender.send(VcpuEvent::Resume).unwrap();
+        vcpu.kvm_vcpu.fd.set_kvm_immediate_exit(1);
+        assert_eq!(format!("{:?}", vcpu.running()), running);
+        assert_eq!(vcpu.immediate_exit_streak, 0);
+        assert_eq!(response_receiver.try_recv().unwrap(), VcpuResponse::Resumed);
+    }
+
+    #[test]
+    fn test_vcpu_event_bursts() {
+        let (vcpu_handle, vcpu_exit_evt) = vcpu_configured_for_boot();
+
+        // Send bursts of events before reading their responses: the vCPU handles all of them, in
+        // order, whether it is running or paused when they arrive.
+        let events = [
+            (VcpuEvent::Resume, VcpuResponse::Resumed),
+            (VcpuEvent::Resume, VcpuResponse::Resumed),
+            (VcpuEvent::Pause, VcpuResponse::Paused),
+            (VcpuEvent::Resume, VcpuResponse::Resumed),
+            (
+                VcpuEvent::SaveState,
+                VcpuResponse::NotAllowed(String::new()),
+            ),
+            (VcpuEvent::Pause, VcpuResponse::Paused),
+            (VcpuEvent::Pause, VcpuResponse::Paused),
+        ];
+        for _ in 0..100 {
+            for (event, _) in events.iter() {
+                vcpu_handle.send_event(event.clone()).unwrap();
+            }
+            for (_, response) in events.iter() {
+                assert_eq!(
+                    &vcpu_handle
+                        .response_receiver()
+                        .recv_timeout(RECV_TIMEOUT_SEC)
+                        .expect("did not receive event response from vcpu"),
+                    response
+                );
+            }
+        }
+
+        // Validate the vcpu did not exit.
+        let err = vcpu_exit_evt.read().unwrap_err();
+        assert_eq!(err.raw_os_error().unwrap(), libc::EAGAIN);
+
+        vcpu_handle.send_event(VcpuEvent::Finish).unwrap();
+    }
+
+    #[test]
+    fn test_vcpu_spurious_kicks() {
+        let (vcpu_handle, vcpu_exit_evt) = vcpu_configured_for_boot();
+        queue_event_expect_response(&vcpu_handle, VcpuEvent::Resume, VcpuResponse::Resumed);
+
+        // Kick the running vcpu without sending it any event.
+        let kicks = METRICS.vcpu.kicks.count();
+        let immediate_exits = METRICS.vcpu.immediate_exits.count();
+        for _ in 0..100 {
+            vcpu_handle
+                .vcpu_thread
+                .as_ref()
+                .unwrap()
+                .kill(sigrtmin() + VCPU_RTSIG_OFFSET)
+                .unwrap();
+        }
+
+        // The kicks do not starve the events sent after them.
+        queue_event_expect_response(&vcpu_handle, VcpuEvent::Pause, VcpuResponse::Paused);
+        assert!(METRICS.vcpu.kicks.count() >= kicks + 101);
+        assert!(METRICS.vcpu.immediate_exits.count() > immediate_exits);
+        queue_event_expect_response(&vcpu_handle, VcpuEvent::Resume, VcpuResponse::Resumed);
+
+        // Validate the vcpu did not exit.
+        let err = vcpu_exit_evt.read().unwrap_err();
+        assert_eq!(err.raw_os_error().unwrap(), libc::EAGAIN);
+
+        vcpu_handle.send_event(VcpuEvent::Finish).unwrap();
+    }
+
     #[test]
     fn test_vcpu_pause_resume() {
         let (vcpu_handle, vcpu_exit_evt) = vcpu_configured_for_boot();
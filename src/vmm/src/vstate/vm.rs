@@ -271,10 +271,10 @@ impl Vm {
         kvm_bindings::KVM_CAP_ONE_REG,
     ];
 
-    /// Creates the GIC (Global Interrupt Controller).
-    pub fn setup_irqchip(&mut self, vcpu_count: u8) -> Result<(), VmError> {
+    /// Creates the GIC (Global Interrupt Controller), with an ITS if `its` is set.
+    pub fn setup_irqchip(&mut self, vcpu_count: u8, its: bool) -> Result<(), VmError> {
         self.irqchip_handle = Some(
-            crate::arch::aarch64::gic::create_gic(&self.fd, vcpu_count.into(), None)
+            crate::arch::aarch64::gic::create_gic(&self.fd, vcpu_count.into(), None, its)
                 .map_err(VmError::VmCreateGIC)?,
         );
         Ok(())
@@ -11,7 +11,7 @@ use libc::EFD_NONBLOCK;
 use utils::eventfd::EventFd;
 use vm_superio::Serial;
 use vmm::devices::legacy::serial::SerialOut;
-use vmm::devices::legacy::{EventFdTrigger, SerialEventsWrapper, SerialWrapper};
+use vmm::devices::legacy::{EventFdTrigger, SerialEventsWrapper, SerialPortMetrics, SerialWrapper};
 
 fn create_serial(
     pipe: c_int,
@@ -25,8 +25,9 @@ fn create_serial(
             EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).unwrap()),
             SerialEventsWrapper {
                 buffer_ready_event_fd: Some(kick_stdin_evt.try_clone().unwrap()),
+                metrics: SerialPortMetrics::default(),
             },
-            SerialOut::Stdout(std::io::stdout()),
+            SerialOut::Stdout(std::io::stdout()).into(),
         ),
         input: Some(Box::new(serial_in)),
     }))
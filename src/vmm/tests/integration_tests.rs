@@ -252,6 +252,7 @@ fn verify_load_snapshot(snapshot_file: TempFile, memory_file: TempFile) {
         None,
         &empty_seccomp_filters,
         vm_resources,
+        None,
     )
     .unwrap();
     // For now we're happy we got this far, we don't test what the guest is actually doing.
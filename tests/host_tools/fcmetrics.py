@@ -72,6 +72,26 @@ def validate_fc_metrics(metrics):
         "max_us",
         "sum_us",
     ]
+    histogram_metrics_fields = [
+        "le_1",
+        "le_4",
+        "le_16",
+        "le_64",
+        "le_256",
+        "le_1024",
+        "gt_1024",
+    ]
+    log2_latency_histogram_metrics_fields = [
+        "count",
+        "min_us",
+        "max_us",
+        "p50_us",
+        "p90_us",
+        "p99_us",
+        {
+            "buckets": [f"le_{2**i}us" for i in range(21)] + ["gt_1048576us"],
+        },
+    ]
     block_metrics = [
         "activate_fails",
         "cfg_fails",
@@ -80,38 +100,70 @@ def validate_fc_metrics(metrics):
         "execute_fails",
         "invalid_reqs_count",
         "flush_count",
+        "flush_skipped_count",
         "queue_event_count",
+        "queue_pops",
+        "queue_empty_pops",
+        "queue_used",
+        "queue_notified",
+        "queue_suppressed",
         "rate_limiter_event_count",
         "update_count",
         "update_fails",
+        "capacity_change_count",
         "read_bytes",
         "write_bytes",
+        "unmapped_bytes",
         "read_count",
         "write_count",
+        "discard_count",
+        "write_zeroes_count",
+        "read_only_fails",
+        "host_enospc_count",
+        "host_enospc_retries",
+        "host_enospc_stalled_events",
+        "bounce_buffer_count",
         "rate_limiter_throttled_events",
         "io_engine_throttled_events",
         "remaining_reqs_count",
         {"read_agg": latency_agg_metrics_fields},
         {"write_agg": latency_agg_metrics_fields},
+        {"read_latency": log2_latency_histogram_metrics_fields},
+        {"write_latency": log2_latency_histogram_metrics_fields},
+        {"flush_latency": log2_latency_histogram_metrics_fields},
+        {"requests_per_pass": histogram_metrics_fields},
+        {"passes_per_kick": histogram_metrics_fields},
+        {"submissions_per_enter": histogram_metrics_fields},
     ]
     net_metrics = [
         "activate_fails",
         "cfg_fails",
         "mac_address_updates",
+        "rate_limiter_updates",
         "no_rx_avail_buffer",
         "no_tx_avail_buffer",
         "event_fails",
         "rx_queue_event_count",
+        "rx_queue_pops",
+        "rx_queue_empty_pops",
+        "rx_queue_used",
+        "rx_queue_notified",
+        "rx_queue_suppressed",
         "rx_event_rate_limiter_count",
         "rx_partial_writes",
         "rx_rate_limiter_throttled",
+        "rx_filtered_frames",
         "rx_tap_event_count",
         "rx_bytes_count",
         "rx_packets_count",
         "rx_fails",
         "rx_count",
+        "tap_mtu_too_small",
         "tap_read_fails",
+        "tap_read_fatal",
+        "tap_read_unknown_fails",
         "tap_write_fails",
+        "tap_write_deferred",
         "tx_bytes_count",
         "tx_malformed_frames",
         "tx_fails",
@@ -119,8 +171,14 @@ def validate_fc_metrics(metrics):
         "tx_packets_count",
         "tx_partial_reads",
         "tx_queue_event_count",
+        "tx_queue_pops",
+        "tx_queue_empty_pops",
+        "tx_queue_used",
+        "tx_queue_notified",
+        "tx_queue_suppressed",
         "tx_rate_limiter_event_count",
         "tx_rate_limiter_throttled",
+        "tx_backpressure",
         "tx_spoofed_mac_count",
         "tx_remaining_reqs_count",
         {"tap_write_agg": latency_agg_metrics_fields},
@@ -139,6 +197,7 @@ def validate_fc_metrics(metrics):
             "stats_updates_count",
             "stats_update_fails",
             "deflate_count",
+            "hugepages_released",
             "event_fails",
         ],
         "block": block_metrics,
@@ -146,6 +205,11 @@ def validate_fc_metrics(metrics):
             "deprecated_http_api_calls",
             "deprecated_cmd_line_api_calls",
         ],
+        "device_errors": [
+            "transient",
+            "degraded",
+            "fatal",
+        ],
         "get_api_requests": [
             "instance_info_count",
             "machine_cfg_count",
@@ -166,11 +230,13 @@ def validate_fc_metrics(metrics):
             "load_snapshot",
             "pause_vm",
             "resume_vm",
+            "clone_microvm",
             "vmm_full_create_snapshot",
             "vmm_diff_create_snapshot",
             "vmm_load_snapshot",
             "vmm_pause_vm",
             "vmm_resume_vm",
+            "vmm_clone_microvm",
         ],
         "logger": [
             "missed_metrics_count",
@@ -233,6 +299,9 @@ def validate_fc_metrics(metrics):
             "exit_mmio_read",
             "exit_mmio_write",
             "failures",
+            "kicks",
+            "immediate_exits",
+            "immediate_exit_max_streak",
             {"exit_io_in_agg": latency_agg_metrics_fields},
             {"exit_io_out_agg": latency_agg_metrics_fields},
             {"exit_mmio_read_agg": latency_agg_metrics_fields},
@@ -241,6 +310,9 @@ def validate_fc_metrics(metrics):
         "vmm": [
             "device_events",
             "panic_count",
+            "auto_pauses",
+            "auto_resumes",
+            "auto_pause_fails",
         ],
         "uart": [
             "error_count",
@@ -249,6 +321,7 @@ def validate_fc_metrics(metrics):
             "missed_write_count",
             "read_count",
             "write_count",
+            "console_pattern_matches",
         ],
         "signals": [
             "sigbus",
@@ -280,6 +353,13 @@ def validate_fc_metrics(metrics):
             "tx_flush_fails",
             "tx_write_fails",
             "rx_read_fails",
+            "dgram_rx_packets_count",
+            "dgram_tx_packets_count",
+            "dgram_rx_drops",
+            "dgram_tx_drops",
+            "credit_stalls",
+            "rst_sent",
+            "conn_table_full",
         ],
         "entropy": [
             "activate_fails",
@@ -325,6 +405,40 @@ def validate_fc_metrics(metrics):
             firecracker_metrics[metrics_name] = block_metrics
         if metrics_name.startswith("net_"):
             firecracker_metrics[metrics_name] = net_metrics
+        if metrics_name.startswith("uart_"):
+            firecracker_metrics[metrics_name] = firecracker_metrics["uart"]
+
+    # add the depth of the queues of every virtio device to the schema
+    virtio_queue_devices = {}
+    for device_name, queues in metrics["virtio_queues"]["devices"].items():
+        virtio_queue_devices[device_name] = {}
+        for queue_index, queue_metrics in queues.items():
+            queue_fields = ["pending", "pending_hwm"]
+            if "unconsumed" in queue_metrics:
+                queue_fields.append("unconsumed")
+            virtio_queue_devices[device_name][queue_index] = queue_fields
+    firecracker_metrics["virtio_queues"] = [
+        "skipped",
+        "invalid_chains",
+        {"devices": virtio_queue_devices},
+    ]
+
+    # add the latencies of every API endpoint which served requests to the schema
+    latency_histogram_metrics_fields = [
+        "le_100us",
+        "le_1ms",
+        "le_10ms",
+        "le_100ms",
+        "le_1s",
+        "gt_1s",
+    ]
+    api_endpoints = {}
+    for endpoint in metrics["api_server"]["endpoints"]:
+        api_endpoints[endpoint] = {
+            phase: latency_histogram_metrics_fields
+            for phase in ["total", "parse", "vmm_action", "serialize"]
+        }
+    firecracker_metrics["api_server"].append({"endpoints": api_endpoints})
 
     firecracker_metrics_schema = create_metrics_schema_objects(firecracker_metrics)
 
@@ -361,6 +475,15 @@ def validate_fc_metrics(metrics):
     validate_missing_metrics(metrics)
 
 
+def sum_metrics(total, metrics):
+    """Adds the (possibly nested) counters of `metrics` to `total`"""
+    if total is None:
+        return metrics
+    if isinstance(metrics, dict):
+        return {name: sum_metrics(total[name], value) for name, value in metrics.items()}
+    return total + metrics
+
+
 class FcDeviceMetrics:
     """
     Provides functions to validate breaking change and
@@ -402,6 +525,13 @@ class FcDeviceMetrics:
                         if metrics_name not in metrics_calculated:
                             metrics_calculated[metrics_name] = 0
                         metrics_calculated[metrics_name] += metric_value
+                    elif isinstance(metric_value, dict) and "sum_us" not in metric_value:
+                        # histograms are aggregated bucket by bucket. The summaries of the
+                        # latency histograms can only be summed while they are empty, i.e.
+                        # with the default metrics verbosity.
+                        metrics_calculated[metrics_name] = sum_metrics(
+                            metrics_calculated.get(metrics_name), metric_value
+                        )
                     elif isinstance(metric_value, dict):
                         # this is for LatencyAggregateMetrics metrics type
                         if metrics_name not in metrics_calculated:
@@ -484,6 +614,9 @@ def flush_fc_metrics_to_cw(fc_metrics, metrics):
             walk_key(group, keys)
             if group in metrics_to_export_once:
                 skip.add(group)
+        elif group == "api_server":
+            # the latencies of the API endpoints are reset on flush
+            walk_key("api_server.endpoints", keys["endpoints"])
     metrics.flush()
 
 
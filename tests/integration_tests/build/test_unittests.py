@@ -26,6 +26,17 @@ def test_unittests(test_fc_session_root_path):
     host.cargo_test(test_fc_session_root_path, extra_args=extra_args + " --examples")
 
 
+@pytest.mark.timeout(600)
+def test_unittests_memory_guards(test_fc_session_root_path):
+    """
+    Run the unit tests with guard pages around the guest memory of the tests, and
+    canaries around the guest memory written by the devices.
+    """
+
+    extra_args = f"--target {TARGET} --features vmm/memory-guards"
+    host.cargo_test(test_fc_session_root_path, extra_args=extra_args)
+
+
 def test_benchmarks_compile():
     """Checks that all benchmarks compile"""
 
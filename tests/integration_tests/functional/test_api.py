@@ -176,6 +176,8 @@ def test_api_put_update_pre_boot(uvm_plain, io_engine):
 
     mem_size_mib = microvm_config_json["mem_size_mib"]
     assert response_json["mem_size_mib"] == mem_size_mib
+    memory_regions = response_json["memory_regions"]
+    assert sum(region["size"] for region in memory_regions) == mem_size_mib << 20
 
     if platform.machine() == "x86_64":
         cpu_template = str(microvm_config_json["cpu_template"])
@@ -509,14 +511,14 @@ def test_api_put_update_post_boot(uvm_plain, io_engine):
     with pytest.raises(RuntimeError, match=NOT_SUPPORTED_AFTER_START):
         test_microvm.api.machine_config.put(vcpu_count=4, mem_size_mib=128)
 
-    # Network interface update is not allowed after boot.
-    with pytest.raises(RuntimeError, match=NOT_SUPPORTED_AFTER_START):
+    # Network interfaces can only be hot-plugged after boot, not replaced.
+    with pytest.raises(RuntimeError, match="The network interface ID is already in use: 1"):
         test_microvm.api.network.put(
             iface_id="1", host_dev_name=tap1.name, guest_mac="06:00:00:00:00:02"
         )
 
-    # Block device update is not allowed after boot.
-    with pytest.raises(RuntimeError, match=NOT_SUPPORTED_AFTER_START):
+    # Block devices can only be hot-plugged after boot, and not as root device.
+    with pytest.raises(RuntimeError, match="A root block device can't be hot-plugged."):
         test_microvm.api.drive.put(
             drive_id="rootfs",
             path_on_host=test_microvm.jailer.jailed_path(test_microvm.rootfs_file),
@@ -688,9 +690,15 @@ def test_api_patch_pre_boot(uvm_plain, io_engine):
     with pytest.raises(RuntimeError, match=NOT_SUPPORTED_BEFORE_START):
         test_microvm.api.drive.patch(drive_id=drive_id, path_on_host="foo.bar")
 
-    # Patching net before boot is not allowed.
-    with pytest.raises(RuntimeError, match=NOT_SUPPORTED_BEFORE_START):
-        test_microvm.api.network.patch(iface_id=iface_id)
+    # Patching net before boot is allowed, e.g. to update the guest MAC address.
+    test_microvm.api.network.patch(iface_id=iface_id, guest_mac="06:00:00:00:00:02")
+    ifaces = test_microvm.api.vm_config.get().json()["network-interfaces"]
+    [iface] = [iface for iface in ifaces if iface["iface_id"] == iface_id]
+    assert iface["guest_mac"] == "06:00:00:00:00:02"
+
+    # But only for the existing interfaces.
+    with pytest.raises(RuntimeError, match="Invalid network interface ID"):
+        test_microvm.api.network.patch(iface_id="2", guest_mac="06:00:00:00:00:03")
 
 
 def test_negative_api_patch_post_boot(uvm_plain, io_engine):
@@ -1176,7 +1184,9 @@ def test_get_full_config_after_restoring_snapshot(microvm_factory, uvm_nano):
     # We expect boot-source to be set with the following values
     expected_cfg["boot-source"] = {
         "kernel_image_path": uvm_nano.get_jailed_resource(uvm_nano.kernel_file),
+        "kernel_fd": None,
         "initrd_path": None,
+        "initrd_fd": None,
         "boot_args": None,
     }
 
@@ -1190,6 +1200,9 @@ def test_get_full_config_after_restoring_snapshot(microvm_factory, uvm_nano):
     # We should expect a null entropy device
     expected_cfg["entropy"] = None
 
+    # We should expect a null console scanner
+    expected_cfg["console-scanner"] = None
+
     # Validate full vm configuration post-restore.
     response = uvm2.api.vm_config.get().json()
     assert response != setup_cfg
@@ -1218,7 +1231,9 @@ def test_get_full_config(uvm_plain):
     expected_cfg["boot-source"] = {
         "boot_args": "",
         "kernel_image_path": f"/{test_microvm.kernel_file.name}",
+        "kernel_fd": None,
         "initrd_path": None,
+        "initrd_fd": None,
     }
     expected_cfg["drives"] = [
         {
@@ -1290,6 +1305,9 @@ def test_get_full_config(uvm_plain):
     # We should expect a null entropy device
     expected_cfg["entropy"] = None
 
+    # We should expect a null console scanner
+    expected_cfg["console-scanner"] = None
+
     # Getting full vm configuration should be available pre-boot.
     response = test_microvm.api.vm_config.get()
     assert response.json() == expected_cfg
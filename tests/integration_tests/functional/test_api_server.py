@@ -28,3 +28,26 @@ def test_api_socket_in_use(uvm_plain):
     microvm.check_log_message(msg)
 
     microvm.mark_killed()
+
+
+def test_single_thread_api(uvm_plain):
+    """
+    Test serving the API on the VMM thread.
+
+    Boot, pause, resume and snapshot the microVM with `--single-thread-api`.
+    """
+    microvm = uvm_plain
+    microvm.jailer.extra_args.update({"single-thread-api": None})
+    microvm.spawn()
+    microvm.basic_config()
+    microvm.add_net_iface()
+    microvm.start()
+    microvm.wait_for_up()
+
+    microvm.pause()
+    microvm.resume()
+    microvm.wait_for_up()
+
+    microvm.snapshot_full()
+    microvm.resume()
+    microvm.wait_for_up()
@@ -6,6 +6,8 @@ import os
 import shutil
 from pathlib import Path
 
+import pytest
+
 import host_tools.drive as drive_tools
 from framework.utils_drive import partuuid_and_disk_path
 from host_tools.fcmetrics import FcDeviceMetrics
@@ -130,6 +132,40 @@ def test_vhost_user_block_disconnect(microvm_factory, guest_kernel, rootfs_ubunt
     _config = vm.api.vm_config.get().json()
 
 
+def test_vhost_user_block_snapshot(microvm_factory, guest_kernel, rootfs_ubuntu_22):
+    """
+    Test that snapshotting a VM with a vhost-user-block device fails cleanly.
+    """
+
+    vm = microvm_factory.build(guest_kernel, None, monitor_memory=False)
+
+    # We need to set up ssh keys manually because we did not specify rootfs
+    # in microvm_factory.build method
+    ssh_key = rootfs_ubuntu_22.with_suffix(".id_rsa")
+    vm.ssh_key = ssh_key
+    vm.spawn()
+    vm.basic_config(add_root_device=False)
+    vm.add_vhost_user_drive(
+        "rootfs", rootfs_ubuntu_22, is_root_device=True, is_read_only=True
+    )
+    vm.add_net_iface()
+    vm.start()
+    vm.wait_for_up()
+
+    vm.api.vm.patch(state="Paused")
+    expected_msg = "Snapshots are not supported on uVMs with vhost-user drives."
+    with pytest.raises(RuntimeError, match=expected_msg):
+        vm.api.snapshot_create.put(
+            mem_file_path="memfile", snapshot_path="statefile", snapshot_type="Full"
+        )
+    assert not (Path(vm.chroot()) / "statefile").exists()
+    assert not (Path(vm.chroot()) / "memfile").exists()
+
+    # The microVM keeps working after the failed attempt.
+    vm.api.vm.patch(state="Resumed")
+    vm.ssh.check_output("true")
+
+
 def test_device_ordering(microvm_factory, guest_kernel, rootfs_ubuntu_22):
     """
     Verify device ordering.
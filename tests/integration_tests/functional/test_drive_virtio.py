@@ -56,12 +56,25 @@ def test_rescan_file(uvm_plain_any, io_engine):
     assert "dd: error reading '/dev/vdb': Input/output error" in stderr
     _check_file_size(test_microvm.ssh, f"{block_copy_name}", truncated_size * MB)
 
+    # Shrinking the disk is rejected, since the guest may be using all of it.
+    with pytest.raises(RuntimeError, match="would shrink the disk"):
+        test_microvm.api.drive.patch(
+            drive_id="scratch",
+            path_on_host=test_microvm.create_jailed_resource(fs.path),
+        )
+
+    _check_block_size(test_microvm.ssh, "/dev/vdb", block_size * MB)
+
+    # Growing it is notified to the guest.
+    grown_size = block_size * 2
+    utils.check_output(f"truncate --size {grown_size}M {fs.path}")
     test_microvm.api.drive.patch(
         drive_id="scratch",
         path_on_host=test_microvm.create_jailed_resource(fs.path),
     )
 
     _check_block_size(test_microvm.ssh, "/dev/vdb", fs.size())
+    assert fs.size() == grown_size * MB
 
 
 def test_device_ordering(uvm_plain_any, io_engine):
@@ -292,6 +305,41 @@ def test_patch_drive(uvm_plain_any, io_engine):
     assert lines[1].strip() == size_bytes_str
 
 
+def test_patch_drive_read_only(uvm_plain_any, io_engine):
+    """
+    Test making a drive read-only while the guest writes to it.
+    """
+    test_microvm = uvm_plain_any
+    test_microvm.spawn()
+    test_microvm.basic_config()
+    test_microvm.add_net_iface()
+
+    fs = drive_tools.FilesystemFile(os.path.join(test_microvm.fsfiles, "scratch"))
+    test_microvm.add_drive("scratch", fs.path, io_engine=io_engine)
+    test_microvm.start()
+
+    # Keep writing to the drive until the writes fail.
+    write_cmd = "dd if=/dev/urandom of=/dev/vdb bs=4k count=64 oflag=direct"
+    test_microvm.ssh.check_output(
+        f"nohup sh -c 'while {write_cmd}; do :; done' >/dev/null 2>&1 &"
+    )
+    test_microvm.api.drive.patch(drive_id="scratch", is_read_only=True)
+
+    # The new writes fail, while the reads keep succeeding.
+    ecode, _, _ = test_microvm.ssh.run(write_cmd)
+    assert ecode != 0
+    test_microvm.ssh.check_output(
+        "dd if=/dev/vdb of=/dev/null bs=4k count=64 iflag=direct"
+    )
+    fc_metrics = test_microvm.flush_metrics()
+    assert fc_metrics["block_scratch"]["read_only_fails"] > 0
+
+    # The drive can't be made writable again.
+    with pytest.raises(RuntimeError, match="can't be made writable"):
+        test_microvm.api.drive.patch(drive_id="scratch", is_read_only=False)
+    assert test_microvm.api.vm_config.get().json()["drives"][1]["is_read_only"]
+
+
 def test_no_flush(uvm_plain_any, io_engine):
     """
     Verify default block ignores flush.
@@ -356,6 +404,45 @@ def test_flush(uvm_plain_rw, io_engine):
     assert fc_metrics["block"]["flush_count"] > 0
 
 
+def test_drive_hotplug(uvm_plain_any, microvm_factory, io_engine):
+    """
+    Hot-plugs a drive in a running microVM, and snapshots it.
+    """
+    test_microvm = uvm_plain_any
+    test_microvm.spawn()
+    test_microvm.basic_config()
+    test_microvm.api.machine_config.patch(hotplug_slots=1)
+    test_microvm.add_net_iface()
+    test_microvm.start()
+
+    # The reserved slot is not bound to a driver until the guest re-probes it.
+    _, stdout, _ = test_microvm.ssh.check_output("ls /sys/block")
+    assert "vdb" not in stdout.split()
+
+    fs = drive_tools.FilesystemFile(os.path.join(test_microvm.fsfiles, "scratch"))
+    test_microvm.add_drive("scratch", fs.path, io_engine=io_engine)
+    test_microvm.ssh.check_output(
+        "cd /sys/bus/platform/devices; for dev in *; do"
+        ' [ -e "$dev/driver" ] ||'
+        ' echo "$dev" > /sys/bus/platform/drivers/virtio-mmio/bind 2>/dev/null;'
+        " done; true"
+    )
+    _check_block_size(test_microvm.ssh, "/dev/vdb", fs.size())
+    _check_mount(test_microvm.ssh, "/dev/vdb")
+
+    # The only slot is taken now.
+    with pytest.raises(RuntimeError, match="No free slot"):
+        test_microvm.add_drive("scratch2", fs.path, io_engine=io_engine)
+
+    # The hot-plugged drive is part of the snapshot.
+    snapshot = test_microvm.snapshot_full()
+    restored_vm = microvm_factory.build()
+    restored_vm.spawn()
+    restored_vm.restore_from_snapshot(snapshot, resume=True)
+    _check_block_size(restored_vm.ssh, "/dev/vdb", fs.size())
+    _check_mount(restored_vm.ssh, "/dev/vdb")
+
+
 def _check_block_size(ssh_connection, dev_path, size):
     _, stdout, stderr = ssh_connection.run("blockdev --getsize64 {}".format(dev_path))
     assert stderr == ""
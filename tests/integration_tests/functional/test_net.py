@@ -78,3 +78,40 @@ def test_multi_queue_unsupported(uvm_plain):
             host_dev_name=tapname,
             guest_mac="AA:FC:00:00:00:01",
         )
+
+
+def test_net_hotplug(uvm_plain_any):
+    """
+    Hot-plugs a network interface in a running microVM.
+    """
+    test_microvm = uvm_plain_any
+    test_microvm.spawn()
+    test_microvm.basic_config()
+    test_microvm.api.machine_config.patch(hotplug_slots=1)
+    test_microvm.add_net_iface()
+    test_microvm.start()
+
+    # The reserved slot is not bound to a driver until the guest re-probes it.
+    _, stdout, _ = test_microvm.ssh.check_output("ls /sys/class/net")
+    assert "eth1" not in stdout.split()
+
+    iface = test_microvm.add_net_iface()
+    test_microvm.ssh.check_output(
+        "cd /sys/bus/platform/devices; for dev in *; do"
+        ' [ -e "$dev/driver" ] ||'
+        ' echo "$dev" > /sys/bus/platform/drivers/virtio-mmio/bind 2>/dev/null;'
+        " done; true"
+    )
+    _, stdout, _ = test_microvm.ssh.check_output("ls /sys/class/net")
+    assert "eth1" in stdout.split()
+
+    # The new interface carries traffic.
+    test_microvm.ssh.check_output(
+        f"ip addr add {iface.guest_ip}/{iface.netmask_len} dev eth1"
+        " && ip link set eth1 up"
+    )
+    test_microvm.ssh.check_output(f"ping -c 3 -W 1 -I eth1 {iface.host_ip}")
+
+    # The only slot is taken now.
+    with pytest.raises(RuntimeError, match="No free slot"):
+        test_microvm.add_net_iface()
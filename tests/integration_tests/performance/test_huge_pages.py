@@ -19,8 +19,8 @@ def check_hugetlbfs_in_use(pid: int, allocation_name: str):
     `allocation_name` should be the name of the smaps entry for which we want to verify that huge pages are used.
     For memfd-backed guest memory, this would be "memfd:guest_mem" (the `guest_mem` part originating from the name
     we give the memfd in memory.rs), for anonymous memory this would be "/anon_hugepage".
-    Note: in our testing, we do not currently configure vhost-user-blk devices, so we only exercise
-    the "/anon_hugepage" case.
+    Note: guest memory is only memfd-backed in our testing when a balloon device is configured, as we
+    do not currently configure vhost-user-blk devices.
     """
 
     # Format of a sample smaps entry:
@@ -260,27 +260,29 @@ def test_ept_violation_count(
     global_props.host_linux_version == "4.14",
     reason="MFD_HUGETLB | MFD_ALLOW_SEALING only supported on kernels >= 4.16",
 )
-def test_negative_huge_pages_plus_balloon(uvm_plain):
-    """Tests that huge pages and memory ballooning cannot be used together"""
+def test_hugetlbfs_balloon(uvm_plain):
+    """Tests that inflating the balloon of a microvm backed by huge pages releases huge pages"""
     uvm_plain.memory_monitor = None
     uvm_plain.spawn()
+    uvm_plain.basic_config(huge_pages=HugePagesConfig.HUGETLBFS_2MB, mem_size_mib=256)
+    uvm_plain.add_net_iface()
+    uvm_plain.api.balloon.put(amount_mib=0, deflate_on_oom=False)
+    uvm_plain.start()
+    uvm_plain.wait_for_up()
 
-    # Ensure setting huge pages and then adding a balloon device doesn't work
-    uvm_plain.basic_config(huge_pages=HugePagesConfig.HUGETLBFS_2MB)
-    with pytest.raises(
-        RuntimeError,
-        match="Firecracker's huge pages support is incompatible with memory ballooning.",
-    ):
-        uvm_plain.api.balloon.put(amount_mib=0, deflate_on_oom=False)
+    # With a balloon device, guest memory is backed by a hugetlbfs memfd.
+    check_hugetlbfs_in_use(uvm_plain.firecracker_pid, "memfd:guest_mem")
 
-    # Ensure adding a balloon device and then setting huge pages doesn't work
-    uvm_plain.basic_config(huge_pages=HugePagesConfig.NONE)
-    uvm_plain.api.balloon.put(amount_mib=0, deflate_on_oom=False)
-    with pytest.raises(
-        RuntimeError,
-        match="Machine config error: Firecracker's huge pages support is incompatible with memory ballooning.",
-    ):
-        uvm_plain.basic_config(huge_pages=HugePagesConfig.HUGETLBFS_2MB)
+    uvm_plain.api.balloon.patch(amount_mib=128)
+    # Give the balloon driver time to inflate.
+    time.sleep(5)
+
+    uvm_plain.flush_metrics()
+    released = sum(
+        metrics["balloon"]["hugepages_released"]
+        for metrics in uvm_plain.get_all_metrics()
+    )
+    assert released > 0
 
 
 @pytest.mark.skipif(
@@ -60,6 +60,8 @@ fc-bindgen \
     --allowlist-var='TUN_.*' \
     --allowlist-var='IFF_NO_PI' \
     --allowlist-var='IFF_MULTI_QUEUE' \
+    --allowlist-var='IFF_ATTACH_QUEUE' \
+    --allowlist-var='IFF_DETACH_QUEUE' \
     --allowlist-var='IFF_TAP' \
     --allowlist-var='IFF_VNET_HDR' \
     --allowlist-var='ETH_.*' \
@@ -80,6 +82,11 @@ fc-bindgen \
 info "BINDGEN virtio_net.h"
 fc-bindgen \
     --allowlist-var "VIRTIO_NET_F_.*" \
+    --allowlist-var "VIRTIO_NET_OK" \
+    --allowlist-var "VIRTIO_NET_ERR" \
+    --allowlist-var "VIRTIO_NET_CTRL_RX.*" \
+    --allowlist-var "VIRTIO_NET_CTRL_MAC.*" \
+    --allowlist-var "VIRTIO_NET_CTRL_MQ.*" \
     --allowlist-var "VIRTIO_F_.*" \
     --allowlist-type "virtio_net_hdr_v1" \
     "$KERNEL_HEADERS_HOME/include/linux/virtio_net.h" >src/vmm/src/devices/virtio/gen/virtio_net.rs